@@ -226,6 +226,7 @@ pub fn create_fdt<T: DeviceInfoForFdt + Clone + Debug, S: ::std::hash::BuildHash
     numa_nodes: &NumaNodes,
     virtio_iommu_bdf: Option<u32>,
     pmu_supported: bool,
+    scmi: bool,
 ) -> FdtWriterResult<Vec<u8>> {
     // Allocate stuff necessary for the holding the blob.
     let mut fdt = FdtWriter::new().unwrap();
@@ -254,6 +255,9 @@ pub fn create_fdt<T: DeviceInfoForFdt + Clone + Debug, S: ::std::hash::BuildHash
     }
     create_clock_node(&mut fdt)?;
     create_psci_node(&mut fdt)?;
+    if scmi {
+        create_scmi_node(&mut fdt)?;
+    }
     create_devices_node(&mut fdt, device_info)?;
     create_pci_nodes(&mut fdt, pci_space_info, virtio_iommu_bdf)?;
     if numa_nodes.len() > 1 {
@@ -370,7 +374,7 @@ fn create_cpu_nodes(
         if numa_nodes.len() > 1 {
             for numa_node_idx in 0..numa_nodes.len() {
                 let numa_node = numa_nodes.get(&(numa_node_idx as u32));
-                if numa_node.unwrap().cpus.contains(&(cpu_id as u8)) {
+                if numa_node.unwrap().cpus.contains(&(cpu_id as u32)) {
                     fdt.property_u32("numa-node-id", numa_node_idx as u32)?;
                 }
             }
@@ -747,6 +751,37 @@ fn create_psci_node(fdt: &mut FdtWriter) -> FdtWriterResult<()> {
     Ok(())
 }
 
+fn create_scmi_node(fdt: &mut FdtWriter) -> FdtWriterResult<()> {
+    // The SCMI agent is reached through the virtio-scmi device, which the
+    // "arm,scmi-virtio" transport binds to. See
+    // https://github.com/torvalds/linux/blob/master/Documentation/devicetree/bindings/firmware/arm,scmi.yaml.
+    let firmware_node = fdt.begin_node("firmware")?;
+    let scmi_node = fdt.begin_node("scmi")?;
+    fdt.property_string("compatible", "arm,scmi-virtio")?;
+    fdt.property_u32("#address-cells", 0x1)?;
+    fdt.property_u32("#size-cells", 0x0)?;
+
+    let power_node = fdt.begin_node("protocol@11")?;
+    fdt.property_u32("reg", 0x11)?;
+    fdt.property_u32("#power-domain-cells", 0x1)?;
+    fdt.end_node(power_node)?;
+
+    let clock_node = fdt.begin_node("protocol@14")?;
+    fdt.property_u32("reg", 0x14)?;
+    fdt.property_u32("#clock-cells", 0x1)?;
+    fdt.end_node(clock_node)?;
+
+    let sensor_node = fdt.begin_node("protocol@15")?;
+    fdt.property_u32("reg", 0x15)?;
+    fdt.property_u32("#thermal-sensor-cells", 0x1)?;
+    fdt.end_node(sensor_node)?;
+
+    fdt.end_node(scmi_node)?;
+    fdt.end_node(firmware_node)?;
+
+    Ok(())
+}
+
 fn create_virtio_node<T: DeviceInfoForFdt + Clone + Debug>(
     fdt: &mut FdtWriter,
     dev_info: &T,
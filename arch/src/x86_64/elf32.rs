@@ -0,0 +1,285 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Loader for 32-bit ELF kernels booted through the PVH boot protocol.
+//!
+//! The ELF loader from linux-loader only handles 64-bit binaries, while
+//! 32-bit kernels built with PVH support are ELF32 binaries carrying the
+//! same Xen note to advertise their 32-bit entry point.
+
+use std::io::{Read, Seek, SeekFrom};
+use std::os::fd::AsFd;
+use std::result;
+
+use linux_loader::loader::elf::PvhBootCapability;
+use linux_loader::loader::KernelLoaderResult;
+use thiserror::Error;
+use vm_memory::{GuestAddress, GuestMemory, GuestUsize};
+
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+const ELFCLASS32: u8 = 1;
+const ELFDATA2LSB: u8 = 1;
+const EM_386: u16 = 3;
+
+const ELF32_EHDR_SIZE: usize = 52;
+const ELF32_PHDR_SIZE: usize = 32;
+
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+
+const XEN_ELFNOTE_PHYS32_ENTRY: u32 = 18;
+// Notes are small, anything bigger is bogus.
+const MAX_NOTE_SEGMENT_SIZE: usize = 0x10000;
+
+/// Errors thrown while loading a 32-bit ELF kernel
+#[derive(Debug, Error)]
+pub enum Error {
+    /// Not a 32-bit x86 ELF binary.
+    #[error("Not a 32-bit x86 ELF binary")]
+    NotElf32,
+    /// Invalid program header.
+    #[error("Invalid program header")]
+    InvalidProgramHeader,
+    /// Segment loaded below the lowest allowed address.
+    #[error("Segment loaded below the lowest allowed address")]
+    InvalidLoadAddress,
+    /// Missing PVH entry point note.
+    #[error("Missing PVH entry point note")]
+    MissingPvhNote,
+    /// Unable to read the kernel image.
+    #[error("Unable to read the kernel image")]
+    ReadKernelImage(#[source] std::io::Error),
+    /// Unable to load the kernel image into guest memory.
+    #[error("Unable to load the kernel image into guest memory")]
+    LoadKernelImage(#[source] vm_memory::GuestMemoryError),
+}
+type Result<T> = result::Result<T, Error>;
+
+fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+// Look for the PVH entry point among the notes of a PT_NOTE segment.
+fn find_pvh_entry(notes: &[u8]) -> Option<u32> {
+    let mut offset = 0;
+    while offset + 12 <= notes.len() {
+        let namesz = read_u32(notes, offset) as usize;
+        let descsz = read_u32(notes, offset + 4) as usize;
+        let note_type = read_u32(notes, offset + 8);
+        let desc = offset + 12 + namesz.next_multiple_of(4);
+        if desc + descsz > notes.len() {
+            return None;
+        }
+
+        if note_type == XEN_ELFNOTE_PHYS32_ENTRY
+            && notes[offset + 12..offset + 12 + namesz].starts_with(b"Xen")
+            && descsz >= 4
+        {
+            return Some(read_u32(notes, desc));
+        }
+
+        offset = desc + descsz.next_multiple_of(4);
+    }
+
+    None
+}
+
+/// Load a 32-bit ELF kernel into guest memory, returning its PVH entry
+/// point. Segments are loaded at their physical address, which can't be
+/// below `lowest_kernel_addr`.
+pub fn load_elf32<F, M: GuestMemory>(
+    guest_mem: &M,
+    kernel_image: &mut F,
+    lowest_kernel_addr: GuestAddress,
+) -> Result<KernelLoaderResult>
+where
+    F: Read + Seek + AsFd,
+{
+    let mut ehdr = [0u8; ELF32_EHDR_SIZE];
+    kernel_image.rewind().map_err(Error::ReadKernelImage)?;
+    kernel_image
+        .read_exact(&mut ehdr)
+        .map_err(Error::ReadKernelImage)?;
+    if ehdr[0..4] != ELF_MAGIC
+        || ehdr[4] != ELFCLASS32
+        || ehdr[5] != ELFDATA2LSB
+        || read_u16(&ehdr, 18) != EM_386
+    {
+        return Err(Error::NotElf32);
+    }
+    if usize::from(read_u16(&ehdr, 42)) != ELF32_PHDR_SIZE {
+        return Err(Error::InvalidProgramHeader);
+    }
+
+    let phoff = read_u32(&ehdr, 28);
+    let phnum = usize::from(read_u16(&ehdr, 44));
+    let mut phdrs = vec![0u8; phnum * ELF32_PHDR_SIZE];
+    kernel_image
+        .seek(SeekFrom::Start(u64::from(phoff)))
+        .map_err(Error::ReadKernelImage)?;
+    kernel_image
+        .read_exact(&mut phdrs)
+        .map_err(Error::ReadKernelImage)?;
+
+    let mut kernel_load = None;
+    let mut kernel_end: GuestUsize = 0;
+    let mut pvh_entry = None;
+    for phdr in phdrs.chunks_exact(ELF32_PHDR_SIZE) {
+        let p_type = read_u32(phdr, 0);
+        let p_offset = u64::from(read_u32(phdr, 4));
+        let p_paddr = u64::from(read_u32(phdr, 12));
+        let p_filesz = read_u32(phdr, 16) as usize;
+        let p_memsz = u64::from(read_u32(phdr, 20));
+
+        kernel_image
+            .seek(SeekFrom::Start(p_offset))
+            .map_err(Error::ReadKernelImage)?;
+
+        match p_type {
+            PT_LOAD => {
+                if p_paddr < lowest_kernel_addr.0 {
+                    return Err(Error::InvalidLoadAddress);
+                }
+
+                guest_mem
+                    .read_exact_volatile_from(
+                        GuestAddress(p_paddr),
+                        &mut kernel_image.as_fd(),
+                        p_filesz,
+                    )
+                    .map_err(Error::LoadKernelImage)?;
+
+                kernel_load = Some(kernel_load.unwrap_or(u64::MAX).min(p_paddr));
+                kernel_end = kernel_end.max(p_paddr + p_memsz);
+            }
+            PT_NOTE if pvh_entry.is_none() => {
+                if p_filesz > MAX_NOTE_SEGMENT_SIZE {
+                    return Err(Error::InvalidProgramHeader);
+                }
+
+                let mut notes = vec![0u8; p_filesz];
+                kernel_image
+                    .read_exact(&mut notes)
+                    .map_err(Error::ReadKernelImage)?;
+                pvh_entry = find_pvh_entry(&notes);
+            }
+            _ => {}
+        }
+    }
+
+    let pvh_entry = pvh_entry.ok_or(Error::MissingPvhNote)?;
+
+    Ok(KernelLoaderResult {
+        kernel_load: GuestAddress(kernel_load.ok_or(Error::InvalidProgramHeader)?),
+        kernel_end,
+        setup_header: None,
+        pvh_boot_cap: PvhBootCapability::PvhEntryPresent(GuestAddress(u64::from(pvh_entry))),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use vmm_sys_util::tempfile::TempFile;
+
+    use super::*;
+    use crate::GuestMemoryMmap;
+
+    fn note(note_type: u32, name: &[u8], desc: &[u8]) -> Vec<u8> {
+        let mut note = Vec::new();
+        note.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        note.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+        note.extend_from_slice(&note_type.to_le_bytes());
+        note.extend_from_slice(name);
+        note.resize(note.len().next_multiple_of(4), 0);
+        note.extend_from_slice(desc);
+        note.resize(note.len().next_multiple_of(4), 0);
+        note
+    }
+
+    #[test]
+    fn test_find_pvh_entry() {
+        let mut notes = note(1, b"GNU\0", &[0; 20]);
+        assert_eq!(find_pvh_entry(&notes), None);
+        notes.extend(note(
+            XEN_ELFNOTE_PHYS32_ENTRY,
+            b"Xen\0",
+            &0x1234u32.to_le_bytes(),
+        ));
+        assert_eq!(find_pvh_entry(&notes), Some(0x1234));
+        assert_eq!(find_pvh_entry(&notes[..notes.len() - 4]), None);
+    }
+
+    #[test]
+    fn test_load_elf32() {
+        let notes = note(
+            XEN_ELFNOTE_PHYS32_ENTRY,
+            b"Xen\0",
+            &0x10_0040u32.to_le_bytes(),
+        );
+        let code = [0x90u8; 16];
+
+        let mut image = vec![0u8; ELF32_EHDR_SIZE];
+        image[0..4].copy_from_slice(&ELF_MAGIC);
+        image[4] = ELFCLASS32;
+        image[5] = ELFDATA2LSB;
+        image[18..20].copy_from_slice(&EM_386.to_le_bytes());
+        image[28..32].copy_from_slice(&(ELF32_EHDR_SIZE as u32).to_le_bytes());
+        image[42..44].copy_from_slice(&(ELF32_PHDR_SIZE as u16).to_le_bytes());
+        image[44..46].copy_from_slice(&2u16.to_le_bytes());
+
+        let data_offset = (ELF32_EHDR_SIZE + 2 * ELF32_PHDR_SIZE) as u32;
+        for (p_type, offset, paddr, size) in [
+            (PT_LOAD, data_offset, 0x10_0000u32, code.len() as u32),
+            (
+                PT_NOTE,
+                data_offset + code.len() as u32,
+                0,
+                notes.len() as u32,
+            ),
+        ] {
+            let mut phdr = [0u8; ELF32_PHDR_SIZE];
+            phdr[0..4].copy_from_slice(&p_type.to_le_bytes());
+            phdr[4..8].copy_from_slice(&offset.to_le_bytes());
+            phdr[12..16].copy_from_slice(&paddr.to_le_bytes());
+            phdr[16..20].copy_from_slice(&size.to_le_bytes());
+            phdr[20..24].copy_from_slice(&size.to_le_bytes());
+            image.extend_from_slice(&phdr);
+        }
+        image.extend_from_slice(&code);
+        image.extend_from_slice(&notes);
+
+        let mut file = TempFile::new().unwrap().into_file();
+        std::io::Write::write_all(&mut file, &image).unwrap();
+
+        let gm = GuestMemoryMmap::from_ranges(&[(GuestAddress(0), 0x20_0000)]).unwrap();
+        let result = load_elf32(&gm, &mut file, GuestAddress(0x10_0000)).unwrap();
+        assert_eq!(result.kernel_load, GuestAddress(0x10_0000));
+        assert_eq!(result.kernel_end, 0x10_0010);
+        assert!(matches!(
+            result.pvh_boot_cap,
+            PvhBootCapability::PvhEntryPresent(GuestAddress(0x10_0040))
+        ));
+        let mut loaded = [0u8; 16];
+        vm_memory::Bytes::read_slice(&gm, &mut loaded, GuestAddress(0x10_0000)).unwrap();
+        assert_eq!(loaded, code);
+
+        assert!(matches!(
+            load_elf32(&gm, &mut file, GuestAddress(0x10_0001)),
+            Err(Error::InvalidLoadAddress)
+        ));
+
+        image[4] = 2;
+        let mut file = TempFile::new().unwrap().into_file();
+        std::io::Write::write_all(&mut file, &image).unwrap();
+        assert!(matches!(
+            load_elf32(&gm, &mut file, GuestAddress(0x10_0000)),
+            Err(Error::NotElf32)
+        ));
+    }
+}
@@ -73,6 +73,10 @@ pub const EBDA_START: GuestAddress = GuestAddress(0xa0000);
 // ACPI RSDP table
 pub const RSDP_POINTER: GuestAddress = EBDA_START;
 
+/// TPM event log, between the ACPI tables and the SMBIOS tables.
+pub const TPM_EVENT_LOG_START: GuestAddress = GuestAddress(0xe0000);
+pub const TPM_EVENT_LOG_SIZE: u64 = 0x10000;
+
 pub const SMBIOS_START: u64 = 0xf0000; // First possible location per the spec.
 
 // == End of "EBDA" range ==
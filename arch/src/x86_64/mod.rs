@@ -7,6 +7,7 @@
 // Use of this source code is governed by a BSD-style license that can be
 // found in the LICENSE-BSD-3-Clause file.
 use std::sync::Arc;
+pub mod elf32;
 pub mod interrupts;
 pub mod layout;
 mod mpspec;
@@ -44,6 +45,8 @@ const AMX_TILE: u8 = 24; // AMX tile load/store instructions
 const AMX_INT8: u8 = 25; // AMX tile computation on 8-bit integers
 
 // KVM feature bits
+#[cfg(feature = "kvm")]
+const KVM_FEATURE_MSI_EXT_DEST_ID_BIT: u8 = 15;
 #[cfg(feature = "tdx")]
 const KVM_FEATURE_CLOCKSOURCE_BIT: u8 = 0;
 #[cfg(feature = "tdx")]
@@ -124,6 +127,10 @@ pub struct CpuidConfig {
     pub sgx_epc_sections: Option<Vec<SgxEpcSection>>,
     pub phys_bits: u8,
     pub kvm_hyperv: bool,
+    /// Advertise the Hyper-V features the VMBus services rely on.
+    pub vmbus: bool,
+    /// Let the guest target APIC IDs above 255 through the MSI address.
+    pub msi_ext_dest_id: bool,
     #[cfg(feature = "tdx")]
     pub tdx: bool,
     pub amx: bool,
@@ -217,8 +224,8 @@ pub fn get_x2apic_id(cpu_id: u32, topology: Option<(u8, u8, u8)>) -> u32 {
 
         let thread_id = cpu_id % (t.0 as u32);
         let core_id = cpu_id / (t.0 as u32) % (t.1 as u32);
-        let die_id = cpu_id / ((t.0 * t.1) as u32) % (t.2 as u32);
-        let socket_id = cpu_id / ((t.0 * t.1 * t.2) as u32);
+        let die_id = cpu_id / (t.0 as u32 * t.1 as u32) % (t.2 as u32);
+        let socket_id = cpu_id / (t.0 as u32 * t.1 as u32 * t.2 as u32);
 
         return thread_id
             | (core_id << thread_mask_width)
@@ -653,6 +660,19 @@ pub fn generate_common_cpuid(
             ecx_bit: Some(TSC_DEADLINE_TIMER_ECX_BIT),
             edx_bit: None,
         });
+        // Patch MSI extended destination ID bit, letting the guest reach
+        // APIC IDs above 255 without interrupt remapping
+        if config.msi_ext_dest_id {
+            cpuid_patches.push(CpuidPatch {
+                function: 0x4000_0001,
+                index: 0,
+                flags_bit: None,
+                eax_bit: Some(KVM_FEATURE_MSI_EXT_DEST_ID_BIT),
+                ebx_bit: None,
+                ecx_bit: None,
+                edx_bit: None,
+            });
+        }
     }
 
     // Supported CPUID
@@ -793,7 +813,7 @@ pub fn generate_common_cpuid(
             ebx: 0xa0000, // "Version"
             ..Default::default()
         });
-        cpuid.push(CpuIdEntry {
+        let mut features = CpuIdEntry {
             function: 0x4000_0003,
             eax: (1 << 1) // AccessPartitionReferenceCounter
                    | (1 << 2) // AccessSynicRegs
@@ -801,7 +821,18 @@ pub fn generate_common_cpuid(
                    | (1 << 9), // AccessPartitionReferenceTsc
             edx: 1 << 3, // CPU dynamic partitioning
             ..Default::default()
-        });
+        };
+        // The VMBus connection relies on the hypercalls posting messages
+        // and signalling events, only handled when the VMBus is enabled.
+        if config.vmbus {
+            features.eax |= (1 << 5) // AccessHypercallMsrs
+                | (1 << 6) // AccessVpIndex
+                | (1 << 11); // AccessFrequencyRegs
+            features.ebx |= (1 << 4) // PostMessages
+                | (1 << 5); // SignalEvents
+            features.edx |= 1 << 8; // Frequency MSRs available
+        }
+        cpuid.push(features);
         cpuid.push(CpuIdEntry {
             function: 0x4000_0004,
             eax: 1 << 5, // Recommend relaxed timing
@@ -820,14 +851,14 @@ pub fn generate_common_cpuid(
 
 pub fn configure_vcpu(
     vcpu: &Arc<dyn hypervisor::Vcpu>,
-    id: u8,
+    id: u32,
     boot_setup: Option<(EntryPoint, &GuestMemoryAtomic<GuestMemoryMmap>)>,
     cpuid: Vec<CpuIdEntry>,
     kvm_hyperv: bool,
     cpu_vendor: CpuVendor,
     topology: Option<(u8, u8, u8)>,
 ) -> super::Result<()> {
-    let x2apic_id = get_x2apic_id(id as u32, topology);
+    let x2apic_id = get_x2apic_id(id, topology);
 
     // Per vCPU CPUID changes; common are handled via generate_common_cpuid()
     let mut cpuid = cpuid;
@@ -842,7 +873,7 @@ pub fn configure_vcpu(
     for entry in &mut cpuid {
         if entry.function == 1 {
             entry.ebx &= 0xffffff;
-            entry.ebx |= x2apic_id << 24;
+            entry.ebx |= (x2apic_id & 0xff) << 24;
             apic_id_patched = true;
             break;
         }
@@ -946,7 +977,7 @@ pub fn configure_system(
     cmdline_addr: GuestAddress,
     cmdline_size: usize,
     initramfs: &Option<InitramfsConfig>,
-    _num_cpus: u8,
+    num_cpus: u32,
     setup_header: Option<setup_header>,
     rsdp_addr: Option<GuestAddress>,
     sgx_epc_region: Option<SgxEpcRegion>,
@@ -966,7 +997,14 @@ pub fn configure_system(
     // Place the MP table after the SMIOS table aligned to 16 bytes
     let offset = GuestAddress(layout::SMBIOS_START).unchecked_add(size);
     let offset = GuestAddress((offset.0 + 16) & !0xf);
-    mptable::setup_mptable(offset, guest_mem, _num_cpus, topology).map_err(Error::MpTableSetup)?;
+    // The MP table can only describe xAPIC IDs, guests with larger APIC IDs
+    // rely on the ACPI tables only.
+    if num_cpus > 0 && get_x2apic_id(num_cpus - 1, topology) >= mptable::MAX_SUPPORTED_CPUS {
+        info!("Skipping MP table creation, APIC IDs don't fit in 8 bits");
+    } else {
+        mptable::setup_mptable(offset, guest_mem, num_cpus as u8, topology)
+            .map_err(Error::MpTableSetup)?;
+    }
 
     // Check that the RAM is not smaller than the RSDP start address
     if let Some(rsdp_addr) = rsdp_addr {
@@ -1365,10 +1403,10 @@ fn update_cpuid_topology(
     cores_per_die: u8,
     dies_per_package: u8,
     cpu_vendor: CpuVendor,
-    id: u8,
+    id: u32,
 ) {
     let x2apic_id = get_x2apic_id(
-        id as u32,
+        id,
         Some((threads_per_core, cores_per_die, dies_per_package)),
     );
 
@@ -1402,7 +1440,7 @@ fn update_cpuid_topology(
         0xb,
         Some(1),
         CpuidReg::EBX,
-        u32::from(dies_per_package * cores_per_die * threads_per_core),
+        u32::from(dies_per_package) * u32::from(cores_per_die) * u32::from(threads_per_core),
     );
     CpuidPatch::set_cpuid_reg(cpuid, 0xb, Some(1), CpuidReg::ECX, 2 << 8);
     CpuidPatch::set_cpuid_reg(cpuid, 0xb, Some(1), CpuidReg::EDX, x2apic_id);
@@ -1424,7 +1462,7 @@ fn update_cpuid_topology(
         0x1f,
         Some(1),
         CpuidReg::EBX,
-        u32::from(cores_per_die * threads_per_core),
+        u32::from(cores_per_die) * u32::from(threads_per_core),
     );
     CpuidPatch::set_cpuid_reg(cpuid, 0x1f, Some(1), CpuidReg::ECX, 2 << 8);
 
@@ -1434,7 +1472,7 @@ fn update_cpuid_topology(
         0x1f,
         Some(2),
         CpuidReg::EBX,
-        u32::from(dies_per_package * cores_per_die * threads_per_core),
+        u32::from(dies_per_package) * u32::from(cores_per_die) * u32::from(threads_per_core),
     );
     CpuidPatch::set_cpuid_reg(cpuid, 0x1f, Some(2), CpuidReg::ECX, 5 << 8);
 
@@ -1454,7 +1492,7 @@ fn update_cpuid_topology(
             ((dies_per_package as u32 - 1) << 8) | (thread_width + die_width) & 0xff,
         );
         CpuidPatch::set_cpuid_reg(cpuid, 0x8000_001e, Some(0), CpuidReg::EDX, 0);
-        if cores_per_die * threads_per_core > 1 {
+        if u32::from(cores_per_die) * u32::from(threads_per_core) > 1 {
             let ecx =
                 CpuidPatch::get_cpuid_reg(cpuid, 0x8000_0001, Some(0), CpuidReg::ECX).unwrap_or(0);
             CpuidPatch::set_cpuid_reg(
@@ -1469,7 +1507,9 @@ fn update_cpuid_topology(
                 0x0000_0001,
                 Some(0),
                 CpuidReg::EBX,
-                (x2apic_id << 24) | (8 << 8) | (((cores_per_die * threads_per_core) as u32) << 16),
+                ((x2apic_id & 0xff) << 24)
+                    | (8 << 8)
+                    | ((u32::from(cores_per_die) * u32::from(threads_per_core)).min(0xff) << 16),
             );
             let cpuid_patches = vec![
                 // Patch tsc deadline timer bit
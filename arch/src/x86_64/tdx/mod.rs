@@ -56,6 +56,11 @@ pub struct TdvfSection {
     pub attributes: u32,
 }
 
+// The section content is measured into MRTD
+pub const TDVF_SECTION_ATTRIBUTES_EXTENDMR: u32 = 1 << 0;
+// The section is added lazily, the guest accepting it on demand
+pub const TDVF_SECTION_ATTRIBUTES_PAGE_AUG: u32 = 1 << 1;
+
 #[repr(u32)]
 #[derive(Clone, Copy, Debug, Default)]
 pub enum TdvfSectionType {
@@ -2,13 +2,16 @@
 //
 // SPDX-License-Identifier: Apache-2.0 AND BSD-3-Clause
 
+use std::fs::File;
 use std::marker::PhantomData;
-use std::os::fd::{AsRawFd, OwnedFd, RawFd};
+use std::mem::ManuallyDrop;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
 
 use thiserror::Error;
 use vmm_sys_util::eventfd::EventFd;
+use vmm_sys_util::write_zeroes::{PunchHole, WriteZeroesAt};
 
-use crate::DiskTopology;
+use crate::{BatchRequest, DiskTopology};
 
 #[derive(Error, Debug)]
 pub enum DiskFileError {
@@ -18,6 +21,9 @@ pub enum DiskFileError {
     /// Failed creating a new AsyncIo.
     #[error("Failed creating a new AsyncIo")]
     NewAsyncIo(#[source] std::io::Error),
+    /// Failed resizing the disk file.
+    #[error("Failed resizing the disk file")]
+    Resize(#[source] std::io::Error),
 }
 
 pub type DiskFileResult<T> = std::result::Result<T, DiskFileError>;
@@ -66,6 +72,17 @@ pub trait DiskFile: Send {
     /// The file descriptor is supposed to be used for `fcntl()` calls but no
     /// other operation.
     fn fd(&mut self) -> BorrowedDiskFd<'_>;
+    /// Whether ranges of the disk image can be deallocated, through
+    /// [`AsyncIo::punch_hole`], and zeroed, through [`AsyncIo::write_zeroes`].
+    fn supports_punch_hole(&self) -> bool {
+        false
+    }
+    /// Grows the disk image to `size` bytes, the new range reading as zeros.
+    fn resize(&mut self, _size: u64) -> DiskFileResult<()> {
+        Err(DiskFileError::Resize(std::io::Error::from(
+            std::io::ErrorKind::Unsupported,
+        )))
+    }
 }
 
 #[derive(Error, Debug)]
@@ -79,6 +96,15 @@ pub enum AsyncIoError {
     /// Failed synchronizing file.
     #[error("Failed synchronizing file")]
     Fsync(#[source] std::io::Error),
+    /// Failed submitting batch requests.
+    #[error("Failed submitting batch requests")]
+    SubmitBatchRequests(#[source] std::io::Error),
+    /// Failed punching a hole in the file.
+    #[error("Failed punching a hole in the file")]
+    PunchHole(#[source] std::io::Error),
+    /// Failed writing zeroes to the file.
+    #[error("Failed writing zeroes to the file")]
+    WriteZeroes(#[source] std::io::Error),
 }
 
 pub type AsyncIoResult<T> = std::result::Result<T, AsyncIoError>;
@@ -99,4 +125,49 @@ pub trait AsyncIo: Send {
     ) -> AsyncIoResult<()>;
     fn fsync(&mut self, user_data: Option<u64>) -> AsyncIoResult<()>;
     fn next_completed_request(&mut self) -> Option<(u64, i32)>;
+    /// Whether reads and writes are queued in a batch and submitted with
+    /// [`AsyncIo::submit_batch_requests`] rather than one by one.
+    fn batch_requests_enabled(&self) -> bool {
+        false
+    }
+    /// Submits a batch of reads and writes, either all of them or none.
+    fn submit_batch_requests(&mut self, _batch_requests: &[BatchRequest]) -> AsyncIoResult<()> {
+        Ok(())
+    }
+    /// Registers the memory the buffers of the requests are taken from, for
+    /// the backends able to access it without mapping it on each request.
+    fn register_buffers(&mut self, _buffers: &[libc::iovec]) {}
+    /// Sets the I/O priority of the requests, for the backends not issuing
+    /// them from the calling thread, as encoded by [`crate::io_priority`].
+    fn set_io_priority(&mut self, _ioprio: u16) {}
+    /// Deallocates a range of the disk image, which then reads as zeros.
+    fn punch_hole(&mut self, _offset: u64, _length: u64, _user_data: u64) -> AsyncIoResult<()> {
+        Err(AsyncIoError::PunchHole(std::io::Error::from(
+            std::io::ErrorKind::Unsupported,
+        )))
+    }
+    /// Zeroes a range of the disk image, keeping it allocated.
+    fn write_zeroes(&mut self, _offset: u64, _length: u64, _user_data: u64) -> AsyncIoResult<()> {
+        Err(AsyncIoError::WriteZeroes(std::io::Error::from(
+            std::io::ErrorKind::Unsupported,
+        )))
+    }
+}
+
+/// Deallocates or zeroes a range of a raw disk image file, for the backends
+/// operating on the file directly.
+pub(crate) fn zero_raw_file(
+    fd: RawFd,
+    offset: u64,
+    length: u64,
+    punch_hole: bool,
+) -> std::io::Result<()> {
+    // SAFETY: the file descriptor is owned by the backend, and isn't closed
+    // when the file is dropped.
+    let mut file = ManuallyDrop::new(unsafe { File::from_raw_fd(fd) });
+    if punch_hole {
+        file.punch_hole(offset, length)
+    } else {
+        file.write_all_zeroes_at(offset, length as usize)
+    }
 }
@@ -103,6 +103,10 @@ impl AsyncIo for FixedVhdAsync {
         self.raw_file_async.fsync(user_data)
     }
 
+    fn set_io_priority(&mut self, ioprio: u16) {
+        self.raw_file_async.set_io_priority(ioprio)
+    }
+
     fn next_completed_request(&mut self) -> Option<(u64, i32)> {
         self.raw_file_async.next_completed_request()
     }
@@ -18,6 +18,9 @@ pub mod fixed_vhd;
 /// Enabled with the `"io_uring"` feature
 pub mod fixed_vhd_async;
 pub mod fixed_vhd_sync;
+pub mod luks;
+pub mod luks_sync;
+pub mod multipath;
 pub mod qcow;
 pub mod qcow_sync;
 #[cfg(feature = "io_uring")]
@@ -26,6 +29,7 @@ pub mod qcow_sync;
 /// Enabled with the `"io_uring"` feature
 pub mod raw_async;
 pub mod raw_async_aio;
+pub mod raw_async_threads;
 pub mod raw_sync;
 pub mod vhd;
 pub mod vhdx;
@@ -57,6 +61,7 @@ use vm_memory::{
 };
 use vm_virtio::{AccessPlatform, Translatable};
 use vmm_sys_util::eventfd::EventFd;
+use vmm_sys_util::write_zeroes::{PunchHole, WriteZeroesAt};
 use vmm_sys_util::{aio, ioctl_io_nr, ioctl_ioc_nr};
 
 use crate::async_io::{AsyncIo, AsyncIoError, AsyncIoResult};
@@ -93,6 +98,8 @@ pub enum Error {
     RawFileError(#[source] std::io::Error),
     #[error("The requested operation does not support multiple descriptors")]
     TooManyDescriptors,
+    #[error("Guest gave us more than one discard or write zeroes segment")]
+    TooManySegments,
     #[error("Failure in vhdx")]
     VhdxError(#[source] VhdxError),
 }
@@ -157,6 +164,10 @@ pub enum ExecuteError {
     AsyncWrite(#[source] AsyncIoError),
     #[error("failed to async flush")]
     AsyncFlush(#[source] AsyncIoError),
+    #[error("Failed to async punch hole")]
+    AsyncPunchHole(#[source] AsyncIoError),
+    #[error("Failed to async write zeroes")]
+    AsyncWriteZeroes(#[source] AsyncIoError),
     #[error("Failed allocating a temporary buffer")]
     TemporaryBufferAllocation(#[source] io::Error),
 }
@@ -177,6 +188,8 @@ impl ExecuteError {
             ExecuteError::AsyncRead(_) => VIRTIO_BLK_S_IOERR,
             ExecuteError::AsyncWrite(_) => VIRTIO_BLK_S_IOERR,
             ExecuteError::AsyncFlush(_) => VIRTIO_BLK_S_IOERR,
+            ExecuteError::AsyncPunchHole(_) => VIRTIO_BLK_S_IOERR,
+            ExecuteError::AsyncWriteZeroes(_) => VIRTIO_BLK_S_IOERR,
             ExecuteError::TemporaryBufferAllocation(_) => VIRTIO_BLK_S_IOERR,
         };
         status as u8
@@ -189,6 +202,8 @@ pub enum RequestType {
     Out,
     Flush,
     GetDeviceId,
+    Discard,
+    WriteZeroes,
     Unsupported(u32),
 }
 
@@ -202,6 +217,8 @@ pub fn request_type<B: Bitmap + 'static>(
         VIRTIO_BLK_T_OUT => Ok(RequestType::Out),
         VIRTIO_BLK_T_FLUSH => Ok(RequestType::Flush),
         VIRTIO_BLK_T_GET_ID => Ok(RequestType::GetDeviceId),
+        VIRTIO_BLK_T_DISCARD => Ok(RequestType::Discard),
+        VIRTIO_BLK_T_WRITE_ZEROES => Ok(RequestType::WriteZeroes),
         t => Ok(RequestType::Unsupported(t)),
     }
 }
@@ -221,6 +238,24 @@ fn sector<B: Bitmap + 'static>(
 
 const DEFAULT_DESCRIPTOR_VEC_SIZE: usize = 32;
 
+// Largest number of iovecs of a vectored read or write, UIO_MAXIOV.
+const MAX_BATCH_REQUEST_IOVECS: usize = 1024;
+
+// Lets the device deallocate the range of a write zeroes request.
+const VIRTIO_BLK_WRITE_ZEROES_FLAG_UNMAP: u32 = 1;
+
+/// Range of the disk covered by a discard or write zeroes request.
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(C)]
+struct DiscardWriteZeroesSegment {
+    sector: u64,
+    num_sectors: u32,
+    flags: u32,
+}
+
+// SAFETY: data structure only contain a series of integers
+unsafe impl ByteValued for DiscardWriteZeroesSegment {}
+
 #[derive(Debug)]
 pub struct AlignedOperation {
     origin_ptr: u64,
@@ -238,6 +273,43 @@ pub struct Request {
     pub writeback: bool,
     pub aligned_operations: SmallVec<[AlignedOperation; DEFAULT_DESCRIPTOR_VEC_SIZE]>,
     pub start: Instant,
+    pub detect_zeroes: bool,
+    pub unmap_zeroes: bool,
+}
+
+/// Read or write queued in a batch, submitted along with the other requests
+/// of the batch.
+pub struct BatchRequest {
+    pub offset: libc::off_t,
+    pub iovecs: SmallVec<[libc::iovec; DEFAULT_DESCRIPTOR_VEC_SIZE]>,
+    pub user_data: u64,
+    pub request_type: RequestType,
+    /// Requests merged into this one, in the order of their data.
+    pub merged_user_data: Vec<u64>,
+}
+
+impl BatchRequest {
+    fn len(&self) -> u64 {
+        self.iovecs.iter().map(|iovec| iovec.iov_len as u64).sum()
+    }
+
+    /// Queues a request in a batch, merging it into the last request of the
+    /// batch if it has the same type and starts where that one ends.
+    pub fn queue(batch_requests: &mut Vec<BatchRequest>, request: BatchRequest) {
+        if let Some(last) = batch_requests.last_mut() {
+            if last.request_type == request.request_type
+                && last.offset as u64 + last.len() == request.offset as u64
+                && last.iovecs.len() + request.iovecs.len() <= MAX_BATCH_REQUEST_IOVECS
+            {
+                last.iovecs.extend(request.iovecs);
+                last.merged_user_data.push(request.user_data);
+                last.merged_user_data.extend(request.merged_user_data);
+                return;
+            }
+        }
+
+        batch_requests.push(request);
+    }
 }
 
 impl Request {
@@ -269,6 +341,8 @@ impl Request {
             writeback: true,
             aligned_operations: SmallVec::with_capacity(DEFAULT_DESCRIPTOR_VEC_SIZE),
             start: Instant::now(),
+            detect_zeroes: false,
+            unmap_zeroes: false,
         };
 
         let status_desc;
@@ -289,7 +363,12 @@ impl Request {
         } else {
             req.data_descriptors.reserve_exact(1);
             while desc.has_next() {
-                if desc.is_write_only() && req.request_type == RequestType::Out {
+                if desc.is_write_only()
+                    && matches!(
+                        req.request_type,
+                        RequestType::Out | RequestType::Discard | RequestType::WriteZeroes
+                    )
+                {
                     return Err(Error::UnexpectedWriteOnlyDescriptor);
                 }
                 if !desc.is_write_only() && req.request_type == RequestType::In {
@@ -381,12 +460,21 @@ impl Request {
                     mem.write_slice(serial, *data_addr)
                         .map_err(ExecuteError::Write)?;
                 }
+                RequestType::Discard => {
+                    return Err(ExecuteError::Unsupported(VIRTIO_BLK_T_DISCARD))
+                }
+                RequestType::WriteZeroes => {
+                    return Err(ExecuteError::Unsupported(VIRTIO_BLK_T_WRITE_ZEROES))
+                }
                 RequestType::Unsupported(t) => return Err(ExecuteError::Unsupported(t)),
             };
         }
         Ok(len)
     }
 
+    /// Submits the request, or queues it in `batch_requests` if it's a read
+    /// or a write and the disk image submits them in batches. Returns whether
+    /// the request completes asynchronously.
     pub fn execute_async<B: Bitmap + 'static>(
         &mut self,
         mem: &vm_memory::GuestMemoryMmap<B>,
@@ -394,11 +482,39 @@ impl Request {
         disk_image: &mut dyn AsyncIo,
         serial: &[u8],
         user_data: u64,
+        batch_requests: &mut Vec<BatchRequest>,
     ) -> result::Result<bool, ExecuteError> {
         let sector = self.sector;
         let request_type = self.request_type;
         let offset = (sector << SECTOR_SHIFT) as libc::off_t;
 
+        // Discards and writes of zeros don't transfer any data, the range
+        // being zeroed or deallocated by the disk image.
+        match request_type {
+            RequestType::Discard | RequestType::WriteZeroes => {
+                let (offset, length, unmap) =
+                    self.discard_write_zeroes_range(mem, disk_nsectors)?;
+                return Self::zero_range(disk_image, offset, length, unmap, user_data);
+            }
+            RequestType::Out if self.detect_zeroes && self.data_is_zero(mem)? => {
+                let length = self.data_len();
+                let top = sector
+                    .checked_add(length.div_ceil(SECTOR_SIZE))
+                    .ok_or(ExecuteError::BadRequest(Error::InvalidOffset))?;
+                if top > disk_nsectors {
+                    return Err(ExecuteError::BadRequest(Error::InvalidOffset));
+                }
+                return Self::zero_range(
+                    disk_image,
+                    offset as u64,
+                    length,
+                    self.unmap_zeroes,
+                    user_data,
+                );
+            }
+            _ => {}
+        }
+
         let mut iovecs: SmallVec<[libc::iovec; DEFAULT_DESCRIPTOR_VEC_SIZE]> =
             SmallVec::with_capacity(self.data_descriptors.len());
         for (data_addr, data_len) in &self.data_descriptors {
@@ -467,13 +583,23 @@ impl Request {
 
         // Queue operations expected to be submitted.
         match request_type {
-            RequestType::In => {
-                for (data_addr, data_len) in &self.data_descriptors {
-                    mem.get_slice(*data_addr, *data_len as usize)
-                        .map_err(ExecuteError::GetHostAddress)?
-                        .bitmap()
-                        .mark_dirty(0, *data_len as usize);
+            RequestType::In | RequestType::Out if disk_image.batch_requests_enabled() => {
+                if request_type == RequestType::In {
+                    self.mark_data_dirty(mem)?;
                 }
+                BatchRequest::queue(
+                    batch_requests,
+                    BatchRequest {
+                        offset,
+                        iovecs,
+                        user_data,
+                        request_type,
+                        merged_user_data: Vec::new(),
+                    },
+                );
+            }
+            RequestType::In => {
+                self.mark_data_dirty(mem)?;
                 disk_image
                     .read_vectored(offset, &iovecs, user_data)
                     .map_err(ExecuteError::AsyncRead)?;
@@ -501,12 +627,123 @@ impl Request {
                     .map_err(ExecuteError::Write)?;
                 return Ok(false);
             }
+            RequestType::Discard | RequestType::WriteZeroes => unreachable!(),
             RequestType::Unsupported(t) => return Err(ExecuteError::Unsupported(t)),
         }
 
         Ok(true)
     }
 
+    // Range of the disk covered by the segment of a discard or write zeroes
+    // request, in bytes, along with whether it can be deallocated. Only one
+    // segment is supported per request.
+    fn discard_write_zeroes_range<B: Bitmap + 'static>(
+        &self,
+        mem: &vm_memory::GuestMemoryMmap<B>,
+        disk_nsectors: u64,
+    ) -> result::Result<(u64, u64, bool), ExecuteError> {
+        let [(data_addr, data_len)] = self.data_descriptors[..] else {
+            return Err(ExecuteError::BadRequest(Error::TooManyDescriptors));
+        };
+        if data_len as usize != std::mem::size_of::<DiscardWriteZeroesSegment>() {
+            return Err(ExecuteError::BadRequest(Error::TooManySegments));
+        }
+
+        let segment: DiscardWriteZeroesSegment = mem
+            .read_obj(data_addr)
+            .map_err(|e| ExecuteError::BadRequest(Error::GuestMemory(e)))?;
+        let top = segment
+            .sector
+            .checked_add(u64::from(segment.num_sectors))
+            .ok_or(ExecuteError::BadRequest(Error::InvalidOffset))?;
+        if top > disk_nsectors {
+            return Err(ExecuteError::BadRequest(Error::InvalidOffset));
+        }
+
+        let unmap = self.request_type == RequestType::Discard
+            || segment.flags & VIRTIO_BLK_WRITE_ZEROES_FLAG_UNMAP != 0;
+
+        Ok((
+            segment.sector << SECTOR_SHIFT,
+            u64::from(segment.num_sectors) << SECTOR_SHIFT,
+            unmap,
+        ))
+    }
+
+    // Deallocates the range if `unmap` is set, otherwise zeroes it.
+    fn zero_range(
+        disk_image: &mut dyn AsyncIo,
+        offset: u64,
+        length: u64,
+        unmap: bool,
+        user_data: u64,
+    ) -> result::Result<bool, ExecuteError> {
+        if length == 0 {
+            return Ok(false);
+        }
+
+        if unmap {
+            disk_image
+                .punch_hole(offset, length, user_data)
+                .map_err(ExecuteError::AsyncPunchHole)?;
+        } else {
+            disk_image
+                .write_zeroes(offset, length, user_data)
+                .map_err(ExecuteError::AsyncWriteZeroes)?;
+        }
+
+        Ok(true)
+    }
+
+    // Whether the data written by the request is all zeros.
+    fn data_is_zero<B: Bitmap + 'static>(
+        &self,
+        mem: &vm_memory::GuestMemoryMmap<B>,
+    ) -> result::Result<bool, ExecuteError> {
+        let mut buf = [0u8; 4096];
+        for (data_addr, data_len) in &self.data_descriptors {
+            let data_len = *data_len as usize;
+            // Check the whole range is valid guest memory first.
+            mem.get_slice(*data_addr, data_len)
+                .map_err(ExecuteError::GetHostAddress)?;
+
+            let mut offset = 0;
+            while offset < data_len {
+                let len = cmp::min(buf.len(), data_len - offset);
+                mem.read_slice(&mut buf[..len], GuestAddress(data_addr.0 + offset as u64))
+                    .map_err(ExecuteError::GetHostAddress)?;
+                if buf[..len].iter().any(|b| *b != 0) {
+                    return Ok(false);
+                }
+                offset += len;
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn mark_data_dirty<B: Bitmap + 'static>(
+        &self,
+        mem: &vm_memory::GuestMemoryMmap<B>,
+    ) -> result::Result<(), ExecuteError> {
+        for (data_addr, data_len) in &self.data_descriptors {
+            mem.get_slice(*data_addr, *data_len as usize)
+                .map_err(ExecuteError::GetHostAddress)?
+                .bitmap()
+                .mark_dirty(0, *data_len as usize);
+        }
+
+        Ok(())
+    }
+
+    /// Length of the data transferred by the request.
+    pub fn data_len(&self) -> u64 {
+        self.data_descriptors
+            .iter()
+            .map(|(_, data_len)| u64::from(*data_len))
+            .sum()
+    }
+
     pub fn complete_async(&mut self) -> result::Result<(), Error> {
         for aligned_operation in self.aligned_operations.drain(..) {
             // We need to perform the copy after the data has been read inside
@@ -540,6 +777,13 @@ impl Request {
     pub fn set_writeback(&mut self, writeback: bool) {
         self.writeback = writeback
     }
+
+    /// Turns the writes of zeros into write zeroes requests, deallocating
+    /// the range if `unmap` is set.
+    pub fn set_detect_zeroes(&mut self, detect_zeroes: bool, unmap: bool) {
+        self.detect_zeroes = detect_zeroes;
+        self.unmap_zeroes = unmap;
+    }
 }
 
 #[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
@@ -744,16 +988,231 @@ where
         Ok(())
     }
 
+    fn punch_hole_sync(
+        &mut self,
+        offset: u64,
+        length: u64,
+        user_data: u64,
+        eventfd: &EventFd,
+        completion_list: &mut VecDeque<(u64, i32)>,
+    ) -> AsyncIoResult<()>
+    where
+        F: PunchHole,
+    {
+        self.file()
+            .punch_hole(offset, length)
+            .map_err(AsyncIoError::PunchHole)?;
+
+        completion_list.push_back((user_data, 0));
+        eventfd.write(1).unwrap();
+
+        Ok(())
+    }
+
+    fn write_zeroes_sync(
+        &mut self,
+        offset: u64,
+        length: u64,
+        user_data: u64,
+        eventfd: &EventFd,
+        completion_list: &mut VecDeque<(u64, i32)>,
+    ) -> AsyncIoResult<()>
+    where
+        F: WriteZeroesAt,
+    {
+        self.file()
+            .write_all_zeroes_at(offset, length as usize)
+            .map_err(AsyncIoError::WriteZeroes)?;
+
+        completion_list.push_back((user_data, 0));
+        eventfd.write(1).unwrap();
+
+        Ok(())
+    }
+
     fn file(&mut self) -> MutexGuard<'_, F>;
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub enum ImageType {
+    #[serde(rename = "vhd")]
     FixedVhd,
+    #[serde(rename = "qcow2")]
     Qcow2,
+    #[serde(rename = "raw")]
     Raw,
+    #[serde(rename = "vhdx")]
     Vhdx,
 }
 
+impl std::fmt::Display for ImageType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let s = match self {
+            ImageType::FixedVhd => "vhd",
+            ImageType::Qcow2 => "qcow2",
+            ImageType::Raw => "raw",
+            ImageType::Vhdx => "vhdx",
+        };
+        write!(f, "{s}")
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ParseImageTypeError {
+    #[error("Invalid image type: {0}")]
+    InvalidValue(String),
+}
+
+impl std::str::FromStr for ImageType {
+    type Err = ParseImageTypeError;
+
+    fn from_str(s: &str) -> result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "vhd" => Ok(ImageType::FixedVhd),
+            "qcow2" => Ok(ImageType::Qcow2),
+            "raw" => Ok(ImageType::Raw),
+            "vhdx" => Ok(ImageType::Vhdx),
+            _ => Err(ParseImageTypeError::InvalidValue(s.to_owned())),
+        }
+    }
+}
+
+/// Caching of the writes to a disk image by the host.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum CacheMode {
+    /// Writes go through the host page cache, and are only made durable
+    /// when the guest flushes its write cache.
+    #[default]
+    #[serde(rename = "writeback")]
+    Writeback,
+    /// Writes go through the host page cache, and are made durable before
+    /// being completed. The guest sees a disk without a write cache.
+    #[serde(rename = "writethrough")]
+    Writethrough,
+    /// Writes bypass the host page cache, and are only made durable when the
+    /// guest flushes its write cache.
+    #[serde(rename = "none")]
+    Direct,
+    /// Writes go through the host page cache, and the flushes of the guest
+    /// are ignored. Data may be lost if the host crashes.
+    #[serde(rename = "unsafe")]
+    Unsafe,
+}
+
+impl std::fmt::Display for CacheMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let s = match self {
+            CacheMode::Writeback => "writeback",
+            CacheMode::Writethrough => "writethrough",
+            CacheMode::Direct => "none",
+            CacheMode::Unsafe => "unsafe",
+        };
+        write!(f, "{s}")
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ParseCacheModeError {
+    #[error("Invalid cache mode: {0}")]
+    InvalidValue(String),
+}
+
+impl std::str::FromStr for CacheMode {
+    type Err = ParseCacheModeError;
+
+    fn from_str(s: &str) -> result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "writeback" => Ok(CacheMode::Writeback),
+            "writethrough" => Ok(CacheMode::Writethrough),
+            "none" => Ok(CacheMode::Direct),
+            "unsafe" => Ok(CacheMode::Unsafe),
+            _ => Err(ParseCacheModeError::InvalidValue(s.to_owned())),
+        }
+    }
+}
+
+/// I/O scheduling class of the requests of a disk, see ioprio_set(2).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum IoClass {
+    /// Served ahead of any other class. Requires CAP_SYS_ADMIN.
+    #[serde(rename = "rt")]
+    RealTime,
+    /// Served according to the priority level, the default of the threads.
+    #[serde(rename = "be")]
+    BestEffort,
+    /// Only served when no other request is pending on the device.
+    #[serde(rename = "idle")]
+    Idle,
+}
+
+impl std::fmt::Display for IoClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let s = match self {
+            IoClass::RealTime => "rt",
+            IoClass::BestEffort => "be",
+            IoClass::Idle => "idle",
+        };
+        write!(f, "{s}")
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ParseIoClassError {
+    #[error("Invalid I/O class: {0}")]
+    InvalidValue(String),
+}
+
+impl std::str::FromStr for IoClass {
+    type Err = ParseIoClassError;
+
+    fn from_str(s: &str) -> result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "rt" => Ok(IoClass::RealTime),
+            "be" => Ok(IoClass::BestEffort),
+            "idle" => Ok(IoClass::Idle),
+            _ => Err(ParseIoClassError::InvalidValue(s.to_owned())),
+        }
+    }
+}
+
+const IOPRIO_CLASS_SHIFT: u16 = 13;
+const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+/// Highest priority level of the real-time and best-effort classes, the
+/// lowest level being the highest priority.
+pub const IOPRIO_MAX_LEVEL: u8 = 7;
+/// Level of the best-effort class the threads get by default.
+pub const IOPRIO_DEFAULT_LEVEL: u8 = 4;
+
+/// Encodes an I/O class and a priority level within it as expected by
+/// ioprio_set(2) and the io_uring requests.
+pub fn io_priority(class: IoClass, level: u8) -> u16 {
+    let (class, level) = match class {
+        IoClass::RealTime => (1, level),
+        IoClass::BestEffort => (2, level),
+        // The idle class has no levels.
+        IoClass::Idle => (3, 0),
+    };
+    (class << IOPRIO_CLASS_SHIFT) | level as u16
+}
+
+/// Sets the I/O priority of the calling thread.
+pub fn set_thread_io_priority(ioprio: u16) -> io::Result<()> {
+    // SAFETY: FFI call with valid arguments, 0 designating the calling thread
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_ioprio_set,
+            IOPRIO_WHO_PROCESS,
+            0,
+            ioprio as libc::c_int,
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
 const QCOW_MAGIC: u32 = 0x5146_49fb;
 const VHDX_SIGN: u64 = 0x656C_6966_7864_6876;
 
@@ -877,3 +1336,112 @@ impl DiskTopology {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn batch_request(request_type: RequestType, offset: libc::off_t, len: usize) -> BatchRequest {
+        BatchRequest {
+            offset,
+            iovecs: SmallVec::from_elem(
+                libc::iovec {
+                    iov_base: std::ptr::null_mut(),
+                    iov_len: len,
+                },
+                1,
+            ),
+            user_data: offset as u64,
+            request_type,
+            merged_user_data: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_batch_request_merging() {
+        let mut batch_requests = Vec::new();
+        BatchRequest::queue(
+            &mut batch_requests,
+            batch_request(RequestType::Out, 0, 4096),
+        );
+        BatchRequest::queue(
+            &mut batch_requests,
+            batch_request(RequestType::Out, 4096, 512),
+        );
+        BatchRequest::queue(
+            &mut batch_requests,
+            batch_request(RequestType::Out, 4608, 512),
+        );
+        // Not contiguous
+        BatchRequest::queue(
+            &mut batch_requests,
+            batch_request(RequestType::Out, 8192, 512),
+        );
+        // Not the same type
+        BatchRequest::queue(
+            &mut batch_requests,
+            batch_request(RequestType::In, 8704, 512),
+        );
+
+        assert_eq!(batch_requests.len(), 3);
+        assert_eq!(batch_requests[0].iovecs.len(), 3);
+        assert_eq!(batch_requests[0].len(), 5120);
+        assert_eq!(batch_requests[0].merged_user_data, vec![4096, 4608]);
+        assert!(batch_requests[1].merged_user_data.is_empty());
+        assert!(batch_requests[2].merged_user_data.is_empty());
+    }
+
+    #[test]
+    fn test_io_priority() {
+        assert_eq!(io_priority(IoClass::RealTime, 0), 0x2000);
+        assert_eq!(
+            io_priority(IoClass::BestEffort, IOPRIO_DEFAULT_LEVEL),
+            0x4004
+        );
+        assert_eq!(io_priority(IoClass::Idle, 7), 0x6000);
+    }
+
+    #[test]
+    fn test_image_type_from_str() {
+        for image_type in [
+            ImageType::FixedVhd,
+            ImageType::Qcow2,
+            ImageType::Raw,
+            ImageType::Vhdx,
+        ] {
+            assert_eq!(
+                image_type.to_string().parse::<ImageType>().unwrap(),
+                image_type
+            );
+        }
+        assert_eq!("QCOW2".parse::<ImageType>().unwrap(), ImageType::Qcow2);
+        "vmdk".parse::<ImageType>().unwrap_err();
+        "".parse::<ImageType>().unwrap_err();
+    }
+
+    #[test]
+    fn test_cache_mode_from_str() {
+        for cache in [
+            CacheMode::Writeback,
+            CacheMode::Writethrough,
+            CacheMode::Direct,
+            CacheMode::Unsafe,
+        ] {
+            assert_eq!(cache.to_string().parse::<CacheMode>().unwrap(), cache);
+        }
+        assert_eq!("none".parse::<CacheMode>().unwrap(), CacheMode::Direct);
+        "directsync".parse::<CacheMode>().unwrap_err();
+    }
+
+    #[test]
+    fn test_discard_write_zeroes_segment_layout() {
+        // As defined by struct virtio_blk_discard_write_zeroes.
+        assert_eq!(std::mem::size_of::<DiscardWriteZeroesSegment>(), 16);
+        assert_eq!(std::mem::offset_of!(DiscardWriteZeroesSegment, sector), 0);
+        assert_eq!(
+            std::mem::offset_of!(DiscardWriteZeroesSegment, num_sectors),
+            8
+        );
+        assert_eq!(std::mem::offset_of!(DiscardWriteZeroesSegment, flags), 12);
+    }
+}
@@ -0,0 +1,610 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! LUKS1 encrypted disk images.
+//!
+//! The payload of the image is decrypted and encrypted on the fly with the
+//! master key unlocked from one of the key slots of the header, so that the
+//! guest sees the plain content of the disk. Only the `aes-xts-plain64`
+//! cipher, the default of `cryptsetup`, is supported.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::{AsRawFd, RawFd};
+
+use aes::cipher::consts::U16;
+use aes::cipher::{BlockCipher, BlockDecrypt, BlockEncrypt, BlockSizeUser, KeyInit};
+use aes::{Aes128, Aes256};
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha512};
+use thiserror::Error;
+use xts_mode::{get_tweak_default, Xts128};
+use zeroize::Zeroizing;
+
+const LUKS_MAGIC: &[u8; 6] = b"LUKS\xba\xbe";
+const LUKS_VERSION: u16 = 1;
+const LUKS_HEADER_SIZE: usize = 592;
+const LUKS_NUM_KEYS: usize = 8;
+const LUKS_KEY_SLOT_OFFSET: usize = 208;
+const LUKS_KEY_SLOT_SIZE: usize = 48;
+const LUKS_KEY_ENABLED: u32 = 0x00ac_71f3;
+const LUKS_DIGEST_SIZE: usize = 20;
+const LUKS_SALT_SIZE: usize = 32;
+// Number of stripes of the key material written by cryptsetup.
+const LUKS_MAX_STRIPES: u32 = 4000;
+const SECTOR_SIZE: u64 = 512;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Failed reading the LUKS header")]
+    ReadHeader(#[source] io::Error),
+    #[error("Image is not a LUKS image")]
+    InvalidMagic,
+    #[error("Unsupported LUKS version {0}")]
+    UnsupportedVersion(u16),
+    #[error("Unsupported LUKS cipher {0}")]
+    UnsupportedCipher(String),
+    #[error("Unsupported LUKS hash {0}")]
+    UnsupportedHash(String),
+    #[error("Failed reading the LUKS key material")]
+    ReadKeyMaterial(#[source] io::Error),
+    #[error("No LUKS key slot is unlocked by the key")]
+    InvalidKey,
+    #[error("Failed getting the LUKS image size")]
+    Size(#[source] io::Error),
+    #[error("LUKS payload offset {0} is beyond the end of the image")]
+    InvalidPayloadOffset(u64),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Clone, Copy)]
+enum Hash {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl Hash {
+    fn from_spec(spec: &str) -> Result<Self> {
+        match spec {
+            "sha1" => Ok(Hash::Sha1),
+            "sha256" => Ok(Hash::Sha256),
+            "sha512" => Ok(Hash::Sha512),
+            _ => Err(Error::UnsupportedHash(spec.to_string())),
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Hash::Sha1 => 20,
+            Hash::Sha256 => 32,
+            Hash::Sha512 => 64,
+        }
+    }
+
+    fn pbkdf2(self, password: &[u8], salt: &[u8], iterations: u32, out: &mut [u8]) {
+        match self {
+            Hash::Sha1 => pbkdf2::pbkdf2_hmac::<Sha1>(password, salt, iterations, out),
+            Hash::Sha256 => pbkdf2::pbkdf2_hmac::<Sha256>(password, salt, iterations, out),
+            Hash::Sha512 => pbkdf2::pbkdf2_hmac::<Sha512>(password, salt, iterations, out),
+        }
+    }
+
+    // Hashes a block of key material along with its index.
+    fn hash_block(self, index: u32, data: &[u8]) -> Vec<u8> {
+        fn hash<D: Digest>(index: u32, data: &[u8]) -> Vec<u8> {
+            D::new()
+                .chain_update(index.to_be_bytes())
+                .chain_update(data)
+                .finalize()
+                .to_vec()
+        }
+
+        match self {
+            Hash::Sha1 => hash::<Sha1>(index, data),
+            Hash::Sha256 => hash::<Sha256>(index, data),
+            Hash::Sha512 => hash::<Sha512>(index, data),
+        }
+    }
+}
+
+fn xor(dst: &mut [u8], src: &[u8]) {
+    for (d, s) in dst.iter_mut().zip(src) {
+        *d ^= s;
+    }
+}
+
+// Diffuses the content of `data` by hashing each of its blocks of the size
+// of the digest.
+fn diffuse(hash: Hash, data: &mut [u8]) {
+    for (index, block) in data.chunks_mut(hash.size()).enumerate() {
+        let digest = hash.hash_block(index as u32, block);
+        let len = block.len();
+        block.copy_from_slice(&digest[..len]);
+    }
+}
+
+// Merges the stripes of the anti-forensic split key material into the key.
+fn af_merge(hash: Hash, material: &[u8], key_bytes: usize) -> Zeroizing<Vec<u8>> {
+    let mut key = Zeroizing::new(vec![0u8; key_bytes]);
+    let mut stripes = material.chunks_exact(key_bytes).peekable();
+    while let Some(stripe) = stripes.next() {
+        xor(&mut key, stripe);
+        if stripes.peek().is_some() {
+            diffuse(hash, &mut key);
+        }
+    }
+
+    key
+}
+
+trait SectorCipher: Send {
+    fn encrypt_sector(&self, sector: u64, data: &mut [u8]);
+    fn decrypt_sector(&self, sector: u64, data: &mut [u8]);
+}
+
+// AES-XTS with the sector number as tweak, the plain64 IV, the first half
+// of the key being the data key and the second half the tweak key.
+fn new_xts<C>(key: &[u8]) -> Xts128<C>
+where
+    C: BlockCipher + BlockEncrypt + BlockDecrypt + BlockSizeUser<BlockSize = U16> + KeyInit,
+{
+    let (data_key, tweak_key) = key.split_at(key.len() / 2);
+    Xts128::new(
+        C::new_from_slice(data_key).unwrap(),
+        C::new_from_slice(tweak_key).unwrap(),
+    )
+}
+
+impl<C> SectorCipher for Xts128<C>
+where
+    C: BlockCipher + BlockEncrypt + BlockDecrypt + BlockSizeUser<BlockSize = U16> + Send,
+{
+    fn encrypt_sector(&self, sector: u64, data: &mut [u8]) {
+        Xts128::encrypt_sector(self, data, get_tweak_default(sector.into()))
+    }
+
+    fn decrypt_sector(&self, sector: u64, data: &mut [u8]) {
+        Xts128::decrypt_sector(self, data, get_tweak_default(sector.into()))
+    }
+}
+
+#[derive(Clone, Copy)]
+enum CipherSpec {
+    Aes128Xts,
+    Aes256Xts,
+}
+
+impl CipherSpec {
+    fn parse(name: &str, mode: &str, key_bytes: usize) -> Result<Self> {
+        match (name, mode, key_bytes) {
+            ("aes", "xts-plain64", 32) => Ok(CipherSpec::Aes128Xts),
+            ("aes", "xts-plain64", 64) => Ok(CipherSpec::Aes256Xts),
+            _ => Err(Error::UnsupportedCipher(format!(
+                "{name}-{mode} with a {key_bytes} bytes key"
+            ))),
+        }
+    }
+
+    fn new_cipher(self, key: &[u8]) -> Box<dyn SectorCipher> {
+        match self {
+            CipherSpec::Aes128Xts => Box::new(new_xts::<Aes128>(key)),
+            CipherSpec::Aes256Xts => Box::new(new_xts::<Aes256>(key)),
+        }
+    }
+}
+
+struct KeySlot {
+    iterations: u32,
+    salt: [u8; LUKS_SALT_SIZE],
+    key_material_offset: u64,
+    stripes: u32,
+}
+
+struct LuksHeader {
+    cipher: CipherSpec,
+    hash: Hash,
+    payload_offset: u64,
+    key_bytes: usize,
+    mk_digest: [u8; LUKS_DIGEST_SIZE],
+    mk_digest_salt: [u8; LUKS_SALT_SIZE],
+    mk_digest_iterations: u32,
+    key_slots: Vec<KeySlot>,
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_string(buf: &[u8]) -> String {
+    let len = buf.iter().position(|c| *c == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
+impl LuksHeader {
+    fn read(file: &File) -> Result<Self> {
+        let mut buf = [0u8; LUKS_HEADER_SIZE];
+        file.read_exact_at(&mut buf, 0).map_err(Error::ReadHeader)?;
+
+        if &buf[0..6] != LUKS_MAGIC {
+            return Err(Error::InvalidMagic);
+        }
+
+        let version = u16::from_be_bytes([buf[6], buf[7]]);
+        if version != LUKS_VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+
+        let key_bytes = read_u32(&buf, 108) as usize;
+        let cipher = CipherSpec::parse(
+            &read_string(&buf[8..40]),
+            &read_string(&buf[40..72]),
+            key_bytes,
+        )?;
+        let hash = Hash::from_spec(&read_string(&buf[72..104]))?;
+
+        // Key slots with an unexpected number of stripes are unusable.
+        let key_slots = (0..LUKS_NUM_KEYS)
+            .map(|i| &buf[LUKS_KEY_SLOT_OFFSET + i * LUKS_KEY_SLOT_SIZE..])
+            .filter(|slot| read_u32(slot, 0) == LUKS_KEY_ENABLED)
+            .map(|slot| KeySlot {
+                iterations: read_u32(slot, 4),
+                salt: slot[8..40].try_into().unwrap(),
+                key_material_offset: u64::from(read_u32(slot, 40)) * SECTOR_SIZE,
+                stripes: read_u32(slot, 44),
+            })
+            .filter(|slot| (1..=LUKS_MAX_STRIPES).contains(&slot.stripes))
+            .collect();
+
+        Ok(LuksHeader {
+            cipher,
+            hash,
+            payload_offset: u64::from(read_u32(&buf, 104)) * SECTOR_SIZE,
+            key_bytes,
+            mk_digest: buf[112..132].try_into().unwrap(),
+            mk_digest_salt: buf[132..164].try_into().unwrap(),
+            mk_digest_iterations: read_u32(&buf, 164),
+            key_slots,
+        })
+    }
+
+    // Unlocks the master key from the first key slot unlocked by `key`.
+    fn unlock(&self, file: &File, key: &[u8]) -> Result<Zeroizing<Vec<u8>>> {
+        for slot in self.key_slots.iter() {
+            let mut slot_key = Zeroizing::new(vec![0u8; self.key_bytes]);
+            self.hash
+                .pbkdf2(key, &slot.salt, slot.iterations, &mut slot_key);
+
+            let material_len = self.key_bytes * slot.stripes as usize;
+            let mut material = Zeroizing::new(vec![
+                0u8;
+                material_len
+                    .next_multiple_of(SECTOR_SIZE as usize)
+            ]);
+            file.read_exact_at(&mut material, slot.key_material_offset)
+                .map_err(Error::ReadKeyMaterial)?;
+            let cipher = self.cipher.new_cipher(&slot_key);
+            for (sector, data) in material.chunks_exact_mut(SECTOR_SIZE as usize).enumerate() {
+                cipher.decrypt_sector(sector as u64, data);
+            }
+
+            let master_key = af_merge(self.hash, &material[..material_len], self.key_bytes);
+            let mut digest = [0u8; LUKS_DIGEST_SIZE];
+            self.hash.pbkdf2(
+                &master_key,
+                &self.mk_digest_salt,
+                self.mk_digest_iterations,
+                &mut digest,
+            );
+            if digest == self.mk_digest {
+                return Ok(master_key);
+            }
+        }
+
+        Err(Error::InvalidKey)
+    }
+}
+
+/// Plain content of the payload of a LUKS image.
+pub struct LuksFile {
+    file: File,
+    cipher: Box<dyn SectorCipher>,
+    payload_offset: u64,
+    size: u64,
+    position: u64,
+}
+
+impl LuksFile {
+    /// Opens a LUKS image, unlocking its master key with `key`, the
+    /// passphrase of one of its key slots.
+    pub fn new(mut file: File, key: &[u8]) -> Result<Self> {
+        let header = LuksHeader::read(&file)?;
+        let master_key = header.unlock(&file, key)?;
+
+        let image_size = file.seek(SeekFrom::End(0)).map_err(Error::Size)?;
+        let size = image_size
+            .checked_sub(header.payload_offset)
+            .ok_or(Error::InvalidPayloadOffset(header.payload_offset))?
+            / SECTOR_SIZE
+            * SECTOR_SIZE;
+
+        Ok(LuksFile {
+            file,
+            cipher: header.cipher.new_cipher(&master_key),
+            payload_offset: header.payload_offset,
+            size,
+            position: 0,
+        })
+    }
+
+    /// Size of the plain content of the image.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    // Reads and decrypts the sectors covering `len` bytes from `offset`,
+    // returning them along with the offset of the data in the first one.
+    fn read_sectors(&self, offset: u64, len: usize) -> io::Result<(Vec<u8>, usize)> {
+        let first_sector = offset / SECTOR_SIZE;
+        let end_sector = (offset + len as u64).div_ceil(SECTOR_SIZE);
+        let mut data = vec![0u8; ((end_sector - first_sector) * SECTOR_SIZE) as usize];
+        self.file
+            .read_exact_at(&mut data, self.payload_offset + first_sector * SECTOR_SIZE)?;
+        for (i, sector) in data.chunks_exact_mut(SECTOR_SIZE as usize).enumerate() {
+            self.cipher.decrypt_sector(first_sector + i as u64, sector);
+        }
+
+        Ok((data, (offset - first_sector * SECTOR_SIZE) as usize))
+    }
+}
+
+impl Read for LuksFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf
+            .len()
+            .min(self.size.saturating_sub(self.position) as usize);
+        if len == 0 {
+            return Ok(0);
+        }
+
+        let (data, start) = self.read_sectors(self.position, len)?;
+        buf[..len].copy_from_slice(&data[start..start + len]);
+        self.position += len as u64;
+
+        Ok(len)
+    }
+}
+
+impl Write for LuksFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf
+            .len()
+            .min(self.size.saturating_sub(self.position) as usize);
+        if len == 0 {
+            return Ok(0);
+        }
+
+        // The sectors only partially written keep the rest of their content.
+        let first_sector = self.position / SECTOR_SIZE;
+        let (mut data, start) = if self.position % SECTOR_SIZE == 0 && len as u64 % SECTOR_SIZE == 0
+        {
+            (vec![0u8; len], 0)
+        } else {
+            self.read_sectors(self.position, len)?
+        };
+        data[start..start + len].copy_from_slice(&buf[..len]);
+        for (i, sector) in data.chunks_exact_mut(SECTOR_SIZE as usize).enumerate() {
+            self.cipher.encrypt_sector(first_sector + i as u64, sector);
+        }
+        self.file
+            .write_all_at(&data, self.payload_offset + first_sector * SECTOR_SIZE)?;
+        self.position += len as u64;
+
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.sync_all()
+    }
+}
+
+impl Seek for LuksFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.size.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        }
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid seek offset"))?;
+        self.position = position;
+
+        Ok(position)
+    }
+}
+
+impl AsRawFd for LuksFile {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vmm_sys_util::tempfile::TempFile;
+
+    use super::*;
+
+    const KEY_BYTES: usize = 64;
+    const STRIPES: usize = 4;
+    const ITERATIONS: u32 = 10;
+    const PASSPHRASE: &[u8] = b"passphrase";
+
+    fn sector_pattern() -> Vec<u8> {
+        (0..SECTOR_SIZE).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_xts() {
+        // Reference ciphertexts from the AES-XTS implementation of OpenSSL.
+        for (key_len, first_block, last_block) in [
+            (
+                64,
+                "03d14e1053a7bcf955ad772d3a22b244",
+                "7ac81d1a488afb0a95b625e7cc005757",
+            ),
+            (
+                32,
+                "9986f2dca81ff5a9f721cbf2c527cd25",
+                "7f44559cbd34bf73559a61999198d0fe",
+            ),
+        ] {
+            let key: Vec<u8> = (0..key_len as u8).collect();
+            let cipher = CipherSpec::parse("aes", "xts-plain64", key_len)
+                .unwrap()
+                .new_cipher(&key);
+
+            let mut data = sector_pattern();
+            cipher.encrypt_sector(3, &mut data);
+            assert_eq!(hex(&data[..16]), first_block);
+            assert_eq!(hex(&data[496..]), last_block);
+
+            cipher.decrypt_sector(3, &mut data);
+            assert_eq!(data, sector_pattern());
+        }
+    }
+
+    fn hex(data: &[u8]) -> String {
+        data.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    // Creates a LUKS1 image holding 4 sectors encrypted with `master_key`,
+    // and a single key slot unlocked by PASSPHRASE.
+    fn create_image(master_key: &[u8], payload: &[u8]) -> TempFile {
+        let hash = Hash::Sha256;
+        let key_material_offset = 2;
+        let payload_offset = 4;
+
+        let mut header = vec![0u8; LUKS_HEADER_SIZE];
+        header[0..6].copy_from_slice(LUKS_MAGIC);
+        header[6..8].copy_from_slice(&LUKS_VERSION.to_be_bytes());
+        header[8..11].copy_from_slice(b"aes");
+        header[40..51].copy_from_slice(b"xts-plain64");
+        header[72..78].copy_from_slice(b"sha256");
+        header[104..108].copy_from_slice(&(payload_offset as u32).to_be_bytes());
+        header[108..112].copy_from_slice(&(KEY_BYTES as u32).to_be_bytes());
+        let digest_salt = [0x11; LUKS_SALT_SIZE];
+        hash.pbkdf2(master_key, &digest_salt, ITERATIONS, &mut header[112..132]);
+        header[132..164].copy_from_slice(&digest_salt);
+        header[164..168].copy_from_slice(&ITERATIONS.to_be_bytes());
+
+        let slot = &mut header[LUKS_KEY_SLOT_OFFSET..LUKS_KEY_SLOT_OFFSET + LUKS_KEY_SLOT_SIZE];
+        let slot_salt = [0x22; LUKS_SALT_SIZE];
+        slot[0..4].copy_from_slice(&LUKS_KEY_ENABLED.to_be_bytes());
+        slot[4..8].copy_from_slice(&ITERATIONS.to_be_bytes());
+        slot[8..40].copy_from_slice(&slot_salt);
+        slot[40..44].copy_from_slice(&(key_material_offset as u32).to_be_bytes());
+        slot[44..48].copy_from_slice(&(STRIPES as u32).to_be_bytes());
+
+        // Split the master key, the last stripe being derived from the
+        // other ones.
+        let mut material = vec![0u8; SECTOR_SIZE as usize];
+        let mut merged = vec![0u8; KEY_BYTES];
+        for i in 0..STRIPES - 1 {
+            let stripe = &mut material[i * KEY_BYTES..(i + 1) * KEY_BYTES];
+            stripe.fill(i as u8 + 1);
+            xor(&mut merged, stripe);
+            diffuse(hash, &mut merged);
+        }
+        xor(&mut merged, master_key);
+        material[(STRIPES - 1) * KEY_BYTES..STRIPES * KEY_BYTES].copy_from_slice(&merged);
+        let mut slot_key = vec![0u8; KEY_BYTES];
+        hash.pbkdf2(PASSPHRASE, &slot_salt, ITERATIONS, &mut slot_key);
+        CipherSpec::Aes256Xts
+            .new_cipher(&slot_key)
+            .encrypt_sector(0, &mut material);
+
+        let mut payload = payload.to_vec();
+        let cipher = CipherSpec::Aes256Xts.new_cipher(master_key);
+        for (i, sector) in payload.chunks_exact_mut(SECTOR_SIZE as usize).enumerate() {
+            cipher.encrypt_sector(i as u64, sector);
+        }
+
+        let image = TempFile::new().unwrap();
+        let file = image.as_file();
+        file.write_all_at(&header, 0).unwrap();
+        file.write_all_at(&material, key_material_offset * SECTOR_SIZE)
+            .unwrap();
+        file.write_all_at(&payload, payload_offset * SECTOR_SIZE)
+            .unwrap();
+        image
+    }
+
+    #[test]
+    fn test_luks_header_fixture() {
+        // Header and first key slot of an aes-xts-plain64 image with a 256
+        // bits key, see test_data/luks/README.md.
+        let image = TempFile::new().unwrap();
+        image
+            .as_file()
+            .write_all_at(include_bytes!("../../test_data/luks/luks1-header.img"), 0)
+            .unwrap();
+
+        let header = LuksHeader::read(image.as_file()).unwrap();
+        assert!(matches!(header.cipher, CipherSpec::Aes128Xts));
+        assert_eq!(header.payload_offset, 4096 * SECTOR_SIZE);
+        assert_eq!(header.key_slots.len(), 1);
+        assert_eq!(header.key_slots[0].stripes, LUKS_MAX_STRIPES);
+
+        let master_key = header.unlock(image.as_file(), b"cloud-hypervisor").unwrap();
+        assert_eq!(*master_key, (0x40..0x60).collect::<Vec<u8>>());
+        assert!(matches!(
+            header.unlock(image.as_file(), b"wrong"),
+            Err(Error::InvalidKey)
+        ));
+    }
+
+    #[test]
+    fn test_luks_file() {
+        let master_key: Vec<u8> = (0..KEY_BYTES as u8).map(|i| i.wrapping_mul(7)).collect();
+        let payload = sector_pattern().repeat(4);
+        let image = create_image(&master_key, &payload);
+
+        let file = || image.as_file().try_clone().unwrap();
+        assert!(matches!(
+            LuksFile::new(file(), b"wrong"),
+            Err(Error::InvalidKey)
+        ));
+
+        let mut luks_file = LuksFile::new(file(), PASSPHRASE).unwrap();
+        assert_eq!(luks_file.size(), payload.len() as u64);
+        let mut data = vec![0u8; payload.len()];
+        luks_file.read_exact(&mut data).unwrap();
+        assert_eq!(data, payload);
+
+        // Write across the boundary of two sectors
+        let mut expected = payload.clone();
+        expected[500..600].fill(0xaa);
+        luks_file.seek(SeekFrom::Start(500)).unwrap();
+        luks_file.write_all(&[0xaa; 100]).unwrap();
+        luks_file.rewind().unwrap();
+        luks_file.read_exact(&mut data).unwrap();
+        assert_eq!(data, expected);
+
+        // The image holds the encrypted data
+        let mut sector = vec![0u8; SECTOR_SIZE as usize];
+        file().read_exact_at(&mut sector, 5 * SECTOR_SIZE).unwrap();
+        assert_ne!(sector, expected[512..1024]);
+        CipherSpec::Aes256Xts
+            .new_cipher(&master_key)
+            .decrypt_sector(1, &mut sector);
+        assert_eq!(sector, expected[512..1024]);
+
+        // Nothing is read or written past the end of the payload
+        luks_file.seek(SeekFrom::End(0)).unwrap();
+        assert_eq!(luks_file.read(&mut data).unwrap(), 0);
+        assert_eq!(luks_file.write(&data).unwrap(), 0);
+    }
+}
@@ -0,0 +1,109 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::os::fd::AsRawFd;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use vmm_sys_util::eventfd::EventFd;
+
+use crate::async_io::{AsyncIo, AsyncIoResult, BorrowedDiskFd, DiskFile, DiskFileResult};
+use crate::luks::{LuksFile, Result as LuksResult};
+use crate::AsyncAdaptor;
+
+pub struct LuksDiskSync {
+    luks_file: Arc<Mutex<LuksFile>>,
+}
+
+impl LuksDiskSync {
+    pub fn new(file: File, key: &[u8]) -> LuksResult<Self> {
+        Ok(LuksDiskSync {
+            luks_file: Arc::new(Mutex::new(LuksFile::new(file, key)?)),
+        })
+    }
+}
+
+impl DiskFile for LuksDiskSync {
+    fn size(&mut self) -> DiskFileResult<u64> {
+        Ok(self.luks_file.lock().unwrap().size())
+    }
+
+    fn new_async_io(&self, _ring_depth: u32) -> DiskFileResult<Box<dyn AsyncIo>> {
+        Ok(Box::new(LuksSync::new(self.luks_file.clone())) as Box<dyn AsyncIo>)
+    }
+
+    fn fd(&mut self) -> BorrowedDiskFd<'_> {
+        let lock = self.luks_file.lock().unwrap();
+        BorrowedDiskFd::new(lock.as_raw_fd())
+    }
+}
+
+pub struct LuksSync {
+    luks_file: Arc<Mutex<LuksFile>>,
+    eventfd: EventFd,
+    completion_list: VecDeque<(u64, i32)>,
+}
+
+impl LuksSync {
+    pub fn new(luks_file: Arc<Mutex<LuksFile>>) -> Self {
+        LuksSync {
+            luks_file,
+            eventfd: EventFd::new(libc::EFD_NONBLOCK)
+                .expect("Failed creating EventFd for LuksSync"),
+            completion_list: VecDeque::new(),
+        }
+    }
+}
+
+impl AsyncAdaptor<LuksFile> for Arc<Mutex<LuksFile>> {
+    fn file(&mut self) -> MutexGuard<'_, LuksFile> {
+        self.lock().unwrap()
+    }
+}
+
+impl AsyncIo for LuksSync {
+    fn notifier(&self) -> &EventFd {
+        &self.eventfd
+    }
+
+    fn read_vectored(
+        &mut self,
+        offset: libc::off_t,
+        iovecs: &[libc::iovec],
+        user_data: u64,
+    ) -> AsyncIoResult<()> {
+        self.luks_file.read_vectored_sync(
+            offset,
+            iovecs,
+            user_data,
+            &self.eventfd,
+            &mut self.completion_list,
+        )
+    }
+
+    fn write_vectored(
+        &mut self,
+        offset: libc::off_t,
+        iovecs: &[libc::iovec],
+        user_data: u64,
+    ) -> AsyncIoResult<()> {
+        self.luks_file.write_vectored_sync(
+            offset,
+            iovecs,
+            user_data,
+            &self.eventfd,
+            &mut self.completion_list,
+        )
+    }
+
+    fn fsync(&mut self, user_data: Option<u64>) -> AsyncIoResult<()> {
+        self.luks_file
+            .fsync_sync(user_data, &self.eventfd, &mut self.completion_list)
+    }
+
+    fn next_completed_request(&mut self) -> Option<(u64, i32)> {
+        self.completion_list.pop_front()
+    }
+}
@@ -0,0 +1,187 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Monitoring of the paths of the host block devices backing the disks.
+//!
+//! A dm-multipath device reaches its storage through the devices listed as
+//! its slaves in sysfs, while a raw block device is its own single path. The
+//! state of a path is the one of its SCSI or NVMe device, which stops being
+//! "running" or "live" when the path fails, and a path removed from the host
+//! is considered failed too.
+
+use std::collections::BTreeMap;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::{fs, io};
+
+/// Interval between two checks of the paths of the disks.
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+const SYSFS_DEV_BLOCK: &str = "/sys/dev/block";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PathEvent {
+    Failed(String),
+    Restored(String),
+}
+
+pub struct PathMonitor {
+    sysfs: PathBuf,
+    // Whether each known path is usable
+    paths: BTreeMap<String, bool>,
+}
+
+// A device without a state in sysfs, such as a virtio-blk one, can't be
+// told failed and is always considered usable.
+fn path_usable(device: &Path) -> bool {
+    match fs::read_to_string(device.join("device/state")) {
+        Ok(state) => matches!(state.trim(), "running" | "live"),
+        Err(_) => true,
+    }
+}
+
+impl PathMonitor {
+    /// Returns a monitor of the paths of the block device at `disk_path`, or
+    /// `None` if the disk isn't backed by a block device.
+    pub fn new(disk_path: &Path) -> io::Result<Option<Self>> {
+        let metadata = fs::metadata(disk_path)?;
+        if !metadata.file_type().is_block_device() {
+            return Ok(None);
+        }
+
+        let rdev = metadata.rdev();
+        let sysfs =
+            Path::new(SYSFS_DEV_BLOCK).join(format!("{}:{}", libc::major(rdev), libc::minor(rdev)));
+
+        Self::from_sysfs(fs::canonicalize(sysfs)?).map(Some)
+    }
+
+    fn from_sysfs(sysfs: PathBuf) -> io::Result<Self> {
+        let mut monitor = PathMonitor {
+            sysfs,
+            paths: BTreeMap::new(),
+        };
+        monitor.paths = monitor.path_states()?;
+
+        Ok(monitor)
+    }
+
+    fn path_states(&self) -> io::Result<BTreeMap<String, bool>> {
+        let mut paths = BTreeMap::new();
+
+        let slaves = self.sysfs.join("slaves");
+        if slaves.is_dir() {
+            for entry in fs::read_dir(slaves)? {
+                let entry = entry?;
+                let name = entry.file_name().to_string_lossy().into_owned();
+                paths.insert(name, path_usable(&entry.path()));
+            }
+        }
+
+        // Not a device-mapper one, the device is its own path.
+        if paths.is_empty() && !self.sysfs.join("dm").is_dir() {
+            let name = self
+                .sysfs
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            paths.insert(name, path_usable(&self.sysfs));
+        }
+
+        Ok(paths)
+    }
+
+    /// Checks the paths of the device, returning the ones which failed or
+    /// were restored since the previous check.
+    pub fn check(&mut self) -> io::Result<Vec<PathEvent>> {
+        let mut paths = self.path_states()?;
+
+        // Keep reporting the removed paths as failed, until they come back.
+        for path in self.paths.keys() {
+            paths.entry(path.clone()).or_insert(false);
+        }
+
+        let mut events = Vec::new();
+        for (path, usable) in paths.iter() {
+            match (self.paths.get(path).copied().unwrap_or(true), *usable) {
+                (true, false) => events.push(PathEvent::Failed(path.clone())),
+                (false, true) => events.push(PathEvent::Restored(path.clone())),
+                _ => {}
+            }
+        }
+        self.paths = paths;
+
+        Ok(events)
+    }
+
+    /// Returns whether no path is left to reach the storage.
+    pub fn all_failed(&self) -> bool {
+        !self.paths.values().any(|usable| *usable)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vmm_sys_util::tempdir::TempDir;
+
+    use super::*;
+
+    fn set_state(sysfs: &Path, path: &str, state: &str) {
+        let device = sysfs.join("slaves").join(path).join("device");
+        fs::create_dir_all(&device).unwrap();
+        fs::write(device.join("state"), format!("{state}\n")).unwrap();
+    }
+
+    #[test]
+    fn test_multipath_paths() {
+        let tmp_dir = TempDir::new_with_prefix("/tmp/ch").unwrap();
+        let sysfs = tmp_dir.as_path().join("dm-0");
+        fs::create_dir_all(sysfs.join("dm")).unwrap();
+        set_state(&sysfs, "sda", "running");
+        set_state(&sysfs, "sdb", "running");
+
+        let mut monitor = PathMonitor::from_sysfs(sysfs.clone()).unwrap();
+        assert!(monitor.check().unwrap().is_empty());
+
+        set_state(&sysfs, "sda", "transport-offline");
+        assert_eq!(
+            monitor.check().unwrap(),
+            vec![PathEvent::Failed("sda".to_string())]
+        );
+        assert!(!monitor.all_failed());
+
+        fs::remove_dir_all(sysfs.join("slaves/sdb")).unwrap();
+        assert_eq!(
+            monitor.check().unwrap(),
+            vec![PathEvent::Failed("sdb".to_string())]
+        );
+        assert!(monitor.all_failed());
+
+        set_state(&sysfs, "sdb", "running");
+        assert_eq!(
+            monitor.check().unwrap(),
+            vec![PathEvent::Restored("sdb".to_string())]
+        );
+        assert!(!monitor.all_failed());
+    }
+
+    #[test]
+    fn test_raw_device_path() {
+        let tmp_dir = TempDir::new_with_prefix("/tmp/ch").unwrap();
+        let sysfs = tmp_dir.as_path().join("sdc");
+        fs::create_dir_all(sysfs.join("device")).unwrap();
+        fs::write(sysfs.join("device/state"), "running\n").unwrap();
+
+        let mut monitor = PathMonitor::from_sysfs(sysfs.clone()).unwrap();
+        assert!(!monitor.all_failed());
+
+        fs::write(sysfs.join("device/state"), "offline\n").unwrap();
+        assert_eq!(
+            monitor.check().unwrap(),
+            vec![PathEvent::Failed("sdc".to_string())]
+        );
+        assert!(monitor.all_failed());
+    }
+}
@@ -31,7 +31,7 @@ use crate::qcow::vec_cache::{CacheMap, Cacheable, VecCache};
 use crate::BlockBackend;
 
 /// Nesting depth limit for disk formats that can open other disk files.
-const MAX_NESTING_DEPTH: u32 = 10;
+pub const MAX_NESTING_DEPTH: u32 = 10;
 
 #[sorted]
 #[derive(Debug, Error)]
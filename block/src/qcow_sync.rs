@@ -43,6 +43,10 @@ impl DiskFile for QcowDiskSync {
         let lock = self.qcow_file.lock().unwrap();
         BorrowedDiskFd::new(lock.as_raw_fd())
     }
+
+    fn supports_punch_hole(&self) -> bool {
+        true
+    }
 }
 
 pub struct QcowSync {
@@ -108,6 +112,26 @@ impl AsyncIo for QcowSync {
             .fsync_sync(user_data, &self.eventfd, &mut self.completion_list)
     }
 
+    fn punch_hole(&mut self, offset: u64, length: u64, user_data: u64) -> AsyncIoResult<()> {
+        self.qcow_file.punch_hole_sync(
+            offset,
+            length,
+            user_data,
+            &self.eventfd,
+            &mut self.completion_list,
+        )
+    }
+
+    fn write_zeroes(&mut self, offset: u64, length: u64, user_data: u64) -> AsyncIoResult<()> {
+        self.qcow_file.write_zeroes_sync(
+            offset,
+            length,
+            user_data,
+            &self.eventfd,
+            &mut self.completion_list,
+        )
+    }
+
     fn next_completed_request(&mut self) -> Option<(u64, i32)> {
         self.completion_list.pop_front()
     }
@@ -2,6 +2,7 @@
 //
 // SPDX-License-Identifier: Apache-2.0 AND BSD-3-Clause
 
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::{Error, Seek, SeekFrom};
 use std::os::unix::io::{AsRawFd, RawFd};
@@ -12,15 +13,40 @@ use vmm_sys_util::eventfd::EventFd;
 use crate::async_io::{
     AsyncIo, AsyncIoError, AsyncIoResult, BorrowedDiskFd, DiskFile, DiskFileError, DiskFileResult,
 };
-use crate::DiskTopology;
+use crate::{BatchRequest, DiskTopology, RequestType};
+
+// Time the kernel thread polling the submission queue keeps polling it
+// after the last request, before going to sleep.
+const SQPOLL_IDLE_MS: u32 = 1000;
+
+// Largest buffer io_uring registers, the guest memory regions being split
+// into buffers of this size.
+const MAX_FIXED_BUFFER_SIZE: usize = 1 << 30;
+
+/// Options of the io_uring instances cutting down the syscalls issued per
+/// request. Each of them is dropped when the host doesn't support it.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct IoUringOptions {
+    /// Have a kernel thread poll the submission queue, the requests being
+    /// submitted without any syscall.
+    pub sqpoll: bool,
+    /// Register the guest memory, for the kernel not to map the buffers of
+    /// each request.
+    pub fixed_buffers: bool,
+}
 
 pub struct RawFileDisk {
     file: File,
+    options: IoUringOptions,
 }
 
 impl RawFileDisk {
     pub fn new(file: File) -> Self {
-        RawFileDisk { file }
+        Self::with_options(file, IoUringOptions::default())
+    }
+
+    pub fn with_options(file: File, options: IoUringOptions) -> Self {
+        RawFileDisk { file, options }
     }
 }
 
@@ -33,7 +59,7 @@ impl DiskFile for RawFileDisk {
 
     fn new_async_io(&self, ring_depth: u32) -> DiskFileResult<Box<dyn AsyncIo>> {
         Ok(Box::new(
-            RawFileAsync::new(self.file.as_raw_fd(), ring_depth)
+            RawFileAsync::with_options(self.file.as_raw_fd(), ring_depth, self.options)
                 .map_err(DiskFileError::NewAsyncIo)?,
         ) as Box<dyn AsyncIo>)
     }
@@ -50,29 +76,197 @@ impl DiskFile for RawFileDisk {
     fn fd(&mut self) -> BorrowedDiskFd<'_> {
         BorrowedDiskFd::new(self.file.as_raw_fd())
     }
+
+    fn supports_punch_hole(&self) -> bool {
+        true
+    }
+
+    fn resize(&mut self, size: u64) -> DiskFileResult<()> {
+        self.file.set_len(size).map_err(DiskFileError::Resize)
+    }
+}
+
+// Builds the entry of an operation on the file, referring to it through
+// its index once registered.
+macro_rules! file_entry {
+    ($self:ident, $opcode:ident($($arg:expr),*) $(.$method:ident($value:expr))*) => {
+        if $self.fixed_file {
+            opcode::$opcode::new(types::Fixed(0), $($arg),*)$(.$method($value))*.build()
+        } else {
+            opcode::$opcode::new(types::Fd($self.fd), $($arg),*)$(.$method($value))*.build()
+        }
+    };
 }
 
 pub struct RawFileAsync {
     fd: RawFd,
     io_uring: IoUring,
     eventfd: EventFd,
+    sqpoll: bool,
+    fixed_buffers_enabled: bool,
+    fixed_file: bool,
+    // Start and end of the registered buffers, in the order of their index.
+    fixed_buffers: Vec<(usize, usize)>,
+    // Vectors of the requests submitted, read by the kernel thread polling
+    // the submission queue after the request was pushed.
+    inflight_iovecs: HashMap<u64, Vec<libc::iovec>>,
+    // Priority of the reads and writes, those submitted by the kernel thread
+    // polling the submission queue not getting the one of the queue thread.
+    ioprio: u16,
 }
 
+// SAFETY: the iovecs kept point to guest memory, which stays mapped until
+// the requests complete.
+unsafe impl Send for RawFileAsync {}
+
 impl RawFileAsync {
     pub fn new(fd: RawFd, ring_depth: u32) -> std::io::Result<Self> {
-        let io_uring = IoUring::new(ring_depth)?;
+        Self::with_options(fd, ring_depth, IoUringOptions::default())
+    }
+
+    pub fn with_options(
+        fd: RawFd,
+        ring_depth: u32,
+        options: IoUringOptions,
+    ) -> std::io::Result<Self> {
+        let mut sqpoll = false;
+        let io_uring = if options.sqpoll {
+            match IoUring::builder()
+                .setup_sqpoll(SQPOLL_IDLE_MS)
+                .build(ring_depth)
+            {
+                Ok(io_uring) => {
+                    sqpoll = true;
+                    io_uring
+                }
+                Err(e) => {
+                    warn!("Failed setting up the io_uring submission queue polling: {e}");
+                    IoUring::new(ring_depth)?
+                }
+            }
+        } else {
+            IoUring::new(ring_depth)?
+        };
         let eventfd = EventFd::new(libc::EFD_NONBLOCK)?;
 
         // Register the io_uring eventfd that will notify when something in
         // the completion queue is ready.
         io_uring.submitter().register_eventfd(eventfd.as_raw_fd())?;
 
+        // Polling the submission queue requires the file to be registered on
+        // older kernels.
+        let mut fixed_file = false;
+        if options.sqpoll || options.fixed_buffers {
+            match io_uring.submitter().register_files(&[fd]) {
+                Ok(()) => fixed_file = true,
+                Err(e) => warn!("Failed registering the disk file with io_uring: {e}"),
+            }
+        }
+
         Ok(RawFileAsync {
             fd,
             io_uring,
             eventfd,
+            sqpoll,
+            fixed_buffers_enabled: options.fixed_buffers,
+            fixed_file,
+            fixed_buffers: Vec::new(),
+            inflight_iovecs: HashMap::new(),
+            ioprio: 0,
         })
     }
+
+    // Index of the registered buffer holding the single buffer of a request.
+    fn fixed_buffer_index(&self, iovecs: &[libc::iovec]) -> Option<u16> {
+        let [iovec] = iovecs else {
+            return None;
+        };
+        let start = iovec.iov_base as usize;
+        let end = start.checked_add(iovec.iov_len)?;
+        self.fixed_buffers
+            .iter()
+            .position(|(buffer_start, buffer_end)| *buffer_start <= start && end <= *buffer_end)
+            .map(|index| index as u16)
+    }
+
+    // Pointer to the vectors of a request, kept until it completes when the
+    // kernel reads them asynchronously.
+    fn submitted_iovecs(&mut self, iovecs: &[libc::iovec], user_data: u64) -> *const libc::iovec {
+        if self.sqpoll {
+            let iovecs = iovecs.to_vec();
+            let iovecs_ptr = iovecs.as_ptr();
+            self.inflight_iovecs.insert(user_data, iovecs);
+            iovecs_ptr
+        } else {
+            iovecs.as_ptr()
+        }
+    }
+
+    fn read_write_entry(
+        &mut self,
+        request_type: RequestType,
+        offset: libc::off_t,
+        iovecs: &[libc::iovec],
+        user_data: u64,
+    ) -> io_uring::squeue::Entry {
+        let offset: u64 = offset.try_into().unwrap();
+        let ioprio = self.ioprio;
+        if let Some(buf_index) = self.fixed_buffer_index(iovecs) {
+            let buf = iovecs[0].iov_base as *mut u8;
+            let len = iovecs[0].iov_len as u32;
+            return match request_type {
+                RequestType::In => file_entry!(
+                    self,
+                    ReadFixed(buf, len, buf_index).offset(offset).ioprio(ioprio)
+                ),
+                _ => file_entry!(
+                    self,
+                    WriteFixed(buf as *const u8, len, buf_index)
+                        .offset(offset)
+                        .ioprio(ioprio)
+                ),
+            };
+        }
+
+        let iovecs_len = iovecs.len() as u32;
+        let iovecs = self.submitted_iovecs(iovecs, user_data);
+        match request_type {
+            RequestType::In => file_entry!(
+                self,
+                Readv(iovecs, iovecs_len).offset(offset).ioprio(ioprio)
+            ),
+            _ => file_entry!(
+                self,
+                Writev(iovecs, iovecs_len).offset(offset).ioprio(ioprio)
+            ),
+        }
+    }
+
+    fn fallocate(
+        &mut self,
+        offset: u64,
+        length: u64,
+        mode: libc::c_int,
+        user_data: u64,
+    ) -> std::io::Result<()> {
+        let (submitter, mut sq, _) = self.io_uring.split();
+
+        // SAFETY: we know the file descriptor is valid.
+        unsafe {
+            sq.push(
+                &file_entry!(self, Fallocate(length).offset(offset).mode(mode))
+                    .user_data(user_data),
+            )
+            .map_err(|_| Error::other("Submission queue is full"))?
+        };
+
+        // Update the submission queue and submit new operations to the
+        // io_uring instance.
+        sq.sync();
+        submitter.submit()?;
+
+        Ok(())
+    }
 }
 
 impl AsyncIo for RawFileAsync {
@@ -86,18 +280,14 @@ impl AsyncIo for RawFileAsync {
         iovecs: &[libc::iovec],
         user_data: u64,
     ) -> AsyncIoResult<()> {
+        let entry = self.read_write_entry(RequestType::In, offset, iovecs, user_data);
         let (submitter, mut sq, _) = self.io_uring.split();
 
         // SAFETY: we know the file descriptor is valid and we
         // relied on vm-memory to provide the buffer address.
         unsafe {
-            sq.push(
-                &opcode::Readv::new(types::Fd(self.fd), iovecs.as_ptr(), iovecs.len() as u32)
-                    .offset(offset.try_into().unwrap())
-                    .build()
-                    .user_data(user_data),
-            )
-            .map_err(|_| AsyncIoError::ReadVectored(Error::other("Submission queue is full")))?
+            sq.push(&entry.user_data(user_data))
+                .map_err(|_| AsyncIoError::ReadVectored(Error::other("Submission queue is full")))?
         };
 
         // Update the submission queue and submit new operations to the
@@ -114,18 +304,15 @@ impl AsyncIo for RawFileAsync {
         iovecs: &[libc::iovec],
         user_data: u64,
     ) -> AsyncIoResult<()> {
+        let entry = self.read_write_entry(RequestType::Out, offset, iovecs, user_data);
         let (submitter, mut sq, _) = self.io_uring.split();
 
         // SAFETY: we know the file descriptor is valid and we
         // relied on vm-memory to provide the buffer address.
         unsafe {
-            sq.push(
-                &opcode::Writev::new(types::Fd(self.fd), iovecs.as_ptr(), iovecs.len() as u32)
-                    .offset(offset.try_into().unwrap())
-                    .build()
-                    .user_data(user_data),
-            )
-            .map_err(|_| AsyncIoError::WriteVectored(Error::other("Submission queue is full")))?
+            sq.push(&entry.user_data(user_data)).map_err(|_| {
+                AsyncIoError::WriteVectored(Error::other("Submission queue is full"))
+            })?
         };
 
         // Update the submission queue and submit new operations to the
@@ -142,12 +329,8 @@ impl AsyncIo for RawFileAsync {
 
             // SAFETY: we know the file descriptor is valid.
             unsafe {
-                sq.push(
-                    &opcode::Fsync::new(types::Fd(self.fd))
-                        .build()
-                        .user_data(user_data),
-                )
-                .map_err(|_| AsyncIoError::Fsync(Error::other("Submission queue is full")))?
+                sq.push(&file_entry!(self, Fsync()).user_data(user_data))
+                    .map_err(|_| AsyncIoError::Fsync(Error::other("Submission queue is full")))?
             };
 
             // Update the submission queue and submit new operations to the
@@ -162,10 +345,132 @@ impl AsyncIo for RawFileAsync {
         Ok(())
     }
 
+    fn punch_hole(&mut self, offset: u64, length: u64, user_data: u64) -> AsyncIoResult<()> {
+        self.fallocate(
+            offset,
+            length,
+            libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+            user_data,
+        )
+        .map_err(AsyncIoError::PunchHole)
+    }
+
+    fn write_zeroes(&mut self, offset: u64, length: u64, user_data: u64) -> AsyncIoResult<()> {
+        self.fallocate(
+            offset,
+            length,
+            libc::FALLOC_FL_ZERO_RANGE | libc::FALLOC_FL_KEEP_SIZE,
+            user_data,
+        )
+        .map_err(AsyncIoError::WriteZeroes)
+    }
+
     fn next_completed_request(&mut self) -> Option<(u64, i32)> {
-        self.io_uring
+        let (user_data, result) = self
+            .io_uring
             .completion()
             .next()
-            .map(|entry| (entry.user_data(), entry.result()))
+            .map(|entry| (entry.user_data(), entry.result()))?;
+        self.inflight_iovecs.remove(&user_data);
+        Some((user_data, result))
+    }
+
+    fn register_buffers(&mut self, buffers: &[libc::iovec]) {
+        if !self.fixed_buffers_enabled {
+            return;
+        }
+
+        let mut fixed_buffers = Vec::new();
+        for buffer in buffers {
+            let end = buffer.iov_base as usize + buffer.iov_len;
+            let mut start = buffer.iov_base as usize;
+            while start < end {
+                let len = (end - start).min(MAX_FIXED_BUFFER_SIZE);
+                fixed_buffers.push(libc::iovec {
+                    iov_base: start as *mut libc::c_void,
+                    iov_len: len,
+                });
+                start += len;
+            }
+        }
+
+        let submitter = self.io_uring.submitter();
+        if !self.fixed_buffers.is_empty() {
+            if let Err(e) = submitter.unregister_buffers() {
+                warn!("Failed unregistering the buffers from io_uring: {e}");
+                return;
+            }
+            self.fixed_buffers.clear();
+        }
+        // SAFETY: the buffers are guest memory, which stays mapped as long
+        // as the device is activated.
+        match unsafe { submitter.register_buffers(&fixed_buffers) } {
+            Ok(()) => {
+                self.fixed_buffers = fixed_buffers
+                    .iter()
+                    .map(|buffer| {
+                        let start = buffer.iov_base as usize;
+                        (start, start + buffer.iov_len)
+                    })
+                    .collect()
+            }
+            Err(e) => warn!("Failed registering the guest memory with io_uring: {e}"),
+        }
+    }
+
+    fn set_io_priority(&mut self, ioprio: u16) {
+        self.ioprio = ioprio;
+    }
+
+    fn batch_requests_enabled(&self) -> bool {
+        true
+    }
+
+    fn submit_batch_requests(&mut self, batch_requests: &[BatchRequest]) -> AsyncIoResult<()> {
+        // Check the whole batch fits so that none of it is submitted
+        // otherwise.
+        let available = {
+            let sq = self.io_uring.submission();
+            sq.capacity() - sq.len()
+        };
+        if available < batch_requests.len() {
+            return Err(AsyncIoError::SubmitBatchRequests(Error::other(
+                "Submission queue is full",
+            )));
+        }
+
+        let mut entries = Vec::with_capacity(batch_requests.len());
+        for batch_request in batch_requests {
+            let entry = match batch_request.request_type {
+                request_type @ (RequestType::In | RequestType::Out) => self.read_write_entry(
+                    request_type,
+                    batch_request.offset,
+                    &batch_request.iovecs,
+                    batch_request.user_data,
+                ),
+                _ => unreachable!("Only reads and writes are batched"),
+            };
+            entries.push(entry.user_data(batch_request.user_data));
+        }
+
+        let (submitter, mut sq, _) = self.io_uring.split();
+        for entry in entries {
+            // SAFETY: we know the file descriptor is valid and we
+            // relied on vm-memory to provide the buffer address.
+            unsafe {
+                sq.push(&entry).map_err(|_| {
+                    AsyncIoError::SubmitBatchRequests(Error::other("Submission queue is full"))
+                })?
+            };
+        }
+
+        // Update the submission queue and submit new operations to the
+        // io_uring instance.
+        sq.sync();
+        submitter
+            .submit()
+            .map_err(AsyncIoError::SubmitBatchRequests)?;
+
+        Ok(())
     }
 }
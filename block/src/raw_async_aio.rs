@@ -5,6 +5,7 @@
 // Copyright © 2023 Crusoe Energy Systems LLC
 //
 
+use std::collections::VecDeque;
 use std::fs::File;
 use std::io::{Seek, SeekFrom};
 use std::os::unix::io::{AsRawFd, RawFd};
@@ -13,7 +14,8 @@ use vmm_sys_util::aio;
 use vmm_sys_util::eventfd::EventFd;
 
 use crate::async_io::{
-    AsyncIo, AsyncIoError, AsyncIoResult, BorrowedDiskFd, DiskFile, DiskFileError, DiskFileResult,
+    zero_raw_file, AsyncIo, AsyncIoError, AsyncIoResult, BorrowedDiskFd, DiskFile, DiskFileError,
+    DiskFileResult,
 };
 use crate::DiskTopology;
 
@@ -53,12 +55,22 @@ impl DiskFile for RawFileDiskAio {
     fn fd(&mut self) -> BorrowedDiskFd<'_> {
         BorrowedDiskFd::new(self.file.as_raw_fd())
     }
+
+    fn supports_punch_hole(&self) -> bool {
+        true
+    }
+
+    fn resize(&mut self, size: u64) -> DiskFileResult<()> {
+        self.file.set_len(size).map_err(DiskFileError::Resize)
+    }
 }
 
 pub struct RawFileAsyncAio {
     fd: RawFd,
     ctx: aio::IoContext,
     eventfd: EventFd,
+    // Requests completed synchronously, which AIO doesn't cover.
+    completion_list: VecDeque<(u64, i32)>,
 }
 
 impl RawFileAsyncAio {
@@ -66,7 +78,12 @@ impl RawFileAsyncAio {
         let eventfd = EventFd::new(libc::EFD_NONBLOCK)?;
         let ctx = aio::IoContext::new(queue_depth)?;
 
-        Ok(RawFileAsyncAio { fd, ctx, eventfd })
+        Ok(RawFileAsyncAio {
+            fd,
+            ctx,
+            eventfd,
+            completion_list: VecDeque::new(),
+        })
     }
 }
 
@@ -144,7 +161,29 @@ impl AsyncIo for RawFileAsyncAio {
         Ok(())
     }
 
+    fn punch_hole(&mut self, offset: u64, length: u64, user_data: u64) -> AsyncIoResult<()> {
+        zero_raw_file(self.fd, offset, length, true).map_err(AsyncIoError::PunchHole)?;
+
+        self.completion_list.push_back((user_data, 0));
+        self.eventfd.write(1).unwrap();
+
+        Ok(())
+    }
+
+    fn write_zeroes(&mut self, offset: u64, length: u64, user_data: u64) -> AsyncIoResult<()> {
+        zero_raw_file(self.fd, offset, length, false).map_err(AsyncIoError::WriteZeroes)?;
+
+        self.completion_list.push_back((user_data, 0));
+        self.eventfd.write(1).unwrap();
+
+        Ok(())
+    }
+
     fn next_completed_request(&mut self) -> Option<(u64, i32)> {
+        if let Some(completed) = self.completion_list.pop_front() {
+            return Some(completed);
+        }
+
         let mut events: [aio::IoEvent; 1] = [aio::IoEvent::default()];
         let rc = self.ctx.get_events(0, &mut events, None).unwrap();
         if rc == 0 {
@@ -0,0 +1,365 @@
+// Copyright © 2024 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0 AND BSD-3-Clause
+
+//! Raw disk image backend running the I/O requests on a pool of worker
+//! threads, for the filesystems where io_uring and AIO perform poorly.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{Seek, SeekFrom};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+use vmm_sys_util::eventfd::EventFd;
+
+use crate::async_io::{
+    zero_raw_file, AsyncIo, AsyncIoError, AsyncIoResult, BorrowedDiskFd, DiskFile, DiskFileError,
+    DiskFileResult,
+};
+use crate::{set_thread_io_priority, DiskTopology};
+
+/// Number of worker threads used when none is specified.
+pub const DEFAULT_IO_THREADS: usize = 4;
+
+pub struct RawFileDiskThreads {
+    file: File,
+    threads: usize,
+}
+
+impl RawFileDiskThreads {
+    pub fn new(file: File, threads: usize) -> Self {
+        RawFileDiskThreads { file, threads }
+    }
+}
+
+impl DiskFile for RawFileDiskThreads {
+    fn size(&mut self) -> DiskFileResult<u64> {
+        self.file
+            .seek(SeekFrom::End(0))
+            .map_err(DiskFileError::Size)
+    }
+
+    fn new_async_io(&self, _ring_depth: u32) -> DiskFileResult<Box<dyn AsyncIo>> {
+        Ok(Box::new(
+            RawFileAsyncThreads::new(self.file.as_raw_fd(), self.threads)
+                .map_err(DiskFileError::NewAsyncIo)?,
+        ) as Box<dyn AsyncIo>)
+    }
+
+    fn topology(&mut self) -> DiskTopology {
+        if let Ok(topology) = DiskTopology::probe(&self.file) {
+            topology
+        } else {
+            warn!("Unable to get device topology. Using default topology");
+            DiskTopology::default()
+        }
+    }
+
+    fn fd(&mut self) -> BorrowedDiskFd<'_> {
+        BorrowedDiskFd::new(self.file.as_raw_fd())
+    }
+
+    fn supports_punch_hole(&self) -> bool {
+        true
+    }
+
+    fn resize(&mut self, size: u64) -> DiskFileResult<()> {
+        self.file.set_len(size).map_err(DiskFileError::Resize)
+    }
+}
+
+enum Operation {
+    Read,
+    Write,
+    Fsync,
+    PunchHole { length: u64 },
+    WriteZeroes { length: u64 },
+}
+
+struct Job {
+    operation: Operation,
+    offset: libc::off_t,
+    // Copied from the caller, the buffers they point to being guest memory
+    // which outlives the request.
+    iovecs: Vec<libc::iovec>,
+    user_data: u64,
+}
+
+// SAFETY: the iovecs point to guest memory which stays mapped until the
+// request completes, and no other thread accesses them meanwhile.
+unsafe impl Send for Job {}
+
+#[derive(Default)]
+struct Queue {
+    jobs: VecDeque<Job>,
+    shutdown: bool,
+}
+
+struct Shared {
+    fd: RawFd,
+    queue: Mutex<Queue>,
+    job_available: Condvar,
+    completion_list: Mutex<VecDeque<(u64, i32)>>,
+    eventfd: EventFd,
+    // Priority the workers apply to themselves before their next job, the
+    // default one being kept while 0.
+    ioprio: AtomicU16,
+}
+
+impl Shared {
+    fn run(&self) {
+        let mut ioprio = 0;
+        loop {
+            let job = {
+                let mut queue = self.queue.lock().unwrap();
+                loop {
+                    if let Some(job) = queue.jobs.pop_front() {
+                        break job;
+                    }
+                    if queue.shutdown {
+                        return;
+                    }
+                    queue = self.job_available.wait(queue).unwrap();
+                }
+            };
+
+            let job_ioprio = self.ioprio.load(Ordering::Acquire);
+            if job_ioprio != ioprio {
+                if let Err(e) = set_thread_io_priority(job_ioprio) {
+                    error!("Failed setting the I/O priority of a disk I/O worker: {e}");
+                }
+                ioprio = job_ioprio;
+            }
+
+            let result = self.execute(&job);
+            self.completion_list
+                .lock()
+                .unwrap()
+                .push_back((job.user_data, result));
+            self.eventfd.write(1).unwrap();
+        }
+    }
+
+    fn execute(&self, job: &Job) -> i32 {
+        let result = match job.operation {
+            // SAFETY: FFI call with valid arguments
+            Operation::Read => unsafe {
+                libc::preadv(
+                    self.fd,
+                    job.iovecs.as_ptr(),
+                    job.iovecs.len() as libc::c_int,
+                    job.offset,
+                )
+            },
+            // SAFETY: FFI call with valid arguments
+            Operation::Write => unsafe {
+                libc::pwritev(
+                    self.fd,
+                    job.iovecs.as_ptr(),
+                    job.iovecs.len() as libc::c_int,
+                    job.offset,
+                )
+            },
+            // SAFETY: FFI call with a valid fd
+            Operation::Fsync => unsafe { libc::fsync(self.fd) as isize },
+            Operation::PunchHole { length } | Operation::WriteZeroes { length } => {
+                let punch_hole = matches!(job.operation, Operation::PunchHole { .. });
+                match zero_raw_file(self.fd, job.offset as u64, length, punch_hole) {
+                    Ok(()) => 0,
+                    Err(e) => return -e.raw_os_error().unwrap_or(libc::EIO),
+                }
+            }
+        };
+
+        if result < 0 {
+            -std::io::Error::last_os_error()
+                .raw_os_error()
+                .unwrap_or(libc::EIO)
+        } else {
+            result as i32
+        }
+    }
+}
+
+pub struct RawFileAsyncThreads {
+    shared: Arc<Shared>,
+    workers: Vec<thread::JoinHandle<()>>,
+}
+
+impl RawFileAsyncThreads {
+    pub fn new(fd: RawFd, threads: usize) -> std::io::Result<Self> {
+        let shared = Arc::new(Shared {
+            fd,
+            queue: Mutex::new(Queue::default()),
+            job_available: Condvar::new(),
+            completion_list: Mutex::new(VecDeque::new()),
+            eventfd: EventFd::new(libc::EFD_NONBLOCK)?,
+            ioprio: AtomicU16::new(0),
+        });
+
+        let mut async_io = RawFileAsyncThreads {
+            shared,
+            workers: Vec::with_capacity(threads),
+        };
+        for i in 0..threads.max(1) {
+            let shared = async_io.shared.clone();
+            let worker = thread::Builder::new()
+                .name(format!("disk_io{i}"))
+                .spawn(move || shared.run())?;
+            async_io.workers.push(worker);
+        }
+
+        Ok(async_io)
+    }
+
+    fn submit(
+        &self,
+        operation: Operation,
+        offset: libc::off_t,
+        iovecs: &[libc::iovec],
+        user_data: u64,
+    ) {
+        self.shared.queue.lock().unwrap().jobs.push_back(Job {
+            operation,
+            offset,
+            iovecs: iovecs.to_vec(),
+            user_data,
+        });
+        self.shared.job_available.notify_one();
+    }
+}
+
+impl Drop for RawFileAsyncThreads {
+    fn drop(&mut self) {
+        self.shared.queue.lock().unwrap().shutdown = true;
+        self.shared.job_available.notify_all();
+        for worker in self.workers.drain(..) {
+            if worker.join().is_err() {
+                error!("Disk I/O worker thread panicked");
+            }
+        }
+    }
+}
+
+impl AsyncIo for RawFileAsyncThreads {
+    fn notifier(&self) -> &EventFd {
+        &self.shared.eventfd
+    }
+
+    fn read_vectored(
+        &mut self,
+        offset: libc::off_t,
+        iovecs: &[libc::iovec],
+        user_data: u64,
+    ) -> AsyncIoResult<()> {
+        self.submit(Operation::Read, offset, iovecs, user_data);
+        Ok(())
+    }
+
+    fn write_vectored(
+        &mut self,
+        offset: libc::off_t,
+        iovecs: &[libc::iovec],
+        user_data: u64,
+    ) -> AsyncIoResult<()> {
+        self.submit(Operation::Write, offset, iovecs, user_data);
+        Ok(())
+    }
+
+    fn fsync(&mut self, user_data: Option<u64>) -> AsyncIoResult<()> {
+        if let Some(user_data) = user_data {
+            self.submit(Operation::Fsync, 0, &[], user_data);
+        } else {
+            // SAFETY: FFI call with a valid fd
+            let result = unsafe { libc::fsync(self.shared.fd) };
+            if result < 0 {
+                return Err(AsyncIoError::Fsync(std::io::Error::last_os_error()));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn next_completed_request(&mut self) -> Option<(u64, i32)> {
+        self.shared.completion_list.lock().unwrap().pop_front()
+    }
+
+    fn set_io_priority(&mut self, ioprio: u16) {
+        self.shared.ioprio.store(ioprio, Ordering::Release);
+    }
+
+    fn punch_hole(&mut self, offset: u64, length: u64, user_data: u64) -> AsyncIoResult<()> {
+        self.submit(
+            Operation::PunchHole { length },
+            offset as libc::off_t,
+            &[],
+            user_data,
+        );
+        Ok(())
+    }
+
+    fn write_zeroes(&mut self, offset: u64, length: u64, user_data: u64) -> AsyncIoResult<()> {
+        self.submit(
+            Operation::WriteZeroes { length },
+            offset as libc::off_t,
+            &[],
+            user_data,
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use vmm_sys_util::tempfile::TempFile;
+
+    use super::*;
+
+    fn wait_completion(async_io: &mut RawFileAsyncThreads) -> (u64, i32) {
+        loop {
+            if let Some(completed) = async_io.next_completed_request() {
+                return completed;
+            }
+            let _ = async_io.notifier().read();
+        }
+    }
+
+    #[test]
+    fn test_raw_file_async_threads() {
+        let mut file = TempFile::new().unwrap().into_file();
+        file.write_all(&[0xaa; 4096]).unwrap();
+        let mut async_io = RawFileAsyncThreads::new(file.as_raw_fd(), 2).unwrap();
+
+        let mut buf = [0u8; 512];
+        let iovecs = [libc::iovec {
+            iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+            iov_len: buf.len(),
+        }];
+        async_io.read_vectored(512, &iovecs, 1).unwrap();
+        assert_eq!(wait_completion(&mut async_io), (1, 512));
+        assert!(buf.iter().all(|b| *b == 0xaa));
+
+        let data = [0x55u8; 512];
+        let iovecs = [libc::iovec {
+            iov_base: data.as_ptr() as *mut libc::c_void,
+            iov_len: data.len(),
+        }];
+        async_io.write_vectored(1024, &iovecs, 2).unwrap();
+        assert_eq!(wait_completion(&mut async_io), (2, 512));
+        async_io.fsync(Some(3)).unwrap();
+        assert_eq!(wait_completion(&mut async_io), (3, 0));
+
+        let iovecs = [libc::iovec {
+            iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+            iov_len: buf.len(),
+        }];
+        async_io.read_vectored(1024, &iovecs, 4).unwrap();
+        assert_eq!(wait_completion(&mut async_io), (4, 512));
+        assert!(buf.iter().all(|b| *b == 0x55));
+    }
+}
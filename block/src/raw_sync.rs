@@ -10,7 +10,8 @@ use std::os::unix::io::{AsRawFd, RawFd};
 use vmm_sys_util::eventfd::EventFd;
 
 use crate::async_io::{
-    AsyncIo, AsyncIoError, AsyncIoResult, BorrowedDiskFd, DiskFile, DiskFileError, DiskFileResult,
+    zero_raw_file, AsyncIo, AsyncIoError, AsyncIoResult, BorrowedDiskFd, DiskFile, DiskFileError,
+    DiskFileResult,
 };
 use crate::DiskTopology;
 
@@ -47,6 +48,14 @@ impl DiskFile for RawFileDiskSync {
     fn fd(&mut self) -> BorrowedDiskFd<'_> {
         BorrowedDiskFd::new(self.file.as_raw_fd())
     }
+
+    fn supports_punch_hole(&self) -> bool {
+        true
+    }
+
+    fn resize(&mut self, size: u64) -> DiskFileResult<()> {
+        self.file.set_len(size).map_err(DiskFileError::Resize)
+    }
 }
 
 pub struct RawFileSync {
@@ -138,4 +147,66 @@ impl AsyncIo for RawFileSync {
     fn next_completed_request(&mut self) -> Option<(u64, i32)> {
         self.completion_list.pop_front()
     }
+
+    fn punch_hole(&mut self, offset: u64, length: u64, user_data: u64) -> AsyncIoResult<()> {
+        zero_raw_file(self.fd, offset, length, true).map_err(AsyncIoError::PunchHole)?;
+
+        self.completion_list.push_back((user_data, 0));
+        self.eventfd.write(1).unwrap();
+
+        Ok(())
+    }
+
+    fn write_zeroes(&mut self, offset: u64, length: u64, user_data: u64) -> AsyncIoResult<()> {
+        zero_raw_file(self.fd, offset, length, false).map_err(AsyncIoError::WriteZeroes)?;
+
+        self.completion_list.push_back((user_data, 0));
+        self.eventfd.write(1).unwrap();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::os::unix::fs::FileExt;
+
+    use vmm_sys_util::tempfile::TempFile;
+
+    use super::*;
+
+    #[test]
+    fn test_raw_file_sync_zeroes() {
+        let mut file = TempFile::new().unwrap().into_file();
+        file.write_all(&[0xaa; 8192]).unwrap();
+        let mut async_io = RawFileSync::new(file.as_raw_fd());
+
+        async_io.write_zeroes(0, 4096, 1).unwrap();
+        assert_eq!(async_io.next_completed_request(), Some((1, 0)));
+        async_io.punch_hole(4096, 2048, 2).unwrap();
+        assert_eq!(async_io.next_completed_request(), Some((2, 0)));
+        assert_eq!(async_io.next_completed_request(), None);
+
+        let mut data = [0xffu8; 8192];
+        file.read_exact_at(&mut data, 0).unwrap();
+        assert!(data[..6144].iter().all(|b| *b == 0));
+        assert!(data[6144..].iter().all(|b| *b == 0xaa));
+        // Punching a hole doesn't change the size of the image.
+        assert_eq!(file.metadata().unwrap().len(), 8192);
+    }
+
+    #[test]
+    fn test_raw_file_disk_sync_resize() {
+        let file = TempFile::new().unwrap().into_file();
+        file.set_len(4096).unwrap();
+        let mut disk = RawFileDiskSync::new(file.try_clone().unwrap());
+        assert_eq!(disk.size().unwrap(), 4096);
+
+        disk.resize(1 << 20).unwrap();
+        assert_eq!(disk.size().unwrap(), 1 << 20);
+        let mut data = [0xffu8; 4096];
+        file.read_exact_at(&mut data, (1 << 20) - 4096).unwrap();
+        assert!(data.iter().all(|b| *b == 0));
+    }
 }
@@ -10,7 +10,7 @@ use std::time::Instant;
 
 use acpi_tables::{aml, Aml, AmlSink};
 use vm_device::interrupt::InterruptSourceGroup;
-use vm_device::BusDevice;
+use vm_device::{BusDevice, ExitReason, ExitReasonSlot};
 use vm_memory::GuestAddress;
 use vmm_sys_util::eventfd::EventFd;
 
@@ -23,6 +23,7 @@ pub struct AcpiShutdownDevice {
     exit_evt: EventFd,
     reset_evt: EventFd,
     vcpus_kill_signalled: Arc<AtomicBool>,
+    exit_reason: ExitReasonSlot,
 }
 
 impl AcpiShutdownDevice {
@@ -31,11 +32,13 @@ impl AcpiShutdownDevice {
         exit_evt: EventFd,
         reset_evt: EventFd,
         vcpus_kill_signalled: Arc<AtomicBool>,
+        exit_reason: ExitReasonSlot,
     ) -> AcpiShutdownDevice {
         AcpiShutdownDevice {
             exit_evt,
             reset_evt,
             vcpus_kill_signalled,
+            exit_reason,
         }
     }
 }
@@ -50,6 +53,7 @@ impl BusDevice for AcpiShutdownDevice {
     fn write(&mut self, _base: u64, _offset: u64, data: &[u8]) -> Option<Arc<Barrier>> {
         if data[0] == 1 {
             info!("ACPI Reboot signalled");
+            self.exit_reason.record(ExitReason::AcpiReboot);
             if let Err(e) = self.reset_evt.write(1) {
                 error!("Error triggering ACPI reset event: {}", e);
             }
@@ -67,6 +71,7 @@ impl BusDevice for AcpiShutdownDevice {
         const SLEEP_VALUE_BIT: u8 = 2;
         if data[0] == (S5_SLEEP_VALUE << SLEEP_VALUE_BIT) | (1 << SLEEP_STATUS_EN_BIT) {
             info!("ACPI Shutdown signalled");
+            self.exit_reason.record(ExitReason::AcpiShutdown);
             if let Err(e) = self.exit_evt.write(1) {
                 error!("Error triggering ACPI shutdown event: {}", e);
             }
@@ -34,7 +34,8 @@ type Result<T> = result::Result<T, Error>;
 // split between two 32 bits registers as follow:
 //
 // 63-56: Destination Field - R/W
-// 55-17: Reserved
+// 55-49: Extended Destination Field - R/W
+// 48-17: Reserved
 // 16:    Interrupt Mask - R/W
 // 15:    Trigger Mode - R/W
 // 14:    Remote IRR - RO
@@ -71,6 +72,11 @@ fn destination_field(entry: RedirectionTableEntry) -> u8 {
     // retrieve the destination field based on bits 56-63.
     ((entry >> 56) & 0xffu64) as u8
 }
+fn extended_destination_field(entry: RedirectionTableEntry) -> u8 {
+    // Bits 14:8 of the APIC ID, set by guests relying on the extended
+    // destination ID to reach APIC IDs above 255.
+    ((entry >> 49) & 0x7fu64) as u8
+}
 fn set_delivery_status(entry: &mut RedirectionTableEntry, val: u8) {
     // Clear bit 12
     *entry &= 0xffff_ffff_ffff_efff;
@@ -338,15 +344,18 @@ impl Ioapic {
         // Validate Destination Mode value, and retrieve Destination ID
         let destination_mode = destination_mode(entry);
         let destination_id = destination_field(entry);
+        let extended_destination_id = extended_destination_field(entry);
 
         // When this bit is set, the message is directed to the processor with
         // the lowest interrupt priority among processors that can receive the
         // interrupt.
         let redirection_hint: u8 = 1;
 
-        // Generate MSI message address
+        // Generate MSI message address, with the extended destination ID
+        // following the same layout as for MSIs
         let low_addr: u32 = self.apic_address.0 as u32
             | (u32::from(destination_id) << 12)
+            | (u32::from(extended_destination_id) << 5)
             | (u32::from(redirection_hint) << 3)
             | (u32::from(destination_mode) << 2);
 
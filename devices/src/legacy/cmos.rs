@@ -6,20 +6,23 @@
 
 use std::cmp::min;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Barrier};
+use std::sync::{Arc, Barrier, Mutex};
 use std::{mem, thread};
 
 // https://github.com/rust-lang/libc/issues/1848
 #[cfg_attr(target_env = "musl", allow(deprecated))]
 use libc::time_t;
-use libc::{clock_gettime, gmtime_r, timespec, tm, CLOCK_REALTIME};
+use libc::{gmtime_r, tm};
 use vm_device::BusDevice;
 use vmm_sys_util::eventfd::EventFd;
 
+use super::RtcClock;
+
 const INDEX_MASK: u8 = 0x7f;
 const INDEX_OFFSET: u64 = 0x0;
 const DATA_OFFSET: u64 = 0x1;
 const DATA_LEN: usize = 128;
+const NANOSECONDS_PER_SECOND: i64 = 1_000_000_000;
 
 /// A CMOS/RTC device commonly seen on x86 I/O port 0x70/0x71.
 pub struct Cmos {
@@ -27,17 +30,20 @@ pub struct Cmos {
     data: [u8; DATA_LEN],
     reset_evt: EventFd,
     vcpus_kill_signalled: Option<Arc<AtomicBool>>,
+    clock: Arc<Mutex<RtcClock>>,
 }
 
 impl Cmos {
     /// Constructs a CMOS/RTC device with initial data.
     /// `mem_below_4g` is the size of memory in bytes below the 32-bit gap.
     /// `mem_above_4g` is the size of memory in bytes above the 32-bit gap.
+    /// `clock` provides the time of the RTC.
     pub fn new(
         mem_below_4g: u64,
         mem_above_4g: u64,
         reset_evt: EventFd,
         vcpus_kill_signalled: Option<Arc<AtomicBool>>,
+        clock: Arc<Mutex<RtcClock>>,
     ) -> Cmos {
         let mut data = [0u8; DATA_LEN];
 
@@ -60,6 +66,7 @@ impl Cmos {
             data,
             reset_evt,
             vcpus_kill_signalled,
+            clock,
         }
     }
 }
@@ -116,16 +123,14 @@ impl BusDevice for Cmos {
                 let day;
                 let month;
                 let year;
-                // SAFETY: The clock_gettime and gmtime_r calls are safe as long as the structs they are
-                // given are large enough, and neither of them fail. It is safe to zero initialize
-                // the tm and timespec struct because it contains only plain data.
+                let time = self.clock.lock().unwrap().now();
+                // SAFETY: The gmtime_r call is safe as long as the struct it is given is large
+                // enough, and it doesn't fail. It is safe to zero initialize the tm struct
+                // because it contains only plain data.
                 let update_in_progress = unsafe {
-                    let mut timespec: timespec = mem::zeroed();
-                    clock_gettime(CLOCK_REALTIME, &mut timespec as *mut _);
-
                     // https://github.com/rust-lang/libc/issues/1848
                     #[cfg_attr(target_env = "musl", allow(deprecated))]
-                    let now: time_t = timespec.tv_sec;
+                    let now: time_t = time.div_euclid(NANOSECONDS_PER_SECOND) as time_t;
                     let mut tm: tm = mem::zeroed();
                     gmtime_r(&now, &mut tm as *mut _);
 
@@ -139,9 +144,9 @@ impl BusDevice for Cmos {
                     year = tm.tm_year;
 
                     // Update in Progress bit held for last 224us of each second
-                    const NANOSECONDS_PER_SECOND: i64 = 1_000_000_000;
                     const UIP_HOLD_LENGTH: i64 = 8 * NANOSECONDS_PER_SECOND / 32768;
-                    timespec.tv_nsec >= (NANOSECONDS_PER_SECOND - UIP_HOLD_LENGTH)
+                    time.rem_euclid(NANOSECONDS_PER_SECOND)
+                        >= (NANOSECONDS_PER_SECOND - UIP_HOLD_LENGTH)
                 };
                 match self.index {
                     0x00 => to_bcd(seconds as u8),
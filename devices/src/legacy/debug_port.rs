@@ -4,6 +4,8 @@
 //
 
 use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::time::Instant;
 
 use vm_device::BusDevice;
@@ -52,11 +54,16 @@ impl fmt::Display for DebugIoPortRange {
 
 pub struct DebugPort {
     timestamp: Instant,
+    // Set as soon as the guest reports a code past the firmware range
+    boot_milestone: Arc<AtomicBool>,
 }
 
 impl DebugPort {
-    pub fn new(timestamp: Instant) -> Self {
-        Self { timestamp }
+    pub fn new(timestamp: Instant, boot_milestone: Arc<AtomicBool>) -> Self {
+        Self {
+            timestamp,
+            boot_milestone,
+        }
     }
 }
 
@@ -74,14 +81,19 @@ impl BusDevice for DebugPort {
         let elapsed = self.timestamp.elapsed();
 
         let code = data[0];
+        let range = DebugIoPortRange::from_u8(code);
         warn!(
             "[{} code 0x{:x}] {}.{:>06} seconds",
-            DebugIoPortRange::from_u8(code),
+            range,
             code,
             elapsed.as_secs(),
             elapsed.as_micros()
         );
 
+        if !matches!(range, DebugIoPortRange::Firmware) {
+            self.boot_milestone.store(true, Ordering::SeqCst);
+        }
+
         None
     }
 }
@@ -13,6 +13,7 @@ mod fwdebug;
 #[cfg(target_arch = "aarch64")]
 mod gpio_pl061;
 mod i8042;
+mod rtc_clock;
 #[cfg(target_arch = "aarch64")]
 mod rtc_pl031;
 mod serial;
@@ -29,6 +30,7 @@ pub use self::gpio_pl061::Error as GpioDeviceError;
 #[cfg(target_arch = "aarch64")]
 pub use self::gpio_pl061::Gpio;
 pub use self::i8042::I8042Device;
+pub use self::rtc_clock::{RtcClock, RtcClockState};
 #[cfg(target_arch = "aarch64")]
 pub use self::rtc_pl031::Rtc;
 pub use self::serial::Serial;
@@ -0,0 +1,157 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Wall-clock time of the guest RTC devices, kept as an offset from the host
+//! realtime clock so that the guest can start at any date.
+
+use serde::{Deserialize, Serialize};
+
+const NANOS_PER_SECOND: i64 = 1_000_000_000;
+// While catching up the time spent paused, the clock runs faster by
+// 1/SLEW_RATIO, i.e. 500ppm as adjtime(3) does.
+const SLEW_RATIO: i64 = 2000;
+
+// Time of the host realtime clock, in nanoseconds since the epoch.
+fn host_time() -> i64 {
+    let mut timespec = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: the timespec is valid, and CLOCK_REALTIME is always supported.
+    unsafe { libc::clock_gettime(libc::CLOCK_REALTIME, &mut timespec) };
+    timespec.tv_sec as i64 * NANOS_PER_SECOND + timespec.tv_nsec as i64
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct RtcClockState {
+    offset: i64,
+    paused_at: Option<i64>,
+    lag: i64,
+}
+
+/// Clock shared by the RTC devices of a VM.
+pub struct RtcClock {
+    // Offset of the guest time from the host one, in nanoseconds.
+    offset: i64,
+    // Whether the time spent paused is caught up gradually rather than at
+    // once.
+    slew: bool,
+    // Host time the guest was paused at.
+    paused_at: Option<i64>,
+    // Time spent paused which remains to catch up, as of `slew_start`.
+    lag: i64,
+    slew_start: i64,
+}
+
+impl RtcClock {
+    /// Creates a clock `offset` nanoseconds ahead of the host one.
+    pub fn new(offset: i64, slew: bool) -> Self {
+        RtcClock {
+            offset,
+            slew,
+            paused_at: None,
+            lag: 0,
+            slew_start: host_time(),
+        }
+    }
+
+    /// Creates a clock from the state of a snapshot, the time spent paused
+    /// since the snapshot being caught up according to `slew` on resume.
+    pub fn from_state(state: RtcClockState, slew: bool) -> Self {
+        RtcClock {
+            offset: state.offset,
+            slew,
+            paused_at: state.paused_at,
+            lag: state.lag,
+            slew_start: state.paused_at.unwrap_or_else(host_time),
+        }
+    }
+
+    fn pending_lag(&self, host: i64) -> i64 {
+        let caught_up = (host - self.slew_start).max(0) / SLEW_RATIO;
+        (self.lag - caught_up).max(0)
+    }
+
+    /// Returns the guest time, in nanoseconds since the epoch.
+    pub fn now(&self) -> i64 {
+        if let Some(time) = replay::replay("rtc").and_then(|time| time.try_into().ok()) {
+            return i64::from_le_bytes(time);
+        }
+
+        let host = self.paused_at.unwrap_or_else(host_time);
+        let time = host + self.offset - self.pending_lag(host);
+        replay::record("rtc", &time.to_le_bytes());
+        time
+    }
+
+    /// Stops the clock, until `resume()` is called.
+    pub fn pause(&mut self) {
+        if self.paused_at.is_none() {
+            let host = host_time();
+            self.lag = self.pending_lag(host);
+            self.slew_start = host;
+            self.paused_at = Some(host);
+        }
+    }
+
+    /// Restarts the clock, which either jumps to account for the time spent
+    /// paused or catches it up over time.
+    pub fn resume(&mut self) {
+        if let Some(paused_at) = self.paused_at.take() {
+            let host = host_time();
+            if self.slew {
+                self.lag += host - paused_at;
+            }
+            self.slew_start = host;
+        }
+    }
+
+    pub fn state(&self) -> RtcClockState {
+        let host = self.paused_at.unwrap_or_else(host_time);
+        RtcClockState {
+            offset: self.offset,
+            paused_at: self.paused_at,
+            lag: self.pending_lag(host),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+
+    const PAUSE: Duration = Duration::from_millis(100);
+
+    #[test]
+    fn test_rtc_clock_pause() {
+        let mut clock = RtcClock::new(-3600 * NANOS_PER_SECOND, false);
+        assert!((clock.now() - host_time() + 3600 * NANOS_PER_SECOND).abs() < NANOS_PER_SECOND);
+
+        clock.pause();
+        let paused = clock.now();
+        thread::sleep(PAUSE);
+        assert_eq!(clock.now(), paused);
+
+        // The clock jumps over the time spent paused.
+        clock.resume();
+        assert!(clock.now() - paused >= PAUSE.as_nanos() as i64);
+
+        // The clock catches the time spent paused up, from where it stopped.
+        let mut clock = RtcClock::new(0, true);
+        clock.pause();
+        let paused = clock.now();
+        thread::sleep(PAUSE);
+        clock.resume();
+        let lag = host_time() - clock.now();
+        assert!(lag > PAUSE.as_nanos() as i64 / 2);
+        assert!(clock.now() >= paused);
+
+        let restored = RtcClock::from_state(clock.state(), true);
+        assert!(host_time() - restored.now() <= lag);
+    }
+}
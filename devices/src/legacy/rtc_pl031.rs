@@ -8,14 +8,14 @@
 //! This is achieved by generating an interrupt signal after counting for a programmed number of cycles of
 //! a real-time clock input.
 //!
-use std::sync::{Arc, Barrier};
-use std::time::Instant;
+use std::sync::{Arc, Barrier, Mutex};
 use std::{io, result};
 
 use thiserror::Error;
 use vm_device::interrupt::InterruptSourceGroup;
 use vm_device::BusDevice;
 
+use super::RtcClock;
 use crate::{read_le_u32, write_le_u32};
 
 // As you can see in https://static.docs.arm.com/ddi0224/c/real_time_clock_pl031_r1p3_technical_reference_manual_DDI0224C.pdf
@@ -52,6 +52,7 @@ pub enum Error {
 type Result<T> = result::Result<T, Error>;
 
 /// Wrapper over `libc::clockid_t` to specify Linux Kernel clock source.
+#[cfg(test)]
 pub enum ClockType {
     /// Equivalent to `libc::CLOCK_MONOTONIC`.
     Monotonic,
@@ -63,6 +64,7 @@ pub enum ClockType {
     ThreadCpu,
 }
 
+#[cfg(test)]
 impl From<ClockType> for libc::clockid_t {
     fn from(ct: ClockType) -> libc::clockid_t {
         match ct {
@@ -79,6 +81,7 @@ impl From<ClockType> for libc::clockid_t {
 /// # Arguments
 ///
 /// * `clock_type` - Identifier of the Linux Kernel clock on which to act.
+#[cfg(test)]
 pub fn get_time(clock_type: ClockType) -> u64 {
     let mut time_struct = libc::timespec {
         tv_sec: 0,
@@ -101,7 +104,9 @@ pub fn seconds_to_nanoseconds(value: i64) -> Option<i64> {
 
 /// A RTC device following the PL031 specification..
 pub struct Rtc {
-    previous_now: Instant,
+    clock: Arc<Mutex<RtcClock>>,
+    // Time of the clock when the RTC was last loaded.
+    load_time: i64,
     tick_offset: i64,
     // This is used for implementing the RTC alarm. However, in Firecracker we do not need it.
     match_value: u32,
@@ -113,12 +118,14 @@ pub struct Rtc {
 }
 
 impl Rtc {
-    /// Constructs an AMBA PL031 RTC device.
-    pub fn new(interrupt: Arc<dyn InterruptSourceGroup>) -> Self {
+    /// Constructs an AMBA PL031 RTC device, initially set to the time of
+    /// `clock`.
+    pub fn new(interrupt: Arc<dyn InterruptSourceGroup>, clock: Arc<Mutex<RtcClock>>) -> Self {
+        let now = clock.lock().unwrap().now();
         Self {
-            // This is used only for duration measuring purposes.
-            previous_now: Instant::now(),
-            tick_offset: get_time(ClockType::Real) as i64,
+            clock,
+            load_time: now,
+            tick_offset: now,
             match_value: 0,
             load: 0,
             imsc: 0,
@@ -134,7 +141,7 @@ impl Rtc {
 
     fn get_time(&self) -> u32 {
         let ts = (self.tick_offset as i128)
-            + (Instant::now().duration_since(self.previous_now).as_nanos() as i128);
+            + ((self.clock.lock().unwrap().now() - self.load_time) as i128);
         (ts / NANOS_PER_SECOND as i128) as u32
     }
 
@@ -150,7 +157,7 @@ impl Rtc {
             }
             RTCLR => {
                 self.load = val;
-                self.previous_now = Instant::now();
+                self.load_time = self.clock.lock().unwrap().now();
                 // If the unwrap fails, then the internal value of the clock has been corrupted and
                 // we want to terminate the execution of the process.
                 self.tick_offset = seconds_to_nanoseconds(i64::from(val)).unwrap();
@@ -404,7 +411,10 @@ mod tests {
     fn test_rtc_read_write_and_event() {
         let intr_evt = EventFd::new(libc::EFD_NONBLOCK).unwrap();
 
-        let mut rtc = Rtc::new(Arc::new(TestInterrupt::new(intr_evt.try_clone().unwrap())));
+        let mut rtc = Rtc::new(
+            Arc::new(TestInterrupt::new(intr_evt.try_clone().unwrap())),
+            Arc::new(Mutex::new(RtcClock::new(0, false))),
+        );
         let mut data = [0; 4];
 
         // Read and write to the MR register.
@@ -417,10 +427,10 @@ mod tests {
         // Read and write to the LR register.
         let v = get_time(ClockType::Real);
         write_le_u32(&mut data, (v / NANOS_PER_SECOND) as u32);
-        let previous_now_before = rtc.previous_now;
+        let load_time_before = rtc.load_time;
         rtc.write(LEGACY_RTC_MAPPED_IO_START, RTCLR, &data);
 
-        assert!(rtc.previous_now > previous_now_before);
+        assert!(rtc.load_time > load_time_before);
 
         rtc.read(LEGACY_RTC_MAPPED_IO_START, RTCLR, &mut data);
         let v_read = read_le_u32(&data);
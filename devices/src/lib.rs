@@ -25,12 +25,16 @@ pub mod interrupt_controller;
 #[cfg(target_arch = "x86_64")]
 pub mod ioapic;
 pub mod legacy;
+pub mod nvdimm;
+pub mod nvme;
 #[cfg(feature = "pvmemcontrol")]
 pub mod pvmemcontrol;
 pub mod pvpanic;
 // TODO: TPM is not yet supported
 #[cfg(not(target_arch = "riscv64"))]
 pub mod tpm;
+#[cfg(target_arch = "x86_64")]
+pub mod vmbus;
 
 pub use self::acpi::{AcpiGedDevice, AcpiPmTimerDevice, AcpiShutdownDevice};
 pub use self::pvpanic::{PvPanicDevice, PVPANIC_DEVICE_MMIO_SIZE};
@@ -0,0 +1,566 @@
+// Copyright © 2025 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! ACPI NVDIMM support, exposing persistent memory through the NFIT along
+//! with a namespace label area the guest manages through _DSM methods.
+//!
+//! The _DSM methods forward their arguments to the VMM through a shared MMIO
+//! region: the handle of the target NVDIMM, the function index and the input
+//! buffer are written first, then a write to the execute register makes the
+//! VMM process the request and fill the output buffer.
+
+use std::fs::File;
+use std::os::unix::fs::FileExt;
+use std::sync::{Arc, Barrier};
+
+use acpi_tables::{aml, Aml, AmlSink};
+use vm_device::BusDevice;
+use vm_memory::GuestAddress;
+
+/// Size of the namespace label area reserved at the end of the backing file.
+pub const NVDIMM_LABEL_AREA_SIZE: u64 = 0x2_0000;
+
+// Layout of the MMIO region shared with the _DSM methods
+const NVDIMM_DSM_HANDLE: usize = 0x0;
+const NVDIMM_DSM_FUNCTION: usize = 0x4;
+const NVDIMM_DSM_INPUT_LEN: usize = 0x8;
+const NVDIMM_DSM_OUTPUT_LEN: usize = 0xc;
+const NVDIMM_DSM_EXECUTE: usize = 0x10;
+const NVDIMM_DSM_BUFFERS: usize = 0x100;
+const NVDIMM_DSM_BUFFER_SIZE: usize = 0x1000;
+const NVDIMM_DSM_INPUT: usize = NVDIMM_DSM_BUFFERS;
+const NVDIMM_DSM_OUTPUT: usize = NVDIMM_DSM_INPUT + NVDIMM_DSM_BUFFER_SIZE;
+pub const NVDIMM_DSM_MMIO_SIZE: usize = NVDIMM_DSM_OUTPUT + NVDIMM_DSM_BUFFER_SIZE;
+
+// Largest label transfer, leaving room for the offset and length of the
+// Set Namespace Label Data input.
+const NVDIMM_MAX_TRANSFER: u32 = (NVDIMM_DSM_BUFFER_SIZE - 8) as u32;
+
+// Functions of the Intel _DSM interface for NVDIMM devices
+const DSM_FN_QUERY: u32 = 0;
+const DSM_FN_GET_LABEL_SIZE: u32 = 4;
+const DSM_FN_GET_LABEL_DATA: u32 = 5;
+const DSM_FN_SET_LABEL_DATA: u32 = 6;
+const DSM_SUPPORTED_FUNCTIONS: u8 = (1 << DSM_FN_QUERY)
+    | (1 << DSM_FN_GET_LABEL_SIZE)
+    | (1 << DSM_FN_GET_LABEL_DATA)
+    | (1 << DSM_FN_SET_LABEL_DATA);
+
+// Status codes of the Intel _DSM interface
+const DSM_STATUS_SUCCESS: u32 = 0;
+const DSM_STATUS_NOT_SUPPORTED: u32 = 1;
+const DSM_STATUS_INVALID_INPUT: u32 = 3;
+const DSM_STATUS_HW_ERROR: u32 = 4;
+
+// 4309AC30-0D11-11E4-9191-0800200C9A66 (Intel NVDIMM _DSM), mixed endian
+const NVDIMM_DSM_UUID: [u8; 16] = [
+    0x30, 0xac, 0x09, 0x43, 0x11, 0x0d, 0xe4, 0x11, 0x91, 0x91, 0x08, 0x00, 0x20, 0x0c, 0x9a, 0x66,
+];
+
+// AML opcodes not provided by acpi_tables
+const AML_DEREF_OF_OP: u8 = 0x83;
+const AML_SIZE_OF_OP: u8 = 0x87;
+const AML_INDEX_OP: u8 = 0x88;
+const AML_MID_OP: u8 = 0x9e;
+const AML_NULL_NAME: u8 = 0x00;
+
+// SizeOf(object)
+struct SizeOf<'a>(&'a dyn Aml);
+
+impl Aml for SizeOf<'_> {
+    fn to_aml_bytes(&self, sink: &mut dyn AmlSink) {
+        sink.byte(AML_SIZE_OF_OP);
+        self.0.to_aml_bytes(sink);
+    }
+}
+
+// DerefOf(Index(object, index))
+struct DerefOfIndex<'a>(&'a dyn Aml, &'a dyn Aml);
+
+impl Aml for DerefOfIndex<'_> {
+    fn to_aml_bytes(&self, sink: &mut dyn AmlSink) {
+        sink.byte(AML_DEREF_OF_OP);
+        sink.byte(AML_INDEX_OP);
+        self.0.to_aml_bytes(sink);
+        self.1.to_aml_bytes(sink);
+        sink.byte(AML_NULL_NAME);
+    }
+}
+
+// Mid(object, index, length)
+struct Mid<'a>(&'a dyn Aml, &'a dyn Aml, &'a dyn Aml);
+
+impl Aml for Mid<'_> {
+    fn to_aml_bytes(&self, sink: &mut dyn AmlSink) {
+        sink.byte(AML_MID_OP);
+        self.0.to_aml_bytes(sink);
+        self.1.to_aml_bytes(sink);
+        self.2.to_aml_bytes(sink);
+        sink.byte(AML_NULL_NAME);
+    }
+}
+
+/// Namespace label area of an NVDIMM, stored in its backing file.
+pub struct NvdimmLabelArea {
+    file: File,
+    offset: u64,
+    size: u32,
+    writable: bool,
+}
+
+impl NvdimmLabelArea {
+    pub fn new(file: File, offset: u64, size: u32, writable: bool) -> Self {
+        NvdimmLabelArea {
+            file,
+            offset,
+            size,
+            writable,
+        }
+    }
+
+    // Decode the offset and length of a label data request, making sure
+    // the range fits in both the label area and the input buffer.
+    fn range(&self, input: &[u8]) -> Option<(u32, u32)> {
+        let offset = u32::from_le_bytes(input.get(0..4)?.try_into().unwrap());
+        let length = u32::from_le_bytes(input.get(4..8)?.try_into().unwrap());
+        if length > NVDIMM_MAX_TRANSFER || offset.checked_add(length)? > self.size {
+            return None;
+        }
+
+        Some((offset, length))
+    }
+
+    fn get_size(&self) -> Vec<u8> {
+        let mut output = DSM_STATUS_SUCCESS.to_le_bytes().to_vec();
+        output.extend(self.size.to_le_bytes());
+        output.extend(NVDIMM_MAX_TRANSFER.to_le_bytes());
+        output
+    }
+
+    fn get_data(&self, input: &[u8]) -> Vec<u8> {
+        let Some((offset, length)) = self.range(input) else {
+            return DSM_STATUS_INVALID_INPUT.to_le_bytes().to_vec();
+        };
+
+        let mut data = vec![0; length as usize];
+        if let Err(e) = self
+            .file
+            .read_exact_at(&mut data, self.offset + offset as u64)
+        {
+            error!("Error reading NVDIMM labels: {}", e);
+            return DSM_STATUS_HW_ERROR.to_le_bytes().to_vec();
+        }
+
+        let mut output = DSM_STATUS_SUCCESS.to_le_bytes().to_vec();
+        output.extend(data);
+        output
+    }
+
+    fn set_data(&self, input: &[u8]) -> Vec<u8> {
+        if !self.writable {
+            return DSM_STATUS_NOT_SUPPORTED.to_le_bytes().to_vec();
+        }
+
+        let Some((offset, data)) = self
+            .range(input)
+            .and_then(|(offset, length)| Some((offset, input.get(8..8 + length as usize)?)))
+        else {
+            return DSM_STATUS_INVALID_INPUT.to_le_bytes().to_vec();
+        };
+
+        if let Err(e) = self.file.write_all_at(data, self.offset + offset as u64) {
+            error!("Error writing NVDIMM labels: {}", e);
+            return DSM_STATUS_HW_ERROR.to_le_bytes().to_vec();
+        }
+
+        DSM_STATUS_SUCCESS.to_le_bytes().to_vec()
+    }
+
+    fn dsm(&self, function: u32, input: &[u8]) -> Vec<u8> {
+        match function {
+            DSM_FN_QUERY => vec![DSM_SUPPORTED_FUNCTIONS],
+            DSM_FN_GET_LABEL_SIZE => self.get_size(),
+            DSM_FN_GET_LABEL_DATA => self.get_data(input),
+            DSM_FN_SET_LABEL_DATA => self.set_data(input),
+            _ => DSM_STATUS_NOT_SUPPORTED.to_le_bytes().to_vec(),
+        }
+    }
+}
+
+/// Persistent memory range described as an NVDIMM.
+pub struct Nvdimm {
+    /// NFIT device handle, also used as the _ADR of the ACPI device.
+    pub handle: u32,
+    /// Guest physical range of the persistent memory.
+    pub base: GuestAddress,
+    pub size: u64,
+    label_area: NvdimmLabelArea,
+}
+
+impl Nvdimm {
+    pub fn new(handle: u32, base: GuestAddress, size: u64, label_area: NvdimmLabelArea) -> Self {
+        Nvdimm {
+            handle,
+            base,
+            size,
+            label_area,
+        }
+    }
+}
+
+impl Aml for Nvdimm {
+    fn to_aml_bytes(&self, sink: &mut dyn AmlSink) {
+        aml::Device::new(
+            format!("_SB_.NVDR.NV{:02X}", self.handle).as_str().into(),
+            vec![
+                &aml::Name::new("_ADR".into(), &self.handle),
+                &aml::Method::new(
+                    "_DSM".into(),
+                    4,
+                    false,
+                    vec![
+                        &aml::If::new(
+                            &aml::Equal::new(
+                                &aml::Arg(0),
+                                &aml::BufferData::new(NVDIMM_DSM_UUID.to_vec()),
+                            ),
+                            vec![&aml::Return::new(&aml::MethodCall::new(
+                                "\\_SB_.NVDR.NCAL".into(),
+                                vec![&aml::Arg(2), &aml::Arg(3), &self.handle],
+                            ))],
+                        ),
+                        &aml::Return::new(&aml::BufferData::new(vec![0])),
+                    ],
+                ),
+            ],
+        )
+        .to_aml_bytes(sink)
+    }
+}
+
+/// Backend of the _DSM methods of the NVDIMM root device and its children.
+pub struct NvdimmController {
+    address: GuestAddress,
+    nvdimms: Vec<Nvdimm>,
+    regs: Vec<u8>,
+}
+
+impl NvdimmController {
+    pub fn new(address: GuestAddress, nvdimms: Vec<Nvdimm>) -> Self {
+        NvdimmController {
+            address,
+            nvdimms,
+            regs: vec![0; NVDIMM_DSM_MMIO_SIZE],
+        }
+    }
+
+    pub fn nvdimms(&self) -> &[Nvdimm] {
+        &self.nvdimms
+    }
+
+    fn reg(&self, offset: usize) -> u32 {
+        u32::from_le_bytes(self.regs[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn execute(&mut self) {
+        let handle = self.reg(NVDIMM_DSM_HANDLE);
+        let function = self.reg(NVDIMM_DSM_FUNCTION);
+        let input_len = (self.reg(NVDIMM_DSM_INPUT_LEN) as usize).min(NVDIMM_DSM_BUFFER_SIZE);
+        let input = &self.regs[NVDIMM_DSM_INPUT..NVDIMM_DSM_INPUT + input_len];
+
+        let mut output = match self.nvdimms.iter().find(|n| n.handle == handle) {
+            Some(nvdimm) => nvdimm.label_area.dsm(function, input),
+            None => DSM_STATUS_NOT_SUPPORTED.to_le_bytes().to_vec(),
+        };
+        output.truncate(NVDIMM_DSM_BUFFER_SIZE);
+
+        self.regs[NVDIMM_DSM_OUTPUT_LEN..NVDIMM_DSM_OUTPUT_LEN + 4]
+            .copy_from_slice(&(output.len() as u32).to_le_bytes());
+        self.regs[NVDIMM_DSM_OUTPUT..NVDIMM_DSM_OUTPUT + output.len()].copy_from_slice(&output);
+    }
+}
+
+impl BusDevice for NvdimmController {
+    fn read(&mut self, _base: u64, offset: u64, data: &mut [u8]) {
+        let offset = offset as usize;
+        match self.regs.get(offset..offset + data.len()) {
+            Some(regs) => data.copy_from_slice(regs),
+            None => {
+                warn!("Invalid NVDIMM controller read: offset 0x{:x}", offset);
+                data.fill(0);
+            }
+        }
+    }
+
+    fn write(&mut self, _base: u64, offset: u64, data: &[u8]) -> Option<Arc<Barrier>> {
+        let offset = offset as usize;
+        match self.regs.get_mut(offset..offset + data.len()) {
+            Some(regs) => regs.copy_from_slice(data),
+            None => {
+                warn!("Invalid NVDIMM controller write: offset 0x{:x}", offset);
+                return None;
+            }
+        }
+
+        if offset == NVDIMM_DSM_EXECUTE {
+            self.execute();
+        }
+
+        None
+    }
+}
+
+impl Aml for NvdimmController {
+    fn to_aml_bytes(&self, sink: &mut dyn AmlSink) {
+        /*
+        Method (NCAL, 3, Serialized)
+        {
+            NHDL = Arg2
+            NFUN = Arg0
+            NILN = Zero
+            If ((Zero < SizeOf (Arg1)))
+            {
+                Local0 = DerefOf (Arg1 [Zero])
+                NILN = SizeOf (Local0)
+                NIBF = Local0
+            }
+            NEXE = One
+            Local1 = NOBF
+            Return (Mid (Local1, Zero, NOLN))
+        }
+        */
+        aml::Device::new(
+            "_SB_.NVDR".into(),
+            vec![
+                &aml::Name::new("_HID".into(), &"ACPI0012"),
+                // The root device doesn't implement any function
+                &aml::Method::new(
+                    "_DSM".into(),
+                    4,
+                    false,
+                    vec![&aml::Return::new(&aml::BufferData::new(vec![0]))],
+                ),
+                &aml::OpRegion::new(
+                    "NVDC".into(),
+                    aml::OpRegionSpace::SystemMemory,
+                    &(self.address.0 as usize),
+                    &NVDIMM_DSM_MMIO_SIZE,
+                ),
+                &aml::Field::new(
+                    "NVDC".into(),
+                    aml::FieldAccessType::DWord,
+                    aml::FieldLockRule::NoLock,
+                    aml::FieldUpdateRule::Preserve,
+                    vec![
+                        aml::FieldEntry::Named(*b"NHDL", 32),
+                        aml::FieldEntry::Named(*b"NFUN", 32),
+                        aml::FieldEntry::Named(*b"NILN", 32),
+                        aml::FieldEntry::Named(*b"NOLN", 32),
+                        aml::FieldEntry::Named(*b"NEXE", 32),
+                    ],
+                ),
+                &aml::Field::new(
+                    "NVDC".into(),
+                    aml::FieldAccessType::QWord,
+                    aml::FieldLockRule::NoLock,
+                    aml::FieldUpdateRule::Preserve,
+                    vec![
+                        aml::FieldEntry::Reserved(NVDIMM_DSM_BUFFERS * 8),
+                        aml::FieldEntry::Named(*b"NIBF", NVDIMM_DSM_BUFFER_SIZE * 8),
+                        aml::FieldEntry::Named(*b"NOBF", NVDIMM_DSM_BUFFER_SIZE * 8),
+                    ],
+                ),
+                &aml::Method::new(
+                    "NCAL".into(),
+                    3,
+                    true,
+                    vec![
+                        &aml::Store::new(&aml::Path::new("NHDL"), &aml::Arg(2)),
+                        &aml::Store::new(&aml::Path::new("NFUN"), &aml::Arg(0)),
+                        &aml::Store::new(&aml::Path::new("NILN"), &aml::ZERO),
+                        &aml::If::new(
+                            &aml::LessThan::new(&aml::ZERO, &SizeOf(&aml::Arg(1))),
+                            vec![
+                                &aml::Store::new(
+                                    &aml::Local(0),
+                                    &DerefOfIndex(&aml::Arg(1), &aml::ZERO),
+                                ),
+                                &aml::Store::new(&aml::Path::new("NILN"), &SizeOf(&aml::Local(0))),
+                                &aml::Store::new(&aml::Path::new("NIBF"), &aml::Local(0)),
+                            ],
+                        ),
+                        &aml::Store::new(&aml::Path::new("NEXE"), &aml::ONE),
+                        &aml::Store::new(&aml::Local(1), &aml::Path::new("NOBF")),
+                        &aml::Return::new(&Mid(
+                            &aml::Local(1),
+                            &aml::ZERO,
+                            &aml::Path::new("NOLN"),
+                        )),
+                    ],
+                ),
+            ],
+        )
+        .to_aml_bytes(sink);
+
+        for nvdimm in &self.nvdimms {
+            nvdimm.to_aml_bytes(sink);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use vmm_sys_util::tempfile::TempFile;
+
+    use super::*;
+
+    const LABEL_AREA_OFFSET: u64 = 0x1000;
+
+    fn controller(writable: bool) -> NvdimmController {
+        let mut file = TempFile::new().unwrap().into_file();
+        file.write_all(&[0xaa; LABEL_AREA_OFFSET as usize + NVDIMM_LABEL_AREA_SIZE as usize])
+            .unwrap();
+        let label_area = NvdimmLabelArea::new(
+            file,
+            LABEL_AREA_OFFSET,
+            NVDIMM_LABEL_AREA_SIZE as u32,
+            writable,
+        );
+        NvdimmController::new(
+            GuestAddress(0),
+            vec![Nvdimm::new(
+                1,
+                GuestAddress(0x1_0000_0000),
+                0x20_0000,
+                label_area,
+            )],
+        )
+    }
+
+    fn call(
+        controller: &mut NvdimmController,
+        handle: u32,
+        function: u32,
+        input: &[u8],
+    ) -> Vec<u8> {
+        controller.write(0, NVDIMM_DSM_HANDLE as u64, &handle.to_le_bytes());
+        controller.write(0, NVDIMM_DSM_FUNCTION as u64, &function.to_le_bytes());
+        controller.write(
+            0,
+            NVDIMM_DSM_INPUT_LEN as u64,
+            &(input.len() as u32).to_le_bytes(),
+        );
+        for (i, chunk) in input.chunks(8).enumerate() {
+            controller.write(0, (NVDIMM_DSM_INPUT + i * 8) as u64, chunk);
+        }
+        controller.write(0, NVDIMM_DSM_EXECUTE as u64, &1u32.to_le_bytes());
+
+        let mut len = [0u8; 4];
+        controller.read(0, NVDIMM_DSM_OUTPUT_LEN as u64, &mut len);
+        let mut output = vec![0u8; u32::from_le_bytes(len) as usize];
+        controller.read(0, NVDIMM_DSM_OUTPUT as u64, &mut output);
+        output
+    }
+
+    fn label_request(offset: u32, length: u32, data: &[u8]) -> Vec<u8> {
+        let mut input = offset.to_le_bytes().to_vec();
+        input.extend(length.to_le_bytes());
+        input.extend(data);
+        input
+    }
+
+    #[test]
+    fn test_nvdimm_dsm_query() {
+        let mut controller = controller(true);
+        assert_eq!(call(&mut controller, 1, DSM_FN_QUERY, &[]), vec![0x71]);
+
+        let mut size = DSM_STATUS_SUCCESS.to_le_bytes().to_vec();
+        size.extend((NVDIMM_LABEL_AREA_SIZE as u32).to_le_bytes());
+        size.extend(NVDIMM_MAX_TRANSFER.to_le_bytes());
+        assert_eq!(call(&mut controller, 1, DSM_FN_GET_LABEL_SIZE, &[]), size);
+
+        // Unknown function and unknown NVDIMM
+        assert_eq!(
+            call(&mut controller, 1, 7, &[]),
+            DSM_STATUS_NOT_SUPPORTED.to_le_bytes()
+        );
+        assert_eq!(
+            call(&mut controller, 2, DSM_FN_QUERY, &[]),
+            DSM_STATUS_NOT_SUPPORTED.to_le_bytes()
+        );
+    }
+
+    #[test]
+    fn test_nvdimm_dsm_label_data() {
+        let mut controller = controller(true);
+
+        let mut expected = DSM_STATUS_SUCCESS.to_le_bytes().to_vec();
+        expected.extend([0xaa; 16]);
+        assert_eq!(
+            call(
+                &mut controller,
+                1,
+                DSM_FN_GET_LABEL_DATA,
+                &label_request(0x100, 16, &[])
+            ),
+            expected
+        );
+
+        assert_eq!(
+            call(
+                &mut controller,
+                1,
+                DSM_FN_SET_LABEL_DATA,
+                &label_request(0x100, 8, &[0x55; 8])
+            ),
+            DSM_STATUS_SUCCESS.to_le_bytes()
+        );
+        let mut expected = DSM_STATUS_SUCCESS.to_le_bytes().to_vec();
+        expected.extend([0x55; 8]);
+        expected.extend([0xaa; 8]);
+        assert_eq!(
+            call(
+                &mut controller,
+                1,
+                DSM_FN_GET_LABEL_DATA,
+                &label_request(0x100, 16, &[])
+            ),
+            expected
+        );
+
+        // Out of the label area, larger than the transfer size, or missing
+        // data to write
+        for (function, input) in [
+            (
+                DSM_FN_GET_LABEL_DATA,
+                label_request(NVDIMM_LABEL_AREA_SIZE as u32 - 4, 8, &[]),
+            ),
+            (
+                DSM_FN_GET_LABEL_DATA,
+                label_request(0, NVDIMM_MAX_TRANSFER + 1, &[]),
+            ),
+            (DSM_FN_SET_LABEL_DATA, label_request(0, 8, &[0x55; 4])),
+            (DSM_FN_GET_LABEL_DATA, vec![0; 4]),
+        ] {
+            assert_eq!(
+                call(&mut controller, 1, function, &input),
+                DSM_STATUS_INVALID_INPUT.to_le_bytes()
+            );
+        }
+
+        let mut controller = self::controller(false);
+        assert_eq!(
+            call(
+                &mut controller,
+                1,
+                DSM_FN_SET_LABEL_DATA,
+                &label_request(0, 8, &[0x55; 8])
+            ),
+            DSM_STATUS_NOT_SUPPORTED.to_le_bytes()
+        );
+    }
+}
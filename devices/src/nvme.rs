@@ -0,0 +1,1769 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! NVMe controller emulation.
+//!
+//! Emulates an NVM Express 1.4 controller exposing a single namespace backed
+//! by a disk image, for the guests lacking virtio drivers. The controller
+//! supports the admin commands needed by the common drivers and the NVM
+//! Read, Write and Flush commands, with the data described by PRPs and the
+//! completions signaled through MSI-X.
+//!
+//! The doorbells are handled as MMIO exits, while the commands are processed
+//! by a worker thread submitting the I/O to the block backend.
+
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+use std::sync::atomic::{fence, Ordering};
+use std::sync::{Arc, Barrier, Mutex};
+use std::time::Duration;
+use std::{cmp, io, result, thread};
+
+use anyhow::anyhow;
+use block::async_io::{AsyncIo, DiskFile, DiskFileError};
+use block::fcntl::{self, LockError, LockType};
+use pci::{
+    BarReprogrammingParams, MsixCap, MsixConfig, PciBarConfiguration, PciBarPrefetchable,
+    PciBarRegionType, PciClassCode, PciConfiguration, PciDevice, PciDeviceError, PciHeaderType,
+    PciMassStorageSubclass, PciProgrammingInterface, PCI_CONFIGURATION_ID,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use vm_allocator::{AddressAllocator, SystemAllocator};
+use vm_device::interrupt::{
+    InterruptIndex, InterruptManager, InterruptSourceGroup, MsiIrqGroupConfig,
+};
+use vm_device::{BusDevice, Resource};
+use vm_memory::bitmap::{AtomicBitmap, Bitmap};
+use vm_memory::{
+    Address, Bytes, GuestAddress, GuestAddressSpace, GuestMemory, GuestMemoryAtomic,
+    GuestMemoryMmap,
+};
+use vm_migration::{Migratable, MigratableError, Pausable, Snapshot, Snapshottable, Transportable};
+use vmm_sys_util::epoll::{ControlOperation, Epoll, EpollEvent, EventSet};
+use vmm_sys_util::eventfd::EventFd;
+
+type GuestMemoryMmapAtomic = GuestMemoryAtomic<GuestMemoryMmap<AtomicBitmap>>;
+
+const NVME_VENDOR_ID: u16 = 0x1b36;
+const NVME_DEVICE_ID: u16 = 0x0010;
+
+// The BAR holds the registers, the doorbells and the MSI-X structures.
+const NVME_BAR_INDEX: usize = 0;
+pub const NVME_BAR_SIZE: u64 = 0x4000;
+const DOORBELL_OFFSET: u64 = 0x1000;
+const MSIX_TABLE_OFFSET: u64 = 0x2000;
+const MSIX_PBA_OFFSET: u64 = 0x3000;
+const MSIX_STRUCTURE_SIZE: u64 = 0x1000;
+
+/// Largest number of I/O queue pairs, each with its own MSI-X vector besides
+/// the one of the admin queue.
+pub const NVME_MAX_IO_QUEUES: usize = 255;
+
+// Controller registers
+const REG_CAP: u64 = 0x00;
+const REG_VS: u64 = 0x08;
+const REG_CC: u64 = 0x14;
+const REG_CSTS: u64 = 0x1c;
+const REG_AQA: u64 = 0x24;
+const REG_ASQ: u64 = 0x28;
+const REG_ACQ: u64 = 0x30;
+
+const NVME_VERSION: u32 = 0x0001_0400;
+
+const CC_EN: u32 = 1 << 0;
+const CC_CSS_SHIFT: u32 = 4;
+const CC_MPS_SHIFT: u32 = 7;
+const CC_SHN_SHIFT: u32 = 14;
+const CC_IOSQES_SHIFT: u32 = 16;
+const CC_IOCQES_SHIFT: u32 = 20;
+const CSTS_RDY: u32 = 1 << 0;
+const CSTS_CFS: u32 = 1 << 1;
+const CSTS_SHST_SHIFT: u32 = 2;
+const CSTS_SHST_MASK: u32 = 0x3 << CSTS_SHST_SHIFT;
+const SHST_PROCESSING: u32 = 0x1;
+const SHST_COMPLETE: u32 = 0x2;
+
+// Time the guest waits for the controller to become ready, in 500ms units.
+const CAP_TIMEOUT: u64 = 30;
+
+// Only 4 KiB memory pages and 512 bytes logical blocks are supported.
+const PAGE_SIZE: u64 = 0x1000;
+const PAGE_MASK: u64 = PAGE_SIZE - 1;
+const LBA_SHIFT: u32 = 9;
+// Largest transfer of a command, as a power of two of pages.
+const MDTS: u8 = 7;
+const MAX_TRANSFER_SIZE: u64 = PAGE_SIZE << MDTS;
+
+const SQ_ENTRY_SIZE: u64 = 64;
+const CQ_ENTRY_SIZE: u64 = 16;
+const SQES: u32 = 6;
+const CQES: u32 = 4;
+
+// Number of outstanding asynchronous event requests, 0's based.
+const AERL: u8 = 3;
+// Number of concurrent aborts, 0's based.
+const ACL: u8 = 3;
+
+const NAMESPACE_ID: u32 = 1;
+const IDENTIFY_SIZE: usize = 4096;
+const LOG_PAGE_SIZE: usize = 512;
+const ERROR_LOG_ENTRY_SIZE: usize = 64;
+
+// Composite temperature reported through the SMART log, in Kelvin.
+const COMPOSITE_TEMPERATURE: u16 = 0x0141;
+const TEMPERATURE_THRESHOLD: u32 = 0x0157;
+
+// Admin commands
+const ADMIN_DELETE_IO_SQ: u8 = 0x00;
+const ADMIN_CREATE_IO_SQ: u8 = 0x01;
+const ADMIN_GET_LOG_PAGE: u8 = 0x02;
+const ADMIN_DELETE_IO_CQ: u8 = 0x04;
+const ADMIN_CREATE_IO_CQ: u8 = 0x05;
+const ADMIN_IDENTIFY: u8 = 0x06;
+const ADMIN_ABORT: u8 = 0x08;
+const ADMIN_SET_FEATURES: u8 = 0x09;
+const ADMIN_GET_FEATURES: u8 = 0x0a;
+const ADMIN_ASYNC_EVENT_REQUEST: u8 = 0x0c;
+
+// NVM commands
+const NVM_FLUSH: u8 = 0x00;
+const NVM_WRITE: u8 = 0x01;
+const NVM_READ: u8 = 0x02;
+
+// Identify data structures
+const CNS_NAMESPACE: u8 = 0x00;
+const CNS_CONTROLLER: u8 = 0x01;
+const CNS_ACTIVE_NAMESPACES: u8 = 0x02;
+const CNS_NAMESPACE_DESCRIPTORS: u8 = 0x03;
+
+// Log pages
+const LOG_ERROR_INFORMATION: u8 = 0x01;
+const LOG_SMART: u8 = 0x02;
+const LOG_FIRMWARE_SLOT: u8 = 0x03;
+
+// Features
+const FEATURE_ARBITRATION: u8 = 0x01;
+const FEATURE_POWER_MANAGEMENT: u8 = 0x02;
+const FEATURE_TEMPERATURE_THRESHOLD: u8 = 0x04;
+const FEATURE_ERROR_RECOVERY: u8 = 0x05;
+const FEATURE_VOLATILE_WRITE_CACHE: u8 = 0x06;
+const FEATURE_NUMBER_OF_QUEUES: u8 = 0x07;
+const FEATURE_INTERRUPT_COALESCING: u8 = 0x08;
+const FEATURE_INTERRUPT_VECTOR_CONFIG: u8 = 0x09;
+const FEATURE_WRITE_ATOMICITY: u8 = 0x0a;
+const FEATURE_ASYNC_EVENT_CONFIG: u8 = 0x0b;
+
+// Status codes, the status code type in the upper byte.
+const SC_SUCCESS: u16 = 0x00;
+const SC_INVALID_OPCODE: u16 = 0x01;
+const SC_INVALID_FIELD: u16 = 0x02;
+const SC_DATA_TRANSFER_ERROR: u16 = 0x04;
+const SC_INTERNAL_ERROR: u16 = 0x06;
+const SC_INVALID_NAMESPACE: u16 = 0x0b;
+const SC_INVALID_PRP_OFFSET: u16 = 0x13;
+const SC_NAMESPACE_WRITE_PROTECTED: u16 = 0x20;
+const SC_LBA_OUT_OF_RANGE: u16 = 0x80;
+const SC_COMPLETION_QUEUE_INVALID: u16 = 0x100;
+const SC_INVALID_QUEUE_ID: u16 = 0x101;
+const SC_INVALID_QUEUE_SIZE: u16 = 0x102;
+const SC_AER_LIMIT_EXCEEDED: u16 = 0x105;
+const SC_INVALID_INTERRUPT_VECTOR: u16 = 0x108;
+const SC_INVALID_LOG_PAGE: u16 = 0x109;
+const SC_INVALID_QUEUE_DELETION: u16 = 0x10c;
+const SC_FEATURE_NOT_SAVEABLE: u16 = 0x10d;
+const SC_WRITE_FAULT: u16 = 0x280;
+const SC_UNRECOVERED_READ_ERROR: u16 = 0x281;
+// Do Not Retry
+const STATUS_DNR: u16 = 1 << 14;
+
+// Worker events
+const KILL_EVENT: u64 = 0;
+const KICK_EVENT: u64 = 1;
+const IO_EVENT: u64 = 2;
+
+// Interval at which pausing checks the I/O completed.
+const PAUSE_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+#[derive(Debug, Error)]
+pub enum NvmeError {
+    #[error("Failed getting the size of the disk image")]
+    DiskSize(#[source] DiskFileError),
+    #[error("Failed creating the asynchronous I/O of the disk image")]
+    CreateAsyncIo(#[source] DiskFileError),
+    #[error("Failed creating the MSI-X interrupt group")]
+    CreateInterruptGroup(#[source] io::Error),
+    #[error("Failed creating the MSI-X configuration")]
+    CreateMsixConfig(#[source] anyhow::Error),
+    #[error("Failed restoring the NVMe controller")]
+    Restore(#[source] anyhow::Error),
+    #[error("Failed creating an EventFd")]
+    EventFd(#[source] io::Error),
+    #[error("Failed starting the NVMe worker")]
+    StartWorker(#[source] io::Error),
+    #[error("Failed to lock the disk image {path} ({lock_type:?})")]
+    LockDiskImage {
+        path: PathBuf,
+        #[source]
+        error: LockError,
+        lock_type: LockType,
+    },
+}
+
+struct NvmeProgrammingInterface;
+
+impl PciProgrammingInterface for NvmeProgrammingInterface {
+    fn get_register_value(&self) -> u8 {
+        // NVM Express
+        0x02
+    }
+}
+
+/// Submission queue entry.
+struct Command {
+    opcode: u8,
+    psdt: u8,
+    cid: u16,
+    nsid: u32,
+    prp1: u64,
+    prp2: u64,
+    cdw10: u32,
+    cdw11: u32,
+    cdw12: u32,
+    cdw13: u32,
+}
+
+impl Command {
+    fn from_bytes(b: &[u8; SQ_ENTRY_SIZE as usize]) -> Self {
+        let dw = |i: usize| u32::from_le_bytes(b[i * 4..i * 4 + 4].try_into().unwrap());
+        let qw = |i: usize| u64::from(dw(i)) | (u64::from(dw(i + 1)) << 32);
+        Command {
+            opcode: b[0],
+            psdt: b[1] >> 6,
+            cid: u16::from_le_bytes([b[2], b[3]]),
+            nsid: dw(1),
+            prp1: qw(6),
+            prp2: qw(8),
+            cdw10: dw(10),
+            cdw11: dw(11),
+            cdw12: dw(12),
+            cdw13: dw(13),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct CompletionEntry {
+    dw0: u32,
+    sqhd: u16,
+    sqid: u16,
+    cid: u16,
+    status: u16,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SubmissionQueue {
+    addr: u64,
+    size: u32,
+    head: u32,
+    tail: u32,
+    cqid: u16,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CompletionQueue {
+    addr: u64,
+    size: u32,
+    head: u32,
+    tail: u32,
+    phase: bool,
+    interrupts: bool,
+    vector: u16,
+    // Completions waiting for the guest to free entries of the queue.
+    pending: VecDeque<CompletionEntry>,
+}
+
+impl CompletionQueue {
+    fn is_full(&self) -> bool {
+        (self.tail + 1) % self.size == self.head
+    }
+}
+
+/// Command submitted to the disk image.
+struct Request {
+    sqid: u16,
+    cid: u16,
+    opcode: u8,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct NvmeControllerState {
+    cc: u32,
+    csts: u32,
+    aqa: u32,
+    asq: u64,
+    acq: u64,
+    sqs: BTreeMap<u16, SubmissionQueue>,
+    cqs: BTreeMap<u16, CompletionQueue>,
+    // Command identifiers of the outstanding asynchronous event requests.
+    aer_cids: Vec<u16>,
+    features: BTreeMap<u8, u32>,
+}
+
+impl NvmeControllerState {
+    fn new() -> Self {
+        NvmeControllerState {
+            cc: 0,
+            csts: 0,
+            aqa: 0,
+            asq: 0,
+            acq: 0,
+            sqs: BTreeMap::new(),
+            cqs: BTreeMap::new(),
+            aer_cids: Vec::new(),
+            features: default_features(),
+        }
+    }
+}
+
+fn default_features() -> BTreeMap<u8, u32> {
+    BTreeMap::from([
+        (FEATURE_ARBITRATION, 0),
+        (FEATURE_POWER_MANAGEMENT, 0),
+        (FEATURE_TEMPERATURE_THRESHOLD, TEMPERATURE_THRESHOLD),
+        (FEATURE_ERROR_RECOVERY, 0),
+        (FEATURE_VOLATILE_WRITE_CACHE, 1),
+        (FEATURE_INTERRUPT_COALESCING, 0),
+        (FEATURE_WRITE_ATOMICITY, 0),
+        (FEATURE_ASYNC_EVENT_CONFIG, 0),
+    ])
+}
+
+/// Returns the guest memory ranges of a transfer of `len` bytes described by
+/// the PRP entries of a command.
+fn prp_ranges<M: GuestMemory>(
+    mem: &M,
+    prp1: u64,
+    prp2: u64,
+    len: u64,
+) -> result::Result<Vec<(GuestAddress, usize)>, u16> {
+    if prp1 & 0x3 != 0 {
+        return Err(SC_INVALID_PRP_OFFSET);
+    }
+
+    let first = cmp::min(len, PAGE_SIZE - (prp1 & PAGE_MASK));
+    let mut ranges = vec![(GuestAddress(prp1), first as usize)];
+    let mut remaining = len - first;
+    if remaining == 0 {
+        return Ok(ranges);
+    }
+
+    // The second entry points to the data, or to a list of entries when the
+    // transfer spans more than two pages.
+    if remaining <= PAGE_SIZE {
+        if prp2 & PAGE_MASK != 0 {
+            return Err(SC_INVALID_PRP_OFFSET);
+        }
+        ranges.push((GuestAddress(prp2), remaining as usize));
+        return Ok(ranges);
+    }
+
+    if prp2 & 0x7 != 0 {
+        return Err(SC_INVALID_PRP_OFFSET);
+    }
+
+    let mut list = prp2;
+    'lists: loop {
+        let entries = (PAGE_SIZE - (list & PAGE_MASK)) / 8;
+        for i in 0..entries {
+            let entry = u64::from_le(
+                mem.read_obj::<u64>(GuestAddress(list + i * 8))
+                    .map_err(|_| SC_DATA_TRANSFER_ERROR)?,
+            );
+
+            // The last entry of a list points to the next list, which starts
+            // a page so that every list but the first holds data entries.
+            if i == entries - 1 && remaining > PAGE_SIZE {
+                if entry & PAGE_MASK != 0 {
+                    return Err(SC_INVALID_PRP_OFFSET);
+                }
+                list = entry;
+                continue 'lists;
+            }
+
+            if entry & PAGE_MASK != 0 {
+                return Err(SC_INVALID_PRP_OFFSET);
+            }
+            let size = cmp::min(remaining, PAGE_SIZE);
+            ranges.push((GuestAddress(entry), size as usize));
+            remaining -= size;
+            if remaining == 0 {
+                return Ok(ranges);
+            }
+        }
+    }
+}
+
+/// Copies `data` to the guest memory described by the PRP entries of a
+/// command, the transfer being `len` bytes long.
+fn write_prps<M: GuestMemory>(
+    mem: &M,
+    prp1: u64,
+    prp2: u64,
+    len: u64,
+    data: &[u8],
+) -> result::Result<(), u16> {
+    let mut data = data;
+    for (addr, size) in prp_ranges(mem, prp1, prp2, len)? {
+        let size = cmp::min(size, data.len());
+        mem.write_slice(&data[..size], addr)
+            .map_err(|_| SC_DATA_TRANSFER_ERROR)?;
+        data = &data[size..];
+        if data.is_empty() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Copies `s` to `dst`, padded with spaces as expected for the ASCII fields.
+fn copy_padded(dst: &mut [u8], s: &str) {
+    dst.fill(b' ');
+    let len = cmp::min(dst.len(), s.len());
+    dst[..len].copy_from_slice(&s.as_bytes()[..len]);
+}
+
+struct Controller {
+    id: String,
+    state: NvmeControllerState,
+    memory: GuestMemoryMmapAtomic,
+    async_io: Box<dyn AsyncIo>,
+    // Largest number of commands submitted to the disk image at once.
+    io_depth: usize,
+    inflight: HashMap<u64, Request>,
+    next_user_data: u64,
+    // Flush of the disk image on shutdown.
+    shutdown_user_data: Option<u64>,
+    reset_pending: bool,
+    paused: bool,
+    msix_config: Arc<Mutex<MsixConfig>>,
+    interrupt_source_group: Arc<dyn InterruptSourceGroup>,
+    num_io_queues: u16,
+    max_queue_entries: u32,
+    // Namespace size, in logical blocks.
+    nsze: u64,
+    readonly: bool,
+    serial: String,
+}
+
+impl Controller {
+    fn enabled(&self) -> bool {
+        self.state.csts & CSTS_RDY != 0
+    }
+
+    fn capabilities(&self) -> u64 {
+        // Contiguous queues of the NVM command set, with 4 bytes doorbells
+        // and 4 KiB pages only.
+        u64::from(self.max_queue_entries - 1) | (1 << 16) | (CAP_TIMEOUT << 24) | (1 << 37)
+    }
+
+    fn read_register(&self, offset: u64) -> u32 {
+        match offset {
+            REG_CAP => self.capabilities() as u32,
+            o if o == REG_CAP + 4 => (self.capabilities() >> 32) as u32,
+            REG_VS => NVME_VERSION,
+            REG_CC => self.state.cc,
+            REG_CSTS => self.state.csts,
+            REG_AQA => self.state.aqa,
+            REG_ASQ => self.state.asq as u32,
+            o if o == REG_ASQ + 4 => (self.state.asq >> 32) as u32,
+            REG_ACQ => self.state.acq as u32,
+            o if o == REG_ACQ + 4 => (self.state.acq >> 32) as u32,
+            _ => 0,
+        }
+    }
+
+    // Returns whether the worker has to be kicked.
+    fn write_register(&mut self, offset: u64, value: u32) -> bool {
+        match offset {
+            REG_CC => return self.write_cc(value),
+            REG_AQA => self.state.aqa = value,
+            REG_ASQ => self.state.asq = (self.state.asq & !0xffff_ffff) | u64::from(value),
+            o if o == REG_ASQ + 4 => {
+                self.state.asq = (self.state.asq & 0xffff_ffff) | (u64::from(value) << 32)
+            }
+            REG_ACQ => self.state.acq = (self.state.acq & !0xffff_ffff) | u64::from(value),
+            o if o == REG_ACQ + 4 => {
+                self.state.acq = (self.state.acq & 0xffff_ffff) | (u64::from(value) << 32)
+            }
+            // Interrupts are signaled through MSI-X only, the subsystem reset
+            // isn't supported and the other registers are read-only.
+            _ => debug!("{}: Ignoring write to register {:#x}", self.id, offset),
+        }
+
+        false
+    }
+
+    fn write_cc(&mut self, value: u32) -> bool {
+        let old = self.state.cc;
+        self.state.cc = value;
+
+        if old & CC_EN == 0 && value & CC_EN != 0 {
+            self.enable();
+            return true;
+        }
+
+        if old & CC_EN != 0 && value & CC_EN == 0 {
+            // The controller is reset once the commands being processed
+            // completed.
+            self.reset_pending = true;
+            return true;
+        }
+
+        if (old >> CC_SHN_SHIFT) & 0x3 == 0 && (value >> CC_SHN_SHIFT) & 0x3 != 0 {
+            self.state.csts =
+                (self.state.csts & !CSTS_SHST_MASK) | (SHST_PROCESSING << CSTS_SHST_SHIFT);
+            return true;
+        }
+
+        false
+    }
+
+    fn enable(&mut self) {
+        let cc = self.state.cc;
+        let asqs = (self.state.aqa & 0xfff) + 1;
+        let acqs = ((self.state.aqa >> 16) & 0xfff) + 1;
+        if (cc >> CC_CSS_SHIFT) & 0x7 != 0
+            || (cc >> CC_MPS_SHIFT) & 0xf != 0
+            || asqs < 2
+            || acqs < 2
+            || self.state.asq & PAGE_MASK != 0
+            || self.state.acq & PAGE_MASK != 0
+        {
+            error!("{}: Invalid controller configuration", self.id);
+            self.state.csts |= CSTS_CFS;
+            return;
+        }
+
+        self.state.sqs.insert(
+            0,
+            SubmissionQueue {
+                addr: self.state.asq,
+                size: asqs,
+                head: 0,
+                tail: 0,
+                cqid: 0,
+            },
+        );
+        self.state.cqs.insert(
+            0,
+            CompletionQueue {
+                addr: self.state.acq,
+                size: acqs,
+                head: 0,
+                tail: 0,
+                phase: true,
+                interrupts: true,
+                vector: 0,
+                pending: VecDeque::new(),
+            },
+        );
+        self.state.csts = CSTS_RDY;
+    }
+
+    fn reset(&mut self) {
+        debug!("{}: Resetting controller", self.id);
+        let state = NvmeControllerState::new();
+        self.state = NvmeControllerState {
+            cc: self.state.cc,
+            aqa: self.state.aqa,
+            asq: self.state.asq,
+            acq: self.state.acq,
+            ..state
+        };
+        self.reset_pending = false;
+    }
+
+    // Returns whether the worker has to be kicked.
+    fn write_doorbell(&mut self, offset: u64, value: u32) -> bool {
+        let index = offset / 4;
+        let qid = (index / 2) as u16;
+        let value = value & 0xffff;
+        if index % 2 == 0 {
+            match self.state.sqs.get_mut(&qid) {
+                Some(sq) if value < sq.size => sq.tail = value,
+                _ => {
+                    warn!("{}: Invalid submission queue {} doorbell", self.id, qid);
+                    return false;
+                }
+            }
+        } else {
+            match self.state.cqs.get_mut(&qid) {
+                Some(cq) if value < cq.size => cq.head = value,
+                _ => {
+                    warn!("{}: Invalid completion queue {} doorbell", self.id, qid);
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+
+    fn trigger_interrupt(&self, vector: u16) {
+        let mut msix_config = self.msix_config.lock().unwrap();
+        // A masked vector is only recorded as pending, to be signaled once
+        // unmasked.
+        if msix_config.masked() || msix_config.table_entries[vector as usize].masked() {
+            msix_config.set_pba_bit(vector, false);
+            return;
+        }
+
+        if let Err(e) = self
+            .interrupt_source_group
+            .trigger(vector as InterruptIndex)
+        {
+            error!("{}: Failed signaling vector {}: {}", self.id, vector, e);
+        }
+    }
+
+    fn complete(&mut self, sqid: u16, cid: u16, dw0: u32, status: u16) {
+        // The queue may have been deleted while the command was processed.
+        let Some(sq) = self.state.sqs.get(&sqid) else {
+            return;
+        };
+        let cqid = sq.cqid;
+        let entry = CompletionEntry {
+            dw0,
+            sqhd: sq.head as u16,
+            sqid,
+            cid,
+            status,
+        };
+        if let Some(cq) = self.state.cqs.get_mut(&cqid) {
+            cq.pending.push_back(entry);
+        }
+        self.post_completions(cqid);
+    }
+
+    fn post_completions(&mut self, cqid: u16) {
+        let mem = self.memory.memory();
+        let Some(cq) = self.state.cqs.get_mut(&cqid) else {
+            return;
+        };
+
+        let mut posted = false;
+        while !cq.is_full() {
+            let Some(entry) = cq.pending.pop_front() else {
+                break;
+            };
+
+            let addr = GuestAddress(cq.addr + u64::from(cq.tail) * CQ_ENTRY_SIZE);
+            let mut bytes = [0u8; CQ_ENTRY_SIZE as usize];
+            bytes[0..4].copy_from_slice(&entry.dw0.to_le_bytes());
+            bytes[8..10].copy_from_slice(&entry.sqhd.to_le_bytes());
+            bytes[10..12].copy_from_slice(&entry.sqid.to_le_bytes());
+            let dw3 = u32::from(entry.cid)
+                | (u32::from(cq.phase) << 16)
+                | (u32::from(entry.status) << 17);
+
+            // The phase tag tells the guest the entry is new, it has to be
+            // written last.
+            let result = mem.write_slice(&bytes[..12], addr).and_then(|_| {
+                fence(Ordering::Release);
+                mem.write_obj(dw3.to_le(), addr.unchecked_add(12))
+            });
+            if result.is_err() {
+                error!(
+                    "{}: Failed writing completion queue {} entry",
+                    self.id, cqid
+                );
+                self.state.csts |= CSTS_CFS;
+                return;
+            }
+
+            cq.tail = (cq.tail + 1) % cq.size;
+            if cq.tail == 0 {
+                cq.phase = !cq.phase;
+            }
+            posted = true;
+        }
+
+        if posted && cq.interrupts {
+            let vector = cq.vector;
+            self.trigger_interrupt(vector);
+        }
+    }
+
+    fn process(&mut self) {
+        self.complete_requests();
+
+        if self.reset_pending {
+            if self.inflight.is_empty() {
+                self.reset();
+            }
+            return;
+        }
+
+        // The disk image is flushed on shutdown, once the commands being
+        // processed completed.
+        if self.shutdown_requested() {
+            if self.inflight.is_empty() && self.shutdown_user_data.is_none() {
+                self.flush_on_shutdown();
+            }
+            return;
+        }
+
+        if !self.enabled() || self.paused || self.state.csts & CSTS_CFS != 0 {
+            return;
+        }
+
+        self.process_submission_queues();
+        self.complete_requests();
+
+        let cqids: Vec<u16> = self.state.cqs.keys().copied().collect();
+        for cqid in cqids {
+            self.post_completions(cqid);
+        }
+    }
+
+    fn shutdown_requested(&self) -> bool {
+        (self.state.csts & CSTS_SHST_MASK) >> CSTS_SHST_SHIFT == SHST_PROCESSING
+    }
+
+    fn flush_on_shutdown(&mut self) {
+        let user_data = self.next_user_data();
+        if let Err(e) = self.async_io.fsync(Some(user_data)) {
+            error!("{}: Failed flushing the disk image: {}", self.id, e);
+            self.shutdown_complete();
+        } else {
+            self.shutdown_user_data = Some(user_data);
+            self.complete_requests();
+        }
+    }
+
+    fn shutdown_complete(&mut self) {
+        self.state.csts = (self.state.csts & !CSTS_SHST_MASK) | (SHST_COMPLETE << CSTS_SHST_SHIFT);
+    }
+
+    fn next_user_data(&mut self) -> u64 {
+        let user_data = self.next_user_data;
+        self.next_user_data = self.next_user_data.wrapping_add(1);
+        user_data
+    }
+
+    fn complete_requests(&mut self) {
+        while let Some((user_data, result)) = self.async_io.next_completed_request() {
+            if self.shutdown_user_data == Some(user_data) {
+                self.shutdown_user_data = None;
+                self.shutdown_complete();
+                continue;
+            }
+
+            let Some(request) = self.inflight.remove(&user_data) else {
+                continue;
+            };
+            // The completions are dropped when the controller is reset.
+            if self.reset_pending {
+                continue;
+            }
+
+            let status = if result < 0 {
+                let e = io::Error::from_raw_os_error(-result);
+                error!("{}: Failed processing command: {}", self.id, e);
+                Self::io_error_status(request.opcode)
+            } else {
+                SC_SUCCESS
+            };
+            self.complete(request.sqid, request.cid, 0, status);
+        }
+    }
+
+    fn io_error_status(opcode: u8) -> u16 {
+        match opcode {
+            NVM_READ => SC_UNRECOVERED_READ_ERROR,
+            NVM_WRITE => SC_WRITE_FAULT,
+            _ => SC_INTERNAL_ERROR,
+        }
+    }
+
+    fn process_submission_queues(&mut self) {
+        let mem = self.memory.memory();
+        let sqids: Vec<u16> = self.state.sqs.keys().copied().collect();
+        for sqid in sqids {
+            while self.inflight.len() < self.io_depth {
+                // Admin commands may delete the queue.
+                let Some(sq) = self.state.sqs.get_mut(&sqid) else {
+                    break;
+                };
+                if sq.head == sq.tail {
+                    break;
+                }
+
+                let addr = GuestAddress(sq.addr + u64::from(sq.head) * SQ_ENTRY_SIZE);
+                sq.head = (sq.head + 1) % sq.size;
+
+                let mut bytes = [0u8; SQ_ENTRY_SIZE as usize];
+                if mem.read_slice(&mut bytes, addr).is_err() {
+                    error!(
+                        "{}: Failed reading submission queue {} entry",
+                        self.id, sqid
+                    );
+                    self.state.csts |= CSTS_CFS;
+                    return;
+                }
+
+                let cmd = Command::from_bytes(&bytes);
+                let result = if sqid == 0 {
+                    self.admin_command(&cmd)
+                } else {
+                    self.io_command(sqid, &cmd)
+                };
+
+                match result {
+                    Ok(Some(dw0)) => self.complete(sqid, cmd.cid, dw0, SC_SUCCESS),
+                    Ok(None) => {}
+                    Err(status) => self.complete(sqid, cmd.cid, 0, status | STATUS_DNR),
+                }
+            }
+        }
+    }
+
+    // Returns the first dword of the completion, none if the command
+    // completes later.
+    fn admin_command(&mut self, cmd: &Command) -> result::Result<Option<u32>, u16> {
+        if cmd.psdt != 0 {
+            return Err(SC_INVALID_FIELD);
+        }
+
+        match cmd.opcode {
+            ADMIN_DELETE_IO_SQ => self.delete_io_sq(cmd),
+            ADMIN_CREATE_IO_SQ => self.create_io_sq(cmd),
+            ADMIN_GET_LOG_PAGE => self.get_log_page(cmd),
+            ADMIN_DELETE_IO_CQ => self.delete_io_cq(cmd),
+            ADMIN_CREATE_IO_CQ => self.create_io_cq(cmd),
+            ADMIN_IDENTIFY => self.identify(cmd),
+            // Commands complete too fast to be aborted.
+            ADMIN_ABORT => Ok(Some(1)),
+            ADMIN_SET_FEATURES => self.set_features(cmd),
+            ADMIN_GET_FEATURES => self.get_features(cmd),
+            ADMIN_ASYNC_EVENT_REQUEST => {
+                // No event is ever reported, the requests stay outstanding.
+                if self.state.aer_cids.len() > AERL as usize {
+                    return Err(SC_AER_LIMIT_EXCEEDED);
+                }
+                self.state.aer_cids.push(cmd.cid);
+                Ok(None)
+            }
+            _ => Err(SC_INVALID_OPCODE),
+        }
+    }
+
+    fn queue_id_and_size(&self, cmd: &Command) -> result::Result<(u16, u32), u16> {
+        let qid = cmd.cdw10 as u16;
+        let size = (cmd.cdw10 >> 16) + 1;
+        if qid == 0 || qid > self.num_io_queues {
+            return Err(SC_INVALID_QUEUE_ID);
+        }
+        if size < 2 || size > self.max_queue_entries {
+            return Err(SC_INVALID_QUEUE_SIZE);
+        }
+        // The queues must be physically contiguous.
+        if cmd.cdw11 & 0x1 == 0 {
+            return Err(SC_INVALID_FIELD);
+        }
+        if cmd.prp1 & PAGE_MASK != 0 {
+            return Err(SC_INVALID_PRP_OFFSET);
+        }
+
+        Ok((qid, size))
+    }
+
+    fn create_io_cq(&mut self, cmd: &Command) -> result::Result<Option<u32>, u16> {
+        let (qid, size) = self.queue_id_and_size(cmd)?;
+        if self.state.cqs.contains_key(&qid) {
+            return Err(SC_INVALID_QUEUE_ID);
+        }
+        if (self.state.cc >> CC_IOCQES_SHIFT) & 0xf != CQES {
+            return Err(SC_INVALID_FIELD);
+        }
+
+        let interrupts = cmd.cdw11 & 0x2 != 0;
+        let vector = (cmd.cdw11 >> 16) as u16;
+        if vector > self.num_io_queues {
+            return Err(SC_INVALID_INTERRUPT_VECTOR);
+        }
+
+        self.state.cqs.insert(
+            qid,
+            CompletionQueue {
+                addr: cmd.prp1,
+                size,
+                head: 0,
+                tail: 0,
+                phase: true,
+                interrupts,
+                vector,
+                pending: VecDeque::new(),
+            },
+        );
+
+        Ok(Some(0))
+    }
+
+    fn create_io_sq(&mut self, cmd: &Command) -> result::Result<Option<u32>, u16> {
+        let (qid, size) = self.queue_id_and_size(cmd)?;
+        if self.state.sqs.contains_key(&qid) {
+            return Err(SC_INVALID_QUEUE_ID);
+        }
+        if (self.state.cc >> CC_IOSQES_SHIFT) & 0xf != SQES {
+            return Err(SC_INVALID_FIELD);
+        }
+
+        let cqid = (cmd.cdw11 >> 16) as u16;
+        if cqid == 0 || !self.state.cqs.contains_key(&cqid) {
+            return Err(SC_COMPLETION_QUEUE_INVALID);
+        }
+
+        self.state.sqs.insert(
+            qid,
+            SubmissionQueue {
+                addr: cmd.prp1,
+                size,
+                head: 0,
+                tail: 0,
+                cqid,
+            },
+        );
+
+        Ok(Some(0))
+    }
+
+    fn delete_io_sq(&mut self, cmd: &Command) -> result::Result<Option<u32>, u16> {
+        let qid = cmd.cdw10 as u16;
+        if qid == 0 || self.state.sqs.remove(&qid).is_none() {
+            return Err(SC_INVALID_QUEUE_ID);
+        }
+
+        Ok(Some(0))
+    }
+
+    fn delete_io_cq(&mut self, cmd: &Command) -> result::Result<Option<u32>, u16> {
+        let qid = cmd.cdw10 as u16;
+        if qid == 0 || !self.state.cqs.contains_key(&qid) {
+            return Err(SC_INVALID_QUEUE_ID);
+        }
+        if self.state.sqs.values().any(|sq| sq.cqid == qid) {
+            return Err(SC_INVALID_QUEUE_DELETION);
+        }
+        self.state.cqs.remove(&qid);
+
+        Ok(Some(0))
+    }
+
+    fn identify(&mut self, cmd: &Command) -> result::Result<Option<u32>, u16> {
+        let data = match cmd.cdw10 as u8 {
+            CNS_NAMESPACE => {
+                if cmd.nsid != NAMESPACE_ID {
+                    return Err(SC_INVALID_NAMESPACE);
+                }
+                self.identify_namespace()
+            }
+            CNS_CONTROLLER => self.identify_controller(),
+            CNS_ACTIVE_NAMESPACES => {
+                let mut data = vec![0u8; IDENTIFY_SIZE];
+                if cmd.nsid < NAMESPACE_ID {
+                    data[0..4].copy_from_slice(&NAMESPACE_ID.to_le_bytes());
+                }
+                data
+            }
+            CNS_NAMESPACE_DESCRIPTORS => {
+                if cmd.nsid != NAMESPACE_ID {
+                    return Err(SC_INVALID_NAMESPACE);
+                }
+                // No namespace identifier is reported.
+                vec![0u8; IDENTIFY_SIZE]
+            }
+            _ => return Err(SC_INVALID_FIELD),
+        };
+
+        write_prps(
+            &*self.memory.memory(),
+            cmd.prp1,
+            cmd.prp2,
+            IDENTIFY_SIZE as u64,
+            &data,
+        )?;
+
+        Ok(Some(0))
+    }
+
+    fn identify_controller(&self) -> Vec<u8> {
+        let mut data = vec![0u8; IDENTIFY_SIZE];
+        // PCI vendor and subsystem vendor
+        data[0..2].copy_from_slice(&NVME_VENDOR_ID.to_le_bytes());
+        data[2..4].copy_from_slice(&NVME_VENDOR_ID.to_le_bytes());
+        // Serial number, model number and firmware revision
+        copy_padded(&mut data[4..24], &self.serial);
+        copy_padded(&mut data[24..64], "Cloud Hypervisor NVMe Ctrl");
+        copy_padded(&mut data[64..72], "1.0");
+        // Recommended arbitration burst
+        data[72] = 6;
+        data[77] = MDTS;
+        data[80..84].copy_from_slice(&NVME_VERSION.to_le_bytes());
+        // I/O controller
+        data[111] = 1;
+        data[258] = ACL;
+        data[259] = AERL;
+        // A single read-only firmware slot
+        data[260] = 0x3;
+        // Warning and critical composite temperature thresholds
+        data[266..268].copy_from_slice(&(TEMPERATURE_THRESHOLD as u16).to_le_bytes());
+        data[268..270].copy_from_slice(&(TEMPERATURE_THRESHOLD as u16 + 30).to_le_bytes());
+        // Required and maximum queue entry sizes
+        data[512] = ((SQES << 4) | SQES) as u8;
+        data[513] = ((CQES << 4) | CQES) as u8;
+        // Number of namespaces
+        data[516..520].copy_from_slice(&NAMESPACE_ID.to_le_bytes());
+        // Volatile write cache
+        data[525] = 1;
+        let subnqn = format!("nqn.2019-08.org.cloudhypervisor:nvme:{}", self.serial);
+        let len = cmp::min(subnqn.len(), 255);
+        data[768..768 + len].copy_from_slice(&subnqn.as_bytes()[..len]);
+        // Maximum power of the single power state, in centiwatts
+        data[2048..2050].copy_from_slice(&2500u16.to_le_bytes());
+        data
+    }
+
+    fn identify_namespace(&self) -> Vec<u8> {
+        let mut data = vec![0u8; IDENTIFY_SIZE];
+        // Size, capacity and utilization
+        data[0..8].copy_from_slice(&self.nsze.to_le_bytes());
+        data[8..16].copy_from_slice(&self.nsze.to_le_bytes());
+        data[16..24].copy_from_slice(&self.nsze.to_le_bytes());
+        // Write protected
+        data[99] = u8::from(self.readonly);
+        // A single format of 512 bytes logical blocks without metadata
+        data[128..132].copy_from_slice(&(LBA_SHIFT << 16).to_le_bytes());
+        data
+    }
+
+    fn get_log_page(&mut self, cmd: &Command) -> result::Result<Option<u32>, u16> {
+        let lid = cmd.cdw10 as u8;
+        let numd = (((cmd.cdw11 & 0xffff) << 16) | (cmd.cdw10 >> 16)) as u64 + 1;
+        let len = numd * 4;
+        let offset = u64::from(cmd.cdw12) | (u64::from(cmd.cdw13) << 32);
+        if len > MAX_TRANSFER_SIZE || offset & 0x3 != 0 {
+            return Err(SC_INVALID_FIELD);
+        }
+
+        let log = match lid {
+            LOG_ERROR_INFORMATION => vec![0u8; ERROR_LOG_ENTRY_SIZE],
+            LOG_SMART => {
+                let mut log = vec![0u8; LOG_PAGE_SIZE];
+                log[1..3].copy_from_slice(&COMPOSITE_TEMPERATURE.to_le_bytes());
+                // Available spare and its threshold
+                log[3] = 100;
+                log[4] = 10;
+                log
+            }
+            LOG_FIRMWARE_SLOT => {
+                let mut log = vec![0u8; LOG_PAGE_SIZE];
+                // Active firmware slot
+                log[0] = 1;
+                copy_padded(&mut log[8..16], "1.0");
+                log
+            }
+            _ => return Err(SC_INVALID_LOG_PAGE),
+        };
+
+        let mut data = vec![0u8; len as usize];
+        if let Some(log) = log.get(offset as usize..) {
+            let size = cmp::min(log.len(), data.len());
+            data[..size].copy_from_slice(&log[..size]);
+        }
+        write_prps(&*self.memory.memory(), cmd.prp1, cmd.prp2, len, &data)?;
+
+        Ok(Some(0))
+    }
+
+    fn number_of_queues(&self) -> u32 {
+        let n = u32::from(self.num_io_queues - 1);
+        n | (n << 16)
+    }
+
+    fn set_features(&mut self, cmd: &Command) -> result::Result<Option<u32>, u16> {
+        if cmd.cdw10 & (1 << 31) != 0 {
+            return Err(SC_FEATURE_NOT_SAVEABLE);
+        }
+
+        let fid = cmd.cdw10 as u8;
+        match fid {
+            FEATURE_NUMBER_OF_QUEUES => {
+                if cmd.cdw11 & 0xffff == 0xffff || cmd.cdw11 >> 16 == 0xffff {
+                    return Err(SC_INVALID_FIELD);
+                }
+                // The number of queues is fixed.
+                Ok(Some(self.number_of_queues()))
+            }
+            // Interrupts aren't coalesced.
+            FEATURE_INTERRUPT_VECTOR_CONFIG => Ok(Some(0)),
+            fid if self.state.features.contains_key(&fid) => {
+                self.state.features.insert(fid, cmd.cdw11);
+                Ok(Some(0))
+            }
+            _ => Err(SC_INVALID_FIELD),
+        }
+    }
+
+    fn get_features(&mut self, cmd: &Command) -> result::Result<Option<u32>, u16> {
+        let fid = cmd.cdw10 as u8;
+        match fid {
+            FEATURE_NUMBER_OF_QUEUES => Ok(Some(self.number_of_queues())),
+            FEATURE_INTERRUPT_VECTOR_CONFIG => Ok(Some(cmd.cdw11 & 0xffff)),
+            fid => self
+                .state
+                .features
+                .get(&fid)
+                .map(|value| Some(*value))
+                .ok_or(SC_INVALID_FIELD),
+        }
+    }
+
+    // Returns none if the command was submitted to the disk image.
+    fn io_command(&mut self, sqid: u16, cmd: &Command) -> result::Result<Option<u32>, u16> {
+        if cmd.nsid != NAMESPACE_ID {
+            return Err(SC_INVALID_NAMESPACE);
+        }
+        if cmd.psdt != 0 {
+            return Err(SC_INVALID_FIELD);
+        }
+
+        let user_data = self.next_user_data();
+        match cmd.opcode {
+            NVM_FLUSH => {
+                if let Err(e) = self.async_io.fsync(Some(user_data)) {
+                    error!("{}: Failed flushing the disk image: {}", self.id, e);
+                    return Err(SC_INTERNAL_ERROR);
+                }
+            }
+            NVM_READ | NVM_WRITE => {
+                if cmd.opcode == NVM_WRITE && self.readonly {
+                    return Err(SC_NAMESPACE_WRITE_PROTECTED);
+                }
+
+                let slba = u64::from(cmd.cdw10) | (u64::from(cmd.cdw11) << 32);
+                let nlb = u64::from(cmd.cdw12 & 0xffff) + 1;
+                if slba.checked_add(nlb).is_none_or(|end| end > self.nsze) {
+                    return Err(SC_LBA_OUT_OF_RANGE);
+                }
+                let len = nlb << LBA_SHIFT;
+                if len > MAX_TRANSFER_SIZE {
+                    return Err(SC_INVALID_FIELD);
+                }
+
+                let mem = self.memory.memory();
+                let mut iovecs = Vec::new();
+                for (addr, size) in prp_ranges(&*mem, cmd.prp1, cmd.prp2, len)? {
+                    let slice = mem
+                        .get_slice(addr, size)
+                        .map_err(|_| SC_DATA_TRANSFER_ERROR)?;
+                    if cmd.opcode == NVM_READ {
+                        slice.bitmap().mark_dirty(0, size);
+                    }
+                    iovecs.push(libc::iovec {
+                        iov_base: slice.ptr_guard().as_ptr() as *mut libc::c_void,
+                        iov_len: size,
+                    });
+                }
+
+                let offset = (slba << LBA_SHIFT) as libc::off_t;
+                let result = if cmd.opcode == NVM_READ {
+                    self.async_io.read_vectored(offset, &iovecs, user_data)
+                } else {
+                    self.async_io.write_vectored(offset, &iovecs, user_data)
+                };
+                if let Err(e) = result {
+                    error!("{}: Failed processing command: {}", self.id, e);
+                    return Err(Self::io_error_status(cmd.opcode));
+                }
+            }
+            _ => return Err(SC_INVALID_OPCODE),
+        }
+
+        self.inflight.insert(
+            user_data,
+            Request {
+                sqid,
+                cid: cmd.cid,
+                opcode: cmd.opcode,
+            },
+        );
+
+        Ok(None)
+    }
+}
+
+/// Thread processing the commands of the controller, stopped when dropped.
+struct NvmeWorker {
+    kill_evt: EventFd,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl NvmeWorker {
+    fn new(id: &str, controller: Arc<Mutex<Controller>>, kick_evt: EventFd) -> io::Result<Self> {
+        let kill_evt = EventFd::new(libc::EFD_NONBLOCK)?;
+        let io_evt = controller.lock().unwrap().async_io.notifier().try_clone()?;
+
+        let epoll = Epoll::new()?;
+        for (fd, data) in [
+            (kill_evt.as_raw_fd(), KILL_EVENT),
+            (kick_evt.as_raw_fd(), KICK_EVENT),
+            (io_evt.as_raw_fd(), IO_EVENT),
+        ] {
+            epoll.ctl(
+                ControlOperation::Add,
+                fd,
+                EpollEvent::new(EventSet::IN, data),
+            )?;
+        }
+
+        let handle = thread::Builder::new().name(id.to_string()).spawn(move || {
+            let mut events = vec![EpollEvent::default(); 3];
+            loop {
+                let num_events = match epoll.wait(-1, &mut events) {
+                    Ok(num_events) => num_events,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => {
+                        error!("Failed waiting for NVMe events: {}", e);
+                        return;
+                    }
+                };
+
+                for event in events.iter().take(num_events) {
+                    match event.data() {
+                        KILL_EVENT => return,
+                        KICK_EVENT => {
+                            let _ = kick_evt.read();
+                        }
+                        IO_EVENT => {
+                            let _ = io_evt.read();
+                        }
+                        _ => {}
+                    }
+                }
+
+                controller.lock().unwrap().process();
+            }
+        })?;
+
+        Ok(NvmeWorker {
+            kill_evt,
+            handle: Some(handle),
+        })
+    }
+}
+
+impl Drop for NvmeWorker {
+    fn drop(&mut self) {
+        if let Err(e) = self.kill_evt.write(1) {
+            error!("Failed stopping NVMe worker: {}", e);
+        }
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// NVMe controller exposing a disk image as its single namespace.
+pub struct NvmeController {
+    id: String,
+    disk_image: Box<dyn DiskFile>,
+    disk_path: PathBuf,
+    readonly: bool,
+    controller: Arc<Mutex<Controller>>,
+    kick_evt: EventFd,
+    _worker: NvmeWorker,
+
+    // PCI configuration registers.
+    configuration: PciConfiguration,
+    msix_config: Arc<Mutex<MsixConfig>>,
+    msix_num: u16,
+    bar_regions: Vec<PciBarConfiguration>,
+}
+
+impl NvmeController {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        id: String,
+        mut disk_image: Box<dyn DiskFile>,
+        disk_path: PathBuf,
+        readonly: bool,
+        num_queues: usize,
+        queue_size: u16,
+        serial: Option<String>,
+        memory: GuestMemoryMmapAtomic,
+        interrupt_manager: &Arc<dyn InterruptManager<GroupConfig = MsiIrqGroupConfig>>,
+        pci_device_bdf: u32,
+        snapshot: Option<Snapshot>,
+    ) -> Result<Self, NvmeError> {
+        let disk_size = disk_image.size().map_err(NvmeError::DiskSize)?;
+        if disk_size % (1 << LBA_SHIFT) != 0 {
+            warn!(
+                "{}: Disk size {} is not a multiple of the logical block size, \
+                the remainder is not accessible",
+                id, disk_size
+            );
+        }
+
+        let num_io_queues = num_queues.clamp(1, NVME_MAX_IO_QUEUES) as u16;
+        let msix_num = num_io_queues + 1;
+        let max_queue_entries = u32::from(queue_size).max(2);
+        let async_io = disk_image
+            .new_async_io(max_queue_entries)
+            .map_err(NvmeError::CreateAsyncIo)?;
+
+        let interrupt_source_group = interrupt_manager
+            .create_group(MsiIrqGroupConfig {
+                base: 0,
+                count: msix_num as InterruptIndex,
+            })
+            .map_err(NvmeError::CreateInterruptGroup)?;
+
+        let msix_state = vm_migration::state_from_id(snapshot.as_ref(), pci::MSIX_CONFIG_ID)
+            .map_err(|e| NvmeError::Restore(anyhow!("Failed to get MsixConfigState: {}", e)))?;
+        let msix_config = Arc::new(Mutex::new(
+            MsixConfig::new(
+                msix_num,
+                interrupt_source_group.clone(),
+                pci_device_bdf,
+                msix_state,
+            )
+            .map_err(|e| NvmeError::CreateMsixConfig(anyhow!("{}", e)))?,
+        ));
+
+        let pci_configuration_state =
+            vm_migration::state_from_id(snapshot.as_ref(), PCI_CONFIGURATION_ID).map_err(|e| {
+                NvmeError::Restore(anyhow!("Failed to get PciConfigurationState: {}", e))
+            })?;
+        let configuration = PciConfiguration::new(
+            NVME_VENDOR_ID,
+            NVME_DEVICE_ID,
+            0x2,
+            PciClassCode::MassStorage,
+            &PciMassStorageSubclass::NvmController,
+            Some(&NvmeProgrammingInterface),
+            PciHeaderType::Device,
+            NVME_VENDOR_ID,
+            NVME_DEVICE_ID,
+            Some(msix_config.clone()),
+            pci_configuration_state,
+        );
+
+        let state: Option<NvmeControllerState> = snapshot
+            .as_ref()
+            .map(|s| s.to_state())
+            .transpose()
+            .map_err(|e| NvmeError::Restore(anyhow!("Failed to get NvmeControllerState: {}", e)))?;
+
+        let controller = Arc::new(Mutex::new(Controller {
+            id: id.clone(),
+            state: state.unwrap_or_else(NvmeControllerState::new),
+            memory,
+            async_io,
+            io_depth: max_queue_entries as usize,
+            inflight: HashMap::new(),
+            next_user_data: 0,
+            shutdown_user_data: None,
+            reset_pending: false,
+            paused: false,
+            msix_config: msix_config.clone(),
+            interrupt_source_group,
+            num_io_queues,
+            max_queue_entries,
+            nsze: disk_size >> LBA_SHIFT,
+            readonly,
+            serial: serial.unwrap_or_else(|| id.clone()),
+        }));
+
+        let kick_evt = EventFd::new(libc::EFD_NONBLOCK).map_err(NvmeError::EventFd)?;
+        let worker = NvmeWorker::new(
+            &id,
+            controller.clone(),
+            kick_evt.try_clone().map_err(NvmeError::EventFd)?,
+        )
+        .map_err(NvmeError::StartWorker)?;
+
+        Ok(NvmeController {
+            id,
+            disk_image,
+            disk_path,
+            readonly,
+            controller,
+            kick_evt,
+            _worker: worker,
+            configuration,
+            msix_config,
+            msix_num,
+            bar_regions: vec![],
+        })
+    }
+
+    fn kick(&self) {
+        if let Err(e) = self.kick_evt.write(1) {
+            error!("{}: Failed kicking the NVMe worker: {}", self.id, e);
+        }
+    }
+
+    pub fn try_lock_image(&mut self) -> Result<(), NvmeError> {
+        let lock_type = match self.readonly {
+            true => LockType::Read,
+            false => LockType::Write,
+        };
+        fcntl::try_acquire_lock(self.disk_image.fd(), lock_type).map_err(|error| {
+            NvmeError::LockDiskImage {
+                path: self.disk_path.clone(),
+                error,
+                lock_type,
+            }
+        })
+    }
+
+    pub fn unlock_image(&mut self) -> Result<(), NvmeError> {
+        fcntl::clear_lock(self.disk_image.fd()).map_err(|error| NvmeError::LockDiskImage {
+            path: self.disk_path.clone(),
+            error,
+            lock_type: LockType::Unlock,
+        })
+    }
+
+    fn read_registers(&self, offset: u64, data: &mut [u8]) {
+        let controller = self.controller.lock().unwrap();
+        let value = match data.len() {
+            4 => u64::from(controller.read_register(offset)),
+            8 => {
+                u64::from(controller.read_register(offset))
+                    | (u64::from(controller.read_register(offset + 4)) << 32)
+            }
+            _ => {
+                warn!("{}: Invalid register read size {}", self.id, data.len());
+                return;
+            }
+        };
+        data.copy_from_slice(&value.to_le_bytes()[..data.len()]);
+    }
+
+    fn write_registers(&self, offset: u64, data: &[u8]) {
+        let mut controller = self.controller.lock().unwrap();
+        let kick = match data.len() {
+            4 => controller.write_register(offset, u32::from_le_bytes(data.try_into().unwrap())),
+            8 => {
+                let value = u64::from_le_bytes(data.try_into().unwrap());
+                let kick_low = controller.write_register(offset, value as u32);
+                controller.write_register(offset + 4, (value >> 32) as u32) || kick_low
+            }
+            _ => {
+                warn!("{}: Invalid register write size {}", self.id, data.len());
+                false
+            }
+        };
+        drop(controller);
+
+        if kick {
+            self.kick();
+        }
+    }
+
+    fn write_doorbell(&self, offset: u64, data: &[u8]) {
+        let Ok(value) = <[u8; 4]>::try_from(data) else {
+            warn!("{}: Invalid doorbell write size {}", self.id, data.len());
+            return;
+        };
+        if self
+            .controller
+            .lock()
+            .unwrap()
+            .write_doorbell(offset, u32::from_le_bytes(value))
+        {
+            self.kick();
+        }
+    }
+}
+
+impl BusDevice for NvmeController {
+    fn read(&mut self, base: u64, offset: u64, data: &mut [u8]) {
+        self.read_bar(base, offset, data)
+    }
+
+    fn write(&mut self, base: u64, offset: u64, data: &[u8]) -> Option<Arc<Barrier>> {
+        self.write_bar(base, offset, data)
+    }
+}
+
+impl PciDevice for NvmeController {
+    fn write_config_register(
+        &mut self,
+        reg_idx: usize,
+        offset: u64,
+        data: &[u8],
+    ) -> (Vec<BarReprogrammingParams>, Option<Arc<Barrier>>) {
+        (
+            self.configuration
+                .write_config_register(reg_idx, offset, data),
+            None,
+        )
+    }
+
+    fn read_config_register(&mut self, reg_idx: usize) -> u32 {
+        self.configuration.read_reg(reg_idx)
+    }
+
+    fn allocate_bars(
+        &mut self,
+        _allocator: &Arc<Mutex<SystemAllocator>>,
+        _mmio32_allocator: &mut AddressAllocator,
+        mmio64_allocator: &mut AddressAllocator,
+        resources: Option<Vec<Resource>>,
+    ) -> std::result::Result<Vec<PciBarConfiguration>, PciDeviceError> {
+        let mut bar_addr = None;
+        let restoring = resources.is_some();
+        if let Some(resources) = resources {
+            for resource in resources {
+                if let Resource::PciBar { index, base, .. } = resource {
+                    if index == NVME_BAR_INDEX {
+                        bar_addr = Some(GuestAddress(base));
+                    }
+                }
+            }
+            if bar_addr.is_none() {
+                return Err(PciDeviceError::MissingResource);
+            }
+        }
+
+        let bar_addr = mmio64_allocator
+            .allocate(bar_addr, NVME_BAR_SIZE, Some(NVME_BAR_SIZE))
+            .ok_or(PciDeviceError::IoAllocationFailed(NVME_BAR_SIZE))?;
+
+        let bar = PciBarConfiguration::default()
+            .set_index(NVME_BAR_INDEX)
+            .set_address(bar_addr.raw_value())
+            .set_size(NVME_BAR_SIZE)
+            .set_region_type(PciBarRegionType::Memory64BitRegion)
+            .set_prefetchable(PciBarPrefetchable::NotPrefetchable);
+
+        // The BAR and the MSI-X capability are already part of the
+        // configuration of a restored device.
+        if !restoring {
+            self.configuration
+                .add_pci_bar(&bar)
+                .map_err(|e| PciDeviceError::IoRegistrationFailed(bar_addr.raw_value(), e))?;
+
+            let msix_cap = MsixCap::new(
+                NVME_BAR_INDEX as u8,
+                self.msix_num,
+                MSIX_TABLE_OFFSET as u32,
+                NVME_BAR_INDEX as u8,
+                MSIX_PBA_OFFSET as u32,
+            );
+            self.configuration
+                .add_capability(&msix_cap)
+                .map_err(PciDeviceError::CapabilitiesSetup)?;
+        }
+
+        let bars = vec![bar];
+        self.bar_regions.clone_from(&bars);
+
+        Ok(bars)
+    }
+
+    fn free_bars(
+        &mut self,
+        _allocator: &mut SystemAllocator,
+        _mmio32_allocator: &mut AddressAllocator,
+        mmio64_allocator: &mut AddressAllocator,
+    ) -> std::result::Result<(), PciDeviceError> {
+        for bar in self.bar_regions.drain(..) {
+            mmio64_allocator.free(GuestAddress(bar.addr()), bar.size());
+        }
+
+        Ok(())
+    }
+
+    fn move_bar(&mut self, old_base: u64, new_base: u64) -> result::Result<(), io::Error> {
+        for bar in self.bar_regions.iter_mut() {
+            if bar.addr() == old_base {
+                *bar = bar.set_address(new_base);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read_bar(&mut self, _base: u64, offset: u64, data: &mut [u8]) {
+        match offset {
+            o if o < DOORBELL_OFFSET => self.read_registers(o, data),
+            o if (MSIX_TABLE_OFFSET..MSIX_TABLE_OFFSET + MSIX_STRUCTURE_SIZE).contains(&o) => self
+                .msix_config
+                .lock()
+                .unwrap()
+                .read_table(o - MSIX_TABLE_OFFSET, data),
+            o if (MSIX_PBA_OFFSET..MSIX_PBA_OFFSET + MSIX_STRUCTURE_SIZE).contains(&o) => self
+                .msix_config
+                .lock()
+                .unwrap()
+                .read_pba(o - MSIX_PBA_OFFSET, data),
+            // The doorbells are write-only.
+            _ => data.fill(0),
+        }
+    }
+
+    fn write_bar(&mut self, _base: u64, offset: u64, data: &[u8]) -> Option<Arc<Barrier>> {
+        match offset {
+            o if o < DOORBELL_OFFSET => self.write_registers(o, data),
+            o if o < MSIX_TABLE_OFFSET => self.write_doorbell(o - DOORBELL_OFFSET, data),
+            o if o < MSIX_TABLE_OFFSET + MSIX_STRUCTURE_SIZE => self
+                .msix_config
+                .lock()
+                .unwrap()
+                .write_table(o - MSIX_TABLE_OFFSET, data),
+            o if (MSIX_PBA_OFFSET..MSIX_PBA_OFFSET + MSIX_STRUCTURE_SIZE).contains(&o) => self
+                .msix_config
+                .lock()
+                .unwrap()
+                .write_pba(o - MSIX_PBA_OFFSET, data),
+            _ => {}
+        }
+
+        None
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn id(&self) -> Option<String> {
+        Some(self.id.clone())
+    }
+}
+
+impl Pausable for NvmeController {
+    fn pause(&mut self) -> result::Result<(), MigratableError> {
+        self.controller.lock().unwrap().paused = true;
+
+        // The commands submitted to the disk image complete before the
+        // controller state can be saved.
+        while !self.controller.lock().unwrap().inflight.is_empty() {
+            thread::sleep(PAUSE_POLL_INTERVAL);
+        }
+
+        Ok(())
+    }
+
+    fn resume(&mut self) -> result::Result<(), MigratableError> {
+        self.controller.lock().unwrap().paused = false;
+        self.kick();
+
+        Ok(())
+    }
+}
+
+impl Snapshottable for NvmeController {
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    fn snapshot(&mut self) -> result::Result<Snapshot, MigratableError> {
+        let mut snapshot = Snapshot::new_from_state(&self.controller.lock().unwrap().state)?;
+
+        // Snapshot PciConfiguration
+        snapshot.add_snapshot(self.configuration.id(), self.configuration.snapshot()?);
+
+        // Snapshot MSI-X
+        let mut msix_config = self.msix_config.lock().unwrap();
+        snapshot.add_snapshot(msix_config.id(), msix_config.snapshot()?);
+
+        Ok(snapshot)
+    }
+}
+
+impl Transportable for NvmeController {}
+impl Migratable for NvmeController {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prp_ranges() {
+        let mem =
+            GuestMemoryMmap::<AtomicBitmap>::from_ranges(&[(GuestAddress(0), 0x10_0000)]).unwrap();
+
+        // Within the first page, at an offset
+        assert_eq!(
+            prp_ranges(&mem, 0x1200, 0, 0x200).unwrap(),
+            vec![(GuestAddress(0x1200), 0x200)]
+        );
+
+        // Over two pages
+        assert_eq!(
+            prp_ranges(&mem, 0x1800, 0x5000, 0x1000).unwrap(),
+            vec![(GuestAddress(0x1800), 0x800), (GuestAddress(0x5000), 0x800)]
+        );
+        assert_eq!(
+            prp_ranges(&mem, 0x1800, 0x5010, 0x1000),
+            Err(SC_INVALID_PRP_OFFSET)
+        );
+
+        // Through a list of entries, the last one of the first list page
+        // pointing to the next list.
+        let list = 0x8ff0;
+        mem.write_obj(0xa000u64, GuestAddress(list)).unwrap();
+        mem.write_obj(0x9000u64, GuestAddress(list + 8)).unwrap();
+        mem.write_obj(0xc000u64, GuestAddress(0x9000)).unwrap();
+        mem.write_obj(0xd000u64, GuestAddress(0x9008)).unwrap();
+        assert_eq!(
+            prp_ranges(&mem, 0x2000, list, 0x3800).unwrap(),
+            vec![
+                (GuestAddress(0x2000), 0x1000),
+                (GuestAddress(0xa000), 0x1000),
+                (GuestAddress(0xc000), 0x1000),
+                (GuestAddress(0xd000), 0x800),
+            ]
+        );
+
+        // A single entry list pointing back to itself
+        let list = 0xeff8;
+        mem.write_obj(list, GuestAddress(list)).unwrap();
+        assert_eq!(
+            prp_ranges(&mem, 0x2000, list, 0x3000),
+            Err(SC_INVALID_PRP_OFFSET)
+        );
+    }
+
+    #[test]
+    fn test_command_from_bytes() {
+        let mut bytes = [0u8; SQ_ENTRY_SIZE as usize];
+        bytes[0] = NVM_READ;
+        bytes[2..4].copy_from_slice(&0x1234u16.to_le_bytes());
+        bytes[4..8].copy_from_slice(&NAMESPACE_ID.to_le_bytes());
+        bytes[24..32].copy_from_slice(&0x1_0000_2000u64.to_le_bytes());
+        bytes[32..40].copy_from_slice(&0x3000u64.to_le_bytes());
+        bytes[40..44].copy_from_slice(&0x10u32.to_le_bytes());
+        bytes[48..52].copy_from_slice(&0x7u32.to_le_bytes());
+
+        let cmd = Command::from_bytes(&bytes);
+        assert_eq!(cmd.opcode, NVM_READ);
+        assert_eq!(cmd.psdt, 0);
+        assert_eq!(cmd.cid, 0x1234);
+        assert_eq!(cmd.nsid, NAMESPACE_ID);
+        assert_eq!(cmd.prp1, 0x1_0000_2000);
+        assert_eq!(cmd.prp2, 0x3000);
+        assert_eq!(cmd.cdw10, 0x10);
+        assert_eq!(cmd.cdw12, 0x7);
+    }
+}
@@ -5,6 +5,8 @@
 
 use std::collections::HashMap;
 use std::ffi::CString;
+use std::num::Wrapping;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Barrier, Mutex, RwLock};
 use std::{io, result};
 
@@ -13,6 +15,7 @@ use pci::{
     BarReprogrammingParams, PciBarConfiguration, PciBarPrefetchable, PciBarRegionType,
     PciClassCode, PciConfiguration, PciDevice, PciDeviceError, PciHeaderType, PciSubclass,
 };
+use rate_limiter::{BucketReduction, TokenBucket};
 use thiserror::Error;
 use vm_allocator::page_size::get_page_size;
 use vm_allocator::{AddressAllocator, SystemAllocator};
@@ -31,7 +34,7 @@ const PVMEMCONTROL_SUBSYSTEM_VENDOR_ID: u16 = 0x1ae0;
 const PVMEMCONTROL_SUBSYSTEM_ID: u16 = 0x011F;
 
 const MAJOR_VERSION: u64 = 1;
-const MINOR_VERSION: u64 = 0;
+const MINOR_VERSION: u64 = 1;
 
 #[derive(Error, Debug)]
 pub enum Error {
@@ -53,6 +56,8 @@ pub enum Error {
     UnknownFunctionCode(u64),
     #[error("Libc call fail")]
     LibcFail(#[source] std::io::Error),
+    #[error("Reclaim rate exceeded")]
+    Throttled,
 }
 
 #[derive(Copy, Clone)]
@@ -147,7 +152,7 @@ unsafe impl ByteValued for PvmemcontrolTransportUnion {}
 unsafe impl ByteValued for PvmemcontrolTransport {}
 
 #[repr(u64)]
-#[derive(Copy, Clone, TryFromPrimitive, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, TryFromPrimitive, Debug)]
 enum FunctionCode {
     Info = 0,
     Dontneed = 1,
@@ -164,6 +169,62 @@ enum FunctionCode {
     MprotectRW = 12,
     Mergeable = 13,
     Unmergeable = 14,
+    Cold = 15,
+}
+
+const FUNCTION_CODE_COUNT: usize = FunctionCode::Cold as usize + 1;
+
+impl FunctionCode {
+    fn name(self) -> &'static str {
+        match self {
+            FunctionCode::Info => "info",
+            FunctionCode::Dontneed => "dontneed",
+            FunctionCode::Remove => "remove",
+            FunctionCode::Free => "free",
+            FunctionCode::Pageout => "pageout",
+            FunctionCode::Dontdump => "dontdump",
+            FunctionCode::SetVMAAnonName => "set_vma_anon_name",
+            FunctionCode::Mlock => "mlock",
+            FunctionCode::Munlock => "munlock",
+            FunctionCode::MprotectNone => "mprotect_none",
+            FunctionCode::MprotectR => "mprotect_r",
+            FunctionCode::MprotectW => "mprotect_w",
+            FunctionCode::MprotectRW => "mprotect_rw",
+            FunctionCode::Mergeable => "mergeable",
+            FunctionCode::Unmergeable => "unmergeable",
+            FunctionCode::Cold => "cold",
+        }
+    }
+
+    /// Reclaiming guest memory costs host I/O and CPU time, these requests
+    /// are subject to the reclaim rate.
+    fn is_reclaim(self) -> bool {
+        matches!(self, FunctionCode::Pageout | FunctionCode::Cold)
+    }
+}
+
+/// Statistics of the requests of one function code
+#[derive(Default)]
+struct FunctionCounters {
+    requests: AtomicU64,
+    bytes: AtomicU64,
+    errors: AtomicU64,
+    throttled: AtomicU64,
+}
+
+impl FunctionCounters {
+    fn to_map(&self) -> HashMap<&'static str, Wrapping<u64>> {
+        let mut counters = HashMap::new();
+        for (name, counter) in [
+            ("requests", &self.requests),
+            ("bytes", &self.bytes),
+            ("errors", &self.errors),
+            ("throttled", &self.throttled),
+        ] {
+            counters.insert(name, Wrapping(counter.load(Ordering::Acquire)));
+        }
+        counters
+    }
 }
 
 #[repr(C)]
@@ -415,6 +476,8 @@ impl PvmemcontrolDevice {
 pub struct PvmemcontrolBusDevice {
     mem: GuestMemoryAtomic<GuestMemoryMmap<AtomicBitmap>>,
     dev: RwLock<PvmemcontrolDevice>,
+    reclaim_bucket: Mutex<Option<TokenBucket>>,
+    counters: [FunctionCounters; FUNCTION_CODE_COUNT],
 }
 
 pub struct PvmemcontrolPciDevice {
@@ -504,6 +567,18 @@ impl PvmemcontrolBusDevice {
         })
     }
 
+    /// Guest memory reclaimed beyond the reclaim rate is refused with
+    /// EAGAIN, letting the guest retry later.
+    fn consume_reclaim_budget(&self, length: u64) -> result::Result<(), Error> {
+        match self.reclaim_bucket.lock().unwrap().as_mut() {
+            Some(bucket) => match bucket.reduce(length) {
+                BucketReduction::Failure => Err(Error::Throttled),
+                BucketReduction::Success | BucketReduction::OverConsumption(_) => Ok(()),
+            },
+            None => Ok(()),
+        }
+    }
+
     fn process_request(
         &self,
         func_code: FunctionCode,
@@ -511,6 +586,10 @@ impl PvmemcontrolBusDevice {
         length: u64,
         arg: u64,
     ) -> Result<PvmemcontrolResp, Error> {
+        if func_code.is_reclaim() {
+            self.consume_reclaim_budget(length)?;
+        }
+
         let result = match func_code {
             FunctionCode::Info => {
                 return Ok(PvmemcontrolResp {
@@ -537,6 +616,7 @@ impl PvmemcontrolBusDevice {
             }
             FunctionCode::Mergeable => self.madvise(addr, length, libc::MADV_MERGEABLE),
             FunctionCode::Unmergeable => self.madvise(addr, length, libc::MADV_UNMERGEABLE),
+            FunctionCode::Cold => self.madvise(addr, length, libc::MADV_COLD),
         };
         result.map(|_| PvmemcontrolResp::default())
     }
@@ -559,7 +639,11 @@ impl PvmemcontrolBusDevice {
 
         let resp_or_err = FunctionCode::try_from(func_code)
             .map_err(|_| Error::UnknownFunctionCode(func_code))
-            .and_then(|func_code| self.process_request(func_code, addr, length, arg));
+            .and_then(|func_code| {
+                let result = self.process_request(func_code, addr, length, arg);
+                self.account_request(func_code, length, &result);
+                result
+            });
 
         let resp = match resp_or_err {
             Ok(resp) => resp,
@@ -574,6 +658,11 @@ impl PvmemcontrolBusDevice {
                     ret_code: 0u32.into(),
                     ..Default::default()
                 },
+                Error::Throttled => PvmemcontrolResp {
+                    ret_errno: (libc::EAGAIN as u32).into(),
+                    ret_code: (func_code as u32).into(),
+                    ..Default::default()
+                },
                 Error::UnknownFunctionCode(func_code) => PvmemcontrolResp {
                     ret_errno: (libc::EOPNOTSUPP as u32).into(),
                     ret_code: (func_code as u32).into(),
@@ -594,6 +683,37 @@ impl PvmemcontrolBusDevice {
         Ok(resp)
     }
 
+    fn account_request(
+        &self,
+        func_code: FunctionCode,
+        length: u64,
+        result: &Result<PvmemcontrolResp, Error>,
+    ) {
+        let counters = &self.counters[func_code as usize];
+        counters.requests.fetch_add(1, Ordering::AcqRel);
+        match result {
+            Ok(_) => {
+                if func_code != FunctionCode::Info {
+                    counters.bytes.fetch_add(length, Ordering::AcqRel);
+                }
+            }
+            Err(Error::Throttled) => {
+                counters.throttled.fetch_add(1, Ordering::AcqRel);
+            }
+            Err(_) => {
+                counters.errors.fetch_add(1, Ordering::AcqRel);
+            }
+        }
+    }
+
+    /// Statistics of the guest requests, by function code
+    pub fn counters(&self) -> HashMap<&'static str, HashMap<&'static str, Wrapping<u64>>> {
+        (0..FUNCTION_CODE_COUNT as u64)
+            .filter_map(|code| FunctionCode::try_from(code).ok())
+            .map(|code| (code.name(), self.counters[code as usize].to_map()))
+            .collect()
+    }
+
     fn handle_pvmemcontrol_request(&self, guest_addr: GuestAddress) {
         let request: PvmemcontrolReq = if let Ok(x) = self.mem.memory().read_obj(guest_addr) {
             x
@@ -660,9 +780,12 @@ impl PvmemcontrolBusDevice {
 }
 
 impl PvmemcontrolDevice {
+    /// `reclaim_rate` limits the bytes per second the guest may reclaim
+    /// through cold and pageout requests.
     pub fn make_device(
         id: String,
         mem: GuestMemoryAtomic<GuestMemoryMmap<AtomicBitmap>>,
+        reclaim_rate: Option<u64>,
     ) -> (PvmemcontrolPciDevice, PvmemcontrolBusDevice) {
         let dev = RwLock::new(PvmemcontrolDevice::error());
         let mut configuration = PciConfiguration::new(
@@ -687,7 +810,14 @@ impl PvmemcontrolDevice {
                 configuration,
                 bar_regions: Vec::new(),
             },
-            PvmemcontrolBusDevice { mem, dev },
+            PvmemcontrolBusDevice {
+                mem,
+                dev,
+                reclaim_bucket: Mutex::new(
+                    reclaim_rate.and_then(|rate| TokenBucket::new(rate, 0, 1000)),
+                ),
+                counters: Default::default(),
+            },
         )
     }
 }
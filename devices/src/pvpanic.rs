@@ -16,9 +16,10 @@ use pci::{
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use vm_allocator::{AddressAllocator, SystemAllocator};
-use vm_device::{BusDevice, Resource};
+use vm_device::{BusDevice, ExitReason, ExitReasonSlot, Resource};
 use vm_memory::{Address, GuestAddress};
 use vm_migration::{Migratable, MigratableError, Pausable, Snapshot, Snapshottable, Transportable};
+use vmm_sys_util::eventfd::EventFd;
 
 const PVPANIC_VENDOR_ID: u16 = 0x1b36;
 const PVPANIC_DEVICE_ID: u16 = 0x0011;
@@ -52,6 +53,9 @@ impl PciSubclass for PvPanicSubclass {
 pub struct PvPanicDevice {
     id: String,
     events: u8,
+    // Signaled when the guest panics, if the VMM acts upon guest crashes
+    crash_evt: Option<EventFd>,
+    exit_reason: ExitReasonSlot,
 
     // PCI configuration registers.
     configuration: PciConfiguration,
@@ -64,7 +68,12 @@ pub struct PvPanicDeviceState {
 }
 
 impl PvPanicDevice {
-    pub fn new(id: String, snapshot: Option<Snapshot>) -> Result<Self, PvPanicError> {
+    pub fn new(
+        id: String,
+        crash_evt: Option<EventFd>,
+        exit_reason: ExitReasonSlot,
+        snapshot: Option<Snapshot>,
+    ) -> Result<Self, PvPanicError> {
         let pci_configuration_state =
             vm_migration::state_from_id(snapshot.as_ref(), PCI_CONFIGURATION_ID).map_err(|e| {
                 PvPanicError::RetrievePciConfigurationState(anyhow!(
@@ -113,6 +122,8 @@ impl PvPanicDevice {
         let pvpanic_device = PvPanicDevice {
             id,
             events,
+            crash_evt,
+            exit_reason,
             configuration,
             bar_regions: vec![],
         };
@@ -150,6 +161,14 @@ impl BusDevice for PvPanicDevice {
         let event = self.event_to_string(data[0]);
         info!("pvpanic got guest event {}", event);
         event!("guest", "panic", "event", &event);
+        if data[0] == PVPANIC_PANICKED {
+            if let Some(crash_evt) = self.crash_evt.as_ref() {
+                self.exit_reason.record(ExitReason::Panic);
+                if let Err(e) = crash_evt.write(1) {
+                    error!("Error signaling guest panic: {:?}", e);
+                }
+            }
+        }
         None
     }
 }
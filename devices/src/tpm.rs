@@ -13,6 +13,7 @@ use arch::aarch64::layout::{TPM_SIZE, TPM_START};
 use arch::x86_64::layout::{TPM_SIZE, TPM_START};
 use thiserror::Error;
 use tpm::emulator::{BackendCmd, Emulator};
+use tpm::event_log::Sha256Digest;
 use tpm::TPM_CRB_BUFFER_MAX;
 use vm_device::BusDevice;
 
@@ -22,6 +23,8 @@ pub enum Error {
     CheckCaps(#[source] anyhow::Error),
     #[error("Failed to initialize tpm")]
     Init(#[source] anyhow::Error),
+    #[error("Failed to extend PCR")]
+    ExtendPcr(#[source] anyhow::Error),
 }
 type Result<T> = anyhow::Result<T, Error>;
 
@@ -233,6 +236,15 @@ impl Tpm {
         Ok(tpm)
     }
 
+    /// Extends `pcr` with the digest of a measurement done by the VMM,
+    /// before the guest runs.
+    pub fn extend_pcr(&mut self, pcr: u32, digest: &Sha256Digest) -> Result<()> {
+        self.emulator
+            .tpm2_startup()
+            .and_then(|_| self.emulator.pcr_extend(pcr, digest))
+            .map_err(|e| Error::ExtendPcr(anyhow!("Failed to extend PCR {}: {:?}", pcr, e)))
+    }
+
     fn get_active_locality(&mut self) -> u32 {
         if get_reg_field(
             &self.regs,
@@ -0,0 +1,282 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Hyper-V integration services.
+//!
+//! Integration services exchange ICMSG messages with the guest, each over
+//! its own VMBus channel. Once the channel is open, the host negotiates the
+//! framework and message versions before issuing any request, the guest
+//! answering every request on the same channel.
+
+// Size of the pipe header preceding every message.
+const VMBUS_PIPE_HEADER_SIZE: usize = 8;
+
+// Offsets of the fields of the ICMSG header, following the pipe header.
+const ICMSG_TYPE: usize = 12;
+const ICMSG_SIZE: usize = 18;
+const ICMSG_STATUS: usize = 20;
+const ICMSG_FLAGS: usize = 25;
+const ICMSG_BODY: usize = 28;
+
+const ICMSGTYPE_NEGOTIATE: u16 = 0;
+const ICMSGTYPE_HEARTBEAT: u16 = 1;
+const ICMSGTYPE_SHUTDOWN: u16 = 3;
+
+const ICMSGHDRFLAG_TRANSACTION: u8 = 1;
+const ICMSGHDRFLAG_REQUEST: u8 = 2;
+const ICMSGHDRFLAG_RESPONSE: u8 = 4;
+
+// Framework and message versions offered to the guest, as major and minor
+// numbers. Version 3.0 is supported by every guest the services target.
+const IC_VERSION: (u16, u16) = (3, 0);
+
+const HEARTBEAT_BODY_SIZE: usize = 40;
+const SHUTDOWN_BODY_SIZE: usize = 2060;
+const SHUTDOWN_FLAG_SHUTDOWN: u32 = 0;
+
+const fn guid(d1: u32, d2: u16, d3: u16, d4: [u8; 8]) -> [u8; 16] {
+    let d1 = d1.to_le_bytes();
+    let d2 = d2.to_le_bytes();
+    let d3 = d3.to_le_bytes();
+    [
+        d1[0], d1[1], d1[2], d1[3], d2[0], d2[1], d3[0], d3[1], d4[0], d4[1], d4[2], d4[3], d4[4],
+        d4[5], d4[6], d4[7],
+    ]
+}
+
+// 57164f39-9115-4e78-ab55-382f3bd5422d
+const HEARTBEAT_GUID: [u8; 16] = guid(
+    0x57164f39,
+    0x9115,
+    0x4e78,
+    [0xab, 0x55, 0x38, 0x2f, 0x3b, 0xd5, 0x42, 0x2d],
+);
+// 0e0b6031-5213-4934-818b-38d90ced39db
+const SHUTDOWN_GUID: [u8; 16] = guid(
+    0x0e0b6031,
+    0x5213,
+    0x4934,
+    [0x81, 0x8b, 0x38, 0xd9, 0x0c, 0xed, 0x39, 0xdb],
+);
+// The instances only need to be unique on the bus.
+const HEARTBEAT_INSTANCE_GUID: [u8; 16] = guid(
+    0x2f9bcc4a,
+    0x0069,
+    0x4af3,
+    [0xb7, 0x6b, 0x6f, 0xd0, 0xbe, 0x52, 0x8c, 0xda],
+);
+const SHUTDOWN_INSTANCE_GUID: [u8; 16] = guid(
+    0xb6650ff7,
+    0xe19d,
+    0x4ab3,
+    [0x8b, 0x28, 0xd8, 0x4e, 0x3b, 0x05, 0x96, 0x27],
+);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum ServiceKind {
+    Heartbeat,
+    Shutdown,
+}
+
+impl ServiceKind {
+    pub(super) fn interface_type(&self) -> [u8; 16] {
+        match self {
+            ServiceKind::Heartbeat => HEARTBEAT_GUID,
+            ServiceKind::Shutdown => SHUTDOWN_GUID,
+        }
+    }
+
+    pub(super) fn interface_instance(&self) -> [u8; 16] {
+        match self {
+            ServiceKind::Heartbeat => HEARTBEAT_INSTANCE_GUID,
+            ServiceKind::Shutdown => SHUTDOWN_INSTANCE_GUID,
+        }
+    }
+}
+
+pub(super) struct IntegrationService {
+    kind: ServiceKind,
+    negotiated: bool,
+    heartbeat_seq: u64,
+    heartbeat_pending: bool,
+}
+
+impl IntegrationService {
+    pub(super) fn new(kind: ServiceKind) -> Self {
+        IntegrationService {
+            kind,
+            negotiated: false,
+            heartbeat_seq: 0,
+            heartbeat_pending: false,
+        }
+    }
+
+    pub(super) fn kind(&self) -> ServiceKind {
+        self.kind
+    }
+
+    pub(super) fn negotiated(&self) -> bool {
+        self.negotiated
+    }
+
+    /// Forget about the guest, as its side of the channel is gone.
+    pub(super) fn reset(&mut self) {
+        self.negotiated = false;
+        self.heartbeat_pending = false;
+    }
+
+    /// Forget about the last heartbeat, which the guest couldn't answer
+    /// while the VM was paused.
+    pub(super) fn resume(&mut self) {
+        self.heartbeat_pending = false;
+    }
+
+    fn message(msg_type: u16, body_size: usize) -> Vec<u8> {
+        let mut msg = vec![0u8; ICMSG_BODY + body_size];
+        msg[4..8].copy_from_slice(&((msg.len() - VMBUS_PIPE_HEADER_SIZE) as u32).to_le_bytes());
+        // Both framework and message versions
+        for offset in [8, 14] {
+            msg[offset..offset + 2].copy_from_slice(&IC_VERSION.0.to_le_bytes());
+            msg[offset + 2..offset + 4].copy_from_slice(&IC_VERSION.1.to_le_bytes());
+        }
+        msg[ICMSG_TYPE..ICMSG_TYPE + 2].copy_from_slice(&msg_type.to_le_bytes());
+        msg[ICMSG_SIZE..ICMSG_SIZE + 2].copy_from_slice(&(body_size as u16).to_le_bytes());
+        msg[ICMSG_FLAGS] = ICMSGHDRFLAG_TRANSACTION | ICMSGHDRFLAG_REQUEST;
+
+        msg
+    }
+
+    /// First request sent to the guest once the channel is open, offering a
+    /// single framework and message version.
+    pub(super) fn negotiate_request(&self) -> Vec<u8> {
+        let mut msg = Self::message(ICMSGTYPE_NEGOTIATE, 16);
+        let body = &mut msg[ICMSG_BODY..];
+        // Framework and message version counts
+        body[0..2].copy_from_slice(&1u16.to_le_bytes());
+        body[2..4].copy_from_slice(&1u16.to_le_bytes());
+        for offset in [8, 12] {
+            body[offset..offset + 2].copy_from_slice(&IC_VERSION.0.to_le_bytes());
+            body[offset + 2..offset + 4].copy_from_slice(&IC_VERSION.1.to_le_bytes());
+        }
+
+        msg
+    }
+
+    /// Periodic heartbeat request, reporting whether the guest answered the
+    /// previous one.
+    pub(super) fn heartbeat_request(&mut self) -> Option<Vec<u8>> {
+        if self.kind != ServiceKind::Heartbeat || !self.negotiated {
+            return None;
+        }
+
+        if self.heartbeat_pending {
+            warn!("Guest missed heartbeat {}", self.heartbeat_seq);
+            event!("vmbus", "heartbeat_missed");
+        }
+
+        self.heartbeat_seq = self.heartbeat_seq.wrapping_add(1);
+        self.heartbeat_pending = true;
+
+        let mut msg = Self::message(ICMSGTYPE_HEARTBEAT, HEARTBEAT_BODY_SIZE);
+        msg[ICMSG_BODY..ICMSG_BODY + 8].copy_from_slice(&self.heartbeat_seq.to_le_bytes());
+
+        Some(msg)
+    }
+
+    /// Request asking the guest to shut itself down.
+    pub(super) fn shutdown_request(&self) -> Option<Vec<u8>> {
+        if self.kind != ServiceKind::Shutdown || !self.negotiated {
+            return None;
+        }
+
+        let mut msg = Self::message(ICMSGTYPE_SHUTDOWN, SHUTDOWN_BODY_SIZE);
+        // Reason code and timeout are left to zero.
+        msg[ICMSG_BODY + 8..ICMSG_BODY + 12].copy_from_slice(&SHUTDOWN_FLAG_SHUTDOWN.to_le_bytes());
+
+        Some(msg)
+    }
+
+    /// Handle a message from the guest, answering one of the requests.
+    pub(super) fn handle_response(&mut self, msg: &[u8]) {
+        if msg.len() < ICMSG_BODY || msg[ICMSG_FLAGS] & ICMSGHDRFLAG_RESPONSE == 0 {
+            debug!("Ignoring unexpected {:?} message", self.kind);
+            return;
+        }
+
+        let msg_type = u16::from_le_bytes([msg[ICMSG_TYPE], msg[ICMSG_TYPE + 1]]);
+        let status = u32::from_le_bytes(msg[ICMSG_STATUS..ICMSG_STATUS + 4].try_into().unwrap());
+        let body = &msg[ICMSG_BODY..];
+        match msg_type {
+            ICMSGTYPE_NEGOTIATE => {
+                // The guest clears the version counts when none is suitable.
+                if status == 0 && body.len() >= 4 && body[0..2] != [0, 0] && body[2..4] != [0, 0] {
+                    info!("Negotiated {:?} integration service", self.kind);
+                    self.negotiated = true;
+                } else {
+                    warn!(
+                        "Failed negotiating {:?} integration service: status {:#x}",
+                        self.kind, status
+                    );
+                }
+            }
+            ICMSGTYPE_HEARTBEAT if body.len() >= 8 => {
+                let seq = u64::from_le_bytes(body[0..8].try_into().unwrap());
+                if seq == self.heartbeat_seq.wrapping_add(1) {
+                    self.heartbeat_pending = false;
+                }
+            }
+            ICMSGTYPE_SHUTDOWN => {
+                if status != 0 {
+                    warn!("Guest refused to shut down: status {:#x}", status);
+                }
+            }
+            t => debug!("Ignoring {:?} message of type {}", self.kind, t),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(mut msg: Vec<u8>) -> Vec<u8> {
+        msg[ICMSG_FLAGS] = ICMSGHDRFLAG_TRANSACTION | ICMSGHDRFLAG_RESPONSE;
+        msg
+    }
+
+    #[test]
+    fn test_heartbeat() {
+        let mut service = IntegrationService::new(ServiceKind::Heartbeat);
+        assert!(service.heartbeat_request().is_none());
+
+        let negotiate = service.negotiate_request();
+        assert_eq!(negotiate.len(), ICMSG_BODY + 16);
+        service.handle_response(&negotiate);
+        assert!(!service.negotiated());
+        service.handle_response(&response(negotiate));
+        assert!(service.negotiated());
+        assert!(service.shutdown_request().is_none());
+
+        let mut heartbeat = response(service.heartbeat_request().unwrap());
+        assert!(service.heartbeat_pending);
+        let seq = u64::from_le_bytes(heartbeat[ICMSG_BODY..ICMSG_BODY + 8].try_into().unwrap());
+        heartbeat[ICMSG_BODY..ICMSG_BODY + 8].copy_from_slice(&(seq + 1).to_le_bytes());
+        service.handle_response(&heartbeat);
+        assert!(!service.heartbeat_pending);
+
+        service.reset();
+        assert!(service.heartbeat_request().is_none());
+    }
+
+    #[test]
+    fn test_negotiation_failure() {
+        let mut service = IntegrationService::new(ServiceKind::Shutdown);
+        let mut negotiate = response(service.negotiate_request());
+        negotiate[ICMSG_BODY..ICMSG_BODY + 4].fill(0);
+        service.handle_response(&negotiate);
+        assert!(!service.negotiated());
+        assert!(service.shutdown_request().is_none());
+    }
+}
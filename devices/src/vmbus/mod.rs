@@ -0,0 +1,904 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Host side of the Hyper-V VMBus.
+//!
+//! VMBus is the bus Windows guests running on Hyper-V discover their
+//! synthetic devices on. The guest and the host exchange control messages
+//! through the synthetic interrupt controller (SynIC): the guest posts them
+//! with the HvPostMessage hypercall, while the host writes them to the
+//! SynIC message page of the guest before injecting a synthetic interrupt.
+//! Each channel then relies on a pair of ring buffers in guest memory, both
+//! sides signaling each other through events.
+//!
+//! Only the heartbeat and shutdown integration services are offered, for
+//! the host to monitor the guest and to shut it down gracefully.
+
+mod ic;
+mod ring;
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::os::unix::io::AsRawFd;
+use std::sync::atomic::{fence, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::{io, result, thread};
+
+use acpi_tables::{aml, Aml, AmlSink};
+use thiserror::Error;
+use vm_device::interrupt::{
+    HvSintSourceConfig, InterruptIndex, InterruptSourceConfig, InterruptSourceGroup,
+};
+use vm_memory::bitmap::AtomicBitmap;
+use vm_memory::{
+    Bytes, GuestAddress, GuestAddressSpace, GuestMemoryAtomic, GuestMemoryError, GuestMemoryMmap,
+};
+use vm_migration::{MigratableError, Pausable};
+use vmm_sys_util::epoll::{ControlOperation, Epoll, EpollEvent, EventSet};
+use vmm_sys_util::eventfd::EventFd;
+
+use self::ic::{IntegrationService, ServiceKind};
+use self::ring::RingBuffer;
+
+pub const HV_STATUS_SUCCESS: u64 = 0;
+pub const HV_STATUS_INVALID_HYPERCALL_CODE: u64 = 2;
+const HV_STATUS_INVALID_PARAMETER: u64 = 5;
+const HV_STATUS_INVALID_CONNECTION_ID: u64 = 0x12;
+const HV_STATUS_INSUFFICIENT_BUFFERS: u64 = 0x13;
+
+const HVCALL_POST_MESSAGE: u64 = 0x5c;
+const HVCALL_SIGNAL_EVENT: u64 = 0x5d;
+const HV_HYPERCALL_CODE_MASK: u64 = 0xffff;
+const HV_HYPERCALL_FAST_BIT: u64 = 1 << 16;
+
+const HV_PAGE_SIZE: u64 = 0x1000;
+const HV_SYNIC_ENABLE: u64 = 1;
+
+// Each SINT owns a 256 bytes slot of the message page and of the event
+// flags page.
+const HV_SINT_SLOT_SIZE: u64 = 256;
+const HV_MESSAGE_TYPE: u64 = 0;
+const HV_MESSAGE_PAYLOAD_SIZE: u64 = 4;
+const HV_MESSAGE_FLAGS: u64 = 5;
+const HV_MESSAGE_PAYLOAD: u64 = 16;
+const HV_MESSAGE_PAYLOAD_MAX: usize = 240;
+const HV_MESSAGE_FLAG_PENDING: u8 = 1;
+const HV_MESSAGE_NONE: u32 = 0;
+const HV_MESSAGE_VMBUS: u32 = 1;
+
+// Offsets of the fields of the HvPostMessage input.
+const HV_POST_MESSAGE_CONNECTION_ID: usize = 0;
+const HV_POST_MESSAGE_TYPE: usize = 8;
+const HV_POST_MESSAGE_PAYLOAD_SIZE: usize = 12;
+const HV_POST_MESSAGE_PAYLOAD: usize = 16;
+
+const VMBUS_MESSAGE_CONNECTION_ID: u32 = 1;
+const VMBUS_MESSAGE_CONNECTION_ID_4: u32 = 4;
+const VMBUS_MESSAGE_SINT: u32 = 2;
+// Connection IDs the guest signals channels through, one per channel.
+const VMBUS_CHANNEL_CONNECTION_ID_BASE: u32 = 0x10000;
+
+const VERSION_WIN8: u32 = (2 << 16) | 4;
+const VERSION_WIN8_1: u32 = 3 << 16;
+const VERSION_WIN10: u32 = 4 << 16;
+const SUPPORTED_VERSIONS: [u32; 3] = [VERSION_WIN10, VERSION_WIN8_1, VERSION_WIN8];
+
+const CHANNELMSG_OFFERCHANNEL: u32 = 1;
+const CHANNELMSG_REQUESTOFFERS: u32 = 3;
+const CHANNELMSG_ALLOFFERS_DELIVERED: u32 = 4;
+const CHANNELMSG_OPENCHANNEL: u32 = 5;
+const CHANNELMSG_OPENCHANNEL_RESULT: u32 = 6;
+const CHANNELMSG_CLOSECHANNEL: u32 = 7;
+const CHANNELMSG_GPADL_HEADER: u32 = 8;
+const CHANNELMSG_GPADL_BODY: u32 = 9;
+const CHANNELMSG_GPADL_CREATED: u32 = 10;
+const CHANNELMSG_GPADL_TEARDOWN: u32 = 11;
+const CHANNELMSG_GPADL_TORNDOWN: u32 = 12;
+const CHANNELMSG_INITIATE_CONTACT: u32 = 14;
+const CHANNELMSG_VERSION_RESPONSE: u32 = 15;
+const CHANNELMSG_UNLOAD: u32 = 16;
+const CHANNELMSG_UNLOAD_RESPONSE: u32 = 17;
+
+const CHANNEL_MESSAGE_HEADER_SIZE: usize = 8;
+const VERSION_RESPONSE_SIZE: usize = 16;
+const OFFER_CHANNEL_SIZE: usize = 196;
+const GPADL_CREATED_SIZE: usize = 20;
+const GPADL_TORNDOWN_SIZE: usize = 12;
+const OPEN_RESULT_SIZE: usize = 20;
+
+// Channels, in the order of their relative IDs.
+const SERVICES: [ServiceKind; 2] = [ServiceKind::Heartbeat, ServiceKind::Shutdown];
+
+/// Number of interrupts the bus relies on: one for the messages, and one
+/// for each channel.
+pub const VMBUS_INTERRUPT_COUNT: usize = SERVICES.len() + 1;
+
+const HEARTBEAT_PERIOD: Duration = Duration::from_secs(5);
+// Delay before retrying to deliver a message, as the guest doesn't let the
+// host know when it frees its message slot.
+const MESSAGE_RETRY_DELAY: Duration = Duration::from_millis(1);
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Invalid VMBus message")]
+    InvalidMessage,
+    #[error("Invalid VMBus ring buffer")]
+    InvalidRing,
+    #[error("Invalid VMBus packet")]
+    InvalidPacket,
+    #[error("VMBus ring buffer is full")]
+    RingFull,
+    #[error("Failed accessing guest memory")]
+    GuestMemory(#[source] GuestMemoryError),
+    #[error("Failed updating VMBus interrupt route")]
+    InterruptRoute(#[source] io::Error),
+    #[error("Failed triggering VMBus interrupt")]
+    TriggerInterrupt(#[source] io::Error),
+}
+
+type Result<T> = result::Result<T, Error>;
+
+fn read_u16(msg: &[u8], offset: usize) -> Result<u16> {
+    msg.get(offset..offset + 2)
+        .map(|b| u16::from_le_bytes(b.try_into().unwrap()))
+        .ok_or(Error::InvalidMessage)
+}
+
+fn read_u32(msg: &[u8], offset: usize) -> Result<u32> {
+    msg.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        .ok_or(Error::InvalidMessage)
+}
+
+fn read_u64(msg: &[u8], offset: usize) -> Result<u64> {
+    msg.get(offset..offset + 8)
+        .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+        .ok_or(Error::InvalidMessage)
+}
+
+fn write_u32(msg: &mut [u8], offset: usize, value: u32) {
+    msg[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn channel_message(msg_type: u32, size: usize) -> Vec<u8> {
+    let mut msg = vec![0u8; size];
+    write_u32(&mut msg, 0, msg_type);
+    msg
+}
+
+// SynIC pages of a vCPU, as configured by the guest.
+#[derive(Clone, Copy, Default)]
+struct Synic {
+    msg_page: Option<GuestAddress>,
+    evt_page: Option<GuestAddress>,
+}
+
+struct Gpadl {
+    relid: u32,
+    pfns: Vec<u64>,
+    count: usize,
+}
+
+impl Gpadl {
+    fn complete(&self) -> bool {
+        self.pfns.len() == self.count
+    }
+}
+
+struct Channel {
+    relid: u32,
+    service: IntegrationService,
+    ring: Option<RingBuffer>,
+    target_vp: u32,
+    next_trans_id: u64,
+}
+
+impl Channel {
+    fn connection_id(&self) -> u32 {
+        VMBUS_CHANNEL_CONNECTION_ID_BASE + self.relid
+    }
+}
+
+pub struct VmBus {
+    memory: GuestMemoryAtomic<GuestMemoryMmap<AtomicBitmap>>,
+    synic: BTreeMap<u32, Synic>,
+    version: Option<u32>,
+    message_vcpu: u32,
+    message_sint: u32,
+    pending_messages: VecDeque<Vec<u8>>,
+    channels: Vec<Channel>,
+    gpadls: HashMap<u32, Gpadl>,
+    interrupt_group: Option<Arc<dyn InterruptSourceGroup>>,
+    irq: u32,
+    notify_evt: EventFd,
+    heartbeat_deadline: Option<Instant>,
+    paused: bool,
+}
+
+impl VmBus {
+    pub fn new(memory: GuestMemoryAtomic<GuestMemoryMmap<AtomicBitmap>>) -> io::Result<Self> {
+        let channels = SERVICES
+            .iter()
+            .enumerate()
+            .map(|(i, kind)| Channel {
+                relid: i as u32 + 1,
+                service: IntegrationService::new(*kind),
+                ring: None,
+                target_vp: 0,
+                next_trans_id: 0,
+            })
+            .collect();
+
+        Ok(VmBus {
+            memory,
+            synic: BTreeMap::new(),
+            version: None,
+            message_vcpu: 0,
+            message_sint: VMBUS_MESSAGE_SINT,
+            pending_messages: VecDeque::new(),
+            channels,
+            gpadls: HashMap::new(),
+            interrupt_group: None,
+            irq: 0,
+            notify_evt: EventFd::new(libc::EFD_NONBLOCK)?,
+            heartbeat_deadline: None,
+            paused: false,
+        })
+    }
+
+    /// Set the interrupts the bus relies on, `VMBUS_INTERRUPT_COUNT` of them,
+    /// along with the IRQ the ACPI device reports.
+    pub fn set_interrupt_group(&mut self, group: Arc<dyn InterruptSourceGroup>, irq: u32) {
+        self.interrupt_group = Some(group);
+        self.irq = irq;
+    }
+
+    /// Track the SynIC configuration of a vCPU, the guest enabling it as
+    /// soon as the vCPU is brought up.
+    pub fn synic_update(&mut self, vcpu_id: u32, control: u64, evt_page: u64, msg_page: u64) {
+        let page = |value: u64| {
+            (control & HV_SYNIC_ENABLE != 0 && value & HV_SYNIC_ENABLE != 0)
+                .then(|| GuestAddress(value & !(HV_PAGE_SIZE - 1)))
+        };
+
+        self.synic.insert(
+            vcpu_id,
+            Synic {
+                msg_page: page(msg_page),
+                evt_page: page(evt_page),
+            },
+        );
+
+        // The message slot might have just become available.
+        self.flush_messages();
+    }
+
+    /// Handle a hypercall KVM forwarded, returning the Hyper-V status.
+    pub fn hypercall(&mut self, input: u64, params: [u64; 2]) -> u64 {
+        let fast = input & HV_HYPERCALL_FAST_BIT != 0;
+        let status = match input & HV_HYPERCALL_CODE_MASK {
+            HVCALL_POST_MESSAGE if !fast => self.post_message(GuestAddress(params[0])),
+            HVCALL_SIGNAL_EVENT => {
+                let param = if fast {
+                    Ok(params[0])
+                } else {
+                    self.memory.memory().read_obj(GuestAddress(params[0]))
+                };
+                match param {
+                    Ok(param) => self.signal_event(param as u32),
+                    Err(e) => {
+                        warn!("Failed reading HvSignalEvent input: {}", e);
+                        HV_STATUS_INVALID_PARAMETER
+                    }
+                }
+            }
+            HVCALL_POST_MESSAGE => HV_STATUS_INVALID_PARAMETER,
+            _ => HV_STATUS_INVALID_HYPERCALL_CODE,
+        };
+
+        // Let the worker reconsider its timeouts.
+        if self.worker_timeout().is_some() {
+            if let Err(e) = self.notify_evt.write(1) {
+                error!("Failed notifying VMBus worker: {}", e);
+            }
+        }
+
+        status
+    }
+
+    /// Ask the guest to shut itself down, returning false if the guest
+    /// doesn't provide the shutdown service.
+    pub fn request_shutdown(&mut self) -> bool {
+        let Some(index) = self
+            .channels
+            .iter()
+            .position(|c| c.ring.is_some() && c.service.kind() == ServiceKind::Shutdown)
+        else {
+            return false;
+        };
+
+        let Some(request) = self.channels[index].service.shutdown_request() else {
+            return false;
+        };
+
+        match self.send_packet(index, &request) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("Failed sending shutdown request: {}", e);
+                false
+            }
+        }
+    }
+
+    fn post_message(&mut self, input: GuestAddress) -> u64 {
+        let mut header = [0u8; HV_POST_MESSAGE_PAYLOAD];
+        if let Err(e) = self.memory.memory().read_slice(&mut header, input) {
+            warn!("Failed reading HvPostMessage input: {}", e);
+            return HV_STATUS_INVALID_PARAMETER;
+        }
+
+        let connection_id = read_u32(&header, HV_POST_MESSAGE_CONNECTION_ID).unwrap();
+        let msg_type = read_u32(&header, HV_POST_MESSAGE_TYPE).unwrap();
+        let size = read_u32(&header, HV_POST_MESSAGE_PAYLOAD_SIZE).unwrap() as usize;
+        if connection_id != VMBUS_MESSAGE_CONNECTION_ID
+            && connection_id != VMBUS_MESSAGE_CONNECTION_ID_4
+        {
+            return HV_STATUS_INVALID_CONNECTION_ID;
+        }
+        if msg_type != HV_MESSAGE_VMBUS || size > HV_MESSAGE_PAYLOAD_MAX {
+            return HV_STATUS_INVALID_PARAMETER;
+        }
+
+        let mut msg = vec![0u8; size];
+        if let Err(e) = self.memory.memory().read_slice(
+            &mut msg,
+            input.unchecked_add(HV_POST_MESSAGE_PAYLOAD as u64),
+        ) {
+            warn!("Failed reading HvPostMessage input: {}", e);
+            return HV_STATUS_INVALID_PARAMETER;
+        }
+
+        match self.handle_message(&msg) {
+            Ok(()) => HV_STATUS_SUCCESS,
+            Err(Error::InvalidMessage) => HV_STATUS_INVALID_PARAMETER,
+            Err(e) => {
+                warn!("Failed handling VMBus message: {}", e);
+                HV_STATUS_INSUFFICIENT_BUFFERS
+            }
+        }
+    }
+
+    fn signal_event(&mut self, connection_id: u32) -> u64 {
+        let Some(index) = self
+            .channels
+            .iter()
+            .position(|c| c.connection_id() == connection_id)
+        else {
+            return HV_STATUS_INVALID_CONNECTION_ID;
+        };
+
+        if let Err(e) = self.process_channel(index) {
+            warn!("Failed processing VMBus channel {}: {}", index + 1, e);
+        }
+
+        HV_STATUS_SUCCESS
+    }
+
+    fn handle_message(&mut self, msg: &[u8]) -> Result<()> {
+        match read_u32(msg, 0)? {
+            CHANNELMSG_INITIATE_CONTACT => self.initiate_contact(msg),
+            CHANNELMSG_REQUESTOFFERS if self.version.is_some() => {
+                self.request_offers();
+                Ok(())
+            }
+            CHANNELMSG_GPADL_HEADER => self.gpadl_header(msg),
+            CHANNELMSG_GPADL_BODY => self.gpadl_body(msg),
+            CHANNELMSG_GPADL_TEARDOWN => self.gpadl_teardown(msg),
+            CHANNELMSG_OPENCHANNEL => self.open_channel(msg),
+            CHANNELMSG_CLOSECHANNEL => self.close_channel(msg),
+            CHANNELMSG_UNLOAD => self.unload(),
+            t => {
+                debug!("Ignoring VMBus message of type {}", t);
+                Ok(())
+            }
+        }
+    }
+
+    fn initiate_contact(&mut self, msg: &[u8]) -> Result<()> {
+        let version = read_u32(msg, 8)?;
+        self.message_vcpu = read_u32(msg, 12)?;
+        // Earlier versions rely on a fixed SINT.
+        self.message_sint = if version >= VERSION_WIN10 {
+            u32::from(*msg.get(16).ok_or(Error::InvalidMessage)?)
+        } else {
+            VMBUS_MESSAGE_SINT
+        };
+        self.route_interrupt(0, self.message_vcpu)?;
+
+        let supported = SUPPORTED_VERSIONS.contains(&version);
+        if supported {
+            info!(
+                "VMBus version {}.{} negotiated",
+                version >> 16,
+                version & 0xffff
+            );
+            self.version = Some(version);
+        }
+
+        let mut response = channel_message(CHANNELMSG_VERSION_RESPONSE, VERSION_RESPONSE_SIZE);
+        response[8] = supported as u8;
+        self.queue_message(response);
+
+        Ok(())
+    }
+
+    fn request_offers(&mut self) {
+        for channel in self.channels.iter() {
+            let mut offer = channel_message(CHANNELMSG_OFFERCHANNEL, OFFER_CHANNEL_SIZE);
+            offer[8..24].copy_from_slice(&channel.service.kind().interface_type());
+            offer[24..40].copy_from_slice(&channel.service.kind().interface_instance());
+            write_u32(&mut offer, 184, channel.relid);
+            // No monitor page, the guest signals the channel through its
+            // dedicated connection ID.
+            offer[188] = 0xff;
+            offer[190] = 1;
+            write_u32(&mut offer, 192, channel.connection_id());
+            self.pending_messages.push_back(offer);
+        }
+
+        self.queue_message(channel_message(
+            CHANNELMSG_ALLOFFERS_DELIVERED,
+            CHANNEL_MESSAGE_HEADER_SIZE,
+        ));
+    }
+
+    fn gpadl_header(&mut self, msg: &[u8]) -> Result<()> {
+        let relid = read_u32(msg, 8)?;
+        let gpadl = read_u32(msg, 12)?;
+        let range_buflen = usize::from(read_u16(msg, 16)?);
+        // Ring buffers are always described by a single range.
+        if read_u16(msg, 18)? != 1 || range_buflen < 8 {
+            return Err(Error::InvalidMessage);
+        }
+
+        let count = (range_buflen - 8) / 8;
+        let pfns = (28..msg.len())
+            .step_by(8)
+            .take(count)
+            .map(|offset| read_u64(msg, offset))
+            .collect::<Result<Vec<u64>>>()?;
+        self.gpadls.insert(gpadl, Gpadl { relid, pfns, count });
+        self.gpadl_created(gpadl);
+
+        Ok(())
+    }
+
+    fn gpadl_body(&mut self, msg: &[u8]) -> Result<()> {
+        let gpadl_id = read_u32(msg, 12)?;
+        let gpadl = self
+            .gpadls
+            .get_mut(&gpadl_id)
+            .ok_or(Error::InvalidMessage)?;
+
+        let remaining = gpadl.count - gpadl.pfns.len();
+        for offset in (16..msg.len()).step_by(8).take(remaining) {
+            gpadl.pfns.push(read_u64(msg, offset)?);
+        }
+        self.gpadl_created(gpadl_id);
+
+        Ok(())
+    }
+
+    fn gpadl_created(&mut self, gpadl_id: u32) {
+        let Some(gpadl) = self.gpadls.get(&gpadl_id).filter(|g| g.complete()) else {
+            return;
+        };
+
+        let mut response = channel_message(CHANNELMSG_GPADL_CREATED, GPADL_CREATED_SIZE);
+        write_u32(&mut response, 8, gpadl.relid);
+        write_u32(&mut response, 12, gpadl_id);
+        self.queue_message(response);
+    }
+
+    fn gpadl_teardown(&mut self, msg: &[u8]) -> Result<()> {
+        let gpadl = read_u32(msg, 12)?;
+        self.gpadls.remove(&gpadl);
+
+        let mut response = channel_message(CHANNELMSG_GPADL_TORNDOWN, GPADL_TORNDOWN_SIZE);
+        write_u32(&mut response, 8, gpadl);
+        self.queue_message(response);
+
+        Ok(())
+    }
+
+    fn channel_index(&self, relid: u32) -> Result<usize> {
+        self.channels
+            .iter()
+            .position(|c| c.relid == relid)
+            .ok_or(Error::InvalidMessage)
+    }
+
+    fn open_channel(&mut self, msg: &[u8]) -> Result<()> {
+        let relid = read_u32(msg, 8)?;
+        let openid = read_u32(msg, 12)?;
+        let gpadl = read_u32(msg, 16)?;
+        let target_vp = read_u32(msg, 20)?;
+        let offset = read_u32(msg, 24)?;
+        let index = self.channel_index(relid)?;
+
+        let ring = self
+            .gpadls
+            .get(&gpadl)
+            .filter(|g| g.complete())
+            .ok_or(Error::InvalidRing)
+            .and_then(|g| RingBuffer::new(&g.pfns, offset));
+        let status = match ring {
+            Ok(ring) => {
+                self.route_interrupt(relid, target_vp)?;
+                let channel = &mut self.channels[index];
+                channel.ring = Some(ring);
+                channel.target_vp = target_vp;
+                0
+            }
+            Err(e) => {
+                warn!("Failed opening VMBus channel {}: {}", relid, e);
+                1
+            }
+        };
+
+        let mut response = channel_message(CHANNELMSG_OPENCHANNEL_RESULT, OPEN_RESULT_SIZE);
+        write_u32(&mut response, 8, relid);
+        write_u32(&mut response, 12, openid);
+        write_u32(&mut response, 16, status);
+        self.queue_message(response);
+
+        if status == 0 {
+            let request = self.channels[index].service.negotiate_request();
+            self.send_packet(index, &request)?;
+        }
+
+        Ok(())
+    }
+
+    fn close_channel(&mut self, msg: &[u8]) -> Result<()> {
+        let index = self.channel_index(read_u32(msg, 8)?)?;
+        self.reset_channel(index);
+
+        Ok(())
+    }
+
+    fn reset_channel(&mut self, index: usize) {
+        let channel = &mut self.channels[index];
+        channel.ring = None;
+        channel.service.reset();
+        if channel.service.kind() == ServiceKind::Heartbeat {
+            self.heartbeat_deadline = None;
+        }
+    }
+
+    fn unload(&mut self) -> Result<()> {
+        for index in 0..self.channels.len() {
+            self.reset_channel(index);
+        }
+        self.gpadls.clear();
+        self.pending_messages.clear();
+        self.version = None;
+
+        self.queue_message(channel_message(
+            CHANNELMSG_UNLOAD_RESPONSE,
+            CHANNEL_MESSAGE_HEADER_SIZE,
+        ));
+
+        Ok(())
+    }
+
+    fn process_channel(&mut self, index: usize) -> Result<()> {
+        let channel = &mut self.channels[index];
+        let Some(ring) = &channel.ring else {
+            return Ok(());
+        };
+
+        let mem = self.memory.memory();
+        while let Some(packet) = ring.read_packet(&mem)? {
+            debug!("VMBus channel {} packet {}", channel.relid, packet.trans_id);
+            channel.service.handle_response(&packet.data);
+        }
+
+        if channel.service.kind() == ServiceKind::Heartbeat
+            && channel.service.negotiated()
+            && self.heartbeat_deadline.is_none()
+        {
+            self.heartbeat_deadline = Some(Instant::now());
+        }
+
+        Ok(())
+    }
+
+    fn send_packet(&mut self, index: usize, data: &[u8]) -> Result<()> {
+        let channel = &mut self.channels[index];
+        let Some(ring) = &channel.ring else {
+            return Ok(());
+        };
+
+        channel.next_trans_id += 1;
+        let signal = ring.write_packet(&self.memory.memory(), data, channel.next_trans_id)?;
+        if signal {
+            let (relid, target_vp) = (channel.relid, channel.target_vp);
+            self.signal_channel(relid, target_vp)?;
+        }
+
+        Ok(())
+    }
+
+    fn route_interrupt(&self, index: InterruptIndex, vcpu: u32) -> Result<()> {
+        let Some(group) = &self.interrupt_group else {
+            return Ok(());
+        };
+
+        group
+            .update(
+                index,
+                InterruptSourceConfig::HvSint(HvSintSourceConfig {
+                    vcpu,
+                    sint: self.message_sint,
+                }),
+                false,
+                true,
+            )
+            .map_err(Error::InterruptRoute)
+    }
+
+    fn trigger(&self, index: InterruptIndex) -> Result<()> {
+        if let Some(group) = &self.interrupt_group {
+            group.trigger(index).map_err(Error::TriggerInterrupt)?;
+        }
+
+        Ok(())
+    }
+
+    // Flag the channel in the event flags of its target vCPU, and let the
+    // guest know about it.
+    fn signal_channel(&self, relid: u32, vcpu: u32) -> Result<()> {
+        let Some(evt_page) = self.synic.get(&vcpu).and_then(|s| s.evt_page) else {
+            return Ok(());
+        };
+
+        // The guest atomically clears the flags it handles, so it might miss
+        // one of the flags we set here if racing with us. This only results
+        // in a spurious event, as the flag being set is set again.
+        let addr = evt_page
+            .unchecked_add(u64::from(self.message_sint) * HV_SINT_SLOT_SIZE)
+            .unchecked_add(u64::from(relid / 8));
+        let mem = self.memory.memory();
+        let flags: u8 = mem
+            .load(addr, Ordering::Acquire)
+            .map_err(Error::GuestMemory)?;
+        mem.store(flags | (1 << (relid % 8)), addr, Ordering::Release)
+            .map_err(Error::GuestMemory)?;
+
+        self.trigger(relid)
+    }
+
+    fn queue_message(&mut self, msg: Vec<u8>) {
+        self.pending_messages.push_back(msg);
+        self.flush_messages();
+    }
+
+    // Deliver as many messages as the message slot of the guest allows.
+    fn flush_messages(&mut self) {
+        while let Some(msg) = self.pending_messages.front() {
+            match self.deliver_message(msg) {
+                Ok(true) => {
+                    self.pending_messages.pop_front();
+                }
+                Ok(false) => break,
+                Err(e) => {
+                    warn!("Failed delivering VMBus message: {}", e);
+                    self.pending_messages.pop_front();
+                }
+            }
+        }
+    }
+
+    fn deliver_message(&self, msg: &[u8]) -> Result<bool> {
+        let Some(msg_page) = self.synic.get(&self.message_vcpu).and_then(|s| s.msg_page) else {
+            return Ok(false);
+        };
+
+        let slot = msg_page.unchecked_add(u64::from(self.message_sint) * HV_SINT_SLOT_SIZE);
+        let mem = self.memory.memory();
+        let msg_type: u32 = mem
+            .load(slot.unchecked_add(HV_MESSAGE_TYPE), Ordering::Acquire)
+            .map_err(Error::GuestMemory)?;
+        if msg_type != HV_MESSAGE_NONE {
+            // Ask the guest to let us know when it's done with the slot.
+            // Only KVM is told about it though, hence the retries.
+            let flags: u8 = mem
+                .load(slot.unchecked_add(HV_MESSAGE_FLAGS), Ordering::Acquire)
+                .map_err(Error::GuestMemory)?;
+            mem.store(
+                flags | HV_MESSAGE_FLAG_PENDING,
+                slot.unchecked_add(HV_MESSAGE_FLAGS),
+                Ordering::Release,
+            )
+            .map_err(Error::GuestMemory)?;
+            return Ok(false);
+        }
+
+        mem.write_slice(msg, slot.unchecked_add(HV_MESSAGE_PAYLOAD))
+            .map_err(Error::GuestMemory)?;
+        mem.write_obj(msg.len() as u8, slot.unchecked_add(HV_MESSAGE_PAYLOAD_SIZE))
+            .map_err(Error::GuestMemory)?;
+        mem.write_obj(0u8, slot.unchecked_add(HV_MESSAGE_FLAGS))
+            .map_err(Error::GuestMemory)?;
+        // The type tells the guest the slot is in use, it must come last.
+        fence(Ordering::SeqCst);
+        mem.store(
+            HV_MESSAGE_VMBUS,
+            slot.unchecked_add(HV_MESSAGE_TYPE),
+            Ordering::Release,
+        )
+        .map_err(Error::GuestMemory)?;
+
+        self.trigger(0)?;
+
+        Ok(true)
+    }
+
+    // Time the worker can wait for before calling process_timeouts().
+    fn worker_timeout(&self) -> Option<Duration> {
+        if self.paused {
+            return None;
+        }
+
+        if !self.pending_messages.is_empty() {
+            return Some(MESSAGE_RETRY_DELAY);
+        }
+
+        self.heartbeat_deadline
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    fn process_timeouts(&mut self) {
+        if self.paused {
+            return;
+        }
+
+        self.flush_messages();
+
+        let now = Instant::now();
+        if self.heartbeat_deadline.is_some_and(|d| d <= now) {
+            self.heartbeat_deadline = Some(now + HEARTBEAT_PERIOD);
+            for index in 0..self.channels.len() {
+                if let Some(request) = self.channels[index].service.heartbeat_request() {
+                    if let Err(e) = self.send_packet(index, &request) {
+                        warn!("Failed sending heartbeat: {}", e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Pausable for VmBus {
+    fn pause(&mut self) -> result::Result<(), MigratableError> {
+        self.paused = true;
+        Ok(())
+    }
+
+    fn resume(&mut self) -> result::Result<(), MigratableError> {
+        self.paused = false;
+        for channel in self.channels.iter_mut() {
+            channel.service.resume();
+        }
+        if self.heartbeat_deadline.is_some() {
+            self.heartbeat_deadline = Some(Instant::now() + HEARTBEAT_PERIOD);
+        }
+
+        self.notify_evt
+            .write(1)
+            .map_err(|e| MigratableError::Resume(e.into()))
+    }
+}
+
+impl Aml for VmBus {
+    fn to_aml_bytes(&self, sink: &mut dyn AmlSink) {
+        aml::Device::new(
+            "_SB_.VMBS".into(),
+            vec![
+                &aml::Name::new("_HID".into(), &"VMBus"),
+                &aml::Name::new("_UID".into(), &aml::ZERO),
+                &aml::Name::new("_DDN".into(), &"VMBUS"),
+                &aml::Name::new("_STA".into(), &0xfu8),
+                &aml::Name::new(
+                    "_CRS".into(),
+                    &aml::ResourceTemplate::new(vec![&aml::Interrupt::new(
+                        true, true, false, false, self.irq,
+                    )]),
+                ),
+            ],
+        )
+        .to_aml_bytes(sink)
+    }
+}
+
+const KILL_EVENT: u64 = 0;
+const NOTIFY_EVENT: u64 = 1;
+
+/// Thread retrying the delivery of the messages and sending heartbeats,
+/// stopped when dropped.
+pub struct VmBusWorker {
+    kill_evt: EventFd,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl VmBusWorker {
+    pub fn new(vmbus: Arc<Mutex<VmBus>>) -> io::Result<Self> {
+        let kill_evt = EventFd::new(libc::EFD_NONBLOCK)?;
+        let notify_evt = vmbus.lock().unwrap().notify_evt.try_clone()?;
+
+        let epoll = Epoll::new()?;
+        epoll.ctl(
+            ControlOperation::Add,
+            kill_evt.as_raw_fd(),
+            EpollEvent::new(EventSet::IN, KILL_EVENT),
+        )?;
+        epoll.ctl(
+            ControlOperation::Add,
+            notify_evt.as_raw_fd(),
+            EpollEvent::new(EventSet::IN, NOTIFY_EVENT),
+        )?;
+
+        let handle = thread::Builder::new()
+            .name("vmbus".to_string())
+            .spawn(move || {
+                let mut events = vec![EpollEvent::default(); 2];
+                loop {
+                    let timeout = vmbus
+                        .lock()
+                        .unwrap()
+                        .worker_timeout()
+                        .map(|t| i32::try_from(t.as_millis()).unwrap_or(i32::MAX))
+                        .unwrap_or(-1);
+                    let num_events = match epoll.wait(timeout, &mut events) {
+                        Ok(num_events) => num_events,
+                        Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                        Err(e) => {
+                            error!("Failed waiting for VMBus events: {}", e);
+                            return;
+                        }
+                    };
+
+                    for event in events.iter().take(num_events) {
+                        match event.data() {
+                            KILL_EVENT => return,
+                            NOTIFY_EVENT => {
+                                let _ = notify_evt.read();
+                            }
+                            _ => {}
+                        }
+                    }
+
+                    vmbus.lock().unwrap().process_timeouts();
+                }
+            })?;
+
+        Ok(VmBusWorker {
+            kill_evt,
+            handle: Some(handle),
+        })
+    }
+}
+
+impl Drop for VmBusWorker {
+    fn drop(&mut self) {
+        if let Err(e) = self.kill_evt.write(1) {
+            error!("Failed stopping VMBus worker: {}", e);
+        }
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
@@ -0,0 +1,286 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! VMBus channel ring buffers.
+//!
+//! A channel relies on a pair of rings described by a single GPADL. The
+//! first pages of the GPADL hold the ring the guest writes to, the remaining
+//! ones the ring the host writes to. Each ring starts with a page holding
+//! its indices, followed by the data area, whose pages don't need to be
+//! contiguous in guest memory.
+
+use std::sync::atomic::{fence, Ordering};
+
+use vm_memory::bitmap::AtomicBitmap;
+use vm_memory::{Bytes, GuestAddress, GuestMemoryMmap};
+
+use super::{Error, Result, HV_PAGE_SIZE};
+
+// Offsets of the fields of the ring header page.
+const RING_WRITE_INDEX: u64 = 0;
+const RING_READ_INDEX: u64 = 4;
+const RING_INTERRUPT_MASK: u64 = 8;
+
+const VM_PKT_DATA_INBAND: u16 = 6;
+const VMBUS_DATA_PACKET_FLAG_COMPLETION_REQUESTED: u16 = 1;
+
+const PACKET_DESCRIPTOR_SIZE: usize = 16;
+// Every packet is followed by the previous write index of the ring.
+const PACKET_TRAILER_SIZE: usize = 8;
+
+/// Packet read from the ring the guest writes to.
+pub(super) struct Packet {
+    pub(super) trans_id: u64,
+    pub(super) data: Vec<u8>,
+}
+
+struct Ring {
+    // Guest frame numbers of the ring, header page included.
+    pfns: Vec<u64>,
+}
+
+impl Ring {
+    fn header(&self, offset: u64) -> GuestAddress {
+        GuestAddress(self.pfns[0] * HV_PAGE_SIZE + offset)
+    }
+
+    fn data_size(&self) -> u32 {
+        ((self.pfns.len() as u64 - 1) * HV_PAGE_SIZE) as u32
+    }
+
+    fn load(&self, mem: &GuestMemoryMmap<AtomicBitmap>, offset: u64) -> Result<u32> {
+        mem.load(self.header(offset), Ordering::Acquire)
+            .map_err(Error::GuestMemory)
+    }
+
+    fn store(&self, mem: &GuestMemoryMmap<AtomicBitmap>, offset: u64, value: u32) -> Result<()> {
+        mem.store(value, self.header(offset), Ordering::Release)
+            .map_err(Error::GuestMemory)
+    }
+
+    // Load both indices, making sure they fall within the data area.
+    fn indices(&self, mem: &GuestMemoryMmap<AtomicBitmap>) -> Result<(u32, u32)> {
+        let write_index = self.load(mem, RING_WRITE_INDEX)?;
+        let read_index = self.load(mem, RING_READ_INDEX)?;
+        let size = self.data_size();
+        if write_index >= size || read_index >= size {
+            return Err(Error::InvalidRing);
+        }
+
+        Ok((write_index, read_index))
+    }
+
+    // Split an access to the data area at the given offset into accesses
+    // to each page it covers, wrapping around the end of the ring.
+    fn data_chunks(&self, offset: u32, len: usize) -> Vec<(GuestAddress, usize)> {
+        let size = u64::from(self.data_size());
+        let mut offset = u64::from(offset);
+        let mut chunks = Vec::new();
+        let mut remaining = len;
+        while remaining > 0 {
+            offset %= size;
+            let page = (offset / HV_PAGE_SIZE) as usize + 1;
+            let page_offset = offset % HV_PAGE_SIZE;
+            let count = std::cmp::min(remaining as u64, HV_PAGE_SIZE - page_offset) as usize;
+            chunks.push((
+                GuestAddress(self.pfns[page] * HV_PAGE_SIZE + page_offset),
+                count,
+            ));
+            offset += count as u64;
+            remaining -= count;
+        }
+
+        chunks
+    }
+
+    fn read_data(
+        &self,
+        mem: &GuestMemoryMmap<AtomicBitmap>,
+        offset: u32,
+        buf: &mut [u8],
+    ) -> Result<()> {
+        let mut pos = 0;
+        for (addr, count) in self.data_chunks(offset, buf.len()) {
+            mem.read_slice(&mut buf[pos..pos + count], addr)
+                .map_err(Error::GuestMemory)?;
+            pos += count;
+        }
+
+        Ok(())
+    }
+
+    fn write_data(
+        &self,
+        mem: &GuestMemoryMmap<AtomicBitmap>,
+        offset: u32,
+        buf: &[u8],
+    ) -> Result<()> {
+        let mut pos = 0;
+        for (addr, count) in self.data_chunks(offset, buf.len()) {
+            mem.write_slice(&buf[pos..pos + count], addr)
+                .map_err(Error::GuestMemory)?;
+            pos += count;
+        }
+
+        Ok(())
+    }
+}
+
+/// Pair of rings a channel exchanges packets through.
+pub(super) struct RingBuffer {
+    // Ring written by the guest.
+    inbound: Ring,
+    // Ring written by the host.
+    outbound: Ring,
+}
+
+impl RingBuffer {
+    /// Split the pages of a GPADL into both rings, the guest providing the
+    /// page offset of the ring the host writes to.
+    pub(super) fn new(pfns: &[u64], offset: u32) -> Result<Self> {
+        let offset = offset as usize;
+        if offset < 2 || pfns.len() < offset + 2 {
+            return Err(Error::InvalidRing);
+        }
+
+        Ok(RingBuffer {
+            inbound: Ring {
+                pfns: pfns[..offset].to_vec(),
+            },
+            outbound: Ring {
+                pfns: pfns[offset..].to_vec(),
+            },
+        })
+    }
+
+    /// Read the next packet written by the guest, if any.
+    pub(super) fn read_packet(
+        &self,
+        mem: &GuestMemoryMmap<AtomicBitmap>,
+    ) -> Result<Option<Packet>> {
+        let ring = &self.inbound;
+        let (write_index, read_index) = ring.indices(mem)?;
+        if write_index == read_index {
+            return Ok(None);
+        }
+
+        let size = ring.data_size();
+        let available = ((write_index + size - read_index) % size) as usize;
+        let mut desc = [0u8; PACKET_DESCRIPTOR_SIZE];
+        ring.read_data(mem, read_index, &mut desc)?;
+        let offset = usize::from(u16::from_le_bytes([desc[2], desc[3]])) * 8;
+        let len = usize::from(u16::from_le_bytes([desc[4], desc[5]])) * 8;
+        if offset < PACKET_DESCRIPTOR_SIZE || offset > len || len + PACKET_TRAILER_SIZE > available
+        {
+            return Err(Error::InvalidPacket);
+        }
+
+        let mut data = vec![0u8; len - offset];
+        ring.read_data(mem, read_index + offset as u32, &mut data)?;
+
+        // The packet must be fully read before the guest can reuse its space.
+        fence(Ordering::SeqCst);
+        ring.store(
+            mem,
+            RING_READ_INDEX,
+            (read_index + (len + PACKET_TRAILER_SIZE) as u32) % size,
+        )?;
+
+        Ok(Some(Packet {
+            trans_id: u64::from_le_bytes(desc[8..16].try_into().unwrap()),
+            data,
+        }))
+    }
+
+    /// Write an in-band packet for the guest, telling if the guest must be
+    /// signaled about it.
+    pub(super) fn write_packet(
+        &self,
+        mem: &GuestMemoryMmap<AtomicBitmap>,
+        data: &[u8],
+        trans_id: u64,
+    ) -> Result<bool> {
+        let ring = &self.outbound;
+        let (write_index, read_index) = ring.indices(mem)?;
+
+        let size = ring.data_size();
+        let len = (PACKET_DESCRIPTOR_SIZE + data.len()).next_multiple_of(8);
+        let total = len + PACKET_TRAILER_SIZE;
+        let used = (write_index + size - read_index) % size;
+        // A full ring can't be told apart from an empty one, hence the
+        // strict comparison.
+        if total >= (size - used) as usize {
+            return Err(Error::RingFull);
+        }
+
+        let mut packet = vec![0u8; total];
+        packet[0..2].copy_from_slice(&VM_PKT_DATA_INBAND.to_le_bytes());
+        packet[2..4].copy_from_slice(&((PACKET_DESCRIPTOR_SIZE / 8) as u16).to_le_bytes());
+        packet[4..6].copy_from_slice(&((len / 8) as u16).to_le_bytes());
+        packet[6..8].copy_from_slice(&VMBUS_DATA_PACKET_FLAG_COMPLETION_REQUESTED.to_le_bytes());
+        packet[8..16].copy_from_slice(&trans_id.to_le_bytes());
+        packet[PACKET_DESCRIPTOR_SIZE..PACKET_DESCRIPTOR_SIZE + data.len()].copy_from_slice(data);
+        packet[len..].copy_from_slice(&(u64::from(write_index) << 32).to_le_bytes());
+        ring.write_data(mem, write_index, &packet)?;
+
+        // The packet must be visible before the guest sees the new index,
+        // which itself must be visible before checking if the guest is
+        // still reading from the ring.
+        fence(Ordering::SeqCst);
+        ring.store(mem, RING_WRITE_INDEX, (write_index + total as u32) % size)?;
+        fence(Ordering::SeqCst);
+
+        // Only signal the guest if the ring was empty, otherwise it will
+        // find the packet when done with the previous ones.
+        let interrupt_mask = ring.load(mem, RING_INTERRUPT_MASK)?;
+        let read_index = ring.load(mem, RING_READ_INDEX)?;
+
+        Ok(interrupt_mask == 0 && read_index == write_index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ring_buffer() {
+        let mem = GuestMemoryMmap::<AtomicBitmap>::from_ranges(&[(
+            GuestAddress(0),
+            6 * HV_PAGE_SIZE as usize,
+        )])
+        .unwrap();
+        assert!(RingBuffer::new(&[0, 1, 2], 2).is_err());
+
+        // Looking at the same pages from the other side lets us read back
+        // the packets written to the host ring.
+        let host = RingBuffer::new(&[0, 1, 2, 3, 4, 5], 2).unwrap();
+        let guest = RingBuffer::new(&[2, 3, 4, 5, 0, 1], 4).unwrap();
+        assert!(guest.read_packet(&mem).unwrap().is_none());
+
+        // Only the first packet written to an empty ring needs a signal.
+        assert!(host.write_packet(&mem, &[1, 2, 3], 1).unwrap());
+        assert!(!host.write_packet(&mem, &[4; 5000], 2).unwrap());
+        assert!(matches!(
+            host.write_packet(&mem, &[5; 8000], 3),
+            Err(Error::RingFull)
+        ));
+
+        let packet = guest.read_packet(&mem).unwrap().unwrap();
+        assert_eq!(packet.trans_id, 1);
+        assert_eq!(packet.data, [1, 2, 3, 0, 0, 0, 0, 0]);
+        let packet = guest.read_packet(&mem).unwrap().unwrap();
+        assert_eq!(packet.trans_id, 2);
+        assert_eq!(packet.data.len(), 5000);
+        assert!(packet.data.iter().all(|b| *b == 4));
+        assert!(guest.read_packet(&mem).unwrap().is_none());
+
+        // Packets wrap around the end of the ring.
+        assert!(host.write_packet(&mem, &[6; 8000], 4).unwrap());
+        let packet = guest.read_packet(&mem).unwrap().unwrap();
+        assert_eq!(packet.trans_id, 4);
+        assert!(packet.data.iter().all(|b| *b == 6));
+    }
+}
@@ -3,7 +3,9 @@
 // SPDX-License-Identifier: Apache-2.0
 
 #![no_main]
-use devices::legacy::Cmos;
+use std::sync::{Arc, Mutex};
+
+use devices::legacy::{Cmos, RtcClock};
 use libc::EFD_NONBLOCK;
 use libfuzzer_sys::{fuzz_target, Corpus};
 use vm_device::BusDevice;
@@ -26,6 +28,7 @@ fuzz_target!(|bytes: &[u8]| -> Corpus {
         u64::from_le_bytes(above_4g),
         EventFd::new(EFD_NONBLOCK).unwrap(),
         None,
+        Arc::new(Mutex::new(RtcClock::new(0, false))),
     );
 
     let mut i = 16;
@@ -4,7 +4,7 @@
 
 #![no_main]
 use std::os::unix::io::AsRawFd;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::{channel, Receiver};
 use std::thread;
 
@@ -14,13 +14,13 @@ use once_cell::sync::Lazy;
 use vm_migration::MigratableError;
 use vmm::api::http::*;
 use vmm::api::{
-    ApiRequest, RequestHandler, VmInfoResponse, VmReceiveMigrationData, VmSendMigrationData,
-    VmmPingResponse,
+    ApiRequest, RequestHandler, VmBackupData, VmDiskSnapshotData, VmGuestCommandData, VmInfoResponse, VmReceiveMigrationData,
+    VmSendMigrationData, VmmPingResponse,
 };
 use vmm::config::RestoreConfig;
 use vmm::vm::{Error as VmError, VmState};
 use vmm::vm_config::*;
-use vmm::{EpollContext, EpollDispatch};
+use vmm::{EpollContext, EpollDispatch, Error as VmmError};
 use vmm_sys_util::eventfd::EventFd;
 
 // Need to be ordered for test case reproducibility
@@ -100,7 +100,7 @@ impl RequestHandler for StubApiRequestHandler {
         Ok(())
     }
 
-    fn vm_snapshot(&mut self, _: &str) -> Result<(), VmError> {
+    fn vm_snapshot(&mut self, _: &str, _: Option<&Path>) -> Result<(), VmError> {
         Ok(())
     }
 
@@ -108,6 +108,10 @@ impl RequestHandler for StubApiRequestHandler {
         Ok(())
     }
 
+    fn vm_hibernate(&mut self, _: &str, _: Option<&Path>) -> Result<(), VmError> {
+        Ok(())
+    }
+
     #[cfg(target_arch = "x86_64")]
     fn vm_coredump(&mut self, _: &str) -> Result<(), VmError> {
         Ok(())
@@ -145,14 +149,22 @@ impl RequestHandler for StubApiRequestHandler {
                     prefault: false,
                     zones: None,
                     thp: true,
+                    thp_collapse: false,
                 },
                 payload: Some(PayloadConfig {
                     kernel: Some(PathBuf::from("/path/to/kernel")),
+                    kernel_fd: None,
                     firmware: None,
+                    firmware_fd: None,
                     cmdline: None,
                     initramfs: None,
+                    initramfs_fd: None,
+                    extra_initramfs: None,
                     #[cfg(feature = "igvm")]
                     igvm: None,
+                    #[cfg(feature = "igvm")]
+                    igvm_fd: None,
+                    fallback_firmware: None,
                 }),
                 rate_limit_groups: None,
                 disks: None,
@@ -169,12 +181,14 @@ impl RequestHandler for StubApiRequestHandler {
                     mode: ConsoleOutputMode::Null,
                     iommu: false,
                     socket: None,
+                    tee: Vec::new(),
                 },
                 console: ConsoleConfig {
                     file: None,
                     mode: ConsoleOutputMode::Tty,
                     iommu: false,
                     socket: None,
+                    tee: Vec::new(),
                 },
                 #[cfg(target_arch = "x86_64")]
                 debug_console: DebugConsoleConfig::default(),
@@ -194,6 +208,16 @@ impl RequestHandler for StubApiRequestHandler {
                 pci_segments: None,
                 platform: None,
                 tpm: None,
+                scmi: None,
+                cloud_init: None,
+                imds: None,
+                netns: None,
+                guest_agent: None,
+                resources: None,
+                rtc: None,
+                restart_policy: None,
+                crash_dump: None,
+                io_threads: None,
                 preserved_fds: None,
                 landlock_enable: false,
                 landlock_rules: None,
@@ -201,6 +225,11 @@ impl RequestHandler for StubApiRequestHandler {
             state: VmState::Running,
             memory_actual_size: 0,
             device_tree: None,
+            last_exit_reason: None,
+            disk_queues: None,
+            balloon: None,
+            #[cfg(target_arch = "x86_64")]
+            sgx_epc: None,
         })
     }
 
@@ -221,7 +250,11 @@ impl RequestHandler for StubApiRequestHandler {
         Ok(())
     }
 
-    fn vm_resize(&mut self, _: Option<u8>, _: Option<u64>, _: Option<u64>) -> Result<(), VmError> {
+    fn vms_request(&mut self, _: &str, request: ApiRequest) -> Result<bool, VmmError> {
+        request(self)
+    }
+
+    fn vm_resize(&mut self, _: Option<u32>, _: Option<u64>, _: Option<u64>) -> Result<(), VmError> {
         Ok(())
     }
 
@@ -241,6 +274,26 @@ impl RequestHandler for StubApiRequestHandler {
         Ok(())
     }
 
+    fn vm_reset_device(&mut self, _: String) -> Result<(), VmError> {
+        Ok(())
+    }
+
+    fn vm_add_rate_limit_group(&mut self, _: RateLimiterGroupConfig) -> Result<(), VmError> {
+        Ok(())
+    }
+
+    fn vm_set_rate_limit_group(&mut self, _: String, _: Option<String>) -> Result<(), VmError> {
+        Ok(())
+    }
+
+    fn vm_set_balloon(&mut self, _: Option<bool>, _: Option<bool>) -> Result<(), VmError> {
+        Ok(())
+    }
+
+    fn vm_resize_disk(&mut self, _: String, _: u64) -> Result<(), VmError> {
+        Ok(())
+    }
+
     fn vm_add_disk(&mut self, _: DiskConfig) -> Result<Option<Vec<u8>>, VmError> {
         Ok(None)
     }
@@ -257,6 +310,10 @@ impl RequestHandler for StubApiRequestHandler {
         Ok(None)
     }
 
+    fn vm_add_pci_segment(&mut self) -> Result<Option<Vec<u8>>, VmError> {
+        Ok(None)
+    }
+
     fn vm_add_vdpa(&mut self, _: VdpaConfig) -> Result<Option<Vec<u8>>, VmError> {
         Ok(None)
     }
@@ -265,10 +322,23 @@ impl RequestHandler for StubApiRequestHandler {
         Ok(None)
     }
 
+    #[cfg(target_arch = "x86_64")]
+    fn vm_add_sgx_epc(&mut self, _: SgxEpcConfig) -> Result<(), VmError> {
+        Ok(())
+    }
+
+    fn vm_console_log(&mut self) -> Result<Option<Vec<u8>>, VmError> {
+        Ok(None)
+    }
+
     fn vm_counters(&mut self) -> Result<Option<Vec<u8>>, VmError> {
         Ok(None)
     }
 
+    fn vm_export_config(&self) -> Result<Option<Vec<u8>>, VmError> {
+        Ok(None)
+    }
+
     fn vm_power_button(&mut self) -> Result<(), VmError> {
         Ok(())
     }
@@ -284,6 +354,22 @@ impl RequestHandler for StubApiRequestHandler {
     fn vm_nmi(&mut self) -> Result<(), VmError> {
         Ok(())
     }
+
+    fn vm_refresh_certificates(&mut self) -> Result<(), VmError> {
+        Ok(())
+    }
+
+    fn vm_guest_command(&mut self, _: VmGuestCommandData) -> Result<Option<Vec<u8>>, VmError> {
+        Ok(None)
+    }
+
+    fn vm_backup(&mut self, _: VmBackupData) -> Result<(), VmError> {
+        Ok(())
+    }
+
+    fn vm_disk_snapshot(&mut self, _: VmDiskSnapshotData) -> Result<(), VmError> {
+        Ok(())
+    }
 }
 
 fn http_receiver_stub(exit_evt: EventFd, api_evt: EventFd, api_receiver: Receiver<ApiRequest>) {
@@ -21,11 +21,18 @@ const CMDLINE_START: GuestAddress = GuestAddress(0x20000);
 fuzz_target!(|bytes: &[u8]| -> Corpus {
     let payload_config = vmm::vm_config::PayloadConfig {
         firmware: None,
+        firmware_fd: None,
         kernel: None,
+        kernel_fd: None,
         cmdline: Some(String::from_utf8_lossy(&bytes).to_string()),
         initramfs: None,
+        initramfs_fd: None,
+        extra_initramfs: None,
         #[cfg(feature = "igvm")]
         igvm: None,
+        #[cfg(feature = "igvm")]
+        igvm_fd: None,
+        fallback_firmware: None,
     };
     let kernel_cmdline = match vmm::vm::Vm::generate_cmdline(&payload_config) {
         Ok(cmdline) => cmdline,
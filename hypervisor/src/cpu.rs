@@ -170,6 +170,11 @@ pub enum HypervisorCpuError {
     #[error("Failed to finalize vcpu")]
     VcpuFinalize(#[source] anyhow::Error),
     ///
+    /// Enabling the PTP service error
+    ///
+    #[error("Failed to enable the PTP service")]
+    EnablePtp(#[source] anyhow::Error),
+    ///
     /// Setting one reg error
     ///
     #[error("Failed to set one reg")]
@@ -336,6 +341,41 @@ pub enum HypervisorCpuError {
     Nmi(#[source] anyhow::Error),
 }
 
+/// Guest accesses triggering a hardware watchpoint
+#[cfg(not(target_arch = "riscv64"))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchpointKind {
+    Write,
+    Read,
+    ReadWrite,
+}
+
+/// Hardware watchpoint on `len` bytes of guest virtual memory
+#[cfg(not(target_arch = "riscv64"))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HwWatchpoint {
+    pub addr: GuestAddress,
+    pub len: u64,
+    pub kind: WatchpointKind,
+}
+
+#[cfg(not(target_arch = "riscv64"))]
+impl HwWatchpoint {
+    pub fn contains(&self, addr: GuestAddress) -> bool {
+        addr.0 >= self.addr.0 && addr.0 < self.addr.0.saturating_add(self.len)
+    }
+}
+
+/// Cause of a debug exit
+#[cfg(feature = "kvm")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugExit {
+    Breakpoint,
+    SingleStep,
+    /// Guest address matching the watchpoint which triggered
+    Watchpoint(GuestAddress),
+}
+
 #[derive(Debug)]
 pub enum VmExit {
     #[cfg(target_arch = "x86_64")]
@@ -343,11 +383,13 @@ pub enum VmExit {
     Ignore,
     Reset,
     Shutdown,
+    #[cfg(target_arch = "x86_64")]
+    TripleFault,
     Hyperv,
     #[cfg(feature = "tdx")]
     Tdx,
     #[cfg(feature = "kvm")]
-    Debug,
+    Debug(DebugExit),
 }
 
 ///
@@ -444,10 +486,16 @@ pub trait Vcpu: Send + Sync {
         Ok(())
     }
     ///
-    /// Sets debug registers to set hardware breakpoints and/or enable single step.
+    /// Sets debug registers to set hardware breakpoints, hardware watchpoints
+    /// and/or enable single step.
     ///
     #[cfg(not(target_arch = "riscv64"))]
-    fn set_guest_debug(&self, _addrs: &[GuestAddress], _singlestep: bool) -> Result<()> {
+    fn set_guest_debug(
+        &self,
+        _addrs: &[GuestAddress],
+        _watchpoints: &[HwWatchpoint],
+        _singlestep: bool,
+    ) -> Result<()> {
         Err(HypervisorCpuError::SetDebugRegs(anyhow!("unimplemented")))
     }
     ///
@@ -459,6 +507,14 @@ pub trait Vcpu: Send + Sync {
     #[cfg(target_arch = "aarch64")]
     fn vcpu_finalize(&self, feature: i32) -> Result<()>;
     ///
+    /// Exposes the PTP service of the hypervisor, which the ptp_kvm driver
+    /// of the guest relies on to cross-timestamp its clock with the host one.
+    ///
+    #[cfg(target_arch = "aarch64")]
+    fn enable_ptp(&self) -> Result<()> {
+        Ok(())
+    }
+    ///
     /// Gets the features that have been finalized for a given CPU.
     ///
     #[cfg(target_arch = "aarch64")]
@@ -471,7 +527,7 @@ pub trait Vcpu: Send + Sync {
         &self,
         vm: &Arc<dyn crate::Vm>,
         kvi: &mut VcpuInit,
-        id: u8,
+        id: u32,
     ) -> Result<()>;
     ///
     /// Returns VcpuInit with default value set
@@ -498,7 +554,7 @@ pub trait Vcpu: Send + Sync {
     /// Configure core registers for a given CPU.
     ///
     #[cfg(any(target_arch = "aarch64", target_arch = "riscv64"))]
-    fn setup_regs(&self, cpu_id: u8, boot_ip: u64, fdt_start: u64) -> Result<()>;
+    fn setup_regs(&self, cpu_id: u32, boot_ip: u64, fdt_start: u64) -> Result<()>;
     ///
     /// Check if the CPU supports PMU
     ///
@@ -11,7 +11,7 @@
 pub mod gic;
 
 use kvm_bindings::{
-    kvm_mp_state, kvm_one_reg, kvm_regs, KVM_REG_ARM_COPROC_MASK, KVM_REG_ARM_CORE,
+    kvm_mp_state, kvm_one_reg, kvm_regs, KVM_REG_ARM64, KVM_REG_ARM_COPROC_MASK, KVM_REG_ARM_CORE,
     KVM_REG_SIZE_MASK, KVM_REG_SIZE_U32, KVM_REG_SIZE_U64,
 };
 pub use kvm_ioctls::{Cap, Kvm};
@@ -81,6 +81,14 @@ macro_rules! arm64_core_reg_id {
     };
 }
 
+// Firmware pseudo-register selecting the vendor specific hypervisor services
+// exposed to the guest, and its bit for the PTP service, as described in
+// Documentation/virt/kvm/arm/fw-pseudo-registers.rst.
+const KVM_REG_ARM_FW_FEAT_BMAP: u64 = 0x0016 << 16;
+pub const KVM_REG_ARM_VENDOR_HYP_BMAP: u64 =
+    KVM_REG_ARM64 as u64 | KVM_REG_SIZE_U64 | KVM_REG_ARM_FW_FEAT_BMAP | 2;
+pub const KVM_REG_ARM_VENDOR_HYP_BIT_PTP: u64 = 1;
+
 /// Specifies whether a particular register is a system register or not.
 ///
 /// The kernel splits the registers on aarch64 in core registers and system registers.
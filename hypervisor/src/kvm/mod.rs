@@ -31,6 +31,8 @@ use crate::aarch64::gic::KvmGicV3Its;
 #[cfg(target_arch = "aarch64")]
 pub use crate::aarch64::{check_required_kvm_extensions, is_system_register, VcpuKvmState};
 #[cfg(target_arch = "aarch64")]
+use crate::aarch64::{KVM_REG_ARM_VENDOR_HYP_BIT_PTP, KVM_REG_ARM_VENDOR_HYP_BMAP};
+#[cfg(target_arch = "aarch64")]
 use crate::arch::aarch64::gic::{Vgic, VgicConfig};
 #[cfg(target_arch = "riscv64")]
 use crate::arch::riscv64::aia::{Vaia, VaiaConfig};
@@ -53,7 +55,8 @@ pub mod x86_64;
 #[cfg(target_arch = "x86_64")]
 use kvm_bindings::{
     kvm_enable_cap, kvm_msr_entry, MsrList, KVM_CAP_HYPERV_SYNIC, KVM_CAP_SPLIT_IRQCHIP,
-    KVM_GUESTDBG_USE_HW_BP,
+    KVM_CAP_X2APIC_API, KVM_EXIT_HYPERV_HCALL, KVM_EXIT_HYPERV_SYNIC, KVM_GUESTDBG_USE_HW_BP,
+    KVM_IRQ_ROUTING_HV_SINT, KVM_X2APIC_API_DISABLE_BROADCAST_QUIRK, KVM_X2APIC_API_USE_32BIT_IDS,
 };
 #[cfg(target_arch = "x86_64")]
 use x86_64::check_required_kvm_extensions;
@@ -559,16 +562,18 @@ impl vm::Vm for KvmVm {
     ///
     fn create_vcpu(
         &self,
-        id: u8,
+        id: u32,
         vm_ops: Option<Arc<dyn VmOps>>,
     ) -> vm::Result<Arc<dyn cpu::Vcpu>> {
         let fd = self
             .fd
-            .create_vcpu(id as u64)
+            .create_vcpu(u64::from(id))
             .map_err(|e| vm::HypervisorVmError::CreateVcpu(e.into()))?;
         let vcpu = KvmVcpu {
             fd: Arc::new(Mutex::new(fd)),
             #[cfg(target_arch = "x86_64")]
+            id,
+            #[cfg(target_arch = "x86_64")]
             msrs: self.msrs.clone(),
             vm_ops,
             #[cfg(target_arch = "x86_64")]
@@ -651,6 +656,18 @@ impl vm::Vm for KvmVm {
                 kvm_route.u.msi.address_hi = cfg.high_addr;
                 kvm_route.u.msi.data = cfg.data;
 
+                // Guests relying on the extended destination ID carry bits
+                // 14:8 of the APIC ID in bits 11:5 of the low address, move
+                // them where KVM expects them with the x2APIC API.
+                #[cfg(target_arch = "x86_64")]
+                {
+                    let ext_dest_id = (cfg.low_addr >> 5) & 0x7f;
+                    if ext_dest_id != 0 && cfg.high_addr == 0 {
+                        kvm_route.u.msi.address_lo = cfg.low_addr & !(0x7f << 5);
+                        kvm_route.u.msi.address_hi = ext_dest_id << 8;
+                    }
+                }
+
                 if self.check_extension(crate::kvm::Cap::MsiDevid) {
                     // On AArch64, there is limitation on the range of the 'devid',
                     // it cannot be greater than 65536 (the max of u16).
@@ -683,6 +700,18 @@ impl vm::Vm for KvmVm {
                 kvm_route.u.irqchip.irqchip = cfg.irqchip;
                 kvm_route.u.irqchip.pin = cfg.pin;
 
+                kvm_route.into()
+            }
+            #[cfg(target_arch = "x86_64")]
+            InterruptSourceConfig::HvSint(cfg) => {
+                let mut kvm_route = kvm_irq_routing_entry {
+                    gsi,
+                    type_: KVM_IRQ_ROUTING_HV_SINT,
+                    ..Default::default()
+                };
+                kvm_route.u.hv_sint.vcpu = cfg.vcpu;
+                kvm_route.u.hv_sint.sint = cfg.sint;
+
                 kvm_route.into()
             }
         }
@@ -832,6 +861,23 @@ impl vm::Vm for KvmVm {
         Ok(())
     }
 
+    #[cfg(target_arch = "x86_64")]
+    fn enable_x2apic_api(&self) -> vm::Result<()> {
+        // Let the local APICs report 32 bits IDs in x2APIC mode, and let MSI
+        // routes carry the upper bits of the destination ID in their high
+        // address.
+        let mut cap = kvm_enable_cap {
+            cap: KVM_CAP_X2APIC_API,
+            ..Default::default()
+        };
+        cap.args[0] =
+            (KVM_X2APIC_API_USE_32BIT_IDS | KVM_X2APIC_API_DISABLE_BROADCAST_QUIRK) as u64;
+        self.fd
+            .enable_cap(&cap)
+            .map_err(|e| vm::HypervisorVmError::EnableX2ApicApi(e.into()))?;
+        Ok(())
+    }
+
     #[cfg(target_arch = "x86_64")]
     fn enable_sgx_attribute(&self, file: File) -> vm::Result<()> {
         let mut cap = kvm_enable_cap {
@@ -937,7 +983,7 @@ impl vm::Vm for KvmVm {
     /// Initialize TDX for this VM
     ///
     #[cfg(feature = "tdx")]
-    fn tdx_init(&self, cpuid: &[CpuIdEntry], max_vcpus: u32) -> vm::Result<()> {
+    fn tdx_init(&self, cpuid: &[CpuIdEntry], max_vcpus: u32, num_l2_vms: u8) -> vm::Result<()> {
         const TDX_ATTR_SEPT_VE_DISABLE: usize = 28;
 
         let mut cpuid: Vec<kvm_bindings::kvm_cpuid_entry2> =
@@ -948,7 +994,10 @@ impl vm::Vm for KvmVm {
         struct TdxInitVm {
             attributes: u64,
             max_vcpus: u32,
-            padding: u32,
+            // Number of L2 VMs the TD can host through TD partitioning,
+            // zero meaning the TD isn't partitioned.
+            num_l2_vms: u8,
+            padding: [u8; 3],
             mrconfigid: [u64; 6],
             mrowner: [u64; 6],
             mrownerconfig: [u64; 6],
@@ -959,7 +1008,8 @@ impl vm::Vm for KvmVm {
         let data = TdxInitVm {
             attributes: 1 << TDX_ATTR_SEPT_VE_DISABLE,
             max_vcpus,
-            padding: 0,
+            num_l2_vms,
+            padding: [0; 3],
             mrconfigid: [0; 6],
             mrowner: [0; 6],
             mrownerconfig: [0; 6],
@@ -1303,6 +1353,22 @@ impl hypervisor::Hypervisor for KvmHypervisor {
         }
     }
 
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    ///
+    /// Get the number of supported hardware watchpoints
+    ///
+    fn get_guest_debug_hw_wps(&self) -> usize {
+        // The four debug address registers are shared with the breakpoints.
+        #[cfg(target_arch = "x86_64")]
+        {
+            4
+        }
+        #[cfg(target_arch = "aarch64")]
+        {
+            self.kvm.get_guest_debug_hw_wps() as usize
+        }
+    }
+
     /// Get maximum number of vCPUs
     fn get_max_vcpus(&self) -> u32 {
         self.kvm.get_max_vcpus().min(u32::MAX as usize) as u32
@@ -1313,6 +1379,8 @@ impl hypervisor::Hypervisor for KvmHypervisor {
 pub struct KvmVcpu {
     fd: Arc<Mutex<VcpuFd>>,
     #[cfg(target_arch = "x86_64")]
+    id: u32,
+    #[cfg(target_arch = "x86_64")]
     msrs: Vec<MsrEntry>,
     vm_ops: Option<Arc<dyn vm::VmOps>>,
     #[cfg(target_arch = "x86_64")]
@@ -1987,7 +2055,8 @@ impl cpu::Vcpu for KvmVcpu {
     /// Triggers the running of the current virtual CPU returning an exit reason.
     ///
     fn run(&self) -> std::result::Result<cpu::VmExit, cpu::HypervisorCpuError> {
-        match self.fd.lock().unwrap().run() {
+        let mut fd = self.fd.lock().unwrap();
+        match fd.run() {
             Ok(run) => match run {
                 #[cfg(target_arch = "x86_64")]
                 VcpuExit::IoIn(addr, data) => {
@@ -2014,7 +2083,9 @@ impl cpu::Vcpu for KvmVcpu {
                 #[cfg(target_arch = "x86_64")]
                 VcpuExit::IoapicEoi(vector) => Ok(cpu::VmExit::IoapicEoi(vector)),
                 #[cfg(target_arch = "x86_64")]
-                VcpuExit::Shutdown | VcpuExit::Hlt => Ok(cpu::VmExit::Reset),
+                VcpuExit::Shutdown => Ok(cpu::VmExit::TripleFault),
+                #[cfg(target_arch = "x86_64")]
+                VcpuExit::Hlt => Ok(cpu::VmExit::Reset),
 
                 #[cfg(target_arch = "aarch64")]
                 VcpuExit::SystemEvent(event_type, flags) => {
@@ -2054,10 +2125,13 @@ impl cpu::Vcpu for KvmVcpu {
 
                     Ok(cpu::VmExit::Ignore)
                 }
+                #[cfg(target_arch = "x86_64")]
+                VcpuExit::Hyperv => self.handle_hyperv_exit(fd.get_kvm_run()),
+                #[cfg(not(target_arch = "x86_64"))]
                 VcpuExit::Hyperv => Ok(cpu::VmExit::Hyperv),
                 #[cfg(feature = "tdx")]
                 VcpuExit::Unsupported(KVM_EXIT_TDX) => Ok(cpu::VmExit::Tdx),
-                VcpuExit::Debug(_) => Ok(cpu::VmExit::Debug),
+                VcpuExit::Debug(debug) => Ok(cpu::VmExit::Debug(Self::debug_exit(&fd, debug))),
 
                 r => Err(cpu::HypervisorCpuError::RunVcpu(anyhow!(
                     "Unexpected exit reason on vcpu run: {:?}",
@@ -2095,11 +2169,13 @@ impl cpu::Vcpu for KvmVcpu {
 
     #[cfg(not(target_arch = "riscv64"))]
     ///
-    /// Sets debug registers to set hardware breakpoints and/or enable single step.
+    /// Sets debug registers to set hardware breakpoints, hardware watchpoints
+    /// and/or enable single step.
     ///
     fn set_guest_debug(
         &self,
         addrs: &[vm_memory::GuestAddress],
+        watchpoints: &[cpu::HwWatchpoint],
         singlestep: bool,
     ) -> cpu::Result<()> {
         let mut dbg = kvm_guest_debug {
@@ -2128,6 +2204,26 @@ impl cpu::Vcpu for KvmVcpu {
                 // Set global breakpoint enable flag
                 dbg.arch.debugreg[7] |= 2 << (i * 2);
             }
+
+            // The watchpoints use the debug registers left by the breakpoints.
+            for (i, watchpoint) in watchpoints.iter().enumerate() {
+                let i = addrs.len() + i;
+                dbg.arch.debugreg[i] = watchpoint.addr.0;
+                // R/W bits: 0b01 on writes, 0b11 on reads or writes, reads
+                // alone can't be watched.
+                let rw = match watchpoint.kind {
+                    cpu::WatchpointKind::Write => 0b01,
+                    cpu::WatchpointKind::Read | cpu::WatchpointKind::ReadWrite => 0b11,
+                };
+                // LEN bits: 0b00, 0b01, 0b11 and 0b10 for 1, 2, 4 and 8 bytes.
+                let len = match watchpoint.len {
+                    1 => 0b00,
+                    2 => 0b01,
+                    8 => 0b10,
+                    _ => 0b11,
+                };
+                dbg.arch.debugreg[7] |= (2 << (i * 2)) | ((rw | (len << 2)) << (16 + i * 4));
+            }
         }
         #[cfg(target_arch = "aarch64")]
         {
@@ -2142,6 +2238,24 @@ impl cpu::Vcpu for KvmVcpu {
                 // bit 2~52: VA[2:52]
                 dbg.arch.dbg_bvr[i] = (!0u64 >> 11) & addr.0;
             }
+
+            for (i, watchpoint) in watchpoints.iter().enumerate() {
+                // DBGWCR_EL1 (Debug Watchpoint Control Registers, D13.3.11):
+                // bit 0: 1 (Enabled)
+                // bit 1~2: 0b11 (PAC = EL1/EL0)
+                // bit 3~4: LSC, 0b01 on loads, 0b10 on stores, 0b11 on both
+                // bit 5~12: BAS, the bytes watched in the doubleword
+                let lsc = match watchpoint.kind {
+                    cpu::WatchpointKind::Read => 0b01u64,
+                    cpu::WatchpointKind::Write => 0b10u64,
+                    cpu::WatchpointKind::ReadWrite => 0b11u64,
+                };
+                let bas = ((1u64 << watchpoint.len) - 1) << (watchpoint.addr.0 & 0x7);
+                dbg.arch.dbg_wcr[i] = 0b1u64 | 0b110u64 | (lsc << 3) | ((bas & 0xff) << 5);
+                // DBGWVR_EL1 (Debug Watchpoint Value Registers, D13.3.12):
+                // bit 3~52: VA[3:52]
+                dbg.arch.dbg_wvr[i] = (!0u64 >> 11) & watchpoint.addr.0 & !0x7;
+            }
         }
         self.fd
             .lock()
@@ -2160,7 +2274,7 @@ impl cpu::Vcpu for KvmVcpu {
         &self,
         vm: &Arc<dyn crate::Vm>,
         kvi: &mut crate::VcpuInit,
-        id: u8,
+        id: u32,
     ) -> cpu::Result<()> {
         use std::arch::is_aarch64_feature_detected;
         #[allow(clippy::nonminimal_bool)]
@@ -2227,6 +2341,26 @@ impl cpu::Vcpu for KvmVcpu {
             .map_err(|e| cpu::HypervisorCpuError::VcpuFinalize(e.into()))
     }
 
+    #[cfg(target_arch = "aarch64")]
+    fn enable_ptp(&self) -> cpu::Result<()> {
+        let fd = self.fd.lock().unwrap();
+        let mut bytes = [0_u8; 8];
+        match fd.get_one_reg(KVM_REG_ARM_VENDOR_HYP_BMAP, &mut bytes) {
+            Ok(_) => {}
+            // Kernels without the firmware pseudo-registers expose all the
+            // hypervisor services.
+            Err(e) if e.errno() == libc::ENOENT => return Ok(()),
+            Err(e) => return Err(cpu::HypervisorCpuError::EnablePtp(e.into())),
+        }
+
+        // The services are shared by all the vCPUs, and can't be changed
+        // once the VM has run, which setting the same value again is not.
+        let bmap = u64::from_le_bytes(bytes) | (1 << KVM_REG_ARM_VENDOR_HYP_BIT_PTP);
+        fd.set_one_reg(KVM_REG_ARM_VENDOR_HYP_BMAP, &bmap.to_le_bytes())
+            .map_err(|e| cpu::HypervisorCpuError::EnablePtp(e.into()))?;
+        Ok(())
+    }
+
     #[cfg(any(target_arch = "aarch64", target_arch = "riscv64"))]
     ///
     /// Gets a list of the guest registers that are supported for the
@@ -2290,7 +2424,7 @@ impl cpu::Vcpu for KvmVcpu {
     /// Configure core registers for a given CPU.
     ///
     #[cfg(target_arch = "aarch64")]
-    fn setup_regs(&self, cpu_id: u8, boot_ip: u64, fdt_start: u64) -> cpu::Result<()> {
+    fn setup_regs(&self, cpu_id: u32, boot_ip: u64, fdt_start: u64) -> cpu::Result<()> {
         let kreg_off = offset_of!(kvm_regs, regs);
 
         // Get the register index of the PSTATE (Processor State) register.
@@ -2338,7 +2472,7 @@ impl cpu::Vcpu for KvmVcpu {
     ///
     /// Configure registers for a given RISC-V CPU.
     ///
-    fn setup_regs(&self, cpu_id: u8, boot_ip: u64, fdt_start: u64) -> cpu::Result<()> {
+    fn setup_regs(&self, cpu_id: u32, boot_ip: u64, fdt_start: u64) -> cpu::Result<()> {
         // Setting the A0 () to the hartid of this CPU.
         let a0 = offset_of!(kvm_riscv_core, regs, user_regs_struct, a0);
         self.fd
@@ -2936,6 +3070,85 @@ impl cpu::Vcpu for KvmVcpu {
 }
 
 impl KvmVcpu {
+    ///
+    /// Decodes the cause of a debug exit from the debug status reported by
+    /// KVM.
+    ///
+    fn debug_exit(fd: &VcpuFd, debug: kvm_bindings::kvm_debug_exit_arch) -> cpu::DebugExit {
+        #[cfg(target_arch = "x86_64")]
+        {
+            // DR6 bit 14 (BS) reports a single step, bits 0~3 the debug
+            // address registers which matched.
+            if debug.dr6 & (1 << 14) != 0 {
+                return cpu::DebugExit::SingleStep;
+            }
+            for i in 0..4 {
+                // Non-zero R/W bits in DR7 make a watchpoint.
+                if debug.dr6 & (1 << i) != 0 && (debug.dr7 >> (16 + i * 4)) & 0b11 != 0 {
+                    return match fd.get_debug_regs() {
+                        Ok(regs) => cpu::DebugExit::Watchpoint(vm_memory::GuestAddress(regs.db[i])),
+                        Err(e) => {
+                            warn!("Failed to get the debug registers: {}", e);
+                            cpu::DebugExit::Breakpoint
+                        }
+                    };
+                }
+            }
+            cpu::DebugExit::Breakpoint
+        }
+        #[cfg(target_arch = "aarch64")]
+        {
+            let _ = fd;
+            // ESR_EL2.EC: 0x32/0x33 for a software step, 0x34/0x35 for a
+            // watchpoint, with the address accessed in FAR_EL2.
+            match debug.hsr >> 26 {
+                0x32 | 0x33 => cpu::DebugExit::SingleStep,
+                0x34 | 0x35 => cpu::DebugExit::Watchpoint(vm_memory::GuestAddress(debug.far)),
+                _ => cpu::DebugExit::Breakpoint,
+            }
+        }
+        #[cfg(target_arch = "riscv64")]
+        {
+            let _ = (fd, debug);
+            cpu::DebugExit::Breakpoint
+        }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    ///
+    /// X86 specific call forwarding the SynIC updates and the Hyper-V
+    /// hypercalls KVM doesn't handle to the VMM.
+    ///
+    fn handle_hyperv_exit(
+        &self,
+        kvm_run: &mut kvm_run,
+    ) -> std::result::Result<cpu::VmExit, cpu::HypervisorCpuError> {
+        let Some(vm_ops) = &self.vm_ops else {
+            return Ok(cpu::VmExit::Hyperv);
+        };
+
+        // SAFETY: accessing a union field in a valid structure, KVM having
+        // exited with KVM_EXIT_HYPERV
+        let hyperv = unsafe { &mut kvm_run.__bindgen_anon_1.hyperv };
+        match hyperv.type_ {
+            KVM_EXIT_HYPERV_SYNIC => {
+                // SAFETY: the exit type tells which union field is valid
+                let synic = unsafe { hyperv.u.synic };
+                vm_ops
+                    .hyperv_synic(self.id, synic.control, synic.evt_page, synic.msg_page)
+                    .map_err(|e| cpu::HypervisorCpuError::RunVcpu(e.into()))?;
+            }
+            KVM_EXIT_HYPERV_HCALL => {
+                // SAFETY: the exit type tells which union field is valid
+                let hcall = unsafe { &mut hyperv.u.hcall };
+                hcall.result = vm_ops.hyperv_hcall(self.id, hcall.input, hcall.params);
+            }
+            t => debug!("Unhandled Hyper-V exit type {}", t),
+        }
+
+        Ok(cpu::VmExit::Hyperv)
+    }
+
     #[cfg(target_arch = "x86_64")]
     ///
     /// X86 specific call that returns the vcpu's current "xsave struct".
@@ -3067,4 +3280,25 @@ mod tests {
         vcpu0.set_regs(&core_regs).unwrap();
         assert_eq!(vcpu0.get_regs().unwrap(), core_regs);
     }
+
+    #[test]
+    #[cfg(target_arch = "aarch64")]
+    fn test_enable_ptp() {
+        use super::*;
+
+        // As defined by the kernel.
+        assert_eq!(KVM_REG_ARM_VENDOR_HYP_BMAP, 0x6030_0000_0016_0002);
+
+        let kvm = KvmHypervisor::new().unwrap();
+        let hypervisor = Arc::new(kvm);
+        let vm = hypervisor.create_vm().expect("new VM fd creation failed");
+        let vcpu = vm.create_vcpu(0, None).unwrap();
+        let mut kvi = vcpu.create_vcpu_init();
+        vm.get_preferred_target(&mut kvi).unwrap();
+        vcpu.vcpu_init(&kvi).unwrap();
+
+        // Enabling the service again must not fail.
+        vcpu.enable_ptp().unwrap();
+        vcpu.enable_ptp().unwrap();
+    }
 }
@@ -56,12 +56,20 @@ use std::sync::Arc;
 use concat_idents::concat_idents;
 #[cfg(target_arch = "x86_64")]
 pub use cpu::CpuVendor;
+#[cfg(feature = "kvm")]
+pub use cpu::DebugExit;
+#[cfg(not(target_arch = "riscv64"))]
+pub use cpu::{HwWatchpoint, WatchpointKind};
 pub use cpu::{HypervisorCpuError, Vcpu, VmExit};
 pub use device::HypervisorDeviceError;
 #[cfg(all(feature = "kvm", target_arch = "aarch64"))]
 pub use kvm::aarch64;
 #[cfg(all(feature = "kvm", target_arch = "riscv64"))]
 pub use kvm::{riscv64, AiaState};
+#[cfg(target_arch = "x86_64")]
+pub use vm::HvSintSourceConfig;
+#[cfg(feature = "sev_snp")]
+pub use vm::SevSnpPolicy;
 pub use vm::{
     DataMatch, HypervisorVmError, InterruptSourceConfig, LegacyIrqSourceConfig, MsiIrqSourceConfig,
     Vm, VmOps,
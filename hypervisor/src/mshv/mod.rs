@@ -7,6 +7,8 @@ use std::any::Any;
 use std::collections::HashMap;
 #[cfg(feature = "sev_snp")]
 use std::num::NonZeroUsize;
+#[cfg(feature = "sev_snp")]
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, RwLock};
 
 #[cfg(feature = "sev_snp")]
@@ -314,6 +316,10 @@ impl MshvHypervisor {
                 msrs[pos].index = *index;
             }
 
+            #[cfg(feature = "sev_snp")]
+            // SAFETY: access union fields
+            let snp_policy = unsafe { snp::get_default_snp_guest_policy().as_uint64 };
+
             Ok(Arc::new(MshvVm {
                 fd: vm_fd,
                 msrs,
@@ -321,6 +327,10 @@ impl MshvHypervisor {
                 #[cfg(feature = "sev_snp")]
                 sev_snp_enabled: mshv_vm_type == VmType::Snp,
                 #[cfg(feature = "sev_snp")]
+                snp_policy: AtomicU64::new(snp_policy),
+                #[cfg(feature = "sev_snp")]
+                snp_certs: Arc::new(ArcSwap::from_pointee(Vec::new())),
+                #[cfg(feature = "sev_snp")]
                 host_access_pages: ArcSwap::new(
                     AtomicBitmap::new(
                         _mem_size.unwrap_or_default() as usize,
@@ -455,6 +465,10 @@ impl hypervisor::Hypervisor for MshvHypervisor {
         0
     }
 
+    fn get_guest_debug_hw_wps(&self) -> usize {
+        0
+    }
+
     #[cfg(target_arch = "aarch64")]
     ///
     /// Retrieve AArch64 host maximum IPA size supported by MSHV.
@@ -502,6 +516,8 @@ pub struct MshvVcpu {
     ghcb: Option<Ghcb>,
     #[cfg(feature = "sev_snp")]
     host_access_pages: ArcSwap<AtomicBitmap>,
+    #[cfg(feature = "sev_snp")]
+    snp_certs: Arc<ArcSwap<Vec<u8>>>,
 }
 
 /// Implementation of Vcpu trait for Microsoft Hypervisor
@@ -1168,21 +1184,36 @@ impl cpu::Vcpu for MshvVcpu {
                                 SVM_EXITCODE_SNP_GUEST_REQUEST
                                 | SVM_EXITCODE_SNP_EXTENDED_GUEST_REQUEST => {
                                     if exit_code == SVM_EXITCODE_SNP_EXTENDED_GUEST_REQUEST {
-                                        info!("Fetching extended guest request is not supported");
-                                        // We don't support extended guest request, so we just write empty data.
-                                        // This matches the behavior of KVM in Linux 6.11.
-
-                                        // Read RBX from the GHCB.
                                         // SAFETY: Accessing data from a mapped address
                                         let data_gpa = unsafe { (*ghcb).rax };
                                         // SAFETY: Accessing data from a mapped address
                                         let data_npages = unsafe { (*ghcb).rbx };
 
-                                        if data_npages > 0 {
-                                            // The certificates are terminated by 24 zero bytes.
-                                            // TODO: Need to check if data_gpa is the address of the shared buffer in the GHCB page
-                                            // in that case we should clear the shared buffer(24 bytes)
-                                            self.gpa_write(data_gpa, &[0; 24])?;
+                                        let certs = self.snp_certs.load();
+                                        if certs.is_empty() {
+                                            // Without a certificate table we write empty data.
+                                            // This matches the behavior of KVM in Linux 6.11.
+                                            if data_npages > 0 {
+                                                // The certificates are terminated by 24 zero bytes.
+                                                // TODO: Need to check if data_gpa is the address of the shared buffer in the GHCB page
+                                                // in that case we should clear the shared buffer(24 bytes)
+                                                self.gpa_write(data_gpa, &[0; 24])?;
+                                            }
+                                        } else {
+                                            let certs_npages =
+                                                (certs.len() as u64).div_ceil(HV_PAGE_SIZE as u64);
+                                            if data_npages < certs_npages {
+                                                // Let the guest retry with a large enough buffer,
+                                                // the PSP request is not issued in that case.
+                                                set_svm_field_u64_ptr!(ghcb, rbx, certs_npages);
+                                                set_svm_field_u64_ptr!(
+                                                    ghcb,
+                                                    exit_info2,
+                                                    SNP_GUEST_VMM_ERR_INVALID_LEN
+                                                );
+                                                return Ok(cpu::VmExit::Ignore);
+                                            }
+                                            self.gpa_write(data_gpa, &certs)?;
                                         }
                                     }
 
@@ -1262,7 +1293,7 @@ impl cpu::Vcpu for MshvVcpu {
     }
 
     #[cfg(target_arch = "aarch64")]
-    fn setup_regs(&self, cpu_id: u8, boot_ip: u64, fdt_start: u64) -> cpu::Result<()> {
+    fn setup_regs(&self, cpu_id: u32, boot_ip: u64, fdt_start: u64) -> cpu::Result<()> {
         let arr_reg_name_value = [(
             hv_register_name_HV_ARM64_REGISTER_PSTATE,
             regs::PSTATE_FAULT_BITS_64,
@@ -1324,7 +1355,7 @@ impl cpu::Vcpu for MshvVcpu {
         &self,
         _vm: &Arc<dyn crate::Vm>,
         _kvi: &mut crate::VcpuInit,
-        _id: u8,
+        _id: u32,
     ) -> cpu::Result<()> {
         Ok(())
     }
@@ -1749,6 +1780,10 @@ pub struct MshvVm {
     #[cfg(feature = "sev_snp")]
     sev_snp_enabled: bool,
     #[cfg(feature = "sev_snp")]
+    snp_policy: AtomicU64,
+    #[cfg(feature = "sev_snp")]
+    snp_certs: Arc<ArcSwap<Vec<u8>>>,
+    #[cfg(feature = "sev_snp")]
     host_access_pages: ArcSwap<AtomicBitmap>,
 }
 
@@ -1834,12 +1869,14 @@ impl vm::Vm for MshvVm {
     ///
     fn create_vcpu(
         &self,
-        id: u8,
+        id: u32,
         vm_ops: Option<Arc<dyn VmOps>>,
     ) -> vm::Result<Arc<dyn cpu::Vcpu>> {
+        // The MSHV API limits the VP index to 8 bits.
+        let vp_index = u8::try_from(id).map_err(|e| vm::HypervisorVmError::CreateVcpu(e.into()))?;
         let vcpu_fd = self
             .fd
-            .create_vcpu(id)
+            .create_vcpu(vp_index)
             .map_err(|e| vm::HypervisorVmError::CreateVcpu(e.into()))?;
 
         /* Map the GHCB page to the VMM(root) address space
@@ -1871,7 +1908,7 @@ impl vm::Vm for MshvVm {
         };
         let vcpu = MshvVcpu {
             fd: vcpu_fd,
-            vp_index: id,
+            vp_index,
             #[cfg(target_arch = "x86_64")]
             cpuid: Vec::new(),
             #[cfg(target_arch = "x86_64")]
@@ -1882,6 +1919,8 @@ impl vm::Vm for MshvVm {
             ghcb,
             #[cfg(feature = "sev_snp")]
             host_access_pages: ArcSwap::new(self.host_access_pages.load().clone()),
+            #[cfg(feature = "sev_snp")]
+            snp_certs: self.snp_certs.clone(),
         };
         Ok(Arc::new(vcpu))
     }
@@ -1891,6 +1930,11 @@ impl vm::Vm for MshvVm {
         Ok(())
     }
 
+    #[cfg(target_arch = "x86_64")]
+    fn enable_x2apic_api(&self) -> vm::Result<()> {
+        Ok(())
+    }
+
     #[cfg(target_arch = "x86_64")]
     fn enable_sgx_attribute(&self, _file: File) -> vm::Result<()> {
         Ok(())
@@ -2146,6 +2190,32 @@ impl vm::Vm for MshvVm {
         self
     }
 
+    /// Set the guest policy of the SEV-SNP VM
+    #[cfg(feature = "sev_snp")]
+    fn sev_snp_set_policy(&self, policy: vm::SevSnpPolicy) -> vm::Result<()> {
+        let mut snp_policy = self.snp_policy.load(Ordering::Acquire);
+        for (bit, enabled) in [
+            (SNP_POLICY_SMT, policy.smt),
+            (SNP_POLICY_DEBUG, policy.debug),
+            (SNP_POLICY_MIGRATE_MA, policy.migrate_ma),
+        ] {
+            if enabled {
+                snp_policy |= bit;
+            } else {
+                snp_policy &= !bit;
+            }
+        }
+        self.snp_policy.store(snp_policy, Ordering::Release);
+        Ok(())
+    }
+
+    /// Set the certificate table returned to extended guest requests
+    #[cfg(feature = "sev_snp")]
+    fn sev_snp_set_certificates(&self, certs: Vec<u8>) -> vm::Result<()> {
+        self.snp_certs.store(Arc::new(certs));
+        Ok(())
+    }
+
     /// Initialize the SEV-SNP VM
     #[cfg(feature = "sev_snp")]
     fn sev_snp_init(&self) -> vm::Result<()> {
@@ -2216,6 +2286,21 @@ impl vm::Vm for MshvVm {
             .copy_from_slice(snp_id_block.id_public_key.qx.as_ref());
         auth_info.id_key[ECDSA_SIG_Y_COMPONENT_START..ECDSA_SIG_Y_COMPONENT_END]
             .copy_from_slice(snp_id_block.id_public_key.qy.as_ref());
+        // The author key signs the ID key, binding the launch to the author
+        // identity rather than a single ID key.
+        if snp_id_block.author_key_enabled != 0 {
+            auth_info.id_key_signature[..SIG_R_COMPONENT_SIZE_IN_BYTES]
+                .copy_from_slice(snp_id_block.author_key_signature.r_comp.as_ref());
+            auth_info.id_key_signature
+                [SIG_R_COMPONENT_SIZE_IN_BYTES..SIG_R_AND_S_COMPONENT_SIZE_IN_BYTES]
+                .copy_from_slice(snp_id_block.author_key_signature.s_comp.as_ref());
+            auth_info.author_key[..ECDSA_CURVE_ID_SIZE_IN_BYTES]
+                .copy_from_slice(snp_id_block.author_public_key.curve.to_le_bytes().as_ref());
+            auth_info.author_key[ECDSA_SIG_X_COMPONENT_START..ECDSA_SIG_X_COMPONENT_END]
+                .copy_from_slice(snp_id_block.author_public_key.qx.as_ref());
+            auth_info.author_key[ECDSA_SIG_Y_COMPONENT_START..ECDSA_SIG_Y_COMPONENT_END]
+                .copy_from_slice(snp_id_block.author_public_key.qy.as_ref());
+        }
 
         let data = mshv_complete_isolated_import {
             import_data: hv_partition_complete_isolated_import_data {
@@ -2226,12 +2311,14 @@ impl vm::Vm for MshvVm {
                         image_id: snp_id_block.image_id,
                         version: snp_id_block.version,
                         guest_svn: snp_id_block.guest_svn,
-                        policy: get_default_snp_guest_policy(),
+                        policy: hv_snp_guest_policy {
+                            as_uint64: self.snp_policy.load(Ordering::Acquire),
+                        },
                     },
                     id_auth_info: auth_info,
                     host_data,
                     id_block_enabled,
-                    author_key_enabled: 0,
+                    author_key_enabled: snp_id_block.author_key_enabled,
                 },
             },
         };
@@ -2419,18 +2506,18 @@ impl vm::Vm for MshvVm {
         // Set additional partition property for SEV-SNP partition.
         #[cfg(feature = "sev_snp")]
         if self.sev_snp_enabled {
-            let snp_policy = snp::get_default_snp_guest_policy();
+            let snp_policy = self.snp_policy.load(Ordering::Acquire);
             let vmgexit_offloads = snp::get_default_vmgexit_offload_features();
             // SAFETY: access union fields
             unsafe {
                 debug!(
                     "Setting the partition isolation policy as: 0x{:x}",
-                    snp_policy.as_uint64
+                    snp_policy
                 );
                 self.fd
                     .set_partition_property(
                         hv_partition_property_code_HV_PARTITION_PROPERTY_ISOLATION_POLICY,
-                        snp_policy.as_uint64,
+                        snp_policy,
                     )
                     .map_err(|e| vm::HypervisorVmError::InitializeVm(e.into()))?;
                 debug!(
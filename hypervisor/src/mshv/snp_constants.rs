@@ -16,3 +16,12 @@ pub const ECDSA_SIG_X_COMPONENT_END: usize =
 pub const ECDSA_SIG_Y_COMPONENT_START: usize = ECDSA_SIG_X_COMPONENT_END;
 pub const ECDSA_SIG_Y_COMPONENT_END: usize =
     ECDSA_SIG_X_COMPONENT_END + ECDSA_SIG_Y_COMPONENT_SIZE_IN_BYTES;
+
+// Guest policy bits, see Chapter 4.3
+pub const SNP_POLICY_SMT: u64 = 1 << 16;
+pub const SNP_POLICY_MIGRATE_MA: u64 = 1 << 18;
+pub const SNP_POLICY_DEBUG: u64 = 1 << 19;
+
+// Extended guest request error reported in SW_EXITINFO2, see the GHCB
+// specification (56421) Chapter 4.1.8
+pub const SNP_GUEST_VMM_ERR_INVALID_LEN: u64 = 1 << 32;
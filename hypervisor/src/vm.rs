@@ -126,6 +126,11 @@ pub enum HypervisorVmError {
     #[error("Failed to enable split Irq")]
     EnableSplitIrq(#[source] anyhow::Error),
     ///
+    /// Enable x2APIC API error
+    ///
+    #[error("Failed to enable x2APIC API")]
+    EnableX2ApicApi(#[source] anyhow::Error),
+    ///
     /// Enable SGX attribute error
     ///
     #[error("Failed to enable SGX attribute")]
@@ -290,6 +295,45 @@ pub struct MsiIrqSourceConfig {
     pub devid: u32,
 }
 
+/// Guest policy bits of an SEV-SNP VM which can be tuned by the user.
+///
+/// The remaining bits (ABI version, reserved fields) are left to the
+/// hypervisor defaults.
+#[cfg(feature = "sev_snp")]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SevSnpPolicy {
+    /// Allow the guest to run with SMT enabled on the host.
+    pub smt: bool,
+    /// Allow the guest to be debugged through the PSP debug commands.
+    pub debug: bool,
+    /// Allow the guest to be associated with a migration agent.
+    pub migrate_ma: bool,
+}
+
+#[cfg(feature = "sev_snp")]
+impl Default for SevSnpPolicy {
+    fn default() -> Self {
+        SevSnpPolicy {
+            smt: true,
+            debug: false,
+            migrate_ma: false,
+        }
+    }
+}
+
+/// Configuration data for Hyper-V synthetic interrupts.
+///
+/// These interrupts are delivered through the synthetic interrupt controller
+/// (SynIC) of a vCPU, on one of its synthetic interrupt sources (SINT).
+#[cfg(target_arch = "x86_64")]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct HvSintSourceConfig {
+    /// Index of the vCPU the interrupt is delivered to.
+    pub vcpu: u32,
+    /// Synthetic interrupt source of the vCPU.
+    pub sint: u32,
+}
+
 /// Configuration data for an interrupt source.
 #[derive(Copy, Clone, Debug)]
 pub enum InterruptSourceConfig {
@@ -297,6 +341,9 @@ pub enum InterruptSourceConfig {
     LegacyIrq(LegacyIrqSourceConfig),
     /// Configuration data for PciMsi, PciMsix and generic MSI interrupts.
     MsiIrq(MsiIrqSourceConfig),
+    /// Configuration data for Hyper-V synthetic interrupts.
+    #[cfg(target_arch = "x86_64")]
+    HvSint(HvSintSourceConfig),
 }
 
 ///
@@ -319,7 +366,7 @@ pub trait Vm: Send + Sync + Any {
     /// Unregister an event that will, when signaled, trigger the `gsi` IRQ.
     fn unregister_irqfd(&self, fd: &EventFd, gsi: u32) -> Result<()>;
     /// Creates a new KVM vCPU file descriptor and maps the memory corresponding
-    fn create_vcpu(&self, id: u8, vm_ops: Option<Arc<dyn VmOps>>) -> Result<Arc<dyn Vcpu>>;
+    fn create_vcpu(&self, id: u32, vm_ops: Option<Arc<dyn VmOps>>) -> Result<Arc<dyn Vcpu>>;
     #[cfg(target_arch = "aarch64")]
     fn create_vgic(&self, config: VgicConfig) -> Result<Arc<Mutex<dyn Vgic>>>;
     #[cfg(target_arch = "riscv64")]
@@ -358,6 +405,9 @@ pub trait Vm: Send + Sync + Any {
     /// Enable split Irq capability
     #[cfg(target_arch = "x86_64")]
     fn enable_split_irq(&self) -> Result<()>;
+    /// Enable 32 bits APIC IDs for the vCPUs and MSI routes
+    #[cfg(target_arch = "x86_64")]
+    fn enable_x2apic_api(&self) -> Result<()>;
     #[cfg(target_arch = "x86_64")]
     fn enable_sgx_attribute(&self, file: File) -> Result<()>;
     /// Retrieve guest clock.
@@ -375,13 +425,23 @@ pub trait Vm: Send + Sync + Any {
     /// Get dirty pages bitmap
     fn get_dirty_log(&self, slot: u32, base_gpa: u64, memory_size: u64) -> Result<Vec<u64>>;
     #[cfg(feature = "sev_snp")]
+    /// Set the SEV-SNP guest policy, before the VM is initialized
+    fn sev_snp_set_policy(&self, _policy: SevSnpPolicy) -> Result<()> {
+        unimplemented!()
+    }
+    #[cfg(feature = "sev_snp")]
+    /// Set the certificate table returned to SEV-SNP extended guest requests
+    fn sev_snp_set_certificates(&self, _certs: Vec<u8>) -> Result<()> {
+        unimplemented!()
+    }
+    #[cfg(feature = "sev_snp")]
     /// Initialize SEV-SNP on this VM
     fn sev_snp_init(&self) -> Result<()> {
         unimplemented!()
     }
     #[cfg(feature = "tdx")]
-    /// Initialize TDX on this VM
-    fn tdx_init(&self, _cpuid: &[CpuIdEntry], _max_vcpus: u32) -> Result<()> {
+    /// Initialize TDX on this VM, reserving room for L2 VMs if partitioned
+    fn tdx_init(&self, _cpuid: &[CpuIdEntry], _max_vcpus: u32, _num_l2_vms: u8) -> Result<()> {
         unimplemented!()
     }
     #[cfg(feature = "tdx")]
@@ -451,4 +511,12 @@ pub trait VmOps: Send + Sync {
     fn pio_read(&self, port: u64, data: &mut [u8]) -> Result<()>;
     #[cfg(target_arch = "x86_64")]
     fn pio_write(&self, port: u64, data: &[u8]) -> Result<()>;
+    /// Let the VMM know about the SynIC configuration of a vCPU, after the
+    /// guest updated it.
+    #[cfg(target_arch = "x86_64")]
+    fn hyperv_synic(&self, vcpu_id: u32, control: u64, evt_page: u64, msg_page: u64) -> Result<()>;
+    /// Handle a Hyper-V hypercall the hypervisor forwarded to the VMM,
+    /// returning its status.
+    #[cfg(target_arch = "x86_64")]
+    fn hyperv_hcall(&self, vcpu_id: u32, input: u64, params: [u64; 2]) -> u64;
 }
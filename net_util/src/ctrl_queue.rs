@@ -15,7 +15,7 @@ use virtio_bindings::virtio_net::{
 };
 use virtio_queue::{Queue, QueueT};
 use vm_memory::{ByteValued, Bytes, GuestMemoryError};
-use vm_virtio::{AccessPlatform, Translatable};
+use vm_virtio::{AccessPlatform, Coalescing, Translatable};
 
 use crate::{GuestMemoryMmap, Tap};
 
@@ -46,6 +46,22 @@ pub enum Error {
 
 type Result<T> = std::result::Result<T, Error>;
 
+// Notification coalescing definitions from the VIRTIO specification.
+pub const VIRTIO_NET_F_NOTF_COAL: u32 = 53;
+const VIRTIO_NET_CTRL_NOTF_COAL: u32 = 6;
+const VIRTIO_NET_CTRL_NOTF_COAL_TX_SET: u32 = 0;
+const VIRTIO_NET_CTRL_NOTF_COAL_RX_SET: u32 = 1;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+struct CoalescingParams {
+    max_packets: u32,
+    usecs: u32,
+}
+
+// SAFETY: CoalescingParams only contains a series of integers
+unsafe impl ByteValued for CoalescingParams {}
+
 #[repr(C, packed)]
 #[derive(Debug, Clone, Copy, Default)]
 pub struct ControlHeader {
@@ -58,11 +74,19 @@ unsafe impl ByteValued for ControlHeader {}
 
 pub struct CtrlQueue {
     pub taps: Vec<Tap>,
+    // Notification coalescing parameters of the RX and TX queues, which the
+    // driver can only change once VIRTIO_NET_F_NOTF_COAL is negotiated.
+    pub rx_coalescing: Option<Arc<Coalescing>>,
+    pub tx_coalescing: Option<Arc<Coalescing>>,
 }
 
 impl CtrlQueue {
     pub fn new(taps: Vec<Tap>) -> Self {
-        CtrlQueue { taps }
+        CtrlQueue {
+            taps,
+            rx_coalescing: None,
+            tx_coalescing: None,
+        }
     }
 
     pub fn process(
@@ -131,6 +155,26 @@ impl CtrlQueue {
                         ok
                     }
                 }
+                VIRTIO_NET_CTRL_NOTF_COAL => {
+                    let params = desc_chain
+                        .memory()
+                        .read_obj::<CoalescingParams>(data_desc_addr)
+                        .map_err(Error::GuestMemory)?;
+                    let coalescing = match u32::from(ctrl_hdr.cmd) {
+                        VIRTIO_NET_CTRL_NOTF_COAL_TX_SET => self.tx_coalescing.as_ref(),
+                        VIRTIO_NET_CTRL_NOTF_COAL_RX_SET => self.rx_coalescing.as_ref(),
+                        _ => None,
+                    };
+                    if let Some(coalescing) = coalescing {
+                        info!("Notification coalescing requested: {:?}", params);
+                        coalescing
+                            .set(u32::from_le(params.usecs), u32::from_le(params.max_packets));
+                        true
+                    } else {
+                        warn!("Unsupported command: {}", ctrl_hdr.cmd);
+                        false
+                    }
+                }
                 _ => {
                     warn!("Unsupported command {:?}", ctrl_hdr);
                     false
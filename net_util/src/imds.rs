@@ -0,0 +1,702 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Emulation of an instance metadata service (IMDS).
+//!
+//! Frames sent by the guest to 169.254.169.254 are intercepted before
+//! reaching the TAP device, and answered by a minimal TCP/HTTP responder
+//! serving a JSON document. Objects are listed the way cloud metadata
+//! services do, one key per line, so that cloud-init and similar agents can
+//! crawl the document. The virtual link being lossless, the responder never
+//! retransmits nor waits for acknowledgements.
+
+use std::collections::{HashMap, VecDeque};
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+
+use serde_json::Value;
+
+pub const IMDS_IPV4_ADDR: Ipv4Addr = Ipv4Addr::new(169, 254, 169, 254);
+const IMDS_PORT: u16 = 80;
+// Locally administered address used when answering ARP requests.
+const IMDS_MAC: [u8; 6] = [0x06, 0x00, 0xa9, 0xfe, 0xa9, 0xfe];
+
+const ETH_HDR_LEN: usize = 14;
+const ETH_TYPE_IPV4: u16 = 0x0800;
+const ETH_TYPE_ARP: u16 = 0x0806;
+const ARP_LEN: usize = 28;
+const ARP_OP_REQUEST: u16 = 1;
+const ARP_OP_REPLY: u16 = 2;
+const IPV4_HDR_LEN: usize = 20;
+const IP_PROTO_TCP: u8 = 6;
+const TCP_HDR_LEN: usize = 20;
+const TCP_OPT_MSS: u8 = 2;
+
+const TCP_FIN: u8 = 0x01;
+const TCP_SYN: u8 = 0x02;
+const TCP_RST: u8 = 0x04;
+const TCP_PSH: u8 = 0x08;
+const TCP_ACK: u8 = 0x10;
+
+const DEFAULT_MSS: u16 = 536;
+const MAX_MSS: u16 = 1460;
+// Metadata requests are small, anything bigger is rejected.
+const MAX_REQUEST_LEN: usize = 8192;
+// Bound the memory used by connections the guest never closes.
+const MAX_CONNECTIONS: usize = 64;
+// IMDSv2 session tokens are accepted but never checked.
+const TOKEN_PATH: &str = "/latest/api/token";
+const TOKEN: &str = "cloud-hypervisor";
+
+struct Connection {
+    guest_mac: [u8; 6],
+    local_mac: [u8; 6],
+    // Next sequence number expected from the guest
+    rcv_nxt: u32,
+    // Next sequence number sent to the guest
+    snd_nxt: u32,
+    mss: u16,
+    request: Vec<u8>,
+    responded: bool,
+    fin_received: bool,
+}
+
+// Fields of a TCP segment received from the guest.
+struct Segment<'a> {
+    guest_mac: [u8; 6],
+    local_mac: [u8; 6],
+    guest_ip: Ipv4Addr,
+    guest_port: u16,
+    local_port: u16,
+    seq: u32,
+    ack: u32,
+    flags: u8,
+    options: &'a [u8],
+    payload: &'a [u8],
+}
+
+pub struct Imds {
+    document: Arc<Value>,
+    connections: HashMap<(Ipv4Addr, u16), Connection>,
+    frames: VecDeque<Vec<u8>>,
+    next_isn: u32,
+}
+
+impl Imds {
+    pub fn new(document: Arc<Value>) -> Self {
+        let mut isn = [0u8; 4];
+        if let Err(e) = getrandom::fill(&mut isn) {
+            warn!("Error generating IMDS initial sequence number: {}", e);
+        }
+
+        Imds {
+            document,
+            connections: HashMap::new(),
+            frames: VecDeque::new(),
+            next_isn: u32::from_ne_bytes(isn),
+        }
+    }
+
+    /// Handle an ethernet frame sent by the guest, returning whether it was
+    /// addressed to the metadata service and shouldn't reach the TAP device.
+    pub fn handle_frame(&mut self, frame: &[u8]) -> bool {
+        if frame.len() < ETH_HDR_LEN {
+            return false;
+        }
+
+        match u16::from_be_bytes([frame[12], frame[13]]) {
+            ETH_TYPE_ARP => self.handle_arp(frame),
+            ETH_TYPE_IPV4 => self.handle_ipv4(frame),
+            _ => false,
+        }
+    }
+
+    /// Whether some frames are waiting to be received by the guest.
+    pub fn has_frames(&self) -> bool {
+        !self.frames.is_empty()
+    }
+
+    /// Next frame to be received by the guest.
+    pub fn pop_frame(&mut self) -> Option<Vec<u8>> {
+        self.frames.pop_front()
+    }
+
+    fn handle_arp(&mut self, frame: &[u8]) -> bool {
+        let arp = &frame[ETH_HDR_LEN..];
+        if arp.len() < ARP_LEN || arp[24..28] != IMDS_IPV4_ADDR.octets() {
+            return false;
+        }
+
+        if u16::from_be_bytes([arp[6], arp[7]]) == ARP_OP_REQUEST {
+            let mut reply = Vec::with_capacity(ETH_HDR_LEN + ARP_LEN);
+            reply.extend_from_slice(&arp[8..14]);
+            reply.extend_from_slice(&IMDS_MAC);
+            reply.extend_from_slice(&ETH_TYPE_ARP.to_be_bytes());
+            // Same hardware and protocol types and lengths as the request
+            reply.extend_from_slice(&arp[0..6]);
+            reply.extend_from_slice(&ARP_OP_REPLY.to_be_bytes());
+            reply.extend_from_slice(&IMDS_MAC);
+            reply.extend_from_slice(&IMDS_IPV4_ADDR.octets());
+            reply.extend_from_slice(&arp[8..18]);
+            self.frames.push_back(reply);
+        }
+
+        true
+    }
+
+    fn handle_ipv4(&mut self, frame: &[u8]) -> bool {
+        let ip = &frame[ETH_HDR_LEN..];
+        if ip.len() < IPV4_HDR_LEN || ip[0] >> 4 != 4 || ip[16..20] != IMDS_IPV4_ADDR.octets() {
+            return false;
+        }
+
+        // Anything else than unfragmented TCP is silently dropped.
+        let hdr_len = usize::from(ip[0] & 0xf) * 4;
+        let total_len = usize::from(u16::from_be_bytes([ip[2], ip[3]])).min(ip.len());
+        let fragmented = u16::from_be_bytes([ip[6], ip[7]]) & 0x3fff != 0;
+        if ip[9] != IP_PROTO_TCP || fragmented || hdr_len < IPV4_HDR_LEN || total_len < hdr_len {
+            return true;
+        }
+
+        let tcp = &ip[hdr_len..total_len];
+        if tcp.len() < TCP_HDR_LEN {
+            return true;
+        }
+        let data_offset = usize::from(tcp[12] >> 4) * 4;
+        if !(TCP_HDR_LEN..=tcp.len()).contains(&data_offset) {
+            return true;
+        }
+
+        let mut guest_mac = [0u8; 6];
+        guest_mac.copy_from_slice(&frame[6..12]);
+        let mut local_mac = [0u8; 6];
+        local_mac.copy_from_slice(&frame[0..6]);
+        self.handle_tcp(&Segment {
+            guest_mac,
+            local_mac,
+            guest_ip: Ipv4Addr::new(ip[12], ip[13], ip[14], ip[15]),
+            guest_port: u16::from_be_bytes([tcp[0], tcp[1]]),
+            local_port: u16::from_be_bytes([tcp[2], tcp[3]]),
+            seq: u32::from_be_bytes([tcp[4], tcp[5], tcp[6], tcp[7]]),
+            ack: u32::from_be_bytes([tcp[8], tcp[9], tcp[10], tcp[11]]),
+            flags: tcp[13],
+            options: &tcp[TCP_HDR_LEN..data_offset],
+            payload: &tcp[data_offset..],
+        });
+
+        true
+    }
+
+    fn handle_tcp(&mut self, segment: &Segment) {
+        let key = (segment.guest_ip, segment.guest_port);
+
+        if segment.flags & TCP_RST != 0 {
+            self.connections.remove(&key);
+            return;
+        }
+
+        if segment.local_port != IMDS_PORT {
+            self.reset(segment);
+            return;
+        }
+
+        if segment.flags & TCP_SYN != 0 {
+            if self.connections.len() >= MAX_CONNECTIONS && !self.connections.contains_key(&key) {
+                self.reset(segment);
+                return;
+            }
+
+            let isn = self.next_isn;
+            self.next_isn = self.next_isn.wrapping_add(64000);
+            let conn = Connection {
+                guest_mac: segment.guest_mac,
+                local_mac: segment.local_mac,
+                rcv_nxt: segment.seq.wrapping_add(1),
+                snd_nxt: isn.wrapping_add(1),
+                mss: parse_mss(segment.options)
+                    .unwrap_or(DEFAULT_MSS)
+                    .min(MAX_MSS),
+                request: Vec::new(),
+                responded: false,
+                fin_received: false,
+            };
+            let mut options = vec![TCP_OPT_MSS, 4];
+            options.extend_from_slice(&MAX_MSS.to_be_bytes());
+            let frame = tcp_frame(
+                &conn,
+                segment.guest_ip,
+                segment.guest_port,
+                isn,
+                TCP_SYN | TCP_ACK,
+                &options,
+                &[],
+            );
+            self.frames.push_back(frame);
+            self.connections.insert(key, conn);
+            return;
+        }
+
+        let Some(conn) = self.connections.get_mut(&key) else {
+            // Late acknowledgements of closed connections are ignored.
+            if !segment.payload.is_empty() || segment.flags & TCP_FIN != 0 {
+                self.reset(segment);
+            }
+            return;
+        };
+
+        let mut needs_ack = false;
+        if !segment.payload.is_empty() {
+            // Out of order segments are dropped, and the expected sequence
+            // number acknowledged again.
+            if segment.seq == conn.rcv_nxt && !conn.responded {
+                conn.request.extend_from_slice(segment.payload);
+                conn.rcv_nxt = conn.rcv_nxt.wrapping_add(segment.payload.len() as u32);
+            }
+            needs_ack = true;
+        }
+        if segment.flags & TCP_FIN != 0 {
+            let fin_seq = segment.seq.wrapping_add(segment.payload.len() as u32);
+            if fin_seq == conn.rcv_nxt {
+                conn.rcv_nxt = conn.rcv_nxt.wrapping_add(1);
+                conn.fin_received = true;
+            }
+            needs_ack = true;
+        }
+
+        let request_complete = conn.request.windows(4).any(|w| w == b"\r\n\r\n");
+        if !conn.responded
+            && (request_complete || conn.fin_received || conn.request.len() > MAX_REQUEST_LEN)
+        {
+            let response = build_response(&self.document, &conn.request);
+            let chunks: Vec<&[u8]> = response.chunks(usize::from(conn.mss)).collect();
+            for (i, chunk) in chunks.iter().enumerate() {
+                let mut flags = TCP_ACK | TCP_PSH;
+                if i + 1 == chunks.len() {
+                    flags |= TCP_FIN;
+                }
+                let frame = tcp_frame(
+                    conn,
+                    segment.guest_ip,
+                    segment.guest_port,
+                    conn.snd_nxt,
+                    flags,
+                    &[],
+                    chunk,
+                );
+                self.frames.push_back(frame);
+                conn.snd_nxt = conn.snd_nxt.wrapping_add(chunk.len() as u32);
+            }
+            // Account for the FIN
+            conn.snd_nxt = conn.snd_nxt.wrapping_add(1);
+            conn.responded = true;
+        } else if needs_ack {
+            let frame = tcp_frame(
+                conn,
+                segment.guest_ip,
+                segment.guest_port,
+                conn.snd_nxt,
+                TCP_ACK,
+                &[],
+                &[],
+            );
+            self.frames.push_back(frame);
+        }
+
+        if conn.responded && conn.fin_received {
+            self.connections.remove(&key);
+        }
+    }
+
+    fn reset(&mut self, segment: &Segment) {
+        let conn = Connection {
+            guest_mac: segment.guest_mac,
+            local_mac: segment.local_mac,
+            rcv_nxt: segment
+                .seq
+                .wrapping_add(segment.payload.len() as u32)
+                .wrapping_add(u32::from(segment.flags & (TCP_SYN | TCP_FIN) != 0)),
+            snd_nxt: 0,
+            mss: DEFAULT_MSS,
+            request: Vec::new(),
+            responded: false,
+            fin_received: false,
+        };
+        let (seq, flags) = if segment.flags & TCP_ACK != 0 {
+            (segment.ack, TCP_RST)
+        } else {
+            (0, TCP_RST | TCP_ACK)
+        };
+        let mut frame = tcp_frame(
+            &conn,
+            segment.guest_ip,
+            segment.guest_port,
+            seq,
+            flags,
+            &[],
+            &[],
+        );
+        // Reply from the port the guest tried to reach.
+        frame[ETH_HDR_LEN + IPV4_HDR_LEN..ETH_HDR_LEN + IPV4_HDR_LEN + 2]
+            .copy_from_slice(&segment.local_port.to_be_bytes());
+        update_tcp_checksum(&mut frame, segment.guest_ip);
+        self.frames.push_back(frame);
+    }
+}
+
+fn parse_mss(mut options: &[u8]) -> Option<u16> {
+    while let Some(&kind) = options.first() {
+        match kind {
+            // End of options
+            0 => break,
+            // No operation
+            1 => options = &options[1..],
+            _ => {
+                let len = usize::from(*options.get(1)?);
+                if !(2..=options.len()).contains(&len) {
+                    break;
+                }
+                if kind == TCP_OPT_MSS && len == 4 {
+                    return Some(u16::from_be_bytes([options[2], options[3]]));
+                }
+                options = &options[len..];
+            }
+        }
+    }
+
+    None
+}
+
+fn checksum(data: &[u8], initial: u32) -> u16 {
+    let mut sum = data.chunks(2).fold(initial, |sum, chunk| {
+        sum + u32::from(u16::from_be_bytes([chunk[0], *chunk.get(1).unwrap_or(&0)]))
+    });
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+fn update_tcp_checksum(frame: &mut [u8], guest_ip: Ipv4Addr) {
+    let tcp_start = ETH_HDR_LEN + IPV4_HDR_LEN;
+    frame[tcp_start + 16..tcp_start + 18].fill(0);
+
+    let tcp_len = frame.len() - tcp_start;
+    let mut pseudo_header = Vec::with_capacity(12);
+    pseudo_header.extend_from_slice(&IMDS_IPV4_ADDR.octets());
+    pseudo_header.extend_from_slice(&guest_ip.octets());
+    pseudo_header.extend_from_slice(&[0, IP_PROTO_TCP]);
+    pseudo_header.extend_from_slice(&(tcp_len as u16).to_be_bytes());
+    let initial = u32::from(!checksum(&pseudo_header, 0));
+    let sum = checksum(&frame[tcp_start..], initial);
+    frame[tcp_start + 16..tcp_start + 18].copy_from_slice(&sum.to_be_bytes());
+}
+
+fn tcp_frame(
+    conn: &Connection,
+    guest_ip: Ipv4Addr,
+    guest_port: u16,
+    seq: u32,
+    flags: u8,
+    options: &[u8],
+    payload: &[u8],
+) -> Vec<u8> {
+    let tcp_hdr_len = TCP_HDR_LEN + options.len();
+    let ip_len = IPV4_HDR_LEN + tcp_hdr_len + payload.len();
+    let mut frame = Vec::with_capacity(ETH_HDR_LEN + ip_len);
+
+    // Ethernet header
+    frame.extend_from_slice(&conn.guest_mac);
+    frame.extend_from_slice(&conn.local_mac);
+    frame.extend_from_slice(&ETH_TYPE_IPV4.to_be_bytes());
+
+    // IPv4 header, with the don't fragment flag set
+    frame.extend_from_slice(&[0x45, 0]);
+    frame.extend_from_slice(&(ip_len as u16).to_be_bytes());
+    frame.extend_from_slice(&[0, 0, 0x40, 0, 64, IP_PROTO_TCP, 0, 0]);
+    frame.extend_from_slice(&IMDS_IPV4_ADDR.octets());
+    frame.extend_from_slice(&guest_ip.octets());
+    let sum = checksum(&frame[ETH_HDR_LEN..], 0);
+    frame[ETH_HDR_LEN + 10..ETH_HDR_LEN + 12].copy_from_slice(&sum.to_be_bytes());
+
+    // TCP header
+    frame.extend_from_slice(&IMDS_PORT.to_be_bytes());
+    frame.extend_from_slice(&guest_port.to_be_bytes());
+    frame.extend_from_slice(&seq.to_be_bytes());
+    frame.extend_from_slice(&conn.rcv_nxt.to_be_bytes());
+    frame.extend_from_slice(&[((tcp_hdr_len / 4) as u8) << 4, flags]);
+    frame.extend_from_slice(&u16::MAX.to_be_bytes());
+    // Checksum and urgent pointer
+    frame.extend_from_slice(&[0, 0, 0, 0]);
+    frame.extend_from_slice(options);
+    frame.extend_from_slice(payload);
+    update_tcp_checksum(&mut frame, guest_ip);
+
+    frame
+}
+
+// Resolve a path against the document, objects and arrays being listed
+// one entry per line, with a trailing slash for nested ones.
+fn lookup(document: &Value, path: &str) -> Option<String> {
+    let mut value = document;
+    for segment in path.split('/').filter(|s| !s.is_empty()) {
+        value = match value {
+            Value::Object(map) => map.get(segment)?,
+            Value::Array(array) => array.get(segment.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+
+    let entry = |name: String, value: &Value| {
+        if value.is_object() || value.is_array() {
+            format!("{name}/")
+        } else {
+            name
+        }
+    };
+    Some(match value {
+        Value::String(s) => s.clone(),
+        Value::Object(map) => map
+            .iter()
+            .map(|(k, v)| entry(k.clone(), v))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Value::Array(array) => array
+            .iter()
+            .enumerate()
+            .map(|(i, v)| entry(i.to_string(), v))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        value => value.to_string(),
+    })
+}
+
+fn build_response(document: &Value, request: &[u8]) -> Vec<u8> {
+    let request = String::from_utf8_lossy(request);
+    let mut request_line = request.lines().next().unwrap_or_default().split(' ');
+    let method = request_line.next().unwrap_or_default();
+    let path = request_line
+        .next()
+        .unwrap_or_default()
+        .split('?')
+        .next()
+        .unwrap_or_default();
+
+    let (status, body) = if method == "PUT" && path == TOKEN_PATH {
+        ("200 OK", TOKEN.to_string())
+    } else if method != "GET" {
+        ("405 Method Not Allowed", String::new())
+    } else if !path.starts_with('/') {
+        ("400 Bad Request", String::new())
+    } else if let Some(body) = lookup(document, path) {
+        ("200 OK", body)
+    } else {
+        ("404 Not Found", String::new())
+    };
+
+    format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\n\
+        Connection: close\r\n\r\n{body}",
+        body.len()
+    )
+    .into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    const GUEST_MAC: [u8; 6] = [0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc];
+    const GATEWAY_MAC: [u8; 6] = [0x12, 0x34, 0x56, 0x78, 0x9a, 0xbd];
+    const GUEST_IP: Ipv4Addr = Ipv4Addr::new(192, 168, 249, 2);
+    const GUEST_PORT: u16 = 40000;
+
+    fn guest_segment(seq: u32, ack: u32, flags: u8, options: &[u8], payload: &[u8]) -> Vec<u8> {
+        let conn = Connection {
+            guest_mac: GATEWAY_MAC,
+            local_mac: GUEST_MAC,
+            rcv_nxt: ack,
+            snd_nxt: 0,
+            mss: DEFAULT_MSS,
+            request: Vec::new(),
+            responded: false,
+            fin_received: false,
+        };
+        // Build the segment as if it was sent by the service, then swap the
+        // addresses and ports.
+        let mut frame = tcp_frame(&conn, GUEST_IP, IMDS_PORT, seq, flags, options, payload);
+        frame[ETH_HDR_LEN + 12..ETH_HDR_LEN + 16].copy_from_slice(&GUEST_IP.octets());
+        frame[ETH_HDR_LEN + 16..ETH_HDR_LEN + 20].copy_from_slice(&IMDS_IPV4_ADDR.octets());
+        frame[ETH_HDR_LEN + IPV4_HDR_LEN..ETH_HDR_LEN + IPV4_HDR_LEN + 2]
+            .copy_from_slice(&GUEST_PORT.to_be_bytes());
+        frame
+    }
+
+    fn tcp_fields(frame: &[u8]) -> (u32, u32, u8, &[u8]) {
+        let tcp = &frame[ETH_HDR_LEN + IPV4_HDR_LEN..];
+        let data_offset = usize::from(tcp[12] >> 4) * 4;
+        (
+            u32::from_be_bytes([tcp[4], tcp[5], tcp[6], tcp[7]]),
+            u32::from_be_bytes([tcp[8], tcp[9], tcp[10], tcp[11]]),
+            tcp[13],
+            &tcp[data_offset..],
+        )
+    }
+
+    fn verify_checksums(frame: &[u8]) {
+        let ip = &frame[ETH_HDR_LEN..];
+        assert_eq!(checksum(&ip[..IPV4_HDR_LEN], 0), 0);
+        let mut pseudo_header = Vec::new();
+        pseudo_header.extend_from_slice(&ip[12..20]);
+        pseudo_header.extend_from_slice(&[0, IP_PROTO_TCP]);
+        pseudo_header.extend_from_slice(&((ip.len() - IPV4_HDR_LEN) as u16).to_be_bytes());
+        let initial = u32::from(!checksum(&pseudo_header, 0));
+        assert_eq!(checksum(&ip[IPV4_HDR_LEN..], initial), 0);
+    }
+
+    #[test]
+    fn test_imds_lookup() {
+        let document = json!({
+            "latest": {
+                "meta-data": {
+                    "instance-id": "i-1234",
+                    "public-keys": ["ssh-ed25519 AAAA"],
+                    "count": 2,
+                },
+            },
+        });
+
+        assert_eq!(lookup(&document, "/").unwrap(), "latest/");
+        assert_eq!(
+            lookup(&document, "/latest/meta-data/").unwrap(),
+            "count\ninstance-id\npublic-keys/"
+        );
+        assert_eq!(
+            lookup(&document, "/latest/meta-data/instance-id").unwrap(),
+            "i-1234"
+        );
+        assert_eq!(
+            lookup(&document, "/latest/meta-data/public-keys/0").unwrap(),
+            "ssh-ed25519 AAAA"
+        );
+        assert_eq!(lookup(&document, "/latest/meta-data/count").unwrap(), "2");
+        assert!(lookup(&document, "/latest/user-data").is_none());
+        assert!(lookup(&document, "/latest/meta-data/public-keys/1").is_none());
+    }
+
+    #[test]
+    fn test_imds_arp() {
+        let mut imds = Imds::new(Arc::new(json!({})));
+
+        let mut request = Vec::new();
+        request.extend_from_slice(&[0xff; 6]);
+        request.extend_from_slice(&GUEST_MAC);
+        request.extend_from_slice(&ETH_TYPE_ARP.to_be_bytes());
+        request.extend_from_slice(&[0, 1, 8, 0, 6, 4, 0, 1]);
+        request.extend_from_slice(&GUEST_MAC);
+        request.extend_from_slice(&GUEST_IP.octets());
+        request.extend_from_slice(&[0; 6]);
+        request.extend_from_slice(&IMDS_IPV4_ADDR.octets());
+        assert!(imds.handle_frame(&request));
+
+        let reply = imds.pop_frame().unwrap();
+        assert_eq!(&reply[0..6], &GUEST_MAC);
+        assert_eq!(&reply[20..22], &ARP_OP_REPLY.to_be_bytes());
+        assert_eq!(&reply[22..28], &IMDS_MAC);
+        assert_eq!(&reply[28..32], &IMDS_IPV4_ADDR.octets());
+        assert_eq!(&reply[32..38], &GUEST_MAC);
+        assert_eq!(&reply[38..42], &GUEST_IP.octets());
+        assert!(!imds.has_frames());
+
+        // Requests for other addresses reach the TAP device
+        request[38..42].copy_from_slice(&GUEST_IP.octets());
+        assert!(!imds.handle_frame(&request));
+        assert!(!imds.has_frames());
+    }
+
+    #[test]
+    fn test_imds_http() {
+        let document = json!({ "latest": { "meta-data": { "instance-id": "i-1234" } } });
+        let mut imds = Imds::new(Arc::new(document));
+
+        // Handshake, the guest advertising a small MSS
+        let syn = guest_segment(1000, 0, TCP_SYN, &[TCP_OPT_MSS, 4, 0, 64], &[]);
+        assert!(imds.handle_frame(&syn));
+        let syn_ack = imds.pop_frame().unwrap();
+        verify_checksums(&syn_ack);
+        assert_eq!(&syn_ack[0..6], &GUEST_MAC);
+        assert_eq!(&syn_ack[6..12], &GATEWAY_MAC);
+        let (isn, ack, flags, _) = tcp_fields(&syn_ack);
+        assert_eq!(ack, 1001);
+        assert_eq!(flags, TCP_SYN | TCP_ACK);
+        assert!(imds.handle_frame(&guest_segment(1001, isn.wrapping_add(1), TCP_ACK, &[], &[])));
+        assert!(!imds.has_frames());
+
+        // Request split in two segments
+        let request =
+            b"GET /latest/meta-data/instance-id HTTP/1.1\r\nHost: 169.254.169.254\r\n\r\n";
+        let (first, second) = request.split_at(20);
+        assert!(imds.handle_frame(&guest_segment(
+            1001,
+            isn.wrapping_add(1),
+            TCP_ACK,
+            &[],
+            first
+        )));
+        let (_, ack, flags, _) = tcp_fields(&imds.pop_frame().unwrap());
+        assert_eq!(ack, 1021);
+        assert_eq!(flags, TCP_ACK);
+        assert!(imds.handle_frame(&guest_segment(
+            1021,
+            isn.wrapping_add(1),
+            TCP_ACK,
+            &[],
+            second
+        )));
+
+        // Response split according to the MSS, the last segment carrying the FIN
+        let mut response = Vec::new();
+        let mut next_seq = isn.wrapping_add(1);
+        while let Some(frame) = imds.pop_frame() {
+            verify_checksums(&frame);
+            let (seq, ack, flags, payload) = tcp_fields(&frame);
+            assert_eq!(seq, next_seq);
+            assert_eq!(ack, 1001 + request.len() as u32);
+            assert!(payload.len() <= 64);
+            response.extend_from_slice(payload);
+            next_seq = next_seq.wrapping_add(payload.len() as u32);
+            assert_eq!(flags & TCP_FIN != 0, !imds.has_frames());
+        }
+        let response = String::from_utf8(response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\ni-1234"));
+
+        // The guest closes the connection
+        let seq = 1001 + request.len() as u32;
+        assert!(imds.handle_frame(&guest_segment(
+            seq,
+            next_seq.wrapping_add(1),
+            TCP_ACK | TCP_FIN,
+            &[],
+            &[]
+        )));
+        let (_, ack, flags, _) = tcp_fields(&imds.pop_frame().unwrap());
+        assert_eq!(ack, seq + 1);
+        assert_eq!(flags, TCP_ACK);
+        assert!(imds.connections.is_empty());
+
+        // Segments for unknown connections are reset
+        assert!(imds.handle_frame(&guest_segment(
+            seq,
+            next_seq.wrapping_add(1),
+            TCP_ACK,
+            &[],
+            b"GET"
+        )));
+        let (_, _, flags, _) = tcp_fields(&imds.pop_frame().unwrap());
+        assert_eq!(flags, TCP_RST);
+    }
+}
@@ -9,10 +9,17 @@
 extern crate log;
 
 mod ctrl_queue;
+mod imds;
 mod mac;
+pub mod macvtap;
+mod netlink;
+pub mod netns;
 mod open_tap;
 mod queue_pair;
+pub mod sriov;
 mod tap;
+#[cfg(feature = "io_uring")]
+mod tap_uring;
 
 use std::io::Error as IoError;
 use std::net::IpAddr;
@@ -32,11 +39,16 @@ use vm_memory::ByteValued;
 
 type GuestMemoryMmap = vm_memory::GuestMemoryMmap<AtomicBitmap>;
 
-pub use ctrl_queue::{CtrlQueue, Error as CtrlQueueError};
+pub use ctrl_queue::{CtrlQueue, Error as CtrlQueueError, VIRTIO_NET_F_NOTF_COAL};
+pub use imds::{Imds, IMDS_IPV4_ADDR};
 pub use mac::{MacAddr, MAC_ADDR_LEN};
 pub use open_tap::{open_tap, Error as OpenTapError};
-pub use queue_pair::{NetCounters, NetQueuePair, NetQueuePairError, RxVirtio, TxVirtio};
+pub use queue_pair::{
+    NetCounters, NetQueuePair, NetQueuePairError, NetRateLimiter, RxVirtio, TxVirtio,
+};
 pub use tap::{Error as TapError, Tap};
+#[cfg(feature = "io_uring")]
+pub use tap_uring::{tap_io_uring_is_supported, TapUring};
 
 #[derive(Error, Debug)]
 pub enum Error {
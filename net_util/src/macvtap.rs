@@ -0,0 +1,233 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Macvtap interface backing a virtio-net device.
+//!
+//! A macvtap interface is stacked on a host network interface, its link, and
+//! exposes its queues through the `/dev/tap<ifindex>` character device, each
+//! open of the device adding a queue. An existing interface is used as is,
+//! while an interface created here is removed once dropped.
+
+use std::fs::OpenOptions;
+use std::io;
+use std::os::fd::IntoRawFd;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::netlink::{
+    self, LinkMessage, Netlink, IFF_UP, IFLA_IFNAME, IFLA_INFO_DATA, IFLA_INFO_KIND, IFLA_LINKINFO,
+    NLM_F_CREATE, NLM_F_EXCL,
+};
+use crate::{MacAddr, Tap, TapError};
+
+const SYSFS_NET: &str = "/sys/class/net";
+
+// Netlink definitions from linux/if_link.h.
+const IFLA_ADDRESS: u16 = 1;
+const IFLA_LINK: u16 = 5;
+const IFLA_MACVLAN_MODE: u16 = 1;
+
+/// How a macvtap interface exchanges frames with the other interfaces
+/// stacked on the same link.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum MacvtapMode {
+    /// Frames between interfaces of the link are switched locally.
+    #[default]
+    Bridge,
+    /// Frames between interfaces of the link go through the external
+    /// switch.
+    Vepa,
+    /// Interfaces of the link can't reach each other.
+    Private,
+    /// The interface takes over the link, being the only one stacked on it.
+    Passthru,
+}
+
+impl MacvtapMode {
+    // MACVLAN_MODE_* value from linux/if_link.h.
+    fn value(self) -> u32 {
+        match self {
+            MacvtapMode::Private => 1,
+            MacvtapMode::Vepa => 2,
+            MacvtapMode::Bridge => 4,
+            MacvtapMode::Passthru => 8,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum MacvtapModeParseError {
+    #[error("Invalid macvtap mode: {0}")]
+    InvalidValue(String),
+}
+
+impl FromStr for MacvtapMode {
+    type Err = MacvtapModeParseError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "bridge" => Ok(MacvtapMode::Bridge),
+            "vepa" => Ok(MacvtapMode::Vepa),
+            "private" => Ok(MacvtapMode::Private),
+            "passthru" => Ok(MacvtapMode::Passthru),
+            _ => Err(MacvtapModeParseError::InvalidValue(s.to_owned())),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Cannot find the interface {0}")]
+    InterfaceIndex(String, #[source] io::Error),
+    #[error("Macvtap interface {0} doesn't exist and has no link to be created on")]
+    MissingLink(String),
+    #[error("Netlink request on the macvtap interface {0} failed")]
+    Netlink(String, #[source] io::Error),
+    #[error("Cannot open a queue of the macvtap interface {0}")]
+    OpenQueue(String, #[source] io::Error),
+    #[error("Cannot configure a queue of the macvtap interface {0}")]
+    ConfigureQueue(String, #[source] TapError),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+// Request creating the macvtap interface `name` on the interface `link`.
+fn create_message(name: &str, link: i32, mode: MacvtapMode, mac: &MacAddr) -> LinkMessage {
+    let mut message = LinkMessage::new(NLM_F_CREATE | NLM_F_EXCL, 0, IFF_UP);
+    message
+        .name(IFLA_IFNAME, name)
+        .attr(IFLA_LINK, &link.to_ne_bytes())
+        .attr(IFLA_ADDRESS, mac.get_bytes())
+        .begin(IFLA_LINKINFO)
+        .name(IFLA_INFO_KIND, "macvtap")
+        .begin(IFLA_INFO_DATA)
+        .attr(IFLA_MACVLAN_MODE, &mode.value().to_ne_bytes())
+        .end()
+        .end();
+    message
+}
+
+/// Macvtap interface, removed when dropped if it was created.
+pub struct Macvtap {
+    name: String,
+    index: i32,
+    // Whether the interface was created here, and must then be removed
+    // once no longer needed.
+    created: bool,
+}
+
+impl Macvtap {
+    /// Open the macvtap interface `name`, creating it on `link` with the
+    /// address `mac` if it doesn't exist. The interface is brought up.
+    pub fn open_or_create(
+        name: &str,
+        link: Option<&str>,
+        mode: MacvtapMode,
+        mac: &MacAddr,
+    ) -> Result<Self> {
+        let netlink = Netlink::open().map_err(|e| Error::Netlink(name.to_string(), e))?;
+        let if_index = |if_name: &str| {
+            netlink::if_index(if_name).map_err(|e| Error::InterfaceIndex(if_name.to_string(), e))
+        };
+
+        let created = !Path::new(SYSFS_NET).join(name).exists();
+        if created {
+            let link = link.ok_or_else(|| Error::MissingLink(name.to_string()))?;
+            info!("Creating macvtap interface {} on {}", name, link);
+            netlink
+                .request(&mut create_message(name, if_index(link)?, mode, mac))
+                .map_err(|e| Error::Netlink(name.to_string(), e))?;
+        }
+
+        let macvtap = Macvtap {
+            name: name.to_string(),
+            index: if_index(name)?,
+            created,
+        };
+        if !created {
+            netlink
+                .request(&mut LinkMessage::new(0, macvtap.index, IFF_UP))
+                .map_err(|e| Error::Netlink(name.to_string(), e))?;
+        }
+
+        Ok(macvtap)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Open `num_queue_pairs` queues of the interface, with the virtio-net
+    /// header size expected by the device.
+    pub fn open_queues(&self, num_queue_pairs: usize) -> Result<Vec<Tap>> {
+        let path = format!("/dev/tap{}", self.index);
+        (0..num_queue_pairs)
+            .map(|_| {
+                let file = OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .custom_flags(libc::O_CLOEXEC)
+                    .open(&path)
+                    .map_err(|e| Error::OpenQueue(self.name.clone(), e))?;
+                Tap::from_tap_fd(file.into_raw_fd(), num_queue_pairs)
+                    .map_err(|e| Error::ConfigureQueue(self.name.clone(), e))
+            })
+            .collect()
+    }
+}
+
+impl Drop for Macvtap {
+    fn drop(&mut self) {
+        if !self.created {
+            return;
+        }
+
+        if let Err(e) = Netlink::open()
+            .and_then(|netlink| netlink.request(&mut LinkMessage::delete(self.index)))
+        {
+            warn!("Cannot remove the macvtap interface {}: {}", self.name, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_message() {
+        let mac = MacAddr::parse_str("12:34:56:78:9a:bc").unwrap();
+        let mut message = create_message("mvtap0", 2, MacvtapMode::Vepa, &mac);
+        let buf = message.finish();
+
+        // Header, ifinfomsg, name, link, address, and nested kind and mode
+        assert_eq!(buf.len(), 16 + 16 + 12 + 8 + 12 + 4 + 12 + 4 + 8);
+        assert_eq!(&buf[36..43], b"mvtap0\0");
+        assert_eq!(&buf[46..48], &IFLA_LINK.to_ne_bytes());
+        assert_eq!(&buf[48..52], &2i32.to_ne_bytes());
+        assert_eq!(&buf[54..56], &IFLA_ADDRESS.to_ne_bytes());
+        assert_eq!(&buf[56..62], mac.get_bytes());
+        assert_eq!(&buf[72..80], b"macvtap\0");
+        assert_eq!(&buf[86..88], &IFLA_MACVLAN_MODE.to_ne_bytes());
+        assert_eq!(&buf[88..92], &2u32.to_ne_bytes());
+    }
+
+    #[test]
+    fn test_macvtap_mode_parsing() {
+        assert_eq!(
+            "bridge".parse::<MacvtapMode>().unwrap(),
+            MacvtapMode::Bridge
+        );
+        assert_eq!(
+            "passthru".parse::<MacvtapMode>().unwrap(),
+            MacvtapMode::Passthru
+        );
+        "source".parse::<MacvtapMode>().unwrap_err();
+    }
+}
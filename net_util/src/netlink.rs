@@ -0,0 +1,255 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Minimal route netlink client, sending link requests to the kernel.
+
+use std::ffi::CString;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+// Netlink definitions from linux/netlink.h, linux/rtnetlink.h and
+// linux/if_link.h.
+const NLMSG_HDRLEN: usize = 16;
+const NLMSG_ERROR: u16 = 0x2;
+const NLM_F_REQUEST: u16 = 0x1;
+const NLM_F_ACK: u16 = 0x4;
+pub(crate) const NLM_F_EXCL: u16 = 0x200;
+pub(crate) const NLM_F_CREATE: u16 = 0x400;
+const RTM_NEWLINK: u16 = 16;
+const RTM_DELLINK: u16 = 17;
+pub(crate) const IFLA_IFNAME: u16 = 3;
+pub(crate) const IFLA_MASTER: u16 = 10;
+pub(crate) const IFLA_LINKINFO: u16 = 18;
+pub(crate) const IFLA_NET_NS_FD: u16 = 28;
+pub(crate) const IFLA_INFO_KIND: u16 = 1;
+pub(crate) const IFLA_INFO_DATA: u16 = 2;
+pub(crate) const IFF_UP: u32 = 0x1;
+
+pub(crate) fn if_index(if_name: &str) -> io::Result<i32> {
+    let name = CString::new(if_name)?;
+    // SAFETY: FFI call with a valid null terminated string.
+    let index = unsafe { libc::if_nametoindex(name.as_ptr()) };
+    if index == 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(index as i32)
+}
+
+// Netlink message describing a link, made of the header, an ifinfomsg and
+// the attributes.
+pub(crate) struct LinkMessage {
+    buf: Vec<u8>,
+    nests: Vec<usize>,
+}
+
+impl LinkMessage {
+    pub(crate) fn new(flags: u16, index: i32, if_flags: u32) -> Self {
+        Self::with_type(RTM_NEWLINK, flags, index, if_flags)
+    }
+
+    pub(crate) fn delete(index: i32) -> Self {
+        Self::with_type(RTM_DELLINK, 0, index, 0)
+    }
+
+    fn with_type(message_type: u16, flags: u16, index: i32, if_flags: u32) -> Self {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&0u32.to_ne_bytes());
+        buf.extend_from_slice(&message_type.to_ne_bytes());
+        buf.extend_from_slice(&(NLM_F_REQUEST | NLM_F_ACK | flags).to_ne_bytes());
+        buf.extend_from_slice(&1u32.to_ne_bytes());
+        buf.extend_from_slice(&0u32.to_ne_bytes());
+        let mut message = LinkMessage {
+            buf,
+            nests: Vec::new(),
+        };
+        message.ifinfo(index, if_flags);
+        message
+    }
+
+    pub(crate) fn ifinfo(&mut self, index: i32, if_flags: u32) {
+        // AF_UNSPEC family, padding and ARPHRD_NETROM type
+        self.buf.extend_from_slice(&[0u8; 4]);
+        self.buf.extend_from_slice(&index.to_ne_bytes());
+        self.buf.extend_from_slice(&if_flags.to_ne_bytes());
+        self.buf.extend_from_slice(&if_flags.to_ne_bytes());
+    }
+
+    fn align(&mut self) {
+        self.buf.resize(self.buf.len().next_multiple_of(4), 0);
+    }
+
+    pub(crate) fn attr(&mut self, kind: u16, data: &[u8]) -> &mut Self {
+        self.buf
+            .extend_from_slice(&((4 + data.len()) as u16).to_ne_bytes());
+        self.buf.extend_from_slice(&kind.to_ne_bytes());
+        self.buf.extend_from_slice(data);
+        self.align();
+        self
+    }
+
+    pub(crate) fn name(&mut self, kind: u16, name: &str) -> &mut Self {
+        let mut data = name.as_bytes().to_vec();
+        data.push(0);
+        self.attr(kind, &data)
+    }
+
+    pub(crate) fn begin(&mut self, kind: u16) -> &mut Self {
+        self.nests.push(self.buf.len());
+        self.attr(kind, &[])
+    }
+
+    pub(crate) fn end(&mut self) -> &mut Self {
+        let start = self.nests.pop().unwrap();
+        let len = (self.buf.len() - start) as u16;
+        self.buf[start..start + 2].copy_from_slice(&len.to_ne_bytes());
+        self
+    }
+
+    pub(crate) fn finish(&mut self) -> &[u8] {
+        let len = self.buf.len() as u32;
+        self.buf[..4].copy_from_slice(&len.to_ne_bytes());
+        &self.buf
+    }
+}
+
+// Route netlink socket of the namespace of the calling thread.
+pub(crate) struct Netlink(OwnedFd);
+
+impl Netlink {
+    pub(crate) fn open() -> io::Result<Self> {
+        // SAFETY: FFI call with valid arguments.
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+                libc::NETLINK_ROUTE,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // SAFETY: 'fd' is a valid socket we own.
+        let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        // SAFETY: sockaddr_nl is plain data, all zeroes addressing the
+        // kernel.
+        let mut addr: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as u16;
+        // SAFETY: FFI call with a valid address.
+        let ret = unsafe {
+            libc::connect(
+                socket.as_raw_fd(),
+                &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+                std::mem::size_of::<libc::sockaddr_nl>() as u32,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Netlink(socket))
+    }
+
+    // Send a request to the kernel and wait for its acknowledgement.
+    pub(crate) fn request(&self, message: &mut LinkMessage) -> io::Result<()> {
+        let message = message.finish();
+        // SAFETY: FFI call with a valid buffer.
+        let ret = unsafe {
+            libc::send(
+                self.0.as_raw_fd(),
+                message.as_ptr() as *const libc::c_void,
+                message.len(),
+                0,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut reply = [0u8; 1024];
+        // SAFETY: FFI call with a valid buffer.
+        let len = unsafe {
+            libc::recv(
+                self.0.as_raw_fd(),
+                reply.as_mut_ptr() as *mut libc::c_void,
+                reply.len(),
+                0,
+            )
+        };
+        if len < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        parse_ack(&reply[..len as usize])
+    }
+}
+
+// Check the reply to a request is an acknowledgement, i.e. an error message
+// with no error.
+fn parse_ack(reply: &[u8]) -> io::Result<()> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "Invalid netlink reply");
+    if reply.len() < NLMSG_HDRLEN + 4 {
+        return Err(invalid());
+    }
+
+    let kind = u16::from_ne_bytes(reply[4..6].try_into().unwrap());
+    if kind != NLMSG_ERROR {
+        return Err(invalid());
+    }
+
+    match i32::from_ne_bytes(reply[NLMSG_HDRLEN..NLMSG_HDRLEN + 4].try_into().unwrap()) {
+        0 => Ok(()),
+        error => Err(io::Error::from_raw_os_error(-error)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_link_message() {
+        let mut message = LinkMessage::new(NLM_F_CREATE, 0, IFF_UP);
+        message
+            .name(IFLA_IFNAME, "br0")
+            .begin(IFLA_LINKINFO)
+            .name(IFLA_INFO_KIND, "bridge")
+            .end();
+        let buf = message.finish();
+
+        // Header, ifinfomsg, name and nested kind, all 4 bytes aligned
+        assert_eq!(buf.len(), 16 + 16 + 8 + 4 + 12);
+        assert_eq!(&buf[..4], &(buf.len() as u32).to_ne_bytes());
+        assert_eq!(&buf[4..6], &RTM_NEWLINK.to_ne_bytes());
+        assert_eq!(
+            &buf[6..8],
+            &(NLM_F_REQUEST | NLM_F_ACK | NLM_F_CREATE).to_ne_bytes()
+        );
+        assert_eq!(&buf[24..28], &IFF_UP.to_ne_bytes());
+        assert_eq!(&buf[32..34], &8u16.to_ne_bytes());
+        assert_eq!(&buf[36..40], b"br0\0");
+        assert_eq!(&buf[40..42], &16u16.to_ne_bytes());
+        assert_eq!(&buf[42..44], &IFLA_LINKINFO.to_ne_bytes());
+        assert_eq!(&buf[44..46], &11u16.to_ne_bytes());
+        assert_eq!(&buf[48..55], b"bridge\0");
+    }
+
+    #[test]
+    fn test_parse_ack() {
+        let mut reply = vec![0u8; 36];
+        reply[4..6].copy_from_slice(&NLMSG_ERROR.to_ne_bytes());
+        parse_ack(&reply).unwrap();
+
+        reply[16..20].copy_from_slice(&(-libc::EEXIST).to_ne_bytes());
+        assert_eq!(
+            parse_ack(&reply).unwrap_err().raw_os_error(),
+            Some(libc::EEXIST)
+        );
+
+        parse_ack(&reply[..16]).unwrap_err();
+    }
+}
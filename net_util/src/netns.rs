@@ -0,0 +1,277 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Network namespace holding the TAP interfaces of a VM.
+//!
+//! The namespace is named the way `ip netns` does, by bind mounting it under
+//! `/run/netns`, so that it can be joined and configured with the usual
+//! tools. When the namespace is created along with a bridge of the root
+//! namespace, a veth pair links that bridge to a bridge of the namespace the
+//! TAP interfaces are attached to.
+
+use std::ffi::CString;
+use std::fs::{self, File};
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use thiserror::Error;
+
+use crate::netlink::{
+    self, LinkMessage, Netlink, IFF_UP, IFLA_IFNAME, IFLA_INFO_DATA, IFLA_INFO_KIND, IFLA_LINKINFO,
+    IFLA_MASTER, IFLA_NET_NS_FD, NLM_F_CREATE, NLM_F_EXCL,
+};
+
+/// Directory the named network namespaces are bound to.
+pub const NETNS_RUN_DIR: &str = "/run/netns";
+
+/// Bridge of the namespace the TAP interfaces are attached to.
+pub const NETNS_BRIDGE_NAME: &str = "br0";
+
+/// End of the veth pair lying in the namespace.
+pub const NETNS_VETH_NAME: &str = "veth0";
+
+// Definition from linux/veth.h.
+const VETH_INFO_PEER: u16 = 1;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Cannot create the network namespace {0}")]
+    Create(String, #[source] io::Error),
+    #[error("Cannot open the network namespace {0}")]
+    Open(String, #[source] io::Error),
+    #[error("Cannot join the network namespace {0}")]
+    Join(String, #[source] io::Error),
+    #[error("Cannot spawn a thread in the network namespace")]
+    SpawnThread(#[source] io::Error),
+    #[error("Cannot find the interface {0}")]
+    InterfaceIndex(String, #[source] io::Error),
+    #[error("Netlink request on the interface {0} failed")]
+    Netlink(String, #[source] io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Named network namespace.
+pub struct NetNamespace {
+    name: String,
+    file: File,
+    // Whether the namespace was created here, and must then be removed once
+    // no longer needed.
+    created: bool,
+    // Whether the namespace is linked to a bridge.
+    bridged: bool,
+}
+
+impl NetNamespace {
+    fn path(name: &str) -> PathBuf {
+        Path::new(NETNS_RUN_DIR).join(name)
+    }
+
+    /// Join the network namespace `name`, creating it if it doesn't exist.
+    pub fn open_or_create(name: &str) -> Result<Self> {
+        let path = Self::path(name);
+        let created = !path.exists();
+        if created {
+            Self::create(name, &path).map_err(|e| Error::Create(name.to_string(), e))?;
+        }
+
+        let file = File::open(&path).map_err(|e| Error::Open(name.to_string(), e))?;
+
+        Ok(NetNamespace {
+            name: name.to_string(),
+            file,
+            created,
+            bridged: false,
+        })
+    }
+
+    // A thread moves to a new namespace, which is then bound to `path` so
+    // that it outlives the thread.
+    fn create(name: &str, path: &Path) -> io::Result<()> {
+        fs::create_dir_all(NETNS_RUN_DIR)?;
+        File::create(path)?;
+
+        let bind = || {
+            // SAFETY: FFI call with valid arguments, only affecting the
+            // calling thread.
+            if unsafe { libc::unshare(libc::CLONE_NEWNET) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let source = CString::new("/proc/thread-self/ns/net").unwrap();
+            let target = CString::new(path.as_os_str().as_bytes())?;
+            // SAFETY: FFI call with valid null terminated strings.
+            let ret = unsafe {
+                libc::mount(
+                    source.as_ptr(),
+                    target.as_ptr(),
+                    std::ptr::null(),
+                    libc::MS_BIND,
+                    std::ptr::null(),
+                )
+            };
+            if ret < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            Ok(())
+        };
+
+        let result = thread::scope(|s| {
+            thread::Builder::new()
+                .name(format!("netns_{name}"))
+                .spawn_scoped(s, bind)?
+                .join()
+                .unwrap_or_else(|e| std::panic::resume_unwind(e))
+        });
+        if result.is_err() {
+            let _ = fs::remove_file(path);
+        }
+
+        result
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether the namespace was created rather than joined.
+    pub fn created(&self) -> bool {
+        self.created
+    }
+
+    /// Whether the namespace is linked to a bridge, the TAP interfaces then
+    /// having to be attached to [`NETNS_BRIDGE_NAME`].
+    pub fn bridged(&self) -> bool {
+        self.bridged
+    }
+
+    /// Run `f` from a thread joining the namespace, so that the interfaces
+    /// and sockets it creates belong to the namespace. The calling thread
+    /// stays in its own namespace.
+    pub fn run<T: Send>(&self, f: impl FnOnce() -> T + Send) -> Result<T> {
+        thread::scope(|s| {
+            let handle = thread::Builder::new()
+                .name(format!("netns_{}", self.name))
+                .spawn_scoped(s, || {
+                    // SAFETY: FFI call with a valid namespace file
+                    // descriptor, only affecting the calling thread.
+                    if unsafe { libc::setns(self.file.as_raw_fd(), libc::CLONE_NEWNET) } < 0 {
+                        return Err(Error::Join(self.name.clone(), io::Error::last_os_error()));
+                    }
+                    Ok(f())
+                })
+                .map_err(Error::SpawnThread)?;
+
+            handle
+                .join()
+                .unwrap_or_else(|e| std::panic::resume_unwind(e))
+        })
+    }
+
+    /// Link the namespace to `bridge` of the calling thread namespace
+    /// through the veth pair made of `veth` and [`NETNS_VETH_NAME`], the
+    /// latter being attached to [`NETNS_BRIDGE_NAME`] in the namespace.
+    pub fn plumb(&mut self, bridge: &str, veth: &str) -> Result<()> {
+        let netlink = Netlink::open().map_err(|e| Error::Netlink(veth.to_string(), e))?;
+        netlink
+            .create_veth(veth, NETNS_VETH_NAME, self.file.as_raw_fd())
+            .map_err(|e| Error::Netlink(veth.to_string(), e))?;
+        netlink.attach(veth, bridge)?;
+
+        self.run(|| {
+            let netlink =
+                Netlink::open().map_err(|e| Error::Netlink(NETNS_BRIDGE_NAME.to_string(), e))?;
+            netlink.set_up("lo")?;
+            netlink
+                .create_bridge(NETNS_BRIDGE_NAME)
+                .map_err(|e| Error::Netlink(NETNS_BRIDGE_NAME.to_string(), e))?;
+            netlink.set_up(NETNS_BRIDGE_NAME)?;
+            netlink.attach(NETNS_VETH_NAME, NETNS_BRIDGE_NAME)
+        })??;
+        self.bridged = true;
+
+        Ok(())
+    }
+
+    /// Attach the interface `if_name` of the namespace to
+    /// [`NETNS_BRIDGE_NAME`]. Must be called from [`NetNamespace::run`].
+    pub fn attach(&self, if_name: &str) -> Result<()> {
+        Netlink::open()
+            .map_err(|e| Error::Netlink(if_name.to_string(), e))?
+            .attach(if_name, NETNS_BRIDGE_NAME)
+    }
+}
+
+impl Drop for NetNamespace {
+    fn drop(&mut self) {
+        if !self.created {
+            return;
+        }
+
+        // The namespace itself goes away with its last user.
+        let path = Self::path(&self.name);
+        if let Ok(target) = CString::new(path.as_os_str().as_bytes()) {
+            // SAFETY: FFI call with a valid null terminated string.
+            unsafe { libc::umount2(target.as_ptr(), libc::MNT_DETACH) };
+        }
+        if let Err(e) = fs::remove_file(&path) {
+            warn!("Cannot remove the network namespace {}: {}", self.name, e);
+        }
+    }
+}
+
+fn if_index(if_name: &str) -> Result<i32> {
+    netlink::if_index(if_name).map_err(|e| Error::InterfaceIndex(if_name.to_string(), e))
+}
+
+impl Netlink {
+    // Both ends are brought up once attached to their bridge.
+    fn create_veth(&self, name: &str, peer: &str, peer_netns: i32) -> io::Result<()> {
+        let mut message = LinkMessage::new(NLM_F_CREATE | NLM_F_EXCL, 0, 0);
+        message
+            .name(IFLA_IFNAME, name)
+            .begin(IFLA_LINKINFO)
+            .name(IFLA_INFO_KIND, "veth")
+            .begin(IFLA_INFO_DATA)
+            .begin(VETH_INFO_PEER);
+        message.ifinfo(0, 0);
+        message
+            .name(IFLA_IFNAME, peer)
+            .attr(IFLA_NET_NS_FD, &peer_netns.to_ne_bytes())
+            .end()
+            .end()
+            .end();
+        self.request(&mut message)
+    }
+
+    fn create_bridge(&self, name: &str) -> io::Result<()> {
+        let mut message = LinkMessage::new(NLM_F_CREATE | NLM_F_EXCL, 0, 0);
+        message
+            .name(IFLA_IFNAME, name)
+            .begin(IFLA_LINKINFO)
+            .name(IFLA_INFO_KIND, "bridge")
+            .end();
+        self.request(&mut message)
+    }
+
+    fn set_up(&self, if_name: &str) -> Result<()> {
+        let mut message = LinkMessage::new(0, if_index(if_name)?, IFF_UP);
+        self.request(&mut message)
+            .map_err(|e| Error::Netlink(if_name.to_string(), e))
+    }
+
+    // Attach `if_name` to `bridge` and bring it up.
+    fn attach(&self, if_name: &str, bridge: &str) -> Result<()> {
+        let master = if_index(bridge)?;
+        let mut message = LinkMessage::new(0, if_index(if_name)?, IFF_UP);
+        message.attr(IFLA_MASTER, &master.to_ne_bytes());
+        self.request(&mut message)
+            .map_err(|e| Error::Netlink(if_name.to_string(), e))
+    }
+}
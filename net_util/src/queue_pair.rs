@@ -8,6 +8,7 @@ use std::os::unix::io::{AsRawFd, RawFd};
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
+use rate_limiter::group::RateLimiterGroupHandle;
 use rate_limiter::{RateLimiter, TokenType};
 use thiserror::Error;
 use virtio_queue::{Queue, QueueOwnedT, QueueT};
@@ -15,7 +16,46 @@ use vm_memory::bitmap::Bitmap;
 use vm_memory::{Bytes, GuestMemory};
 use vm_virtio::{AccessPlatform, Translatable};
 
-use super::{register_listener, unregister_listener, vnet_hdr_len, Tap};
+use super::{register_listener, unregister_listener, vnet_hdr_len, Imds, Tap};
+
+/// Rate limiter of a queue of a net device, either of its own or shared
+/// with the other devices of a rate-limit group.
+pub enum NetRateLimiter {
+    Queue(RateLimiter),
+    Group(RateLimiterGroupHandle),
+}
+
+impl NetRateLimiter {
+    pub fn consume(&self, tokens: u64, token_type: TokenType) -> bool {
+        match self {
+            NetRateLimiter::Queue(r) => r.consume(tokens, token_type),
+            NetRateLimiter::Group(r) => r.consume(tokens, token_type),
+        }
+    }
+
+    pub fn is_blocked(&self) -> bool {
+        match self {
+            NetRateLimiter::Queue(r) => r.is_blocked(),
+            NetRateLimiter::Group(r) => r.is_blocked(),
+        }
+    }
+
+    pub fn event_handler(&self) -> io::Result<()> {
+        match self {
+            NetRateLimiter::Queue(r) => r.event_handler().map_err(io::Error::other),
+            NetRateLimiter::Group(r) => r.event_handler().map_err(io::Error::other),
+        }
+    }
+}
+
+impl AsRawFd for NetRateLimiter {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            NetRateLimiter::Queue(r) => r.as_raw_fd(),
+            NetRateLimiter::Group(r) => r.as_raw_fd(),
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct TxVirtio {
@@ -44,8 +84,9 @@ impl TxVirtio {
         mem: &vm_memory::GuestMemoryMmap<B>,
         tap: &Tap,
         queue: &mut Queue,
-        rate_limiter: &mut Option<RateLimiter>,
+        rate_limiter: &mut Option<NetRateLimiter>,
         access_platform: Option<&Arc<dyn AccessPlatform>>,
+        mut imds: Option<&mut Imds>,
     ) -> Result<bool, NetQueuePairError> {
         let mut retry_write = false;
         let mut rate_limit_reached = false;
@@ -86,7 +127,16 @@ impl TxVirtio {
                 next_desc = desc_chain.next();
             }
 
-            let len = if !iovecs.is_empty() {
+            let imds_len = imds
+                .as_deref_mut()
+                .and_then(|imds| intercept_tx_frame(imds, &iovecs));
+
+            let len = if let Some(len) = imds_len {
+                self.counter_bytes += Wrapping(u64::from(len) - vnet_hdr_len() as u64);
+                self.counter_frames += Wrapping(1);
+
+                len
+            } else if !iovecs.is_empty() {
                 // SAFETY: FFI call with correct arguments
                 let result = unsafe {
                     libc::writev(
@@ -172,8 +222,10 @@ impl RxVirtio {
         mem: &vm_memory::GuestMemoryMmap<B>,
         tap: &Tap,
         queue: &mut Queue,
-        rate_limiter: &mut Option<RateLimiter>,
+        rate_limiter: &mut Option<NetRateLimiter>,
         access_platform: Option<&Arc<dyn AccessPlatform>>,
+        mut imds: Option<&mut Imds>,
+        replay_source: Option<&str>,
     ) -> Result<bool, NetQueuePairError> {
         let mut exhausted_descs = true;
         let mut rate_limit_reached = false;
@@ -227,7 +279,44 @@ impl RxVirtio {
                 next_desc = desc_chain.next();
             }
 
-            let len = if !iovecs.is_empty() {
+            let imds_frame = imds
+                .as_deref_mut()
+                .filter(|_| !iovecs.is_empty())
+                .and_then(Imds::pop_frame);
+
+            let len = if let Some(frame) = imds_frame {
+                let len = write_rx_frame(&iovecs, &frame);
+
+                desc_chain
+                    .memory()
+                    .write_obj(1u16, num_buffers_addr)
+                    .map_err(NetQueuePairError::GuestMemory)?;
+
+                self.counter_bytes += Wrapping(frame.len() as u64);
+                self.counter_frames += Wrapping(1);
+
+                len as u32
+            } else if let Some(source) =
+                replay_source.filter(|_| replay::is_replaying() && !iovecs.is_empty())
+            {
+                // The frames come from the trace rather than from the TAP.
+                let Some(frame) = replay::replay(source) else {
+                    exhausted_descs = false;
+                    queue.go_to_previous_position();
+                    break;
+                };
+                let len = write_iovecs(&iovecs, &frame);
+
+                desc_chain
+                    .memory()
+                    .write_obj(1u16, num_buffers_addr)
+                    .map_err(NetQueuePairError::GuestMemory)?;
+
+                self.counter_bytes += Wrapping(frame.len().saturating_sub(vnet_hdr_len()) as u64);
+                self.counter_frames += Wrapping(1);
+
+                len as u32
+            } else if !iovecs.is_empty() {
                 // SAFETY: FFI call with correct arguments
                 let result = unsafe {
                     libc::readv(
@@ -254,6 +343,10 @@ impl RxVirtio {
                     return Err(NetQueuePairError::InvalidVirtioNetHeader);
                 }
 
+                if let Some(source) = replay_source.filter(|_| replay::is_recording()) {
+                    replay::record(source, &read_iovecs(&iovecs, result as usize));
+                }
+
                 // Write num_buffers to guest memory. We simply write 1 as we
                 // never spread the frame over more than one descriptor chain.
                 desc_chain
@@ -294,6 +387,69 @@ impl RxVirtio {
     }
 }
 
+// Hand a frame sent by the guest to the metadata service, returning the
+// length of the frame if it was consumed.
+pub(crate) fn intercept_tx_frame(imds: &mut Imds, iovecs: &[libc::iovec]) -> Option<u32> {
+    let mut frame = Vec::new();
+    for iovec in iovecs {
+        // SAFETY: the iovecs were built from guest memory slices validated
+        // while walking the descriptor chain.
+        frame.extend_from_slice(unsafe {
+            std::slice::from_raw_parts(iovec.iov_base as *const u8, iovec.iov_len)
+        });
+    }
+
+    if frame.len() < vnet_hdr_len() || !imds.handle_frame(&frame[vnet_hdr_len()..]) {
+        return None;
+    }
+
+    Some(frame.len() as u32)
+}
+
+// Write a frame from the metadata service into the guest buffers, preceded
+// by an empty virtio-net header, returning the number of bytes written.
+pub(crate) fn write_rx_frame(iovecs: &[libc::iovec], frame: &[u8]) -> usize {
+    let mut data = vec![0u8; vnet_hdr_len()];
+    data.extend_from_slice(frame);
+
+    write_iovecs(iovecs, &data)
+}
+
+// Gather the first `len` bytes of the guest buffers.
+pub(crate) fn read_iovecs(iovecs: &[libc::iovec], len: usize) -> Vec<u8> {
+    let mut data = Vec::with_capacity(len);
+    for iovec in iovecs {
+        let len = iovec.iov_len.min(len - data.len());
+        // SAFETY: the iovecs were built from guest memory slices validated
+        // while walking the descriptor chain.
+        data.extend_from_slice(unsafe {
+            std::slice::from_raw_parts(iovec.iov_base as *const u8, len)
+        });
+    }
+
+    data
+}
+
+// Scatter data, virtio-net header included, into the guest buffers,
+// returning the number of bytes written.
+pub(crate) fn write_iovecs(iovecs: &[libc::iovec], data: &[u8]) -> usize {
+    let mut written = 0;
+    for iovec in iovecs {
+        let len = iovec.iov_len.min(data.len() - written);
+        // SAFETY: the iovecs were built from guest memory slices validated
+        // while walking the descriptor chain.
+        unsafe {
+            std::ptr::copy_nonoverlapping(data[written..].as_ptr(), iovec.iov_base as *mut u8, len)
+        };
+        written += len;
+    }
+    if written < data.len() {
+        warn!("net: rx: frame truncated");
+    }
+
+    written
+}
+
 #[derive(Default, Clone)]
 struct IovecBuffer(Vec<libc::iovec>);
 
@@ -397,9 +553,12 @@ pub struct NetQueuePair {
     pub tap_rx_event_id: u16,
     pub tap_tx_event_id: u16,
     pub rx_desc_avail: bool,
-    pub rx_rate_limiter: Option<RateLimiter>,
-    pub tx_rate_limiter: Option<RateLimiter>,
+    pub rx_rate_limiter: Option<NetRateLimiter>,
+    pub tx_rate_limiter: Option<NetRateLimiter>,
     pub access_platform: Option<Arc<dyn AccessPlatform>>,
+    pub imds: Option<Imds>,
+    // Source of the RX frames in the I/O trace, if any.
+    pub replay_source: Option<String>,
 }
 
 impl NetQueuePair {
@@ -414,6 +573,7 @@ impl NetQueuePair {
             queue,
             &mut self.tx_rate_limiter,
             self.access_platform.as_ref(),
+            self.imds.as_mut(),
         )?;
 
         // We got told to try again when writing to the tap. Wait for the TAP to be writable
@@ -453,6 +613,22 @@ impl NetQueuePair {
             .map_err(NetQueuePairError::QueueNeedsNotification)
     }
 
+    /// Whether frames from the metadata service are waiting to be received
+    /// by the guest.
+    pub fn imds_pending(&self) -> bool {
+        self.imds.as_ref().is_some_and(Imds::has_frames)
+    }
+
+    /// Whether the RX frames come from the I/O trace rather than the TAP.
+    pub fn replaying(&self) -> bool {
+        self.replay_source.is_some() && replay::is_replaying()
+    }
+
+    /// Whether frames which don't come through the TAP are pending.
+    pub fn rx_frames_pending(&self) -> bool {
+        self.imds_pending() || self.replay_source.as_deref().is_some_and(replay::pending)
+    }
+
     pub fn process_rx<B: Bitmap + 'static>(
         &mut self,
         mem: &vm_memory::GuestMemoryMmap<B>,
@@ -464,6 +640,8 @@ impl NetQueuePair {
             queue,
             &mut self.rx_rate_limiter,
             self.access_platform.as_ref(),
+            self.imds.as_mut(),
+            self.replay_source.as_deref(),
         )?;
         let rate_limit_reached = self
             .rx_rate_limiter
@@ -0,0 +1,329 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! SR-IOV virtual function created on behalf of a VM.
+//!
+//! The virtual functions of the physical function are enabled if none is,
+//! the requested one is given its MAC address, VLAN and trust setting
+//! through netlink, and it is then bound to vfio-pci so that it can be
+//! assigned to the VM. Everything is undone once the function is dropped,
+//! unless its VFIO device is still open.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::netlink::{self, LinkMessage, Netlink};
+use crate::MacAddr;
+
+/// Directory of the PCI devices, the virtual functions being assigned
+/// through their node in it.
+pub const SYSFS_PCI_DEVICES: &str = "/sys/bus/pci/devices";
+
+const SYSFS_NET: &str = "/sys/class/net";
+const SYSFS_PCI_DRIVERS_PROBE: &str = "/sys/bus/pci/drivers_probe";
+const VFIO_PCI_DRIVER: &str = "vfio-pci";
+const VFIO_DIR: &str = "/dev/vfio";
+const PROC_SELF_FD: &str = "/proc/self/fd";
+
+// Netlink definitions from linux/if_link.h.
+const IFLA_VFINFO_LIST: u16 = 22;
+const IFLA_VF_INFO: u16 = 1;
+const IFLA_VF_MAC: u16 = 1;
+const IFLA_VF_VLAN: u16 = 2;
+const IFLA_VF_TRUST: u16 = 9;
+// Size of the address in struct ifla_vf_mac.
+const IFLA_VF_MAC_LEN: usize = 32;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Cannot access {0}")]
+    Sysfs(PathBuf, #[source] io::Error),
+    #[error("Physical function {0} has no virtual function {1}")]
+    InvalidVf(String, u32),
+    #[error("Cannot find the interface {0}")]
+    InterfaceIndex(String, #[source] io::Error),
+    #[error("Cannot configure the virtual function {1} of {0}")]
+    Netlink(String, u32, #[source] io::Error),
+    #[error("Cannot bind {0} to vfio-pci")]
+    Bind(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+fn read_sysfs(path: &Path) -> Result<String> {
+    fs::read_to_string(path)
+        .map(|s| s.trim().to_string())
+        .map_err(|e| Error::Sysfs(path.to_path_buf(), e))
+}
+
+fn write_sysfs(path: &Path, value: &str) -> Result<()> {
+    fs::write(path, value).map_err(|e| Error::Sysfs(path.to_path_buf(), e))
+}
+
+fn read_sysfs_u32(path: &Path) -> Result<u32> {
+    read_sysfs(path)?.parse().map_err(|e| {
+        Error::Sysfs(
+            path.to_path_buf(),
+            io::Error::new(io::ErrorKind::InvalidData, e),
+        )
+    })
+}
+
+// Name of the driver bound to a PCI device, if any.
+fn pci_driver(device: &Path) -> Option<String> {
+    fs::read_link(device.join("driver"))
+        .ok()?
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+}
+
+// Request setting the MAC address, VLAN and trust of a virtual function, a
+// `None` MAC address or VLAN being left untouched.
+fn vf_message(
+    pf_index: i32,
+    vf: u32,
+    mac: Option<&[u8]>,
+    vlan: Option<u16>,
+    trust: Option<bool>,
+) -> LinkMessage {
+    let mut message = LinkMessage::new(0, pf_index, 0);
+    message.begin(IFLA_VFINFO_LIST).begin(IFLA_VF_INFO);
+    if let Some(mac) = mac {
+        let mut data = vf.to_ne_bytes().to_vec();
+        data.extend_from_slice(mac);
+        data.resize(4 + IFLA_VF_MAC_LEN, 0);
+        message.attr(IFLA_VF_MAC, &data);
+    }
+    if let Some(vlan) = vlan {
+        // No QoS priority
+        let mut data = vf.to_ne_bytes().to_vec();
+        data.extend_from_slice(&u32::from(vlan).to_ne_bytes());
+        data.extend_from_slice(&0u32.to_ne_bytes());
+        message.attr(IFLA_VF_VLAN, &data);
+    }
+    if let Some(trust) = trust {
+        let mut data = vf.to_ne_bytes().to_vec();
+        data.extend_from_slice(&u32::from(trust).to_ne_bytes());
+        message.attr(IFLA_VF_TRUST, &data);
+    }
+    message.end().end();
+    message
+}
+
+/// Virtual function bound to vfio-pci, given back to the host when dropped.
+pub struct SriovVf {
+    pf: String,
+    vf: u32,
+    bdf: String,
+    mac: Option<MacAddr>,
+    vlan: Option<u16>,
+    trust: bool,
+    // Whether the virtual functions were enabled here, and must then be
+    // disabled once none of them is assigned anymore.
+    enabled: bool,
+    // Whether the MAC address, VLAN and trust were applied.
+    configured: bool,
+}
+
+impl SriovVf {
+    /// Set up the virtual function `vf` of the physical function `pf`, a
+    /// network interface, for its assignment through VFIO.
+    pub fn create(
+        pf: &str,
+        vf: u32,
+        mac: Option<MacAddr>,
+        vlan: Option<u16>,
+        trust: bool,
+    ) -> Result<Self> {
+        let pf_device = Path::new(SYSFS_NET).join(pf).join("device");
+
+        let numvfs_path = pf_device.join("sriov_numvfs");
+        let numvfs = read_sysfs_u32(&numvfs_path)?;
+        // The number of virtual functions can't be changed once they are
+        // enabled, all of them are then made available.
+        let enabled = numvfs == 0;
+        let numvfs = if enabled {
+            read_sysfs_u32(&pf_device.join("sriov_totalvfs"))?
+        } else {
+            numvfs
+        };
+        if vf >= numvfs {
+            return Err(Error::InvalidVf(pf.to_string(), vf));
+        }
+        if enabled {
+            info!("Enabling {} virtual functions on {}", numvfs, pf);
+            write_sysfs(&numvfs_path, &numvfs.to_string())?;
+        }
+
+        let mut sriov_vf = SriovVf {
+            pf: pf.to_string(),
+            vf,
+            bdf: String::new(),
+            mac,
+            vlan,
+            trust,
+            enabled,
+            configured: false,
+        };
+
+        let virtfn = pf_device.join(format!("virtfn{vf}"));
+        sriov_vf.bdf = fs::read_link(&virtfn)
+            .map_err(|e| Error::Sysfs(virtfn.clone(), e))?
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .ok_or(Error::InvalidVf(pf.to_string(), vf))?;
+
+        sriov_vf.configure(
+            mac.as_ref().map(|m| m.get_bytes()),
+            vlan,
+            trust.then_some(true),
+        )?;
+        sriov_vf.configured = true;
+
+        sriov_vf.bind(VFIO_PCI_DRIVER)?;
+        if pci_driver(&sriov_vf.path()).as_deref() != Some(VFIO_PCI_DRIVER) {
+            return Err(Error::Bind(sriov_vf.bdf.clone()));
+        }
+
+        Ok(sriov_vf)
+    }
+
+    /// Sysfs node of the virtual function, to be assigned through VFIO.
+    pub fn path(&self) -> PathBuf {
+        Path::new(SYSFS_PCI_DEVICES).join(&self.bdf)
+    }
+
+    fn configure(&self, mac: Option<&[u8]>, vlan: Option<u16>, trust: Option<bool>) -> Result<()> {
+        if mac.is_none() && vlan.is_none() && trust.is_none() {
+            return Ok(());
+        }
+
+        let pf_index =
+            netlink::if_index(&self.pf).map_err(|e| Error::InterfaceIndex(self.pf.clone(), e))?;
+        let mut message = vf_message(pf_index, self.vf, mac, vlan, trust);
+        Netlink::open()
+            .and_then(|netlink| netlink.request(&mut message))
+            .map_err(|e| Error::Netlink(self.pf.clone(), self.vf, e))
+    }
+
+    // Rebind the virtual function to `driver`, the one matching the device
+    // being probed if empty.
+    fn bind(&self, driver: &str) -> Result<()> {
+        let device = self.path();
+        if pci_driver(&device).is_some() {
+            write_sysfs(&device.join("driver").join("unbind"), &self.bdf)?;
+        }
+        write_sysfs(&device.join("driver_override"), &format!("{driver}\n"))?;
+        write_sysfs(Path::new(SYSFS_PCI_DRIVERS_PROBE), &self.bdf)
+    }
+
+    // Whether the VFIO group of the virtual function is still opened by
+    // this process, unbinding the function then blocking forever.
+    fn group_opened(&self) -> bool {
+        let Some(group) = fs::read_link(self.path().join("iommu_group"))
+            .ok()
+            .and_then(|group| group.file_name().map(|name| name.to_os_string()))
+        else {
+            return false;
+        };
+        let groups = [
+            Path::new(VFIO_DIR).join(&group),
+            Path::new(VFIO_DIR).join(format!("noiommu-{}", group.to_string_lossy())),
+        ];
+
+        let Ok(fds) = fs::read_dir(PROC_SELF_FD) else {
+            return true;
+        };
+        fds.flatten().any(|fd| {
+            fs::read_link(fd.path()).is_ok_and(|target| groups.iter().any(|g| *g == target))
+        })
+    }
+
+    // Whether a virtual function of the physical function is still bound
+    // to vfio-pci, likely assigned to a VM.
+    fn vfs_assigned(&self) -> bool {
+        let pf_device = Path::new(SYSFS_NET).join(&self.pf).join("device");
+        let Ok(numvfs) = read_sysfs_u32(&pf_device.join("sriov_numvfs")) else {
+            return true;
+        };
+
+        (0..numvfs).any(|vf| {
+            pci_driver(&pf_device.join(format!("virtfn{vf}"))).as_deref() == Some(VFIO_PCI_DRIVER)
+        })
+    }
+}
+
+impl Drop for SriovVf {
+    fn drop(&mut self) {
+        if !self.bdf.is_empty() && self.group_opened() {
+            warn!(
+                "Cannot give {} back to the host, its VFIO device is still open",
+                self.bdf
+            );
+            return;
+        }
+
+        if self.configured {
+            let zero_mac = [0u8; 6];
+            if let Err(e) = self.configure(
+                self.mac.map(|_| &zero_mac[..]),
+                self.vlan.map(|_| 0),
+                self.trust.then_some(false),
+            ) {
+                warn!("Cannot reset {}: {}", self.bdf, e);
+            }
+        }
+
+        if !self.bdf.is_empty() {
+            if let Err(e) = self.bind("") {
+                warn!("Cannot give {} back to the host: {}", self.bdf, e);
+            }
+        }
+
+        if self.enabled && !self.vfs_assigned() {
+            info!("Disabling the virtual functions of {}", self.pf);
+            let numvfs_path = Path::new(SYSFS_NET)
+                .join(&self.pf)
+                .join("device")
+                .join("sriov_numvfs");
+            if let Err(e) = write_sysfs(&numvfs_path, "0") {
+                warn!("Cannot disable the virtual functions of {}: {}", self.pf, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vf_message() {
+        let mac = [0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc];
+        let mut message = vf_message(4, 2, Some(&mac), Some(100), Some(true));
+        let buf = message.finish();
+
+        // Header, ifinfomsg, VF list and info nests, MAC, VLAN and trust
+        assert_eq!(buf.len(), 16 + 16 + 4 + 4 + 40 + 16 + 12);
+        assert_eq!(&buf[20..24], &4i32.to_ne_bytes());
+        assert_eq!(&buf[32..34], &((buf.len() - 32) as u16).to_ne_bytes());
+        assert_eq!(&buf[34..36], &IFLA_VFINFO_LIST.to_ne_bytes());
+        assert_eq!(&buf[40..42], &40u16.to_ne_bytes());
+        assert_eq!(&buf[42..44], &IFLA_VF_MAC.to_ne_bytes());
+        assert_eq!(&buf[44..48], &2u32.to_ne_bytes());
+        assert_eq!(&buf[48..54], &mac);
+        assert_eq!(&buf[82..84], &IFLA_VF_VLAN.to_ne_bytes());
+        assert_eq!(&buf[88..92], &100u32.to_ne_bytes());
+        assert_eq!(&buf[98..100], &IFLA_VF_TRUST.to_ne_bytes());
+        assert_eq!(&buf[104..108], &1u32.to_ne_bytes());
+
+        // Nothing to change
+        let mut message = vf_message(4, 2, None, None, None);
+        assert_eq!(message.finish().len(), 16 + 16 + 4 + 4);
+    }
+}
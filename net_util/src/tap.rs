@@ -426,6 +426,27 @@ impl Tap {
         unsafe { Self::ioctl_with_val(&self.tap_file, net_gen::TUNSETOFFLOAD(), flags as c_ulong) }
     }
 
+    /// Keep the tap interface once all its file descriptors are closed, or
+    /// let the kernel remove it then.
+    pub fn set_persist(&self, persist: bool) -> Result<()> {
+        // SAFETY: ioctl is safe. Called with a valid tap fd, and we check the return.
+        unsafe {
+            Self::ioctl_with_val(&self.tap_file, net_gen::TUNSETPERSIST(), persist as c_ulong)
+        }
+    }
+
+    /// Let the given user open the tap interface.
+    pub fn set_owner(&self, uid: u32) -> Result<()> {
+        // SAFETY: ioctl is safe. Called with a valid tap fd, and we check the return.
+        unsafe { Self::ioctl_with_val(&self.tap_file, net_gen::TUNSETOWNER(), uid as c_ulong) }
+    }
+
+    /// Let the members of the given group open the tap interface.
+    pub fn set_group(&self, gid: u32) -> Result<()> {
+        // SAFETY: ioctl is safe. Called with a valid tap fd, and we check the return.
+        unsafe { Self::ioctl_with_val(&self.tap_file, net_gen::TUNSETGROUP(), gid as c_ulong) }
+    }
+
     /// Enable the tap interface.
     pub fn enable(&self) -> Result<()> {
         let sock = create_unix_socket().map_err(Error::NetUtil)?;
@@ -475,6 +496,11 @@ impl Tap {
         self.if_name.clone()
     }
 
+    /// File of the tap queue, e.g. to hand it over to the kernel.
+    pub fn file(&self) -> &File {
+        &self.tap_file
+    }
+
     #[cfg(fuzzing)]
     pub fn new_for_fuzzing(tap_file: File, if_name: Vec<u8>) -> Self {
         Tap { tap_file, if_name }
@@ -712,6 +738,18 @@ mod tests {
         tap.set_offload(0).unwrap();
     }
 
+    #[test]
+    fn test_set_persist_and_owner() {
+        let _tap_ip_guard = TAP_IP_LOCK.lock().unwrap();
+
+        let tap = Tap::new(1).unwrap();
+        tap.set_owner(1000).unwrap();
+        tap.set_group(100).unwrap();
+        tap.set_persist(true).unwrap();
+        // Let the kernel remove the interface once the test is done.
+        tap.set_persist(false).unwrap();
+    }
+
     #[test]
     fn test_tap_enable() {
         let _tap_ip_guard = TAP_IP_LOCK.lock().unwrap();
@@ -0,0 +1,461 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! io_uring based processing of the TAP device.
+//!
+//! Rather than reading and writing the TAP device from the queue pair
+//! thread, frames are exchanged with it through an io_uring instance.
+//!
+//! A fixed set of receive buffers, backed by hugepages and allocated on the
+//! host NUMA node of the device, is registered with the ring and kept
+//! posted with READ_FIXED requests, so that the kernel pulls frames off the
+//! TAP device ahead of the guest providing RX buffers, the frames being
+//! copied into the guest buffers once available. The TAP device not being a
+//! socket, multishot receive can't be used, and re-posting the registered
+//! buffers is what keeps the reads outstanding.
+//!
+//! Frames sent by the guest are submitted as WRITEV requests straight from
+//! guest memory, a whole batch of descriptor chains costing a single system
+//! call, and the descriptors are returned to the guest upon completion.
+
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::num::Wrapping;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::atomic::Ordering;
+
+use io_uring::{opcode, types, IoUring, Probe};
+use rate_limiter::TokenType;
+use virtio_queue::{Queue, QueueOwnedT, QueueT};
+use vm_memory::bitmap::Bitmap;
+use vm_memory::{Bytes, GuestMemory};
+use vm_virtio::{HugepageBuffer, Translatable};
+use vmm_sys_util::eventfd::EventFd;
+
+use super::queue_pair::{intercept_tx_frame, write_iovecs, write_rx_frame};
+use super::{vnet_hdr_len, Imds, NetQueuePair, NetQueuePairError, Tap};
+
+// Number of receive buffers registered with the ring.
+const RX_BUFFER_COUNT: usize = 16;
+// Large enough for a 64KiB TSO/UFO frame along with its virtio-net header.
+const RX_BUFFER_SIZE: usize = 65562;
+// Tells the completions of TX requests, tagged with the head of the
+// descriptor chain, from the ones of RX requests, tagged with the index of
+// the receive buffer.
+const TX_USER_DATA: u64 = 1 << 63;
+// The TAP device isn't seekable, the current position is used.
+const TAP_OFFSET: u64 = u64::MAX;
+
+/// Check if io_uring can be used for TAP devices on the current system.
+pub fn tap_io_uring_is_supported() -> bool {
+    let error_msg = "io_uring not supported for TAP devices:";
+
+    let io_uring = match IoUring::new(1) {
+        Ok(io_uring) => io_uring,
+        Err(e) => {
+            info!("{} failed to create io_uring instance: {}", error_msg, e);
+            return false;
+        }
+    };
+
+    let mut probe = Probe::new();
+    if let Err(e) = io_uring.submitter().register_probe(&mut probe) {
+        info!("{} failed to register a probe: {}", error_msg, e);
+        return false;
+    }
+
+    if !probe.is_supported(opcode::ReadFixed::CODE) {
+        info!("{} IORING_OP_READ_FIXED operation not supported", error_msg);
+        return false;
+    }
+
+    if !probe.is_supported(opcode::Writev::CODE) {
+        info!("{} IORING_OP_WRITEV operation not supported", error_msg);
+        return false;
+    }
+
+    true
+}
+
+pub struct TapUring {
+    // Declared first so that the ring, and the requests it holds, goes away
+    // before the buffers they point to.
+    io_uring: IoUring,
+    eventfd: EventFd,
+    rx_buffers: HugepageBuffer,
+    // Frames read from the TAP device, waiting for guest RX buffers, as
+    // pairs of receive buffer index and frame length.
+    rx_frames: VecDeque<(usize, usize)>,
+    // Descriptor chains being written to the TAP device, keeping their
+    // iovecs alive until completion.
+    tx_inflight: HashMap<u16, Vec<libc::iovec>>,
+    tx_depth: usize,
+}
+
+// SAFETY: The raw pointers held by TapUring point either to the receive
+// buffers it owns, or to guest memory, which is safe to access from any
+// thread.
+unsafe impl Send for TapUring {}
+
+impl TapUring {
+    /// Create an io_uring instance for the given TAP device, allowing up to
+    /// `tx_depth` frames to be written concurrently, and start receiving.
+    /// The receive buffers are preferably allocated on `numa_node`.
+    pub fn new(tap: &Tap, tx_depth: u16, numa_node: Option<u32>) -> io::Result<Self> {
+        let tx_depth = tx_depth as usize;
+        let io_uring = IoUring::new((RX_BUFFER_COUNT + tx_depth).next_power_of_two() as u32)?;
+        let eventfd = EventFd::new(libc::EFD_NONBLOCK)?;
+
+        // Register the io_uring eventfd that will notify when something in
+        // the completion queue is ready.
+        io_uring.submitter().register_eventfd(eventfd.as_raw_fd())?;
+
+        let mut rx_buffers = HugepageBuffer::new(RX_BUFFER_COUNT * RX_BUFFER_SIZE, numa_node)?;
+        let iovecs: Vec<libc::iovec> = rx_buffers
+            .chunks_exact_mut(RX_BUFFER_SIZE)
+            .map(|buffer| libc::iovec {
+                iov_base: buffer.as_mut_ptr() as *mut libc::c_void,
+                iov_len: RX_BUFFER_SIZE,
+            })
+            .collect();
+        // SAFETY: the buffers are owned by TapUring and outlive the ring.
+        unsafe { io_uring.submitter().register_buffers(&iovecs)? };
+
+        let mut tap_uring = TapUring {
+            io_uring,
+            eventfd,
+            rx_buffers,
+            rx_frames: VecDeque::with_capacity(RX_BUFFER_COUNT),
+            tx_inflight: HashMap::new(),
+            tx_depth,
+        };
+
+        for index in 0..RX_BUFFER_COUNT {
+            tap_uring.post_read(tap.as_raw_fd(), index)?;
+        }
+        tap_uring.io_uring.submit()?;
+
+        Ok(tap_uring)
+    }
+
+    /// Notified when requests have completed.
+    pub fn notifier(&self) -> &EventFd {
+        &self.eventfd
+    }
+
+    fn post_read(&mut self, fd: RawFd, index: usize) -> io::Result<()> {
+        let buffer = &mut self.rx_buffers[index * RX_BUFFER_SIZE..(index + 1) * RX_BUFFER_SIZE];
+        let read = opcode::ReadFixed::new(
+            types::Fd(fd),
+            buffer.as_mut_ptr(),
+            RX_BUFFER_SIZE as u32,
+            index as u16,
+        )
+        .offset(TAP_OFFSET)
+        .build()
+        .user_data(index as u64);
+
+        // SAFETY: the file descriptor is valid and the buffer, registered
+        // with the ring, lives as long as it.
+        unsafe { self.io_uring.submission().push(&read) }
+            .map_err(|_| io::Error::other("Submission queue is full"))
+    }
+
+    /// Submit the frames sent by the guest to the TAP device. Returns
+    /// whether the guest must be notified of descriptors being used already,
+    /// which only happens for the frames not reaching the TAP device.
+    pub fn process_tx<B: Bitmap + 'static>(
+        &mut self,
+        mem: &vm_memory::GuestMemoryMmap<B>,
+        queue: &mut Queue,
+        net: &mut NetQueuePair,
+    ) -> Result<bool, NetQueuePairError> {
+        let mut rate_limit_reached = false;
+
+        while let Some(mut desc_chain) = queue.pop_descriptor_chain(mem) {
+            if rate_limit_reached
+                || self.tx_inflight.len() >= self.tx_depth
+                || self.io_uring.submission().is_full()
+            {
+                queue.go_to_previous_position();
+                break;
+            }
+
+            let head_index = desc_chain.head_index();
+            let mut next_desc = desc_chain.next();
+
+            let mut iovecs = Vec::new();
+            while let Some(desc) = next_desc {
+                let desc_addr = desc
+                    .addr()
+                    .translate_gva(net.access_platform.as_ref(), desc.len() as usize);
+                if !desc.is_write_only() && desc.len() > 0 {
+                    let buf = desc_chain
+                        .memory()
+                        .get_slice(desc_addr, desc.len() as usize)
+                        .map_err(NetQueuePairError::GuestMemory)?
+                        .ptr_guard_mut();
+                    iovecs.push(libc::iovec {
+                        iov_base: buf.as_ptr() as *mut libc::c_void,
+                        iov_len: desc.len() as libc::size_t,
+                    });
+                } else {
+                    error!(
+                        "Invalid descriptor chain: address = 0x{:x} length = {} write_only = {}",
+                        desc_addr.0,
+                        desc.len(),
+                        desc.is_write_only()
+                    );
+                    return Err(NetQueuePairError::DescriptorChainInvalid);
+                }
+                next_desc = desc_chain.next();
+            }
+
+            let len = iovecs.iter().map(|iovec| iovec.iov_len as u64).sum::<u64>();
+            let imds_len = net
+                .imds
+                .as_mut()
+                .and_then(|imds| intercept_tx_frame(imds, &iovecs));
+
+            if let Some(len) = imds_len {
+                net.tx.counter_bytes += Wrapping(u64::from(len) - vnet_hdr_len() as u64);
+                net.tx.counter_frames += Wrapping(1);
+                queue
+                    .add_used(desc_chain.memory(), head_index, len)
+                    .map_err(NetQueuePairError::QueueAddUsed)?;
+            } else if iovecs.is_empty() {
+                queue
+                    .add_used(desc_chain.memory(), head_index, 0)
+                    .map_err(NetQueuePairError::QueueAddUsed)?;
+            } else {
+                let write = opcode::Writev::new(
+                    types::Fd(net.tap.as_raw_fd()),
+                    iovecs.as_ptr(),
+                    iovecs.len() as u32,
+                )
+                .offset(TAP_OFFSET)
+                .build()
+                .user_data(TX_USER_DATA | u64::from(head_index));
+
+                // SAFETY: the file descriptor is valid, the iovecs point to
+                // guest memory and are kept until the request completes.
+                unsafe { self.io_uring.submission().push(&write) }.map_err(|_| {
+                    NetQueuePairError::WriteTap(io::Error::other("Submission queue is full"))
+                })?;
+                self.tx_inflight.insert(head_index, iovecs);
+            }
+
+            if let Some(rate_limiter) = &mut net.tx_rate_limiter {
+                rate_limit_reached = !rate_limiter.consume(1, TokenType::Ops)
+                    || !rate_limiter.consume(len, TokenType::Bytes);
+            }
+
+            if !queue
+                .enable_notification(mem)
+                .map_err(NetQueuePairError::QueueEnableNotification)?
+            {
+                break;
+            }
+        }
+
+        self.io_uring
+            .submit()
+            .map_err(NetQueuePairError::WriteTap)?;
+        update_tx_counters(net);
+
+        queue
+            .needs_notification(mem)
+            .map_err(NetQueuePairError::QueueNeedsNotification)
+    }
+
+    /// Reap the completed requests, returning the descriptors of the frames
+    /// written to the TAP device to the guest, and keeping the frames read
+    /// from it for `process_rx()`. Returns whether the guest must be notified
+    /// about the TX queue.
+    pub fn process_completions<B: Bitmap + 'static>(
+        &mut self,
+        mem: &vm_memory::GuestMemoryMmap<B>,
+        tx_queue: &mut Queue,
+        net: &mut NetQueuePair,
+    ) -> Result<bool, NetQueuePairError> {
+        let completions: Vec<(u64, i32)> = self
+            .io_uring
+            .completion()
+            .map(|cqe| (cqe.user_data(), cqe.result()))
+            .collect();
+
+        let mut tx_used = false;
+        for (user_data, result) in completions {
+            if user_data & TX_USER_DATA != 0 {
+                let head_index = user_data as u16;
+                self.tx_inflight.remove(&head_index);
+
+                if result < 0 {
+                    let e = io::Error::from_raw_os_error(-result);
+                    error!("net: tx: failed writing to tap: {}", e);
+                    return Err(NetQueuePairError::WriteTap(e));
+                }
+                if (result as usize) < vnet_hdr_len() {
+                    return Err(NetQueuePairError::InvalidVirtioNetHeader);
+                }
+
+                net.tx.counter_bytes += Wrapping(result as u64 - vnet_hdr_len() as u64);
+                net.tx.counter_frames += Wrapping(1);
+
+                tx_queue
+                    .add_used(mem, head_index, result as u32)
+                    .map_err(NetQueuePairError::QueueAddUsed)?;
+                tx_used = true;
+            } else {
+                if result < 0 {
+                    let e = io::Error::from_raw_os_error(-result);
+                    error!("net: rx: failed reading from tap: {}", e);
+                    return Err(NetQueuePairError::ReadTap(e));
+                }
+                if (result as usize) < vnet_hdr_len() {
+                    return Err(NetQueuePairError::InvalidVirtioNetHeader);
+                }
+
+                self.rx_frames
+                    .push_back((user_data as usize, result as usize));
+            }
+        }
+
+        update_tx_counters(net);
+
+        if !tx_used {
+            return Ok(false);
+        }
+
+        tx_queue
+            .needs_notification(mem)
+            .map_err(NetQueuePairError::QueueNeedsNotification)
+    }
+
+    /// Deliver the frames read from the TAP device, or coming from the
+    /// metadata service, into the guest RX buffers, and post the receive
+    /// buffers again. Returns whether the guest must be notified.
+    pub fn process_rx<B: Bitmap + 'static>(
+        &mut self,
+        mem: &vm_memory::GuestMemoryMmap<B>,
+        queue: &mut Queue,
+        net: &mut NetQueuePair,
+    ) -> Result<bool, NetQueuePairError> {
+        let mut rate_limit_reached = net.rx_rate_limiter.as_ref().is_some_and(|r| r.is_blocked());
+
+        while !rate_limit_reached && (!self.rx_frames.is_empty() || net.imds_pending()) {
+            let Some(mut desc_chain) = queue.pop_descriptor_chain(mem) else {
+                net.rx_desc_avail = false;
+                break;
+            };
+
+            let desc = desc_chain
+                .next()
+                .ok_or(NetQueuePairError::DescriptorChainTooShort)?;
+
+            let num_buffers_addr = desc_chain
+                .memory()
+                .checked_offset(
+                    desc.addr()
+                        .translate_gva(net.access_platform.as_ref(), desc.len() as usize),
+                    10,
+                )
+                .ok_or(NetQueuePairError::DescriptorInvalidHeader)?;
+            let mut next_desc = Some(desc);
+
+            let mut iovecs = Vec::new();
+            while let Some(desc) = next_desc {
+                let desc_addr = desc
+                    .addr()
+                    .translate_gva(net.access_platform.as_ref(), desc.len() as usize);
+                if desc.is_write_only() && desc.len() > 0 {
+                    let buf = desc_chain
+                        .memory()
+                        .get_slice(desc_addr, desc.len() as usize)
+                        .map_err(NetQueuePairError::GuestMemory)?
+                        .ptr_guard_mut();
+                    iovecs.push(libc::iovec {
+                        iov_base: buf.as_ptr() as *mut libc::c_void,
+                        iov_len: desc.len() as libc::size_t,
+                    });
+                } else {
+                    error!(
+                        "Invalid descriptor chain: address = 0x{:x} length = {} write_only = {}",
+                        desc_addr.0,
+                        desc.len(),
+                        desc.is_write_only()
+                    );
+                    return Err(NetQueuePairError::DescriptorChainInvalid);
+                }
+                next_desc = desc_chain.next();
+            }
+
+            let imds_frame = net.imds.as_mut().and_then(Imds::pop_frame);
+            let len = if let Some(frame) = imds_frame {
+                net.rx.counter_bytes += Wrapping(frame.len() as u64);
+                write_rx_frame(&iovecs, &frame)
+            } else {
+                let (index, frame_len) = self.rx_frames.pop_front().unwrap();
+                let start = index * RX_BUFFER_SIZE;
+                let len = write_iovecs(&iovecs, &self.rx_buffers[start..start + frame_len]);
+                self.post_read(net.tap.as_raw_fd(), index)
+                    .map_err(NetQueuePairError::ReadTap)?;
+                net.rx.counter_bytes += Wrapping((frame_len - vnet_hdr_len()) as u64);
+                len
+            };
+            net.rx.counter_frames += Wrapping(1);
+
+            // Write num_buffers to guest memory. We simply write 1 as we
+            // never spread the frame over more than one descriptor chain.
+            desc_chain
+                .memory()
+                .write_obj(1u16, num_buffers_addr)
+                .map_err(NetQueuePairError::GuestMemory)?;
+
+            if let Some(rate_limiter) = &mut net.rx_rate_limiter {
+                rate_limit_reached = !rate_limiter.consume(1, TokenType::Ops)
+                    || !rate_limiter.consume(len as u64, TokenType::Bytes);
+            }
+
+            queue
+                .add_used(desc_chain.memory(), desc_chain.head_index(), len as u32)
+                .map_err(NetQueuePairError::QueueAddUsed)?;
+
+            if !queue
+                .enable_notification(mem)
+                .map_err(NetQueuePairError::QueueEnableNotification)?
+            {
+                net.rx_desc_avail = false;
+                break;
+            }
+        }
+
+        self.io_uring.submit().map_err(NetQueuePairError::ReadTap)?;
+
+        net.counters
+            .rx_bytes
+            .fetch_add(net.rx.counter_bytes.0, Ordering::AcqRel);
+        net.counters
+            .rx_frames
+            .fetch_add(net.rx.counter_frames.0, Ordering::AcqRel);
+        net.rx.counter_bytes = Wrapping(0);
+        net.rx.counter_frames = Wrapping(0);
+
+        queue
+            .needs_notification(mem)
+            .map_err(NetQueuePairError::QueueNeedsNotification)
+    }
+}
+
+fn update_tx_counters(net: &mut NetQueuePair) {
+    net.counters
+        .tx_bytes
+        .fetch_add(net.tx.counter_bytes.0, Ordering::AcqRel);
+    net.counters
+        .tx_frames
+        .fetch_add(net.tx.counter_frames.0, Ordering::AcqRel);
+    net.tx.counter_bytes = Wrapping(0);
+    net.tx.counter_frames = Wrapping(0);
+}
@@ -270,6 +270,12 @@ impl TupleValue for u64 {
     }
 }
 
+impl TupleValue for String {
+    fn parse_value(input: &str) -> Result<Self, TupleError> {
+        Ok(input.to_string())
+    }
+}
+
 impl TupleValue for Vec<u8> {
     fn parse_value(input: &str) -> Result<Self, TupleError> {
         Ok(IntegerList::from_str(input)
@@ -425,4 +431,20 @@ mod tests {
         parser.parse("cmdline=\"").unwrap_err();
         parser.parse("cmdline=\"\"\"").unwrap_err();
     }
+
+    #[test]
+    fn test_string_tuple() {
+        let tuple = Tuple::<String, String>::from_str("[disk0@/path/to/disk,net0@tap1]").unwrap();
+        assert_eq!(
+            tuple.0,
+            vec![
+                ("disk0".to_owned(), "/path/to/disk".to_owned()),
+                ("net0".to_owned(), "tap1".to_owned()),
+            ]
+        );
+
+        Tuple::<String, String>::from_str("[disk0]").unwrap_err();
+        Tuple::<String, String>::from_str("[disk0@a@b]").unwrap_err();
+        Tuple::<String, String>::from_str("disk0@/path/to/disk").unwrap_err();
+    }
 }
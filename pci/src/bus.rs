@@ -54,13 +54,18 @@ pub type Result<T> = std::result::Result<T, PciRootError>;
 pub struct PciRoot {
     /// Configuration space.
     config: PciConfiguration,
+    /// Registers mirrored from the host bridge.
+    mirrored_registers: HashMap<usize, u32>,
 }
 
 impl PciRoot {
     /// Create an empty PCI root bridge.
     pub fn new(config: Option<PciConfiguration>) -> Self {
         if let Some(config) = config {
-            PciRoot { config }
+            PciRoot {
+                config,
+                mirrored_registers: HashMap::new(),
+            }
         } else {
             PciRoot {
                 config: PciConfiguration::new(
@@ -76,9 +81,17 @@ impl PciRoot {
                     None,
                     None,
                 ),
+                mirrored_registers: HashMap::new(),
             }
         }
     }
+
+    /// Expose the given host bridge registers instead of the emulated ones,
+    /// as some assigned devices (e.g. Intel IGD) have their guest driver
+    /// identifying the platform through the host bridge.
+    pub fn mirror_registers(&mut self, registers: &[(usize, u32)]) {
+        self.mirrored_registers.extend(registers.iter().copied());
+    }
 }
 
 impl BusDevice for PciRoot {}
@@ -97,6 +110,10 @@ impl PciDevice for PciRoot {
     }
 
     fn read_config_register(&mut self, reg_idx: usize) -> u32 {
+        if let Some(value) = self.mirrored_registers.get(&reg_idx) {
+            return *value;
+        }
+
         self.config.read_reg(reg_idx)
     }
 
@@ -156,6 +173,15 @@ impl PciBus {
         Ok(())
     }
 
+    /// Mirror host bridge registers on the root bridge of the bus.
+    pub fn mirror_host_bridge(&self, registers: &[(usize, u32)]) {
+        if let Some(root) = self.devices.get(&0) {
+            if let Some(root) = root.lock().unwrap().as_any_mut().downcast_mut::<PciRoot>() {
+                root.mirror_registers(registers);
+            }
+        }
+    }
+
     pub fn add_device(&mut self, device_id: u32, device: Arc<Mutex<dyn PciDevice>>) -> Result<()> {
         self.devices.insert(device_id, device);
         Ok(())
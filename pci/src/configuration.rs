@@ -822,6 +822,11 @@ impl PciConfiguration {
         Ok(())
     }
 
+    /// Returns the address of the expansion ROM BAR.
+    pub fn get_rom_bar_addr(&self) -> u64 {
+        u64::from(self.rom_bar_addr & ROM_BAR_ADDR_MASK)
+    }
+
     /// Returns the address of the given BAR region.
     pub fn get_bar_addr(&self, bar_num: usize) -> u64 {
         let bar_idx = BAR0_REG + bar_num;
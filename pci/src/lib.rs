@@ -13,7 +13,10 @@ mod configuration;
 mod device;
 mod msi;
 mod msix;
+mod rom;
 mod vfio;
+mod vfio_dirty;
+mod vfio_igd;
 mod vfio_user;
 
 use std::fmt::{self, Debug, Display};
@@ -34,7 +37,12 @@ pub use self::device::{
 };
 pub use self::msi::{msi_num_enabled_vectors, MsiCap, MsiConfig};
 pub use self::msix::{MsixCap, MsixConfig, MsixTableEntry, MSIX_CONFIG_ID, MSIX_TABLE_ENTRY_SIZE};
-pub use self::vfio::{MmioRegion, VfioDmaMapping, VfioPciDevice, VfioPciError};
+pub use self::rom::PciRom;
+pub use self::vfio::{
+    MmioRegion, VfioDmaMapping, VfioPciDevice, VfioPciError, VfioResetMethod,
+    VfioResetMethodParseError, VfioVgaDevice, VGA_IO_PORT_RANGES,
+};
+pub use self::vfio_dirty::{vfio_dirty_log, vfio_start_dirty_log, vfio_stop_dirty_log};
 pub use self::vfio_user::{VfioUserDmaMapping, VfioUserPciDevice, VfioUserPciDeviceError};
 
 /// PCI has four interrupt pins A->D.
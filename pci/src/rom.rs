@@ -0,0 +1,131 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0 OR BSD-3-Clause
+//
+
+//! PCI option ROM support.
+//!
+//! An option ROM holds the firmware the guest firmware runs to initialize a
+//! device before booting, e.g. to boot from the network. It is exposed to
+//! the guest through the expansion ROM BAR of the device.
+
+use std::path::Path;
+use std::{fs, io};
+
+// Signature found at the start of each image of an option ROM.
+const PCI_ROM_SIGNATURE: [u8; 2] = [0x55, 0xaa];
+// The expansion ROM BAR decodes address bits 31:11.
+const PCI_ROM_MIN_SIZE: u64 = 2 << 10;
+// Largest ROM supported by the firmware of most platforms.
+const PCI_ROM_MAX_SIZE: u64 = 16 << 20;
+
+/// Content of an option ROM, served through an expansion ROM BAR.
+#[derive(Clone)]
+pub struct PciRom {
+    data: Vec<u8>,
+}
+
+impl PciRom {
+    /// Loads an option ROM from a file, checking it starts with the option
+    /// ROM signature and fits in an expansion ROM BAR.
+    pub fn from_file(path: &Path) -> io::Result<Self> {
+        let data = fs::read(path)?;
+
+        if !data.starts_with(&PCI_ROM_SIGNATURE) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} is not an option ROM", path.display()),
+            ));
+        }
+
+        if data.len() as u64 > PCI_ROM_MAX_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Option ROM {} is larger than {PCI_ROM_MAX_SIZE} bytes",
+                    path.display()
+                ),
+            ));
+        }
+
+        Ok(PciRom { data })
+    }
+
+    /// Size of the expansion ROM BAR holding the ROM, which must be a power
+    /// of two of at least 2 KiB.
+    pub fn bar_size(&self) -> u64 {
+        (self.data.len() as u64)
+            .next_power_of_two()
+            .max(PCI_ROM_MIN_SIZE)
+    }
+
+    /// Reads the ROM at `offset` from the start of the BAR, the part of the
+    /// BAR past the end of the ROM reading as zeros.
+    pub fn read(&self, offset: u64, data: &mut [u8]) {
+        data.fill(0);
+        let Ok(offset) = usize::try_from(offset) else {
+            return;
+        };
+        if let Some(rom) = self.data.get(offset..) {
+            let len = rom.len().min(data.len());
+            data[..len].copy_from_slice(&rom[..len]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vmm_sys_util::tempfile::TempFile;
+
+    use super::*;
+
+    fn rom_file(data: &[u8]) -> TempFile {
+        let file = TempFile::new().unwrap();
+        fs::write(file.as_path(), data).unwrap();
+        file
+    }
+
+    #[test]
+    fn test_pci_rom_from_file() {
+        let file = rom_file(&[0x55, 0xaa, 0x01]);
+        let rom = PciRom::from_file(file.as_path()).unwrap();
+        assert_eq!(rom.bar_size(), PCI_ROM_MIN_SIZE);
+
+        let mut data = vec![0x55, 0xaa];
+        data.resize(0x1001, 0);
+        let file = rom_file(&data);
+        let rom = PciRom::from_file(file.as_path()).unwrap();
+        assert_eq!(rom.bar_size(), 0x2000);
+
+        // Missing signature
+        let file = rom_file(&[0xaa, 0x55, 0x01]);
+        PciRom::from_file(file.as_path()).unwrap_err();
+
+        // Too large
+        let mut data = vec![0x55, 0xaa];
+        data.resize(PCI_ROM_MAX_SIZE as usize + 1, 0);
+        let file = rom_file(&data);
+        PciRom::from_file(file.as_path()).unwrap_err();
+    }
+
+    #[test]
+    fn test_pci_rom_read() {
+        let file = rom_file(&[0x55, 0xaa, 0x01, 0x02]);
+        let rom = PciRom::from_file(file.as_path()).unwrap();
+
+        let mut data = [0xff; 4];
+        rom.read(0, &mut data);
+        assert_eq!(data, [0x55, 0xaa, 0x01, 0x02]);
+
+        // Past the end of the ROM
+        let mut data = [0xff; 4];
+        rom.read(2, &mut data);
+        assert_eq!(data, [0x01, 0x02, 0, 0]);
+        let mut data = [0xff; 4];
+        rom.read(0x100, &mut data);
+        assert_eq!(data, [0; 4]);
+        let mut data = [0xff; 4];
+        rom.read(u64::MAX, &mut data);
+        assert_eq!(data, [0; 4]);
+    }
+}
@@ -5,11 +5,12 @@
 
 use std::any::Any;
 use std::collections::{BTreeMap, HashMap};
-use std::io;
-use std::os::unix::io::AsRawFd;
-use std::path::PathBuf;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
 use std::ptr::null_mut;
+use std::str::FromStr;
 use std::sync::{Arc, Barrier, Mutex};
+use std::{fs, io};
 
 use anyhow::anyhow;
 use byteorder::{ByteOrder, LittleEndian};
@@ -36,17 +37,39 @@ use vmm_sys_util::eventfd::EventFd;
 
 use crate::msi::{MsiConfigState, MSI_CONFIG_ID};
 use crate::msix::MsixConfigState;
+use crate::vfio_igd::{self, IgdOpRegion, IGD_ASLS_REG_IDX};
 use crate::{
     msi_num_enabled_vectors, BarReprogrammingParams, MsiCap, MsiConfig, MsixCap, MsixConfig,
     PciBarConfiguration, PciBarPrefetchable, PciBarRegionType, PciBdf, PciCapabilityId,
     PciClassCode, PciConfiguration, PciDevice, PciDeviceError, PciExpressCapabilityId,
-    PciHeaderType, PciSubclass, MSIX_CONFIG_ID, MSIX_TABLE_ENTRY_SIZE, PCI_CONFIGURATION_ID,
+    PciHeaderType, PciRom, PciSubclass, MSIX_CONFIG_ID, MSIX_TABLE_ENTRY_SIZE,
+    PCI_CONFIGURATION_ID,
 };
 
 pub(crate) const VFIO_COMMON_ID: &str = "vfio_common";
 
+// Smallest huge page size worth aligning BAR mappings on.
+const MIN_HUGE_PAGE_SIZE: u64 = 2 << 20;
+
+// Upper bound for the alignment of BAR mappings, matching the largest page
+// table entry size the kernel uses for PFN mappings.
+const BAR_MMAP_MAX_ALIGNMENT: u64 = 1 << 30;
+
+// Number of MSI-X vectors to wire. Some devices expose an MSI-X table larger
+// than the number of vectors the backend can actually signal, in which case
+// passing an eventfd for each table entry would get the whole request
+// rejected.
+fn msix_vector_count(table_entries: usize, supported_vectors: Option<u32>) -> usize {
+    match supported_vectors {
+        Some(count) => std::cmp::min(table_entries, count as usize),
+        None => table_entries,
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum VfioPciError {
+    #[error("Failed to copy the IGD OpRegion")]
+    CopyIgdOpRegion(#[source] io::Error),
     #[error("Failed to create user memory region")]
     CreateUserMemoryRegion(#[source] HypervisorVmError),
     #[error("Failed to DMA map: {0} for device {1} (guest BDF: {2})")]
@@ -75,6 +98,8 @@ pub enum VfioPciError {
     RetrievePciConfigurationState(#[source] anyhow::Error),
     #[error("Failed to retrieve VfioCommonState")]
     RetrieveVfioCommonState(#[source] anyhow::Error),
+    #[error("Failed to select reset method {1} for device {2}")]
+    SelectResetMethod(#[source] io::Error, &'static str, PathBuf),
 }
 
 #[derive(Copy, Clone)]
@@ -460,6 +485,8 @@ pub(crate) struct VfioCommon {
     pub(crate) vfio_wrapper: Arc<dyn Vfio>,
     pub(crate) patches: HashMap<usize, ConfigPatch>,
     x_nv_gpudirect_clique: Option<u8>,
+    // Option ROM replacing the one of the device
+    rom: Option<PciRom>,
 }
 
 impl VfioCommon {
@@ -471,6 +498,7 @@ impl VfioCommon {
         bdf: PciBdf,
         snapshot: Option<Snapshot>,
         x_nv_gpudirect_clique: Option<u8>,
+        rom: Option<PciRom>,
     ) -> Result<Self, VfioPciError> {
         let pci_configuration_state =
             vm_migration::state_from_id(snapshot.as_ref(), PCI_CONFIGURATION_ID).map_err(|e| {
@@ -507,6 +535,7 @@ impl VfioCommon {
             vfio_wrapper,
             patches: HashMap::new(),
             x_nv_gpudirect_clique,
+            rom,
         };
 
         let state: Option<VfioCommonState> = snapshot
@@ -616,6 +645,9 @@ impl VfioCommon {
                     bar_id += 1;
                     continue;
                 }
+            } else if bar_id == VFIO_PCI_ROM_REGION_INDEX && self.rom.is_some() {
+                // The option ROM comes from a file instead of the device.
+                region_size = self.rom.as_ref().map_or(0, PciRom::bar_size);
             } else {
                 let bar_offset = if bar_id == VFIO_PCI_ROM_REGION_INDEX {
                     (PCI_ROM_EXP_BAR_INDEX * 4) as u32
@@ -919,10 +951,19 @@ impl VfioCommon {
 
         let mut pci_express_cap_found = false;
         let mut power_management_cap_found = false;
+        let mut nv_gpudirect_cap_space_used = false;
 
         while cap_iter != 0 {
             let cap_id = self.vfio_wrapper.read_config_byte(cap_iter.into());
 
+            let cap_start = u32::from(cap_iter);
+            let cap_end = cap_start + self.capability_size(PciCapabilityId::from(cap_id), cap_iter);
+            if cap_start < NV_GPUDIRECT_CAP_OFFSET + NV_GPUDIRECT_CAP_SIZE
+                && NV_GPUDIRECT_CAP_OFFSET < cap_end
+            {
+                nv_gpudirect_cap_space_used = true;
+            }
+
             match PciCapabilityId::from(cap_id) {
                 PciCapabilityId::MessageSignalledInterrupts => {
                     if let Some(irq_info) = self.vfio_wrapper.get_irq_info(VFIO_PCI_MSI_IRQ_INDEX) {
@@ -965,7 +1006,22 @@ impl VfioCommon {
         }
 
         if let Some(clique_id) = self.x_nv_gpudirect_clique {
-            self.add_nv_gpudirect_clique_cap(cap_iter, clique_id);
+            let vendor_id = self
+                .vfio_wrapper
+                .read_config_word(PCI_CONFIG_VENDOR_ID_OFFSET);
+            if vendor_id != PCI_VENDOR_ID_NVIDIA {
+                warn!(
+                    "Ignoring GPUDirect clique {} for non NVIDIA device (vendor ID 0x{:04x})",
+                    clique_id, vendor_id
+                );
+            } else if nv_gpudirect_cap_space_used {
+                warn!(
+                    "Ignoring GPUDirect clique {}: capability space at 0x{:x} already in use",
+                    clique_id, NV_GPUDIRECT_CAP_OFFSET
+                );
+            } else {
+                self.add_nv_gpudirect_clique_cap(cap_iter, clique_id);
+            }
         }
 
         if pci_express_cap_found && power_management_cap_found {
@@ -973,10 +1029,32 @@ impl VfioCommon {
         }
     }
 
+    // Size of the capability at offset `cap`. Capabilities whose layout isn't
+    // known are assumed to only span their first dword.
+    fn capability_size(&self, cap_id: PciCapabilityId, cap: u8) -> u32 {
+        match cap_id {
+            PciCapabilityId::MessageSignalledInterrupts => MsiCap {
+                msg_ctl: self.vfio_wrapper.read_config_word(u32::from(cap) + 2),
+                ..Default::default()
+            }
+            .size() as u32,
+            PciCapabilityId::MsiX => 12,
+            PciCapabilityId::PowerManagement => 8,
+            PciCapabilityId::PciExpress => 0x3c,
+            PciCapabilityId::VendorSpecific => {
+                u32::from(self.vfio_wrapper.read_config_byte(u32::from(cap) + 2))
+            }
+            _ => 4,
+        }
+    }
+
+    // Expose the vendor specific "P2P" capability defined by NVIDIA, which
+    // the guest driver reads to find out which GPUs it can perform PCIe P2P
+    // between: all GPUs sharing the same clique ID.
     fn add_nv_gpudirect_clique_cap(&mut self, cap_iter: u8, clique_id: u8) {
         // Turing, Ampere, Hopper, and Lovelace GPUs have dedicated space
         // at 0xD4 for this capability.
-        let cap_offset = 0xd4u32;
+        let cap_offset = NV_GPUDIRECT_CAP_OFFSET;
 
         let reg_idx = (cap_iter / 4) as usize;
         self.patches.insert(
@@ -1094,8 +1172,22 @@ impl VfioCommon {
 
     pub(crate) fn enable_msix(&self) -> Result<(), VfioPciError> {
         if let Some(msix) = &self.interrupt.msix {
+            let table_entries = msix.bar.table_entries.len();
+            let num_vectors = msix_vector_count(
+                table_entries,
+                self.vfio_wrapper
+                    .get_irq_info(VFIO_PCI_MSIX_IRQ_INDEX)
+                    .map(|irq_info| irq_info.count),
+            );
+            if num_vectors < table_entries {
+                warn!(
+                    "MSI-X table has {} entries but only {} vectors are supported",
+                    table_entries, num_vectors
+                );
+            }
+
             let mut irq_fds: Vec<EventFd> = Vec::new();
-            for i in 0..msix.bar.table_entries.len() {
+            for i in 0..num_vectors {
                 if let Some(eventfd) = msix.interrupt_source_group.notifier(i as InterruptIndex) {
                     irq_fds.push(eventfd);
                 } else {
@@ -1190,7 +1282,13 @@ impl VfioCommon {
         if let Some(region) = self.find_region(addr) {
             let offset = addr - region.start.raw_value();
 
-            if self.interrupt.msix_table_accessed(region.index, offset) {
+            if let Some(rom) = self
+                .rom
+                .as_ref()
+                .filter(|_| region.index == VFIO_PCI_ROM_REGION_INDEX)
+            {
+                rom.read(offset, data);
+            } else if self.interrupt.msix_table_accessed(region.index, offset) {
                 self.interrupt.msix_read_table(offset, data);
             } else {
                 self.vfio_wrapper.region_read(region.index, offset, data);
@@ -1217,8 +1315,11 @@ impl VfioCommon {
         if let Some(region) = self.find_region(addr) {
             let offset = addr - region.start.raw_value();
 
-            // If the MSI-X table is written to, we need to update our cache.
-            if self.interrupt.msix_table_accessed(region.index, offset) {
+            // The option ROM is read-only, and if the MSI-X table is written
+            // to, we need to update our cache.
+            if region.index == VFIO_PCI_ROM_REGION_INDEX && self.rom.is_some() {
+                warn!("Ignoring write to the option ROM at offset 0x{:x}", offset);
+            } else if self.interrupt.msix_table_accessed(region.index, offset) {
                 self.interrupt.msix_write_table(offset, data);
             } else {
                 self.vfio_wrapper.region_write(region.index, offset, data);
@@ -1434,6 +1535,67 @@ impl Snapshottable for VfioCommon {
     }
 }
 
+/// Reset applied to a VFIO PCI device when it gets attached to or detached
+/// from the VM.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum VfioResetMethod {
+    /// Let the kernel pick the best reset method available for the device.
+    #[default]
+    Auto,
+    /// Function level reset only.
+    Flr,
+    /// Secondary bus reset only.
+    Bus,
+    /// Don't reset the device.
+    None,
+}
+
+#[derive(Debug, Error)]
+pub enum VfioResetMethodParseError {
+    #[error("Invalid reset method: {0}")]
+    InvalidValue(String),
+}
+
+impl FromStr for VfioResetMethod {
+    type Err = VfioResetMethodParseError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(VfioResetMethod::Auto),
+            "flr" => Ok(VfioResetMethod::Flr),
+            "bus" => Ok(VfioResetMethod::Bus),
+            "none" => Ok(VfioResetMethod::None),
+            _ => Err(VfioResetMethodParseError::InvalidValue(s.to_owned())),
+        }
+    }
+}
+
+// Reset the device through VFIO_DEVICE_RESET. When a specific method is
+// requested, the kernel is first restricted to it through the sysfs
+// `reset_method` attribute of the device, and the device is left untouched
+// when that fails.
+fn reset_device(
+    device: &VfioDevice,
+    device_path: &Path,
+    reset_method: VfioResetMethod,
+) -> Result<(), VfioPciError> {
+    let method = match reset_method {
+        VfioResetMethod::None => return Ok(()),
+        VfioResetMethod::Auto => None,
+        VfioResetMethod::Flr => Some("flr"),
+        VfioResetMethod::Bus => Some("bus"),
+    };
+
+    if let Some(method) = method {
+        fs::write(device_path.join("reset_method"), method)
+            .map_err(|e| VfioPciError::SelectResetMethod(e, method, device_path.to_path_buf()))?;
+    }
+
+    device.reset();
+
+    Ok(())
+}
+
 /// VfioPciDevice represents a VFIO PCI device.
 /// This structure implements the BusDevice and PciDevice traits.
 ///
@@ -1450,6 +1612,12 @@ pub struct VfioPciDevice {
     memory_slot_allocator: MemorySlotAllocator,
     bdf: PciBdf,
     device_path: PathBuf,
+    p2p_dma: bool,
+    reset_method: VfioResetMethod,
+    reset_on_detach: bool,
+    igd: bool,
+    igd_opregion: Option<IgdOpRegion>,
+    vga_device: Option<Arc<Mutex<VfioVgaDevice>>>,
 }
 
 impl VfioPciDevice {
@@ -1468,9 +1636,13 @@ impl VfioPciDevice {
         snapshot: Option<Snapshot>,
         x_nv_gpudirect_clique: Option<u8>,
         device_path: PathBuf,
+        p2p_dma: bool,
+        reset_method: VfioResetMethod,
+        reset_on_detach: bool,
+        rom: Option<PciRom>,
     ) -> Result<Self, VfioPciError> {
         let device = Arc::new(device);
-        device.reset();
+        reset_device(&device, &device_path, reset_method)?;
 
         let vfio_wrapper = VfioDeviceWrapper::new(Arc::clone(&device));
 
@@ -1482,8 +1654,23 @@ impl VfioPciDevice {
             bdf,
             vm_migration::snapshot_from_id(snapshot.as_ref(), VFIO_COMMON_ID),
             x_nv_gpudirect_clique,
+            rom,
         )?;
 
+        let igd = vfio_igd::is_igd(
+            common
+                .vfio_wrapper
+                .read_config_word(PCI_CONFIG_VENDOR_ID_OFFSET),
+            common
+                .vfio_wrapper
+                .read_config_byte(PCI_CONFIG_BASE_CLASS_OFFSET),
+        );
+        let igd_opregion = if igd {
+            IgdOpRegion::new(&device).map_err(VfioPciError::CopyIgdOpRegion)?
+        } else {
+            None
+        };
+
         let vfio_pci_device = VfioPciDevice {
             id,
             vm: vm.clone(),
@@ -1494,6 +1681,12 @@ impl VfioPciDevice {
             memory_slot_allocator,
             bdf,
             device_path: device_path.clone(),
+            p2p_dma,
+            reset_method,
+            reset_on_detach,
+            igd,
+            igd_opregion,
+            vga_device: None,
         };
 
         Ok(vfio_pci_device)
@@ -1503,6 +1696,106 @@ impl VfioPciDevice {
         self.iommu_attached
     }
 
+    /// Host bridge registers which must be mirrored on the guest host
+    /// bridge for the guest driver of an Intel IGD to identify the platform.
+    pub fn igd_host_bridge_registers(&self) -> Option<Vec<(usize, u32)>> {
+        if self.igd {
+            vfio_igd::host_bridge_registers(&self.device)
+        } else {
+            None
+        }
+    }
+
+    /// Create the device forwarding the legacy VGA ranges to the device,
+    /// if the VGA region is exposed through VFIO.
+    pub fn enable_legacy_vga(&mut self) -> Option<Arc<Mutex<VfioVgaDevice>>> {
+        if self.device.get_region_size(VFIO_PCI_VGA_REGION_INDEX) == 0 {
+            return None;
+        }
+
+        let vga_device = Arc::new(Mutex::new(VfioVgaDevice {
+            device: Arc::clone(&self.device),
+        }));
+        self.vga_device = Some(vga_device.clone());
+
+        Some(vga_device)
+    }
+
+    pub fn legacy_vga_device(&self) -> Option<Arc<Mutex<VfioVgaDevice>>> {
+        self.vga_device.clone()
+    }
+
+    // Expose the copy of the OpRegion to the guest in the 32-bit MMIO
+    // space, and point the ASL Storage register to it.
+    fn map_igd_opregion(&mut self, mmio32_allocator: &mut AddressAllocator) {
+        let Some(opregion) = self.igd_opregion.as_mut() else {
+            return;
+        };
+
+        let Some(guest_addr) = mmio32_allocator.allocate(None, opregion.size, Some(opregion.size))
+        else {
+            error!("Could not allocate guest address for the IGD OpRegion");
+            return;
+        };
+
+        let slot = self.memory_slot_allocator.next_memory_slot();
+        let mem_region = self.vm.make_user_memory_region(
+            slot,
+            guest_addr.raw_value(),
+            opregion.size,
+            opregion.host_addr,
+            false,
+            false,
+        );
+        if let Err(e) = self.vm.create_user_memory_region(mem_region) {
+            error!("Could not map the IGD OpRegion: {}", e);
+            self.memory_slot_allocator.free_memory_slot(slot);
+            mmio32_allocator.free(guest_addr, opregion.size);
+            return;
+        }
+
+        opregion.guest_addr = Some(guest_addr);
+        opregion.slot = slot;
+        self.common.patches.insert(
+            IGD_ASLS_REG_IDX,
+            ConfigPatch {
+                mask: 0xffff_ffff,
+                patch: guest_addr.raw_value() as u32,
+            },
+        );
+    }
+
+    fn unmap_igd_opregion(&mut self, mmio32_allocator: &mut AddressAllocator) {
+        let Some(opregion) = self.igd_opregion.as_mut() else {
+            return;
+        };
+        let Some(guest_addr) = opregion.guest_addr.take() else {
+            return;
+        };
+
+        let mem_region = self.vm.make_user_memory_region(
+            opregion.slot,
+            guest_addr.raw_value(),
+            opregion.size,
+            opregion.host_addr,
+            false,
+            false,
+        );
+        if let Err(e) = self.vm.remove_user_memory_region(mem_region) {
+            error!("Could not unmap the IGD OpRegion: {}", e);
+        }
+        self.memory_slot_allocator.free_memory_slot(opregion.slot);
+        mmio32_allocator.free(guest_addr, opregion.size);
+        self.common.patches.remove(&IGD_ASLS_REG_IDX);
+    }
+
+    // The BARs are mapped into the VFIO container shared with the other
+    // devices not attached to the virtual IOMMU, so that peer-to-peer DMA
+    // can reach them.
+    fn dma_map_bars(&self) -> bool {
+        self.p2p_dma && !self.iommu_attached
+    }
+
     fn generate_sparse_areas(
         caps: &[VfioRegionInfoCap],
         region_index: u32,
@@ -1587,6 +1880,103 @@ impl VfioPciDevice {
         }])
     }
 
+    // Map a sparse area of a region at a host virtual address sharing the
+    // alignment of the area within the BAR, up to 1GiB. This lets the kernel
+    // back huge BARs with PMD or PUD sized entries when the guest faults them
+    // in, rather than populating them page by page. Nothing gets populated at
+    // mmap time, the cost being only paid for the parts the guest touches.
+    pub(crate) fn mmap_sparse_area(
+        fd: RawFd,
+        prot: libc::c_int,
+        region_offset: u64,
+        area_offset: u64,
+        area_size: u64,
+    ) -> io::Result<*mut libc::c_void> {
+        let file_offset = (region_offset + area_offset) as libc::off_t;
+        // Largest power of two fitting both the size and offset of the area.
+        let mut align = 1u64 << (u64::BITS - 1 - area_size.leading_zeros());
+        if area_offset != 0 {
+            align = std::cmp::min(align, 1u64 << area_offset.trailing_zeros());
+        }
+        let align = std::cmp::min(align, BAR_MMAP_MAX_ALIGNMENT);
+
+        if align <= MIN_HUGE_PAGE_SIZE {
+            // SAFETY: FFI call with correct arguments
+            let host_addr = unsafe {
+                libc::mmap(
+                    null_mut(),
+                    area_size as usize,
+                    prot,
+                    libc::MAP_SHARED,
+                    fd,
+                    file_offset,
+                )
+            };
+            if std::ptr::eq(host_addr, libc::MAP_FAILED) {
+                return Err(io::Error::last_os_error());
+            }
+            return Ok(host_addr);
+        }
+
+        // Reserve enough address space to find an aligned range within it.
+        let reserved_size = (area_size + align) as usize;
+        // SAFETY: FFI call with correct arguments
+        let reserved = unsafe {
+            libc::mmap(
+                null_mut(),
+                reserved_size,
+                libc::PROT_NONE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE,
+                -1,
+                0,
+            )
+        };
+        if std::ptr::eq(reserved, libc::MAP_FAILED) {
+            return Err(io::Error::last_os_error());
+        }
+
+        let reserved_start = reserved as u64;
+        let reserved_end = reserved_start + reserved_size as u64;
+        let aligned_start = (reserved_start + align - 1) & !(align - 1);
+        let aligned_end = aligned_start + area_size;
+
+        // SAFETY: FFI call with correct arguments, the target range being
+        // part of the reservation made above.
+        let host_addr = unsafe {
+            libc::mmap(
+                aligned_start as *mut libc::c_void,
+                area_size as usize,
+                prot,
+                libc::MAP_SHARED | libc::MAP_FIXED,
+                fd,
+                file_offset,
+            )
+        };
+        if std::ptr::eq(host_addr, libc::MAP_FAILED) {
+            let err = io::Error::last_os_error();
+            // SAFETY: FFI call with correct arguments
+            unsafe { libc::munmap(reserved, reserved_size) };
+            return Err(err);
+        }
+
+        // Release the parts of the reservation left around the mapping.
+        // SAFETY: FFI calls with correct arguments, both ranges being part
+        // of the reservation made above.
+        unsafe {
+            if aligned_start > reserved_start {
+                libc::munmap(reserved, (aligned_start - reserved_start) as usize);
+            }
+            if reserved_end > aligned_end {
+                libc::munmap(
+                    aligned_end as *mut libc::c_void,
+                    (reserved_end - aligned_end) as usize,
+                );
+            }
+        }
+
+        Ok(host_addr)
+    }
+
     /// Map MMIO regions into the guest, and avoid VM exits when the guest tries
     /// to reach those regions.
     ///
@@ -1597,8 +1987,14 @@ impl VfioPciDevice {
     /// * `mem_slot` - The closure to return a memory slot.
     pub fn map_mmio_regions(&mut self) -> Result<(), VfioPciError> {
         let fd = self.device.as_raw_fd();
+        let dma_map_bars = self.dma_map_bars();
 
         for region in self.common.mmio_regions.iter_mut() {
+            // An option ROM given as a file is emulated.
+            if region.index == VFIO_PCI_ROM_REGION_INDEX && self.common.rom.is_some() {
+                continue;
+            }
+
             let region_flags = self.device.get_region_flags(region.index);
             if region_flags & VFIO_REGION_INFO_FLAG_MMAP != 0 {
                 let mut prot = 0;
@@ -1618,10 +2014,15 @@ impl VfioPciDevice {
 
                 // Don't try to mmap the region if it contains MSI-X table or
                 // MSI-X PBA subregion, and if we couldn't find MSIX_MAPPABLE
-                // in the list of supported capabilities.
+                // in the list of supported capabilities. A sparse mmap
+                // capability still allows mapping everything but the MSI-X
+                // structures, which is what the kernel reports for such BARs.
                 if let Some(msix) = self.common.interrupt.msix.as_ref() {
                     if (region.index == msix.cap.table_bir() || region.index == msix.cap.pba_bir())
                         && !caps.contains(&VfioRegionInfoCap::MsixMappable)
+                        && !caps
+                            .iter()
+                            .any(|cap| matches!(cap, VfioRegionInfoCap::SparseMmap(_)))
                     {
                         continue;
                     }
@@ -1639,37 +2040,31 @@ impl VfioPciDevice {
                 )?;
 
                 for area in sparse_areas.iter() {
-                    // SAFETY: FFI call with correct arguments
-                    let host_addr = unsafe {
-                        libc::mmap(
-                            null_mut(),
-                            area.size as usize,
-                            prot,
-                            libc::MAP_SHARED,
-                            fd,
-                            mmap_offset as libc::off_t + area.offset as libc::off_t,
-                        )
-                    };
-
-                    if std::ptr::eq(host_addr, libc::MAP_FAILED) {
-                        error!(
-                            "Could not mmap sparse area (offset = 0x{:x}, size = 0x{:x}): {}",
-                            area.offset,
-                            area.size,
-                            std::io::Error::last_os_error()
-                        );
-                        return Err(VfioPciError::MmapArea);
+                    if area.size == 0 {
+                        continue;
                     }
 
+                    // Such areas are left trapped, accesses being emulated
+                    // through the VFIO region read/write operations.
                     if !is_page_size_aligned(area.size) || !is_page_size_aligned(area.offset) {
                         warn!(
                             "Could not mmap sparse area that is not page size aligned (offset = 0x{:x}, size = 0x{:x})",
                             area.offset,
                             area.size,
                             );
-                        return Ok(());
+                        continue;
                     }
 
+                    let host_addr =
+                        Self::mmap_sparse_area(fd, prot, mmap_offset, area.offset, area.size)
+                            .map_err(|e| {
+                                error!(
+                            "Could not mmap sparse area (offset = 0x{:x}, size = 0x{:x}): {}",
+                            area.offset, area.size, e
+                        );
+                                VfioPciError::MmapArea
+                            })?;
+
                     let user_memory_region = UserMemoryRegion {
                         slot: self.memory_slot_allocator.next_memory_slot(),
                         start: region.start.0 + area.offset,
@@ -1692,7 +2087,7 @@ impl VfioPciDevice {
                         .create_user_memory_region(mem_region)
                         .map_err(VfioPciError::CreateUserMemoryRegion)?;
 
-                    if !self.iommu_attached {
+                    if dma_map_bars {
                         self.container
                             .vfio_dma_map(
                                 user_memory_region.start,
@@ -1711,10 +2106,11 @@ impl VfioPciDevice {
     }
 
     pub fn unmap_mmio_regions(&mut self) {
+        let dma_map_bars = self.dma_map_bars();
         for region in self.common.mmio_regions.iter() {
             for user_memory_region in region.user_memory_regions.iter() {
                 // Unmap from vfio container
-                if !self.iommu_attached {
+                if dma_map_bars {
                     if let Err(e) = self
                         .container
                         .vfio_dma_unmap(user_memory_region.start, user_memory_region.size)
@@ -1807,6 +2203,25 @@ impl Drop for VfioPciDevice {
         if self.common.interrupt.intx_in_use() {
             self.common.disable_intx();
         }
+
+        if self.reset_on_detach {
+            if let Err(e) = reset_device(&self.device, &self.device_path, self.reset_method) {
+                warn!("Failed to reset device on detach: {}", e);
+            }
+        }
+
+        // Give the kernel back its default set of reset methods.
+        if matches!(
+            self.reset_method,
+            VfioResetMethod::Flr | VfioResetMethod::Bus
+        ) {
+            if let Err(e) = fs::write(self.device_path.join("reset_method"), "default") {
+                warn!(
+                    "Failed to restore default reset method for {:?}: {}",
+                    self.device_path, e
+                );
+            }
+        }
     }
 }
 
@@ -1820,6 +2235,38 @@ impl BusDevice for VfioPciDevice {
     }
 }
 
+/// Legacy VGA I/O port ranges, as (base, length) pairs.
+pub const VGA_IO_PORT_RANGES: [(u64, u64); 2] = [(0x3b0, 0xc), (0x3c0, 0x20)];
+
+/// Forwards accesses to the legacy VGA ranges to the VGA region of a VFIO
+/// device, where offsets match the legacy addresses.
+pub struct VfioVgaDevice {
+    device: Arc<VfioDevice>,
+}
+
+impl BusDevice for VfioVgaDevice {
+    fn read(&mut self, base: u64, offset: u64, data: &mut [u8]) {
+        self.device
+            .region_read(VFIO_PCI_VGA_REGION_INDEX, data, base + offset)
+    }
+
+    fn write(&mut self, base: u64, offset: u64, data: &[u8]) -> Option<Arc<Barrier>> {
+        self.device
+            .region_write(VFIO_PCI_VGA_REGION_INDEX, data, base + offset);
+        None
+    }
+}
+
+// NVIDIA PCI vendor ID.
+const PCI_VENDOR_ID_NVIDIA: u16 = 0x10de;
+// Location and size of the NVIDIA GPUDirect P2P clique capability.
+const NV_GPUDIRECT_CAP_OFFSET: u32 = 0xd4;
+const NV_GPUDIRECT_CAP_SIZE: u32 = 8;
+
+// Offset of the 16-bit vendor ID register in the PCI configuration space.
+const PCI_CONFIG_VENDOR_ID_OFFSET: u32 = 0x00;
+// Offset of the base class code byte in the PCI configuration space.
+const PCI_CONFIG_BASE_CLASS_OFFSET: u32 = 0x0b;
 // Offset of the 16-bit status register in the PCI configuration space.
 const PCI_CONFIG_STATUS_OFFSET: u32 = 0x06;
 // Status bit indicating the presence of a capabilities list.
@@ -1857,8 +2304,11 @@ impl PciDevice for VfioPciDevice {
         mmio64_allocator: &mut AddressAllocator,
         resources: Option<Vec<Resource>>,
     ) -> Result<Vec<PciBarConfiguration>, PciDeviceError> {
-        self.common
-            .allocate_bars(allocator, mmio32_allocator, mmio64_allocator, resources)
+        let bars =
+            self.common
+                .allocate_bars(allocator, mmio32_allocator, mmio64_allocator, resources)?;
+        self.map_igd_opregion(mmio32_allocator);
+        Ok(bars)
     }
 
     fn free_bars(
@@ -1867,6 +2317,7 @@ impl PciDevice for VfioPciDevice {
         mmio32_allocator: &mut AddressAllocator,
         mmio64_allocator: &mut AddressAllocator,
     ) -> Result<(), PciDeviceError> {
+        self.unmap_igd_opregion(mmio32_allocator);
         self.common
             .free_bars(allocator, mmio32_allocator, mmio64_allocator)
     }
@@ -1893,13 +2344,14 @@ impl PciDevice for VfioPciDevice {
     }
 
     fn move_bar(&mut self, old_base: u64, new_base: u64) -> Result<(), io::Error> {
+        let dma_map_bars = self.dma_map_bars();
         for region in self.common.mmio_regions.iter_mut() {
             if region.start.raw_value() == old_base {
                 region.start = GuestAddress(new_base);
 
                 for user_memory_region in region.user_memory_regions.iter_mut() {
                     // Unmap the old MMIO region from vfio container
-                    if !self.iommu_attached {
+                    if dma_map_bars {
                         if let Err(e) = self
                             .container
                             .vfio_dma_unmap(user_memory_region.start, user_memory_region.size)
@@ -1951,7 +2403,7 @@ impl PciDevice for VfioPciDevice {
                         .map_err(io::Error::other)?;
 
                     // Map the moved mmio region to vfio container
-                    if !self.iommu_attached {
+                    if dma_map_bars {
                         self.container
                             .vfio_dma_map(
                                 user_memory_region.start,
@@ -2002,7 +2454,20 @@ impl Snapshottable for VfioPciDevice {
     }
 }
 impl Transportable for VfioPciDevice {}
-impl Migratable for VfioPciDevice {}
+impl Migratable for VfioPciDevice {
+    fn start_dirty_log(&mut self) -> std::result::Result<(), MigratableError> {
+        // DMA writes are tracked per container by the device manager, using
+        // guest physical addresses as IOVAs. This doesn't hold for devices
+        // attached to the virtual IOMMU, which are mapped with guest IOVAs.
+        if self.iommu_attached {
+            return Err(MigratableError::StartDirtyLog(anyhow!(
+                "DMA dirty page tracking is not supported for VFIO devices attached to a virtual IOMMU"
+            )));
+        }
+
+        Ok(())
+    }
+}
 
 /// This structure implements the ExternalDmaMapping trait. It is meant to
 /// be used when the caller tries to provide a way to update the mappings
@@ -2072,3 +2537,61 @@ impl<M: GuestAddressSpace + Sync + Send> ExternalDmaMapping for VfioDmaMapping<M
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::FileExt;
+
+    use vmm_sys_util::tempfile::TempFile;
+
+    use super::*;
+
+    #[test]
+    fn test_msix_vector_count() {
+        assert_eq!(msix_vector_count(64, None), 64);
+        assert_eq!(msix_vector_count(64, Some(64)), 64);
+        assert_eq!(msix_vector_count(64, Some(128)), 64);
+        // Table larger than what the backend supports
+        assert_eq!(msix_vector_count(2048, Some(64)), 64);
+        assert_eq!(msix_vector_count(16, Some(0)), 0);
+    }
+
+    #[test]
+    fn test_mmap_sparse_area() {
+        let file = TempFile::new().unwrap().into_file();
+        file.set_len(8 << 20).unwrap();
+        let fd = file.as_raw_fd();
+        let prot = libc::PROT_READ | libc::PROT_WRITE;
+
+        // Large areas are mapped at an address sharing their alignment
+        // within the region.
+        let host_addr = VfioPciDevice::mmap_sparse_area(fd, prot, 0, 4 << 20, 4 << 20).unwrap();
+        assert_eq!(host_addr as u64 % (4 << 20), 0);
+        // SAFETY: the mapping is 4MiB long and writable
+        unsafe { *(host_addr as *mut u8) = 0xaa };
+        let mut data = [0u8; 1];
+        file.read_exact_at(&mut data, 4 << 20).unwrap();
+        assert_eq!(data[0], 0xaa);
+        // SAFETY: unmapping the mapping created above
+        unsafe { libc::munmap(host_addr, 4 << 20) };
+
+        // The region offset is part of the file offset.
+        let host_addr =
+            VfioPciDevice::mmap_sparse_area(fd, prot, 2 << 20, 2 << 20, 4 << 20).unwrap();
+        // SAFETY: the mapping is 4MiB long and writable
+        unsafe { *(host_addr as *mut u8) = 0x55 };
+        file.read_exact_at(&mut data, 4 << 20).unwrap();
+        assert_eq!(data[0], 0x55);
+        // SAFETY: unmapping the mapping created above
+        unsafe { libc::munmap(host_addr, 4 << 20) };
+
+        // Small areas are mapped as is.
+        let host_addr = VfioPciDevice::mmap_sparse_area(fd, prot, 0, 0x1000, 0x1000).unwrap();
+        assert_eq!(host_addr as u64 % 0x1000, 0);
+        // SAFETY: unmapping the mapping created above
+        unsafe { libc::munmap(host_addr, 0x1000) };
+
+        // Invalid file descriptor
+        VfioPciDevice::mmap_sparse_area(-1, prot, 0, 0, 4 << 20).unwrap_err();
+    }
+}
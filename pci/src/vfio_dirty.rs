@@ -0,0 +1,136 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0 OR BSD-3-Clause
+//
+
+//! DMA dirty page tracking for VFIO containers.
+//!
+//! Assigned devices write to guest memory behind the back of the
+//! hypervisor, meaning these writes never show up in its dirty log. The
+//! type1 IOMMU backend can track them on behalf of the VMM for every
+//! mapping of a container, based on the pages pinned by the devices. If any
+//! device of the container doesn't pin the pages it accesses, as physical
+//! devices don't, every mapped page is reported as dirty, so this is only
+//! meaningful for mediated devices. Relying on the dirty bits of the host
+//! IOMMU instead requires iommufd.
+
+use std::io;
+use std::mem::size_of;
+
+use vfio_ioctls::VfioContainer;
+use vm_allocator::page_size::get_page_size;
+use vm_migration::protocol::MemoryRangeTable;
+use vmm_sys_util::ioctl::ioctl_with_ref;
+use vmm_sys_util::ioctl_io_nr;
+
+const VFIO_TYPE: u32 = 0x3b;
+const VFIO_BASE: u32 = 100;
+
+ioctl_io_nr!(VFIO_IOMMU_DIRTY_PAGES, VFIO_TYPE, VFIO_BASE + 17);
+
+const VFIO_IOMMU_DIRTY_PAGES_FLAG_START: u32 = 1 << 0;
+const VFIO_IOMMU_DIRTY_PAGES_FLAG_STOP: u32 = 1 << 1;
+const VFIO_IOMMU_DIRTY_PAGES_FLAG_GET_BITMAP: u32 = 1 << 2;
+
+// struct vfio_iommu_type1_dirty_bitmap
+#[repr(C)]
+struct VfioDirtyBitmap {
+    argsz: u32,
+    flags: u32,
+}
+
+// struct vfio_iommu_type1_dirty_bitmap followed by its
+// struct vfio_iommu_type1_dirty_bitmap_get payload.
+#[repr(C)]
+struct VfioDirtyBitmapGet {
+    argsz: u32,
+    flags: u32,
+    iova: u64,
+    size: u64,
+    bitmap_pgsize: u64,
+    bitmap_size: u64,
+    bitmap_data: u64,
+}
+
+fn dirty_pages_ioctl<T>(container: &VfioContainer, arg: &T) -> io::Result<()> {
+    // SAFETY: FFI call with a valid container fd and argument, the kernel
+    // only writing to the bitmap buffer the argument points to, if any.
+    let ret = unsafe { ioctl_with_ref(container, VFIO_IOMMU_DIRTY_PAGES(), arg) };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+fn set_dirty_tracking(container: &VfioContainer, flags: u32) -> io::Result<()> {
+    let arg = VfioDirtyBitmap {
+        argsz: size_of::<VfioDirtyBitmap>() as u32,
+        flags,
+    };
+
+    dirty_pages_ioctl(container, &arg)
+}
+
+/// Start tracking the pages written by the devices of the container. This
+/// fails if the host IOMMU backend doesn't support dirty page tracking, but
+/// not if some devices of the container can't be tracked precisely, which is
+/// up to the caller to check.
+pub fn vfio_start_dirty_log(container: &VfioContainer) -> io::Result<()> {
+    set_dirty_tracking(container, VFIO_IOMMU_DIRTY_PAGES_FLAG_START)
+}
+
+/// Stop tracking the pages written by the devices of the container.
+pub fn vfio_stop_dirty_log(container: &VfioContainer) -> io::Result<()> {
+    set_dirty_tracking(container, VFIO_IOMMU_DIRTY_PAGES_FLAG_STOP)
+}
+
+/// Retrieve, and clear, the pages written by the devices of the container
+/// within the given range. The range can't split any DMA mapping of the
+/// container, and IOVAs are expected to be guest physical addresses.
+pub fn vfio_dirty_log(
+    container: &VfioContainer,
+    iova: u64,
+    size: u64,
+) -> io::Result<MemoryRangeTable> {
+    let page_size = get_page_size();
+    let num_pages = size.div_ceil(page_size);
+    let mut bitmap = vec![0u64; num_pages.div_ceil(64) as usize];
+
+    let arg = VfioDirtyBitmapGet {
+        argsz: size_of::<VfioDirtyBitmapGet>() as u32,
+        flags: VFIO_IOMMU_DIRTY_PAGES_FLAG_GET_BITMAP,
+        iova,
+        size,
+        bitmap_pgsize: page_size,
+        bitmap_size: (bitmap.len() * size_of::<u64>()) as u64,
+        bitmap_data: bitmap.as_mut_ptr() as u64,
+    };
+
+    dirty_pages_ioctl(container, &arg)?;
+
+    Ok(MemoryRangeTable::from_bitmap(bitmap, iova, page_size))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::offset_of;
+
+    use super::*;
+
+    #[test]
+    fn test_dirty_bitmap_layout() {
+        // Matches struct vfio_iommu_type1_dirty_bitmap
+        assert_eq!(size_of::<VfioDirtyBitmap>(), 8);
+
+        // Matches struct vfio_iommu_type1_dirty_bitmap followed by
+        // struct vfio_iommu_type1_dirty_bitmap_get, whose bitmap field is a
+        // struct vfio_bitmap.
+        assert_eq!(size_of::<VfioDirtyBitmapGet>(), 48);
+        assert_eq!(offset_of!(VfioDirtyBitmapGet, iova), 8);
+        assert_eq!(offset_of!(VfioDirtyBitmapGet, size), 16);
+        assert_eq!(offset_of!(VfioDirtyBitmapGet, bitmap_pgsize), 24);
+        assert_eq!(offset_of!(VfioDirtyBitmapGet, bitmap_size), 32);
+        assert_eq!(offset_of!(VfioDirtyBitmapGet, bitmap_data), 40);
+    }
+}
@@ -0,0 +1,128 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0 OR BSD-3-Clause
+//
+
+//! Intel integrated graphics device (IGD) passthrough support.
+//!
+//! Besides the device itself, the guest graphics driver relies on the
+//! OpRegion, a memory area shared with the host firmware describing the
+//! display outputs, which it finds through the ASL Storage register of the
+//! device. It also identifies the platform through the host bridge. VFIO
+//! exposes both through Intel specific device regions.
+
+use std::io;
+use std::ptr::null_mut;
+
+use vfio_bindings::bindings::vfio::VFIO_PCI_NUM_REGIONS;
+use vfio_ioctls::{VfioDevice, VfioRegionInfoCap};
+use vm_allocator::page_size::align_page_size_up;
+use vm_memory::GuestAddress;
+
+const PCI_VENDOR_ID_INTEL: u16 = 0x8086;
+const PCI_CLASS_DISPLAY: u8 = 0x03;
+
+const VFIO_REGION_TYPE_PCI_VENDOR_TYPE: u32 = 1 << 31;
+const VFIO_REGION_SUBTYPE_INTEL_IGD_OPREGION: u32 = 1;
+const VFIO_REGION_SUBTYPE_INTEL_IGD_HOST_CFG: u32 = 2;
+
+// Device specific regions follow the standard ones, there are only a
+// handful of them at most.
+const VFIO_MAX_DEVICE_SPECIFIC_REGIONS: u32 = 16;
+
+/// ASL Storage register, holding the guest address of the OpRegion.
+pub(crate) const IGD_ASLS_REG_IDX: usize = 0xfc / 4;
+
+// Host bridge registers mirrored to the guest: vendor and device IDs,
+// revision and class code, subsystem vendor and device IDs.
+const HOST_BRIDGE_MIRRORED_REGS: [usize; 3] = [0, 2, 11];
+
+/// Tell if the device is an Intel display controller, based on its vendor
+/// ID and the base class from its configuration space.
+pub(crate) fn is_igd(vendor_id: u16, class: u8) -> bool {
+    vendor_id == PCI_VENDOR_ID_INTEL && class == PCI_CLASS_DISPLAY
+}
+
+fn find_intel_region(device: &VfioDevice, subtype: u32) -> Option<u32> {
+    (VFIO_PCI_NUM_REGIONS..VFIO_PCI_NUM_REGIONS + VFIO_MAX_DEVICE_SPECIFIC_REGIONS)
+        .take_while(|index| device.get_region_size(*index) != 0)
+        .find(|index| {
+            device.get_region_caps(*index).iter().any(|cap| {
+                matches!(cap, VfioRegionInfoCap::Type(t)
+                    if t.type_ == (VFIO_REGION_TYPE_PCI_VENDOR_TYPE | u32::from(PCI_VENDOR_ID_INTEL))
+                        && t.subtype == subtype)
+            })
+        })
+}
+
+/// Read the host bridge registers the guest graphics driver relies on, from
+/// the read-only copy of the host bridge configuration space VFIO exposes.
+pub(crate) fn host_bridge_registers(device: &VfioDevice) -> Option<Vec<(usize, u32)>> {
+    let index = find_intel_region(device, VFIO_REGION_SUBTYPE_INTEL_IGD_HOST_CFG)?;
+
+    Some(
+        HOST_BRIDGE_MIRRORED_REGS
+            .iter()
+            .map(|reg_idx| {
+                let mut data = [0u8; 4];
+                device.region_read(index, &mut data, (reg_idx * 4) as u64);
+                (*reg_idx, u32::from_le_bytes(data))
+            })
+            .collect(),
+    )
+}
+
+/// Copy of the host OpRegion, exposed to the guest as a memory region.
+pub(crate) struct IgdOpRegion {
+    pub(crate) host_addr: u64,
+    pub(crate) size: u64,
+    pub(crate) guest_addr: Option<GuestAddress>,
+    pub(crate) slot: u32,
+}
+
+impl IgdOpRegion {
+    /// Copy the OpRegion of the device, if VFIO exposes one.
+    pub(crate) fn new(device: &VfioDevice) -> io::Result<Option<Self>> {
+        let Some(index) = find_intel_region(device, VFIO_REGION_SUBTYPE_INTEL_IGD_OPREGION) else {
+            return Ok(None);
+        };
+
+        let region_size = device.get_region_size(index);
+        let size = align_page_size_up(region_size);
+
+        // SAFETY: FFI call with correct arguments
+        let host_addr = unsafe {
+            libc::mmap(
+                null_mut(),
+                size as usize,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE,
+                -1,
+                0,
+            )
+        };
+        if std::ptr::eq(host_addr, libc::MAP_FAILED) {
+            return Err(io::Error::last_os_error());
+        }
+
+        // SAFETY: the mapping was just created with at least region_size
+        // bytes and isn't shared with anything else yet.
+        let data =
+            unsafe { std::slice::from_raw_parts_mut(host_addr as *mut u8, region_size as usize) };
+        device.region_read(index, data, 0);
+
+        Ok(Some(IgdOpRegion {
+            host_addr: host_addr as u64,
+            size,
+            guest_addr: None,
+            slot: 0,
+        }))
+    }
+}
+
+impl Drop for IgdOpRegion {
+    fn drop(&mut self) {
+        // SAFETY: FFI call with correct arguments
+        unsafe { libc::munmap(self.host_addr as *mut libc::c_void, self.size as usize) };
+    }
+}
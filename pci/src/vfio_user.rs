@@ -5,7 +5,6 @@
 
 use std::any::Any;
 use std::os::unix::prelude::AsRawFd;
-use std::ptr::null_mut;
 use std::sync::{Arc, Barrier, Mutex};
 
 use hypervisor::HypervisorVmError;
@@ -13,6 +12,7 @@ use thiserror::Error;
 use vfio_bindings::bindings::vfio::*;
 use vfio_ioctls::VfioIrq;
 use vfio_user::{Client, Error as VfioUserError};
+use vm_allocator::page_size::is_page_size_aligned;
 use vm_allocator::{AddressAllocator, MemorySlotAllocator, SystemAllocator};
 use vm_device::dma_mapping::ExternalDmaMapping;
 use vm_device::interrupt::{InterruptManager, InterruptSourceGroup, MsiIrqGroupConfig};
@@ -24,12 +24,16 @@ use vm_memory::{
 use vm_migration::{Migratable, MigratableError, Pausable, Snapshot, Snapshottable, Transportable};
 use vmm_sys_util::eventfd::EventFd;
 
-use crate::vfio::{UserMemoryRegion, Vfio, VfioCommon, VfioError, VFIO_COMMON_ID};
+use crate::vfio::{UserMemoryRegion, Vfio, VfioCommon, VfioError, VfioPciDevice, VFIO_COMMON_ID};
 use crate::{
     BarReprogrammingParams, PciBarConfiguration, PciBdf, PciDevice, PciDeviceError, PciSubclass,
     VfioPciError,
 };
 
+// Maximum number of file descriptors passed along a single message, as
+// sendmsg() limits the size of the ancillary data.
+const VFIO_USER_MAX_FDS_PER_MESSAGE: u32 = 16;
+
 pub struct VfioUserPciDevice {
     id: String,
     vm: Arc<dyn hypervisor::Vm>,
@@ -98,6 +102,7 @@ impl VfioUserPciDevice {
             bdf,
             vm_migration::snapshot_from_id(snapshot.as_ref(), VFIO_COMMON_ID),
             None,
+            None,
         )
         .map_err(VfioUserPciDeviceError::CreateVfioCommon)?;
 
@@ -156,27 +161,34 @@ impl VfioUserPciDevice {
                 };
 
                 for s in mmaps.iter() {
-                    // SAFETY: FFI call with correct arguments
-                    let host_addr = unsafe {
-                        libc::mmap(
-                            null_mut(),
-                            s.size as usize,
-                            prot,
-                            libc::MAP_SHARED,
-                            file_offset.as_ref().unwrap().file().as_raw_fd(),
-                            file_offset.as_ref().unwrap().start() as libc::off_t
-                                + s.offset as libc::off_t,
-                        )
-                    };
-
-                    if std::ptr::eq(host_addr, libc::MAP_FAILED) {
-                        error!(
-                            "Could not mmap regions, error:{}",
-                            std::io::Error::last_os_error()
+                    // Same as with kernel VFIO, such areas are left trapped
+                    // and accessed through region read/write messages.
+                    if s.size == 0
+                        || !is_page_size_aligned(s.size)
+                        || !is_page_size_aligned(s.offset)
+                    {
+                        warn!(
+                            "Could not mmap sparse area that is not page size aligned (offset = 0x{:x}, size = 0x{:x})",
+                            s.offset, s.size
                         );
                         continue;
                     }
 
+                    let file_offset = file_offset.as_ref().unwrap();
+                    let host_addr = match VfioPciDevice::mmap_sparse_area(
+                        file_offset.file().as_raw_fd(),
+                        prot,
+                        file_offset.start(),
+                        s.offset,
+                        s.size,
+                    ) {
+                        Ok(host_addr) => host_addr,
+                        Err(e) => {
+                            error!("Could not mmap regions, error:{}", e);
+                            continue;
+                        }
+                    };
+
                     let user_memory_region = UserMemoryRegion {
                         slot: self.memory_slot_allocator.next_memory_slot(),
                         start: mmio_region.start.0 + s.offset,
@@ -340,16 +352,11 @@ impl Vfio for VfioUserClientWrapper {
         );
         let fds: Vec<i32> = event_fds.iter().map(|e| e.as_raw_fd()).collect();
 
-        // Batch into blocks of 16 fds as sendmsg() has a size limit
+        // Batch the fds as sendmsg() has a size limit
         let mut sent_fds = 0;
         let num_fds = event_fds.len() as u32;
         while sent_fds < num_fds {
-            let remaining_fds = num_fds - sent_fds;
-            let count = if remaining_fds > 16 {
-                16
-            } else {
-                remaining_fds
-            };
+            let count = std::cmp::min(num_fds - sent_fds, VFIO_USER_MAX_FDS_PER_MESSAGE);
 
             self.client
                 .lock()
@@ -5,15 +5,22 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use core::panic::AssertUnwindSafe;
+use std::collections::HashMap;
 use std::fs::File;
 use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::{io, result, thread};
 
 use thiserror::Error;
 use vmm_sys_util::eventfd::EventFd;
+use vmm_sys_util::timerfd::TimerFd;
 
-use crate::{RateLimiter, TokenType};
+use crate::{BucketUpdate, RateLimiter, TokenType, TIMER_REFILL_DUR};
+
+// Scale of the virtual time of the shares, keeping the precision of the
+// bytes divided by the weights.
+const VTIME_SCALE: u64 = 1 << 16;
 
 /// Errors associated with rate-limiter group.
 #[derive(Debug, Error)]
@@ -41,6 +48,10 @@ pub enum Error {
     /// Cannot write to EventFd.
     #[error("Error writing to EventFd")]
     EventFdWrite(#[source] io::Error),
+
+    /// Cannot create or wait for the fairness timer.
+    #[error("Error handling the fairness timer")]
+    FairnessTimer(#[source] io::Error),
 }
 
 /// Handle to a RateLimiterGroup
@@ -48,23 +59,41 @@ pub enum Error {
 /// The RateLimiterGroupHandle may be used in exactly the same way as
 /// the RateLimiter type. When the RateLimiter within a RateLimiterGroup
 /// is unblocked, each RateLimiterGroupHandle will be notified.
+///
+/// Handles created with a share only get their fair part of the bandwidth
+/// when the consumers of the group compete for it, see
+/// [`RateLimiterGroup::new_weighted_handle`].
 pub struct RateLimiterGroupHandle {
     eventfd: Arc<EventFd>,
+    share: Option<String>,
     inner: Arc<RateLimiterGroupInner>,
 }
 
 impl RateLimiterGroupHandle {
-    fn new(inner: Arc<RateLimiterGroupInner>) -> result::Result<Self, Error> {
+    fn new(
+        inner: Arc<RateLimiterGroupInner>,
+        share: Option<String>,
+    ) -> result::Result<Self, Error> {
         let eventfd = Arc::new(EventFd::new(0).map_err(Error::EventFd)?);
-        inner.handles.lock().unwrap().push(eventfd.clone());
-        Ok(Self { eventfd, inner })
+        inner.handles.lock().unwrap().push(HandleEntry {
+            eventfd: eventfd.clone(),
+            share: share.clone(),
+        });
+        Ok(Self {
+            eventfd,
+            share,
+            inner,
+        })
     }
 
     /// Attempts to consume tokens and returns whether that is possible.
     ///
     /// If rate limiting is disabled on provided `token_type`, this function will always succeed.
     pub fn consume(&self, tokens: u64, token_type: TokenType) -> bool {
-        self.inner.rate_limiter.consume(tokens, token_type)
+        match (&self.share, token_type) {
+            (Some(share), TokenType::Bytes) => self.inner.consume_share(share, tokens),
+            (_, token_type) => self.inner.rate_limiter.consume(tokens, token_type),
+        }
     }
 
     /// Adds tokens of `token_type` to their respective bucket.
@@ -90,14 +119,20 @@ impl RateLimiterGroupHandle {
     /// The limiter 'blocks' when a `consume()` operation fails because there was not enough
     /// budget for it.
     /// An event will be generated on the exported FD when the limiter 'unblocks'.
+    /// A handle with a share is also blocked while it lets the shares which
+    /// consumed less go first.
     pub fn is_blocked(&self) -> bool {
         self.inner.rate_limiter.is_blocked()
+            || self
+                .share
+                .as_ref()
+                .is_some_and(|share| self.inner.is_deferred(share))
     }
 }
 
 impl Clone for RateLimiterGroupHandle {
     fn clone(&self) -> Self {
-        RateLimiterGroupHandle::new(self.inner.clone()).unwrap()
+        RateLimiterGroupHandle::new(self.inner.clone(), self.share.clone()).unwrap()
     }
 }
 
@@ -109,19 +144,152 @@ impl AsRawFd for RateLimiterGroupHandle {
 
 impl Drop for RateLimiterGroupHandle {
     fn drop(&mut self) {
+        // Always lock the shares before the handles.
+        let mut shares = self.inner.shares.lock().unwrap();
         let mut handles = self.inner.handles.lock().unwrap();
         let index = handles
             .iter()
-            .position(|handle| handle.as_raw_fd() == self.eventfd.as_raw_fd())
+            .position(|handle| handle.eventfd.as_raw_fd() == self.eventfd.as_raw_fd())
             .expect("RateLimiterGroupHandle must be subscribed to RateLimiterGroup");
         handles.remove(index);
+
+        if let Some(share) = &self.share {
+            if !handles.iter().any(|h| h.share.as_ref() == Some(share)) {
+                shares.remove(share);
+            }
+        }
     }
 }
 
+struct HandleEntry {
+    eventfd: Arc<EventFd>,
+    share: Option<String>,
+}
+
+// Part of the bandwidth of a group given to the handles of one consumer,
+// such as the queues of a net device, in proportion to its weight.
+struct Share {
+    weight: u64,
+    // Bytes consumed, scaled by the inverse of the weight
+    vtime: u64,
+    // Whether the share was refused tokens since it last consumed some
+    waiting: bool,
+}
+
 struct RateLimiterGroupInner {
     id: String,
     rate_limiter: RateLimiter,
-    handles: Mutex<Vec<Arc<EventFd>>>,
+    handles: Mutex<Vec<HandleEntry>>,
+    shares: Mutex<HashMap<String, Share>>,
+    // Ends the wait of the shares held back for others which didn't come
+    // back for their tokens, e.g. because they had nothing left to send.
+    fairness_timer: Mutex<TimerFd>,
+    fairness_timer_active: AtomicBool,
+}
+
+impl RateLimiterGroupInner {
+    // Lowest virtual time of the shares other than `share` waiting for
+    // tokens.
+    fn min_waiting_vtime(shares: &HashMap<String, Share>, share: &str) -> Option<u64> {
+        shares
+            .iter()
+            .filter(|(id, s)| id.as_str() != share && s.waiting)
+            .map(|(_, s)| s.vtime)
+            .min()
+    }
+
+    fn is_deferred(&self, share: &str) -> bool {
+        let shares = self.shares.lock().unwrap();
+        let min_waiting = Self::min_waiting_vtime(&shares, share);
+        shares
+            .get(share)
+            .is_some_and(|s| s.waiting && min_waiting.is_some_and(|min| s.vtime > min))
+    }
+
+    // Weighted fair queuing of the bytes: while some shares wait for tokens,
+    // only the ones which consumed the least, relative to their weight, can
+    // consume more.
+    fn consume_share(&self, share: &str, tokens: u64) -> bool {
+        let mut shares = self.shares.lock().unwrap();
+        let min_waiting = Self::min_waiting_vtime(&shares, share);
+        let Some(s) = shares.get_mut(share) else {
+            return self.rate_limiter.consume(tokens, TokenType::Bytes);
+        };
+
+        // A share coming back from idle doesn't get to spend the tokens it
+        // didn't use meanwhile.
+        if let (false, Some(min)) = (s.waiting, min_waiting) {
+            s.vtime = s.vtime.max(min);
+        }
+
+        if min_waiting.is_some_and(|min| s.vtime > min) {
+            s.waiting = true;
+            self.arm_fairness_timer();
+            return false;
+        }
+
+        if !self.rate_limiter.consume(tokens, TokenType::Bytes) {
+            s.waiting = true;
+            return false;
+        }
+
+        s.vtime = s
+            .vtime
+            .saturating_add(tokens.saturating_mul(VTIME_SCALE) / s.weight);
+        if s.waiting {
+            s.waiting = false;
+            // The shares held back for this one can try again.
+            self.notify_waiting(&shares);
+        }
+
+        true
+    }
+
+    fn notify_waiting(&self, shares: &HashMap<String, Share>) {
+        let handles = self.handles.lock().unwrap();
+        for handle in handles.iter() {
+            let waiting = handle
+                .share
+                .as_ref()
+                .and_then(|share| shares.get(share))
+                .is_some_and(|s| s.waiting);
+            if waiting {
+                if let Err(e) = handle.eventfd.write(1) {
+                    warn!("Error notifying rate-limiter group handle: {}", e);
+                }
+            }
+        }
+    }
+
+    fn arm_fairness_timer(&self) {
+        if !self.fairness_timer_active.swap(true, Ordering::Relaxed) {
+            // Panic when failing to arm the timer, as the RateLimiter does
+            self.fairness_timer
+                .lock()
+                .unwrap()
+                .reset(TIMER_REFILL_DUR, None)
+                .expect("Can't arm the timer (unexpected 'timerfd_settime' failure).");
+        }
+    }
+
+    // Lets all the waiting shares try again, forgetting the ones which didn't
+    // come back for their tokens.
+    fn fairness_timer_expired(&self) -> result::Result<(), Error> {
+        self.fairness_timer
+            .lock()
+            .unwrap()
+            .wait()
+            .map_err(|e| Error::FairnessTimer(e.into()))?;
+        self.fairness_timer_active.store(false, Ordering::Relaxed);
+
+        let mut shares = self.shares.lock().unwrap();
+        self.notify_waiting(&shares);
+        for share in shares.values_mut() {
+            share.waiting = false;
+        }
+
+        Ok(())
+    }
 }
 
 /// A RateLimiterGroup is an extension of RateLimiter that enables rate-limiting
@@ -138,6 +306,7 @@ pub struct RateLimiterGroup {
 enum EpollDispatch {
     Kill = 1,
     Unblocked = 2,
+    Fairness = 3,
     Unknown,
 }
 
@@ -147,6 +316,7 @@ impl From<u64> for EpollDispatch {
         match v {
             1 => Kill,
             2 => Unblocked,
+            3 => Fairness,
             _ => Unknown,
         }
     }
@@ -175,6 +345,7 @@ impl RateLimiterGroup {
 
         let epoll_fd = epoll::create(true).map_err(Error::Epoll)?;
         let kill_evt = EventFd::new(0).map_err(Error::EventFd)?;
+        let fairness_timer = TimerFd::new().map_err(|e| Error::FairnessTimer(e.into()))?;
 
         epoll::ctl(
             epoll_fd,
@@ -192,6 +363,14 @@ impl RateLimiterGroup {
         )
         .map_err(Error::Epoll)?;
 
+        epoll::ctl(
+            epoll_fd,
+            epoll::ControlOptions::EPOLL_CTL_ADD,
+            fairness_timer.as_raw_fd(),
+            epoll::Event::new(epoll::Events::EPOLLIN, EpollDispatch::Fairness as u64),
+        )
+        .map_err(Error::Epoll)?;
+
         // Use 'File' to enforce closing on 'epoll_fd'
         // SAFETY: epoll_fd is valid
         let epoll_file = unsafe { File::from_raw_fd(epoll_fd) };
@@ -201,6 +380,9 @@ impl RateLimiterGroup {
                 id: id.to_string(),
                 rate_limiter,
                 handles: Mutex::new(Vec::new()),
+                shares: Mutex::new(HashMap::new()),
+                fairness_timer: Mutex::new(fairness_timer),
+                fairness_timer_active: AtomicBool::new(false),
             }),
             epoll_file,
             kill_evt,
@@ -208,9 +390,36 @@ impl RateLimiterGroup {
         })
     }
 
+    /// Updates the token buckets shared by the handles of the group.
+    pub fn update_buckets(&self, bytes: BucketUpdate, ops: BucketUpdate) {
+        self.inner.rate_limiter.update_buckets(bytes, ops);
+    }
+
     /// Create a new RateLimiterGroupHandle.
     pub fn new_handle(&self) -> result::Result<RateLimiterGroupHandle, Error> {
-        RateLimiterGroupHandle::new(self.inner.clone())
+        RateLimiterGroupHandle::new(self.inner.clone(), None)
+    }
+
+    /// Create a new RateLimiterGroupHandle consuming the bytes of `share`.
+    ///
+    /// When the group runs out of bytes, each share gets a part of them in
+    /// proportion to its `weight`, whatever the number of its handles.
+    pub fn new_weighted_handle(
+        &self,
+        share: &str,
+        weight: u16,
+    ) -> result::Result<RateLimiterGroupHandle, Error> {
+        let mut shares = self.inner.shares.lock().unwrap();
+        let weight = u64::from(weight.max(1));
+        shares
+            .entry(share.to_string())
+            .and_modify(|s| s.weight = weight)
+            .or_insert(Share {
+                weight,
+                vtime: 0,
+                waiting: false,
+            });
+        RateLimiterGroupHandle::new(self.inner.clone(), Some(share.to_string()))
     }
 
     /// Start a worker thread to broadcast an event to each RateLimiterGroupHandle
@@ -222,7 +431,7 @@ impl RateLimiterGroup {
             .name(format!("rate-limit-group-{}", inner.id))
             .spawn(move || {
                 let res = std::panic::catch_unwind(AssertUnwindSafe(move || {
-                    const EPOLL_EVENTS_LEN: usize = 2;
+                    const EPOLL_EVENTS_LEN: usize = 3;
 
                     let mut events =
                         [epoll::Event::new(epoll::Events::empty(), 0); EPOLL_EVENTS_LEN];
@@ -250,9 +459,10 @@ impl RateLimiterGroup {
                                     inner.rate_limiter.event_handler().unwrap();
                                     let handles = inner.handles.lock().unwrap();
                                     for handle in handles.iter() {
-                                        handle.write(1).map_err(Error::EventFdWrite)?
+                                        handle.eventfd.write(1).map_err(Error::EventFdWrite)?
                                     }
                                 }
+                                EpollDispatch::Fairness => inner.fairness_timer_expired()?,
                                 EpollDispatch::Kill => {
                                     info!(
                                         "KILL_EVENT received, stopping rate-limit-group epoll loop"
@@ -470,6 +680,41 @@ pub(crate) mod tests {
         assert!(h.consume(100, TokenType::Bytes));
     }
 
+    #[test]
+    fn test_rate_limiter_group_weighted() {
+        // rate limiter with limit of 1000 bytes/s
+        let mut l = RateLimiterGroup::new("test", 1000, 0, 1000, 0, 0, 0).unwrap();
+        l.start_thread(EventFd::new(0).unwrap()).unwrap();
+
+        let a = l.new_weighted_handle("a", 1).unwrap();
+        let b = l.new_weighted_handle("b", 2).unwrap();
+
+        // "a" takes all the bytes, and "b" has to wait for more
+        assert!(a.consume(1000, TokenType::Bytes));
+        assert!(!b.consume(100, TokenType::Bytes));
+        assert!(b.is_blocked());
+
+        // wait for the limiter to unblock
+        thread::sleep(Duration::from_millis(2 * REFILL_TIMER_INTERVAL_MS));
+        a.event_handler().unwrap();
+        b.event_handler().unwrap();
+
+        // "a" consumed more than "b" and lets it go first
+        assert!(!a.consume(10, TokenType::Bytes));
+        assert!(a.is_blocked());
+        assert!(b.consume(10, TokenType::Bytes));
+        assert!(!b.is_blocked());
+
+        // "a" is notified, and takes its turn
+        a.event_handler().unwrap();
+        assert!(!a.is_blocked());
+        assert!(a.consume(10, TokenType::Bytes));
+
+        // a handle with no share isn't held back
+        let c = l.new_handle().unwrap();
+        assert!(c.consume(10, TokenType::Bytes));
+    }
+
     #[test]
     fn test_rate_limiter_group_overconsumption() {
         // initialize the rate limiter
@@ -485,7 +485,7 @@ impl RateLimiter {
 
     /// Updates the parameters of the token buckets associated with this RateLimiter.
     // TODO: Please note that, right now, the buckets become full after being updated.
-    pub fn update_buckets(&mut self, bytes: BucketUpdate, ops: BucketUpdate) {
+    pub fn update_buckets(&self, bytes: BucketUpdate, ops: BucketUpdate) {
         let mut guard = self.inner.lock().unwrap();
         match bytes {
             BucketUpdate::Disabled => guard.bandwidth = None,
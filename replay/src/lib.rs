@@ -0,0 +1,203 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Record and replay of the inputs the devices give to the guest.
+//!
+//! When recording, the data coming from the host (network frames, disk
+//! reads, random bytes, clock reads) is appended to a trace file, each input
+//! tagged with the source it came from. When replaying, the devices take
+//! their inputs from the trace instead, source by source and in the same
+//! order, so that the guest sees the same data again.
+
+#[macro_use]
+extern crate log;
+
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use once_cell::sync::OnceCell;
+
+const MAGIC: &[u8; 8] = b"CHREPLAY";
+const VERSION: u32 = 1;
+
+static TRACE: OnceCell<Trace> = OnceCell::new();
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Record,
+    Replay,
+}
+
+enum Trace {
+    Record(Mutex<File>),
+    Replay(Mutex<HashMap<String, VecDeque<Vec<u8>>>>),
+}
+
+fn write_header<W: Write>(w: &mut W) -> io::Result<()> {
+    w.write_all(MAGIC)?;
+    w.write_all(&VERSION.to_le_bytes())
+}
+
+// Encodes an input as the length of its source, the source, the length of
+// the data and the data.
+fn encode_input(source: &str, data: &[u8]) -> Vec<u8> {
+    let mut input = Vec::with_capacity(2 + source.len() + 4 + data.len());
+    input.extend_from_slice(&(source.len() as u16).to_le_bytes());
+    input.extend_from_slice(source.as_bytes());
+    input.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    input.extend_from_slice(data);
+    input
+}
+
+fn read_inputs<R: Read>(r: &mut R) -> io::Result<HashMap<String, VecDeque<Vec<u8>>>> {
+    let mut magic = [0u8; 8];
+    let mut version = [0u8; 4];
+    r.read_exact(&mut magic)?;
+    r.read_exact(&mut version)?;
+    if &magic != MAGIC || u32::from_le_bytes(version) != VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not an I/O trace of a supported version",
+        ));
+    }
+
+    let mut inputs: HashMap<String, VecDeque<Vec<u8>>> = HashMap::new();
+    loop {
+        let mut source_len = [0u8; 2];
+        match r.read_exact(&mut source_len) {
+            Ok(()) => (),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
+        match read_input(r, u16::from_le_bytes(source_len) as usize) {
+            Ok((source, data)) => inputs.entry(source).or_default().push_back(data),
+            // The VMM recording the trace may have stopped in the middle of
+            // writing an input.
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                warn!("Ignoring the truncated last input of the I/O trace");
+                break;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(inputs)
+}
+
+fn read_input<R: Read>(r: &mut R, source_len: usize) -> io::Result<(String, Vec<u8>)> {
+    let mut source = vec![0u8; source_len];
+    r.read_exact(&mut source)?;
+    let source =
+        String::from_utf8(source).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    let mut data_len = [0u8; 4];
+    r.read_exact(&mut data_len)?;
+    let mut data = vec![0u8; u32::from_le_bytes(data_len) as usize];
+    r.read_exact(&mut data)?;
+
+    Ok((source, data))
+}
+
+/// Records the inputs to, or replays them from, the trace at `path`.
+///
+/// This function must only be called once from the main thread before any
+/// threads are created, the devices checking the mode as they are created.
+pub fn set_trace(mode: Mode, path: &Path) -> io::Result<()> {
+    assert!(TRACE.get().is_none());
+
+    let trace = match mode {
+        Mode::Record => {
+            let mut file = File::create(path)?;
+            write_header(&mut file)?;
+            Trace::Record(Mutex::new(file))
+        }
+        Mode::Replay => {
+            let inputs = read_inputs(&mut io::BufReader::new(File::open(path)?))?;
+            Trace::Replay(Mutex::new(inputs))
+        }
+    };
+
+    TRACE.get_or_init(|| trace);
+    Ok(())
+}
+
+/// Returns the mode of the trace, if any.
+pub fn mode() -> Option<Mode> {
+    TRACE.get().map(|trace| match trace {
+        Trace::Record(_) => Mode::Record,
+        Trace::Replay(_) => Mode::Replay,
+    })
+}
+
+pub fn is_recording() -> bool {
+    mode() == Some(Mode::Record)
+}
+
+pub fn is_replaying() -> bool {
+    mode() == Some(Mode::Replay)
+}
+
+/// Appends the input `data` from `source` to the trace, when recording.
+pub fn record(source: &str, data: &[u8]) {
+    if let Some(Trace::Record(file)) = TRACE.get() {
+        // Each input is written at once, so that the trace is usable up to
+        // the last input even if the VMM crashes.
+        if let Err(e) = file.lock().unwrap().write_all(&encode_input(source, data)) {
+            error!("Failed to record input from {}: {}", source, e);
+        }
+    }
+}
+
+/// Returns the next input from `source` in the trace, when replaying.
+pub fn replay(source: &str) -> Option<Vec<u8>> {
+    match TRACE.get() {
+        Some(Trace::Replay(inputs)) => inputs
+            .lock()
+            .unwrap()
+            .get_mut(source)
+            .and_then(VecDeque::pop_front),
+        _ => None,
+    }
+}
+
+/// Whether inputs from `source` remain to be replayed.
+pub fn pending(source: &str) -> bool {
+    match TRACE.get() {
+        Some(Trace::Replay(inputs)) => inputs
+            .lock()
+            .unwrap()
+            .get(source)
+            .is_some_and(|inputs| !inputs.is_empty()),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trace_inputs() {
+        let mut trace = Vec::new();
+        write_header(&mut trace).unwrap();
+        trace.extend(encode_input("_net0/rx0", &[1, 2, 3]));
+        trace.extend(encode_input("rtc", &42i64.to_le_bytes()));
+        trace.extend(encode_input("_net0/rx0", &[]));
+
+        let mut inputs = read_inputs(&mut trace.as_slice()).unwrap();
+        let frames = inputs.remove("_net0/rx0").unwrap();
+        assert_eq!(frames, [vec![1, 2, 3], vec![]]);
+        assert_eq!(inputs["rtc"], [42i64.to_le_bytes().to_vec()]);
+
+        // A truncated last input is dropped.
+        let inputs = read_inputs(&mut &trace[..trace.len() - 3]).unwrap();
+        assert_eq!(inputs["_net0/rx0"], [vec![1, 2, 3]]);
+
+        assert!(read_inputs(&mut &b"CHREPLAY\x02\0\0\0"[..]).is_err());
+    }
+}
@@ -6,7 +6,7 @@
 use std::collections::VecDeque;
 use std::io::Write;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 const MAX_BUFFER_SIZE: usize = 1 << 20;
 
@@ -109,3 +109,145 @@ impl Write for SerialBuffer {
         self.out.flush()
     }
 }
+
+// Ring buffer keeping the latest output, for it to be read at any time while
+// the output goes on.
+#[derive(Clone)]
+pub struct RingBuffer {
+    buffer: Arc<Mutex<VecDeque<u8>>>,
+    capacity: usize,
+}
+
+impl RingBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            buffer: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    pub fn contents(&self) -> Vec<u8> {
+        self.buffer.lock().unwrap().iter().copied().collect()
+    }
+}
+
+impl Write for RingBuffer {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, std::io::Error> {
+        let mut buffer = self.buffer.lock().unwrap();
+        let kept = &buf[buf.len().saturating_sub(self.capacity)..];
+        let excess = (buffer.len() + kept.len()).saturating_sub(self.capacity);
+        buffer.drain(..excess);
+        buffer.extend(kept);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), std::io::Error> {
+        Ok(())
+    }
+}
+
+// Additional outputs the output of a console is duplicated to, shared by the
+// writers successively connected to the console.
+#[derive(Clone, Default)]
+pub struct OutputSinks(Arc<Mutex<Vec<Box<dyn Write + Send>>>>);
+
+impl OutputSinks {
+    pub fn new(sinks: Vec<Box<dyn Write + Send>>) -> Self {
+        Self(Arc::new(Mutex::new(sinks)))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.lock().unwrap().is_empty()
+    }
+
+    // Wrap the output of a console, if any, so that everything written to
+    // the console also goes to the sinks.
+    pub fn tee(&self, out: Option<Box<dyn Write + Send>>) -> Option<Box<dyn Write + Send>> {
+        if self.is_empty() {
+            return out;
+        }
+
+        Some(Box::new(Tee {
+            out,
+            sinks: self.clone(),
+        }))
+    }
+}
+
+struct Tee {
+    out: Option<Box<dyn Write + Send>>,
+    sinks: OutputSinks,
+}
+
+impl Write for Tee {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, std::io::Error> {
+        // Only what the output took is written to the sinks, as the rest is
+        // written again by the caller.
+        let written = match self.out.as_mut() {
+            Some(out) => out.write(buf)?,
+            None => buf.len(),
+        };
+
+        // A failing sink must not hold the output back, it's up to the sink
+        // to report its errors.
+        for sink in self.sinks.0.lock().unwrap().iter_mut() {
+            let _ = sink.write_all(&buf[..written]);
+        }
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> Result<(), std::io::Error> {
+        for sink in self.sinks.0.lock().unwrap().iter_mut() {
+            let _ = sink.flush();
+        }
+
+        match self.out.as_mut() {
+            Some(out) => out.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ring_buffer() {
+        let mut ring = RingBuffer::new(8);
+        ring.write_all(b"hello").unwrap();
+        assert_eq!(ring.contents(), b"hello");
+
+        // The oldest output is dropped to make room for the latest.
+        ring.write_all(b" world").unwrap();
+        assert_eq!(ring.contents(), b"lo world");
+
+        // Only the end of an output larger than the buffer is kept.
+        assert_eq!(ring.write(b"0123456789").unwrap(), 10);
+        assert_eq!(ring.contents(), b"23456789");
+    }
+
+    #[test]
+    fn test_output_sinks_tee() {
+        // Without sinks, the output is left as is.
+        assert!(OutputSinks::default().tee(None).is_none());
+
+        let ring = RingBuffer::new(64);
+        let sinks = OutputSinks::new(vec![Box::new(ring.clone())]);
+        assert!(!sinks.is_empty());
+
+        let out = RingBuffer::new(64);
+        let mut tee = sinks.tee(Some(Box::new(out.clone()))).unwrap();
+        tee.write_all(b"console").unwrap();
+        tee.flush().unwrap();
+        assert_eq!(out.contents(), b"console");
+        assert_eq!(ring.contents(), b"console");
+
+        // The sinks are shared by the writers of the console.
+        let mut tee = sinks.tee(None).unwrap();
+        tee.write_all(b" output").unwrap();
+        assert_eq!(ring.contents(), b"console output");
+        assert_eq!(out.contents(), b"console");
+    }
+}
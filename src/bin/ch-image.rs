@@ -0,0 +1,278 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::fs::{File, OpenOptions};
+use std::os::unix::fs::MetadataExt;
+use std::process;
+
+use block::qcow::{self, ImageType, QcowFile, QcowHeader, RawFile};
+use clap::{Arg, ArgMatches, Command};
+use option_parser::{ByteSized, ByteSizedParseError};
+use serde_json::json;
+use thiserror::Error;
+
+// Version of the QCOW2 images created.
+const QCOW_VERSION: u32 = 3;
+
+#[derive(Error, Debug)]
+enum Error {
+    #[error("Error opening image {0}")]
+    OpenImage(String, #[source] std::io::Error),
+    #[error("Error creating image {0}")]
+    CreateImage(String, #[source] std::io::Error),
+    #[error("Error parsing image size")]
+    InvalidSize(#[source] ByteSizedParseError),
+    #[error("Error creating QCOW2 image")]
+    CreateQcow(#[source] qcow::Error),
+    #[error("Error converting image")]
+    Convert(#[source] qcow::Error),
+    #[error("Error reading image header")]
+    ReadHeader(#[source] qcow::Error),
+    #[error("Error reading image metadata")]
+    Metadata(#[source] std::io::Error),
+}
+
+fn image_type(matches: &ArgMatches) -> ImageType {
+    match matches.get_one::<String>("format").unwrap().as_str() {
+        "qcow2" => ImageType::Qcow2,
+        _ => ImageType::Raw,
+    }
+}
+
+fn open_image(path: &str) -> Result<RawFile, Error> {
+    let file = File::open(path).map_err(|e| Error::OpenImage(path.to_string(), e))?;
+    Ok(RawFile::new(file, false))
+}
+
+fn create_command(matches: &ArgMatches) -> Result<(), Error> {
+    let path = matches.get_one::<String>("path").unwrap();
+    let size = matches
+        .get_one::<String>("size")
+        .unwrap()
+        .parse::<ByteSized>()
+        .map_err(Error::InvalidSize)?
+        .0;
+
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .map_err(|e| Error::CreateImage(path.to_string(), e))?;
+    match image_type(matches) {
+        ImageType::Qcow2 => {
+            QcowFile::new(RawFile::new(file, false), QCOW_VERSION, size)
+                .map_err(Error::CreateQcow)?;
+        }
+        // The raw image is created sparse, allocating no data.
+        ImageType::Raw => file
+            .set_len(size)
+            .map_err(|e| Error::CreateImage(path.to_string(), e))?,
+    }
+
+    Ok(())
+}
+
+fn convert_command(matches: &ArgMatches) -> Result<(), Error> {
+    let src = open_image(matches.get_one::<String>("source").unwrap())?;
+    let dst_path = matches.get_one::<String>("destination").unwrap();
+    let dst = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(dst_path)
+        .map_err(|e| Error::CreateImage(dst_path.to_string(), e))?;
+
+    // Only the data ranges of the source are copied, leaving holes in the
+    // destination for the rest.
+    qcow::convert(
+        src,
+        RawFile::new(dst, false),
+        image_type(matches),
+        qcow::MAX_NESTING_DEPTH,
+    )
+    .map_err(Error::Convert)
+}
+
+fn inspect_command(matches: &ArgMatches) -> Result<(), Error> {
+    let path = matches.get_one::<String>("path").unwrap();
+    let mut file = open_image(path)?;
+    let metadata = file.metadata().map_err(Error::Metadata)?;
+    // Blocks are reported in 512 bytes units, whatever the file system.
+    let disk_size = metadata.blocks() * 512;
+
+    let info = match qcow::detect_image_type(&mut file).map_err(Error::ReadHeader)? {
+        ImageType::Qcow2 => {
+            let header = QcowHeader::new(&mut file).map_err(Error::ReadHeader)?;
+            json!({
+                "format": "qcow2",
+                "version": header.version,
+                "virtual_size": header.size,
+                "disk_size": disk_size,
+                "cluster_size": 1u64 << header.cluster_bits,
+                "backing_file": header.backing_file_path,
+            })
+        }
+        ImageType::Raw => json!({
+            "format": "raw",
+            "virtual_size": metadata.len(),
+            "disk_size": disk_size,
+        }),
+    };
+    println!("{}", serde_json::to_string_pretty(&info).unwrap());
+
+    Ok(())
+}
+
+fn format_arg() -> Arg {
+    Arg::new("format")
+        .long("format")
+        .short('f')
+        .help("Format of the image")
+        .value_parser(["raw", "qcow2"])
+        .default_value("raw")
+        .num_args(1)
+}
+
+fn get_cli_commands_sorted() -> Box<[Command]> {
+    [
+        Command::new("convert")
+            .about("Convert an image, preserving its holes")
+            .arg(
+                Arg::new("source")
+                    .index(1)
+                    .help("Path of the image to convert, in any format")
+                    .required(true),
+            )
+            .arg(
+                Arg::new("destination")
+                    .index(2)
+                    .help("Path of the converted image")
+                    .required(true),
+            )
+            .arg(format_arg()),
+        Command::new("create")
+            .about("Create an empty image")
+            .arg(
+                Arg::new("path")
+                    .index(1)
+                    .help("Path of the image")
+                    .required(true),
+            )
+            .arg(
+                Arg::new("size")
+                    .long("size")
+                    .short('s')
+                    .help("Virtual size of the image, e.g. 10G")
+                    .num_args(1)
+                    .required(true),
+            )
+            .arg(format_arg()),
+        Command::new("inspect")
+            .about("Print information about an image")
+            .arg(
+                Arg::new("path")
+                    .index(1)
+                    .help("Path of the image")
+                    .required(true),
+            ),
+    ]
+    .to_vec()
+    .into_boxed_slice()
+}
+
+fn main() {
+    let app = Command::new("ch-image")
+        .author(env!("CARGO_PKG_AUTHORS"))
+        .version(env!("BUILD_VERSION"))
+        .about("Create, convert and inspect disk images for cloud-hypervisor.")
+        .arg_required_else_help(true)
+        .subcommand_required(true)
+        .subcommands(get_cli_commands_sorted());
+
+    let matches = app.get_matches();
+
+    let result = match matches.subcommand() {
+        Some(("convert", matches)) => convert_command(matches),
+        Some(("create", matches)) => create_command(matches),
+        Some(("inspect", matches)) => inspect_command(matches),
+        _ => unreachable!(),
+    };
+
+    if let Err(top_error) = result {
+        cloud_hypervisor::cli_print_error_chain(&top_error, "ch-image", |_, _, _| None);
+        process::exit(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    use vmm_sys_util::tempdir::TempDir;
+
+    use super::*;
+
+    fn run(args: &[&str]) -> Result<(), Error> {
+        let matches = Command::new("ch-image")
+            .subcommands(get_cli_commands_sorted())
+            .get_matches_from(std::iter::once("ch-image").chain(args.iter().copied()));
+        match matches.subcommand() {
+            Some(("convert", matches)) => convert_command(matches),
+            Some(("create", matches)) => create_command(matches),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_cli_commands_sorted() {
+        let commands = get_cli_commands_sorted();
+        assert!(commands
+            .windows(2)
+            .all(|w| w[0].get_name() < w[1].get_name()));
+    }
+
+    #[test]
+    fn test_create_and_convert() {
+        let dir = TempDir::new_with_prefix("/tmp/ch-image").unwrap();
+        let path = |name: &str| dir.as_path().join(name).to_str().unwrap().to_string();
+
+        run(&["create", &path("disk.raw"), "--size", "4M"]).unwrap();
+        let mut raw = OpenOptions::new()
+            .write(true)
+            .open(path("disk.raw"))
+            .unwrap();
+        assert_eq!(raw.metadata().unwrap().len(), 4 << 20);
+        raw.seek(SeekFrom::Start(1 << 20)).unwrap();
+        raw.write_all(b"cloud-hypervisor").unwrap();
+        drop(raw);
+
+        run(&[
+            "convert",
+            &path("disk.raw"),
+            &path("disk.qcow2"),
+            "-f",
+            "qcow2",
+        ])
+        .unwrap();
+        let header = QcowHeader::new(&mut open_image(&path("disk.qcow2")).unwrap()).unwrap();
+        assert_eq!(header.size, 4 << 20);
+
+        run(&["convert", &path("disk.qcow2"), &path("copy.raw")]).unwrap();
+        let mut data = Vec::new();
+        File::open(path("copy.raw"))
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        assert_eq!(data.len(), 4 << 20);
+        assert_eq!(&data[1 << 20..(1 << 20) + 16], b"cloud-hypervisor");
+
+        run(&["create", &path("empty.qcow2"), "-s", "1G", "-f", "qcow2"]).unwrap();
+        let header = QcowHeader::new(&mut open_image(&path("empty.qcow2")).unwrap()).unwrap();
+        assert_eq!(header.size, 1 << 30);
+        assert!(run(&["create", &path("empty.qcow2"), "-s", "1G"]).is_err());
+    }
+}
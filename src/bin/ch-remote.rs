@@ -7,22 +7,29 @@
 #[path = "../test_util.rs"]
 mod test_util;
 
-use std::io::Read;
+use std::io::{Read, Write};
 use std::marker::PhantomData;
 use std::os::unix::net::UnixStream;
-use std::process;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::{process, thread};
 
 use api_client::{
     simple_api_command, simple_api_command_with_fds, simple_api_full_command,
-    Error as ApiClientError,
+    simple_api_full_command_and_response, Error as ApiClientError,
 };
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
 use clap::{Arg, ArgAction, ArgMatches, Command};
 use option_parser::{ByteSized, ByteSizedParseError};
+use serde_json::json;
 use thiserror::Error;
 use vmm::config::RestoreConfig;
+#[cfg(target_arch = "x86_64")]
+use vmm::vm_config::SgxEpcConfig;
 use vmm::vm_config::{
-    DeviceConfig, DiskConfig, FsConfig, NetConfig, PmemConfig, UserDeviceConfig, VdpaConfig,
-    VsockConfig,
+    DeviceConfig, DiskConfig, FsConfig, NetConfig, PmemConfig, RateLimiterGroupConfig,
+    UserDeviceConfig, VdpaConfig, VsockConfig,
 };
 #[cfg(feature = "dbus_api")]
 use zbus::{proxy, zvariant::Optional};
@@ -42,6 +49,8 @@ enum Error {
     InvalidMemorySize(#[source] ByteSizedParseError),
     #[error("Error parsing balloon size")]
     InvalidBalloonSize(#[source] ByteSizedParseError),
+    #[error("Error parsing disk size")]
+    InvalidDiskSize(#[source] ByteSizedParseError),
     #[error("Error parsing device syntax")]
     AddDeviceConfig(#[source] vmm::config::Error),
     #[error("Error parsing disk syntax")]
@@ -52,18 +61,37 @@ enum Error {
     AddPmemConfig(#[source] vmm::config::Error),
     #[error("Error parsing network syntax")]
     AddNetConfig(#[source] vmm::config::Error),
+    #[error("Error parsing rate limiter group syntax")]
+    AddRateLimitGroupConfig(#[source] vmm::config::Error),
     #[error("Error parsing user device syntax")]
     AddUserDeviceConfig(#[source] vmm::config::Error),
     #[error("Error parsing vDPA device syntax")]
     AddVdpaConfig(#[source] vmm::config::Error),
     #[error("Error parsing vsock syntax")]
     AddVsockConfig(#[source] vmm::config::Error),
+    #[cfg(target_arch = "x86_64")]
+    #[error("Error parsing SGX EPC syntax")]
+    AddSgxEpcConfig(#[source] vmm::config::Error),
     #[error("Error parsing restore syntax")]
     Restore(#[source] vmm::config::Error),
     #[error("Error reading from stdin")]
     ReadingStdin(#[source] std::io::Error),
     #[error("Error reading from file")]
     ReadingFile(#[source] std::io::Error),
+    #[error("Error importing libvirt domain")]
+    ImportLibvirt(#[source] vmm::libvirt::Error),
+    #[error("Error importing OVF appliance")]
+    ImportOvf(#[source] vmm::ovf::Error),
+    #[error("Invalid OVF property, expected <key>=<value>: {0}")]
+    InvalidOvfProperty(String),
+    #[error("Invalid backup disk, expected <id>=<overlay path>: {0}")]
+    InvalidBackupDisk(String),
+    #[error("Invalid guest agent response")]
+    InvalidGuestResponse(#[source] serde_json::Error),
+    #[error("Invalid guest command output")]
+    InvalidGuestOutput,
+    #[error("Error writing guest command output")]
+    WritingOutput(#[source] std::io::Error),
 }
 
 enum TargetApi<'a> {
@@ -81,26 +109,39 @@ trait DBusApi1 {
     fn vm_add_disk(&self, disk_config: &str) -> zbus::Result<Optional<String>>;
     fn vm_add_fs(&self, fs_config: &str) -> zbus::Result<Optional<String>>;
     fn vm_add_net(&self, net_config: &str) -> zbus::Result<Optional<String>>;
+    fn vm_add_pci_segment(&self) -> zbus::Result<Optional<String>>;
     fn vm_add_pmem(&self, pmem_config: &str) -> zbus::Result<Optional<String>>;
+    fn vm_add_rate_limit_group(&self, rate_limit_group_config: &str) -> zbus::Result<()>;
+    fn vm_add_sgx_epc(&self, sgx_epc_config: &str) -> zbus::Result<()>;
     fn vm_add_user_device(&self, vm_add_user_device: &str) -> zbus::Result<Optional<String>>;
     fn vm_add_vdpa(&self, vdpa_config: &str) -> zbus::Result<Optional<String>>;
     fn vm_add_vsock(&self, vsock_config: &str) -> zbus::Result<Optional<String>>;
+    fn vm_backup(&self, vm_backup: &str) -> zbus::Result<()>;
     fn vm_boot(&self) -> zbus::Result<()>;
+    fn vm_export_config(&self) -> zbus::Result<Optional<String>>;
+    fn vm_console_log(&self) -> zbus::Result<Optional<String>>;
     fn vm_coredump(&self, vm_coredump_data: &str) -> zbus::Result<()>;
     fn vm_counters(&self) -> zbus::Result<Optional<String>>;
     fn vm_create(&self, vm_config: &str) -> zbus::Result<()>;
     fn vm_delete(&self) -> zbus::Result<()>;
+    fn vm_disk_snapshot(&self, vm_disk_snapshot: &str) -> zbus::Result<()>;
+    fn vm_guest_command(&self, guest_command_data: &str) -> zbus::Result<Optional<String>>;
+    fn vm_hibernate(&self, vm_hibernate_config: &str) -> zbus::Result<()>;
     fn vm_info(&self) -> zbus::Result<String>;
     fn vm_pause(&self) -> zbus::Result<()>;
     fn vm_power_button(&self) -> zbus::Result<()>;
     fn vm_reboot(&self) -> zbus::Result<()>;
     fn vm_remove_device(&self, vm_remove_device: &str) -> zbus::Result<()>;
+    fn vm_reset_device(&self, vm_reset_device: &str) -> zbus::Result<()>;
     fn vm_resize(&self, vm_resize: &str) -> zbus::Result<()>;
+    fn vm_resize_disk(&self, vm_resize_disk: &str) -> zbus::Result<()>;
     fn vm_resize_zone(&self, vm_resize_zone: &str) -> zbus::Result<()>;
     fn vm_restore(&self, restore_config: &str) -> zbus::Result<()>;
     fn vm_receive_migration(&self, receive_migration_data: &str) -> zbus::Result<()>;
     fn vm_send_migration(&self, receive_migration_data: &str) -> zbus::Result<()>;
     fn vm_resume(&self) -> zbus::Result<()>;
+    fn vm_set_balloon(&self, vm_set_balloon: &str) -> zbus::Result<()>;
+    fn vm_set_rate_limit_group(&self, vm_set_rate_limit_group: &str) -> zbus::Result<()>;
     fn vm_shutdown(&self) -> zbus::Result<()>;
     fn vm_snapshot(&self, vm_snapshot_config: &str) -> zbus::Result<()>;
 }
@@ -156,10 +197,19 @@ impl<'a> DBusApi1ProxyBlocking<'a> {
         self.print_response(self.vm_add_net(net_config))
     }
 
+    fn api_vm_add_pci_segment(&self) -> ApiResult {
+        self.print_response(self.vm_add_pci_segment())
+    }
+
     fn api_vm_add_pmem(&self, pmem_config: &str) -> ApiResult {
         self.print_response(self.vm_add_pmem(pmem_config))
     }
 
+    fn api_vm_add_rate_limit_group(&self, rate_limit_group_config: &str) -> ApiResult {
+        self.vm_add_rate_limit_group(rate_limit_group_config)
+            .map_err(Error::DBusApiClient)
+    }
+
     fn api_vm_add_user_device(&self, vm_add_user_device: &str) -> ApiResult {
         self.print_response(self.vm_add_user_device(vm_add_user_device))
     }
@@ -172,15 +222,33 @@ impl<'a> DBusApi1ProxyBlocking<'a> {
         self.print_response(self.vm_add_vsock(vsock_config))
     }
 
+    #[cfg(target_arch = "x86_64")]
+    fn api_vm_add_sgx_epc(&self, sgx_epc_config: &str) -> ApiResult {
+        self.vm_add_sgx_epc(sgx_epc_config)
+            .map_err(Error::DBusApiClient)
+    }
+
+    fn api_vm_backup(&self, vm_backup: &str) -> ApiResult {
+        self.vm_backup(vm_backup).map_err(Error::DBusApiClient)
+    }
+
     fn api_vm_boot(&self) -> ApiResult {
         self.vm_boot().map_err(Error::DBusApiClient)
     }
 
+    fn api_vm_export_config(&self) -> ApiResult {
+        self.print_response(self.vm_export_config())
+    }
+
     fn api_vm_coredump(&self, vm_coredump_data: &str) -> ApiResult {
         self.vm_coredump(vm_coredump_data)
             .map_err(Error::DBusApiClient)
     }
 
+    fn api_vm_console_log(&self) -> ApiResult {
+        self.print_response(self.vm_console_log())
+    }
+
     fn api_vm_counters(&self) -> ApiResult {
         self.print_response(self.vm_counters())
     }
@@ -193,6 +261,22 @@ impl<'a> DBusApi1ProxyBlocking<'a> {
         self.vm_delete().map_err(Error::DBusApiClient)
     }
 
+    fn api_vm_disk_snapshot(&self, vm_disk_snapshot: &str) -> ApiResult {
+        self.vm_disk_snapshot(vm_disk_snapshot)
+            .map_err(Error::DBusApiClient)
+    }
+
+    fn api_vm_guest_command(&self, guest_command_data: &str) -> Result<Option<String>, Error> {
+        self.vm_guest_command(guest_command_data)
+            .map(Option::from)
+            .map_err(Error::DBusApiClient)
+    }
+
+    fn api_vm_hibernate(&self, vm_hibernate_config: &str) -> ApiResult {
+        self.vm_hibernate(vm_hibernate_config)
+            .map_err(Error::DBusApiClient)
+    }
+
     fn api_vm_info(&self) -> ApiResult {
         self.vm_info()
             .map(|info| println!("{info}"))
@@ -216,10 +300,30 @@ impl<'a> DBusApi1ProxyBlocking<'a> {
             .map_err(Error::DBusApiClient)
     }
 
+    fn api_vm_reset_device(&self, vm_reset_device: &str) -> ApiResult {
+        self.vm_reset_device(vm_reset_device)
+            .map_err(Error::DBusApiClient)
+    }
+
+    fn api_vm_set_balloon(&self, vm_set_balloon: &str) -> ApiResult {
+        self.vm_set_balloon(vm_set_balloon)
+            .map_err(Error::DBusApiClient)
+    }
+
+    fn api_vm_set_rate_limit_group(&self, vm_set_rate_limit_group: &str) -> ApiResult {
+        self.vm_set_rate_limit_group(vm_set_rate_limit_group)
+            .map_err(Error::DBusApiClient)
+    }
+
     fn api_vm_resize(&self, vm_resize: &str) -> ApiResult {
         self.vm_resize(vm_resize).map_err(Error::DBusApiClient)
     }
 
+    fn api_vm_resize_disk(&self, vm_resize_disk: &str) -> ApiResult {
+        self.vm_resize_disk(vm_resize_disk)
+            .map_err(Error::DBusApiClient)
+    }
+
     fn api_vm_resize_zone(&self, vm_resize_zone: &str) -> ApiResult {
         self.vm_resize_zone(vm_resize_zone)
             .map_err(Error::DBusApiClient)
@@ -266,12 +370,22 @@ impl TargetApi<'_> {
 
 fn rest_api_do_command(matches: &ArgMatches, socket: &mut UnixStream) -> ApiResult {
     match matches.subcommand_name() {
+        Some("backup") => {
+            let backup = backup_data(matches.subcommand_matches("backup").unwrap())?;
+            simple_api_command(socket, "PUT", "backup", Some(&backup)).map_err(Error::HttpApiClient)
+        }
         Some("boot") => {
             simple_api_command(socket, "PUT", "boot", None).map_err(Error::HttpApiClient)
         }
         Some("delete") => {
             simple_api_command(socket, "PUT", "delete", None).map_err(Error::HttpApiClient)
         }
+        Some("disk-snapshot") => {
+            let disk_snapshot =
+                disk_snapshot_data(matches.subcommand_matches("disk-snapshot").unwrap());
+            simple_api_command(socket, "PUT", "disk-snapshot", Some(&disk_snapshot))
+                .map_err(Error::HttpApiClient)
+        }
         Some("shutdown-vmm") => simple_api_full_command(socket, "PUT", "vmm.shutdown", None)
             .map_err(Error::HttpApiClient),
         Some("resume") => {
@@ -289,6 +403,12 @@ fn rest_api_do_command(matches: &ArgMatches, socket: &mut UnixStream) -> ApiResu
         Some("info") => {
             simple_api_command(socket, "GET", "info", None).map_err(Error::HttpApiClient)
         }
+        Some("config") => {
+            simple_api_command(socket, "GET", "config", None).map_err(Error::HttpApiClient)
+        }
+        Some("console-log") => {
+            simple_api_command(socket, "GET", "console-log", None).map_err(Error::HttpApiClient)
+        }
         Some("counters") => {
             simple_api_command(socket, "GET", "counters", None).map_err(Error::HttpApiClient)
         }
@@ -299,6 +419,10 @@ fn rest_api_do_command(matches: &ArgMatches, socket: &mut UnixStream) -> ApiResu
             simple_api_command(socket, "PUT", "shutdown", None).map_err(Error::HttpApiClient)
         }
         Some("nmi") => simple_api_command(socket, "PUT", "nmi", None).map_err(Error::HttpApiClient),
+        Some("refresh-certificates") => {
+            simple_api_command(socket, "PUT", "refresh-certificates", None)
+                .map_err(Error::HttpApiClient)
+        }
         Some("resize") => {
             let resize = resize_config(
                 matches
@@ -335,6 +459,22 @@ fn rest_api_do_command(matches: &ArgMatches, socket: &mut UnixStream) -> ApiResu
             simple_api_command(socket, "PUT", "resize-zone", Some(&resize_zone))
                 .map_err(Error::HttpApiClient)
         }
+        Some("resize-disk") => {
+            let resize_disk = resize_disk_config(
+                matches
+                    .subcommand_matches("resize-disk")
+                    .unwrap()
+                    .get_one::<String>("id")
+                    .unwrap(),
+                matches
+                    .subcommand_matches("resize-disk")
+                    .unwrap()
+                    .get_one::<String>("size")
+                    .unwrap(),
+            )?;
+            simple_api_command(socket, "PUT", "resize-disk", Some(&resize_disk))
+                .map_err(Error::HttpApiClient)
+        }
         Some("add-device") => {
             let device_config = add_device_config(
                 matches
@@ -357,15 +497,44 @@ fn rest_api_do_command(matches: &ArgMatches, socket: &mut UnixStream) -> ApiResu
             simple_api_command(socket, "PUT", "remove-device", Some(&remove_device_data))
                 .map_err(Error::HttpApiClient)
         }
+        Some("reset-device") => {
+            let reset_device_data = reset_device_config(
+                matches
+                    .subcommand_matches("reset-device")
+                    .unwrap()
+                    .get_one::<String>("id")
+                    .unwrap(),
+            );
+            simple_api_command(socket, "PUT", "reset-device", Some(&reset_device_data))
+                .map_err(Error::HttpApiClient)
+        }
+        Some("set-balloon") => {
+            let set_balloon_data =
+                set_balloon_config(matches.subcommand_matches("set-balloon").unwrap());
+            simple_api_command(socket, "PUT", "set-balloon", Some(&set_balloon_data))
+                .map_err(Error::HttpApiClient)
+        }
+        Some("set-rate-limit-group") => {
+            let set_rate_limit_group_data = set_rate_limit_group_config(
+                matches.subcommand_matches("set-rate-limit-group").unwrap(),
+            );
+            simple_api_command(
+                socket,
+                "PUT",
+                "set-rate-limit-group",
+                Some(&set_rate_limit_group_data),
+            )
+            .map_err(Error::HttpApiClient)
+        }
         Some("add-disk") => {
-            let disk_config = add_disk_config(
+            let (disk_config, fds) = add_disk_config(
                 matches
                     .subcommand_matches("add-disk")
                     .unwrap()
                     .get_one::<String>("disk_config")
                     .unwrap(),
             )?;
-            simple_api_command(socket, "PUT", "add-disk", Some(&disk_config))
+            simple_api_command_with_fds(socket, "PUT", "add-disk", Some(&disk_config), fds)
                 .map_err(Error::HttpApiClient)
         }
         Some("add-fs") => {
@@ -390,6 +559,22 @@ fn rest_api_do_command(matches: &ArgMatches, socket: &mut UnixStream) -> ApiResu
             simple_api_command(socket, "PUT", "add-pmem", Some(&pmem_config))
                 .map_err(Error::HttpApiClient)
         }
+        Some("add-rate-limit-group") => {
+            let rate_limit_group_config = add_rate_limit_group_config(
+                matches
+                    .subcommand_matches("add-rate-limit-group")
+                    .unwrap()
+                    .get_one::<String>("rate_limit_group_config")
+                    .unwrap(),
+            )?;
+            simple_api_command(
+                socket,
+                "PUT",
+                "add-rate-limit-group",
+                Some(&rate_limit_group_config),
+            )
+            .map_err(Error::HttpApiClient)
+        }
         Some("add-net") => {
             let (net_config, fds) = add_net_config(
                 matches
@@ -401,6 +586,9 @@ fn rest_api_do_command(matches: &ArgMatches, socket: &mut UnixStream) -> ApiResu
             simple_api_command_with_fds(socket, "PUT", "add-net", Some(&net_config), fds)
                 .map_err(Error::HttpApiClient)
         }
+        Some("add-pci-segment") => {
+            simple_api_command(socket, "PUT", "add-pci-segment", None).map_err(Error::HttpApiClient)
+        }
         Some("add-user-device") => {
             let device_config = add_user_device_config(
                 matches
@@ -434,6 +622,18 @@ fn rest_api_do_command(matches: &ArgMatches, socket: &mut UnixStream) -> ApiResu
             simple_api_command(socket, "PUT", "add-vsock", Some(&vsock_config))
                 .map_err(Error::HttpApiClient)
         }
+        #[cfg(target_arch = "x86_64")]
+        Some("add-sgx-epc") => {
+            let sgx_epc_config = add_sgx_epc_config(
+                matches
+                    .subcommand_matches("add-sgx-epc")
+                    .unwrap()
+                    .get_one::<String>("sgx_epc_config")
+                    .unwrap(),
+            )?;
+            simple_api_command(socket, "PUT", "add-sgx-epc", Some(&sgx_epc_config))
+                .map_err(Error::HttpApiClient)
+        }
         Some("snapshot") => {
             let snapshot_config = snapshot_config(
                 matches
@@ -441,10 +641,29 @@ fn rest_api_do_command(matches: &ArgMatches, socket: &mut UnixStream) -> ApiResu
                     .unwrap()
                     .get_one::<String>("snapshot_config")
                     .unwrap(),
+                matches
+                    .subcommand_matches("snapshot")
+                    .unwrap()
+                    .get_one::<String>("signing_key"),
             );
             simple_api_command(socket, "PUT", "snapshot", Some(&snapshot_config))
                 .map_err(Error::HttpApiClient)
         }
+        Some("hibernate") => {
+            let hibernate_config = snapshot_config(
+                matches
+                    .subcommand_matches("hibernate")
+                    .unwrap()
+                    .get_one::<String>("hibernate_config")
+                    .unwrap(),
+                matches
+                    .subcommand_matches("hibernate")
+                    .unwrap()
+                    .get_one::<String>("signing_key"),
+            );
+            simple_api_command(socket, "PUT", "hibernate", Some(&hibernate_config))
+                .map_err(Error::HttpApiClient)
+        }
         Some("restore") => {
             let (restore_config, fds) = restore_config(
                 matches
@@ -508,6 +727,24 @@ fn rest_api_do_command(matches: &ArgMatches, socket: &mut UnixStream) -> ApiResu
             )?;
             simple_api_command(socket, "PUT", "create", Some(&data)).map_err(Error::HttpApiClient)
         }
+        Some("import-libvirt") => {
+            let data = import_libvirt_data(
+                matches
+                    .subcommand_matches("import-libvirt")
+                    .unwrap()
+                    .get_one::<String>("path")
+                    .unwrap(),
+            )?;
+            simple_api_command(socket, "PUT", "create", Some(&data)).map_err(Error::HttpApiClient)
+        }
+        Some("import-ovf") => {
+            let data = import_ovf_data(matches.subcommand_matches("import-ovf").unwrap())?;
+            simple_api_command(socket, "PUT", "create", Some(&data)).map_err(Error::HttpApiClient)
+        }
+        Some("guest") => guest_command(matches.subcommand_matches("guest").unwrap(), |data| {
+            simple_api_full_command_and_response(socket, "PUT", "vm.guest-command", Some(data))
+                .map_err(Error::HttpApiClient)
+        }),
         _ => unreachable!(),
     }
 }
@@ -515,14 +752,25 @@ fn rest_api_do_command(matches: &ArgMatches, socket: &mut UnixStream) -> ApiResu
 #[cfg(feature = "dbus_api")]
 fn dbus_api_do_command(matches: &ArgMatches, proxy: &DBusApi1ProxyBlocking<'_>) -> ApiResult {
     match matches.subcommand_name() {
+        Some("backup") => {
+            let backup = backup_data(matches.subcommand_matches("backup").unwrap())?;
+            proxy.api_vm_backup(&backup)
+        }
         Some("boot") => proxy.api_vm_boot(),
         Some("delete") => proxy.api_vm_delete(),
+        Some("disk-snapshot") => {
+            let disk_snapshot =
+                disk_snapshot_data(matches.subcommand_matches("disk-snapshot").unwrap());
+            proxy.api_vm_disk_snapshot(&disk_snapshot)
+        }
         Some("shutdown-vmm") => proxy.api_vmm_shutdown(),
         Some("resume") => proxy.api_vm_resume(),
         Some("power-button") => proxy.api_vm_power_button(),
         Some("reboot") => proxy.api_vm_reboot(),
         Some("pause") => proxy.api_vm_pause(),
         Some("info") => proxy.api_vm_info(),
+        Some("config") => proxy.api_vm_export_config(),
+        Some("console-log") => proxy.api_vm_console_log(),
         Some("counters") => proxy.api_vm_counters(),
         Some("ping") => proxy.api_vmm_ping(),
         Some("shutdown") => proxy.api_vm_shutdown(),
@@ -561,6 +809,21 @@ fn dbus_api_do_command(matches: &ArgMatches, proxy: &DBusApi1ProxyBlocking<'_>)
             )?;
             proxy.api_vm_resize_zone(&resize_zone)
         }
+        Some("resize-disk") => {
+            let resize_disk = resize_disk_config(
+                matches
+                    .subcommand_matches("resize-disk")
+                    .unwrap()
+                    .get_one::<String>("id")
+                    .unwrap(),
+                matches
+                    .subcommand_matches("resize-disk")
+                    .unwrap()
+                    .get_one::<String>("size")
+                    .unwrap(),
+            )?;
+            proxy.api_vm_resize_disk(&resize_disk)
+        }
         Some("add-device") => {
             let device_config = add_device_config(
                 matches
@@ -581,8 +844,29 @@ fn dbus_api_do_command(matches: &ArgMatches, proxy: &DBusApi1ProxyBlocking<'_>)
             );
             proxy.api_vm_remove_device(&remove_device_data)
         }
+        Some("reset-device") => {
+            let reset_device_data = reset_device_config(
+                matches
+                    .subcommand_matches("reset-device")
+                    .unwrap()
+                    .get_one::<String>("id")
+                    .unwrap(),
+            );
+            proxy.api_vm_reset_device(&reset_device_data)
+        }
+        Some("set-balloon") => {
+            let set_balloon_data =
+                set_balloon_config(matches.subcommand_matches("set-balloon").unwrap());
+            proxy.api_vm_set_balloon(&set_balloon_data)
+        }
+        Some("set-rate-limit-group") => {
+            let set_rate_limit_group_data = set_rate_limit_group_config(
+                matches.subcommand_matches("set-rate-limit-group").unwrap(),
+            );
+            proxy.api_vm_set_rate_limit_group(&set_rate_limit_group_data)
+        }
         Some("add-disk") => {
-            let disk_config = add_disk_config(
+            let (disk_config, _fds) = add_disk_config(
                 matches
                     .subcommand_matches("add-disk")
                     .unwrap()
@@ -611,6 +895,16 @@ fn dbus_api_do_command(matches: &ArgMatches, proxy: &DBusApi1ProxyBlocking<'_>)
             )?;
             proxy.api_vm_add_pmem(&pmem_config)
         }
+        Some("add-rate-limit-group") => {
+            let rate_limit_group_config = add_rate_limit_group_config(
+                matches
+                    .subcommand_matches("add-rate-limit-group")
+                    .unwrap()
+                    .get_one::<String>("rate_limit_group_config")
+                    .unwrap(),
+            )?;
+            proxy.api_vm_add_rate_limit_group(&rate_limit_group_config)
+        }
         Some("add-net") => {
             let (net_config, _fds) = add_net_config(
                 matches
@@ -621,6 +915,7 @@ fn dbus_api_do_command(matches: &ArgMatches, proxy: &DBusApi1ProxyBlocking<'_>)
             )?;
             proxy.api_vm_add_net(&net_config)
         }
+        Some("add-pci-segment") => proxy.api_vm_add_pci_segment(),
         Some("add-user-device") => {
             let device_config = add_user_device_config(
                 matches
@@ -651,6 +946,17 @@ fn dbus_api_do_command(matches: &ArgMatches, proxy: &DBusApi1ProxyBlocking<'_>)
             )?;
             proxy.api_vm_add_vsock(&vsock_config)
         }
+        #[cfg(target_arch = "x86_64")]
+        Some("add-sgx-epc") => {
+            let sgx_epc_config = add_sgx_epc_config(
+                matches
+                    .subcommand_matches("add-sgx-epc")
+                    .unwrap()
+                    .get_one::<String>("sgx_epc_config")
+                    .unwrap(),
+            )?;
+            proxy.api_vm_add_sgx_epc(&sgx_epc_config)
+        }
         Some("snapshot") => {
             let snapshot_config = snapshot_config(
                 matches
@@ -658,9 +964,27 @@ fn dbus_api_do_command(matches: &ArgMatches, proxy: &DBusApi1ProxyBlocking<'_>)
                     .unwrap()
                     .get_one::<String>("snapshot_config")
                     .unwrap(),
+                matches
+                    .subcommand_matches("snapshot")
+                    .unwrap()
+                    .get_one::<String>("signing_key"),
             );
             proxy.api_vm_snapshot(&snapshot_config)
         }
+        Some("hibernate") => {
+            let hibernate_config = snapshot_config(
+                matches
+                    .subcommand_matches("hibernate")
+                    .unwrap()
+                    .get_one::<String>("hibernate_config")
+                    .unwrap(),
+                matches
+                    .subcommand_matches("hibernate")
+                    .unwrap()
+                    .get_one::<String>("signing_key"),
+            );
+            proxy.api_vm_hibernate(&hibernate_config)
+        }
         Some("restore") => {
             let (restore_config, _fds) = restore_config(
                 matches
@@ -715,6 +1039,23 @@ fn dbus_api_do_command(matches: &ArgMatches, proxy: &DBusApi1ProxyBlocking<'_>)
             )?;
             proxy.api_vm_create(&data)
         }
+        Some("import-libvirt") => {
+            let data = import_libvirt_data(
+                matches
+                    .subcommand_matches("import-libvirt")
+                    .unwrap()
+                    .get_one::<String>("path")
+                    .unwrap(),
+            )?;
+            proxy.api_vm_create(&data)
+        }
+        Some("import-ovf") => {
+            let data = import_ovf_data(matches.subcommand_matches("import-ovf").unwrap())?;
+            proxy.api_vm_create(&data)
+        }
+        Some("guest") => guest_command(matches.subcommand_matches("guest").unwrap(), |data| {
+            proxy.api_vm_guest_command(data)
+        }),
         _ => unreachable!(),
     }
 }
@@ -724,7 +1065,7 @@ fn resize_config(
     memory: Option<&str>,
     balloon: Option<&str>,
 ) -> Result<String, Error> {
-    let desired_vcpus: Option<u8> = if let Some(cpus) = cpus {
+    let desired_vcpus: Option<u32> = if let Some(cpus) = cpus {
         Some(cpus.parse().map_err(Error::InvalidCpuCount)?)
     } else {
         None
@@ -773,6 +1114,46 @@ fn resize_zone_config(id: &str, size: &str) -> Result<String, Error> {
     Ok(serde_json::to_string(&resize_zone).unwrap())
 }
 
+fn resize_disk_config(id: &str, size: &str) -> Result<String, Error> {
+    let resize_disk = vmm::api::VmResizeDiskData {
+        id: id.to_owned(),
+        desired_size: size.parse::<ByteSized>().map_err(Error::InvalidDiskSize)?.0,
+    };
+
+    Ok(serde_json::to_string(&resize_disk).unwrap())
+}
+
+fn backup_data(matches: &ArgMatches) -> Result<String, Error> {
+    let disks = matches
+        .get_many::<String>("disk")
+        .unwrap_or_default()
+        .map(|d| {
+            d.split_once('=')
+                .map(|(id, overlay)| vmm::api::VmBackupDiskData {
+                    id: id.to_string(),
+                    overlay: overlay.into(),
+                })
+                .ok_or_else(|| Error::InvalidBackupDisk(d.to_string()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let backup = vmm::api::VmBackupData {
+        disks,
+        quiesce: matches.get_flag("quiesce"),
+    };
+
+    Ok(serde_json::to_string(&backup).unwrap())
+}
+
+fn disk_snapshot_data(matches: &ArgMatches) -> String {
+    let disk_snapshot = vmm::api::VmDiskSnapshotData {
+        id: matches.get_one::<String>("id").unwrap().to_owned(),
+        overlay: matches.get_one::<String>("overlay").unwrap().into(),
+    };
+
+    serde_json::to_string(&disk_snapshot).unwrap()
+}
+
 fn add_device_config(config: &str) -> Result<String, Error> {
     let device_config = DeviceConfig::parse(config).map_err(Error::AddDeviceConfig)?;
     let device_config = serde_json::to_string(&device_config).unwrap();
@@ -793,11 +1174,43 @@ fn remove_device_config(id: &str) -> String {
     serde_json::to_string(&remove_device_data).unwrap()
 }
 
-fn add_disk_config(config: &str) -> Result<String, Error> {
-    let disk_config = DiskConfig::parse(config).map_err(Error::AddDiskConfig)?;
+fn reset_device_config(id: &str) -> String {
+    let reset_device_data = vmm::api::VmResetDeviceData { id: id.to_owned() };
+
+    serde_json::to_string(&reset_device_data).unwrap()
+}
+
+fn set_balloon_config(matches: &ArgMatches) -> String {
+    let set_balloon_data = vmm::api::VmSetBalloonData {
+        deflate_on_oom: matches
+            .get_one::<String>("deflate_on_oom")
+            .map(|v| v == "on"),
+        free_page_reporting: matches
+            .get_one::<String>("free_page_reporting")
+            .map(|v| v == "on"),
+    };
+
+    serde_json::to_string(&set_balloon_data).unwrap()
+}
+
+fn set_rate_limit_group_config(matches: &ArgMatches) -> String {
+    let set_rate_limit_group_data = vmm::api::VmSetRateLimitGroupData {
+        id: matches.get_one::<String>("id").unwrap().to_owned(),
+        rate_limit_group: matches.get_one::<String>("rate_limit_group").cloned(),
+    };
+
+    serde_json::to_string(&set_rate_limit_group_data).unwrap()
+}
+
+fn add_disk_config(config: &str) -> Result<(String, Vec<i32>), Error> {
+    let mut disk_config = DiskConfig::parse(config).map_err(Error::AddDiskConfig)?;
+
+    // The key FD is taken out of the DiskConfig and sent along with the
+    // request, as it is represented with a different value on the server side.
+    let fds = disk_config.key_fd.take().into_iter().collect();
     let disk_config = serde_json::to_string(&disk_config).unwrap();
 
-    Ok(disk_config)
+    Ok((disk_config, fds))
 }
 
 fn add_fs_config(config: &str) -> Result<String, Error> {
@@ -814,6 +1227,14 @@ fn add_pmem_config(config: &str) -> Result<String, Error> {
     Ok(pmem_config)
 }
 
+fn add_rate_limit_group_config(config: &str) -> Result<String, Error> {
+    let rate_limit_group_config =
+        RateLimiterGroupConfig::parse(config).map_err(Error::AddRateLimitGroupConfig)?;
+    let rate_limit_group_config = serde_json::to_string(&rate_limit_group_config).unwrap();
+
+    Ok(rate_limit_group_config)
+}
+
 fn add_net_config(config: &str) -> Result<(String, Vec<i32>), Error> {
     let mut net_config = NetConfig::parse(config).map_err(Error::AddNetConfig)?;
 
@@ -841,9 +1262,18 @@ fn add_vsock_config(config: &str) -> Result<String, Error> {
     Ok(vsock_config)
 }
 
-fn snapshot_config(url: &str) -> String {
+#[cfg(target_arch = "x86_64")]
+fn add_sgx_epc_config(config: &str) -> Result<String, Error> {
+    let sgx_epc_config = SgxEpcConfig::parse(config).map_err(Error::AddSgxEpcConfig)?;
+    let sgx_epc_config = serde_json::to_string(&sgx_epc_config).unwrap();
+
+    Ok(sgx_epc_config)
+}
+
+fn snapshot_config(url: &str, signing_key: Option<&String>) -> String {
     let snapshot_config = vmm::api::VmSnapshotConfig {
         destination_url: String::from(url),
+        signing_key: signing_key.map(PathBuf::from),
     };
 
     serde_json::to_string(&snapshot_config).unwrap()
@@ -903,6 +1333,110 @@ fn create_data(path: &str) -> Result<String, Error> {
     Ok(data)
 }
 
+fn import_libvirt_data(path: &str) -> Result<String, Error> {
+    let domain = vmm::libvirt::parse_domain(&create_data(path)?).map_err(Error::ImportLibvirt)?;
+    for device in domain.ignored {
+        eprintln!("Ignoring unsupported {device} device");
+    }
+
+    Ok(serde_json::to_string(&domain.config).unwrap())
+}
+
+fn import_ovf_data(matches: &ArgMatches) -> Result<String, Error> {
+    let properties = matches
+        .get_many::<String>("property")
+        .unwrap_or_default()
+        .map(|p| {
+            p.split_once('=')
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .ok_or_else(|| Error::InvalidOvfProperty(p.to_string()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let options = vmm::ovf::ImportOptions {
+        firmware: matches.get_one::<String>("firmware").unwrap().into(),
+        disk_dir: matches.get_one::<String>("disk-dir").unwrap().into(),
+        cloud_init: matches.get_flag("cloud-init"),
+        properties,
+    };
+    let appliance = vmm::ovf::import(
+        Path::new(matches.get_one::<String>("path").unwrap()),
+        &options,
+    )
+    .map_err(Error::ImportOvf)?;
+    for device in appliance.ignored {
+        eprintln!("Ignoring unsupported {device} device");
+    }
+
+    Ok(serde_json::to_string(&appliance.config).unwrap())
+}
+
+fn guest_command(
+    matches: &ArgMatches,
+    mut execute: impl FnMut(&str) -> Result<Option<String>, Error>,
+) -> ApiResult {
+    let mut run = |command: &str, arguments: Option<serde_json::Value>| {
+        let data = json!({ "execute": command, "arguments": arguments }).to_string();
+        let response = execute(&data)?.unwrap_or_else(|| "null".to_string());
+        serde_json::from_str::<serde_json::Value>(&response).map_err(Error::InvalidGuestResponse)
+    };
+
+    match matches.subcommand() {
+        Some(("fsfreeze", _)) => {
+            let count = run("guest-fsfreeze-freeze", None)?;
+            println!("Frozen filesystems: {count}");
+        }
+        Some(("thaw", _)) => {
+            let count = run("guest-fsfreeze-thaw", None)?;
+            println!("Thawed filesystems: {count}");
+        }
+        Some(("osinfo", _)) => {
+            let info = run("guest-get-osinfo", None)?;
+            println!("{}", serde_json::to_string_pretty(&info).unwrap());
+        }
+        Some(("exec", matches)) => {
+            let exec = run(
+                "guest-exec",
+                Some(json!({
+                    "path": matches.get_one::<String>("path").unwrap(),
+                    "arg": matches.get_many::<String>("args").unwrap_or_default().collect::<Vec<_>>(),
+                    "capture-output": true,
+                })),
+            )?;
+
+            // The command runs asynchronously in the guest, its status has to
+            // be polled until it exits.
+            let status = loop {
+                let status = run("guest-exec-status", Some(json!({ "pid": exec["pid"] })))?;
+                if status["exited"].as_bool().unwrap_or(true) {
+                    break status;
+                }
+                thread::sleep(Duration::from_millis(100));
+            };
+
+            for (key, mut output) in [
+                ("out-data", Box::new(std::io::stdout()) as Box<dyn Write>),
+                ("err-data", Box::new(std::io::stderr())),
+            ] {
+                if let Some(data) = status[key].as_str() {
+                    let data = STANDARD
+                        .decode(data)
+                        .map_err(|_| Error::InvalidGuestOutput)?;
+                    output.write_all(&data).map_err(Error::WritingOutput)?;
+                }
+            }
+
+            if let Some(signal) = status["signal"].as_i64() {
+                process::exit(128 + signal as i32);
+            }
+            process::exit(status["exitcode"].as_i64().unwrap_or_default() as i32);
+        }
+        _ => unreachable!(),
+    }
+
+    Ok(())
+}
+
 /// Returns all [`Arg`]s in alphabetical order.
 ///
 /// This is the order used in the `--help` output.
@@ -956,6 +1490,7 @@ fn get_cli_commands_sorted() -> Box<[Command]> {
         Command::new("add-net")
             .about("Add network device")
             .arg(Arg::new("net_config").index(1).help(NetConfig::SYNTAX)),
+        Command::new("add-pci-segment").about("Add the next reserved PCI segment"),
         Command::new("add-pmem")
             .about("Add persistent memory device")
             .arg(
@@ -963,6 +1498,21 @@ fn get_cli_commands_sorted() -> Box<[Command]> {
                     .index(1)
                     .help(vmm::vm_config::PmemConfig::SYNTAX),
             ),
+        Command::new("add-rate-limit-group")
+            .about("Add rate limiter group")
+            .arg(
+                Arg::new("rate_limit_group_config")
+                    .index(1)
+                    .help(RateLimiterGroupConfig::SYNTAX),
+            ),
+        #[cfg(target_arch = "x86_64")]
+        Command::new("add-sgx-epc")
+            .about("Add SGX EPC section, given to the guest on its next boot")
+            .arg(
+                Arg::new("sgx_epc_config")
+                    .index(1)
+                    .help(SgxEpcConfig::SYNTAX),
+            ),
         Command::new("add-user-device")
             .about("Add userspace device")
             .arg(
@@ -976,7 +1526,25 @@ fn get_cli_commands_sorted() -> Box<[Command]> {
         Command::new("add-vsock")
             .about("Add vsock device")
             .arg(Arg::new("vsock_config").index(1).help(VsockConfig::SYNTAX)),
+        Command::new("backup")
+            .about("Switch disks to overlays, leaving their current image to back up")
+            .arg(
+                Arg::new("disk")
+                    .long("disk")
+                    .help("Disk to switch to a new QCOW2 overlay: <id>=<overlay path>")
+                    .num_args(1)
+                    .action(ArgAction::Append),
+            )
+            .arg(
+                Arg::new("quiesce")
+                    .long("quiesce")
+                    .help("Freeze the guest filesystems through the guest agent meanwhile")
+                    .num_args(0)
+                    .action(ArgAction::SetTrue),
+            ),
         Command::new("boot").about("Boot a created VM"),
+        Command::new("config").about("Normalized configuration of the VM"),
+        Command::new("console-log").about("Latest output of the consoles kept in ring buffers"),
         Command::new("coredump")
             .about("Create a coredump from VM")
             .arg(Arg::new("coredump_config").index(1).help("<file_path>")),
@@ -985,6 +1553,93 @@ fn get_cli_commands_sorted() -> Box<[Command]> {
             .about("Create VM from a JSON configuration")
             .arg(Arg::new("path").index(1).default_value("-")),
         Command::new("delete").about("Delete a VM"),
+        Command::new("disk-snapshot")
+            .about("Switch a disk to an overlay, leaving its current image to back up")
+            .arg(
+                Arg::new("id")
+                    .long("id")
+                    .help("Disk identifier")
+                    .num_args(1)
+                    .required(true),
+            )
+            .arg(
+                Arg::new("overlay")
+                    .long("overlay")
+                    .help("Path of the QCOW2 overlay to create")
+                    .num_args(1)
+                    .required(true),
+            ),
+        Command::new("guest")
+            .about("Run a command of the guest agent")
+            .subcommand_required(true)
+            .subcommand(
+                Command::new("exec")
+                    .about("Run a program in the guest and wait for its completion")
+                    .arg(
+                        Arg::new("path")
+                            .index(1)
+                            .required(true)
+                            .help("Path of the program in the guest"),
+                    )
+                    .arg(
+                        Arg::new("args")
+                            .index(2)
+                            .num_args(0..)
+                            .trailing_var_arg(true)
+                            .allow_hyphen_values(true)
+                            .help("Arguments of the program"),
+                    ),
+            )
+            .subcommand(Command::new("fsfreeze").about("Freeze the guest filesystems"))
+            .subcommand(Command::new("osinfo").about("Guest operating system information"))
+            .subcommand(Command::new("thaw").about("Thaw the guest filesystems")),
+        Command::new("hibernate")
+            .about("Save the VM to disk and exit the VMM")
+            .arg(
+                Arg::new("hibernate_config")
+                    .index(1)
+                    .help("<destination_url>"),
+            )
+            .arg(
+                Arg::new("signing_key")
+                    .long("signing-key")
+                    .help("Key file to sign the snapshot with")
+                    .num_args(1),
+            ),
+        Command::new("import-libvirt")
+            .about("Create VM from a libvirt domain XML definition")
+            .arg(Arg::new("path").index(1).default_value("-")),
+        Command::new("import-ovf")
+            .about("Create VM from an OVF appliance, either an OVF descriptor or an OVA archive")
+            .arg(Arg::new("path").index(1).required(true))
+            .arg(
+                Arg::new("firmware")
+                    .long("firmware")
+                    .help("Firmware booting the appliance from its disks")
+                    .num_args(1)
+                    .required(true),
+            )
+            .arg(
+                Arg::new("disk-dir")
+                    .long("disk-dir")
+                    .help("Directory where the converted disks and cloud-init data are written")
+                    .num_args(1)
+                    .default_value("."),
+            )
+            .arg(
+                Arg::new("cloud-init")
+                    .long("cloud-init")
+                    .help("Map the appliance properties to cloud-init data")
+                    .num_args(0)
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("property")
+                    .long("property")
+                    .help("Value of an appliance property: <key>=<value>")
+                    .num_args(1)
+                    .action(ArgAction::Append),
+            ),
         Command::new("info").about("Info on the VM"),
         Command::new("nmi").about("Trigger NMI"),
         Command::new("pause").about("Pause the VM"),
@@ -998,9 +1653,14 @@ fn get_cli_commands_sorted() -> Box<[Command]> {
                     .index(1)
                     .help("<receiver_url>"),
             ),
+        Command::new("refresh-certificates")
+            .about("Reload the platform certificates returned to a confidential guest"),
         Command::new("remove-device")
             .about("Remove VFIO and PCI device")
             .arg(Arg::new("id").index(1).help("<device_id>")),
+        Command::new("reset-device")
+            .about("Reset VFIO device")
+            .arg(Arg::new("id").index(1).help("<device_id>")),
         Command::new("resize")
             .about("Resize the VM")
             .arg(
@@ -1021,6 +1681,20 @@ fn get_cli_commands_sorted() -> Box<[Command]> {
                     .help("New memory size in bytes (supports K/M/G suffix)")
                     .num_args(1),
             ),
+        Command::new("resize-disk")
+            .about("Grow a disk")
+            .arg(
+                Arg::new("id")
+                    .long("id")
+                    .help("Disk identifier")
+                    .num_args(1),
+            )
+            .arg(
+                Arg::new("size")
+                    .long("size")
+                    .help("New disk size in bytes (supports K/M/G suffix)")
+                    .num_args(1),
+            ),
         Command::new("resize-zone")
             .about("Resize a memory zone")
             .arg(
@@ -1056,6 +1730,30 @@ fn get_cli_commands_sorted() -> Box<[Command]> {
                     .num_args(0)
                     .action(ArgAction::SetTrue),
             ),
+        Command::new("set-balloon")
+            .about("Change the features of the balloon")
+            .arg(
+                Arg::new("deflate_on_oom")
+                    .long("deflate-on-oom")
+                    .help("Deflate the balloon when the guest is out of memory")
+                    .value_parser(["on", "off"])
+                    .num_args(1),
+            )
+            .arg(
+                Arg::new("free_page_reporting")
+                    .long("free-page-reporting")
+                    .help("Let the guest report its free pages")
+                    .value_parser(["on", "off"])
+                    .num_args(1),
+            ),
+        Command::new("set-rate-limit-group")
+            .about("Move a disk to another rate limiter group")
+            .arg(Arg::new("id").index(1).help("<disk_id>").required(true))
+            .arg(
+                Arg::new("rate_limit_group")
+                    .index(2)
+                    .help("<group_id>, the disk leaving its group if omitted"),
+            ),
         Command::new("shutdown").about("Shutdown the VM"),
         Command::new("shutdown-vmm").about("Shutdown the VMM"),
         Command::new("snapshot")
@@ -1064,6 +1762,12 @@ fn get_cli_commands_sorted() -> Box<[Command]> {
                 Arg::new("snapshot_config")
                     .index(1)
                     .help("<destination_url>"),
+            )
+            .arg(
+                Arg::new("signing_key")
+                    .long("signing-key")
+                    .help("Key file to sign the snapshot with")
+                    .num_args(1),
             ),
     ]
     .to_vec()
@@ -1234,4 +1938,52 @@ mod tests {
             assert_args_sorted(|| command.get_arguments());
         }
     }
+
+    #[test]
+    fn test_disk_snapshot_data() {
+        let command = get_cli_commands_sorted()
+            .into_vec()
+            .into_iter()
+            .find(|command| command.get_name() == "disk-snapshot")
+            .unwrap();
+        command
+            .clone()
+            .try_get_matches_from(["disk-snapshot", "--id", "disk0"])
+            .unwrap_err();
+
+        let matches = command
+            .try_get_matches_from([
+                "disk-snapshot",
+                "--id",
+                "disk0",
+                "--overlay",
+                "/tmp/disk0.qcow2",
+            ])
+            .unwrap();
+        let disk_snapshot: vmm::api::VmDiskSnapshotData =
+            serde_json::from_str(&disk_snapshot_data(&matches)).unwrap();
+        assert_eq!(disk_snapshot.id, "disk0");
+        assert_eq!(disk_snapshot.overlay, PathBuf::from("/tmp/disk0.qcow2"));
+    }
+
+    #[test]
+    fn test_set_balloon_config() {
+        let command = get_cli_commands_sorted()
+            .into_vec()
+            .into_iter()
+            .find(|command| command.get_name() == "set-balloon")
+            .unwrap();
+        command
+            .clone()
+            .try_get_matches_from(["set-balloon", "--deflate-on-oom", "yes"])
+            .unwrap_err();
+
+        let matches = command
+            .try_get_matches_from(["set-balloon", "--deflate-on-oom", "off"])
+            .unwrap();
+        let set_balloon: vmm::api::VmSetBalloonData =
+            serde_json::from_str(&set_balloon_config(&matches)).unwrap();
+        assert_eq!(set_balloon.deflate_on_oom, Some(false));
+        assert_eq!(set_balloon.free_page_reporting, None);
+    }
 }
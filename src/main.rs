@@ -25,14 +25,19 @@ use vmm::api::dbus::{dbus_api_graceful_shutdown, DBusApiOptions};
 use vmm::api::http::http_api_graceful_shutdown;
 use vmm::api::ApiAction;
 use vmm::config::{RestoreConfig, VmParams};
+use vmm::hooks::HookConfig;
 use vmm::landlock::{Landlock, LandlockError};
 use vmm::vm_config;
+#[cfg(feature = "pvmemcontrol")]
+use vmm::vm_config::PvmemcontrolConfig;
 #[cfg(target_arch = "x86_64")]
 use vmm::vm_config::SgxEpcConfig;
 use vmm::vm_config::{
-    BalloonConfig, DeviceConfig, DiskConfig, FsConfig, LandlockConfig, NetConfig, NumaConfig,
-    PciSegmentConfig, PmemConfig, RateLimiterGroupConfig, TpmConfig, UserDeviceConfig, VdpaConfig,
-    VmConfig, VsockConfig,
+    BalloonConfig, CloudInitConfig, CrashDumpConfig, DeviceConfig, DiskConfig,
+    FallbackFirmwareConfig, FsConfig, GuestAgentConfig, ImdsConfig, IoThreadsConfig,
+    LandlockConfig, NetConfig, NetnsConfig, NumaConfig, PciSegmentConfig, PmemConfig,
+    RateLimitScheduleConfig, RateLimiterGroupConfig, ResourcesConfig, RestartPolicyConfig,
+    RtcConfig, ScmiConfig, TpmConfig, UserDeviceConfig, VdpaConfig, VmConfig, VsockConfig,
 };
 use vmm_sys_util::eventfd::EventFd;
 use vmm_sys_util::signal::block_signal;
@@ -64,6 +69,10 @@ enum Error {
     VmRestore(#[source] vmm::api::ApiError),
     #[error("Error parsing restore")]
     ParsingRestore(#[source] vmm::config::Error),
+    #[error("Error reading hibernation manifest")]
+    ReadingHibernationManifest(#[source] anyhow::Error),
+    #[error("Error resuming VM")]
+    VmResume(#[source] vmm::api::ApiError),
     #[error("Failed to join on VMM thread: {0:?}")]
     ThreadJoin(std::boxed::Box<dyn std::any::Any + std::marker::Send>),
     #[error("VMM thread exited with error")]
@@ -84,11 +93,21 @@ enum Error {
     EventMonitorIo(#[source] std::io::Error),
     #[error("Event monitor thread failed")]
     EventMonitorThread(#[source] vmm::Error),
+    #[error("Error parsing --io-trace")]
+    ParsingIoTrace(#[source] option_parser::OptionParserError),
+    #[error("Error parsing --io-trace: path and mode=record|replay required")]
+    BareIoTrace,
+    #[error("Error opening the I/O trace")]
+    IoTrace(#[source] std::io::Error),
+    #[error("Error parsing --hook")]
+    ParsingHook(#[source] vmm::hooks::Error),
+    #[error("Hooks thread failed")]
+    HooksThread(#[source] vmm::Error),
     #[cfg(feature = "guest_debug")]
     #[error("Error parsing --gdb")]
     ParsingGdb(#[source] option_parser::OptionParserError),
     #[cfg(feature = "guest_debug")]
-    #[error("Error parsing --gdb: path required")]
+    #[error("Error parsing --gdb: path or tcp required")]
     BareGdb,
     #[error("Error creating log file")]
     LogFileCreation(#[source] std::io::Error),
@@ -189,7 +208,10 @@ fn get_cli_options_sorted(
     [
         Arg::new("api-socket")
             .long("api-socket")
-            .help("HTTP API socket (UNIX domain socket): path=</path/to/a/file> or fd=<fd>.")
+            .help(
+                "HTTP API socket (UNIX domain socket): path=</path/to/a/file> or fd=<fd>. \
+                 Defaults to the socket passed through systemd socket activation, if any.",
+            )
             .num_args(1)
             .group("vmm-config"),
         Arg::new("balloon")
@@ -197,14 +219,28 @@ fn get_cli_options_sorted(
             .help(BalloonConfig::SYNTAX)
             .num_args(1)
             .group("vm-config"),
+        Arg::new("cloud-init")
+            .long("cloud-init")
+            .help(CloudInitConfig::SYNTAX)
+            .num_args(1)
+            .group("vm-config"),
         Arg::new("cmdline")
             .long("cmdline")
             .help("Kernel command line")
             .num_args(1)
-            .group("vm-config"), Arg::new("console")
+            .group("vm-config"),
+        Arg::new("config")
+            .long("config")
+            .help(
+                "Path to a JSON, YAML or TOML file describing the VM. \
+                The other VM options override the matching fields",
+            )
+            .num_args(1)
+            .group("vm-payload"),
+        Arg::new("console")
             .long("console")
             .help(
-                "Control (virtio) console: \"off|null|pty|tty|file=</path/to/a/file>,iommu=on|off\"",
+                "Control (virtio) console: \"off|null|pty|tty|file=</path/to/a/file>,iommu=on|off,tee=[file@</path/to/a/file>,socket@</path/to/a/socket>,ring@<size>]\"",
             )
             .default_value("tty")
             .group("vm-config"),
@@ -219,6 +255,11 @@ fn get_cli_options_sorted(
             )
             .default_value(default_vcpus)
             .group("vm-config"),
+        Arg::new("crash-dump")
+            .long("crash-dump")
+            .help(CrashDumpConfig::SYNTAX)
+            .num_args(1)
+            .group("vm-config"),
         #[cfg(target_arch = "x86_64")]
         Arg::new("debug-console")
             .long("debug-console")
@@ -259,9 +300,17 @@ fn get_cli_options_sorted(
             .help("File to report events on: path=</path/to/a/file> or fd=<fd>")
             .num_args(1)
             .group("vmm-config"),
+        Arg::new("fallback-firmware")
+            .long("fallback-firmware")
+            .help(FallbackFirmwareConfig::SYNTAX)
+            .num_args(1)
+            .group("vm-config"),
         Arg::new("firmware")
             .long("firmware")
-            .help("Path to firmware that is loaded in an architectural specific way")
+            .help(
+                "Path to firmware that is loaded in an architectural specific way, \
+                or fd=<fd> to read it from an inherited file descriptor",
+            )
             .num_args(1)
             .group("vm-payload"),
         Arg::new("fs")
@@ -272,13 +321,24 @@ fn get_cli_options_sorted(
         #[cfg(feature = "guest_debug")]
         Arg::new("gdb")
             .long("gdb")
-            .help("GDB socket (UNIX domain socket): path=</path/to/a/file>")
+            .help("GDB socket (UNIX domain socket or TCP): path=</path/to/a/file>|tcp=<host_ip:port>")
             .num_args(1)
             .group("vmm-config"),
+        Arg::new("guest-agent")
+            .long("guest-agent")
+            .help(GuestAgentConfig::SYNTAX)
+            .num_args(0..=1)
+            .default_missing_value("")
+            .group("vm-config"),
+        Arg::new("hook")
+            .long("hook")
+            .help(HookConfig::SYNTAX)
+            .num_args(1..)
+            .group("vmm-config"),
         #[cfg(feature = "igvm")]
         Arg::new("igvm")
             .long("igvm")
-            .help("Path to IGVM file to load.")
+            .help("Path to IGVM file to load, or fd=<fd> to read it from an inherited file descriptor.")
             .num_args(1)
             .group("vm-payload"),
         #[cfg(feature = "sev_snp")]
@@ -287,16 +347,50 @@ fn get_cli_options_sorted(
             .help("Host specific data to SEV SNP guest")
             .num_args(1)
             .group("vm-config"),
+        #[cfg(feature = "sev_snp")]
+        Arg::new("id-auth")
+            .long("id-auth")
+            .help("Path to the ID authentication information signing the SEV SNP ID block")
+            .num_args(1)
+            .group("vm-config"),
+        #[cfg(feature = "sev_snp")]
+        Arg::new("id-block")
+            .long("id-block")
+            .help("Path to the SEV SNP ID block binding the launch measurement to an identity")
+            .num_args(1)
+            .group("vm-config"),
+        Arg::new("imds")
+            .long("imds")
+            .help(ImdsConfig::SYNTAX)
+            .num_args(1)
+            .group("vm-config"),
         Arg::new("initramfs")
             .long("initramfs")
-            .help("Path to initramfs image")
+            .help(
+                "Path to initramfs image, images being concatenated when given multiple times. \
+                The first image can be read from an inherited file descriptor with fd=<fd>",
+            )
+            .num_args(1..)
+            .group("vm-config"),
+        Arg::new("io-threads")
+            .long("io-threads")
+            .help(IoThreadsConfig::SYNTAX)
             .num_args(1)
             .group("vm-config"),
+        Arg::new("io-trace")
+            .long("io-trace")
+            .help(
+                "Record the inputs of the devices to a trace, or replay them from it: \
+                path=</path/to/a/file>,mode=record|replay",
+            )
+            .num_args(1)
+            .group("vmm-config"),
         Arg::new("kernel")
             .long("kernel")
             .help(
                 "Path to kernel to load. This may be a kernel or firmware that supports a PVH \
-                entry point (e.g. vmlinux) or architecture equivalent",
+                entry point (e.g. vmlinux) or architecture equivalent. Use fd=<fd> to read it \
+                from an inherited file descriptor",
             )
             .num_args(1)
             .group("vm-payload"),
@@ -328,7 +422,7 @@ fn get_cli_options_sorted(
                      hotplug_method=acpi|virtio-mem,\
                      hotplug_size=<hotpluggable_memory_size>,\
                      hotplugged_size=<hotplugged_memory_size>,\
-                     prefault=on|off,thp=on|off\"",
+                     prefault=on|off,thp=on|off,thp_collapse=on|off\"",
             )
             .default_value(default_memory)
             .group("vm-config"),
@@ -351,6 +445,11 @@ fn get_cli_options_sorted(
             .help(NetConfig::SYNTAX)
             .num_args(1..)
             .group("vm-config"),
+        Arg::new("netns")
+            .long("netns")
+            .help(NetnsConfig::SYNTAX)
+            .num_args(1)
+            .group("vm-config"),
         Arg::new("numa")
             .long("numa")
             .help(NumaConfig::SYNTAX)
@@ -364,7 +463,7 @@ fn get_cli_options_sorted(
         Arg::new("platform")
             .long("platform")
             .help(
-                "num_pci_segments=<num_pci_segments>,iommu_segments=<list_of_segments>,iommu_address_width=<bits>,serial_number=<dmi_device_serial_number>,uuid=<dmi_device_uuid>,oem_strings=<list_of_strings>"
+                "num_pci_segments=<num_pci_segments>,max_pci_segments=<max_pci_segments>,iommu_segments=<list_of_segments>,iommu_address_width=<bits>,serial_number=<dmi_device_serial_number>,uuid=<dmi_device_uuid>,oem_strings=<list_of_strings>,boot_method=both|fdt|acpi,vmbus=on|off,legacy_devices=on|off,auto_numa=on|off,host_numa_distances=on|off"
             )
             .num_args(1)
             .group("vm-config"),
@@ -376,9 +475,9 @@ fn get_cli_options_sorted(
         #[cfg(feature = "pvmemcontrol")]
         Arg::new("pvmemcontrol")
             .long("pvmemcontrol")
-            .help("Pvmemcontrol device")
-            .num_args(0)
-            .action(ArgAction::SetTrue)
+            .help(PvmemcontrolConfig::SYNTAX)
+            .num_args(0..=1)
+            .default_missing_value("")
             .group("vm-config"),
         Arg::new("pvpanic")
             .long("pvpanic")
@@ -391,11 +490,35 @@ fn get_cli_options_sorted(
             .help(RateLimiterGroupConfig::SYNTAX)
             .num_args(1..)
             .group("vm-config"),
+        Arg::new("rate-limit-schedule")
+            .long("rate-limit-schedule")
+            .help(RateLimitScheduleConfig::SYNTAX)
+            .num_args(1..)
+            .group("vm-config"),
+        Arg::new("resources")
+            .long("resources")
+            .help(ResourcesConfig::SYNTAX)
+            .num_args(1)
+            .group("vm-config"),
+        Arg::new("restart-policy")
+            .long("restart-policy")
+            .help(RestartPolicyConfig::SYNTAX)
+            .num_args(1)
+            .group("vm-config"),
         Arg::new("restore")
             .long("restore")
             .help(RestoreConfig::SYNTAX)
             .num_args(1)
             .group("vmm-config"),
+        Arg::new("resume")
+            .long("resume")
+            .help(
+                "Resume a VM hibernated to the given URL (e.g. file:///foo/bar), \
+                 with the API socket it had unless --api-socket is given",
+            )
+            .num_args(1)
+            .conflicts_with("restore")
+            .group("vmm-config"),
         Arg::new("rng")
             .long("rng")
             .help(
@@ -403,6 +526,16 @@ fn get_cli_options_sorted(
             )
             .default_value(default_rng)
             .group("vm-config"),
+        Arg::new("rtc")
+            .long("rtc")
+            .help(RtcConfig::SYNTAX)
+            .num_args(1)
+            .group("vm-config"),
+        Arg::new("scmi")
+            .long("scmi")
+            .help(ScmiConfig::SYNTAX)
+            .num_args(1)
+            .group("vm-config"),
         Arg::new("seccomp")
             .long("seccomp")
             .num_args(1)
@@ -410,7 +543,7 @@ fn get_cli_options_sorted(
             .default_value("true"),
         Arg::new("serial")
             .long("serial")
-            .help("Control serial port: off|null|pty|tty|file=</path/to/a/file>|socket=</path/to/a/file>")
+            .help("Control serial port: off|null|pty|tty|file=</path/to/a/file>|socket=</path/to/a/file>,tee=[file@</path/to/a/file>,socket@</path/to/a/socket>,ring@<size>]")
             .default_value("null")
             .group("vm-config"),
         #[cfg(target_arch = "x86_64")]
@@ -419,6 +552,12 @@ fn get_cli_options_sorted(
             .help(SgxEpcConfig::SYNTAX)
             .num_args(1..)
             .group("vm-config"),
+        #[cfg(feature = "sev_snp")]
+        Arg::new("snp-certs")
+            .long("snp-certs")
+            .help("Path to the SEV SNP certificate table returned to extended guest requests")
+            .num_args(1)
+            .group("vm-config"),
         Arg::new("tpm")
             .long("tpm")
             .num_args(1)
@@ -434,6 +573,12 @@ fn get_cli_options_sorted(
             .action(ArgAction::Count)
             .help("Sets the level of debugging output")
             .group("logging"),
+        Arg::new("validate-config")
+            .long("validate-config")
+            .help("Check the VM configuration and the host resources it relies on, then exit without creating the VM")
+            .num_args(0)
+            .action(ArgAction::SetTrue)
+            .group("vmm-config"),
         Arg::new("vdpa")
             .long("vdpa")
             .help(VdpaConfig::SYNTAX)
@@ -526,6 +671,14 @@ fn start_vmm(cmd_arguments: ArgMatches) -> Result<Option<String>, Error> {
                     None,
                 )
             }
+        } else if let Some(fd) = vmm::systemd::listen_fd() {
+            // Socket activated by systemd.
+            (None, Some(fd))
+        } else if let Some(source_url) = cmd_arguments.get_one::<String>("resume") {
+            // Listen where the hibernated VMM did.
+            let manifest = vmm::migration::recv_hibernation_manifest(source_url)
+                .map_err(|e| Error::ReadingHibernationManifest(e.into()))?;
+            (manifest.api_socket, None)
         } else {
             (None, None)
         };
@@ -588,13 +741,17 @@ fn start_vmm(cmd_arguments: ArgMatches) -> Result<Option<String>, Error> {
     let hypervisor = hypervisor::new().map_err(Error::CreateHypervisor)?;
 
     #[cfg(feature = "guest_debug")]
-    let gdb_socket_path = if let Some(gdb_config) = cmd_arguments.get_one::<String>("gdb") {
+    let gdb_listener = if let Some(gdb_config) = cmd_arguments.get_one::<String>("gdb") {
         let mut parser = OptionParser::new();
-        parser.add("path");
+        parser.add("path").add("tcp");
         parser.parse(gdb_config).map_err(Error::ParsingGdb)?;
 
         if parser.is_set("path") {
-            Some(std::path::PathBuf::from(parser.get("path").unwrap()))
+            Some(vmm::GdbListener::Unix(std::path::PathBuf::from(
+                parser.get("path").unwrap(),
+            )))
+        } else if let Some(addr) = parser.convert("tcp").map_err(Error::ParsingGdb)? {
+            Some(vmm::GdbListener::Tcp(addr))
         } else {
             return Err(Error::BareGdb);
         }
@@ -646,6 +803,41 @@ fn start_vmm(cmd_arguments: ArgMatches) -> Result<Option<String>, Error> {
         })
         .transpose()?;
 
+    if let Some(trace_config) = cmd_arguments.get_one::<String>("io-trace") {
+        let mut parser = OptionParser::new();
+        parser.add("path").add("mode");
+        parser.parse(trace_config).map_err(Error::ParsingIoTrace)?;
+
+        let mode = match parser.get("mode").as_deref() {
+            Some("record") => replay::Mode::Record,
+            Some("replay") => replay::Mode::Replay,
+            _ => return Err(Error::BareIoTrace),
+        };
+        let path = parser.get("path").ok_or(Error::BareIoTrace)?;
+        replay::set_trace(mode, std::path::Path::new(&path)).map_err(Error::IoTrace)?;
+    }
+
+    let hooks = cmd_arguments
+        .get_many::<String>("hook")
+        .into_iter()
+        .flatten()
+        .map(|hook| HookConfig::parse(hook))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(Error::ParsingHook)?;
+    // The hooks are run on the events of the monitor, which is created
+    // without file if none was requested.
+    let hook_events = if hooks.is_empty() {
+        None
+    } else {
+        let mut monitor = match event_monitor.take() {
+            Some(monitor) => monitor,
+            None => event_monitor::set_monitor(None).map_err(Error::EventMonitorIo)?,
+        };
+        let events = monitor.subscribe();
+        event_monitor = Some(monitor);
+        Some(events)
+    };
+
     #[cfg(feature = "dbus_api")]
     let dbus_options = match (
         cmd_arguments.get_one::<String>("dbus-service-name"),
@@ -684,6 +876,19 @@ fn start_vmm(cmd_arguments: ArgMatches) -> Result<Option<String>, Error> {
         .map_err(Error::EventMonitorThread)?;
     }
 
+    let hooks_handle = hook_events
+        .map(|events| {
+            vmm::hooks::start_hooks_thread(
+                hooks,
+                events,
+                &seccomp_action,
+                hypervisor.hypervisor_type(),
+                exit_evt.try_clone().unwrap(),
+            )
+        })
+        .transpose()
+        .map_err(Error::HooksThread)?;
+
     event!("vmm", "starting");
 
     let vmm_thread_handle = vmm::start_vmm_thread(
@@ -696,7 +901,7 @@ fn start_vmm(cmd_arguments: ArgMatches) -> Result<Option<String>, Error> {
         api_request_sender_clone,
         api_request_receiver,
         #[cfg(feature = "guest_debug")]
-        gdb_socket_path,
+        gdb_listener,
         #[cfg(feature = "guest_debug")]
         debug_evt.try_clone().unwrap(),
         #[cfg(feature = "guest_debug")]
@@ -712,14 +917,15 @@ fn start_vmm(cmd_arguments: ArgMatches) -> Result<Option<String>, Error> {
         #[cfg(feature = "igvm")]
         let payload_present = cmd_arguments.contains_id("kernel")
             || cmd_arguments.contains_id("firmware")
-            || cmd_arguments.contains_id("igvm");
+            || cmd_arguments.contains_id("igvm")
+            || cmd_arguments.contains_id("config");
         #[cfg(not(feature = "igvm"))]
-        let payload_present =
-            cmd_arguments.contains_id("kernel") || cmd_arguments.contains_id("firmware");
+        let payload_present = cmd_arguments.contains_id("kernel")
+            || cmd_arguments.contains_id("firmware")
+            || cmd_arguments.contains_id("config");
 
         if payload_present {
-            let vm_params = VmParams::from_arg_matches(&cmd_arguments);
-            let vm_config = VmConfig::parse(vm_params).map_err(Error::ParsingConfig)?;
+            let vm_config = vm_config_from_args(&cmd_arguments).map_err(Error::ParsingConfig)?;
 
             // Create and boot the VM based off the VM config we just built.
             let sender = api_request_sender.clone();
@@ -741,6 +947,23 @@ fn start_vmm(cmd_arguments: ArgMatches) -> Result<Option<String>, Error> {
                     RestoreConfig::parse(restore_params).map_err(Error::ParsingRestore)?,
                 )
                 .map_err(Error::VmRestore)?;
+        } else if let Some(source_url) = cmd_arguments.get_one::<String>("resume") {
+            // The hibernated VM is restored with its original config, which
+            // names its TAP interfaces.
+            let restore_config = RestoreConfig {
+                source_url: std::path::PathBuf::from(source_url),
+                ..Default::default()
+            };
+            vmm::api::VmRestore
+                .send(
+                    api_evt.try_clone().unwrap(),
+                    api_request_sender.clone(),
+                    restore_config,
+                )
+                .map_err(Error::VmRestore)?;
+            vmm::api::VmResume
+                .send(api_evt.try_clone().unwrap(), api_request_sender, ())
+                .map_err(Error::VmResume)?;
         }
 
         Ok(())
@@ -750,6 +973,8 @@ fn start_vmm(cmd_arguments: ArgMatches) -> Result<Option<String>, Error> {
         if let Err(e) = exit_evt.write(1) {
             warn!("writing to exit EventFd: {e}");
         }
+    } else if let Some(notifier) = vmm::systemd::Notifier::from_env() {
+        notifier.ready();
     }
 
     if landlock_enable {
@@ -759,9 +984,12 @@ fn start_vmm(cmd_arguments: ArgMatches) -> Result<Option<String>, Error> {
             .map_err(Error::ApplyLandlock)?;
     }
 
-    vmm_thread_handle
-        .thread_handle
-        .join()
+    let vmm_thread_result = vmm_thread_handle.thread_handle.join();
+    // Let the hooks of the last events, such as the VM shutdown, complete.
+    if let Some(hooks_handle) = hooks_handle {
+        hooks_handle.stop();
+    }
+    vmm_thread_result
         .map_err(Error::ThreadJoin)?
         .map_err(Error::VmmThread)?;
 
@@ -777,6 +1005,63 @@ fn start_vmm(cmd_arguments: ArgMatches) -> Result<Option<String>, Error> {
     r.map(|_| api_socket_path)
 }
 
+fn vm_config_from_args(cmd_arguments: &ArgMatches) -> Result<VmConfig, vmm::config::Error> {
+    let vm_params = VmParams::from_arg_matches(cmd_arguments);
+    if let Some(path) = cmd_arguments.get_one::<String>("config") {
+        VmConfig::parse_with_file(std::path::Path::new(path), vm_params, cmd_arguments)
+    } else {
+        VmConfig::parse(vm_params)
+    }
+}
+
+// Joins an error with its sources, as a single line.
+fn error_chain(error: &(dyn std::error::Error + 'static)) -> String {
+    std::iter::successors(Some(error), |e| (*e).source())
+        .map(|e| e.to_string())
+        .collect::<Vec<_>>()
+        .join(": ")
+}
+
+// Checks the VM configuration given on the command line, the files it relies
+// on and the hypervisor availability, without creating the VM. The problems
+// found are printed as JSON on stdout, returning whether there was none.
+fn validate_config(cmd_arguments: &ArgMatches) -> bool {
+    let mut errors = Vec::new();
+
+    match vm_config_from_args(cmd_arguments) {
+        Ok(vm_config) => {
+            errors.extend(vm_config.validate_host().iter().map(|e| {
+                serde_json::json!({
+                    "kind": "host",
+                    "error": e.to_string(),
+                })
+            }));
+        }
+        Err(e) => errors.push(serde_json::json!({
+            "kind": "config",
+            "error": error_chain(&e),
+        })),
+    }
+
+    if let Err(e) = hypervisor::new() {
+        errors.push(serde_json::json!({
+            "kind": "hypervisor",
+            "error": error_chain(&e),
+        }));
+    }
+
+    let valid = errors.is_empty();
+    println!(
+        "{}",
+        serde_json::json!({
+            "valid": valid,
+            "errors": errors,
+        })
+    );
+
+    valid
+}
+
 // This is a best-effort solution to the latency induced by the RCU
 // synchronization that happens in the kernel whenever the file descriptor table
 // fills up.
@@ -874,6 +1159,14 @@ fn main() {
         return;
     }
 
+    if cmd_arguments.get_flag("validate-config") {
+        std::process::exit(if validate_config(&cmd_arguments) {
+            0
+        } else {
+            1
+        });
+    }
+
     if let Err(e) = expand_fdtable() {
         warn!("Error expanding FD table: {e}");
     }
@@ -967,16 +1260,30 @@ mod unit_tests {
                 prefault: false,
                 zones: None,
                 thp: true,
+                thp_collapse: false,
             },
             payload: Some(PayloadConfig {
                 kernel: Some(PathBuf::from("/path/to/kernel")),
+                kernel_fd: None,
                 firmware: None,
+                firmware_fd: None,
                 cmdline: None,
                 initramfs: None,
+                initramfs_fd: None,
+                extra_initramfs: None,
                 #[cfg(feature = "igvm")]
                 igvm: None,
+                #[cfg(feature = "igvm")]
+                igvm_fd: None,
                 #[cfg(feature = "sev_snp")]
                 host_data: None,
+                #[cfg(feature = "sev_snp")]
+                id_block: None,
+                #[cfg(feature = "sev_snp")]
+                id_auth: None,
+                #[cfg(feature = "sev_snp")]
+                snp_certs: None,
+                fallback_firmware: None,
             }),
             rate_limit_groups: None,
             disks: None,
@@ -993,12 +1300,14 @@ mod unit_tests {
                 mode: ConsoleOutputMode::Null,
                 iommu: false,
                 socket: None,
+                tee: Vec::new(),
             },
             console: ConsoleConfig {
                 file: None,
                 mode: ConsoleOutputMode::Tty,
                 iommu: false,
                 socket: None,
+                tee: Vec::new(),
             },
             #[cfg(target_arch = "x86_64")]
             debug_console: DebugConsoleConfig::default(),
@@ -1019,6 +1328,16 @@ mod unit_tests {
             pci_segments: None,
             platform: None,
             tpm: None,
+            scmi: None,
+            cloud_init: None,
+            imds: None,
+            netns: None,
+            guest_agent: None,
+            resources: None,
+            rtc: None,
+            restart_policy: None,
+            crash_dump: None,
+            io_threads: None,
             preserved_fds: None,
             landlock_enable: false,
             landlock_rules: None,
@@ -1181,6 +1500,67 @@ mod unit_tests {
         });
     }
 
+    #[test]
+    fn test_valid_vm_config_initramfs() {
+        [
+            (
+                vec![
+                    "cloud-hypervisor",
+                    "--kernel",
+                    "/path/to/kernel",
+                    "--initramfs",
+                    "/path/to/initramfs",
+                ],
+                r#"{
+                    "payload": {"kernel": "/path/to/kernel", "initramfs": "/path/to/initramfs"}
+                }"#,
+                true,
+            ),
+            (
+                vec![
+                    "cloud-hypervisor",
+                    "--kernel",
+                    "/path/to/kernel",
+                    "--initramfs",
+                    "/path/to/initramfs",
+                    "--initramfs",
+                    "/path/to/seed",
+                    "/path/to/firmware",
+                ],
+                r#"{
+                    "payload": {
+                        "kernel": "/path/to/kernel",
+                        "initramfs": "/path/to/initramfs",
+                        "extra_initramfs": ["/path/to/seed", "/path/to/firmware"]
+                    }
+                }"#,
+                true,
+            ),
+            (
+                vec![
+                    "cloud-hypervisor",
+                    "--kernel",
+                    "/path/to/kernel",
+                    "--initramfs",
+                    "/path/to/initramfs",
+                    "/path/to/seed",
+                ],
+                r#"{
+                    "payload": {
+                        "kernel": "/path/to/kernel",
+                        "initramfs": "/path/to/seed",
+                        "extra_initramfs": ["/path/to/initramfs"]
+                    }
+                }"#,
+                false,
+            ),
+        ]
+        .iter()
+        .for_each(|(cli, openapi, equal)| {
+            compare_vm_config_cli_vs_json(cli, openapi, *equal);
+        });
+    }
+
     #[test]
     fn test_valid_vm_config_disks() {
         [
@@ -2009,6 +2389,34 @@ mod unit_tests {
         });
     }
 
+    #[test]
+    fn test_valid_vm_config_file() {
+        let path = std::env::temp_dir()
+            .join(format!("ch-config-{}.yaml", std::process::id()))
+            .to_string_lossy()
+            .to_string();
+        std::fs::write(
+            &path,
+            "payload:\n  kernel: /path/to/kernel\n  cmdline: console=hvc0\ncpus:\n  boot_vcpus: 2\n  max_vcpus: 2\n",
+        )
+        .unwrap();
+
+        let (default_vcpus, default_memory, default_rng) = prepare_default_values();
+        let cmd_arguments = create_app(default_vcpus, default_memory, default_rng)
+            .get_matches_from(["cloud-hypervisor", "--config", &path, "--cmdline", "quiet"]);
+        let vm_params = VmParams::from_arg_matches(&cmd_arguments);
+        let vm_config =
+            VmConfig::parse_with_file(std::path::Path::new(&path), vm_params, &cmd_arguments)
+                .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let payload = vm_config.payload.as_ref().unwrap();
+        assert_eq!(payload.kernel, Some(PathBuf::from("/path/to/kernel")));
+        assert_eq!(payload.cmdline.as_deref(), Some("quiet"));
+        // Options left to their default value don't override the file.
+        assert_eq!(vm_config.cpus.boot_vcpus, 2);
+    }
+
     // TODO the check for the option list being sorted could be moved into the
     // getter itself, when the getter becomes a const function. This however
     // needs more support by Rust (as of March 2025).
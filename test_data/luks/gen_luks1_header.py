@@ -0,0 +1,74 @@
+#!/usr/bin/env python3
+# Generates luks1-header.img, see README.md.
+
+import hashlib, struct, uuid
+from cryptography.hazmat.primitives.ciphers import Cipher, algorithms, modes
+
+SECTOR = 512
+KEY_BYTES = 32
+STRIPES = 4000
+ITER = 1000
+PASS = b"cloud-hypervisor"
+MK = bytes(range(0x40, 0x40 + KEY_BYTES))
+MK_SALT = hashlib.sha256(b"mk-digest-salt").digest()
+SLOT_SALT = hashlib.sha256(b"slot0-salt").digest()
+
+def pbkdf2(pw, salt, n):
+    return hashlib.pbkdf2_hmac("sha256", pw, salt, ITER, n)
+
+def diffuse(buf):
+    out = b""
+    ds = 32
+    for i in range(0, len(buf), ds):
+        blk = buf[i:i+ds]
+        h = hashlib.sha256(struct.pack(">I", i // ds) + blk).digest()
+        out += h[:len(blk)]
+    return out
+
+def xor(a, b):
+    return bytes(x ^ y for x, y in zip(a, b))
+
+# AF split, the random stripes derived deterministically
+stripes = []
+d = bytes(KEY_BYTES)
+for i in range(STRIPES - 1):
+    s = hashlib.shake_256(b"stripe%d" % i).digest(KEY_BYTES)
+    stripes.append(s)
+    d = diffuse(xor(d, s))
+stripes.append(xor(d, MK))
+material = b"".join(stripes)
+material += bytes(-len(material) % SECTOR)
+
+slot_key = pbkdf2(PASS, SLOT_SALT, KEY_BYTES)
+enc = b""
+for n in range(len(material) // SECTOR):
+    c = Cipher(algorithms.AES(slot_key), modes.XTS(n.to_bytes(16, "little"))).encryptor()
+    enc += c.update(material[n*SECTOR:(n+1)*SECTOR]) + c.finalize()
+
+hdr = bytearray(592)
+hdr[0:6] = b"LUKS\xba\xbe"
+hdr[6:8] = struct.pack(">H", 1)
+hdr[8:11] = b"aes"
+hdr[40:51] = b"xts-plain64"
+hdr[72:78] = b"sha256"
+hdr[104:108] = struct.pack(">I", 4096)
+hdr[108:112] = struct.pack(">I", KEY_BYTES)
+hdr[112:132] = pbkdf2(MK, MK_SALT, 20)
+hdr[132:164] = MK_SALT
+hdr[164:168] = struct.pack(">I", ITER)
+hdr[168:204] = str(uuid.UUID("6b1b1b7e-3c3a-4f1e-9a57-2f0c1d3e4a5b")).encode()
+sectors = (len(material) // SECTOR + 7) // 8 * 8
+for i in range(8):
+    off = 208 + i * 48
+    if i == 0:
+        hdr[off:off+4] = struct.pack(">I", 0x00AC71F3)
+        hdr[off+4:off+8] = struct.pack(">I", ITER)
+        hdr[off+8:off+40] = SLOT_SALT
+    else:
+        hdr[off:off+4] = struct.pack(">I", 0x0000DEAD)
+    hdr[off+40:off+44] = struct.pack(">I", 8 + i * sectors)
+    hdr[off+44:off+48] = struct.pack(">I", STRIPES)
+
+img = bytes(hdr) + bytes(8 * SECTOR - len(hdr)) + enc
+open("luks1-header.img", "wb").write(img)
+print(len(img), len(material)//SECTOR, sectors)
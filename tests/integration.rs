@@ -652,7 +652,7 @@ fn remote_command_w_output(api_socket: &str, command: &str, arg: Option<&str>) -
 
 fn resize_command(
     api_socket: &str,
-    desired_vcpus: Option<u8>,
+    desired_vcpus: Option<u32>,
     desired_ram: Option<usize>,
     desired_balloon: Option<usize>,
     event_file: Option<&str>,
@@ -3221,7 +3221,7 @@ mod common_parallel {
         handle_child_output(r, &output);
     }
 
-    fn _test_virtio_block(image_name: &str, disable_io_uring: bool, disable_aio: bool) {
+    fn _test_virtio_block(image_name: &str, io_engine: Option<&str>) {
         let focal = UbuntuDiskConfig::new(image_name.to_string());
         let guest = Guest::new(Box::new(focal));
 
@@ -3251,10 +3251,11 @@ mod common_parallel {
                 )
                 .as_str(),
                 format!(
-                    "path={},readonly=on,direct=on,num_queues=4,_disable_io_uring={},_disable_aio={}",
+                    "path={},readonly=on,direct=on,num_queues=4{}",
                     blk_file_path.to_str().unwrap(),
-                    disable_io_uring,
-                    disable_aio,
+                    io_engine
+                        .map(|e| format!(",io_engine={e}"))
+                        .unwrap_or_default(),
                 )
                 .as_str(),
             ])
@@ -3308,27 +3309,40 @@ mod common_parallel {
 
     #[test]
     fn test_virtio_block_io_uring() {
-        _test_virtio_block(FOCAL_IMAGE_NAME, false, true)
+        _test_virtio_block(FOCAL_IMAGE_NAME, Some("io_uring"))
+    }
+
+    #[test]
+    fn test_virtio_block_io_uring_sqpoll_fixed_buffers() {
+        _test_virtio_block(
+            FOCAL_IMAGE_NAME,
+            Some("io_uring,io_uring_sqpoll=on,io_uring_fixed_buffers=on"),
+        )
     }
 
     #[test]
     fn test_virtio_block_aio() {
-        _test_virtio_block(FOCAL_IMAGE_NAME, true, false)
+        _test_virtio_block(FOCAL_IMAGE_NAME, Some("aio"))
     }
 
     #[test]
     fn test_virtio_block_sync() {
-        _test_virtio_block(FOCAL_IMAGE_NAME, true, true)
+        _test_virtio_block(FOCAL_IMAGE_NAME, Some("sync"))
+    }
+
+    #[test]
+    fn test_virtio_block_threads() {
+        _test_virtio_block(FOCAL_IMAGE_NAME, Some("threads"))
     }
 
     #[test]
     fn test_virtio_block_qcow2() {
-        _test_virtio_block(FOCAL_IMAGE_NAME_QCOW2, false, false)
+        _test_virtio_block(FOCAL_IMAGE_NAME_QCOW2, None)
     }
 
     #[test]
     fn test_virtio_block_qcow2_backing_file() {
-        _test_virtio_block(FOCAL_IMAGE_NAME_QCOW2_BACKING_FILE, false, false)
+        _test_virtio_block(FOCAL_IMAGE_NAME_QCOW2_BACKING_FILE, None)
     }
 
     #[test]
@@ -3353,7 +3367,7 @@ mod common_parallel {
             .output()
             .expect("Expect generating VHD image from RAW image");
 
-        _test_virtio_block(FOCAL_IMAGE_NAME_VHD, false, false)
+        _test_virtio_block(FOCAL_IMAGE_NAME_VHD, None)
     }
 
     #[test]
@@ -3377,7 +3391,7 @@ mod common_parallel {
             .output()
             .expect("Expect generating dynamic VHDx image from RAW image");
 
-        _test_virtio_block(FOCAL_IMAGE_NAME_VHDX, false, false)
+        _test_virtio_block(FOCAL_IMAGE_NAME_VHDX, None)
     }
 
     #[test]
@@ -4839,27 +4853,18 @@ mod common_parallel {
                 .ssh_command("echo 1 | sudo tee /sys/bus/cpu/devices/cpu3/online")
                 .unwrap();
             thread::sleep(std::time::Duration::new(10, 0));
-            assert_eq!(
-                guest.get_cpu_count().unwrap_or_default(),
-                u32::from(desired_vcpus)
-            );
+            assert_eq!(guest.get_cpu_count().unwrap_or_default(), desired_vcpus);
 
             guest.reboot_linux(0, None);
 
-            assert_eq!(
-                guest.get_cpu_count().unwrap_or_default(),
-                u32::from(desired_vcpus)
-            );
+            assert_eq!(guest.get_cpu_count().unwrap_or_default(), desired_vcpus);
 
             // Resize the VM
             let desired_vcpus = 2;
             resize_command(&api_socket, Some(desired_vcpus), None, None, None);
 
             thread::sleep(std::time::Duration::new(10, 0));
-            assert_eq!(
-                guest.get_cpu_count().unwrap_or_default(),
-                u32::from(desired_vcpus)
-            );
+            assert_eq!(guest.get_cpu_count().unwrap_or_default(), desired_vcpus);
 
             // Resize the VM back up to 4
             let desired_vcpus = 4;
@@ -4872,10 +4877,7 @@ mod common_parallel {
                 .ssh_command("echo 1 | sudo tee /sys/bus/cpu/devices/cpu3/online")
                 .unwrap();
             thread::sleep(std::time::Duration::new(10, 0));
-            assert_eq!(
-                guest.get_cpu_count().unwrap_or_default(),
-                u32::from(desired_vcpus)
-            );
+            assert_eq!(guest.get_cpu_count().unwrap_or_default(), desired_vcpus);
         });
 
         kill_child(&mut child);
@@ -5093,10 +5095,7 @@ mod common_parallel {
                 .ssh_command("echo 1 | sudo tee /sys/bus/cpu/devices/cpu3/online")
                 .unwrap();
             thread::sleep(std::time::Duration::new(10, 0));
-            assert_eq!(
-                guest.get_cpu_count().unwrap_or_default(),
-                u32::from(desired_vcpus)
-            );
+            assert_eq!(guest.get_cpu_count().unwrap_or_default(), desired_vcpus);
 
             assert!(guest.get_total_memory().unwrap_or_default() > 960_000);
         });
@@ -11,14 +11,25 @@ use anyhow::anyhow;
 use libc::{c_void, sockaddr_storage, socklen_t};
 use thiserror::Error;
 
+use crate::event_log::Sha256Digest;
 use crate::socket::SocketDev;
 use crate::{
     Commands, MemberType, Ptm, PtmCap, PtmEst, PtmInit, PtmResult, PtmSetBufferSize,
-    TPM_CRB_BUFFER_MAX, TPM_SUCCESS,
+    TPM_ALG_SHA256, TPM_CRB_BUFFER_MAX, TPM_SUCCESS,
 };
 
 const TPM_REQ_HDR_SIZE: usize = 10;
 
+/* TPM 2.0 commands sent by the VMM itself */
+const TPM_ST_NO_SESSIONS: u16 = 0x8001;
+const TPM_ST_SESSIONS: u16 = 0x8002;
+const TPM_CC_STARTUP: u32 = 0x144;
+const TPM_CC_PCR_EXTEND: u32 = 0x182;
+const TPM_SU_CLEAR: u16 = 0x0;
+const TPM_RS_PW: u32 = 0x4000_0009;
+/* returned by TPM2_Startup when the TPM is already started */
+const TPM_RC_INITIALIZE: u32 = 0x100;
+
 /* capability flags returned by PTM_GET_CAPABILITY */
 const PTM_CAP_INIT: u64 = 1;
 const PTM_CAP_SHUTDOWN: u64 = 1 << 1;
@@ -43,6 +54,31 @@ pub fn is_selftest(input: &[u8]) -> bool {
     false
 }
 
+fn pcr_extend_request(pcr: u32, digest: &Sha256Digest) -> Vec<u8> {
+    let mut auth = Vec::new();
+    // Empty password session
+    auth.extend_from_slice(&TPM_RS_PW.to_be_bytes());
+    auth.extend_from_slice(&0u16.to_be_bytes()); // nonce
+    auth.push(0); // session attributes
+    auth.extend_from_slice(&0u16.to_be_bytes()); // hmac
+
+    let mut params = Vec::new();
+    params.extend_from_slice(&1u32.to_be_bytes()); // digests count
+    params.extend_from_slice(&TPM_ALG_SHA256.to_be_bytes());
+    params.extend_from_slice(digest);
+
+    let size = TPM_REQ_HDR_SIZE + 4 + 4 + auth.len() + params.len();
+    let mut request = Vec::with_capacity(size);
+    request.extend_from_slice(&TPM_ST_SESSIONS.to_be_bytes());
+    request.extend_from_slice(&(size as u32).to_be_bytes());
+    request.extend_from_slice(&TPM_CC_PCR_EXTEND.to_be_bytes());
+    request.extend_from_slice(&pcr.to_be_bytes());
+    request.extend_from_slice(&(auth.len() as u32).to_be_bytes());
+    request.extend_from_slice(&auth);
+    request.extend_from_slice(&params);
+    request
+}
+
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("Could not initialize emulator's backend")]
@@ -59,6 +95,8 @@ pub enum Error {
     SendReceive(#[source] anyhow::Error),
     #[error("Incorrect response to Self Test")]
     SelfTest(#[source] anyhow::Error),
+    #[error("TPM command failed")]
+    Command(#[source] anyhow::Error),
 }
 
 type Result<T> = anyhow::Result<T, Error>;
@@ -387,6 +425,53 @@ impl Emulator {
         Ok(())
     }
 
+    /// Sends a TPM command built by the VMM and returns its response code
+    fn run_tpm_cmd(&mut self, request: Vec<u8>) -> Result<u32> {
+        let mut buffer = request;
+        let input_len = buffer.len();
+        buffer.resize(TPM_CRB_BUFFER_MAX, 0);
+
+        let mut cmd = BackendCmd {
+            buffer: &mut buffer,
+            input_len,
+        };
+        self.deliver_request(&mut cmd)?;
+
+        Ok(u32::from_be_bytes(buffer[6..10].try_into().unwrap()))
+    }
+
+    /// Starts the TPM with TPM2_Startup(TPM_SU_CLEAR), unless it has already
+    /// been started, e.g. by swtpm itself.
+    pub fn tpm2_startup(&mut self) -> Result<()> {
+        let mut request = Vec::with_capacity(12);
+        request.extend_from_slice(&TPM_ST_NO_SESSIONS.to_be_bytes());
+        request.extend_from_slice(&12u32.to_be_bytes());
+        request.extend_from_slice(&TPM_CC_STARTUP.to_be_bytes());
+        request.extend_from_slice(&TPM_SU_CLEAR.to_be_bytes());
+
+        match self.run_tpm_cmd(request)? {
+            TPM_SUCCESS | TPM_RC_INITIALIZE => Ok(()),
+            rc => Err(Error::Command(anyhow!(
+                "TPM2_Startup failed with response code {:#x}",
+                rc
+            ))),
+        }
+    }
+
+    /// Extends the SHA-256 bank of `pcr` with `digest` using TPM2_PCR_Extend
+    pub fn pcr_extend(&mut self, pcr: u32, digest: &Sha256Digest) -> Result<()> {
+        let request = pcr_extend_request(pcr, digest);
+
+        match self.run_tpm_cmd(request)? {
+            TPM_SUCCESS => Ok(()),
+            rc => Err(Error::Command(anyhow!(
+                "TPM2_PCR_Extend of PCR {} failed with response code {:#x}",
+                pcr,
+                rc
+            ))),
+        }
+    }
+
     pub fn cancel_cmd(&mut self) -> Result<()> {
         let mut res: PtmResult = 0;
 
@@ -451,3 +536,26 @@ impl Emulator {
         self.set_buffer_size(0).unwrap_or(TPM_CRB_BUFFER_MAX)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pcr_extend_request() {
+        let digest = [0x5a; 32];
+        let request = pcr_extend_request(4, &digest);
+        assert_eq!(request.len(), 65);
+        assert_eq!(
+            &request[0..10],
+            &[0x80, 0x02, 0, 0, 0, 65, 0, 0, 0x01, 0x82]
+        );
+        assert_eq!(&request[10..14], &4u32.to_be_bytes());
+        assert_eq!(
+            &request[14..27],
+            &[0, 0, 0, 9, 0x40, 0, 0, 0x09, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(&request[27..33], &[0, 0, 0, 1, 0, 0x0b]);
+        assert_eq!(&request[33..], &digest);
+    }
+}
@@ -0,0 +1,121 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! TCG2 measured boot event log.
+//!
+//! The log follows the crypto agile format described by the
+//! [TCG PC Client Platform Firmware Profile Specification](https://trustedcomputinggroup.org/resource/pc-client-specific-platform-firmware-profile-specification/),
+//! recording the SHA-256 digest of each measurement extended into the PCRs
+//! so the guest can replay them. All fields are little-endian.
+
+use crate::{SHA256_DIGEST_SIZE, TPM_ALG_SHA256};
+
+/// Code executed by the platform before the OS, i.e. the firmware.
+pub const EV_POST_CODE: u32 = 0x1;
+/// Informative event not extended into any PCR.
+pub const EV_NO_ACTION: u32 = 0x3;
+/// Delimits the pre-OS measurements from the OS ones.
+pub const EV_SEPARATOR: u32 = 0x4;
+/// Component loaded by the initial program loader, e.g. the kernel.
+pub const EV_IPL: u32 = 0xd;
+
+// Signature of the event describing the format of the log.
+const SPEC_ID_EVENT_SIGNATURE: &[u8; 16] = b"Spec ID Event03\0";
+// TPM 2.0 log format version.
+const SPEC_VERSION_MAJOR: u8 = 2;
+const SPEC_VERSION_MINOR: u8 = 0;
+const SPEC_ERRATA: u8 = 0;
+// UINTN fields are 64-bit wide.
+const UINTN_SIZE: u8 = 2;
+// The first event keeps the SHA-1 sized digest of the legacy format.
+const SHA1_DIGEST_SIZE: usize = 20;
+
+/// SHA-256 digest of a measurement.
+pub type Sha256Digest = [u8; SHA256_DIGEST_SIZE];
+
+/// In-memory TCG2 event log, with the SHA-256 bank only.
+pub struct EventLog {
+    data: Vec<u8>,
+}
+
+impl EventLog {
+    /// Creates a log holding the event describing its format.
+    pub fn new() -> Self {
+        let mut spec_id = Vec::new();
+        spec_id.extend_from_slice(SPEC_ID_EVENT_SIGNATURE);
+        // Platform class, client platform.
+        spec_id.extend_from_slice(&0u32.to_le_bytes());
+        spec_id.push(SPEC_VERSION_MINOR);
+        spec_id.push(SPEC_VERSION_MAJOR);
+        spec_id.push(SPEC_ERRATA);
+        spec_id.push(UINTN_SIZE);
+        // Number of algorithms followed by their digest sizes.
+        spec_id.extend_from_slice(&1u32.to_le_bytes());
+        spec_id.extend_from_slice(&TPM_ALG_SHA256.to_le_bytes());
+        spec_id.extend_from_slice(&(SHA256_DIGEST_SIZE as u16).to_le_bytes());
+        // No vendor information.
+        spec_id.push(0);
+
+        let mut data = Vec::new();
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&EV_NO_ACTION.to_le_bytes());
+        data.extend_from_slice(&[0u8; SHA1_DIGEST_SIZE]);
+        data.extend_from_slice(&(spec_id.len() as u32).to_le_bytes());
+        data.extend_from_slice(&spec_id);
+
+        EventLog { data }
+    }
+
+    /// Records a measurement of `digest` extended into `pcr`, along with its
+    /// event data.
+    pub fn add_event(&mut self, pcr: u32, event_type: u32, digest: &Sha256Digest, event: &[u8]) {
+        self.data.extend_from_slice(&pcr.to_le_bytes());
+        self.data.extend_from_slice(&event_type.to_le_bytes());
+        self.data.extend_from_slice(&1u32.to_le_bytes());
+        self.data.extend_from_slice(&TPM_ALG_SHA256.to_le_bytes());
+        self.data.extend_from_slice(digest);
+        self.data
+            .extend_from_slice(&(event.len() as u32).to_le_bytes());
+        self.data.extend_from_slice(event);
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl Default for EventLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_log() {
+        let mut log = EventLog::new();
+        // Header event followed by the 33 bytes of the Spec ID event
+        assert_eq!(log.as_slice().len(), 32 + 33);
+        assert_eq!(&log.as_slice()[4..8], &EV_NO_ACTION.to_le_bytes());
+        assert_eq!(&log.as_slice()[28..32], &33u32.to_le_bytes());
+        assert_eq!(&log.as_slice()[32..48], SPEC_ID_EVENT_SIGNATURE);
+        assert_eq!(&log.as_slice()[60..64], &[0x0b, 0, 32, 0]);
+
+        let digest = [0xab; SHA256_DIGEST_SIZE];
+        log.add_event(8, EV_IPL, &digest, b"console=ttyS0");
+        let event = &log.as_slice()[65..];
+        assert_eq!(event.len(), 4 + 4 + 4 + 2 + 32 + 4 + 13);
+        assert_eq!(&event[0..4], &8u32.to_le_bytes());
+        assert_eq!(&event[4..8], &EV_IPL.to_le_bytes());
+        assert_eq!(&event[8..12], &1u32.to_le_bytes());
+        assert_eq!(&event[12..14], &TPM_ALG_SHA256.to_le_bytes());
+        assert_eq!(&event[14..46], &digest);
+        assert_eq!(&event[46..50], &13u32.to_le_bytes());
+        assert_eq!(&event[50..], b"console=ttyS0");
+    }
+}
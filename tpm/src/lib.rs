@@ -7,6 +7,7 @@
 extern crate log;
 
 pub mod emulator;
+pub mod event_log;
 pub mod socket;
 
 use anyhow::anyhow;
@@ -14,6 +15,8 @@ use thiserror::Error;
 
 pub const TPM_CRB_BUFFER_MAX: usize = 3968; // 0x1_000 - 0x80
 pub const TPM_SUCCESS: u32 = 0x0;
+pub const TPM_ALG_SHA256: u16 = 0x000b;
+pub const SHA256_DIGEST_SIZE: usize = 32;
 
 /*
  * Structures required to process Request and Responses of Control commands
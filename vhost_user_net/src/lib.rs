@@ -100,6 +100,8 @@ impl VhostUserNetThread {
                 rx_rate_limiter: None,
                 tx_rate_limiter: None,
                 access_platform: None,
+                imds: None,
+                replay_source: None,
             },
         })
     }
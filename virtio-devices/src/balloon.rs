@@ -514,6 +514,35 @@ impl Balloon {
         (self.config.actual as u64) << VIRTIO_BALLOON_PFN_SHIFT
     }
 
+    // Set the features offered to the guest, which its driver negotiates the
+    // next time it initializes the device. Free page reporting can only be
+    // offered by a device created with its queue, returning false otherwise.
+    pub fn set_features(&mut self, deflate_on_oom: bool, free_page_reporting: bool) -> bool {
+        let mut avail_features = self.common.avail_features
+            & !((1u64 << VIRTIO_BALLOON_F_DEFLATE_ON_OOM) | (1u64 << VIRTIO_BALLOON_F_REPORTING));
+        if deflate_on_oom {
+            avail_features |= 1u64 << VIRTIO_BALLOON_F_DEFLATE_ON_OOM;
+        }
+
+        let reporting_queue = self.common.queue_sizes.len() > MIN_NUM_QUEUES;
+        if free_page_reporting && reporting_queue {
+            avail_features |= 1u64 << VIRTIO_BALLOON_F_REPORTING;
+        }
+        self.common.avail_features = avail_features;
+
+        reporting_queue || !free_page_reporting
+    }
+
+    // Whether the guest driver negotiated deflating the balloon on OOM.
+    pub fn deflate_on_oom(&self) -> bool {
+        self.common.feature_acked(VIRTIO_BALLOON_F_DEFLATE_ON_OOM)
+    }
+
+    // Whether the guest driver negotiated free page reporting.
+    pub fn free_page_reporting(&self) -> bool {
+        self.common.feature_acked(VIRTIO_BALLOON_F_REPORTING)
+    }
+
     fn state(&self) -> BalloonState {
         BalloonState {
             avail_features: self.common.avail_features,
@@ -637,6 +666,7 @@ impl VirtioDevice for Balloon {
         spawn_virtio_thread(
             &self.id,
             &self.seccomp_action,
+            self.common.landlock.as_ref(),
             Thread::VirtioBalloon,
             &mut epoll_threads,
             &self.exit_evt,
@@ -676,3 +706,47 @@ impl Snapshottable for Balloon {
 }
 impl Transportable for Balloon {}
 impl Migratable for Balloon {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_balloon(free_page_reporting: bool) -> Balloon {
+        Balloon::new(
+            "balloon0".to_string(),
+            0,
+            false,
+            free_page_reporting,
+            SeccompAction::Allow,
+            EventFd::new(libc::EFD_NONBLOCK).unwrap(),
+            None,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_balloon_set_features() {
+        let deflate_on_oom = 1u64 << VIRTIO_BALLOON_F_DEFLATE_ON_OOM;
+        let reporting = 1u64 << VIRTIO_BALLOON_F_REPORTING;
+
+        let mut balloon = create_balloon(true);
+        assert_eq!(balloon.common.avail_features & deflate_on_oom, 0);
+        assert!(balloon.set_features(true, false));
+        assert_ne!(balloon.common.avail_features & deflate_on_oom, 0);
+        assert_eq!(balloon.common.avail_features & reporting, 0);
+        assert!(balloon.set_features(false, true));
+        assert_eq!(balloon.common.avail_features & deflate_on_oom, 0);
+        assert_ne!(balloon.common.avail_features & reporting, 0);
+
+        // Not negotiated until the guest driver initializes the device.
+        assert!(!balloon.deflate_on_oom());
+        assert!(!balloon.free_page_reporting());
+
+        // Without its queue, free page reporting can't be offered.
+        let mut balloon = create_balloon(false);
+        assert!(!balloon.set_features(true, true));
+        assert_ne!(balloon.common.avail_features & deflate_on_oom, 0);
+        assert_eq!(balloon.common.avail_features & reporting, 0);
+        assert!(balloon.set_features(true, false));
+    }
+}
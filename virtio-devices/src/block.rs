@@ -14,13 +14,17 @@ use std::ops::Deref;
 use std::os::unix::io::AsRawFd;
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-use std::sync::{Arc, Barrier};
+use std::sync::{mpsc, Arc, Barrier, Mutex};
+use std::time::{Duration, Instant};
 use std::{io, result};
 
 use anyhow::anyhow;
-use block::async_io::{AsyncIo, AsyncIoError, DiskFile};
+use block::async_io::{AsyncIo, AsyncIoError, DiskFile, DiskFileError};
 use block::fcntl::{get_lock_state, LockError, LockType};
-use block::{build_serial, fcntl, Request, RequestType, VirtioBlockConfig};
+use block::multipath::{PathEvent, PathMonitor};
+use block::{
+    build_serial, fcntl, BatchRequest, CacheMode, Request, RequestType, VirtioBlockConfig,
+};
 use rate_limiter::group::{RateLimiterGroup, RateLimiterGroupHandle};
 use rate_limiter::TokenType;
 use seccompiler::SeccompAction;
@@ -30,9 +34,12 @@ use virtio_bindings::virtio_blk::*;
 use virtio_bindings::virtio_config::*;
 use virtio_bindings::virtio_ring::{VIRTIO_RING_F_EVENT_IDX, VIRTIO_RING_F_INDIRECT_DESC};
 use virtio_queue::{Queue, QueueOwnedT, QueueT};
-use vm_memory::{ByteValued, Bytes, GuestAddressSpace, GuestMemoryAtomic, GuestMemoryError};
+use vm_memory::{
+    ByteValued, Bytes, GuestAddressSpace, GuestMemory, GuestMemoryAtomic, GuestMemoryError,
+    GuestMemoryRegion,
+};
 use vm_migration::{Migratable, MigratableError, Pausable, Snapshot, Snapshottable, Transportable};
-use vm_virtio::AccessPlatform;
+use vm_virtio::{AccessPlatform, Coalescing};
 use vmm_sys_util::eventfd::EventFd;
 
 use super::{
@@ -40,8 +47,10 @@ use super::{
     Error as DeviceError, VirtioCommon, VirtioDevice, VirtioDeviceType, VirtioInterruptType,
     EPOLL_HELPER_EVENT_LAST,
 };
+use crate::coalescing::NotificationCoalescer;
+use crate::io_thread_pool::{IoThread, IoThreadGroup};
 use crate::seccomp_filters::Thread;
-use crate::thread_helper::spawn_virtio_thread;
+use crate::thread_helper::{spawn_virtio_thread, ThreadLandlock};
 use crate::{GuestMemoryMmap, VirtioInterrupt};
 
 const SECTOR_SHIFT: u8 = 9;
@@ -53,11 +62,43 @@ const QUEUE_AVAIL_EVENT: u16 = EPOLL_HELPER_EVENT_LAST + 1;
 const COMPLETION_EVENT: u16 = EPOLL_HELPER_EVENT_LAST + 2;
 // New 'wake up' event from the rate limiter
 const RATE_LIMITER_EVENT: u16 = EPOLL_HELPER_EVENT_LAST + 3;
+// A delayed used buffer notification is due.
+const COALESCING_EVENT: u16 = EPOLL_HELPER_EVENT_LAST + 4;
+// The device moved to another rate limiter group.
+const RATE_LIMITER_UPDATE_EVENT: u16 = EPOLL_HELPER_EVENT_LAST + 5;
+// New 'wake up' event from the rate limiter, registered in alternation with
+// RATE_LIMITER_EVENT each time the rate limiter changes. This tells apart the
+// events of the replaced rate limiter still pending in the same epoll_wait()
+// batch, which must not be read from the new one.
+const RATE_LIMITER_ALT_EVENT: u16 = EPOLL_HELPER_EVENT_LAST + 6;
+// A path to the storage was restored after all of them failed.
+const PATHS_RESTORED_EVENT: u16 = EPOLL_HELPER_EVENT_LAST + 7;
+// The disk was given another image to switch to.
+const DISK_IMAGE_SWITCH_EVENT: u16 = EPOLL_HELPER_EVENT_LAST + 8;
+// New completed tasks are pending on the completion ring, registered in
+// alternation with COMPLETION_EVENT each time the disk image is switched, as
+// done for RATE_LIMITER_ALT_EVENT.
+const COMPLETION_ALT_EVENT: u16 = EPOLL_HELPER_EVENT_LAST + 9;
 
 // latency scale, for reduce precision loss in calculate.
 const LATENCY_SCALE: u64 = 10000;
+// Upper bounds of the buckets of the latency histograms, in microseconds,
+// with the names of the read and write counters reporting them. Each counter
+// includes the requests of the lower buckets, the slower requests being the
+// ones missing from the last counter.
+const LATENCY_BUCKETS: [(u64, &str, &str); 6] = [
+    (10, "read_latency_le_10us", "write_latency_le_10us"),
+    (100, "read_latency_le_100us", "write_latency_le_100us"),
+    (1_000, "read_latency_le_1ms", "write_latency_le_1ms"),
+    (10_000, "read_latency_le_10ms", "write_latency_le_10ms"),
+    (100_000, "read_latency_le_100ms", "write_latency_le_100ms"),
+    (1_000_000, "read_latency_le_1s", "write_latency_le_1s"),
+];
 
 pub const MINIMUM_BLOCK_QUEUE_SIZE: u16 = 2;
+// Queue size advertised when the guest picks the size of the queues. Larger
+// queues would mostly grow the io_uring and AIO contexts of each queue.
+pub const MAXIMUM_BLOCK_QUEUE_SIZE: u16 = 1024;
 
 #[derive(Error, Debug)]
 pub enum Error {
@@ -79,8 +120,12 @@ pub enum Error {
     QueueIterator(#[source] virtio_queue::Error),
     #[error("Failed to update request status")]
     RequestStatus(#[source] GuestMemoryError),
+    #[error("Failed to access the request data")]
+    RequestData(#[source] GuestMemoryError),
     #[error("Failed to enable notification")]
     QueueEnableNotification(#[source] virtio_queue::Error),
+    #[error("Failed to read the available index of the queue")]
+    QueueAvailIndex(#[source] virtio_queue::Error),
     #[error("Failed to get {lock_type:?} lock for disk image: {path}")]
     LockDiskImage {
         /// The underlying error.
@@ -91,10 +136,58 @@ pub enum Error {
         /// The path of the disk image.
         path: PathBuf,
     },
+    #[error("Disk size {0} is not a multiple of the sector size")]
+    ResizeUnaligned(u64),
+    #[error("Disk size {0} is smaller than the current size of the disk")]
+    ResizeShrink(u64),
+    #[error("Can't resize a read-only disk")]
+    ResizeReadOnly,
+    #[error("Failed getting the size of the disk image")]
+    DiskImageSize(#[source] DiskFileError),
+    #[error("Failed to extend the disk image")]
+    ExtendDiskImage(#[source] DiskFileError),
+    #[error("The disk image is {image_size} bytes, smaller than {size} bytes")]
+    DiskImageNotExtended { size: u64, image_size: u64 },
+    #[error("Failed to signal the config change")]
+    ConfigChangeSignal(#[source] io::Error),
+    #[error("Can't switch the image of a read-only disk")]
+    SwitchReadOnly,
+    #[error("The new disk image is {image_size} bytes, smaller than the disk")]
+    SwitchDiskImageSize { image_size: u64 },
+    #[error("Failed to create the I/O context of the new disk image")]
+    SwitchAsyncIo(#[source] DiskFileError),
+    #[error("Failed to signal the disk image switch")]
+    SwitchDiskImageSignal(#[source] io::Error),
 }
 
 pub type Result<T> = result::Result<T, Error>;
 
+// Disk image handed over to a queue, which moves to it once the requests in
+// flight on the current image completed.
+struct DiskImageSwitch {
+    disk_image: Box<dyn AsyncIo>,
+    done: mpsc::Sender<()>,
+}
+
+/// Tracks the queues of a disk moving to a new image.
+pub struct DiskImageSwitchDone {
+    receiver: mpsc::Receiver<()>,
+    queues: usize,
+}
+
+impl DiskImageSwitchDone {
+    /// Waits for all the queues to use the new image, returning false if
+    /// some of them didn't within `timeout`.
+    pub fn wait(self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        (0..self.queues).all(|_| {
+            self.receiver
+                .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                .is_ok()
+        })
+    }
+}
+
 // latency will be records as microseconds, average latency
 // will be save as scaled value.
 #[derive(Clone)]
@@ -109,6 +202,11 @@ pub struct BlockCounters {
     write_latency_min: Arc<AtomicU64>,
     write_latency_max: Arc<AtomicU64>,
     write_latency_avg: Arc<AtomicU64>,
+    // Requests completed within each bucket of LATENCY_BUCKETS only
+    read_latency_buckets: Arc<[AtomicU64; LATENCY_BUCKETS.len()]>,
+    write_latency_buckets: Arc<[AtomicU64; LATENCY_BUCKETS.len()]>,
+    // Times the guest filled the queue, waiting for requests to complete
+    queue_full: Arc<AtomicU64>,
 }
 
 impl Default for BlockCounters {
@@ -124,33 +222,220 @@ impl Default for BlockCounters {
             write_latency_min: Arc::new(AtomicU64::new(u64::MAX)),
             write_latency_max: Arc::new(AtomicU64::new(u64::MAX)),
             write_latency_avg: Arc::new(AtomicU64::new(u64::MAX)),
+            read_latency_buckets: Arc::default(),
+            write_latency_buckets: Arc::default(),
+            queue_full: Arc::new(AtomicU64::new(0)),
         }
     }
 }
 
+// Accounts for a request completed in `latency` microseconds, `ops` being the
+// count of the requests of the same type completed so far, this one included.
+fn record_latency(
+    min: &AtomicU64,
+    max: &AtomicU64,
+    avg: &AtomicU64,
+    buckets: &[AtomicU64],
+    latency: u64,
+    ops: u64,
+) {
+    if latency < min.load(Ordering::Relaxed) {
+        min.store(latency, Ordering::Relaxed);
+    }
+    let last_max = max.load(Ordering::Relaxed);
+    if latency > last_max || last_max == u64::MAX {
+        max.store(latency, Ordering::Relaxed);
+    }
+
+    let last_avg = avg.load(Ordering::Relaxed);
+    // Special case the first real latency report
+    let new_avg = if last_avg == u64::MAX {
+        latency * LATENCY_SCALE
+    } else {
+        // Cumulative average is guaranteed to be positive if being
+        // calculated properly
+        (last_avg as i64 + ((latency * LATENCY_SCALE) as i64 - last_avg as i64) / ops as i64)
+            .try_into()
+            .unwrap()
+    };
+    avg.store(new_avg, Ordering::Relaxed);
+
+    if let Some(bucket) = LATENCY_BUCKETS
+        .iter()
+        .position(|(bound, _, _)| latency <= *bound)
+    {
+        buckets[bucket].fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl BlockCounters {
+    // The ops counters are only updated once the whole batch of completed
+    // requests is accounted for, `batch_ops` being the reads completed so
+    // far in the batch.
+    fn record_read(&self, latency: u64, batch_ops: u64) {
+        record_latency(
+            &self.read_latency_min,
+            &self.read_latency_max,
+            &self.read_latency_avg,
+            self.read_latency_buckets.as_ref(),
+            latency,
+            self.read_ops.load(Ordering::Relaxed) + batch_ops,
+        );
+    }
+
+    fn record_write(&self, latency: u64, batch_ops: u64) {
+        record_latency(
+            &self.write_latency_min,
+            &self.write_latency_max,
+            &self.write_latency_avg,
+            self.write_latency_buckets.as_ref(),
+            latency,
+            self.write_ops.load(Ordering::Relaxed) + batch_ops,
+        );
+    }
+
+    fn add_ops(&self, read_bytes: u64, read_ops: u64, write_bytes: u64, write_ops: u64) {
+        self.write_bytes.fetch_add(write_bytes, Ordering::AcqRel);
+        self.write_ops.fetch_add(write_ops, Ordering::AcqRel);
+        self.read_bytes.fetch_add(read_bytes, Ordering::AcqRel);
+        self.read_ops.fetch_add(read_ops, Ordering::AcqRel);
+    }
+
+    fn to_map(&self) -> HashMap<&'static str, Wrapping<u64>> {
+        let mut counters = HashMap::new();
+
+        counters.insert(
+            "read_bytes",
+            Wrapping(self.read_bytes.load(Ordering::Acquire)),
+        );
+        counters.insert(
+            "write_bytes",
+            Wrapping(self.write_bytes.load(Ordering::Acquire)),
+        );
+        counters.insert("read_ops", Wrapping(self.read_ops.load(Ordering::Acquire)));
+        counters.insert(
+            "write_ops",
+            Wrapping(self.write_ops.load(Ordering::Acquire)),
+        );
+        counters.insert(
+            "write_latency_min",
+            Wrapping(self.write_latency_min.load(Ordering::Acquire)),
+        );
+        counters.insert(
+            "write_latency_max",
+            Wrapping(self.write_latency_max.load(Ordering::Acquire)),
+        );
+        counters.insert(
+            "write_latency_avg",
+            Wrapping(self.write_latency_avg.load(Ordering::Acquire) / LATENCY_SCALE),
+        );
+        counters.insert(
+            "read_latency_min",
+            Wrapping(self.read_latency_min.load(Ordering::Acquire)),
+        );
+        counters.insert(
+            "read_latency_max",
+            Wrapping(self.read_latency_max.load(Ordering::Acquire)),
+        );
+        counters.insert(
+            "read_latency_avg",
+            Wrapping(self.read_latency_avg.load(Ordering::Acquire) / LATENCY_SCALE),
+        );
+
+        let mut read_requests = Wrapping(0);
+        let mut write_requests = Wrapping(0);
+        for (i, (_, read_name, write_name)) in LATENCY_BUCKETS.iter().enumerate() {
+            read_requests += Wrapping(self.read_latency_buckets[i].load(Ordering::Acquire));
+            write_requests += Wrapping(self.write_latency_buckets[i].load(Ordering::Acquire));
+            counters.insert(*read_name, read_requests);
+            counters.insert(*write_name, write_requests);
+        }
+
+        counters.insert(
+            "queue_full_events",
+            Wrapping(self.queue_full.load(Ordering::Acquire)),
+        );
+
+        counters
+    }
+}
+
+// Weight of the share of each queue, matching the default weight of the net
+// devices which may be part of the same group.
+const QUEUE_SHARE_WEIGHT: u16 = 100;
+
+// Handle of a queue on the rate limiter group, consuming from its own share
+// of the bandwidth if any.
+fn new_rate_limiter_handle(
+    rate_limiter_group: &RateLimiterGroup,
+    share: Option<&str>,
+) -> result::Result<RateLimiterGroupHandle, rate_limiter::group::Error> {
+    match share {
+        Some(share) => rate_limiter_group.new_weighted_handle(share, QUEUE_SHARE_WEIGHT),
+        None => rate_limiter_group.new_handle(),
+    }
+}
+
 struct BlockEpollHandler {
+    id: String,
     queue_index: u16,
     queue: Queue,
     mem: GuestMemoryAtomic<GuestMemoryMmap>,
     disk_image: Box<dyn AsyncIo>,
-    disk_nsectors: u64,
+    disk_nsectors: Arc<AtomicU64>,
     interrupt_cb: Arc<dyn VirtioInterrupt>,
     serial: Vec<u8>,
     kill_evt: EventFd,
     pause_evt: EventFd,
     writeback: Arc<AtomicBool>,
+    ignore_flush: bool,
+    detect_zeroes: bool,
+    unmap_zeroes: bool,
     counters: BlockCounters,
+    queue_counters: BlockCounters,
+    // Whether the guest filled the queue when it was last processed
+    queue_full: bool,
     queue_evt: EventFd,
     inflight_requests: VecDeque<(u16, Request)>,
     rate_limiter: Option<RateLimiterGroupHandle>,
+    rate_limiter_event: u16,
+    rate_limiter_group: Arc<Mutex<Option<Arc<RateLimiterGroup>>>>,
+    // Share of the group bandwidth the queue consumes, when the queues of
+    // the device get a fair part of it
+    rate_limiter_share: Option<String>,
+    rate_limiter_evt: EventFd,
     access_platform: Option<Arc<dyn AccessPlatform>>,
     read_only: bool,
     host_cpus: Option<Vec<usize>>,
+    io_priority: Option<u16>,
+    coalescer: Option<NotificationCoalescer>,
+    paths_failed: Arc<AtomicBool>,
+    paths_restored_evt: EventFd,
+    completion_event: u16,
+    disk_image_switch_slot: Arc<Mutex<Option<DiskImageSwitch>>>,
+    disk_image_switch_evt: EventFd,
+    disk_image_switch: Option<DiskImageSwitch>,
+    held_requests: Vec<(u16, Request)>,
+    // Requests merged into another one, indexed by the head of the latter.
+    merged_requests: HashMap<u16, Vec<u16>>,
 }
 
 impl BlockEpollHandler {
     fn process_queue_submit(&mut self) -> Result<()> {
+        // The requests are left in the queue while the disk is paused.
+        if self.paths_failed.load(Ordering::Acquire) {
+            return Ok(());
+        }
+
+        // Neither are they while the disk moves to another image.
+        if self.disk_image_switch_pending() {
+            return Ok(());
+        }
+
+        self.check_queue_full()?;
+
         let queue = &mut self.queue;
+        let mut batch_requests = Vec::new();
 
         while let Some(mut desc_chain) = queue.pop_descriptor_chain(self.mem.memory()) {
             let mut request = Request::parse(&mut desc_chain, self.access_platform.as_ref())
@@ -160,8 +445,13 @@ impl BlockEpollHandler {
             // "A device MUST set the status byte to VIRTIO_BLK_S_IOERR for a write request
             // if the VIRTIO_BLK_F_RO feature if offered, and MUST NOT write any data."
             if self.read_only
-                && (request.request_type == RequestType::Out
-                    || request.request_type == RequestType::Flush)
+                && matches!(
+                    request.request_type,
+                    RequestType::Out
+                        | RequestType::Flush
+                        | RequestType::Discard
+                        | RequestType::WriteZeroes
+                )
             {
                 desc_chain
                     .memory()
@@ -211,14 +501,22 @@ impl BlockEpollHandler {
             }
 
             request.set_writeback(self.writeback.load(Ordering::Acquire));
+            request.set_detect_zeroes(self.detect_zeroes, self.unmap_zeroes);
 
-            let result = request.execute_async(
-                desc_chain.memory(),
-                self.disk_nsectors,
-                self.disk_image.as_mut(),
-                &self.serial,
-                desc_chain.head_index() as u64,
-            );
+            // Flushes complete right away with the unsafe cache mode, the
+            // data being left in the host page cache.
+            let result = if self.ignore_flush && request.request_type == RequestType::Flush {
+                Ok(false)
+            } else {
+                request.execute_async(
+                    desc_chain.memory(),
+                    self.disk_nsectors.load(Ordering::Acquire),
+                    self.disk_image.as_mut(),
+                    &self.serial,
+                    desc_chain.head_index() as u64,
+                    &mut batch_requests,
+                )
+            };
 
             if let Ok(true) = result {
                 self.inflight_requests
@@ -248,6 +546,71 @@ impl BlockEpollHandler {
             }
         }
 
+        self.submit_batch_requests(batch_requests)
+    }
+
+    // Counts the times the guest ran out of room in the queue, which it
+    // found full of requests yet to be processed.
+    fn check_queue_full(&mut self) -> Result<()> {
+        let avail_idx = self
+            .queue
+            .avail_idx(self.mem.memory().deref(), Ordering::Acquire)
+            .map_err(Error::QueueAvailIndex)?;
+        let pending = (avail_idx - Wrapping(self.queue.next_avail())).0;
+        let queue_full = pending >= self.queue.size();
+        if queue_full && !self.queue_full {
+            for counters in [&self.counters, &self.queue_counters] {
+                counters.queue_full.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.queue_full = queue_full;
+
+        Ok(())
+    }
+
+    // Submit the reads and writes queued in a batch, failing all of them if
+    // the batch can't be submitted.
+    fn submit_batch_requests(&mut self, batch_requests: Vec<BatchRequest>) -> Result<()> {
+        if batch_requests.is_empty() {
+            return Ok(());
+        }
+
+        if let Err(e) = self.disk_image.submit_batch_requests(&batch_requests) {
+            warn!("Batch of {} requests failed: {:?}", batch_requests.len(), e);
+            let mem = self.mem.memory();
+            for batch_request in &batch_requests {
+                let user_data = std::iter::once(batch_request.user_data)
+                    .chain(batch_request.merged_user_data.iter().copied());
+                for user_data in user_data {
+                    let desc_index = user_data as u16;
+                    let mut request = self.find_inflight_request(desc_index)?;
+                    request.complete_async().map_err(Error::RequestCompleting)?;
+                    mem.write_obj(VIRTIO_BLK_S_IOERR as u8, request.status_addr)
+                        .map_err(Error::RequestStatus)?;
+                    self.queue
+                        .add_used(mem.deref(), desc_index, 0)
+                        .map_err(Error::QueueAddUsed)?;
+                }
+            }
+            self.queue
+                .enable_notification(mem.deref())
+                .map_err(Error::QueueEnableNotification)?;
+            return Ok(());
+        }
+
+        for batch_request in batch_requests {
+            if !batch_request.merged_user_data.is_empty() {
+                self.merged_requests.insert(
+                    batch_request.user_data as u16,
+                    batch_request
+                        .merged_user_data
+                        .into_iter()
+                        .map(|user_data| user_data as u16)
+                        .collect(),
+                );
+            }
+        }
+
         Ok(())
     }
 
@@ -262,6 +625,18 @@ impl BlockEpollHandler {
                 ))
             })?
         {
+            if let Some(coalescer) = self.coalescer.as_mut() {
+                let next_used = Wrapping(self.queue.next_used());
+                if !coalescer.notify_now(next_used).map_err(|e| {
+                    EpollHelperError::HandleEvent(anyhow!(
+                        "Failed to coalesce notifications: {:?}",
+                        e
+                    ))
+                })? {
+                    return Ok(());
+                }
+            }
+
             self.signal_used_queue().map_err(|e| {
                 EpollHelperError::HandleEvent(anyhow!("Failed to signal used queue: {:?}", e))
             })?;
@@ -299,6 +674,35 @@ impl BlockEpollHandler {
         Err(Error::MissingEntryRequestList)
     }
 
+    // Records the data read by `request` to the I/O trace, or replaces it with
+    // the recorded data when replaying. The reads are traced per sector, as
+    // the requests can complete in any order.
+    fn trace_read(&self, request: &Request) -> Result<()> {
+        let source = format!("{}/{}", self.id, request.sector);
+        let mem = self.mem.memory();
+
+        if let Some(data) = replay::replay(&source) {
+            let mut data = data.as_slice();
+            for (data_addr, data_len) in &request.data_descriptors {
+                let len = (*data_len as usize).min(data.len());
+                mem.write_slice(&data[..len], *data_addr)
+                    .map_err(Error::RequestData)?;
+                data = &data[len..];
+            }
+        } else if replay::is_recording() {
+            let mut data = Vec::new();
+            for (data_addr, data_len) in &request.data_descriptors {
+                let start = data.len();
+                data.resize(start + *data_len as usize, 0);
+                mem.read_slice(&mut data[start..], *data_addr)
+                    .map_err(Error::RequestData)?;
+            }
+            replay::record(&source, &data);
+        }
+
+        Ok(())
+    }
+
     fn process_queue_complete(&mut self) -> Result<()> {
         let mem = self.mem.memory();
         let mut read_bytes = Wrapping(0);
@@ -308,130 +712,163 @@ impl BlockEpollHandler {
 
         while let Some((user_data, result)) = self.disk_image.next_completed_request() {
             let desc_index = user_data as u16;
+            let merged = self.merged_requests.remove(&desc_index).unwrap_or_default();
+
+            // The result of merged requests covers the data of all of them,
+            // in order.
+            let mut remaining = result;
+            for desc_index in std::iter::once(desc_index).chain(merged) {
+                let mut request = self.find_inflight_request(desc_index)?;
+                let result = if remaining < 0 {
+                    remaining
+                } else {
+                    let len = remaining.min(request.data_len().try_into().unwrap_or(i32::MAX));
+                    remaining -= len;
+                    len
+                };
 
-            let mut request = self.find_inflight_request(desc_index)?;
-
-            request.complete_async().map_err(Error::RequestCompleting)?;
-
-            let latency = request.start.elapsed().as_micros() as u64;
-            let read_ops_last = self.counters.read_ops.load(Ordering::Relaxed);
-            let write_ops_last = self.counters.write_ops.load(Ordering::Relaxed);
-            let read_max = self.counters.read_latency_max.load(Ordering::Relaxed);
-            let write_max = self.counters.write_latency_max.load(Ordering::Relaxed);
-            let mut read_avg = self.counters.read_latency_avg.load(Ordering::Relaxed);
-            let mut write_avg = self.counters.write_latency_avg.load(Ordering::Relaxed);
-            let (status, len) = if result >= 0 {
-                match request.request_type {
-                    RequestType::In => {
-                        for (_, data_len) in &request.data_descriptors {
-                            read_bytes += Wrapping(*data_len as u64);
+                request.complete_async().map_err(Error::RequestCompleting)?;
+
+                let latency = request.start.elapsed().as_micros() as u64;
+                let (status, len) = if result >= 0 {
+                    match request.request_type {
+                        RequestType::In => {
+                            if replay::mode().is_some() {
+                                self.trace_read(&request)?;
+                            }
+                            for (_, data_len) in &request.data_descriptors {
+                                read_bytes += Wrapping(*data_len as u64);
+                            }
+                            read_ops += Wrapping(1);
+                            self.counters.record_read(latency, read_ops.0);
+                            self.queue_counters.record_read(latency, read_ops.0);
                         }
-                        read_ops += Wrapping(1);
-                        if latency < self.counters.read_latency_min.load(Ordering::Relaxed) {
-                            self.counters
-                                .read_latency_min
-                                .store(latency, Ordering::Relaxed);
+                        RequestType::Out => {
+                            if !request.writeback {
+                                self.disk_image.fsync(None).map_err(Error::Fsync)?;
+                            }
+                            for (_, data_len) in &request.data_descriptors {
+                                write_bytes += Wrapping(*data_len as u64);
+                            }
+                            write_ops += Wrapping(1);
+                            self.counters.record_write(latency, write_ops.0);
+                            self.queue_counters.record_write(latency, write_ops.0);
                         }
-                        if latency > read_max || read_max == u64::MAX {
-                            self.counters
-                                .read_latency_max
-                                .store(latency, Ordering::Relaxed);
-                        }
-
-                        // Special case the first real latency report
-                        read_avg = if read_avg == u64::MAX {
-                            latency * LATENCY_SCALE
-                        } else {
-                            // Cumulative average is guaranteed to be
-                            // positive if being calculated properly
-                            (read_avg as i64
-                                + ((latency * LATENCY_SCALE) as i64 - read_avg as i64)
-                                    / (read_ops_last + read_ops.0) as i64)
-                                .try_into()
-                                .unwrap()
-                        };
+                        _ => {}
                     }
-                    RequestType::Out => {
-                        if !request.writeback {
-                            self.disk_image.fsync(None).map_err(Error::Fsync)?;
-                        }
-                        for (_, data_len) in &request.data_descriptors {
-                            write_bytes += Wrapping(*data_len as u64);
-                        }
-                        write_ops += Wrapping(1);
-                        if latency < self.counters.write_latency_min.load(Ordering::Relaxed) {
-                            self.counters
-                                .write_latency_min
-                                .store(latency, Ordering::Relaxed);
-                        }
-                        if latency > write_max || write_max == u64::MAX {
-                            self.counters
-                                .write_latency_max
-                                .store(latency, Ordering::Relaxed);
-                        }
 
-                        // Special case the first real latency report
-                        write_avg = if write_avg == u64::MAX {
-                            latency * LATENCY_SCALE
-                        } else {
-                            // Cumulative average is guaranteed to be
-                            // positive if being calculated properly
-                            (write_avg as i64
-                                + ((latency * LATENCY_SCALE) as i64 - write_avg as i64)
-                                    / (write_ops_last + write_ops.0) as i64)
-                                .try_into()
-                                .unwrap()
-                        }
-                    }
-                    _ => {}
-                }
+                    (VIRTIO_BLK_S_OK as u8, result as u32)
+                } else if self.paths_failed.load(Ordering::Acquire) {
+                    // Submitted again once a path is restored.
+                    self.held_requests.push((desc_index, request));
+                    continue;
+                } else {
+                    warn!(
+                        "Request failed: {:x?} {:?}",
+                        request,
+                        io::Error::from_raw_os_error(-result)
+                    );
+                    (VIRTIO_BLK_S_IOERR as u8, 0)
+                };
 
-                self.counters
-                    .read_latency_avg
-                    .store(read_avg, Ordering::Relaxed);
+                mem.write_obj(status, request.status_addr)
+                    .map_err(Error::RequestStatus)?;
 
-                self.counters
-                    .write_latency_avg
-                    .store(write_avg, Ordering::Relaxed);
+                let queue = &mut self.queue;
 
-                (VIRTIO_BLK_S_OK as u8, result as u32)
-            } else {
-                warn!(
-                    "Request failed: {:x?} {:?}",
-                    request,
-                    io::Error::from_raw_os_error(-result)
-                );
-                (VIRTIO_BLK_S_IOERR as u8, 0)
-            };
+                queue
+                    .add_used(mem.deref(), desc_index, len)
+                    .map_err(Error::QueueAddUsed)?;
+                queue
+                    .enable_notification(mem.deref())
+                    .map_err(Error::QueueEnableNotification)?;
+            }
+        }
 
-            mem.write_obj(status, request.status_addr)
-                .map_err(Error::RequestStatus)?;
+        for counters in [&self.counters, &self.queue_counters] {
+            counters.add_ops(read_bytes.0, read_ops.0, write_bytes.0, write_ops.0);
+        }
 
-            let queue = &mut self.queue;
+        Ok(())
+    }
+
+    // Submit the requests which failed while the disk was paused again.
+    fn resubmit_held_requests(&mut self) -> Result<()> {
+        let mem = self.mem.memory();
+        let mut batch_requests = Vec::new();
 
-            queue
-                .add_used(mem.deref(), desc_index, len)
+        for (desc_index, mut request) in std::mem::take(&mut self.held_requests) {
+            let result = request.execute_async(
+                mem.deref(),
+                self.disk_nsectors.load(Ordering::Acquire),
+                self.disk_image.as_mut(),
+                &self.serial,
+                desc_index as u64,
+                &mut batch_requests,
+            );
+
+            if let Ok(true) = result {
+                self.inflight_requests.push_back((desc_index, request));
+                continue;
+            }
+
+            let status = match result {
+                Ok(_) => VIRTIO_BLK_S_OK,
+                Err(e) => {
+                    warn!("Request failed: {:x?} {:?}", request, e);
+                    VIRTIO_BLK_S_IOERR
+                }
+            };
+            mem.write_obj(status as u8, request.status_addr)
+                .map_err(Error::RequestStatus)?;
+            self.queue
+                .add_used(mem.deref(), desc_index, 0)
                 .map_err(Error::QueueAddUsed)?;
-            queue
-                .enable_notification(mem.deref())
-                .map_err(Error::QueueEnableNotification)?;
         }
 
-        self.counters
-            .write_bytes
-            .fetch_add(write_bytes.0, Ordering::AcqRel);
-        self.counters
-            .write_ops
-            .fetch_add(write_ops.0, Ordering::AcqRel);
+        self.submit_batch_requests(batch_requests)
+    }
 
-        self.counters
-            .read_bytes
-            .fetch_add(read_bytes.0, Ordering::AcqRel);
-        self.counters
-            .read_ops
-            .fetch_add(read_ops.0, Ordering::AcqRel);
+    fn disk_image_switch_pending(&mut self) -> bool {
+        if self.disk_image_switch.is_none() {
+            self.disk_image_switch = self.disk_image_switch_slot.lock().unwrap().take();
+        }
 
-        Ok(())
+        self.disk_image_switch.is_some()
+    }
+
+    // Move to the disk image handed over to the queue, if any, once no
+    // request is in flight on the current one, which is then flushed so that
+    // it holds all the writes completed so far. Returns whether it did.
+    fn switch_disk_image(&mut self, helper: &mut EpollHelper) -> anyhow::Result<bool> {
+        if !self.inflight_requests.is_empty() || !self.disk_image_switch_pending() {
+            return Ok(false);
+        }
+        self.disk_image.fsync(None).map_err(Error::Fsync)?;
+        let switch = self.disk_image_switch.take().unwrap();
+
+        helper.del_event_custom(
+            self.disk_image.notifier().as_raw_fd(),
+            self.completion_event,
+            epoll::Events::EPOLLIN,
+        )?;
+        self.completion_event = match self.completion_event {
+            COMPLETION_EVENT => COMPLETION_ALT_EVENT,
+            _ => COMPLETION_EVENT,
+        };
+        self.disk_image = switch.disk_image;
+        if let Some(ioprio) = self.io_priority {
+            self.disk_image.set_io_priority(ioprio);
+        }
+        helper.add_event(
+            self.disk_image.notifier().as_raw_fd(),
+            self.completion_event,
+        )?;
+
+        // Whoever switched the image may have stopped waiting.
+        let _ = switch.done.send(());
+
+        Ok(true)
     }
 
     fn signal_used_queue(&self) -> result::Result<(), DeviceError> {
@@ -478,18 +915,85 @@ impl BlockEpollHandler {
         }
     }
 
-    fn run(
-        &mut self,
-        paused: Arc<AtomicBool>,
-        paused_sync: Arc<Barrier>,
-    ) -> result::Result<(), EpollHelperError> {
+    fn set_queue_thread_io_priority(&mut self) {
+        let Some(ioprio) = self.io_priority else {
+            return;
+        };
+
+        // The I/O engine only gets the priority once the thread has it, not
+        // to fail the requests of an unprivileged process with EPERM.
+        match block::set_thread_io_priority(ioprio) {
+            Ok(()) => self.disk_image.set_io_priority(ioprio),
+            Err(e) => {
+                error!(
+                    "Failed setting the I/O priority of the virtqueue thread {}: {}",
+                    self.queue_index, e
+                );
+                self.io_priority = None;
+            }
+        }
+    }
+
+    fn epoll_helper(&self) -> result::Result<EpollHelper, EpollHelperError> {
         let mut helper = EpollHelper::new(&self.kill_evt, &self.pause_evt)?;
         helper.add_event(self.queue_evt.as_raw_fd(), QUEUE_AVAIL_EVENT)?;
-        helper.add_event(self.disk_image.notifier().as_raw_fd(), COMPLETION_EVENT)?;
+        helper.add_event(
+            self.disk_image.notifier().as_raw_fd(),
+            self.completion_event,
+        )?;
         if let Some(rate_limiter) = &self.rate_limiter {
             helper.add_event(rate_limiter.as_raw_fd(), RATE_LIMITER_EVENT)?;
         }
+        helper.add_event(self.rate_limiter_evt.as_raw_fd(), RATE_LIMITER_UPDATE_EVENT)?;
+        if let Some(coalescer) = &self.coalescer {
+            helper.add_event(coalescer.as_raw_fd(), COALESCING_EVENT)?;
+        }
+        helper.add_event(self.paths_restored_evt.as_raw_fd(), PATHS_RESTORED_EVENT)?;
+        helper.add_event(
+            self.disk_image_switch_evt.as_raw_fd(),
+            DISK_IMAGE_SWITCH_EVENT,
+        )?;
+
+        Ok(helper)
+    }
+
+    // Replace the handle of the previous rate limiter group, if any, with one
+    // of the group the device belongs to now.
+    fn update_rate_limiter(&mut self, helper: &mut EpollHelper) -> anyhow::Result<()> {
+        if let Some(rate_limiter) = self.rate_limiter.take() {
+            helper.del_event_custom(
+                rate_limiter.as_raw_fd(),
+                self.rate_limiter_event,
+                epoll::Events::EPOLLIN,
+            )?;
+        }
+
+        self.rate_limiter_event = match self.rate_limiter_event {
+            RATE_LIMITER_EVENT => RATE_LIMITER_ALT_EVENT,
+            _ => RATE_LIMITER_EVENT,
+        };
+        self.rate_limiter = self
+            .rate_limiter_group
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|r| new_rate_limiter_handle(r, self.rate_limiter_share.as_deref()))
+            .transpose()?;
+        if let Some(rate_limiter) = &self.rate_limiter {
+            helper.add_event(rate_limiter.as_raw_fd(), self.rate_limiter_event)?;
+        }
+
+        Ok(())
+    }
+
+    fn run(
+        &mut self,
+        paused: Arc<AtomicBool>,
+        paused_sync: Arc<Barrier>,
+    ) -> result::Result<(), EpollHelperError> {
+        let mut helper = self.epoll_helper()?;
         self.set_queue_thread_affinity();
+        self.set_queue_thread_io_priority();
         helper.run(paused, paused_sync, self)?;
 
         Ok(())
@@ -499,7 +1003,7 @@ impl BlockEpollHandler {
 impl EpollHelperHandler for BlockEpollHandler {
     fn handle_event(
         &mut self,
-        _helper: &mut EpollHelper,
+        helper: &mut EpollHelper,
         event: &epoll::Event,
     ) -> result::Result<(), EpollHelperError> {
         let ev_type = event.data as u16;
@@ -516,7 +1020,12 @@ impl EpollHelperHandler for BlockEpollHandler {
                     self.process_queue_submit_and_signal()?
                 }
             }
-            COMPLETION_EVENT => {
+            COMPLETION_EVENT | COMPLETION_ALT_EVENT => {
+                if ev_type != self.completion_event {
+                    // Event of the disk image replaced by the current one.
+                    return Ok(());
+                }
+
                 self.disk_image.notifier().read().map_err(|e| {
                     EpollHelperError::HandleEvent(anyhow!("Failed to get queue event: {:?}", e))
                 })?;
@@ -528,6 +1037,10 @@ impl EpollHelperHandler for BlockEpollHandler {
                     ))
                 })?;
 
+                self.switch_disk_image(helper).map_err(|e| {
+                    EpollHelperError::HandleEvent(anyhow!("Failed to switch disk image: {:?}", e))
+                })?;
+
                 let rate_limit_reached = self.rate_limiter.as_ref().is_some_and(|r| r.is_blocked());
 
                 // Process the queue only when the rate limit is not reached
@@ -541,7 +1054,12 @@ impl EpollHelperHandler for BlockEpollHandler {
                 }
                 self.try_signal_used_queue()?;
             }
-            RATE_LIMITER_EVENT => {
+            RATE_LIMITER_EVENT | RATE_LIMITER_ALT_EVENT => {
+                if ev_type != self.rate_limiter_event {
+                    // Event of the rate limiter replaced by the current one.
+                    return Ok(());
+                }
+
                 if let Some(rate_limiter) = &mut self.rate_limiter {
                     // Upon rate limiter event, call the rate limiter handler
                     // and restart processing the queue.
@@ -559,6 +1077,89 @@ impl EpollHelperHandler for BlockEpollHandler {
                     )));
                 }
             }
+            RATE_LIMITER_UPDATE_EVENT => {
+                self.rate_limiter_evt.read().map_err(|e| {
+                    EpollHelperError::HandleEvent(anyhow!(
+                        "Failed to get rate limiter update event: {:?}",
+                        e
+                    ))
+                })?;
+
+                self.update_rate_limiter(helper).map_err(|e| {
+                    EpollHelperError::HandleEvent(anyhow!("Failed to update rate limiter: {:?}", e))
+                })?;
+
+                // Requests held back by the previous group may now proceed.
+                let rate_limit_reached = self.rate_limiter.as_ref().is_some_and(|r| r.is_blocked());
+                if !rate_limit_reached {
+                    self.process_queue_submit_and_signal()?
+                }
+            }
+            COALESCING_EVENT => {
+                let next_used = Wrapping(self.queue.next_used());
+                let coalescer = self.coalescer.as_mut().ok_or_else(|| {
+                    EpollHelperError::HandleEvent(anyhow!(
+                        "Unexpected 'COALESCING_EVENT' when coalescing is not enabled."
+                    ))
+                })?;
+                if coalescer.timer_expired(next_used).map_err(|e| {
+                    EpollHelperError::HandleEvent(anyhow!(
+                        "Failed to handle coalescing timer: {:?}",
+                        e
+                    ))
+                })? {
+                    self.signal_used_queue().map_err(|e| {
+                        EpollHelperError::HandleEvent(anyhow!(
+                            "Failed to signal used queue: {:?}",
+                            e
+                        ))
+                    })?;
+                }
+            }
+            PATHS_RESTORED_EVENT => {
+                self.paths_restored_evt.read().map_err(|e| {
+                    EpollHelperError::HandleEvent(anyhow!(
+                        "Failed to get paths restored event: {:?}",
+                        e
+                    ))
+                })?;
+
+                self.resubmit_held_requests().map_err(|e| {
+                    EpollHelperError::HandleEvent(anyhow!(
+                        "Failed to resubmit held requests: {:?}",
+                        e
+                    ))
+                })?;
+
+                let rate_limit_reached = self.rate_limiter.as_ref().is_some_and(|r| r.is_blocked());
+                if !rate_limit_reached {
+                    self.process_queue_submit().map_err(|e| {
+                        EpollHelperError::HandleEvent(anyhow!(
+                            "Failed to process queue (submit): {:?}",
+                            e
+                        ))
+                    })?;
+                }
+                self.try_signal_used_queue()?;
+            }
+            DISK_IMAGE_SWITCH_EVENT => {
+                self.disk_image_switch_evt.read().map_err(|e| {
+                    EpollHelperError::HandleEvent(anyhow!(
+                        "Failed to get disk image switch event: {:?}",
+                        e
+                    ))
+                })?;
+
+                let switched = self.switch_disk_image(helper).map_err(|e| {
+                    EpollHelperError::HandleEvent(anyhow!("Failed to switch disk image: {:?}", e))
+                })?;
+
+                // Requests held back during the switch may now proceed.
+                let rate_limit_reached = self.rate_limiter.as_ref().is_some_and(|r| r.is_blocked());
+                if switched && !rate_limit_reached {
+                    self.process_queue_submit_and_signal()?
+                }
+            }
             _ => {
                 return Err(EpollHelperError::HandleEvent(anyhow!(
                     "Unexpected event: {}",
@@ -576,16 +1177,31 @@ pub struct Block {
     id: String,
     disk_image: Box<dyn DiskFile>,
     disk_path: PathBuf,
-    disk_nsectors: u64,
+    disk_nsectors: Arc<AtomicU64>,
     config: VirtioBlockConfig,
     writeback: Arc<AtomicBool>,
+    cache_mode: CacheMode,
+    detect_zeroes: bool,
     counters: BlockCounters,
+    queue_counters: Vec<BlockCounters>,
     seccomp_action: SeccompAction,
-    rate_limiter: Option<Arc<RateLimiterGroup>>,
+    rate_limiter: Arc<Mutex<Option<Arc<RateLimiterGroup>>>>,
+    rate_limiter_evts: Vec<EventFd>,
+    fair_queues: bool,
     exit_evt: EventFd,
     read_only: bool,
     serial: Vec<u8>,
     queue_affinity: BTreeMap<u16, Vec<usize>>,
+    io_thread: Option<Arc<IoThread>>,
+    io_priority: Option<u16>,
+    coalescing: Option<Arc<Coalescing>>,
+    path_monitor: Option<PathMonitor>,
+    pause_on_path_failure: bool,
+    paths_failed: Arc<AtomicBool>,
+    paths_restored_evts: Vec<EventFd>,
+    disk_image_switch_slots: Vec<Arc<Mutex<Option<DiskImageSwitch>>>>,
+    disk_image_switch_evts: Vec<EventFd>,
+    negotiated_queue_sizes: Vec<u16>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -708,16 +1324,31 @@ impl Block {
             id,
             disk_image,
             disk_path,
-            disk_nsectors,
+            disk_nsectors: Arc::new(AtomicU64::new(disk_nsectors)),
             config,
             writeback: Arc::new(AtomicBool::new(true)),
+            cache_mode: CacheMode::default(),
+            detect_zeroes: false,
             counters: BlockCounters::default(),
+            queue_counters: Vec::new(),
             seccomp_action,
-            rate_limiter,
+            rate_limiter: Arc::new(Mutex::new(rate_limiter)),
+            rate_limiter_evts: Vec::new(),
+            fair_queues: false,
             exit_evt,
             read_only,
             serial,
             queue_affinity,
+            io_thread: None,
+            io_priority: None,
+            coalescing: None,
+            path_monitor: None,
+            pause_on_path_failure: false,
+            paths_failed: Arc::new(AtomicBool::new(false)),
+            paths_restored_evts: Vec::new(),
+            disk_image_switch_slots: Vec::new(),
+            disk_image_switch_evts: Vec::new(),
+            negotiated_queue_sizes: Vec::new(),
         })
     }
 
@@ -768,10 +1399,281 @@ impl Block {
         })
     }
 
+    /// Service the queues from a shared I/O thread rather than spawning a
+    /// thread per queue.
+    pub fn set_io_thread(&mut self, io_thread: Arc<IoThread>) {
+        self.io_thread = Some(io_thread);
+    }
+
+    /// Issue the I/O of the queues with the priority `ioprio`, as encoded by
+    /// [`block::io_priority`].
+    pub fn set_io_priority(&mut self, ioprio: u16) {
+        self.io_priority = Some(ioprio);
+    }
+
+    /// Give each queue a fair part of the bandwidth of the rate limiter
+    /// group when they compete for it, so that a busy queue can't starve
+    /// the others.
+    pub fn set_fair_queues(&mut self, fair_queues: bool) {
+        self.fair_queues = fair_queues;
+    }
+
+    /// Delay used buffer notifications by up to `usecs` microseconds, unless
+    /// `max_used` requests complete in the meantime.
+    pub fn set_coalescing(&mut self, usecs: u32, max_used: u32) {
+        self.coalescing = Some(Arc::new(Coalescing::new(usecs, max_used)));
+    }
+
+    /// Select how the writes of the guest are cached by the host. The
+    /// writethrough mode hides the write cache from the guest, making each
+    /// write durable before completing it.
+    pub fn set_cache_mode(&mut self, cache_mode: CacheMode) {
+        if cache_mode == CacheMode::Writethrough {
+            self.common.avail_features &=
+                !((1u64 << VIRTIO_BLK_F_FLUSH) | (1u64 << VIRTIO_BLK_F_CONFIG_WCE));
+            self.config.writeback = 0;
+        }
+        self.cache_mode = cache_mode;
+    }
+
+    /// Let the guest discard ranges of the disk, deallocating them from the
+    /// disk image so that it stays sparse.
+    pub fn set_discard(&mut self, discard: bool) {
+        if !discard {
+            return;
+        }
+
+        if self.read_only {
+            warn!("Ignoring discard for read-only disk {}", self.id);
+            return;
+        }
+
+        if !self.disk_image.supports_punch_hole() {
+            warn!(
+                "Ignoring discard for disk {}, its image format doesn't support it",
+                self.id
+            );
+            return;
+        }
+
+        self.common.avail_features |=
+            (1u64 << VIRTIO_BLK_F_DISCARD) | (1u64 << VIRTIO_BLK_F_WRITE_ZEROES);
+        // A single segment per request, aligned on the logical block size.
+        let sector_alignment = (self.config.blk_size >> SECTOR_SHIFT).max(1);
+        self.config.max_discard_sectors = u32::MAX;
+        self.config.max_discard_seg = 1;
+        self.config.discard_sector_alignment = sector_alignment;
+        self.config.max_write_zeroes_sectors = u32::MAX;
+        self.config.max_write_zeroes_seg = 1;
+        self.config.write_zeroes_may_unmap = 1;
+    }
+
+    /// Turn the writes of zeros from the guest into write zeroes requests,
+    /// deallocating the range when discard is enabled.
+    pub fn set_detect_zeroes(&mut self, detect_zeroes: bool) {
+        if detect_zeroes && !self.disk_image.supports_punch_hole() {
+            warn!(
+                "Ignoring detect_zeroes for disk {}, its image format doesn't support it",
+                self.id
+            );
+            return;
+        }
+        self.detect_zeroes = detect_zeroes;
+    }
+
+    /// Grow the disk to `size` bytes, extending the disk image unless it
+    /// already was, e.g. by resizing the host block device, and notify the
+    /// guest of its new capacity.
+    pub fn resize(&mut self, size: u64) -> Result<()> {
+        if size % SECTOR_SIZE != 0 {
+            return Err(Error::ResizeUnaligned(size));
+        }
+        let disk_nsectors = size / SECTOR_SIZE;
+        if disk_nsectors < self.disk_nsectors.load(Ordering::Acquire) {
+            return Err(Error::ResizeShrink(size));
+        }
+        if self.read_only {
+            return Err(Error::ResizeReadOnly);
+        }
+
+        if self.disk_image.size().map_err(Error::DiskImageSize)? < size {
+            self.disk_image
+                .resize(size)
+                .map_err(Error::ExtendDiskImage)?;
+        }
+
+        // Check the image was actually extended before exposing the new
+        // sectors to the guest.
+        let image_size = self.disk_image.size().map_err(Error::DiskImageSize)?;
+        if image_size < size {
+            return Err(Error::DiskImageNotExtended { size, image_size });
+        }
+
+        info!("Resizing disk {} to {} sectors", self.id, disk_nsectors);
+        self.disk_nsectors.store(disk_nsectors, Ordering::Release);
+        self.config.capacity = disk_nsectors;
+
+        if let Some(interrupt_cb) = &self.common.interrupt_cb {
+            interrupt_cb
+                .trigger(VirtioInterruptType::Config)
+                .map_err(Error::ConfigChangeSignal)?;
+        }
+
+        Ok(())
+    }
+
+    /// Move the disk to another image, e.g. an overlay of the current one,
+    /// the requests in flight completing on the current image first. The
+    /// returned handle tracks the queues moving to the new image.
+    pub fn switch_disk_image(
+        &mut self,
+        mut disk_image: Box<dyn DiskFile>,
+        disk_path: PathBuf,
+    ) -> Result<DiskImageSwitchDone> {
+        if self.read_only {
+            return Err(Error::SwitchReadOnly);
+        }
+
+        let image_size = disk_image.size().map_err(Error::DiskImageSize)?;
+        if image_size < self.disk_nsectors.load(Ordering::Acquire) * SECTOR_SIZE {
+            return Err(Error::SwitchDiskImageSize { image_size });
+        }
+
+        let mut async_ios = Vec::new();
+        for queue_size in self.negotiated_queue_sizes.iter() {
+            async_ios.push(
+                disk_image
+                    .new_async_io(*queue_size as u32)
+                    .map_err(Error::SwitchAsyncIo)?,
+            );
+        }
+
+        self.unlock_image()?;
+        let disk_image = std::mem::replace(&mut self.disk_image, disk_image);
+        let disk_path = std::mem::replace(&mut self.disk_path, disk_path);
+        if let Err(e) = self.try_lock_image() {
+            self.disk_image = disk_image;
+            self.disk_path = disk_path;
+            self.try_lock_image()?;
+            return Err(e);
+        }
+
+        info!(
+            "Switching disk {} to image {}",
+            self.id,
+            self.disk_path.display()
+        );
+        let (sender, receiver) = mpsc::channel();
+        for ((slot, evt), disk_image) in self
+            .disk_image_switch_slots
+            .iter()
+            .zip(self.disk_image_switch_evts.iter())
+            .zip(async_ios)
+        {
+            *slot.lock().unwrap() = Some(DiskImageSwitch {
+                disk_image,
+                done: sender.clone(),
+            });
+            evt.write(1).map_err(Error::SwitchDiskImageSignal)?;
+        }
+
+        Ok(DiskImageSwitchDone {
+            receiver,
+            queues: self.negotiated_queue_sizes.len(),
+        })
+    }
+
+    /// Sizes of the queues negotiated with the guest, empty until the
+    /// driver activates the device.
+    pub fn negotiated_queue_sizes(&self) -> &[u16] {
+        &self.negotiated_queue_sizes
+    }
+
+    /// Move the device to another rate limiter group, or out of any group,
+    /// while its queues keep being processed.
+    pub fn set_rate_limiter(
+        &mut self,
+        rate_limiter: Option<Arc<RateLimiterGroup>>,
+    ) -> io::Result<()> {
+        *self.rate_limiter.lock().unwrap() = rate_limiter;
+        for rate_limiter_evt in self.rate_limiter_evts.iter() {
+            rate_limiter_evt.write(1)?;
+        }
+
+        Ok(())
+    }
+
+    /// Monitor the paths of the host block device backing the disk, if any,
+    /// pausing the I/O of the disk while all of them failed when
+    /// `pause_on_path_failure` is set, rather than failing the requests.
+    pub fn monitor_paths(&mut self, pause_on_path_failure: bool) -> io::Result<()> {
+        self.path_monitor = PathMonitor::new(&self.disk_path)?;
+        self.pause_on_path_failure = pause_on_path_failure;
+        if self.path_monitor.is_some() {
+            info!("Monitoring the paths of disk {}", self.id);
+        }
+
+        Ok(())
+    }
+
+    /// Check the paths of the host block device backing the disk, reporting
+    /// their failures and pausing or resuming the disk accordingly.
+    pub fn check_paths(&mut self) -> io::Result<()> {
+        let Some(path_monitor) = self.path_monitor.as_mut() else {
+            return Ok(());
+        };
+
+        for path_event in path_monitor.check()? {
+            match path_event {
+                PathEvent::Failed(path) => {
+                    warn!("Path {} of disk {} failed", path, self.id);
+                    event!(
+                        "virtio-device",
+                        "path-failed",
+                        "id",
+                        &self.id,
+                        "path",
+                        &path
+                    );
+                }
+                PathEvent::Restored(path) => {
+                    info!("Path {} of disk {} restored", path, self.id);
+                    event!(
+                        "virtio-device",
+                        "path-restored",
+                        "id",
+                        &self.id,
+                        "path",
+                        &path
+                    );
+                }
+            }
+        }
+
+        let paths_failed = self.pause_on_path_failure && path_monitor.all_failed();
+        if self.paths_failed.swap(paths_failed, Ordering::AcqRel) != paths_failed {
+            if paths_failed {
+                warn!(
+                    "Pausing the I/O of disk {} until a path is restored",
+                    self.id
+                );
+                event!("virtio-device", "io-paused", "id", &self.id);
+            } else {
+                info!("Resuming the I/O of disk {}", self.id);
+                event!("virtio-device", "io-resumed", "id", &self.id);
+                for paths_restored_evt in self.paths_restored_evts.iter() {
+                    paths_restored_evt.write(1)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     fn state(&self) -> BlockState {
         BlockState {
             disk_path: self.disk_path.to_str().unwrap().to_owned(),
-            disk_nsectors: self.disk_nsectors,
+            disk_nsectors: self.disk_nsectors.load(Ordering::Acquire),
             avail_features: self.common.avail_features,
             acked_features: self.common.acked_features,
             config: self.config,
@@ -779,8 +1681,11 @@ impl Block {
     }
 
     fn update_writeback(&mut self) {
-        // Use writeback from config if VIRTIO_BLK_F_CONFIG_WCE
-        let writeback = if self.common.feature_acked(VIRTIO_BLK_F_CONFIG_WCE.into()) {
+        // Writes are never made durable with the unsafe cache mode
+        let writeback = if self.cache_mode == CacheMode::Unsafe {
+            true
+        } else if self.common.feature_acked(VIRTIO_BLK_F_CONFIG_WCE.into()) {
+            // Use writeback from config if VIRTIO_BLK_F_CONFIG_WCE
             self.config.writeback == 1
         } else {
             // Else check if VIRTIO_BLK_F_FLUSH negotiated
@@ -865,6 +1770,42 @@ impl VirtioDevice for Block {
 
         let mut epoll_threads = Vec::new();
         let event_idx = self.common.feature_acked(VIRTIO_RING_F_EVENT_IDX.into());
+        self.rate_limiter_evts.clear();
+        self.paths_restored_evts.clear();
+        self.disk_image_switch_slots.clear();
+        self.disk_image_switch_evts.clear();
+        self.negotiated_queue_sizes = queues.iter().map(|(_, queue, _)| queue.size()).collect();
+        // The counters of the queues are kept across resets, as the ones of
+        // the whole device.
+        self.queue_counters
+            .resize_with(queues.len(), BlockCounters::default);
+
+        let mut io_thread_group = if self.io_thread.is_some() {
+            // The I/O thread acknowledges the pause for all the queues.
+            self.common.paused_sync = Some(Arc::new(Barrier::new(2)));
+            Some(
+                IoThreadGroup::new(
+                    &self.id,
+                    self.common.paused.clone(),
+                    self.common.paused_sync.clone().unwrap(),
+                    &self.exit_evt,
+                )
+                .map_err(ActivateError::CloneExitEventFd)?,
+            )
+        } else {
+            None
+        };
+
+        // The guest memory is registered with the backends able to access
+        // it without mapping it on each request.
+        let guest_memory_buffers: Vec<libc::iovec> = mem
+            .memory()
+            .iter()
+            .map(|region| libc::iovec {
+                iov_base: region.as_ptr() as *mut libc::c_void,
+                iov_len: region.len() as usize,
+            })
+            .collect();
 
         for i in 0..queues.len() {
             let (_, mut queue, queue_evt) = queues.remove(0);
@@ -873,8 +1814,37 @@ impl VirtioDevice for Block {
             let queue_size = queue.size();
             let (kill_evt, pause_evt) = self.common.dup_eventfds();
             let queue_idx = i as u16;
+            let rate_limiter_evt =
+                EventFd::new(libc::EFD_NONBLOCK).map_err(ActivateError::CreateRateLimiter)?;
+            self.rate_limiter_evts.push(
+                rate_limiter_evt
+                    .try_clone()
+                    .map_err(ActivateError::CreateRateLimiter)?,
+            );
+            let paths_restored_evt = EventFd::new(libc::EFD_NONBLOCK)
+                .map_err(ActivateError::CreatePathsRestoredEvent)?;
+            self.paths_restored_evts.push(
+                paths_restored_evt
+                    .try_clone()
+                    .map_err(ActivateError::CreatePathsRestoredEvent)?,
+            );
+            let disk_image_switch_slot = Arc::new(Mutex::new(None));
+            self.disk_image_switch_slots
+                .push(disk_image_switch_slot.clone());
+            let disk_image_switch_evt = EventFd::new(libc::EFD_NONBLOCK)
+                .map_err(ActivateError::CreateDiskImageSwitchEvent)?;
+            self.disk_image_switch_evts.push(
+                disk_image_switch_evt
+                    .try_clone()
+                    .map_err(ActivateError::CreateDiskImageSwitchEvent)?,
+            );
+
+            let rate_limiter_share = self
+                .fair_queues
+                .then(|| format!("{}_q{}", self.id, queue_idx));
 
             let mut handler = BlockEpollHandler {
+                id: self.id.clone(),
                 queue_index: queue_idx,
                 queue,
                 mem: mem.clone(),
@@ -885,13 +1855,18 @@ impl VirtioDevice for Block {
                         error!("failed to create new AsyncIo: {}", e);
                         ActivateError::BadActivate
                     })?,
-                disk_nsectors: self.disk_nsectors,
+                disk_nsectors: self.disk_nsectors.clone(),
                 interrupt_cb: interrupt_cb.clone(),
                 serial: self.serial.clone(),
                 kill_evt,
                 pause_evt,
                 writeback: self.writeback.clone(),
+                ignore_flush: self.cache_mode == CacheMode::Unsafe,
+                detect_zeroes: self.detect_zeroes,
+                unmap_zeroes: self.common.avail_features & (1u64 << VIRTIO_BLK_F_DISCARD) != 0,
                 counters: self.counters.clone(),
+                queue_counters: self.queue_counters[i].clone(),
+                queue_full: false,
                 queue_evt,
                 // Analysis during boot shows around ~40 maximum requests
                 // This gives head room for systems with slower I/O without
@@ -899,28 +1874,73 @@ impl VirtioDevice for Block {
                 inflight_requests: VecDeque::with_capacity(64),
                 rate_limiter: self
                     .rate_limiter
+                    .lock()
+                    .unwrap()
                     .as_ref()
-                    .map(|r| r.new_handle())
+                    .map(|r| new_rate_limiter_handle(r, rate_limiter_share.as_deref()))
                     .transpose()
                     .unwrap(),
+                rate_limiter_event: RATE_LIMITER_EVENT,
+                rate_limiter_group: self.rate_limiter.clone(),
+                rate_limiter_share,
+                rate_limiter_evt,
                 access_platform: self.common.access_platform.clone(),
                 read_only: self.read_only,
                 host_cpus: self.queue_affinity.get(&queue_idx).cloned(),
+                io_priority: self.io_priority,
+                coalescer: self
+                    .coalescing
+                    .clone()
+                    .map(NotificationCoalescer::new)
+                    .transpose()
+                    .map_err(ActivateError::CreateCoalescingTimer)?,
+                paths_failed: self.paths_failed.clone(),
+                paths_restored_evt,
+                completion_event: COMPLETION_EVENT,
+                disk_image_switch_slot,
+                disk_image_switch_evt,
+                disk_image_switch: None,
+                held_requests: Vec::new(),
+                merged_requests: HashMap::new(),
             };
+            handler.disk_image.register_buffers(&guest_memory_buffers);
+
+            if let Some(io_thread_group) = io_thread_group.as_mut() {
+                let helper = handler.epoll_helper().map_err(|e| {
+                    error!("failed to create epoll helper: {:?}", e);
+                    ActivateError::BadActivate
+                })?;
+                io_thread_group.add_queue(helper, Box::new(handler));
+                continue;
+            }
 
             let paused = self.common.paused.clone();
             let paused_sync = self.common.paused_sync.clone();
+            let thread_type = if self.read_only {
+                Thread::VirtioBlockReadOnly
+            } else {
+                Thread::VirtioBlock
+            };
 
             spawn_virtio_thread(
                 &format!("{}_q{}", self.id.clone(), i),
                 &self.seccomp_action,
-                Thread::VirtioBlock,
+                self.common.landlock.as_ref(),
+                thread_type,
                 &mut epoll_threads,
                 &self.exit_evt,
                 move || handler.run(paused, paused_sync.unwrap()),
             )?;
         }
 
+        if let (Some(io_thread), Some(io_thread_group)) = (&self.io_thread, io_thread_group) {
+            self.common.io_thread_group = Some(
+                io_thread
+                    .submit(io_thread_group)
+                    .map_err(ActivateError::IoThread)?,
+            );
+        }
+
         self.common.epoll_threads = Some(epoll_threads);
         event!("virtio-device", "activated", "id", &self.id);
 
@@ -929,60 +1949,29 @@ impl VirtioDevice for Block {
 
     fn reset(&mut self) -> Option<Arc<dyn VirtioInterrupt>> {
         let result = self.common.reset();
+        self.negotiated_queue_sizes.clear();
         event!("virtio-device", "reset", "id", &self.id);
         result
     }
 
     fn counters(&self) -> Option<HashMap<&'static str, Wrapping<u64>>> {
-        let mut counters = HashMap::new();
-
-        counters.insert(
-            "read_bytes",
-            Wrapping(self.counters.read_bytes.load(Ordering::Acquire)),
-        );
-        counters.insert(
-            "write_bytes",
-            Wrapping(self.counters.write_bytes.load(Ordering::Acquire)),
-        );
-        counters.insert(
-            "read_ops",
-            Wrapping(self.counters.read_ops.load(Ordering::Acquire)),
-        );
-        counters.insert(
-            "write_ops",
-            Wrapping(self.counters.write_ops.load(Ordering::Acquire)),
-        );
-        counters.insert(
-            "write_latency_min",
-            Wrapping(self.counters.write_latency_min.load(Ordering::Acquire)),
-        );
-        counters.insert(
-            "write_latency_max",
-            Wrapping(self.counters.write_latency_max.load(Ordering::Acquire)),
-        );
-        counters.insert(
-            "write_latency_avg",
-            Wrapping(self.counters.write_latency_avg.load(Ordering::Acquire) / LATENCY_SCALE),
-        );
-        counters.insert(
-            "read_latency_min",
-            Wrapping(self.counters.read_latency_min.load(Ordering::Acquire)),
-        );
-        counters.insert(
-            "read_latency_max",
-            Wrapping(self.counters.read_latency_max.load(Ordering::Acquire)),
-        );
-        counters.insert(
-            "read_latency_avg",
-            Wrapping(self.counters.read_latency_avg.load(Ordering::Acquire) / LATENCY_SCALE),
-        );
+        Some(self.counters.to_map())
+    }
 
-        Some(counters)
+    fn queue_counters(&self) -> Vec<HashMap<&'static str, Wrapping<u64>>> {
+        self.queue_counters
+            .iter()
+            .map(BlockCounters::to_map)
+            .collect()
     }
 
     fn set_access_platform(&mut self, access_platform: Arc<dyn AccessPlatform>) {
         self.common.set_access_platform(access_platform)
     }
+
+    fn set_thread_landlock(&mut self, landlock: ThreadLandlock) {
+        self.common.landlock = Some(landlock);
+    }
 }
 
 impl Pausable for Block {
@@ -1006,3 +1995,91 @@ impl Snapshottable for Block {
 }
 impl Transportable for Block {}
 impl Migratable for Block {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disk_image_switch_done() {
+        let (sender, receiver) = mpsc::channel();
+        for _ in 0..2 {
+            sender.send(()).unwrap();
+        }
+        let done = DiskImageSwitchDone {
+            receiver,
+            queues: 2,
+        };
+        assert!(done.wait(Duration::from_millis(10)));
+
+        // One of the queues didn't move to the new image.
+        let (sender, receiver) = mpsc::channel();
+        sender.send(()).unwrap();
+        let done = DiskImageSwitchDone {
+            receiver,
+            queues: 2,
+        };
+        assert!(!done.wait(Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn test_new_rate_limiter_handle() {
+        let mut group = RateLimiterGroup::new("group0", 1000, 0, 1000, 0, 0, 0).unwrap();
+        group.start_thread(EventFd::new(0).unwrap()).unwrap();
+
+        let q0 = new_rate_limiter_handle(&group, Some("disk0_q0")).unwrap();
+        let q1 = new_rate_limiter_handle(&group, Some("disk0_q1")).unwrap();
+
+        // The first queue takes all the bandwidth, the second has to wait.
+        assert!(q0.consume(1000, TokenType::Bytes));
+        assert!(!q1.consume(100, TokenType::Bytes));
+
+        // Wait for the refill timer of the group to unblock it.
+        std::thread::sleep(Duration::from_millis(200));
+        q0.event_handler().unwrap();
+        q1.event_handler().unwrap();
+
+        // With fair queues, the second queue now goes first.
+        assert!(!q0.consume(10, TokenType::Bytes));
+        assert!(q1.consume(10, TokenType::Bytes));
+
+        // Without, the queue isn't held back.
+        let q2 = new_rate_limiter_handle(&group, None).unwrap();
+        assert!(q2.consume(10, TokenType::Bytes));
+    }
+
+    #[test]
+    fn test_block_counters() {
+        let counters = BlockCounters::default();
+
+        // A batch of three reads, the last one slower than a second.
+        for (i, latency) in [5, 50, 2_000_000].into_iter().enumerate() {
+            counters.record_read(latency, i as u64 + 1);
+        }
+        counters.record_write(1_000, 1);
+        counters.add_ops(3 * 4096, 3, 512, 1);
+
+        let map = counters.to_map();
+        assert_eq!(map["read_bytes"].0, 3 * 4096);
+        assert_eq!(map["read_ops"].0, 3);
+        assert_eq!(map["read_latency_min"].0, 5);
+        assert_eq!(map["read_latency_max"].0, 2_000_000);
+        assert_eq!(map["read_latency_avg"].0, 666_685);
+        assert_eq!(map["write_bytes"].0, 512);
+        assert_eq!(map["write_ops"].0, 1);
+        assert_eq!(map["write_latency_avg"].0, 1_000);
+
+        // The histograms are cumulative.
+        assert_eq!(map["read_latency_le_10us"].0, 1);
+        assert_eq!(map["read_latency_le_100us"].0, 2);
+        assert_eq!(map["read_latency_le_1s"].0, 2);
+        assert_eq!(map["write_latency_le_100us"].0, 0);
+        assert_eq!(map["write_latency_le_1ms"].0, 1);
+        assert_eq!(map["write_latency_le_1s"].0, 1);
+        assert_eq!(map["queue_full_events"].0, 0);
+
+        // The counters of a queue are shared with its worker thread.
+        counters.clone().add_ops(4096, 1, 0, 0);
+        assert_eq!(counters.to_map()["read_ops"].0, 4);
+    }
+}
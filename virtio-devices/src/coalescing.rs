@@ -0,0 +1,148 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Used buffer notification coalescing.
+//!
+//! Rather than notifying the driver every time some buffers have been used,
+//! the notification is delayed by up to a configured amount of time, unless
+//! enough buffers have been used in the meantime. This mitigates interrupt
+//! storms from devices sustaining high throughputs.
+
+use std::io;
+use std::num::Wrapping;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::Arc;
+use std::time::Duration;
+
+use vm_virtio::Coalescing;
+use vmm_sys_util::timerfd::TimerFd;
+
+pub struct NotificationCoalescer {
+    params: Arc<Coalescing>,
+    timer: TimerFd,
+    timer_armed: bool,
+    // Index of the used ring when the driver was last notified.
+    notified_used: Wrapping<u16>,
+    pending: bool,
+}
+
+impl NotificationCoalescer {
+    pub fn new(params: Arc<Coalescing>) -> io::Result<Self> {
+        let timer = TimerFd::new()?;
+        // The timer can be reset after it expired but before its expiration
+        // is handled, so reading it must not block.
+        // SAFETY: FFI calls with a valid file descriptor.
+        let ret = unsafe {
+            let fd = timer.as_raw_fd();
+            let flags = libc::fcntl(fd, libc::F_GETFL);
+            libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK)
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(NotificationCoalescer {
+            params,
+            timer,
+            timer_armed: false,
+            notified_used: Wrapping(0),
+            pending: false,
+        })
+    }
+
+    /// Called whenever the driver would otherwise be notified, with the
+    /// current index of the used ring. Returns whether the notification must
+    /// be sent right away, or arms the timer to send it later on.
+    pub fn notify_now(&mut self, next_used: Wrapping<u16>) -> io::Result<bool> {
+        let (usecs, max_used) = self.params.get();
+        let used = (next_used - self.notified_used).0;
+        if usecs == 0 || (max_used != 0 && u32::from(used) >= max_used) {
+            self.notified(next_used)?;
+            return Ok(true);
+        }
+
+        self.pending = true;
+        if !self.timer_armed {
+            self.timer
+                .reset(Duration::from_micros(u64::from(usecs)), None)?;
+            self.timer_armed = true;
+        }
+
+        Ok(false)
+    }
+
+    /// Handles the expiration of the timer. Returns whether a notification
+    /// is pending and must be sent.
+    pub fn timer_expired(&mut self, next_used: Wrapping<u16>) -> io::Result<bool> {
+        if let Err(e) = self.timer.wait() {
+            let e = io::Error::from(e);
+            return match e.kind() {
+                io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted => Ok(false),
+                _ => Err(e),
+            };
+        }
+        self.timer_armed = false;
+
+        if !self.pending {
+            return Ok(false);
+        }
+
+        self.notified(next_used)?;
+        Ok(true)
+    }
+
+    fn notified(&mut self, next_used: Wrapping<u16>) -> io::Result<()> {
+        self.notified_used = next_used;
+        self.pending = false;
+        if self.timer_armed {
+            self.timer.clear()?;
+            self.timer_armed = false;
+        }
+
+        Ok(())
+    }
+}
+
+impl AsRawFd for NotificationCoalescer {
+    fn as_raw_fd(&self) -> RawFd {
+        self.timer.as_raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notification_coalescer() {
+        let params = Arc::new(Coalescing::new(0, 0));
+        let mut coalescer = NotificationCoalescer::new(params.clone()).unwrap();
+
+        // Coalescing disabled, the driver is always notified right away.
+        assert!(coalescer.notify_now(Wrapping(1)).unwrap());
+        assert!(!coalescer.timer_expired(Wrapping(1)).unwrap());
+
+        // Notifications are delayed until enough buffers have been used.
+        params.set(1_000_000, 4);
+        assert!(!coalescer.notify_now(Wrapping(2)).unwrap());
+        assert!(!coalescer.notify_now(Wrapping(4)).unwrap());
+        assert!(coalescer.notify_now(Wrapping(5)).unwrap());
+        // The timer was disarmed by the immediate notification.
+        assert!(!coalescer.timer_expired(Wrapping(5)).unwrap());
+
+        // The used index wrapping around doesn't trigger a notification.
+        params.set(1_000_000, 0);
+        assert!(!coalescer.notify_now(Wrapping(u16::MAX)).unwrap());
+        assert!(!coalescer.notify_now(Wrapping(3)).unwrap());
+
+        // Pending notifications are sent once the timer expires.
+        params.set(1, 0);
+        let mut coalescer = NotificationCoalescer::new(params).unwrap();
+        assert!(!coalescer.notify_now(Wrapping(1)).unwrap());
+        std::thread::sleep(Duration::from_millis(10));
+        assert!(coalescer.timer_expired(Wrapping(2)).unwrap());
+        assert!(!coalescer.timer_expired(Wrapping(2)).unwrap());
+    }
+}
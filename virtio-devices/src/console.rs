@@ -13,7 +13,7 @@ use anyhow::anyhow;
 use libc::{EFD_NONBLOCK, TIOCGWINSZ};
 use seccompiler::SeccompAction;
 use serde::{Deserialize, Serialize};
-use serial_buffer::SerialBuffer;
+use serial_buffer::{OutputSinks, SerialBuffer};
 use thiserror::Error;
 use virtio_queue::{Queue, QueueT};
 use vm_memory::{ByteValued, Bytes, GuestAddressSpace, GuestMemory, GuestMemoryAtomic};
@@ -148,6 +148,7 @@ impl ConsoleEpollHandler {
         in_buffer: Arc<Mutex<VecDeque<u8>>>,
         resizer: Arc<ConsoleResizer>,
         endpoint: Endpoint,
+        sinks: &OutputSinks,
         input_queue_evt: EventFd,
         output_queue_evt: EventFd,
         config_evt: EventFd,
@@ -170,6 +171,7 @@ impl ConsoleEpollHandler {
         } else {
             (None, None)
         };
+        let out = sinks.tee(out);
 
         ConsoleEpollHandler {
             mem,
@@ -570,6 +572,7 @@ pub struct Console {
     resizer: Arc<ConsoleResizer>,
     resize_pipe: Option<File>,
     endpoint: Endpoint,
+    sinks: OutputSinks,
     seccomp_action: SeccompAction,
     in_buffer: Arc<Mutex<VecDeque<u8>>>,
     exit_evt: EventFd,
@@ -666,6 +669,7 @@ impl Console {
                 resizer: resizer.clone(),
                 resize_pipe,
                 endpoint,
+                sinks: OutputSinks::default(),
                 seccomp_action,
                 in_buffer: Arc::new(Mutex::new(in_buffer)),
                 exit_evt,
@@ -674,6 +678,11 @@ impl Console {
         ))
     }
 
+    /// Duplicate the output of the console to the given sinks.
+    pub fn set_output_sinks(&mut self, sinks: OutputSinks) {
+        self.sinks = sinks;
+    }
+
     fn state(&self) -> ConsoleState {
         ConsoleState {
             avail_features: self.common.avail_features,
@@ -750,6 +759,7 @@ impl VirtioDevice for Console {
             self.in_buffer.clone(),
             Arc::clone(&self.resizer),
             self.endpoint.clone(),
+            &self.sinks,
             input_queue_evt,
             output_queue_evt,
             self.resizer.config_evt.try_clone().unwrap(),
@@ -766,6 +776,7 @@ impl VirtioDevice for Console {
         spawn_virtio_thread(
             &self.id,
             &self.seccomp_action,
+            self.common.landlock.as_ref(),
             Thread::VirtioConsole,
             &mut epoll_threads,
             &self.exit_evt,
@@ -20,6 +20,8 @@ use vm_migration::{MigratableError, Pausable};
 use vm_virtio::{AccessPlatform, VirtioDeviceType};
 use vmm_sys_util::eventfd::EventFd;
 
+use crate::io_thread_pool::IoThreadGroupHandle;
+use crate::thread_helper::ThreadLandlock;
 use crate::{
     ActivateError, ActivateResult, Error, GuestMemoryMmap, GuestRegionMmap,
     VIRTIO_F_RING_INDIRECT_DESC,
@@ -151,6 +153,11 @@ pub trait VirtioDevice: Send {
         None
     }
 
+    /// Return the counters of each queue of the device
+    fn queue_counters(&self) -> Vec<HashMap<&'static str, Wrapping<u64>>> {
+        Vec::new()
+    }
+
     /// Helper to allow common implementation of read_config
     fn read_config_from_slice(&self, config: &[u8], offset: u64, mut data: &mut [u8]) {
         let config_len = config.len() as u64;
@@ -174,6 +181,10 @@ pub trait VirtioDevice: Send {
     /// Set the access platform trait to let the device perform address
     /// translations if needed.
     fn set_access_platform(&mut self, _access_platform: Arc<dyn AccessPlatform>) {}
+
+    /// Restrict the worker threads of the device to the files it uses, on
+    /// top of the Landlock rules of the VMM.
+    fn set_thread_landlock(&mut self, _landlock: ThreadLandlock) {}
 }
 
 /// Trait to define address translation for devices managed by virtio-iommu
@@ -202,10 +213,12 @@ pub struct VirtioCommon {
     pub paused: Arc<AtomicBool>,
     pub paused_sync: Option<Arc<Barrier>>,
     pub epoll_threads: Option<Vec<thread::JoinHandle<()>>>,
+    pub io_thread_group: Option<IoThreadGroupHandle>,
     pub queue_sizes: Vec<u16>,
     pub device_type: u32,
     pub min_queues: u16,
     pub access_platform: Option<Arc<dyn AccessPlatform>>,
+    pub landlock: Option<ThreadLandlock>,
 }
 
 impl VirtioCommon {
@@ -278,6 +291,10 @@ impl VirtioCommon {
             }
         }
 
+        if let Some(io_thread_group) = self.io_thread_group.take() {
+            io_thread_group.wait();
+        }
+
         // Return the interrupt
         Some(self.interrupt_cb.take().unwrap())
     }
@@ -291,6 +308,10 @@ impl VirtioCommon {
                 }
             }
         }
+
+        if let Some(io_thread_group) = self.io_thread_group.take() {
+            io_thread_group.wait();
+        }
     }
 
     pub fn dup_eventfds(&self) -> (EventFd, EventFd) {
@@ -342,6 +363,9 @@ impl Pausable for VirtioCommon {
                 t.thread().unpark();
             }
         }
+        if let Some(io_thread_group) = &self.io_thread_group {
+            io_thread_group.resume();
+        }
 
         Ok(())
     }
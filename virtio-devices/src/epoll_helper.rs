@@ -40,6 +40,17 @@ pub enum EpollHelperError {
     HandleTimeout(#[source] anyhow::Error),
 }
 
+/// Outcome of servicing an `EpollHelper` through `EpollHelper::poll()`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum EpollHelperPoll {
+    /// The pending events have been handled.
+    Ready,
+    /// The device is being paused.
+    Paused,
+    /// The device is being stopped.
+    Killed,
+}
+
 pub const EPOLL_HELPER_EVENT_PAUSE: u16 = 0;
 pub const EPOLL_HELPER_EVENT_KILL: u16 = 1;
 pub const EPOLL_HELPER_EVENT_LAST: u16 = 15;
@@ -159,6 +170,62 @@ impl EpollHelper {
         self.run_with_timeout(paused, paused_sync, handler, -1, false)
     }
 
+    // Handle the events currently pending without blocking. This allows for
+    // a single thread to service several EpollHelper instances, by watching
+    // their epoll file descriptors and polling whichever becomes readable.
+    // Unlike run(), pausing doesn't park the calling thread. It's up to the
+    // caller to stop polling until the device is resumed, and then to call
+    // resume().
+    pub fn poll(
+        &mut self,
+        handler: &mut dyn EpollHelperHandler,
+    ) -> std::result::Result<EpollHelperPoll, EpollHelperError> {
+        const EPOLL_EVENTS_LEN: usize = 100;
+        let mut events = vec![epoll::Event::new(epoll::Events::empty(), 0); EPOLL_EVENTS_LEN];
+
+        let num_events = loop {
+            match epoll::wait(self.epoll_file.as_raw_fd(), 0, &mut events[..]) {
+                Ok(res) => break res,
+                Err(e) => {
+                    if e.kind() == std::io::ErrorKind::Interrupted {
+                        continue;
+                    }
+                    return Err(EpollHelperError::Wait(e));
+                }
+            }
+        };
+
+        let mut status = EpollHelperPoll::Ready;
+        for event in events.iter().take(num_events) {
+            let ev_type = event.data as u16;
+
+            match ev_type {
+                EPOLL_HELPER_EVENT_KILL => {
+                    info!("KILL_EVENT received, stopping epoll loop");
+                    return Ok(EpollHelperPoll::Killed);
+                }
+                EPOLL_HELPER_EVENT_PAUSE => {
+                    info!("PAUSE_EVENT received, pausing epoll loop");
+                    // The pause only becomes effective once the caller
+                    // acknowledges it, so the other events can still be
+                    // handled.
+                    status = EpollHelperPoll::Paused;
+                }
+                _ => {
+                    handler.handle_event(self, event)?;
+                }
+            }
+        }
+
+        Ok(status)
+    }
+
+    // Drain the pause event after the device has been resumed, for an
+    // EpollHelper serviced through poll().
+    pub fn resume(&mut self) {
+        let _ = self.pause_evt.read();
+    }
+
     #[cfg(not(fuzzing))]
     pub fn run_with_timeout(
         &mut self,
@@ -0,0 +1,483 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Shared I/O thread pool.
+//!
+//! By default, each virtqueue (or pair of virtqueues) of a device is serviced
+//! by a dedicated thread, which quickly adds up to hundreds of threads on VMs
+//! with dozens of disks and network interfaces. The I/O thread pool instead
+//! provides a fixed number of threads, optionally pinned to a set of host
+//! CPUs, each of them servicing the queues of all the devices assigned to it.
+//!
+//! Every queue keeps its own `EpollHelper`, whose epoll file descriptor is
+//! watched by the epoll loop of the I/O thread. Whenever it becomes readable,
+//! the pending events are handled through `EpollHelper::poll()`.
+//!
+//! The queues of a device are handed over as a single `IoThreadGroup`, always
+//! serviced by the same I/O thread. Since they can't block the thread until
+//! the device is resumed, the queues of a group acknowledge a pause request
+//! altogether, once all of them have seen it.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Barrier, Mutex};
+use std::thread::{self, JoinHandle};
+
+use seccompiler::{apply_filter, SeccompAction};
+use thiserror::Error;
+use vmm_sys_util::eventfd::EventFd;
+
+use crate::epoll_helper::{EpollHelper, EpollHelperHandler, EpollHelperPoll};
+use crate::seccomp_filters::{get_seccomp_filter, Thread};
+
+// Token of the event used to wake up the I/O thread. The tokens of the queues
+// are built from the group identifier and the queue index, which can't
+// collide with it.
+const WAKE_TOKEN: u64 = u64::MAX;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Failed to create epoll file descriptor")]
+    CreateEpoll(#[source] io::Error),
+    #[error("Failed to create wake-up EventFd")]
+    CreateWakeEvent(#[source] io::Error),
+    #[error("Failed to register the wake-up EventFd")]
+    RegisterWakeEvent(#[source] io::Error),
+    #[error("Failed to wake up the I/O thread")]
+    WakeUp(#[source] io::Error),
+    #[error("Failed to clone exit event fd")]
+    CloneExitEventFd(#[source] io::Error),
+    #[error("Failed to create seccomp filter")]
+    CreateSeccompFilter(#[source] seccompiler::Error),
+    #[error("Failed to spawn I/O thread")]
+    ThreadSpawn(#[source] io::Error),
+}
+
+struct IoThreadQueue {
+    helper: EpollHelper,
+    handler: Box<dyn EpollHelperHandler + Send>,
+    paused: bool,
+}
+
+/// Queues of a device, handed over to an I/O thread upon activation.
+pub struct IoThreadGroup {
+    name: String,
+    queues: Vec<Option<IoThreadQueue>>,
+    paused: Arc<AtomicBool>,
+    paused_sync: Arc<Barrier>,
+    // Whether the pause of the device has been acknowledged.
+    pause_acked: bool,
+    exit_evt: EventFd,
+    // Dropped along with the group, to let IoThreadGroupHandle::wait()
+    // return.
+    done: Option<Sender<()>>,
+}
+
+impl IoThreadGroup {
+    /// Create a new group of queues. The I/O thread waits on `paused_sync`
+    /// once on behalf of all the queues, so the barrier must be sized for
+    /// two threads, the I/O thread and the one pausing the device.
+    pub fn new(
+        name: &str,
+        paused: Arc<AtomicBool>,
+        paused_sync: Arc<Barrier>,
+        exit_evt: &EventFd,
+    ) -> io::Result<Self> {
+        Ok(IoThreadGroup {
+            name: name.to_string(),
+            queues: Vec::new(),
+            paused,
+            paused_sync,
+            pause_acked: false,
+            exit_evt: exit_evt.try_clone()?,
+            done: None,
+        })
+    }
+
+    /// Add a queue to the group, given the epoll helper it has been set up
+    /// with and the handler of its events.
+    pub fn add_queue(&mut self, helper: EpollHelper, handler: Box<dyn EpollHelperHandler + Send>) {
+        self.queues.push(Some(IoThreadQueue {
+            helper,
+            handler,
+            paused: false,
+        }));
+    }
+
+    fn is_empty(&self) -> bool {
+        self.queues.iter().all(Option::is_none)
+    }
+}
+
+/// Handle on a group of queues serviced by an I/O thread.
+pub struct IoThreadGroupHandle {
+    io_thread: Arc<IoThread>,
+    done: Receiver<()>,
+}
+
+impl IoThreadGroupHandle {
+    /// Let the I/O thread know the device has been resumed.
+    pub fn resume(&self) {
+        if let Err(e) = self.io_thread.wake_up() {
+            error!("Failed to wake up I/O thread: {:?}", e);
+        }
+    }
+
+    /// Wait for the I/O thread to release the queues of the group, which
+    /// happens once the device has been killed.
+    pub fn wait(self) {
+        // The sender is never used, this returns when it's dropped.
+        let _ = self.done.recv();
+    }
+}
+
+/// A thread of the I/O thread pool.
+pub struct IoThread {
+    wake_evt: EventFd,
+    pending: Mutex<Vec<IoThreadGroup>>,
+    kill: AtomicBool,
+}
+
+impl IoThread {
+    fn wake_up(&self) -> io::Result<()> {
+        self.wake_evt.write(1)
+    }
+
+    /// Hand the queues of a device over to the I/O thread.
+    pub fn submit(
+        self: &Arc<Self>,
+        mut group: IoThreadGroup,
+    ) -> Result<IoThreadGroupHandle, Error> {
+        let (done_tx, done_rx) = channel();
+        group.done = Some(done_tx);
+        self.pending.lock().unwrap().push(group);
+        self.wake_up().map_err(Error::WakeUp)?;
+
+        Ok(IoThreadGroupHandle {
+            io_thread: self.clone(),
+            done: done_rx,
+        })
+    }
+}
+
+struct IoThreadWorker {
+    io_thread: Arc<IoThread>,
+    epoll_file: File,
+    groups: HashMap<u32, IoThreadGroup>,
+    next_group_id: u32,
+}
+
+impl IoThreadWorker {
+    fn token(group_id: u32, queue_index: usize) -> u64 {
+        (u64::from(group_id) << 32) | queue_index as u64
+    }
+
+    fn register(epoll_fd: RawFd, fd: RawFd, token: u64) {
+        if let Err(e) = epoll::ctl(
+            epoll_fd,
+            epoll::ControlOptions::EPOLL_CTL_ADD,
+            fd,
+            epoll::Event::new(epoll::Events::EPOLLIN, token),
+        ) {
+            error!("Failed to register queue with I/O thread: {}", e);
+        }
+    }
+
+    fn unregister(epoll_fd: RawFd, fd: RawFd) {
+        if let Err(e) = epoll::ctl(
+            epoll_fd,
+            epoll::ControlOptions::EPOLL_CTL_DEL,
+            fd,
+            epoll::Event::new(epoll::Events::EPOLLIN, 0),
+        ) {
+            error!("Failed to unregister queue from I/O thread: {}", e);
+        }
+    }
+
+    fn adopt_groups(&mut self) {
+        let groups: Vec<IoThreadGroup> = self.io_thread.pending.lock().unwrap().drain(..).collect();
+        for mut group in groups {
+            let group_id = self.next_group_id;
+            self.next_group_id = self.next_group_id.wrapping_add(1);
+
+            // The device might be activated while paused, on the restore
+            // code path, in which case nothing should be processed until it
+            // has been resumed.
+            if group.paused.load(Ordering::SeqCst) {
+                group.pause_acked = true;
+            }
+
+            for (index, queue) in group.queues.iter_mut().enumerate() {
+                let queue = queue.as_mut().unwrap();
+                if group.pause_acked {
+                    queue.paused = true;
+                } else {
+                    Self::register(
+                        self.epoll_file.as_raw_fd(),
+                        queue.helper.as_raw_fd(),
+                        Self::token(group_id, index),
+                    );
+                }
+            }
+
+            info!("I/O thread now servicing {}", group.name);
+            self.groups.insert(group_id, group);
+        }
+    }
+
+    fn resume_groups(&mut self) {
+        let epoll_fd = self.epoll_file.as_raw_fd();
+        for (group_id, group) in self.groups.iter_mut() {
+            if !group.pause_acked || group.paused.load(Ordering::SeqCst) {
+                continue;
+            }
+
+            group.pause_acked = false;
+            for (index, queue) in group.queues.iter_mut().enumerate() {
+                if let Some(queue) = queue.as_mut() {
+                    queue.helper.resume();
+                    queue.paused = false;
+                    Self::register(
+                        epoll_fd,
+                        queue.helper.as_raw_fd(),
+                        Self::token(*group_id, index),
+                    );
+                }
+            }
+        }
+    }
+
+    fn poll_queue(&mut self, group_id: u32, index: usize) {
+        let epoll_fd = self.epoll_file.as_raw_fd();
+        let Some(group) = self.groups.get_mut(&group_id) else {
+            return;
+        };
+        let Some(queue) = group.queues.get_mut(index).and_then(Option::as_mut) else {
+            return;
+        };
+        if queue.paused {
+            return;
+        }
+
+        let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+            queue.helper.poll(queue.handler.as_mut())
+        }));
+
+        let remove = match result {
+            Ok(Ok(EpollHelperPoll::Ready)) => false,
+            Ok(Ok(EpollHelperPoll::Paused)) => {
+                // Stop watching the queue until the device is resumed.
+                queue.paused = true;
+                Self::unregister(epoll_fd, queue.helper.as_raw_fd());
+                false
+            }
+            Ok(Ok(EpollHelperPoll::Killed)) => true,
+            Ok(Err(e)) => {
+                error!("Error running worker: {:?}", e);
+                group.exit_evt.write(1).ok();
+                true
+            }
+            Err(_) => {
+                error!("{} queue panicked", group.name);
+                group.exit_evt.write(1).ok();
+                true
+            }
+        };
+
+        if remove {
+            if let Some(queue) = group.queues[index].take() {
+                Self::unregister(epoll_fd, queue.helper.as_raw_fd());
+            }
+        }
+
+        if group.is_empty() {
+            info!("I/O thread no longer servicing {}", group.name);
+            self.groups.remove(&group_id);
+            return;
+        }
+
+        if !group.pause_acked && group.queues.iter().flatten().all(|q| q.paused) {
+            // Acknowledge the pause on behalf of all the queues of the
+            // device.
+            group.paused_sync.wait();
+            group.pause_acked = true;
+        }
+    }
+
+    fn run(&mut self) {
+        const EPOLL_EVENTS_LEN: usize = 100;
+        let mut events = vec![epoll::Event::new(epoll::Events::empty(), 0); EPOLL_EVENTS_LEN];
+
+        loop {
+            let num_events = match epoll::wait(self.epoll_file.as_raw_fd(), -1, &mut events[..]) {
+                Ok(res) => res,
+                Err(e) => {
+                    if e.kind() == io::ErrorKind::Interrupted {
+                        continue;
+                    }
+                    error!("Failed to wait for I/O thread events: {}", e);
+                    return;
+                }
+            };
+
+            for event in events.iter().take(num_events) {
+                let token = event.data;
+                if token == WAKE_TOKEN {
+                    let _ = self.io_thread.wake_evt.read();
+                    if self.io_thread.kill.load(Ordering::SeqCst) {
+                        return;
+                    }
+                    self.adopt_groups();
+                    self.resume_groups();
+                } else {
+                    self.poll_queue((token >> 32) as u32, token as u32 as usize);
+                }
+            }
+        }
+    }
+}
+
+/// Pool of threads servicing the queues of several devices.
+pub struct IoThreadPool {
+    io_threads: Vec<Arc<IoThread>>,
+    handles: Vec<JoinHandle<()>>,
+    next: usize,
+}
+
+fn set_thread_affinity(host_cpus: &[usize]) {
+    // SAFETY: all zeros is a valid pattern
+    let mut cpuset: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    // SAFETY: FFI call, trivially safe
+    unsafe { libc::CPU_ZERO(&mut cpuset) };
+    for host_cpu in host_cpus {
+        // SAFETY: FFI call, trivially safe
+        unsafe { libc::CPU_SET(*host_cpu, &mut cpuset) };
+    }
+
+    // SAFETY: FFI call with correct arguments
+    let ret = unsafe {
+        libc::sched_setaffinity(
+            0,
+            std::mem::size_of::<libc::cpu_set_t>(),
+            &cpuset as *const libc::cpu_set_t,
+        )
+    };
+    if ret != 0 {
+        error!(
+            "Failed scheduling the I/O thread on the expected CPU set: {}",
+            io::Error::last_os_error()
+        )
+    }
+}
+
+impl IoThreadPool {
+    /// Spawn `count` I/O threads, each of them being pinned to the host CPUs
+    /// found for its index in `affinity`, if any.
+    pub fn new(
+        count: usize,
+        affinity: &HashMap<usize, Vec<usize>>,
+        seccomp_action: &SeccompAction,
+        exit_evt: &EventFd,
+    ) -> Result<Self, Error> {
+        // Threads already spawned are stopped through drop() if any of them
+        // fails to be created.
+        let mut pool = IoThreadPool {
+            io_threads: Vec::with_capacity(count),
+            handles: Vec::with_capacity(count),
+            next: 0,
+        };
+
+        for index in 0..count {
+            let epoll_fd = epoll::create(true).map_err(Error::CreateEpoll)?;
+            // SAFETY: epoll_fd is a valid fd
+            let epoll_file = unsafe { File::from_raw_fd(epoll_fd) };
+            let wake_evt = EventFd::new(libc::EFD_NONBLOCK).map_err(Error::CreateWakeEvent)?;
+            epoll::ctl(
+                epoll_file.as_raw_fd(),
+                epoll::ControlOptions::EPOLL_CTL_ADD,
+                wake_evt.as_raw_fd(),
+                epoll::Event::new(epoll::Events::EPOLLIN, WAKE_TOKEN),
+            )
+            .map_err(Error::RegisterWakeEvent)?;
+
+            let io_thread = Arc::new(IoThread {
+                wake_evt,
+                pending: Mutex::new(Vec::new()),
+                kill: AtomicBool::new(false),
+            });
+
+            let seccomp_filter = get_seccomp_filter(seccomp_action, Thread::VirtioIoThread)
+                .map_err(Error::CreateSeccompFilter)?;
+            let thread_exit_evt = exit_evt.try_clone().map_err(Error::CloneExitEventFd)?;
+            let host_cpus = affinity.get(&index).cloned();
+            let mut worker = IoThreadWorker {
+                io_thread: io_thread.clone(),
+                epoll_file,
+                groups: HashMap::new(),
+                next_group_id: 0,
+            };
+
+            let handle = thread::Builder::new()
+                .name(format!("io_thread{index}"))
+                .spawn(move || {
+                    if let Some(host_cpus) = host_cpus {
+                        set_thread_affinity(&host_cpus);
+                    }
+                    if !seccomp_filter.is_empty() {
+                        if let Err(e) = apply_filter(&seccomp_filter) {
+                            error!("Error applying seccomp filter: {:?}", e);
+                            thread_exit_evt.write(1).ok();
+                            return;
+                        }
+                    }
+                    worker.run();
+                })
+                .map_err(Error::ThreadSpawn)?;
+
+            pool.io_threads.push(io_thread);
+            pool.handles.push(handle);
+        }
+
+        Ok(pool)
+    }
+
+    /// Return the I/O thread at `index`, or the next one in a round-robin
+    /// fashion if no index is provided.
+    pub fn io_thread(&mut self, index: Option<usize>) -> Option<Arc<IoThread>> {
+        if self.io_threads.is_empty() {
+            return None;
+        }
+
+        let index = index.unwrap_or_else(|| {
+            let index = self.next;
+            self.next = (self.next + 1) % self.io_threads.len();
+            index
+        });
+
+        self.io_threads.get(index).cloned()
+    }
+}
+
+impl Drop for IoThreadPool {
+    fn drop(&mut self) {
+        for io_thread in self.io_threads.iter() {
+            io_thread.kill.store(true, Ordering::SeqCst);
+            if let Err(e) = io_thread.wake_up() {
+                error!("Failed to wake up I/O thread: {:?}", e);
+            }
+        }
+
+        for handle in self.handles.drain(..) {
+            if let Err(e) = handle.join() {
+                error!("Error joining I/O thread: {:?}", e);
+            }
+        }
+    }
+}
@@ -1111,6 +1111,7 @@ impl VirtioDevice for Iommu {
         spawn_virtio_thread(
             &self.id,
             &self.seccomp_action,
+            self.common.landlock.as_ref(),
             Thread::VirtioIommu,
             &mut epoll_threads,
             &self.exit_evt,
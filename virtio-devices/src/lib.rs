@@ -9,6 +9,18 @@
 // SPDX-License-Identifier: Apache-2.0 AND BSD-3-Clause
 
 //! Implements virtio devices, queues, and transport mechanisms.
+//!
+//! The devices are independent from the VMM, which lets other programs
+//! embed them: each device implements [`VirtioDevice`], and is exposed to
+//! the guest through a transport, such as [`transport::VirtioPciDevice`],
+//! which notifies the guest through the [`VirtioInterrupt`] implemented by
+//! the embedding program. The devices whose data path is implemented by a
+//! separate backend process, such as the ones of the `vhost_user_block` and
+//! `vhost_user_net` crates, are driven by the [`vhost_user`] frontends.
+//!
+//! The device threads apply the seccomp filters of [`seccomp_filters`],
+//! which only allow the system calls the devices need, unless the device is
+//! created with `SeccompAction::Allow`.
 
 #[macro_use]
 extern crate event_monitor;
@@ -24,17 +36,21 @@ use thiserror::Error;
 mod device;
 pub mod balloon;
 pub mod block;
+mod coalescing;
 mod console;
 pub mod epoll_helper;
+pub mod io_thread_pool;
 mod iommu;
 pub mod mem;
 pub mod net;
 mod pmem;
 mod rng;
+mod scmi;
 pub mod seccomp_filters;
 mod thread_helper;
 pub mod transport;
 pub mod vdpa;
+mod vhost_net;
 pub mod vhost_user;
 pub mod vsock;
 pub mod watchdog;
@@ -45,20 +61,26 @@ use vm_virtio::VirtioDeviceType;
 
 pub use self::balloon::Balloon;
 pub use self::block::{Block, BlockState};
-pub use self::console::{Console, ConsoleResizer, Endpoint};
+pub use self::console::{Console, ConsoleResizer, ConsoleState, Endpoint};
 pub use self::device::{
     DmaRemapping, UserspaceMapping, VirtioCommon, VirtioDevice, VirtioInterrupt,
     VirtioInterruptType, VirtioSharedMemoryList,
 };
 pub use self::epoll_helper::{
-    EpollHelper, EpollHelperError, EpollHelperHandler, EPOLL_HELPER_EVENT_LAST,
+    EpollHelper, EpollHelperError, EpollHelperHandler, EpollHelperPoll, EPOLL_HELPER_EVENT_LAST,
 };
-pub use self::iommu::{AccessPlatformMapping, Iommu, IommuMapping};
+pub use self::io_thread_pool::{IoThread, IoThreadPool};
+pub use self::iommu::{AccessPlatformMapping, Iommu, IommuMapping, IommuState};
 pub use self::mem::{BlocksState, Mem, VirtioMemMappingSource, VIRTIO_MEM_ALIGN_SIZE};
 pub use self::net::{Net, NetCtrlEpollHandler};
-pub use self::pmem::Pmem;
-pub use self::rng::Rng;
+pub use self::pmem::{Pmem, PmemState};
+pub use self::rng::{Rng, RngState};
+pub use self::scmi::{Scmi, ScmiState};
+pub use self::thread_helper::ThreadLandlock;
 pub use self::vdpa::{Vdpa, VdpaDmaMapping};
+pub use self::vhost_user::{
+    Blk as VhostUserBlk, Fs as VhostUserFs, Net as VhostUserNet, VhostUserConfig,
+};
 pub use self::vsock::Vsock;
 pub use self::watchdog::Watchdog;
 
@@ -101,6 +123,14 @@ pub enum ActivateError {
     CreateRateLimiter(#[source] std::io::Error),
     #[error("Failed to activate the vDPA device")]
     ActivateVdpa(#[source] vdpa::Error),
+    #[error("Failed to hand the queues over to the I/O thread")]
+    IoThread(#[source] io_thread_pool::Error),
+    #[error("Failed to create notification coalescing timer")]
+    CreateCoalescingTimer(#[source] std::io::Error),
+    #[error("Failed to create the paths restored event")]
+    CreatePathsRestoredEvent(#[source] std::io::Error),
+    #[error("Failed to create the disk image switch event")]
+    CreateDiskImageSwitchEvent(#[source] std::io::Error),
 }
 
 pub type ActivateResult = std::result::Result<(), ActivateError>;
@@ -966,6 +966,7 @@ impl VirtioDevice for Mem {
         spawn_virtio_thread(
             &self.id,
             &self.seccomp_action,
+            self.common.landlock.as_ref(),
             Thread::VirtioMem,
             &mut epoll_threads,
             &self.exit_evt,
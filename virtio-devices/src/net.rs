@@ -12,17 +12,23 @@ use std::ops::Deref;
 use std::os::unix::io::{AsRawFd, RawFd};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Barrier};
-use std::{result, thread};
+use std::{io, result, thread};
 
 use anyhow::anyhow;
+use net_util::macvtap::{Error as MacvtapError, Macvtap};
 #[cfg(not(fuzzing))]
 use net_util::virtio_features_to_tap_offload;
+#[cfg(feature = "io_uring")]
+use net_util::TapUring;
 use net_util::{
-    build_net_config_space, build_net_config_space_with_mq, open_tap, CtrlQueue, MacAddr,
-    NetCounters, NetQueuePair, OpenTapError, RxVirtio, Tap, TapError, TxVirtio, VirtioNetConfig,
+    build_net_config_space, build_net_config_space_with_mq, open_tap, CtrlQueue, Imds, MacAddr,
+    NetCounters, NetQueuePair, NetRateLimiter, OpenTapError, RxVirtio, Tap, TapError, TxVirtio,
+    VirtioNetConfig, VIRTIO_NET_F_NOTF_COAL,
 };
+use rate_limiter::group::RateLimiterGroup;
 use seccompiler::SeccompAction;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use thiserror::Error;
 use virtio_bindings::virtio_config::*;
 use virtio_bindings::virtio_net::*;
@@ -30,7 +36,7 @@ use virtio_bindings::virtio_ring::VIRTIO_RING_F_EVENT_IDX;
 use virtio_queue::{Queue, QueueT};
 use vm_memory::{ByteValued, GuestAddressSpace, GuestMemoryAtomic};
 use vm_migration::{Migratable, MigratableError, Pausable, Snapshot, Snapshottable, Transportable};
-use vm_virtio::AccessPlatform;
+use vm_virtio::{AccessPlatform, Coalescing};
 use vmm_sys_util::eventfd::EventFd;
 
 use super::{
@@ -38,9 +44,12 @@ use super::{
     Error as DeviceError, RateLimiterConfig, VirtioCommon, VirtioDevice, VirtioDeviceType,
     VirtioInterruptType, EPOLL_HELPER_EVENT_LAST,
 };
+use crate::coalescing::NotificationCoalescer;
+use crate::io_thread_pool::{IoThread, IoThreadGroup};
 use crate::seccomp_filters::Thread;
-use crate::thread_helper::spawn_virtio_thread;
-use crate::{GuestMemoryMmap, VirtioInterrupt};
+use crate::thread_helper::{spawn_virtio_thread, ThreadLandlock};
+use crate::vhost_net::{self, VhostNetQueuePair};
+use crate::{GuestMemoryMmap, GuestRegionMmap, VirtioInterrupt};
 
 /// Control queue
 // Event available on the control queue.
@@ -71,13 +80,19 @@ impl NetCtrlEpollHandler {
             })
     }
 
+    fn epoll_helper(&self) -> std::result::Result<EpollHelper, EpollHelperError> {
+        let mut helper = EpollHelper::new(&self.kill_evt, &self.pause_evt)?;
+        helper.add_event(self.queue_evt.as_raw_fd(), CTRL_QUEUE_EVENT)?;
+
+        Ok(helper)
+    }
+
     pub fn run_ctrl(
         &mut self,
         paused: Arc<AtomicBool>,
         paused_sync: Arc<Barrier>,
     ) -> std::result::Result<(), EpollHelperError> {
-        let mut helper = EpollHelper::new(&self.kill_evt, &self.pause_evt)?;
-        helper.add_event(self.queue_evt.as_raw_fd(), CTRL_QUEUE_EVENT)?;
+        let mut helper = self.epoll_helper()?;
         helper.run(paused, paused_sync, self)?;
 
         Ok(())
@@ -150,6 +165,13 @@ pub const TX_TAP_EVENT: u16 = EPOLL_HELPER_EVENT_LAST + 4;
 pub const RX_RATE_LIMITER_EVENT: u16 = EPOLL_HELPER_EVENT_LAST + 5;
 // New 'wake up' event from the tx rate limiter
 pub const TX_RATE_LIMITER_EVENT: u16 = EPOLL_HELPER_EVENT_LAST + 6;
+// Requests to the TAP device submitted through io_uring have completed.
+#[cfg(feature = "io_uring")]
+pub const TAP_URING_EVENT: u16 = EPOLL_HELPER_EVENT_LAST + 7;
+// A delayed notification of the rx queue is due.
+pub const RX_COALESCING_EVENT: u16 = EPOLL_HELPER_EVENT_LAST + 8;
+// A delayed notification of the tx queue is due.
+pub const TX_COALESCING_EVENT: u16 = EPOLL_HELPER_EVENT_LAST + 9;
 
 #[derive(Error, Debug)]
 pub enum Error {
@@ -159,6 +181,8 @@ pub enum Error {
     TapError(#[source] TapError),
     #[error("Error calling dup() on tap fd")]
     DuplicateTapFd(#[source] std::io::Error),
+    #[error("Failed to open the macvtap queues")]
+    Macvtap(#[source] MacvtapError),
 }
 
 pub type Result<T> = result::Result<T, Error>;
@@ -177,10 +201,32 @@ struct NetEpollHandler {
     // a restore as the vCPU thread isn't ready to handle the interrupt. This causes
     // issues when combined with VIRTIO_RING_F_EVENT_IDX interrupt suppression.
     driver_awake: bool,
+    #[cfg(feature = "io_uring")]
+    tap_uring: Option<TapUring>,
+    rx_coalescer: Option<NotificationCoalescer>,
+    tx_coalescer: Option<NotificationCoalescer>,
 }
 
 impl NetEpollHandler {
-    fn signal_used_queue(&self, queue_index: u16) -> result::Result<(), DeviceError> {
+    fn signal_used_queue(&mut self, queue_index: u16) -> result::Result<(), DeviceError> {
+        let (queue, coalescer) = if queue_index == self.queue_index_base {
+            (&self.queue_pair.0, self.rx_coalescer.as_mut())
+        } else {
+            (&self.queue_pair.1, self.tx_coalescer.as_mut())
+        };
+        if let Some(coalescer) = coalescer {
+            if !coalescer
+                .notify_now(Wrapping(queue.next_used()))
+                .map_err(DeviceError::IoError)?
+            {
+                return Ok(());
+            }
+        }
+
+        self.trigger_used_queue(queue_index)
+    }
+
+    fn trigger_used_queue(&self, queue_index: u16) -> result::Result<(), DeviceError> {
         self.interrupt_cb
             .trigger(VirtioInterruptType::Queue(queue_index))
             .map_err(|e| {
@@ -197,6 +243,23 @@ impl NetEpollHandler {
 
         self.net.rx_desc_avail = true;
 
+        #[cfg(feature = "io_uring")]
+        if self.tap_uring.is_some() {
+            return self.process_uring_rx();
+        }
+
+        // Frames from the metadata service or the I/O trace don't come
+        // through the TAP, so deliver them as soon as some RX buffers are
+        // available.
+        if self.net.rx_frames_pending() {
+            self.handle_rx_tap_event()?;
+        }
+
+        // When replaying, the TAP is never read.
+        if self.net.replaying() {
+            return Ok(());
+        }
+
         let rate_limit_reached = self
             .net
             .rx_rate_limiter
@@ -219,6 +282,23 @@ impl NetEpollHandler {
     }
 
     fn process_tx(&mut self) -> result::Result<(), DeviceError> {
+        #[cfg(feature = "io_uring")]
+        if let Some(tap_uring) = self.tap_uring.as_mut() {
+            if tap_uring
+                .process_tx(&self.mem.memory(), &mut self.queue_pair.1, &mut self.net)
+                .map_err(DeviceError::NetQueuePair)?
+                || !self.driver_awake
+            {
+                self.signal_used_queue(self.queue_index_base + 1)?;
+            }
+
+            // Deliver the replies from the metadata service, if any.
+            if self.net.imds_pending() && self.net.rx_desc_avail {
+                self.process_uring_rx()?;
+            }
+            return Ok(());
+        }
+
         if self
             .net
             .process_tx(&self.mem.memory(), &mut self.queue_pair.1)
@@ -230,6 +310,12 @@ impl NetEpollHandler {
         } else {
             debug!("Not signalling TX queue");
         }
+
+        // Deliver the replies from the metadata service, or the frames from
+        // the I/O trace, if any.
+        if self.net.rx_frames_pending() && self.net.rx_desc_avail {
+            self.handle_rx_tap_event()?;
+        }
         Ok(())
     }
 
@@ -262,11 +348,73 @@ impl NetEpollHandler {
         Ok(())
     }
 
-    fn run(
-        &mut self,
-        paused: Arc<AtomicBool>,
-        paused_sync: Arc<Barrier>,
-    ) -> result::Result<(), EpollHelperError> {
+    #[cfg(feature = "io_uring")]
+    fn process_uring_rx(&mut self) -> result::Result<(), DeviceError> {
+        let tap_uring = self.tap_uring.as_mut().unwrap();
+        if tap_uring
+            .process_rx(&self.mem.memory(), &mut self.queue_pair.0, &mut self.net)
+            .map_err(DeviceError::NetQueuePair)?
+            || !self.driver_awake
+        {
+            self.signal_used_queue(self.queue_index_base)?;
+            debug!("Signalling RX queue");
+        } else {
+            debug!("Not signalling RX queue");
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "io_uring")]
+    fn handle_tap_uring_event(&mut self) -> result::Result<(), DeviceError> {
+        let tap_uring = self.tap_uring.as_mut().unwrap();
+        if let Err(e) = tap_uring.notifier().read() {
+            error!("Failed to get tap io_uring event: {:?}", e);
+        }
+
+        if tap_uring
+            .process_completions(&self.mem.memory(), &mut self.queue_pair.1, &mut self.net)
+            .map_err(DeviceError::NetQueuePair)?
+            || !self.driver_awake
+        {
+            self.signal_used_queue(self.queue_index_base + 1)?;
+        }
+
+        // Frames held back while too many of them were being written can be
+        // sent now.
+        self.handle_tx_event()?;
+        self.process_uring_rx()
+    }
+
+    fn handle_coalescing_event(&mut self, queue_index: u16) -> result::Result<(), DeviceError> {
+        let (queue, coalescer) = if queue_index == self.queue_index_base {
+            (&self.queue_pair.0, self.rx_coalescer.as_mut())
+        } else {
+            (&self.queue_pair.1, self.tx_coalescer.as_mut())
+        };
+        let Some(coalescer) = coalescer else {
+            return Err(DeviceError::IoError(io::Error::other(
+                "Unexpected coalescing event when coalescing is not enabled",
+            )));
+        };
+
+        if coalescer
+            .timer_expired(Wrapping(queue.next_used()))
+            .map_err(DeviceError::IoError)?
+        {
+            self.trigger_used_queue(queue_index)?;
+        }
+
+        Ok(())
+    }
+
+    fn tap_uring_enabled(&self) -> bool {
+        #[cfg(feature = "io_uring")]
+        return self.tap_uring.is_some();
+        #[cfg(not(feature = "io_uring"))]
+        return false;
+    }
+
+    fn epoll_helper(&mut self) -> result::Result<EpollHelper, EpollHelperError> {
         let mut helper = EpollHelper::new(&self.kill_evt, &self.pause_evt)?;
         helper.add_event(self.queue_evt_pair.0.as_raw_fd(), RX_QUEUE_EVENT)?;
         helper.add_event(self.queue_evt_pair.1.as_raw_fd(), TX_QUEUE_EVENT)?;
@@ -276,6 +424,16 @@ impl NetEpollHandler {
         if let Some(rate_limiter) = &self.net.tx_rate_limiter {
             helper.add_event(rate_limiter.as_raw_fd(), TX_RATE_LIMITER_EVENT)?;
         }
+        #[cfg(feature = "io_uring")]
+        if let Some(tap_uring) = &self.tap_uring {
+            helper.add_event(tap_uring.notifier().as_raw_fd(), TAP_URING_EVENT)?;
+        }
+        if let Some(coalescer) = &self.rx_coalescer {
+            helper.add_event(coalescer.as_raw_fd(), RX_COALESCING_EVENT)?;
+        }
+        if let Some(coalescer) = &self.tx_coalescer {
+            helper.add_event(coalescer.as_raw_fd(), TX_COALESCING_EVENT)?;
+        }
 
         let mem = self.mem.memory();
         // If there are some already available descriptors on the RX queue,
@@ -291,14 +449,27 @@ impl NetEpollHandler {
                 .avail_idx(mem.deref(), Ordering::Acquire)
                 .map_err(EpollHelperError::QueueRingIndex)?
         {
-            helper.add_event(self.net.tap.as_raw_fd(), RX_TAP_EVENT)?;
-            self.net.rx_tap_listening = true;
-            info!("Listener registered at start");
+            if self.tap_uring_enabled() || self.net.replaying() {
+                self.net.rx_desc_avail = true;
+            } else {
+                helper.add_event(self.net.tap.as_raw_fd(), RX_TAP_EVENT)?;
+                self.net.rx_tap_listening = true;
+                info!("Listener registered at start");
+            }
         }
 
         // The NetQueuePair needs the epoll fd.
         self.net.epoll_fd = Some(helper.as_raw_fd());
 
+        Ok(helper)
+    }
+
+    fn run(
+        &mut self,
+        paused: Arc<AtomicBool>,
+        paused_sync: Arc<Barrier>,
+    ) -> result::Result<(), EpollHelperError> {
+        let mut helper = self.epoll_helper()?;
         helper.run(paused, paused_sync, self)?;
 
         Ok(())
@@ -353,7 +524,18 @@ impl EpollHelperHandler for NetEpollHandler {
                         ))
                     })?;
 
-                    if !self.net.rx_tap_listening && self.net.rx_desc_avail {
+                    if self.tap_uring_enabled() {
+                        #[cfg(feature = "io_uring")]
+                        self.process_uring_rx().map_err(|e| {
+                            EpollHelperError::HandleEvent(anyhow!(
+                                "Error processing RX queue: {:?}",
+                                e
+                            ))
+                        })?;
+                    } else if !self.net.rx_tap_listening
+                        && self.net.rx_desc_avail
+                        && !self.net.replaying()
+                    {
                         net_util::register_listener(
                             self.net.epoll_fd.unwrap(),
                             self.net.tap.as_raw_fd(),
@@ -396,6 +578,30 @@ impl EpollHelperHandler for NetEpollHandler {
                     )));
                 }
             }
+            #[cfg(feature = "io_uring")]
+            TAP_URING_EVENT => {
+                self.handle_tap_uring_event().map_err(|e| {
+                    EpollHelperError::HandleEvent(anyhow!("Error processing tap io_uring: {:?}", e))
+                })?;
+            }
+            RX_COALESCING_EVENT => {
+                self.handle_coalescing_event(self.queue_index_base)
+                    .map_err(|e| {
+                        EpollHelperError::HandleEvent(anyhow!(
+                            "Error signalling RX queue (coalescing): {:?}",
+                            e
+                        ))
+                    })?;
+            }
+            TX_COALESCING_EVENT => {
+                self.handle_coalescing_event(self.queue_index_base + 1)
+                    .map_err(|e| {
+                        EpollHelperError::HandleEvent(anyhow!(
+                            "Error signalling TX queue (coalescing): {:?}",
+                            e
+                        ))
+                    })?;
+            }
             _ => {
                 return Err(EpollHelperError::HandleEvent(anyhow!(
                     "Unexpected event: {}",
@@ -416,7 +622,24 @@ pub struct Net {
     counters: NetCounters,
     seccomp_action: SeccompAction,
     rate_limiter_config: Option<RateLimiterConfig>,
+    // Rate-limit group shared with other devices, and the weight of this
+    // device in the group.
+    rate_limit_group: Option<(Arc<RateLimiterGroup>, u16)>,
     exit_evt: EventFd,
+    imds: Option<Arc<Value>>,
+    #[cfg(feature = "io_uring")]
+    io_uring: bool,
+    #[cfg(feature = "io_uring")]
+    numa_node: Option<u32>,
+    io_thread: Option<Arc<IoThread>>,
+    rx_coalescing: Arc<Coalescing>,
+    tx_coalescing: Arc<Coalescing>,
+    vhost_net: bool,
+    // Queue pairs serviced by vhost-net while the device is activated.
+    vhost_net_queue_pairs: Vec<VhostNetQueuePair>,
+    // Macvtap interface the TAP devices are the queues of, dropped after
+    // them.
+    macvtap: Option<Macvtap>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -489,7 +712,7 @@ impl Net {
                 }
             }
 
-            avail_features |= 1 << VIRTIO_NET_F_CTRL_VQ;
+            avail_features |= (1 << VIRTIO_NET_F_CTRL_VQ) | (1 << VIRTIO_NET_F_NOTF_COAL);
             let queue_num = num_queues + 1;
 
             let mut config = VirtioNetConfig::default();
@@ -537,7 +760,19 @@ impl Net {
             counters: NetCounters::default(),
             seccomp_action,
             rate_limiter_config,
+            rate_limit_group: None,
             exit_evt,
+            imds: None,
+            #[cfg(feature = "io_uring")]
+            io_uring: false,
+            #[cfg(feature = "io_uring")]
+            numa_node: None,
+            io_thread: None,
+            rx_coalescing: Arc::new(Coalescing::default()),
+            tx_coalescing: Arc::new(Coalescing::default()),
+            vhost_net: false,
+            vhost_net_queue_pairs: Vec::new(),
+            macvtap: None,
         })
     }
 
@@ -644,6 +879,177 @@ impl Net {
         )
     }
 
+    /// Create a new virtio network device backed by the queues of the given
+    /// macvtap interface, which lives as long as the device.
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_macvtap(
+        id: String,
+        macvtap: Macvtap,
+        guest_mac: Option<MacAddr>,
+        mtu: Option<u16>,
+        iommu: bool,
+        num_queues: usize,
+        queue_size: u16,
+        seccomp_action: SeccompAction,
+        rate_limiter_config: Option<RateLimiterConfig>,
+        exit_evt: EventFd,
+        state: Option<NetState>,
+        offload_tso: bool,
+        offload_ufo: bool,
+        offload_csum: bool,
+    ) -> Result<Self> {
+        let taps = macvtap
+            .open_queues(num_queues / 2)
+            .map_err(Error::Macvtap)?;
+
+        if let Some(mtu) = mtu {
+            taps[0].set_mtu(mtu as i32).map_err(Error::TapError)?;
+        }
+
+        let mut net = Self::new_with_tap(
+            id,
+            taps,
+            guest_mac,
+            iommu,
+            num_queues,
+            queue_size,
+            seccomp_action,
+            rate_limiter_config,
+            exit_evt,
+            state,
+            offload_tso,
+            offload_ufo,
+            offload_csum,
+        )?;
+        net.macvtap = Some(macvtap);
+
+        Ok(net)
+    }
+
+    /// Name of the TAP interface backing the device.
+    pub fn tap_name(&self) -> String {
+        String::from_utf8_lossy(&self.taps[0].get_if_name())
+            .trim_end_matches('\0')
+            .to_string()
+    }
+
+    /// Let the user `uid` and the group `gid` open the TAP interface, and
+    /// keep it or let it be removed once the device is gone, depending on
+    /// `persist`.
+    ///
+    /// The persistence is changed last, so that the interface is never kept
+    /// when it fails to be configured.
+    pub fn configure_tap(
+        &self,
+        persist: Option<bool>,
+        uid: Option<u32>,
+        gid: Option<u32>,
+    ) -> Result<()> {
+        let tap = &self.taps[0];
+        if let Some(uid) = uid {
+            tap.set_owner(uid).map_err(Error::TapError)?;
+        }
+        if let Some(gid) = gid {
+            tap.set_group(gid).map_err(Error::TapError)?;
+        }
+        if let Some(persist) = persist {
+            tap.set_persist(persist).map_err(Error::TapError)?;
+        }
+
+        Ok(())
+    }
+
+    /// Serve the given document as an instance metadata service, reachable
+    /// by the guest on 169.254.169.254.
+    pub fn set_imds(&mut self, document: Arc<Value>) {
+        self.imds = Some(document);
+    }
+
+    /// Exchange frames with the TAP devices through io_uring, rather than
+    /// reading and writing them from the queue pair threads.
+    #[cfg(feature = "io_uring")]
+    pub fn enable_io_uring(&mut self) {
+        self.io_uring = true;
+    }
+
+    /// Allocate the buffers holding the frames received through io_uring on
+    /// the given host NUMA node.
+    #[cfg(feature = "io_uring")]
+    pub fn set_numa_node(&mut self, numa_node: u32) {
+        self.numa_node = Some(numa_node);
+    }
+
+    /// Let the vhost-net kernel module move the frames between the queues
+    /// and the TAP devices, unless it can't be used once the device is
+    /// activated.
+    pub fn enable_vhost_net(&mut self) {
+        self.vhost_net = true;
+        // The kernel doesn't coalesce the notifications.
+        self.common.avail_features &= !(1 << VIRTIO_NET_F_NOTF_COAL);
+    }
+
+    /// Service the queues from a shared I/O thread rather than spawning a
+    /// thread per queue pair and one for the control queue.
+    pub fn set_io_thread(&mut self, io_thread: Arc<IoThread>) {
+        self.io_thread = Some(io_thread);
+    }
+
+    /// Share the bandwidth of `rate_limit_group` with its other devices, in
+    /// proportion to `weight` when they compete for it.
+    pub fn set_rate_limit_group(&mut self, rate_limit_group: Arc<RateLimiterGroup>, weight: u16) {
+        self.rate_limit_group = Some((rate_limit_group, weight));
+    }
+
+    /// Delay used buffer notifications by up to `usecs` microseconds, unless
+    /// `max_used` frames are received or sent in the meantime. The driver can
+    /// later on change these parameters if it supports notification
+    /// coalescing.
+    pub fn set_coalescing(&mut self, usecs: u32, max_used: u32) {
+        self.rx_coalescing.set(usecs, max_used);
+        self.tx_coalescing.set(usecs, max_used);
+    }
+
+    // Hand the queue pairs over to vhost-net, all of them being set up
+    // before any starts moving frames.
+    fn activate_vhost_net(
+        &self,
+        mem: &GuestMemoryAtomic<GuestMemoryMmap>,
+        interrupt_cb: &Arc<dyn VirtioInterrupt>,
+        queues: &[(usize, Queue, EventFd)],
+    ) -> vhost_net::Result<Vec<VhostNetQueuePair>> {
+        if self.imds.is_some() {
+            return Err(vhost_net::Error::Unsupported(
+                "the instance metadata service",
+            ));
+        }
+        if self.common.access_platform.is_some() {
+            return Err(vhost_net::Error::Unsupported("the virtual IOMMU"));
+        }
+
+        let queue_pairs = queues
+            .chunks(2)
+            .zip(self.taps.iter())
+            .map(|(queue_pair, tap)| {
+                #[cfg(not(fuzzing))]
+                tap.set_offload(virtio_features_to_tap_offload(self.common.acked_features))
+                    .map_err(vhost_net::Error::SetTapOffload)?;
+                VhostNetQueuePair::new(
+                    mem,
+                    self.common.acked_features,
+                    tap.clone(),
+                    queue_pair,
+                    interrupt_cb,
+                )
+            })
+            .collect::<vhost_net::Result<Vec<_>>>()?;
+
+        for queue_pair in queue_pairs.iter() {
+            queue_pair.start()?;
+        }
+
+        Ok(queue_pairs)
+    }
+
     fn state(&self) -> NetState {
         NetState {
             avail_features: self.common.avail_features,
@@ -706,18 +1112,43 @@ impl VirtioDevice for Net {
 
         let num_queues = queues.len();
         let event_idx = self.common.feature_acked(VIRTIO_RING_F_EVENT_IDX.into());
-        if self.common.feature_acked(VIRTIO_NET_F_CTRL_VQ.into()) && num_queues % 2 != 0 {
+
+        let mut io_thread_group = if self.io_thread.is_some() {
+            // The I/O thread acknowledges the pause for all the queues.
+            self.common.paused_sync = Some(Arc::new(Barrier::new(2)));
+            Some(
+                IoThreadGroup::new(
+                    &self.id,
+                    self.common.paused.clone(),
+                    self.common.paused_sync.clone().unwrap(),
+                    &self.exit_evt,
+                )
+                .map_err(ActivateError::CloneExitEventFd)?,
+            )
+        } else {
+            None
+        };
+
+        let has_ctrl_queue =
+            self.common.feature_acked(VIRTIO_NET_F_CTRL_VQ.into()) && num_queues % 2 != 0;
+        if has_ctrl_queue {
             let ctrl_queue_index = num_queues - 1;
             let (_, mut ctrl_queue, ctrl_queue_evt) = queues.remove(ctrl_queue_index);
 
             ctrl_queue.set_event_idx(event_idx);
 
             let (kill_evt, pause_evt) = self.common.dup_eventfds();
+            let mut ctrl_q = CtrlQueue::new(self.taps.clone());
+            if self.common.feature_acked(VIRTIO_NET_F_NOTF_COAL.into()) {
+                ctrl_q.rx_coalescing = Some(self.rx_coalescing.clone());
+                ctrl_q.tx_coalescing = Some(self.tx_coalescing.clone());
+            }
+
             let mut ctrl_handler = NetCtrlEpollHandler {
                 mem: mem.clone(),
                 kill_evt,
                 pause_evt,
-                ctrl_q: CtrlQueue::new(self.taps.clone()),
+                ctrl_q,
                 queue: ctrl_queue,
                 queue_evt: ctrl_queue_evt,
                 access_platform: self.common.access_platform.clone(),
@@ -725,25 +1156,68 @@ impl VirtioDevice for Net {
                 interrupt_cb: interrupt_cb.clone(),
             };
 
-            let paused = self.common.paused.clone();
-            // Let's update the barrier as we need 1 for each RX/TX pair +
-            // 1 for the control queue + 1 for the main thread signalling
-            // the pause.
-            self.common.paused_sync = Some(Arc::new(Barrier::new(self.taps.len() + 2)));
-            let paused_sync = self.common.paused_sync.clone();
+            if let Some(io_thread_group) = io_thread_group.as_mut() {
+                let helper = ctrl_handler.epoll_helper().map_err(|e| {
+                    error!("failed to create epoll helper: {:?}", e);
+                    ActivateError::BadActivate
+                })?;
+                io_thread_group.add_queue(helper, Box::new(ctrl_handler));
+            } else {
+                let paused = self.common.paused.clone();
+                // Let's update the barrier as we need 1 for each RX/TX pair +
+                // 1 for the control queue + 1 for the main thread signalling
+                // the pause.
+                self.common.paused_sync = Some(Arc::new(Barrier::new(self.taps.len() + 2)));
+                let paused_sync = self.common.paused_sync.clone();
+
+                let mut epoll_threads = Vec::new();
+                spawn_virtio_thread(
+                    &format!("{}_ctrl", &self.id),
+                    &self.seccomp_action,
+                    self.common.landlock.as_ref(),
+                    Thread::VirtioNetCtl,
+                    &mut epoll_threads,
+                    &self.exit_evt,
+                    move || ctrl_handler.run_ctrl(paused, paused_sync.unwrap()),
+                )?;
+                self.ctrl_queue_epoll_thread = Some(epoll_threads.remove(0));
+            }
+        }
 
-            let mut epoll_threads = Vec::new();
-            spawn_virtio_thread(
-                &format!("{}_ctrl", &self.id),
-                &self.seccomp_action,
-                Thread::VirtioNetCtl,
-                &mut epoll_threads,
-                &self.exit_evt,
-                move || ctrl_handler.run_ctrl(paused, paused_sync.unwrap()),
-            )?;
-            self.ctrl_queue_epoll_thread = Some(epoll_threads.remove(0));
+        // The control queue is always serviced from userspace, while the
+        // other queues are left to vhost-net when possible.
+        if self.vhost_net {
+            match self.activate_vhost_net(&mem, &interrupt_cb, &queues) {
+                Ok(queue_pairs) => {
+                    info!("Datapath of {} offloaded to vhost-net", self.id);
+                    self.vhost_net_queue_pairs = queue_pairs;
+                    queues.clear();
+                    if !has_ctrl_queue {
+                        io_thread_group = None;
+                    }
+                    // Only the control queue thread acknowledges the pause.
+                    self.common.paused_sync =
+                        Some(Arc::new(Barrier::new(1 + usize::from(has_ctrl_queue))));
+                }
+                Err(e) => warn!(
+                    "Failed to offload the datapath of {} to vhost-net, servicing it from userspace: {}",
+                    self.id, e
+                ),
+            }
         }
 
+        // Notifications are only coalesced when enabled from the
+        // configuration, or when the driver is able to enable it.
+        let coalescing = self.common.feature_acked(VIRTIO_NET_F_NOTF_COAL.into())
+            || self.rx_coalescing.get().0 != 0
+            || self.tx_coalescing.get().0 != 0;
+        let new_coalescer = |params: &Arc<Coalescing>| {
+            coalescing
+                .then(|| NotificationCoalescer::new(params.clone()))
+                .transpose()
+                .map_err(ActivateError::CreateCoalescingTimer)
+        };
+
         let mut epoll_threads = Vec::new();
         let mut taps = self.taps.clone();
         for i in 0..queues.len() / 2 {
@@ -761,17 +1235,26 @@ impl VirtioDevice for Net {
 
             let (kill_evt, pause_evt) = self.common.dup_eventfds();
 
-            let rx_rate_limiter: Option<rate_limiter::RateLimiter> = self
-                .rate_limiter_config
-                .map(RateLimiterConfig::try_into)
-                .transpose()
-                .map_err(ActivateError::CreateRateLimiter)?;
-
-            let tx_rate_limiter: Option<rate_limiter::RateLimiter> = self
-                .rate_limiter_config
-                .map(RateLimiterConfig::try_into)
-                .transpose()
-                .map_err(ActivateError::CreateRateLimiter)?;
+            let (rx_rate_limiter, tx_rate_limiter) =
+                if let Some((rate_limit_group, weight)) = &self.rate_limit_group {
+                    // All the queues of the device consume from one share.
+                    let new_handle = || {
+                        rate_limit_group
+                            .new_weighted_handle(&self.id, *weight)
+                            .map(NetRateLimiter::Group)
+                            .map_err(|e| ActivateError::CreateRateLimiter(io::Error::other(e)))
+                    };
+                    (Some(new_handle()?), Some(new_handle()?))
+                } else {
+                    let new_rate_limiter = || {
+                        self.rate_limiter_config
+                            .map(RateLimiterConfig::try_into)
+                            .transpose()
+                            .map(|r| r.map(NetRateLimiter::Queue))
+                            .map_err(ActivateError::CreateRateLimiter)
+                    };
+                    (new_rate_limiter()?, new_rate_limiter()?)
+                };
 
             let tap = taps.remove(0);
             #[cfg(not(fuzzing))]
@@ -781,6 +1264,23 @@ impl VirtioDevice for Net {
                     ActivateError::BadActivate
                 })?;
 
+            #[cfg(feature = "io_uring")]
+            // The I/O trace only hooks the epoll path.
+            let tap_uring = if self.io_uring && replay::mode().is_none() {
+                match TapUring::new(&tap, queue_pair.1.size(), self.numa_node) {
+                    Ok(tap_uring) => Some(tap_uring),
+                    Err(e) => {
+                        warn!(
+                            "Failed to set up io_uring for the TAP device, using epoll: {}",
+                            e
+                        );
+                        None
+                    }
+                }
+            } else {
+                None
+            };
+
             let mut handler = NetEpollHandler {
                 net: NetQueuePair {
                     tap_for_write_epoll: tap.clone(),
@@ -797,6 +1297,8 @@ impl VirtioDevice for Net {
                     rx_rate_limiter,
                     tx_rate_limiter,
                     access_platform: self.common.access_platform.clone(),
+                    imds: self.imds.clone().map(Imds::new),
+                    replay_source: replay::mode().map(|_| format!("{}/rx{}", self.id, i)),
                 },
                 mem: mem.clone(),
                 queue_index_base: (i * 2) as u16,
@@ -806,14 +1308,28 @@ impl VirtioDevice for Net {
                 kill_evt,
                 pause_evt,
                 driver_awake: false,
+                #[cfg(feature = "io_uring")]
+                tap_uring,
+                rx_coalescer: new_coalescer(&self.rx_coalescing)?,
+                tx_coalescer: new_coalescer(&self.tx_coalescing)?,
             };
 
+            if let Some(io_thread_group) = io_thread_group.as_mut() {
+                let helper = handler.epoll_helper().map_err(|e| {
+                    error!("failed to create epoll helper: {:?}", e);
+                    ActivateError::BadActivate
+                })?;
+                io_thread_group.add_queue(helper, Box::new(handler));
+                continue;
+            }
+
             let paused = self.common.paused.clone();
             let paused_sync = self.common.paused_sync.clone();
 
             spawn_virtio_thread(
                 &format!("{}_qp{}", self.id.clone(), i),
                 &self.seccomp_action,
+                self.common.landlock.as_ref(),
                 Thread::VirtioNet,
                 &mut epoll_threads,
                 &self.exit_evt,
@@ -821,6 +1337,14 @@ impl VirtioDevice for Net {
             )?;
         }
 
+        if let (Some(io_thread), Some(io_thread_group)) = (&self.io_thread, io_thread_group) {
+            self.common.io_thread_group = Some(
+                io_thread
+                    .submit(io_thread_group)
+                    .map_err(ActivateError::IoThread)?,
+            );
+        }
+
         self.common.epoll_threads = Some(epoll_threads);
 
         event!("virtio-device", "activated", "id", &self.id);
@@ -829,6 +1353,8 @@ impl VirtioDevice for Net {
 
     fn reset(&mut self) -> Option<Arc<dyn VirtioInterrupt>> {
         let result = self.common.reset();
+        // Closing the vhost-net instances stops them.
+        self.vhost_net_queue_pairs.clear();
         event!("virtio-device", "reset", "id", &self.id);
         result
     }
@@ -859,14 +1385,44 @@ impl VirtioDevice for Net {
     fn set_access_platform(&mut self, access_platform: Arc<dyn AccessPlatform>) {
         self.common.set_access_platform(access_platform)
     }
+
+    fn set_thread_landlock(&mut self, landlock: ThreadLandlock) {
+        self.common.landlock = Some(landlock);
+    }
+
+    fn add_memory_region(
+        &mut self,
+        _region: &Arc<GuestRegionMmap>,
+    ) -> result::Result<(), DeviceError> {
+        for queue_pair in self.vhost_net_queue_pairs.iter() {
+            queue_pair
+                .update_mem_table()
+                .map_err(|e| DeviceError::IoError(io::Error::other(e)))?;
+        }
+
+        Ok(())
+    }
 }
 
 impl Pausable for Net {
     fn pause(&mut self) -> result::Result<(), MigratableError> {
-        self.common.pause()
+        self.common.pause()?;
+
+        for queue_pair in self.vhost_net_queue_pairs.iter() {
+            queue_pair
+                .stop()
+                .map_err(|e| MigratableError::Pause(e.into()))?;
+        }
+        Ok(())
     }
 
     fn resume(&mut self) -> result::Result<(), MigratableError> {
+        for queue_pair in self.vhost_net_queue_pairs.iter() {
+            queue_pair
+                .start()
+                .map_err(|e| MigratableError::Resume(e.into()))?;
+        }
+
         self.common.resume()?;
 
         if let Some(ctrl_queue_epoll_thread) = &self.ctrl_queue_epoll_thread {
@@ -882,6 +1438,14 @@ impl Snapshottable for Net {
     }
 
     fn snapshot(&mut self) -> std::result::Result<Snapshot, MigratableError> {
+        // The queues are moved forward by the kernel behind the back of the
+        // transport, which would save stale indexes.
+        if !self.vhost_net_queue_pairs.is_empty() {
+            return Err(MigratableError::Snapshot(anyhow!(
+                "Can't snapshot a virtio-net device serviced by vhost-net"
+            )));
+        }
+
         Snapshot::new_from_state(&self.state())
     }
 }
@@ -32,7 +32,7 @@ use super::{
     EPOLL_HELPER_EVENT_LAST, VIRTIO_F_IOMMU_PLATFORM, VIRTIO_F_VERSION_1,
 };
 use crate::seccomp_filters::Thread;
-use crate::thread_helper::spawn_virtio_thread;
+use crate::thread_helper::{spawn_virtio_thread, ThreadLandlock};
 use crate::{GuestMemoryMmap, MmapRegion, VirtioInterrupt, VirtioInterruptType};
 
 const QUEUE_SIZE: u16 = 256;
@@ -153,6 +153,7 @@ struct PmemEpollHandler {
     mem: GuestMemoryAtomic<GuestMemoryMmap>,
     queue: Queue,
     disk: File,
+    synchronous: bool,
     interrupt_cb: Arc<dyn VirtioInterrupt>,
     queue_evt: EventFd,
     kill_evt: EventFd,
@@ -166,7 +167,13 @@ impl PmemEpollHandler {
         while let Some(mut desc_chain) = self.queue.pop_descriptor_chain(self.mem.memory()) {
             let len = match Request::parse(&mut desc_chain, self.access_platform.as_ref()) {
                 Ok(ref req) if (req.type_ == RequestType::Flush) => {
-                    let status_code = match self.disk.sync_all() {
+                    // Nothing is left to flush from a synchronous mapping.
+                    let result = if self.synchronous {
+                        Ok(())
+                    } else {
+                        self.disk.sync_all()
+                    };
+                    let status_code = match result {
                         Ok(()) => VIRTIO_PMEM_RESP_TYPE_OK,
                         Err(e) => {
                             error!("failed flushing disk image: {}", e);
@@ -266,6 +273,7 @@ pub struct Pmem {
     common: VirtioCommon,
     id: String,
     disk: Option<File>,
+    synchronous: bool,
     config: VirtioPmemConfig,
     mapping: UserspaceMapping,
     seccomp_action: SeccompAction,
@@ -331,6 +339,7 @@ impl Pmem {
             },
             id,
             disk: Some(disk),
+            synchronous: false,
             config,
             mapping,
             seccomp_action,
@@ -339,6 +348,12 @@ impl Pmem {
         })
     }
 
+    /// Tell the memory is mapped with MAP_SYNC, e.g. from a device DAX,
+    /// the guest writes being persistent without flushing the file.
+    pub fn set_synchronous(&mut self, synchronous: bool) {
+        self.synchronous = synchronous;
+    }
+
     fn state(&self) -> PmemState {
         PmemState {
             avail_features: self.common.avail_features,
@@ -404,6 +419,7 @@ impl VirtioDevice for Pmem {
                 mem,
                 queue,
                 disk,
+                synchronous: self.synchronous,
                 interrupt_cb,
                 queue_evt,
                 kill_evt,
@@ -418,6 +434,7 @@ impl VirtioDevice for Pmem {
             spawn_virtio_thread(
                 &self.id,
                 &self.seccomp_action,
+                self.common.landlock.as_ref(),
                 Thread::VirtioPmem,
                 &mut epoll_threads,
                 &self.exit_evt,
@@ -445,6 +462,10 @@ impl VirtioDevice for Pmem {
     fn set_access_platform(&mut self, access_platform: Arc<dyn AccessPlatform>) {
         self.common.set_access_platform(access_platform)
     }
+
+    fn set_thread_landlock(&mut self, landlock: ThreadLandlock) {
+        self.common.landlock = Some(landlock);
+    }
 }
 
 impl Pausable for Pmem {
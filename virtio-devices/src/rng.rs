@@ -15,7 +15,7 @@ use seccompiler::SeccompAction;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use virtio_queue::{Queue, QueueT};
-use vm_memory::{GuestAddressSpace, GuestMemory, GuestMemoryAtomic};
+use vm_memory::{Bytes, GuestAddressSpace, GuestMemory, GuestMemoryAtomic};
 use vm_migration::{Migratable, MigratableError, Pausable, Snapshot, Snapshottable, Transportable};
 use vm_virtio::{AccessPlatform, Translatable};
 use vmm_sys_util::eventfd::EventFd;
@@ -26,7 +26,7 @@ use super::{
     VIRTIO_F_IOMMU_PLATFORM, VIRTIO_F_VERSION_1,
 };
 use crate::seccomp_filters::Thread;
-use crate::thread_helper::spawn_virtio_thread;
+use crate::thread_helper::{spawn_virtio_thread, ThreadLandlock};
 use crate::{GuestMemoryMmap, VirtioInterrupt, VirtioInterruptType};
 
 const QUEUE_SIZE: u16 = 256;
@@ -43,11 +43,14 @@ enum Error {
     InvalidDescriptor,
     #[error("Failed to write to guest memory")]
     GuestMemoryWrite(#[source] vm_memory::guest_memory::Error),
+    #[error("Failed to read from guest memory")]
+    GuestMemoryRead(#[source] vm_memory::guest_memory::Error),
     #[error("Failed adding used index")]
     QueueAddUsed(#[source] virtio_queue::Error),
 }
 
 struct RngEpollHandler {
+    id: String,
     mem: GuestMemoryAtomic<GuestMemoryMmap>,
     queue: Queue,
     random_file: File,
@@ -71,16 +74,34 @@ impl RngEpollHandler {
                 return Err(Error::InvalidDescriptor);
             }
 
-            // Fill the read with data from the random device on the host.
-            let len = desc_chain
-                .memory()
-                .read_volatile_from(
-                    desc.addr()
-                        .translate_gva(self.access_platform.as_ref(), desc.len() as usize),
-                    &mut self.random_file,
-                    desc.len() as usize,
-                )
-                .map_err(Error::GuestMemoryWrite)?;
+            let addr = desc
+                .addr()
+                .translate_gva(self.access_platform.as_ref(), desc.len() as usize);
+
+            // Fill the read with data from the random device on the host, or
+            // with the recorded data when replaying.
+            let len = if let Some(data) = replay::replay(&self.id) {
+                let len = data.len().min(desc.len() as usize);
+                desc_chain
+                    .memory()
+                    .write_slice(&data[..len], addr)
+                    .map_err(Error::GuestMemoryWrite)?;
+                len
+            } else {
+                let len = desc_chain
+                    .memory()
+                    .read_volatile_from(addr, &mut self.random_file, desc.len() as usize)
+                    .map_err(Error::GuestMemoryWrite)?;
+                if replay::is_recording() {
+                    let mut data = vec![0u8; len];
+                    desc_chain
+                        .memory()
+                        .read_slice(&mut data, addr)
+                        .map_err(Error::GuestMemoryRead)?;
+                    replay::record(&self.id, &data);
+                }
+                len
+            };
 
             queue
                 .add_used(desc_chain.memory(), desc_chain.head_index(), len as u32)
@@ -264,6 +285,7 @@ impl VirtioDevice for Rng {
             let (_, queue, queue_evt) = queues.remove(0);
 
             let mut handler = RngEpollHandler {
+                id: self.id.clone(),
                 mem,
                 queue,
                 random_file,
@@ -280,6 +302,7 @@ impl VirtioDevice for Rng {
             spawn_virtio_thread(
                 &self.id,
                 &self.seccomp_action,
+                self.common.landlock.as_ref(),
                 Thread::VirtioRng,
                 &mut epoll_threads,
                 &self.exit_evt,
@@ -303,6 +326,10 @@ impl VirtioDevice for Rng {
     fn set_access_platform(&mut self, access_platform: Arc<dyn AccessPlatform>) {
         self.common.set_access_platform(access_platform)
     }
+
+    fn set_thread_landlock(&mut self, landlock: ThreadLandlock) {
+        self.common.landlock = Some(landlock);
+    }
 }
 
 impl Pausable for Rng {
@@ -0,0 +1,1127 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Virtio SCMI device
+//!
+//! Implements a small subset of the Arm System Control and Management
+//! Interface (SCMI) over virtio so that guests can discover host provided
+//! sensors, power domains and clocks. Only the base, power domain, clock and
+//! sensor protocols are exposed, and only the commands required by the Linux
+//! `scmi-virtio` transport are handled. Sensors are backed by host files
+//! (typically hwmon sysfs attributes) which are read on every request, while
+//! clocks run at fixed rates.
+
+use std::fs::File;
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Barrier, Mutex};
+use std::{io, result};
+
+use anyhow::anyhow;
+use seccompiler::SeccompAction;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use virtio_queue::{Queue, QueueT};
+use vm_memory::{Bytes, GuestAddressSpace, GuestMemoryAtomic};
+use vm_migration::{Migratable, MigratableError, Pausable, Snapshot, Snapshottable, Transportable};
+use vm_virtio::{AccessPlatform, Translatable};
+use vmm_sys_util::eventfd::EventFd;
+
+use super::{
+    ActivateError, ActivateResult, EpollHelper, EpollHelperError, EpollHelperHandler,
+    Error as DeviceError, VirtioCommon, VirtioDevice, VirtioDeviceType, EPOLL_HELPER_EVENT_LAST,
+    VIRTIO_F_IOMMU_PLATFORM, VIRTIO_F_VERSION_1,
+};
+use crate::seccomp_filters::Thread;
+use crate::thread_helper::spawn_virtio_thread;
+use crate::{GuestMemoryMmap, VirtioInterrupt, VirtioInterruptType};
+
+const QUEUE_SIZE: u16 = 64;
+// One command queue and one event queue. The event queue is never used
+// since no notifications are generated, but the driver expects it.
+const QUEUE_SIZES: &[u16] = &[QUEUE_SIZE, QUEUE_SIZE];
+
+// New descriptors are pending on the command queue.
+const CMD_QUEUE_EVENT: u16 = EPOLL_HELPER_EVENT_LAST + 1;
+
+// Largest request accepted from the guest.
+const MAX_REQUEST_SIZE: usize = 256;
+
+// Largest payload following the status of a response, as expected by the
+// Linux `scmi-virtio` transport. Replies listing several items are split
+// so that each one fits.
+const SCMI_MAX_MSG_SIZE: usize = 128;
+
+// Size of the header and status preceding the payload of a response.
+const SCMI_RESPONSE_HEADER_SIZE: usize = 8;
+
+// Protocol identifiers.
+const SCMI_PROTOCOL_BASE: u8 = 0x10;
+const SCMI_PROTOCOL_POWER: u8 = 0x11;
+const SCMI_PROTOCOL_CLOCK: u8 = 0x14;
+const SCMI_PROTOCOL_SENSOR: u8 = 0x15;
+
+// Commands common to every protocol.
+const SCMI_PROTOCOL_VERSION: u8 = 0x0;
+const SCMI_PROTOCOL_ATTRIBUTES: u8 = 0x1;
+const SCMI_PROTOCOL_MESSAGE_ATTRIBUTES: u8 = 0x2;
+
+// Base protocol commands.
+const SCMI_BASE_DISCOVER_VENDOR: u8 = 0x3;
+const SCMI_BASE_DISCOVER_SUB_VENDOR: u8 = 0x4;
+const SCMI_BASE_DISCOVER_IMPLEMENTATION_VERSION: u8 = 0x5;
+const SCMI_BASE_DISCOVER_LIST_PROTOCOLS: u8 = 0x6;
+
+// Power domain protocol commands.
+const SCMI_POWER_DOMAIN_ATTRIBUTES: u8 = 0x3;
+const SCMI_POWER_STATE_SET: u8 = 0x4;
+const SCMI_POWER_STATE_GET: u8 = 0x5;
+
+// Clock protocol commands.
+const SCMI_CLOCK_ATTRIBUTES: u8 = 0x3;
+const SCMI_CLOCK_DESCRIBE_RATES: u8 = 0x4;
+const SCMI_CLOCK_RATE_SET: u8 = 0x5;
+const SCMI_CLOCK_RATE_GET: u8 = 0x6;
+const SCMI_CLOCK_CONFIG_SET: u8 = 0x7;
+
+// Sensor protocol commands.
+const SCMI_SENSOR_DESCRIPTION_GET: u8 = 0x3;
+const SCMI_SENSOR_READING_GET: u8 = 0x6;
+
+// Protocol versions advertised to the guest.
+const SCMI_BASE_VERSION: u32 = 0x2_0000;
+const SCMI_POWER_VERSION: u32 = 0x2_0000;
+const SCMI_CLOCK_VERSION: u32 = 0x1_0000;
+const SCMI_SENSOR_VERSION: u32 = 0x1_0000;
+
+// Status codes.
+const SCMI_SUCCESS: i32 = 0;
+const SCMI_NOT_SUPPORTED: i32 = -1;
+const SCMI_INVALID_PARAMETERS: i32 = -2;
+const SCMI_NOT_FOUND: i32 = -4;
+
+// Power states.
+const SCMI_POWER_STATE_ON: u32 = 0;
+const SCMI_POWER_STATE_OFF: u32 = 1 << 30;
+
+// Clock attributes.
+const SCMI_CLOCK_ENABLED: u32 = 1 << 0;
+
+const SCMI_NAME_LEN: usize = 16;
+
+// Size of a sensor descriptor, as defined by version 1 of the protocol.
+const SCMI_SENSOR_DESC_SIZE: usize = 4 + 4 + 4 + SCMI_NAME_LEN;
+
+#[derive(Error, Debug)]
+enum Error {
+    #[error("Descriptor chain too short")]
+    DescriptorChainTooShort,
+    #[error("Invalid descriptor")]
+    InvalidDescriptor,
+    #[error("Failed to read from guest memory")]
+    GuestMemoryRead(#[source] vm_memory::guest_memory::Error),
+    #[error("Failed to write to guest memory")]
+    GuestMemoryWrite(#[source] vm_memory::guest_memory::Error),
+    #[error("Failed adding used index")]
+    QueueAddUsed(#[source] virtio_queue::Error),
+}
+
+/// Kind of value reported by a host sensor, derived from the hwmon
+/// attribute name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SensorKind {
+    Temperature,
+    Voltage,
+    Current,
+    Power,
+    Fan,
+    Unspecified,
+}
+
+impl SensorKind {
+    fn from_path(path: &Path) -> Self {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        if name.starts_with("temp") {
+            SensorKind::Temperature
+        } else if name.starts_with("in") {
+            SensorKind::Voltage
+        } else if name.starts_with("curr") {
+            SensorKind::Current
+        } else if name.starts_with("power") {
+            SensorKind::Power
+        } else if name.starts_with("fan") {
+            SensorKind::Fan
+        } else {
+            SensorKind::Unspecified
+        }
+    }
+
+    // Returns the SCMI sensor type and the unit multiplier (power of ten)
+    // matching the hwmon sysfs units.
+    fn scmi_type(&self) -> (u32, i32) {
+        match self {
+            SensorKind::Temperature => (2, -3),
+            SensorKind::Voltage => (5, -3),
+            SensorKind::Current => (6, -3),
+            SensorKind::Power => (7, -6),
+            SensorKind::Fan => (65, 0),
+            SensorKind::Unspecified => (1, 0),
+        }
+    }
+}
+
+struct Sensor {
+    name: String,
+    kind: SensorKind,
+    file: File,
+}
+
+impl Sensor {
+    fn open(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // hwmon attributes are named like "temp1_input", expose "temp1".
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().trim_end_matches("_input").to_string())
+            .unwrap_or_default();
+
+        Ok(Sensor {
+            name,
+            kind: SensorKind::from_path(path),
+            file,
+        })
+    }
+
+    fn try_clone(&self) -> io::Result<Self> {
+        Ok(Sensor {
+            name: self.name.clone(),
+            kind: self.kind,
+            file: self.file.try_clone()?,
+        })
+    }
+
+    fn read(&self) -> io::Result<i64> {
+        let mut buf = [0u8; 32];
+        let len = self.file.read_at(&mut buf, 0)?;
+        std::str::from_utf8(&buf[..len])
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+            .trim()
+            .parse::<i64>()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+fn scmi_name(name: &str) -> [u8; SCMI_NAME_LEN] {
+    let mut out = [0u8; SCMI_NAME_LEN];
+    let bytes = name.as_bytes();
+    // Keep the last byte as the NUL terminator.
+    let len = std::cmp::min(bytes.len(), SCMI_NAME_LEN - 1);
+    out[..len].copy_from_slice(&bytes[..len]);
+    out
+}
+
+struct ScmiResponse(Vec<u8>);
+
+impl ScmiResponse {
+    fn new(header: u32, status: i32) -> Self {
+        let mut r = ScmiResponse(Vec::new());
+        r.push(header);
+        r.push(status as u32);
+        r
+    }
+
+    fn push(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn push_bytes(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+}
+
+/// Resources exposed to the guest, along with the state the guest put them
+/// in, and the SCMI protocols giving access to them.
+struct ScmiAgent {
+    sensors: Vec<Sensor>,
+    power_domains: Arc<Mutex<Vec<u32>>>,
+    clock_rates: Vec<u64>,
+    clocks_enabled: Arc<Mutex<Vec<bool>>>,
+}
+
+impl ScmiAgent {
+    fn param(params: &[u8], index: usize) -> Option<u32> {
+        params
+            .get(index * 4..index * 4 + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn handle_base(&self, header: u32, msg_id: u8, params: &[u8]) -> ScmiResponse {
+        let mut r = ScmiResponse::new(header, SCMI_SUCCESS);
+        match msg_id {
+            SCMI_PROTOCOL_VERSION => r.push(SCMI_BASE_VERSION),
+            SCMI_PROTOCOL_ATTRIBUTES => {
+                // Number of agents in bits 15:8, number of protocols in 7:0.
+                r.push(3);
+            }
+            SCMI_PROTOCOL_MESSAGE_ATTRIBUTES => match Self::param(params, 0) {
+                Some(id) if id <= u32::from(SCMI_BASE_DISCOVER_LIST_PROTOCOLS) => r.push(0),
+                _ => r = ScmiResponse::new(header, SCMI_NOT_FOUND),
+            },
+            SCMI_BASE_DISCOVER_VENDOR => r.push_bytes(&scmi_name("CloudHypervisor")),
+            SCMI_BASE_DISCOVER_SUB_VENDOR => r.push_bytes(&scmi_name("")),
+            SCMI_BASE_DISCOVER_IMPLEMENTATION_VERSION => r.push(1),
+            SCMI_BASE_DISCOVER_LIST_PROTOCOLS => {
+                let skip = Self::param(params, 0).unwrap_or(0);
+                let protocols: Vec<u8> = [
+                    SCMI_PROTOCOL_POWER,
+                    SCMI_PROTOCOL_CLOCK,
+                    SCMI_PROTOCOL_SENSOR,
+                ]
+                .into_iter()
+                .skip(skip as usize)
+                .collect();
+                r.push(protocols.len() as u32);
+                let mut packed = protocols.clone();
+                packed.resize(protocols.len().div_ceil(4) * 4, 0);
+                r.push_bytes(&packed);
+            }
+            _ => r = ScmiResponse::new(header, SCMI_NOT_SUPPORTED),
+        }
+        r
+    }
+
+    fn handle_power(&self, header: u32, msg_id: u8, params: &[u8]) -> ScmiResponse {
+        let mut states = self.power_domains.lock().unwrap();
+        let mut r = ScmiResponse::new(header, SCMI_SUCCESS);
+        match msg_id {
+            SCMI_PROTOCOL_VERSION => r.push(SCMI_POWER_VERSION),
+            SCMI_PROTOCOL_ATTRIBUTES => {
+                r.push(states.len() as u32);
+                // No shared memory statistics region.
+                r.push(0);
+                r.push(0);
+                r.push(0);
+            }
+            SCMI_PROTOCOL_MESSAGE_ATTRIBUTES => match Self::param(params, 0) {
+                Some(id) if id <= u32::from(SCMI_POWER_STATE_GET) => r.push(0),
+                _ => r = ScmiResponse::new(header, SCMI_NOT_FOUND),
+            },
+            SCMI_POWER_DOMAIN_ATTRIBUTES => match Self::param(params, 0) {
+                Some(domain) if (domain as usize) < states.len() => {
+                    // Synchronous state changes are supported.
+                    r.push(1 << 30);
+                    r.push_bytes(&scmi_name(&format!("pd{domain}")));
+                }
+                Some(_) => r = ScmiResponse::new(header, SCMI_NOT_FOUND),
+                None => r = ScmiResponse::new(header, SCMI_INVALID_PARAMETERS),
+            },
+            SCMI_POWER_STATE_SET => match (Self::param(params, 1), Self::param(params, 2)) {
+                (Some(domain), Some(state)) if (domain as usize) < states.len() => {
+                    if state != SCMI_POWER_STATE_ON && state != SCMI_POWER_STATE_OFF {
+                        r = ScmiResponse::new(header, SCMI_INVALID_PARAMETERS);
+                    } else {
+                        states[domain as usize] = state;
+                    }
+                }
+                (Some(_), Some(_)) => r = ScmiResponse::new(header, SCMI_NOT_FOUND),
+                _ => r = ScmiResponse::new(header, SCMI_INVALID_PARAMETERS),
+            },
+            SCMI_POWER_STATE_GET => match Self::param(params, 0) {
+                Some(domain) if (domain as usize) < states.len() => r.push(states[domain as usize]),
+                Some(_) => r = ScmiResponse::new(header, SCMI_NOT_FOUND),
+                None => r = ScmiResponse::new(header, SCMI_INVALID_PARAMETERS),
+            },
+            _ => r = ScmiResponse::new(header, SCMI_NOT_SUPPORTED),
+        }
+        r
+    }
+
+    fn handle_clock(&self, header: u32, msg_id: u8, params: &[u8]) -> ScmiResponse {
+        let mut enabled = self.clocks_enabled.lock().unwrap();
+        let mut r = ScmiResponse::new(header, SCMI_SUCCESS);
+        match msg_id {
+            SCMI_PROTOCOL_VERSION => r.push(SCMI_CLOCK_VERSION),
+            SCMI_PROTOCOL_ATTRIBUTES => {
+                // Number of clocks in bits 15:0, no asynchronous rate changes.
+                r.push(self.clock_rates.len() as u32);
+            }
+            SCMI_PROTOCOL_MESSAGE_ATTRIBUTES => match Self::param(params, 0) {
+                Some(id) if id <= u32::from(SCMI_CLOCK_CONFIG_SET) => r.push(0),
+                _ => r = ScmiResponse::new(header, SCMI_NOT_FOUND),
+            },
+            SCMI_CLOCK_ATTRIBUTES => match Self::param(params, 0) {
+                Some(clock) if (clock as usize) < self.clock_rates.len() => {
+                    r.push(if enabled[clock as usize] {
+                        SCMI_CLOCK_ENABLED
+                    } else {
+                        0
+                    });
+                    r.push_bytes(&scmi_name(&format!("clk{clock}")));
+                }
+                Some(_) => r = ScmiResponse::new(header, SCMI_NOT_FOUND),
+                None => r = ScmiResponse::new(header, SCMI_INVALID_PARAMETERS),
+            },
+            SCMI_CLOCK_DESCRIBE_RATES => match (Self::param(params, 0), Self::param(params, 1)) {
+                // Each clock has a single rate, described as a discrete list.
+                (Some(clock), Some(0)) if (clock as usize) < self.clock_rates.len() => {
+                    let rate = self.clock_rates[clock as usize];
+                    r.push(1);
+                    r.push(rate as u32);
+                    r.push((rate >> 32) as u32);
+                }
+                (Some(clock), Some(_)) if (clock as usize) < self.clock_rates.len() => {
+                    r = ScmiResponse::new(header, SCMI_INVALID_PARAMETERS)
+                }
+                (Some(_), Some(_)) => r = ScmiResponse::new(header, SCMI_NOT_FOUND),
+                _ => r = ScmiResponse::new(header, SCMI_INVALID_PARAMETERS),
+            },
+            SCMI_CLOCK_RATE_SET => match (
+                Self::param(params, 1),
+                Self::param(params, 2),
+                Self::param(params, 3),
+            ) {
+                (Some(clock), Some(low), Some(high))
+                    if (clock as usize) < self.clock_rates.len() =>
+                {
+                    // Rates are fixed, only the current one can be set.
+                    if ((u64::from(high) << 32) | u64::from(low))
+                        != self.clock_rates[clock as usize]
+                    {
+                        r = ScmiResponse::new(header, SCMI_INVALID_PARAMETERS);
+                    }
+                }
+                (Some(_), Some(_), Some(_)) => r = ScmiResponse::new(header, SCMI_NOT_FOUND),
+                _ => r = ScmiResponse::new(header, SCMI_INVALID_PARAMETERS),
+            },
+            SCMI_CLOCK_RATE_GET => match Self::param(params, 0) {
+                Some(clock) if (clock as usize) < self.clock_rates.len() => {
+                    let rate = self.clock_rates[clock as usize];
+                    r.push(rate as u32);
+                    r.push((rate >> 32) as u32);
+                }
+                Some(_) => r = ScmiResponse::new(header, SCMI_NOT_FOUND),
+                None => r = ScmiResponse::new(header, SCMI_INVALID_PARAMETERS),
+            },
+            SCMI_CLOCK_CONFIG_SET => match (Self::param(params, 0), Self::param(params, 1)) {
+                (Some(clock), Some(attributes)) if (clock as usize) < enabled.len() => {
+                    enabled[clock as usize] = attributes & SCMI_CLOCK_ENABLED != 0;
+                }
+                (Some(_), Some(_)) => r = ScmiResponse::new(header, SCMI_NOT_FOUND),
+                _ => r = ScmiResponse::new(header, SCMI_INVALID_PARAMETERS),
+            },
+            _ => r = ScmiResponse::new(header, SCMI_NOT_SUPPORTED),
+        }
+        r
+    }
+
+    fn handle_sensor(
+        &self,
+        header: u32,
+        msg_id: u8,
+        params: &[u8],
+        max_payload: usize,
+    ) -> ScmiResponse {
+        let mut r = ScmiResponse::new(header, SCMI_SUCCESS);
+        match msg_id {
+            SCMI_PROTOCOL_VERSION => r.push(SCMI_SENSOR_VERSION),
+            SCMI_PROTOCOL_ATTRIBUTES => {
+                // Number of sensors, no asynchronous reads.
+                r.push(self.sensors.len() as u32);
+                r.push(0);
+                r.push(0);
+                r.push(0);
+            }
+            SCMI_PROTOCOL_MESSAGE_ATTRIBUTES => match Self::param(params, 0) {
+                Some(id)
+                    if id <= u32::from(SCMI_SENSOR_DESCRIPTION_GET)
+                        || id == u32::from(SCMI_SENSOR_READING_GET) =>
+                {
+                    r.push(0)
+                }
+                _ => r = ScmiResponse::new(header, SCMI_NOT_FOUND),
+            },
+            SCMI_SENSOR_DESCRIPTION_GET => {
+                let first = Self::param(params, 0).unwrap_or(0) as usize;
+                if first > self.sensors.len() {
+                    return ScmiResponse::new(header, SCMI_INVALID_PARAMETERS);
+                }
+                // Only return the descriptors fitting in the response, the
+                // driver asking for the remaining ones next.
+                let count = std::cmp::min(
+                    self.sensors.len() - first,
+                    max_payload.saturating_sub(4) / SCMI_SENSOR_DESC_SIZE,
+                );
+                let remaining = self.sensors.len() - first - count;
+                // Number of descriptors returned in bits 11:0, number of
+                // remaining ones in bits 31:16.
+                r.push(((remaining as u32) << 16) | count as u32);
+                for (i, sensor) in self.sensors[first..first + count].iter().enumerate() {
+                    let (sensor_type, multiplier) = sensor.kind.scmi_type();
+                    r.push((first + i) as u32);
+                    r.push(0);
+                    r.push(((multiplier as u32 & 0x1f) << 11) | (sensor_type & 0xff));
+                    r.push_bytes(&scmi_name(&sensor.name));
+                }
+            }
+            SCMI_SENSOR_READING_GET => match Self::param(params, 0) {
+                Some(id) => match self.sensors.get(id as usize) {
+                    Some(sensor) => match sensor.read() {
+                        Ok(value) => {
+                            r.push(value as u32);
+                            r.push((value >> 32) as u32);
+                        }
+                        Err(e) => {
+                            warn!("Failed reading SCMI sensor {}: {}", sensor.name, e);
+                            r = ScmiResponse::new(header, SCMI_NOT_FOUND);
+                        }
+                    },
+                    None => r = ScmiResponse::new(header, SCMI_NOT_FOUND),
+                },
+                None => r = ScmiResponse::new(header, SCMI_INVALID_PARAMETERS),
+            },
+            _ => r = ScmiResponse::new(header, SCMI_NOT_SUPPORTED),
+        }
+        r
+    }
+
+    /// Handle `request`, the response being written to a buffer of
+    /// `response_len` bytes.
+    fn handle_request(&self, request: &[u8], response_len: usize) -> Option<ScmiResponse> {
+        let header = Self::param(request, 0)?;
+        let msg_id = (header & 0xff) as u8;
+        let protocol_id = ((header >> 10) & 0xff) as u8;
+        let params = &request[4..];
+        let max_payload = std::cmp::min(
+            response_len.saturating_sub(SCMI_RESPONSE_HEADER_SIZE),
+            SCMI_MAX_MSG_SIZE,
+        );
+
+        Some(match protocol_id {
+            SCMI_PROTOCOL_BASE => self.handle_base(header, msg_id, params),
+            SCMI_PROTOCOL_POWER => self.handle_power(header, msg_id, params),
+            SCMI_PROTOCOL_CLOCK => self.handle_clock(header, msg_id, params),
+            SCMI_PROTOCOL_SENSOR => self.handle_sensor(header, msg_id, params, max_payload),
+            _ => ScmiResponse::new(header, SCMI_NOT_SUPPORTED),
+        })
+    }
+}
+
+struct ScmiEpollHandler {
+    mem: GuestMemoryAtomic<GuestMemoryMmap>,
+    queue: Queue,
+    agent: ScmiAgent,
+    interrupt_cb: Arc<dyn VirtioInterrupt>,
+    queue_evt: EventFd,
+    kill_evt: EventFd,
+    pause_evt: EventFd,
+    access_platform: Option<Arc<dyn AccessPlatform>>,
+}
+
+impl ScmiEpollHandler {
+    fn process_queue(&mut self) -> result::Result<bool, Error> {
+        let mut used_descs = false;
+        while let Some(mut desc_chain) = self.queue.pop_descriptor_chain(self.mem.memory()) {
+            let req_desc = desc_chain.next().ok_or(Error::DescriptorChainTooShort)?;
+            let resp_desc = desc_chain.next().ok_or(Error::DescriptorChainTooShort)?;
+
+            if req_desc.is_write_only() || !resp_desc.is_write_only() {
+                return Err(Error::InvalidDescriptor);
+            }
+
+            let req_len = std::cmp::min(req_desc.len() as usize, MAX_REQUEST_SIZE);
+            let mut request = vec![0u8; req_len];
+            desc_chain
+                .memory()
+                .read_slice(
+                    &mut request,
+                    req_desc
+                        .addr()
+                        .translate_gva(self.access_platform.as_ref(), req_len),
+                )
+                .map_err(Error::GuestMemoryRead)?;
+
+            let mut len = 0;
+            if let Some(response) = self
+                .agent
+                .handle_request(&request, resp_desc.len() as usize)
+            {
+                let resp_len = std::cmp::min(response.0.len(), resp_desc.len() as usize);
+                desc_chain
+                    .memory()
+                    .write_slice(
+                        &response.0[..resp_len],
+                        resp_desc
+                            .addr()
+                            .translate_gva(self.access_platform.as_ref(), resp_len),
+                    )
+                    .map_err(Error::GuestMemoryWrite)?;
+                len = resp_len as u32;
+            }
+
+            self.queue
+                .add_used(desc_chain.memory(), desc_chain.head_index(), len)
+                .map_err(Error::QueueAddUsed)?;
+            used_descs = true;
+        }
+
+        Ok(used_descs)
+    }
+
+    fn signal_used_queue(&self) -> result::Result<(), DeviceError> {
+        self.interrupt_cb
+            .trigger(VirtioInterruptType::Queue(0))
+            .map_err(|e| {
+                error!("Failed to signal used queue: {:?}", e);
+                DeviceError::FailedSignalingUsedQueue(e)
+            })
+    }
+
+    fn run(
+        &mut self,
+        paused: Arc<AtomicBool>,
+        paused_sync: Arc<Barrier>,
+    ) -> result::Result<(), EpollHelperError> {
+        let mut helper = EpollHelper::new(&self.kill_evt, &self.pause_evt)?;
+        helper.add_event(self.queue_evt.as_raw_fd(), CMD_QUEUE_EVENT)?;
+        helper.run(paused, paused_sync, self)?;
+
+        Ok(())
+    }
+}
+
+impl EpollHelperHandler for ScmiEpollHandler {
+    fn handle_event(
+        &mut self,
+        _helper: &mut EpollHelper,
+        event: &epoll::Event,
+    ) -> result::Result<(), EpollHelperError> {
+        let ev_type = event.data as u16;
+        match ev_type {
+            CMD_QUEUE_EVENT => {
+                self.queue_evt.read().map_err(|e| {
+                    EpollHelperError::HandleEvent(anyhow!("Failed to get queue event: {:?}", e))
+                })?;
+                let needs_notification = self.process_queue().map_err(|e| {
+                    EpollHelperError::HandleEvent(anyhow!("Failed to process queue : {:?}", e))
+                })?;
+                if needs_notification {
+                    self.signal_used_queue().map_err(|e| {
+                        EpollHelperError::HandleEvent(anyhow!(
+                            "Failed to signal used queue: {:?}",
+                            e
+                        ))
+                    })?;
+                }
+            }
+            _ => {
+                return Err(EpollHelperError::HandleEvent(anyhow!(
+                    "Unexpected event: {}",
+                    ev_type
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Virtio device exposing host sensors, power domains and clocks through
+/// SCMI.
+pub struct Scmi {
+    common: VirtioCommon,
+    id: String,
+    sensors: Vec<Sensor>,
+    power_domains: Arc<Mutex<Vec<u32>>>,
+    clock_rates: Vec<u64>,
+    clocks_enabled: Arc<Mutex<Vec<bool>>>,
+    seccomp_action: SeccompAction,
+    exit_evt: EventFd,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ScmiState {
+    pub avail_features: u64,
+    pub acked_features: u64,
+    pub power_domains: Vec<u32>,
+    #[serde(default)]
+    pub clocks_enabled: Vec<bool>,
+}
+
+impl Scmi {
+    /// Create a new virtio SCMI device.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        id: String,
+        sensors: &[PathBuf],
+        power_domains: u32,
+        clock_rates: &[u64],
+        iommu: bool,
+        seccomp_action: SeccompAction,
+        exit_evt: EventFd,
+        state: Option<ScmiState>,
+    ) -> io::Result<Scmi> {
+        let sensors = sensors
+            .iter()
+            .map(|p| Sensor::open(p))
+            .collect::<io::Result<Vec<Sensor>>>()?;
+
+        let (avail_features, acked_features, domains, mut clocks_enabled, paused) =
+            if let Some(state) = state {
+                info!("Restoring virtio-scmi {}", id);
+                (
+                    state.avail_features,
+                    state.acked_features,
+                    state.power_domains,
+                    state.clocks_enabled,
+                    true,
+                )
+            } else {
+                let mut avail_features = 1u64 << VIRTIO_F_VERSION_1;
+
+                if iommu {
+                    avail_features |= 1u64 << VIRTIO_F_IOMMU_PLATFORM;
+                }
+
+                (
+                    avail_features,
+                    0,
+                    vec![SCMI_POWER_STATE_ON; power_domains as usize],
+                    Vec::new(),
+                    false,
+                )
+            };
+        // Clocks are disabled until the guest enables them.
+        clocks_enabled.resize(clock_rates.len(), false);
+
+        Ok(Scmi {
+            common: VirtioCommon {
+                device_type: VirtioDeviceType::Scmi as u32,
+                queue_sizes: QUEUE_SIZES.to_vec(),
+                paused_sync: Some(Arc::new(Barrier::new(2))),
+                avail_features,
+                acked_features,
+                min_queues: 1,
+                paused: Arc::new(AtomicBool::new(paused)),
+                ..Default::default()
+            },
+            id,
+            sensors,
+            power_domains: Arc::new(Mutex::new(domains)),
+            clock_rates: clock_rates.to_vec(),
+            clocks_enabled: Arc::new(Mutex::new(clocks_enabled)),
+            seccomp_action,
+            exit_evt,
+        })
+    }
+
+    fn state(&self) -> ScmiState {
+        ScmiState {
+            avail_features: self.common.avail_features,
+            acked_features: self.common.acked_features,
+            power_domains: self.power_domains.lock().unwrap().clone(),
+            clocks_enabled: self.clocks_enabled.lock().unwrap().clone(),
+        }
+    }
+
+    #[cfg(fuzzing)]
+    pub fn wait_for_epoll_threads(&mut self) {
+        self.common.wait_for_epoll_threads();
+    }
+}
+
+impl Drop for Scmi {
+    fn drop(&mut self) {
+        if let Some(kill_evt) = self.common.kill_evt.take() {
+            // Ignore the result because there is nothing we can do about it.
+            let _ = kill_evt.write(1);
+        }
+        self.common.wait_for_epoll_threads();
+    }
+}
+
+impl VirtioDevice for Scmi {
+    fn device_type(&self) -> u32 {
+        self.common.device_type
+    }
+
+    fn queue_max_sizes(&self) -> &[u16] {
+        &self.common.queue_sizes
+    }
+
+    fn features(&self) -> u64 {
+        self.common.avail_features
+    }
+
+    fn ack_features(&mut self, value: u64) {
+        self.common.ack_features(value)
+    }
+
+    fn activate(
+        &mut self,
+        mem: GuestMemoryAtomic<GuestMemoryMmap>,
+        interrupt_cb: Arc<dyn VirtioInterrupt>,
+        mut queues: Vec<(usize, Queue, EventFd)>,
+    ) -> ActivateResult {
+        self.common.activate(&queues, &interrupt_cb)?;
+        let (kill_evt, pause_evt) = self.common.dup_eventfds();
+
+        let sensors = self
+            .sensors
+            .iter()
+            .map(|s| s.try_clone())
+            .collect::<io::Result<Vec<Sensor>>>()
+            .map_err(|e| {
+                error!("failed cloning scmi sensor: {}", e);
+                ActivateError::BadActivate
+            })?;
+
+        let (_, queue, queue_evt) = queues.remove(0);
+
+        let mut handler = ScmiEpollHandler {
+            mem,
+            queue,
+            agent: ScmiAgent {
+                sensors,
+                power_domains: self.power_domains.clone(),
+                clock_rates: self.clock_rates.clone(),
+                clocks_enabled: self.clocks_enabled.clone(),
+            },
+            interrupt_cb,
+            queue_evt,
+            kill_evt,
+            pause_evt,
+            access_platform: self.common.access_platform.clone(),
+        };
+
+        let paused = self.common.paused.clone();
+        let paused_sync = self.common.paused_sync.clone();
+        let mut epoll_threads = Vec::new();
+        spawn_virtio_thread(
+            &self.id,
+            &self.seccomp_action,
+            self.common.landlock.as_ref(),
+            Thread::VirtioScmi,
+            &mut epoll_threads,
+            &self.exit_evt,
+            move || handler.run(paused, paused_sync.unwrap()),
+        )?;
+
+        self.common.epoll_threads = Some(epoll_threads);
+
+        event!("virtio-device", "activated", "id", &self.id);
+        Ok(())
+    }
+
+    fn reset(&mut self) -> Option<Arc<dyn VirtioInterrupt>> {
+        let result = self.common.reset();
+        event!("virtio-device", "reset", "id", &self.id);
+        result
+    }
+
+    fn set_access_platform(&mut self, access_platform: Arc<dyn AccessPlatform>) {
+        self.common.set_access_platform(access_platform)
+    }
+}
+
+impl Pausable for Scmi {
+    fn pause(&mut self) -> result::Result<(), MigratableError> {
+        self.common.pause()
+    }
+
+    fn resume(&mut self) -> result::Result<(), MigratableError> {
+        self.common.resume()
+    }
+}
+
+impl Snapshottable for Scmi {
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    fn snapshot(&mut self) -> std::result::Result<Snapshot, MigratableError> {
+        Snapshot::new_from_state(&self.state())
+    }
+}
+
+impl Transportable for Scmi {}
+impl Migratable for Scmi {}
+
+#[cfg(test)]
+mod tests {
+    use vmm_sys_util::tempfile::TempFile;
+
+    use super::*;
+
+    fn request(protocol_id: u8, msg_id: u8, params: &[u32]) -> Vec<u8> {
+        let header = (u32::from(protocol_id) << 10) | u32::from(msg_id);
+        std::iter::once(header)
+            .chain(params.iter().copied())
+            .flat_map(u32::to_le_bytes)
+            .collect()
+    }
+
+    fn handle(agent: &ScmiAgent, request: &[u8], response_len: usize) -> Vec<u32> {
+        agent
+            .handle_request(request, response_len)
+            .unwrap()
+            .0
+            .chunks(4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect()
+    }
+
+    fn agent(sensors: &[TempFile], power_domains: usize, clock_rates: &[u64]) -> ScmiAgent {
+        ScmiAgent {
+            sensors: sensors
+                .iter()
+                .map(|f| Sensor::open(f.as_path()).unwrap())
+                .collect(),
+            power_domains: Arc::new(Mutex::new(vec![SCMI_POWER_STATE_ON; power_domains])),
+            clock_rates: clock_rates.to_vec(),
+            clocks_enabled: Arc::new(Mutex::new(vec![false; clock_rates.len()])),
+        }
+    }
+
+    fn sensor_file(value: &str) -> TempFile {
+        let file = TempFile::new_with_prefix("/tmp/temp").unwrap();
+        file.as_file().write_all_at(value.as_bytes(), 0).unwrap();
+        file
+    }
+
+    #[test]
+    fn test_base_protocol() {
+        let agent = agent(&[], 1, &[]);
+
+        let r = handle(
+            &agent,
+            &request(SCMI_PROTOCOL_BASE, SCMI_PROTOCOL_ATTRIBUTES, &[]),
+            136,
+        );
+        assert_eq!(r[1..], [SCMI_SUCCESS as u32, 3]);
+
+        let r = handle(
+            &agent,
+            &request(SCMI_PROTOCOL_BASE, SCMI_BASE_DISCOVER_LIST_PROTOCOLS, &[0]),
+            136,
+        );
+        assert_eq!(r[2], 3);
+        assert_eq!(r[3].to_le_bytes(), [0x11, 0x14, 0x15, 0]);
+
+        let r = handle(
+            &agent,
+            &request(SCMI_PROTOCOL_BASE, SCMI_BASE_DISCOVER_LIST_PROTOCOLS, &[2]),
+            136,
+        );
+        assert_eq!(r[2], 1);
+        assert_eq!(r[3].to_le_bytes(), [0x15, 0, 0, 0]);
+
+        let r = handle(&agent, &request(0x13, SCMI_PROTOCOL_VERSION, &[]), 136);
+        assert_eq!(r[1], SCMI_NOT_SUPPORTED as u32);
+    }
+
+    #[test]
+    fn test_power_protocol() {
+        let agent = agent(&[], 2, &[]);
+
+        let r = handle(
+            &agent,
+            &request(
+                SCMI_PROTOCOL_POWER,
+                SCMI_POWER_STATE_SET,
+                &[0, 1, SCMI_POWER_STATE_OFF],
+            ),
+            136,
+        );
+        assert_eq!(r[1], SCMI_SUCCESS as u32);
+        let r = handle(
+            &agent,
+            &request(SCMI_PROTOCOL_POWER, SCMI_POWER_STATE_GET, &[1]),
+            136,
+        );
+        assert_eq!(r[1..], [SCMI_SUCCESS as u32, SCMI_POWER_STATE_OFF]);
+        let r = handle(
+            &agent,
+            &request(SCMI_PROTOCOL_POWER, SCMI_POWER_STATE_GET, &[0]),
+            136,
+        );
+        assert_eq!(r[1..], [SCMI_SUCCESS as u32, SCMI_POWER_STATE_ON]);
+
+        let r = handle(
+            &agent,
+            &request(SCMI_PROTOCOL_POWER, SCMI_POWER_STATE_SET, &[0, 1, 2]),
+            136,
+        );
+        assert_eq!(r[1], SCMI_INVALID_PARAMETERS as u32);
+        let r = handle(
+            &agent,
+            &request(SCMI_PROTOCOL_POWER, SCMI_POWER_STATE_GET, &[2]),
+            136,
+        );
+        assert_eq!(r[1], SCMI_NOT_FOUND as u32);
+    }
+
+    #[test]
+    fn test_clock_protocol() {
+        let agent = agent(&[], 0, &[24_000_000, 5_000_000_000]);
+
+        let r = handle(
+            &agent,
+            &request(SCMI_PROTOCOL_CLOCK, SCMI_PROTOCOL_ATTRIBUTES, &[]),
+            136,
+        );
+        assert_eq!(r[1..], [SCMI_SUCCESS as u32, 2]);
+
+        // Clocks are disabled until the guest enables them
+        let r = handle(
+            &agent,
+            &request(SCMI_PROTOCOL_CLOCK, SCMI_CLOCK_ATTRIBUTES, &[1]),
+            136,
+        );
+        assert_eq!(r[1..3], [SCMI_SUCCESS as u32, 0]);
+        let r = handle(
+            &agent,
+            &request(
+                SCMI_PROTOCOL_CLOCK,
+                SCMI_CLOCK_CONFIG_SET,
+                &[1, SCMI_CLOCK_ENABLED],
+            ),
+            136,
+        );
+        assert_eq!(r[1], SCMI_SUCCESS as u32);
+        let r = handle(
+            &agent,
+            &request(SCMI_PROTOCOL_CLOCK, SCMI_CLOCK_ATTRIBUTES, &[1]),
+            136,
+        );
+        assert_eq!(r[1..3], [SCMI_SUCCESS as u32, SCMI_CLOCK_ENABLED]);
+        assert_eq!(*agent.clocks_enabled.lock().unwrap(), [false, true]);
+
+        let r = handle(
+            &agent,
+            &request(SCMI_PROTOCOL_CLOCK, SCMI_CLOCK_DESCRIBE_RATES, &[1, 0]),
+            136,
+        );
+        assert_eq!(r[1..], [SCMI_SUCCESS as u32, 1, 0x2a05_f200, 0x1]);
+        let r = handle(
+            &agent,
+            &request(SCMI_PROTOCOL_CLOCK, SCMI_CLOCK_DESCRIBE_RATES, &[1, 1]),
+            136,
+        );
+        assert_eq!(r[1], SCMI_INVALID_PARAMETERS as u32);
+
+        let r = handle(
+            &agent,
+            &request(SCMI_PROTOCOL_CLOCK, SCMI_CLOCK_RATE_GET, &[0]),
+            136,
+        );
+        assert_eq!(r[1..], [SCMI_SUCCESS as u32, 24_000_000, 0]);
+
+        // Only the fixed rate of the clock can be set
+        let r = handle(
+            &agent,
+            &request(
+                SCMI_PROTOCOL_CLOCK,
+                SCMI_CLOCK_RATE_SET,
+                &[0, 0, 24_000_000, 0],
+            ),
+            136,
+        );
+        assert_eq!(r[1], SCMI_SUCCESS as u32);
+        let r = handle(
+            &agent,
+            &request(
+                SCMI_PROTOCOL_CLOCK,
+                SCMI_CLOCK_RATE_SET,
+                &[0, 0, 12_000_000, 0],
+            ),
+            136,
+        );
+        assert_eq!(r[1], SCMI_INVALID_PARAMETERS as u32);
+
+        let r = handle(
+            &agent,
+            &request(SCMI_PROTOCOL_CLOCK, SCMI_CLOCK_RATE_GET, &[2]),
+            136,
+        );
+        assert_eq!(r[1], SCMI_NOT_FOUND as u32);
+    }
+
+    #[test]
+    fn test_sensor_descriptions() {
+        let files: Vec<TempFile> = (0..6).map(|_| sensor_file("42000\n")).collect();
+        let agent = agent(&files, 0, &[]);
+
+        // The 128 bytes payload only fits 4 descriptors
+        let r = handle(
+            &agent,
+            &request(SCMI_PROTOCOL_SENSOR, SCMI_SENSOR_DESCRIPTION_GET, &[0]),
+            136,
+        );
+        assert_eq!(r.len() * 4, 8 + 4 + 4 * SCMI_SENSOR_DESC_SIZE);
+        assert_eq!(r[1..3], [SCMI_SUCCESS as u32, (2 << 16) | 4]);
+        assert_eq!(r[3], 0);
+        assert_eq!(r[3 + 3 * SCMI_SENSOR_DESC_SIZE / 4], 3);
+        // Temperature in millidegrees Celsius
+        assert_eq!(r[5], (0x1d << 11) | 2);
+
+        let r = handle(
+            &agent,
+            &request(SCMI_PROTOCOL_SENSOR, SCMI_SENSOR_DESCRIPTION_GET, &[4]),
+            136,
+        );
+        assert_eq!(r.len() * 4, 8 + 4 + 2 * SCMI_SENSOR_DESC_SIZE);
+        assert_eq!(r[1..3], [SCMI_SUCCESS as u32, 2]);
+        assert_eq!(r[3], 4);
+
+        // Smaller response buffers hold fewer descriptors
+        let r = handle(
+            &agent,
+            &request(SCMI_PROTOCOL_SENSOR, SCMI_SENSOR_DESCRIPTION_GET, &[0]),
+            64,
+        );
+        assert_eq!(r[1..3], [SCMI_SUCCESS as u32, (5 << 16) | 1]);
+
+        let r = handle(
+            &agent,
+            &request(SCMI_PROTOCOL_SENSOR, SCMI_SENSOR_DESCRIPTION_GET, &[6]),
+            136,
+        );
+        assert_eq!(r[1..], [SCMI_SUCCESS as u32, 0]);
+        let r = handle(
+            &agent,
+            &request(SCMI_PROTOCOL_SENSOR, SCMI_SENSOR_DESCRIPTION_GET, &[7]),
+            136,
+        );
+        assert_eq!(r[1], SCMI_INVALID_PARAMETERS as u32);
+    }
+
+    #[test]
+    fn test_sensor_reading() {
+        let files = [sensor_file("42000\n"), sensor_file("-5\n")];
+        let agent = agent(&files, 0, &[]);
+
+        let r = handle(
+            &agent,
+            &request(SCMI_PROTOCOL_SENSOR, SCMI_SENSOR_READING_GET, &[0, 0]),
+            136,
+        );
+        assert_eq!(r[1..], [SCMI_SUCCESS as u32, 42000, 0]);
+        let r = handle(
+            &agent,
+            &request(SCMI_PROTOCOL_SENSOR, SCMI_SENSOR_READING_GET, &[1, 0]),
+            136,
+        );
+        assert_eq!(r[1..], [SCMI_SUCCESS as u32, -5i32 as u32, u32::MAX]);
+        let r = handle(
+            &agent,
+            &request(SCMI_PROTOCOL_SENSOR, SCMI_SENSOR_READING_GET, &[2, 0]),
+            136,
+        );
+        assert_eq!(r[1], SCMI_NOT_FOUND as u32);
+    }
+}
@@ -13,13 +13,16 @@ use seccompiler::{
 pub enum Thread {
     VirtioBalloon,
     VirtioBlock,
+    VirtioBlockReadOnly,
     VirtioConsole,
     VirtioIommu,
+    VirtioIoThread,
     VirtioMem,
     VirtioNet,
     VirtioNetCtl,
     VirtioPmem,
     VirtioRng,
+    VirtioScmi,
     VirtioVhostBlock,
     VirtioVhostFs,
     VirtioVhostNet,
@@ -101,6 +104,7 @@ fn virtio_balloon_thread_rules() -> Vec<(i64, Vec<SeccompRule>)> {
 
 fn virtio_block_thread_rules() -> Vec<(i64, Vec<SeccompRule>)> {
     vec![
+        (libc::SYS_eventfd2, vec![]),
         (libc::SYS_fallocate, vec![]),
         (libc::SYS_fdatasync, vec![]),
         (libc::SYS_fsync, vec![]),
@@ -112,6 +116,7 @@ fn virtio_block_thread_rules() -> Vec<(i64, Vec<SeccompRule>)> {
         (libc::SYS_io_getevents, vec![]),
         (libc::SYS_io_submit, vec![]),
         (libc::SYS_io_uring_enter, vec![]),
+        (libc::SYS_ioprio_set, vec![]),
         (libc::SYS_lseek, vec![]),
         (libc::SYS_pread64, vec![]),
         (libc::SYS_preadv, vec![]),
@@ -124,10 +129,28 @@ fn virtio_block_thread_rules() -> Vec<(i64, Vec<SeccompRule>)> {
     ]
 }
 
+// Read-only disks never modify their backing file.
+fn virtio_block_read_only_thread_rules() -> Vec<(i64, Vec<SeccompRule>)> {
+    virtio_block_thread_rules()
+        .into_iter()
+        .filter(|(syscall, _)| {
+            ![
+                libc::SYS_fallocate,
+                libc::SYS_ftruncate,
+                libc::SYS_pwrite64,
+                libc::SYS_pwritev,
+            ]
+            .contains(syscall)
+        })
+        .collect()
+}
+
 fn virtio_console_thread_rules() -> Vec<(i64, Vec<SeccompRule>)> {
     vec![
+        (libc::SYS_accept4, vec![]),
         (libc::SYS_ioctl, create_virtio_console_ioctl_seccomp_rule()),
         (libc::SYS_sched_getaffinity, vec![]),
+        (libc::SYS_sendto, vec![]),
         (libc::SYS_set_robust_list, vec![]),
     ]
 }
@@ -136,6 +159,16 @@ fn virtio_iommu_thread_rules() -> Vec<(i64, Vec<SeccompRule>)> {
     vec![(libc::SYS_ioctl, create_virtio_iommu_ioctl_seccomp_rule())]
 }
 
+// I/O threads service the queues of both virtio-block and virtio-net devices.
+fn virtio_io_thread_rules() -> Vec<(i64, Vec<SeccompRule>)> {
+    let mut rules = virtio_block_thread_rules();
+    rules.append(&mut virtio_net_thread_rules());
+    // Must come last, as the ioctl rule of the control queue supersedes the
+    // ones of the other queues.
+    rules.append(&mut virtio_net_ctl_thread_rules());
+    rules
+}
+
 fn virtio_mem_thread_rules() -> Vec<(i64, Vec<SeccompRule>)> {
     vec![
         (libc::SYS_fallocate, vec![]),
@@ -147,6 +180,7 @@ fn virtio_mem_thread_rules() -> Vec<(i64, Vec<SeccompRule>)> {
 
 fn virtio_net_thread_rules() -> Vec<(i64, Vec<SeccompRule>)> {
     vec![
+        (libc::SYS_io_uring_enter, vec![]),
         (libc::SYS_readv, vec![]),
         (libc::SYS_timerfd_settime, vec![]),
         (libc::SYS_writev, vec![]),
@@ -180,6 +214,16 @@ fn virtio_rng_thread_rules() -> Vec<(i64, Vec<SeccompRule>)> {
     ]
 }
 
+fn virtio_scmi_thread_rules() -> Vec<(i64, Vec<SeccompRule>)> {
+    vec![
+        (libc::SYS_pread64, vec![]),
+        (libc::SYS_sched_getaffinity, vec![]),
+        (libc::SYS_set_robust_list, vec![]),
+        #[cfg(feature = "sev_snp")]
+        (libc::SYS_ioctl, create_mshv_sev_snp_ioctl_seccomp_rule()),
+    ]
+}
+
 fn virtio_vhost_fs_thread_rules() -> Vec<(i64, Vec<SeccompRule>)> {
     vec![
         (libc::SYS_clock_nanosleep, vec![]),
@@ -263,13 +307,16 @@ fn get_seccomp_rules(thread_type: Thread) -> Vec<(i64, Vec<SeccompRule>)> {
     let mut rules = match thread_type {
         Thread::VirtioBalloon => virtio_balloon_thread_rules(),
         Thread::VirtioBlock => virtio_block_thread_rules(),
+        Thread::VirtioBlockReadOnly => virtio_block_read_only_thread_rules(),
         Thread::VirtioConsole => virtio_console_thread_rules(),
         Thread::VirtioIommu => virtio_iommu_thread_rules(),
+        Thread::VirtioIoThread => virtio_io_thread_rules(),
         Thread::VirtioMem => virtio_mem_thread_rules(),
         Thread::VirtioNet => virtio_net_thread_rules(),
         Thread::VirtioNetCtl => virtio_net_ctl_thread_rules(),
         Thread::VirtioPmem => virtio_pmem_thread_rules(),
         Thread::VirtioRng => virtio_rng_thread_rules(),
+        Thread::VirtioScmi => virtio_scmi_thread_rules(),
         Thread::VirtioVhostBlock => virtio_vhost_block_thread_rules(),
         Thread::VirtioVhostFs => virtio_vhost_fs_thread_rules(),
         Thread::VirtioVhostNet => virtio_vhost_net_thread_rules(),
@@ -335,3 +382,32 @@ pub fn get_seccomp_filter(
         .map_err(Error::Backend),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_virtio_block_read_only_thread_rules() {
+        let syscalls: Vec<i64> = virtio_block_read_only_thread_rules()
+            .into_iter()
+            .map(|(syscall, _)| syscall)
+            .collect();
+
+        for syscall in [
+            libc::SYS_fallocate,
+            libc::SYS_ftruncate,
+            libc::SYS_pwrite64,
+            libc::SYS_pwritev,
+        ] {
+            assert!(!syscalls.contains(&syscall));
+        }
+        // Reads and flushes are still allowed.
+        for syscall in [libc::SYS_pread64, libc::SYS_preadv, libc::SYS_fsync] {
+            assert!(syscalls.contains(&syscall));
+        }
+        assert_eq!(syscalls.len(), virtio_block_thread_rules().len() - 4);
+
+        get_seccomp_filter(&SeccompAction::Trap, Thread::VirtioBlockReadOnly).unwrap();
+    }
+}
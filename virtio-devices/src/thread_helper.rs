@@ -4,8 +4,13 @@
 //
 
 use std::panic::AssertUnwindSafe;
+use std::path::PathBuf;
 use std::thread::{self, JoinHandle};
 
+use landlock::{
+    path_beneath_rules, Access, AccessFs, BitFlags, Ruleset, RulesetAttr, RulesetCreatedAttr,
+    RulesetError, ABI,
+};
 use seccompiler::{apply_filter, SeccompAction};
 use vmm_sys_util::eventfd::EventFd;
 
@@ -13,9 +18,37 @@ use crate::epoll_helper::EpollHelperError;
 use crate::seccomp_filters::{get_seccomp_filter, Thread};
 use crate::ActivateError;
 
+/// Files the worker threads of a device may access, Landlock restricting
+/// them to these on top of the rules applied to the whole VMM.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ThreadLandlock {
+    rules: Vec<(PathBuf, BitFlags<AccessFs>)>,
+}
+
+impl ThreadLandlock {
+    pub fn add_rule(&mut self, path: PathBuf, access: BitFlags<AccessFs>) {
+        self.rules.push((path, access));
+    }
+
+    fn restrict_self(&self) -> Result<(), RulesetError> {
+        let mut ruleset = Ruleset::default()
+            .handle_access(AccessFs::from_all(ABI::V3))?
+            .create()?;
+        for (path, access) in self.rules.iter() {
+            ruleset
+                .as_mut()
+                .add_rules(path_beneath_rules([path], *access))?;
+        }
+        ruleset.restrict_self()?;
+
+        Ok(())
+    }
+}
+
 pub(crate) fn spawn_virtio_thread<F>(
     name: &str,
     seccomp_action: &SeccompAction,
+    landlock: Option<&ThreadLandlock>,
     thread_type: Thread,
     epoll_threads: &mut Vec<JoinHandle<()>>,
     exit_evt: &EventFd,
@@ -32,10 +65,20 @@ where
         .try_clone()
         .map_err(ActivateError::CloneExitEventFd)?;
     let thread_name = name.to_string();
+    let landlock = landlock.cloned();
 
     thread::Builder::new()
         .name(name.to_string())
         .spawn(move || {
+            // Landlock goes first, the seccomp filter not allowing its
+            // syscalls.
+            if let Some(landlock) = landlock {
+                if let Err(e) = landlock.restrict_self() {
+                    error!("Error applying landlock to {} thread: {:?}", thread_name, e);
+                    thread_exit_evt.write(1).ok();
+                    return;
+                }
+            }
             if !seccomp_filter.is_empty() {
                 if let Err(e) = apply_filter(&seccomp_filter) {
                     error!("Error applying seccomp filter: {:?}", e);
@@ -18,7 +18,7 @@ use libc::EFD_NONBLOCK;
 use pci::{
     BarReprogrammingParams, MsixCap, MsixConfig, PciBarConfiguration, PciBarRegionType,
     PciCapability, PciCapabilityId, PciClassCode, PciConfiguration, PciDevice, PciDeviceError,
-    PciHeaderType, PciMassStorageSubclass, PciNetworkControllerSubclass, PciSubclass,
+    PciHeaderType, PciMassStorageSubclass, PciNetworkControllerSubclass, PciRom, PciSubclass,
 };
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
@@ -254,6 +254,7 @@ const MSIX_PBA_SIZE: u64 = 0x800;
 const CAPABILITY_BAR_SIZE: u64 = 0x80000;
 const VIRTIO_COMMON_BAR_INDEX: usize = 0;
 const VIRTIO_SHM_BAR_INDEX: usize = 2;
+const VIRTIO_ROM_BAR_INDEX: usize = 6;
 
 const NOTIFY_OFF_MULTIPLIER: u32 = 4; // A dword per notification address.
 
@@ -371,6 +372,9 @@ pub struct VirtioPciDevice {
 
     // Pending activations
     pending_activations: Arc<Mutex<Vec<VirtioPciDeviceActivator>>>,
+
+    // Option ROM exposed through the expansion ROM BAR
+    rom: Option<PciRom>,
 }
 
 impl VirtioPciDevice {
@@ -389,6 +393,7 @@ impl VirtioPciDevice {
         dma_handler: Option<Arc<dyn ExternalDmaMapping>>,
         pending_activations: Arc<Mutex<Vec<VirtioPciDeviceActivator>>>,
         snapshot: Option<Snapshot>,
+        rom: Option<PciRom>,
     ) -> Result<Self> {
         let mut locked_device = device.lock().unwrap();
         let mut queue_evts = Vec::new();
@@ -594,6 +599,7 @@ impl VirtioPciDevice {
             activate_evt,
             dma_handler,
             pending_activations,
+            rom,
         };
 
         if let Some(msix_config) = &virtio_pci_device.msix_config {
@@ -961,6 +967,7 @@ impl PciDevice for VirtioPciDevice {
         let device = device_clone.lock().unwrap();
 
         let mut settings_bar_addr = None;
+        let mut rom_bar_addr = None;
         let mut use_64bit_bar = self.use_64bit_bar;
         let restoring = resources.is_some();
         if let Some(resources) = resources {
@@ -978,7 +985,8 @@ impl PciDevice for VirtioPciDevice {
                             PciBarType::Mmio32 => false,
                             PciBarType::Mmio64 => true,
                         };
-                        break;
+                    } else if index == VIRTIO_ROM_BAR_INDEX {
+                        rom_bar_addr = Some(GuestAddress(base));
                     }
                 }
             }
@@ -1066,6 +1074,28 @@ impl PciDevice for VirtioPciDevice {
             bars.push(bar);
         }
 
+        // Allocate the expansion ROM BAR if the device comes with an option
+        // ROM, disabled until the guest enables it.
+        if let Some(rom) = &self.rom {
+            let size = rom.bar_size();
+            let addr = mmio32_allocator
+                .allocate(rom_bar_addr, size, Some(size))
+                .ok_or(PciDeviceError::IoAllocationFailed(size))?;
+            let bar = PciBarConfiguration::default()
+                .set_index(VIRTIO_ROM_BAR_INDEX)
+                .set_address(addr.raw_value())
+                .set_size(size)
+                .set_region_type(PciBarRegionType::Memory32BitRegion);
+
+            if !restoring {
+                self.configuration
+                    .add_pci_rom_bar(&bar, 0)
+                    .map_err(|e| PciDeviceError::IoRegistrationFailed(addr.raw_value(), e))?;
+            }
+
+            bars.push(bar);
+        }
+
         self.bar_regions.clone_from(&bars);
 
         Ok(bars)
@@ -1107,7 +1137,14 @@ impl PciDevice for VirtioPciDevice {
         Ok(())
     }
 
-    fn read_bar(&mut self, _base: u64, offset: u64, data: &mut [u8]) {
+    fn read_bar(&mut self, base: u64, offset: u64, data: &mut [u8]) {
+        if let Some(rom) = &self.rom {
+            if base == self.configuration.get_rom_bar_addr() {
+                rom.read(offset, data);
+                return;
+            }
+        }
+
         match offset {
             o if o < COMMON_CONFIG_BAR_OFFSET + COMMON_CONFIG_SIZE => self.common_config.read(
                 o - COMMON_CONFIG_BAR_OFFSET,
@@ -1152,7 +1189,13 @@ impl PciDevice for VirtioPciDevice {
         }
     }
 
-    fn write_bar(&mut self, _base: u64, offset: u64, data: &[u8]) -> Option<Arc<Barrier>> {
+    fn write_bar(&mut self, base: u64, offset: u64, data: &[u8]) -> Option<Arc<Barrier>> {
+        // The option ROM is read-only.
+        if self.rom.is_some() && base == self.configuration.get_rom_bar_addr() {
+            warn!("{}: Ignoring write to the option ROM", self.id);
+            return None;
+        }
+
         match offset {
             o if o < COMMON_CONFIG_BAR_OFFSET + COMMON_CONFIG_SIZE => self.common_config.write(
                 o - COMMON_CONFIG_BAR_OFFSET,
@@ -1176,8 +1219,8 @@ impl PciDevice for VirtioPciDevice {
                 .contains(&o) =>
             {
                 #[cfg(feature = "sev_snp")]
-                for (event, addr) in self.ioeventfds(_base) {
-                    if addr == _base + offset {
+                for (event, addr) in self.ioeventfds(base) {
+                    if addr == base + offset {
                         event.write(1).unwrap();
                     }
                 }
@@ -0,0 +1,185 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Datapath of a virtio-net device offloaded to the vhost-net kernel module.
+//!
+//! Each queue pair is handed over to its own `/dev/vhost-net` instance, which
+//! moves the frames between the queues and the TAP device from the kernel,
+//! being kicked through the queue eventfds and notifying the driver through
+//! the interrupt eventfds.
+
+use std::sync::Arc;
+
+use net_util::Tap;
+use thiserror::Error;
+use vhost::net::VhostNet;
+use vhost::vhost_kern::net::Net as VhostKernNet;
+use vhost::vhost_kern::VhostKernBackend;
+use vhost::{VhostBackend, VhostUserMemoryRegionInfo, VringConfigData};
+use virtio_bindings::virtio_config::VIRTIO_F_VERSION_1;
+use virtio_bindings::virtio_net::VIRTIO_NET_F_MRG_RXBUF;
+use virtio_bindings::virtio_ring::VIRTIO_RING_F_EVENT_IDX;
+use virtio_queue::{Queue, QueueT};
+use vm_memory::{Address, GuestAddressSpace, GuestMemory, GuestMemoryAtomic, GuestMemoryRegion};
+use vmm_sys_util::eventfd::EventFd;
+
+use crate::{GuestMemoryMmap, VirtioInterrupt, VirtioInterruptType, VIRTIO_F_RING_INDIRECT_DESC};
+
+// Features negotiated with the driver which vhost-net must know about, the
+// offloads being handled by the TAP device.
+const VHOST_NET_FEATURES: u64 = (1 << VIRTIO_F_VERSION_1)
+    | (1 << VIRTIO_F_RING_INDIRECT_DESC)
+    | (1 << VIRTIO_RING_F_EVENT_IDX)
+    | (1 << VIRTIO_NET_F_MRG_RXBUF);
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Failed to open /dev/vhost-net")]
+    Open(#[source] vhost::Error),
+    #[error("vhost-net can't be used along with {0}")]
+    Unsupported(&'static str),
+    #[error("Failed to program the TAP device offloads")]
+    SetTapOffload(#[source] net_util::TapError),
+    #[error("Failed to set owner")]
+    SetOwner(#[source] vhost::Error),
+    #[error("Failed to get virtio features")]
+    GetFeatures(#[source] vhost::Error),
+    #[error("Virtio features {0:#x} not supported by vhost-net")]
+    UnsupportedFeatures(u64),
+    #[error("Failed to set virtio features")]
+    SetFeatures(#[source] vhost::Error),
+    #[error("Failed to set memory table")]
+    SetMemTable(#[source] vhost::Error),
+    #[error("Failed to set vring num")]
+    SetVringNum(#[source] vhost::Error),
+    #[error("Failed to set vring address")]
+    SetVringAddr(#[source] vhost::Error),
+    #[error("Failed to set vring base")]
+    SetVringBase(#[source] vhost::Error),
+    #[error("No interrupt eventfd for queue {0}")]
+    MissingInterruptEventFd(usize),
+    #[error("Failed to set vring eventfd when buffer are used")]
+    SetVringCall(#[source] vhost::Error),
+    #[error("Failed to set vring eventfd when new descriptors are available")]
+    SetVringKick(#[source] vhost::Error),
+    #[error("Failed to set the TAP device backing the vrings")]
+    SetBackend(#[source] vhost::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Queue pair serviced by a vhost-net instance, vring 0 being the receive
+/// queue and vring 1 the transmit queue.
+pub(crate) struct VhostNetQueuePair {
+    vhost: VhostKernNet<GuestMemoryAtomic<GuestMemoryMmap>>,
+    tap: Tap,
+}
+
+impl VhostNetQueuePair {
+    /// Set up a vhost-net instance to service `queues` with `tap`. No frame
+    /// is moved until the queue pair is started.
+    pub(crate) fn new(
+        mem: &GuestMemoryAtomic<GuestMemoryMmap>,
+        acked_features: u64,
+        tap: Tap,
+        queues: &[(usize, Queue, EventFd)],
+        interrupt_cb: &Arc<dyn VirtioInterrupt>,
+    ) -> Result<Self> {
+        let vhost = VhostKernNet::new(mem.clone()).map_err(Error::Open)?;
+        vhost.set_owner().map_err(Error::SetOwner)?;
+
+        let features = acked_features & VHOST_NET_FEATURES;
+        let unsupported = features & !vhost.get_features().map_err(Error::GetFeatures)?;
+        if unsupported != 0 {
+            return Err(Error::UnsupportedFeatures(unsupported));
+        }
+        vhost.set_features(features).map_err(Error::SetFeatures)?;
+
+        let queue_pair = VhostNetQueuePair { vhost, tap };
+        queue_pair.update_mem_table()?;
+
+        for (vring_index, (queue_index, queue, queue_evt)) in queues.iter().enumerate() {
+            let vhost = &queue_pair.vhost;
+            vhost
+                .set_vring_num(vring_index, queue.size())
+                .map_err(Error::SetVringNum)?;
+
+            // The addresses are translated by the vhost crate, from the
+            // guest memory handed over to the instance.
+            let config_data = VringConfigData {
+                queue_max_size: queue.max_size(),
+                queue_size: queue.size(),
+                flags: 0u32,
+                desc_table_addr: queue.desc_table(),
+                used_ring_addr: queue.used_ring(),
+                avail_ring_addr: queue.avail_ring(),
+                log_addr: None,
+            };
+            vhost
+                .set_vring_addr(vring_index, &config_data)
+                .map_err(Error::SetVringAddr)?;
+            vhost
+                .set_vring_base(vring_index, queue.next_avail())
+                .map_err(Error::SetVringBase)?;
+
+            let eventfd = interrupt_cb
+                .notifier(VirtioInterruptType::Queue(*queue_index as u16))
+                .ok_or(Error::MissingInterruptEventFd(*queue_index))?;
+            vhost
+                .set_vring_call(vring_index, &eventfd)
+                .map_err(Error::SetVringCall)?;
+            vhost
+                .set_vring_kick(vring_index, queue_evt)
+                .map_err(Error::SetVringKick)?;
+        }
+
+        Ok(queue_pair)
+    }
+
+    /// Let the kernel access the guest memory as currently laid out.
+    pub(crate) fn update_mem_table(&self) -> Result<()> {
+        let regions: Vec<VhostUserMemoryRegionInfo> = self
+            .vhost
+            .mem()
+            .memory()
+            .iter()
+            .map(|region| VhostUserMemoryRegionInfo {
+                guest_phys_addr: region.start_addr().raw_value(),
+                memory_size: region.len(),
+                userspace_addr: region.as_ptr() as u64,
+                // The kernel accesses the memory through the VMM mappings.
+                mmap_offset: 0,
+                mmap_handle: -1,
+            })
+            .collect();
+
+        self.vhost
+            .set_mem_table(&regions)
+            .map_err(Error::SetMemTable)
+    }
+
+    /// Start moving frames between the queues and the TAP device.
+    pub(crate) fn start(&self) -> Result<()> {
+        for vring_index in 0..2 {
+            self.vhost
+                .set_backend(vring_index, Some(self.tap.file()))
+                .map_err(Error::SetBackend)?;
+        }
+
+        Ok(())
+    }
+
+    /// Stop moving frames, leaving the queues as they are until started
+    /// again.
+    pub(crate) fn stop(&self) -> Result<()> {
+        for vring_index in 0..2 {
+            self.vhost
+                .set_backend(vring_index, None)
+                .map_err(Error::SetBackend)?;
+        }
+
+        Ok(())
+    }
+}
@@ -28,7 +28,7 @@ use super::super::{ActivateResult, VirtioCommon, VirtioDevice, VirtioDeviceType}
 use super::vu_common_ctrl::{VhostUserConfig, VhostUserHandle};
 use super::{Error, Result, DEFAULT_VIRTIO_FEATURES};
 use crate::seccomp_filters::Thread;
-use crate::thread_helper::spawn_virtio_thread;
+use crate::thread_helper::{spawn_virtio_thread, ThreadLandlock};
 use crate::vhost_user::VhostUserCommon;
 use crate::{GuestMemoryMmap, GuestRegionMmap, VirtioInterrupt, VIRTIO_F_IOMMU_PLATFORM};
 
@@ -230,6 +230,10 @@ impl VirtioDevice for Blk {
         self.common.device_type
     }
 
+    fn set_thread_landlock(&mut self, landlock: ThreadLandlock) {
+        self.common.landlock = Some(landlock);
+    }
+
     fn queue_max_sizes(&self) -> &[u16] {
         &self.common.queue_sizes
     }
@@ -311,6 +315,7 @@ impl VirtioDevice for Blk {
         spawn_virtio_thread(
             &self.id,
             &self.seccomp_action,
+            self.common.landlock.as_ref(),
             Thread::VirtioVhostBlock,
             &mut epoll_threads,
             &self.exit_evt,
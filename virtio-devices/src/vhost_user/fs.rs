@@ -19,7 +19,7 @@ use vmm_sys_util::eventfd::EventFd;
 use super::vu_common_ctrl::VhostUserHandle;
 use super::{Error, Result, DEFAULT_VIRTIO_FEATURES};
 use crate::seccomp_filters::Thread;
-use crate::thread_helper::spawn_virtio_thread;
+use crate::thread_helper::{spawn_virtio_thread, ThreadLandlock};
 use crate::vhost_user::VhostUserCommon;
 use crate::{
     ActivateResult, GuestMemoryMmap, GuestRegionMmap, MmapRegion, UserspaceMapping, VirtioCommon,
@@ -240,6 +240,10 @@ impl VirtioDevice for Fs {
         self.common.device_type
     }
 
+    fn set_thread_landlock(&mut self, landlock: ThreadLandlock) {
+        self.common.landlock = Some(landlock);
+    }
+
     fn queue_max_sizes(&self) -> &[u16] {
         &self.common.queue_sizes
     }
@@ -291,6 +295,7 @@ impl VirtioDevice for Fs {
         spawn_virtio_thread(
             &self.id,
             &self.seccomp_action,
+            self.common.landlock.as_ref(),
             Thread::VirtioVhostFs,
             &mut epoll_threads,
             &self.exit_evt,
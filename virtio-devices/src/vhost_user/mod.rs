@@ -1,6 +1,10 @@
 // Copyright 2019 Intel Corporation. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
+//! Frontends of the vhost-user devices, whose virtqueues are processed by a
+//! backend process reached through a UNIX socket, as described by
+//! [`VhostUserConfig`].
+
 use std::io;
 use std::ops::Deref;
 use std::os::unix::io::AsRawFd;
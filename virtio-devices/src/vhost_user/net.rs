@@ -24,7 +24,7 @@ use vm_migration::{Migratable, MigratableError, Pausable, Snapshot, Snapshottabl
 use vmm_sys_util::eventfd::EventFd;
 
 use crate::seccomp_filters::Thread;
-use crate::thread_helper::spawn_virtio_thread;
+use crate::thread_helper::{spawn_virtio_thread, ThreadLandlock};
 use crate::vhost_user::vu_common_ctrl::{VhostUserConfig, VhostUserHandle};
 use crate::vhost_user::{Error, Result, VhostUserCommon};
 use crate::{
@@ -267,6 +267,10 @@ impl VirtioDevice for Net {
         self.common.device_type
     }
 
+    fn set_thread_landlock(&mut self, landlock: ThreadLandlock) {
+        self.common.landlock = Some(landlock);
+    }
+
     fn queue_max_sizes(&self) -> &[u16] {
         &self.common.queue_sizes
     }
@@ -329,6 +333,7 @@ impl VirtioDevice for Net {
             spawn_virtio_thread(
                 &format!("{}_ctrl", &self.id),
                 &self.seccomp_action,
+                self.common.landlock.as_ref(),
                 Thread::VirtioVhostNetCtl,
                 &mut epoll_threads,
                 &self.exit_evt,
@@ -364,6 +369,7 @@ impl VirtioDevice for Net {
         spawn_virtio_thread(
             &self.id,
             &self.seccomp_action,
+            self.common.landlock.as_ref(),
             Thread::VirtioVhostNet,
             &mut epoll_threads,
             &self.exit_evt,
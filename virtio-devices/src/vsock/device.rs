@@ -45,7 +45,7 @@ use vmm_sys_util::eventfd::EventFd;
 ///
 use super::{VsockBackend, VsockPacket};
 use crate::seccomp_filters::Thread;
-use crate::thread_helper::spawn_virtio_thread;
+use crate::thread_helper::{spawn_virtio_thread, ThreadLandlock};
 use crate::{
     ActivateResult, EpollHelper, EpollHelperError, EpollHelperHandler, Error as DeviceError,
     GuestMemoryMmap, VirtioCommon, VirtioDevice, VirtioDeviceType, VirtioInterrupt,
@@ -467,6 +467,7 @@ where
         spawn_virtio_thread(
             &self.id,
             &self.seccomp_action,
+            self.common.landlock.as_ref(),
             Thread::VirtioVsock,
             &mut epoll_threads,
             &self.exit_evt,
@@ -492,6 +493,10 @@ where
     fn set_access_platform(&mut self, access_platform: Arc<dyn AccessPlatform>) {
         self.common.set_access_platform(access_platform)
     }
+
+    fn set_thread_landlock(&mut self, landlock: ThreadLandlock) {
+        self.common.landlock = Some(landlock);
+    }
 }
 
 impl<B> Pausable for Vsock<B>
@@ -18,6 +18,7 @@ use seccompiler::SeccompAction;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use virtio_queue::{Queue, QueueT};
+use vm_device::{ExitReason, ExitReasonSlot};
 use vm_memory::{Bytes, GuestAddressSpace, GuestMemoryAtomic};
 use vm_migration::{Migratable, MigratableError, Pausable, Snapshot, Snapshottable, Transportable};
 use vmm_sys_util::eventfd::EventFd;
@@ -70,6 +71,7 @@ struct WatchdogEpollHandler {
     timer: File,
     last_ping_time: Arc<Mutex<Option<Instant>>>,
     reset_evt: EventFd,
+    exit_reason: ExitReasonSlot,
 }
 
 impl WatchdogEpollHandler {
@@ -170,6 +172,8 @@ impl EpollHelperHandler for WatchdogEpollHandler {
                     let gap = now.duration_since(*last_ping_time).as_secs();
                     if gap > WATCHDOG_TIMEOUT {
                         error!("Watchdog triggered: {} seconds since last ping", gap);
+                        event!("watchdog", "expired");
+                        self.exit_reason.record(ExitReason::Watchdog);
                         self.reset_evt.write(1).ok();
                     }
                 }
@@ -191,6 +195,7 @@ pub struct Watchdog {
     id: String,
     seccomp_action: SeccompAction,
     reset_evt: EventFd,
+    exit_reason: ExitReasonSlot,
     last_ping_time: Arc<Mutex<Option<Instant>>>,
     timer: File,
     exit_evt: EventFd,
@@ -208,6 +213,7 @@ impl Watchdog {
     pub fn new(
         id: String,
         reset_evt: EventFd,
+        exit_reason: ExitReasonSlot,
         seccomp_action: SeccompAction,
         exit_evt: EventFd,
         state: Option<WatchdogState>,
@@ -249,6 +255,7 @@ impl Watchdog {
             id,
             seccomp_action,
             reset_evt,
+            exit_reason,
             last_ping_time: Arc::new(Mutex::new(last_ping_time)),
             timer,
             exit_evt,
@@ -360,6 +367,7 @@ impl VirtioDevice for Watchdog {
             timer,
             last_ping_time: self.last_ping_time.clone(),
             reset_evt,
+            exit_reason: self.exit_reason.clone(),
         };
 
         let paused = self.common.paused.clone();
@@ -369,6 +377,7 @@ impl VirtioDevice for Watchdog {
         spawn_virtio_thread(
             &self.id,
             &self.seccomp_action,
+            self.common.landlock.as_ref(),
             Thread::VirtioWatchdog,
             &mut epoll_threads,
             &self.exit_evt,
@@ -0,0 +1,83 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::fmt::{self, Display};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+/// Cause of the guest stopping or resetting the VM.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExitReason {
+    /// The guest entered the ACPI S5 sleep state.
+    AcpiShutdown,
+    /// The guest wrote the ACPI reset register.
+    AcpiReboot,
+    /// The guest powered off through the hypervisor, e.g. with PSCI.
+    Shutdown,
+    /// The guest reset through another path, e.g. the i8042 controller, the
+    /// CMOS or PSCI.
+    Reset,
+    /// A vCPU hit a triple fault.
+    TripleFault,
+    /// The watchdog expired.
+    Watchdog,
+    /// The guest reported a panic through pvpanic.
+    Panic,
+}
+
+impl Display for ExitReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            ExitReason::AcpiShutdown => "acpi-shutdown",
+            ExitReason::AcpiReboot => "acpi-reboot",
+            ExitReason::Shutdown => "shutdown",
+            ExitReason::Reset => "reset",
+            ExitReason::TripleFault => "triple-fault",
+            ExitReason::Watchdog => "watchdog",
+            ExitReason::Panic => "panic",
+        };
+        write!(f, "{s}")
+    }
+}
+
+/// Holds the cause of a pending exit, recorded by the device or vCPU
+/// signaling it and consumed by the VMM when handling the exit.
+#[derive(Clone, Default)]
+pub struct ExitReasonSlot(Arc<Mutex<Option<ExitReason>>>);
+
+impl ExitReasonSlot {
+    /// Records the cause of an exit, unless one is already pending since
+    /// the first cause is the one that triggered the exit.
+    pub fn record(&self, reason: ExitReason) {
+        self.0.lock().unwrap().get_or_insert(reason);
+    }
+
+    /// Takes the cause of the pending exit, if any.
+    pub fn take(&self) -> Option<ExitReason> {
+        self.0.lock().unwrap().take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_reason_slot_keeps_first() {
+        let slot = ExitReasonSlot::default();
+        assert_eq!(slot.take(), None);
+
+        // The i8042 reset following the ACPI one doesn't replace it
+        slot.clone().record(ExitReason::AcpiReboot);
+        slot.record(ExitReason::Reset);
+        assert_eq!(slot.take(), Some(ExitReason::AcpiReboot));
+        assert_eq!(slot.take(), None);
+
+        slot.record(ExitReason::TripleFault);
+        assert_eq!(slot.take(), Some(ExitReason::TripleFault));
+    }
+}
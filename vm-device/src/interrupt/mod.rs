@@ -59,6 +59,8 @@
 
 use std::sync::Arc;
 
+#[cfg(target_arch = "x86_64")]
+pub use hypervisor::HvSintSourceConfig;
 pub use hypervisor::{InterruptSourceConfig, LegacyIrqSourceConfig, MsiIrqSourceConfig};
 use vmm_sys_util::eventfd::EventFd;
 
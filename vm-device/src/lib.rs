@@ -7,9 +7,11 @@ use serde::{Deserialize, Serialize};
 
 mod bus;
 pub mod dma_mapping;
+mod exit_reason;
 pub mod interrupt;
 
 pub use self::bus::{Bus, BusDevice, BusDeviceSync, Error as BusError};
+pub use self::exit_reason::{ExitReason, ExitReasonSlot};
 
 /// Type of Message Signalled Interrupt
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
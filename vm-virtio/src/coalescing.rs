@@ -0,0 +1,56 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Notification coalescing parameters of a set of virtqueues.
+///
+/// They can be updated at any time, e.g. by the driver through the control
+/// queue of a network device, while the queues are being processed.
+#[derive(Debug, Default)]
+pub struct Coalescing(AtomicU64);
+
+impl Coalescing {
+    pub fn new(usecs: u32, max_used: u32) -> Self {
+        Coalescing(AtomicU64::new(Self::pack(usecs, max_used)))
+    }
+
+    fn pack(usecs: u32, max_used: u32) -> u64 {
+        (u64::from(usecs) << 32) | u64::from(max_used)
+    }
+
+    /// Updates the maximum delay of a used buffer notification, in
+    /// microseconds, and the number of used buffers after which the driver is
+    /// notified without further delay. A null delay disables coalescing.
+    pub fn set(&self, usecs: u32, max_used: u32) {
+        self.0.store(Self::pack(usecs, max_used), Ordering::Release);
+    }
+
+    /// Returns the maximum delay of a used buffer notification, in
+    /// microseconds, along with the number of used buffers after which the
+    /// driver is notified without further delay.
+    pub fn get(&self) -> (u32, u32) {
+        let value = self.0.load(Ordering::Acquire);
+        ((value >> 32) as u32, value as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coalescing_params() {
+        let coalescing = Coalescing::default();
+        assert_eq!(coalescing.get(), (0, 0));
+
+        coalescing.set(50, 32);
+        assert_eq!(coalescing.get(), (50, 32));
+
+        coalescing.set(u32::MAX, 0);
+        assert_eq!(coalescing.get(), (u32::MAX, 0));
+
+        assert_eq!(Coalescing::new(0, u32::MAX).get(), (0, u32::MAX));
+    }
+}
@@ -0,0 +1,167 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::ops::{Deref, DerefMut};
+use std::{io, ptr, slice};
+
+use log::debug;
+
+const HUGEPAGE_SIZE: usize = 2 << 20;
+
+const MPOL_PREFERRED: u32 = 1;
+
+/// Buffer of VMM memory backed by transparent hugepages, for the data
+/// structures heavily accessed by the device workers.
+///
+/// The buffer is aligned on a hugepage boundary, its size being rounded up
+/// to a whole number of hugepages, and its pages are preferably allocated
+/// on the given host NUMA node.
+pub struct HugepageBuffer {
+    addr: *mut u8,
+    len: usize,
+    mapping_len: usize,
+}
+
+// SAFETY: The buffer is exclusively owned, the pointer being the address
+// of a private mapping.
+unsafe impl Send for HugepageBuffer {}
+// SAFETY: See above.
+unsafe impl Sync for HugepageBuffer {}
+
+impl HugepageBuffer {
+    pub fn new(len: usize, numa_node: Option<u32>) -> io::Result<Self> {
+        let mapping_len = len.div_ceil(HUGEPAGE_SIZE).max(1) * HUGEPAGE_SIZE;
+
+        // Map an extra hugepage, so that an aligned mapping can be carved out
+        // of it.
+        // SAFETY: FFI call creating a new anonymous mapping.
+        let addr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                mapping_len + HUGEPAGE_SIZE,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE,
+                -1,
+                0,
+            )
+        };
+        if addr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+
+        let head = (addr as usize).next_multiple_of(HUGEPAGE_SIZE) - addr as usize;
+        // SAFETY: the unmapped ranges lie within the mapping created above.
+        unsafe {
+            if head != 0 {
+                libc::munmap(addr, head);
+            }
+            libc::munmap(addr.add(head + mapping_len), HUGEPAGE_SIZE - head);
+        }
+
+        let buffer = HugepageBuffer {
+            // SAFETY: the offset lies within the mapping created above.
+            addr: unsafe { (addr as *mut u8).add(head) },
+            len,
+            mapping_len,
+        };
+
+        // Transparent hugepages may be disabled, in which case regular pages
+        // are used.
+        // SAFETY: FFI call on the mapping owned by the buffer.
+        let ret = unsafe {
+            libc::madvise(
+                buffer.addr as *mut libc::c_void,
+                mapping_len,
+                libc::MADV_HUGEPAGE,
+            )
+        };
+        if ret != 0 {
+            debug!(
+                "Failed to back buffer with hugepages: {}",
+                io::Error::last_os_error()
+            );
+        }
+
+        if let Some(node) = numa_node {
+            buffer.set_numa_node(node)?;
+        }
+
+        Ok(buffer)
+    }
+
+    fn set_numa_node(&self, node: u32) -> io::Result<()> {
+        let mut nodemask = vec![0u64; node as usize / 64 + 1];
+        nodemask[node as usize / 64] |= 1 << (node % 64);
+        // Linux cuts off the last node of the mask.
+        let maxnode = node as u64 + 2;
+
+        // SAFETY: FFI call on the mapping owned by the buffer, with a valid
+        // nodemask.
+        let ret = unsafe {
+            libc::syscall(
+                libc::SYS_mbind,
+                self.addr as *mut libc::c_void,
+                self.mapping_len,
+                MPOL_PREFERRED,
+                nodemask.as_ptr(),
+                maxnode,
+                0,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+}
+
+impl Deref for HugepageBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // SAFETY: the mapping is valid for the lifetime of the buffer.
+        unsafe { slice::from_raw_parts(self.addr, self.len) }
+    }
+}
+
+impl DerefMut for HugepageBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        // SAFETY: the mapping is valid for the lifetime of the buffer.
+        unsafe { slice::from_raw_parts_mut(self.addr, self.len) }
+    }
+}
+
+impl Drop for HugepageBuffer {
+    fn drop(&mut self) {
+        // SAFETY: the mapping is owned by the buffer.
+        unsafe { libc::munmap(self.addr as *mut libc::c_void, self.mapping_len) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hugepage_buffer() {
+        for len in [1, HUGEPAGE_SIZE, HUGEPAGE_SIZE + 1] {
+            let mut buffer = HugepageBuffer::new(len, None).unwrap();
+            assert_eq!(buffer.len(), len);
+            assert_eq!(buffer.as_ptr() as usize % HUGEPAGE_SIZE, 0);
+            assert_eq!(
+                buffer.mapping_len,
+                len.div_ceil(HUGEPAGE_SIZE) * HUGEPAGE_SIZE
+            );
+            assert!(buffer.iter().all(|&b| b == 0));
+
+            buffer.fill(0xa5);
+            assert!(buffer.iter().all(|&b| b == 0xa5));
+        }
+
+        let buffer = HugepageBuffer::new(0, None).unwrap();
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.mapping_len, HUGEPAGE_SIZE);
+    }
+}
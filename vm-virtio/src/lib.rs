@@ -16,7 +16,11 @@ use std::sync::Arc;
 use virtio_queue::{Queue, QueueT};
 use vm_memory::GuestAddress;
 
+pub mod coalescing;
+pub mod hugepage_buffer;
 pub mod queue;
+pub use coalescing::*;
+pub use hugepage_buffer::*;
 pub use queue::*;
 
 pub const VIRTIO_MSI_NO_VECTOR: u16 = 0xffff;
@@ -40,6 +44,7 @@ pub enum VirtioDeviceType {
     Mem = 24,
     Fs = 26,
     Pmem = 27,
+    Scmi = 32,
     Watchdog = 35, // Temporary until official number allocated
     Unknown = 0xFF,
 }
@@ -60,6 +65,7 @@ impl From<u32> for VirtioDeviceType {
             24 => VirtioDeviceType::Mem,
             26 => VirtioDeviceType::Fs,
             27 => VirtioDeviceType::Pmem,
+            32 => VirtioDeviceType::Scmi,
             35 => VirtioDeviceType::Watchdog,
             _ => VirtioDeviceType::Unknown,
         }
@@ -85,6 +91,7 @@ impl fmt::Display for VirtioDeviceType {
             VirtioDeviceType::Mem => "mem",
             VirtioDeviceType::Fs => "fs",
             VirtioDeviceType::Pmem => "pmem",
+            VirtioDeviceType::Scmi => "scmi",
             VirtioDeviceType::Watchdog => "watchdog",
             VirtioDeviceType::Unknown => "UNKNOWN",
         };
@@ -16,6 +16,7 @@ use arch::aarch64::DeviceInfoForFdt;
 use arch::DeviceType;
 use arch::NumaNodes;
 use bitflags::bitflags;
+use devices::nvdimm::Nvdimm;
 use pci::PciBdf;
 use tracer::trace_scoped;
 use vm_memory::{Address, Bytes, GuestAddress, GuestMemoryRegion};
@@ -97,6 +98,71 @@ struct ProcessorGiccAffinity {
     pub clock_domain: u32,
 }
 
+#[allow(dead_code)]
+#[repr(C, packed)]
+#[derive(Default, IntoBytes, Immutable, FromBytes)]
+struct NfitSpaRange {
+    pub type_: u16,
+    pub length: u16,
+    pub range_index: u16,
+    pub flags: u16,
+    _reserved: u32,
+    pub proximity_domain: u32,
+    pub range_type: [u8; 16],
+    pub base_address: u64,
+    pub range_length: u64,
+    pub memory_attributes: u64,
+}
+
+#[allow(dead_code)]
+#[repr(C, packed)]
+#[derive(Default, IntoBytes, Immutable, FromBytes)]
+struct NfitRegionMapping {
+    pub type_: u16,
+    pub length: u16,
+    pub device_handle: u32,
+    pub physical_id: u16,
+    pub region_id: u16,
+    pub range_index: u16,
+    pub control_region_index: u16,
+    pub region_size: u64,
+    pub region_offset: u64,
+    pub physical_address_base: u64,
+    pub interleave_index: u16,
+    pub interleave_ways: u16,
+    pub state_flags: u16,
+    _reserved: u16,
+}
+
+#[allow(dead_code)]
+#[repr(C, packed)]
+#[derive(Default, IntoBytes, Immutable, FromBytes)]
+struct NfitControlRegion {
+    pub type_: u16,
+    pub length: u16,
+    pub control_region_index: u16,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub revision_id: u16,
+    pub subsystem_vendor_id: u16,
+    pub subsystem_device_id: u16,
+    pub subsystem_revision_id: u16,
+    pub valid_fields: u8,
+    pub manufacturing_location: u8,
+    pub manufacturing_date: u16,
+    _reserved: u16,
+    pub serial_number: u32,
+    pub format_interface_code: u16,
+    pub block_control_windows: u16,
+    pub block_control_window_size: u64,
+    pub command_register_offset: u64,
+    pub command_register_size: u64,
+    pub status_register_offset: u64,
+    pub status_register_size: u64,
+    pub flags: u16,
+    _reserved2: [u8; 6],
+}
+
 bitflags! {
     pub struct MemAffinityFlags: u32 {
         const NOFLAGS = 0;
@@ -227,6 +293,12 @@ fn create_facp_table(dsdt_offset: GuestAddress, device_manager: &Arc<Mutex<Devic
             // X_PM_TMR_BLK
             facp.write(208, address);
         }
+
+        // IAPC_BOOT_ARCH: CMOS RTC Not Present
+        #[cfg(target_arch = "x86_64")]
+        if !device_manager.legacy_devices() {
+            facp.write(109, 1u16 << 5);
+        }
     }
 
     // aarch64 specific fields
@@ -270,12 +342,15 @@ fn create_mcfg_table(pci_segments: &[PciSegment]) -> Sdt {
 }
 
 fn create_tpm2_table() -> Sdt {
-    let mut tpm = Sdt::new(*b"TPM2", 52, 3, *b"CLOUDH", *b"CHTPM2  ", 1);
+    let mut tpm = Sdt::new(*b"TPM2", 76, 4, *b"CLOUDH", *b"CHTPM2  ", 1);
 
     tpm.write(36, 0_u16); //Platform Class
     tpm.write(38, 0_u16); // Reserved Space
     tpm.write(40, 0xfed4_0040_u64); // Address of Control Area
     tpm.write(48, 7_u32); //Start Method
+                          // Start Method Specific Parameters (52..64) are left empty
+    tpm.write(64, arch::layout::TPM_EVENT_LOG_SIZE as u32); // Log Area Minimum Length
+    tpm.write(68, arch::layout::TPM_EVENT_LOG_START.0); // Log Area Start Address
 
     tpm.update_checksum();
     tpm
@@ -324,9 +399,9 @@ fn create_srat_table(
 
         for cpu in &node.cpus {
             #[cfg(target_arch = "x86_64")]
-            let x2apic_id = arch::x86_64::get_x2apic_id(*cpu as u32, topology);
+            let x2apic_id = arch::x86_64::get_x2apic_id(*cpu, topology);
             #[cfg(target_arch = "aarch64")]
-            let x2apic_id = *cpu as u32;
+            let x2apic_id = *cpu;
 
             // Flags
             // - Enabled = 1 (bit 0)
@@ -357,6 +432,71 @@ fn create_srat_table(
     srat
 }
 
+fn create_nfit_table(nvdimms: &[Nvdimm]) -> Sdt {
+    // Persistent memory range type, 66F0D379-B4F3-4074-AC43-0D3318B78CDB
+    const NFIT_SPA_PERSISTENT_MEMORY: [u8; 16] = [
+        0x79, 0xd3, 0xf0, 0x66, 0xf3, 0xb4, 0x74, 0x40, 0xac, 0x43, 0x0d, 0x33, 0x18, 0xb7, 0x8c,
+        0xdb,
+    ];
+    // EFI_MEMORY_WB | EFI_MEMORY_NV
+    const NFIT_SPA_MEMORY_ATTRIBUTES: u64 = 0x8 | 0x8000;
+    // Byte addressable, energy backed
+    const NFIT_FORMAT_INTERFACE_CODE: u16 = 0x301;
+
+    let mut nfit = Sdt::new(*b"NFIT", 36, 1, *b"CLOUDH", *b"CHNFIT  ", 1);
+    // NFIT reserved 4 bytes
+    nfit.append(0u32);
+
+    // Check the structures are the right size as expected by the ACPI
+    // specification.
+    assert_eq!(std::mem::size_of::<NfitSpaRange>(), 56);
+    assert_eq!(std::mem::size_of::<NfitRegionMapping>(), 48);
+    assert_eq!(std::mem::size_of::<NfitControlRegion>(), 80);
+
+    for (i, nvdimm) in nvdimms.iter().enumerate() {
+        // Each NVDIMM gets its own range, mapping and control region
+        let index = i as u16 + 1;
+
+        nfit.append(NfitSpaRange {
+            type_: 0,
+            length: 56,
+            range_index: index,
+            range_type: NFIT_SPA_PERSISTENT_MEMORY,
+            base_address: nvdimm.base.raw_value(),
+            range_length: nvdimm.size,
+            memory_attributes: NFIT_SPA_MEMORY_ATTRIBUTES,
+            ..Default::default()
+        });
+
+        nfit.append(NfitRegionMapping {
+            type_: 1,
+            length: 48,
+            device_handle: nvdimm.handle,
+            physical_id: index,
+            range_index: index,
+            control_region_index: index,
+            region_size: nvdimm.size,
+            interleave_ways: 1,
+            ..Default::default()
+        });
+
+        nfit.append(NfitControlRegion {
+            type_: 4,
+            length: 80,
+            control_region_index: index,
+            vendor_id: 0x8086,
+            device_id: 0x7,
+            revision_id: 1,
+            serial_number: nvdimm.handle,
+            format_interface_code: NFIT_FORMAT_INTERFACE_CODE,
+            ..Default::default()
+        });
+    }
+
+    nfit.update_checksum();
+    nfit
+}
+
 fn create_slit_table(numa_nodes: &NumaNodes) -> Sdt {
     let mut slit = Sdt::new(*b"SLIT", 36, 1, *b"CLOUDH", *b"CHSLIT  ", 1);
     // Number of System Localities on 8 bytes.
@@ -813,6 +953,18 @@ pub fn create_acpi_tables(
         prev_tbl_off = viot_offset;
     }
 
+    // NFIT
+    if let Some(nvdimm_controller) = device_manager.lock().unwrap().nvdimm_controller() {
+        let nfit = create_nfit_table(nvdimm_controller.lock().unwrap().nvdimms());
+        let nfit_offset = prev_tbl_off.checked_add(prev_tbl_len).unwrap();
+        guest_mem
+            .write_slice(nfit.as_slice(), nfit_offset)
+            .expect("Error writing NFIT table");
+        tables.push(nfit_offset.0);
+        prev_tbl_len = nfit.len() as u64;
+        prev_tbl_off = nfit_offset;
+    }
+
     // XSDT
     let mut xsdt = Sdt::new(*b"XSDT", 36, 1, *b"CLOUDH", *b"CHXSDT  ", 1);
     for table in tables {
@@ -824,6 +976,14 @@ pub fn create_acpi_tables(
         .write_slice(xsdt.as_slice(), xsdt_offset)
         .expect("Error writing XSDT table");
 
+    // The TPM event log follows the tables
+    if tpm_enabled {
+        assert!(
+            xsdt_offset.0 + xsdt.len() as u64 <= arch::layout::TPM_EVENT_LOG_START.0,
+            "ACPI tables overlap the TPM event log"
+        );
+    }
+
     // RSDP
     let rsdp = Rsdp::new(*b"CLOUDH", xsdt_offset.0);
     guest_mem
@@ -18,13 +18,17 @@ use zbus::interface;
 use zbus::zvariant::Optional;
 
 use super::{ApiAction, ApiRequest};
+#[cfg(target_arch = "x86_64")]
+use crate::api::VmAddSgxEpc;
 #[cfg(all(target_arch = "x86_64", feature = "guest_debug"))]
 use crate::api::VmCoredump;
 use crate::api::{
-    AddDisk, Body, VmAddDevice, VmAddFs, VmAddNet, VmAddPmem, VmAddUserDevice, VmAddVdpa,
-    VmAddVsock, VmBoot, VmCounters, VmCreate, VmDelete, VmInfo, VmPause, VmPowerButton, VmReboot,
-    VmReceiveMigration, VmRemoveDevice, VmResize, VmResizeZone, VmRestore, VmResume,
-    VmSendMigration, VmShutdown, VmSnapshot, VmmPing, VmmShutdown,
+    AddDisk, Body, VmAddDevice, VmAddFs, VmAddNet, VmAddPciSegment, VmAddPmem, VmAddRateLimitGroup,
+    VmAddUserDevice, VmAddVdpa, VmAddVsock, VmBackup, VmBoot, VmConsoleLog, VmCounters, VmCreate,
+    VmDelete, VmDiskSnapshot, VmExportConfig, VmGuestCommand, VmHibernate, VmInfo, VmPause,
+    VmPowerButton, VmReboot, VmReceiveMigration, VmRemoveDevice, VmResetDevice, VmResize,
+    VmResizeDisk, VmResizeZone, VmRestore, VmResume, VmSendMigration, VmSetBalloon,
+    VmSetRateLimitGroup, VmShutdown, VmSnapshot, VmmPing, VmmShutdown,
 };
 use crate::seccomp_filters::{get_seccomp_filter, Thread};
 use crate::{Error as VmmError, NetConfig, Result as VmmResult, VmConfig};
@@ -152,11 +156,40 @@ impl DBusApi {
         self.vm_action(&VmAddNet, net_config).await
     }
 
+    async fn vm_add_pci_segment(&self) -> Result<Optional<String>> {
+        self.vm_action(&VmAddPciSegment, ()).await
+    }
+
     async fn vm_add_pmem(&self, pmem_config: String) -> Result<Optional<String>> {
         let pmem_config = serde_json::from_str(&pmem_config).map_err(api_error)?;
         self.vm_action(&VmAddPmem, pmem_config).await
     }
 
+    #[allow(unused_variables)]
+    // zbus doesn't support cfg attributes on interface methods
+    // as a workaround, we make the *call to the internal API* conditionally
+    // compile and return an error on unsupported platforms.
+    async fn vm_add_sgx_epc(&self, sgx_epc_config: String) -> Result<()> {
+        #[cfg(target_arch = "x86_64")]
+        {
+            let sgx_epc_config = serde_json::from_str(&sgx_epc_config).map_err(api_error)?;
+            self.vm_action(&VmAddSgxEpc, sgx_epc_config)
+                .await
+                .map(|_| ())
+        }
+
+        #[cfg(not(target_arch = "x86_64"))]
+        Err(api_error("VmAddSgxEpc only works on x86_64"))
+    }
+
+    async fn vm_add_rate_limit_group(&self, rate_limit_group_config: String) -> Result<()> {
+        let rate_limit_group_config =
+            serde_json::from_str(&rate_limit_group_config).map_err(api_error)?;
+        self.vm_action(&VmAddRateLimitGroup, rate_limit_group_config)
+            .await
+            .map(|_| ())
+    }
+
     async fn vm_add_user_device(&self, vm_add_user_device: String) -> Result<Optional<String>> {
         let vm_add_user_device = serde_json::from_str(&vm_add_user_device).map_err(api_error)?;
         self.vm_action(&VmAddUserDevice, vm_add_user_device).await
@@ -172,6 +205,11 @@ impl DBusApi {
         self.vm_action(&VmAddVsock, vsock_config).await
     }
 
+    async fn vm_backup(&self, vm_backup: String) -> Result<()> {
+        let vm_backup = serde_json::from_str(&vm_backup).map_err(api_error)?;
+        self.vm_action(&VmBackup, vm_backup).await.map(|_| ())
+    }
+
     async fn vm_boot(&self) -> Result<()> {
         self.vm_action(&VmBoot, ()).await.map(|_| ())
     }
@@ -195,10 +233,18 @@ impl DBusApi {
         ))
     }
 
+    async fn vm_console_log(&self) -> Result<Optional<String>> {
+        self.vm_action(&VmConsoleLog, ()).await
+    }
+
     async fn vm_counters(&self) -> Result<Optional<String>> {
         self.vm_action(&VmCounters, ()).await
     }
 
+    async fn vm_export_config(&self) -> Result<Optional<String>> {
+        self.vm_action(&VmExportConfig, ()).await
+    }
+
     async fn vm_create(&self, vm_config: String) -> Result<()> {
         let api_sender = self.clone_api_sender().await;
         let api_notifier = self.clone_api_notifier()?;
@@ -225,6 +271,25 @@ impl DBusApi {
         self.vm_action(&VmDelete, ()).await.map(|_| ())
     }
 
+    async fn vm_disk_snapshot(&self, vm_disk_snapshot: String) -> Result<()> {
+        let vm_disk_snapshot = serde_json::from_str(&vm_disk_snapshot).map_err(api_error)?;
+        self.vm_action(&VmDiskSnapshot, vm_disk_snapshot)
+            .await
+            .map(|_| ())
+    }
+
+    async fn vm_guest_command(&self, guest_command_data: String) -> Result<Optional<String>> {
+        let guest_command_data = serde_json::from_str(&guest_command_data).map_err(api_error)?;
+        self.vm_action(&VmGuestCommand, guest_command_data).await
+    }
+
+    async fn vm_hibernate(&self, vm_hibernate_config: String) -> Result<()> {
+        let vm_hibernate_config = serde_json::from_str(&vm_hibernate_config).map_err(api_error)?;
+        self.vm_action(&VmHibernate, vm_hibernate_config)
+            .await
+            .map(|_| ())
+    }
+
     async fn vm_info(&self) -> Result<String> {
         let api_sender = self.clone_api_sender().await;
         let api_notifier = self.clone_api_notifier()?;
@@ -254,11 +319,25 @@ impl DBusApi {
             .map(|_| ())
     }
 
+    async fn vm_reset_device(&self, vm_reset_device: String) -> Result<()> {
+        let vm_reset_device = serde_json::from_str(&vm_reset_device).map_err(api_error)?;
+        self.vm_action(&VmResetDevice, vm_reset_device)
+            .await
+            .map(|_| ())
+    }
+
     async fn vm_resize(&self, vm_resize: String) -> Result<()> {
         let vm_resize = serde_json::from_str(&vm_resize).map_err(api_error)?;
         self.vm_action(&VmResize, vm_resize).await.map(|_| ())
     }
 
+    async fn vm_resize_disk(&self, vm_resize_disk: String) -> Result<()> {
+        let vm_resize_disk = serde_json::from_str(&vm_resize_disk).map_err(api_error)?;
+        self.vm_action(&VmResizeDisk, vm_resize_disk)
+            .await
+            .map(|_| ())
+    }
+
     async fn vm_resize_zone(&self, vm_resize_zone: String) -> Result<()> {
         let vm_resize_zone = serde_json::from_str(&vm_resize_zone).map_err(api_error)?;
         self.vm_action(&VmResizeZone, vm_resize_zone)
@@ -290,6 +369,21 @@ impl DBusApi {
         self.vm_action(&VmResume, ()).await.map(|_| ())
     }
 
+    async fn vm_set_balloon(&self, vm_set_balloon: String) -> Result<()> {
+        let vm_set_balloon = serde_json::from_str(&vm_set_balloon).map_err(api_error)?;
+        self.vm_action(&VmSetBalloon, vm_set_balloon)
+            .await
+            .map(|_| ())
+    }
+
+    async fn vm_set_rate_limit_group(&self, vm_set_rate_limit_group: String) -> Result<()> {
+        let vm_set_rate_limit_group =
+            serde_json::from_str(&vm_set_rate_limit_group).map_err(api_error)?;
+        self.vm_action(&VmSetRateLimitGroup, vm_set_rate_limit_group)
+            .await
+            .map(|_| ())
+    }
+
     async fn vm_shutdown(&self) -> Result<()> {
         self.vm_action(&VmShutdown, ()).await.map(|_| ())
     }
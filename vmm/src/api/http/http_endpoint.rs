@@ -12,13 +12,18 @@ use micro_http::{Body, Method, Request, Response, StatusCode, Version};
 use vmm_sys_util::eventfd::EventFd;
 
 use crate::api::http::{error_response, EndpointHandler, HttpError};
+#[cfg(target_arch = "x86_64")]
+use crate::api::VmAddSgxEpc;
 #[cfg(all(target_arch = "x86_64", feature = "guest_debug"))]
 use crate::api::VmCoredump;
 use crate::api::{
-    AddDisk, ApiAction, ApiError, ApiRequest, NetConfig, VmAddDevice, VmAddFs, VmAddNet, VmAddPmem,
-    VmAddUserDevice, VmAddVdpa, VmAddVsock, VmBoot, VmConfig, VmCounters, VmDelete, VmNmi, VmPause,
-    VmPowerButton, VmReboot, VmReceiveMigration, VmRemoveDevice, VmResize, VmResizeZone, VmRestore,
-    VmResume, VmSendMigration, VmShutdown, VmSnapshot,
+    AddDisk, ApiAction, ApiError, ApiRequest, DiskConfig, NetConfig, VmAddDevice, VmAddFs,
+    VmAddNet, VmAddPciSegment, VmAddPmem, VmAddRateLimitGroup, VmAddUserDevice, VmAddVdpa,
+    VmAddVsock, VmBackup, VmBoot, VmConfig, VmConsoleLog, VmCounters, VmDelete, VmDiskSnapshot,
+    VmExportConfig, VmGuestCommand, VmHibernate, VmNmi, VmPause, VmPowerButton, VmReboot,
+    VmReceiveMigration, VmRefreshCertificates, VmRemoveDevice, VmResetDevice, VmResize,
+    VmResizeDisk, VmResizeZone, VmRestore, VmResume, VmSendMigration, VmSetBalloon,
+    VmSetRateLimitGroup, VmShutdown, VmSnapshot,
 };
 use crate::config::RestoreConfig;
 use crate::cpu::Error as CpuError;
@@ -55,6 +60,50 @@ impl EndpointHandler for VmCreate {
                             }
                         }
 
+                        // Payload components and disk keys given as FDs are
+                        // expected to be sent along with the request, in order.
+                        let mut config_fds = vm_config
+                            .payload
+                            .as_mut()
+                            .map(|payload| payload.fds_mut())
+                            .unwrap_or_default();
+                        config_fds.extend(
+                            vm_config
+                                .disks
+                                .iter_mut()
+                                .flatten()
+                                .filter_map(|disk| disk.key_fd.as_mut()),
+                        );
+                        if config_fds.len() != req.files.len() {
+                            warn!(
+                                "Expected {} payload and disk key FDs, got {}",
+                                config_fds.len(),
+                                req.files.len()
+                            );
+                            return error_response(HttpError::BadRequest, StatusCode::BadRequest);
+                        }
+
+                        let mut fds = Vec::new();
+                        let files = req.files.iter();
+                        for (config_fd, file) in config_fds.iter_mut().zip(files) {
+                            // Cloning the file dup() its FD, the request
+                            // still owning the original one.
+                            let fd = match file.try_clone() {
+                                Ok(file) => file.into_raw_fd(),
+                                Err(_) => {
+                                    return error_response(
+                                        HttpError::InternalServerError,
+                                        StatusCode::InternalServerError,
+                                    )
+                                }
+                            };
+                            **config_fd = fd;
+                            fds.push(fd);
+                        }
+                        // SAFETY: the FDs have just been duplicated from the
+                        // ones received along with the request.
+                        unsafe { vm_config.add_preserved_fds(fds) };
+
                         match crate::api::VmCreate
                             .send(api_notifier, api_sender, vm_config)
                             .map_err(HttpError::ApiError)
@@ -166,7 +215,9 @@ macro_rules! vm_action_put_handler_body {
     };
 }
 
+vm_action_get_handler!(VmConsoleLog);
 vm_action_get_handler!(VmCounters);
+vm_action_get_handler!(VmExportConfig);
 
 vm_action_put_handler!(VmBoot);
 vm_action_put_handler!(VmDelete);
@@ -176,19 +227,32 @@ vm_action_put_handler!(VmPause);
 vm_action_put_handler!(VmResume);
 vm_action_put_handler!(VmPowerButton);
 vm_action_put_handler!(VmNmi);
+vm_action_put_handler!(VmRefreshCertificates);
+vm_action_put_handler!(VmAddPciSegment);
 
 vm_action_put_handler_body!(VmAddDevice);
-vm_action_put_handler_body!(AddDisk);
 vm_action_put_handler_body!(VmAddFs);
 vm_action_put_handler_body!(VmAddPmem);
 vm_action_put_handler_body!(VmAddVdpa);
 vm_action_put_handler_body!(VmAddVsock);
+vm_action_put_handler_body!(VmAddRateLimitGroup);
 vm_action_put_handler_body!(VmAddUserDevice);
 vm_action_put_handler_body!(VmRemoveDevice);
+vm_action_put_handler_body!(VmResetDevice);
+vm_action_put_handler_body!(VmResizeDisk);
 vm_action_put_handler_body!(VmResizeZone);
+vm_action_put_handler_body!(VmSetBalloon);
+vm_action_put_handler_body!(VmSetRateLimitGroup);
 vm_action_put_handler_body!(VmSnapshot);
+vm_action_put_handler_body!(VmHibernate);
 vm_action_put_handler_body!(VmReceiveMigration);
 vm_action_put_handler_body!(VmSendMigration);
+vm_action_put_handler_body!(VmGuestCommand);
+vm_action_put_handler_body!(VmBackup);
+vm_action_put_handler_body!(VmDiskSnapshot);
+
+#[cfg(target_arch = "x86_64")]
+vm_action_put_handler_body!(VmAddSgxEpc);
 
 #[cfg(all(target_arch = "x86_64", feature = "guest_debug"))]
 vm_action_put_handler_body!(VmCoredump);
@@ -221,6 +285,34 @@ impl PutHandler for VmAddNet {
 
 impl GetHandler for VmAddNet {}
 
+impl PutHandler for AddDisk {
+    fn handle_request(
+        &'static self,
+        api_notifier: EventFd,
+        api_sender: Sender<ApiRequest>,
+        body: &Option<Body>,
+        mut files: Vec<File>,
+    ) -> std::result::Result<Option<Body>, HttpError> {
+        if let Some(body) = body {
+            let mut disk_cfg: DiskConfig = serde_json::from_slice(body.raw())?;
+            if disk_cfg.key_fd.is_some() {
+                warn!("Ignoring FDs sent via the HTTP request body");
+                disk_cfg.key_fd = None;
+            }
+            // The key of an encrypted disk is sent along with the request.
+            if let Some(file) = files.pop() {
+                disk_cfg.key_fd = Some(file.into_raw_fd());
+            }
+            self.send(api_notifier, api_sender, disk_cfg)
+                .map_err(HttpError::ApiError)
+        } else {
+            Err(HttpError::BadRequest)
+        }
+    }
+}
+
+impl GetHandler for AddDisk {}
+
 impl PutHandler for VmResize {
     fn handle_request(
         &'static self,
@@ -24,13 +24,18 @@ use thiserror::Error;
 use vmm_sys_util::eventfd::EventFd;
 
 use self::http_endpoint::{VmActionHandler, VmCreate, VmInfo, VmmPing, VmmShutdown};
+#[cfg(target_arch = "x86_64")]
+use crate::api::VmAddSgxEpc;
 #[cfg(all(target_arch = "x86_64", feature = "guest_debug"))]
 use crate::api::VmCoredump;
 use crate::api::{
-    AddDisk, ApiError, ApiRequest, VmAddDevice, VmAddFs, VmAddNet, VmAddPmem, VmAddUserDevice,
-    VmAddVdpa, VmAddVsock, VmBoot, VmCounters, VmDelete, VmNmi, VmPause, VmPowerButton, VmReboot,
-    VmReceiveMigration, VmRemoveDevice, VmResize, VmResizeZone, VmRestore, VmResume,
-    VmSendMigration, VmShutdown, VmSnapshot,
+    with_target_vm, AddDisk, ApiError, ApiRequest, VmAddDevice, VmAddFs, VmAddNet, VmAddPciSegment,
+    VmAddPmem, VmAddRateLimitGroup, VmAddUserDevice, VmAddVdpa, VmAddVsock, VmBackup, VmBoot,
+    VmConsoleLog, VmCounters, VmDelete, VmDiskSnapshot, VmExportConfig, VmGuestCommand,
+    VmHibernate, VmNmi, VmPause, VmPowerButton, VmReboot, VmReceiveMigration,
+    VmRefreshCertificates, VmRemoveDevice, VmResetDevice, VmResize, VmResizeDisk, VmResizeZone,
+    VmRestore, VmResume, VmSendMigration, VmSetBalloon, VmSetRateLimitGroup, VmShutdown,
+    VmSnapshot,
 };
 use crate::landlock::Landlock;
 use crate::seccomp_filters::{get_seccomp_filter, Thread};
@@ -69,6 +74,7 @@ pub enum HttpError {
 }
 
 const HTTP_ROOT: &str = "/api/v1";
+const HTTP_VMS_ROOT: &str = "/api/v1/vms/";
 
 /// Creates the error response's JSON body meant to be sent back to an API client.
 ///
@@ -182,6 +188,10 @@ pub static HTTP_ROUTES: Lazy<HttpRoutes> = Lazy::new(|| {
         endpoint!("/vm.add-device"),
         Box::new(VmActionHandler::new(&VmAddDevice)),
     );
+    r.routes.insert(
+        endpoint!("/vm.add-rate-limit-group"),
+        Box::new(VmActionHandler::new(&VmAddRateLimitGroup)),
+    );
     r.routes.insert(
         endpoint!("/vm.add-user-device"),
         Box::new(VmActionHandler::new(&VmAddUserDevice)),
@@ -198,10 +208,19 @@ pub static HTTP_ROUTES: Lazy<HttpRoutes> = Lazy::new(|| {
         endpoint!("/vm.add-net"),
         Box::new(VmActionHandler::new(&VmAddNet)),
     );
+    r.routes.insert(
+        endpoint!("/vm.add-pci-segment"),
+        Box::new(VmActionHandler::new(&VmAddPciSegment)),
+    );
     r.routes.insert(
         endpoint!("/vm.add-pmem"),
         Box::new(VmActionHandler::new(&VmAddPmem)),
     );
+    #[cfg(target_arch = "x86_64")]
+    r.routes.insert(
+        endpoint!("/vm.add-sgx-epc"),
+        Box::new(VmActionHandler::new(&VmAddSgxEpc)),
+    );
     r.routes.insert(
         endpoint!("/vm.add-vdpa"),
         Box::new(VmActionHandler::new(&VmAddVdpa)),
@@ -210,10 +229,22 @@ pub static HTTP_ROUTES: Lazy<HttpRoutes> = Lazy::new(|| {
         endpoint!("/vm.add-vsock"),
         Box::new(VmActionHandler::new(&VmAddVsock)),
     );
+    r.routes.insert(
+        endpoint!("/vm.backup"),
+        Box::new(VmActionHandler::new(&VmBackup)),
+    );
     r.routes.insert(
         endpoint!("/vm.boot"),
         Box::new(VmActionHandler::new(&VmBoot)),
     );
+    r.routes.insert(
+        endpoint!("/vm.config"),
+        Box::new(VmActionHandler::new(&VmExportConfig)),
+    );
+    r.routes.insert(
+        endpoint!("/vm.console-log"),
+        Box::new(VmActionHandler::new(&VmConsoleLog)),
+    );
     r.routes.insert(
         endpoint!("/vm.counters"),
         Box::new(VmActionHandler::new(&VmCounters)),
@@ -224,6 +255,18 @@ pub static HTTP_ROUTES: Lazy<HttpRoutes> = Lazy::new(|| {
         endpoint!("/vm.delete"),
         Box::new(VmActionHandler::new(&VmDelete)),
     );
+    r.routes.insert(
+        endpoint!("/vm.disk-snapshot"),
+        Box::new(VmActionHandler::new(&VmDiskSnapshot)),
+    );
+    r.routes.insert(
+        endpoint!("/vm.guest-command"),
+        Box::new(VmActionHandler::new(&VmGuestCommand)),
+    );
+    r.routes.insert(
+        endpoint!("/vm.hibernate"),
+        Box::new(VmActionHandler::new(&VmHibernate)),
+    );
     r.routes.insert(endpoint!("/vm.info"), Box::new(VmInfo {}));
     r.routes.insert(
         endpoint!("/vm.pause"),
@@ -241,14 +284,26 @@ pub static HTTP_ROUTES: Lazy<HttpRoutes> = Lazy::new(|| {
         endpoint!("/vm.receive-migration"),
         Box::new(VmActionHandler::new(&VmReceiveMigration)),
     );
+    r.routes.insert(
+        endpoint!("/vm.refresh-certificates"),
+        Box::new(VmActionHandler::new(&VmRefreshCertificates)),
+    );
     r.routes.insert(
         endpoint!("/vm.remove-device"),
         Box::new(VmActionHandler::new(&VmRemoveDevice)),
     );
+    r.routes.insert(
+        endpoint!("/vm.reset-device"),
+        Box::new(VmActionHandler::new(&VmResetDevice)),
+    );
     r.routes.insert(
         endpoint!("/vm.resize"),
         Box::new(VmActionHandler::new(&VmResize)),
     );
+    r.routes.insert(
+        endpoint!("/vm.resize-disk"),
+        Box::new(VmActionHandler::new(&VmResizeDisk)),
+    );
     r.routes.insert(
         endpoint!("/vm.resize-zone"),
         Box::new(VmActionHandler::new(&VmResizeZone)),
@@ -265,6 +320,14 @@ pub static HTTP_ROUTES: Lazy<HttpRoutes> = Lazy::new(|| {
         endpoint!("/vm.send-migration"),
         Box::new(VmActionHandler::new(&VmSendMigration)),
     );
+    r.routes.insert(
+        endpoint!("/vm.set-balloon"),
+        Box::new(VmActionHandler::new(&VmSetBalloon)),
+    );
+    r.routes.insert(
+        endpoint!("/vm.set-rate-limit-group"),
+        Box::new(VmActionHandler::new(&VmSetRateLimitGroup)),
+    );
     r.routes.insert(
         endpoint!("/vm.shutdown"),
         Box::new(VmActionHandler::new(&VmShutdown)),
@@ -294,15 +357,22 @@ fn handle_http_request(
     api_sender: &Sender<ApiRequest>,
 ) -> Response {
     let path = request.uri().get_abs_path().to_string();
-    let mut response = match HTTP_ROUTES.routes.get(&path) {
-        Some(route) => match api_notifier.try_clone() {
-            Ok(notifier) => route.handle_request(request, notifier, api_sender.clone()),
-            Err(_) => error_response(
-                HttpError::InternalServerError,
-                StatusCode::InternalServerError,
-            ),
-        },
-        None => error_response(HttpError::NotFound, StatusCode::NotFound),
+    let vms_action = path
+        .strip_prefix(HTTP_VMS_ROOT)
+        .and_then(|path| path.split_once('/'));
+    let mut response = if let Some((id, action)) = vms_action {
+        handle_vms_request(request, id, action, api_notifier, api_sender)
+    } else {
+        match HTTP_ROUTES.routes.get(&path) {
+            Some(route) => match api_notifier.try_clone() {
+                Ok(notifier) => route.handle_request(request, notifier, api_sender.clone()),
+                Err(_) => error_response(
+                    HttpError::InternalServerError,
+                    StatusCode::InternalServerError,
+                ),
+            },
+            None => error_response(HttpError::NotFound, StatusCode::NotFound),
+        }
     };
 
     response.set_server("Cloud Hypervisor API");
@@ -310,6 +380,31 @@ fn handle_http_request(
     response
 }
 
+// Handles a request of the VM of the given id of the multi-VM mode.
+fn handle_vms_request(
+    request: &Request,
+    id: &str,
+    action: &str,
+    api_notifier: &EventFd,
+    api_sender: &Sender<ApiRequest>,
+) -> Response {
+    // Only the VM actions apply to the VMs of the multi-VM mode.
+    let route = match HTTP_ROUTES.routes.get(&endpoint!(format!("/{action}"))) {
+        Some(route) if !id.is_empty() && action.starts_with("vm.") => route,
+        _ => return error_response(HttpError::NotFound, StatusCode::NotFound),
+    };
+
+    match api_notifier.try_clone() {
+        Ok(notifier) => with_target_vm(id, || {
+            route.handle_request(request, notifier, api_sender.clone())
+        }),
+        Err(_) => error_response(
+            HttpError::InternalServerError,
+            StatusCode::InternalServerError,
+        ),
+    }
+}
+
 fn start_http_thread(
     mut server: HttpServer,
     api_notifier: EventFd,
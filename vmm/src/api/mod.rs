@@ -33,12 +33,15 @@
 pub mod dbus;
 pub mod http;
 
+use std::cell::RefCell;
 use std::io;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::{channel, RecvError, SendError, Sender};
 
 use micro_http::Body;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
+use vm_device::ExitReason;
 use vm_migration::MigratableError;
 use vmm_sys_util::eventfd::EventFd;
 
@@ -48,15 +51,21 @@ pub use self::http::{start_http_fd_thread, start_http_path_thread};
 use crate::config::RestoreConfig;
 use crate::device_tree::DeviceTree;
 use crate::vm::{Error as VmError, VmState};
+#[cfg(target_arch = "x86_64")]
+use crate::vm_config::SgxEpcConfig;
 use crate::vm_config::{
-    DeviceConfig, DiskConfig, FsConfig, NetConfig, PmemConfig, UserDeviceConfig, VdpaConfig,
-    VmConfig, VsockConfig,
+    DeviceConfig, DiskConfig, FsConfig, NetConfig, PmemConfig, RateLimiterGroupConfig,
+    UserDeviceConfig, VdpaConfig, VmConfig, VsockConfig,
 };
 use crate::Error as VmmError;
 
 /// API errors are sent back from the VMM API server through the ApiResponse.
 #[derive(Error, Debug)]
 pub enum ApiError {
+    /// Cannot clone EventFd.
+    #[error("Cannot clone EventFd")]
+    EventFdClone(#[source] io::Error),
+
     /// Cannot write to EventFd.
     #[error("Cannot write to EventFd")]
     EventFdWrite(#[source] io::Error),
@@ -89,6 +98,10 @@ pub enum ApiError {
     #[error("The VM info is not available")]
     VmInfo(#[source] VmError),
 
+    /// The console output is not available.
+    #[error("The console output is not available")]
+    VmConsoleLog(#[source] VmError),
+
     /// The VM could not be paused.
     #[error("The VM could not be paused")]
     VmPause(#[source] VmError),
@@ -121,6 +134,10 @@ pub enum ApiError {
     #[error("The VM could not restored")]
     VmRestore(#[source] VmError),
 
+    /// The VM could not be hibernated.
+    #[error("The VM could not be hibernated")]
+    VmHibernate(#[source] VmError),
+
     /// The VM could not be coredumped.
     #[error("The VM could not be coredumped")]
     VmCoredump(#[source] VmError),
@@ -149,6 +166,26 @@ pub enum ApiError {
     #[error("The device could not be removed from the VM")]
     VmRemoveDevice(#[source] VmError),
 
+    /// The device could not be reset.
+    #[error("The device could not be reset")]
+    VmResetDevice(#[source] VmError),
+
+    /// The rate limiter group could not be added to the VM.
+    #[error("The rate limiter group could not be added to the VM")]
+    VmAddRateLimitGroup(#[source] VmError),
+
+    /// The rate limiter group of the device could not be changed.
+    #[error("The rate limiter group of the device could not be changed")]
+    VmSetRateLimitGroup(#[source] VmError),
+
+    /// The features of the balloon could not be changed.
+    #[error("The features of the balloon could not be changed")]
+    VmSetBalloon(#[source] VmError),
+
+    /// The disk could not be resized.
+    #[error("The disk could not be resized")]
+    VmResizeDisk(#[source] VmError),
+
     /// Cannot create seccomp filter
     #[error("Cannot create seccomp filter")]
     CreateSeccompFilter(#[source] seccompiler::Error),
@@ -181,6 +218,15 @@ pub enum ApiError {
     #[error("The vsock device could not be added to the VM")]
     VmAddVsock(#[source] VmError),
 
+    /// The PCI segment could not be added to the VM.
+    #[error("The PCI segment could not be added to the VM")]
+    VmAddPciSegment(#[source] VmError),
+
+    /// The SGX EPC section could not be added to the VM.
+    #[cfg(target_arch = "x86_64")]
+    #[error("The SGX EPC section could not be added to the VM")]
+    VmAddSgxEpc(#[source] VmError),
+
     /// Error starting migration receiver
     #[error("Error starting migration receiver")]
     VmReceiveMigration(#[source] MigratableError),
@@ -196,6 +242,22 @@ pub enum ApiError {
     /// Error triggering NMI
     #[error("Error triggering NMI")]
     VmNmi(#[source] VmError),
+
+    /// Error refreshing the platform certificates
+    #[error("Error refreshing the platform certificates")]
+    VmRefreshCertificates(#[source] VmError),
+
+    /// The guest agent command failed
+    #[error("The guest agent command failed")]
+    VmGuestCommand(#[source] VmError),
+
+    /// The disks could not be switched to overlays for a backup.
+    #[error("The disks could not be switched to overlays for a backup")]
+    VmBackup(#[source] VmError),
+
+    /// The disk could not be switched to an overlay for a snapshot.
+    #[error("The disk could not be switched to an overlay for a snapshot")]
+    VmDiskSnapshot(#[source] VmError),
 }
 pub type ApiResult<T> = Result<T, ApiError>;
 
@@ -205,6 +267,52 @@ pub struct VmInfoResponse {
     pub state: VmState,
     pub memory_actual_size: u64,
     pub device_tree: Option<DeviceTree>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_exit_reason: Option<ExitReason>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disk_queues: Option<Vec<DiskQueueInfo>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub balloon: Option<BalloonInfo>,
+    #[cfg(target_arch = "x86_64")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sgx_epc: Option<Vec<SgxEpcSectionInfo>>,
+}
+
+/// Sizes of the queues of a disk negotiated with the guest, none until its
+/// driver activates the disk.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DiskQueueInfo {
+    pub id: String,
+    pub queue_sizes: Vec<u16>,
+}
+
+/// Features of the balloon negotiated with the guest, which may differ from
+/// the configured ones until its driver initializes the device again.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BalloonInfo {
+    pub deflate_on_oom: bool,
+    pub free_page_reporting: bool,
+}
+
+/// Latest output of the consoles duplicated to a ring buffer, for the
+/// consoles having one.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct VmConsoleLogResponse {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub serial: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub console: Option<String>,
+}
+
+/// SGX EPC section backing the guest, as laid out in its address space.
+#[cfg(target_arch = "x86_64")]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SgxEpcSectionInfo {
+    pub id: String,
+    pub start: u64,
+    pub size: u64,
+    pub prefault: bool,
+    pub guest_numa_id: Option<u32>,
 }
 
 #[derive(Clone, Deserialize, Serialize)]
@@ -217,7 +325,7 @@ pub struct VmmPingResponse {
 
 #[derive(Clone, Deserialize, Serialize, Default, Debug)]
 pub struct VmResizeData {
-    pub desired_vcpus: Option<u8>,
+    pub desired_vcpus: Option<u32>,
     pub desired_ram: Option<u64>,
     pub desired_balloon: Option<u64>,
 }
@@ -233,10 +341,38 @@ pub struct VmRemoveDeviceData {
     pub id: String,
 }
 
+#[derive(Clone, Deserialize, Serialize, Default, Debug)]
+pub struct VmResetDeviceData {
+    pub id: String,
+}
+
+#[derive(Clone, Deserialize, Serialize, Default, Debug)]
+pub struct VmSetRateLimitGroupData {
+    pub id: String,
+    /// The group to move the device to, none detaching it from any group
+    pub rate_limit_group: Option<String>,
+}
+
+/// Features of the balloon to change, the unset ones being left as they are
+#[derive(Clone, Deserialize, Serialize, Default, Debug)]
+pub struct VmSetBalloonData {
+    pub deflate_on_oom: Option<bool>,
+    pub free_page_reporting: Option<bool>,
+}
+
+#[derive(Clone, Deserialize, Serialize, Default, Debug)]
+pub struct VmResizeDiskData {
+    pub id: String,
+    pub desired_size: u64,
+}
+
 #[derive(Clone, Deserialize, Serialize, Default, Debug)]
 pub struct VmSnapshotConfig {
     /// The snapshot destination URL
     pub destination_url: String,
+    /// Key file to sign the snapshot with
+    #[serde(default)]
+    pub signing_key: Option<PathBuf>,
 }
 
 #[derive(Clone, Deserialize, Serialize, Default, Debug)]
@@ -260,6 +396,37 @@ pub struct VmSendMigrationData {
     pub local: bool,
 }
 
+#[derive(Clone, Deserialize, Serialize, Default, Debug)]
+pub struct VmGuestCommandData {
+    /// Name of the QEMU guest agent command
+    pub execute: String,
+    /// Arguments of the command
+    #[serde(default)]
+    pub arguments: Option<serde_json::Value>,
+}
+
+#[derive(Clone, Deserialize, Serialize, Default, Debug)]
+pub struct VmBackupDiskData {
+    pub id: String,
+    /// Path of the QCOW2 overlay to create, backed by the current image
+    pub overlay: PathBuf,
+}
+
+#[derive(Clone, Deserialize, Serialize, Default, Debug)]
+pub struct VmBackupData {
+    pub disks: Vec<VmBackupDiskData>,
+    /// Freeze the guest filesystems through the guest agent meanwhile
+    #[serde(default)]
+    pub quiesce: bool,
+}
+
+#[derive(Clone, Deserialize, Serialize, Default, Debug)]
+pub struct VmDiskSnapshotData {
+    pub id: String,
+    /// Path of the QCOW2 overlay to create, backed by the current image
+    pub overlay: PathBuf,
+}
+
 pub enum ApiResponsePayload {
     /// No data is sent on the channel.
     Empty,
@@ -286,10 +453,20 @@ pub trait RequestHandler {
 
     fn vm_resume(&mut self) -> Result<(), VmError>;
 
-    fn vm_snapshot(&mut self, destination_url: &str) -> Result<(), VmError>;
+    fn vm_snapshot(
+        &mut self,
+        destination_url: &str,
+        signing_key: Option<&Path>,
+    ) -> Result<(), VmError>;
 
     fn vm_restore(&mut self, restore_cfg: RestoreConfig) -> Result<(), VmError>;
 
+    fn vm_hibernate(
+        &mut self,
+        destination_url: &str,
+        signing_key: Option<&Path>,
+    ) -> Result<(), VmError>;
+
     #[cfg(all(target_arch = "x86_64", feature = "guest_debug"))]
     fn vm_coredump(&mut self, destination_url: &str) -> Result<(), VmError>;
 
@@ -305,9 +482,12 @@ pub trait RequestHandler {
 
     fn vmm_shutdown(&mut self) -> Result<(), VmError>;
 
+    /// Runs the request against the VM of the given id of the multi-VM mode.
+    fn vms_request(&mut self, id: &str, request: ApiRequest) -> Result<bool, VmmError>;
+
     fn vm_resize(
         &mut self,
-        desired_vcpus: Option<u8>,
+        desired_vcpus: Option<u32>,
         desired_ram: Option<u64>,
         desired_balloon: Option<u64>,
     ) -> Result<(), VmError>;
@@ -323,6 +503,27 @@ pub trait RequestHandler {
 
     fn vm_remove_device(&mut self, id: String) -> Result<(), VmError>;
 
+    fn vm_reset_device(&mut self, id: String) -> Result<(), VmError>;
+
+    fn vm_add_rate_limit_group(
+        &mut self,
+        rate_limit_group_cfg: RateLimiterGroupConfig,
+    ) -> Result<(), VmError>;
+
+    fn vm_set_rate_limit_group(
+        &mut self,
+        id: String,
+        rate_limit_group: Option<String>,
+    ) -> Result<(), VmError>;
+
+    fn vm_set_balloon(
+        &mut self,
+        deflate_on_oom: Option<bool>,
+        free_page_reporting: Option<bool>,
+    ) -> Result<(), VmError>;
+
+    fn vm_resize_disk(&mut self, id: String, desired_size: u64) -> Result<(), VmError>;
+
     fn vm_add_disk(&mut self, disk_cfg: DiskConfig) -> Result<Option<Vec<u8>>, VmError>;
 
     fn vm_add_fs(&mut self, fs_cfg: FsConfig) -> Result<Option<Vec<u8>>, VmError>;
@@ -335,8 +536,17 @@ pub trait RequestHandler {
 
     fn vm_add_vsock(&mut self, vsock_cfg: VsockConfig) -> Result<Option<Vec<u8>>, VmError>;
 
+    fn vm_add_pci_segment(&mut self) -> Result<Option<Vec<u8>>, VmError>;
+
+    #[cfg(target_arch = "x86_64")]
+    fn vm_add_sgx_epc(&mut self, sgx_epc_cfg: SgxEpcConfig) -> Result<(), VmError>;
+
+    fn vm_console_log(&mut self) -> Result<Option<Vec<u8>>, VmError>;
+
     fn vm_counters(&mut self) -> Result<Option<Vec<u8>>, VmError>;
 
+    fn vm_export_config(&self) -> Result<Option<Vec<u8>>, VmError>;
+
     fn vm_power_button(&mut self) -> Result<(), VmError>;
 
     fn vm_receive_migration(
@@ -350,6 +560,17 @@ pub trait RequestHandler {
     ) -> Result<(), MigratableError>;
 
     fn vm_nmi(&mut self) -> Result<(), VmError>;
+
+    fn vm_refresh_certificates(&mut self) -> Result<(), VmError>;
+
+    fn vm_guest_command(
+        &mut self,
+        guest_command_data: VmGuestCommandData,
+    ) -> Result<Option<Vec<u8>>, VmError>;
+
+    fn vm_backup(&mut self, backup_data: VmBackupData) -> Result<(), VmError>;
+
+    fn vm_disk_snapshot(&mut self, disk_snapshot_data: VmDiskSnapshotData) -> Result<(), VmError>;
 }
 
 /// It would be nice if we could pass around an object like this:
@@ -368,6 +589,21 @@ pub trait RequestHandler {
 pub type ApiRequest =
     Box<dyn FnOnce(&mut dyn RequestHandler) -> Result<bool, VmmError> + Send + 'static>;
 
+thread_local! {
+    // The VM of the multi-VM mode targeted by the requests sent from the
+    // current thread, if any.
+    static TARGET_VM: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Runs `f`, the API requests it sends targeting the VM of the given id of
+/// the multi-VM mode instead of the VM of the VMM.
+pub fn with_target_vm<R, F: FnOnce() -> R>(id: &str, f: F) -> R {
+    TARGET_VM.with(|target| target.replace(Some(id.to_owned())));
+    let r = f();
+    TARGET_VM.with(|target| target.take());
+    r
+}
+
 fn get_response<Action: ApiAction>(
     action: &Action,
     api_evt: EventFd,
@@ -377,6 +613,11 @@ fn get_response<Action: ApiAction>(
     let (response_sender, response_receiver) = channel();
 
     let request = action.request(data, response_sender);
+    // The request carries the VM it targets, for the VMM thread to route it.
+    let request: ApiRequest = match TARGET_VM.with(|target| target.borrow().clone()) {
+        Some(id) => Box::new(move |vmm| vmm.vms_request(&id, request)),
+        None => request,
+    };
 
     // Send the VM request.
     api_sender.send(request).map_err(ApiError::RequestSend)?;
@@ -673,6 +914,39 @@ impl ApiAction for VmAddVsock {
     }
 }
 
+pub struct VmAddPciSegment;
+
+impl ApiAction for VmAddPciSegment {
+    type RequestBody = ();
+    type ResponseBody = Option<Body>;
+
+    fn request(&self, _: Self::RequestBody, response_sender: Sender<ApiResponse>) -> ApiRequest {
+        Box::new(move |vmm| {
+            info!("API request event: VmAddPciSegment");
+
+            let response = vmm
+                .vm_add_pci_segment()
+                .map_err(ApiError::VmAddPciSegment)
+                .map(ApiResponsePayload::VmAction);
+
+            response_sender
+                .send(response)
+                .map_err(VmmError::ApiResponseSend)?;
+
+            Ok(false)
+        })
+    }
+
+    fn send(
+        &self,
+        api_evt: EventFd,
+        api_sender: Sender<ApiRequest>,
+        data: Self::RequestBody,
+    ) -> ApiResult<Self::ResponseBody> {
+        get_response_body(self, api_evt, api_sender, data)
+    }
+}
+
 pub struct VmAddUserDevice;
 
 impl ApiAction for VmAddUserDevice {
@@ -782,6 +1056,39 @@ impl ApiAction for VmCoredump {
     }
 }
 
+pub struct VmConsoleLog;
+
+impl ApiAction for VmConsoleLog {
+    type RequestBody = ();
+    type ResponseBody = Option<Body>;
+
+    fn request(&self, _: Self::RequestBody, response_sender: Sender<ApiResponse>) -> ApiRequest {
+        Box::new(move |vmm| {
+            info!("API request event: VmConsoleLog");
+
+            let response = vmm
+                .vm_console_log()
+                .map_err(ApiError::VmConsoleLog)
+                .map(ApiResponsePayload::VmAction);
+
+            response_sender
+                .send(response)
+                .map_err(VmmError::ApiResponseSend)?;
+
+            Ok(false)
+        })
+    }
+
+    fn send(
+        &self,
+        api_evt: EventFd,
+        api_sender: Sender<ApiRequest>,
+        data: Self::RequestBody,
+    ) -> ApiResult<Self::ResponseBody> {
+        get_response_body(self, api_evt, api_sender, data)
+    }
+}
+
 pub struct VmCounters;
 
 impl ApiAction for VmCounters {
@@ -815,6 +1122,39 @@ impl ApiAction for VmCounters {
     }
 }
 
+pub struct VmExportConfig;
+
+impl ApiAction for VmExportConfig {
+    type RequestBody = ();
+    type ResponseBody = Option<Body>;
+
+    fn request(&self, _: Self::RequestBody, response_sender: Sender<ApiResponse>) -> ApiRequest {
+        Box::new(move |vmm| {
+            info!("API request event: VmExportConfig");
+
+            let response = vmm
+                .vm_export_config()
+                .map_err(ApiError::VmInfo)
+                .map(ApiResponsePayload::VmAction);
+
+            response_sender
+                .send(response)
+                .map_err(VmmError::ApiResponseSend)?;
+
+            Ok(false)
+        })
+    }
+
+    fn send(
+        &self,
+        api_evt: EventFd,
+        api_sender: Sender<ApiRequest>,
+        data: Self::RequestBody,
+    ) -> ApiResult<Self::ResponseBody> {
+        get_response_body(self, api_evt, api_sender, data)
+    }
+}
+
 pub struct VmCreate;
 
 impl ApiAction for VmCreate {
@@ -1094,6 +1434,200 @@ impl ApiAction for VmRemoveDevice {
     }
 }
 
+pub struct VmResetDevice;
+
+impl ApiAction for VmResetDevice {
+    type RequestBody = VmResetDeviceData;
+    type ResponseBody = Option<Body>;
+
+    fn request(
+        &self,
+        reset_device_data: Self::RequestBody,
+        response_sender: Sender<ApiResponse>,
+    ) -> ApiRequest {
+        Box::new(move |vmm| {
+            info!("API request event: VmResetDevice {:?}", reset_device_data);
+
+            let response = vmm
+                .vm_reset_device(reset_device_data.id)
+                .map_err(ApiError::VmResetDevice)
+                .map(|_| ApiResponsePayload::Empty);
+
+            response_sender
+                .send(response)
+                .map_err(VmmError::ApiResponseSend)?;
+
+            Ok(false)
+        })
+    }
+
+    fn send(
+        &self,
+        api_evt: EventFd,
+        api_sender: Sender<ApiRequest>,
+        data: Self::RequestBody,
+    ) -> ApiResult<Self::ResponseBody> {
+        get_response_body(self, api_evt, api_sender, data)
+    }
+}
+
+pub struct VmAddRateLimitGroup;
+
+impl ApiAction for VmAddRateLimitGroup {
+    type RequestBody = RateLimiterGroupConfig;
+    type ResponseBody = Option<Body>;
+
+    fn request(
+        &self,
+        config: Self::RequestBody,
+        response_sender: Sender<ApiResponse>,
+    ) -> ApiRequest {
+        Box::new(move |vmm| {
+            info!("API request event: VmAddRateLimitGroup {:?}", config);
+
+            let response = vmm
+                .vm_add_rate_limit_group(config)
+                .map_err(ApiError::VmAddRateLimitGroup)
+                .map(|_| ApiResponsePayload::Empty);
+
+            response_sender
+                .send(response)
+                .map_err(VmmError::ApiResponseSend)?;
+
+            Ok(false)
+        })
+    }
+
+    fn send(
+        &self,
+        api_evt: EventFd,
+        api_sender: Sender<ApiRequest>,
+        data: Self::RequestBody,
+    ) -> ApiResult<Self::ResponseBody> {
+        get_response_body(self, api_evt, api_sender, data)
+    }
+}
+
+pub struct VmSetRateLimitGroup;
+
+impl ApiAction for VmSetRateLimitGroup {
+    type RequestBody = VmSetRateLimitGroupData;
+    type ResponseBody = Option<Body>;
+
+    fn request(
+        &self,
+        rate_limit_group_data: Self::RequestBody,
+        response_sender: Sender<ApiResponse>,
+    ) -> ApiRequest {
+        Box::new(move |vmm| {
+            info!(
+                "API request event: VmSetRateLimitGroup {:?}",
+                rate_limit_group_data
+            );
+
+            let response = vmm
+                .vm_set_rate_limit_group(
+                    rate_limit_group_data.id,
+                    rate_limit_group_data.rate_limit_group,
+                )
+                .map_err(ApiError::VmSetRateLimitGroup)
+                .map(|_| ApiResponsePayload::Empty);
+
+            response_sender
+                .send(response)
+                .map_err(VmmError::ApiResponseSend)?;
+
+            Ok(false)
+        })
+    }
+
+    fn send(
+        &self,
+        api_evt: EventFd,
+        api_sender: Sender<ApiRequest>,
+        data: Self::RequestBody,
+    ) -> ApiResult<Self::ResponseBody> {
+        get_response_body(self, api_evt, api_sender, data)
+    }
+}
+
+pub struct VmSetBalloon;
+
+impl ApiAction for VmSetBalloon {
+    type RequestBody = VmSetBalloonData;
+    type ResponseBody = Option<Body>;
+
+    fn request(
+        &self,
+        set_balloon_data: Self::RequestBody,
+        response_sender: Sender<ApiResponse>,
+    ) -> ApiRequest {
+        Box::new(move |vmm| {
+            info!("API request event: VmSetBalloon {:?}", set_balloon_data);
+
+            let response = vmm
+                .vm_set_balloon(
+                    set_balloon_data.deflate_on_oom,
+                    set_balloon_data.free_page_reporting,
+                )
+                .map_err(ApiError::VmSetBalloon)
+                .map(|_| ApiResponsePayload::Empty);
+
+            response_sender
+                .send(response)
+                .map_err(VmmError::ApiResponseSend)?;
+
+            Ok(false)
+        })
+    }
+
+    fn send(
+        &self,
+        api_evt: EventFd,
+        api_sender: Sender<ApiRequest>,
+        data: Self::RequestBody,
+    ) -> ApiResult<Self::ResponseBody> {
+        get_response_body(self, api_evt, api_sender, data)
+    }
+}
+
+pub struct VmResizeDisk;
+
+impl ApiAction for VmResizeDisk {
+    type RequestBody = VmResizeDiskData;
+    type ResponseBody = Option<Body>;
+
+    fn request(
+        &self,
+        resize_disk_data: Self::RequestBody,
+        response_sender: Sender<ApiResponse>,
+    ) -> ApiRequest {
+        Box::new(move |vmm| {
+            info!("API request event: VmResizeDisk {:?}", resize_disk_data);
+
+            let response = vmm
+                .vm_resize_disk(resize_disk_data.id, resize_disk_data.desired_size)
+                .map_err(ApiError::VmResizeDisk)
+                .map(|_| ApiResponsePayload::Empty);
+
+            response_sender
+                .send(response)
+                .map_err(VmmError::ApiResponseSend)?;
+
+            Ok(false)
+        })
+    }
+
+    fn send(
+        &self,
+        api_evt: EventFd,
+        api_sender: Sender<ApiRequest>,
+        data: Self::RequestBody,
+    ) -> ApiResult<Self::ResponseBody> {
+        get_response_body(self, api_evt, api_sender, data)
+    }
+}
+
 pub struct VmResize;
 
 impl ApiAction for VmResize {
@@ -1135,6 +1669,45 @@ impl ApiAction for VmResize {
     }
 }
 
+#[cfg(target_arch = "x86_64")]
+pub struct VmAddSgxEpc;
+
+#[cfg(target_arch = "x86_64")]
+impl ApiAction for VmAddSgxEpc {
+    type RequestBody = SgxEpcConfig;
+    type ResponseBody = Option<Body>;
+
+    fn request(
+        &self,
+        config: Self::RequestBody,
+        response_sender: Sender<ApiResponse>,
+    ) -> ApiRequest {
+        Box::new(move |vmm| {
+            info!("API request event: VmAddSgxEpc {:?}", config);
+
+            let response = vmm
+                .vm_add_sgx_epc(config)
+                .map_err(ApiError::VmAddSgxEpc)
+                .map(|_| ApiResponsePayload::Empty);
+
+            response_sender
+                .send(response)
+                .map_err(VmmError::ApiResponseSend)?;
+
+            Ok(false)
+        })
+    }
+
+    fn send(
+        &self,
+        api_evt: EventFd,
+        api_sender: Sender<ApiRequest>,
+        data: Self::RequestBody,
+    ) -> ApiResult<Self::ResponseBody> {
+        get_response_body(self, api_evt, api_sender, data)
+    }
+}
+
 pub struct VmResizeZone;
 
 impl ApiAction for VmResizeZone {
@@ -1327,7 +1900,7 @@ impl ApiAction for VmSnapshot {
             info!("API request event: VmSnapshot {:?}", config);
 
             let response = vmm
-                .vm_snapshot(&config.destination_url)
+                .vm_snapshot(&config.destination_url, config.signing_key.as_deref())
                 .map_err(ApiError::VmSnapshot)
                 .map(|_| ApiResponsePayload::Empty);
 
@@ -1349,6 +1922,45 @@ impl ApiAction for VmSnapshot {
     }
 }
 
+pub struct VmHibernate;
+
+impl ApiAction for VmHibernate {
+    type RequestBody = VmSnapshotConfig;
+    type ResponseBody = Option<Body>;
+
+    fn request(
+        &self,
+        config: Self::RequestBody,
+        response_sender: Sender<ApiResponse>,
+    ) -> ApiRequest {
+        Box::new(move |vmm| {
+            info!("API request event: VmHibernate {:?}", config);
+
+            let response = vmm
+                .vm_hibernate(&config.destination_url, config.signing_key.as_deref())
+                .map_err(ApiError::VmHibernate)
+                .map(|_| ApiResponsePayload::Empty);
+            // The VMM exits once the VM is hibernated.
+            let exit = response.is_ok();
+
+            response_sender
+                .send(response)
+                .map_err(VmmError::ApiResponseSend)?;
+
+            Ok(exit)
+        })
+    }
+
+    fn send(
+        &self,
+        api_evt: EventFd,
+        api_sender: Sender<ApiRequest>,
+        data: Self::RequestBody,
+    ) -> ApiResult<Self::ResponseBody> {
+        get_response_body(self, api_evt, api_sender, data)
+    }
+}
+
 pub struct VmmPing;
 
 impl ApiAction for VmmPing {
@@ -1446,3 +2058,147 @@ impl ApiAction for VmNmi {
         get_response_body(self, api_evt, api_sender, data)
     }
 }
+
+pub struct VmRefreshCertificates;
+
+impl ApiAction for VmRefreshCertificates {
+    type RequestBody = ();
+    type ResponseBody = Option<Body>;
+
+    fn request(&self, _: Self::RequestBody, response_sender: Sender<ApiResponse>) -> ApiRequest {
+        Box::new(move |vmm| {
+            info!("API request event: VmRefreshCertificates");
+
+            let response = vmm
+                .vm_refresh_certificates()
+                .map_err(ApiError::VmRefreshCertificates)
+                .map(|_| ApiResponsePayload::Empty);
+
+            response_sender
+                .send(response)
+                .map_err(VmmError::ApiResponseSend)?;
+
+            Ok(false)
+        })
+    }
+
+    fn send(
+        &self,
+        api_evt: EventFd,
+        api_sender: Sender<ApiRequest>,
+        data: Self::RequestBody,
+    ) -> ApiResult<Self::ResponseBody> {
+        get_response_body(self, api_evt, api_sender, data)
+    }
+}
+
+pub struct VmGuestCommand;
+
+impl ApiAction for VmGuestCommand {
+    type RequestBody = VmGuestCommandData;
+    type ResponseBody = Option<Body>;
+
+    fn request(
+        &self,
+        guest_command_data: Self::RequestBody,
+        response_sender: Sender<ApiResponse>,
+    ) -> ApiRequest {
+        Box::new(move |vmm| {
+            info!("API request event: VmGuestCommand {:?}", guest_command_data);
+
+            let response = vmm
+                .vm_guest_command(guest_command_data)
+                .map_err(ApiError::VmGuestCommand)
+                .map(ApiResponsePayload::VmAction);
+
+            response_sender
+                .send(response)
+                .map_err(VmmError::ApiResponseSend)?;
+
+            Ok(false)
+        })
+    }
+
+    fn send(
+        &self,
+        api_evt: EventFd,
+        api_sender: Sender<ApiRequest>,
+        data: Self::RequestBody,
+    ) -> ApiResult<Self::ResponseBody> {
+        get_response_body(self, api_evt, api_sender, data)
+    }
+}
+
+pub struct VmBackup;
+
+impl ApiAction for VmBackup {
+    type RequestBody = VmBackupData;
+    type ResponseBody = Option<Body>;
+
+    fn request(
+        &self,
+        backup_data: Self::RequestBody,
+        response_sender: Sender<ApiResponse>,
+    ) -> ApiRequest {
+        Box::new(move |vmm| {
+            info!("API request event: VmBackup {:?}", backup_data);
+
+            let response = vmm
+                .vm_backup(backup_data)
+                .map_err(ApiError::VmBackup)
+                .map(|_| ApiResponsePayload::Empty);
+
+            response_sender
+                .send(response)
+                .map_err(VmmError::ApiResponseSend)?;
+
+            Ok(false)
+        })
+    }
+
+    fn send(
+        &self,
+        api_evt: EventFd,
+        api_sender: Sender<ApiRequest>,
+        data: Self::RequestBody,
+    ) -> ApiResult<Self::ResponseBody> {
+        get_response_body(self, api_evt, api_sender, data)
+    }
+}
+
+pub struct VmDiskSnapshot;
+
+impl ApiAction for VmDiskSnapshot {
+    type RequestBody = VmDiskSnapshotData;
+    type ResponseBody = Option<Body>;
+
+    fn request(
+        &self,
+        disk_snapshot_data: Self::RequestBody,
+        response_sender: Sender<ApiResponse>,
+    ) -> ApiRequest {
+        Box::new(move |vmm| {
+            info!("API request event: VmDiskSnapshot {:?}", disk_snapshot_data);
+
+            let response = vmm
+                .vm_disk_snapshot(disk_snapshot_data)
+                .map_err(ApiError::VmDiskSnapshot)
+                .map(|_| ApiResponsePayload::Empty);
+
+            response_sender
+                .send(response)
+                .map_err(VmmError::ApiResponseSend)?;
+
+            Ok(false)
+        })
+    }
+
+    fn send(
+        &self,
+        api_evt: EventFd,
+        api_sender: Sender<ApiRequest>,
+        data: Self::RequestBody,
+    ) -> ApiResult<Self::ResponseBody> {
+        get_response_body(self, api_evt, api_sender, data)
+    }
+}
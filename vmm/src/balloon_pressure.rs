@@ -0,0 +1,116 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Resizing of the balloon following the memory pressure of the host.
+//!
+//! The pressure is taken from the pressure stall information (PSI) of the
+//! host, as the share of the last 10 seconds during which some tasks were
+//! stalled waiting for memory. Above `pressure_high`, the balloon is inflated
+//! by a step to give memory back to the host, and below `pressure_low` it is
+//! deflated by a step to give it back to the guest, always staying between
+//! `min_size` and `max_size`.
+
+use std::time::Duration;
+use std::{fs, io};
+
+use thiserror::Error;
+
+use crate::vm_config::BalloonConfig;
+
+pub const HOST_MEMORY_PRESSURE: &str = "/proc/pressure/memory";
+
+/// Interval between two adjustments of the balloons, matching the window of
+/// the pressure they follow.
+pub const ADJUST_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Cannot read the host memory pressure")]
+    Read(#[source] io::Error),
+
+    #[error("Invalid host memory pressure: {0}")]
+    Invalid(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+// Parse the pressure over the last 10 seconds from the "some" line of a PSI
+// file, such as "some avg10=1.53 avg60=0.87 avg300=0.22 total=12345".
+fn parse_pressure(psi: &str) -> Result<f64> {
+    psi.lines()
+        .find_map(|line| line.strip_prefix("some "))
+        .and_then(|line| {
+            line.split_whitespace()
+                .find_map(|field| field.strip_prefix("avg10="))
+        })
+        .and_then(|avg10| avg10.parse().ok())
+        .ok_or_else(|| Error::Invalid(psi.to_string()))
+}
+
+/// Returns the host memory pressure, in percent.
+pub fn host_memory_pressure() -> Result<f64> {
+    parse_pressure(&fs::read_to_string(HOST_MEMORY_PRESSURE).map_err(Error::Read)?)
+}
+
+/// Returns the size to resize the balloon to from `size` under `pressure`,
+/// if it needs resizing.
+pub fn balloon_target(config: &BalloonConfig, size: u64, pressure: f64) -> Option<u64> {
+    let min_size = config.min_size;
+    let max_size = config.max_size.unwrap_or(min_size).max(min_size);
+
+    let target = if pressure >= f64::from(config.pressure_high) {
+        size.saturating_add(config.step)
+    } else if pressure < f64::from(config.pressure_low) {
+        size.saturating_sub(config.step)
+    } else {
+        size
+    }
+    .clamp(min_size, max_size);
+
+    (target != size).then_some(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm_config::*;
+
+    #[test]
+    fn test_parse_pressure() {
+        let psi = "some avg10=12.50 avg60=3.10 avg300=0.80 total=123456\n\
+                   full avg10=2.00 avg60=0.50 avg300=0.10 total=23456\n";
+        assert_eq!(parse_pressure(psi).unwrap(), 12.5);
+        assert!(parse_pressure("full avg10=2.00 avg60=0.50").is_err());
+        assert!(parse_pressure("some avg60=0.50").is_err());
+    }
+
+    #[test]
+    fn test_balloon_target() {
+        let config = BalloonConfig {
+            size: 0,
+            deflate_on_oom: false,
+            free_page_reporting: false,
+            auto_resize: true,
+            min_size: 256 << 20,
+            max_size: Some(1 << 30),
+            step: default_balloonconfig_step(),
+            pressure_high: default_balloonconfig_pressure_high(),
+            pressure_low: default_balloonconfig_pressure_low(),
+        };
+
+        // Inflated under pressure, up to the maximum size.
+        assert_eq!(balloon_target(&config, 512 << 20, 20.0), Some(640 << 20));
+        assert_eq!(balloon_target(&config, 960 << 20, 20.0), Some(1 << 30));
+        assert_eq!(balloon_target(&config, 1 << 30, 20.0), None);
+
+        // Deflated without pressure, down to the minimum size.
+        assert_eq!(balloon_target(&config, 512 << 20, 0.0), Some(384 << 20));
+        assert_eq!(balloon_target(&config, 256 << 20, 0.0), None);
+
+        // Left alone in between, unless out of bounds.
+        assert_eq!(balloon_target(&config, 512 << 20, 5.0), None);
+        assert_eq!(balloon_target(&config, 0, 5.0), Some(256 << 20));
+    }
+}
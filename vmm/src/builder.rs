@@ -0,0 +1,359 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Entry point for programs embedding the VMM.
+//!
+//! The `cloud-hypervisor` binary only parses its command line before
+//! starting the VMM thread, which other programs can start the same way
+//! through [`VmmBuilder`]. The VM is then managed through the methods of
+//! [`VmmHandle`], without the need for the HTTP or D-Bus API servers:
+//!
+//! ```no_run
+//! use vmm::vm_config::VmConfig;
+//! use vmm::{VmmBuilder, VmmVersionInfo};
+//!
+//! # fn run(config: VmConfig) -> Result<(), Box<dyn std::error::Error>> {
+//! let vmm = VmmBuilder::new(
+//!     VmmVersionInfo::new("my-vmm", env!("CARGO_PKG_VERSION")),
+//!     hypervisor::new()?,
+//! )
+//! .build()?;
+//!
+//! vmm.create_vm(config)?;
+//! vmm.boot_vm()?;
+//! vmm.pause_vm()?;
+//! vmm.snapshot_vm("file:///var/lib/my-vmm/snapshot")?;
+//! vmm.shutdown_vmm()?;
+//!
+//! vmm.join()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Any other [`crate::api`] action can be sent through [`VmmHandle::send`].
+
+#[cfg(feature = "guest_debug")]
+use std::net::SocketAddr;
+use std::os::unix::io::RawFd;
+#[cfg(feature = "guest_debug")]
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::Arc;
+
+use libc::EFD_NONBLOCK;
+use seccompiler::SeccompAction;
+use vmm_sys_util::eventfd::EventFd;
+
+#[cfg(feature = "dbus_api")]
+use crate::api::dbus::{dbus_api_graceful_shutdown, DBusApiOptions};
+use crate::api::http::http_api_graceful_shutdown;
+use crate::api::{
+    ApiAction, ApiError, ApiRequest, ApiResult, VmBoot, VmCreate, VmDelete, VmInfo, VmInfoResponse,
+    VmPause, VmRestore, VmResume, VmShutdown, VmSnapshot, VmSnapshotConfig, VmmShutdown,
+};
+use crate::config::RestoreConfig;
+use crate::vm_config::VmConfig;
+#[cfg(feature = "guest_debug")]
+use crate::GdbListener;
+use crate::{start_vmm_thread, Error, Result, VmmThreadHandle, VmmVersionInfo};
+
+/// Builder of a VMM running in its own thread.
+///
+/// The VMM is sandboxed with seccomp filters by default, which assumes the
+/// embedding process doesn't rely on the VMM threads to perform other
+/// system calls, e.g. from signal handlers.
+pub struct VmmBuilder {
+    version: VmmVersionInfo,
+    hypervisor: Arc<dyn hypervisor::Hypervisor>,
+    http_path: Option<String>,
+    http_fd: Option<RawFd>,
+    #[cfg(feature = "dbus_api")]
+    dbus_options: Option<DBusApiOptions>,
+    #[cfg(feature = "guest_debug")]
+    debug_listener: Option<GdbListener>,
+    exit_event: Option<EventFd>,
+    seccomp_action: SeccompAction,
+    landlock_enable: bool,
+}
+
+impl VmmBuilder {
+    pub fn new(version: VmmVersionInfo, hypervisor: Arc<dyn hypervisor::Hypervisor>) -> Self {
+        VmmBuilder {
+            version,
+            hypervisor,
+            http_path: None,
+            http_fd: None,
+            #[cfg(feature = "dbus_api")]
+            dbus_options: None,
+            #[cfg(feature = "guest_debug")]
+            debug_listener: None,
+            exit_event: None,
+            seccomp_action: SeccompAction::Trap,
+            landlock_enable: false,
+        }
+    }
+
+    /// Serves the HTTP API on the UNIX socket at `path`.
+    pub fn http_path(mut self, path: impl Into<String>) -> Self {
+        self.http_path = Some(path.into());
+        self
+    }
+
+    /// Serves the HTTP API on an already listening UNIX socket.
+    pub fn http_fd(mut self, fd: RawFd) -> Self {
+        self.http_fd = Some(fd);
+        self
+    }
+
+    /// Serves the D-Bus API.
+    #[cfg(feature = "dbus_api")]
+    pub fn dbus_options(mut self, options: DBusApiOptions) -> Self {
+        self.dbus_options = Some(options);
+        self
+    }
+
+    /// Serves the GDB remote protocol on the UNIX socket at `path`.
+    #[cfg(feature = "guest_debug")]
+    pub fn debug_path(mut self, path: PathBuf) -> Self {
+        self.debug_listener = Some(GdbListener::Unix(path));
+        self
+    }
+
+    /// Serves the GDB remote protocol on the TCP socket at `addr`.
+    #[cfg(feature = "guest_debug")]
+    pub fn debug_tcp(mut self, addr: SocketAddr) -> Self {
+        self.debug_listener = Some(GdbListener::Tcp(addr));
+        self
+    }
+
+    /// Uses `exit_event`, rather than a new one, to report the VMM threads
+    /// failures, allowing other threads of the program to share it.
+    pub fn exit_event(mut self, exit_event: EventFd) -> Self {
+        self.exit_event = Some(exit_event);
+        self
+    }
+
+    /// Sets the action taken on a system call not allowed by the seccomp
+    /// filters, `SeccompAction::Allow` disabling them.
+    pub fn seccomp_action(mut self, seccomp_action: SeccompAction) -> Self {
+        self.seccomp_action = seccomp_action;
+        self
+    }
+
+    /// Restricts the files the VMM threads can access to the ones of the VM
+    /// configuration.
+    pub fn landlock(mut self, enable: bool) -> Self {
+        self.landlock_enable = enable;
+        self
+    }
+
+    /// Starts the VMM thread, as well as the API ones.
+    pub fn build(self) -> Result<VmmHandle> {
+        let (api_sender, api_receiver) = channel();
+        let api_event = EventFd::new(EFD_NONBLOCK).map_err(Error::EventFdCreate)?;
+        let exit_event = match self.exit_event {
+            Some(exit_event) => exit_event,
+            None => EventFd::new(EFD_NONBLOCK).map_err(Error::EventFdCreate)?,
+        };
+
+        let thread_handle = start_vmm_thread(
+            self.version,
+            &self.http_path,
+            self.http_fd,
+            #[cfg(feature = "dbus_api")]
+            self.dbus_options,
+            api_event.try_clone().map_err(Error::EventFdClone)?,
+            api_sender.clone(),
+            api_receiver,
+            #[cfg(feature = "guest_debug")]
+            self.debug_listener,
+            #[cfg(feature = "guest_debug")]
+            EventFd::new(EFD_NONBLOCK).map_err(Error::EventFdCreate)?,
+            #[cfg(feature = "guest_debug")]
+            EventFd::new(EFD_NONBLOCK).map_err(Error::EventFdCreate)?,
+            exit_event.try_clone().map_err(Error::EventFdClone)?,
+            &self.seccomp_action,
+            self.hypervisor,
+            self.landlock_enable,
+        )?;
+
+        Ok(VmmHandle {
+            thread_handle,
+            api_event,
+            api_sender,
+            exit_event,
+        })
+    }
+}
+
+/// Handle of a VMM started by [`VmmBuilder`].
+pub struct VmmHandle {
+    thread_handle: VmmThreadHandle,
+    api_event: EventFd,
+    api_sender: Sender<ApiRequest>,
+    exit_event: EventFd,
+}
+
+impl VmmHandle {
+    /// Returns the event to pass to the [`crate::api::ApiAction`]s.
+    pub fn api_event(&self) -> Result<EventFd> {
+        self.api_event.try_clone().map_err(Error::EventFdClone)
+    }
+
+    /// Returns the sender to pass to the [`crate::api::ApiAction`]s.
+    pub fn api_sender(&self) -> Sender<ApiRequest> {
+        self.api_sender.clone()
+    }
+
+    /// Sends the `action` request to the VMM, waiting for its response.
+    pub fn send<A: ApiAction>(
+        &self,
+        action: &A,
+        data: A::RequestBody,
+    ) -> ApiResult<A::ResponseBody> {
+        let api_event = self.api_event.try_clone().map_err(ApiError::EventFdClone)?;
+        action.send(api_event, self.api_sender(), data)
+    }
+
+    /// Creates the VM, which must then be booted.
+    pub fn create_vm(&self, config: VmConfig) -> ApiResult<()> {
+        self.send(&VmCreate, Box::new(config))
+    }
+
+    pub fn boot_vm(&self) -> ApiResult<()> {
+        self.send(&VmBoot, ()).map(|_| ())
+    }
+
+    pub fn pause_vm(&self) -> ApiResult<()> {
+        self.send(&VmPause, ()).map(|_| ())
+    }
+
+    pub fn resume_vm(&self) -> ApiResult<()> {
+        self.send(&VmResume, ()).map(|_| ())
+    }
+
+    /// Snapshots the paused VM to `destination_url`, e.g. a `file://` URL.
+    pub fn snapshot_vm(&self, destination_url: &str) -> ApiResult<()> {
+        let config = VmSnapshotConfig {
+            destination_url: destination_url.to_string(),
+            ..Default::default()
+        };
+        self.send(&VmSnapshot, config).map(|_| ())
+    }
+
+    /// Restores a VM from a snapshot, instead of creating it.
+    pub fn restore_vm(&self, config: RestoreConfig) -> ApiResult<()> {
+        self.send(&VmRestore, config).map(|_| ())
+    }
+
+    /// Shuts the VM down, which can then be booted again.
+    pub fn shutdown_vm(&self) -> ApiResult<()> {
+        self.send(&VmShutdown, ()).map(|_| ())
+    }
+
+    pub fn delete_vm(&self) -> ApiResult<()> {
+        self.send(&VmDelete, ()).map(|_| ())
+    }
+
+    pub fn vm_info(&self) -> ApiResult<VmInfoResponse> {
+        self.send(&VmInfo, ())
+    }
+
+    /// Shuts the VMM down, after which [`VmmHandle::join`] returns.
+    pub fn shutdown_vmm(&self) -> ApiResult<()> {
+        self.send(&VmmShutdown, ())
+    }
+
+    /// Requests the VMM to exit, shutting its VM down.
+    pub fn exit(&self) {
+        if let Err(e) = self.exit_event.write(1) {
+            warn!("Error writing to exit EventFd: {e}");
+        }
+    }
+
+    /// Waits for the VMM to exit, then stops the API threads.
+    pub fn join(self) -> Result<()> {
+        let result = self
+            .thread_handle
+            .thread_handle
+            .join()
+            .map_err(Error::ThreadCleanup)?;
+
+        if let Some(http_api_handle) = self.thread_handle.http_api_handle {
+            http_api_graceful_shutdown(http_api_handle)?;
+        }
+        #[cfg(feature = "dbus_api")]
+        if let Some(dbus_shutdown_chs) = self.thread_handle.dbus_shutdown_chs {
+            dbus_api_graceful_shutdown(dbus_shutdown_chs);
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_vmm() -> VmmHandle {
+        VmmBuilder::new(
+            VmmVersionInfo::new("dummy", "dummy"),
+            hypervisor::new().unwrap(),
+        )
+        .seccomp_action(SeccompAction::Allow)
+        .build()
+        .unwrap()
+    }
+
+    #[test]
+    fn test_vmm_builder_shutdown() {
+        let vmm = build_vmm();
+        vmm.shutdown_vmm().unwrap();
+        vmm.join().unwrap();
+    }
+
+    #[test]
+    fn test_vmm_builder_exit_event() {
+        let exit_event = EventFd::new(EFD_NONBLOCK).unwrap();
+        let vmm = VmmBuilder::new(
+            VmmVersionInfo::new("dummy", "dummy"),
+            hypervisor::new().unwrap(),
+        )
+        .seccomp_action(SeccompAction::Allow)
+        .exit_event(exit_event.try_clone().unwrap())
+        .build()
+        .unwrap();
+
+        // Another thread of the program requesting the VMM to exit.
+        exit_event.write(1).unwrap();
+        vmm.join().unwrap();
+    }
+
+    #[test]
+    fn test_vmm_handle_vm_lifecycle() {
+        let vmm = build_vmm();
+
+        assert!(matches!(
+            vmm.vm_info(),
+            Err(ApiError::VmInfo(crate::vm::Error::VmNotCreated))
+        ));
+        assert!(matches!(
+            vmm.pause_vm(),
+            Err(ApiError::VmPause(crate::vm::Error::VmNotRunning))
+        ));
+
+        let config = crate::unit_tests::create_dummy_vm_config();
+        vmm.create_vm(*config.clone()).unwrap();
+        let info = vmm.vm_info().unwrap();
+        assert_eq!(info.config, config);
+        assert_eq!(info.state, crate::vm::VmState::Created);
+
+        vmm.delete_vm().unwrap();
+        assert!(vmm.vm_info().is_err());
+
+        vmm.shutdown_vmm().unwrap();
+        vmm.join().unwrap();
+    }
+}
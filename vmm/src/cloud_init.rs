@@ -0,0 +1,329 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Generation of cloud-init NoCloud seeds.
+//!
+//! The seed is a FAT12 filesystem labelled `CIDATA`, holding the user-data,
+//! meta-data and network-config files at its root. The image only depends
+//! on the content of the files, so that the same seed is generated again
+//! when restoring the VM.
+
+use std::fs::File;
+use std::io::{self, Seek, Write};
+use std::os::fd::FromRawFd;
+
+const SECTOR_SIZE: usize = 512;
+const DIR_ENTRY_SIZE: usize = 32;
+const ROOT_DIR_ENTRIES: usize = 16;
+// Highest number of clusters a FAT12 filesystem can hold.
+const MAX_FAT12_CLUSTERS: usize = 4084;
+
+const ATTR_ARCHIVE: u8 = 0x20;
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_LONG_NAME: u8 = 0x0f;
+// Characters of a long file name held by each directory entry.
+const LONG_NAME_CHARS: usize = 13;
+
+const VOLUME_LABEL: &[u8; 11] = b"CIDATA     ";
+// 2000-01-01, keeping the image reproducible.
+const FAT_DATE: u16 = (20 << 9) | (1 << 5) | 1;
+
+fn short_name_checksum(name: &[u8; 11]) -> u8 {
+    name.iter().fold(0u8, |sum, b| {
+        (sum >> 1).wrapping_add(sum << 7).wrapping_add(*b)
+    })
+}
+
+// Build a unique 8.3 name for a file, the actual name being stored as a
+// long file name.
+fn short_name(name: &str, index: usize) -> [u8; 11] {
+    let mut short = [b' '; 11];
+    let base: Vec<u8> = name
+        .bytes()
+        .filter(|b| b.is_ascii_alphanumeric() || *b == b'-')
+        .map(|b| b.to_ascii_uppercase())
+        .take(6)
+        .collect();
+    short[..base.len()].copy_from_slice(&base);
+    short[base.len()] = b'~';
+    short[base.len() + 1] = b'1' + index as u8;
+    short
+}
+
+fn long_name_entries(name: &str, checksum: u8) -> Vec<[u8; DIR_ENTRY_SIZE]> {
+    let mut chars: Vec<u16> = name.encode_utf16().collect();
+    // The name is terminated by a null character, unless it fills the
+    // last entry, the remaining characters being set to 0xffff.
+    if chars.len() % LONG_NAME_CHARS != 0 {
+        chars.push(0);
+    }
+    chars.resize(chars.len().next_multiple_of(LONG_NAME_CHARS), 0xffff);
+
+    let count = chars.len() / LONG_NAME_CHARS;
+    let mut entries = Vec::with_capacity(count);
+    // Entries are stored from the last part of the name to the first one.
+    for (i, part) in chars.chunks_exact(LONG_NAME_CHARS).enumerate().rev() {
+        let mut entry = [0u8; DIR_ENTRY_SIZE];
+        entry[0] = (i + 1) as u8 | if i + 1 == count { 0x40 } else { 0 };
+        entry[11] = ATTR_LONG_NAME;
+        entry[13] = checksum;
+        for (j, c) in part.iter().enumerate() {
+            let offset = match j {
+                0..=4 => 1 + j * 2,
+                5..=10 => 14 + (j - 5) * 2,
+                _ => 28 + (j - 11) * 2,
+            };
+            entry[offset..offset + 2].copy_from_slice(&c.to_le_bytes());
+        }
+        entries.push(entry);
+    }
+
+    entries
+}
+
+fn short_entry(name: &[u8; 11], attr: u8, cluster: u16, size: u32) -> [u8; DIR_ENTRY_SIZE] {
+    let mut entry = [0u8; DIR_ENTRY_SIZE];
+    entry[0..11].copy_from_slice(name);
+    entry[11] = attr;
+    for offset in [16, 18, 24] {
+        entry[offset..offset + 2].copy_from_slice(&FAT_DATE.to_le_bytes());
+    }
+    entry[26..28].copy_from_slice(&cluster.to_le_bytes());
+    entry[28..32].copy_from_slice(&size.to_le_bytes());
+    entry
+}
+
+fn set_fat12_entry(fat: &mut [u8], cluster: usize, value: u16) {
+    let offset = cluster * 3 / 2;
+    if cluster % 2 == 0 {
+        fat[offset] = value as u8;
+        fat[offset + 1] = (fat[offset + 1] & 0xf0) | ((value >> 8) as u8 & 0x0f);
+    } else {
+        fat[offset] = (fat[offset] & 0x0f) | ((value as u8 & 0x0f) << 4);
+        fat[offset + 1] = (value >> 4) as u8;
+    }
+}
+
+/// Build a FAT12 image labelled `CIDATA`, holding the given files at its
+/// root.
+pub fn create_seed_image(files: &[(&str, &[u8])]) -> io::Result<Vec<u8>> {
+    let total_size: usize = files.iter().map(|(_, data)| data.len()).sum();
+    // Pick the smallest cluster size letting the files fit in a FAT12
+    // filesystem.
+    let sectors_per_cluster = [1, 2, 4, 8, 16, 32, 64]
+        .into_iter()
+        .find(|spc| {
+            let cluster_size = spc * SECTOR_SIZE;
+            let clusters: usize = files
+                .iter()
+                .map(|(_, data)| data.len().div_ceil(cluster_size))
+                .sum();
+            clusters <= MAX_FAT12_CLUSTERS
+        })
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("cloud-init files are too large ({total_size} bytes)"),
+            )
+        })?;
+    let cluster_size = sectors_per_cluster * SECTOR_SIZE;
+
+    // Root directory entries, starting with the volume label.
+    let mut root_dir = vec![short_entry(VOLUME_LABEL, ATTR_VOLUME_ID, 0, 0)];
+    let mut chains = Vec::new();
+    let mut next_cluster = 2;
+    for (index, (name, data)) in files.iter().enumerate() {
+        let clusters = data.len().div_ceil(cluster_size);
+        let first_cluster = if clusters > 0 { next_cluster } else { 0 };
+        chains.push((first_cluster, clusters, *data));
+        next_cluster += clusters;
+
+        let short = short_name(name, index);
+        root_dir.extend(long_name_entries(name, short_name_checksum(&short)));
+        root_dir.push(short_entry(
+            &short,
+            ATTR_ARCHIVE,
+            first_cluster as u16,
+            data.len() as u32,
+        ));
+    }
+    if root_dir.len() > ROOT_DIR_ENTRIES {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Too many cloud-init files",
+        ));
+    }
+
+    let cluster_count = (next_cluster - 2).max(1);
+    let fat_sectors = ((cluster_count + 2) * 3).div_ceil(2).div_ceil(SECTOR_SIZE);
+    let root_dir_sectors = (ROOT_DIR_ENTRIES * DIR_ENTRY_SIZE).div_ceil(SECTOR_SIZE);
+    let data_start = 1 + 2 * fat_sectors + root_dir_sectors;
+    let total_sectors = data_start + cluster_count * sectors_per_cluster;
+
+    let mut image = vec![0u8; total_sectors * SECTOR_SIZE];
+
+    // Boot sector, with the BIOS parameter block
+    let boot = &mut image[..SECTOR_SIZE];
+    boot[0..3].copy_from_slice(&[0xeb, 0x3c, 0x90]);
+    boot[3..11].copy_from_slice(b"CLOUDHYP");
+    boot[11..13].copy_from_slice(&(SECTOR_SIZE as u16).to_le_bytes());
+    boot[13] = sectors_per_cluster as u8;
+    // Reserved sectors
+    boot[14..16].copy_from_slice(&1u16.to_le_bytes());
+    // Number of FATs
+    boot[16] = 2;
+    boot[17..19].copy_from_slice(&(ROOT_DIR_ENTRIES as u16).to_le_bytes());
+    if let Ok(total_sectors) = u16::try_from(total_sectors) {
+        boot[19..21].copy_from_slice(&total_sectors.to_le_bytes());
+    } else {
+        boot[32..36].copy_from_slice(&(total_sectors as u32).to_le_bytes());
+    }
+    // Media descriptor
+    boot[21] = 0xf8;
+    boot[22..24].copy_from_slice(&(fat_sectors as u16).to_le_bytes());
+    // Sectors per track and number of heads
+    boot[24..26].copy_from_slice(&32u16.to_le_bytes());
+    boot[26..28].copy_from_slice(&64u16.to_le_bytes());
+    // Drive number
+    boot[36] = 0x80;
+    // Extended boot signature, followed by the volume id, label and type
+    boot[38] = 0x29;
+    boot[39..43].copy_from_slice(&0x4349_4441u32.to_le_bytes());
+    boot[43..54].copy_from_slice(VOLUME_LABEL);
+    boot[54..62].copy_from_slice(b"FAT12   ");
+    boot[510..512].copy_from_slice(&[0x55, 0xaa]);
+
+    // File allocation table, with the first two entries reserved.
+    let mut fat = vec![0u8; fat_sectors * SECTOR_SIZE];
+    set_fat12_entry(&mut fat, 0, 0xff8);
+    set_fat12_entry(&mut fat, 1, 0xfff);
+    for (first_cluster, clusters, data) in chains {
+        for i in 0..clusters {
+            let cluster = first_cluster + i;
+            let next = if i + 1 == clusters {
+                0xfff
+            } else {
+                cluster as u16 + 1
+            };
+            set_fat12_entry(&mut fat, cluster, next);
+        }
+
+        let offset = (data_start + (first_cluster.max(2) - 2) * sectors_per_cluster) * SECTOR_SIZE;
+        image[offset..offset + data.len()].copy_from_slice(data);
+    }
+    for i in 0..2 {
+        let offset = (1 + i * fat_sectors) * SECTOR_SIZE;
+        image[offset..offset + fat.len()].copy_from_slice(&fat);
+    }
+
+    let offset = (1 + 2 * fat_sectors) * SECTOR_SIZE;
+    for (i, entry) in root_dir.iter().enumerate() {
+        let offset = offset + i * DIR_ENTRY_SIZE;
+        image[offset..offset + DIR_ENTRY_SIZE].copy_from_slice(entry);
+    }
+
+    Ok(image)
+}
+
+/// Write the seed into an anonymous file the block device can be backed
+/// by, without the seed being exposed on the host filesystem.
+pub fn create_seed_file(files: &[(&str, &[u8])]) -> io::Result<File> {
+    let image = create_seed_image(files)?;
+
+    // SAFETY: FFI call with a valid null terminated name
+    let fd = unsafe { libc::memfd_create(c"cloud-init".as_ptr(), libc::MFD_CLOEXEC) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: fd is a valid file descriptor we own
+    let mut file = unsafe { File::from_raw_fd(fd) };
+    file.write_all(&image)?;
+    file.rewind()?;
+
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_u16(image: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes([image[offset], image[offset + 1]])
+    }
+
+    fn fat12_entry(fat: &[u8], cluster: usize) -> u16 {
+        let value = read_u16(fat, cluster * 3 / 2);
+        if cluster % 2 == 0 {
+            value & 0xfff
+        } else {
+            value >> 4
+        }
+    }
+
+    #[test]
+    fn test_long_name_entries() {
+        let short = short_name("network-config", 2);
+        assert_eq!(&short, b"NETWOR~3   ");
+
+        let entries = long_name_entries("network-config", short_name_checksum(&short));
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0][0], 0x42);
+        assert_eq!(entries[1][0], 0x01);
+        // Last character of the name, followed by the terminator
+        assert_eq!(read_u16(&entries[0], 1), u16::from(b'g'));
+        assert_eq!(read_u16(&entries[0], 3), 0);
+        assert_eq!(read_u16(&entries[0], 5), 0xffff);
+    }
+
+    #[test]
+    fn test_create_seed_image() {
+        let user_data = vec![b'u'; 1500];
+        let image = create_seed_image(&[
+            ("user-data", &user_data),
+            ("meta-data", b"instance-id: test\n"),
+            ("network-config", b""),
+        ])
+        .unwrap();
+
+        assert_eq!(&image[510..512], &[0x55, 0xaa]);
+        assert_eq!(&image[43..54], VOLUME_LABEL);
+        let fat_sectors = usize::from(read_u16(&image, 22));
+        let fat = &image[SECTOR_SIZE..(1 + fat_sectors) * SECTOR_SIZE];
+        let data_start = (2 + 2 * fat_sectors) * SECTOR_SIZE;
+
+        // user-data spans 3 clusters, followed by meta-data
+        assert_eq!(fat12_entry(fat, 2), 3);
+        assert_eq!(fat12_entry(fat, 3), 4);
+        assert_eq!(fat12_entry(fat, 4), 0xfff);
+        assert_eq!(fat12_entry(fat, 5), 0xfff);
+        assert_eq!(&image[data_start..data_start + 1500], &user_data[..]);
+        let meta_data = data_start + 3 * SECTOR_SIZE;
+        assert_eq!(&image[meta_data..meta_data + 18], b"instance-id: test\n");
+
+        // Both FATs are identical
+        assert_eq!(
+            fat,
+            &image[(1 + fat_sectors) * SECTOR_SIZE..(1 + 2 * fat_sectors) * SECTOR_SIZE]
+        );
+
+        // Label, then one long name entry and one short entry for both
+        // user-data and meta-data, and two long name entries for
+        // network-config, which is empty.
+        let root_dir = &image[(1 + 2 * fat_sectors) * SECTOR_SIZE..data_start];
+        assert_eq!(root_dir[11], ATTR_VOLUME_ID);
+        assert_eq!(
+            &root_dir[2 * DIR_ENTRY_SIZE..2 * DIR_ENTRY_SIZE + 11],
+            b"USER-D~1   "
+        );
+        assert_eq!(read_u16(root_dir, 2 * DIR_ENTRY_SIZE + 26), 2);
+        assert_eq!(read_u16(root_dir, 4 * DIR_ENTRY_SIZE + 26), 5);
+        assert_eq!(
+            &root_dir[7 * DIR_ENTRY_SIZE..7 * DIR_ENTRY_SIZE + 11],
+            b"NETWOR~3   "
+        );
+        assert_eq!(read_u16(root_dir, 7 * DIR_ENTRY_SIZE + 26), 0);
+        assert_eq!(root_dir[8 * DIR_ENTRY_SIZE], 0);
+    }
+}
@@ -4,14 +4,18 @@
 //
 
 use std::collections::{BTreeSet, HashMap};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use std::{fmt, result};
+use std::{fmt, fs, io, result};
 
+use block::{CacheMode, ImageType, IoClass, IOPRIO_MAX_LEVEL};
+use clap::parser::ValueSource;
 use clap::ArgMatches;
+use devices::nvme::NVME_MAX_IO_QUEUES;
 use option_parser::{
     ByteSized, IntegerList, OptionParser, OptionParserError, StringList, Toggle, Tuple,
 };
+use pci::VfioResetMethod;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use virtio_bindings::virtio_blk::VIRTIO_BLK_ID_BYTES;
@@ -22,7 +26,15 @@ use crate::landlock::LandlockAccess;
 use crate::vm_config::*;
 
 const MAX_NUM_PCI_SEGMENTS: u16 = 96;
+// Largest MSI-X table defined by the PCI specification.
+const MAX_MSIX_VECTORS: u16 = 2048;
 const MAX_IOMMU_ADDRESS_WIDTH_BITS: u8 = 64;
+// TD partitioning allows up to 3 L2 VMs besides the L1 VMM.
+#[cfg(feature = "tdx")]
+const MAX_TDX_L2_VMS: u8 = 3;
+// Only x86_64 supports vCPU identifiers wider than 8 bits.
+#[cfg(not(target_arch = "x86_64"))]
+const MAX_VCPUS: u32 = u8::MAX as u32;
 
 /// Errors associated with VM configuration parameters.
 #[derive(Debug, Error)]
@@ -53,12 +65,49 @@ pub enum Error {
     ParseMemoryZoneIdMissing,
     /// Error parsing rate-limiter group options
     ParseRateLimiterGroup(#[source] OptionParserError),
+    /// Error parsing rate-limit schedule options
+    ParseRateLimitSchedule(#[source] OptionParserError),
+    /// Unknown rate-limiter group of a rate-limit schedule
+    ParseRateLimitScheduleUnknownGroup(String),
     /// Error parsing disk options
     ParseDisk(#[source] OptionParserError),
     /// Error parsing network options
     ParseNetwork(#[source] OptionParserError),
     /// Error parsing RNG options
     ParseRng(#[source] OptionParserError),
+    /// Error parsing SCMI options
+    ParseScmi(#[source] OptionParserError),
+    /// Error parsing cloud-init options
+    ParseCloudInit(#[source] OptionParserError),
+    /// Error parsing metadata service options
+    ParseImds(#[source] OptionParserError),
+    /// Missing document for the metadata service
+    ParseImdsDocumentMissing,
+    /// Error parsing network namespace options
+    ParseNetns(#[source] OptionParserError),
+    /// Missing name of the network namespace
+    ParseNetnsNameMissing,
+    /// Error parsing guest agent options
+    ParseGuestAgent(#[source] OptionParserError),
+    /// Error parsing pvmemcontrol parameters
+    #[cfg(feature = "pvmemcontrol")]
+    ParsePvmemcontrol(#[source] OptionParserError),
+    /// Error parsing resources parameters
+    ParseResources(#[source] OptionParserError),
+    /// Error parsing RTC parameters
+    ParseRtc(#[source] OptionParserError),
+    /// Error parsing restart policy parameters
+    ParseRestartPolicy(#[source] OptionParserError),
+    /// Error parsing crash dump parameters
+    ParseCrashDump(#[source] OptionParserError),
+    /// Missing path for the crash dumps
+    ParseCrashDumpPathMissing,
+    /// Error parsing I/O threads options
+    ParseIoThreads(#[source] OptionParserError),
+    /// Error parsing fallback firmware options
+    ParseFallbackFirmware(#[source] OptionParserError),
+    /// Missing path for the fallback firmware
+    ParseFallbackFirmwarePathMissing,
     /// Error parsing balloon options
     ParseBalloon(#[source] OptionParserError),
     /// Error parsing filesystem parameters
@@ -72,10 +121,16 @@ pub enum Error {
     ParseDebugConsole(#[source] OptionParserError),
     /// No mode given for console
     ParseConsoleInvalidModeGiven,
+    /// Invalid additional output of the console
+    ParseConsoleInvalidSink(String),
     /// Failed parsing device parameters
     ParseDevice(#[source] OptionParserError),
     /// Missing path from device,
     ParseDevicePathMissing,
+    /// Missing virtual function from device
+    ParseDeviceSriovVfMissing,
+    /// SR-IOV parameters without physical function
+    ParseDeviceSriovPfMissing,
     /// Failed parsing vsock parameters
     ParseVsock(#[source] OptionParserError),
     /// Failed parsing restore parameters
@@ -119,18 +174,37 @@ pub enum Error {
     ParseLandlockRules(#[source] OptionParserError),
     /// Missing fields in Landlock rules
     ParseLandlockMissingFields,
+    /// Invalid file descriptor for a payload component
+    ParsePayloadFd(String),
+    /// Failed reading the VM configuration file
+    ReadConfigFile(#[source] io::Error),
+    /// Failed deserializing the VM configuration file
+    ParseConfigFile(String),
 }
 
 #[derive(Debug, PartialEq, Eq, Error)]
 pub enum ValidationError {
     /// No kernel specified
     KernelMissing,
+    /// Additional initramfs images without a first one
+    ExtraInitramfsWithoutInitramfs,
+    /// Payload component given both as a path and a file descriptor
+    PayloadPathAndFd(String),
     /// Missing file value for console
     ConsoleFileMissing,
     /// Missing socket path for console
     ConsoleSocketPathMissing,
+    /// Console output duplicated while the console is off
+    ConsoleTeeWithoutOutput,
+    /// Console output duplicated to several ring buffers
+    ConsoleTeeMultipleRings,
+    /// Console ring buffer of no size
+    InvalidConsoleRingSize,
     /// Max is less than boot
     CpusMaxLowerThanBoot,
+    /// Max is above the architecture limit
+    #[cfg(not(target_arch = "x86_64"))]
+    CpusMaxTooHigh(u32),
     /// Missing file value for debug-console
     #[cfg(target_arch = "x86_64")]
     DebugconFileMissing,
@@ -159,16 +233,26 @@ pub enum ValidationError {
     VnetReservedFd,
     /// Hardware checksum offload is disabled.
     NoHardwareChecksumOffload,
+    /// io_uring requested for a vhost-user net device
+    VnetIoUringVhostUser,
     /// Hugepages not turned on
     HugePageSizeWithoutHugePages,
     /// Huge page size is not power of 2
     InvalidHugePageSize(u64),
+    /// Hugepage collapse requested without transparent huge pages
+    ThpCollapseWithoutThp,
     /// CPU Hotplug is not permitted with TDX
     #[cfg(feature = "tdx")]
     TdxNoCpuHotplug,
     /// Missing firmware for TDX
     #[cfg(feature = "tdx")]
     TdxFirmwareMissing,
+    /// Too many L2 VMs for TD partitioning
+    #[cfg(feature = "tdx")]
+    TdxTooManyL2Vms(u8),
+    /// TD partitioning requested without TDX
+    #[cfg(feature = "tdx")]
+    TdxL2VmsWithoutTdx,
     /// Insufficient vCPUs for queues
     TooManyQueues,
     /// Invalid queue size
@@ -181,14 +265,45 @@ pub enum ValidationError {
     MemoryZoneReused(String, u32, u32),
     /// Invalid number of PCI segments
     InvalidNumPciSegments(u16),
+    /// Invalid number of PCI segments including the ones added at runtime
+    InvalidMaxPciSegments(u16),
     /// Invalid PCI segment id
     InvalidPciSegment(u16),
     /// Invalid PCI segment aperture weight
     InvalidPciSegmentApertureWeight(u32),
     /// Invalid IOMMU address width in bits
     InvalidIommuAddressWidthBits(u8),
+    /// Boot method selection is only supported on AArch64
+    BootMethodUnsupported,
+    /// VMBus is only supported on x86_64
+    #[cfg(not(target_arch = "x86_64"))]
+    VmBusUnsupported,
+    /// VMBus relies on the Hyper-V enlightenments
+    #[cfg(target_arch = "x86_64")]
+    VmBusRequiresKvmHyperv,
+    /// SGX EPC section is reused across NUMA nodes
+    #[cfg(target_arch = "x86_64")]
+    SgxEpcSectionReused(String, u32, u32),
+    /// NUMA node refers to an unknown SGX EPC section
+    #[cfg(target_arch = "x86_64")]
+    UnknownSgxEpcSection(String),
+    /// Disabling the legacy devices is only supported on x86_64
+    #[cfg(not(target_arch = "x86_64"))]
+    LegacyDevicesUnsupported,
+    /// Hotplug relies on ACPI, which the device tree boot method disables
+    #[cfg(target_arch = "aarch64")]
+    BootMethodFdtHotplug,
+    /// The ACPI boot method only applies to direct kernel boot
+    #[cfg(target_arch = "aarch64")]
+    BootMethodAcpiFirmware,
     /// Balloon too big
     BalloonLargerThanRam(u64, u64),
+    /// Balloon resized automatically without a maximum size
+    BalloonAutoResizeWithoutMaxSize,
+    /// Balloon minimum size greater than its maximum size
+    BalloonInvalidSizeRange(u64, u64),
+    /// Balloon pressure thresholds out of order or above 100%
+    BalloonInvalidPressure(u8, u8),
     /// On a IOMMU segment but not behind IOMMU
     OnIommuSegment(u16),
     // On a IOMMU segment but IOMMU not supported
@@ -209,11 +324,19 @@ pub enum ValidationError {
     DefaultPciSegmentInvalidNode(u32),
     /// Invalid rate-limiter group
     InvalidRateLimiterGroup,
+    /// Invalid rate-limit schedule of the given group
+    InvalidRateLimitSchedule(String),
     /// The specified I/O port was invalid. It should be provided in hex, such as `0xe9`.
     #[cfg(target_arch = "x86_64")]
     InvalidIoPortHex(String),
     #[cfg(feature = "sev_snp")]
     InvalidHostData,
+    /// SEV-SNP options used without enabling SEV-SNP
+    #[cfg(feature = "sev_snp")]
+    SevSnpOptionsWithoutSevSnp,
+    /// SEV-SNP ID authentication information provided without ID block
+    #[cfg(feature = "sev_snp")]
+    SevSnpIdAuthWithoutIdBlock,
     /// Restore expects all net ids that have fds
     RestoreMissingRequiredNetId(String),
     /// Number of FDs passed during Restore are incorrect to the NetConfig
@@ -224,6 +347,126 @@ pub enum ValidationError {
     InvalidLandlockAccess(String),
     /// Invalid block device serial length
     InvalidSerialLength(usize, usize),
+    /// SCMI device without any sensor, power domain or clock
+    ScmiNoResources,
+    /// cloud-init seed without user-data nor network-config
+    CloudInitNoData,
+    /// Invalid network namespace name
+    InvalidNetnsName(String),
+    /// Invalid network interface name
+    InvalidInterfaceName(String),
+    /// veth interface of a network namespace without any bridge
+    NetnsVethWithoutBridge,
+    /// Fallback firmware without a primary firmware
+    FallbackFirmwareWithoutFirmware,
+    /// Fallback firmware with a null boot timeout
+    FallbackFirmwareZeroTimeout,
+    /// Fallback firmware not supported on this architecture
+    FallbackFirmwareUnsupported,
+    /// NVDIMM devices not supported on this architecture
+    NvdimmUnsupported,
+    /// NVDIMM devices can't be placed behind a virtual IOMMU
+    NvdimmIommuUnsupported,
+    /// SCMI clock with a null rate
+    InvalidScmiClockRate,
+    /// Device of a GPUDirect clique with peer-to-peer DMA disabled
+    GpuDirectCliqueWithoutP2pDma(u8),
+    /// More than one device requesting the legacy VGA ranges
+    MultipleLegacyVga,
+    /// Device without path nor virtual function to create
+    DevicePathMissing,
+    /// Virtual function assigned twice
+    DuplicateSriovVf(String, u32),
+    /// VLAN of a virtual function out of range
+    InvalidSriovVlan(u16),
+    /// GPUDirect clique ID out of range
+    InvalidGpuDirectClique(u8),
+    /// Devices of a GPUDirect clique not all placed behind the virtual IOMMU
+    GpuDirectCliqueIommuMismatch(u8),
+    /// Crash dumps enabled without the pvpanic device
+    CrashDumpWithoutPvpanic,
+    /// No thread in the I/O thread pool
+    IoThreadsZero,
+    /// Affinity set for an I/O thread outside of the pool
+    InvalidIoThreadAffinity(u16),
+    /// Device assigned to an I/O thread outside of the pool
+    InvalidIoThread(u16),
+    /// I/O thread assigned to a vhost-user device
+    IoThreadVhostUser,
+    /// I/O thread assigned to a disk with queue affinity
+    IoThreadQueueAffinity,
+    /// Invalid number of MSI-X vectors of a device
+    InvalidMsixVectors(u16),
+    /// Notification coalescing without any delay
+    CoalescingUsecsZero,
+    /// Notification coalescing enabled on a vhost-user device
+    CoalescingVhostUser,
+    /// Rate limiter group assigned to a vhost-user net device
+    RateLimitGroupVhostUser,
+    /// Net device weight of zero
+    NetWeightZero,
+    /// Pausing on path failures enabled on a vhost-user disk
+    PauseOnPathFailureVhostUser,
+    /// Fair sharing between the queues of a disk that isn't rate limited
+    FairQueuesWithoutRateLimiter,
+    /// Image format given for a vhost-user disk
+    FormatVhostUser,
+    /// Cache mode given for a vhost-user disk
+    CacheVhostUser,
+    /// Cache mode relying on the host page cache for a disk using O_DIRECT
+    CacheModeDirect(CacheMode),
+    /// Encrypted disk without exactly one key file or key FD
+    EncryptionKey,
+    /// Key given for a disk that isn't encrypted
+    KeyWithoutEncryption,
+    /// Encryption enabled on a vhost-user disk
+    EncryptionVhostUser,
+    /// Queue size negotiated with the guest on a vhost-user disk
+    QueueSizeAutoVhostUser,
+    /// Image format given for an encrypted disk
+    EncryptionFormat,
+    /// Encrypted disk using O_DIRECT
+    EncryptionDirect,
+    /// Discard or zero detection enabled on a vhost-user disk
+    DiscardVhostUser,
+    /// I/O engine given for a vhost-user disk
+    IoEngineVhostUser,
+    /// I/O engine not supported by the image format or encryption of a disk
+    IoEngineUnsupported(IoEngine, &'static str),
+    /// Worker thread count given without the threads I/O engine
+    IoEngineThreadsWithoutThreads,
+    /// Threads I/O engine without any worker thread
+    IoEngineThreadsZero,
+    /// io_uring options given with another I/O engine
+    IoUringOptionsWithoutIoUring,
+    /// I/O class given for a vhost-user disk
+    IoClassVhostUser,
+    /// I/O priority level given without a class having levels
+    IoPrioWithoutIoClass,
+    /// I/O priority level out of range
+    InvalidIoPrio(u8),
+    /// I/O thread assigned to a disk with an I/O class
+    IoThreadIoClass,
+    /// NVMe interface for a vhost-user disk
+    NvmeVhostUser,
+    /// NVMe disk placed behind the virtual IOMMU
+    NvmeIommu,
+    /// NVMe disk using O_DIRECT
+    NvmeDirect,
+    /// More queues than supported by the NVMe controller
+    NvmeTooManyQueues(usize),
+    /// Disk option not supported by the NVMe controller
+    NvmeUnsupported(&'static str),
+    /// TAP options on a net device not opening its TAP interface
+    TapOptionsWithoutTap,
+    /// Macvtap interface along with another network backend
+    MacvtapWithOtherBackend,
+    /// Link to create the macvtap interface on without macvtap interface
+    MacvtapLinkWithoutMacvtap,
+    /// Net device option not supported along with vhost-net
+    VhostNetUnsupported(&'static str),
+    /// File the VM relies on missing from the host
+    HostFileMissing(&'static str, PathBuf),
 }
 
 type ValidationResult<T> = std::result::Result<T, ValidationError>;
@@ -233,9 +476,35 @@ impl fmt::Display for ValidationError {
         use self::ValidationError::*;
         match self {
             KernelMissing => write!(f, "No kernel specified"),
+            ExtraInitramfsWithoutInitramfs => {
+                write!(f, "Additional initramfs images require an initramfs")
+            }
+            PayloadPathAndFd(s) => {
+                write!(f, "Payload {s} given both as a path and a file descriptor")
+            }
             ConsoleFileMissing => write!(f, "Path missing when using file console mode"),
             ConsoleSocketPathMissing => write!(f, "Path missing when using socket console mode"),
+            ConsoleTeeWithoutOutput => {
+                write!(
+                    f,
+                    "Console output can't be duplicated while the console is off"
+                )
+            }
+            ConsoleTeeMultipleRings => {
+                write!(
+                    f,
+                    "Console output can only be duplicated to one ring buffer"
+                )
+            }
+            InvalidConsoleRingSize => write!(f, "Console ring buffer size must not be zero"),
             CpusMaxLowerThanBoot => write!(f, "Max CPUs lower than boot CPUs"),
+            #[cfg(not(target_arch = "x86_64"))]
+            CpusMaxTooHigh(max_vcpus) => {
+                write!(
+                    f,
+                    "Max CPUs ({max_vcpus}) higher than the supported {MAX_VCPUS}"
+                )
+            }
             #[cfg(target_arch = "x86_64")]
             DebugconFileMissing => write!(f, "Path missing when using file mode for debug console"),
             DiskSocketAndPath => write!(f, "Disk path and vhost socket both provided"),
@@ -265,12 +534,19 @@ impl fmt::Display for ValidationError {
                 f,
                 "\"offload_tso\" and \"offload_ufo\" depend on \"offload_tso\""
             ),
+            VnetIoUringVhostUser => write!(
+                f,
+                "\"io_uring\" is only supported for virtio-net devices backed by a TAP device"
+            ),
             HugePageSizeWithoutHugePages => {
                 write!(f, "Huge page size specified but huge pages not enabled")
             }
             InvalidHugePageSize(s) => {
                 write!(f, "Huge page size is not power of 2: {s}")
             }
+            ThpCollapseWithoutThp => {
+                write!(f, "Hugepage collapse requires transparent huge pages")
+            }
             #[cfg(feature = "tdx")]
             TdxNoCpuHotplug => {
                 write!(f, "CPU hotplug is not permitted with TDX")
@@ -279,6 +555,17 @@ impl fmt::Display for ValidationError {
             TdxFirmwareMissing => {
                 write!(f, "No TDX firmware specified")
             }
+            #[cfg(feature = "tdx")]
+            TdxTooManyL2Vms(n) => {
+                write!(
+                    f,
+                    "Number of L2 VMs {n} exceeds the maximum {MAX_TDX_L2_VMS} for TD partitioning"
+                )
+            }
+            #[cfg(feature = "tdx")]
+            TdxL2VmsWithoutTdx => {
+                write!(f, "TD partitioning requires TDX to be enabled")
+            }
             TooManyQueues => {
                 write!(f, "Number of vCPUs is insufficient for number of queues")
             }
@@ -309,6 +596,12 @@ impl fmt::Display for ValidationError {
                     "Number of PCI segments ({n}) not in range of 1 to {MAX_NUM_PCI_SEGMENTS}"
                 )
             }
+            InvalidMaxPciSegments(n) => {
+                write!(
+                    f,
+                    "Maximum number of PCI segments ({n}) not in range of \"num_pci_segments\" to {MAX_NUM_PCI_SEGMENTS}"
+                )
+            }
             InvalidPciSegment(pci_segment) => {
                 write!(f, "Invalid PCI segment id: {pci_segment}")
             }
@@ -318,12 +611,74 @@ impl fmt::Display for ValidationError {
             InvalidIommuAddressWidthBits(iommu_address_width_bits) => {
                 write!(f, "IOMMU address width in bits ({iommu_address_width_bits}) should be less than or equal to {MAX_IOMMU_ADDRESS_WIDTH_BITS}")
             }
+            BootMethodUnsupported => {
+                write!(f, "Selecting the boot method is only supported on AArch64")
+            }
+            #[cfg(not(target_arch = "x86_64"))]
+            VmBusUnsupported => {
+                write!(f, "VMBus is only supported on x86_64")
+            }
+            #[cfg(not(target_arch = "x86_64"))]
+            LegacyDevicesUnsupported => {
+                write!(
+                    f,
+                    "Disabling the legacy devices is only supported on x86_64"
+                )
+            }
+            #[cfg(target_arch = "x86_64")]
+            SgxEpcSectionReused(s, u1, u2) => {
+                write!(
+                    f,
+                    "SGX EPC section: {s} belongs to multiple NUMA nodes {u1} and {u2}"
+                )
+            }
+            #[cfg(target_arch = "x86_64")]
+            UnknownSgxEpcSection(s) => {
+                write!(f, "Unknown SGX EPC section: {s}")
+            }
+            #[cfg(target_arch = "x86_64")]
+            VmBusRequiresKvmHyperv => {
+                write!(
+                    f,
+                    "VMBus requires the Hyper-V enlightenments (kvm_hyperv=on)"
+                )
+            }
+            #[cfg(target_arch = "aarch64")]
+            BootMethodFdtHotplug => {
+                write!(
+                    f,
+                    "CPU and memory hotplug are not supported with boot_method=fdt"
+                )
+            }
+            #[cfg(target_arch = "aarch64")]
+            BootMethodAcpiFirmware => {
+                write!(
+                    f,
+                    "boot_method=acpi is not supported when booting from a firmware"
+                )
+            }
             BalloonLargerThanRam(balloon_size, ram_size) => {
                 write!(
                     f,
                     "Ballon size ({balloon_size}) greater than RAM ({ram_size})"
                 )
             }
+            BalloonAutoResizeWithoutMaxSize => {
+                write!(f, "Balloon auto_resize requires max_size to be set")
+            }
+            BalloonInvalidSizeRange(min_size, max_size) => {
+                write!(
+                    f,
+                    "Balloon min_size ({min_size}) greater than max_size ({max_size})"
+                )
+            }
+            BalloonInvalidPressure(pressure_low, pressure_high) => {
+                write!(
+                    f,
+                    "Balloon pressure_low ({pressure_low}) must be lower than \
+                    pressure_high ({pressure_high}), at most 100"
+                )
+            }
             OnIommuSegment(pci_segment) => {
                 write!(
                     f,
@@ -364,6 +719,9 @@ impl fmt::Display for ValidationError {
             InvalidRateLimiterGroup => {
                 write!(f, "Invalid rate-limiter group")
             }
+            InvalidRateLimitSchedule(id) => {
+                write!(f, "Invalid schedule of rate-limiter group {id}")
+            }
             #[cfg(target_arch = "x86_64")]
             InvalidIoPortHex(s) => {
                 write!(
@@ -375,6 +733,20 @@ impl fmt::Display for ValidationError {
             InvalidHostData => {
                 write!(f, "Invalid host data format")
             }
+            #[cfg(feature = "sev_snp")]
+            SevSnpOptionsWithoutSevSnp => {
+                write!(
+                    f,
+                    "SEV-SNP guest policy, ID block and certificates require SEV-SNP to be enabled"
+                )
+            }
+            #[cfg(feature = "sev_snp")]
+            SevSnpIdAuthWithoutIdBlock => {
+                write!(
+                    f,
+                    "SEV-SNP ID authentication information requires an ID block"
+                )
+            }
             RestoreMissingRequiredNetId(s) => {
                 write!(f, "Net id {s} is associated with FDs and is required")
             }
@@ -400,6 +772,264 @@ impl fmt::Display for ValidationError {
                     "Block device serial length ({actual}) exceeds maximum allowed length ({max})"
                 )
             }
+            ScmiNoResources => {
+                write!(
+                    f,
+                    "SCMI device requires at least one sensor, power domain or clock"
+                )
+            }
+            CloudInitNoData => {
+                write!(f, "cloud-init seed requires user_data or network_config")
+            }
+            InvalidNetnsName(s) => {
+                write!(f, "Invalid network namespace name: {s}")
+            }
+            InvalidInterfaceName(s) => {
+                write!(f, "Invalid network interface name: {s}")
+            }
+            NetnsVethWithoutBridge => {
+                write!(f, "Network namespace veth interface requires a bridge")
+            }
+            FallbackFirmwareWithoutFirmware => {
+                write!(f, "Fallback firmware requires a primary firmware")
+            }
+            FallbackFirmwareZeroTimeout => {
+                write!(f, "Fallback firmware timeout must be greater than zero")
+            }
+            FallbackFirmwareUnsupported => {
+                write!(f, "Fallback firmware is not supported on this architecture")
+            }
+            NvdimmUnsupported => {
+                write!(f, "NVDIMM devices are not supported on this architecture")
+            }
+            NvdimmIommuUnsupported => {
+                write!(f, "NVDIMM devices can't be placed behind a virtual IOMMU")
+            }
+            CrashDumpWithoutPvpanic => {
+                write!(
+                    f,
+                    "Crash dumps are taken on guest panics and need --pvpanic"
+                )
+            }
+            IoThreadsZero => {
+                write!(f, "Number of I/O threads must be greater than zero")
+            }
+            InvalidIoThreadAffinity(i) => {
+                write!(
+                    f,
+                    "Affinity set for I/O thread {i}, which is not part of the pool"
+                )
+            }
+            InvalidIoThread(i) => {
+                write!(
+                    f,
+                    "Device assigned to I/O thread {i}, which is not part of the pool"
+                )
+            }
+            IoThreadVhostUser => {
+                write!(f, "\"io_thread\" is not supported for vhost-user devices")
+            }
+            IoThreadQueueAffinity => {
+                write!(
+                    f,
+                    "\"io_thread\" and \"queue_affinity\" are mutually exclusive"
+                )
+            }
+            InvalidMsixVectors(n) => {
+                write!(
+                    f,
+                    "Number of MSI-X vectors ({n}) not in range of 1 to {MAX_MSIX_VECTORS}"
+                )
+            }
+            CoalescingUsecsZero => {
+                write!(f, "\"coalesce_usecs\" must be greater than zero")
+            }
+            CoalescingVhostUser => {
+                write!(
+                    f,
+                    "Notification coalescing is not supported for vhost-user devices"
+                )
+            }
+            RateLimitGroupVhostUser => {
+                write!(
+                    f,
+                    "Rate limiter groups are not supported for vhost-user net devices"
+                )
+            }
+            NetWeightZero => {
+                write!(f, "Net device weight must be greater than zero")
+            }
+            PauseOnPathFailureVhostUser => {
+                write!(
+                    f,
+                    "Pausing on path failures is not supported for vhost-user disks"
+                )
+            }
+            FairQueuesWithoutRateLimiter => {
+                write!(
+                    f,
+                    "\"fair_queues\" can only be used with a rate limiter or rate limiter group"
+                )
+            }
+            FormatVhostUser => {
+                write!(f, "The image format can't be set for vhost-user disks")
+            }
+            CacheVhostUser => {
+                write!(f, "The cache mode can't be set for vhost-user disks")
+            }
+            CacheModeDirect(cache) => {
+                write!(f, "Disk cache mode {cache} can't be used with direct=on")
+            }
+            EncryptionKey => {
+                write!(
+                    f,
+                    "Encrypted disks need exactly one of \"key_file\" and \"key_fd\""
+                )
+            }
+            KeyWithoutEncryption => {
+                write!(
+                    f,
+                    "\"key_file\" and \"key_fd\" can only be used with \"encryption\""
+                )
+            }
+            EncryptionVhostUser => {
+                write!(f, "Encryption is not supported for vhost-user disks")
+            }
+            QueueSizeAutoVhostUser => {
+                write!(
+                    f,
+                    "\"queue_size=auto\" is not supported for vhost-user disks"
+                )
+            }
+            EncryptionFormat => {
+                write!(f, "The image format can't be set for encrypted disks")
+            }
+            EncryptionDirect => {
+                write!(f, "Encrypted disks can't be opened with O_DIRECT")
+            }
+            DiscardVhostUser => {
+                write!(
+                    f,
+                    "\"discard\" and \"detect_zeroes\" are not supported for vhost-user disks"
+                )
+            }
+            IoEngineVhostUser => {
+                write!(f, "\"io_engine\" is not supported for vhost-user disks")
+            }
+            IoEngineUnsupported(e, o) => {
+                write!(f, "The {e} I/O engine is not supported for {o}")
+            }
+            IoEngineThreadsWithoutThreads => {
+                write!(
+                    f,
+                    "\"io_engine_threads\" can only be used with \"io_engine=threads\""
+                )
+            }
+            IoEngineThreadsZero => {
+                write!(f, "\"io_engine_threads\" must be at least 1")
+            }
+            IoUringOptionsWithoutIoUring => {
+                write!(
+                    f,
+                    "\"io_uring_sqpoll\" and \"io_uring_fixed_buffers\" can only be used with the io_uring I/O engine"
+                )
+            }
+            IoClassVhostUser => {
+                write!(f, "\"io_class\" is not supported for vhost-user disks")
+            }
+            IoPrioWithoutIoClass => {
+                write!(
+                    f,
+                    "\"io_prio\" can only be used with \"io_class=rt\" or \"io_class=be\""
+                )
+            }
+            InvalidIoPrio(n) => {
+                write!(
+                    f,
+                    "I/O priority level ({n}) not in range of 0 to {IOPRIO_MAX_LEVEL}"
+                )
+            }
+            IoThreadIoClass => {
+                write!(f, "\"io_thread\" and \"io_class\" are mutually exclusive")
+            }
+            NvmeVhostUser => {
+                write!(f, "vhost-user disks can't use the NVMe interface")
+            }
+            NvmeIommu => {
+                write!(f, "NVMe disks can't be placed behind the virtual IOMMU")
+            }
+            NvmeDirect => {
+                write!(f, "NVMe disks can't be opened with O_DIRECT")
+            }
+            NvmeTooManyQueues(n) => {
+                write!(
+                    f,
+                    "NVMe disks support up to {NVME_MAX_IO_QUEUES} queues, {n} requested"
+                )
+            }
+            NvmeUnsupported(o) => {
+                write!(f, "{o} is not supported for NVMe disks")
+            }
+            TapOptionsWithoutTap => {
+                write!(
+                    f,
+                    "\"tap_persist\", \"tap_uid\" and \"tap_gid\" need a TAP interface opened by Cloud Hypervisor"
+                )
+            }
+            MacvtapWithOtherBackend => {
+                write!(
+                    f,
+                    "\"macvtap\" can't be combined with \"tap\", \"fd\" or \"vhost_user\""
+                )
+            }
+            MacvtapLinkWithoutMacvtap => {
+                write!(f, "\"macvtap_link\" needs \"macvtap\"")
+            }
+            VhostNetUnsupported(o) => {
+                write!(f, "{o} is not supported along with \"vhost\"")
+            }
+            HostFileMissing(o, p) => {
+                write!(f, "{o} {} does not exist on the host", p.display())
+            }
+            InvalidScmiClockRate => {
+                write!(f, "SCMI clock rates must be non-zero")
+            }
+            GpuDirectCliqueWithoutP2pDma(clique) => {
+                write!(
+                    f,
+                    "Devices of GPUDirect clique {clique} can't disable peer-to-peer DMA"
+                )
+            }
+            InvalidGpuDirectClique(clique) => {
+                write!(
+                    f,
+                    "GPUDirect clique {clique} is out of range (0-{MAX_GPUDIRECT_CLIQUE_ID})"
+                )
+            }
+            GpuDirectCliqueIommuMismatch(clique) => {
+                write!(
+                    f,
+                    "Devices of GPUDirect clique {clique} must either all or none be placed behind the virtual IOMMU"
+                )
+            }
+            MultipleLegacyVga => {
+                write!(
+                    f,
+                    "Legacy VGA ranges can only be forwarded to a single device"
+                )
+            }
+            DevicePathMissing => {
+                write!(f, "Device needs a path or a virtual function to create")
+            }
+            DuplicateSriovVf(pf, vf) => {
+                write!(f, "Duplicated virtual function {vf} of {pf}")
+            }
+            InvalidSriovVlan(vlan) => {
+                write!(
+                    f,
+                    "VLAN {vlan} of virtual function is out of range (0-4094)"
+                )
+            }
         }
     }
 }
@@ -414,10 +1044,15 @@ impl fmt::Display for Error {
             ParseConsoleInvalidModeGiven => {
                 write!(f, "Error parsing --console: invalid console mode given")
             }
+            ParseConsoleInvalidSink(s) => {
+                write!(f, "Error parsing --console: invalid tee output: {s}")
+            }
             ParseCpus(o) => write!(f, "Error parsing --cpus: {o}"),
             InvalidCpuFeatures(o) => write!(f, "Invalid feature in --cpus features list: {o}"),
             ParseDevice(o) => write!(f, "Error parsing --device: {o}"),
             ParseDevicePathMissing => write!(f, "Error parsing --device: path missing"),
+            ParseDeviceSriovVfMissing => write!(f, "Error parsing --device: sriov_vf missing"),
+            ParseDeviceSriovPfMissing => write!(f, "Error parsing --device: sriov_pf missing"),
             ParseFileSystem(o) => write!(f, "Error parsing --fs: {o}"),
             ParseFsSockMissing => write!(f, "Error parsing --fs: socket missing"),
             ParseFsTagMissing => write!(f, "Error parsing --fs: tag missing"),
@@ -436,8 +1071,31 @@ impl fmt::Display for Error {
             ParseMemoryZoneIdMissing => write!(f, "Error parsing --memory-zone: id missing"),
             ParseNetwork(o) => write!(f, "Error parsing --net: {o}"),
             ParseRateLimiterGroup(o) => write!(f, "Error parsing --rate-limit-group: {o}"),
+            ParseRateLimitSchedule(o) => write!(f, "Error parsing --rate-limit-schedule: {o}"),
+            ParseRateLimitScheduleUnknownGroup(g) => {
+                write!(f, "Error parsing --rate-limit-schedule: unknown group {g}")
+            }
             ParseDisk(o) => write!(f, "Error parsing --disk: {o}"),
             ParseRng(o) => write!(f, "Error parsing --rng: {o}"),
+            ParseScmi(o) => write!(f, "Error parsing --scmi: {o}"),
+            ParseCloudInit(o) => write!(f, "Error parsing --cloud-init: {o}"),
+            ParseImds(o) => write!(f, "Error parsing --imds: {o}"),
+            ParseImdsDocumentMissing => write!(f, "Error parsing --imds: document missing"),
+            ParseNetns(o) => write!(f, "Error parsing --netns: {o}"),
+            ParseNetnsNameMissing => write!(f, "Error parsing --netns: name missing"),
+            ParseGuestAgent(o) => write!(f, "Error parsing --guest-agent: {o}"),
+            #[cfg(feature = "pvmemcontrol")]
+            ParsePvmemcontrol(o) => write!(f, "Error parsing --pvmemcontrol: {o}"),
+            ParseResources(o) => write!(f, "Error parsing --resources: {o}"),
+            ParseRtc(o) => write!(f, "Error parsing --rtc: {o}"),
+            ParseRestartPolicy(o) => write!(f, "Error parsing --restart-policy: {o}"),
+            ParseCrashDump(o) => write!(f, "Error parsing --crash-dump: {o}"),
+            ParseCrashDumpPathMissing => write!(f, "Error parsing --crash-dump: path missing"),
+            ParseIoThreads(o) => write!(f, "Error parsing --io-threads: {o}"),
+            ParseFallbackFirmware(o) => write!(f, "Error parsing --fallback-firmware: {o}"),
+            ParseFallbackFirmwarePathMissing => {
+                write!(f, "Error parsing --fallback-firmware: path missing")
+            }
             ParseBalloon(o) => write!(f, "Error parsing --balloon: {o}"),
             ParseRestore(o) => write!(f, "Error parsing --restore: {o}"),
             #[cfg(target_arch = "x86_64")]
@@ -466,6 +1124,9 @@ impl fmt::Display for Error {
             ParseTpm(o) => write!(f, "Error parsing --tpm: {o}"),
             ParseTpmPathMissing => write!(f, "Error parsing --tpm: path missing"),
             ParseLandlockRules(o) => write!(f, "Error parsing --landlock-rules: {o}"),
+            ParsePayloadFd(s) => write!(f, "Error parsing payload file descriptor: {s}"),
+            ReadConfigFile(e) => write!(f, "Error reading --config: {e}"),
+            ParseConfigFile(s) => write!(f, "Error parsing --config: {s}"),
             ParseLandlockMissingFields => write!(
                 f,
                 "Error parsing --landlock-rules: path/access field missing"
@@ -489,10 +1150,12 @@ pub struct VmParams<'a> {
     pub memory: &'a str,
     pub memory_zones: Option<Vec<&'a str>>,
     pub firmware: Option<&'a str>,
+    pub fallback_firmware: Option<&'a str>,
     pub kernel: Option<&'a str>,
-    pub initramfs: Option<&'a str>,
+    pub initramfs: Option<Vec<&'a str>>,
     pub cmdline: Option<&'a str>,
     pub rate_limit_groups: Option<Vec<&'a str>>,
+    pub rate_limit_schedules: Option<Vec<&'a str>>,
     pub disks: Option<Vec<&'a str>>,
     pub net: Option<Vec<&'a str>>,
     pub rng: &'a str,
@@ -508,7 +1171,7 @@ pub struct VmParams<'a> {
     pub vdpa: Option<Vec<&'a str>>,
     pub vsock: Option<&'a str>,
     #[cfg(feature = "pvmemcontrol")]
-    pub pvmemcontrol: bool,
+    pub pvmemcontrol: Option<&'a str>,
     pub pvpanic: bool,
     #[cfg(target_arch = "x86_64")]
     pub sgx_epc: Option<Vec<&'a str>>,
@@ -519,10 +1182,26 @@ pub struct VmParams<'a> {
     pub pci_segments: Option<Vec<&'a str>>,
     pub platform: Option<&'a str>,
     pub tpm: Option<&'a str>,
+    pub scmi: Option<&'a str>,
+    pub cloud_init: Option<&'a str>,
+    pub imds: Option<&'a str>,
+    pub netns: Option<&'a str>,
+    pub guest_agent: Option<&'a str>,
+    pub resources: Option<&'a str>,
+    pub rtc: Option<&'a str>,
+    pub restart_policy: Option<&'a str>,
+    pub crash_dump: Option<&'a str>,
+    pub io_threads: Option<&'a str>,
     #[cfg(feature = "igvm")]
     pub igvm: Option<&'a str>,
     #[cfg(feature = "sev_snp")]
     pub host_data: Option<&'a str>,
+    #[cfg(feature = "sev_snp")]
+    pub id_block: Option<&'a str>,
+    #[cfg(feature = "sev_snp")]
+    pub id_auth: Option<&'a str>,
+    #[cfg(feature = "sev_snp")]
+    pub snp_certs: Option<&'a str>,
     pub landlock_enable: bool,
     pub landlock_rules: Option<Vec<&'a str>>,
 }
@@ -538,12 +1217,20 @@ impl<'a> VmParams<'a> {
         let rng = args.get_one::<String>("rng").unwrap();
         let serial = args.get_one::<String>("serial").unwrap();
         let firmware = args.get_one::<String>("firmware").map(|x| x as &str);
+        let fallback_firmware = args
+            .get_one::<String>("fallback-firmware")
+            .map(|x| x as &str);
         let kernel = args.get_one::<String>("kernel").map(|x| x as &str);
-        let initramfs = args.get_one::<String>("initramfs").map(|x| x as &str);
+        let initramfs: Option<Vec<&str>> = args
+            .get_many::<String>("initramfs")
+            .map(|x| x.map(|y| y as &str).collect());
         let cmdline = args.get_one::<String>("cmdline").map(|x| x as &str);
         let rate_limit_groups: Option<Vec<&str>> = args
             .get_many::<String>("rate-limit-group")
             .map(|x| x.map(|y| y as &str).collect());
+        let rate_limit_schedules: Option<Vec<&str>> = args
+            .get_many::<String>("rate-limit-schedule")
+            .map(|x| x.map(|y| y as &str).collect());
         let disks: Option<Vec<&str>> = args
             .get_many::<String>("disk")
             .map(|x| x.map(|y| y as &str).collect());
@@ -571,7 +1258,7 @@ impl<'a> VmParams<'a> {
             .map(|x| x.map(|y| y as &str).collect());
         let vsock: Option<&str> = args.get_one::<String>("vsock").map(|x| x as &str);
         #[cfg(feature = "pvmemcontrol")]
-        let pvmemcontrol = args.get_flag("pvmemcontrol");
+        let pvmemcontrol: Option<&str> = args.get_one::<String>("pvmemcontrol").map(|x| x as &str);
         let pvpanic = args.get_flag("pvpanic");
         #[cfg(target_arch = "x86_64")]
         let sgx_epc: Option<Vec<&str>> = args
@@ -588,10 +1275,27 @@ impl<'a> VmParams<'a> {
         #[cfg(feature = "guest_debug")]
         let gdb = args.contains_id("gdb");
         let tpm: Option<&str> = args.get_one::<String>("tpm").map(|x| x as &str);
+        let scmi: Option<&str> = args.get_one::<String>("scmi").map(|x| x as &str);
+        let cloud_init: Option<&str> = args.get_one::<String>("cloud-init").map(|x| x as &str);
+        let imds: Option<&str> = args.get_one::<String>("imds").map(|x| x as &str);
+        let netns: Option<&str> = args.get_one::<String>("netns").map(|x| x as &str);
+        let guest_agent: Option<&str> = args.get_one::<String>("guest-agent").map(|x| x as &str);
+        let resources: Option<&str> = args.get_one::<String>("resources").map(|x| x as &str);
+        let rtc: Option<&str> = args.get_one::<String>("rtc").map(|x| x as &str);
+        let restart_policy: Option<&str> =
+            args.get_one::<String>("restart-policy").map(|x| x as &str);
+        let crash_dump: Option<&str> = args.get_one::<String>("crash-dump").map(|x| x as &str);
+        let io_threads: Option<&str> = args.get_one::<String>("io-threads").map(|x| x as &str);
         #[cfg(feature = "igvm")]
         let igvm = args.get_one::<String>("igvm").map(|x| x as &str);
         #[cfg(feature = "sev_snp")]
         let host_data = args.get_one::<String>("host-data").map(|x| x as &str);
+        #[cfg(feature = "sev_snp")]
+        let id_block = args.get_one::<String>("id-block").map(|x| x as &str);
+        #[cfg(feature = "sev_snp")]
+        let id_auth = args.get_one::<String>("id-auth").map(|x| x as &str);
+        #[cfg(feature = "sev_snp")]
+        let snp_certs = args.get_one::<String>("snp-certs").map(|x| x as &str);
         let landlock_enable = args.get_flag("landlock");
         let landlock_rules: Option<Vec<&str>> = args
             .get_many::<String>("landlock-rules")
@@ -602,10 +1306,12 @@ impl<'a> VmParams<'a> {
             memory,
             memory_zones,
             firmware,
+            fallback_firmware,
             kernel,
             initramfs,
             cmdline,
             rate_limit_groups,
+            rate_limit_schedules,
             disks,
             net,
             rng,
@@ -632,10 +1338,26 @@ impl<'a> VmParams<'a> {
             pci_segments,
             platform,
             tpm,
+            scmi,
+            cloud_init,
+            imds,
+            netns,
+            guest_agent,
+            resources,
+            rtc,
+            restart_policy,
+            crash_dump,
+            io_threads,
             #[cfg(feature = "igvm")]
             igvm,
             #[cfg(feature = "sev_snp")]
             host_data,
+            #[cfg(feature = "sev_snp")]
+            id_block,
+            #[cfg(feature = "sev_snp")]
+            id_auth,
+            #[cfg(feature = "sev_snp")]
+            snp_certs,
             landlock_enable,
             landlock_rules,
         }
@@ -659,6 +1381,24 @@ impl FromStr for HotplugMethod {
     }
 }
 
+#[derive(Debug)]
+pub enum ParseBootMethodError {
+    InvalidValue(String),
+}
+
+impl FromStr for BootMethod {
+    type Err = ParseBootMethodError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "both" => Ok(BootMethod::Both),
+            "fdt" => Ok(BootMethod::DeviceTree),
+            "acpi" => Ok(BootMethod::Acpi),
+            _ => Err(ParseBootMethodError::InvalidValue(s.to_owned())),
+        }
+    }
+}
+
 pub enum CpuTopologyParseError {
     InvalidValue(String),
 }
@@ -705,11 +1445,11 @@ impl CpusConfig {
             .add("features");
         parser.parse(cpus).map_err(Error::ParseCpus)?;
 
-        let boot_vcpus: u8 = parser
+        let boot_vcpus: u32 = parser
             .convert("boot")
             .map_err(Error::ParseCpus)?
             .unwrap_or(DEFAULT_VCPUS);
-        let max_vcpus: u8 = parser
+        let max_vcpus: u32 = parser
             .convert("max")
             .map_err(Error::ParseCpus)?
             .unwrap_or(boot_vcpus);
@@ -724,7 +1464,7 @@ impl CpusConfig {
             .map_err(Error::ParseCpus)?
             .unwrap_or(DEFAULT_MAX_PHYS_BITS);
         let affinity = parser
-            .convert::<Tuple<u8, Vec<usize>>>("affinity")
+            .convert::<Tuple<u32, Vec<usize>>>("affinity")
             .map_err(Error::ParseCpus)?
             .map(|v| {
                 v.0.iter()
@@ -766,6 +1506,17 @@ impl CpusConfig {
             features,
         })
     }
+
+    /// Whether some vCPUs are given APIC IDs which don't fit in 8 bits, 255
+    /// being the xAPIC broadcast ID.
+    #[cfg(target_arch = "x86_64")]
+    pub fn has_wide_apic_ids(&self) -> bool {
+        let topology = self
+            .topology
+            .as_ref()
+            .map(|t| (t.threads_per_core, t.cores_per_die, t.packages));
+        arch::x86_64::get_x2apic_id(self.max_vcpus.saturating_sub(1), topology) >= 255
+    }
 }
 
 impl PciSegmentConfig {
@@ -801,12 +1552,8 @@ impl PciSegmentConfig {
     }
 
     pub fn validate(&self, vm_config: &VmConfig) -> ValidationResult<()> {
-        let num_pci_segments = match &vm_config.platform {
-            Some(platform_config) => platform_config.num_pci_segments,
-            None => 1,
-        };
-
-        if self.pci_segment >= num_pci_segments {
+        // The apertures of the segments added at runtime are set at boot.
+        if self.pci_segment >= vm_config.max_pci_segments() {
             return Err(ValidationError::InvalidPciSegment(self.pci_segment));
         }
 
@@ -831,21 +1578,34 @@ impl PlatformConfig {
         let mut parser = OptionParser::new();
         parser
             .add("num_pci_segments")
+            .add("max_pci_segments")
             .add("iommu_segments")
             .add("iommu_address_width")
             .add("serial_number")
             .add("uuid")
-            .add("oem_strings");
+            .add("oem_strings")
+            .add("boot_method")
+            .add("vmbus")
+            .add("legacy_devices")
+            .add("auto_numa")
+            .add("host_numa_distances");
         #[cfg(feature = "tdx")]
-        parser.add("tdx");
+        parser.add("tdx").add("tdx_l2_vms");
         #[cfg(feature = "sev_snp")]
-        parser.add("sev_snp");
+        parser
+            .add("sev_snp")
+            .add("sev_snp_smt")
+            .add("sev_snp_debug")
+            .add("sev_snp_migrate_ma");
         parser.parse(platform).map_err(Error::ParsePlatform)?;
 
         let num_pci_segments: u16 = parser
             .convert("num_pci_segments")
             .map_err(Error::ParsePlatform)?
             .unwrap_or(DEFAULT_NUM_PCI_SEGMENTS);
+        let max_pci_segments = parser
+            .convert("max_pci_segments")
+            .map_err(Error::ParsePlatform)?;
         let iommu_segments = parser
             .convert::<IntegerList>("iommu_segments")
             .map_err(Error::ParsePlatform)?
@@ -862,29 +1622,90 @@ impl PlatformConfig {
             .convert::<StringList>("oem_strings")
             .map_err(Error::ParsePlatform)?
             .map(|v| v.0);
+        let boot_method = parser
+            .convert("boot_method")
+            .map_err(Error::ParsePlatform)?
+            .unwrap_or_default();
+        let vmbus = parser
+            .convert::<Toggle>("vmbus")
+            .map_err(Error::ParsePlatform)?
+            .unwrap_or(Toggle(false))
+            .0;
+        let legacy_devices = parser
+            .convert::<Toggle>("legacy_devices")
+            .map_err(Error::ParsePlatform)?
+            .unwrap_or(Toggle(true))
+            .0;
+        let auto_numa = parser
+            .convert::<Toggle>("auto_numa")
+            .map_err(Error::ParsePlatform)?
+            .unwrap_or(Toggle(false))
+            .0;
+        let host_numa_distances = parser
+            .convert::<Toggle>("host_numa_distances")
+            .map_err(Error::ParsePlatform)?
+            .unwrap_or(Toggle(false))
+            .0;
         #[cfg(feature = "tdx")]
         let tdx = parser
             .convert::<Toggle>("tdx")
             .map_err(Error::ParsePlatform)?
             .unwrap_or(Toggle(false))
             .0;
+        #[cfg(feature = "tdx")]
+        let tdx_l2_vms = parser
+            .convert("tdx_l2_vms")
+            .map_err(Error::ParsePlatform)?
+            .unwrap_or_default();
         #[cfg(feature = "sev_snp")]
         let sev_snp = parser
             .convert::<Toggle>("sev_snp")
             .map_err(Error::ParsePlatform)?
             .unwrap_or(Toggle(false))
             .0;
+        #[cfg(feature = "sev_snp")]
+        let sev_snp_smt = parser
+            .convert::<Toggle>("sev_snp_smt")
+            .map_err(Error::ParsePlatform)?
+            .unwrap_or(Toggle(true))
+            .0;
+        #[cfg(feature = "sev_snp")]
+        let sev_snp_debug = parser
+            .convert::<Toggle>("sev_snp_debug")
+            .map_err(Error::ParsePlatform)?
+            .unwrap_or(Toggle(false))
+            .0;
+        #[cfg(feature = "sev_snp")]
+        let sev_snp_migrate_ma = parser
+            .convert::<Toggle>("sev_snp_migrate_ma")
+            .map_err(Error::ParsePlatform)?
+            .unwrap_or(Toggle(false))
+            .0;
         Ok(PlatformConfig {
             num_pci_segments,
+            max_pci_segments,
             iommu_segments,
             iommu_address_width_bits,
             serial_number,
             uuid,
             oem_strings,
+            boot_method,
+            vmbus,
+            legacy_devices,
+            auto_numa,
+            host_numa_distances,
             #[cfg(feature = "tdx")]
             tdx,
+            #[cfg(feature = "tdx")]
+            tdx_l2_vms,
             #[cfg(feature = "sev_snp")]
             sev_snp,
+            #[cfg(feature = "sev_snp")]
+            sev_snp_smt,
+            #[cfg(feature = "sev_snp")]
+            sev_snp_debug,
+            #[cfg(feature = "sev_snp")]
+            sev_snp_migrate_ma,
         })
     }
 
@@ -895,9 +1716,16 @@ impl PlatformConfig {
             ));
         }
 
+        let max_pci_segments = self.max_pci_segments.unwrap_or(self.num_pci_segments);
+        if max_pci_segments < self.num_pci_segments || max_pci_segments > MAX_NUM_PCI_SEGMENTS {
+            return Err(ValidationError::InvalidMaxPciSegments(max_pci_segments));
+        }
+
+        // The segments added at runtime are placed behind the IOMMU from
+        // boot, the guest learning the topology of the IOMMU then.
         if let Some(iommu_segments) = &self.iommu_segments {
             for segment in iommu_segments {
-                if *segment >= self.num_pci_segments {
+                if *segment >= max_pci_segments {
                     return Err(ValidationError::InvalidPciSegment(*segment));
                 }
             }
@@ -909,6 +1737,32 @@ impl PlatformConfig {
             ));
         }
 
+        #[cfg(not(target_arch = "aarch64"))]
+        if self.boot_method != BootMethod::Both {
+            return Err(ValidationError::BootMethodUnsupported);
+        }
+
+        #[cfg(not(target_arch = "x86_64"))]
+        if self.vmbus {
+            return Err(ValidationError::VmBusUnsupported);
+        }
+
+        #[cfg(not(target_arch = "x86_64"))]
+        if !self.legacy_devices {
+            return Err(ValidationError::LegacyDevicesUnsupported);
+        }
+
+        #[cfg(feature = "tdx")]
+        {
+            if self.tdx_l2_vms > MAX_TDX_L2_VMS {
+                return Err(ValidationError::TdxTooManyL2Vms(self.tdx_l2_vms));
+            }
+
+            if self.tdx_l2_vms > 0 && !self.tdx {
+                return Err(ValidationError::TdxL2VmsWithoutTdx);
+            }
+        }
+
         Ok(())
     }
 }
@@ -927,7 +1781,8 @@ impl MemoryConfig {
             .add("hugepages")
             .add("hugepage_size")
             .add("prefault")
-            .add("thp");
+            .add("thp")
+            .add("thp_collapse");
         parser.parse(memory).map_err(Error::ParseMemory)?;
 
         let size = parser
@@ -976,6 +1831,11 @@ impl MemoryConfig {
             .map_err(Error::ParseMemory)?
             .unwrap_or(Toggle(true))
             .0;
+        let thp_collapse = parser
+            .convert::<Toggle>("thp_collapse")
+            .map_err(Error::ParseMemory)?
+            .unwrap_or(Toggle(false))
+            .0;
 
         let zones: Option<Vec<MemoryZoneConfig>> = if let Some(memory_zones) = &memory_zones {
             let mut zones = Vec::new();
@@ -1063,6 +1923,7 @@ impl MemoryConfig {
             prefault,
             zones,
             thp,
+            thp_collapse,
         })
     }
 
@@ -1085,6 +1946,55 @@ impl MemoryConfig {
     }
 }
 
+// Parses the bandwidth and operations token buckets shared by the
+// rate-limiter groups and their schedules.
+fn parse_rate_limiter_config(
+    parser: &OptionParser,
+) -> result::Result<RateLimiterConfig, OptionParserError> {
+    let bw_size = parser.convert("bw_size")?.unwrap_or_default();
+    let bw_one_time_burst = parser.convert("bw_one_time_burst")?.unwrap_or_default();
+    let bw_refill_time = parser.convert("bw_refill_time")?.unwrap_or_default();
+    let ops_size = parser.convert("ops_size")?.unwrap_or_default();
+    let ops_one_time_burst = parser.convert("ops_one_time_burst")?.unwrap_or_default();
+    let ops_refill_time = parser.convert("ops_refill_time")?.unwrap_or_default();
+
+    let bw_tb_config = if bw_size != 0 && bw_refill_time != 0 {
+        Some(TokenBucketConfig {
+            size: bw_size,
+            one_time_burst: Some(bw_one_time_burst),
+            refill_time: bw_refill_time,
+        })
+    } else {
+        None
+    };
+    let ops_tb_config = if ops_size != 0 && ops_refill_time != 0 {
+        Some(TokenBucketConfig {
+            size: ops_size,
+            one_time_burst: Some(ops_one_time_burst),
+            refill_time: ops_refill_time,
+        })
+    } else {
+        None
+    };
+
+    Ok(RateLimiterConfig {
+        bandwidth: bw_tb_config,
+        ops: ops_tb_config,
+    })
+}
+
+// Minute of the day of a "HH:MM" time.
+fn parse_time_of_day(time: &str) -> Option<u32> {
+    let (hours, minutes) = time.split_once(':')?;
+    let hours: u32 = hours.parse().ok()?;
+    let minutes: u32 = minutes.parse().ok()?;
+    if hours >= 24 || minutes >= 60 {
+        return None;
+    }
+
+    Some(hours * 60 + minutes)
+}
+
 impl RateLimiterGroupConfig {
     pub const SYNTAX: &'static str = "Rate Limit Group parameters \
         \"bw_size=<bytes>,bw_one_time_burst=<bytes>,bw_refill_time=<ms>,\
@@ -1106,56 +2016,13 @@ impl RateLimiterGroupConfig {
             .map_err(Error::ParseRateLimiterGroup)?;
 
         let id = parser.get("id").unwrap_or_default();
-        let bw_size = parser
-            .convert("bw_size")
-            .map_err(Error::ParseRateLimiterGroup)?
-            .unwrap_or_default();
-        let bw_one_time_burst = parser
-            .convert("bw_one_time_burst")
-            .map_err(Error::ParseRateLimiterGroup)?
-            .unwrap_or_default();
-        let bw_refill_time = parser
-            .convert("bw_refill_time")
-            .map_err(Error::ParseRateLimiterGroup)?
-            .unwrap_or_default();
-        let ops_size = parser
-            .convert("ops_size")
-            .map_err(Error::ParseRateLimiterGroup)?
-            .unwrap_or_default();
-        let ops_one_time_burst = parser
-            .convert("ops_one_time_burst")
-            .map_err(Error::ParseRateLimiterGroup)?
-            .unwrap_or_default();
-        let ops_refill_time = parser
-            .convert("ops_refill_time")
-            .map_err(Error::ParseRateLimiterGroup)?
-            .unwrap_or_default();
-
-        let bw_tb_config = if bw_size != 0 && bw_refill_time != 0 {
-            Some(TokenBucketConfig {
-                size: bw_size,
-                one_time_burst: Some(bw_one_time_burst),
-                refill_time: bw_refill_time,
-            })
-        } else {
-            None
-        };
-        let ops_tb_config = if ops_size != 0 && ops_refill_time != 0 {
-            Some(TokenBucketConfig {
-                size: ops_size,
-                one_time_burst: Some(ops_one_time_burst),
-                refill_time: ops_refill_time,
-            })
-        } else {
-            None
-        };
+        let rate_limiter_config =
+            parse_rate_limiter_config(&parser).map_err(Error::ParseRateLimiterGroup)?;
 
         Ok(RateLimiterGroupConfig {
             id,
-            rate_limiter_config: RateLimiterConfig {
-                bandwidth: bw_tb_config,
-                ops: ops_tb_config,
-            },
+            rate_limiter_config,
+            schedules: None,
         })
     }
 
@@ -1168,20 +2035,92 @@ impl RateLimiterGroupConfig {
             return Err(ValidationError::InvalidRateLimiterGroup);
         }
 
+        for schedule in self.schedules.iter().flatten() {
+            let start = parse_time_of_day(&schedule.start);
+            let end = parse_time_of_day(&schedule.end);
+            if start.is_none() || end.is_none() || start == end {
+                return Err(ValidationError::InvalidRateLimitSchedule(self.id.clone()));
+            }
+        }
+
         Ok(())
     }
+
+    /// Returns the index of the schedule active at the given minute of the
+    /// day, the first one listed winning when several overlap. A schedule
+    /// whose end precedes its start spans midnight.
+    pub fn active_schedule(&self, minute_of_day: u32) -> Option<usize> {
+        self.schedules.iter().flatten().position(|schedule| {
+            match (
+                parse_time_of_day(&schedule.start),
+                parse_time_of_day(&schedule.end),
+            ) {
+                (Some(start), Some(end)) if start < end => (start..end).contains(&minute_of_day),
+                (Some(start), Some(end)) => minute_of_day >= start || minute_of_day < end,
+                _ => false,
+            }
+        })
+    }
+}
+
+impl RateLimitScheduleConfig {
+    pub const SYNTAX: &'static str = "Rate Limit Schedule parameters \
+        \"group=<group_id>,start=<HH:MM>,end=<HH:MM>,\
+        bw_size=<bytes>,bw_one_time_burst=<bytes>,bw_refill_time=<ms>,\
+        ops_size=<io_ops>,ops_one_time_burst=<io_ops>,ops_refill_time=<ms>\"";
+
+    /// Parses a schedule, returning it along with the id of its group.
+    pub fn parse(rate_limit_schedule: &str) -> Result<(String, Self)> {
+        let mut parser = OptionParser::new();
+        parser
+            .add("group")
+            .add("start")
+            .add("end")
+            .add("bw_size")
+            .add("bw_one_time_burst")
+            .add("bw_refill_time")
+            .add("ops_size")
+            .add("ops_one_time_burst")
+            .add("ops_refill_time");
+        parser
+            .parse(rate_limit_schedule)
+            .map_err(Error::ParseRateLimitSchedule)?;
+
+        let group = parser.get("group").unwrap_or_default();
+        let start = parser.get("start").unwrap_or_default();
+        let end = parser.get("end").unwrap_or_default();
+        let rate_limiter_config =
+            parse_rate_limiter_config(&parser).map_err(Error::ParseRateLimitSchedule)?;
+
+        Ok((
+            group,
+            RateLimitScheduleConfig {
+                start,
+                end,
+                rate_limiter_config,
+            },
+        ))
+    }
 }
 
 impl DiskConfig {
     pub const SYNTAX: &'static str = "Disk parameters \
          \"path=<disk_image_path>,readonly=on|off,direct=on|off,iommu=on|off,\
-         num_queues=<number_of_queues>,queue_size=<size_of_each_queue>,\
+         num_queues=<number_of_queues>,queue_size=<size_of_each_queue>|auto,\
          vhost_user=on|off,socket=<vhost_user_socket_path>,\
          bw_size=<bytes>,bw_one_time_burst=<bytes>,bw_refill_time=<ms>,\
          ops_size=<io_ops>,ops_one_time_burst=<io_ops>,ops_refill_time=<ms>,\
          id=<device_id>,pci_segment=<segment_id>,rate_limit_group=<group_id>,\
-         queue_affinity=<list_of_queue_indices_with_their_associated_cpuset>,\
-         serial=<serial_number>";
+         fair_queues=on|off,queue_affinity=<list_of_queue_indices_with_their_associated_cpuset>,\
+         serial=<serial_number>,io_thread=<io_thread_index>,\
+         coalesce_usecs=<usecs>,coalesce_max_used=<used_buffers>,\
+         pause_on_path_failure=on|off,format=raw|qcow2|vhd|vhdx,\
+         cache=writeback|writethrough|none|unsafe,encryption=luks,\
+         key_file=<key_file_path>,key_fd=<key_fd>,discard=on|off,\
+         detect_zeroes=on|off,interface=virtio|nvme,\
+         io_engine=io_uring|aio|sync|threads,io_engine_threads=<number_of_threads>,\
+         io_uring_sqpoll=on|off,io_uring_fixed_buffers=on|off,\
+         io_class=rt|be|idle,io_prio=<priority_level>,msix_vectors=<number_of_vectors>";
 
     pub fn parse(disk: &str) -> Result<Self> {
         let mut parser = OptionParser::new();
@@ -1201,12 +2140,30 @@ impl DiskConfig {
             .add("ops_one_time_burst")
             .add("ops_refill_time")
             .add("id")
-            .add("_disable_io_uring")
-            .add("_disable_aio")
             .add("pci_segment")
             .add("serial")
             .add("rate_limit_group")
-            .add("queue_affinity");
+            .add("fair_queues")
+            .add("queue_affinity")
+            .add("io_thread")
+            .add("coalesce_usecs")
+            .add("coalesce_max_used")
+            .add("pause_on_path_failure")
+            .add("format")
+            .add("cache")
+            .add("encryption")
+            .add("key_file")
+            .add("key_fd")
+            .add("discard")
+            .add("detect_zeroes")
+            .add("interface")
+            .add("io_engine")
+            .add("io_engine_threads")
+            .add("io_uring_sqpoll")
+            .add("io_uring_fixed_buffers")
+            .add("io_class")
+            .add("io_prio")
+            .add("msix_vectors");
         parser.parse(disk).map_err(Error::ParseDisk)?;
 
         let path = parser.get("path").map(PathBuf::from);
@@ -1226,7 +2183,7 @@ impl DiskConfig {
             .unwrap_or(Toggle(false))
             .0;
         let queue_size = parser
-            .convert("queue_size")
+            .convert::<QueueSize>("queue_size")
             .map_err(Error::ParseDisk)?
             .unwrap_or_else(default_diskconfig_queue_size);
         let num_queues = parser
@@ -1240,16 +2197,6 @@ impl DiskConfig {
             .0;
         let vhost_socket = parser.get("socket");
         let id = parser.get("id");
-        let disable_io_uring = parser
-            .convert::<Toggle>("_disable_io_uring")
-            .map_err(Error::ParseDisk)?
-            .unwrap_or(Toggle(false))
-            .0;
-        let disable_aio = parser
-            .convert::<Toggle>("_disable_aio")
-            .map_err(Error::ParseDisk)?
-            .unwrap_or(Toggle(false))
-            .0;
         let pci_segment = parser
             .convert("pci_segment")
             .map_err(Error::ParseDisk)?
@@ -1291,6 +2238,66 @@ impl DiskConfig {
                     })
                     .collect()
             });
+        let io_thread = parser
+            .convert::<u16>("io_thread")
+            .map_err(Error::ParseDisk)?;
+        let coalescing = parse_coalescing(&parser).map_err(Error::ParseDisk)?;
+        let pause_on_path_failure = parser
+            .convert::<Toggle>("pause_on_path_failure")
+            .map_err(Error::ParseDisk)?
+            .unwrap_or(Toggle(false))
+            .0;
+        let fair_queues = parser
+            .convert::<Toggle>("fair_queues")
+            .map_err(Error::ParseDisk)?
+            .unwrap_or(Toggle(false))
+            .0;
+        let format = parser
+            .convert::<ImageType>("format")
+            .map_err(Error::ParseDisk)?;
+        let cache = parser
+            .convert::<CacheMode>("cache")
+            .map_err(Error::ParseDisk)?;
+        let encryption = parser
+            .convert::<DiskEncryption>("encryption")
+            .map_err(Error::ParseDisk)?;
+        let key_file = parser.get("key_file").map(PathBuf::from);
+        let key_fd = parser.convert::<i32>("key_fd").map_err(Error::ParseDisk)?;
+        let discard = parser
+            .convert::<Toggle>("discard")
+            .map_err(Error::ParseDisk)?
+            .unwrap_or(Toggle(false))
+            .0;
+        let detect_zeroes = parser
+            .convert::<Toggle>("detect_zeroes")
+            .map_err(Error::ParseDisk)?
+            .unwrap_or(Toggle(false))
+            .0;
+        let interface = parser
+            .convert::<DiskInterface>("interface")
+            .map_err(Error::ParseDisk)?
+            .unwrap_or_default();
+        let io_engine = parser
+            .convert::<IoEngine>("io_engine")
+            .map_err(Error::ParseDisk)?;
+        let io_engine_threads = parser
+            .convert::<usize>("io_engine_threads")
+            .map_err(Error::ParseDisk)?;
+        let io_uring_sqpoll = parser
+            .convert::<Toggle>("io_uring_sqpoll")
+            .map_err(Error::ParseDisk)?
+            .unwrap_or(Toggle(false))
+            .0;
+        let io_uring_fixed_buffers = parser
+            .convert::<Toggle>("io_uring_fixed_buffers")
+            .map_err(Error::ParseDisk)?
+            .unwrap_or(Toggle(false))
+            .0;
+        let io_class = parser
+            .convert::<IoClass>("io_class")
+            .map_err(Error::ParseDisk)?;
+        let io_prio = parser.convert("io_prio").map_err(Error::ParseDisk)?;
+        let msix_vectors = parser.convert("msix_vectors").map_err(Error::ParseDisk)?;
         let bw_tb_config = if bw_size != 0 && bw_refill_time != 0 {
             Some(TokenBucketConfig {
                 size: bw_size,
@@ -1329,12 +2336,29 @@ impl DiskConfig {
             vhost_socket,
             rate_limit_group,
             rate_limiter_config,
+            fair_queues,
             id,
-            disable_io_uring,
-            disable_aio,
             pci_segment,
             serial,
             queue_affinity,
+            io_thread,
+            coalescing,
+            pause_on_path_failure,
+            format,
+            cache,
+            encryption,
+            key_file,
+            key_fd,
+            discard,
+            detect_zeroes,
+            interface,
+            io_engine,
+            io_engine_threads,
+            io_uring_sqpoll,
+            io_uring_fixed_buffers,
+            io_class,
+            io_prio,
+            msix_vectors,
         })
     }
 
@@ -1343,8 +2367,14 @@ impl DiskConfig {
             return Err(ValidationError::TooManyQueues);
         }
 
-        if self.queue_size <= MINIMUM_BLOCK_QUEUE_SIZE {
-            return Err(ValidationError::InvalidQueueSize(self.queue_size));
+        match self.queue_size {
+            QueueSize::Fixed(size) if size <= MINIMUM_BLOCK_QUEUE_SIZE => {
+                return Err(ValidationError::InvalidQueueSize(size));
+            }
+            QueueSize::Auto if self.vhost_user => {
+                return Err(ValidationError::QueueSizeAutoVhostUser);
+            }
+            _ => {}
         }
 
         if self.vhost_user && self.iommu {
@@ -1367,6 +2397,11 @@ impl DiskConfig {
             return Err(ValidationError::InvalidRateLimiterGroup);
         }
 
+        if self.fair_queues && self.rate_limiter_config.is_none() && self.rate_limit_group.is_none()
+        {
+            return Err(ValidationError::FairQueuesWithoutRateLimiter);
+        }
+
         // Check Block device serial length
         if let Some(ref serial) = self.serial {
             if serial.len() > VIRTIO_BLK_ID_BYTES as usize {
@@ -1377,48 +2412,311 @@ impl DiskConfig {
             }
         }
 
+        if let Some(io_thread) = self.io_thread {
+            if self.vhost_user {
+                return Err(ValidationError::IoThreadVhostUser);
+            }
+
+            if self.queue_affinity.is_some() {
+                return Err(ValidationError::IoThreadQueueAffinity);
+            }
+
+            // The priority is set on the threads submitting the I/O, which
+            // an I/O thread shares with other devices.
+            if self.io_class.is_some() {
+                return Err(ValidationError::IoThreadIoClass);
+            }
+
+            validate_io_thread(io_thread, vm_config)?;
+        }
+
+        if self.vhost_user && self.io_class.is_some() {
+            return Err(ValidationError::IoClassVhostUser);
+        }
+
+        if let Some(io_prio) = self.io_prio {
+            if !matches!(self.io_class, Some(IoClass::RealTime | IoClass::BestEffort)) {
+                return Err(ValidationError::IoPrioWithoutIoClass);
+            }
+
+            if io_prio > IOPRIO_MAX_LEVEL {
+                return Err(ValidationError::InvalidIoPrio(io_prio));
+            }
+        }
+
+        if let Some(coalescing) = &self.coalescing {
+            if self.vhost_user {
+                return Err(ValidationError::CoalescingVhostUser);
+            }
+
+            coalescing.validate()?;
+        }
+
+        validate_msix_vectors(self.msix_vectors)?;
+
+        if self.vhost_user && self.pause_on_path_failure {
+            return Err(ValidationError::PauseOnPathFailureVhostUser);
+        }
+
+        if self.vhost_user && self.format.is_some() {
+            return Err(ValidationError::FormatVhostUser);
+        }
+
+        if let Some(cache) = self.cache {
+            if self.vhost_user {
+                return Err(ValidationError::CacheVhostUser);
+            }
+
+            if self.direct && cache != CacheMode::Direct {
+                return Err(ValidationError::CacheModeDirect(cache));
+            }
+        }
+
+        if self.encryption.is_some() {
+            if self.vhost_user {
+                return Err(ValidationError::EncryptionVhostUser);
+            }
+
+            if self.key_file.is_some() == self.key_fd.is_some() {
+                return Err(ValidationError::EncryptionKey);
+            }
+
+            if self.format.is_some() {
+                return Err(ValidationError::EncryptionFormat);
+            }
+
+            if self.direct_io() {
+                return Err(ValidationError::EncryptionDirect);
+            }
+        } else if self.key_file.is_some() || self.key_fd.is_some() {
+            return Err(ValidationError::KeyWithoutEncryption);
+        }
+
+        if self.vhost_user && (self.discard || self.detect_zeroes) {
+            return Err(ValidationError::DiscardVhostUser);
+        }
+
+        if let Some(io_engine) = self.io_engine {
+            self.validate_io_engine(io_engine)?;
+        } else if self.io_engine_threads.is_some() {
+            return Err(ValidationError::IoEngineThreadsWithoutThreads);
+        }
+
+        // The io_uring engine is picked by default when the host supports
+        // it, the options being ignored otherwise.
+        if (self.io_uring_sqpoll || self.io_uring_fixed_buffers)
+            && (self.vhost_user || self.io_engine.is_some_and(|e| e != IoEngine::IoUring))
+        {
+            return Err(ValidationError::IoUringOptionsWithoutIoUring);
+        }
+
+        if self.interface == DiskInterface::Nvme {
+            self.validate_nvme()?;
+        }
+
+        Ok(())
+    }
+
+    // The image format may only be known once the image is opened, the
+    // engines it doesn't support are rejected then.
+    fn validate_io_engine(&self, io_engine: IoEngine) -> ValidationResult<()> {
+        if self.vhost_user {
+            return Err(ValidationError::IoEngineVhostUser);
+        }
+
+        match self.io_engine_threads {
+            Some(0) => return Err(ValidationError::IoEngineThreadsZero),
+            Some(_) if io_engine != IoEngine::Threads => {
+                return Err(ValidationError::IoEngineThreadsWithoutThreads)
+            }
+            _ => {}
+        }
+
+        if io_engine == IoEngine::Sync {
+            return Ok(());
+        }
+
+        if self.encryption.is_some() {
+            return Err(ValidationError::IoEngineUnsupported(
+                io_engine,
+                "encrypted disks",
+            ));
+        }
+
+        match self.format {
+            Some(ImageType::Qcow2) => Err(ValidationError::IoEngineUnsupported(
+                io_engine,
+                "qcow2 images",
+            )),
+            Some(ImageType::Vhdx) => Err(ValidationError::IoEngineUnsupported(
+                io_engine,
+                "vhdx images",
+            )),
+            Some(ImageType::FixedVhd) if io_engine != IoEngine::IoUring => Err(
+                ValidationError::IoEngineUnsupported(io_engine, "vhd images"),
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    // The NVMe controller serves its queues from a single thread, submitting
+    // the I/O to the disk image as is.
+    fn validate_nvme(&self) -> ValidationResult<()> {
+        if self.vhost_user {
+            return Err(ValidationError::NvmeVhostUser);
+        }
+
+        if self.iommu {
+            return Err(ValidationError::NvmeIommu);
+        }
+
+        if self.direct_io() {
+            return Err(ValidationError::NvmeDirect);
+        }
+
+        if self.num_queues > NVME_MAX_IO_QUEUES {
+            return Err(ValidationError::NvmeTooManyQueues(self.num_queues));
+        }
+
+        let unsupported = [
+            (self.queue_size == QueueSize::Auto, "queue_size=auto"),
+            (
+                self.rate_limiter_config.is_some() || self.rate_limit_group.is_some(),
+                "rate limiting",
+            ),
+            (self.queue_affinity.is_some(), "queue_affinity"),
+            (self.io_thread.is_some(), "io_thread"),
+            (self.coalescing.is_some(), "coalescing"),
+            (self.msix_vectors.is_some(), "msix_vectors"),
+            (self.io_class.is_some(), "io_class"),
+            (self.pause_on_path_failure, "pause_on_path_failure"),
+            (
+                self.discard || self.detect_zeroes,
+                "discard and detect_zeroes",
+            ),
+        ];
+        if let Some((_, option)) = unsupported.into_iter().find(|(set, _)| *set) {
+            return Err(ValidationError::NvmeUnsupported(option));
+        }
+
         Ok(())
     }
 }
 
 #[derive(Debug)]
-pub enum ParseVhostModeError {
+pub enum ParseQueueSizeError {
     InvalidValue(String),
 }
 
-impl FromStr for VhostMode {
-    type Err = ParseVhostModeError;
+impl FromStr for QueueSize {
+    type Err = ParseQueueSizeError;
 
     fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
         match s.to_lowercase().as_str() {
-            "client" => Ok(VhostMode::Client),
-            "server" => Ok(VhostMode::Server),
-            _ => Err(ParseVhostModeError::InvalidValue(s.to_owned())),
+            "auto" => Ok(QueueSize::Auto),
+            _ => s
+                .parse()
+                .map(QueueSize::Fixed)
+                .map_err(|_| ParseQueueSizeError::InvalidValue(s.to_owned())),
         }
     }
 }
 
-impl NetConfig {
-    pub const SYNTAX: &'static str = "Network parameters \
-    \"tap=<if_name>,ip=<ip_addr>,mask=<net_mask>,mac=<mac_addr>,fd=<fd1,fd2...>,iommu=on|off,\
-    num_queues=<number_of_queues>,queue_size=<size_of_each_queue>,id=<device_id>,\
-    vhost_user=<vhost_user_enable>,socket=<vhost_user_socket_path>,vhost_mode=client|server,\
-    bw_size=<bytes>,bw_one_time_burst=<bytes>,bw_refill_time=<ms>,\
-    ops_size=<io_ops>,ops_one_time_burst=<io_ops>,ops_refill_time=<ms>,pci_segment=<segment_id>\
-    offload_tso=on|off,offload_ufo=on|off,offload_csum=on|off\"";
+#[derive(Debug)]
+pub enum ParseDiskEncryptionError {
+    InvalidValue(String),
+}
 
-    pub fn parse(net: &str) -> Result<Self> {
-        let mut parser = OptionParser::new();
+impl FromStr for DiskEncryption {
+    type Err = ParseDiskEncryptionError;
 
-        parser
-            .add("tap")
-            .add("ip")
-            .add("mask")
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "luks" => Ok(DiskEncryption::Luks),
+            _ => Err(ParseDiskEncryptionError::InvalidValue(s.to_owned())),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ParseDiskInterfaceError {
+    InvalidValue(String),
+}
+
+impl FromStr for DiskInterface {
+    type Err = ParseDiskInterfaceError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "virtio" => Ok(DiskInterface::Virtio),
+            "nvme" => Ok(DiskInterface::Nvme),
+            _ => Err(ParseDiskInterfaceError::InvalidValue(s.to_owned())),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ParseIoEngineError {
+    InvalidValue(String),
+}
+
+impl FromStr for IoEngine {
+    type Err = ParseIoEngineError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "io_uring" => Ok(IoEngine::IoUring),
+            "aio" => Ok(IoEngine::Aio),
+            "sync" => Ok(IoEngine::Sync),
+            "threads" => Ok(IoEngine::Threads),
+            _ => Err(ParseIoEngineError::InvalidValue(s.to_owned())),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ParseVhostModeError {
+    InvalidValue(String),
+}
+
+impl FromStr for VhostMode {
+    type Err = ParseVhostModeError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "client" => Ok(VhostMode::Client),
+            "server" => Ok(VhostMode::Server),
+            _ => Err(ParseVhostModeError::InvalidValue(s.to_owned())),
+        }
+    }
+}
+
+impl NetConfig {
+    pub const SYNTAX: &'static str = "Network parameters \
+    \"tap=<if_name>,ip=<ip_addr>,mask=<net_mask>,mac=<mac_addr>,fd=<fd1,fd2...>,iommu=on|off,\
+    num_queues=<number_of_queues>,queue_size=<size_of_each_queue>,id=<device_id>,\
+    vhost_user=<vhost_user_enable>,socket=<vhost_user_socket_path>,vhost_mode=client|server,\
+    bw_size=<bytes>,bw_one_time_burst=<bytes>,bw_refill_time=<ms>,\
+    ops_size=<io_ops>,ops_one_time_burst=<io_ops>,ops_refill_time=<ms>,pci_segment=<segment_id>\
+    offload_tso=on|off,offload_ufo=on|off,offload_csum=on|off,io_uring=on|off,\
+    io_thread=<io_thread_index>,coalesce_usecs=<usecs>,coalesce_max_used=<frames>,\
+    rate_limit_group=<group_id>,weight=<weight>,tap_persist=on|off,tap_uid=<uid>,tap_gid=<gid>,\
+    macvtap=<if_name>,macvtap_link=<if_name>,macvtap_mode=bridge|vepa|private|passthru,\
+    vhost=on|off,romfile=<option_rom_path>,msix_vectors=<number_of_vectors>\"";
+
+    pub fn parse(net: &str) -> Result<Self> {
+        let mut parser = OptionParser::new();
+
+        parser
+            .add("tap")
+            .add("ip")
+            .add("mask")
             .add("mac")
             .add("host_mac")
             .add("offload_tso")
             .add("offload_ufo")
             .add("offload_csum")
+            .add("io_uring")
             .add("mtu")
             .add("iommu")
             .add("queue_size")
@@ -1434,7 +2732,21 @@ impl NetConfig {
             .add("ops_size")
             .add("ops_one_time_burst")
             .add("ops_refill_time")
-            .add("pci_segment");
+            .add("pci_segment")
+            .add("io_thread")
+            .add("coalesce_usecs")
+            .add("coalesce_max_used")
+            .add("rate_limit_group")
+            .add("weight")
+            .add("tap_persist")
+            .add("tap_uid")
+            .add("tap_gid")
+            .add("macvtap")
+            .add("macvtap_link")
+            .add("macvtap_mode")
+            .add("vhost")
+            .add("romfile")
+            .add("msix_vectors");
         parser.parse(net).map_err(Error::ParseNetwork)?;
 
         let tap = parser.get("tap");
@@ -1466,6 +2778,41 @@ impl NetConfig {
             .map_err(Error::ParseNetwork)?
             .unwrap_or(Toggle(true))
             .0;
+        let io_uring = parser
+            .convert::<Toggle>("io_uring")
+            .map_err(Error::ParseNetwork)?
+            .unwrap_or(Toggle(false))
+            .0;
+        let io_thread = parser
+            .convert::<u16>("io_thread")
+            .map_err(Error::ParseNetwork)?;
+        let coalescing = parse_coalescing(&parser).map_err(Error::ParseNetwork)?;
+        let rate_limit_group = parser.get("rate_limit_group");
+        let weight = parser
+            .convert("weight")
+            .map_err(Error::ParseNetwork)?
+            .unwrap_or(DEFAULT_NET_WEIGHT);
+        let tap_persist = parser
+            .convert::<Toggle>("tap_persist")
+            .map_err(Error::ParseNetwork)?
+            .map(|toggle| toggle.0);
+        let tap_uid = parser.convert("tap_uid").map_err(Error::ParseNetwork)?;
+        let tap_gid = parser.convert("tap_gid").map_err(Error::ParseNetwork)?;
+        let macvtap = parser.get("macvtap");
+        let macvtap_link = parser.get("macvtap_link");
+        let macvtap_mode = parser
+            .convert("macvtap_mode")
+            .map_err(Error::ParseNetwork)?
+            .unwrap_or_default();
+        let vhost = parser
+            .convert::<Toggle>("vhost")
+            .map_err(Error::ParseNetwork)?
+            .unwrap_or(Toggle(false))
+            .0;
+        let romfile = parser.get("romfile").map(PathBuf::from);
+        let msix_vectors = parser
+            .convert("msix_vectors")
+            .map_err(Error::ParseNetwork)?;
         let mtu = parser.convert("mtu").map_err(Error::ParseNetwork)?;
         let iommu = parser
             .convert::<Toggle>("iommu")
@@ -1570,6 +2917,20 @@ impl NetConfig {
             offload_tso,
             offload_ufo,
             offload_csum,
+            io_uring,
+            io_thread,
+            coalescing,
+            rate_limit_group,
+            weight,
+            tap_persist,
+            tap_uid,
+            tap_gid,
+            macvtap,
+            macvtap_link,
+            macvtap_mode,
+            vhost,
+            romfile,
+            msix_vectors,
         };
         Ok(config)
     }
@@ -1621,6 +2982,82 @@ impl NetConfig {
             return Err(ValidationError::NoHardwareChecksumOffload);
         }
 
+        if self.vhost_user && self.io_uring {
+            return Err(ValidationError::VnetIoUringVhostUser);
+        }
+
+        if let Some(io_thread) = self.io_thread {
+            if self.vhost_user {
+                return Err(ValidationError::IoThreadVhostUser);
+            }
+
+            validate_io_thread(io_thread, vm_config)?;
+        }
+
+        if let Some(coalescing) = &self.coalescing {
+            if self.vhost_user {
+                return Err(ValidationError::CoalescingVhostUser);
+            }
+
+            coalescing.validate()?;
+        }
+
+        validate_msix_vectors(self.msix_vectors)?;
+
+        if self.rate_limit_group.is_some() {
+            if self.vhost_user {
+                return Err(ValidationError::RateLimitGroupVhostUser);
+            }
+
+            if self.rate_limiter_config.is_some() {
+                return Err(ValidationError::InvalidRateLimiterGroup);
+            }
+        }
+
+        if self.weight == 0 {
+            return Err(ValidationError::NetWeightZero);
+        }
+
+        // Only the TAP interfaces opened by Cloud Hypervisor can be configured.
+        if (self.tap_persist.is_some() || self.tap_uid.is_some() || self.tap_gid.is_some())
+            && (self.vhost_user
+                || self.macvtap.is_some()
+                || (self.tap.is_none() && self.fds.is_some()))
+        {
+            return Err(ValidationError::TapOptionsWithoutTap);
+        }
+
+        if self.macvtap.is_some() && (self.vhost_user || self.tap.is_some() || self.fds.is_some()) {
+            return Err(ValidationError::MacvtapWithOtherBackend);
+        }
+
+        if self.macvtap_link.is_some() && self.macvtap.is_none() {
+            return Err(ValidationError::MacvtapLinkWithoutMacvtap);
+        }
+
+        // The kernel moves the frames on its own, without going through the
+        // rate limiters and the notification coalescers, nor translating the
+        // addresses through the virtual IOMMU.
+        if self.vhost {
+            if self.vhost_user {
+                return Err(ValidationError::VhostNetUnsupported("\"vhost_user\""));
+            }
+            if self.iommu {
+                return Err(ValidationError::VhostNetUnsupported("\"iommu\""));
+            }
+            if self.io_uring {
+                return Err(ValidationError::VhostNetUnsupported("\"io_uring\""));
+            }
+            if self.rate_limiter_config.is_some() || self.rate_limit_group.is_some() {
+                return Err(ValidationError::VhostNetUnsupported("Rate limiting"));
+            }
+            if self.coalescing.is_some() {
+                return Err(ValidationError::VhostNetUnsupported(
+                    "Notification coalescing",
+                ));
+            }
+        }
+
         Ok(())
     }
 }
@@ -1646,335 +3083,469 @@ impl RngConfig {
     }
 }
 
-impl BalloonConfig {
-    pub const SYNTAX: &'static str =
-        "Balloon parameters \"size=<balloon_size>,deflate_on_oom=on|off,\
-        free_page_reporting=on|off\"";
+impl ScmiConfig {
+    pub const SYNTAX: &'static str = "SCMI device parameters \
+        \"sensors=<list_of_host_sensor_files>,power_domains=<number_of_power_domains>,\
+        clocks=<list_of_clock_rates_in_hz>,iommu=on|off\"";
 
-    pub fn parse(balloon: &str) -> Result<Self> {
+    pub fn parse(scmi: &str) -> Result<Self> {
         let mut parser = OptionParser::new();
-        parser.add("size");
-        parser.add("deflate_on_oom");
-        parser.add("free_page_reporting");
-        parser.parse(balloon).map_err(Error::ParseBalloon)?;
-
-        let size = parser
-            .convert::<ByteSized>("size")
-            .map_err(Error::ParseBalloon)?
-            .map(|v| v.0)
-            .unwrap_or(0);
-
-        let deflate_on_oom = parser
-            .convert::<Toggle>("deflate_on_oom")
-            .map_err(Error::ParseBalloon)?
-            .unwrap_or(Toggle(false))
-            .0;
-
-        let free_page_reporting = parser
-            .convert::<Toggle>("free_page_reporting")
-            .map_err(Error::ParseBalloon)?
+        parser
+            .add("sensors")
+            .add("power_domains")
+            .add("clocks")
+            .add("iommu");
+        parser.parse(scmi).map_err(Error::ParseScmi)?;
+
+        let sensors = parser
+            .convert::<StringList>("sensors")
+            .map_err(Error::ParseScmi)?
+            .map(|v| v.0.iter().map(PathBuf::from).collect());
+        let power_domains = parser
+            .convert("power_domains")
+            .map_err(Error::ParseScmi)?
+            .unwrap_or_default();
+        let clocks = parser
+            .convert::<IntegerList>("clocks")
+            .map_err(Error::ParseScmi)?
+            .map(|v| v.0);
+        let iommu = parser
+            .convert::<Toggle>("iommu")
+            .map_err(Error::ParseScmi)?
             .unwrap_or(Toggle(false))
             .0;
 
-        Ok(BalloonConfig {
-            size,
-            deflate_on_oom,
-            free_page_reporting,
+        Ok(ScmiConfig {
+            sensors,
+            power_domains,
+            clocks,
+            iommu,
         })
     }
+
+    pub fn validate(&self) -> ValidationResult<()> {
+        if self.sensors.as_ref().map(|s| s.is_empty()).unwrap_or(true)
+            && self.power_domains == 0
+            && self.clocks.as_ref().map(|c| c.is_empty()).unwrap_or(true)
+        {
+            return Err(ValidationError::ScmiNoResources);
+        }
+
+        if self.clocks.iter().flatten().any(|rate| *rate == 0) {
+            return Err(ValidationError::InvalidScmiClockRate);
+        }
+
+        Ok(())
+    }
 }
 
-impl FsConfig {
-    pub const SYNTAX: &'static str = "virtio-fs parameters \
-    \"tag=<tag_name>,socket=<socket_path>,num_queues=<number_of_queues>,\
-    queue_size=<size_of_each_queue>,id=<device_id>,pci_segment=<segment_id>\"";
+impl CloudInitConfig {
+    pub const SYNTAX: &'static str = "cloud-init NoCloud seed parameters \
+        \"user_data=<user_data_file>,meta_data=<meta_data_file>,\
+        network_config=<network_config_file>\"";
 
-    pub fn parse(fs: &str) -> Result<Self> {
+    pub fn parse(cloud_init: &str) -> Result<Self> {
         let mut parser = OptionParser::new();
         parser
-            .add("tag")
-            .add("queue_size")
-            .add("num_queues")
-            .add("socket")
-            .add("id")
-            .add("pci_segment");
-        parser.parse(fs).map_err(Error::ParseFileSystem)?;
+            .add("user_data")
+            .add("meta_data")
+            .add("network_config");
+        parser.parse(cloud_init).map_err(Error::ParseCloudInit)?;
+
+        let user_data = parser.get("user_data").map(PathBuf::from);
+        let meta_data = parser.get("meta_data").map(PathBuf::from);
+        let network_config = parser.get("network_config").map(PathBuf::from);
+
+        Ok(CloudInitConfig {
+            user_data,
+            meta_data,
+            network_config,
+        })
+    }
 
-        let tag = parser.get("tag").ok_or(Error::ParseFsTagMissing)?;
-        if tag.len() > virtio_devices::vhost_user::VIRTIO_FS_TAG_LEN {
-            return Err(Error::ParseFsTagTooLong);
+    pub fn validate(&self) -> ValidationResult<()> {
+        if self.user_data.is_none() && self.network_config.is_none() {
+            return Err(ValidationError::CloudInitNoData);
         }
-        let socket = PathBuf::from(parser.get("socket").ok_or(Error::ParseFsSockMissing)?);
 
-        let queue_size = parser
-            .convert("queue_size")
-            .map_err(Error::ParseFileSystem)?
-            .unwrap_or_else(default_fsconfig_queue_size);
-        let num_queues = parser
-            .convert("num_queues")
-            .map_err(Error::ParseFileSystem)?
-            .unwrap_or_else(default_fsconfig_num_queues);
+        Ok(())
+    }
+}
 
-        let id = parser.get("id");
+impl ImdsConfig {
+    pub const SYNTAX: &'static str = "Instance metadata service parameters \
+        \"document=<json_document_file>\"";
 
-        let pci_segment = parser
-            .convert("pci_segment")
-            .map_err(Error::ParseFileSystem)?
-            .unwrap_or_default();
+    pub fn parse(imds: &str) -> Result<Self> {
+        let mut parser = OptionParser::new();
+        parser.add("document");
+        parser.parse(imds).map_err(Error::ParseImds)?;
 
-        Ok(FsConfig {
-            tag,
-            socket,
-            num_queues,
-            queue_size,
-            id,
-            pci_segment,
-        })
+        let document = parser
+            .get("document")
+            .map(PathBuf::from)
+            .ok_or(Error::ParseImdsDocumentMissing)?;
+
+        Ok(ImdsConfig { document })
     }
+}
 
-    pub fn validate(&self, vm_config: &VmConfig) -> ValidationResult<()> {
-        if self.num_queues > vm_config.cpus.boot_vcpus as usize {
-            return Err(ValidationError::TooManyQueues);
+impl NetnsConfig {
+    pub const SYNTAX: &'static str = "Network namespace of the TAP interfaces, joined if it \
+        exists and created otherwise, linked to a bridge when created \
+        \"name=<netns_name>,bridge=<bridge_name>,veth=<veth_interface_name>\"";
+
+    pub fn parse(netns: &str) -> Result<Self> {
+        let mut parser = OptionParser::new();
+        parser.add("name").add("bridge").add("veth");
+        parser.parse(netns).map_err(Error::ParseNetns)?;
+
+        let name = parser.get("name").ok_or(Error::ParseNetnsNameMissing)?;
+        let bridge = parser.get("bridge");
+        let veth = parser.get("veth");
+
+        Ok(NetnsConfig { name, bridge, veth })
+    }
+
+    pub fn validate(&self) -> ValidationResult<()> {
+        if self.name.is_empty() || self.name == "." || self.name == ".." || self.name.contains('/')
+        {
+            return Err(ValidationError::InvalidNetnsName(self.name.clone()));
         }
 
-        if let Some(platform_config) = vm_config.platform.as_ref() {
-            if self.pci_segment >= platform_config.num_pci_segments {
-                return Err(ValidationError::InvalidPciSegment(self.pci_segment));
+        for if_name in self.bridge.iter().chain(self.veth.iter()) {
+            if if_name.is_empty()
+                || if_name.len() >= IFNAMSIZ
+                || if_name.contains(|c: char| c == '/' || c.is_whitespace())
+            {
+                return Err(ValidationError::InvalidInterfaceName(if_name.clone()));
             }
+        }
 
-            if let Some(iommu_segments) = platform_config.iommu_segments.as_ref() {
-                if iommu_segments.contains(&self.pci_segment) {
-                    return Err(ValidationError::IommuNotSupportedOnSegment(
-                        self.pci_segment,
-                    ));
-                }
-            }
+        if self.veth.is_some() && self.bridge.is_none() {
+            return Err(ValidationError::NetnsVethWithoutBridge);
         }
 
         Ok(())
     }
 }
 
-impl PmemConfig {
-    pub const SYNTAX: &'static str = "Persistent memory parameters \
-    \"file=<backing_file_path>,size=<persistent_memory_size>,iommu=on|off,\
-    discard_writes=on|off,id=<device_id>,pci_segment=<segment_id>\"";
+impl GuestAgentConfig {
+    pub const SYNTAX: &'static str = "QEMU guest agent parameters \
+        \"port=<vsock_port>\"";
 
-    pub fn parse(pmem: &str) -> Result<Self> {
+    pub fn parse(guest_agent: &str) -> Result<Self> {
         let mut parser = OptionParser::new();
+        parser.add("port");
+        parser.parse(guest_agent).map_err(Error::ParseGuestAgent)?;
+
+        let port = parser
+            .convert("port")
+            .map_err(Error::ParseGuestAgent)?
+            .unwrap_or(DEFAULT_GUEST_AGENT_PORT);
+
+        Ok(GuestAgentConfig { port })
+    }
+}
+
+#[cfg(feature = "pvmemcontrol")]
+impl PvmemcontrolConfig {
+    pub const SYNTAX: &'static str = "Pvmemcontrol device parameters \
+        \"reclaim_rate=<bytes_per_second>\"";
+
+    pub fn parse(pvmemcontrol: &str) -> Result<Self> {
+        let mut parser = OptionParser::new();
+        parser.add("reclaim_rate");
         parser
-            .add("size")
-            .add("file")
-            .add("iommu")
-            .add("discard_writes")
-            .add("id")
-            .add("pci_segment");
-        parser.parse(pmem).map_err(Error::ParsePersistentMemory)?;
+            .parse(pvmemcontrol)
+            .map_err(Error::ParsePvmemcontrol)?;
 
-        let file = PathBuf::from(parser.get("file").ok_or(Error::ParsePmemFileMissing)?);
-        let size = parser
-            .convert::<ByteSized>("size")
-            .map_err(Error::ParsePersistentMemory)?
+        let reclaim_rate = parser
+            .convert::<ByteSized>("reclaim_rate")
+            .map_err(Error::ParsePvmemcontrol)?
             .map(|v| v.0);
-        let iommu = parser
-            .convert::<Toggle>("iommu")
-            .map_err(Error::ParsePersistentMemory)?
+
+        Ok(PvmemcontrolConfig { reclaim_rate })
+    }
+}
+
+impl ResourcesConfig {
+    pub const SYNTAX: &'static str = "Host resources reservation parameters \
+        \"cgroup=<cgroup_path>,manifest=<manifest_file>,\
+        cpu_quota=<percent_of_a_cpu>,memory_max=<memory_size>,\
+        io_max=[<major>:<minor> <io.max_limits>,...],threads=on|off\"";
+
+    pub fn parse(resources: &str) -> Result<Self> {
+        let mut parser = OptionParser::new();
+        parser
+            .add("cgroup")
+            .add("manifest")
+            .add("cpu_quota")
+            .add("memory_max")
+            .add("io_max")
+            .add("threads");
+        parser.parse(resources).map_err(Error::ParseResources)?;
+
+        let cgroup = parser.get("cgroup").map(PathBuf::from);
+        let manifest = parser.get("manifest").map(PathBuf::from);
+        let cpu_quota = parser.convert("cpu_quota").map_err(Error::ParseResources)?;
+        let memory_max = parser
+            .convert::<ByteSized>("memory_max")
+            .map_err(Error::ParseResources)?
+            .map(|v| v.0);
+        let io_max = parser
+            .convert::<StringList>("io_max")
+            .map_err(Error::ParseResources)?
+            .map(|v| v.0);
+        let threads = parser
+            .convert::<Toggle>("threads")
+            .map_err(Error::ParseResources)?
             .unwrap_or(Toggle(false))
             .0;
-        let discard_writes = parser
-            .convert::<Toggle>("discard_writes")
-            .map_err(Error::ParsePersistentMemory)?
-            .unwrap_or(Toggle(false))
-            .0;
-        let id = parser.get("id");
-        let pci_segment = parser
-            .convert("pci_segment")
-            .map_err(Error::ParsePersistentMemory)?
-            .unwrap_or_default();
 
-        Ok(PmemConfig {
-            file,
-            size,
-            iommu,
-            discard_writes,
-            id,
-            pci_segment,
+        if cgroup.is_none()
+            && (cpu_quota.is_some() || memory_max.is_some() || io_max.is_some() || threads)
+        {
+            return Err(Error::ParseResources(OptionParserError::InvalidValue(
+                "cgroup limits and threads require a cgroup".to_owned(),
+            )));
+        }
+
+        Ok(ResourcesConfig {
+            cgroup,
+            manifest,
+            cpu_quota,
+            memory_max,
+            io_max,
+            threads,
         })
     }
+}
 
-    pub fn validate(&self, vm_config: &VmConfig) -> ValidationResult<()> {
-        if let Some(platform_config) = vm_config.platform.as_ref() {
-            if self.pci_segment >= platform_config.num_pci_segments {
-                return Err(ValidationError::InvalidPciSegment(self.pci_segment));
-            }
+#[derive(Debug)]
+pub enum ParseRtcBaseError {
+    InvalidValue(String),
+}
 
-            if let Some(iommu_segments) = platform_config.iommu_segments.as_ref() {
-                if iommu_segments.contains(&self.pci_segment) && !self.iommu {
-                    return Err(ValidationError::OnIommuSegment(self.pci_segment));
-                }
-            }
+// Days between the epoch and the given date of the proleptic Gregorian
+// calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+// Seconds since the epoch of an ISO 8601 "YYYY-MM-DDTHH:MM:SS" date, in UTC
+// unless followed by a "+HH:MM" or "-HH:MM" offset.
+fn parse_iso8601(date: &str) -> Option<i64> {
+    let date = date.strip_suffix('Z').unwrap_or(date);
+    let (date, offset) = match date.get(19..) {
+        Some("") => (date, 0),
+        Some(offset) => {
+            let sign = match offset.as_bytes()[0] {
+                b'+' => 1,
+                b'-' => -1,
+                _ => return None,
+            };
+            let (hours, minutes) = offset[1..].split_once(':')?;
+            let hours: i64 = hours.parse().ok()?;
+            let minutes: i64 = minutes.parse().ok()?;
+            if hours > 23 || minutes > 59 {
+                return None;
+            }
+            (&date[..19], sign * (hours * 3600 + minutes * 60))
         }
+        None => return None,
+    };
+
+    let fields: Vec<i64> = date
+        .split(['-', 'T', ':'])
+        .map(|field| field.parse().ok())
+        .collect::<Option<_>>()?;
+    let [year, month, day, hours, minutes, seconds] = fields[..] else {
+        return None;
+    };
+    let days_in_month =
+        days_from_civil(year + month / 12, month % 12 + 1, 1) - days_from_civil(year, month, 1);
+    if !(1..=12).contains(&month)
+        || !(1..=days_in_month).contains(&day)
+        || hours > 23
+        || minutes > 59
+        || seconds > 59
+    {
+        return None;
+    }
 
-        Ok(())
+    Some(days_from_civil(year, month, day) * 86400 + hours * 3600 + minutes * 60 + seconds - offset)
+}
+
+impl FromStr for RtcBase {
+    type Err = ParseRtcBaseError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "utc" => Ok(RtcBase::Utc),
+            "localtime" => Ok(RtcBase::Localtime),
+            _ => parse_iso8601(s)
+                .map(RtcBase::Date)
+                .ok_or_else(|| ParseRtcBaseError::InvalidValue(s.to_owned())),
+        }
     }
 }
 
-impl ConsoleConfig {
-    pub fn parse(console: &str) -> Result<Self> {
-        let mut parser = OptionParser::new();
-        parser
-            .add_valueless("off")
-            .add_valueless("pty")
-            .add_valueless("tty")
-            .add_valueless("null")
-            .add("file")
-            .add("iommu")
-            .add("socket");
-        parser.parse(console).map_err(Error::ParseConsole)?;
+#[derive(Debug)]
+pub enum ParseRtcDriftError {
+    InvalidValue(String),
+}
 
-        let mut file: Option<PathBuf> = default_consoleconfig_file();
-        let mut socket: Option<PathBuf> = None;
-        let mut mode: ConsoleOutputMode = ConsoleOutputMode::Off;
+impl FromStr for RtcDrift {
+    type Err = ParseRtcDriftError;
 
-        if parser.is_set("off") {
-        } else if parser.is_set("pty") {
-            mode = ConsoleOutputMode::Pty
-        } else if parser.is_set("tty") {
-            mode = ConsoleOutputMode::Tty
-        } else if parser.is_set("null") {
-            mode = ConsoleOutputMode::Null
-        } else if parser.is_set("file") {
-            mode = ConsoleOutputMode::File;
-            file =
-                Some(PathBuf::from(parser.get("file").ok_or(
-                    Error::Validation(ValidationError::ConsoleFileMissing),
-                )?));
-        } else if parser.is_set("socket") {
-            mode = ConsoleOutputMode::Socket;
-            socket = Some(PathBuf::from(parser.get("socket").ok_or(
-                Error::Validation(ValidationError::ConsoleSocketPathMissing),
-            )?));
-        } else {
-            return Err(Error::ParseConsoleInvalidModeGiven);
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "step" => Ok(RtcDrift::Step),
+            "slew" => Ok(RtcDrift::Slew),
+            _ => Err(ParseRtcDriftError::InvalidValue(s.to_owned())),
         }
-        let iommu = parser
-            .convert::<Toggle>("iommu")
-            .map_err(Error::ParseConsole)?
-            .unwrap_or(Toggle(false))
-            .0;
-
-        Ok(Self {
-            file,
-            mode,
-            iommu,
-            socket,
-        })
     }
 }
 
-#[cfg(target_arch = "x86_64")]
-impl DebugConsoleConfig {
-    pub fn parse(debug_console_ops: &str) -> Result<Self> {
+impl RtcConfig {
+    pub const SYNTAX: &'static str = "Guest RTC parameters \
+        \"base=utc|localtime|<YYYY-MM-DDTHH:MM:SS[Z|+HH:MM|-HH:MM]>,\
+        drift=step|slew\"";
+
+    pub fn parse(rtc: &str) -> Result<Self> {
         let mut parser = OptionParser::new();
-        parser
-            .add_valueless("off")
-            .add_valueless("pty")
-            .add_valueless("tty")
-            .add_valueless("null")
-            .add("file")
-            .add("iobase");
-        parser
-            .parse(debug_console_ops)
-            .map_err(Error::ParseConsole)?;
+        parser.add("base").add("drift");
+        parser.parse(rtc).map_err(Error::ParseRtc)?;
 
-        let mut file: Option<PathBuf> = default_consoleconfig_file();
-        let mut iobase: Option<u16> = None;
-        let mut mode: ConsoleOutputMode = ConsoleOutputMode::Off;
+        let base = parser
+            .convert("base")
+            .map_err(Error::ParseRtc)?
+            .unwrap_or_default();
+        let drift = parser
+            .convert("drift")
+            .map_err(Error::ParseRtc)?
+            .unwrap_or_default();
 
-        if parser.is_set("off") {
-        } else if parser.is_set("pty") {
-            mode = ConsoleOutputMode::Pty
-        } else if parser.is_set("tty") {
-            mode = ConsoleOutputMode::Tty
-        } else if parser.is_set("null") {
-            mode = ConsoleOutputMode::Null
-        } else if parser.is_set("file") {
-            mode = ConsoleOutputMode::File;
-            file =
-                Some(PathBuf::from(parser.get("file").ok_or(
-                    Error::Validation(ValidationError::ConsoleFileMissing),
-                )?));
-        } else {
-            return Err(Error::ParseConsoleInvalidModeGiven);
+        Ok(RtcConfig { base, drift })
+    }
+}
+
+#[derive(Debug)]
+pub enum ParseRestartPolicyError {
+    InvalidValue(String),
+}
+
+impl FromStr for RestartPolicy {
+    type Err = ParseRestartPolicyError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "never" => Ok(RestartPolicy::Never),
+            "on-crash" => Ok(RestartPolicy::OnCrash),
+            _ => Err(ParseRestartPolicyError::InvalidValue(s.to_owned())),
         }
+    }
+}
 
-        if parser.is_set("iobase") {
-            if let Some(iobase_opt) = parser.get("iobase") {
-                if !iobase_opt.starts_with("0x") {
-                    return Err(Error::Validation(ValidationError::InvalidIoPortHex(
-                        iobase_opt,
-                    )));
-                }
-                iobase = Some(u16::from_str_radix(&iobase_opt[2..], 16).map_err(|_| {
-                    Error::Validation(ValidationError::InvalidIoPortHex(iobase_opt))
-                })?);
-            }
+impl RestartPolicyConfig {
+    pub const SYNTAX: &'static str = "Restart policy on guest crashes \
+        \"never|on-crash[:max=<max_consecutive_restarts>,\
+        backoff=<first_restart_delay_ms>]\"";
+
+    pub fn parse(restart_policy: &str) -> Result<Self> {
+        let (policy, options) = restart_policy
+            .split_once(':')
+            .unwrap_or((restart_policy, ""));
+        let policy = policy.parse().map_err(|_| {
+            Error::ParseRestartPolicy(OptionParserError::InvalidValue(policy.to_owned()))
+        })?;
+
+        let mut parser = OptionParser::new();
+        parser.add("max").add("backoff");
+        parser.parse(options).map_err(Error::ParseRestartPolicy)?;
+        if policy == RestartPolicy::Never && (parser.is_set("max") || parser.is_set("backoff")) {
+            return Err(Error::ParseRestartPolicy(OptionParserError::InvalidValue(
+                restart_policy.to_owned(),
+            )));
         }
 
-        Ok(Self { file, mode, iobase })
+        let max = parser
+            .convert("max")
+            .map_err(Error::ParseRestartPolicy)?
+            .unwrap_or(DEFAULT_RESTART_MAX);
+        let backoff = parser
+            .convert("backoff")
+            .map_err(Error::ParseRestartPolicy)?
+            .unwrap_or(DEFAULT_RESTART_BACKOFF_MS);
+
+        Ok(RestartPolicyConfig {
+            policy,
+            max,
+            backoff,
+        })
     }
 }
 
-impl DeviceConfig {
-    pub const SYNTAX: &'static str =
-        "Direct device assignment parameters \"path=<device_path>,iommu=on|off,id=<device_id>,pci_segment=<segment_id>\"";
+impl CrashDumpConfig {
+    pub const SYNTAX: &'static str = "Dump of the guest memory on guest panics \
+        \"path=<directory_of_the_dumps>\"";
 
-    pub fn parse(device: &str) -> Result<Self> {
+    pub fn parse(crash_dump: &str) -> Result<Self> {
         let mut parser = OptionParser::new();
-        parser
-            .add("path")
-            .add("id")
-            .add("iommu")
-            .add("pci_segment")
-            .add("x_nv_gpudirect_clique");
-        parser.parse(device).map_err(Error::ParseDevice)?;
-
+        parser.add("path");
+        parser.parse(crash_dump).map_err(Error::ParseCrashDump)?;
         let path = parser
             .get("path")
             .map(PathBuf::from)
-            .ok_or(Error::ParseDevicePathMissing)?;
-        let iommu = parser
-            .convert::<Toggle>("iommu")
-            .map_err(Error::ParseDevice)?
-            .unwrap_or(Toggle(false))
-            .0;
-        let id = parser.get("id");
-        let pci_segment = parser
-            .convert::<u16>("pci_segment")
-            .map_err(Error::ParseDevice)?
-            .unwrap_or_default();
-        let x_nv_gpudirect_clique = parser
-            .convert::<u8>("x_nv_gpudirect_clique")
-            .map_err(Error::ParseDevice)?;
-        Ok(DeviceConfig {
-            path,
-            iommu,
-            id,
-            pci_segment,
-            x_nv_gpudirect_clique,
-        })
+            .ok_or(Error::ParseCrashDumpPathMissing)?;
+
+        Ok(CrashDumpConfig { path })
     }
+}
 
-    pub fn validate(&self, vm_config: &VmConfig) -> ValidationResult<()> {
-        if let Some(platform_config) = vm_config.platform.as_ref() {
-            if self.pci_segment >= platform_config.num_pci_segments {
-                return Err(ValidationError::InvalidPciSegment(self.pci_segment));
-            }
+impl IoThreadsConfig {
+    pub const SYNTAX: &'static str = "I/O thread pool parameters \
+        \"count=<number_of_threads>,\
+        affinity=<list_of_thread_indices_with_their_associated_cpuset>\"";
 
-            if let Some(iommu_segments) = platform_config.iommu_segments.as_ref() {
-                if iommu_segments.contains(&self.pci_segment) && !self.iommu {
-                    return Err(ValidationError::OnIommuSegment(self.pci_segment));
-                }
+    pub fn parse(io_threads: &str) -> Result<Self> {
+        let mut parser = OptionParser::new();
+        parser.add("count").add("affinity");
+        parser.parse(io_threads).map_err(Error::ParseIoThreads)?;
+
+        let count = parser
+            .convert("count")
+            .map_err(Error::ParseIoThreads)?
+            .unwrap_or(DEFAULT_IO_THREADS);
+        let affinity = parser
+            .convert::<Tuple<u16, Vec<usize>>>("affinity")
+            .map_err(Error::ParseIoThreads)?
+            .map(|v| {
+                v.0.iter()
+                    .map(|(e1, e2)| IoThreadAffinity {
+                        io_thread: *e1,
+                        host_cpus: e2.clone(),
+                    })
+                    .collect()
+            });
+
+        Ok(IoThreadsConfig { count, affinity })
+    }
+
+    pub fn validate(&self) -> ValidationResult<()> {
+        if self.count == 0 {
+            return Err(ValidationError::IoThreadsZero);
+        }
+
+        for affinity in self.affinity.iter().flatten() {
+            if affinity.io_thread >= self.count {
+                return Err(ValidationError::InvalidIoThreadAffinity(affinity.io_thread));
             }
         }
 
@@ -1982,103 +3553,237 @@ impl DeviceConfig {
     }
 }
 
-impl UserDeviceConfig {
-    pub const SYNTAX: &'static str =
-        "Userspace device socket=<socket_path>,id=<device_id>,pci_segment=<segment_id>\"";
-
-    pub fn parse(user_device: &str) -> Result<Self> {
-        let mut parser = OptionParser::new();
-        parser.add("socket").add("id").add("pci_segment");
-        parser.parse(user_device).map_err(Error::ParseUserDevice)?;
-
-        let socket = parser
-            .get("socket")
-            .map(PathBuf::from)
-            .ok_or(Error::ParseUserDeviceSocketMissing)?;
-        let id = parser.get("id");
-        let pci_segment = parser
-            .convert::<u16>("pci_segment")
-            .map_err(Error::ParseUserDevice)?
-            .unwrap_or_default();
+fn validate_io_thread(io_thread: u16, vm_config: &VmConfig) -> ValidationResult<()> {
+    match &vm_config.io_threads {
+        Some(io_threads) if io_thread < io_threads.count => Ok(()),
+        _ => Err(ValidationError::InvalidIoThread(io_thread)),
+    }
+}
 
-        Ok(UserDeviceConfig {
-            socket,
-            id,
-            pci_segment,
-        })
+fn validate_msix_vectors(msix_vectors: Option<u16>) -> ValidationResult<()> {
+    match msix_vectors {
+        Some(n) if n == 0 || n > MAX_MSIX_VECTORS => Err(ValidationError::InvalidMsixVectors(n)),
+        _ => Ok(()),
     }
+}
 
-    pub fn validate(&self, vm_config: &VmConfig) -> ValidationResult<()> {
-        if let Some(platform_config) = vm_config.platform.as_ref() {
-            if self.pci_segment >= platform_config.num_pci_segments {
-                return Err(ValidationError::InvalidPciSegment(self.pci_segment));
-            }
+fn parse_coalescing(
+    parser: &OptionParser,
+) -> std::result::Result<Option<CoalescingConfig>, OptionParserError> {
+    let usecs = parser.convert::<u32>("coalesce_usecs")?;
+    let max_used = parser.convert::<u32>("coalesce_max_used")?;
+
+    Ok(
+        (usecs.is_some() || max_used.is_some()).then(|| CoalescingConfig {
+            usecs: usecs.unwrap_or_default(),
+            max_used: max_used.unwrap_or_default(),
+        }),
+    )
+}
 
-            if let Some(iommu_segments) = platform_config.iommu_segments.as_ref() {
-                if iommu_segments.contains(&self.pci_segment) {
-                    return Err(ValidationError::IommuNotSupportedOnSegment(
-                        self.pci_segment,
-                    ));
-                }
-            }
+impl CoalescingConfig {
+    pub fn validate(&self) -> ValidationResult<()> {
+        if self.usecs == 0 {
+            return Err(ValidationError::CoalescingUsecsZero);
         }
 
         Ok(())
     }
 }
 
-impl VdpaConfig {
-    pub const SYNTAX: &'static str = "vDPA device \
-        \"path=<device_path>,num_queues=<number_of_queues>,iommu=on|off,\
-        id=<device_id>,pci_segment=<segment_id>\"";
+impl FallbackFirmwareConfig {
+    pub const SYNTAX: &'static str = "Fallback firmware parameters \
+        \"path=<firmware_file>,timeout=<boot_timeout_in_seconds>\"";
 
-    pub fn parse(vdpa: &str) -> Result<Self> {
+    pub fn parse(fallback_firmware: &str) -> Result<Self> {
         let mut parser = OptionParser::new();
+        parser.add("path").add("timeout");
         parser
-            .add("path")
-            .add("num_queues")
-            .add("iommu")
-            .add("id")
-            .add("pci_segment");
-        parser.parse(vdpa).map_err(Error::ParseVdpa)?;
+            .parse(fallback_firmware)
+            .map_err(Error::ParseFallbackFirmware)?;
 
         let path = parser
             .get("path")
             .map(PathBuf::from)
-            .ok_or(Error::ParseVdpaPathMissing)?;
-        let num_queues = parser
-            .convert("num_queues")
-            .map_err(Error::ParseVdpa)?
-            .unwrap_or_else(default_vdpaconfig_num_queues);
-        let iommu = parser
-            .convert::<Toggle>("iommu")
-            .map_err(Error::ParseVdpa)?
+            .ok_or(Error::ParseFallbackFirmwarePathMissing)?;
+        let timeout = parser
+            .convert("timeout")
+            .map_err(Error::ParseFallbackFirmware)?
+            .unwrap_or(DEFAULT_FALLBACK_FIRMWARE_TIMEOUT);
+
+        Ok(FallbackFirmwareConfig { path, timeout })
+    }
+
+    pub fn validate(&self) -> ValidationResult<()> {
+        // Boot progress is only reported through the x86 debug I/O port
+        if cfg!(not(target_arch = "x86_64")) {
+            return Err(ValidationError::FallbackFirmwareUnsupported);
+        }
+
+        if self.timeout == 0 {
+            return Err(ValidationError::FallbackFirmwareZeroTimeout);
+        }
+
+        Ok(())
+    }
+}
+
+impl BalloonConfig {
+    pub const SYNTAX: &'static str =
+        "Balloon parameters \"size=<balloon_size>,deflate_on_oom=on|off,\
+        free_page_reporting=on|off,auto_resize=on|off,min_size=<balloon_size>,\
+        max_size=<balloon_size>,step=<resize_step>,pressure_high=<percent>,\
+        pressure_low=<percent>\"";
+
+    pub fn parse(balloon: &str) -> Result<Self> {
+        let mut parser = OptionParser::new();
+        parser.add("size");
+        parser.add("deflate_on_oom");
+        parser.add("free_page_reporting");
+        parser.add("auto_resize");
+        parser.add("min_size");
+        parser.add("max_size");
+        parser.add("step");
+        parser.add("pressure_high");
+        parser.add("pressure_low");
+        parser.parse(balloon).map_err(Error::ParseBalloon)?;
+
+        let size = parser
+            .convert::<ByteSized>("size")
+            .map_err(Error::ParseBalloon)?
+            .map(|v| v.0)
+            .unwrap_or(0);
+
+        let deflate_on_oom = parser
+            .convert::<Toggle>("deflate_on_oom")
+            .map_err(Error::ParseBalloon)?
             .unwrap_or(Toggle(false))
             .0;
+
+        let free_page_reporting = parser
+            .convert::<Toggle>("free_page_reporting")
+            .map_err(Error::ParseBalloon)?
+            .unwrap_or(Toggle(false))
+            .0;
+
+        let auto_resize = parser
+            .convert::<Toggle>("auto_resize")
+            .map_err(Error::ParseBalloon)?
+            .unwrap_or(Toggle(false))
+            .0;
+
+        let min_size = parser
+            .convert::<ByteSized>("min_size")
+            .map_err(Error::ParseBalloon)?
+            .map(|v| v.0)
+            .unwrap_or(0);
+
+        let max_size = parser
+            .convert::<ByteSized>("max_size")
+            .map_err(Error::ParseBalloon)?
+            .map(|v| v.0);
+
+        let step = parser
+            .convert::<ByteSized>("step")
+            .map_err(Error::ParseBalloon)?
+            .map(|v| v.0)
+            .unwrap_or_else(default_balloonconfig_step);
+
+        let pressure_high = parser
+            .convert("pressure_high")
+            .map_err(Error::ParseBalloon)?
+            .unwrap_or_else(default_balloonconfig_pressure_high);
+
+        let pressure_low = parser
+            .convert("pressure_low")
+            .map_err(Error::ParseBalloon)?
+            .unwrap_or_else(default_balloonconfig_pressure_low);
+
+        Ok(BalloonConfig {
+            size,
+            deflate_on_oom,
+            free_page_reporting,
+            auto_resize,
+            min_size,
+            max_size,
+            step,
+            pressure_high,
+            pressure_low,
+        })
+    }
+}
+
+impl FsConfig {
+    pub const SYNTAX: &'static str = "virtio-fs parameters \
+    \"tag=<tag_name>,socket=<socket_path>,num_queues=<number_of_queues>,\
+    queue_size=<size_of_each_queue>,id=<device_id>,pci_segment=<segment_id>,\
+    msix_vectors=<number_of_vectors>\"";
+
+    pub fn parse(fs: &str) -> Result<Self> {
+        let mut parser = OptionParser::new();
+        parser
+            .add("tag")
+            .add("queue_size")
+            .add("num_queues")
+            .add("socket")
+            .add("id")
+            .add("pci_segment")
+            .add("msix_vectors");
+        parser.parse(fs).map_err(Error::ParseFileSystem)?;
+
+        let tag = parser.get("tag").ok_or(Error::ParseFsTagMissing)?;
+        if tag.len() > virtio_devices::vhost_user::VIRTIO_FS_TAG_LEN {
+            return Err(Error::ParseFsTagTooLong);
+        }
+        let socket = PathBuf::from(parser.get("socket").ok_or(Error::ParseFsSockMissing)?);
+
+        let queue_size = parser
+            .convert("queue_size")
+            .map_err(Error::ParseFileSystem)?
+            .unwrap_or_else(default_fsconfig_queue_size);
+        let num_queues = parser
+            .convert("num_queues")
+            .map_err(Error::ParseFileSystem)?
+            .unwrap_or_else(default_fsconfig_num_queues);
+
         let id = parser.get("id");
+
         let pci_segment = parser
             .convert("pci_segment")
-            .map_err(Error::ParseVdpa)?
+            .map_err(Error::ParseFileSystem)?
             .unwrap_or_default();
+        let msix_vectors = parser
+            .convert("msix_vectors")
+            .map_err(Error::ParseFileSystem)?;
 
-        Ok(VdpaConfig {
-            path,
+        Ok(FsConfig {
+            tag,
+            socket,
             num_queues,
-            iommu,
+            queue_size,
             id,
             pci_segment,
+            msix_vectors,
         })
     }
 
     pub fn validate(&self, vm_config: &VmConfig) -> ValidationResult<()> {
+        if self.num_queues > vm_config.cpus.boot_vcpus as usize {
+            return Err(ValidationError::TooManyQueues);
+        }
+
+        validate_msix_vectors(self.msix_vectors)?;
+
         if let Some(platform_config) = vm_config.platform.as_ref() {
             if self.pci_segment >= platform_config.num_pci_segments {
                 return Err(ValidationError::InvalidPciSegment(self.pci_segment));
             }
 
             if let Some(iommu_segments) = platform_config.iommu_segments.as_ref() {
-                if iommu_segments.contains(&self.pci_segment) && !self.iommu {
-                    return Err(ValidationError::OnIommuSegment(self.pci_segment));
+                if iommu_segments.contains(&self.pci_segment) {
+                    return Err(ValidationError::IommuNotSupportedOnSegment(
+                        self.pci_segment,
+                    ));
                 }
             }
         }
@@ -2087,56 +3792,79 @@ impl VdpaConfig {
     }
 }
 
-impl VsockConfig {
-    pub const SYNTAX: &'static str = "Virtio VSOCK parameters \
-        \"cid=<context_id>,socket=<socket_path>,iommu=on|off,id=<device_id>,pci_segment=<segment_id>\"";
+impl PmemConfig {
+    pub const SYNTAX: &'static str = "Persistent memory parameters \
+    \"file=<backing_file_path>,size=<persistent_memory_size>,iommu=on|off,\
+    discard_writes=on|off,nvdimm=on|off,id=<device_id>,pci_segment=<segment_id>\"";
 
-    pub fn parse(vsock: &str) -> Result<Self> {
+    pub fn parse(pmem: &str) -> Result<Self> {
         let mut parser = OptionParser::new();
         parser
-            .add("socket")
-            .add("cid")
+            .add("size")
+            .add("file")
             .add("iommu")
+            .add("discard_writes")
+            .add("nvdimm")
             .add("id")
             .add("pci_segment");
-        parser.parse(vsock).map_err(Error::ParseVsock)?;
+        parser.parse(pmem).map_err(Error::ParsePersistentMemory)?;
 
-        let socket = parser
-            .get("socket")
-            .map(PathBuf::from)
-            .ok_or(Error::ParseVsockSockMissing)?;
+        let file = PathBuf::from(parser.get("file").ok_or(Error::ParsePmemFileMissing)?);
+        let size = parser
+            .convert::<ByteSized>("size")
+            .map_err(Error::ParsePersistentMemory)?
+            .map(|v| v.0);
         let iommu = parser
             .convert::<Toggle>("iommu")
-            .map_err(Error::ParseVsock)?
+            .map_err(Error::ParsePersistentMemory)?
+            .unwrap_or(Toggle(false))
+            .0;
+        let discard_writes = parser
+            .convert::<Toggle>("discard_writes")
+            .map_err(Error::ParsePersistentMemory)?
+            .unwrap_or(Toggle(false))
+            .0;
+        let nvdimm = parser
+            .convert::<Toggle>("nvdimm")
+            .map_err(Error::ParsePersistentMemory)?
             .unwrap_or(Toggle(false))
             .0;
-        let cid = parser
-            .convert("cid")
-            .map_err(Error::ParseVsock)?
-            .ok_or(Error::ParseVsockCidMissing)?;
         let id = parser.get("id");
         let pci_segment = parser
             .convert("pci_segment")
-            .map_err(Error::ParseVsock)?
+            .map_err(Error::ParsePersistentMemory)?
             .unwrap_or_default();
 
-        Ok(VsockConfig {
-            cid,
-            socket,
+        Ok(PmemConfig {
+            file,
+            size,
             iommu,
+            discard_writes,
+            nvdimm,
             id,
             pci_segment,
         })
     }
 
     pub fn validate(&self, vm_config: &VmConfig) -> ValidationResult<()> {
+        if self.nvdimm {
+            // NVDIMM devices are described through ACPI
+            if cfg!(target_arch = "riscv64") {
+                return Err(ValidationError::NvdimmUnsupported);
+            }
+
+            if self.iommu {
+                return Err(ValidationError::NvdimmIommuUnsupported);
+            }
+        }
+
         if let Some(platform_config) = vm_config.platform.as_ref() {
             if self.pci_segment >= platform_config.num_pci_segments {
                 return Err(ValidationError::InvalidPciSegment(self.pci_segment));
             }
 
             if let Some(iommu_segments) = platform_config.iommu_segments.as_ref() {
-                if iommu_segments.contains(&self.pci_segment) && !self.iommu {
+                if iommu_segments.contains(&self.pci_segment) && !self.iommu && !self.nvdimm {
                     return Err(ValidationError::OnIommuSegment(self.pci_segment));
                 }
             }
@@ -2146,2124 +3874,5146 @@ impl VsockConfig {
     }
 }
 
-#[cfg(target_arch = "x86_64")]
-impl SgxEpcConfig {
-    pub const SYNTAX: &'static str = "SGX EPC parameters \
-        \"id=<epc_section_identifier>,size=<epc_section_size>,prefault=on|off\"";
-
-    pub fn parse(sgx_epc: &str) -> Result<Self> {
+impl ConsoleConfig {
+    pub fn parse(console: &str) -> Result<Self> {
         let mut parser = OptionParser::new();
-        parser.add("id").add("size").add("prefault");
-        parser.parse(sgx_epc).map_err(Error::ParseSgxEpc)?;
+        parser
+            .add_valueless("off")
+            .add_valueless("pty")
+            .add_valueless("tty")
+            .add_valueless("null")
+            .add("file")
+            .add("iommu")
+            .add("socket")
+            .add("tee");
+        parser.parse(console).map_err(Error::ParseConsole)?;
 
-        let id = parser.get("id").ok_or(Error::ParseSgxEpcIdMissing)?;
-        let size = parser
-            .convert::<ByteSized>("size")
-            .map_err(Error::ParseSgxEpc)?
-            .unwrap_or(ByteSized(0))
-            .0;
-        let prefault = parser
-            .convert::<Toggle>("prefault")
-            .map_err(Error::ParseSgxEpc)?
+        let mut file: Option<PathBuf> = default_consoleconfig_file();
+        let mut socket: Option<PathBuf> = None;
+        let mut mode: ConsoleOutputMode = ConsoleOutputMode::Off;
+
+        if parser.is_set("off") {
+        } else if parser.is_set("pty") {
+            mode = ConsoleOutputMode::Pty
+        } else if parser.is_set("tty") {
+            mode = ConsoleOutputMode::Tty
+        } else if parser.is_set("null") {
+            mode = ConsoleOutputMode::Null
+        } else if parser.is_set("file") {
+            mode = ConsoleOutputMode::File;
+            file =
+                Some(PathBuf::from(parser.get("file").ok_or(
+                    Error::Validation(ValidationError::ConsoleFileMissing),
+                )?));
+        } else if parser.is_set("socket") {
+            mode = ConsoleOutputMode::Socket;
+            socket = Some(PathBuf::from(parser.get("socket").ok_or(
+                Error::Validation(ValidationError::ConsoleSocketPathMissing),
+            )?));
+        } else {
+            return Err(Error::ParseConsoleInvalidModeGiven);
+        }
+        let iommu = parser
+            .convert::<Toggle>("iommu")
+            .map_err(Error::ParseConsole)?
             .unwrap_or(Toggle(false))
             .0;
+        let tee = parser
+            .convert::<Tuple<String, String>>("tee")
+            .map_err(Error::ParseConsole)?
+            .map(|v| {
+                v.0.into_iter()
+                    .map(|(kind, value)| Self::parse_sink(&kind, &value))
+                    .collect::<Result<Vec<_>>>()
+            })
+            .transpose()?
+            .unwrap_or_default();
 
-        Ok(SgxEpcConfig { id, size, prefault })
+        Ok(Self {
+            file,
+            mode,
+            iommu,
+            socket,
+            tee,
+        })
     }
-}
 
-impl NumaConfig {
-    pub const SYNTAX: &'static str = "Settings related to a given NUMA node \
-        \"guest_numa_id=<node_id>,cpus=<cpus_id>,distances=<list_of_distances_to_destination_nodes>,\
-        memory_zones=<list_of_memory_zones>,sgx_epc_sections=<list_of_sgx_epc_sections>,\
-        pci_segments=<list_of_pci_segments>\"";
-
-    pub fn parse(numa: &str) -> Result<Self> {
-        let mut parser = OptionParser::new();
-        parser
-            .add("guest_numa_id")
-            .add("cpus")
-            .add("distances")
-            .add("memory_zones")
-            .add("sgx_epc_sections")
-            .add("pci_segments");
-
-        parser.parse(numa).map_err(Error::ParseNuma)?;
-
-        let guest_numa_id = parser
-            .convert::<u32>("guest_numa_id")
-            .map_err(Error::ParseNuma)?
-            .unwrap_or(0);
-        let cpus = parser
-            .convert::<IntegerList>("cpus")
-            .map_err(Error::ParseNuma)?
-            .map(|v| v.0.iter().map(|e| *e as u8).collect());
-        let distances = parser
-            .convert::<Tuple<u64, u64>>("distances")
-            .map_err(Error::ParseNuma)?
-            .map(|v| {
-                v.0.iter()
-                    .map(|(e1, e2)| NumaDistance {
-                        destination: *e1 as u32,
-                        distance: *e2 as u8,
-                    })
-                    .collect()
-            });
-        let memory_zones = parser
-            .convert::<StringList>("memory_zones")
-            .map_err(Error::ParseNuma)?
-            .map(|v| v.0);
-        #[cfg(target_arch = "x86_64")]
-        let sgx_epc_sections = parser
-            .convert::<StringList>("sgx_epc_sections")
-            .map_err(Error::ParseNuma)?
-            .map(|v| v.0);
-        let pci_segments = parser
-            .convert::<IntegerList>("pci_segments")
-            .map_err(Error::ParseNuma)?
-            .map(|v| v.0.iter().map(|e| *e as u16).collect());
-        Ok(NumaConfig {
-            guest_numa_id,
-            cpus,
-            distances,
-            memory_zones,
-            #[cfg(target_arch = "x86_64")]
-            sgx_epc_sections,
-            pci_segments,
-        })
+    fn parse_sink(kind: &str, value: &str) -> Result<ConsoleSink> {
+        let invalid = || Error::ParseConsoleInvalidSink(format!("{kind}@{value}"));
+        match kind {
+            "file" => Ok(ConsoleSink::File(PathBuf::from(value))),
+            "socket" => Ok(ConsoleSink::Socket(PathBuf::from(value))),
+            "ring" => value
+                .parse::<ByteSized>()
+                .map(|size| ConsoleSink::Ring(size.0))
+                .map_err(|_| invalid()),
+            _ => Err(invalid()),
+        }
     }
-}
 
-#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, Default)]
-pub struct RestoredNetConfig {
-    pub id: String,
-    #[serde(default)]
-    pub num_fds: usize,
-    #[serde(
-        default,
-        serialize_with = "serialize_restorednetconfig_fds",
-        deserialize_with = "deserialize_restorednetconfig_fds"
-    )]
-    pub fds: Option<Vec<i32>>,
-}
+    pub fn validate(&self) -> ValidationResult<()> {
+        if self.tee.is_empty() {
+            return Ok(());
+        }
 
-fn serialize_restorednetconfig_fds<S>(
-    x: &Option<Vec<i32>>,
-    s: S,
-) -> std::result::Result<S::Ok, S::Error>
-where
-    S: serde::Serializer,
-{
-    if let Some(x) = x {
-        warn!("'RestoredNetConfig' contains FDs that can't be serialized correctly. Serializing them as invalid FDs.");
-        let invalid_fds = vec![-1; x.len()];
-        s.serialize_some(&invalid_fds)
-    } else {
-        s.serialize_none()
-    }
-}
+        if self.mode == ConsoleOutputMode::Off {
+            return Err(ValidationError::ConsoleTeeWithoutOutput);
+        }
 
-fn deserialize_restorednetconfig_fds<'de, D>(
-    d: D,
-) -> std::result::Result<Option<Vec<i32>>, D::Error>
-where
-    D: serde::Deserializer<'de>,
-{
-    let invalid_fds: Option<Vec<i32>> = Option::deserialize(d)?;
-    if let Some(invalid_fds) = invalid_fds {
-        warn!("'RestoredNetConfig' contains FDs that can't be deserialized correctly. Deserializing them as invalid FDs.");
-        Ok(Some(vec![-1; invalid_fds.len()]))
-    } else {
-        Ok(None)
-    }
-}
+        let rings: Vec<u64> = self
+            .tee
+            .iter()
+            .filter_map(|sink| match sink {
+                ConsoleSink::Ring(size) => Some(*size),
+                _ => None,
+            })
+            .collect();
+        if rings.len() > 1 {
+            return Err(ValidationError::ConsoleTeeMultipleRings);
+        }
+        if rings.contains(&0) {
+            return Err(ValidationError::InvalidConsoleRingSize);
+        }
 
-#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, Default)]
-pub struct RestoreConfig {
-    pub source_url: PathBuf,
-    #[serde(default)]
-    pub prefault: bool,
-    #[serde(default)]
-    pub net_fds: Option<Vec<RestoredNetConfig>>,
+        Ok(())
+    }
 }
 
-impl RestoreConfig {
-    pub const SYNTAX: &'static str = "Restore from a VM snapshot. \
-        \nRestore parameters \"source_url=<source_url>,prefault=on|off,\
-        net_fds=<list_of_net_ids_with_their_associated_fds>\" \
-        \n`source_url` should be a valid URL (e.g file:///foo/bar or tcp://192.168.1.10/foo) \
-        \n`prefault` brings memory pages in when enabled (disabled by default) \
-        \n`net_fds` is a list of net ids with new file descriptors. \
-        Only net devices backed by FDs directly are needed as input.";
-
-    pub fn parse(restore: &str) -> Result<Self> {
+#[cfg(target_arch = "x86_64")]
+impl DebugConsoleConfig {
+    pub fn parse(debug_console_ops: &str) -> Result<Self> {
         let mut parser = OptionParser::new();
-        parser.add("source_url").add("prefault").add("net_fds");
-        parser.parse(restore).map_err(Error::ParseRestore)?;
-
-        let source_url = parser
-            .get("source_url")
-            .map(PathBuf::from)
-            .ok_or(Error::ParseRestoreSourceUrlMissing)?;
-        let prefault = parser
-            .convert::<Toggle>("prefault")
-            .map_err(Error::ParseRestore)?
-            .unwrap_or(Toggle(false))
-            .0;
-        let net_fds = parser
-            .convert::<Tuple<String, Vec<u64>>>("net_fds")
-            .map_err(Error::ParseRestore)?
-            .map(|v| {
-                v.0.iter()
-                    .map(|(id, fds)| RestoredNetConfig {
-                        id: id.clone(),
-                        num_fds: fds.len(),
-                        fds: Some(fds.iter().map(|e| *e as i32).collect()),
-                    })
-                    .collect()
-            });
+        parser
+            .add_valueless("off")
+            .add_valueless("pty")
+            .add_valueless("tty")
+            .add_valueless("null")
+            .add("file")
+            .add("iobase");
+        parser
+            .parse(debug_console_ops)
+            .map_err(Error::ParseConsole)?;
 
-        Ok(RestoreConfig {
-            source_url,
-            prefault,
-            net_fds,
-        })
-    }
+        let mut file: Option<PathBuf> = default_consoleconfig_file();
+        let mut iobase: Option<u16> = None;
+        let mut mode: ConsoleOutputMode = ConsoleOutputMode::Off;
 
-    // Ensure all net devices from 'VmConfig' backed by FDs have a
-    // corresponding 'RestoreNetConfig' with a matched 'id' and expected
-    // number of FDs.
-    pub fn validate(&self, vm_config: &VmConfig) -> ValidationResult<()> {
-        let mut restored_net_with_fds = HashMap::new();
-        for n in self.net_fds.iter().flatten() {
-            assert_eq!(
-                n.num_fds,
-                n.fds.as_ref().map_or(0, |f| f.len()),
-                "Invalid 'RestoredNetConfig' with conflicted fields."
-            );
-            if restored_net_with_fds.insert(n.id.clone(), n).is_some() {
-                return Err(ValidationError::IdentifierNotUnique(n.id.clone()));
-            }
+        if parser.is_set("off") {
+        } else if parser.is_set("pty") {
+            mode = ConsoleOutputMode::Pty
+        } else if parser.is_set("tty") {
+            mode = ConsoleOutputMode::Tty
+        } else if parser.is_set("null") {
+            mode = ConsoleOutputMode::Null
+        } else if parser.is_set("file") {
+            mode = ConsoleOutputMode::File;
+            file =
+                Some(PathBuf::from(parser.get("file").ok_or(
+                    Error::Validation(ValidationError::ConsoleFileMissing),
+                )?));
+        } else {
+            return Err(Error::ParseConsoleInvalidModeGiven);
         }
 
-        for net_fds in vm_config.net.iter().flatten() {
-            if let Some(expected_fds) = &net_fds.fds {
-                let expected_id = net_fds
-                    .id
-                    .as_ref()
-                    .expect("Invalid 'NetConfig' with empty 'id' for VM restore.");
-                if let Some(r) = restored_net_with_fds.remove(expected_id) {
-                    if r.num_fds != expected_fds.len() {
-                        return Err(ValidationError::RestoreNetFdCountMismatch(
-                            expected_id.clone(),
-                            r.num_fds,
-                            expected_fds.len(),
-                        ));
-                    }
-                } else {
-                    return Err(ValidationError::RestoreMissingRequiredNetId(
-                        expected_id.clone(),
-                    ));
+        if parser.is_set("iobase") {
+            if let Some(iobase_opt) = parser.get("iobase") {
+                if !iobase_opt.starts_with("0x") {
+                    return Err(Error::Validation(ValidationError::InvalidIoPortHex(
+                        iobase_opt,
+                    )));
                 }
+                iobase = Some(u16::from_str_radix(&iobase_opt[2..], 16).map_err(|_| {
+                    Error::Validation(ValidationError::InvalidIoPortHex(iobase_opt))
+                })?);
             }
         }
 
-        if !restored_net_with_fds.is_empty() {
-            warn!("Ignoring unused 'net_fds' for VM restore.")
-        }
-
-        Ok(())
+        Ok(Self { file, mode, iobase })
     }
 }
 
-impl TpmConfig {
-    pub const SYNTAX: &'static str = "TPM device \
-        \"(UNIX Domain Socket from swtpm) socket=</path/to/a/socket>\"";
+// The NVIDIA P2P capability stores the clique ID on 4 bits.
+const MAX_GPUDIRECT_CLIQUE_ID: u8 = 15;
 
-    pub fn parse(tpm: &str) -> Result<Self> {
-        let mut parser = OptionParser::new();
-        parser.add("socket");
-        parser.parse(tpm).map_err(Error::ParseTpm)?;
-        let socket = parser
-            .get("socket")
-            .map(PathBuf::from)
-            .ok_or(Error::ParseTpmPathMissing)?;
-        Ok(TpmConfig { socket })
-    }
-}
+// VLAN 4095 is reserved, 0 meaning untagged.
+const MAX_VLAN_ID: u16 = 4094;
 
-impl LandlockConfig {
-    pub const SYNTAX: &'static str = "Landlock parameters \
-        \"path=<path/to/{file/dir}>,access=[rw]\"";
+impl DeviceConfig {
+    pub const SYNTAX: &'static str =
+        "Direct device assignment parameters \"path=<device_path|mdev_sysfs_path>,iommu=on|off,id=<device_id>,pci_segment=<segment_id>,p2p_dma=on|off,reset_method=auto|flr|bus|none,reset_on_detach=on|off,legacy_vga=on|off,romfile=<option_rom_path>,sriov_pf=<pf_interface>,sriov_vf=<vf_index>,sriov_mac=<vf_mac>,sriov_vlan=<vf_vlan>,sriov_trust=on|off\"";
 
-    pub fn parse(landlock_rule: &str) -> Result<Self> {
+    pub fn parse(device: &str) -> Result<Self> {
         let mut parser = OptionParser::new();
-        parser.add("path").add("access");
         parser
-            .parse(landlock_rule)
-            .map_err(Error::ParseLandlockRules)?;
-
-        let path = parser
-            .get("path")
-            .map(PathBuf::from)
-            .ok_or(Error::ParseLandlockMissingFields)?;
+            .add("path")
+            .add("id")
+            .add("iommu")
+            .add("pci_segment")
+            .add("x_nv_gpudirect_clique")
+            .add("p2p_dma")
+            .add("reset_method")
+            .add("reset_on_detach")
+            .add("legacy_vga")
+            .add("romfile")
+            .add("sriov_pf")
+            .add("sriov_vf")
+            .add("sriov_mac")
+            .add("sriov_vlan")
+            .add("sriov_trust");
+        parser.parse(device).map_err(Error::ParseDevice)?;
 
-        let access = parser
-            .get("access")
-            .ok_or(Error::ParseLandlockMissingFields)?;
+        let sriov = match parser.get("sriov_pf") {
+            Some(pf) => Some(SriovVfConfig {
+                pf,
+                vf: parser
+                    .convert("sriov_vf")
+                    .map_err(Error::ParseDevice)?
+                    .ok_or(Error::ParseDeviceSriovVfMissing)?,
+                mac: parser.convert("sriov_mac").map_err(Error::ParseDevice)?,
+                vlan: parser.convert("sriov_vlan").map_err(Error::ParseDevice)?,
+                trust: parser
+                    .convert::<Toggle>("sriov_trust")
+                    .map_err(Error::ParseDevice)?
+                    .unwrap_or(Toggle(false))
+                    .0,
+            }),
+            None if ["sriov_vf", "sriov_mac", "sriov_vlan", "sriov_trust"]
+                .iter()
+                .any(|option| parser.is_set(option)) =>
+            {
+                return Err(Error::ParseDeviceSriovPfMissing);
+            }
+            None => None,
+        };
+        // The path of a virtual function is only known once created.
+        let path = match parser.get("path") {
+            Some(path) => PathBuf::from(path),
+            None if sriov.is_some() => PathBuf::new(),
+            None => return Err(Error::ParseDevicePathMissing),
+        };
+        let iommu = parser
+            .convert::<Toggle>("iommu")
+            .map_err(Error::ParseDevice)?
+            .unwrap_or(Toggle(false))
+            .0;
+        let id = parser.get("id");
+        let pci_segment = parser
+            .convert::<u16>("pci_segment")
+            .map_err(Error::ParseDevice)?
+            .unwrap_or_default();
+        let x_nv_gpudirect_clique = parser
+            .convert::<u8>("x_nv_gpudirect_clique")
+            .map_err(Error::ParseDevice)?;
+        let p2p_dma = parser
+            .convert::<Toggle>("p2p_dma")
+            .map_err(Error::ParseDevice)?
+            .unwrap_or(Toggle(true))
+            .0;
+        let reset_method = parser
+            .convert::<VfioResetMethod>("reset_method")
+            .map_err(Error::ParseDevice)?
+            .unwrap_or_default();
+        let reset_on_detach = parser
+            .convert::<Toggle>("reset_on_detach")
+            .map_err(Error::ParseDevice)?
+            .unwrap_or(Toggle(false))
+            .0;
+        let legacy_vga = parser
+            .convert::<Toggle>("legacy_vga")
+            .map_err(Error::ParseDevice)?
+            .unwrap_or(Toggle(false))
+            .0;
+        let romfile = parser.get("romfile").map(PathBuf::from);
+        Ok(DeviceConfig {
+            path,
+            iommu,
+            id,
+            pci_segment,
+            x_nv_gpudirect_clique,
+            p2p_dma,
+            reset_method,
+            reset_on_detach,
+            legacy_vga,
+            romfile,
+            sriov,
+        })
+    }
 
-        if access.chars().count() > 2 {
-            return Err(Error::ParseLandlockRules(OptionParserError::InvalidValue(
-                access.to_string(),
-            )));
-        }
+    pub fn validate(&self, vm_config: &VmConfig) -> ValidationResult<()> {
+        if let Some(platform_config) = vm_config.platform.as_ref() {
+            if self.pci_segment >= platform_config.num_pci_segments {
+                return Err(ValidationError::InvalidPciSegment(self.pci_segment));
+            }
 
-        Ok(LandlockConfig { path, access })
-    }
+            if let Some(iommu_segments) = platform_config.iommu_segments.as_ref() {
+                if iommu_segments.contains(&self.pci_segment) && !self.iommu {
+                    return Err(ValidationError::OnIommuSegment(self.pci_segment));
+                }
+            }
+        }
 
-    pub fn validate(&self) -> ValidationResult<()> {
-        if !self.path.exists() {
-            return Err(ValidationError::LandlockPathDoesNotExist(self.path.clone()));
+        match &self.sriov {
+            Some(sriov) => {
+                if let Some(vlan) = sriov.vlan.filter(|vlan| *vlan > MAX_VLAN_ID) {
+                    return Err(ValidationError::InvalidSriovVlan(vlan));
+                }
+            }
+            None if self.path.as_os_str().is_empty() => {
+                return Err(ValidationError::DevicePathMissing);
+            }
+            None => {}
         }
-        LandlockAccess::try_from(self.access.as_str())
-            .map_err(|e| ValidationError::InvalidLandlockAccess(e.to_string()))?;
-        Ok(())
-    }
-}
 
-impl VmConfig {
-    fn validate_identifier(
-        id_list: &mut BTreeSet<String>,
-        id: &Option<String>,
-    ) -> ValidationResult<()> {
-        if let Some(id) = id.as_ref() {
-            if id.starts_with("__") {
-                return Err(ValidationError::InvalidIdentifier(id.clone()));
+        if let Some(clique) = self.x_nv_gpudirect_clique {
+            if clique > MAX_GPUDIRECT_CLIQUE_ID {
+                return Err(ValidationError::InvalidGpuDirectClique(clique));
             }
 
-            if !id_list.insert(id.clone()) {
-                return Err(ValidationError::IdentifierNotUnique(id.clone()));
+            // Exposing a clique to the guest is meaningless without
+            // peer-to-peer DMA.
+            if !self.p2p_dma {
+                return Err(ValidationError::GpuDirectCliqueWithoutP2pDma(clique));
             }
         }
 
         Ok(())
     }
+}
 
-    pub fn backed_by_shared_memory(&self) -> bool {
-        if self.memory.shared || self.memory.hugepages {
-            return true;
-        }
+impl UserDeviceConfig {
+    pub const SYNTAX: &'static str =
+        "Userspace device socket=<socket_path>,id=<device_id>,pci_segment=<segment_id>\"";
 
-        if self.memory.size == 0 {
-            for zone in self.memory.zones.as_ref().unwrap() {
-                if !zone.shared && !zone.hugepages {
-                    return false;
-                }
-            }
-            true
-        } else {
-            false
-        }
-    }
+    pub fn parse(user_device: &str) -> Result<Self> {
+        let mut parser = OptionParser::new();
+        parser.add("socket").add("id").add("pci_segment");
+        parser.parse(user_device).map_err(Error::ParseUserDevice)?;
 
-    // Also enables virtio-iommu if the config needs it
-    // Returns the list of unique identifiers provided through the
-    // configuration.
-    pub fn validate(&mut self) -> ValidationResult<BTreeSet<String>> {
-        let mut id_list = BTreeSet::new();
+        let socket = parser
+            .get("socket")
+            .map(PathBuf::from)
+            .ok_or(Error::ParseUserDeviceSocketMissing)?;
+        let id = parser.get("id");
+        let pci_segment = parser
+            .convert::<u16>("pci_segment")
+            .map_err(Error::ParseUserDevice)?
+            .unwrap_or_default();
 
-        self.payload
-            .as_ref()
-            .ok_or(ValidationError::KernelMissing)?;
+        Ok(UserDeviceConfig {
+            socket,
+            id,
+            pci_segment,
+        })
+    }
 
-        #[cfg(feature = "tdx")]
-        {
-            let tdx_enabled = self.platform.as_ref().map(|p| p.tdx).unwrap_or(false);
-            // At this point we know payload isn't None.
-            if tdx_enabled && self.payload.as_ref().unwrap().firmware.is_none() {
-                return Err(ValidationError::TdxFirmwareMissing);
-            }
-            if tdx_enabled && (self.cpus.max_vcpus != self.cpus.boot_vcpus) {
-                return Err(ValidationError::TdxNoCpuHotplug);
+    pub fn validate(&self, vm_config: &VmConfig) -> ValidationResult<()> {
+        if let Some(platform_config) = vm_config.platform.as_ref() {
+            if self.pci_segment >= platform_config.num_pci_segments {
+                return Err(ValidationError::InvalidPciSegment(self.pci_segment));
             }
-        }
-
-        #[cfg(feature = "sev_snp")]
-        {
-            let host_data_opt = &self.payload.as_ref().unwrap().host_data;
 
-            if let Some(host_data) = host_data_opt {
-                if host_data.len() != 64 {
-                    return Err(ValidationError::InvalidHostData);
+            if let Some(iommu_segments) = platform_config.iommu_segments.as_ref() {
+                if iommu_segments.contains(&self.pci_segment) {
+                    return Err(ValidationError::IommuNotSupportedOnSegment(
+                        self.pci_segment,
+                    ));
                 }
             }
         }
-        // The 'conflict' check is introduced in commit 24438e0390d3
-        // (vm-virtio: Enable the vmm support for virtio-console).
-        //
-        // Allow simultaneously set serial and console as TTY mode, for
-        // someone want to use virtio console for better performance, and
-        // want to keep legacy serial to catch boot stage logs for debug.
-        // Using such double tty mode, you need to configure the kernel
-        // properly, such as:
-        // "console=hvc0 earlyprintk=ttyS0"
 
-        let mut tty_consoles = Vec::new();
-        if self.console.mode == ConsoleOutputMode::Tty {
-            tty_consoles.push("virtio-console");
-        };
-        if self.serial.mode == ConsoleOutputMode::Tty {
-            tty_consoles.push("serial-console");
-        };
-        #[cfg(target_arch = "x86_64")]
-        if self.debug_console.mode == ConsoleOutputMode::Tty {
-            tty_consoles.push("debug-console");
-        };
-        if tty_consoles.len() > 1 {
-            warn!("Using TTY output for multiple consoles: {:?}", tty_consoles);
-        }
+        Ok(())
+    }
+}
 
-        if self.console.mode == ConsoleOutputMode::File && self.console.file.is_none() {
-            return Err(ValidationError::ConsoleFileMissing);
-        }
+impl VdpaConfig {
+    pub const SYNTAX: &'static str = "vDPA device \
+        \"path=<device_path>,num_queues=<number_of_queues>,iommu=on|off,\
+        id=<device_id>,pci_segment=<segment_id>,msix_vectors=<number_of_vectors>\"";
 
-        if self.serial.mode == ConsoleOutputMode::File && self.serial.file.is_none() {
-            return Err(ValidationError::ConsoleFileMissing);
-        }
+    pub fn parse(vdpa: &str) -> Result<Self> {
+        let mut parser = OptionParser::new();
+        parser
+            .add("path")
+            .add("num_queues")
+            .add("iommu")
+            .add("id")
+            .add("pci_segment")
+            .add("msix_vectors");
+        parser.parse(vdpa).map_err(Error::ParseVdpa)?;
 
-        if self.cpus.max_vcpus < self.cpus.boot_vcpus {
-            return Err(ValidationError::CpusMaxLowerThanBoot);
-        }
+        let path = parser
+            .get("path")
+            .map(PathBuf::from)
+            .ok_or(Error::ParseVdpaPathMissing)?;
+        let num_queues = parser
+            .convert("num_queues")
+            .map_err(Error::ParseVdpa)?
+            .unwrap_or_else(default_vdpaconfig_num_queues);
+        let iommu = parser
+            .convert::<Toggle>("iommu")
+            .map_err(Error::ParseVdpa)?
+            .unwrap_or(Toggle(false))
+            .0;
+        let id = parser.get("id");
+        let pci_segment = parser
+            .convert("pci_segment")
+            .map_err(Error::ParseVdpa)?
+            .unwrap_or_default();
+        let msix_vectors = parser.convert("msix_vectors").map_err(Error::ParseVdpa)?;
 
-        if let Some(rate_limit_groups) = &self.rate_limit_groups {
-            for rate_limit_group in rate_limit_groups {
-                rate_limit_group.validate(self)?;
+        Ok(VdpaConfig {
+            path,
+            num_queues,
+            iommu,
+            id,
+            pci_segment,
+            msix_vectors,
+        })
+    }
 
-                Self::validate_identifier(&mut id_list, &Some(rate_limit_group.id.clone()))?;
+    pub fn validate(&self, vm_config: &VmConfig) -> ValidationResult<()> {
+        validate_msix_vectors(self.msix_vectors)?;
+
+        if let Some(platform_config) = vm_config.platform.as_ref() {
+            if self.pci_segment >= platform_config.num_pci_segments {
+                return Err(ValidationError::InvalidPciSegment(self.pci_segment));
             }
-        }
 
-        if let Some(disks) = &self.disks {
-            for disk in disks {
-                if disk.vhost_socket.as_ref().and(disk.path.as_ref()).is_some() {
-                    return Err(ValidationError::DiskSocketAndPath);
-                }
-                if disk.vhost_user && !self.backed_by_shared_memory() {
-                    return Err(ValidationError::VhostUserRequiresSharedMemory);
-                }
-                if disk.vhost_user && disk.vhost_socket.is_none() {
-                    return Err(ValidationError::VhostUserMissingSocket);
+            if let Some(iommu_segments) = platform_config.iommu_segments.as_ref() {
+                if iommu_segments.contains(&self.pci_segment) && !self.iommu {
+                    return Err(ValidationError::OnIommuSegment(self.pci_segment));
                 }
-                if let Some(rate_limit_group) = &disk.rate_limit_group {
-                    if let Some(rate_limit_groups) = &self.rate_limit_groups {
-                        if !rate_limit_groups
-                            .iter()
-                            .any(|cfg| &cfg.id == rate_limit_group)
-                        {
-                            return Err(ValidationError::InvalidRateLimiterGroup);
-                        }
-                    } else {
-                        return Err(ValidationError::InvalidRateLimiterGroup);
-                    }
-                }
-
-                disk.validate(self)?;
-                self.iommu |= disk.iommu;
-
-                Self::validate_identifier(&mut id_list, &disk.id)?;
-            }
-        }
-
-        if let Some(nets) = &self.net {
-            for net in nets {
-                if net.vhost_user && !self.backed_by_shared_memory() {
-                    return Err(ValidationError::VhostUserRequiresSharedMemory);
-                }
-                net.validate(self)?;
-                self.iommu |= net.iommu;
-
-                Self::validate_identifier(&mut id_list, &net.id)?;
-            }
-        }
-
-        if let Some(fses) = &self.fs {
-            if !fses.is_empty() && !self.backed_by_shared_memory() {
-                return Err(ValidationError::VhostUserRequiresSharedMemory);
-            }
-            for fs in fses {
-                fs.validate(self)?;
-
-                Self::validate_identifier(&mut id_list, &fs.id)?;
             }
         }
 
-        if let Some(pmems) = &self.pmem {
-            for pmem in pmems {
-                pmem.validate(self)?;
-                self.iommu |= pmem.iommu;
+        Ok(())
+    }
+}
 
-                Self::validate_identifier(&mut id_list, &pmem.id)?;
-            }
-        }
+impl VsockConfig {
+    pub const SYNTAX: &'static str = "Virtio VSOCK parameters \
+        \"cid=<context_id>,socket=<socket_path>,iommu=on|off,id=<device_id>,pci_segment=<segment_id>\"";
 
-        self.iommu |= self.rng.iommu;
-        self.iommu |= self.console.iommu;
+    pub fn parse(vsock: &str) -> Result<Self> {
+        let mut parser = OptionParser::new();
+        parser
+            .add("socket")
+            .add("cid")
+            .add("iommu")
+            .add("id")
+            .add("pci_segment");
+        parser.parse(vsock).map_err(Error::ParseVsock)?;
 
-        if let Some(t) = &self.cpus.topology {
-            if t.threads_per_core == 0
-                || t.cores_per_die == 0
-                || t.dies_per_package == 0
-                || t.packages == 0
-            {
-                return Err(ValidationError::CpuTopologyZeroPart);
-            }
+        let socket = parser
+            .get("socket")
+            .map(PathBuf::from)
+            .ok_or(Error::ParseVsockSockMissing)?;
+        let iommu = parser
+            .convert::<Toggle>("iommu")
+            .map_err(Error::ParseVsock)?
+            .unwrap_or(Toggle(false))
+            .0;
+        let cid = parser
+            .convert("cid")
+            .map_err(Error::ParseVsock)?
+            .ok_or(Error::ParseVsockCidMissing)?;
+        let id = parser.get("id");
+        let pci_segment = parser
+            .convert("pci_segment")
+            .map_err(Error::ParseVsock)?
+            .unwrap_or_default();
 
-            // The setting of dies doesn't apply on AArch64.
-            // Only '1' value is accepted, so its impact on the vcpu topology
-            // setting can be ignored.
-            #[cfg(target_arch = "aarch64")]
-            if t.dies_per_package != 1 {
-                return Err(ValidationError::CpuTopologyDiesPerPackage);
-            }
+        Ok(VsockConfig {
+            cid,
+            socket,
+            iommu,
+            id,
+            pci_segment,
+        })
+    }
 
-            let total = t.threads_per_core * t.cores_per_die * t.dies_per_package * t.packages;
-            if total != self.cpus.max_vcpus {
-                return Err(ValidationError::CpuTopologyCount);
+    pub fn validate(&self, vm_config: &VmConfig) -> ValidationResult<()> {
+        if let Some(platform_config) = vm_config.platform.as_ref() {
+            if self.pci_segment >= platform_config.num_pci_segments {
+                return Err(ValidationError::InvalidPciSegment(self.pci_segment));
             }
-        }
 
-        if let Some(hugepage_size) = &self.memory.hugepage_size {
-            if !self.memory.hugepages {
-                return Err(ValidationError::HugePageSizeWithoutHugePages);
-            }
-            if !hugepage_size.is_power_of_two() {
-                return Err(ValidationError::InvalidHugePageSize(*hugepage_size));
+            if let Some(iommu_segments) = platform_config.iommu_segments.as_ref() {
+                if iommu_segments.contains(&self.pci_segment) && !self.iommu {
+                    return Err(ValidationError::OnIommuSegment(self.pci_segment));
+                }
             }
         }
 
-        if let Some(user_devices) = &self.user_devices {
-            if !user_devices.is_empty() && !self.backed_by_shared_memory() {
-                return Err(ValidationError::UserDevicesRequireSharedMemory);
-            }
-
-            for user_device in user_devices {
-                user_device.validate(self)?;
+        Ok(())
+    }
+}
 
-                Self::validate_identifier(&mut id_list, &user_device.id)?;
-            }
-        }
+#[cfg(target_arch = "x86_64")]
+impl SgxEpcConfig {
+    pub const SYNTAX: &'static str = "SGX EPC parameters \
+        \"id=<epc_section_identifier>,size=<epc_section_size>,prefault=on|off\"";
 
-        if let Some(vdpa_devices) = &self.vdpa {
-            for vdpa_device in vdpa_devices {
-                vdpa_device.validate(self)?;
-                self.iommu |= vdpa_device.iommu;
+    pub fn parse(sgx_epc: &str) -> Result<Self> {
+        let mut parser = OptionParser::new();
+        parser.add("id").add("size").add("prefault");
+        parser.parse(sgx_epc).map_err(Error::ParseSgxEpc)?;
 
-                Self::validate_identifier(&mut id_list, &vdpa_device.id)?;
-            }
-        }
+        let id = parser.get("id").ok_or(Error::ParseSgxEpcIdMissing)?;
+        let size = parser
+            .convert::<ByteSized>("size")
+            .map_err(Error::ParseSgxEpc)?
+            .unwrap_or(ByteSized(0))
+            .0;
+        let prefault = parser
+            .convert::<Toggle>("prefault")
+            .map_err(Error::ParseSgxEpc)?
+            .unwrap_or(Toggle(false))
+            .0;
 
-        if let Some(vsock) = &self.vsock {
-            if [!0, 0, 1, 2].contains(&vsock.cid) {
-                return Err(ValidationError::VsockSpecialCid(vsock.cid));
-            }
-        }
+        Ok(SgxEpcConfig { id, size, prefault })
+    }
+}
 
-        if let Some(balloon) = &self.balloon {
-            let mut ram_size = self.memory.size;
+impl NumaConfig {
+    pub const SYNTAX: &'static str = "Settings related to a given NUMA node \
+        \"guest_numa_id=<node_id>,cpus=<cpus_id>,distances=<list_of_distances_to_destination_nodes>,\
+        memory_zones=<list_of_memory_zones>,sgx_epc_sections=<list_of_sgx_epc_sections>,\
+        pci_segments=<list_of_pci_segments>\"";
 
-            if let Some(zones) = &self.memory.zones {
-                for zone in zones {
-                    ram_size += zone.size;
-                }
-            }
+    pub fn parse(numa: &str) -> Result<Self> {
+        let mut parser = OptionParser::new();
+        parser
+            .add("guest_numa_id")
+            .add("cpus")
+            .add("distances")
+            .add("memory_zones")
+            .add("sgx_epc_sections")
+            .add("pci_segments");
 
-            if balloon.size >= ram_size {
-                return Err(ValidationError::BalloonLargerThanRam(
-                    balloon.size,
-                    ram_size,
-                ));
-            }
-        }
+        parser.parse(numa).map_err(Error::ParseNuma)?;
 
-        if let Some(devices) = &self.devices {
-            let mut device_paths = BTreeSet::new();
-            for device in devices {
-                if !device_paths.insert(device.path.to_string_lossy()) {
-                    return Err(ValidationError::DuplicateDevicePath(
-                        device.path.to_string_lossy().to_string(),
-                    ));
-                }
+        let guest_numa_id = parser
+            .convert::<u32>("guest_numa_id")
+            .map_err(Error::ParseNuma)?
+            .unwrap_or(0);
+        let cpus = parser
+            .convert::<IntegerList>("cpus")
+            .map_err(Error::ParseNuma)?
+            .map(|v| v.0.iter().map(|e| *e as u32).collect());
+        let distances = parser
+            .convert::<Tuple<u64, u64>>("distances")
+            .map_err(Error::ParseNuma)?
+            .map(|v| {
+                v.0.iter()
+                    .map(|(e1, e2)| NumaDistance {
+                        destination: *e1 as u32,
+                        distance: *e2 as u8,
+                    })
+                    .collect()
+            });
+        let memory_zones = parser
+            .convert::<StringList>("memory_zones")
+            .map_err(Error::ParseNuma)?
+            .map(|v| v.0);
+        #[cfg(target_arch = "x86_64")]
+        let sgx_epc_sections = parser
+            .convert::<StringList>("sgx_epc_sections")
+            .map_err(Error::ParseNuma)?
+            .map(|v| v.0);
+        let pci_segments = parser
+            .convert::<IntegerList>("pci_segments")
+            .map_err(Error::ParseNuma)?
+            .map(|v| v.0.iter().map(|e| *e as u16).collect());
+        Ok(NumaConfig {
+            guest_numa_id,
+            cpus,
+            distances,
+            memory_zones,
+            #[cfg(target_arch = "x86_64")]
+            sgx_epc_sections,
+            pci_segments,
+        })
+    }
+}
 
-                device.validate(self)?;
-                self.iommu |= device.iommu;
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, Default)]
+pub struct RestoredNetConfig {
+    pub id: String,
+    #[serde(default)]
+    pub num_fds: usize,
+    #[serde(
+        default,
+        serialize_with = "serialize_restorednetconfig_fds",
+        deserialize_with = "deserialize_restorednetconfig_fds"
+    )]
+    pub fds: Option<Vec<i32>>,
+}
 
-                Self::validate_identifier(&mut id_list, &device.id)?;
-            }
-        }
+fn serialize_restorednetconfig_fds<S>(
+    x: &Option<Vec<i32>>,
+    s: S,
+) -> std::result::Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    if let Some(x) = x {
+        warn!("'RestoredNetConfig' contains FDs that can't be serialized correctly. Serializing them as invalid FDs.");
+        let invalid_fds = vec![-1; x.len()];
+        s.serialize_some(&invalid_fds)
+    } else {
+        s.serialize_none()
+    }
+}
 
-        if let Some(vsock) = &self.vsock {
-            vsock.validate(self)?;
-            self.iommu |= vsock.iommu;
+fn deserialize_restorednetconfig_fds<'de, D>(
+    d: D,
+) -> std::result::Result<Option<Vec<i32>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let invalid_fds: Option<Vec<i32>> = Option::deserialize(d)?;
+    if let Some(invalid_fds) = invalid_fds {
+        warn!("'RestoredNetConfig' contains FDs that can't be deserialized correctly. Deserializing them as invalid FDs.");
+        Ok(Some(vec![-1; invalid_fds.len()]))
+    } else {
+        Ok(None)
+    }
+}
 
-            Self::validate_identifier(&mut id_list, &vsock.id)?;
-        }
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, Default)]
+pub struct RestoredDiskConfig {
+    pub id: String,
+    pub path: PathBuf,
+}
 
-        let num_pci_segments = match &self.platform {
-            Some(platform_config) => platform_config.num_pci_segments,
-            None => 1,
-        };
-        if let Some(numa) = &self.numa {
-            let mut used_numa_node_memory_zones = HashMap::new();
-            let mut used_pci_segments = HashMap::new();
-            for numa_node in numa.iter() {
-                if let Some(memory_zones) = numa_node.memory_zones.clone() {
-                    for memory_zone in memory_zones.iter() {
-                        if !used_numa_node_memory_zones.contains_key(memory_zone) {
-                            used_numa_node_memory_zones
-                                .insert(memory_zone.to_string(), numa_node.guest_numa_id);
-                        } else {
-                            return Err(ValidationError::MemoryZoneReused(
-                                memory_zone.to_string(),
-                                *used_numa_node_memory_zones.get(memory_zone).unwrap(),
-                                numa_node.guest_numa_id,
-                            ));
-                        }
-                    }
-                }
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, Default)]
+pub struct RestoredTapConfig {
+    pub id: String,
+    pub tap: String,
+}
 
-                if let Some(pci_segments) = numa_node.pci_segments.clone() {
-                    for pci_segment in pci_segments.iter() {
-                        if *pci_segment >= num_pci_segments {
-                            return Err(ValidationError::InvalidPciSegment(*pci_segment));
-                        }
-                        if *pci_segment == 0 && numa_node.guest_numa_id != 0 {
-                            return Err(ValidationError::DefaultPciSegmentInvalidNode(
-                                numa_node.guest_numa_id,
-                            ));
-                        }
-                        if !used_pci_segments.contains_key(pci_segment) {
-                            used_pci_segments.insert(*pci_segment, numa_node.guest_numa_id);
-                        } else {
-                            return Err(ValidationError::PciSegmentReused(
-                                *pci_segment,
-                                *used_pci_segments.get(pci_segment).unwrap(),
-                                numa_node.guest_numa_id,
-                            ));
-                        }
-                    }
-                }
-            }
-        }
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, Default)]
+pub struct RestoreConfig {
+    pub source_url: PathBuf,
+    #[serde(default)]
+    pub prefault: bool,
+    #[serde(default)]
+    pub net_fds: Option<Vec<RestoredNetConfig>>,
+    /// Map the guest memory copy-on-write from the snapshot instead of
+    /// copying it, sharing it with the other clones of the snapshot.
+    #[serde(default)]
+    pub clone: bool,
+    #[serde(default)]
+    pub disk_paths: Option<Vec<RestoredDiskConfig>>,
+    #[serde(default)]
+    pub net_taps: Option<Vec<RestoredTapConfig>>,
+    /// Key file the snapshot must have been signed with
+    #[serde(default)]
+    pub signing_key: Option<PathBuf>,
+    /// Restore the snapshot even if it fails the signature verification
+    #[serde(default)]
+    pub force: bool,
+}
 
-        if let Some(zones) = &self.memory.zones {
-            for zone in zones.iter() {
-                let id = zone.id.clone();
-                Self::validate_identifier(&mut id_list, &Some(id))?;
-            }
-        }
+impl RestoreConfig {
+    pub const SYNTAX: &'static str = "Restore from a VM snapshot. \
+        \nRestore parameters \"source_url=<source_url>,prefault=on|off,\
+        net_fds=<list_of_net_ids_with_their_associated_fds>,clone=on|off,\
+        disk_paths=<list_of_disk_ids_with_their_associated_paths>,\
+        net_taps=<list_of_net_ids_with_their_associated_taps>,\
+        signing_key=<signing_key_file>,force=on|off\" \
+        \n`source_url` should be a valid URL (e.g file:///foo/bar or tcp://192.168.1.10/foo) \
+        \n`prefault` brings memory pages in when enabled (disabled by default) \
+        \n`net_fds` is a list of net ids with new file descriptors. \
+        Only net devices backed by FDs directly are needed as input. \
+        \n`clone` maps the guest memory copy-on-write from the snapshot (disabled by default) \
+        \n`disk_paths` and `net_taps` give new disk images and TAP interfaces to the devices. \
+        \n`signing_key` verifies the snapshot was signed with the key, unless `force` is enabled.";
 
-        #[cfg(target_arch = "x86_64")]
-        if let Some(sgx_epcs) = &self.sgx_epc {
-            for sgx_epc in sgx_epcs.iter() {
-                let id = sgx_epc.id.clone();
-                Self::validate_identifier(&mut id_list, &Some(id))?;
-            }
-        }
+    pub fn parse(restore: &str) -> Result<Self> {
+        let mut parser = OptionParser::new();
+        parser
+            .add("source_url")
+            .add("prefault")
+            .add("net_fds")
+            .add("clone")
+            .add("disk_paths")
+            .add("net_taps")
+            .add("signing_key")
+            .add("force");
+        parser.parse(restore).map_err(Error::ParseRestore)?;
 
-        if let Some(pci_segments) = &self.pci_segments {
-            for pci_segment in pci_segments {
-                pci_segment.validate(self)?;
-            }
-        }
+        let source_url = parser
+            .get("source_url")
+            .map(PathBuf::from)
+            .ok_or(Error::ParseRestoreSourceUrlMissing)?;
+        let prefault = parser
+            .convert::<Toggle>("prefault")
+            .map_err(Error::ParseRestore)?
+            .unwrap_or(Toggle(false))
+            .0;
+        let net_fds = parser
+            .convert::<Tuple<String, Vec<u64>>>("net_fds")
+            .map_err(Error::ParseRestore)?
+            .map(|v| {
+                v.0.iter()
+                    .map(|(id, fds)| RestoredNetConfig {
+                        id: id.clone(),
+                        num_fds: fds.len(),
+                        fds: Some(fds.iter().map(|e| *e as i32).collect()),
+                    })
+                    .collect()
+            });
+        let clone = parser
+            .convert::<Toggle>("clone")
+            .map_err(Error::ParseRestore)?
+            .unwrap_or(Toggle(false))
+            .0;
+        let disk_paths = parser
+            .convert::<Tuple<String, String>>("disk_paths")
+            .map_err(Error::ParseRestore)?
+            .map(|v| {
+                v.0.into_iter()
+                    .map(|(id, path)| RestoredDiskConfig {
+                        id,
+                        path: PathBuf::from(path),
+                    })
+                    .collect()
+            });
+        let net_taps = parser
+            .convert::<Tuple<String, String>>("net_taps")
+            .map_err(Error::ParseRestore)?
+            .map(|v| {
+                v.0.into_iter()
+                    .map(|(id, tap)| RestoredTapConfig { id, tap })
+                    .collect()
+            });
+        let signing_key = parser.get("signing_key").map(PathBuf::from);
+        let force = parser
+            .convert::<Toggle>("force")
+            .map_err(Error::ParseRestore)?
+            .unwrap_or(Toggle(false))
+            .0;
 
-        self.platform.as_ref().map(|p| p.validate()).transpose()?;
-        self.iommu |= self
-            .platform
-            .as_ref()
-            .map(|p| p.iommu_segments.is_some())
-            .unwrap_or_default();
+        Ok(RestoreConfig {
+            source_url,
+            prefault,
+            net_fds,
+            clone,
+            disk_paths,
+            net_taps,
+            signing_key,
+            force,
+        })
+    }
 
-        if let Some(landlock_rules) = &self.landlock_rules {
-            for landlock_rule in landlock_rules {
-                landlock_rule.validate()?;
+    // Ensure all net devices from 'VmConfig' backed by FDs have a
+    // corresponding 'RestoreNetConfig' with a matched 'id' and expected
+    // number of FDs.
+    pub fn validate(&self, vm_config: &VmConfig) -> ValidationResult<()> {
+        let mut restored_net_with_fds = HashMap::new();
+        for n in self.net_fds.iter().flatten() {
+            assert_eq!(
+                n.num_fds,
+                n.fds.as_ref().map_or(0, |f| f.len()),
+                "Invalid 'RestoredNetConfig' with conflicted fields."
+            );
+            if restored_net_with_fds.insert(n.id.clone(), n).is_some() {
+                return Err(ValidationError::IdentifierNotUnique(n.id.clone()));
             }
         }
 
-        Ok(id_list)
-    }
-
-    pub fn parse(vm_params: VmParams) -> Result<Self> {
-        let mut rate_limit_groups: Option<Vec<RateLimiterGroupConfig>> = None;
-        if let Some(rate_limit_group_list) = &vm_params.rate_limit_groups {
-            let mut rate_limit_group_config_list = Vec::new();
-            for item in rate_limit_group_list.iter() {
-                let rate_limit_group_config = RateLimiterGroupConfig::parse(item)?;
-                rate_limit_group_config_list.push(rate_limit_group_config);
+        for net_fds in vm_config.net.iter().flatten() {
+            if let Some(expected_fds) = &net_fds.fds {
+                let expected_id = net_fds
+                    .id
+                    .as_ref()
+                    .expect("Invalid 'NetConfig' with empty 'id' for VM restore.");
+                if let Some(r) = restored_net_with_fds.remove(expected_id) {
+                    if r.num_fds != expected_fds.len() {
+                        return Err(ValidationError::RestoreNetFdCountMismatch(
+                            expected_id.clone(),
+                            r.num_fds,
+                            expected_fds.len(),
+                        ));
+                    }
+                } else {
+                    return Err(ValidationError::RestoreMissingRequiredNetId(
+                        expected_id.clone(),
+                    ));
+                }
             }
-            rate_limit_groups = Some(rate_limit_group_config_list);
         }
 
-        let mut disks: Option<Vec<DiskConfig>> = None;
-        if let Some(disk_list) = &vm_params.disks {
-            let mut disk_config_list = Vec::new();
-            for item in disk_list.iter() {
-                let disk_config = DiskConfig::parse(item)?;
-                disk_config_list.push(disk_config);
-            }
-            disks = Some(disk_config_list);
+        if !restored_net_with_fds.is_empty() {
+            warn!("Ignoring unused 'net_fds' for VM restore.")
         }
 
-        let mut net: Option<Vec<NetConfig>> = None;
-        if let Some(net_list) = &vm_params.net {
-            let mut net_config_list = Vec::new();
-            for item in net_list.iter() {
-                let net_config = NetConfig::parse(item)?;
-                net_config_list.push(net_config);
+        let mut restored_ids = BTreeSet::new();
+        for id in self.disk_paths.iter().flatten().map(|d| &d.id) {
+            if !restored_ids.insert(id) {
+                return Err(ValidationError::IdentifierNotUnique(id.clone()));
             }
-            net = Some(net_config_list);
         }
-
-        let rng = RngConfig::parse(vm_params.rng)?;
-
-        let mut balloon: Option<BalloonConfig> = None;
-        if let Some(balloon_params) = &vm_params.balloon {
-            balloon = Some(BalloonConfig::parse(balloon_params)?);
+        restored_ids.clear();
+        for id in self.net_taps.iter().flatten().map(|n| &n.id) {
+            if !restored_ids.insert(id) {
+                return Err(ValidationError::IdentifierNotUnique(id.clone()));
+            }
         }
 
-        #[cfg(feature = "pvmemcontrol")]
-        let pvmemcontrol: Option<PvmemcontrolConfig> = vm_params
-            .pvmemcontrol
-            .then_some(PvmemcontrolConfig::default());
+        Ok(())
+    }
+}
 
-        let mut fs: Option<Vec<FsConfig>> = None;
-        if let Some(fs_list) = &vm_params.fs {
-            let mut fs_config_list = Vec::new();
-            for item in fs_list.iter() {
-                fs_config_list.push(FsConfig::parse(item)?);
-            }
-            fs = Some(fs_config_list);
+impl TpmConfig {
+    pub const SYNTAX: &'static str = "TPM device \
+        \"(UNIX Domain Socket from swtpm) socket=</path/to/a/socket>\"";
+
+    pub fn parse(tpm: &str) -> Result<Self> {
+        let mut parser = OptionParser::new();
+        parser.add("socket");
+        parser.parse(tpm).map_err(Error::ParseTpm)?;
+        let socket = parser
+            .get("socket")
+            .map(PathBuf::from)
+            .ok_or(Error::ParseTpmPathMissing)?;
+        Ok(TpmConfig { socket })
+    }
+}
+
+impl LandlockConfig {
+    pub const SYNTAX: &'static str = "Landlock parameters \
+        \"path=<path/to/{file/dir}>,access=[rw]\"";
+
+    pub fn parse(landlock_rule: &str) -> Result<Self> {
+        let mut parser = OptionParser::new();
+        parser.add("path").add("access");
+        parser
+            .parse(landlock_rule)
+            .map_err(Error::ParseLandlockRules)?;
+
+        let path = parser
+            .get("path")
+            .map(PathBuf::from)
+            .ok_or(Error::ParseLandlockMissingFields)?;
+
+        let access = parser
+            .get("access")
+            .ok_or(Error::ParseLandlockMissingFields)?;
+
+        if access.chars().count() > 2 {
+            return Err(Error::ParseLandlockRules(OptionParserError::InvalidValue(
+                access.to_string(),
+            )));
         }
 
-        let mut pmem: Option<Vec<PmemConfig>> = None;
-        if let Some(pmem_list) = &vm_params.pmem {
-            let mut pmem_config_list = Vec::new();
-            for item in pmem_list.iter() {
-                let pmem_config = PmemConfig::parse(item)?;
-                pmem_config_list.push(pmem_config);
-            }
-            pmem = Some(pmem_config_list);
+        Ok(LandlockConfig { path, access })
+    }
+
+    pub fn validate(&self) -> ValidationResult<()> {
+        if !self.path.exists() {
+            return Err(ValidationError::LandlockPathDoesNotExist(self.path.clone()));
         }
+        LandlockAccess::try_from(self.access.as_str())
+            .map_err(|e| ValidationError::InvalidLandlockAccess(e.to_string()))?;
+        Ok(())
+    }
+}
 
-        let console = ConsoleConfig::parse(vm_params.console)?;
-        let serial = ConsoleConfig::parse(vm_params.serial)?;
-        #[cfg(target_arch = "x86_64")]
-        let debug_console = DebugConsoleConfig::parse(vm_params.debug_console)?;
+// Payload components are given either as a path, or as a file descriptor
+// inherited from the parent process through the fd=<fd> syntax.
+fn parse_payload_file(value: Option<&str>) -> Result<(Option<PathBuf>, Option<i32>)> {
+    let Some(value) = value else {
+        return Ok((None, None));
+    };
+
+    if let Some(fd) = value.strip_prefix("fd=") {
+        let fd = fd
+            .parse()
+            .map_err(|_| Error::ParsePayloadFd(value.to_string()))?;
+        Ok((None, Some(fd)))
+    } else {
+        Ok((Some(PathBuf::from(value)), None))
+    }
+}
 
-        let mut devices: Option<Vec<DeviceConfig>> = None;
-        if let Some(device_list) = &vm_params.devices {
-            let mut device_config_list = Vec::new();
-            for item in device_list.iter() {
-                let device_config = DeviceConfig::parse(item)?;
-                device_config_list.push(device_config);
+impl VmConfig {
+    fn validate_identifier(
+        id_list: &mut BTreeSet<String>,
+        id: &Option<String>,
+    ) -> ValidationResult<()> {
+        if let Some(id) = id.as_ref() {
+            if id.starts_with("__") {
+                return Err(ValidationError::InvalidIdentifier(id.clone()));
             }
-            devices = Some(device_config_list);
-        }
 
-        let mut user_devices: Option<Vec<UserDeviceConfig>> = None;
-        if let Some(user_device_list) = &vm_params.user_devices {
-            let mut user_device_config_list = Vec::new();
-            for item in user_device_list.iter() {
-                let user_device_config = UserDeviceConfig::parse(item)?;
-                user_device_config_list.push(user_device_config);
+            if !id_list.insert(id.clone()) {
+                return Err(ValidationError::IdentifierNotUnique(id.clone()));
             }
-            user_devices = Some(user_device_config_list);
         }
 
-        let mut vdpa: Option<Vec<VdpaConfig>> = None;
-        if let Some(vdpa_list) = &vm_params.vdpa {
-            let mut vdpa_config_list = Vec::new();
-            for item in vdpa_list.iter() {
-                let vdpa_config = VdpaConfig::parse(item)?;
-                vdpa_config_list.push(vdpa_config);
+        Ok(())
+    }
+
+    pub fn backed_by_shared_memory(&self) -> bool {
+        if self.memory.shared || self.memory.hugepages {
+            return true;
+        }
+
+        if self.memory.size == 0 {
+            for zone in self.memory.zones.as_ref().unwrap() {
+                if !zone.shared && !zone.hugepages {
+                    return false;
+                }
             }
-            vdpa = Some(vdpa_config_list);
+            true
+        } else {
+            false
         }
+    }
 
-        let mut vsock: Option<VsockConfig> = None;
-        if let Some(vs) = &vm_params.vsock {
-            let vsock_config = VsockConfig::parse(vs)?;
-            vsock = Some(vsock_config);
+    // Also enables virtio-iommu if the config needs it
+    // Returns the list of unique identifiers provided through the
+    // configuration.
+    pub fn validate(&mut self) -> ValidationResult<BTreeSet<String>> {
+        let mut id_list = BTreeSet::new();
+
+        let payload = self
+            .payload
+            .as_ref()
+            .ok_or(ValidationError::KernelMissing)?;
+
+        if !payload.has_initramfs() && payload.extra_initramfs.is_some() {
+            return Err(ValidationError::ExtraInitramfsWithoutInitramfs);
         }
 
-        let mut pci_segments: Option<Vec<PciSegmentConfig>> = None;
-        if let Some(pci_segment_list) = &vm_params.pci_segments {
-            let mut pci_segment_config_list = Vec::new();
-            for item in pci_segment_list.iter() {
-                let pci_segment_config = PciSegmentConfig::parse(item)?;
-                pci_segment_config_list.push(pci_segment_config);
+        for (name, path, fd) in [
+            (
+                "firmware",
+                payload.firmware.is_some(),
+                payload.firmware_fd.is_some(),
+            ),
+            (
+                "kernel",
+                payload.kernel.is_some(),
+                payload.kernel_fd.is_some(),
+            ),
+            (
+                "initramfs",
+                payload.initramfs.is_some(),
+                payload.initramfs_fd.is_some(),
+            ),
+            #[cfg(feature = "igvm")]
+            ("igvm", payload.igvm.is_some(), payload.igvm_fd.is_some()),
+        ] {
+            if path && fd {
+                return Err(ValidationError::PayloadPathAndFd(name.to_string()));
             }
-            pci_segments = Some(pci_segment_config_list);
         }
 
-        let platform = vm_params.platform.map(PlatformConfig::parse).transpose()?;
+        if let Some(fallback_firmware) = &payload.fallback_firmware {
+            if payload.firmware.is_none() && payload.firmware_fd.is_none() {
+                return Err(ValidationError::FallbackFirmwareWithoutFirmware);
+            }
+            fallback_firmware.validate()?;
+        }
 
-        #[cfg(target_arch = "x86_64")]
-        let mut sgx_epc: Option<Vec<SgxEpcConfig>> = None;
-        #[cfg(target_arch = "x86_64")]
+        #[cfg(feature = "tdx")]
         {
-            if let Some(sgx_epc_list) = &vm_params.sgx_epc {
-                warn!("SGX support is deprecated and will be removed in a future release.");
-                let mut sgx_epc_config_list = Vec::new();
-                for item in sgx_epc_list.iter() {
-                    let sgx_epc_config = SgxEpcConfig::parse(item)?;
-                    sgx_epc_config_list.push(sgx_epc_config);
-                }
-                sgx_epc = Some(sgx_epc_config_list);
+            let tdx_enabled = self.platform.as_ref().map(|p| p.tdx).unwrap_or(false);
+            if tdx_enabled && payload.firmware.is_none() && payload.firmware_fd.is_none() {
+                return Err(ValidationError::TdxFirmwareMissing);
+            }
+            if tdx_enabled && (self.cpus.max_vcpus != self.cpus.boot_vcpus) {
+                return Err(ValidationError::TdxNoCpuHotplug);
             }
         }
 
-        let mut numa: Option<Vec<NumaConfig>> = None;
-        if let Some(numa_list) = &vm_params.numa {
-            let mut numa_config_list = Vec::new();
-            for item in numa_list.iter() {
-                let numa_config = NumaConfig::parse(item)?;
-                numa_config_list.push(numa_config);
+        #[cfg(feature = "sev_snp")]
+        {
+            let host_data_opt = &self.payload.as_ref().unwrap().host_data;
+
+            if let Some(host_data) = host_data_opt {
+                if host_data.len() != 64 {
+                    return Err(ValidationError::InvalidHostData);
+                }
             }
-            numa = Some(numa_config_list);
-        }
 
-        #[cfg(not(feature = "igvm"))]
-        let payload_present = vm_params.kernel.is_some() || vm_params.firmware.is_some();
+            if payload.id_auth.is_some() && payload.id_block.is_none() {
+                return Err(ValidationError::SevSnpIdAuthWithoutIdBlock);
+            }
 
-        #[cfg(feature = "igvm")]
-        let payload_present =
-            vm_params.kernel.is_some() || vm_params.firmware.is_some() || vm_params.igvm.is_some();
+            let custom_policy = self
+                .platform
+                .as_ref()
+                .map(|p| !p.sev_snp_smt || p.sev_snp_debug || p.sev_snp_migrate_ma)
+                .unwrap_or(false);
+            if !self.is_sev_snp_enabled()
+                && (custom_policy || payload.id_block.is_some() || payload.snp_certs.is_some())
+            {
+                return Err(ValidationError::SevSnpOptionsWithoutSevSnp);
+            }
+        }
+        // The 'conflict' check is introduced in commit 24438e0390d3
+        // (vm-virtio: Enable the vmm support for virtio-console).
+        //
+        // Allow simultaneously set serial and console as TTY mode, for
+        // someone want to use virtio console for better performance, and
+        // want to keep legacy serial to catch boot stage logs for debug.
+        // Using such double tty mode, you need to configure the kernel
+        // properly, such as:
+        // "console=hvc0 earlyprintk=ttyS0"
 
-        let payload = if payload_present {
-            Some(PayloadConfig {
-                kernel: vm_params.kernel.map(PathBuf::from),
-                initramfs: vm_params.initramfs.map(PathBuf::from),
-                cmdline: vm_params.cmdline.map(|s| s.to_string()),
-                firmware: vm_params.firmware.map(PathBuf::from),
-                #[cfg(feature = "igvm")]
-                igvm: vm_params.igvm.map(PathBuf::from),
-                #[cfg(feature = "sev_snp")]
-                host_data: vm_params.host_data.map(|s| s.to_string()),
-            })
-        } else {
-            None
+        let mut tty_consoles = Vec::new();
+        if self.console.mode == ConsoleOutputMode::Tty {
+            tty_consoles.push("virtio-console");
+        };
+        if self.serial.mode == ConsoleOutputMode::Tty {
+            tty_consoles.push("serial-console");
         };
+        #[cfg(target_arch = "x86_64")]
+        if self.debug_console.mode == ConsoleOutputMode::Tty {
+            tty_consoles.push("debug-console");
+        };
+        if tty_consoles.len() > 1 {
+            warn!("Using TTY output for multiple consoles: {:?}", tty_consoles);
+        }
 
-        let mut tpm: Option<TpmConfig> = None;
-        if let Some(tc) = vm_params.tpm {
-            let tpm_conf = TpmConfig::parse(tc)?;
-            tpm = Some(TpmConfig {
-                socket: tpm_conf.socket,
-            });
+        if self.console.mode == ConsoleOutputMode::File && self.console.file.is_none() {
+            return Err(ValidationError::ConsoleFileMissing);
         }
 
-        #[cfg(feature = "guest_debug")]
-        let gdb = vm_params.gdb;
+        if self.serial.mode == ConsoleOutputMode::File && self.serial.file.is_none() {
+            return Err(ValidationError::ConsoleFileMissing);
+        }
 
-        let mut landlock_rules: Option<Vec<LandlockConfig>> = None;
-        if let Some(ll_rules) = vm_params.landlock_rules {
-            landlock_rules = Some(
-                ll_rules
-                    .iter()
-                    .map(|rule| LandlockConfig::parse(rule))
-                    .collect::<Result<Vec<LandlockConfig>>>()?,
-            );
+        self.console.validate()?;
+        self.serial.validate()?;
+
+        if self.cpus.max_vcpus < self.cpus.boot_vcpus {
+            return Err(ValidationError::CpusMaxLowerThanBoot);
         }
 
-        let mut config = VmConfig {
-            cpus: CpusConfig::parse(vm_params.cpus)?,
-            memory: MemoryConfig::parse(vm_params.memory, vm_params.memory_zones)?,
-            payload,
-            rate_limit_groups,
-            disks,
-            net,
-            rng,
-            balloon,
-            fs,
-            pmem,
-            serial,
-            console,
-            #[cfg(target_arch = "x86_64")]
-            debug_console,
-            devices,
-            user_devices,
-            vdpa,
-            vsock,
-            #[cfg(feature = "pvmemcontrol")]
-            pvmemcontrol,
-            pvpanic: vm_params.pvpanic,
-            iommu: false, // updated in VmConfig::validate()
-            #[cfg(target_arch = "x86_64")]
-            sgx_epc,
-            numa,
-            watchdog: vm_params.watchdog,
-            #[cfg(feature = "guest_debug")]
-            gdb,
-            pci_segments,
-            platform,
-            tpm,
-            preserved_fds: None,
-            landlock_enable: vm_params.landlock_enable,
-            landlock_rules,
-        };
-        config.validate().map_err(Error::Validation)?;
-        Ok(config)
-    }
+        #[cfg(not(target_arch = "x86_64"))]
+        if self.cpus.max_vcpus > MAX_VCPUS {
+            return Err(ValidationError::CpusMaxTooHigh(self.cpus.max_vcpus));
+        }
 
-    pub fn remove_device(&mut self, id: &str) -> bool {
-        let mut removed = false;
+        if let Some(rate_limit_groups) = &self.rate_limit_groups {
+            for rate_limit_group in rate_limit_groups {
+                rate_limit_group.validate(self)?;
 
-        // Remove if VFIO device
-        if let Some(devices) = self.devices.as_mut() {
-            let len = devices.len();
-            devices.retain(|dev| dev.id.as_ref().map(|id| id.as_ref()) != Some(id));
-            removed |= devices.len() != len;
+                Self::validate_identifier(&mut id_list, &Some(rate_limit_group.id.clone()))?;
+            }
         }
 
-        // Remove if VFIO user device
-        if let Some(user_devices) = self.user_devices.as_mut() {
-            let len = user_devices.len();
-            user_devices.retain(|dev| dev.id.as_ref().map(|id| id.as_ref()) != Some(id));
-            removed |= user_devices.len() != len;
-        }
+        if let Some(disks) = &self.disks {
+            for disk in disks {
+                if disk.vhost_socket.as_ref().and(disk.path.as_ref()).is_some() {
+                    return Err(ValidationError::DiskSocketAndPath);
+                }
+                if disk.vhost_user && !self.backed_by_shared_memory() {
+                    return Err(ValidationError::VhostUserRequiresSharedMemory);
+                }
+                if disk.vhost_user && disk.vhost_socket.is_none() {
+                    return Err(ValidationError::VhostUserMissingSocket);
+                }
+                if let Some(rate_limit_group) = &disk.rate_limit_group {
+                    if let Some(rate_limit_groups) = &self.rate_limit_groups {
+                        if !rate_limit_groups
+                            .iter()
+                            .any(|cfg| &cfg.id == rate_limit_group)
+                        {
+                            return Err(ValidationError::InvalidRateLimiterGroup);
+                        }
+                    } else {
+                        return Err(ValidationError::InvalidRateLimiterGroup);
+                    }
+                }
 
-        // Remove if disk device
-        if let Some(disks) = self.disks.as_mut() {
-            let len = disks.len();
-            disks.retain(|dev| dev.id.as_ref().map(|id| id.as_ref()) != Some(id));
-            removed |= disks.len() != len;
-        }
+                disk.validate(self)?;
+                self.iommu |= disk.iommu;
 
-        // Remove if fs device
-        if let Some(fs) = self.fs.as_mut() {
-            let len = fs.len();
-            fs.retain(|dev| dev.id.as_ref().map(|id| id.as_ref()) != Some(id));
-            removed |= fs.len() != len;
+                Self::validate_identifier(&mut id_list, &disk.id)?;
+            }
         }
 
-        // Remove if net device
-        if let Some(net) = self.net.as_mut() {
-            let len = net.len();
-            net.retain(|dev| dev.id.as_ref().map(|id| id.as_ref()) != Some(id));
-            removed |= net.len() != len;
-        }
+        if let Some(nets) = &self.net {
+            for net in nets {
+                if net.vhost_user && !self.backed_by_shared_memory() {
+                    return Err(ValidationError::VhostUserRequiresSharedMemory);
+                }
+                if let Some(rate_limit_group) = &net.rate_limit_group {
+                    if let Some(rate_limit_groups) = &self.rate_limit_groups {
+                        if !rate_limit_groups
+                            .iter()
+                            .any(|cfg| &cfg.id == rate_limit_group)
+                        {
+                            return Err(ValidationError::InvalidRateLimiterGroup);
+                        }
+                    } else {
+                        return Err(ValidationError::InvalidRateLimiterGroup);
+                    }
+                }
+                net.validate(self)?;
+                self.iommu |= net.iommu;
 
-        // Remove if pmem device
-        if let Some(pmem) = self.pmem.as_mut() {
-            let len = pmem.len();
-            pmem.retain(|dev| dev.id.as_ref().map(|id| id.as_ref()) != Some(id));
-            removed |= pmem.len() != len;
+                Self::validate_identifier(&mut id_list, &net.id)?;
+            }
         }
 
-        // Remove if vDPA device
-        if let Some(vdpa) = self.vdpa.as_mut() {
-            let len = vdpa.len();
-            vdpa.retain(|dev| dev.id.as_ref().map(|id| id.as_ref()) != Some(id));
-            removed |= vdpa.len() != len;
+        if let Some(fses) = &self.fs {
+            if !fses.is_empty() && !self.backed_by_shared_memory() {
+                return Err(ValidationError::VhostUserRequiresSharedMemory);
+            }
+            for fs in fses {
+                fs.validate(self)?;
+
+                Self::validate_identifier(&mut id_list, &fs.id)?;
+            }
         }
 
-        // Remove if vsock device
-        if let Some(vsock) = self.vsock.as_ref() {
-            if vsock.id.as_ref().map(|id| id.as_ref()) == Some(id) {
-                self.vsock = None;
-                removed = true;
+        if let Some(pmems) = &self.pmem {
+            for pmem in pmems {
+                pmem.validate(self)?;
+                self.iommu |= pmem.iommu;
+
+                Self::validate_identifier(&mut id_list, &pmem.id)?;
             }
         }
 
-        removed
-    }
+        self.iommu |= self.rng.iommu;
+        self.iommu |= self.console.iommu;
 
-    /// # Safety
-    /// To use this safely, the caller must guarantee that the input
-    /// fds are all valid.
-    pub unsafe fn add_preserved_fds(&mut self, mut fds: Vec<i32>) {
-        if fds.is_empty() {
-            return;
+        if let Some(scmi) = &self.scmi {
+            scmi.validate()?;
+            self.iommu |= scmi.iommu;
         }
 
-        if let Some(preserved_fds) = &self.preserved_fds {
-            fds.append(&mut preserved_fds.clone());
+        if let Some(cloud_init) = &self.cloud_init {
+            cloud_init.validate()?;
         }
 
-        self.preserved_fds = Some(fds);
-    }
+        if let Some(netns) = &self.netns {
+            netns.validate()?;
+        }
 
-    #[cfg(feature = "tdx")]
-    pub fn is_tdx_enabled(&self) -> bool {
-        self.platform.as_ref().map(|p| p.tdx).unwrap_or(false)
-    }
+        if let Some(io_threads) = &self.io_threads {
+            io_threads.validate()?;
+        }
 
-    #[cfg(feature = "sev_snp")]
-    pub fn is_sev_snp_enabled(&self) -> bool {
-        self.platform.as_ref().map(|p| p.sev_snp).unwrap_or(false)
-    }
-}
+        if self.crash_dump.is_some() && !self.pvpanic {
+            return Err(ValidationError::CrashDumpWithoutPvpanic);
+        }
 
-impl Clone for VmConfig {
-    fn clone(&self) -> Self {
-        VmConfig {
-            cpus: self.cpus.clone(),
-            memory: self.memory.clone(),
-            payload: self.payload.clone(),
-            rate_limit_groups: self.rate_limit_groups.clone(),
-            disks: self.disks.clone(),
-            net: self.net.clone(),
-            rng: self.rng.clone(),
-            balloon: self.balloon.clone(),
-            #[cfg(feature = "pvmemcontrol")]
-            pvmemcontrol: self.pvmemcontrol.clone(),
-            fs: self.fs.clone(),
-            pmem: self.pmem.clone(),
-            serial: self.serial.clone(),
-            console: self.console.clone(),
-            #[cfg(target_arch = "x86_64")]
-            debug_console: self.debug_console.clone(),
-            devices: self.devices.clone(),
-            user_devices: self.user_devices.clone(),
-            vdpa: self.vdpa.clone(),
-            vsock: self.vsock.clone(),
-            #[cfg(target_arch = "x86_64")]
-            sgx_epc: self.sgx_epc.clone(),
-            numa: self.numa.clone(),
-            pci_segments: self.pci_segments.clone(),
-            platform: self.platform.clone(),
-            tpm: self.tpm.clone(),
-            preserved_fds: self
-                .preserved_fds
-                .as_ref()
-                // SAFETY: FFI call with valid FDs
-                .map(|fds| fds.iter().map(|fd| unsafe { libc::dup(*fd) }).collect()),
-            landlock_rules: self.landlock_rules.clone(),
-            ..*self
+        if let Some(t) = &self.cpus.topology {
+            if t.threads_per_core == 0
+                || t.cores_per_die == 0
+                || t.dies_per_package == 0
+                || t.packages == 0
+            {
+                return Err(ValidationError::CpuTopologyZeroPart);
+            }
+
+            // The setting of dies doesn't apply on AArch64.
+            // Only '1' value is accepted, so its impact on the vcpu topology
+            // setting can be ignored.
+            #[cfg(target_arch = "aarch64")]
+            if t.dies_per_package != 1 {
+                return Err(ValidationError::CpuTopologyDiesPerPackage);
+            }
+
+            let total = u32::from(t.threads_per_core)
+                * u32::from(t.cores_per_die)
+                * u32::from(t.dies_per_package)
+                * u32::from(t.packages);
+            if total != self.cpus.max_vcpus {
+                return Err(ValidationError::CpuTopologyCount);
+            }
         }
-    }
-}
 
-impl Drop for VmConfig {
-    fn drop(&mut self) {
-        if let Some(mut fds) = self.preserved_fds.take() {
-            for fd in fds.drain(..) {
-                // SAFETY: FFI call with valid FDs
-                unsafe { libc::close(fd) };
+        if let Some(hugepage_size) = &self.memory.hugepage_size {
+            if !self.memory.hugepages {
+                return Err(ValidationError::HugePageSizeWithoutHugePages);
+            }
+            if !hugepage_size.is_power_of_two() {
+                return Err(ValidationError::InvalidHugePageSize(*hugepage_size));
             }
         }
-    }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::fs::File;
-    use std::net::{IpAddr, Ipv4Addr};
-    use std::os::unix::io::AsRawFd;
+        if self.memory.thp_collapse && !self.memory.thp {
+            return Err(ValidationError::ThpCollapseWithoutThp);
+        }
 
-    use net_util::MacAddr;
+        if let Some(user_devices) = &self.user_devices {
+            if !user_devices.is_empty() && !self.backed_by_shared_memory() {
+                return Err(ValidationError::UserDevicesRequireSharedMemory);
+            }
 
-    use super::*;
+            for user_device in user_devices {
+                user_device.validate(self)?;
 
-    #[test]
-    fn test_cpu_parsing() -> Result<()> {
-        assert_eq!(CpusConfig::parse("")?, CpusConfig::default());
+                Self::validate_identifier(&mut id_list, &user_device.id)?;
+            }
+        }
 
-        assert_eq!(
-            CpusConfig::parse("boot=1")?,
-            CpusConfig {
+        if let Some(vdpa_devices) = &self.vdpa {
+            for vdpa_device in vdpa_devices {
+                vdpa_device.validate(self)?;
+                self.iommu |= vdpa_device.iommu;
+
+                Self::validate_identifier(&mut id_list, &vdpa_device.id)?;
+            }
+        }
+
+        if let Some(vsock) = &self.vsock {
+            if [!0, 0, 1, 2].contains(&vsock.cid) {
+                return Err(ValidationError::VsockSpecialCid(vsock.cid));
+            }
+        }
+
+        if let Some(balloon) = &self.balloon {
+            let mut ram_size = self.memory.size;
+
+            if let Some(zones) = &self.memory.zones {
+                for zone in zones {
+                    ram_size += zone.size;
+                }
+            }
+
+            if balloon.size >= ram_size {
+                return Err(ValidationError::BalloonLargerThanRam(
+                    balloon.size,
+                    ram_size,
+                ));
+            }
+
+            if balloon.auto_resize {
+                let max_size = balloon
+                    .max_size
+                    .ok_or(ValidationError::BalloonAutoResizeWithoutMaxSize)?;
+
+                if balloon.min_size > max_size {
+                    return Err(ValidationError::BalloonInvalidSizeRange(
+                        balloon.min_size,
+                        max_size,
+                    ));
+                }
+
+                if max_size >= ram_size {
+                    return Err(ValidationError::BalloonLargerThanRam(max_size, ram_size));
+                }
+
+                if balloon.pressure_low >= balloon.pressure_high || balloon.pressure_high > 100 {
+                    return Err(ValidationError::BalloonInvalidPressure(
+                        balloon.pressure_low,
+                        balloon.pressure_high,
+                    ));
+                }
+            }
+        }
+
+        if let Some(devices) = &self.devices {
+            let mut device_paths = BTreeSet::new();
+            let mut sriov_vfs = BTreeSet::new();
+            for device in devices {
+                // The path of a virtual function is the one it was given
+                // when last created, if any.
+                if let Some(sriov) = &device.sriov {
+                    if !sriov_vfs.insert((sriov.pf.as_str(), sriov.vf)) {
+                        return Err(ValidationError::DuplicateSriovVf(
+                            sriov.pf.clone(),
+                            sriov.vf,
+                        ));
+                    }
+                } else if !device_paths.insert(device.path.to_string_lossy()) {
+                    return Err(ValidationError::DuplicateDevicePath(
+                        device.path.to_string_lossy().to_string(),
+                    ));
+                }
+
+                device.validate(self)?;
+                self.iommu |= device.iommu;
+
+                Self::validate_identifier(&mut id_list, &device.id)?;
+            }
+
+            // The legacy VGA ranges can only be routed to a single device.
+            if devices.iter().filter(|d| d.legacy_vga).count() > 1 {
+                return Err(ValidationError::MultipleLegacyVga);
+            }
+
+            // P2P between devices of a clique requires their BARs to be
+            // reachable from the same IOMMU domain.
+            let mut cliques = HashMap::new();
+            for device in devices {
+                if let Some(clique) = device.x_nv_gpudirect_clique {
+                    if *cliques.entry(clique).or_insert(device.iommu) != device.iommu {
+                        return Err(ValidationError::GpuDirectCliqueIommuMismatch(clique));
+                    }
+                }
+            }
+        }
+
+        if let Some(vsock) = &self.vsock {
+            vsock.validate(self)?;
+            self.iommu |= vsock.iommu;
+
+            Self::validate_identifier(&mut id_list, &vsock.id)?;
+        }
+
+        let max_pci_segments = self.max_pci_segments();
+        if let Some(numa) = &self.numa {
+            let mut used_numa_node_memory_zones = HashMap::new();
+            let mut used_pci_segments = HashMap::new();
+            #[cfg(target_arch = "x86_64")]
+            let mut used_sgx_epc_sections = HashMap::new();
+            for numa_node in numa.iter() {
+                if let Some(memory_zones) = numa_node.memory_zones.clone() {
+                    for memory_zone in memory_zones.iter() {
+                        if !used_numa_node_memory_zones.contains_key(memory_zone) {
+                            used_numa_node_memory_zones
+                                .insert(memory_zone.to_string(), numa_node.guest_numa_id);
+                        } else {
+                            return Err(ValidationError::MemoryZoneReused(
+                                memory_zone.to_string(),
+                                *used_numa_node_memory_zones.get(memory_zone).unwrap(),
+                                numa_node.guest_numa_id,
+                            ));
+                        }
+                    }
+                }
+
+                if let Some(pci_segments) = numa_node.pci_segments.clone() {
+                    for pci_segment in pci_segments.iter() {
+                        if *pci_segment >= max_pci_segments {
+                            return Err(ValidationError::InvalidPciSegment(*pci_segment));
+                        }
+                        if *pci_segment == 0 && numa_node.guest_numa_id != 0 {
+                            return Err(ValidationError::DefaultPciSegmentInvalidNode(
+                                numa_node.guest_numa_id,
+                            ));
+                        }
+                        if !used_pci_segments.contains_key(pci_segment) {
+                            used_pci_segments.insert(*pci_segment, numa_node.guest_numa_id);
+                        } else {
+                            return Err(ValidationError::PciSegmentReused(
+                                *pci_segment,
+                                *used_pci_segments.get(pci_segment).unwrap(),
+                                numa_node.guest_numa_id,
+                            ));
+                        }
+                    }
+                }
+
+                #[cfg(target_arch = "x86_64")]
+                if let Some(sgx_epc_sections) = &numa_node.sgx_epc_sections {
+                    for sgx_epc_section in sgx_epc_sections.iter() {
+                        if !self
+                            .sgx_epc
+                            .iter()
+                            .flatten()
+                            .any(|sgx_epc| &sgx_epc.id == sgx_epc_section)
+                        {
+                            return Err(ValidationError::UnknownSgxEpcSection(
+                                sgx_epc_section.to_string(),
+                            ));
+                        }
+                        if let Some(guest_numa_id) = used_sgx_epc_sections
+                            .insert(sgx_epc_section.to_string(), numa_node.guest_numa_id)
+                        {
+                            return Err(ValidationError::SgxEpcSectionReused(
+                                sgx_epc_section.to_string(),
+                                guest_numa_id,
+                                numa_node.guest_numa_id,
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(zones) = &self.memory.zones {
+            for zone in zones.iter() {
+                let id = zone.id.clone();
+                Self::validate_identifier(&mut id_list, &Some(id))?;
+            }
+        }
+
+        #[cfg(target_arch = "x86_64")]
+        if let Some(sgx_epcs) = &self.sgx_epc {
+            for sgx_epc in sgx_epcs.iter() {
+                let id = sgx_epc.id.clone();
+                Self::validate_identifier(&mut id_list, &Some(id))?;
+            }
+        }
+
+        if let Some(pci_segments) = &self.pci_segments {
+            for pci_segment in pci_segments {
+                pci_segment.validate(self)?;
+            }
+        }
+
+        self.platform.as_ref().map(|p| p.validate()).transpose()?;
+        #[cfg(target_arch = "x86_64")]
+        if self.is_vmbus_enabled() && !self.cpus.kvm_hyperv {
+            return Err(ValidationError::VmBusRequiresKvmHyperv);
+        }
+        #[cfg(target_arch = "aarch64")]
+        match self.boot_method() {
+            // Without ACPI tables there is no way to notify the guest of
+            // hotplugged CPUs or memory.
+            BootMethod::DeviceTree
+                if self.cpus.max_vcpus > self.cpus.boot_vcpus
+                    || self.memory.hotplug_size.is_some()
+                    || self
+                        .memory
+                        .zones
+                        .iter()
+                        .flatten()
+                        .any(|z| z.hotplug_size.is_some()) =>
+            {
+                return Err(ValidationError::BootMethodFdtHotplug);
+            }
+            // ACPI is forced through the kernel command line, which the
+            // firmware doesn't get.
+            BootMethod::Acpi
+                if self
+                    .payload
+                    .as_ref()
+                    .is_some_and(|p| p.firmware.is_some() || p.firmware_fd.is_some()) =>
+            {
+                return Err(ValidationError::BootMethodAcpiFirmware);
+            }
+            _ => {}
+        }
+        self.iommu |= self
+            .platform
+            .as_ref()
+            .map(|p| p.iommu_segments.is_some())
+            .unwrap_or_default();
+
+        if let Some(landlock_rules) = &self.landlock_rules {
+            for landlock_rule in landlock_rules {
+                landlock_rule.validate()?;
+            }
+        }
+
+        Ok(id_list)
+    }
+
+    /// Checks the files the VM relies on exist on the host, returning all
+    /// the missing ones, as opposed to [`VmConfig::validate`] which only
+    /// checks the consistency of the configuration.
+    pub fn validate_host(&self) -> Vec<ValidationError> {
+        let mut files: Vec<(&'static str, &Path)> = Vec::new();
+
+        if let Some(payload) = &self.payload {
+            files.extend(payload.kernel.as_deref().map(|p| ("--kernel", p)));
+            files.extend(payload.firmware.as_deref().map(|p| ("--firmware", p)));
+            files.extend(payload.initramfs.as_deref().map(|p| ("--initramfs", p)));
+            for path in payload.extra_initramfs.iter().flatten() {
+                files.push(("--initramfs", path));
+            }
+            #[cfg(feature = "igvm")]
+            files.extend(payload.igvm.as_deref().map(|p| ("--igvm", p)));
+            if let Some(fallback) = &payload.fallback_firmware {
+                files.push(("--fallback-firmware", &fallback.path));
+            }
+        }
+
+        for disk in self.disks.iter().flatten().filter(|d| !d.vhost_user) {
+            files.extend(disk.path.as_deref().map(|p| ("--disk", p)));
+            files.extend(disk.key_file.as_deref().map(|p| ("--disk", p)));
+        }
+
+        for net in self.net.iter().flatten() {
+            files.extend(net.romfile.as_deref().map(|p| ("--net", p)));
+        }
+
+        for device in self.devices.iter().flatten() {
+            files.push(("--device", &device.path));
+            files.extend(device.romfile.as_deref().map(|p| ("--device", p)));
+        }
+
+        files.push(("--rng", &self.rng.src));
+
+        files
+            .into_iter()
+            .filter(|(_, path)| !path.exists())
+            .map(|(option, path)| ValidationError::HostFileMissing(option, path.to_path_buf()))
+            .collect()
+    }
+
+    pub fn parse(vm_params: VmParams) -> Result<Self> {
+        let mut config = Self::from_params(vm_params)?;
+        config.validate().map_err(Error::Validation)?;
+        Ok(config)
+    }
+
+    /// Deserializes a VM configuration from a JSON, YAML or TOML file, the
+    /// format being picked from the file extension and defaulting to JSON.
+    /// The returned configuration is not validated.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path).map_err(Error::ReadConfigFile)?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("yaml" | "yml") => {
+                serde_yaml::from_str(&content).map_err(|e| Error::ParseConfigFile(e.to_string()))
+            }
+            Some("toml") => {
+                toml::from_str(&content).map_err(|e| Error::ParseConfigFile(e.to_string()))
+            }
+            _ => serde_json::from_str(&content).map_err(|e| Error::ParseConfigFile(e.to_string())),
+        }
+    }
+
+    /// Builds the VM configuration from the file given through `--config`,
+    /// letting the options explicitly passed on the command line replace the
+    /// matching fields, then validates the result.
+    pub fn parse_with_file(path: &Path, vm_params: VmParams, args: &ArgMatches) -> Result<Self> {
+        let mut config = Self::from_file(path)?;
+        let mut cli = Self::from_params(vm_params)?;
+        let given = |id: &str| args.value_source(id) == Some(ValueSource::CommandLine);
+
+        macro_rules! override_field {
+            ($id:literal, $field:ident) => {
+                if given($id) {
+                    config.$field = cli.$field.clone();
+                }
+            };
+        }
+
+        override_field!("cpus", cpus);
+        if given("memory") {
+            let zones = config.memory.zones.take();
+            config.memory = cli.memory.clone();
+            config.memory.zones = zones;
+        }
+        if given("memory-zone") {
+            config.memory.zones = cli.memory.zones.take();
+        }
+
+        if let Some(mut cli_payload) = cli.payload.take() {
+            if let Some(payload) = config.payload.as_mut() {
+                if given("kernel") {
+                    payload.kernel = cli_payload.kernel.take();
+                    payload.kernel_fd = cli_payload.kernel_fd.take();
+                }
+                if given("firmware") {
+                    payload.firmware = cli_payload.firmware.take();
+                    payload.firmware_fd = cli_payload.firmware_fd.take();
+                }
+                if given("initramfs") {
+                    payload.initramfs = cli_payload.initramfs.take();
+                    payload.initramfs_fd = cli_payload.initramfs_fd.take();
+                    payload.extra_initramfs = cli_payload.extra_initramfs.take();
+                }
+                if given("cmdline") {
+                    payload.cmdline = cli_payload.cmdline.take();
+                }
+                if given("fallback-firmware") {
+                    payload.fallback_firmware = cli_payload.fallback_firmware.take();
+                }
+                #[cfg(feature = "igvm")]
+                if given("igvm") {
+                    payload.igvm = cli_payload.igvm.take();
+                    payload.igvm_fd = cli_payload.igvm_fd.take();
+                }
+                #[cfg(feature = "sev_snp")]
+                {
+                    if given("host-data") {
+                        payload.host_data = cli_payload.host_data.take();
+                    }
+                    if given("id-block") {
+                        payload.id_block = cli_payload.id_block.take();
+                    }
+                    if given("id-auth") {
+                        payload.id_auth = cli_payload.id_auth.take();
+                    }
+                    if given("snp-certs") {
+                        payload.snp_certs = cli_payload.snp_certs.take();
+                    }
+                }
+            } else {
+                config.payload = Some(cli_payload);
+            }
+        }
+
+        override_field!("rate-limit-group", rate_limit_groups);
+        override_field!("disk", disks);
+        override_field!("net", net);
+        override_field!("rng", rng);
+        override_field!("balloon", balloon);
+        override_field!("fs", fs);
+        override_field!("pmem", pmem);
+        override_field!("serial", serial);
+        override_field!("console", console);
+        #[cfg(target_arch = "x86_64")]
+        override_field!("debug-console", debug_console);
+        override_field!("device", devices);
+        override_field!("user-device", user_devices);
+        override_field!("vdpa", vdpa);
+        override_field!("vsock", vsock);
+        #[cfg(feature = "pvmemcontrol")]
+        override_field!("pvmemcontrol", pvmemcontrol);
+        override_field!("pvpanic", pvpanic);
+        #[cfg(target_arch = "x86_64")]
+        override_field!("sgx-epc", sgx_epc);
+        override_field!("numa", numa);
+        override_field!("watchdog", watchdog);
+        #[cfg(feature = "guest_debug")]
+        override_field!("gdb", gdb);
+        override_field!("pci-segment", pci_segments);
+        override_field!("platform", platform);
+        override_field!("tpm", tpm);
+        override_field!("scmi", scmi);
+        override_field!("cloud-init", cloud_init);
+        override_field!("imds", imds);
+        override_field!("netns", netns);
+        override_field!("guest-agent", guest_agent);
+        override_field!("resources", resources);
+        override_field!("rtc", rtc);
+        override_field!("restart-policy", restart_policy);
+        override_field!("crash-dump", crash_dump);
+        override_field!("io-threads", io_threads);
+        override_field!("landlock", landlock_enable);
+        override_field!("landlock-rules", landlock_rules);
+
+        // The payload FDs inherited through the command line now belong to
+        // the merged configuration.
+        config.preserved_fds = cli.preserved_fds.take();
+        config.validate().map_err(Error::Validation)?;
+        Ok(config)
+    }
+
+    fn from_params(vm_params: VmParams) -> Result<Self> {
+        let mut rate_limit_groups: Option<Vec<RateLimiterGroupConfig>> = None;
+        if let Some(rate_limit_group_list) = &vm_params.rate_limit_groups {
+            let mut rate_limit_group_config_list = Vec::new();
+            for item in rate_limit_group_list.iter() {
+                let rate_limit_group_config = RateLimiterGroupConfig::parse(item)?;
+                rate_limit_group_config_list.push(rate_limit_group_config);
+            }
+            rate_limit_groups = Some(rate_limit_group_config_list);
+        }
+
+        if let Some(rate_limit_schedule_list) = &vm_params.rate_limit_schedules {
+            for item in rate_limit_schedule_list.iter() {
+                let (group, schedule) = RateLimitScheduleConfig::parse(item)?;
+                let rate_limit_group = rate_limit_groups
+                    .iter_mut()
+                    .flatten()
+                    .find(|g| g.id == group)
+                    .ok_or(Error::ParseRateLimitScheduleUnknownGroup(group))?;
+                rate_limit_group
+                    .schedules
+                    .get_or_insert_with(Vec::new)
+                    .push(schedule);
+            }
+        }
+
+        let mut disks: Option<Vec<DiskConfig>> = None;
+        if let Some(disk_list) = &vm_params.disks {
+            let mut disk_config_list = Vec::new();
+            for item in disk_list.iter() {
+                let disk_config = DiskConfig::parse(item)?;
+                disk_config_list.push(disk_config);
+            }
+            disks = Some(disk_config_list);
+        }
+
+        let mut net: Option<Vec<NetConfig>> = None;
+        if let Some(net_list) = &vm_params.net {
+            let mut net_config_list = Vec::new();
+            for item in net_list.iter() {
+                let net_config = NetConfig::parse(item)?;
+                net_config_list.push(net_config);
+            }
+            net = Some(net_config_list);
+        }
+
+        let rng = RngConfig::parse(vm_params.rng)?;
+
+        let mut balloon: Option<BalloonConfig> = None;
+        if let Some(balloon_params) = &vm_params.balloon {
+            balloon = Some(BalloonConfig::parse(balloon_params)?);
+        }
+
+        #[cfg(feature = "pvmemcontrol")]
+        let pvmemcontrol: Option<PvmemcontrolConfig> = vm_params
+            .pvmemcontrol
+            .map(PvmemcontrolConfig::parse)
+            .transpose()?;
+
+        let mut fs: Option<Vec<FsConfig>> = None;
+        if let Some(fs_list) = &vm_params.fs {
+            let mut fs_config_list = Vec::new();
+            for item in fs_list.iter() {
+                fs_config_list.push(FsConfig::parse(item)?);
+            }
+            fs = Some(fs_config_list);
+        }
+
+        let mut pmem: Option<Vec<PmemConfig>> = None;
+        if let Some(pmem_list) = &vm_params.pmem {
+            let mut pmem_config_list = Vec::new();
+            for item in pmem_list.iter() {
+                let pmem_config = PmemConfig::parse(item)?;
+                pmem_config_list.push(pmem_config);
+            }
+            pmem = Some(pmem_config_list);
+        }
+
+        let console = ConsoleConfig::parse(vm_params.console)?;
+        let serial = ConsoleConfig::parse(vm_params.serial)?;
+        #[cfg(target_arch = "x86_64")]
+        let debug_console = DebugConsoleConfig::parse(vm_params.debug_console)?;
+
+        let mut devices: Option<Vec<DeviceConfig>> = None;
+        if let Some(device_list) = &vm_params.devices {
+            let mut device_config_list = Vec::new();
+            for item in device_list.iter() {
+                let device_config = DeviceConfig::parse(item)?;
+                device_config_list.push(device_config);
+            }
+            devices = Some(device_config_list);
+        }
+
+        let mut user_devices: Option<Vec<UserDeviceConfig>> = None;
+        if let Some(user_device_list) = &vm_params.user_devices {
+            let mut user_device_config_list = Vec::new();
+            for item in user_device_list.iter() {
+                let user_device_config = UserDeviceConfig::parse(item)?;
+                user_device_config_list.push(user_device_config);
+            }
+            user_devices = Some(user_device_config_list);
+        }
+
+        let mut vdpa: Option<Vec<VdpaConfig>> = None;
+        if let Some(vdpa_list) = &vm_params.vdpa {
+            let mut vdpa_config_list = Vec::new();
+            for item in vdpa_list.iter() {
+                let vdpa_config = VdpaConfig::parse(item)?;
+                vdpa_config_list.push(vdpa_config);
+            }
+            vdpa = Some(vdpa_config_list);
+        }
+
+        let mut vsock: Option<VsockConfig> = None;
+        if let Some(vs) = &vm_params.vsock {
+            let vsock_config = VsockConfig::parse(vs)?;
+            vsock = Some(vsock_config);
+        }
+
+        let mut pci_segments: Option<Vec<PciSegmentConfig>> = None;
+        if let Some(pci_segment_list) = &vm_params.pci_segments {
+            let mut pci_segment_config_list = Vec::new();
+            for item in pci_segment_list.iter() {
+                let pci_segment_config = PciSegmentConfig::parse(item)?;
+                pci_segment_config_list.push(pci_segment_config);
+            }
+            pci_segments = Some(pci_segment_config_list);
+        }
+
+        let platform = vm_params.platform.map(PlatformConfig::parse).transpose()?;
+
+        #[cfg(target_arch = "x86_64")]
+        let mut sgx_epc: Option<Vec<SgxEpcConfig>> = None;
+        #[cfg(target_arch = "x86_64")]
+        {
+            if let Some(sgx_epc_list) = &vm_params.sgx_epc {
+                warn!("SGX support is deprecated and will be removed in a future release.");
+                let mut sgx_epc_config_list = Vec::new();
+                for item in sgx_epc_list.iter() {
+                    let sgx_epc_config = SgxEpcConfig::parse(item)?;
+                    sgx_epc_config_list.push(sgx_epc_config);
+                }
+                sgx_epc = Some(sgx_epc_config_list);
+            }
+        }
+
+        let mut numa: Option<Vec<NumaConfig>> = None;
+        if let Some(numa_list) = &vm_params.numa {
+            let mut numa_config_list = Vec::new();
+            for item in numa_list.iter() {
+                let numa_config = NumaConfig::parse(item)?;
+                numa_config_list.push(numa_config);
+            }
+            numa = Some(numa_config_list);
+        }
+
+        // Any payload option creates the payload, so that it can be merged
+        // into the one coming from a configuration file.
+        #[cfg(not(feature = "igvm"))]
+        let payload_present = vm_params.kernel.is_some()
+            || vm_params.firmware.is_some()
+            || vm_params.initramfs.is_some()
+            || vm_params.cmdline.is_some()
+            || vm_params.fallback_firmware.is_some();
+
+        #[cfg(feature = "igvm")]
+        let payload_present = vm_params.kernel.is_some()
+            || vm_params.firmware.is_some()
+            || vm_params.initramfs.is_some()
+            || vm_params.cmdline.is_some()
+            || vm_params.fallback_firmware.is_some()
+            || vm_params.igvm.is_some();
+
+        let mut payload = if payload_present {
+            let (kernel, kernel_fd) = parse_payload_file(vm_params.kernel)?;
+            let (firmware, firmware_fd) = parse_payload_file(vm_params.firmware)?;
+            let (initramfs, initramfs_fd) = parse_payload_file(
+                vm_params
+                    .initramfs
+                    .as_ref()
+                    .and_then(|v| v.first().copied()),
+            )?;
+            #[cfg(feature = "igvm")]
+            let (igvm, igvm_fd) = parse_payload_file(vm_params.igvm)?;
+            Some(PayloadConfig {
+                kernel,
+                kernel_fd,
+                initramfs,
+                initramfs_fd,
+                extra_initramfs: vm_params
+                    .initramfs
+                    .as_ref()
+                    .filter(|v| v.len() > 1)
+                    .map(|v| v[1..].iter().map(PathBuf::from).collect()),
+                cmdline: vm_params.cmdline.map(|s| s.to_string()),
+                firmware,
+                firmware_fd,
+                #[cfg(feature = "igvm")]
+                igvm,
+                #[cfg(feature = "igvm")]
+                igvm_fd,
+                #[cfg(feature = "sev_snp")]
+                host_data: vm_params.host_data.map(|s| s.to_string()),
+                #[cfg(feature = "sev_snp")]
+                id_block: vm_params.id_block.map(PathBuf::from),
+                #[cfg(feature = "sev_snp")]
+                id_auth: vm_params.id_auth.map(PathBuf::from),
+                #[cfg(feature = "sev_snp")]
+                snp_certs: vm_params.snp_certs.map(PathBuf::from),
+                fallback_firmware: vm_params
+                    .fallback_firmware
+                    .map(FallbackFirmwareConfig::parse)
+                    .transpose()?,
+            })
+        } else {
+            None
+        };
+
+        let payload_fds: Vec<i32> = payload
+            .as_mut()
+            .map(|p| p.fds_mut().into_iter().map(|fd| *fd).collect())
+            .unwrap_or_default();
+
+        let mut tpm: Option<TpmConfig> = None;
+        if let Some(tc) = vm_params.tpm {
+            let tpm_conf = TpmConfig::parse(tc)?;
+            tpm = Some(TpmConfig {
+                socket: tpm_conf.socket,
+            });
+        }
+
+        let scmi = vm_params.scmi.map(ScmiConfig::parse).transpose()?;
+        let cloud_init = vm_params
+            .cloud_init
+            .map(CloudInitConfig::parse)
+            .transpose()?;
+        let imds = vm_params.imds.map(ImdsConfig::parse).transpose()?;
+        let netns = vm_params.netns.map(NetnsConfig::parse).transpose()?;
+        let guest_agent = vm_params
+            .guest_agent
+            .map(GuestAgentConfig::parse)
+            .transpose()?;
+        let resources = vm_params
+            .resources
+            .map(ResourcesConfig::parse)
+            .transpose()?;
+        let rtc = vm_params.rtc.map(RtcConfig::parse).transpose()?;
+        let restart_policy = vm_params
+            .restart_policy
+            .map(RestartPolicyConfig::parse)
+            .transpose()?;
+        let crash_dump = vm_params
+            .crash_dump
+            .map(CrashDumpConfig::parse)
+            .transpose()?;
+        let io_threads = vm_params
+            .io_threads
+            .map(IoThreadsConfig::parse)
+            .transpose()?;
+
+        #[cfg(feature = "guest_debug")]
+        let gdb = vm_params.gdb;
+
+        let mut landlock_rules: Option<Vec<LandlockConfig>> = None;
+        if let Some(ll_rules) = vm_params.landlock_rules {
+            landlock_rules = Some(
+                ll_rules
+                    .iter()
+                    .map(|rule| LandlockConfig::parse(rule))
+                    .collect::<Result<Vec<LandlockConfig>>>()?,
+            );
+        }
+
+        let mut config = VmConfig {
+            cpus: CpusConfig::parse(vm_params.cpus)?,
+            memory: MemoryConfig::parse(vm_params.memory, vm_params.memory_zones)?,
+            payload,
+            rate_limit_groups,
+            disks,
+            net,
+            rng,
+            balloon,
+            fs,
+            pmem,
+            serial,
+            console,
+            #[cfg(target_arch = "x86_64")]
+            debug_console,
+            devices,
+            user_devices,
+            vdpa,
+            vsock,
+            #[cfg(feature = "pvmemcontrol")]
+            pvmemcontrol,
+            pvpanic: vm_params.pvpanic,
+            iommu: false, // updated in VmConfig::validate()
+            #[cfg(target_arch = "x86_64")]
+            sgx_epc,
+            numa,
+            watchdog: vm_params.watchdog,
+            #[cfg(feature = "guest_debug")]
+            gdb,
+            pci_segments,
+            platform,
+            tpm,
+            scmi,
+            cloud_init,
+            imds,
+            netns,
+            guest_agent,
+            resources,
+            rtc,
+            restart_policy,
+            crash_dump,
+            io_threads,
+            preserved_fds: None,
+            landlock_enable: vm_params.landlock_enable,
+            landlock_rules,
+        };
+        // SAFETY: payload FDs are inherited from the parent process, which
+        // is responsible for their validity.
+        unsafe { config.add_preserved_fds(payload_fds) };
+        Ok(config)
+    }
+
+    pub fn remove_device(&mut self, id: &str) -> bool {
+        let mut removed = false;
+
+        // Remove if VFIO device
+        if let Some(devices) = self.devices.as_mut() {
+            let len = devices.len();
+            devices.retain(|dev| dev.id.as_ref().map(|id| id.as_ref()) != Some(id));
+            removed |= devices.len() != len;
+        }
+
+        // Remove if VFIO user device
+        if let Some(user_devices) = self.user_devices.as_mut() {
+            let len = user_devices.len();
+            user_devices.retain(|dev| dev.id.as_ref().map(|id| id.as_ref()) != Some(id));
+            removed |= user_devices.len() != len;
+        }
+
+        // Remove if disk device
+        if let Some(disks) = self.disks.as_mut() {
+            let len = disks.len();
+            disks.retain(|dev| dev.id.as_ref().map(|id| id.as_ref()) != Some(id));
+            removed |= disks.len() != len;
+        }
+
+        // Remove if fs device
+        if let Some(fs) = self.fs.as_mut() {
+            let len = fs.len();
+            fs.retain(|dev| dev.id.as_ref().map(|id| id.as_ref()) != Some(id));
+            removed |= fs.len() != len;
+        }
+
+        // Remove if net device
+        if let Some(net) = self.net.as_mut() {
+            let len = net.len();
+            net.retain(|dev| dev.id.as_ref().map(|id| id.as_ref()) != Some(id));
+            removed |= net.len() != len;
+        }
+
+        // Remove if pmem device
+        if let Some(pmem) = self.pmem.as_mut() {
+            let len = pmem.len();
+            pmem.retain(|dev| dev.id.as_ref().map(|id| id.as_ref()) != Some(id));
+            removed |= pmem.len() != len;
+        }
+
+        // Remove if vDPA device
+        if let Some(vdpa) = self.vdpa.as_mut() {
+            let len = vdpa.len();
+            vdpa.retain(|dev| dev.id.as_ref().map(|id| id.as_ref()) != Some(id));
+            removed |= vdpa.len() != len;
+        }
+
+        // Remove if vsock device
+        if let Some(vsock) = self.vsock.as_ref() {
+            if vsock.id.as_ref().map(|id| id.as_ref()) == Some(id) {
+                self.vsock = None;
+                removed = true;
+            }
+        }
+
+        removed
+    }
+
+    /// Moves the disk `id` to the `rate_limit_group`, or out of any group,
+    /// dropping its own rate limiter. Returns whether the disk exists.
+    pub fn set_rate_limit_group(&mut self, id: &str, rate_limit_group: Option<String>) -> bool {
+        let Some(disk) = self
+            .disks
+            .iter_mut()
+            .flatten()
+            .find(|disk| disk.id.as_deref() == Some(id))
+        else {
+            return false;
+        };
+
+        disk.rate_limit_group = rate_limit_group;
+        disk.rate_limiter_config = None;
+        true
+    }
+
+    /// Changes the features of the balloon which are set, returning the
+    /// resulting balloon configuration if there is a balloon.
+    pub fn set_balloon(
+        &mut self,
+        deflate_on_oom: Option<bool>,
+        free_page_reporting: Option<bool>,
+    ) -> Option<&BalloonConfig> {
+        let balloon = self.balloon.as_mut()?;
+        if let Some(deflate_on_oom) = deflate_on_oom {
+            balloon.deflate_on_oom = deflate_on_oom;
+        }
+        if let Some(free_page_reporting) = free_page_reporting {
+            balloon.free_page_reporting = free_page_reporting;
+        }
+
+        Some(balloon)
+    }
+
+    /// # Safety
+    /// To use this safely, the caller must guarantee that the input
+    /// fds are all valid.
+    pub unsafe fn add_preserved_fds(&mut self, mut fds: Vec<i32>) {
+        if fds.is_empty() {
+            return;
+        }
+
+        if let Some(preserved_fds) = &self.preserved_fds {
+            fds.append(&mut preserved_fds.clone());
+        }
+
+        self.preserved_fds = Some(fds);
+    }
+
+    #[cfg(feature = "tdx")]
+    pub fn is_tdx_enabled(&self) -> bool {
+        self.platform.as_ref().map(|p| p.tdx).unwrap_or(false)
+    }
+
+    #[cfg(feature = "sev_snp")]
+    pub fn is_sev_snp_enabled(&self) -> bool {
+        self.platform.as_ref().map(|p| p.sev_snp).unwrap_or(false)
+    }
+
+    pub fn boot_method(&self) -> BootMethod {
+        self.platform
+            .as_ref()
+            .map(|p| p.boot_method)
+            .unwrap_or_default()
+    }
+
+    pub fn is_vmbus_enabled(&self) -> bool {
+        self.platform.as_ref().map(|p| p.vmbus).unwrap_or(false)
+    }
+
+    pub fn legacy_devices(&self) -> bool {
+        self.platform
+            .as_ref()
+            .map(|p| p.legacy_devices)
+            .unwrap_or(true)
+    }
+
+    pub fn is_auto_numa_enabled(&self) -> bool {
+        self.platform.as_ref().map(|p| p.auto_numa).unwrap_or(false)
+    }
+
+    pub fn is_host_numa_distances_enabled(&self) -> bool {
+        self.platform
+            .as_ref()
+            .map(|p| p.host_numa_distances)
+            .unwrap_or(false)
+    }
+
+    /// Number of PCI segments including the ones which can be added at
+    /// runtime.
+    pub fn max_pci_segments(&self) -> u16 {
+        self.platform
+            .as_ref()
+            .map(|p| p.max_pci_segments.unwrap_or(p.num_pci_segments))
+            .unwrap_or(DEFAULT_NUM_PCI_SEGMENTS)
+    }
+}
+
+impl Clone for VmConfig {
+    fn clone(&self) -> Self {
+        VmConfig {
+            cpus: self.cpus.clone(),
+            memory: self.memory.clone(),
+            payload: self.payload.clone(),
+            rate_limit_groups: self.rate_limit_groups.clone(),
+            disks: self.disks.clone(),
+            net: self.net.clone(),
+            rng: self.rng.clone(),
+            balloon: self.balloon.clone(),
+            #[cfg(feature = "pvmemcontrol")]
+            pvmemcontrol: self.pvmemcontrol.clone(),
+            fs: self.fs.clone(),
+            pmem: self.pmem.clone(),
+            serial: self.serial.clone(),
+            console: self.console.clone(),
+            #[cfg(target_arch = "x86_64")]
+            debug_console: self.debug_console.clone(),
+            devices: self.devices.clone(),
+            user_devices: self.user_devices.clone(),
+            vdpa: self.vdpa.clone(),
+            vsock: self.vsock.clone(),
+            #[cfg(target_arch = "x86_64")]
+            sgx_epc: self.sgx_epc.clone(),
+            numa: self.numa.clone(),
+            pci_segments: self.pci_segments.clone(),
+            platform: self.platform.clone(),
+            tpm: self.tpm.clone(),
+            scmi: self.scmi.clone(),
+            cloud_init: self.cloud_init.clone(),
+            imds: self.imds.clone(),
+            netns: self.netns.clone(),
+            guest_agent: self.guest_agent.clone(),
+            resources: self.resources.clone(),
+            rtc: self.rtc,
+            restart_policy: self.restart_policy,
+            crash_dump: self.crash_dump.clone(),
+            io_threads: self.io_threads.clone(),
+            preserved_fds: self
+                .preserved_fds
+                .as_ref()
+                // SAFETY: FFI call with valid FDs
+                .map(|fds| fds.iter().map(|fd| unsafe { libc::dup(*fd) }).collect()),
+            landlock_rules: self.landlock_rules.clone(),
+            ..*self
+        }
+    }
+}
+
+impl Drop for VmConfig {
+    fn drop(&mut self) {
+        if let Some(mut fds) = self.preserved_fds.take() {
+            for fd in fds.drain(..) {
+                // SAFETY: FFI call with valid FDs
+                unsafe { libc::close(fd) };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::os::unix::io::AsRawFd;
+
+    use net_util::macvtap::MacvtapMode;
+    use net_util::MacAddr;
+
+    use super::*;
+
+    #[test]
+    fn test_cpu_parsing() -> Result<()> {
+        assert_eq!(CpusConfig::parse("")?, CpusConfig::default());
+
+        assert_eq!(
+            CpusConfig::parse("boot=1")?,
+            CpusConfig {
+                boot_vcpus: 1,
+                max_vcpus: 1,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            CpusConfig::parse("boot=1,max=2")?,
+            CpusConfig {
+                boot_vcpus: 1,
+                max_vcpus: 2,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            CpusConfig::parse("boot=8,topology=2:2:1:2")?,
+            CpusConfig {
+                boot_vcpus: 8,
+                max_vcpus: 8,
+                topology: Some(CpuTopology {
+                    threads_per_core: 2,
+                    cores_per_die: 2,
+                    dies_per_package: 1,
+                    packages: 2
+                }),
+                ..Default::default()
+            }
+        );
+
+        CpusConfig::parse("boot=8,topology=2:2:1").unwrap_err();
+        CpusConfig::parse("boot=8,topology=2:2:1:x").unwrap_err();
+        assert_eq!(
+            CpusConfig::parse("boot=1,kvm_hyperv=on")?,
+            CpusConfig {
                 boot_vcpus: 1,
                 max_vcpus: 1,
+                kvm_hyperv: true,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            CpusConfig::parse("boot=2,affinity=[0@[0,2],1@[1,3]]")?,
+            CpusConfig {
+                boot_vcpus: 2,
+                max_vcpus: 2,
+                affinity: Some(vec![
+                    CpuAffinity {
+                        vcpu: 0,
+                        host_cpus: vec![0, 2],
+                    },
+                    CpuAffinity {
+                        vcpu: 1,
+                        host_cpus: vec![1, 3],
+                    }
+                ]),
+                ..Default::default()
+            },
+        );
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            assert!(!CpusConfig::parse("boot=255")?.has_wide_apic_ids());
+            assert!(CpusConfig::parse("boot=256")?.has_wide_apic_ids());
+            assert!(CpusConfig::parse("boot=2,max=300")?.has_wide_apic_ids());
+            // Sparse APIC IDs: 3 cores per die are given 2 bits
+            assert!(!CpusConfig::parse("boot=192,topology=1:3:1:64")?.has_wide_apic_ids());
+            assert!(CpusConfig::parse("boot=195,topology=1:3:1:65")?.has_wide_apic_ids());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mem_parsing() -> Result<()> {
+        assert_eq!(MemoryConfig::parse("", None)?, MemoryConfig::default());
+        // Default string
+        assert_eq!(
+            MemoryConfig::parse("size=512M", None)?,
+            MemoryConfig::default()
+        );
+        assert_eq!(
+            MemoryConfig::parse("size=512M,mergeable=on", None)?,
+            MemoryConfig {
+                size: 512 << 20,
+                mergeable: true,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            MemoryConfig::parse("mergeable=on", None)?,
+            MemoryConfig {
+                mergeable: true,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            MemoryConfig::parse("size=1G,mergeable=off", None)?,
+            MemoryConfig {
+                size: 1 << 30,
+                mergeable: false,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            MemoryConfig::parse("hotplug_method=acpi", None)?,
+            MemoryConfig {
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            MemoryConfig::parse("hotplug_method=acpi,hotplug_size=512M", None)?,
+            MemoryConfig {
+                hotplug_size: Some(512 << 20),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            MemoryConfig::parse("hotplug_method=virtio-mem,hotplug_size=512M", None)?,
+            MemoryConfig {
+                hotplug_size: Some(512 << 20),
+                hotplug_method: HotplugMethod::VirtioMem,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            MemoryConfig::parse("hugepages=on,size=1G,hugepage_size=2M", None)?,
+            MemoryConfig {
+                hugepage_size: Some(2 << 20),
+                size: 1 << 30,
+                hugepages: true,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            MemoryConfig::parse("prefault=on,thp_collapse=on", None)?,
+            MemoryConfig {
+                prefault: true,
+                thp_collapse: true,
+                ..Default::default()
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_rate_limit_group_parsing() -> Result<()> {
+        assert_eq!(
+            RateLimiterGroupConfig::parse("id=group0,bw_size=1000,bw_refill_time=100")?,
+            RateLimiterGroupConfig {
+                id: "group0".to_string(),
+                rate_limiter_config: RateLimiterConfig {
+                    bandwidth: Some(TokenBucketConfig {
+                        size: 1000,
+                        one_time_burst: Some(0),
+                        refill_time: 100,
+                    }),
+                    ops: None,
+                },
+                schedules: None,
+            }
+        );
+        assert_eq!(
+            RateLimiterGroupConfig::parse("id=group0,ops_size=1000,ops_refill_time=100")?,
+            RateLimiterGroupConfig {
+                id: "group0".to_string(),
+                rate_limiter_config: RateLimiterConfig {
+                    bandwidth: None,
+                    ops: Some(TokenBucketConfig {
+                        size: 1000,
+                        one_time_burst: Some(0),
+                        refill_time: 100,
+                    }),
+                },
+                schedules: None,
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_rate_limit_schedule_parsing() -> Result<()> {
+        assert_eq!(
+            RateLimitScheduleConfig::parse(
+                "group=group0,start=22:00,end=02:30,bw_size=2000,bw_refill_time=100"
+            )?,
+            (
+                "group0".to_string(),
+                RateLimitScheduleConfig {
+                    start: "22:00".to_string(),
+                    end: "02:30".to_string(),
+                    rate_limiter_config: RateLimiterConfig {
+                        bandwidth: Some(TokenBucketConfig {
+                            size: 2000,
+                            one_time_burst: Some(0),
+                            refill_time: 100,
+                        }),
+                        ops: None,
+                    },
+                }
+            )
+        );
+
+        let mut group = RateLimiterGroupConfig::parse("id=group0,bw_size=1000,bw_refill_time=100")?;
+        group.schedules = Some(vec![
+            RateLimitScheduleConfig::parse("group=group0,start=22:00,end=02:30")?.1,
+            RateLimitScheduleConfig::parse("group=group0,start=12:00,end=13:00")?.1,
+            RateLimitScheduleConfig::parse("group=group0,start=12:30,end=14:00")?.1,
+        ]);
+        assert_eq!(group.active_schedule(21 * 60 + 59), None);
+        assert_eq!(group.active_schedule(22 * 60), Some(0));
+        assert_eq!(group.active_schedule(60), Some(0));
+        assert_eq!(group.active_schedule(2 * 60 + 30), None);
+        assert_eq!(group.active_schedule(12 * 60 + 45), Some(1));
+        assert_eq!(group.active_schedule(13 * 60 + 30), Some(2));
+        Ok(())
+    }
+
+    #[test]
+    fn test_pci_segment_parsing() -> Result<()> {
+        assert_eq!(
+            PciSegmentConfig::parse("pci_segment=0")?,
+            PciSegmentConfig {
+                pci_segment: 0,
+                mmio32_aperture_weight: 1,
+                mmio64_aperture_weight: 1,
+            }
+        );
+        assert_eq!(
+            PciSegmentConfig::parse(
+                "pci_segment=0,mmio32_aperture_weight=1,mmio64_aperture_weight=1"
+            )?,
+            PciSegmentConfig {
+                pci_segment: 0,
+                mmio32_aperture_weight: 1,
+                mmio64_aperture_weight: 1,
+            }
+        );
+        assert_eq!(
+            PciSegmentConfig::parse("pci_segment=0,mmio32_aperture_weight=2")?,
+            PciSegmentConfig {
+                pci_segment: 0,
+                mmio32_aperture_weight: 2,
+                mmio64_aperture_weight: 1,
+            }
+        );
+        assert_eq!(
+            PciSegmentConfig::parse("pci_segment=0,mmio64_aperture_weight=2")?,
+            PciSegmentConfig {
+                pci_segment: 0,
+                mmio32_aperture_weight: 1,
+                mmio64_aperture_weight: 2,
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_platform_parsing() -> Result<()> {
+        #[cfg(feature = "sev_snp")]
+        {
+            let platform = PlatformConfig::parse("sev_snp=on")?;
+            assert!(platform.sev_snp);
+            assert!(platform.sev_snp_smt);
+            assert!(!platform.sev_snp_debug);
+            assert!(!platform.sev_snp_migrate_ma);
+
+            let platform = PlatformConfig::parse(
+                "sev_snp=on,sev_snp_smt=off,sev_snp_debug=on,sev_snp_migrate_ma=on",
+            )?;
+            assert!(!platform.sev_snp_smt);
+            assert!(platform.sev_snp_debug);
+            assert!(platform.sev_snp_migrate_ma);
+
+            PlatformConfig::parse("sev_snp=on,sev_snp_debug=yes").unwrap_err();
+        }
+
+        #[cfg(feature = "tdx")]
+        {
+            let platform = PlatformConfig::parse("tdx=on")?;
+            assert!(platform.tdx);
+            assert_eq!(platform.tdx_l2_vms, 0);
+
+            let platform = PlatformConfig::parse("tdx=on,tdx_l2_vms=2")?;
+            assert_eq!(platform.tdx_l2_vms, 2);
+
+            PlatformConfig::parse("tdx=on,tdx_l2_vms=-1").unwrap_err();
+            PlatformConfig::parse("tdx=on,tdx_l2_vms=two").unwrap_err();
+        }
+
+        let platform = PlatformConfig::parse("num_pci_segments=2")?;
+        assert_eq!(platform.max_pci_segments, None);
+        let platform = PlatformConfig::parse("num_pci_segments=2,max_pci_segments=4")?;
+        assert_eq!(platform.num_pci_segments, 2);
+        assert_eq!(platform.max_pci_segments, Some(4));
+        PlatformConfig::parse("max_pci_segments=-1").unwrap_err();
+
+        Ok(())
+    }
+
+    fn disk_fixture() -> DiskConfig {
+        DiskConfig {
+            path: Some(PathBuf::from("/path/to_file")),
+            readonly: false,
+            direct: false,
+            iommu: false,
+            num_queues: 1,
+            queue_size: QueueSize::Fixed(128),
+            vhost_user: false,
+            vhost_socket: None,
+            id: None,
+            rate_limit_group: None,
+            rate_limiter_config: None,
+            fair_queues: false,
+            pci_segment: 0,
+            serial: None,
+            queue_affinity: None,
+            io_thread: None,
+            coalescing: None,
+            pause_on_path_failure: false,
+            format: None,
+            cache: None,
+            encryption: None,
+            key_file: None,
+            key_fd: None,
+            discard: false,
+            detect_zeroes: false,
+            interface: DiskInterface::Virtio,
+            io_engine: None,
+            io_engine_threads: None,
+            io_uring_sqpoll: false,
+            io_uring_fixed_buffers: false,
+            io_class: None,
+            io_prio: None,
+            msix_vectors: None,
+        }
+    }
+
+    #[test]
+    fn test_disk_parsing() -> Result<()> {
+        assert_eq!(
+            DiskConfig::parse("path=/path/to_file")?,
+            DiskConfig { ..disk_fixture() }
+        );
+        assert_eq!(
+            DiskConfig::parse("path=/path/to_file,id=mydisk0")?,
+            DiskConfig {
+                id: Some("mydisk0".to_owned()),
+                ..disk_fixture()
+            }
+        );
+        assert_eq!(
+            DiskConfig::parse("vhost_user=true,socket=/tmp/sock")?,
+            DiskConfig {
+                path: None,
+                vhost_socket: Some(String::from("/tmp/sock")),
+                vhost_user: true,
+                ..disk_fixture()
+            }
+        );
+        assert_eq!(
+            DiskConfig::parse("path=/path/to_file,iommu=on")?,
+            DiskConfig {
+                iommu: true,
+                ..disk_fixture()
+            }
+        );
+        assert_eq!(
+            DiskConfig::parse("path=/path/to_file,iommu=on,queue_size=256")?,
+            DiskConfig {
+                iommu: true,
+                queue_size: QueueSize::Fixed(256),
+                ..disk_fixture()
+            }
+        );
+        assert_eq!(
+            DiskConfig::parse("path=/path/to_file,iommu=on,queue_size=256,num_queues=4")?,
+            DiskConfig {
+                iommu: true,
+                queue_size: QueueSize::Fixed(256),
+                num_queues: 4,
+                ..disk_fixture()
+            }
+        );
+        assert_eq!(
+            DiskConfig::parse("path=/path/to_file,queue_size=auto")?,
+            DiskConfig {
+                queue_size: QueueSize::Auto,
+                ..disk_fixture()
+            }
+        );
+        DiskConfig::parse("path=/path/to_file,queue_size=max").unwrap_err();
+        assert_eq!(
+            DiskConfig::parse("path=/path/to_file,direct=on")?,
+            DiskConfig {
+                direct: true,
+                ..disk_fixture()
+            }
+        );
+        assert_eq!(
+            DiskConfig::parse("path=/path/to_file,serial=test")?,
+            DiskConfig {
+                serial: Some(String::from("test")),
+                ..disk_fixture()
+            }
+        );
+        assert_eq!(
+            DiskConfig::parse("path=/path/to_file,rate_limit_group=group0")?,
+            DiskConfig {
+                rate_limit_group: Some("group0".to_string()),
+                ..disk_fixture()
+            }
+        );
+        assert_eq!(
+            DiskConfig::parse("path=/path/to_file,queue_affinity=[0@[1],1@[2],2@[3,4],3@[5-8]]")?,
+            DiskConfig {
+                queue_affinity: Some(vec![
+                    VirtQueueAffinity {
+                        queue_index: 0,
+                        host_cpus: vec![1],
+                    },
+                    VirtQueueAffinity {
+                        queue_index: 1,
+                        host_cpus: vec![2],
+                    },
+                    VirtQueueAffinity {
+                        queue_index: 2,
+                        host_cpus: vec![3, 4],
+                    },
+                    VirtQueueAffinity {
+                        queue_index: 3,
+                        host_cpus: vec![5, 6, 7, 8],
+                    }
+                ]),
+                ..disk_fixture()
+            }
+        );
+        assert_eq!(
+            DiskConfig::parse("path=/path/to_file,io_thread=2")?,
+            DiskConfig {
+                io_thread: Some(2),
+                ..disk_fixture()
+            }
+        );
+        assert_eq!(
+            DiskConfig::parse("path=/path/to_file,coalesce_usecs=50,coalesce_max_used=32")?,
+            DiskConfig {
+                coalescing: Some(CoalescingConfig {
+                    usecs: 50,
+                    max_used: 32,
+                }),
+                ..disk_fixture()
+            }
+        );
+        assert_eq!(
+            DiskConfig::parse("path=/path/to_file,coalesce_max_used=32")?,
+            DiskConfig {
+                coalescing: Some(CoalescingConfig {
+                    usecs: 0,
+                    max_used: 32,
+                }),
+                ..disk_fixture()
+            }
+        );
+        DiskConfig::parse("path=/path/to_file,coalesce_usecs=-1").unwrap_err();
+        assert_eq!(
+            DiskConfig::parse("path=/path/to_file,rate_limit_group=group0,fair_queues=on")?,
+            DiskConfig {
+                rate_limit_group: Some("group0".to_string()),
+                fair_queues: true,
+                ..disk_fixture()
+            }
+        );
+        assert_eq!(
+            DiskConfig::parse("path=/path/to_file,pause_on_path_failure=on")?,
+            DiskConfig {
+                pause_on_path_failure: true,
+                ..disk_fixture()
+            }
+        );
+        assert_eq!(
+            DiskConfig::parse("path=/path/to_file,discard=on")?,
+            DiskConfig {
+                discard: true,
+                ..disk_fixture()
+            }
+        );
+        DiskConfig::parse("path=/path/to_file,detect_zeroes=unmap").unwrap_err();
+        assert_eq!(
+            DiskConfig::parse("path=/path/to_file,format=qcow2")?,
+            DiskConfig {
+                format: Some(ImageType::Qcow2),
+                ..disk_fixture()
+            }
+        );
+        assert_eq!(
+            DiskConfig::parse("path=/path/to_file,format=vhd")?,
+            DiskConfig {
+                format: Some(ImageType::FixedVhd),
+                ..disk_fixture()
+            }
+        );
+        DiskConfig::parse("path=/path/to_file,format=vmdk").unwrap_err();
+        assert_eq!(
+            DiskConfig::parse("path=/path/to_file,cache=none")?,
+            DiskConfig {
+                cache: Some(CacheMode::Direct),
+                ..disk_fixture()
+            }
+        );
+        assert_eq!(
+            DiskConfig::parse("path=/path/to_file,cache=writethrough")?,
+            DiskConfig {
+                cache: Some(CacheMode::Writethrough),
+                ..disk_fixture()
+            }
+        );
+        DiskConfig::parse("path=/path/to_file,cache=directsync").unwrap_err();
+        assert_eq!(
+            DiskConfig::parse("path=/path/to_file,encryption=luks,key_file=/path/to_key")?,
+            DiskConfig {
+                encryption: Some(DiskEncryption::Luks),
+                key_file: Some(PathBuf::from("/path/to_key")),
+                ..disk_fixture()
+            }
+        );
+        assert_eq!(
+            DiskConfig::parse("path=/path/to_file,encryption=luks,key_fd=3")?,
+            DiskConfig {
+                encryption: Some(DiskEncryption::Luks),
+                key_fd: Some(3),
+                ..disk_fixture()
+            }
+        );
+        DiskConfig::parse("path=/path/to_file,encryption=bitlocker").unwrap_err();
+        assert_eq!(
+            DiskConfig::parse("path=/path/to_file,discard=on,detect_zeroes=on")?,
+            DiskConfig {
+                discard: true,
+                detect_zeroes: true,
+                ..disk_fixture()
+            }
+        );
+        assert_eq!(
+            DiskConfig::parse("path=/path/to_file,interface=nvme")?,
+            DiskConfig {
+                interface: DiskInterface::Nvme,
+                ..disk_fixture()
+            }
+        );
+        DiskConfig::parse("path=/path/to_file,interface=scsi").unwrap_err();
+        assert_eq!(
+            DiskConfig::parse("path=/path/to_file,io_engine=threads,io_engine_threads=8")?,
+            DiskConfig {
+                io_engine: Some(IoEngine::Threads),
+                io_engine_threads: Some(8),
+                ..disk_fixture()
+            }
+        );
+        DiskConfig::parse("path=/path/to_file,io_engine=posix").unwrap_err();
+        assert_eq!(
+            DiskConfig::parse(
+                "path=/path/to_file,io_engine=io_uring,io_uring_sqpoll=on,io_uring_fixed_buffers=on"
+            )?,
+            DiskConfig {
+                io_engine: Some(IoEngine::IoUring),
+                io_uring_sqpoll: true,
+                io_uring_fixed_buffers: true,
+                ..disk_fixture()
+            }
+        );
+        assert_eq!(
+            DiskConfig::parse("path=/path/to_file,io_class=be,io_prio=7")?,
+            DiskConfig {
+                io_class: Some(IoClass::BestEffort),
+                io_prio: Some(7),
+                ..disk_fixture()
+            }
+        );
+        DiskConfig::parse("path=/path/to_file,io_class=low").unwrap_err();
+        assert_eq!(
+            DiskConfig::parse("path=/path/to_file,num_queues=4,msix_vectors=2")?,
+            DiskConfig {
+                num_queues: 4,
+                msix_vectors: Some(2),
+                ..disk_fixture()
+            }
+        );
+        DiskConfig::parse("path=/path/to_file,msix_vectors=-1").unwrap_err();
+        Ok(())
+    }
+
+    fn net_fixture() -> NetConfig {
+        NetConfig {
+            tap: None,
+            ip: IpAddr::V4(Ipv4Addr::new(192, 168, 249, 1)),
+            mask: IpAddr::V4(Ipv4Addr::new(255, 255, 255, 0)),
+            mac: MacAddr::parse_str("de:ad:be:ef:12:34").unwrap(),
+            host_mac: Some(MacAddr::parse_str("12:34:de:ad:be:ef").unwrap()),
+            mtu: None,
+            iommu: false,
+            num_queues: 2,
+            queue_size: 256,
+            vhost_user: false,
+            vhost_socket: None,
+            vhost_mode: VhostMode::Client,
+            id: None,
+            fds: None,
+            rate_limiter_config: None,
+            pci_segment: 0,
+            offload_tso: true,
+            offload_ufo: true,
+            offload_csum: true,
+            io_uring: false,
+            io_thread: None,
+            coalescing: None,
+            rate_limit_group: None,
+            weight: DEFAULT_NET_WEIGHT,
+            tap_persist: None,
+            tap_uid: None,
+            tap_gid: None,
+            macvtap: None,
+            macvtap_link: None,
+            macvtap_mode: MacvtapMode::Bridge,
+            vhost: false,
+            romfile: None,
+            msix_vectors: None,
+        }
+    }
+
+    #[test]
+    fn test_net_parsing() -> Result<()> {
+        // mac address is random
+        assert_eq!(
+            NetConfig::parse("mac=de:ad:be:ef:12:34,host_mac=12:34:de:ad:be:ef")?,
+            net_fixture(),
+        );
+
+        assert_eq!(
+            NetConfig::parse("mac=de:ad:be:ef:12:34,host_mac=12:34:de:ad:be:ef,id=mynet0")?,
+            NetConfig {
+                id: Some("mynet0".to_owned()),
+                ..net_fixture()
+            }
+        );
+
+        assert_eq!(
+            NetConfig::parse(
+                "mac=de:ad:be:ef:12:34,host_mac=12:34:de:ad:be:ef,tap=tap0,ip=192.168.100.1,mask=255.255.255.128"
+            )?,
+            NetConfig {
+                tap: Some("tap0".to_owned()),
+                ip: "192.168.100.1".parse().unwrap(),
+                mask: "255.255.255.128".parse().unwrap(),
+                ..net_fixture()
+            }
+        );
+
+        assert_eq!(
+            NetConfig::parse(
+                "mac=de:ad:be:ef:12:34,host_mac=12:34:de:ad:be:ef,vhost_user=true,socket=/tmp/sock"
+            )?,
+            NetConfig {
+                vhost_user: true,
+                vhost_socket: Some("/tmp/sock".to_owned()),
+                ..net_fixture()
+            }
+        );
+
+        assert_eq!(
+            NetConfig::parse("mac=de:ad:be:ef:12:34,host_mac=12:34:de:ad:be:ef,num_queues=4,queue_size=1024,iommu=on")?,
+            NetConfig {
+                num_queues: 4,
+                queue_size: 1024,
+                iommu: true,
+                ..net_fixture()
+            }
+        );
+
+        assert_eq!(
+            NetConfig::parse("mac=de:ad:be:ef:12:34,fd=[3,7],num_queues=4")?,
+            NetConfig {
+                host_mac: None,
+                fds: Some(vec![3, 7]),
+                num_queues: 4,
+                ..net_fixture()
+            }
+        );
+
+        assert_eq!(
+            NetConfig::parse("mac=de:ad:be:ef:12:34,host_mac=12:34:de:ad:be:ef,io_uring=on")?,
+            NetConfig {
+                io_uring: true,
+                ..net_fixture()
+            }
+        );
+        assert_eq!(
+            NetConfig::parse("mac=de:ad:be:ef:12:34,host_mac=12:34:de:ad:be:ef,io_uring=off")?,
+            net_fixture()
+        );
+        NetConfig::parse("mac=de:ad:be:ef:12:34,host_mac=12:34:de:ad:be:ef,io_uring=uring")
+            .unwrap_err();
+
+        assert_eq!(
+            NetConfig::parse("mac=de:ad:be:ef:12:34,host_mac=12:34:de:ad:be:ef,io_thread=1")?,
+            NetConfig {
+                io_thread: Some(1),
+                ..net_fixture()
+            }
+        );
+        NetConfig::parse("mac=de:ad:be:ef:12:34,host_mac=12:34:de:ad:be:ef,coalesce_max_used=foo")
+            .unwrap_err();
+
+        assert_eq!(
+            NetConfig::parse(
+                "mac=de:ad:be:ef:12:34,host_mac=12:34:de:ad:be:ef,coalesce_usecs=100"
+            )?,
+            NetConfig {
+                coalescing: Some(CoalescingConfig {
+                    usecs: 100,
+                    max_used: 0,
+                }),
+                ..net_fixture()
+            }
+        );
+
+        assert_eq!(
+            NetConfig::parse(
+                "mac=de:ad:be:ef:12:34,host_mac=12:34:de:ad:be:ef,rate_limit_group=group0,weight=200"
+            )?,
+            NetConfig {
+                rate_limit_group: Some("group0".to_string()),
+                weight: 200,
+                ..net_fixture()
+            }
+        );
+
+        assert_eq!(
+            NetConfig::parse(
+                "mac=de:ad:be:ef:12:34,host_mac=12:34:de:ad:be:ef,tap=tap0,tap_persist=on,tap_uid=1000,tap_gid=100"
+            )?,
+            NetConfig {
+                tap: Some("tap0".to_string()),
+                tap_persist: Some(true),
+                tap_uid: Some(1000),
+                tap_gid: Some(100),
+                ..net_fixture()
+            }
+        );
+        assert_eq!(
+            NetConfig::parse(
+                "mac=de:ad:be:ef:12:34,host_mac=12:34:de:ad:be:ef,tap=tap0,tap_persist=off"
+            )?,
+            NetConfig {
+                tap: Some("tap0".to_string()),
+                tap_persist: Some(false),
+                ..net_fixture()
+            }
+        );
+        NetConfig::parse("mac=de:ad:be:ef:12:34,host_mac=12:34:de:ad:be:ef,tap_uid=-1")
+            .unwrap_err();
+        NetConfig::parse("mac=de:ad:be:ef:12:34,host_mac=12:34:de:ad:be:ef,tap_persist=maybe")
+            .unwrap_err();
+
+        assert_eq!(
+            NetConfig::parse(
+                "mac=de:ad:be:ef:12:34,host_mac=12:34:de:ad:be:ef,romfile=/path/to/rom"
+            )?,
+            NetConfig {
+                romfile: Some(PathBuf::from("/path/to/rom")),
+                ..net_fixture()
+            }
+        );
+
+        assert_eq!(
+            NetConfig::parse("mac=de:ad:be:ef:12:34,host_mac=12:34:de:ad:be:ef,msix_vectors=3")?,
+            NetConfig {
+                msix_vectors: Some(3),
+                ..net_fixture()
+            }
+        );
+
+        assert_eq!(
+            NetConfig::parse(
+                "mac=de:ad:be:ef:12:34,host_mac=12:34:de:ad:be:ef,macvtap=mvtap0,macvtap_link=eth0,macvtap_mode=vepa"
+            )?,
+            NetConfig {
+                macvtap: Some("mvtap0".to_string()),
+                macvtap_link: Some("eth0".to_string()),
+                macvtap_mode: MacvtapMode::Vepa,
+                ..net_fixture()
+            }
+        );
+        NetConfig::parse("macvtap=mvtap0,macvtap_mode=source").unwrap_err();
+
+        assert_eq!(
+            NetConfig::parse("mac=de:ad:be:ef:12:34,host_mac=12:34:de:ad:be:ef,vhost=on")?,
+            NetConfig {
+                vhost: true,
+                ..net_fixture()
+            }
+        );
+        NetConfig::parse("mac=de:ad:be:ef:12:34,host_mac=12:34:de:ad:be:ef,vhost=kernel")
+            .unwrap_err();
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_rng() -> Result<()> {
+        assert_eq!(RngConfig::parse("")?, RngConfig::default());
+        assert_eq!(
+            RngConfig::parse("src=/dev/random")?,
+            RngConfig {
+                src: PathBuf::from("/dev/random"),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            RngConfig::parse("src=/dev/random,iommu=on")?,
+            RngConfig {
+                src: PathBuf::from("/dev/random"),
+                iommu: true,
+            }
+        );
+        assert_eq!(
+            RngConfig::parse("iommu=on")?,
+            RngConfig {
+                iommu: true,
+                ..Default::default()
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_scmi() -> Result<()> {
+        assert_eq!(
+            ScmiConfig::parse("power_domains=2")?,
+            ScmiConfig {
+                power_domains: 2,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            ScmiConfig::parse(
+                "sensors=[/sys/class/hwmon/hwmon0/temp1_input,/sys/class/hwmon/hwmon0/in0_input],iommu=on"
+            )?,
+            ScmiConfig {
+                sensors: Some(vec![
+                    PathBuf::from("/sys/class/hwmon/hwmon0/temp1_input"),
+                    PathBuf::from("/sys/class/hwmon/hwmon0/in0_input"),
+                ]),
+                power_domains: 0,
+                clocks: None,
+                iommu: true,
+            }
+        );
+        assert_eq!(
+            ScmiConfig::parse("clocks=[24000000,100000000]")?,
+            ScmiConfig {
+                clocks: Some(vec![24000000, 100000000]),
+                ..Default::default()
+            }
+        );
+        ScmiConfig::parse("power_domains=foo").unwrap_err();
+        ScmiConfig::parse("clocks=[24MHz]").unwrap_err();
+        assert_eq!(
+            ScmiConfig::parse("")?.validate(),
+            Err(ValidationError::ScmiNoResources)
+        );
+        ScmiConfig::parse("power_domains=1")?.validate().unwrap();
+        ScmiConfig::parse("clocks=[24000000]")?.validate().unwrap();
+        assert_eq!(
+            ScmiConfig::parse("clocks=[24000000,0]")?.validate(),
+            Err(ValidationError::InvalidScmiClockRate)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_cloud_init() -> Result<()> {
+        assert_eq!(
+            CloudInitConfig::parse("user_data=/path/to/user-data")?,
+            CloudInitConfig {
+                user_data: Some(PathBuf::from("/path/to/user-data")),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            CloudInitConfig::parse(
+                "user_data=/path/to/user-data,meta_data=/path/to/meta-data,network_config=/path/to/network-config"
+            )?,
+            CloudInitConfig {
+                user_data: Some(PathBuf::from("/path/to/user-data")),
+                meta_data: Some(PathBuf::from("/path/to/meta-data")),
+                network_config: Some(PathBuf::from("/path/to/network-config")),
+            }
+        );
+        CloudInitConfig::parse("vendor_data=/path/to/vendor-data").unwrap_err();
+        assert_eq!(
+            CloudInitConfig::parse("meta_data=/path/to/meta-data")?.validate(),
+            Err(ValidationError::CloudInitNoData)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_netns() -> Result<()> {
+        assert_eq!(
+            NetnsConfig::parse("name=vm0")?,
+            NetnsConfig {
+                name: "vm0".to_string(),
+                bridge: None,
+                veth: None,
+            }
+        );
+        let netns = NetnsConfig::parse("name=vm0,bridge=br0")?;
+        assert_eq!(netns.bridge.as_deref(), Some("br0"));
+        assert_eq!(netns.veth_name(), "veth-vm0");
+        assert!(matches!(
+            NetnsConfig::parse("bridge=br0"),
+            Err(Error::ParseNetnsNameMissing)
+        ));
+
+        assert_eq!(
+            NetnsConfig::parse("name=../vm0")?.validate(),
+            Err(ValidationError::InvalidNetnsName("../vm0".to_string()))
+        );
+        assert_eq!(
+            NetnsConfig::parse("name=vm0,bridge=br0,veth=veth-too-long-name")?.validate(),
+            Err(ValidationError::InvalidInterfaceName(
+                "veth-too-long-name".to_string()
+            ))
+        );
+        assert_eq!(
+            NetnsConfig::parse("name=vm0,veth=veth-vm0")?.validate(),
+            Err(ValidationError::NetnsVethWithoutBridge)
+        );
+        NetnsConfig::parse("name=vm0,bridge=br0,veth=veth-vm0")?
+            .validate()
+            .unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_imds() -> Result<()> {
+        assert_eq!(
+            ImdsConfig::parse("document=/path/to/metadata.json")?,
+            ImdsConfig {
+                document: PathBuf::from("/path/to/metadata.json"),
+            }
+        );
+        assert!(matches!(
+            ImdsConfig::parse(""),
+            Err(Error::ParseImdsDocumentMissing)
+        ));
+        ImdsConfig::parse("document=/path/to/metadata.json,port=8080").unwrap_err();
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "pvmemcontrol")]
+    fn test_parse_pvmemcontrol() -> Result<()> {
+        assert_eq!(
+            PvmemcontrolConfig::parse("")?,
+            PvmemcontrolConfig::default()
+        );
+        assert_eq!(
+            PvmemcontrolConfig::parse("reclaim_rate=64M")?,
+            PvmemcontrolConfig {
+                reclaim_rate: Some(64 << 20)
+            }
+        );
+        PvmemcontrolConfig::parse("reclaim_rate=fast").unwrap_err();
+        PvmemcontrolConfig::parse("rate=64M").unwrap_err();
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_balloon() -> Result<()> {
+        let balloon = BalloonConfig::parse("size=1G")?;
+        assert_eq!(balloon.size, 1 << 30);
+        assert!(!balloon.auto_resize);
+        assert_eq!(balloon.max_size, None);
+
+        let balloon = BalloonConfig::parse(
+            "auto_resize=on,min_size=128M,max_size=2G,step=64M,pressure_high=20,pressure_low=2",
+        )?;
+        assert!(balloon.auto_resize);
+        assert_eq!(balloon.min_size, 128 << 20);
+        assert_eq!(balloon.max_size, Some(2 << 30));
+        assert_eq!(balloon.step, 64 << 20);
+        assert_eq!(balloon.pressure_high, 20);
+        assert_eq!(balloon.pressure_low, 2);
+
+        let balloon = BalloonConfig::parse("auto_resize=on,max_size=1G")?;
+        assert_eq!(balloon.step, DEFAULT_BALLOON_STEP);
+        assert_eq!(balloon.pressure_high, DEFAULT_BALLOON_PRESSURE_HIGH);
+        assert_eq!(balloon.pressure_low, DEFAULT_BALLOON_PRESSURE_LOW);
+
+        BalloonConfig::parse("pressure_high=high").unwrap_err();
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_guest_agent() -> Result<()> {
+        assert_eq!(GuestAgentConfig::parse("")?, GuestAgentConfig::default());
+        assert_eq!(
+            GuestAgentConfig::parse("port=4444")?,
+            GuestAgentConfig { port: 4444 }
+        );
+        GuestAgentConfig::parse("port=-1").unwrap_err();
+        GuestAgentConfig::parse("cid=3").unwrap_err();
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_resources() -> Result<()> {
+        assert_eq!(ResourcesConfig::parse("")?, ResourcesConfig::default());
+        assert_eq!(
+            ResourcesConfig::parse("cgroup=/sys/fs/cgroup/vm0,manifest=/run/vm0.json")?,
+            ResourcesConfig {
+                cgroup: Some(PathBuf::from("/sys/fs/cgroup/vm0")),
+                manifest: Some(PathBuf::from("/run/vm0.json")),
                 ..Default::default()
             }
         );
         assert_eq!(
-            CpusConfig::parse("boot=1,max=2")?,
-            CpusConfig {
-                boot_vcpus: 1,
-                max_vcpus: 2,
-                ..Default::default()
+            ResourcesConfig::parse(
+                "cgroup=/sys/fs/cgroup/vm0,cpu_quota=150,memory_max=2G,\
+                io_max=[8:0 rbps=1048576 wbps=1048576,8:16 wiops=100],threads=on"
+            )?,
+            ResourcesConfig {
+                cgroup: Some(PathBuf::from("/sys/fs/cgroup/vm0")),
+                manifest: None,
+                cpu_quota: Some(150),
+                memory_max: Some(2 << 30),
+                io_max: Some(vec![
+                    "8:0 rbps=1048576 wbps=1048576".to_owned(),
+                    "8:16 wiops=100".to_owned(),
+                ]),
+                threads: true,
+            }
+        );
+        ResourcesConfig::parse("path=/sys/fs/cgroup/vm0").unwrap_err();
+        ResourcesConfig::parse("memory_max=2G").unwrap_err();
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_rtc() -> Result<()> {
+        assert_eq!(RtcConfig::parse("")?, RtcConfig::default());
+        assert_eq!(
+            RtcConfig::parse("base=localtime,drift=slew")?,
+            RtcConfig {
+                base: RtcBase::Localtime,
+                drift: RtcDrift::Slew,
+            }
+        );
+        assert_eq!(
+            RtcConfig::parse("base=2038-01-19T03:14:08Z")?.base,
+            RtcBase::Date(1 << 31)
+        );
+        assert_eq!(
+            RtcConfig::parse("base=2000-03-01T01:00:00+01:00")?.base,
+            RtcBase::Date(951868800)
+        );
+        assert_eq!(
+            RtcConfig::parse("base=1969-12-31T23:59:59")?.base,
+            RtcBase::Date(-1)
+        );
+        RtcConfig::parse("base=2023-02-29T00:00:00").unwrap_err();
+        RtcConfig::parse("base=2024-01-01").unwrap_err();
+        RtcConfig::parse("drift=smear").unwrap_err();
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_restart_policy() -> Result<()> {
+        assert_eq!(
+            RestartPolicyConfig::parse("never")?,
+            RestartPolicyConfig::default()
+        );
+        assert_eq!(
+            RestartPolicyConfig::parse("on-crash")?,
+            RestartPolicyConfig {
+                policy: RestartPolicy::OnCrash,
+                max: DEFAULT_RESTART_MAX,
+                backoff: DEFAULT_RESTART_BACKOFF_MS,
+            }
+        );
+        assert_eq!(
+            RestartPolicyConfig::parse("on-crash:max=3,backoff=500")?,
+            RestartPolicyConfig {
+                policy: RestartPolicy::OnCrash,
+                max: 3,
+                backoff: 500,
+            }
+        );
+        RestartPolicyConfig::parse("always").unwrap_err();
+        RestartPolicyConfig::parse("never:max=3").unwrap_err();
+        RestartPolicyConfig::parse("on-crash:max=-1").unwrap_err();
+        RestartPolicyConfig::parse("on-crash:delay=1").unwrap_err();
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_crash_dump() -> Result<()> {
+        assert_eq!(
+            CrashDumpConfig::parse("path=/var/crash")?,
+            CrashDumpConfig {
+                path: PathBuf::from("/var/crash")
+            }
+        );
+        CrashDumpConfig::parse("").unwrap_err();
+        CrashDumpConfig::parse("path=/var/crash,size=1").unwrap_err();
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_io_threads() -> Result<()> {
+        assert_eq!(
+            IoThreadsConfig::parse("")?,
+            IoThreadsConfig {
+                count: DEFAULT_IO_THREADS,
+                affinity: None,
+            }
+        );
+        assert_eq!(
+            IoThreadsConfig::parse("count=4,affinity=[0@[2,3],3@[8-11]]")?,
+            IoThreadsConfig {
+                count: 4,
+                affinity: Some(vec![
+                    IoThreadAffinity {
+                        io_thread: 0,
+                        host_cpus: vec![2, 3],
+                    },
+                    IoThreadAffinity {
+                        io_thread: 3,
+                        host_cpus: vec![8, 9, 10, 11],
+                    },
+                ]),
+            }
+        );
+        IoThreadsConfig::parse("count=4,size=2").unwrap_err();
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_fallback_firmware() -> Result<()> {
+        assert_eq!(
+            FallbackFirmwareConfig::parse("path=/path/to/firmware")?,
+            FallbackFirmwareConfig {
+                path: PathBuf::from("/path/to/firmware"),
+                timeout: DEFAULT_FALLBACK_FIRMWARE_TIMEOUT,
+            }
+        );
+        assert_eq!(
+            FallbackFirmwareConfig::parse("path=/path/to/firmware,timeout=10")?,
+            FallbackFirmwareConfig {
+                path: PathBuf::from("/path/to/firmware"),
+                timeout: 10,
+            }
+        );
+        assert!(matches!(
+            FallbackFirmwareConfig::parse("timeout=10"),
+            Err(Error::ParseFallbackFirmwarePathMissing)
+        ));
+        FallbackFirmwareConfig::parse("path=/path/to/firmware,timeout=soon").unwrap_err();
+        Ok(())
+    }
+
+    fn fs_fixture() -> FsConfig {
+        FsConfig {
+            socket: PathBuf::from("/tmp/sock"),
+            tag: "mytag".to_owned(),
+            num_queues: 1,
+            queue_size: 1024,
+            id: None,
+            pci_segment: 0,
+            msix_vectors: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_fs() -> Result<()> {
+        // "tag" and "socket" must be supplied
+        FsConfig::parse("").unwrap_err();
+        FsConfig::parse("tag=mytag").unwrap_err();
+        FsConfig::parse("socket=/tmp/sock").unwrap_err();
+        assert_eq!(FsConfig::parse("tag=mytag,socket=/tmp/sock")?, fs_fixture());
+        assert_eq!(
+            FsConfig::parse("tag=mytag,socket=/tmp/sock,num_queues=4,queue_size=1024")?,
+            FsConfig {
+                num_queues: 4,
+                queue_size: 1024,
+                ..fs_fixture()
+            }
+        );
+        assert_eq!(
+            FsConfig::parse("tag=mytag,socket=/tmp/sock,msix_vectors=2")?,
+            FsConfig {
+                msix_vectors: Some(2),
+                ..fs_fixture()
+            }
+        );
+
+        Ok(())
+    }
+
+    fn pmem_fixture() -> PmemConfig {
+        PmemConfig {
+            file: PathBuf::from("/tmp/pmem"),
+            size: Some(128 << 20),
+            iommu: false,
+            discard_writes: false,
+            nvdimm: false,
+            id: None,
+            pci_segment: 0,
+        }
+    }
+
+    #[test]
+    fn test_pmem_parsing() -> Result<()> {
+        // Must always give a file and size
+        PmemConfig::parse("").unwrap_err();
+        PmemConfig::parse("size=128M").unwrap_err();
+        assert_eq!(
+            PmemConfig::parse("file=/tmp/pmem,size=128M")?,
+            pmem_fixture()
+        );
+        assert_eq!(
+            PmemConfig::parse("file=/tmp/pmem,size=128M,id=mypmem0")?,
+            PmemConfig {
+                id: Some("mypmem0".to_owned()),
+                ..pmem_fixture()
+            }
+        );
+        assert_eq!(
+            PmemConfig::parse("file=/tmp/pmem,size=128M,iommu=on,discard_writes=on")?,
+            PmemConfig {
+                discard_writes: true,
+                iommu: true,
+                ..pmem_fixture()
+            }
+        );
+        assert_eq!(
+            PmemConfig::parse("file=/tmp/pmem,size=128M,nvdimm=on")?,
+            PmemConfig {
+                nvdimm: true,
+                ..pmem_fixture()
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_console_parsing() -> Result<()> {
+        ConsoleConfig::parse("").unwrap_err();
+        ConsoleConfig::parse("badmode").unwrap_err();
+        assert_eq!(
+            ConsoleConfig::parse("off")?,
+            ConsoleConfig {
+                mode: ConsoleOutputMode::Off,
+                iommu: false,
+                file: None,
+                socket: None,
+                tee: Vec::new(),
+            }
+        );
+        assert_eq!(
+            ConsoleConfig::parse("pty")?,
+            ConsoleConfig {
+                mode: ConsoleOutputMode::Pty,
+                iommu: false,
+                file: None,
+                socket: None,
+                tee: Vec::new(),
+            }
+        );
+        assert_eq!(
+            ConsoleConfig::parse("tty")?,
+            ConsoleConfig {
+                mode: ConsoleOutputMode::Tty,
+                iommu: false,
+                file: None,
+                socket: None,
+                tee: Vec::new(),
+            }
+        );
+        assert_eq!(
+            ConsoleConfig::parse("null")?,
+            ConsoleConfig {
+                mode: ConsoleOutputMode::Null,
+                iommu: false,
+                file: None,
+                socket: None,
+                tee: Vec::new(),
+            }
+        );
+        assert_eq!(
+            ConsoleConfig::parse("file=/tmp/console")?,
+            ConsoleConfig {
+                mode: ConsoleOutputMode::File,
+                iommu: false,
+                file: Some(PathBuf::from("/tmp/console")),
+                socket: None,
+                tee: Vec::new(),
+            }
+        );
+        assert_eq!(
+            ConsoleConfig::parse("null,iommu=on")?,
+            ConsoleConfig {
+                mode: ConsoleOutputMode::Null,
+                iommu: true,
+                file: None,
+                socket: None,
+                tee: Vec::new(),
+            }
+        );
+        assert_eq!(
+            ConsoleConfig::parse("file=/tmp/console,iommu=on")?,
+            ConsoleConfig {
+                mode: ConsoleOutputMode::File,
+                iommu: true,
+                file: Some(PathBuf::from("/tmp/console")),
+                socket: None,
+                tee: Vec::new(),
+            }
+        );
+        assert_eq!(
+            ConsoleConfig::parse("socket=/tmp/serial.sock,iommu=on")?,
+            ConsoleConfig {
+                mode: ConsoleOutputMode::Socket,
+                iommu: true,
+                file: None,
+                socket: Some(PathBuf::from("/tmp/serial.sock")),
+                tee: Vec::new(),
+            }
+        );
+        assert_eq!(
+            ConsoleConfig::parse(
+                "pty,tee=[file@/tmp/serial.log,socket@/tmp/serial-tee.sock,ring@64K]"
+            )?,
+            ConsoleConfig {
+                mode: ConsoleOutputMode::Pty,
+                iommu: false,
+                file: None,
+                socket: None,
+                tee: vec![
+                    ConsoleSink::File(PathBuf::from("/tmp/serial.log")),
+                    ConsoleSink::Socket(PathBuf::from("/tmp/serial-tee.sock")),
+                    ConsoleSink::Ring(64 << 10),
+                ],
+            }
+        );
+        ConsoleConfig::parse("tty,tee=[pipe@/tmp/serial]").unwrap_err();
+        ConsoleConfig::parse("tty,tee=[ring@big]").unwrap_err();
+        Ok(())
+    }
+
+    fn device_fixture() -> DeviceConfig {
+        DeviceConfig {
+            path: PathBuf::from("/path/to/device"),
+            id: None,
+            iommu: false,
+            pci_segment: 0,
+            x_nv_gpudirect_clique: None,
+            p2p_dma: true,
+            reset_method: VfioResetMethod::Auto,
+            reset_on_detach: false,
+            legacy_vga: false,
+            romfile: None,
+            sriov: None,
+        }
+    }
+
+    #[test]
+    fn test_device_parsing() -> Result<()> {
+        // Device must have a path provided
+        DeviceConfig::parse("").unwrap_err();
+        assert_eq!(
+            DeviceConfig::parse("path=/path/to/device")?,
+            device_fixture()
+        );
+
+        assert_eq!(
+            DeviceConfig::parse("path=/path/to/device,iommu=on")?,
+            DeviceConfig {
+                iommu: true,
+                ..device_fixture()
+            }
+        );
+
+        assert_eq!(
+            DeviceConfig::parse("path=/path/to/device,iommu=on,id=mydevice0")?,
+            DeviceConfig {
+                id: Some("mydevice0".to_owned()),
+                iommu: true,
+                ..device_fixture()
+            }
+        );
+
+        assert_eq!(
+            DeviceConfig::parse("path=/path/to/device,p2p_dma=off")?,
+            DeviceConfig {
+                p2p_dma: false,
+                ..device_fixture()
+            }
+        );
+
+        assert_eq!(
+            DeviceConfig::parse("path=/path/to/device,reset_method=bus,reset_on_detach=on")?,
+            DeviceConfig {
+                reset_method: VfioResetMethod::Bus,
+                reset_on_detach: true,
+                ..device_fixture()
+            }
+        );
+        assert_eq!(
+            DeviceConfig::parse("path=/path/to/device,reset_method=flr")?,
+            DeviceConfig {
+                reset_method: VfioResetMethod::Flr,
+                ..device_fixture()
+            }
+        );
+        assert_eq!(
+            DeviceConfig::parse("path=/path/to/device,reset_method=none")?,
+            DeviceConfig {
+                reset_method: VfioResetMethod::None,
+                ..device_fixture()
+            }
+        );
+        assert_eq!(
+            DeviceConfig::parse("path=/path/to/device,reset_method=auto")?,
+            device_fixture()
+        );
+        DeviceConfig::parse("path=/path/to/device,reset_method=sbr").unwrap_err();
+
+        assert_eq!(
+            DeviceConfig::parse("path=/path/to/device,romfile=/path/to/rom")?,
+            DeviceConfig {
+                romfile: Some(PathBuf::from("/path/to/rom")),
+                ..device_fixture()
+            }
+        );
+
+        assert_eq!(
+            DeviceConfig::parse("path=/path/to/device,legacy_vga=on")?,
+            DeviceConfig {
+                legacy_vga: true,
+                ..device_fixture()
+            }
+        );
+        assert_eq!(
+            DeviceConfig::parse("path=/path/to/device,legacy_vga=off")?,
+            device_fixture()
+        );
+        DeviceConfig::parse("path=/path/to/device,legacy_vga=vga").unwrap_err();
+
+        assert_eq!(
+            DeviceConfig::parse("path=/path/to/device,x_nv_gpudirect_clique=3")?,
+            DeviceConfig {
+                x_nv_gpudirect_clique: Some(3),
+                ..device_fixture()
             }
         );
+        DeviceConfig::parse("path=/path/to/device,x_nv_gpudirect_clique=256").unwrap_err();
+        DeviceConfig::parse("path=/path/to/device,x_nv_gpudirect_clique=-1").unwrap_err();
+
+        // The path of a virtual function is filled in once created
         assert_eq!(
-            CpusConfig::parse("boot=8,topology=2:2:1:2")?,
-            CpusConfig {
-                boot_vcpus: 8,
-                max_vcpus: 8,
-                topology: Some(CpuTopology {
-                    threads_per_core: 2,
-                    cores_per_die: 2,
-                    dies_per_package: 1,
-                    packages: 2
+            DeviceConfig::parse(
+                "sriov_pf=eth0,sriov_vf=3,sriov_mac=12:34:56:78:90:ab,sriov_vlan=100,sriov_trust=on"
+            )?,
+            DeviceConfig {
+                path: PathBuf::new(),
+                sriov: Some(SriovVfConfig {
+                    pf: "eth0".to_owned(),
+                    vf: 3,
+                    mac: Some(MacAddr::parse_str("12:34:56:78:90:ab").unwrap()),
+                    vlan: Some(100),
+                    trust: true,
                 }),
-                ..Default::default()
+                ..device_fixture()
             }
         );
+        assert!(matches!(
+            DeviceConfig::parse("sriov_pf=eth0"),
+            Err(Error::ParseDeviceSriovVfMissing)
+        ));
+        assert!(matches!(
+            DeviceConfig::parse("path=/path/to/device,sriov_vf=3"),
+            Err(Error::ParseDeviceSriovPfMissing)
+        ));
 
-        CpusConfig::parse("boot=8,topology=2:2:1").unwrap_err();
-        CpusConfig::parse("boot=8,topology=2:2:1:x").unwrap_err();
+        Ok(())
+    }
+
+    fn vdpa_fixture() -> VdpaConfig {
+        VdpaConfig {
+            path: PathBuf::from("/dev/vhost-vdpa"),
+            num_queues: 1,
+            iommu: false,
+            id: None,
+            pci_segment: 0,
+            msix_vectors: None,
+        }
+    }
+
+    #[test]
+    fn test_vdpa_parsing() -> Result<()> {
+        // path is required
+        VdpaConfig::parse("").unwrap_err();
+        assert_eq!(VdpaConfig::parse("path=/dev/vhost-vdpa")?, vdpa_fixture());
         assert_eq!(
-            CpusConfig::parse("boot=1,kvm_hyperv=on")?,
-            CpusConfig {
-                boot_vcpus: 1,
-                max_vcpus: 1,
-                kvm_hyperv: true,
-                ..Default::default()
+            VdpaConfig::parse("path=/dev/vhost-vdpa,num_queues=2,id=my_vdpa")?,
+            VdpaConfig {
+                num_queues: 2,
+                id: Some("my_vdpa".to_owned()),
+                ..vdpa_fixture()
             }
         );
         assert_eq!(
-            CpusConfig::parse("boot=2,affinity=[0@[0,2],1@[1,3]]")?,
-            CpusConfig {
-                boot_vcpus: 2,
-                max_vcpus: 2,
-                affinity: Some(vec![
-                    CpuAffinity {
-                        vcpu: 0,
-                        host_cpus: vec![0, 2],
-                    },
-                    CpuAffinity {
-                        vcpu: 1,
-                        host_cpus: vec![1, 3],
-                    }
-                ]),
-                ..Default::default()
-            },
+            VdpaConfig::parse("path=/dev/vhost-vdpa,msix_vectors=4")?,
+            VdpaConfig {
+                msix_vectors: Some(4),
+                ..vdpa_fixture()
+            }
         );
-
+        VdpaConfig::parse("path=/dev/vhost-vdpa,msix_vectors=many").unwrap_err();
         Ok(())
     }
 
     #[test]
-    fn test_mem_parsing() -> Result<()> {
-        assert_eq!(MemoryConfig::parse("", None)?, MemoryConfig::default());
-        // Default string
-        assert_eq!(
-            MemoryConfig::parse("size=512M", None)?,
-            MemoryConfig::default()
-        );
+    fn test_tpm_parsing() -> Result<()> {
+        // path is required
+        TpmConfig::parse("").unwrap_err();
         assert_eq!(
-            MemoryConfig::parse("size=512M,mergeable=on", None)?,
-            MemoryConfig {
-                size: 512 << 20,
-                mergeable: true,
-                ..Default::default()
+            TpmConfig::parse("socket=/var/run/tpm.sock")?,
+            TpmConfig {
+                socket: PathBuf::from("/var/run/tpm.sock"),
             }
         );
+        Ok(())
+    }
+
+    #[test]
+    fn test_vsock_parsing() -> Result<()> {
+        // socket and cid is required
+        VsockConfig::parse("").unwrap_err();
         assert_eq!(
-            MemoryConfig::parse("mergeable=on", None)?,
-            MemoryConfig {
-                mergeable: true,
-                ..Default::default()
+            VsockConfig::parse("socket=/tmp/sock,cid=3")?,
+            VsockConfig {
+                cid: 3,
+                socket: PathBuf::from("/tmp/sock"),
+                iommu: false,
+                id: None,
+                pci_segment: 0,
             }
         );
         assert_eq!(
-            MemoryConfig::parse("size=1G,mergeable=off", None)?,
-            MemoryConfig {
-                size: 1 << 30,
-                mergeable: false,
-                ..Default::default()
+            VsockConfig::parse("socket=/tmp/sock,cid=3,iommu=on")?,
+            VsockConfig {
+                cid: 3,
+                socket: PathBuf::from("/tmp/sock"),
+                iommu: true,
+                id: None,
+                pci_segment: 0,
             }
         );
+        Ok(())
+    }
+
+    #[test]
+    fn test_restore_parsing() -> Result<()> {
         assert_eq!(
-            MemoryConfig::parse("hotplug_method=acpi", None)?,
-            MemoryConfig {
-                ..Default::default()
+            RestoreConfig::parse("source_url=/path/to/snapshot")?,
+            RestoreConfig {
+                source_url: PathBuf::from("/path/to/snapshot"),
+                prefault: false,
+                net_fds: None,
+                clone: false,
+                disk_paths: None,
+                net_taps: None,
+                signing_key: None,
+                force: false,
             }
         );
         assert_eq!(
-            MemoryConfig::parse("hotplug_method=acpi,hotplug_size=512M", None)?,
-            MemoryConfig {
-                hotplug_size: Some(512 << 20),
-                ..Default::default()
+            RestoreConfig::parse(
+                "source_url=/path/to/snapshot,prefault=off,net_fds=[net0@[3,4],net1@[5,6,7,8]]"
+            )?,
+            RestoreConfig {
+                source_url: PathBuf::from("/path/to/snapshot"),
+                prefault: false,
+                net_fds: Some(vec![
+                    RestoredNetConfig {
+                        id: "net0".to_string(),
+                        num_fds: 2,
+                        fds: Some(vec![3, 4]),
+                    },
+                    RestoredNetConfig {
+                        id: "net1".to_string(),
+                        num_fds: 4,
+                        fds: Some(vec![5, 6, 7, 8]),
+                    }
+                ]),
+                clone: false,
+                disk_paths: None,
+                net_taps: None,
+                signing_key: None,
+                force: false,
             }
         );
         assert_eq!(
-            MemoryConfig::parse("hotplug_method=virtio-mem,hotplug_size=512M", None)?,
-            MemoryConfig {
-                hotplug_size: Some(512 << 20),
-                hotplug_method: HotplugMethod::VirtioMem,
-                ..Default::default()
+            RestoreConfig::parse(
+                "source_url=/path/to/snapshot,clone=on,disk_paths=[disk0@/path/to/overlay.qcow2],net_taps=[net0@tap1]"
+            )?,
+            RestoreConfig {
+                source_url: PathBuf::from("/path/to/snapshot"),
+                prefault: false,
+                net_fds: None,
+                clone: true,
+                disk_paths: Some(vec![RestoredDiskConfig {
+                    id: "disk0".to_string(),
+                    path: PathBuf::from("/path/to/overlay.qcow2"),
+                }]),
+                net_taps: Some(vec![RestoredTapConfig {
+                    id: "net0".to_string(),
+                    tap: "tap1".to_string(),
+                }]),
+                signing_key: None,
+                force: false,
             }
         );
         assert_eq!(
-            MemoryConfig::parse("hugepages=on,size=1G,hugepage_size=2M", None)?,
-            MemoryConfig {
-                hugepage_size: Some(2 << 20),
-                size: 1 << 30,
-                hugepages: true,
+            RestoreConfig::parse("source_url=/path/to/snapshot,signing_key=/path/to/key,force=on")?,
+            RestoreConfig {
+                source_url: PathBuf::from("/path/to/snapshot"),
+                signing_key: Some(PathBuf::from("/path/to/key")),
+                force: true,
                 ..Default::default()
             }
         );
-        Ok(())
-    }
+        // Parsing should fail as source_url is a required field
+        RestoreConfig::parse("prefault=off").unwrap_err();
+        Ok(())
+    }
+
+    #[test]
+    fn test_restore_config_validation() {
+        // interested in only VmConfig.net, so set rest to default values
+        let mut snapshot_vm_config = VmConfig {
+            cpus: CpusConfig::default(),
+            memory: MemoryConfig::default(),
+            payload: None,
+            rate_limit_groups: None,
+            disks: None,
+            rng: RngConfig::default(),
+            balloon: None,
+            fs: None,
+            pmem: None,
+            serial: default_serial(),
+            console: default_console(),
+            #[cfg(target_arch = "x86_64")]
+            debug_console: DebugConsoleConfig::default(),
+            devices: None,
+            user_devices: None,
+            vdpa: None,
+            vsock: None,
+            #[cfg(feature = "pvmemcontrol")]
+            pvmemcontrol: None,
+            pvpanic: false,
+            iommu: false,
+            #[cfg(target_arch = "x86_64")]
+            sgx_epc: None,
+            numa: None,
+            watchdog: false,
+            #[cfg(feature = "guest_debug")]
+            gdb: false,
+            pci_segments: None,
+            platform: None,
+            tpm: None,
+            scmi: None,
+            cloud_init: None,
+            imds: None,
+            netns: None,
+            guest_agent: None,
+            resources: None,
+            rtc: None,
+            restart_policy: None,
+            crash_dump: None,
+            io_threads: None,
+            preserved_fds: None,
+            net: Some(vec![
+                NetConfig {
+                    id: Some("net0".to_owned()),
+                    num_queues: 2,
+                    fds: Some(vec![-1, -1, -1, -1]),
+                    ..net_fixture()
+                },
+                NetConfig {
+                    id: Some("net1".to_owned()),
+                    num_queues: 1,
+                    fds: Some(vec![-1, -1]),
+                    ..net_fixture()
+                },
+                NetConfig {
+                    id: Some("net2".to_owned()),
+                    fds: None,
+                    ..net_fixture()
+                },
+            ]),
+            landlock_enable: false,
+            landlock_rules: None,
+        };
+
+        let valid_config = RestoreConfig {
+            source_url: PathBuf::from("/path/to/snapshot"),
+            prefault: false,
+            net_fds: Some(vec![
+                RestoredNetConfig {
+                    id: "net0".to_string(),
+                    num_fds: 4,
+                    fds: Some(vec![3, 4, 5, 6]),
+                },
+                RestoredNetConfig {
+                    id: "net1".to_string(),
+                    num_fds: 2,
+                    fds: Some(vec![7, 8]),
+                },
+            ]),
+            clone: false,
+            disk_paths: None,
+            net_taps: None,
+            signing_key: None,
+            force: false,
+        };
+        valid_config.validate(&snapshot_vm_config).unwrap();
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.net_fds = Some(vec![RestoredNetConfig {
+            id: "netx".to_string(),
+            num_fds: 4,
+            fds: Some(vec![3, 4, 5, 6]),
+        }]);
+        assert_eq!(
+            invalid_config.validate(&snapshot_vm_config),
+            Err(ValidationError::RestoreMissingRequiredNetId(
+                "net0".to_string()
+            ))
+        );
 
-    #[test]
-    fn test_rate_limit_group_parsing() -> Result<()> {
+        invalid_config.net_fds = Some(vec![
+            RestoredNetConfig {
+                id: "net0".to_string(),
+                num_fds: 4,
+                fds: Some(vec![3, 4, 5, 6]),
+            },
+            RestoredNetConfig {
+                id: "net0".to_string(),
+                num_fds: 4,
+                fds: Some(vec![3, 4, 5, 6]),
+            },
+        ]);
         assert_eq!(
-            RateLimiterGroupConfig::parse("id=group0,bw_size=1000,bw_refill_time=100")?,
-            RateLimiterGroupConfig {
-                id: "group0".to_string(),
-                rate_limiter_config: RateLimiterConfig {
-                    bandwidth: Some(TokenBucketConfig {
-                        size: 1000,
-                        one_time_burst: Some(0),
-                        refill_time: 100,
-                    }),
-                    ops: None,
-                }
-            }
+            invalid_config.validate(&snapshot_vm_config),
+            Err(ValidationError::IdentifierNotUnique("net0".to_string()))
         );
+
+        invalid_config.net_fds = Some(vec![RestoredNetConfig {
+            id: "net0".to_string(),
+            num_fds: 4,
+            fds: Some(vec![3, 4, 5, 6]),
+        }]);
         assert_eq!(
-            RateLimiterGroupConfig::parse("id=group0,ops_size=1000,ops_refill_time=100")?,
-            RateLimiterGroupConfig {
-                id: "group0".to_string(),
-                rate_limiter_config: RateLimiterConfig {
-                    bandwidth: None,
-                    ops: Some(TokenBucketConfig {
-                        size: 1000,
-                        one_time_burst: Some(0),
-                        refill_time: 100,
-                    }),
-                }
-            }
+            invalid_config.validate(&snapshot_vm_config),
+            Err(ValidationError::RestoreMissingRequiredNetId(
+                "net1".to_string()
+            ))
         );
-        Ok(())
-    }
 
-    #[test]
-    fn test_pci_segment_parsing() -> Result<()> {
+        invalid_config.net_fds = Some(vec![RestoredNetConfig {
+            id: "net0".to_string(),
+            num_fds: 2,
+            fds: Some(vec![3, 4]),
+        }]);
         assert_eq!(
-            PciSegmentConfig::parse("pci_segment=0")?,
-            PciSegmentConfig {
-                pci_segment: 0,
-                mmio32_aperture_weight: 1,
-                mmio64_aperture_weight: 1,
-            }
+            invalid_config.validate(&snapshot_vm_config),
+            Err(ValidationError::RestoreNetFdCountMismatch(
+                "net0".to_string(),
+                2,
+                4
+            ))
         );
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.disk_paths = Some(vec![
+            RestoredDiskConfig {
+                id: "disk0".to_string(),
+                path: PathBuf::from("/path/to/overlay0.qcow2"),
+            },
+            RestoredDiskConfig {
+                id: "disk0".to_string(),
+                path: PathBuf::from("/path/to/overlay1.qcow2"),
+            },
+        ]);
         assert_eq!(
-            PciSegmentConfig::parse(
-                "pci_segment=0,mmio32_aperture_weight=1,mmio64_aperture_weight=1"
-            )?,
-            PciSegmentConfig {
-                pci_segment: 0,
-                mmio32_aperture_weight: 1,
-                mmio64_aperture_weight: 1,
-            }
+            invalid_config.validate(&snapshot_vm_config),
+            Err(ValidationError::IdentifierNotUnique("disk0".to_string()))
         );
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.net_taps = Some(vec![
+            RestoredTapConfig {
+                id: "net0".to_string(),
+                tap: "tap1".to_string(),
+            },
+            RestoredTapConfig {
+                id: "net0".to_string(),
+                tap: "tap2".to_string(),
+            },
+        ]);
         assert_eq!(
-            PciSegmentConfig::parse("pci_segment=0,mmio32_aperture_weight=2")?,
-            PciSegmentConfig {
-                pci_segment: 0,
-                mmio32_aperture_weight: 2,
-                mmio64_aperture_weight: 1,
-            }
+            invalid_config.validate(&snapshot_vm_config),
+            Err(ValidationError::IdentifierNotUnique("net0".to_string()))
         );
+
+        // Disks and net devices can share an identifier.
+        let mut still_valid_config = valid_config.clone();
+        still_valid_config.clone = true;
+        still_valid_config.disk_paths = Some(vec![RestoredDiskConfig {
+            id: "dev0".to_string(),
+            path: PathBuf::from("/path/to/overlay0.qcow2"),
+        }]);
+        still_valid_config.net_taps = Some(vec![RestoredTapConfig {
+            id: "dev0".to_string(),
+            tap: "tap1".to_string(),
+        }]);
+        still_valid_config.validate(&snapshot_vm_config).unwrap();
+
+        let another_valid_config = RestoreConfig {
+            source_url: PathBuf::from("/path/to/snapshot"),
+            prefault: false,
+            net_fds: None,
+            clone: false,
+            disk_paths: None,
+            net_taps: None,
+            signing_key: None,
+            force: false,
+        };
+        snapshot_vm_config.net = Some(vec![NetConfig {
+            id: Some("net2".to_owned()),
+            fds: None,
+            ..net_fixture()
+        }]);
+        another_valid_config.validate(&snapshot_vm_config).unwrap();
+    }
+
+    fn platform_fixture() -> PlatformConfig {
+        PlatformConfig {
+            num_pci_segments: MAX_NUM_PCI_SEGMENTS,
+            max_pci_segments: None,
+            iommu_segments: None,
+            iommu_address_width_bits: MAX_IOMMU_ADDRESS_WIDTH_BITS,
+            serial_number: None,
+            uuid: None,
+            oem_strings: None,
+            boot_method: BootMethod::Both,
+            vmbus: false,
+            legacy_devices: true,
+            auto_numa: false,
+            host_numa_distances: false,
+            #[cfg(feature = "tdx")]
+            tdx: false,
+            #[cfg(feature = "tdx")]
+            tdx_l2_vms: 0,
+            #[cfg(feature = "sev_snp")]
+            sev_snp: false,
+            #[cfg(feature = "sev_snp")]
+            sev_snp_smt: true,
+            #[cfg(feature = "sev_snp")]
+            sev_snp_debug: false,
+            #[cfg(feature = "sev_snp")]
+            sev_snp_migrate_ma: false,
+        }
+    }
+
+    fn numa_fixture() -> NumaConfig {
+        NumaConfig {
+            guest_numa_id: 0,
+            cpus: None,
+            distances: None,
+            memory_zones: None,
+            #[cfg(target_arch = "x86_64")]
+            sgx_epc_sections: None,
+            pci_segments: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_payload_file() -> Result<()> {
+        assert_eq!(parse_payload_file(None)?, (None, None));
         assert_eq!(
-            PciSegmentConfig::parse("pci_segment=0,mmio64_aperture_weight=2")?,
-            PciSegmentConfig {
-                pci_segment: 0,
-                mmio32_aperture_weight: 1,
-                mmio64_aperture_weight: 2,
-            }
+            parse_payload_file(Some("/path/to/kernel"))?,
+            (Some(PathBuf::from("/path/to/kernel")), None)
         );
+        assert_eq!(parse_payload_file(Some("fd=3"))?, (None, Some(3)));
+        assert!(parse_payload_file(Some("fd=kernel")).is_err());
 
         Ok(())
     }
 
-    fn disk_fixture() -> DiskConfig {
-        DiskConfig {
-            path: Some(PathBuf::from("/path/to_file")),
-            readonly: false,
-            direct: false,
+    #[test]
+    fn test_config_validation() {
+        let mut valid_config = VmConfig {
+            cpus: CpusConfig {
+                boot_vcpus: 1,
+                max_vcpus: 1,
+                ..Default::default()
+            },
+            memory: MemoryConfig {
+                size: 536_870_912,
+                mergeable: false,
+                hotplug_method: HotplugMethod::Acpi,
+                hotplug_size: None,
+                hotplugged_size: None,
+                shared: false,
+                hugepages: false,
+                hugepage_size: None,
+                prefault: false,
+                zones: None,
+                thp: true,
+                thp_collapse: false,
+            },
+            payload: Some(PayloadConfig {
+                kernel: Some(PathBuf::from("/path/to/kernel")),
+                kernel_fd: None,
+                firmware: None,
+                firmware_fd: None,
+                cmdline: None,
+                initramfs: None,
+                initramfs_fd: None,
+                extra_initramfs: None,
+                #[cfg(feature = "igvm")]
+                igvm: None,
+                #[cfg(feature = "igvm")]
+                igvm_fd: None,
+                #[cfg(feature = "sev_snp")]
+                host_data: Some(
+                    "243eb7dc1a21129caa91dcbb794922b933baecb5823a377eb431188673288c07".to_string(),
+                ),
+                #[cfg(feature = "sev_snp")]
+                id_block: None,
+                #[cfg(feature = "sev_snp")]
+                id_auth: None,
+                #[cfg(feature = "sev_snp")]
+                snp_certs: None,
+                fallback_firmware: None,
+            }),
+            rate_limit_groups: None,
+            disks: None,
+            net: None,
+            rng: RngConfig {
+                src: PathBuf::from("/dev/urandom"),
+                iommu: false,
+            },
+            balloon: None,
+            fs: None,
+            pmem: None,
+            serial: ConsoleConfig {
+                file: None,
+                mode: ConsoleOutputMode::Null,
+                iommu: false,
+                socket: None,
+                tee: Vec::new(),
+            },
+            console: ConsoleConfig {
+                file: None,
+                mode: ConsoleOutputMode::Tty,
+                iommu: false,
+                socket: None,
+                tee: Vec::new(),
+            },
+            #[cfg(target_arch = "x86_64")]
+            debug_console: DebugConsoleConfig::default(),
+            devices: None,
+            user_devices: None,
+            vdpa: None,
+            vsock: None,
+            #[cfg(feature = "pvmemcontrol")]
+            pvmemcontrol: None,
+            pvpanic: false,
             iommu: false,
-            num_queues: 1,
-            queue_size: 128,
-            vhost_user: false,
-            vhost_socket: None,
-            id: None,
-            disable_io_uring: false,
-            disable_aio: false,
-            rate_limit_group: None,
-            rate_limiter_config: None,
-            pci_segment: 0,
-            serial: None,
-            queue_affinity: None,
-        }
-    }
+            #[cfg(target_arch = "x86_64")]
+            sgx_epc: None,
+            numa: None,
+            watchdog: false,
+            #[cfg(feature = "guest_debug")]
+            gdb: false,
+            pci_segments: None,
+            platform: None,
+            tpm: None,
+            scmi: None,
+            cloud_init: None,
+            imds: None,
+            netns: None,
+            guest_agent: None,
+            resources: None,
+            rtc: None,
+            restart_policy: None,
+            crash_dump: None,
+            io_threads: None,
+            preserved_fds: None,
+            landlock_enable: false,
+            landlock_rules: None,
+        };
 
-    #[test]
-    fn test_disk_parsing() -> Result<()> {
-        assert_eq!(
-            DiskConfig::parse("path=/path/to_file")?,
-            DiskConfig { ..disk_fixture() }
-        );
-        assert_eq!(
-            DiskConfig::parse("path=/path/to_file,id=mydisk0")?,
-            DiskConfig {
-                id: Some("mydisk0".to_owned()),
-                ..disk_fixture()
-            }
-        );
+        valid_config.validate().unwrap();
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.serial.mode = ConsoleOutputMode::Tty;
+        invalid_config.console.mode = ConsoleOutputMode::Tty;
+        valid_config.validate().unwrap();
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.payload = None;
         assert_eq!(
-            DiskConfig::parse("vhost_user=true,socket=/tmp/sock")?,
-            DiskConfig {
-                path: None,
-                vhost_socket: Some(String::from("/tmp/sock")),
-                vhost_user: true,
-                ..disk_fixture()
-            }
+            invalid_config.validate(),
+            Err(ValidationError::KernelMissing)
         );
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.payload.as_mut().unwrap().extra_initramfs =
+            Some(vec![PathBuf::from("/path/to/seed")]);
         assert_eq!(
-            DiskConfig::parse("path=/path/to_file,iommu=on")?,
-            DiskConfig {
-                iommu: true,
-                ..disk_fixture()
-            }
+            invalid_config.validate(),
+            Err(ValidationError::ExtraInitramfsWithoutInitramfs)
         );
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.payload.as_mut().unwrap().kernel_fd = Some(-1);
         assert_eq!(
-            DiskConfig::parse("path=/path/to_file,iommu=on,queue_size=256")?,
-            DiskConfig {
-                iommu: true,
-                queue_size: 256,
-                ..disk_fixture()
-            }
+            invalid_config.validate(),
+            Err(ValidationError::PayloadPathAndFd("kernel".to_string()))
         );
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.payload.as_mut().unwrap().fallback_firmware = Some(FallbackFirmwareConfig {
+            path: PathBuf::from("/path/to/fallback"),
+            timeout: DEFAULT_FALLBACK_FIRMWARE_TIMEOUT,
+        });
         assert_eq!(
-            DiskConfig::parse("path=/path/to_file,iommu=on,queue_size=256,num_queues=4")?,
-            DiskConfig {
-                iommu: true,
-                queue_size: 256,
-                num_queues: 4,
-                ..disk_fixture()
-            }
+            invalid_config.validate(),
+            Err(ValidationError::FallbackFirmwareWithoutFirmware)
         );
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.serial.mode = ConsoleOutputMode::File;
+        invalid_config.serial.file = None;
         assert_eq!(
-            DiskConfig::parse("path=/path/to_file,direct=on")?,
-            DiskConfig {
-                direct: true,
-                ..disk_fixture()
-            }
+            invalid_config.validate(),
+            Err(ValidationError::ConsoleFileMissing)
         );
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.serial.mode = ConsoleOutputMode::Off;
+        invalid_config.serial.tee = vec![ConsoleSink::Ring(4096)];
         assert_eq!(
-            DiskConfig::parse("path=/path/to_file,serial=test")?,
-            DiskConfig {
-                serial: Some(String::from("test")),
-                ..disk_fixture()
-            }
+            invalid_config.validate(),
+            Err(ValidationError::ConsoleTeeWithoutOutput)
         );
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.console.tee = vec![ConsoleSink::Ring(4096), ConsoleSink::Ring(8192)];
         assert_eq!(
-            DiskConfig::parse("path=/path/to_file,rate_limit_group=group0")?,
-            DiskConfig {
-                rate_limit_group: Some("group0".to_string()),
-                ..disk_fixture()
-            }
+            invalid_config.validate(),
+            Err(ValidationError::ConsoleTeeMultipleRings)
         );
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.serial.tee = vec![ConsoleSink::Ring(0)];
         assert_eq!(
-            DiskConfig::parse("path=/path/to_file,queue_affinity=[0@[1],1@[2],2@[3,4],3@[5-8]]")?,
-            DiskConfig {
-                queue_affinity: Some(vec![
-                    VirtQueueAffinity {
-                        queue_index: 0,
-                        host_cpus: vec![1],
-                    },
-                    VirtQueueAffinity {
-                        queue_index: 1,
-                        host_cpus: vec![2],
-                    },
-                    VirtQueueAffinity {
-                        queue_index: 2,
-                        host_cpus: vec![3, 4],
-                    },
-                    VirtQueueAffinity {
-                        queue_index: 3,
-                        host_cpus: vec![5, 6, 7, 8],
-                    }
-                ]),
-                ..disk_fixture()
-            }
+            invalid_config.validate(),
+            Err(ValidationError::InvalidConsoleRingSize)
         );
-        Ok(())
-    }
 
-    fn net_fixture() -> NetConfig {
-        NetConfig {
-            tap: None,
-            ip: IpAddr::V4(Ipv4Addr::new(192, 168, 249, 1)),
-            mask: IpAddr::V4(Ipv4Addr::new(255, 255, 255, 0)),
-            mac: MacAddr::parse_str("de:ad:be:ef:12:34").unwrap(),
-            host_mac: Some(MacAddr::parse_str("12:34:de:ad:be:ef").unwrap()),
-            mtu: None,
-            iommu: false,
-            num_queues: 2,
-            queue_size: 256,
-            vhost_user: false,
-            vhost_socket: None,
-            vhost_mode: VhostMode::Client,
-            id: None,
-            fds: None,
-            rate_limiter_config: None,
-            pci_segment: 0,
-            offload_tso: true,
-            offload_ufo: true,
-            offload_csum: true,
-        }
-    }
+        let mut still_valid_config = valid_config.clone();
+        still_valid_config.serial.tee = vec![
+            ConsoleSink::File(PathBuf::from("/tmp/serial.log")),
+            ConsoleSink::Ring(4096),
+        ];
+        still_valid_config.validate().unwrap();
 
-    #[test]
-    fn test_net_parsing() -> Result<()> {
-        // mac address is random
+        let mut invalid_config = valid_config.clone();
+        invalid_config.cpus.max_vcpus = 16;
+        invalid_config.cpus.boot_vcpus = 32;
         assert_eq!(
-            NetConfig::parse("mac=de:ad:be:ef:12:34,host_mac=12:34:de:ad:be:ef")?,
-            net_fixture(),
+            invalid_config.validate(),
+            Err(ValidationError::CpusMaxLowerThanBoot)
         );
 
+        let mut invalid_config = valid_config.clone();
+        invalid_config.cpus.max_vcpus = 16;
+        invalid_config.cpus.boot_vcpus = 16;
+        invalid_config.cpus.topology = Some(CpuTopology {
+            threads_per_core: 2,
+            cores_per_die: 8,
+            dies_per_package: 1,
+            packages: 2,
+        });
         assert_eq!(
-            NetConfig::parse("mac=de:ad:be:ef:12:34,host_mac=12:34:de:ad:be:ef,id=mynet0")?,
-            NetConfig {
-                id: Some("mynet0".to_owned()),
-                ..net_fixture()
-            }
+            invalid_config.validate(),
+            Err(ValidationError::CpuTopologyCount)
         );
 
+        let mut invalid_config = valid_config.clone();
+        invalid_config.disks = Some(vec![DiskConfig {
+            vhost_socket: Some("/path/to/sock".to_owned()),
+            path: Some(PathBuf::from("/path/to/image")),
+            ..disk_fixture()
+        }]);
         assert_eq!(
-            NetConfig::parse(
-                "mac=de:ad:be:ef:12:34,host_mac=12:34:de:ad:be:ef,tap=tap0,ip=192.168.100.1,mask=255.255.255.128"
-            )?,
-            NetConfig {
-                tap: Some("tap0".to_owned()),
-                ip: "192.168.100.1".parse().unwrap(),
-                mask: "255.255.255.128".parse().unwrap(),
-                ..net_fixture()
-            }
+            invalid_config.validate(),
+            Err(ValidationError::DiskSocketAndPath)
         );
 
+        let mut invalid_config = valid_config.clone();
+        invalid_config.memory.shared = true;
+        invalid_config.disks = Some(vec![DiskConfig {
+            path: None,
+            vhost_user: true,
+            ..disk_fixture()
+        }]);
         assert_eq!(
-            NetConfig::parse(
-                "mac=de:ad:be:ef:12:34,host_mac=12:34:de:ad:be:ef,vhost_user=true,socket=/tmp/sock"
-            )?,
-            NetConfig {
-                vhost_user: true,
-                vhost_socket: Some("/tmp/sock".to_owned()),
-                ..net_fixture()
-            }
+            invalid_config.validate(),
+            Err(ValidationError::VhostUserMissingSocket)
         );
 
+        let mut invalid_config = valid_config.clone();
+        invalid_config.disks = Some(vec![DiskConfig {
+            path: None,
+            vhost_user: true,
+            vhost_socket: Some("/path/to/sock".to_owned()),
+            ..disk_fixture()
+        }]);
         assert_eq!(
-            NetConfig::parse("mac=de:ad:be:ef:12:34,host_mac=12:34:de:ad:be:ef,num_queues=4,queue_size=1024,iommu=on")?,
-            NetConfig {
-                num_queues: 4,
-                queue_size: 1024,
-                iommu: true,
-                ..net_fixture()
-            }
+            invalid_config.validate(),
+            Err(ValidationError::VhostUserRequiresSharedMemory)
         );
 
+        let mut still_valid_config = valid_config.clone();
+        still_valid_config.disks = Some(vec![DiskConfig {
+            path: None,
+            vhost_user: true,
+            vhost_socket: Some("/path/to/sock".to_owned()),
+            ..disk_fixture()
+        }]);
+        still_valid_config.memory.shared = true;
+        still_valid_config.validate().unwrap();
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.net = Some(vec![NetConfig {
+            vhost_user: true,
+            ..net_fixture()
+        }]);
         assert_eq!(
-            NetConfig::parse("mac=de:ad:be:ef:12:34,fd=[3,7],num_queues=4")?,
-            NetConfig {
-                host_mac: None,
-                fds: Some(vec![3, 7]),
-                num_queues: 4,
-                ..net_fixture()
-            }
+            invalid_config.validate(),
+            Err(ValidationError::VhostUserRequiresSharedMemory)
+        );
+
+        let mut still_valid_config = valid_config.clone();
+        still_valid_config.net = Some(vec![NetConfig {
+            vhost_user: true,
+            vhost_socket: Some("/path/to/sock".to_owned()),
+            ..net_fixture()
+        }]);
+        still_valid_config.memory.shared = true;
+        still_valid_config.validate().unwrap();
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.net = Some(vec![NetConfig {
+            fds: Some(vec![0]),
+            ..net_fixture()
+        }]);
+        assert_eq!(
+            invalid_config.validate(),
+            Err(ValidationError::VnetReservedFd)
         );
 
-        Ok(())
-    }
-
-    #[test]
-    fn test_parse_rng() -> Result<()> {
-        assert_eq!(RngConfig::parse("")?, RngConfig::default());
+        let mut invalid_config = valid_config.clone();
+        invalid_config.net = Some(vec![NetConfig {
+            offload_csum: false,
+            ..net_fixture()
+        }]);
         assert_eq!(
-            RngConfig::parse("src=/dev/random")?,
-            RngConfig {
-                src: PathBuf::from("/dev/random"),
-                ..Default::default()
-            }
+            invalid_config.validate(),
+            Err(ValidationError::NoHardwareChecksumOffload)
         );
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.net = Some(vec![NetConfig {
+            vhost_user: true,
+            vhost_socket: Some("/tmp/sock".to_owned()),
+            io_uring: true,
+            ..net_fixture()
+        }]);
+        invalid_config.memory.shared = true;
         assert_eq!(
-            RngConfig::parse("src=/dev/random,iommu=on")?,
-            RngConfig {
-                src: PathBuf::from("/dev/random"),
-                iommu: true,
-            }
+            invalid_config.validate(),
+            Err(ValidationError::VnetIoUringVhostUser)
         );
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.io_threads = Some(IoThreadsConfig {
+            count: 0,
+            affinity: None,
+        });
         assert_eq!(
-            RngConfig::parse("iommu=on")?,
-            RngConfig {
-                iommu: true,
-                ..Default::default()
-            }
+            invalid_config.validate(),
+            Err(ValidationError::IoThreadsZero)
         );
-        Ok(())
-    }
-
-    fn fs_fixture() -> FsConfig {
-        FsConfig {
-            socket: PathBuf::from("/tmp/sock"),
-            tag: "mytag".to_owned(),
-            num_queues: 1,
-            queue_size: 1024,
-            id: None,
-            pci_segment: 0,
-        }
-    }
 
-    #[test]
-    fn test_parse_fs() -> Result<()> {
-        // "tag" and "socket" must be supplied
-        FsConfig::parse("").unwrap_err();
-        FsConfig::parse("tag=mytag").unwrap_err();
-        FsConfig::parse("socket=/tmp/sock").unwrap_err();
-        assert_eq!(FsConfig::parse("tag=mytag,socket=/tmp/sock")?, fs_fixture());
+        let mut invalid_config = valid_config.clone();
+        invalid_config.crash_dump = Some(CrashDumpConfig {
+            path: PathBuf::from("/var/crash"),
+        });
         assert_eq!(
-            FsConfig::parse("tag=mytag,socket=/tmp/sock,num_queues=4,queue_size=1024")?,
-            FsConfig {
-                num_queues: 4,
-                queue_size: 1024,
-                ..fs_fixture()
-            }
+            invalid_config.validate(),
+            Err(ValidationError::CrashDumpWithoutPvpanic)
         );
+        invalid_config.pvpanic = true;
+        invalid_config.validate().unwrap();
 
-        Ok(())
-    }
-
-    fn pmem_fixture() -> PmemConfig {
-        PmemConfig {
-            file: PathBuf::from("/tmp/pmem"),
-            size: Some(128 << 20),
-            iommu: false,
-            discard_writes: false,
-            id: None,
-            pci_segment: 0,
-        }
-    }
-
-    #[test]
-    fn test_pmem_parsing() -> Result<()> {
-        // Must always give a file and size
-        PmemConfig::parse("").unwrap_err();
-        PmemConfig::parse("size=128M").unwrap_err();
+        let mut invalid_config = valid_config.clone();
+        invalid_config.io_threads = Some(IoThreadsConfig {
+            count: 2,
+            affinity: Some(vec![IoThreadAffinity {
+                io_thread: 2,
+                host_cpus: vec![0],
+            }]),
+        });
         assert_eq!(
-            PmemConfig::parse("file=/tmp/pmem,size=128M")?,
-            pmem_fixture()
+            invalid_config.validate(),
+            Err(ValidationError::InvalidIoThreadAffinity(2))
         );
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.net = Some(vec![NetConfig {
+            io_thread: Some(0),
+            ..net_fixture()
+        }]);
         assert_eq!(
-            PmemConfig::parse("file=/tmp/pmem,size=128M,id=mypmem0")?,
-            PmemConfig {
-                id: Some("mypmem0".to_owned()),
-                ..pmem_fixture()
-            }
+            invalid_config.validate(),
+            Err(ValidationError::InvalidIoThread(0))
         );
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.io_threads = Some(IoThreadsConfig {
+            count: 2,
+            affinity: None,
+        });
+        invalid_config.disks = Some(vec![DiskConfig {
+            io_thread: Some(1),
+            queue_affinity: Some(vec![VirtQueueAffinity {
+                queue_index: 0,
+                host_cpus: vec![1],
+            }]),
+            ..disk_fixture()
+        }]);
         assert_eq!(
-            PmemConfig::parse("file=/tmp/pmem,size=128M,iommu=on,discard_writes=on")?,
-            PmemConfig {
-                discard_writes: true,
-                iommu: true,
-                ..pmem_fixture()
-            }
+            invalid_config.validate(),
+            Err(ValidationError::IoThreadQueueAffinity)
         );
 
-        Ok(())
-    }
+        let mut still_valid_config = valid_config.clone();
+        still_valid_config.io_threads = Some(IoThreadsConfig {
+            count: 2,
+            affinity: None,
+        });
+        still_valid_config.disks = Some(vec![DiskConfig {
+            io_thread: Some(1),
+            ..disk_fixture()
+        }]);
+        still_valid_config.net = Some(vec![NetConfig {
+            io_thread: Some(0),
+            ..net_fixture()
+        }]);
+        still_valid_config.validate().unwrap();
 
-    #[test]
-    fn test_console_parsing() -> Result<()> {
-        ConsoleConfig::parse("").unwrap_err();
-        ConsoleConfig::parse("badmode").unwrap_err();
-        assert_eq!(
-            ConsoleConfig::parse("off")?,
-            ConsoleConfig {
-                mode: ConsoleOutputMode::Off,
-                iommu: false,
-                file: None,
-                socket: None,
-            }
-        );
+        let mut still_valid_config = valid_config.clone();
+        still_valid_config.net = Some(vec![NetConfig {
+            io_uring: true,
+            ..net_fixture()
+        }]);
+        still_valid_config.validate().unwrap();
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.disks = Some(vec![DiskConfig {
+            coalescing: Some(CoalescingConfig {
+                usecs: 0,
+                max_used: 16,
+            }),
+            ..disk_fixture()
+        }]);
         assert_eq!(
-            ConsoleConfig::parse("pty")?,
-            ConsoleConfig {
-                mode: ConsoleOutputMode::Pty,
-                iommu: false,
-                file: None,
-                socket: None,
-            }
+            invalid_config.validate(),
+            Err(ValidationError::CoalescingUsecsZero)
         );
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.memory.shared = true;
+        invalid_config.net = Some(vec![NetConfig {
+            vhost_user: true,
+            vhost_socket: Some("/tmp/sock".to_string()),
+            coalescing: Some(CoalescingConfig {
+                usecs: 50,
+                max_used: 0,
+            }),
+            ..net_fixture()
+        }]);
         assert_eq!(
-            ConsoleConfig::parse("tty")?,
-            ConsoleConfig {
-                mode: ConsoleOutputMode::Tty,
-                iommu: false,
-                file: None,
-                socket: None,
-            }
+            invalid_config.validate(),
+            Err(ValidationError::CoalescingVhostUser)
         );
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.disks = Some(vec![DiskConfig {
+            fair_queues: true,
+            ..disk_fixture()
+        }]);
         assert_eq!(
-            ConsoleConfig::parse("null")?,
-            ConsoleConfig {
-                mode: ConsoleOutputMode::Null,
-                iommu: false,
-                file: None,
-                socket: None,
-            }
+            invalid_config.validate(),
+            Err(ValidationError::FairQueuesWithoutRateLimiter)
         );
+
+        let mut still_valid_config = valid_config.clone();
+        still_valid_config.disks = Some(vec![DiskConfig {
+            fair_queues: true,
+            rate_limiter_config: Some(RateLimiterConfig {
+                bandwidth: Some(TokenBucketConfig {
+                    size: 1000,
+                    one_time_burst: None,
+                    refill_time: 100,
+                }),
+                ops: None,
+            }),
+            ..disk_fixture()
+        }]);
+        still_valid_config.validate().unwrap();
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.memory.shared = true;
+        invalid_config.disks = Some(vec![DiskConfig {
+            path: None,
+            vhost_user: true,
+            vhost_socket: Some("/tmp/sock".to_string()),
+            pause_on_path_failure: true,
+            ..disk_fixture()
+        }]);
         assert_eq!(
-            ConsoleConfig::parse("file=/tmp/console")?,
-            ConsoleConfig {
-                mode: ConsoleOutputMode::File,
-                iommu: false,
-                file: Some(PathBuf::from("/tmp/console")),
-                socket: None,
-            }
+            invalid_config.validate(),
+            Err(ValidationError::PauseOnPathFailureVhostUser)
         );
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.memory.shared = true;
+        invalid_config.disks = Some(vec![DiskConfig {
+            path: None,
+            vhost_user: true,
+            vhost_socket: Some("/tmp/sock".to_string()),
+            format: Some(ImageType::Qcow2),
+            ..disk_fixture()
+        }]);
         assert_eq!(
-            ConsoleConfig::parse("null,iommu=on")?,
-            ConsoleConfig {
-                mode: ConsoleOutputMode::Null,
-                iommu: true,
-                file: None,
-                socket: None,
-            }
+            invalid_config.validate(),
+            Err(ValidationError::FormatVhostUser)
         );
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.disks = Some(vec![DiskConfig {
+            direct: true,
+            cache: Some(CacheMode::Writeback),
+            ..disk_fixture()
+        }]);
         assert_eq!(
-            ConsoleConfig::parse("file=/tmp/console,iommu=on")?,
-            ConsoleConfig {
-                mode: ConsoleOutputMode::File,
-                iommu: true,
-                file: Some(PathBuf::from("/tmp/console")),
-                socket: None,
-            }
+            invalid_config.validate(),
+            Err(ValidationError::CacheModeDirect(CacheMode::Writeback))
         );
+
+        let mut still_valid_config = valid_config.clone();
+        still_valid_config.disks = Some(vec![DiskConfig {
+            direct: true,
+            cache: Some(CacheMode::Direct),
+            ..disk_fixture()
+        }]);
+        still_valid_config.validate().unwrap();
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.memory.shared = true;
+        invalid_config.disks = Some(vec![DiskConfig {
+            path: None,
+            vhost_user: true,
+            vhost_socket: Some("/tmp/sock".to_string()),
+            cache: Some(CacheMode::Direct),
+            ..disk_fixture()
+        }]);
         assert_eq!(
-            ConsoleConfig::parse("socket=/tmp/serial.sock,iommu=on")?,
-            ConsoleConfig {
-                mode: ConsoleOutputMode::Socket,
-                iommu: true,
-                file: None,
-                socket: Some(PathBuf::from("/tmp/serial.sock")),
-            }
+            invalid_config.validate(),
+            Err(ValidationError::CacheVhostUser)
         );
-        Ok(())
-    }
 
-    fn device_fixture() -> DeviceConfig {
-        DeviceConfig {
-            path: PathBuf::from("/path/to/device"),
-            id: None,
-            iommu: false,
-            pci_segment: 0,
-            x_nv_gpudirect_clique: None,
-        }
-    }
+        // The cache modes relying on the page cache open the image without
+        // O_DIRECT.
+        let disk = DiskConfig {
+            cache: Some(CacheMode::Direct),
+            ..disk_fixture()
+        };
+        assert!(disk.direct_io());
+        let disk = DiskConfig {
+            cache: Some(CacheMode::Unsafe),
+            ..disk_fixture()
+        };
+        assert!(!disk.direct_io());
 
-    #[test]
-    fn test_device_parsing() -> Result<()> {
-        // Device must have a path provided
-        DeviceConfig::parse("").unwrap_err();
+        let mut invalid_config = valid_config.clone();
+        invalid_config.disks = Some(vec![DiskConfig {
+            encryption: Some(DiskEncryption::Luks),
+            ..disk_fixture()
+        }]);
         assert_eq!(
-            DeviceConfig::parse("path=/path/to/device")?,
-            device_fixture()
+            invalid_config.validate(),
+            Err(ValidationError::EncryptionKey)
         );
 
+        let mut invalid_config = valid_config.clone();
+        invalid_config.disks = Some(vec![DiskConfig {
+            encryption: Some(DiskEncryption::Luks),
+            key_file: Some(PathBuf::from("/path/to_key")),
+            key_fd: Some(3),
+            ..disk_fixture()
+        }]);
         assert_eq!(
-            DeviceConfig::parse("path=/path/to/device,iommu=on")?,
-            DeviceConfig {
-                iommu: true,
-                ..device_fixture()
-            }
+            invalid_config.validate(),
+            Err(ValidationError::EncryptionKey)
         );
 
+        let mut invalid_config = valid_config.clone();
+        invalid_config.disks = Some(vec![DiskConfig {
+            key_file: Some(PathBuf::from("/path/to_key")),
+            ..disk_fixture()
+        }]);
         assert_eq!(
-            DeviceConfig::parse("path=/path/to/device,iommu=on,id=mydevice0")?,
-            DeviceConfig {
-                id: Some("mydevice0".to_owned()),
-                iommu: true,
-                ..device_fixture()
-            }
+            invalid_config.validate(),
+            Err(ValidationError::KeyWithoutEncryption)
         );
 
-        Ok(())
-    }
-
-    fn vdpa_fixture() -> VdpaConfig {
-        VdpaConfig {
-            path: PathBuf::from("/dev/vhost-vdpa"),
-            num_queues: 1,
-            iommu: false,
-            id: None,
-            pci_segment: 0,
-        }
-    }
-
-    #[test]
-    fn test_vdpa_parsing() -> Result<()> {
-        // path is required
-        VdpaConfig::parse("").unwrap_err();
-        assert_eq!(VdpaConfig::parse("path=/dev/vhost-vdpa")?, vdpa_fixture());
+        let mut invalid_config = valid_config.clone();
+        invalid_config.disks = Some(vec![DiskConfig {
+            encryption: Some(DiskEncryption::Luks),
+            key_file: Some(PathBuf::from("/path/to_key")),
+            format: Some(ImageType::Raw),
+            ..disk_fixture()
+        }]);
         assert_eq!(
-            VdpaConfig::parse("path=/dev/vhost-vdpa,num_queues=2,id=my_vdpa")?,
-            VdpaConfig {
-                num_queues: 2,
-                id: Some("my_vdpa".to_owned()),
-                ..vdpa_fixture()
-            }
+            invalid_config.validate(),
+            Err(ValidationError::EncryptionFormat)
         );
-        Ok(())
-    }
 
-    #[test]
-    fn test_tpm_parsing() -> Result<()> {
-        // path is required
-        TpmConfig::parse("").unwrap_err();
+        let mut invalid_config = valid_config.clone();
+        invalid_config.disks = Some(vec![DiskConfig {
+            encryption: Some(DiskEncryption::Luks),
+            key_file: Some(PathBuf::from("/path/to_key")),
+            cache: Some(CacheMode::Direct),
+            ..disk_fixture()
+        }]);
         assert_eq!(
-            TpmConfig::parse("socket=/var/run/tpm.sock")?,
-            TpmConfig {
-                socket: PathBuf::from("/var/run/tpm.sock"),
-            }
+            invalid_config.validate(),
+            Err(ValidationError::EncryptionDirect)
         );
-        Ok(())
-    }
 
-    #[test]
-    fn test_vsock_parsing() -> Result<()> {
-        // socket and cid is required
-        VsockConfig::parse("").unwrap_err();
+        let mut still_valid_config = valid_config.clone();
+        still_valid_config.disks = Some(vec![DiskConfig {
+            encryption: Some(DiskEncryption::Luks),
+            key_fd: Some(3),
+            ..disk_fixture()
+        }]);
+        still_valid_config.validate().unwrap();
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.disks = Some(vec![DiskConfig {
+            queue_size: QueueSize::Fixed(2),
+            ..disk_fixture()
+        }]);
         assert_eq!(
-            VsockConfig::parse("socket=/tmp/sock,cid=3")?,
-            VsockConfig {
-                cid: 3,
-                socket: PathBuf::from("/tmp/sock"),
-                iommu: false,
-                id: None,
-                pci_segment: 0,
-            }
+            invalid_config.validate(),
+            Err(ValidationError::InvalidQueueSize(2))
         );
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.memory.shared = true;
+        invalid_config.disks = Some(vec![DiskConfig {
+            path: None,
+            vhost_user: true,
+            vhost_socket: Some("/tmp/sock".to_string()),
+            queue_size: QueueSize::Auto,
+            ..disk_fixture()
+        }]);
         assert_eq!(
-            VsockConfig::parse("socket=/tmp/sock,cid=3,iommu=on")?,
-            VsockConfig {
-                cid: 3,
-                socket: PathBuf::from("/tmp/sock"),
-                iommu: true,
-                id: None,
-                pci_segment: 0,
-            }
+            invalid_config.validate(),
+            Err(ValidationError::QueueSizeAutoVhostUser)
         );
-        Ok(())
-    }
 
-    #[test]
-    fn test_restore_parsing() -> Result<()> {
+        let mut invalid_config = valid_config.clone();
+        invalid_config.memory.shared = true;
+        invalid_config.disks = Some(vec![DiskConfig {
+            path: None,
+            vhost_user: true,
+            vhost_socket: Some("/tmp/sock".to_string()),
+            discard: true,
+            ..disk_fixture()
+        }]);
         assert_eq!(
-            RestoreConfig::parse("source_url=/path/to/snapshot")?,
-            RestoreConfig {
-                source_url: PathBuf::from("/path/to/snapshot"),
-                prefault: false,
-                net_fds: None,
-            }
+            invalid_config.validate(),
+            Err(ValidationError::DiscardVhostUser)
         );
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.memory.shared = true;
+        invalid_config.disks = Some(vec![DiskConfig {
+            path: None,
+            vhost_user: true,
+            vhost_socket: Some("/tmp/sock".to_string()),
+            detect_zeroes: true,
+            ..disk_fixture()
+        }]);
         assert_eq!(
-            RestoreConfig::parse(
-                "source_url=/path/to/snapshot,prefault=off,net_fds=[net0@[3,4],net1@[5,6,7,8]]"
-            )?,
-            RestoreConfig {
-                source_url: PathBuf::from("/path/to/snapshot"),
-                prefault: false,
-                net_fds: Some(vec![
-                    RestoredNetConfig {
-                        id: "net0".to_string(),
-                        num_fds: 2,
-                        fds: Some(vec![3, 4]),
-                    },
-                    RestoredNetConfig {
-                        id: "net1".to_string(),
-                        num_fds: 4,
-                        fds: Some(vec![5, 6, 7, 8]),
-                    }
-                ]),
-            }
+            invalid_config.validate(),
+            Err(ValidationError::DiscardVhostUser)
         );
-        // Parsing should fail as source_url is a required field
-        RestoreConfig::parse("prefault=off").unwrap_err();
-        Ok(())
-    }
 
-    #[test]
-    fn test_restore_config_validation() {
-        // interested in only VmConfig.net, so set rest to default values
-        let mut snapshot_vm_config = VmConfig {
-            cpus: CpusConfig::default(),
-            memory: MemoryConfig::default(),
-            payload: None,
-            rate_limit_groups: None,
-            disks: None,
-            rng: RngConfig::default(),
-            balloon: None,
-            fs: None,
-            pmem: None,
-            serial: default_serial(),
-            console: default_console(),
-            #[cfg(target_arch = "x86_64")]
-            debug_console: DebugConsoleConfig::default(),
-            devices: None,
-            user_devices: None,
-            vdpa: None,
-            vsock: None,
-            #[cfg(feature = "pvmemcontrol")]
-            pvmemcontrol: None,
-            pvpanic: false,
-            iommu: false,
-            #[cfg(target_arch = "x86_64")]
-            sgx_epc: None,
-            numa: None,
-            watchdog: false,
-            #[cfg(feature = "guest_debug")]
-            gdb: false,
-            pci_segments: None,
-            platform: None,
-            tpm: None,
-            preserved_fds: None,
-            net: Some(vec![
-                NetConfig {
-                    id: Some("net0".to_owned()),
-                    num_queues: 2,
-                    fds: Some(vec![-1, -1, -1, -1]),
-                    ..net_fixture()
-                },
-                NetConfig {
-                    id: Some("net1".to_owned()),
-                    num_queues: 1,
-                    fds: Some(vec![-1, -1]),
-                    ..net_fixture()
-                },
-                NetConfig {
-                    id: Some("net2".to_owned()),
-                    fds: None,
-                    ..net_fixture()
-                },
-            ]),
-            landlock_enable: false,
-            landlock_rules: None,
-        };
+        let mut still_valid_config = valid_config.clone();
+        still_valid_config.disks = Some(vec![DiskConfig {
+            discard: true,
+            detect_zeroes: true,
+            ..disk_fixture()
+        }]);
+        still_valid_config.validate().unwrap();
 
-        let valid_config = RestoreConfig {
-            source_url: PathBuf::from("/path/to/snapshot"),
-            prefault: false,
-            net_fds: Some(vec![
-                RestoredNetConfig {
-                    id: "net0".to_string(),
-                    num_fds: 4,
-                    fds: Some(vec![3, 4, 5, 6]),
-                },
-                RestoredNetConfig {
-                    id: "net1".to_string(),
-                    num_fds: 2,
-                    fds: Some(vec![7, 8]),
-                },
-            ]),
-        };
-        valid_config.validate(&snapshot_vm_config).unwrap();
+        let mut invalid_config = valid_config.clone();
+        invalid_config.disks = Some(vec![DiskConfig {
+            interface: DiskInterface::Nvme,
+            direct: true,
+            ..disk_fixture()
+        }]);
+        assert_eq!(invalid_config.validate(), Err(ValidationError::NvmeDirect));
 
         let mut invalid_config = valid_config.clone();
-        invalid_config.net_fds = Some(vec![RestoredNetConfig {
-            id: "netx".to_string(),
-            num_fds: 4,
-            fds: Some(vec![3, 4, 5, 6]),
+        invalid_config.disks = Some(vec![DiskConfig {
+            interface: DiskInterface::Nvme,
+            num_queues: NVME_MAX_IO_QUEUES + 1,
+            ..disk_fixture()
         }]);
         assert_eq!(
-            invalid_config.validate(&snapshot_vm_config),
-            Err(ValidationError::RestoreMissingRequiredNetId(
-                "net0".to_string()
+            invalid_config.validate(),
+            Err(ValidationError::NvmeTooManyQueues(NVME_MAX_IO_QUEUES + 1))
+        );
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.disks = Some(vec![DiskConfig {
+            interface: DiskInterface::Nvme,
+            discard: true,
+            ..disk_fixture()
+        }]);
+        assert_eq!(
+            invalid_config.validate(),
+            Err(ValidationError::NvmeUnsupported(
+                "discard and detect_zeroes"
             ))
         );
 
-        invalid_config.net_fds = Some(vec![
-            RestoredNetConfig {
-                id: "net0".to_string(),
-                num_fds: 4,
-                fds: Some(vec![3, 4, 5, 6]),
-            },
-            RestoredNetConfig {
-                id: "net0".to_string(),
-                num_fds: 4,
-                fds: Some(vec![3, 4, 5, 6]),
-            },
-        ]);
+        let mut still_valid_config = valid_config.clone();
+        still_valid_config.disks = Some(vec![DiskConfig {
+            interface: DiskInterface::Nvme,
+            num_queues: 4,
+            ..disk_fixture()
+        }]);
+        still_valid_config.validate().unwrap();
+
+        let mut still_valid_config = valid_config.clone();
+        still_valid_config.disks = Some(vec![DiskConfig {
+            queue_size: QueueSize::Auto,
+            ..disk_fixture()
+        }]);
+        still_valid_config.validate().unwrap();
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.disks = Some(vec![DiskConfig {
+            io_engine_threads: Some(8),
+            ..disk_fixture()
+        }]);
         assert_eq!(
-            invalid_config.validate(&snapshot_vm_config),
-            Err(ValidationError::IdentifierNotUnique("net0".to_string()))
+            invalid_config.validate(),
+            Err(ValidationError::IoEngineThreadsWithoutThreads)
         );
 
-        invalid_config.net_fds = Some(vec![RestoredNetConfig {
-            id: "net0".to_string(),
-            num_fds: 4,
-            fds: Some(vec![3, 4, 5, 6]),
+        let mut invalid_config = valid_config.clone();
+        invalid_config.disks = Some(vec![DiskConfig {
+            io_engine: Some(IoEngine::Threads),
+            io_engine_threads: Some(0),
+            ..disk_fixture()
         }]);
         assert_eq!(
-            invalid_config.validate(&snapshot_vm_config),
-            Err(ValidationError::RestoreMissingRequiredNetId(
-                "net1".to_string()
-            ))
+            invalid_config.validate(),
+            Err(ValidationError::IoEngineThreadsZero)
         );
 
-        invalid_config.net_fds = Some(vec![RestoredNetConfig {
-            id: "net0".to_string(),
-            num_fds: 2,
-            fds: Some(vec![3, 4]),
+        let mut invalid_config = valid_config.clone();
+        invalid_config.disks = Some(vec![DiskConfig {
+            io_engine: Some(IoEngine::Aio),
+            format: Some(ImageType::Qcow2),
+            ..disk_fixture()
         }]);
         assert_eq!(
-            invalid_config.validate(&snapshot_vm_config),
-            Err(ValidationError::RestoreNetFdCountMismatch(
-                "net0".to_string(),
-                2,
-                4
+            invalid_config.validate(),
+            Err(ValidationError::IoEngineUnsupported(
+                IoEngine::Aio,
+                "qcow2 images"
             ))
         );
 
-        let another_valid_config = RestoreConfig {
-            source_url: PathBuf::from("/path/to/snapshot"),
-            prefault: false,
-            net_fds: None,
-        };
-        snapshot_vm_config.net = Some(vec![NetConfig {
-            id: Some("net2".to_owned()),
-            fds: None,
-            ..net_fixture()
+        let mut still_valid_config = valid_config.clone();
+        still_valid_config.disks = Some(vec![DiskConfig {
+            io_engine: Some(IoEngine::IoUring),
+            format: Some(ImageType::FixedVhd),
+            ..disk_fixture()
         }]);
-        another_valid_config.validate(&snapshot_vm_config).unwrap();
-    }
+        still_valid_config.validate().unwrap();
 
-    fn platform_fixture() -> PlatformConfig {
-        PlatformConfig {
-            num_pci_segments: MAX_NUM_PCI_SEGMENTS,
-            iommu_segments: None,
-            iommu_address_width_bits: MAX_IOMMU_ADDRESS_WIDTH_BITS,
-            serial_number: None,
-            uuid: None,
-            oem_strings: None,
-            #[cfg(feature = "tdx")]
-            tdx: false,
-            #[cfg(feature = "sev_snp")]
-            sev_snp: false,
-        }
-    }
+        let mut still_valid_config = valid_config.clone();
+        still_valid_config.disks = Some(vec![DiskConfig {
+            io_engine: Some(IoEngine::Sync),
+            format: Some(ImageType::Qcow2),
+            ..disk_fixture()
+        }]);
+        still_valid_config.validate().unwrap();
 
-    fn numa_fixture() -> NumaConfig {
-        NumaConfig {
-            guest_numa_id: 0,
-            cpus: None,
-            distances: None,
-            memory_zones: None,
-            #[cfg(target_arch = "x86_64")]
-            sgx_epc_sections: None,
-            pci_segments: None,
-        }
-    }
+        let mut still_valid_config = valid_config.clone();
+        still_valid_config.disks = Some(vec![DiskConfig {
+            io_uring_sqpoll: true,
+            io_uring_fixed_buffers: true,
+            ..disk_fixture()
+        }]);
+        still_valid_config.validate().unwrap();
 
-    #[test]
-    fn test_config_validation() {
-        let mut valid_config = VmConfig {
-            cpus: CpusConfig {
-                boot_vcpus: 1,
-                max_vcpus: 1,
-                ..Default::default()
-            },
-            memory: MemoryConfig {
-                size: 536_870_912,
-                mergeable: false,
-                hotplug_method: HotplugMethod::Acpi,
-                hotplug_size: None,
-                hotplugged_size: None,
-                shared: false,
-                hugepages: false,
-                hugepage_size: None,
-                prefault: false,
-                zones: None,
-                thp: true,
-            },
-            payload: Some(PayloadConfig {
-                kernel: Some(PathBuf::from("/path/to/kernel")),
-                firmware: None,
-                cmdline: None,
-                initramfs: None,
-                #[cfg(feature = "igvm")]
-                igvm: None,
-                #[cfg(feature = "sev_snp")]
-                host_data: Some(
-                    "243eb7dc1a21129caa91dcbb794922b933baecb5823a377eb431188673288c07".to_string(),
-                ),
-            }),
-            rate_limit_groups: None,
-            disks: None,
-            net: None,
-            rng: RngConfig {
-                src: PathBuf::from("/dev/urandom"),
-                iommu: false,
-            },
-            balloon: None,
-            fs: None,
-            pmem: None,
-            serial: ConsoleConfig {
-                file: None,
-                mode: ConsoleOutputMode::Null,
-                iommu: false,
-                socket: None,
-            },
-            console: ConsoleConfig {
-                file: None,
-                mode: ConsoleOutputMode::Tty,
-                iommu: false,
-                socket: None,
-            },
-            #[cfg(target_arch = "x86_64")]
-            debug_console: DebugConsoleConfig::default(),
-            devices: None,
-            user_devices: None,
-            vdpa: None,
-            vsock: None,
-            #[cfg(feature = "pvmemcontrol")]
-            pvmemcontrol: None,
-            pvpanic: false,
-            iommu: false,
-            #[cfg(target_arch = "x86_64")]
-            sgx_epc: None,
-            numa: None,
-            watchdog: false,
-            #[cfg(feature = "guest_debug")]
-            gdb: false,
-            pci_segments: None,
-            platform: None,
-            tpm: None,
-            preserved_fds: None,
-            landlock_enable: false,
-            landlock_rules: None,
-        };
+        let mut still_valid_config = valid_config.clone();
+        still_valid_config.disks = Some(vec![DiskConfig {
+            msix_vectors: Some(2),
+            ..disk_fixture()
+        }]);
+        still_valid_config.validate().unwrap();
 
-        valid_config.validate().unwrap();
+        let mut still_valid_config = valid_config.clone();
+        still_valid_config.disks = Some(vec![DiskConfig {
+            io_class: Some(IoClass::Idle),
+            ..disk_fixture()
+        }]);
+        still_valid_config.validate().unwrap();
 
         let mut invalid_config = valid_config.clone();
-        invalid_config.serial.mode = ConsoleOutputMode::Tty;
-        invalid_config.console.mode = ConsoleOutputMode::Tty;
-        valid_config.validate().unwrap();
+        invalid_config.disks = Some(vec![DiskConfig {
+            io_class: Some(IoClass::Idle),
+            io_prio: Some(0),
+            ..disk_fixture()
+        }]);
+        assert_eq!(
+            invalid_config.validate(),
+            Err(ValidationError::IoPrioWithoutIoClass)
+        );
 
         let mut invalid_config = valid_config.clone();
-        invalid_config.payload = None;
+        invalid_config.disks = Some(vec![DiskConfig {
+            io_class: Some(IoClass::BestEffort),
+            io_prio: Some(8),
+            ..disk_fixture()
+        }]);
         assert_eq!(
             invalid_config.validate(),
-            Err(ValidationError::KernelMissing)
+            Err(ValidationError::InvalidIoPrio(8))
+        );
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.net = Some(vec![NetConfig {
+            msix_vectors: Some(0),
+            ..net_fixture()
+        }]);
+        assert_eq!(
+            invalid_config.validate(),
+            Err(ValidationError::InvalidMsixVectors(0))
         );
 
         let mut invalid_config = valid_config.clone();
-        invalid_config.serial.mode = ConsoleOutputMode::File;
-        invalid_config.serial.file = None;
+        invalid_config.disks = Some(vec![DiskConfig {
+            msix_vectors: Some(MAX_MSIX_VECTORS + 1),
+            ..disk_fixture()
+        }]);
         assert_eq!(
             invalid_config.validate(),
-            Err(ValidationError::ConsoleFileMissing)
+            Err(ValidationError::InvalidMsixVectors(MAX_MSIX_VECTORS + 1))
         );
 
         let mut invalid_config = valid_config.clone();
-        invalid_config.cpus.max_vcpus = 16;
-        invalid_config.cpus.boot_vcpus = 32;
+        invalid_config.memory.shared = true;
+        invalid_config.fs = Some(vec![FsConfig {
+            msix_vectors: Some(0),
+            ..fs_fixture()
+        }]);
         assert_eq!(
             invalid_config.validate(),
-            Err(ValidationError::CpusMaxLowerThanBoot)
+            Err(ValidationError::InvalidMsixVectors(0))
         );
 
         let mut invalid_config = valid_config.clone();
-        invalid_config.cpus.max_vcpus = 16;
-        invalid_config.cpus.boot_vcpus = 16;
-        invalid_config.cpus.topology = Some(CpuTopology {
-            threads_per_core: 2,
-            cores_per_die: 8,
-            dies_per_package: 1,
-            packages: 2,
-        });
+        invalid_config.vdpa = Some(vec![VdpaConfig {
+            msix_vectors: Some(MAX_MSIX_VECTORS + 1),
+            ..vdpa_fixture()
+        }]);
         assert_eq!(
             invalid_config.validate(),
-            Err(ValidationError::CpuTopologyCount)
+            Err(ValidationError::InvalidMsixVectors(MAX_MSIX_VECTORS + 1))
         );
 
+        let mut still_valid_config = valid_config.clone();
+        still_valid_config.vdpa = Some(vec![VdpaConfig {
+            msix_vectors: Some(MAX_MSIX_VECTORS),
+            ..vdpa_fixture()
+        }]);
+        still_valid_config.validate().unwrap();
+
         let mut invalid_config = valid_config.clone();
         invalid_config.disks = Some(vec![DiskConfig {
-            vhost_socket: Some("/path/to/sock".to_owned()),
-            path: Some(PathBuf::from("/path/to/image")),
+            io_engine: Some(IoEngine::Aio),
+            io_uring_sqpoll: true,
             ..disk_fixture()
         }]);
         assert_eq!(
             invalid_config.validate(),
-            Err(ValidationError::DiskSocketAndPath)
+            Err(ValidationError::IoUringOptionsWithoutIoUring)
         );
 
+        let mut still_valid_config = valid_config.clone();
+        still_valid_config.net = Some(vec![NetConfig {
+            coalescing: Some(CoalescingConfig {
+                usecs: 50,
+                max_used: 0,
+            }),
+            ..net_fixture()
+        }]);
+        still_valid_config.validate().unwrap();
+
         let mut invalid_config = valid_config.clone();
+        invalid_config.net = Some(vec![NetConfig {
+            rate_limit_group: Some("group0".to_string()),
+            ..net_fixture()
+        }]);
+        assert_eq!(
+            invalid_config.validate(),
+            Err(ValidationError::InvalidRateLimiterGroup)
+        );
+
+        let mut still_valid_config = valid_config.clone();
+        still_valid_config.rate_limit_groups = Some(vec![RateLimiterGroupConfig::parse(
+            "id=group0,bw_size=1000,bw_refill_time=100",
+        )
+        .unwrap()]);
+        still_valid_config.net = Some(vec![
+            NetConfig {
+                id: Some("net0".to_string()),
+                rate_limit_group: Some("group0".to_string()),
+                ..net_fixture()
+            },
+            NetConfig {
+                id: Some("net1".to_string()),
+                rate_limit_group: Some("group0".to_string()),
+                weight: 300,
+                ..net_fixture()
+            },
+        ]);
+        still_valid_config.validate().unwrap();
+
+        let mut invalid_config = still_valid_config.clone();
+        invalid_config.net = Some(vec![NetConfig {
+            rate_limit_group: Some("group0".to_string()),
+            weight: 0,
+            ..net_fixture()
+        }]);
+        assert_eq!(
+            invalid_config.validate(),
+            Err(ValidationError::NetWeightZero)
+        );
+
+        let mut invalid_config = still_valid_config.clone();
         invalid_config.memory.shared = true;
-        invalid_config.disks = Some(vec![DiskConfig {
-            path: None,
+        invalid_config.net = Some(vec![NetConfig {
             vhost_user: true,
-            ..disk_fixture()
+            vhost_socket: Some("/tmp/sock".to_string()),
+            rate_limit_group: Some("group0".to_string()),
+            ..net_fixture()
         }]);
         assert_eq!(
             invalid_config.validate(),
-            Err(ValidationError::VhostUserMissingSocket)
+            Err(ValidationError::RateLimitGroupVhostUser)
         );
 
         let mut invalid_config = valid_config.clone();
-        invalid_config.disks = Some(vec![DiskConfig {
-            path: None,
+        invalid_config.net = Some(vec![NetConfig {
+            fds: Some(vec![3]),
+            tap_persist: Some(false),
+            ..net_fixture()
+        }]);
+        assert_eq!(
+            invalid_config.validate(),
+            Err(ValidationError::TapOptionsWithoutTap)
+        );
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.memory.shared = true;
+        invalid_config.net = Some(vec![NetConfig {
             vhost_user: true,
-            vhost_socket: Some("/path/to/sock".to_owned()),
-            ..disk_fixture()
+            vhost_socket: Some("/tmp/sock".to_string()),
+            tap_gid: Some(100),
+            ..net_fixture()
         }]);
         assert_eq!(
             invalid_config.validate(),
-            Err(ValidationError::VhostUserRequiresSharedMemory)
+            Err(ValidationError::TapOptionsWithoutTap)
         );
 
         let mut still_valid_config = valid_config.clone();
-        still_valid_config.disks = Some(vec![DiskConfig {
-            path: None,
-            vhost_user: true,
-            vhost_socket: Some("/path/to/sock".to_owned()),
-            ..disk_fixture()
+        still_valid_config.net = Some(vec![NetConfig {
+            tap: Some("tap0".to_string()),
+            tap_gid: Some(100),
+            ..net_fixture()
+        }]);
+        still_valid_config.validate().unwrap();
+
+        let mut still_valid_config = valid_config.clone();
+        still_valid_config.net = Some(vec![NetConfig {
+            tap_persist: Some(true),
+            tap_uid: Some(1000),
+            ..net_fixture()
+        }]);
+        still_valid_config.validate().unwrap();
+
+        let mut still_valid_config = valid_config.clone();
+        still_valid_config.net = Some(vec![NetConfig {
+            macvtap: Some("mvtap0".to_string()),
+            macvtap_link: Some("eth0".to_string()),
+            ..net_fixture()
         }]);
-        still_valid_config.memory.shared = true;
         still_valid_config.validate().unwrap();
 
         let mut invalid_config = valid_config.clone();
         invalid_config.net = Some(vec![NetConfig {
-            vhost_user: true,
+            tap: Some("tap0".to_string()),
+            macvtap: Some("mvtap0".to_string()),
             ..net_fixture()
         }]);
         assert_eq!(
             invalid_config.validate(),
-            Err(ValidationError::VhostUserRequiresSharedMemory)
+            Err(ValidationError::MacvtapWithOtherBackend)
+        );
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.net = Some(vec![NetConfig {
+            macvtap_link: Some("eth0".to_string()),
+            ..net_fixture()
+        }]);
+        assert_eq!(
+            invalid_config.validate(),
+            Err(ValidationError::MacvtapLinkWithoutMacvtap)
         );
 
         let mut still_valid_config = valid_config.clone();
         still_valid_config.net = Some(vec![NetConfig {
-            vhost_user: true,
-            vhost_socket: Some("/path/to/sock".to_owned()),
+            macvtap: Some("mvtap0".to_string()),
+            vhost: true,
             ..net_fixture()
         }]);
-        still_valid_config.memory.shared = true;
         still_valid_config.validate().unwrap();
 
         let mut invalid_config = valid_config.clone();
         invalid_config.net = Some(vec![NetConfig {
-            fds: Some(vec![0]),
+            vhost: true,
+            iommu: true,
             ..net_fixture()
         }]);
         assert_eq!(
             invalid_config.validate(),
-            Err(ValidationError::VnetReservedFd)
+            Err(ValidationError::VhostNetUnsupported("\"iommu\""))
         );
 
         let mut invalid_config = valid_config.clone();
         invalid_config.net = Some(vec![NetConfig {
-            offload_csum: false,
+            vhost: true,
+            coalescing: Some(CoalescingConfig {
+                usecs: 100,
+                max_used: 0,
+            }),
             ..net_fixture()
         }]);
         assert_eq!(
             invalid_config.validate(),
-            Err(ValidationError::NoHardwareChecksumOffload)
+            Err(ValidationError::VhostNetUnsupported(
+                "Notification coalescing"
+            ))
+        );
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.memory.shared = true;
+        invalid_config.net = Some(vec![NetConfig {
+            vhost: true,
+            vhost_user: true,
+            vhost_socket: Some("/tmp/sock".to_string()),
+            ..net_fixture()
+        }]);
+        assert_eq!(
+            invalid_config.validate(),
+            Err(ValidationError::VhostNetUnsupported("\"vhost_user\""))
+        );
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.net = Some(vec![NetConfig {
+            vhost: true,
+            io_uring: true,
+            ..net_fixture()
+        }]);
+        assert_eq!(
+            invalid_config.validate(),
+            Err(ValidationError::VhostNetUnsupported("\"io_uring\""))
+        );
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.net = Some(vec![NetConfig {
+            vhost: true,
+            rate_limiter_config: Some(RateLimiterConfig {
+                bandwidth: Some(TokenBucketConfig {
+                    size: 1000,
+                    one_time_burst: None,
+                    refill_time: 100,
+                }),
+                ops: None,
+            }),
+            ..net_fixture()
+        }]);
+        assert_eq!(
+            invalid_config.validate(),
+            Err(ValidationError::VhostNetUnsupported("Rate limiting"))
         );
 
         let mut invalid_config = valid_config.clone();
@@ -4302,6 +9052,18 @@ mod tests {
             Err(ValidationError::InvalidHugePageSize(3 << 20))
         );
 
+        let mut still_valid_config = valid_config.clone();
+        still_valid_config.memory.thp_collapse = true;
+        still_valid_config.validate().unwrap();
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.memory.thp = false;
+        invalid_config.memory.thp_collapse = true;
+        assert_eq!(
+            invalid_config.validate(),
+            Err(ValidationError::ThpCollapseWithoutThp)
+        );
+
         let mut still_valid_config = valid_config.clone();
         still_valid_config.platform = Some(platform_fixture());
         still_valid_config.validate().unwrap();
@@ -4325,6 +9087,64 @@ mod tests {
         });
         still_valid_config.validate().unwrap();
 
+        let mut still_valid_config = valid_config.clone();
+        still_valid_config.platform = Some(PlatformConfig {
+            num_pci_segments: 1,
+            max_pci_segments: Some(4),
+            iommu_segments: Some(vec![2, 3]),
+            ..platform_fixture()
+        });
+        still_valid_config.validate().unwrap();
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.platform = Some(PlatformConfig {
+            num_pci_segments: 4,
+            max_pci_segments: Some(2),
+            ..platform_fixture()
+        });
+        assert_eq!(
+            invalid_config.validate(),
+            Err(ValidationError::InvalidMaxPciSegments(2))
+        );
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.platform = Some(PlatformConfig {
+            num_pci_segments: 1,
+            max_pci_segments: Some(MAX_NUM_PCI_SEGMENTS + 1),
+            ..platform_fixture()
+        });
+        assert_eq!(
+            invalid_config.validate(),
+            Err(ValidationError::InvalidMaxPciSegments(
+                MAX_NUM_PCI_SEGMENTS + 1
+            ))
+        );
+
+        let mut still_valid_config = valid_config.clone();
+        still_valid_config.platform = Some(PlatformConfig {
+            num_pci_segments: 1,
+            max_pci_segments: Some(4),
+            ..platform_fixture()
+        });
+        assert_eq!(still_valid_config.max_pci_segments(), 4);
+        still_valid_config.platform = None;
+        assert_eq!(still_valid_config.max_pci_segments(), 1);
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.platform = Some(PlatformConfig {
+            num_pci_segments: 1,
+            max_pci_segments: Some(4),
+            ..platform_fixture()
+        });
+        invalid_config.disks = Some(vec![DiskConfig {
+            pci_segment: 2,
+            ..disk_fixture()
+        }]);
+        assert_eq!(
+            invalid_config.validate(),
+            Err(ValidationError::InvalidPciSegment(2))
+        );
+
         let mut invalid_config = valid_config.clone();
         invalid_config.platform = Some(PlatformConfig {
             iommu_segments: Some(vec![MAX_NUM_PCI_SEGMENTS + 1, MAX_NUM_PCI_SEGMENTS + 2]),
@@ -4335,17 +9155,130 @@ mod tests {
             Err(ValidationError::InvalidPciSegment(MAX_NUM_PCI_SEGMENTS + 1))
         );
 
-        let mut invalid_config = valid_config.clone();
-        invalid_config.platform = Some(PlatformConfig {
-            iommu_address_width_bits: MAX_IOMMU_ADDRESS_WIDTH_BITS + 1,
-            ..platform_fixture()
-        });
-        assert_eq!(
-            invalid_config.validate(),
-            Err(ValidationError::InvalidIommuAddressWidthBits(
-                MAX_IOMMU_ADDRESS_WIDTH_BITS + 1
-            ))
-        );
+        let mut invalid_config = valid_config.clone();
+        invalid_config.platform = Some(PlatformConfig {
+            iommu_address_width_bits: MAX_IOMMU_ADDRESS_WIDTH_BITS + 1,
+            ..platform_fixture()
+        });
+        assert_eq!(
+            invalid_config.validate(),
+            Err(ValidationError::InvalidIommuAddressWidthBits(
+                MAX_IOMMU_ADDRESS_WIDTH_BITS + 1
+            ))
+        );
+
+        #[cfg(feature = "tdx")]
+        {
+            let mut invalid_config = valid_config.clone();
+            invalid_config.platform = Some(PlatformConfig {
+                tdx_l2_vms: MAX_TDX_L2_VMS + 1,
+                ..platform_fixture()
+            });
+            assert_eq!(
+                invalid_config.validate(),
+                Err(ValidationError::TdxTooManyL2Vms(MAX_TDX_L2_VMS + 1))
+            );
+
+            let mut invalid_config = valid_config.clone();
+            invalid_config.platform = Some(PlatformConfig {
+                tdx_l2_vms: 1,
+                ..platform_fixture()
+            });
+            assert_eq!(
+                invalid_config.validate(),
+                Err(ValidationError::TdxL2VmsWithoutTdx)
+            );
+
+            PlatformConfig {
+                tdx: true,
+                tdx_l2_vms: MAX_TDX_L2_VMS,
+                ..platform_fixture()
+            }
+            .validate()
+            .unwrap();
+        }
+
+        #[cfg(not(target_arch = "aarch64"))]
+        {
+            let mut invalid_config = valid_config.clone();
+            invalid_config.platform = Some(PlatformConfig {
+                boot_method: BootMethod::Acpi,
+                ..platform_fixture()
+            });
+            assert_eq!(
+                invalid_config.validate(),
+                Err(ValidationError::BootMethodUnsupported)
+            );
+        }
+
+        #[cfg(target_arch = "aarch64")]
+        {
+            let mut config = valid_config.clone();
+            config.platform = Some(PlatformConfig {
+                boot_method: BootMethod::DeviceTree,
+                ..platform_fixture()
+            });
+            assert!(config.validate().is_ok());
+
+            let mut invalid_config = config.clone();
+            invalid_config.cpus.max_vcpus = invalid_config.cpus.boot_vcpus + 1;
+            assert_eq!(
+                invalid_config.validate(),
+                Err(ValidationError::BootMethodFdtHotplug)
+            );
+
+            let mut invalid_config = config.clone();
+            invalid_config.memory.hotplug_size = Some(1 << 30);
+            assert_eq!(
+                invalid_config.validate(),
+                Err(ValidationError::BootMethodFdtHotplug)
+            );
+
+            let mut config = valid_config.clone();
+            config.platform = Some(PlatformConfig {
+                boot_method: BootMethod::Acpi,
+                ..platform_fixture()
+            });
+            assert!(config.validate().is_ok());
+
+            let mut invalid_config = config.clone();
+            invalid_config.payload.as_mut().unwrap().firmware =
+                Some(PathBuf::from("/path/to/firmware"));
+            assert_eq!(
+                invalid_config.validate(),
+                Err(ValidationError::BootMethodAcpiFirmware)
+            );
+        }
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            let mut invalid_config = valid_config.clone();
+            invalid_config.platform = Some(PlatformConfig {
+                vmbus: true,
+                ..platform_fixture()
+            });
+            assert_eq!(
+                invalid_config.validate(),
+                Err(ValidationError::VmBusRequiresKvmHyperv)
+            );
+
+            let mut still_valid_config = invalid_config.clone();
+            still_valid_config.cpus.kvm_hyperv = true;
+            still_valid_config.validate().unwrap();
+        }
+
+        #[cfg(not(target_arch = "x86_64"))]
+        {
+            let mut invalid_config = valid_config.clone();
+            invalid_config.platform = Some(PlatformConfig {
+                legacy_devices: false,
+                ..platform_fixture()
+            });
+            assert_eq!(
+                invalid_config.validate(),
+                Err(ValidationError::LegacyDevicesUnsupported)
+            );
+        }
 
         let mut still_valid_config = valid_config.clone();
         still_valid_config.platform = Some(PlatformConfig {
@@ -4439,6 +9372,17 @@ mod tests {
             Err(ValidationError::OnIommuSegment(1))
         );
 
+        let mut invalid_config = valid_config.clone();
+        invalid_config.pmem = Some(vec![PmemConfig {
+            iommu: true,
+            nvdimm: true,
+            ..pmem_fixture()
+        }]);
+        assert_eq!(
+            invalid_config.validate(),
+            Err(ValidationError::NvdimmIommuUnsupported)
+        );
+
         let mut invalid_config = valid_config.clone();
         invalid_config.platform = Some(PlatformConfig {
             num_pci_segments: MAX_NUM_PCI_SEGMENTS,
@@ -4555,6 +9499,39 @@ mod tests {
             Err(ValidationError::PciSegmentReused(1, 0, 1))
         );
 
+        #[cfg(target_arch = "x86_64")]
+        {
+            let mut invalid_config = valid_config.clone();
+            invalid_config.numa = Some(vec![NumaConfig {
+                guest_numa_id: 0,
+                sgx_epc_sections: Some(vec!["epc0".to_owned()]),
+                ..numa_fixture()
+            }]);
+            assert_eq!(
+                invalid_config.validate(),
+                Err(ValidationError::UnknownSgxEpcSection("epc0".to_owned()))
+            );
+
+            invalid_config.sgx_epc = Some(vec![SgxEpcConfig {
+                id: "epc0".to_owned(),
+                size: 0x10_0000,
+                prefault: false,
+            }]);
+            invalid_config.numa.as_mut().unwrap().push(NumaConfig {
+                guest_numa_id: 1,
+                sgx_epc_sections: Some(vec!["epc0".to_owned()]),
+                ..numa_fixture()
+            });
+            assert_eq!(
+                invalid_config.validate(),
+                Err(ValidationError::SgxEpcSectionReused(
+                    "epc0".to_owned(),
+                    0,
+                    1
+                ))
+            );
+        }
+
         let mut invalid_config = valid_config.clone();
         invalid_config.pci_segments = Some(vec![PciSegmentConfig {
             pci_segment: 0,
@@ -4622,6 +9599,12 @@ mod tests {
             Err(ValidationError::InvalidRateLimiterGroup)
         );
 
+        let mut still_valid_config = invalid_config.clone();
+        still_valid_config.disks.as_mut().unwrap()[0].id = Some("disk0".into());
+        assert!(!still_valid_config.set_rate_limit_group("disk1", None));
+        assert!(still_valid_config.set_rate_limit_group("disk0", None));
+        still_valid_config.validate().unwrap();
+
         // Test serial length validation
         let mut valid_serial_config = valid_config.clone();
         valid_serial_config.disks = Some(vec![DiskConfig {
@@ -4695,19 +9678,194 @@ mod tests {
             },
         ]);
         invalid_config.validate().unwrap_err();
+
+        let sriov_fixture = SriovVfConfig {
+            pf: "eth0".to_owned(),
+            vf: 0,
+            mac: None,
+            vlan: None,
+            trust: false,
+        };
+        let mut still_valid_config = valid_config.clone();
+        still_valid_config.devices = Some(vec![
+            DeviceConfig {
+                path: PathBuf::new(),
+                sriov: Some(sriov_fixture.clone()),
+                ..device_fixture()
+            },
+            DeviceConfig {
+                path: PathBuf::new(),
+                sriov: Some(SriovVfConfig {
+                    vf: 1,
+                    vlan: Some(4094),
+                    ..sriov_fixture.clone()
+                }),
+                ..device_fixture()
+            },
+        ]);
+        still_valid_config.validate().unwrap();
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.devices = Some(vec![
+            DeviceConfig {
+                sriov: Some(sriov_fixture.clone()),
+                ..device_fixture()
+            },
+            DeviceConfig {
+                path: "/device1".into(),
+                sriov: Some(sriov_fixture.clone()),
+                ..device_fixture()
+            },
+        ]);
+        assert_eq!(
+            invalid_config.validate(),
+            Err(ValidationError::DuplicateSriovVf("eth0".to_owned(), 0))
+        );
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.devices = Some(vec![DeviceConfig {
+            sriov: Some(SriovVfConfig {
+                vlan: Some(4095),
+                ..sriov_fixture
+            }),
+            ..device_fixture()
+        }]);
+        assert_eq!(
+            invalid_config.validate(),
+            Err(ValidationError::InvalidSriovVlan(4095))
+        );
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.devices = Some(vec![DeviceConfig {
+            path: PathBuf::new(),
+            ..device_fixture()
+        }]);
+        assert_eq!(
+            invalid_config.validate(),
+            Err(ValidationError::DevicePathMissing)
+        );
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.devices = Some(vec![
+            DeviceConfig {
+                path: "/device1".into(),
+                legacy_vga: true,
+                ..device_fixture()
+            },
+            DeviceConfig {
+                path: "/device2".into(),
+                legacy_vga: true,
+                ..device_fixture()
+            },
+        ]);
+        assert_eq!(
+            invalid_config.validate(),
+            Err(ValidationError::MultipleLegacyVga)
+        );
+
+        let mut still_valid_config = valid_config.clone();
+        still_valid_config.devices = Some(vec![DeviceConfig {
+            romfile: Some(PathBuf::from("/path/to/rom")),
+            ..device_fixture()
+        }]);
+        still_valid_config.net = Some(vec![NetConfig {
+            romfile: Some(PathBuf::from("/path/to/rom")),
+            ..net_fixture()
+        }]);
+        still_valid_config.validate().unwrap();
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.devices = Some(vec![DeviceConfig {
+            x_nv_gpudirect_clique: Some(16),
+            ..device_fixture()
+        }]);
+        assert_eq!(
+            invalid_config.validate(),
+            Err(ValidationError::InvalidGpuDirectClique(16))
+        );
+
+        let mut still_valid_config = valid_config.clone();
+        still_valid_config.devices = Some(vec![DeviceConfig {
+            x_nv_gpudirect_clique: Some(MAX_GPUDIRECT_CLIQUE_ID),
+            ..device_fixture()
+        }]);
+        still_valid_config.validate().unwrap();
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.devices = Some(vec![
+            DeviceConfig {
+                path: "/device1".into(),
+                x_nv_gpudirect_clique: Some(1),
+                ..device_fixture()
+            },
+            DeviceConfig {
+                path: "/device2".into(),
+                iommu: true,
+                x_nv_gpudirect_clique: Some(1),
+                ..device_fixture()
+            },
+        ]);
+        assert_eq!(
+            invalid_config.validate(),
+            Err(ValidationError::GpuDirectCliqueIommuMismatch(1))
+        );
+
+        let mut still_valid_config = valid_config.clone();
+        still_valid_config.devices = Some(vec![
+            DeviceConfig {
+                path: "/device1".into(),
+                x_nv_gpudirect_clique: Some(1),
+                ..device_fixture()
+            },
+            DeviceConfig {
+                path: "/device2".into(),
+                iommu: true,
+                x_nv_gpudirect_clique: Some(2),
+                ..device_fixture()
+            },
+        ]);
+        still_valid_config.validate().unwrap();
+
+        let mut still_valid_config = valid_config.clone();
+        still_valid_config.devices = Some(vec![
+            DeviceConfig {
+                path: "/device1".into(),
+                legacy_vga: true,
+                ..device_fixture()
+            },
+            DeviceConfig {
+                path: "/device2".into(),
+                ..device_fixture()
+            },
+        ]);
+        still_valid_config.validate().unwrap();
+
         #[cfg(feature = "sev_snp")]
         {
             // Payload with empty host data
             let mut config_with_no_host_data = valid_config.clone();
             config_with_no_host_data.payload = Some(PayloadConfig {
                 kernel: Some(PathBuf::from("/path/to/kernel")),
+                kernel_fd: None,
                 firmware: None,
+                firmware_fd: None,
                 cmdline: None,
                 initramfs: None,
+                initramfs_fd: None,
+                extra_initramfs: None,
                 #[cfg(feature = "igvm")]
                 igvm: None,
+                #[cfg(feature = "igvm")]
+                igvm_fd: None,
                 #[cfg(feature = "sev_snp")]
                 host_data: Some("".to_string()),
+                #[cfg(feature = "sev_snp")]
+                id_block: None,
+                #[cfg(feature = "sev_snp")]
+                id_auth: None,
+                #[cfg(feature = "sev_snp")]
+                snp_certs: None,
+                fallback_firmware: None,
             });
             config_with_no_host_data.validate().unwrap_err();
 
@@ -4715,13 +9873,26 @@ mod tests {
             let mut valid_config_with_no_host_data = valid_config.clone();
             valid_config_with_no_host_data.payload = Some(PayloadConfig {
                 kernel: Some(PathBuf::from("/path/to/kernel")),
+                kernel_fd: None,
                 firmware: None,
+                firmware_fd: None,
                 cmdline: None,
                 initramfs: None,
+                initramfs_fd: None,
+                extra_initramfs: None,
                 #[cfg(feature = "igvm")]
                 igvm: None,
+                #[cfg(feature = "igvm")]
+                igvm_fd: None,
                 #[cfg(feature = "sev_snp")]
                 host_data: None,
+                #[cfg(feature = "sev_snp")]
+                id_block: None,
+                #[cfg(feature = "sev_snp")]
+                id_auth: None,
+                #[cfg(feature = "sev_snp")]
+                snp_certs: None,
+                fallback_firmware: None,
             });
             valid_config_with_no_host_data.validate().unwrap();
 
@@ -4729,19 +9900,169 @@ mod tests {
             let mut config_with_invalid_host_data = valid_config.clone();
             config_with_invalid_host_data.payload = Some(PayloadConfig {
                 kernel: Some(PathBuf::from("/path/to/kernel")),
+                kernel_fd: None,
                 firmware: None,
+                firmware_fd: None,
                 cmdline: None,
                 initramfs: None,
+                initramfs_fd: None,
+                extra_initramfs: None,
                 #[cfg(feature = "igvm")]
                 igvm: None,
+                #[cfg(feature = "igvm")]
+                igvm_fd: None,
                 #[cfg(feature = "sev_snp")]
                 host_data: Some(
                     "243eb7dc1a21129caa91dcbb794922b933baecb5823a377eb43118867328".to_string(),
                 ),
+                #[cfg(feature = "sev_snp")]
+                id_block: None,
+                #[cfg(feature = "sev_snp")]
+                id_auth: None,
+                #[cfg(feature = "sev_snp")]
+                snp_certs: None,
+                fallback_firmware: None,
             });
             config_with_invalid_host_data.validate().unwrap_err();
+
+            // Guest policy and ID block with SEV-SNP enabled
+            let mut still_valid_config = valid_config.clone();
+            still_valid_config.platform = Some(PlatformConfig {
+                sev_snp: true,
+                sev_snp_smt: false,
+                sev_snp_debug: true,
+                ..platform_fixture()
+            });
+            let payload = still_valid_config.payload.as_mut().unwrap();
+            payload.id_block = Some(PathBuf::from("/tmp/id_block"));
+            payload.id_auth = Some(PathBuf::from("/tmp/id_auth"));
+            still_valid_config.validate().unwrap();
+
+            // ID block without SEV-SNP enabled
+            let mut invalid_config = valid_config.clone();
+            invalid_config.payload.as_mut().unwrap().id_block =
+                Some(PathBuf::from("/tmp/id_block"));
+            assert_eq!(
+                invalid_config.validate(),
+                Err(ValidationError::SevSnpOptionsWithoutSevSnp)
+            );
+
+            // ID authentication information without ID block
+            let mut invalid_config = valid_config.clone();
+            invalid_config.payload.as_mut().unwrap().id_auth = Some(PathBuf::from("/tmp/id_auth"));
+            assert_eq!(
+                invalid_config.validate(),
+                Err(ValidationError::SevSnpIdAuthWithoutIdBlock)
+            );
+
+            // Guest policy without SEV-SNP enabled
+            let mut invalid_config = valid_config.clone();
+            invalid_config.platform = Some(PlatformConfig {
+                sev_snp_debug: true,
+                ..platform_fixture()
+            });
+            assert_eq!(
+                invalid_config.validate(),
+                Err(ValidationError::SevSnpOptionsWithoutSevSnp)
+            );
+
+            // Certificate table with SEV-SNP enabled
+            let mut still_valid_config = valid_config.clone();
+            still_valid_config.platform = Some(PlatformConfig {
+                sev_snp: true,
+                ..platform_fixture()
+            });
+            still_valid_config.payload.as_mut().unwrap().snp_certs =
+                Some(PathBuf::from("/tmp/snp_certs"));
+            still_valid_config.validate().unwrap();
+
+            // Certificate table without SEV-SNP enabled
+            let mut invalid_config = valid_config.clone();
+            invalid_config.payload.as_mut().unwrap().snp_certs =
+                Some(PathBuf::from("/tmp/snp_certs"));
+            assert_eq!(
+                invalid_config.validate(),
+                Err(ValidationError::SevSnpOptionsWithoutSevSnp)
+            );
         }
 
+        let mut invalid_config = valid_config.clone();
+        invalid_config.scmi = Some(ScmiConfig::parse("clocks=[0]").unwrap());
+        assert_eq!(
+            invalid_config.validate(),
+            Err(ValidationError::InvalidScmiClockRate)
+        );
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.devices = Some(vec![DeviceConfig {
+            x_nv_gpudirect_clique: Some(1),
+            p2p_dma: false,
+            ..device_fixture()
+        }]);
+        assert_eq!(
+            invalid_config.validate(),
+            Err(ValidationError::GpuDirectCliqueWithoutP2pDma(1))
+        );
+
+        let mut still_valid_config = valid_config.clone();
+        still_valid_config.scmi = Some(ScmiConfig::parse("clocks=[24000000],iommu=on").unwrap());
+        still_valid_config.validate().unwrap();
+        assert!(still_valid_config.iommu);
+
+        // Balloon resized automatically within bounds
+        let mut still_valid_config = valid_config.clone();
+        still_valid_config.balloon =
+            Some(BalloonConfig::parse("auto_resize=on,min_size=64M,max_size=256M").unwrap());
+        still_valid_config.validate().unwrap();
+
+        // Balloon features changed at runtime, the unset ones being kept
+        let balloon = still_valid_config
+            .set_balloon(Some(true), None)
+            .unwrap()
+            .clone();
+        assert!(balloon.deflate_on_oom);
+        assert!(!balloon.free_page_reporting);
+        assert!(balloon.auto_resize);
+        let mut no_balloon_config = valid_config.clone();
+        assert!(no_balloon_config
+            .set_balloon(Some(true), Some(true))
+            .is_none());
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.balloon = Some(BalloonConfig::parse("auto_resize=on").unwrap());
+        assert_eq!(
+            invalid_config.validate(),
+            Err(ValidationError::BalloonAutoResizeWithoutMaxSize)
+        );
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.balloon =
+            Some(BalloonConfig::parse("auto_resize=on,min_size=256M,max_size=64M").unwrap());
+        assert_eq!(
+            invalid_config.validate(),
+            Err(ValidationError::BalloonInvalidSizeRange(
+                256 << 20,
+                64 << 20
+            ))
+        );
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.balloon = Some(BalloonConfig::parse("auto_resize=on,max_size=1G").unwrap());
+        assert_eq!(
+            invalid_config.validate(),
+            Err(ValidationError::BalloonLargerThanRam(1 << 30, 536_870_912))
+        );
+
+        let mut invalid_config = valid_config.clone();
+        invalid_config.balloon = Some(
+            BalloonConfig::parse("auto_resize=on,max_size=256M,pressure_high=5,pressure_low=5")
+                .unwrap(),
+        );
+        assert_eq!(
+            invalid_config.validate(),
+            Err(ValidationError::BalloonInvalidPressure(5, 5))
+        );
+
         let mut still_valid_config = valid_config;
         // SAFETY: Safe as the file was just opened
         let fd1 = unsafe { libc::dup(File::open("/dev/null").unwrap().as_raw_fd()) };
@@ -4753,6 +10074,70 @@ mod tests {
         }
         let _still_valid_config = still_valid_config.clone();
     }
+    #[test]
+    fn test_validate_host() {
+        let kernel = std::env::temp_dir().join("ch-validate-host-kernel");
+        fs::write(&kernel, b"").unwrap();
+
+        let config: VmConfig = serde_json::from_value(serde_json::json!({
+            "payload": { "kernel": kernel },
+            "disks": [
+                { "path": "/path/to/missing/disk" },
+                { "path": "/path/to/vhost-user/disk", "vhost_user": true },
+            ],
+            "rng": { "src": "/dev/urandom" },
+        }))
+        .unwrap();
+        assert_eq!(
+            config.validate_host(),
+            vec![ValidationError::HostFileMissing(
+                "--disk",
+                PathBuf::from("/path/to/missing/disk")
+            )]
+        );
+
+        fs::remove_file(&kernel).unwrap();
+        assert_eq!(config.validate_host().len(), 2);
+    }
+
+    #[test]
+    fn test_disk_queue_size() {
+        assert_eq!(QueueSize::Auto.max_size(1024), 1024);
+        assert_eq!(QueueSize::Fixed(256).max_size(1024), 256);
+
+        let disk: DiskConfig = serde_json::from_value(serde_json::json!({
+            "path": "/path/to_file",
+            "queue_size": "auto",
+        }))
+        .unwrap();
+        assert_eq!(disk.queue_size, QueueSize::Auto);
+        assert_eq!(
+            serde_json::to_value(&disk).unwrap()["queue_size"],
+            serde_json::json!("auto")
+        );
+
+        let disk: DiskConfig = serde_json::from_value(serde_json::json!({
+            "path": "/path/to_file",
+            "queue_size": 256,
+        }))
+        .unwrap();
+        assert_eq!(disk.queue_size, QueueSize::Fixed(256));
+        assert_eq!(
+            serde_json::to_value(&disk).unwrap()["queue_size"],
+            serde_json::json!(256)
+        );
+
+        let disk: DiskConfig =
+            serde_json::from_value(serde_json::json!({ "path": "/path/to_file" })).unwrap();
+        assert_eq!(disk.queue_size, QueueSize::Fixed(DEFAULT_DISK_QUEUE_SIZE));
+
+        serde_json::from_value::<DiskConfig>(serde_json::json!({
+            "path": "/path/to_file",
+            "queue_size": "max",
+        }))
+        .unwrap_err();
+    }
+
     #[test]
     fn test_landlock_parsing() -> Result<()> {
         // should not be empty
@@ -11,19 +11,21 @@
 //
 
 use std::fs::{read_link, File, OpenOptions};
+use std::io::Write;
 use std::mem::zeroed;
 use std::os::fd::{AsRawFd, FromRawFd, RawFd};
 use std::os::unix::fs::OpenOptionsExt;
-use std::os::unix::net::UnixListener;
+use std::os::unix::net::{UnixListener, UnixStream};
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use std::{io, result};
 
 use libc::{cfmakeraw, isatty, tcgetattr, tcsetattr, termios, TCSANOW};
+use serial_buffer::{OutputSinks, RingBuffer};
 use thiserror::Error;
 
 use crate::sigwinch_listener::listen_for_sigwinch_on_tty;
-use crate::vm_config::ConsoleOutputMode;
+use crate::vm_config::{ConsoleOutputMode, ConsoleSink};
 use crate::Vmm;
 
 const TIOCSPTLCK: libc::c_int = 0x4004_5431;
@@ -65,12 +67,104 @@ pub enum ConsoleOutput {
     Off,
 }
 
+/// Additional outputs a console duplicates its output to.
+#[derive(Clone)]
+pub struct ConsoleTee {
+    pub sinks: OutputSinks,
+    /// Ring buffer among the sinks, read through the API.
+    pub ring: Option<RingBuffer>,
+}
+
 #[derive(Clone)]
 pub struct ConsoleInfo {
     pub console_main_fd: ConsoleOutput,
     pub serial_main_fd: ConsoleOutput,
     #[cfg(target_arch = "x86_64")]
     pub debug_main_fd: ConsoleOutput,
+    pub console_tee: ConsoleTee,
+    pub serial_tee: ConsoleTee,
+}
+
+// Unix socket streaming the output to the clients connected to it, which
+// are accepted as the output goes. A client not keeping up with the output
+// is disconnected rather than holding the console back.
+struct SocketSink {
+    listener: UnixListener,
+    path: PathBuf,
+    clients: Vec<UnixStream>,
+}
+
+impl Write for SocketSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        while let Ok((client, _)) = self.listener.accept() {
+            self.clients.push(client);
+        }
+
+        self.clients.retain(|client| {
+            // SAFETY: FFI call with a valid socket and buffer.
+            let ret = unsafe {
+                libc::send(
+                    client.as_raw_fd(),
+                    buf.as_ptr() as *const libc::c_void,
+                    buf.len(),
+                    libc::MSG_DONTWAIT | libc::MSG_NOSIGNAL,
+                )
+            };
+            ret == buf.len() as isize
+        });
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for SocketSink {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.path).ok();
+    }
+}
+
+fn create_console_tee(tee: &[ConsoleSink]) -> ConsoleDeviceResult<ConsoleTee> {
+    let mut sinks: Vec<Box<dyn Write + Send>> = Vec::new();
+    let mut ring = None;
+    for sink in tee {
+        match sink {
+            ConsoleSink::File(path) => {
+                let file = File::create(path).map_err(ConsoleDeviceError::CreateConsoleDevice)?;
+                sinks.push(Box::new(file));
+            }
+            ConsoleSink::Socket(path) => {
+                let listener =
+                    UnixListener::bind(path).map_err(ConsoleDeviceError::CreateConsoleDevice)?;
+                // SAFETY: FFI call with a valid socket.
+                let ret =
+                    unsafe { libc::fcntl(listener.as_raw_fd(), libc::F_SETFL, libc::O_NONBLOCK) };
+                if ret < 0 {
+                    return Err(ConsoleDeviceError::CreateConsoleDevice(
+                        io::Error::last_os_error(),
+                    ));
+                }
+                sinks.push(Box::new(SocketSink {
+                    listener,
+                    path: path.clone(),
+                    clients: Vec::new(),
+                }));
+            }
+            ConsoleSink::Ring(size) => {
+                let buffer = RingBuffer::new(*size as usize);
+                sinks.push(Box::new(buffer.clone()));
+                ring = Some(buffer);
+            }
+        }
+    }
+
+    Ok(ConsoleTee {
+        sinks: OutputSinks::new(sinks),
+        ring,
+    })
 }
 
 fn modify_mode<F: FnOnce(&mut termios)>(
@@ -293,7 +387,51 @@ pub(crate) fn pre_create_console_devices(vmm: &mut Vmm) -> ConsoleDeviceResult<C
             ConsoleOutputMode::Null => ConsoleOutput::Null,
             ConsoleOutputMode::Off => ConsoleOutput::Off,
         },
+        console_tee: create_console_tee(&vmconfig.console.tee)?,
+        serial_tee: create_console_tee(&vmconfig.serial.tee)?,
     };
 
     Ok(console_info)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use vmm_sys_util::tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_create_console_tee() {
+        let tee = create_console_tee(&[]).unwrap();
+        assert!(tee.sinks.is_empty());
+        assert!(tee.ring.is_none());
+
+        let dir = TempDir::new_with_prefix("/tmp/ch-console-tee").unwrap();
+        let file_path = dir.as_path().join("serial.log");
+        let socket_path = dir.as_path().join("serial.sock");
+        let tee = create_console_tee(&[
+            ConsoleSink::File(file_path.clone()),
+            ConsoleSink::Socket(socket_path.clone()),
+            ConsoleSink::Ring(4),
+        ])
+        .unwrap();
+
+        let mut client = UnixStream::connect(&socket_path).unwrap();
+        let mut out = tee.sinks.tee(None).unwrap();
+        out.write_all(b"boot").unwrap();
+        out.flush().unwrap();
+
+        assert_eq!(std::fs::read(&file_path).unwrap(), b"boot");
+        let mut buf = [0u8; 4];
+        client.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"boot");
+        assert_eq!(tee.ring.as_ref().unwrap().contents(), b"boot");
+
+        // The socket is removed along with its sink.
+        drop(out);
+        drop(tee);
+        assert!(!socket_path.exists());
+    }
+}
@@ -60,7 +60,7 @@ use linux_loader::elf::Elf64_Nhdr;
 use seccompiler::{apply_filter, SeccompAction};
 use thiserror::Error;
 use tracer::trace_scoped;
-use vm_device::BusDevice;
+use vm_device::{BusDevice, ExitReason, ExitReasonSlot};
 #[cfg(all(target_arch = "x86_64", feature = "guest_debug"))]
 use vm_memory::ByteValued;
 #[cfg(feature = "guest_debug")]
@@ -81,7 +81,7 @@ use crate::coredump::{
     NT_PRSTATUS,
 };
 #[cfg(feature = "guest_debug")]
-use crate::gdb::{get_raw_tid, Debuggable, DebuggableError};
+use crate::gdb::{Debuggable, DebuggableError};
 #[cfg(target_arch = "x86_64")]
 use crate::memory_manager::MemoryManager;
 use crate::seccomp_filters::{get_seccomp_filter, Thread};
@@ -147,6 +147,10 @@ pub enum Error {
     #[error("Error finalising vCPU")]
     VcpuArmFinalize(#[source] hypervisor::HypervisorCpuError),
 
+    #[cfg(target_arch = "aarch64")]
+    #[error("Error enabling the PTP service of vCPU")]
+    VcpuArmEnablePtp(#[source] hypervisor::HypervisorCpuError),
+
     #[cfg(target_arch = "aarch64")]
     #[error("Error initialising GICR base address")]
     VcpuSetGicrBaseAddr(#[source] hypervisor::HypervisorCpuError),
@@ -338,7 +342,7 @@ macro_rules! round_up {
 pub struct Vcpu {
     // The hypervisor abstracted CPU.
     vcpu: Arc<dyn hypervisor::Vcpu>,
-    id: u8,
+    id: u32,
     #[cfg(target_arch = "aarch64")]
     mpidr: u64,
     saved_state: Option<CpuState>,
@@ -356,8 +360,8 @@ impl Vcpu {
     /// * `vm_ops` - Optional object for exit handling.
     /// * `cpu_vendor` - CPU vendor as reported by __cpuid(0x0)
     pub fn new(
-        id: u8,
-        apic_id: u8,
+        id: u32,
+        apic_id: u32,
         vm: &Arc<dyn hypervisor::Vm>,
         vm_ops: Option<Arc<dyn VmOps>>,
         #[cfg(target_arch = "x86_64")] cpu_vendor: CpuVendor,
@@ -447,6 +451,10 @@ impl Vcpu {
 
         self.vcpu.vcpu_init(&kvi).map_err(Error::VcpuArmInit)?;
 
+        // Let the guest synchronize its clock with the host one through its
+        // ptp_kvm driver.
+        self.vcpu.enable_ptp().map_err(Error::VcpuArmEnablePtp)?;
+
         if sve_supported {
             let finalized_features = self.vcpu.vcpu_get_finalized_features();
             self.vcpu
@@ -520,20 +528,26 @@ pub struct CpuManager {
     vcpus_kill_signalled: Arc<AtomicBool>,
     vcpus_pause_signalled: Arc<AtomicBool>,
     vcpus_kick_signalled: Arc<AtomicBool>,
+    // Cause of the pending guest-initiated exit
+    exit_reason: ExitReasonSlot,
     exit_evt: EventFd,
     #[cfg_attr(target_arch = "aarch64", allow(dead_code))]
     reset_evt: EventFd,
+    #[cfg_attr(not(target_arch = "x86_64"), allow(dead_code))]
+    crash_evt: EventFd,
     #[cfg(feature = "guest_debug")]
     vm_debug_evt: EventFd,
+    #[cfg(feature = "guest_debug")]
+    debug_stops: Arc<Mutex<Vec<(usize, hypervisor::DebugExit)>>>,
     vcpu_states: Vec<VcpuState>,
-    selected_cpu: u8,
+    selected_cpu: u32,
     vcpus: Vec<Arc<Mutex<Vcpu>>>,
     seccomp_action: SeccompAction,
     vm_ops: Arc<dyn VmOps>,
     #[cfg_attr(target_arch = "aarch64", allow(dead_code))]
     acpi_address: Option<GuestAddress>,
-    proximity_domain_per_cpu: BTreeMap<u8, u32>,
-    affinity: BTreeMap<u8, Vec<usize>>,
+    proximity_domain_per_cpu: BTreeMap<u32, u32>,
+    affinity: BTreeMap<u32, Vec<usize>>,
     dynamic: bool,
     hypervisor: Arc<dyn hypervisor::Hypervisor>,
     #[cfg(feature = "sev_snp")]
@@ -555,11 +569,12 @@ impl BusDevice for CpuManager {
 
         match offset {
             CPU_SELECTION_OFFSET => {
-                data[0] = self.selected_cpu;
+                let len = data.len().min(4);
+                data[..len].copy_from_slice(&self.selected_cpu.to_le_bytes()[..len]);
             }
             CPU_STATUS_OFFSET => {
                 if self.selected_cpu < self.max_vcpus() {
-                    let state = &self.vcpu_states[usize::from(self.selected_cpu)];
+                    let state = &self.vcpu_states[self.selected_cpu as usize];
                     if state.active() {
                         data[0] |= 1 << CPU_ENABLE_FLAG;
                     }
@@ -585,11 +600,14 @@ impl BusDevice for CpuManager {
     fn write(&mut self, _base: u64, offset: u64, data: &[u8]) -> Option<Arc<Barrier>> {
         match offset {
             CPU_SELECTION_OFFSET => {
-                self.selected_cpu = data[0];
+                let mut selected_cpu = [0u8; 4];
+                let len = data.len().min(4);
+                selected_cpu[..len].copy_from_slice(&data[..len]);
+                self.selected_cpu = u32::from_le_bytes(selected_cpu);
             }
             CPU_STATUS_OFFSET => {
                 if self.selected_cpu < self.max_vcpus() {
-                    let state = &mut self.vcpu_states[usize::from(self.selected_cpu)];
+                    let state = &mut self.vcpu_states[self.selected_cpu as usize];
                     // The ACPI code writes back a 1 to acknowledge the insertion
                     if (data[0] & (1 << CPU_INSERTING_FLAG) == 1 << CPU_INSERTING_FLAG)
                         && state.inserting
@@ -680,6 +698,7 @@ impl CpuManager {
         vm: Arc<dyn hypervisor::Vm>,
         exit_evt: EventFd,
         reset_evt: EventFd,
+        crash_evt: EventFd,
         #[cfg(feature = "guest_debug")] vm_debug_evt: EventFd,
         hypervisor: &Arc<dyn hypervisor::Hypervisor>,
         seccomp_action: SeccompAction,
@@ -688,12 +707,12 @@ impl CpuManager {
         numa_nodes: &NumaNodes,
         #[cfg(feature = "sev_snp")] sev_snp_enabled: bool,
     ) -> Result<Arc<Mutex<CpuManager>>> {
-        if u32::from(config.max_vcpus) > hypervisor.get_max_vcpus() {
+        if config.max_vcpus > hypervisor.get_max_vcpus() {
             return Err(Error::MaximumVcpusExceeded);
         }
 
-        let mut vcpu_states = Vec::with_capacity(usize::from(config.max_vcpus));
-        vcpu_states.resize_with(usize::from(config.max_vcpus), VcpuState::default);
+        let mut vcpu_states = Vec::with_capacity(config.max_vcpus as usize);
+        vcpu_states.resize_with(config.max_vcpus as usize, VcpuState::default);
         let hypervisor_type = hypervisor.hypervisor_type();
         #[cfg(target_arch = "x86_64")]
         let cpu_vendor = hypervisor.get_cpu_vendor();
@@ -730,7 +749,7 @@ impl CpuManager {
             }
         }
 
-        let proximity_domain_per_cpu: BTreeMap<u8, u32> = {
+        let proximity_domain_per_cpu: BTreeMap<u32, u32> = {
             let mut cpu_list = Vec::new();
             for (proximity_domain, numa_node) in numa_nodes.iter() {
                 for cpu in numa_node.cpus.iter() {
@@ -765,13 +784,17 @@ impl CpuManager {
             vcpus_kill_signalled: Arc::new(AtomicBool::new(false)),
             vcpus_pause_signalled: Arc::new(AtomicBool::new(false)),
             vcpus_kick_signalled: Arc::new(AtomicBool::new(false)),
+            exit_reason: ExitReasonSlot::default(),
             vcpu_states,
             exit_evt,
             reset_evt,
+            crash_evt,
             #[cfg(feature = "guest_debug")]
             vm_debug_evt,
+            #[cfg(feature = "guest_debug")]
+            debug_stops: Arc::new(Mutex::new(Vec::new())),
             selected_cpu: 0,
-            vcpus: Vec::with_capacity(usize::from(config.max_vcpus)),
+            vcpus: Vec::with_capacity(config.max_vcpus as usize),
             seccomp_action,
             vm_ops,
             acpi_address: None,
@@ -789,6 +812,7 @@ impl CpuManager {
         &mut self,
         memory_manager: &Arc<Mutex<MemoryManager>>,
         hypervisor: &Arc<dyn hypervisor::Hypervisor>,
+        vmbus: bool,
         #[cfg(feature = "tdx")] tdx: bool,
     ) -> Result<()> {
         let sgx_epc_sections = memory_manager
@@ -806,6 +830,8 @@ impl CpuManager {
                     sgx_epc_sections,
                     phys_bits,
                     kvm_hyperv: self.config.kvm_hyperv,
+                    vmbus,
+                    msi_ext_dest_id: self.config.has_wide_apic_ids(),
                     #[cfg(feature = "tdx")]
                     tdx,
                     amx: self.config.features.amx,
@@ -817,19 +843,19 @@ impl CpuManager {
         Ok(())
     }
 
-    fn create_vcpu(&mut self, cpu_id: u8, snapshot: Option<Snapshot>) -> Result<Arc<Mutex<Vcpu>>> {
+    fn create_vcpu(&mut self, cpu_id: u32, snapshot: Option<Snapshot>) -> Result<Arc<Mutex<Vcpu>>> {
         info!("Creating vCPU: cpu_id = {}", cpu_id);
 
         #[cfg(target_arch = "x86_64")]
         let topology = self.get_vcpu_topology();
         #[cfg(target_arch = "x86_64")]
-        let x2apic_id = arch::x86_64::get_x2apic_id(cpu_id as u32, topology);
+        let x2apic_id = arch::x86_64::get_x2apic_id(cpu_id, topology);
         #[cfg(any(target_arch = "aarch64", target_arch = "riscv64"))]
-        let x2apic_id = cpu_id as u32;
+        let x2apic_id = cpu_id;
 
         let mut vcpu = Vcpu::new(
             cpu_id,
-            x2apic_id as u8,
+            x2apic_id,
             &self.vm,
             Some(self.vm_ops.clone()),
             #[cfg(target_arch = "x86_64")]
@@ -883,8 +909,10 @@ impl CpuManager {
         assert!(!self.cpuid.is_empty());
 
         #[cfg(target_arch = "x86_64")]
+        // Without any explicit topology, all the vCPUs are exposed as cores
+        // of a single die, as long as their number fits in the topology.
         let topology = self.config.topology.clone().map_or_else(
-            || Some((1, self.boot_vcpus(), 1)),
+            || u8::try_from(self.boot_vcpus()).ok().map(|n| (1, n, 1)),
             |t| Some((t.threads_per_core, t.cores_per_die, t.dies_per_package)),
         );
         #[cfg(target_arch = "x86_64")]
@@ -907,7 +935,7 @@ impl CpuManager {
     /// Only create new vCPUs if there aren't any inactive ones to reuse
     fn create_vcpus(
         &mut self,
-        desired_vcpus: u8,
+        desired_vcpus: u32,
         snapshot: Option<Snapshot>,
     ) -> Result<Vec<Arc<Mutex<Vcpu>>>> {
         let mut vcpus: Vec<Arc<Mutex<Vcpu>>> = vec![];
@@ -924,7 +952,7 @@ impl CpuManager {
         }
 
         // Only create vCPUs in excess of all the allocated vCPUs.
-        for cpu_id in self.vcpus.len() as u8..desired_vcpus {
+        for cpu_id in self.vcpus.len() as u32..desired_vcpus {
             vcpus.push(self.create_vcpu(
                 cpu_id,
                 // TODO: The special format of the CPU id can be removed once
@@ -962,27 +990,32 @@ impl CpuManager {
     fn start_vcpu(
         &mut self,
         vcpu: Arc<Mutex<Vcpu>>,
-        vcpu_id: u8,
+        vcpu_id: u32,
         vcpu_thread_barrier: Arc<Barrier>,
         inserting: bool,
     ) -> Result<()> {
         let reset_evt = self.reset_evt.try_clone().unwrap();
+        #[cfg(target_arch = "x86_64")]
+        let crash_evt = self.crash_evt.try_clone().unwrap();
         let exit_evt = self.exit_evt.try_clone().unwrap();
         #[cfg(feature = "kvm")]
         let hypervisor_type = self.hypervisor.hypervisor_type();
         #[cfg(feature = "guest_debug")]
         let vm_debug_evt = self.vm_debug_evt.try_clone().unwrap();
+        #[cfg(feature = "guest_debug")]
+        let debug_stops = self.debug_stops.clone();
         let panic_exit_evt = self.exit_evt.try_clone().unwrap();
         let vcpu_kill_signalled = self.vcpus_kill_signalled.clone();
         let vcpu_pause_signalled = self.vcpus_pause_signalled.clone();
         let vcpu_kick_signalled = self.vcpus_kick_signalled.clone();
+        let exit_reason = self.exit_reason.clone();
 
-        let vcpu_kill = self.vcpu_states[usize::from(vcpu_id)].kill.clone();
-        let vcpu_run_interrupted = self.vcpu_states[usize::from(vcpu_id)]
+        let vcpu_kill = self.vcpu_states[vcpu_id as usize].kill.clone();
+        let vcpu_run_interrupted = self.vcpu_states[vcpu_id as usize]
             .vcpu_run_interrupted
             .clone();
         let panic_vcpu_run_interrupted = vcpu_run_interrupted.clone();
-        let vcpu_paused = self.vcpu_states[usize::from(vcpu_id)].paused.clone();
+        let vcpu_paused = self.vcpu_states[vcpu_id as usize].paused.clone();
 
         // Prepare the CPU set the current vCPU is expected to run onto.
         let cpuset = self.affinity.get(&vcpu_id).map(|host_cpus| {
@@ -1128,13 +1161,18 @@ impl CpuManager {
                             match vcpu.run() {
                                 Ok(run) => match run {
                                     #[cfg(feature = "kvm")]
-                                    VmExit::Debug => {
+                                    VmExit::Debug(_exit) => {
                                         info!("VmExit::Debug");
                                         #[cfg(feature = "guest_debug")]
                                         {
                                             vcpu_pause_signalled.store(true, Ordering::SeqCst);
-                                            let raw_tid = get_raw_tid(vcpu_id as usize);
-                                            vm_debug_evt.write(raw_tid as u64).unwrap();
+                                            // Several vCPUs can stop at once, record all of
+                                            // them for the GDB stub to report.
+                                            debug_stops
+                                                .lock()
+                                                .unwrap()
+                                                .push((vcpu_id as usize, _exit));
+                                            vm_debug_evt.write(1).unwrap();
                                         }
                                     }
                                     #[cfg(target_arch = "x86_64")]
@@ -1152,16 +1190,27 @@ impl CpuManager {
                                     VmExit::Hyperv => {}
                                     VmExit::Reset => {
                                         info!("VmExit::Reset");
+                                        exit_reason.record(ExitReason::Reset);
                                         vcpu_run_interrupted.store(true, Ordering::SeqCst);
                                         reset_evt.write(1).unwrap();
                                         break;
                                     }
                                     VmExit::Shutdown => {
                                         info!("VmExit::Shutdown");
+                                        exit_reason.record(ExitReason::Shutdown);
                                         vcpu_run_interrupted.store(true, Ordering::SeqCst);
                                         exit_evt.write(1).unwrap();
                                         break;
                                     }
+                                    #[cfg(target_arch = "x86_64")]
+                                    VmExit::TripleFault => {
+                                        warn!("VmExit::TripleFault");
+                                        event!("cpu", "triple-fault", "cpu_id", vcpu_id.to_string());
+                                        exit_reason.record(ExitReason::TripleFault);
+                                        vcpu_run_interrupted.store(true, Ordering::SeqCst);
+                                        crash_evt.write(1).unwrap();
+                                        break;
+                                    }
                                     #[cfg(feature = "tdx")]
                                     VmExit::Tdx => {
                                         if let Some(vcpu) = Arc::get_mut(&mut vcpu.vcpu) {
@@ -1213,8 +1262,8 @@ impl CpuManager {
 
         // On hot plug calls into this function entry_point is None. It is for
         // those hotplug CPU additions that we need to set the inserting flag.
-        self.vcpu_states[usize::from(vcpu_id)].handle = handle;
-        self.vcpu_states[usize::from(vcpu_id)].inserting = inserting;
+        self.vcpu_states[vcpu_id as usize].handle = handle;
+        self.vcpu_states[vcpu_id as usize].inserting = inserting;
 
         Ok(())
     }
@@ -1222,7 +1271,7 @@ impl CpuManager {
     /// Start up as many vCPUs threads as needed to reach `desired_vcpus`
     fn activate_vcpus(
         &mut self,
-        desired_vcpus: u8,
+        desired_vcpus: u32,
         inserting: bool,
         paused: Option<bool>,
     ) -> Result<()> {
@@ -1257,11 +1306,11 @@ impl CpuManager {
         Ok(())
     }
 
-    fn mark_vcpus_for_removal(&mut self, desired_vcpus: u8) {
+    fn mark_vcpus_for_removal(&mut self, desired_vcpus: u32) {
         // Mark vCPUs for removal, actual removal happens on ejection
         for cpu_id in desired_vcpus..self.present_vcpus() {
-            self.vcpu_states[usize::from(cpu_id)].removing = true;
-            self.vcpu_states[usize::from(cpu_id)]
+            self.vcpu_states[cpu_id as usize].removing = true;
+            self.vcpu_states[cpu_id as usize]
                 .pending_removal
                 .store(true, Ordering::SeqCst);
         }
@@ -1276,9 +1325,9 @@ impl CpuManager {
         false
     }
 
-    fn remove_vcpu(&mut self, cpu_id: u8) -> Result<()> {
+    fn remove_vcpu(&mut self, cpu_id: u32) -> Result<()> {
         info!("Removing vCPU: cpu_id = {}", cpu_id);
-        let state = &mut self.vcpu_states[usize::from(cpu_id)];
+        let state = &mut self.vcpu_states[cpu_id as usize];
         state.kill.store(true, Ordering::SeqCst);
         state.signal_thread();
         state.join_thread()?;
@@ -1306,7 +1355,7 @@ impl CpuManager {
     }
 
     pub fn start_restored_vcpus(&mut self) -> Result<()> {
-        self.activate_vcpus(self.vcpus.len() as u8, false, Some(true))
+        self.activate_vcpus(self.vcpus.len() as u32, false, Some(true))
             .map_err(|e| {
                 Error::StartRestoreVcpu(anyhow!("Failed to start restored vCPUs: {:#?}", e))
             })?;
@@ -1314,7 +1363,7 @@ impl CpuManager {
         Ok(())
     }
 
-    pub fn resize(&mut self, desired_vcpus: u8) -> Result<bool> {
+    pub fn resize(&mut self, desired_vcpus: u32) -> Result<bool> {
         if desired_vcpus.cmp(&self.present_vcpus()) == cmp::Ordering::Equal {
             return Ok(false);
         }
@@ -1387,11 +1436,11 @@ impl CpuManager {
         Ok(())
     }
 
-    pub fn boot_vcpus(&self) -> u8 {
+    pub fn boot_vcpus(&self) -> u32 {
         self.config.boot_vcpus
     }
 
-    pub fn max_vcpus(&self) -> u8 {
+    pub fn max_vcpus(&self) -> u32 {
         self.config.max_vcpus
     }
 
@@ -1401,10 +1450,10 @@ impl CpuManager {
         self.cpuid.clone()
     }
 
-    fn present_vcpus(&self) -> u8 {
+    fn present_vcpus(&self) -> u32 {
         self.vcpu_states
             .iter()
-            .fold(0, |acc, state| acc + state.active() as u8)
+            .fold(0, |acc, state| acc + state.active() as u32)
     }
 
     #[cfg(target_arch = "aarch64")]
@@ -1442,12 +1491,12 @@ impl CpuManager {
             madt.write(36, arch::layout::APIC_START.0);
 
             for cpu in 0..self.config.max_vcpus {
-                let x2apic_id = get_x2apic_id(cpu.into(), self.get_vcpu_topology());
+                let x2apic_id = get_x2apic_id(cpu, self.get_vcpu_topology());
 
                 let lapic = LocalX2Apic {
                     r#type: acpi::ACPI_X2APIC_PROCESSOR,
                     length: 16,
-                    processor_id: cpu.into(),
+                    processor_id: cpu,
                     apic_id: x2apic_id,
                     flags: if cpu < self.config.boot_vcpus {
                         1 << MADT_CPU_ENABLE_FLAG
@@ -1501,8 +1550,8 @@ impl CpuManager {
                     r#type: acpi::ACPI_APIC_GENERIC_CPU_INTERFACE,
                     length: 80,
                     reserved0: 0,
-                    cpu_interface_number: cpu as u32,
-                    uid: cpu as u32,
+                    cpu_interface_number: cpu,
+                    uid: cpu,
                     flags: 1,
                     parking_version: 0,
                     performance_interrupt: 0,
@@ -1570,8 +1619,10 @@ impl CpuManager {
         // If topology is not specified, the default setting is:
         // 1 package, multiple cores, 1 thread per core
         // This is also the behavior when PPTT is missing.
+        // The number of vCPUs is limited to 255 on AArch64.
         let (threads_per_core, cores_per_package, packages) =
-            self.get_vcpu_topology().unwrap_or((1, self.max_vcpus(), 1));
+            self.get_vcpu_topology()
+                .unwrap_or((1, self.max_vcpus() as u8, 1));
 
         let mut pptt = Sdt::new(*b"PPTT", 36, 2, *b"CLOUDH", *b"CHPPTT  ", 1);
 
@@ -1640,8 +1691,8 @@ impl CpuManager {
     }
 
     #[cfg(all(target_arch = "x86_64", feature = "guest_debug"))]
-    fn create_standard_regs(&self, cpu_id: u8) -> StandardRegisters {
-        self.vcpus[usize::from(cpu_id)]
+    fn create_standard_regs(&self, cpu_id: u32) -> StandardRegisters {
+        self.vcpus[cpu_id as usize]
             .lock()
             .unwrap()
             .vcpu
@@ -1649,8 +1700,8 @@ impl CpuManager {
     }
 
     #[cfg(feature = "guest_debug")]
-    fn get_regs(&self, cpu_id: u8) -> Result<StandardRegisters> {
-        self.vcpus[usize::from(cpu_id)]
+    fn get_regs(&self, cpu_id: u32) -> Result<StandardRegisters> {
+        self.vcpus[cpu_id as usize]
             .lock()
             .unwrap()
             .vcpu
@@ -1659,8 +1710,8 @@ impl CpuManager {
     }
 
     #[cfg(feature = "guest_debug")]
-    fn set_regs(&self, cpu_id: u8, regs: &StandardRegisters) -> Result<()> {
-        self.vcpus[usize::from(cpu_id)]
+    fn set_regs(&self, cpu_id: u32, regs: &StandardRegisters) -> Result<()> {
+        self.vcpus[cpu_id as usize]
             .lock()
             .unwrap()
             .vcpu
@@ -1669,8 +1720,8 @@ impl CpuManager {
     }
 
     #[cfg(all(target_arch = "x86_64", feature = "guest_debug"))]
-    fn get_sregs(&self, cpu_id: u8) -> Result<SpecialRegisters> {
-        self.vcpus[usize::from(cpu_id)]
+    fn get_sregs(&self, cpu_id: u32) -> Result<SpecialRegisters> {
+        self.vcpus[cpu_id as usize]
             .lock()
             .unwrap()
             .vcpu
@@ -1679,8 +1730,8 @@ impl CpuManager {
     }
 
     #[cfg(all(target_arch = "x86_64", feature = "guest_debug"))]
-    fn set_sregs(&self, cpu_id: u8, sregs: &SpecialRegisters) -> Result<()> {
-        self.vcpus[usize::from(cpu_id)]
+    fn set_sregs(&self, cpu_id: u32, sregs: &SpecialRegisters) -> Result<()> {
+        self.vcpus[cpu_id as usize]
             .lock()
             .unwrap()
             .vcpu
@@ -1692,10 +1743,10 @@ impl CpuManager {
     fn translate_gva(
         &self,
         _guest_memory: &GuestMemoryAtomic<GuestMemoryMmap>,
-        cpu_id: u8,
+        cpu_id: u32,
         gva: u64,
     ) -> Result<u64> {
-        let (gpa, _) = self.vcpus[usize::from(cpu_id)]
+        let (gpa, _) = self.vcpus[cpu_id as usize]
             .lock()
             .unwrap()
             .vcpu
@@ -1724,22 +1775,22 @@ impl CpuManager {
     fn translate_gva(
         &self,
         guest_memory: &GuestMemoryAtomic<GuestMemoryMmap>,
-        cpu_id: u8,
+        cpu_id: u32,
         gva: u64,
     ) -> Result<u64> {
-        let tcr_el1: u64 = self.vcpus[usize::from(cpu_id)]
+        let tcr_el1: u64 = self.vcpus[cpu_id as usize]
             .lock()
             .unwrap()
             .vcpu
             .get_sys_reg(TCR_EL1)
             .map_err(|e| Error::TranslateVirtualAddress(e.into()))?;
-        let ttbr1_el1: u64 = self.vcpus[usize::from(cpu_id)]
+        let ttbr1_el1: u64 = self.vcpus[cpu_id as usize]
             .lock()
             .unwrap()
             .vcpu
             .get_sys_reg(TTBR1_EL1)
             .map_err(|e| Error::TranslateVirtualAddress(e.into()))?;
-        let id_aa64mmfr0_el1: u64 = self.vcpus[usize::from(cpu_id)]
+        let id_aa64mmfr0_el1: u64 = self.vcpus[cpu_id as usize]
             .lock()
             .unwrap()
             .vcpu
@@ -1885,16 +1936,20 @@ impl CpuManager {
         &self.vcpus_kill_signalled
     }
 
+    pub(crate) fn exit_reason(&self) -> &ExitReasonSlot {
+        &self.exit_reason
+    }
+
     #[cfg(feature = "igvm")]
     pub(crate) fn get_cpuid_leaf(
         &self,
-        cpu_id: u8,
+        cpu_id: u32,
         eax: u32,
         ecx: u32,
         xfem: u64,
         xss: u64,
     ) -> Result<[u32; 4]> {
-        let leaf_info = self.vcpus[usize::from(cpu_id)]
+        let leaf_info = self.vcpus[cpu_id as usize]
             .lock()
             .unwrap()
             .vcpu
@@ -1922,7 +1977,7 @@ impl CpuManager {
 }
 
 struct Cpu {
-    cpu_id: u8,
+    cpu_id: u32,
     proximity_domain: u32,
     dynamic: bool,
     #[cfg(target_arch = "x86_64")]
@@ -1938,12 +1993,12 @@ const MADT_CPU_ONLINE_CAPABLE_FLAG: usize = 1;
 impl Cpu {
     #[cfg(target_arch = "x86_64")]
     fn generate_mat(&self) -> Vec<u8> {
-        let x2apic_id = arch::x86_64::get_x2apic_id(self.cpu_id.into(), self.topology);
+        let x2apic_id = arch::x86_64::get_x2apic_id(self.cpu_id, self.topology);
 
         let lapic = LocalX2Apic {
             r#type: crate::acpi::ACPI_X2APIC_PROCESSOR,
             length: 16,
-            processor_id: self.cpu_id.into(),
+            processor_id: self.cpu_id,
             apic_id: x2apic_id,
             flags: 1 << MADT_CPU_ENABLE_FLAG,
             _reserved: 0,
@@ -2045,7 +2100,7 @@ impl Aml for Cpu {
 }
 
 struct CpuNotify {
-    cpu_id: u8,
+    cpu_id: u32,
 }
 
 impl Aml for CpuNotify {
@@ -2060,7 +2115,7 @@ impl Aml for CpuNotify {
 }
 
 struct CpuMethods {
-    max_vcpus: u8,
+    max_vcpus: u32,
     dynamic: bool,
 }
 
@@ -2098,7 +2153,7 @@ impl Aml for CpuMethods {
 
             let mut cpu_notifies_refs: Vec<&dyn Aml> = Vec::new();
             for cpu_id in 0..self.max_vcpus {
-                cpu_notifies_refs.push(&cpu_notifies[usize::from(cpu_id)]);
+                cpu_notifies_refs.push(&cpu_notifies[cpu_id as usize]);
             }
 
             aml::Method::new("CTFY".into(), 2, true, cpu_notifies_refs).to_aml_bytes(sink);
@@ -2359,16 +2414,21 @@ impl Debuggable for CpuManager {
         &self,
         cpu_id: usize,
         addrs: &[GuestAddress],
+        watchpoints: &[hypervisor::HwWatchpoint],
         singlestep: bool,
     ) -> std::result::Result<(), DebuggableError> {
         self.vcpus[cpu_id]
             .lock()
             .unwrap()
             .vcpu
-            .set_guest_debug(addrs, singlestep)
+            .set_guest_debug(addrs, watchpoints, singlestep)
             .map_err(DebuggableError::SetDebug)
     }
 
+    fn debug_stops(&self) -> Vec<(usize, hypervisor::DebugExit)> {
+        std::mem::take(&mut *self.debug_stops.lock().unwrap())
+    }
+
     fn debug_pause(&mut self) -> std::result::Result<(), DebuggableError> {
         Ok(())
     }
@@ -2381,7 +2441,7 @@ impl Debuggable for CpuManager {
     fn read_regs(&self, cpu_id: usize) -> std::result::Result<CoreRegs, DebuggableError> {
         // General registers: RAX, RBX, RCX, RDX, RSI, RDI, RBP, RSP, r8-r15
         let gregs = self
-            .get_regs(cpu_id as u8)
+            .get_regs(cpu_id as u32)
             .map_err(DebuggableError::ReadRegs)?;
         let regs = [
             gregs.get_rax(),
@@ -2409,7 +2469,7 @@ impl Debuggable for CpuManager {
 
         // Segment registers: CS, SS, DS, ES, FS, GS
         let sregs = self
-            .get_sregs(cpu_id as u8)
+            .get_sregs(cpu_id as u32)
             .map_err(DebuggableError::ReadRegs)?;
         let segments = X86SegmentRegs {
             cs: sregs.cs.selector as u32,
@@ -2434,7 +2494,7 @@ impl Debuggable for CpuManager {
     #[cfg(target_arch = "aarch64")]
     fn read_regs(&self, cpu_id: usize) -> std::result::Result<CoreRegs, DebuggableError> {
         let gregs = self
-            .get_regs(cpu_id as u8)
+            .get_regs(cpu_id as u32)
             .map_err(DebuggableError::ReadRegs)?;
         Ok(CoreRegs {
             x: gregs.get_regs(),
@@ -2451,9 +2511,9 @@ impl Debuggable for CpuManager {
         regs: &CoreRegs,
     ) -> std::result::Result<(), DebuggableError> {
         let orig_gregs = self
-            .get_regs(cpu_id as u8)
+            .get_regs(cpu_id as u32)
             .map_err(DebuggableError::ReadRegs)?;
-        let mut gregs = self.create_standard_regs(cpu_id as u8);
+        let mut gregs = self.create_standard_regs(cpu_id as u32);
         gregs.set_rax(regs.regs[0]);
         gregs.set_rbx(regs.regs[1]);
         gregs.set_rcx(regs.regs[2]);
@@ -2474,13 +2534,13 @@ impl Debuggable for CpuManager {
         // Update the lower 32-bit of rflags.
         gregs.set_rflags((orig_gregs.get_rflags() & !(u32::MAX as u64)) | (regs.eflags as u64));
 
-        self.set_regs(cpu_id as u8, &gregs)
+        self.set_regs(cpu_id as u32, &gregs)
             .map_err(DebuggableError::WriteRegs)?;
 
         // Segment registers: CS, SS, DS, ES, FS, GS
         // Since GDB care only selectors, we call get_sregs() first.
         let mut sregs = self
-            .get_sregs(cpu_id as u8)
+            .get_sregs(cpu_id as u32)
             .map_err(DebuggableError::ReadRegs)?;
         sregs.cs.selector = regs.segments.cs as u16;
         sregs.ss.selector = regs.segments.ss as u16;
@@ -2489,7 +2549,7 @@ impl Debuggable for CpuManager {
         sregs.fs.selector = regs.segments.fs as u16;
         sregs.gs.selector = regs.segments.gs as u16;
 
-        self.set_sregs(cpu_id as u8, &sregs)
+        self.set_sregs(cpu_id as u32, &sregs)
             .map_err(DebuggableError::WriteRegs)?;
 
         // TODO: Add other registers
@@ -2504,14 +2564,14 @@ impl Debuggable for CpuManager {
         regs: &CoreRegs,
     ) -> std::result::Result<(), DebuggableError> {
         let mut gregs = self
-            .get_regs(cpu_id as u8)
+            .get_regs(cpu_id as u32)
             .map_err(DebuggableError::ReadRegs)?;
 
         gregs.set_regs(regs.x);
         gregs.set_sp(regs.sp);
         gregs.set_pc(regs.pc);
 
-        self.set_regs(cpu_id as u8, &gregs)
+        self.set_regs(cpu_id as u32, &gregs)
             .map_err(DebuggableError::WriteRegs)?;
 
         Ok(())
@@ -2529,7 +2589,7 @@ impl Debuggable for CpuManager {
 
         while total_read < len as u64 {
             let gaddr = vaddr.0 + total_read;
-            let paddr = match self.translate_gva(guest_memory, cpu_id as u8, gaddr) {
+            let paddr = match self.translate_gva(guest_memory, cpu_id as u32, gaddr) {
                 Ok(paddr) => paddr,
                 Err(_) if gaddr == u64::MIN => gaddr, // Silently return GVA as GPA if GVA == 0.
                 Err(e) => return Err(DebuggableError::TranslateGva(e)),
@@ -2559,7 +2619,7 @@ impl Debuggable for CpuManager {
 
         while total_written < data.len() as u64 {
             let gaddr = vaddr.0 + total_written;
-            let paddr = match self.translate_gva(guest_memory, cpu_id as u8, gaddr) {
+            let paddr = match self.translate_gva(guest_memory, cpu_id as u32, gaddr) {
                 Ok(paddr) => paddr,
                 Err(_) if gaddr == u64::MIN => gaddr, // Silently return GVA as GPA if GVA == 0.
                 Err(e) => return Err(DebuggableError::TranslateGva(e)),
@@ -2624,7 +2684,7 @@ impl CpuElf64Writable for CpuManager {
             pos += descsz - size_of::<X86_64UserRegs>() - size_of::<u64>();
 
             let orig_rax: u64 = 0;
-            let gregs = self.vcpus[usize::from(vcpu_id)]
+            let gregs = self.vcpus[vcpu_id as usize]
                 .lock()
                 .unwrap()
                 .vcpu
@@ -2652,7 +2712,7 @@ impl CpuElf64Writable for CpuManager {
                 orig_rax,
             ];
 
-            let sregs = self.vcpus[usize::from(vcpu_id)]
+            let sregs = self.vcpus[vcpu_id as usize]
                 .lock()
                 .unwrap()
                 .vcpu
@@ -2726,7 +2786,7 @@ impl CpuElf64Writable for CpuManager {
 
             pos += round_up!(COREDUMP_NAME_SIZE as usize, 4);
 
-            let gregs = self.vcpus[usize::from(vcpu_id)]
+            let gregs = self.vcpus[vcpu_id as usize]
                 .lock()
                 .unwrap()
                 .vcpu
@@ -2755,7 +2815,7 @@ impl CpuElf64Writable for CpuManager {
                 gregs.get_r15(),
             ];
 
-            let sregs = self.vcpus[usize::from(vcpu_id)]
+            let sregs = self.vcpus[vcpu_id as usize]
                 .lock()
                 .unwrap()
                 .vcpu
@@ -0,0 +1,225 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Dump of the guest memory taken when the guest panics.
+//!
+//! The dump is an ELF core file holding one `PT_LOAD` segment per RAM
+//! region, at its guest physical address, compressed as a single zlib
+//! stream. The file is written while the guest stays paused, from a thread
+//! of its own so that the VMM keeps handling the API meanwhile.
+
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use miniz_oxide::deflate::core::{create_comp_flags_from_zip_params, CompressorOxide};
+use miniz_oxide::deflate::stream::deflate;
+use miniz_oxide::{MZFlush, MZStatus};
+use vm_memory::{Bytes, GuestAddress, GuestMemory, GuestMemoryRegion};
+
+use crate::GuestMemoryMmap;
+
+// Fast compression, most of the time being spent on the guest memory which
+// is often made of zeroes.
+const COMPRESSION_LEVEL: i32 = 1;
+// Size of the guest memory reads and of the compressed output buffer.
+const CHUNK_SIZE: usize = 1 << 20;
+
+const ELF_HEADER_SIZE: u64 = 64;
+const ELF_PROGRAM_HEADER_SIZE: u64 = 56;
+const ET_CORE: u16 = 4;
+const PT_LOAD: u32 = 1;
+const PF_RWX: u32 = 0x7;
+#[cfg(target_arch = "x86_64")]
+const EM_MACHINE: u16 = 62;
+#[cfg(target_arch = "aarch64")]
+const EM_MACHINE: u16 = 183;
+#[cfg(target_arch = "riscv64")]
+const EM_MACHINE: u16 = 243;
+
+/// Path of a new dump in the `dir` directory, named after the time of the
+/// crash.
+pub(crate) fn dump_path(dir: &Path) -> PathBuf {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    dir.join(format!(
+        "crash-{}.{:03}.core.zz",
+        now.as_secs(),
+        now.subsec_millis()
+    ))
+}
+
+struct ZlibWriter<W: Write> {
+    compressor: Box<CompressorOxide>,
+    buffer: Vec<u8>,
+    output: W,
+}
+
+impl<W: Write> ZlibWriter<W> {
+    fn new(output: W) -> Self {
+        // Positive window bits for the zlib header and checksum
+        let flags = create_comp_flags_from_zip_params(COMPRESSION_LEVEL, 1, 0);
+        ZlibWriter {
+            compressor: Box::new(CompressorOxide::new(flags)),
+            buffer: vec![0; CHUNK_SIZE],
+            output,
+        }
+    }
+
+    fn deflate(&mut self, mut input: &[u8], flush: MZFlush) -> io::Result<()> {
+        loop {
+            let result = deflate(&mut self.compressor, input, &mut self.buffer, flush);
+            let status = result
+                .status
+                .map_err(|e| io::Error::other(format!("Error compressing: {e:?}")))?;
+            self.output
+                .write_all(&self.buffer[..result.bytes_written])?;
+            input = &input[result.bytes_consumed..];
+
+            match flush {
+                MZFlush::Finish if matches!(status, MZStatus::StreamEnd) => return Ok(()),
+                MZFlush::Finish => {}
+                _ if input.is_empty() => return Ok(()),
+                _ => {}
+            }
+        }
+    }
+
+    fn write_all(&mut self, input: &[u8]) -> io::Result<()> {
+        if input.is_empty() {
+            return Ok(());
+        }
+        self.deflate(input, MZFlush::None)
+    }
+
+    fn finish(mut self) -> io::Result<W> {
+        self.deflate(&[], MZFlush::Finish)?;
+        self.output.flush()?;
+        Ok(self.output)
+    }
+}
+
+fn elf_header(phnum: u16) -> Vec<u8> {
+    let mut header = Vec::with_capacity(ELF_HEADER_SIZE as usize);
+    // Magic, 64-bit, little endian, current version
+    header.extend_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1]);
+    header.resize(16, 0);
+    header.extend_from_slice(&ET_CORE.to_le_bytes());
+    header.extend_from_slice(&EM_MACHINE.to_le_bytes());
+    header.extend_from_slice(&1u32.to_le_bytes());
+    // Entry point
+    header.extend_from_slice(&0u64.to_le_bytes());
+    // Program and section header offsets
+    header.extend_from_slice(&ELF_HEADER_SIZE.to_le_bytes());
+    header.extend_from_slice(&0u64.to_le_bytes());
+    // Flags
+    header.extend_from_slice(&0u32.to_le_bytes());
+    header.extend_from_slice(&(ELF_HEADER_SIZE as u16).to_le_bytes());
+    header.extend_from_slice(&(ELF_PROGRAM_HEADER_SIZE as u16).to_le_bytes());
+    header.extend_from_slice(&phnum.to_le_bytes());
+    // No section header
+    header.extend_from_slice(&[0; 6]);
+    header
+}
+
+fn elf_load_header(offset: u64, guest_addr: u64, size: u64) -> Vec<u8> {
+    let mut header = Vec::with_capacity(ELF_PROGRAM_HEADER_SIZE as usize);
+    header.extend_from_slice(&PT_LOAD.to_le_bytes());
+    header.extend_from_slice(&PF_RWX.to_le_bytes());
+    header.extend_from_slice(&offset.to_le_bytes());
+    // Virtual and physical addresses, the former being unknown
+    header.extend_from_slice(&0u64.to_le_bytes());
+    header.extend_from_slice(&guest_addr.to_le_bytes());
+    header.extend_from_slice(&size.to_le_bytes());
+    header.extend_from_slice(&size.to_le_bytes());
+    // Alignment
+    header.extend_from_slice(&0u64.to_le_bytes());
+    header
+}
+
+/// Write the RAM of the guest to a new file at `path`.
+pub(crate) fn dump_guest_memory(memory: &GuestMemoryMmap, path: &Path) -> io::Result<()> {
+    let file = OpenOptions::new().write(true).create_new(true).open(path)?;
+    let mut writer = ZlibWriter::new(io::BufWriter::new(file));
+
+    let regions: Vec<(u64, u64)> = memory
+        .iter()
+        .map(|region| (region.start_addr().raw_value(), region.len()))
+        .collect();
+    let phnum = u16::try_from(regions.len())
+        .map_err(|_| io::Error::other("Too many guest memory regions"))?;
+
+    writer.write_all(&elf_header(phnum))?;
+    let mut offset = ELF_HEADER_SIZE + ELF_PROGRAM_HEADER_SIZE * u64::from(phnum);
+    for (guest_addr, size) in regions.iter() {
+        writer.write_all(&elf_load_header(offset, *guest_addr, *size))?;
+        offset += size;
+    }
+
+    let mut buffer = vec![0; CHUNK_SIZE];
+    for (guest_addr, size) in regions {
+        let mut done = 0;
+        while done < size {
+            let len = (size - done).min(CHUNK_SIZE as u64) as usize;
+            memory
+                .read_slice(&mut buffer[..len], GuestAddress(guest_addr + done))
+                .map_err(io::Error::other)?;
+            writer.write_all(&buffer[..len])?;
+            done += len as u64;
+        }
+    }
+
+    writer.finish()?.into_inner()?.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use vmm_sys_util::tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_dump_guest_memory() {
+        let memory = GuestMemoryMmap::from_ranges(&[
+            (GuestAddress(0), 0x10000),
+            (GuestAddress(0x100000), 0x4000),
+        ])
+        .unwrap();
+        memory
+            .write_obj(0x1234_5678u32, GuestAddress(0x100))
+            .unwrap();
+        memory
+            .write_obj(0xdead_beefu32, GuestAddress(0x100010))
+            .unwrap();
+
+        let dir = TempDir::new().unwrap();
+        let path = dump_path(dir.as_path());
+        dump_guest_memory(&memory, &path).unwrap();
+        // Dumps are never overwritten.
+        dump_guest_memory(&memory, &path).unwrap_err();
+
+        let core =
+            miniz_oxide::inflate::decompress_to_vec_zlib(&std::fs::read(&path).unwrap()).unwrap();
+        let data_offset = (ELF_HEADER_SIZE + 2 * ELF_PROGRAM_HEADER_SIZE) as usize;
+        assert_eq!(core.len(), data_offset + 0x10000 + 0x4000);
+        assert_eq!(&core[..4], b"\x7fELF");
+        assert_eq!(core[56..58], 2u16.to_le_bytes());
+
+        // Physical address of the second segment
+        let phdr = ELF_HEADER_SIZE as usize + ELF_PROGRAM_HEADER_SIZE as usize;
+        assert_eq!(core[phdr + 24..phdr + 32], 0x100000u64.to_le_bytes());
+        assert_eq!(
+            core[data_offset + 0x100..data_offset + 0x104],
+            0x1234_5678u32.to_le_bytes()
+        );
+        let second = data_offset + 0x10000;
+        assert_eq!(
+            core[second + 0x10..second + 0x14],
+            0xdead_beefu32.to_le_bytes()
+        );
+    }
+}
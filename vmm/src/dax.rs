@@ -0,0 +1,141 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Device DAX support.
+//!
+//! A device DAX, e.g. `/dev/dax0.0`, is a character device exposing host
+//! persistent memory or CXL memory to be mapped directly, without going
+//! through the page cache. It can only be mapped shared, at an offset and
+//! with a length aligned to the alignment of the device.
+
+use std::fs;
+use std::io;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::{Path, PathBuf};
+
+// Alignment of the devices of the kernels not reporting it.
+const DEFAULT_DAX_ALIGN: u64 = 2 << 20;
+
+/// Device DAX a file path refers to.
+#[derive(Debug, PartialEq, Eq)]
+pub struct DaxDevice {
+    /// Size of the device in bytes.
+    pub size: u64,
+    /// Alignment of the mappings of the device in bytes.
+    pub align: u64,
+}
+
+impl DaxDevice {
+    /// Returns the device DAX `path` refers to, none if it isn't one.
+    pub fn probe(path: &Path) -> io::Result<Option<Self>> {
+        let metadata = fs::metadata(path)?;
+        if !metadata.file_type().is_char_device() {
+            return Ok(None);
+        }
+
+        let rdev = metadata.rdev();
+        Self::from_sysfs(&PathBuf::from(format!(
+            "/sys/dev/char/{}:{}",
+            libc::major(rdev),
+            libc::minor(rdev)
+        )))
+    }
+
+    fn from_sysfs(dir: &Path) -> io::Result<Option<Self>> {
+        // The device belongs to the dax bus, or to the dax class with older
+        // kernels.
+        match fs::read_link(dir.join("subsystem")) {
+            Ok(subsystem) if subsystem.ends_with("dax") => {}
+            Ok(_) => return Ok(None),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        }
+
+        let size = read_sysfs_u64(&dir.join("size"))?;
+        let align = match read_sysfs_u64(&dir.join("align")) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => DEFAULT_DAX_ALIGN,
+            align => align?,
+        };
+
+        Ok(Some(DaxDevice { size, align }))
+    }
+
+    /// Checks a mapping of `size` bytes at `offset` is aligned to the
+    /// alignment of the device and fits in it.
+    pub fn check_mapping(&self, offset: u64, size: u64) -> io::Result<()> {
+        if offset % self.align != 0 || size % self.align != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Mapping of {size:#x} bytes at {offset:#x} is not aligned to {:#x}",
+                    self.align
+                ),
+            ));
+        }
+
+        if offset.checked_add(size).is_none_or(|end| end > self.size) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Mapping of {size:#x} bytes at {offset:#x} exceeds the {:#x} bytes of the device",
+                    self.size
+                ),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+fn read_sysfs_u64(path: &Path) -> io::Result<u64> {
+    fs::read_to_string(path)?.trim().parse().map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid value in {}: {e}", path.display()),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::symlink;
+
+    use vmm_sys_util::tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_dax_device_from_sysfs() {
+        let dir = TempDir::new_with_prefix("/tmp/ch-dax").unwrap();
+        let dir = dir.as_path();
+        fs::write(dir.join("size"), "17179869184\n").unwrap();
+
+        // Not a device DAX
+        assert_eq!(DaxDevice::from_sysfs(dir).unwrap(), None);
+        symlink("../../../class/tty", dir.join("subsystem")).unwrap();
+        assert_eq!(DaxDevice::from_sysfs(dir).unwrap(), None);
+
+        // The alignment defaults to 2 MiB when not reported
+        fs::remove_file(dir.join("subsystem")).unwrap();
+        symlink("../../../../bus/dax", dir.join("subsystem")).unwrap();
+        assert_eq!(
+            DaxDevice::from_sysfs(dir).unwrap(),
+            Some(DaxDevice {
+                size: 16 << 30,
+                align: 2 << 20,
+            })
+        );
+
+        fs::write(dir.join("align"), "1073741824\n").unwrap();
+        let dax = DaxDevice::from_sysfs(dir).unwrap().unwrap();
+        assert_eq!(dax.align, 1 << 30);
+
+        assert!(dax.check_mapping(0, 16 << 30).is_ok());
+        assert!(dax.check_mapping(4 << 30, 2 << 30).is_ok());
+        assert!(dax.check_mapping(0, 2 << 20).is_err());
+        assert!(dax.check_mapping(2 << 20, 1 << 30).is_err());
+        assert!(dax.check_mapping(8 << 30, 16 << 30).is_err());
+    }
+}
@@ -15,11 +15,15 @@ use std::io::{self, stdout, IsTerminal, Seek, SeekFrom};
 use std::num::Wrapping;
 use std::os::unix::fs::OpenOptionsExt;
 use std::os::unix::io::{AsRawFd, FromRawFd};
-use std::path::PathBuf;
-use std::result;
+use std::path::{Path, PathBuf};
+#[cfg(target_arch = "x86_64")]
+use std::sync::atomic::AtomicBool;
 use std::sync::{Arc, Mutex};
 #[cfg(not(target_arch = "riscv64"))]
 use std::time::Instant;
+#[cfg(not(target_arch = "riscv64"))]
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{panic, result, thread};
 
 use acpi_tables::sdt::GenericAddress;
 #[cfg(not(target_arch = "riscv64"))]
@@ -33,15 +37,21 @@ use arch::{layout, NumaNodes};
 use arch::{DeviceType, MmioDeviceInfo};
 use block::async_io::DiskFile;
 use block::fixed_vhd_sync::FixedVhdDiskSync;
+use block::luks_sync::LuksDiskSync;
 use block::qcow_sync::QcowDiskSync;
 use block::raw_async_aio::RawFileDiskAio;
+use block::raw_async_threads::{RawFileDiskThreads, DEFAULT_IO_THREADS};
 use block::raw_sync::RawFileDiskSync;
 use block::vhdx_sync::VhdxDiskSync;
 use block::{
-    block_aio_is_supported, block_io_uring_is_supported, detect_image_type, qcow, vhdx, ImageType,
+    block_aio_is_supported, block_io_uring_is_supported, detect_image_type, io_priority, luks,
+    qcow, vhdx, ImageType, IOPRIO_DEFAULT_LEVEL,
 };
 #[cfg(feature = "io_uring")]
-use block::{fixed_vhd_async::FixedVhdDiskAsync, raw_async::RawFileDisk};
+use block::{
+    fixed_vhd_async::FixedVhdDiskAsync,
+    raw_async::{IoUringOptions, RawFileDisk},
+};
 #[cfg(target_arch = "riscv64")]
 use devices::aia;
 #[cfg(target_arch = "x86_64")]
@@ -57,8 +67,11 @@ use devices::ioapic;
 use devices::legacy::Pl011;
 #[cfg(any(target_arch = "x86_64", target_arch = "riscv64"))]
 use devices::legacy::Serial;
+use devices::legacy::{RtcClock, RtcClockState};
 #[cfg(feature = "pvmemcontrol")]
 use devices::pvmemcontrol::{PvmemcontrolBusDevice, PvmemcontrolPciDevice};
+#[cfg(target_arch = "x86_64")]
+use devices::vmbus::{VmBus, VmBusWorker, VMBUS_INTERRUPT_COUNT};
 use devices::{interrupt_controller, AcpiNotificationFlags};
 #[cfg(target_arch = "aarch64")]
 use hypervisor::arch::aarch64::regs::AARCH64_PMU_IRQ;
@@ -67,21 +80,31 @@ use libc::{
     tcsetattr, termios, MAP_NORESERVE, MAP_PRIVATE, MAP_SHARED, O_TMPFILE, PROT_READ, PROT_WRITE,
     TCSANOW,
 };
+use net_util::macvtap::Macvtap;
+use net_util::netns::NetNamespace;
+use net_util::sriov::SriovVf;
+#[cfg(target_arch = "x86_64")]
+use pci::VGA_IO_PORT_RANGES;
 use pci::{
-    DeviceRelocation, MmioRegion, PciBarRegionType, PciBdf, PciDevice, VfioDmaMapping,
-    VfioPciDevice, VfioUserDmaMapping, VfioUserPciDevice, VfioUserPciDeviceError,
+    vfio_dirty_log, vfio_start_dirty_log, vfio_stop_dirty_log, DeviceRelocation, MmioRegion,
+    PciBarRegionType, PciBdf, PciDevice, PciRom, VfioDmaMapping, VfioPciDevice, VfioResetMethod,
+    VfioUserDmaMapping, VfioUserPciDevice, VfioUserPciDeviceError,
 };
 use rate_limiter::group::RateLimiterGroup;
+use rate_limiter::{BucketUpdate, TokenBucket};
 use seccompiler::SeccompAction;
 use serde::{Deserialize, Serialize};
+use serial_buffer::OutputSinks;
 use thiserror::Error;
 use tracer::trace_scoped;
 use vfio_ioctls::{VfioContainer, VfioDevice, VfioDeviceFd};
+use virtio_devices::block::{DiskImageSwitchDone, MAXIMUM_BLOCK_QUEUE_SIZE};
+use virtio_devices::net::NetState;
 use virtio_devices::transport::{VirtioPciDevice, VirtioPciDeviceActivator, VirtioTransport};
 use virtio_devices::vhost_user::VhostUserConfig;
 use virtio_devices::{
-    AccessPlatformMapping, ActivateError, Block, Endpoint, IommuMapping, VdpaDmaMapping,
-    VirtioMemMappingSource,
+    AccessPlatformMapping, ActivateError, Block, Endpoint, IoThread, IoThreadPool, IommuMapping,
+    TokenBucketConfig, VdpaDmaMapping, VirtioMemMappingSource,
 };
 use vm_allocator::{AddressAllocator, SystemAllocator};
 use vm_device::dma_mapping::ExternalDmaMapping;
@@ -89,6 +112,7 @@ use vm_device::interrupt::{
     InterruptIndex, InterruptManager, LegacyIrqGroupConfig, MsiIrqGroupConfig,
 };
 use vm_device::{Bus, BusDevice, BusDeviceSync, Resource};
+use vm_memory::bitmap::AtomicBitmap;
 use vm_memory::guest_memory::FileOffset;
 use vm_memory::{Address, GuestAddress, GuestMemoryRegion, GuestUsize, MmapRegion};
 #[cfg(target_arch = "x86_64")]
@@ -101,19 +125,28 @@ use vm_migration::{
 use vm_virtio::{AccessPlatform, VirtioDeviceType};
 use vmm_sys_util::eventfd::EventFd;
 
+use crate::api::{BalloonInfo, DiskQueueInfo};
+use crate::config::add_to_config;
 use crate::console_devices::{ConsoleDeviceError, ConsoleInfo, ConsoleOutput};
 use crate::cpu::{CpuManager, CPU_MANAGER_ACPI_SIZE};
+use crate::dax::DaxDevice;
 use crate::device_tree::{DeviceNode, DeviceTree};
 use crate::interrupt::{LegacyUserspaceInterruptManager, MsiInterruptManager};
+use crate::landlock::{Landlock, LandlockError};
 use crate::memory_manager::{Error as MemoryManagerError, MemoryManager, MEMORY_MANAGER_ACPI_SIZE};
 use crate::pci_segment::PciSegment;
 use crate::serial_manager::{Error as SerialManagerError, SerialManager};
+use crate::vfio_group::{same_device, IommuGroup, VfioGroupError};
 use crate::vm_config::{
-    ConsoleOutputMode, DeviceConfig, DiskConfig, FsConfig, NetConfig, PmemConfig, UserDeviceConfig,
-    VdpaConfig, VhostMode, VmConfig, VsockConfig, DEFAULT_IOMMU_ADDRESS_WIDTH_BITS,
+    ApplyLandlock, ConsoleOutputMode, DeviceConfig, DiskConfig, DiskEncryption, DiskInterface,
+    FsConfig, IoEngine, NetConfig, PmemConfig, QueueSize, RateLimiterGroupConfig, RtcBase,
+    RtcDrift, UserDeviceConfig, VdpaConfig, VhostMode, VmConfig, VsockConfig,
+    DEFAULT_DISK_NUM_QUEUES, DEFAULT_DISK_QUEUE_SIZE, DEFAULT_IOMMU_ADDRESS_WIDTH_BITS,
     DEFAULT_PCI_SEGMENT_APERTURE_WEIGHT,
 };
-use crate::{device_node, GuestRegionMmap, PciDeviceInfo, DEVICE_MANAGER_SNAPSHOT_ID};
+use crate::{
+    device_node, GuestRegionMmap, PciDeviceInfo, PciSegmentInfo, DEVICE_MANAGER_SNAPSHOT_ID,
+};
 
 #[cfg(any(target_arch = "aarch64", target_arch = "riscv64"))]
 const MMIO_LEN: u64 = 0x1000;
@@ -133,6 +166,8 @@ const PVMEMCONTROL_DEVICE_NAME: &str = "__pvmemcontrol";
 const BALLOON_DEVICE_NAME: &str = "__balloon";
 const CONSOLE_DEVICE_NAME: &str = "__console";
 const PVPANIC_DEVICE_NAME: &str = "__pvpanic";
+const SCMI_DEVICE_NAME: &str = "__scmi";
+const CLOUD_INIT_DEVICE_NAME: &str = "__cloud_init";
 
 // Devices that the user may name and for which we generate
 // identifiers if the user doesn't give one
@@ -147,6 +182,9 @@ const VFIO_DEVICE_NAME_PREFIX: &str = "_vfio";
 const VFIO_USER_DEVICE_NAME_PREFIX: &str = "_vfio_user";
 const VIRTIO_PCI_DEVICE_NAME_PREFIX: &str = "_virtio-pci";
 
+// Version of the QCOW2 overlays the disks are switched to.
+const QCOW_OVERLAY_VERSION: u32 = 3;
+
 /// Errors associated with device manager
 #[derive(Error, Debug)]
 pub enum DeviceManagerError {
@@ -235,10 +273,54 @@ pub enum DeviceManagerError {
     #[error("Cannot create virtio-watchdog device")]
     CreateVirtioWatchdog(#[source] io::Error),
 
+    /// Cannot create virtio-scmi device
+    #[error("Cannot create virtio-scmi device")]
+    CreateVirtioScmi(#[source] io::Error),
+
+    /// Cannot create cloud-init seed
+    #[error("Cannot create cloud-init seed")]
+    CreateCloudInitSeed(#[source] io::Error),
+
+    /// NVDIMM devices can't be hotplugged
+    #[error("NVDIMM devices can't be hotplugged")]
+    NvdimmHotplugUnsupported,
+
+    /// Cannot create an NVMe controller
+    #[error("Cannot create an NVMe controller")]
+    CreateNvme(#[source] devices::nvme::NvmeError),
+
+    /// No PCI segment left to be added at runtime
+    #[error("No PCI segment left to be added at runtime")]
+    NoPciSegmentToAdd,
+
+    /// Disks exposed through NVMe can't be hotplugged
+    #[error("Disks exposed through NVMe can't be hotplugged")]
+    NvmeHotplugUnsupported,
+
+    /// Cannot read the metadata service document
+    #[error("Cannot read the metadata service document")]
+    ReadImdsDocument(#[source] io::Error),
+
+    /// Cannot parse the metadata service document
+    #[error("Cannot parse the metadata service document")]
+    ParseImdsDocument(#[source] serde_json::Error),
+
     /// Failed to parse disk image format
     #[error("Failed to parse disk image format")]
     DetectImageType(#[source] io::Error),
 
+    /// Disk image format not matching the one from the configuration
+    #[error("Disk image format is {1} rather than {0}")]
+    ImageTypeMismatch(ImageType, ImageType),
+
+    /// I/O engine not supported by the host
+    #[error("The {0} I/O engine is not supported by the host")]
+    IoEngineUnavailable(IoEngine),
+
+    /// I/O engine not supported by the disk image format
+    #[error("The {0} I/O engine is not supported for {1} images")]
+    IoEngineUnsupported(IoEngine, ImageType),
+
     /// Cannot open qcow disk path
     #[error("Cannot open qcow disk path")]
     QcowDeviceCreate(#[source] qcow::Error),
@@ -344,6 +426,26 @@ pub enum DeviceManagerError {
     #[error("Cannot create a VFIO PCI device")]
     VfioPciCreate(#[source] pci::VfioPciError),
 
+    /// Cannot load the option ROM of a device
+    #[error("Cannot load the option ROM of a device")]
+    LoadOptionRom(#[source] io::Error),
+
+    /// Legacy VGA ranges not exposed for a VFIO device
+    #[error("Legacy VGA ranges not exposed for VFIO device {0:?}")]
+    VfioLegacyVgaUnavailable(PathBuf),
+
+    /// Mediated device placed behind a virtual IOMMU
+    #[error("Mediated device {0:?} can't be placed behind a virtual IOMMU")]
+    MdevIommuUnsupported(PathBuf),
+
+    /// Mediated device restricted to a specific reset method
+    #[error("Mediated device {0:?} only supports the auto and none reset methods")]
+    MdevResetMethodUnsupported(PathBuf),
+
+    /// Invalid IOMMU group for a VFIO device
+    #[error("Invalid IOMMU group for VFIO device")]
+    VfioGroup(#[source] VfioGroupError),
+
     /// Failed to map VFIO MMIO region.
     #[error("Failed to map VFIO MMIO region")]
     VfioMapRegion(#[source] pci::VfioPciError),
@@ -452,6 +554,62 @@ pub enum DeviceManagerError {
     #[error("Failed to find device corresponding to the given identifier")]
     UnknownDeviceId(String),
 
+    /// Only VFIO devices can be reset.
+    #[error("Not allowed to reset device {0}, only VFIO devices can be reset")]
+    ResetNotAllowed(String),
+
+    /// The device is already being reset.
+    #[error("Device {0} is already being reset")]
+    ResetPending(String),
+
+    /// Only virtio-block devices can move between rate limiter groups.
+    #[error("Not allowed to change the rate limiter group of device {0}")]
+    RateLimitGroupNotAllowed(String),
+
+    /// Failed to find the rate limiter group with the given identifier.
+    #[error("Failed to find rate limiter group {0}")]
+    UnknownRateLimitGroup(String),
+
+    /// Rate limiter group with the given identifier already exists.
+    #[error("Rate limiter group {0} already exists")]
+    DuplicateRateLimitGroup(String),
+
+    /// Failed to move the device to another rate limiter group.
+    #[error("Failed to update the rate limiter group of the device")]
+    SetRateLimitGroup(#[source] io::Error),
+
+    /// Only virtio-block devices can be resized.
+    #[error("Not allowed to resize device {0}")]
+    ResizeDiskNotAllowed(String),
+
+    /// Failed to resize the disk.
+    #[error("Failed to resize the disk")]
+    ResizeDisk(#[source] virtio_devices::block::Error),
+
+    /// Only the writable virtio-block devices can be switched to an overlay.
+    #[error("Not allowed to create an overlay for device {0}")]
+    DiskOverlayNotAllowed(String),
+
+    /// The path of the disk image can't be recorded in the overlay.
+    #[error("Disk image path {0} is not valid UTF-8")]
+    DiskOverlayBackingPath(PathBuf),
+
+    /// Failed to create the overlay file.
+    #[error("Failed to create the overlay file {0}")]
+    CreateDiskOverlayFile(PathBuf, #[source] io::Error),
+
+    /// Failed to create the overlay image.
+    #[error("Failed to create the overlay image")]
+    CreateDiskOverlay(#[source] qcow::Error),
+
+    /// Failed to switch the disk to another image.
+    #[error("Failed to switch the disk image")]
+    SwitchDiskImage(#[source] virtio_devices::block::Error),
+
+    /// A device sharing the IOMMU group is already being removed or reset.
+    #[error("Device {0} shares its IOMMU group with a device being removed or reset")]
+    ResetGroupBusy(String),
+
     /// Failed to find an available PCI device ID.
     #[error("Failed to find an available PCI device ID")]
     NextPciDeviceId(#[source] pci::PciRootError),
@@ -492,6 +650,14 @@ pub enum DeviceManagerError {
     #[error("Trying to use a size that is not multiple of 2MiB")]
     PmemSizeNotAligned,
 
+    /// The device DAX backing the pmem device can't be used
+    #[error("Invalid device DAX backing the pmem device")]
+    PmemDax(#[source] io::Error),
+
+    /// Trying to use discard_writes or nvdimm with a device DAX
+    #[error("Trying to use discard_writes or nvdimm with a device DAX")]
+    PmemDaxUnsupported,
+
     /// Could not find the node in the device tree.
     #[error("Could not find the node in the device tree")]
     MissingNode,
@@ -557,6 +723,14 @@ pub enum DeviceManagerError {
     #[error("Failed to create FixedVhdxDiskSync")]
     CreateFixedVhdxDiskSync(#[source] vhdx::VhdxError),
 
+    /// Failed to read the key of an encrypted disk image
+    #[error("Failed to read the key of an encrypted disk image")]
+    ReadDiskKey(#[source] io::Error),
+
+    /// Failed to create LuksDiskSync
+    #[error("Failed to create LuksDiskSync")]
+    CreateLuksDiskSync(#[source] luks::Error),
+
     /// Failed to add DMA mapping handler to virtio-mem device.
     #[error("Failed to add DMA mapping handler to virtio-mem device")]
     AddDmaMappingHandlerVirtioMem(#[source] virtio_devices::mem::Error),
@@ -629,6 +803,10 @@ pub enum DeviceManagerError {
     #[error("Cannot create a RateLimiterGroup")]
     RateLimiterGroupCreate(#[source] rate_limiter::group::Error),
 
+    /// Cannot create the I/O thread pool
+    #[error("Cannot create the I/O thread pool")]
+    CreateIoThreadPool(#[source] virtio_devices::io_thread_pool::Error),
+
     /// Cannot start sigwinch listener
     #[error("Cannot start sigwinch listener")]
     StartSigwinchListener(#[source] std::io::Error),
@@ -644,11 +822,36 @@ pub enum DeviceManagerError {
     /// Cannot lock images of all block devices.
     #[error("Cannot lock images of all block devices")]
     DiskLockError(#[source] virtio_devices::block::Error),
+
+    /// Cannot set up the network namespace of the TAP interfaces
+    #[error("Cannot set up the network namespace of the TAP interfaces")]
+    NetNamespace(#[source] net_util::netns::Error),
+
+    /// Cannot set up the macvtap interface of a virtio-net device
+    #[error("Cannot set up the macvtap interface of a virtio-net device")]
+    Macvtap(#[source] net_util::macvtap::Error),
+
+    /// Cannot set up the SR-IOV virtual function of a device
+    #[error("Cannot set up the SR-IOV virtual function of a device")]
+    SriovVf(#[source] net_util::sriov::Error),
+
+    /// Cannot lock images of all NVMe disks.
+    #[error("Cannot lock images of all NVMe disks")]
+    NvmeDiskLockError(#[source] devices::nvme::NvmeError),
+
+    /// Cannot start the VMBus worker
+    #[cfg(target_arch = "x86_64")]
+    #[error("Cannot start the VMBus worker")]
+    StartVmBusWorker(#[source] io::Error),
+
+    /// Cannot set up the Landlock rules of the device worker threads
+    #[error("Cannot set up the Landlock rules of the device worker threads")]
+    ThreadLandlock(#[source] LandlockError),
 }
 
 pub type DeviceManagerResult<T> = result::Result<T, DeviceManagerError>;
 
-const DEVICE_MANAGER_ACPI_SIZE: usize = 0x10;
+const DEVICE_MANAGER_ACPI_SIZE: usize = 0x18;
 
 #[derive(Default)]
 pub struct Console {
@@ -840,6 +1043,33 @@ impl DeviceRelocation for AddressManager {
 struct DeviceManagerState {
     device_tree: DeviceTree,
     device_id_cnt: Wrapping<usize>,
+    #[serde(default)]
+    rtc_clock: Option<RtcClockState>,
+}
+
+// Offset of the guest RTC from the host realtime clock, in nanoseconds.
+#[cfg(not(target_arch = "riscv64"))]
+fn rtc_offset(base: RtcBase) -> i64 {
+    const NANOS_PER_SECOND: i64 = 1_000_000_000;
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    match base {
+        RtcBase::Utc => 0,
+        RtcBase::Localtime => {
+            // https://github.com/rust-lang/libc/issues/1848
+            #[cfg_attr(target_env = "musl", allow(deprecated))]
+            let now = now.as_secs() as libc::time_t;
+            // SAFETY: It is safe to zero initialize the tm struct because it
+            // contains only plain data, which localtime_r fills.
+            let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+            // SAFETY: Both structs are valid.
+            unsafe { libc::localtime_r(&now, &mut tm) };
+            tm.tm_gmtoff * NANOS_PER_SECOND
+        }
+        RtcBase::Date(date) => date * NANOS_PER_SECOND - now.as_nanos() as i64,
+    }
 }
 
 #[derive(Debug)]
@@ -871,6 +1101,61 @@ struct MetaVirtioDevice {
     id: String,
     pci_segment: u16,
     dma_handler: Option<Arc<dyn ExternalDmaMapping>>,
+    rom: Option<PciRom>,
+    msix_vectors: Option<u16>,
+}
+
+// VFIO device opened ahead of being added to the VM, along with the VFIO
+// container it is attached to
+struct OpenedVfioDevice {
+    device: VfioDevice,
+    container: Arc<VfioContainer>,
+    // Whether the guest memory must be DMA mapped through the container
+    needs_dma_mapping: bool,
+}
+
+// Backing file of a persistent memory device, mapped into the guest
+struct PmemMapping {
+    file: File,
+    mmap_region: MmapRegion<AtomicBitmap>,
+    base: u64,
+    size: u64,
+    host_addr: u64,
+    mem_slot: u32,
+    // Mapped with MAP_SYNC from a device DAX.
+    synchronous: bool,
+}
+
+// VFIO devices reset together, the requested one along with the devices
+// sharing its IOMMU group, and the ones the guest already ejected
+struct PendingDeviceReset {
+    devices: Vec<(PciBdf, DeviceConfig)>,
+    ejected: Vec<PciBdf>,
+}
+
+impl PendingDeviceReset {
+    fn new(devices: Vec<(PciBdf, DeviceConfig)>) -> Self {
+        PendingDeviceReset {
+            devices,
+            ejected: Vec::new(),
+        }
+    }
+
+    fn contains(&self, bdf: PciBdf) -> bool {
+        self.devices.iter().any(|(b, _)| *b == bdf)
+    }
+
+    // Returns whether all the devices are now ejected.
+    fn eject(&mut self, bdf: PciBdf) -> bool {
+        if !self.ejected.contains(&bdf) {
+            self.ejected.push(bdf);
+        }
+        self.ejected()
+    }
+
+    fn ejected(&self) -> bool {
+        self.devices.iter().all(|(b, _)| self.ejected.contains(b))
+    }
 }
 
 #[derive(Default)]
@@ -960,6 +1245,9 @@ pub struct DeviceManager {
     /// All disks. Needed for locking and unlocking the images.
     block_devices: Vec<Arc<Mutex<Block>>>,
 
+    /// Disks exposed through an emulated NVMe controller.
+    nvme_devices: Vec<Arc<Mutex<devices::nvme::NvmeController>>>,
+
     // List of bus devices
     // Let the DeviceManager keep strong references to the BusDevice devices.
     // This allows the IO and MMIO buses to be provided with Weak references,
@@ -987,6 +1275,10 @@ pub struct DeviceManager {
     // DeviceManager to be reused.
     vfio_container: Option<Arc<VfioContainer>>,
 
+    // VFIO devices being reset, waiting for the guest to eject them before
+    // they get reset and plugged back.
+    pending_device_resets: Vec<PendingDeviceReset>,
+
     // Paravirtualized IOMMU
     iommu_device: Option<Arc<Mutex<virtio_devices::Iommu>>>,
     iommu_mapping: Option<Arc<IommuMapping>>,
@@ -1004,6 +1296,8 @@ pub struct DeviceManager {
     // Exit event
     exit_evt: EventFd,
     reset_evt: EventFd,
+    // Signaled on guest crashes, e.g. watchdog expiry or guest panic
+    crash_evt: EventFd,
 
     #[cfg(any(target_arch = "aarch64", target_arch = "riscv64"))]
     id_to_dev_info: HashMap<(DeviceType, String), MmioDeviceInfo>,
@@ -1021,6 +1315,10 @@ pub struct DeviceManager {
     // activation and thus start the threads from the VMM thread
     activate_evt: EventFd,
 
+    // EventFd kicking the VMM thread to plug back the VFIO devices being
+    // reset once the guest ejected them
+    device_reset_evt: EventFd,
+
     #[cfg(not(target_arch = "riscv64"))]
     acpi_address: GuestAddress,
 
@@ -1042,6 +1340,24 @@ pub struct DeviceManager {
     // pvpanic device
     pvpanic_device: Option<Arc<Mutex<devices::PvPanicDevice>>>,
 
+    #[cfg(not(target_arch = "riscv64"))]
+    // TPM device
+    tpm_device: Option<Arc<Mutex<devices::tpm::Tpm>>>,
+
+    #[cfg(not(target_arch = "riscv64"))]
+    // ACPI NVDIMM devices
+    nvdimm_controller: Option<Arc<Mutex<devices::nvdimm::NvdimmController>>>,
+
+    #[cfg(not(target_arch = "riscv64"))]
+    // Guest mappings of the NVDIMM devices memory
+    nvdimm_mappings: Vec<MmapRegion<AtomicBitmap>>,
+
+    // Hyper-V VMBus, along with the thread servicing it
+    #[cfg(target_arch = "x86_64")]
+    vmbus: Option<Arc<Mutex<VmBus>>>,
+    #[cfg(target_arch = "x86_64")]
+    vmbus_worker: Option<VmBusWorker>,
+
     // Flag to force setting the iommu on virtio devices
     force_iommu: bool,
 
@@ -1058,6 +1374,10 @@ pub struct DeviceManager {
     // Start time of the VM
     timestamp: Instant,
 
+    #[cfg(target_arch = "x86_64")]
+    // Set once the guest reports boot progress past the firmware
+    boot_milestone: Arc<AtomicBool>,
+
     // Pending activations
     pending_activations: Arc<Mutex<Vec<VirtioPciDeviceActivator>>>,
 
@@ -1069,7 +1389,28 @@ pub struct DeviceManager {
 
     rate_limit_groups: HashMap<String, Arc<RateLimiterGroup>>,
 
+    // Clock of the RTC device, if any
+    rtc_clock: Option<Arc<Mutex<RtcClock>>>,
+
+    // Index of the schedule currently applied to each rate-limiter group,
+    // groups missing from it using their own limits.
+    active_rate_limit_schedules: HashMap<String, usize>,
+
+    // Threads shared by the virtio-block and virtio-net devices, if any
+    io_thread_pool: Option<IoThreadPool>,
+
     mmio_regions: Arc<Mutex<Vec<MmioRegion>>>,
+
+    // Names of the TAP interfaces of the virtio-net devices, by device id
+    tap_names: BTreeMap<String, String>,
+
+    // Network namespace the TAP interfaces are created in
+    netns: Option<Arc<NetNamespace>>,
+
+    // SR-IOV virtual functions created for the VFIO devices, by device id.
+    // Must stay the last field so that the virtual functions are given back
+    // to the host after the VFIO devices are closed.
+    sriov_vfs: HashMap<String, SriovVf>,
 }
 
 fn create_mmio_allocators(
@@ -1111,14 +1452,17 @@ impl DeviceManager {
         cpu_manager: Arc<Mutex<CpuManager>>,
         exit_evt: EventFd,
         reset_evt: EventFd,
+        crash_evt: EventFd,
         seccomp_action: SeccompAction,
         numa_nodes: NumaNodes,
         activate_evt: &EventFd,
+        device_reset_evt: &EventFd,
         force_iommu: bool,
         boot_id_list: BTreeSet<String>,
         #[cfg(not(target_arch = "riscv64"))] timestamp: Instant,
         snapshot: Option<Snapshot>,
         dynamic: bool,
+        #[cfg(target_arch = "x86_64")] vmbus: Option<Arc<Mutex<VmBus>>>,
     ) -> DeviceManagerResult<Arc<Mutex<Self>>> {
         trace_scoped!("DeviceManager::new");
 
@@ -1138,9 +1482,12 @@ impl DeviceManager {
             } else {
                 1
             };
+        // The segments which can be added at runtime get their apertures
+        // carved out at boot.
+        let max_pci_segments = config.lock().unwrap().max_pci_segments();
 
         let mut mmio32_aperture_weights: Vec<u32> =
-            std::iter::repeat_n(DEFAULT_PCI_SEGMENT_APERTURE_WEIGHT, num_pci_segments.into())
+            std::iter::repeat_n(DEFAULT_PCI_SEGMENT_APERTURE_WEIGHT, max_pci_segments.into())
                 .collect();
         if let Some(pci_segments) = &config.lock().unwrap().pci_segments {
             for pci_segment in pci_segments.iter() {
@@ -1154,13 +1501,13 @@ impl DeviceManager {
         let pci_mmio32_allocators = create_mmio_allocators(
             start_of_mmio32_area,
             end_of_mmio32_area,
-            num_pci_segments,
+            max_pci_segments,
             mmio32_aperture_weights,
             4 << 10,
         );
 
         let mut mmio64_aperture_weights: Vec<u32> =
-            std::iter::repeat_n(DEFAULT_PCI_SEGMENT_APERTURE_WEIGHT, num_pci_segments.into())
+            std::iter::repeat_n(DEFAULT_PCI_SEGMENT_APERTURE_WEIGHT, max_pci_segments.into())
                 .collect();
         if let Some(pci_segments) = &config.lock().unwrap().pci_segments {
             for pci_segment in pci_segments.iter() {
@@ -1174,7 +1521,7 @@ impl DeviceManager {
         let pci_mmio64_allocators = create_mmio_allocators(
             start_of_mmio64_area,
             end_of_mmio64_area,
-            num_pci_segments,
+            max_pci_segments,
             mmio64_aperture_weights,
             4 << 30,
         );
@@ -1221,7 +1568,7 @@ impl DeviceManager {
             &pci_irq_slots,
         )?];
 
-        for i in 1..num_pci_segments as usize {
+        for i in 1..max_pci_segments as usize {
             pci_segments.push(PciSegment::new(
                 i as u16,
                 numa_node_id_from_pci_segment_id(&numa_nodes, i as u16),
@@ -1229,6 +1576,7 @@ impl DeviceManager {
                 Arc::clone(&address_manager.pci_mmio32_allocators[i]),
                 Arc::clone(&address_manager.pci_mmio64_allocators[i]),
                 &pci_irq_slots,
+                i >= num_pci_segments as usize,
             )?);
         }
 
@@ -1255,28 +1603,34 @@ impl DeviceManager {
         let mut rate_limit_groups = HashMap::<String, Arc<RateLimiterGroup>>::new();
         if let Some(rate_limit_groups_cfg) = config.lock().unwrap().rate_limit_groups.as_ref() {
             for rate_limit_group_cfg in rate_limit_groups_cfg {
-                let rate_limit_cfg = rate_limit_group_cfg.rate_limiter_config;
-                let bw = rate_limit_cfg.bandwidth.unwrap_or_default();
-                let ops = rate_limit_cfg.ops.unwrap_or_default();
-                let mut rate_limit_group = RateLimiterGroup::new(
-                    &rate_limit_group_cfg.id,
-                    bw.size,
-                    bw.one_time_burst.unwrap_or(0),
-                    bw.refill_time,
-                    ops.size,
-                    ops.one_time_burst.unwrap_or(0),
-                    ops.refill_time,
-                )
-                .map_err(DeviceManagerError::RateLimiterGroupCreate)?;
-
-                let exit_evt = exit_evt.try_clone().map_err(DeviceManagerError::EventFd)?;
-
-                rate_limit_group.start_thread(exit_evt).unwrap();
-                rate_limit_groups
-                    .insert(rate_limit_group_cfg.id.clone(), Arc::new(rate_limit_group));
+                rate_limit_groups.insert(
+                    rate_limit_group_cfg.id.clone(),
+                    Self::create_rate_limit_group(rate_limit_group_cfg, &exit_evt)?,
+                );
             }
         }
 
+        let io_thread_pool =
+            if let Some(io_threads_cfg) = config.lock().unwrap().io_threads.as_ref() {
+                let affinity = io_threads_cfg
+                    .affinity
+                    .iter()
+                    .flatten()
+                    .map(|a| (a.io_thread as usize, a.host_cpus.clone()))
+                    .collect();
+                Some(
+                    IoThreadPool::new(
+                        io_threads_cfg.count as usize,
+                        &affinity,
+                        &seccomp_action,
+                        &exit_evt,
+                    )
+                    .map_err(DeviceManagerError::CreateIoThreadPool)?,
+                )
+            } else {
+                None
+            };
+
         let device_manager = DeviceManager {
             address_manager: Arc::clone(&address_manager),
             console: Arc::new(Console::default()),
@@ -1289,12 +1643,14 @@ impl DeviceManager {
             cpu_manager,
             virtio_devices: Vec::new(),
             block_devices: vec![],
+            nvme_devices: vec![],
             bus_devices: Vec::new(),
             device_id_cnt,
             msi_interrupt_manager,
             legacy_interrupt_manager: None,
             passthrough_device: None,
             vfio_container: None,
+            pending_device_resets: Vec::new(),
             iommu_device: None,
             iommu_mapping: None,
             iommu_attached_devices: None,
@@ -1302,6 +1658,7 @@ impl DeviceManager {
             device_tree,
             exit_evt,
             reset_evt,
+            crash_evt,
             #[cfg(any(target_arch = "aarch64", target_arch = "riscv64"))]
             id_to_dev_info: HashMap::new(),
             seccomp_action,
@@ -1310,6 +1667,9 @@ impl DeviceManager {
             activate_evt: activate_evt
                 .try_clone()
                 .map_err(DeviceManagerError::EventFd)?,
+            device_reset_evt: device_reset_evt
+                .try_clone()
+                .map_err(DeviceManagerError::EventFd)?,
             #[cfg(not(target_arch = "riscv64"))]
             acpi_address,
             selected_segment: 0,
@@ -1322,18 +1682,36 @@ impl DeviceManager {
             #[cfg(feature = "pvmemcontrol")]
             pvmemcontrol_devices: None,
             pvpanic_device: None,
+            #[cfg(not(target_arch = "riscv64"))]
+            tpm_device: None,
+            #[cfg(not(target_arch = "riscv64"))]
+            nvdimm_controller: None,
+            #[cfg(not(target_arch = "riscv64"))]
+            nvdimm_mappings: Vec::new(),
+            #[cfg(target_arch = "x86_64")]
+            vmbus,
+            #[cfg(target_arch = "x86_64")]
+            vmbus_worker: None,
             force_iommu,
             io_uring_supported: None,
             aio_supported: None,
             boot_id_list,
             #[cfg(not(target_arch = "riscv64"))]
             timestamp,
+            #[cfg(target_arch = "x86_64")]
+            boot_milestone: Arc::new(AtomicBool::new(false)),
             pending_activations: Arc::new(Mutex::new(Vec::default())),
             #[cfg(not(target_arch = "riscv64"))]
             acpi_platform_addresses: AcpiPlatformAddresses::default(),
             snapshot,
             rate_limit_groups,
+            rtc_clock: None,
+            active_rate_limit_schedules: HashMap::new(),
+            io_thread_pool,
             mmio_regions: Arc::new(Mutex::new(Vec::new())),
+            tap_names: BTreeMap::new(),
+            netns: None,
+            sriov_vfs: HashMap::new(),
         };
 
         let device_manager = Arc::new(Mutex::new(device_manager));
@@ -1432,7 +1810,8 @@ impl DeviceManager {
         if let Some(tpm) = self.config.clone().lock().unwrap().tpm.as_ref() {
             let tpm_dev = self.add_tpm_device(tpm.socket.clone())?;
             self.bus_devices
-                .push(Arc::clone(&tpm_dev) as Arc<dyn BusDeviceSync>)
+                .push(Arc::clone(&tpm_dev) as Arc<dyn BusDeviceSync>);
+            self.tpm_device = Some(tpm_dev);
         }
         self.legacy_interrupt_manager = Some(legacy_interrupt_manager);
 
@@ -1442,6 +1821,11 @@ impl DeviceManager {
 
         self.virtio_devices = virtio_devices;
 
+        #[cfg(not(target_arch = "riscv64"))]
+        {
+            self.nvdimm_controller = self.add_nvdimm_controller()?;
+        }
+
         // Add pvmemcontrol if required
         #[cfg(feature = "pvmemcontrol")]
         {
@@ -1457,6 +1841,42 @@ impl DeviceManager {
             self.pvpanic_device = self.add_pvpanic_device()?;
         }
 
+        #[cfg(target_arch = "x86_64")]
+        self.add_vmbus()?;
+
+        Ok(())
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    fn add_vmbus(&mut self) -> DeviceManagerResult<()> {
+        let Some(vmbus) = self.vmbus.clone() else {
+            return Ok(());
+        };
+
+        // VMBus interrupts are delivered through the SynIC, the IRQ is only
+        // reported to the guest as the resource of the ACPI device.
+        let irq = self
+            .address_manager
+            .allocator
+            .lock()
+            .unwrap()
+            .allocate_irq()
+            .ok_or(DeviceManagerError::AllocateIrq)?;
+        let interrupt_group = self
+            .msi_interrupt_manager
+            .create_group(MsiIrqGroupConfig {
+                base: 0,
+                count: VMBUS_INTERRUPT_COUNT as InterruptIndex,
+            })
+            .map_err(DeviceManagerError::CreateInterruptGroup)?;
+        vmbus
+            .lock()
+            .unwrap()
+            .set_interrupt_group(interrupt_group, irq);
+
+        self.vmbus_worker =
+            Some(VmBusWorker::new(vmbus).map_err(DeviceManagerError::StartVmBusWorker)?);
+
         Ok(())
     }
 
@@ -1464,9 +1884,34 @@ impl DeviceManager {
         DeviceManagerState {
             device_tree: self.device_tree.lock().unwrap().clone(),
             device_id_cnt: self.device_id_cnt,
+            rtc_clock: self
+                .rtc_clock
+                .as_ref()
+                .map(|clock| clock.lock().unwrap().state()),
         }
     }
 
+    // Creates the clock of the RTC device, carrying on from the snapshot
+    // if any.
+    #[cfg(not(target_arch = "riscv64"))]
+    fn create_rtc_clock(&mut self) -> Arc<Mutex<RtcClock>> {
+        let rtc = self.config.lock().unwrap().rtc.unwrap_or_default();
+        let slew = rtc.drift == RtcDrift::Slew;
+        let state = self
+            .snapshot
+            .as_ref()
+            .and_then(|snapshot| snapshot.to_state::<DeviceManagerState>().ok())
+            .and_then(|state| state.rtc_clock);
+        let clock = match state {
+            Some(state) => RtcClock::from_state(state, slew),
+            None => RtcClock::new(rtc_offset(rtc.base), slew),
+        };
+
+        let clock = Arc::new(Mutex::new(clock));
+        self.rtc_clock = Some(clock.clone());
+        clock
+    }
+
     fn get_msi_iova_space(&mut self) -> (u64, u64) {
         #[cfg(target_arch = "aarch64")]
         {
@@ -1556,6 +2001,8 @@ impl DeviceManager {
                     handle.id,
                     handle.pci_segment,
                     handle.dma_handler,
+                    handle.rom,
+                    handle.msix_vectors,
                 )?;
 
                 if handle.iommu {
@@ -1569,6 +2016,8 @@ impl DeviceManager {
             let mut vfio_user_iommu_device_ids = self.add_user_devices()?;
             iommu_attached_devices.append(&mut vfio_user_iommu_device_ids);
 
+            self.add_nvme_devices()?;
+
             // Add all devices from forced iommu segments
             if let Some(platform_config) = self.config.lock().unwrap().platform.as_ref() {
                 if let Some(iommu_segments) = platform_config.iommu_segments.as_ref() {
@@ -1584,7 +2033,8 @@ impl DeviceManager {
             }
 
             if let Some(iommu_device) = iommu_device {
-                let dev_id = self.add_virtio_pci_device(iommu_device, &None, iommu_id, 0, None)?;
+                let dev_id =
+                    self.add_virtio_pci_device(iommu_device, &None, iommu_id, 0, None, None, None)?;
                 self.iommu_attached_devices = Some((dev_id, iommu_attached_devices));
             }
         }
@@ -1744,10 +2194,12 @@ impl DeviceManager {
             .unwrap()
             .vcpus_kill_signalled()
             .clone();
+        let exit_reason = self.cpu_manager.lock().unwrap().exit_reason().clone();
         let shutdown_device = Arc::new(Mutex::new(devices::AcpiShutdownDevice::new(
             exit_evt,
             reset_evt,
             vcpus_kill_signalled,
+            exit_reason,
         )));
 
         self.bus_devices
@@ -1846,26 +2298,29 @@ impl DeviceManager {
 
     #[cfg(target_arch = "x86_64")]
     fn add_legacy_devices(&mut self, reset_evt: EventFd) -> DeviceManagerResult<()> {
-        let vcpus_kill_signalled = self
-            .cpu_manager
-            .lock()
-            .unwrap()
-            .vcpus_kill_signalled()
-            .clone();
-        // Add a shutdown device (i8042)
-        let i8042 = Arc::new(Mutex::new(devices::legacy::I8042Device::new(
-            reset_evt.try_clone().unwrap(),
-            vcpus_kill_signalled.clone(),
-        )));
+        // Legacy-free platforms rely on ACPI for reset and shutdown, and on
+        // the KVM clock instead of the RTC.
+        if self.config.lock().unwrap().legacy_devices() {
+            let vcpus_kill_signalled = self
+                .cpu_manager
+                .lock()
+                .unwrap()
+                .vcpus_kill_signalled()
+                .clone();
+            // Add a shutdown device (i8042)
+            let i8042 = Arc::new(Mutex::new(devices::legacy::I8042Device::new(
+                reset_evt.try_clone().unwrap(),
+                vcpus_kill_signalled.clone(),
+            )));
 
-        self.bus_devices
-            .push(Arc::clone(&i8042) as Arc<dyn BusDeviceSync>);
+            self.bus_devices
+                .push(Arc::clone(&i8042) as Arc<dyn BusDeviceSync>);
+
+            self.address_manager
+                .io_bus
+                .insert(i8042, 0x61, 0x4)
+                .map_err(DeviceManagerError::BusError)?;
 
-        self.address_manager
-            .io_bus
-            .insert(i8042, 0x61, 0x4)
-            .map_err(DeviceManagerError::BusError)?;
-        {
             // Add a CMOS emulated device
             let mem_size = self
                 .memory_manager
@@ -1884,6 +2339,7 @@ impl DeviceManager {
                 mem_above_4g,
                 reset_evt,
                 Some(vcpus_kill_signalled),
+                self.create_rtc_clock(),
             )));
 
             self.bus_devices
@@ -1893,20 +2349,23 @@ impl DeviceManager {
                 .io_bus
                 .insert(cmos, 0x70, 0x2)
                 .map_err(DeviceManagerError::BusError)?;
+        }
 
-            let fwdebug = Arc::new(Mutex::new(devices::legacy::FwDebugDevice::new()));
+        let fwdebug = Arc::new(Mutex::new(devices::legacy::FwDebugDevice::new()));
 
-            self.bus_devices
-                .push(Arc::clone(&fwdebug) as Arc<dyn BusDeviceSync>);
+        self.bus_devices
+            .push(Arc::clone(&fwdebug) as Arc<dyn BusDeviceSync>);
 
-            self.address_manager
-                .io_bus
-                .insert(fwdebug, 0x402, 0x1)
-                .map_err(DeviceManagerError::BusError)?;
-        }
+        self.address_manager
+            .io_bus
+            .insert(fwdebug, 0x402, 0x1)
+            .map_err(DeviceManagerError::BusError)?;
 
         // 0x80 debug port
-        let debug_port = Arc::new(Mutex::new(devices::legacy::DebugPort::new(self.timestamp)));
+        let debug_port = Arc::new(Mutex::new(devices::legacy::DebugPort::new(
+            self.timestamp,
+            self.boot_milestone.clone(),
+        )));
         self.bus_devices
             .push(Arc::clone(&debug_port) as Arc<dyn BusDeviceSync>);
         self.address_manager
@@ -1937,7 +2396,10 @@ impl DeviceManager {
             })
             .map_err(DeviceManagerError::CreateInterruptGroup)?;
 
-        let rtc_device = Arc::new(Mutex::new(devices::legacy::Rtc::new(interrupt_group)));
+        let rtc_device = Arc::new(Mutex::new(devices::legacy::Rtc::new(
+            interrupt_group,
+            self.create_rtc_clock(),
+        )));
 
         self.bus_devices
             .push(Arc::clone(&rtc_device) as Arc<dyn BusDeviceSync>);
@@ -2239,6 +2701,7 @@ impl DeviceManager {
         &mut self,
         virtio_devices: &mut Vec<MetaVirtioDevice>,
         console_fd: ConsoleOutput,
+        sinks: OutputSinks,
         resize_pipe: Option<Arc<File>>,
     ) -> DeviceManagerResult<Option<Arc<virtio_devices::ConsoleResizer>>> {
         let console_config = self.config.lock().unwrap().console.clone();
@@ -2277,7 +2740,7 @@ impl DeviceManager {
         };
         let id = String::from(CONSOLE_DEVICE_NAME);
 
-        let (virtio_console_device, console_resizer) = virtio_devices::Console::new(
+        let (mut virtio_console_device, console_resizer) = virtio_devices::Console::new(
             id.clone(),
             endpoint,
             self.console_resize_pipe
@@ -2292,6 +2755,7 @@ impl DeviceManager {
                 .map_err(DeviceManagerError::RestoreGetState)?,
         )
         .map_err(DeviceManagerError::CreateVirtioConsole)?;
+        virtio_console_device.set_output_sinks(sinks);
         let virtio_console_device = Arc::new(Mutex::new(virtio_console_device));
         virtio_devices.push(MetaVirtioDevice {
             virtio_device: Arc::clone(&virtio_console_device)
@@ -2300,6 +2764,8 @@ impl DeviceManager {
             id: id.clone(),
             pci_segment: 0,
             dma_handler: None,
+            rom: None,
+            msix_vectors: None,
         });
 
         // Fill the device tree with a new node. In case of restore, we
@@ -2347,6 +2813,7 @@ impl DeviceManager {
             | ConsoleOutput::Pty(_)
             | ConsoleOutput::Socket(_) => None,
         };
+        let serial_writer = console_info.serial_tee.sinks.tee(serial_writer);
 
         if !matches!(console_info.serial_main_fd, ConsoleOutput::Off) {
             let serial = self.add_serial_device(interrupt_manager, serial_writer)?;
@@ -2355,6 +2822,7 @@ impl DeviceManager {
                     let serial_manager = SerialManager::new(
                         serial,
                         console_info.serial_main_fd,
+                        console_info.serial_tee.sinks,
                         serial_config.socket,
                     )
                     .map_err(DeviceManagerError::CreateSerialManager)?;
@@ -2393,6 +2861,7 @@ impl DeviceManager {
         let console_resizer = self.add_virtio_console_device(
             virtio_devices,
             console_info.console_main_fd,
+            console_info.console_tee.sinks,
             console_resize_pipe,
         )?;
 
@@ -2434,6 +2903,11 @@ impl DeviceManager {
             dev.try_lock_image()
                 .map_err(DeviceManagerError::DiskLockError)?;
         }
+        for dev in &self.nvme_devices {
+            let mut dev = dev.lock().unwrap();
+            dev.try_lock_image()
+                .map_err(DeviceManagerError::NvmeDiskLockError)?;
+        }
         Ok(())
     }
 
@@ -2449,6 +2923,11 @@ impl DeviceManager {
             dev.unlock_image()
                 .map_err(DeviceManagerError::DiskLockError)?;
         }
+        for dev in &self.nvme_devices {
+            let mut dev = dev.lock().unwrap();
+            dev.unlock_image()
+                .map_err(DeviceManagerError::NvmeDiskLockError)?;
+        }
         Ok(())
     }
 
@@ -2477,6 +2956,9 @@ impl DeviceManager {
         // Add virtio-watchdog device
         devices.append(&mut self.make_virtio_watchdog_devices()?);
 
+        // Add virtio-scmi if required
+        devices.append(&mut self.make_virtio_scmi_devices()?);
+
         // Add vDPA devices if required
         devices.append(&mut self.make_vdpa_devices()?);
 
@@ -2494,6 +2976,44 @@ impl DeviceManager {
         supported
     }
 
+    // Pick the I/O thread servicing a device, either the one it has been
+    // assigned to or the next one in a round-robin fashion.
+    fn io_thread(&mut self, index: Option<u16>) -> Option<Arc<IoThread>> {
+        self.io_thread_pool
+            .as_mut()?
+            .io_thread(index.map(|i| i as usize))
+    }
+
+    // Host NUMA node the guest memory of the NUMA node holding the given PCI
+    // segment is bound to, if any.
+    #[cfg(feature = "io_uring")]
+    fn host_numa_node(&self, pci_segment: u16) -> Option<u32> {
+        let config = self.config.lock().unwrap();
+        let zones = config.memory.zones.as_ref()?;
+        let zone_ids: Vec<&String> = match config.numa.as_ref() {
+            Some(numa) => numa
+                .iter()
+                .find(|node| {
+                    node.pci_segments
+                        .as_ref()
+                        .is_some_and(|segments| segments.contains(&pci_segment))
+                })
+                .or_else(|| numa.iter().find(|node| node.guest_numa_id == 0))?
+                .memory_zones
+                .iter()
+                .flatten()
+                .collect(),
+            None => zones.iter().map(|zone| &zone.id).collect(),
+        };
+
+        let mut nodes = zones
+            .iter()
+            .filter(|zone| zone_ids.contains(&&zone.id))
+            .map(|zone| zone.host_numa_node);
+        let node = nodes.next()??;
+        nodes.all(|n| n == Some(node)).then_some(node)
+    }
+
     // Cache whether io_uring is supported to avoid probing for very block device
     fn io_uring_is_supported(&mut self) -> bool {
         if let Some(supported) = self.io_uring_supported {
@@ -2505,73 +3025,34 @@ impl DeviceManager {
         supported
     }
 
-    /// Creates a [`MetaVirtioDevice`] from the provided [`DiskConfig`].
+    /// Opens the disk image described by the provided [`DiskConfig`] and
+    /// picks the backend handling its format.
     ///
-    /// Depending on the config, this is a [`vhost_user::Blk`] device or a [`virtio_devices::Block`]
-    /// device.
+    /// This doesn't rely on the [`DeviceManager`] so that several images
+    /// can be opened concurrently.
     ///
     /// # Arguments
-    /// - `disk_cfg`: The [`DiskConfig`] used to create the block device.
-    /// - `is_hotplug`: Whether the device is being hotplugged and the lock for the disk image
-    ///   should be acquired right away. Locking will only happen for normal block devices, and not
-    ///   vhost-user devices.
-    fn make_virtio_block_device(
-        &mut self,
-        disk_cfg: &mut DiskConfig,
-        is_hotplug: bool,
-    ) -> DeviceManagerResult<MetaVirtioDevice> {
-        let id = if let Some(id) = &disk_cfg.id {
-            id.clone()
-        } else {
-            let id = self.next_device_name(DISK_DEVICE_NAME_PREFIX)?;
-            disk_cfg.id = Some(id.clone());
-            id
-        };
-
-        info!("Creating virtio-block device: {:?}", disk_cfg);
-
-        let (virtio_device, migratable_device) = if disk_cfg.vhost_user {
-            if is_hotplug {
-                log::debug!("Acquiring image lock for vhost-user block device not supported");
-            }
-            let socket = disk_cfg.vhost_socket.as_ref().unwrap().clone();
-            let vu_cfg = VhostUserConfig {
-                socket,
-                num_queues: disk_cfg.num_queues,
-                queue_size: disk_cfg.queue_size,
-            };
-            let vhost_user_block = Arc::new(Mutex::new(
-                match virtio_devices::vhost_user::Blk::new(
-                    id.clone(),
-                    vu_cfg,
-                    self.seccomp_action.clone(),
-                    self.exit_evt
-                        .try_clone()
-                        .map_err(DeviceManagerError::EventFd)?,
-                    self.force_iommu,
-                    state_from_id(self.snapshot.as_ref(), id.as_str())
-                        .map_err(DeviceManagerError::RestoreGetState)?,
-                ) {
-                    Ok(vub_device) => vub_device,
-                    Err(e) => {
-                        return Err(DeviceManagerError::CreateVhostUserBlk(e));
-                    }
-                },
-            ));
-
-            (
-                Arc::clone(&vhost_user_block) as Arc<Mutex<dyn virtio_devices::VirtioDevice>>,
-                vhost_user_block as Arc<Mutex<dyn Migratable>>,
-            )
+    /// - `disk_cfg`: The [`DiskConfig`] of a non vhost-user block device.
+    /// - `disk_file`: An already opened disk image, used instead of opening `disk_cfg.path`.
+    /// - `io_uring_supported`: Whether the host supports io_uring for block devices.
+    /// - `aio_supported`: Whether the host supports aio for block devices.
+    fn open_disk_image(
+        disk_cfg: &DiskConfig,
+        disk_file: Option<File>,
+        io_uring_supported: bool,
+        aio_supported: bool,
+    ) -> DeviceManagerResult<Box<dyn DiskFile>> {
+        let mut file: File = if let Some(file) = disk_file {
+            file
         } else {
             let mut options = OpenOptions::new();
             options.read(true);
             options.write(!disk_cfg.readonly);
-            if disk_cfg.direct {
+            if disk_cfg.direct_io() {
                 options.custom_flags(libc::O_DIRECT);
             }
             // Open block device path
-            let mut file: File = options
+            options
                 .open(
                     disk_cfg
                         .path
@@ -2579,74 +3060,221 @@ impl DeviceManager {
                         .ok_or(DeviceManagerError::NoDiskPath)?
                         .clone(),
                 )
-                .map_err(DeviceManagerError::Disk)?;
-            let image_type =
-                detect_image_type(&mut file).map_err(DeviceManagerError::DetectImageType)?;
-
-            let image = match image_type {
-                ImageType::FixedVhd => {
-                    // Use asynchronous backend relying on io_uring if the
-                    // syscalls are supported.
-                    if cfg!(feature = "io_uring")
-                        && !disk_cfg.disable_io_uring
-                        && self.io_uring_is_supported()
-                    {
-                        info!("Using asynchronous fixed VHD disk file (io_uring)");
-
-                        #[cfg(not(feature = "io_uring"))]
-                        unreachable!("Checked in if statement above");
-                        #[cfg(feature = "io_uring")]
-                        {
-                            Box::new(
-                                FixedVhdDiskAsync::new(file)
-                                    .map_err(DeviceManagerError::CreateFixedVhdDiskAsync)?,
-                            ) as Box<dyn DiskFile>
-                        }
-                    } else {
-                        info!("Using synchronous fixed VHD disk file");
-                        Box::new(
-                            FixedVhdDiskSync::new(file)
-                                .map_err(DeviceManagerError::CreateFixedVhdDiskSync)?,
-                        ) as Box<dyn DiskFile>
-                    }
-                }
-                ImageType::Raw => {
-                    // Use asynchronous backend relying on io_uring if the
-                    // syscalls are supported.
-                    if cfg!(feature = "io_uring")
-                        && !disk_cfg.disable_io_uring
-                        && self.io_uring_is_supported()
-                    {
-                        info!("Using asynchronous RAW disk file (io_uring)");
+                .map_err(DeviceManagerError::Disk)?
+        };
 
-                        #[cfg(not(feature = "io_uring"))]
-                        unreachable!("Checked in if statement above");
-                        #[cfg(feature = "io_uring")]
-                        {
-                            Box::new(RawFileDisk::new(file)) as Box<dyn DiskFile>
-                        }
-                    } else if !disk_cfg.disable_aio && self.aio_is_supported() {
-                        info!("Using asynchronous RAW disk file (aio)");
-                        Box::new(RawFileDiskAio::new(file)) as Box<dyn DiskFile>
-                    } else {
-                        info!("Using synchronous RAW disk file");
-                        Box::new(RawFileDiskSync::new(file)) as Box<dyn DiskFile>
-                    }
+        // The header of an encrypted image is the only one in clear, the
+        // format of its content being up to the guest.
+        if let Some(DiskEncryption::Luks) = disk_cfg.encryption {
+            let key = disk_cfg
+                .read_key()
+                .map_err(DeviceManagerError::ReadDiskKey)?;
+            info!("Using synchronous LUKS disk file");
+            return Ok(Box::new(
+                LuksDiskSync::new(file, &key).map_err(DeviceManagerError::CreateLuksDiskSync)?,
+            ) as Box<dyn DiskFile>);
+        }
+
+        // An image given as raw is never probed, as the guest may have
+        // written the header of another format at its start.
+        let image_type = match disk_cfg.format {
+            Some(ImageType::Raw) => ImageType::Raw,
+            format => {
+                let image_type =
+                    detect_image_type(&mut file).map_err(DeviceManagerError::DetectImageType)?;
+                if let Some(format) = format.filter(|f| *f != image_type) {
+                    return Err(DeviceManagerError::ImageTypeMismatch(format, image_type));
                 }
-                ImageType::Qcow2 => {
-                    info!("Using synchronous QCOW2 disk file");
+                image_type
+            }
+        };
+
+        let io_uring_supported = cfg!(feature = "io_uring") && io_uring_supported;
+        let io_engine = match (image_type, disk_cfg.io_engine) {
+            (_, Some(IoEngine::IoUring)) if !io_uring_supported => {
+                return Err(DeviceManagerError::IoEngineUnavailable(IoEngine::IoUring));
+            }
+            (_, Some(IoEngine::Aio)) if !aio_supported => {
+                return Err(DeviceManagerError::IoEngineUnavailable(IoEngine::Aio));
+            }
+            (ImageType::Raw, Some(io_engine))
+            | (ImageType::FixedVhd, Some(io_engine @ (IoEngine::IoUring | IoEngine::Sync)))
+            | (_, Some(io_engine @ IoEngine::Sync)) => io_engine,
+            (_, Some(io_engine)) => {
+                return Err(DeviceManagerError::IoEngineUnsupported(
+                    io_engine, image_type,
+                ));
+            }
+            // Pick the fastest backend supported by the host.
+            (ImageType::Raw | ImageType::FixedVhd, None) if io_uring_supported => IoEngine::IoUring,
+            (ImageType::Raw, None) if aio_supported => IoEngine::Aio,
+            (_, None) => IoEngine::Sync,
+        };
+
+        let io_uring_options = disk_cfg.io_uring_sqpoll || disk_cfg.io_uring_fixed_buffers;
+        if io_uring_options && (image_type != ImageType::Raw || io_engine != IoEngine::IoUring) {
+            warn!(
+                "Ignoring the io_uring options of disk {:?}, not using a raw image with the io_uring I/O engine",
+                disk_cfg.id
+            );
+        }
+
+        let image = match image_type {
+            ImageType::FixedVhd => {
+                if io_engine == IoEngine::IoUring {
+                    info!("Using asynchronous fixed VHD disk file (io_uring)");
+
+                    #[cfg(not(feature = "io_uring"))]
+                    unreachable!("Checked when picking the I/O engine");
+                    #[cfg(feature = "io_uring")]
+                    {
+                        Box::new(
+                            FixedVhdDiskAsync::new(file)
+                                .map_err(DeviceManagerError::CreateFixedVhdDiskAsync)?,
+                        ) as Box<dyn DiskFile>
+                    }
+                } else {
+                    info!("Using synchronous fixed VHD disk file");
                     Box::new(
-                        QcowDiskSync::new(file, disk_cfg.direct)
-                            .map_err(DeviceManagerError::CreateQcowDiskSync)?,
+                        FixedVhdDiskSync::new(file)
+                            .map_err(DeviceManagerError::CreateFixedVhdDiskSync)?,
                     ) as Box<dyn DiskFile>
                 }
-                ImageType::Vhdx => {
-                    info!("Using synchronous VHDX disk file");
-                    Box::new(
-                        VhdxDiskSync::new(file)
-                            .map_err(DeviceManagerError::CreateFixedVhdxDiskSync)?,
-                    ) as Box<dyn DiskFile>
+            }
+            ImageType::Raw => match io_engine {
+                IoEngine::IoUring => {
+                    info!("Using asynchronous RAW disk file (io_uring)");
+
+                    #[cfg(not(feature = "io_uring"))]
+                    unreachable!("Checked when picking the I/O engine");
+                    #[cfg(feature = "io_uring")]
+                    {
+                        let options = IoUringOptions {
+                            sqpoll: disk_cfg.io_uring_sqpoll,
+                            fixed_buffers: disk_cfg.io_uring_fixed_buffers,
+                        };
+                        Box::new(RawFileDisk::with_options(file, options)) as Box<dyn DiskFile>
+                    }
+                }
+                IoEngine::Aio => {
+                    info!("Using asynchronous RAW disk file (aio)");
+                    Box::new(RawFileDiskAio::new(file)) as Box<dyn DiskFile>
                 }
+                IoEngine::Sync => {
+                    info!("Using synchronous RAW disk file");
+                    Box::new(RawFileDiskSync::new(file)) as Box<dyn DiskFile>
+                }
+                IoEngine::Threads => {
+                    let threads = disk_cfg.io_engine_threads.unwrap_or(DEFAULT_IO_THREADS);
+                    info!("Using RAW disk file on {threads} worker threads");
+                    Box::new(RawFileDiskThreads::new(file, threads)) as Box<dyn DiskFile>
+                }
+            },
+            ImageType::Qcow2 => {
+                info!("Using synchronous QCOW2 disk file");
+                Box::new(
+                    QcowDiskSync::new(file, disk_cfg.direct_io())
+                        .map_err(DeviceManagerError::CreateQcowDiskSync)?,
+                ) as Box<dyn DiskFile>
+            }
+            ImageType::Vhdx => {
+                info!("Using synchronous VHDX disk file");
+                Box::new(
+                    VhdxDiskSync::new(file).map_err(DeviceManagerError::CreateFixedVhdxDiskSync)?,
+                ) as Box<dyn DiskFile>
+            }
+        };
+
+        Ok(image)
+    }
+
+    /// Creates a [`MetaVirtioDevice`] from the provided [`DiskConfig`].
+    ///
+    /// Depending on the config, this is a [`vhost_user::Blk`] device or a [`virtio_devices::Block`]
+    /// device.
+    ///
+    /// # Arguments
+    /// - `disk_cfg`: The [`DiskConfig`] used to create the block device.
+    /// - `is_hotplug`: Whether the device is being hotplugged and the lock for the disk image
+    ///   should be acquired right away. Locking will only happen for normal block devices, and not
+    ///   vhost-user devices.
+    /// - `disk_image`: An already opened disk image, used instead of opening `disk_cfg.path`.
+    // With Landlock enabled, the worker threads of a device are further
+    // restricted to the files from its own config.
+    fn set_thread_landlock(
+        &self,
+        virtio_device: &mut dyn virtio_devices::VirtioDevice,
+        config: &dyn ApplyLandlock,
+    ) -> DeviceManagerResult<()> {
+        if !self.config.lock().unwrap().landlock_enable {
+            return Ok(());
+        }
+
+        let mut landlock = Landlock::new().map_err(DeviceManagerError::ThreadLandlock)?;
+        config
+            .apply_landlock(&mut landlock)
+            .map_err(DeviceManagerError::ThreadLandlock)?;
+        virtio_device.set_thread_landlock(landlock.thread_landlock());
+
+        Ok(())
+    }
+
+    fn make_virtio_block_device(
+        &mut self,
+        disk_cfg: &mut DiskConfig,
+        is_hotplug: bool,
+        disk_image: Option<Box<dyn DiskFile>>,
+    ) -> DeviceManagerResult<MetaVirtioDevice> {
+        let id = if let Some(id) = &disk_cfg.id {
+            id.clone()
+        } else {
+            let id = self.next_device_name(DISK_DEVICE_NAME_PREFIX)?;
+            disk_cfg.id = Some(id.clone());
+            id
+        };
+
+        info!("Creating virtio-block device: {:?}", disk_cfg);
+
+        let (virtio_device, migratable_device) = if disk_cfg.vhost_user {
+            if is_hotplug {
+                log::debug!("Acquiring image lock for vhost-user block device not supported");
+            }
+            let socket = disk_cfg.vhost_socket.as_ref().unwrap().clone();
+            let vu_cfg = VhostUserConfig {
+                socket,
+                num_queues: disk_cfg.num_queues,
+                queue_size: disk_cfg.queue_size.max_size(MAXIMUM_BLOCK_QUEUE_SIZE),
+            };
+            let vhost_user_block = Arc::new(Mutex::new(
+                match virtio_devices::vhost_user::Blk::new(
+                    id.clone(),
+                    vu_cfg,
+                    self.seccomp_action.clone(),
+                    self.exit_evt
+                        .try_clone()
+                        .map_err(DeviceManagerError::EventFd)?,
+                    self.force_iommu,
+                    state_from_id(self.snapshot.as_ref(), id.as_str())
+                        .map_err(DeviceManagerError::RestoreGetState)?,
+                ) {
+                    Ok(vub_device) => vub_device,
+                    Err(e) => {
+                        return Err(DeviceManagerError::CreateVhostUserBlk(e));
+                    }
+                },
+            ));
+
+            (
+                Arc::clone(&vhost_user_block) as Arc<Mutex<dyn virtio_devices::VirtioDevice>>,
+                vhost_user_block as Arc<Mutex<dyn Migratable>>,
+            )
+        } else {
+            let image = if let Some(image) = disk_image {
+                image
+            } else {
+                let io_uring_supported = self.io_uring_is_supported();
+                let aio_supported = self.aio_is_supported();
+                Self::open_disk_image(disk_cfg, None, io_uring_supported, aio_supported)?
             };
 
             let rate_limit_group =
@@ -2701,7 +3329,7 @@ impl DeviceManager {
                 disk_cfg.readonly,
                 self.force_iommu | disk_cfg.iommu,
                 disk_cfg.num_queues,
-                disk_cfg.queue_size,
+                disk_cfg.queue_size.max_size(MAXIMUM_BLOCK_QUEUE_SIZE),
                 disk_cfg.serial.clone(),
                 self.seccomp_action.clone(),
                 rate_limit_group,
@@ -2714,6 +3342,42 @@ impl DeviceManager {
             )
             .map_err(DeviceManagerError::CreateVirtioBlock)?;
 
+            // Disks pinning their queues to host CPUs or setting their I/O
+            // priority keep a thread per queue, unless explicitly assigned to
+            // an I/O thread.
+            if disk_cfg.io_thread.is_some()
+                || (disk_cfg.queue_affinity.is_none() && disk_cfg.io_class.is_none())
+            {
+                if let Some(io_thread) = self.io_thread(disk_cfg.io_thread) {
+                    virtio_block.set_io_thread(io_thread);
+                }
+            }
+
+            if let Some(io_class) = disk_cfg.io_class {
+                virtio_block.set_io_priority(io_priority(
+                    io_class,
+                    disk_cfg.io_prio.unwrap_or(IOPRIO_DEFAULT_LEVEL),
+                ));
+            }
+
+            if let Some(coalescing) = disk_cfg.coalescing {
+                virtio_block.set_coalescing(coalescing.usecs, coalescing.max_used);
+            }
+
+            if let Some(cache) = disk_cfg.cache {
+                virtio_block.set_cache_mode(cache);
+            }
+
+            virtio_block.set_fair_queues(disk_cfg.fair_queues);
+            virtio_block.set_discard(disk_cfg.discard);
+            virtio_block.set_detect_zeroes(disk_cfg.detect_zeroes);
+
+            if let Err(e) = virtio_block.monitor_paths(disk_cfg.pause_on_path_failure) {
+                warn!("Cannot monitor the paths of disk {}: {}", id, e);
+            }
+
+            self.preserve_key_fd(disk_cfg);
+
             // We lock the file here only for hotplugging. In normal operation,
             // state save/resume, and live-migration, locking is part of the outer control flow
             // to ensure proper order of (un)locking.
@@ -2733,6 +3397,7 @@ impl DeviceManager {
                 virtio_block as Arc<Mutex<dyn Migratable>>,
             )
         };
+        self.set_thread_landlock(&mut *virtio_device.lock().unwrap(), disk_cfg)?;
 
         // Fill the device tree with a new node. In case of restore, we
         // know there is nothing to do, so we can simply override the
@@ -2748,26 +3413,301 @@ impl DeviceManager {
             id,
             pci_segment: disk_cfg.pci_segment,
             dma_handler: None,
+            rom: None,
+            msix_vectors: disk_cfg.msix_vectors,
         })
     }
 
+    // The key FD is kept open so that the image can be unlocked again on
+    // reboot.
+    fn preserve_key_fd(&self, disk_cfg: &DiskConfig) {
+        if let Some(key_fd) = disk_cfg.key_fd {
+            let mut config = self.config.lock().unwrap();
+            if !config
+                .preserved_fds
+                .iter()
+                .flatten()
+                .any(|fd| *fd == key_fd)
+            {
+                // SAFETY: 'key_fd' is valid because the disk image has
+                // just been unlocked by reading the key from it.
+                unsafe { config.add_preserved_fds(vec![key_fd]) };
+            }
+        }
+    }
+
     fn make_virtio_block_devices(&mut self) -> DeviceManagerResult<Vec<MetaVirtioDevice>> {
         let mut devices = Vec::new();
 
         let mut block_devices = self.config.lock().unwrap().disks.clone();
         if let Some(disk_list_cfg) = &mut block_devices {
-            for disk_cfg in disk_list_cfg.iter_mut() {
-                devices.push(self.make_virtio_block_device(disk_cfg, false)?);
+            let disk_images = self.open_disk_images(disk_list_cfg, DiskInterface::Virtio)?;
+            for (disk_cfg, disk_image) in disk_list_cfg.iter_mut().zip(disk_images) {
+                // NVMe controllers are created along with the other PCI
+                // devices.
+                if disk_cfg.interface == DiskInterface::Nvme {
+                    continue;
+                }
+                devices.push(self.make_virtio_block_device(disk_cfg, false, disk_image)?);
             }
         }
         self.config.lock().unwrap().disks = block_devices;
 
+        // Add the cloud-init seed if required
+        devices.append(&mut self.make_cloud_init_devices()?);
+
+        Ok(devices)
+    }
+
+    // Opening an image can take a while, e.g. when the metadata of a QCOW2
+    // or VHDX file has to be parsed, or when the backing storage is remote.
+    // Since the images don't depend on each other, they are all opened
+    // concurrently before the devices get created one after the other.
+    //
+    // Only the images of the disks exposed through `interface` are opened.
+    fn open_disk_images(
+        &mut self,
+        disk_list_cfg: &[DiskConfig],
+        interface: DiskInterface,
+    ) -> DeviceManagerResult<Vec<Option<Box<dyn DiskFile>>>> {
+        let opened =
+            |disk_cfg: &DiskConfig| !disk_cfg.vhost_user && disk_cfg.interface == interface;
+        if !disk_list_cfg.iter().any(opened) {
+            return Ok(disk_list_cfg.iter().map(|_| None).collect());
+        }
+
+        let io_uring_supported = self.io_uring_is_supported();
+        let aio_supported = self.aio_is_supported();
+
+        thread::scope(|s| {
+            let handles: Vec<_> = disk_list_cfg
+                .iter()
+                .map(|disk_cfg| {
+                    opened(disk_cfg).then(|| {
+                        s.spawn(move || {
+                            Self::open_disk_image(disk_cfg, None, io_uring_supported, aio_supported)
+                        })
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle
+                        .map(|h| h.join().unwrap_or_else(|e| panic::resume_unwind(e)))
+                        .transpose()
+                })
+                .collect()
+        })
+    }
+
+    fn make_cloud_init_devices(&mut self) -> DeviceManagerResult<Vec<MetaVirtioDevice>> {
+        let mut devices = Vec::new();
+
+        let (cloud_init_config, uuid) = {
+            let config = self.config.lock().unwrap();
+            if let Some(cloud_init_config) = &config.cloud_init {
+                (
+                    cloud_init_config.clone(),
+                    config.platform.as_ref().and_then(|p| p.uuid.clone()),
+                )
+            } else {
+                return Ok(devices);
+            }
+        };
+
+        info!("Creating cloud-init seed: {:?}", cloud_init_config);
+
+        let read = |path: &Option<PathBuf>| {
+            path.as_ref()
+                .map(std::fs::read)
+                .transpose()
+                .map_err(DeviceManagerError::CreateCloudInitSeed)
+        };
+        let user_data = read(&cloud_init_config.user_data)?.unwrap_or_default();
+        // The instance-id must remain the same across reboots and restores,
+        // otherwise cloud-init would run its first boot modules again.
+        let meta_data = read(&cloud_init_config.meta_data)?.unwrap_or_else(|| {
+            format!(
+                "instance-id: {}\n",
+                uuid.as_deref().unwrap_or("cloud-hypervisor")
+            )
+            .into_bytes()
+        });
+        let network_config = read(&cloud_init_config.network_config)?;
+
+        let mut files: Vec<(&str, &[u8])> = vec![
+            ("meta-data", meta_data.as_slice()),
+            ("user-data", user_data.as_slice()),
+        ];
+        if let Some(network_config) = &network_config {
+            files.push(("network-config", network_config.as_slice()));
+        }
+        let file = crate::cloud_init::create_seed_file(&files)
+            .map_err(DeviceManagerError::CreateCloudInitSeed)?;
+
+        // The seed isn't backed by any host path, only the serial is exposed
+        // to the guest.
+        let mut disk_cfg = DiskConfig {
+            path: Some(PathBuf::from("cloud-init")),
+            readonly: true,
+            direct: false,
+            iommu: false,
+            num_queues: DEFAULT_DISK_NUM_QUEUES,
+            queue_size: QueueSize::Fixed(DEFAULT_DISK_QUEUE_SIZE),
+            vhost_user: false,
+            vhost_socket: None,
+            rate_limit_group: None,
+            rate_limiter_config: None,
+            fair_queues: false,
+            id: Some(String::from(CLOUD_INIT_DEVICE_NAME)),
+            io_engine: None,
+            io_engine_threads: None,
+            io_uring_sqpoll: false,
+            io_uring_fixed_buffers: false,
+            io_class: None,
+            io_prio: None,
+            msix_vectors: None,
+            pci_segment: 0,
+            serial: Some(String::from("cloud-init")),
+            queue_affinity: None,
+            io_thread: None,
+            coalescing: None,
+            pause_on_path_failure: false,
+            format: None,
+            cache: None,
+            encryption: None,
+            key_file: None,
+            key_fd: None,
+            discard: false,
+            detect_zeroes: false,
+            interface: DiskInterface::Virtio,
+        };
+        let io_uring_supported = self.io_uring_is_supported();
+        let aio_supported = self.aio_is_supported();
+        let image =
+            Self::open_disk_image(&disk_cfg, Some(file), io_uring_supported, aio_supported)?;
+        devices.push(self.make_virtio_block_device(&mut disk_cfg, false, Some(image))?);
+
         Ok(devices)
     }
 
+    /// Creates a [`virtio_devices::Net`] device from the provided [`NetConfig`],
+    /// opening and configuring its TAP interfaces.
+    ///
+    /// This doesn't rely on the [`DeviceManager`] so that several devices
+    /// can be created concurrently.
+    fn create_virtio_net(
+        id: String,
+        net_cfg: &mut NetConfig,
+        force_iommu: bool,
+        seccomp_action: SeccompAction,
+        exit_evt: EventFd,
+        state: Option<NetState>,
+        netns: Option<&NetNamespace>,
+    ) -> DeviceManagerResult<virtio_devices::Net> {
+        // The TAP interfaces given as file descriptors are left in their own
+        // namespace, as are the macvtap interfaces, which must live in the
+        // namespace of their link.
+        if let Some(netns) = netns.filter(|_| {
+            net_cfg.macvtap.is_none() && (net_cfg.tap.is_some() || net_cfg.fds.is_none())
+        }) {
+            return netns
+                .run(|| {
+                    let virtio_net = Self::create_virtio_net(
+                        id,
+                        net_cfg,
+                        force_iommu,
+                        seccomp_action,
+                        exit_evt,
+                        state,
+                        None,
+                    )?;
+                    if netns.bridged() {
+                        netns
+                            .attach(&virtio_net.tap_name())
+                            .map_err(DeviceManagerError::NetNamespace)?;
+                    }
+                    Ok(virtio_net)
+                })
+                .map_err(DeviceManagerError::NetNamespace)?;
+        }
+
+        if let Some(macvtap) = &net_cfg.macvtap {
+            let macvtap = Macvtap::open_or_create(
+                macvtap,
+                net_cfg.macvtap_link.as_deref(),
+                net_cfg.macvtap_mode,
+                &net_cfg.mac,
+            )
+            .map_err(DeviceManagerError::Macvtap)?;
+            virtio_devices::Net::from_macvtap(
+                id,
+                macvtap,
+                Some(net_cfg.mac),
+                net_cfg.mtu,
+                force_iommu | net_cfg.iommu,
+                net_cfg.num_queues,
+                net_cfg.queue_size,
+                seccomp_action,
+                net_cfg.rate_limiter_config,
+                exit_evt,
+                state,
+                net_cfg.offload_tso,
+                net_cfg.offload_ufo,
+                net_cfg.offload_csum,
+            )
+        } else if let (None, Some(fds)) = (&net_cfg.tap, &net_cfg.fds) {
+            // An explicit TAP interface name takes precedence over file
+            // descriptors.
+            virtio_devices::Net::from_tap_fds(
+                id,
+                fds,
+                Some(net_cfg.mac),
+                net_cfg.mtu,
+                force_iommu | net_cfg.iommu,
+                net_cfg.queue_size,
+                seccomp_action,
+                net_cfg.rate_limiter_config,
+                exit_evt,
+                state,
+                net_cfg.offload_tso,
+                net_cfg.offload_ufo,
+                net_cfg.offload_csum,
+            )
+        } else {
+            virtio_devices::Net::new(
+                id,
+                net_cfg.tap.as_deref(),
+                Some(net_cfg.ip),
+                Some(net_cfg.mask),
+                Some(net_cfg.mac),
+                &mut net_cfg.host_mac,
+                net_cfg.mtu,
+                force_iommu | net_cfg.iommu,
+                net_cfg.num_queues,
+                net_cfg.queue_size,
+                seccomp_action,
+                net_cfg.rate_limiter_config,
+                exit_evt,
+                state,
+                net_cfg.offload_tso,
+                net_cfg.offload_ufo,
+                net_cfg.offload_csum,
+            )
+            .and_then(|virtio_net| {
+                virtio_net.configure_tap(net_cfg.tap_persist, net_cfg.tap_uid, net_cfg.tap_gid)?;
+                Ok(virtio_net)
+            })
+        }
+        .map_err(DeviceManagerError::CreateVirtioNet)
+    }
+
     fn make_virtio_net_device(
         &mut self,
         net_cfg: &mut NetConfig,
+        virtio_net: Option<virtio_devices::Net>,
     ) -> DeviceManagerResult<MetaVirtioDevice> {
         let id = if let Some(id) = &net_cfg.id {
             id.clone()
@@ -2819,91 +3759,85 @@ impl DeviceManager {
                 vhost_user_net as Arc<Mutex<dyn Migratable>>,
             )
         } else {
-            let state = state_from_id(self.snapshot.as_ref(), id.as_str())
-                .map_err(DeviceManagerError::RestoreGetState)?;
-            let virtio_net = if let Some(ref tap_if_name) = net_cfg.tap {
-                Arc::new(Mutex::new(
-                    virtio_devices::Net::new(
-                        id.clone(),
-                        Some(tap_if_name),
-                        Some(net_cfg.ip),
-                        Some(net_cfg.mask),
-                        Some(net_cfg.mac),
-                        &mut net_cfg.host_mac,
-                        net_cfg.mtu,
-                        self.force_iommu | net_cfg.iommu,
-                        net_cfg.num_queues,
-                        net_cfg.queue_size,
-                        self.seccomp_action.clone(),
-                        net_cfg.rate_limiter_config,
-                        self.exit_evt
-                            .try_clone()
-                            .map_err(DeviceManagerError::EventFd)?,
-                        state,
-                        net_cfg.offload_tso,
-                        net_cfg.offload_ufo,
-                        net_cfg.offload_csum,
-                    )
-                    .map_err(DeviceManagerError::CreateVirtioNet)?,
-                ))
-            } else if let Some(fds) = &net_cfg.fds {
-                let net = virtio_devices::Net::from_tap_fds(
+            let virtio_net = if let Some(virtio_net) = virtio_net {
+                virtio_net
+            } else {
+                Self::create_virtio_net(
                     id.clone(),
-                    fds,
-                    Some(net_cfg.mac),
-                    net_cfg.mtu,
-                    self.force_iommu | net_cfg.iommu,
-                    net_cfg.queue_size,
+                    net_cfg,
+                    self.force_iommu,
                     self.seccomp_action.clone(),
-                    net_cfg.rate_limiter_config,
                     self.exit_evt
                         .try_clone()
                         .map_err(DeviceManagerError::EventFd)?,
-                    state,
-                    net_cfg.offload_tso,
-                    net_cfg.offload_ufo,
-                    net_cfg.offload_csum,
-                )
-                .map_err(DeviceManagerError::CreateVirtioNet)?;
+                    state_from_id(self.snapshot.as_ref(), id.as_str())
+                        .map_err(DeviceManagerError::RestoreGetState)?,
+                    self.net_namespace()?.as_deref(),
+                )?
+            };
 
+            if let (None, Some(fds)) = (&net_cfg.tap, &net_cfg.fds) {
                 // SAFETY: 'fds' are valid because TAP devices are created successfully
                 unsafe {
                     self.config.lock().unwrap().add_preserved_fds(fds.clone());
                 }
+            }
 
-                Arc::new(Mutex::new(net))
-            } else {
-                Arc::new(Mutex::new(
-                    virtio_devices::Net::new(
-                        id.clone(),
-                        None,
-                        Some(net_cfg.ip),
-                        Some(net_cfg.mask),
-                        Some(net_cfg.mac),
-                        &mut net_cfg.host_mac,
-                        net_cfg.mtu,
-                        self.force_iommu | net_cfg.iommu,
-                        net_cfg.num_queues,
-                        net_cfg.queue_size,
-                        self.seccomp_action.clone(),
-                        net_cfg.rate_limiter_config,
-                        self.exit_evt
-                            .try_clone()
-                            .map_err(DeviceManagerError::EventFd)?,
-                        state,
-                        net_cfg.offload_tso,
-                        net_cfg.offload_ufo,
-                        net_cfg.offload_csum,
-                    )
-                    .map_err(DeviceManagerError::CreateVirtioNet)?,
-                ))
-            };
+            self.tap_names.insert(id.clone(), virtio_net.tap_name());
+            let virtio_net = Arc::new(Mutex::new(virtio_net));
+
+            if let Some(document) = self.imds_document()? {
+                virtio_net.lock().unwrap().set_imds(document);
+            }
+
+            if let Some(io_thread) = self.io_thread(net_cfg.io_thread) {
+                virtio_net.lock().unwrap().set_io_thread(io_thread);
+            }
+
+            if let Some(coalescing) = net_cfg.coalescing {
+                virtio_net
+                    .lock()
+                    .unwrap()
+                    .set_coalescing(coalescing.usecs, coalescing.max_used);
+            }
+
+            if let Some(rate_limit_group) = net_cfg
+                .rate_limit_group
+                .as_ref()
+                .and_then(|g| self.rate_limit_groups.get(g))
+            {
+                virtio_net
+                    .lock()
+                    .unwrap()
+                    .set_rate_limit_group(rate_limit_group.clone(), net_cfg.weight);
+            }
+
+            if net_cfg.io_uring {
+                #[cfg(feature = "io_uring")]
+                if net_util::tap_io_uring_is_supported() {
+                    info!("Using io_uring for TAP packet processing");
+                    let mut net = virtio_net.lock().unwrap();
+                    net.enable_io_uring();
+                    if let Some(numa_node) = self.host_numa_node(net_cfg.pci_segment) {
+                        net.set_numa_node(numa_node);
+                    }
+                } else {
+                    warn!("io_uring is not supported for TAP devices, falling back to epoll");
+                }
+                #[cfg(not(feature = "io_uring"))]
+                warn!("io_uring support is not compiled in, falling back to epoll");
+            }
+
+            if net_cfg.vhost {
+                virtio_net.lock().unwrap().enable_vhost_net();
+            }
 
             (
                 Arc::clone(&virtio_net) as Arc<Mutex<dyn virtio_devices::VirtioDevice>>,
                 virtio_net as Arc<Mutex<dyn Migratable>>,
             )
         };
+        self.set_thread_landlock(&mut *virtio_device.lock().unwrap(), net_cfg)?;
 
         // Fill the device tree with a new node. In case of restore, we
         // know there is nothing to do, so we can simply override the
@@ -2913,34 +3847,149 @@ impl DeviceManager {
             .unwrap()
             .insert(id.clone(), device_node!(id, migratable_device));
 
+        let rom = net_cfg
+            .romfile
+            .as_deref()
+            .map(PciRom::from_file)
+            .transpose()
+            .map_err(DeviceManagerError::LoadOptionRom)?;
+
         Ok(MetaVirtioDevice {
             virtio_device,
             iommu: net_cfg.iommu,
             id,
             pci_segment: net_cfg.pci_segment,
             dma_handler: None,
+            rom,
+            msix_vectors: net_cfg.msix_vectors,
         })
     }
 
-    /// Add virto-net and vhost-user-net devices
-    fn make_virtio_net_devices(&mut self) -> DeviceManagerResult<Vec<MetaVirtioDevice>> {
-        let mut devices = Vec::new();
-        let mut net_devices = self.config.lock().unwrap().net.clone();
-        if let Some(net_list_cfg) = &mut net_devices {
-            for net_cfg in net_list_cfg.iter_mut() {
-                devices.push(self.make_virtio_net_device(net_cfg)?);
-            }
-        }
-        self.config.lock().unwrap().net = net_devices;
+    /// Load the document served by the metadata service, if enabled.
+    fn imds_document(&self) -> DeviceManagerResult<Option<Arc<serde_json::Value>>> {
+        let Some(imds_config) = self.config.lock().unwrap().imds.clone() else {
+            return Ok(None);
+        };
 
-        Ok(devices)
+        let document =
+            std::fs::read(&imds_config.document).map_err(DeviceManagerError::ReadImdsDocument)?;
+        let document =
+            serde_json::from_slice(&document).map_err(DeviceManagerError::ParseImdsDocument)?;
+
+        Ok(Some(Arc::new(document)))
     }
 
-    fn make_virtio_rng_devices(&mut self) -> DeviceManagerResult<Vec<MetaVirtioDevice>> {
-        let mut devices = Vec::new();
+    // Setting up a TAP interface involves several ioctls and netlink
+    // requests, which add up when a VM has many of them. The interfaces
+    // being independent, they're all set up concurrently, once the devices
+    // have been given their identifiers.
+    fn create_virtio_nets(
+        &mut self,
+        net_list_cfg: &mut [NetConfig],
+    ) -> DeviceManagerResult<Vec<Option<virtio_devices::Net>>> {
+        let mut args = Vec::new();
+        for net_cfg in net_list_cfg.iter_mut() {
+            if net_cfg.vhost_user {
+                args.push(None);
+                continue;
+            }
 
-        // Add virtio-rng if required
-        let rng_config = self.config.lock().unwrap().rng.clone();
+            let id = if let Some(id) = &net_cfg.id {
+                id.clone()
+            } else {
+                let id = self.next_device_name(NET_DEVICE_NAME_PREFIX)?;
+                net_cfg.id = Some(id.clone());
+                id
+            };
+            let exit_evt = self
+                .exit_evt
+                .try_clone()
+                .map_err(DeviceManagerError::EventFd)?;
+            let state = state_from_id(self.snapshot.as_ref(), id.as_str())
+                .map_err(DeviceManagerError::RestoreGetState)?;
+            args.push(Some((id, exit_evt, state)));
+        }
+
+        let netns = self.net_namespace()?;
+        let force_iommu = self.force_iommu;
+        let seccomp_action = &self.seccomp_action;
+        thread::scope(|s| {
+            let handles: Vec<_> = net_list_cfg
+                .iter_mut()
+                .zip(args)
+                .map(|(net_cfg, args)| {
+                    args.map(|(id, exit_evt, state)| {
+                        let seccomp_action = seccomp_action.clone();
+                        let netns = netns.as_deref();
+                        s.spawn(move || {
+                            Self::create_virtio_net(
+                                id,
+                                net_cfg,
+                                force_iommu,
+                                seccomp_action,
+                                exit_evt,
+                                state,
+                                netns,
+                            )
+                        })
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle
+                        .map(|h| h.join().unwrap_or_else(|e| panic::resume_unwind(e)))
+                        .transpose()
+                })
+                .collect()
+        })
+    }
+
+    /// Network namespace the TAP interfaces are created in, joined or
+    /// created along with its link to the bridge the first time it's needed.
+    fn net_namespace(&mut self) -> DeviceManagerResult<Option<Arc<NetNamespace>>> {
+        if self.netns.is_none() {
+            let Some(netns_cfg) = self.config.lock().unwrap().netns.clone() else {
+                return Ok(None);
+            };
+
+            info!("Setting up network namespace: {:?}", netns_cfg);
+
+            let mut netns = NetNamespace::open_or_create(&netns_cfg.name)
+                .map_err(DeviceManagerError::NetNamespace)?;
+            if let Some(bridge) = netns_cfg.bridge.as_deref().filter(|_| netns.created()) {
+                netns
+                    .plumb(bridge, &netns_cfg.veth_name())
+                    .map_err(DeviceManagerError::NetNamespace)?;
+            }
+            self.netns = Some(Arc::new(netns));
+        }
+
+        Ok(self.netns.clone())
+    }
+
+    /// Add virto-net and vhost-user-net devices
+    fn make_virtio_net_devices(&mut self) -> DeviceManagerResult<Vec<MetaVirtioDevice>> {
+        let mut devices = Vec::new();
+        let mut net_devices = self.config.lock().unwrap().net.clone();
+        if let Some(net_list_cfg) = &mut net_devices {
+            let virtio_nets = self.create_virtio_nets(net_list_cfg)?;
+            for (net_cfg, virtio_net) in net_list_cfg.iter_mut().zip(virtio_nets) {
+                devices.push(self.make_virtio_net_device(net_cfg, virtio_net)?);
+            }
+        }
+        self.config.lock().unwrap().net = net_devices;
+
+        Ok(devices)
+    }
+
+    fn make_virtio_rng_devices(&mut self) -> DeviceManagerResult<Vec<MetaVirtioDevice>> {
+        let mut devices = Vec::new();
+
+        // Add virtio-rng if required
+        let rng_config = self.config.lock().unwrap().rng.clone();
         if let Some(rng_path) = rng_config.src.to_str() {
             info!("Creating virtio-rng device: {:?}", rng_config);
             let id = String::from(RNG_DEVICE_NAME);
@@ -2959,6 +4008,7 @@ impl DeviceManager {
                 )
                 .map_err(DeviceManagerError::CreateVirtioRng)?,
             ));
+            self.set_thread_landlock(&mut *virtio_rng_device.lock().unwrap(), &rng_config)?;
             devices.push(MetaVirtioDevice {
                 virtio_device: Arc::clone(&virtio_rng_device)
                     as Arc<Mutex<dyn virtio_devices::VirtioDevice>>,
@@ -2966,6 +4016,8 @@ impl DeviceManager {
                 id: id.clone(),
                 pci_segment: 0,
                 dma_handler: None,
+                rom: None,
+                msix_vectors: None,
             });
 
             // Fill the device tree with a new node. In case of restore, we
@@ -3015,6 +4067,7 @@ impl DeviceManager {
                 )
                 .map_err(DeviceManagerError::CreateVirtioFs)?,
             ));
+            self.set_thread_landlock(&mut *virtio_fs_device.lock().unwrap(), fs_cfg)?;
 
             // Update the device tree with the migratable device.
             node.migratable = Some(Arc::clone(&virtio_fs_device) as Arc<Mutex<dyn Migratable>>);
@@ -3027,6 +4080,8 @@ impl DeviceManager {
                 id,
                 pci_segment: fs_cfg.pci_segment,
                 dma_handler: None,
+                rom: None,
+                msix_vectors: fs_cfg.msix_vectors,
             })
         } else {
             Err(DeviceManagerError::NoVirtioFsSock)
@@ -3047,26 +4102,19 @@ impl DeviceManager {
         Ok(devices)
     }
 
-    fn make_virtio_pmem_device(
+    // Open the backing file of a persistent memory device and map it into
+    // the guest address space, leaving out the last `reserved` bytes of the
+    // file. The guest range is reused from the device tree when restoring.
+    fn map_pmem_file(
         &mut self,
-        pmem_cfg: &mut PmemConfig,
-    ) -> DeviceManagerResult<MetaVirtioDevice> {
-        let id = if let Some(id) = &pmem_cfg.id {
-            id.clone()
-        } else {
-            let id = self.next_device_name(PMEM_DEVICE_NAME_PREFIX)?;
-            pmem_cfg.id = Some(id.clone());
-            id
-        };
-
-        info!("Creating virtio-pmem device: {:?}", pmem_cfg);
-
-        let mut node = device_node!(id);
-
+        id: &str,
+        pmem_cfg: &PmemConfig,
+        reserved: u64,
+    ) -> DeviceManagerResult<PmemMapping> {
         // Look for the id in the device tree. If it can be found, that means
         // the device is being restored, otherwise it's created from scratch.
-        let region_range = if let Some(node) = self.device_tree.lock().unwrap().get(&id) {
-            info!("Restoring virtio-pmem {} resources", id);
+        let region_range = if let Some(node) = self.device_tree.lock().unwrap().get(id) {
+            info!("Restoring pmem {} resources", id);
 
             let mut region_range: Option<(u64, u64)> = None;
             for resource in node.resources.iter() {
@@ -3109,20 +4157,37 @@ impl DeviceManager {
             .open(&pmem_cfg.file)
             .map_err(DeviceManagerError::PmemFileOpen)?;
 
+        // A device DAX can only be mapped shared, and doesn't support the
+        // read and write calls accessing the label area of an NVDIMM.
+        let dax = DaxDevice::probe(&pmem_cfg.file).map_err(DeviceManagerError::PmemDax)?;
+        if dax.is_some() && (pmem_cfg.discard_writes || pmem_cfg.nvdimm) {
+            return Err(DeviceManagerError::PmemDaxUnsupported);
+        }
+
         let size = if let Some(size) = pmem_cfg.size {
             if set_len {
                 file.set_len(size)
                     .map_err(DeviceManagerError::PmemFileSetLen)?;
             }
             size
+        } else if let Some(dax) = dax.as_ref() {
+            dax.size
         } else {
             file.seek(SeekFrom::End(0))
                 .map_err(DeviceManagerError::PmemFileSetLen)?
         };
+        if let Some(dax) = dax.as_ref() {
+            dax.check_mapping(0, size)
+                .map_err(DeviceManagerError::PmemDax)?;
+        }
 
         if size % 0x20_0000 != 0 {
             return Err(DeviceManagerError::PmemSizeNotAligned);
         }
+        let size = size
+            .checked_sub(reserved)
+            .filter(|size| *size > 0)
+            .ok_or(DeviceManagerError::PmemSizeNotAligned)?;
 
         let (region_base, region_size) = if let Some((base, size)) = region_range {
             // The memory needs to be 2MiB aligned in order to support
@@ -3158,7 +4223,9 @@ impl DeviceManager {
             region_size as usize,
             PROT_READ | PROT_WRITE,
             MAP_NORESERVE
-                | if pmem_cfg.discard_writes {
+                | if dax.is_some() {
+                    libc::MAP_SHARED_VALIDATE | libc::MAP_SYNC
+                } else if pmem_cfg.discard_writes {
                     MAP_PRIVATE
                 } else {
                     MAP_SHARED
@@ -3174,6 +4241,43 @@ impl DeviceManager {
             .create_userspace_mapping(region_base, region_size, host_addr, false, false, false)
             .map_err(DeviceManagerError::MemoryManager)?;
 
+        Ok(PmemMapping {
+            file,
+            mmap_region,
+            base: region_base,
+            size: region_size,
+            host_addr,
+            mem_slot,
+            synchronous: dax.is_some(),
+        })
+    }
+
+    fn make_virtio_pmem_device(
+        &mut self,
+        pmem_cfg: &mut PmemConfig,
+    ) -> DeviceManagerResult<MetaVirtioDevice> {
+        let id = if let Some(id) = &pmem_cfg.id {
+            id.clone()
+        } else {
+            let id = self.next_device_name(PMEM_DEVICE_NAME_PREFIX)?;
+            pmem_cfg.id = Some(id.clone());
+            id
+        };
+
+        info!("Creating virtio-pmem device: {:?}", pmem_cfg);
+
+        let mut node = device_node!(id);
+
+        let PmemMapping {
+            file,
+            mmap_region,
+            base: region_base,
+            size: region_size,
+            host_addr,
+            mem_slot,
+            synchronous,
+        } = self.map_pmem_file(&id, pmem_cfg, 0)?;
+
         let mapping = virtio_devices::UserspaceMapping {
             host_addr,
             mem_slot,
@@ -3182,23 +4286,24 @@ impl DeviceManager {
             mergeable: false,
         };
 
-        let virtio_pmem_device = Arc::new(Mutex::new(
-            virtio_devices::Pmem::new(
-                id.clone(),
-                file,
-                GuestAddress(region_base),
-                mapping,
-                mmap_region,
-                self.force_iommu | pmem_cfg.iommu,
-                self.seccomp_action.clone(),
-                self.exit_evt
-                    .try_clone()
-                    .map_err(DeviceManagerError::EventFd)?,
-                state_from_id(self.snapshot.as_ref(), id.as_str())
-                    .map_err(DeviceManagerError::RestoreGetState)?,
-            )
-            .map_err(DeviceManagerError::CreateVirtioPmem)?,
-        ));
+        let mut virtio_pmem_device = virtio_devices::Pmem::new(
+            id.clone(),
+            file,
+            GuestAddress(region_base),
+            mapping,
+            mmap_region,
+            self.force_iommu | pmem_cfg.iommu,
+            self.seccomp_action.clone(),
+            self.exit_evt
+                .try_clone()
+                .map_err(DeviceManagerError::EventFd)?,
+            state_from_id(self.snapshot.as_ref(), id.as_str())
+                .map_err(DeviceManagerError::RestoreGetState)?,
+        )
+        .map_err(DeviceManagerError::CreateVirtioPmem)?;
+        virtio_pmem_device.set_synchronous(synchronous);
+        self.set_thread_landlock(&mut virtio_pmem_device, pmem_cfg)?;
+        let virtio_pmem_device = Arc::new(Mutex::new(virtio_pmem_device));
 
         // Update the device tree with correct resource information and with
         // the migratable device.
@@ -3216,6 +4321,8 @@ impl DeviceManager {
             id,
             pci_segment: pmem_cfg.pci_segment,
             dma_handler: None,
+            rom: None,
+            msix_vectors: None,
         })
     }
 
@@ -3224,7 +4331,7 @@ impl DeviceManager {
         // Add virtio-pmem if required
         let mut pmem_devices = self.config.lock().unwrap().pmem.clone();
         if let Some(pmem_list_cfg) = &mut pmem_devices {
-            for pmem_cfg in pmem_list_cfg.iter_mut() {
+            for pmem_cfg in pmem_list_cfg.iter_mut().filter(|p| !p.nvdimm) {
                 devices.push(self.make_virtio_pmem_device(pmem_cfg)?);
             }
         }
@@ -3233,6 +4340,85 @@ impl DeviceManager {
         Ok(devices)
     }
 
+    #[cfg(not(target_arch = "riscv64"))]
+    fn add_nvdimm_controller(
+        &mut self,
+    ) -> DeviceManagerResult<Option<Arc<Mutex<devices::nvdimm::NvdimmController>>>> {
+        let mut nvdimms = Vec::new();
+        let mut pmem_devices = self.config.lock().unwrap().pmem.clone();
+        for pmem_cfg in pmem_devices.iter_mut().flatten().filter(|p| p.nvdimm) {
+            let id = if let Some(id) = &pmem_cfg.id {
+                id.clone()
+            } else {
+                let id = self.next_device_name(PMEM_DEVICE_NAME_PREFIX)?;
+                pmem_cfg.id = Some(id.clone());
+                id
+            };
+
+            info!("Creating NVDIMM device: {:?}", pmem_cfg);
+
+            let PmemMapping {
+                file,
+                mmap_region,
+                base,
+                size,
+                ..
+            } = self.map_pmem_file(&id, pmem_cfg, devices::nvdimm::NVDIMM_LABEL_AREA_SIZE)?;
+            self.nvdimm_mappings.push(mmap_region);
+
+            // The label area follows the persistent memory in the backing file
+            let label_area = devices::nvdimm::NvdimmLabelArea::new(
+                file,
+                size,
+                devices::nvdimm::NVDIMM_LABEL_AREA_SIZE as u32,
+                !pmem_cfg.discard_writes,
+            );
+            nvdimms.push(devices::nvdimm::Nvdimm::new(
+                nvdimms.len() as u32 + 1,
+                GuestAddress(base),
+                size,
+                label_area,
+            ));
+
+            let mut node = device_node!(id);
+            node.resources
+                .push(Resource::MmioAddressRange { base, size });
+            self.device_tree.lock().unwrap().insert(id, node);
+        }
+        self.config.lock().unwrap().pmem = pmem_devices;
+
+        if nvdimms.is_empty() {
+            return Ok(None);
+        }
+
+        let address = self
+            .address_manager
+            .allocator
+            .lock()
+            .unwrap()
+            .allocate_platform_mmio_addresses(
+                None,
+                devices::nvdimm::NVDIMM_DSM_MMIO_SIZE as u64,
+                None,
+            )
+            .ok_or(DeviceManagerError::AllocateMmioAddress)?;
+        let nvdimm_controller = Arc::new(Mutex::new(devices::nvdimm::NvdimmController::new(
+            address, nvdimms,
+        )));
+        self.address_manager
+            .mmio_bus
+            .insert(
+                nvdimm_controller.clone(),
+                address.0,
+                devices::nvdimm::NVDIMM_DSM_MMIO_SIZE as u64,
+            )
+            .map_err(DeviceManagerError::BusError)?;
+        self.bus_devices
+            .push(Arc::clone(&nvdimm_controller) as Arc<dyn BusDeviceSync>);
+
+        Ok(Some(nvdimm_controller))
+    }
+
     fn make_virtio_vsock_device(
         &mut self,
         vsock_cfg: &mut VsockConfig,
@@ -3271,6 +4457,7 @@ impl DeviceManager {
             )
             .map_err(DeviceManagerError::CreateVirtioVsock)?,
         ));
+        self.set_thread_landlock(&mut *vsock_device.lock().unwrap(), vsock_cfg)?;
 
         // Fill the device tree with a new node. In case of restore, we
         // know there is nothing to do, so we can simply override the
@@ -3287,6 +4474,8 @@ impl DeviceManager {
             id,
             pci_segment: vsock_cfg.pci_segment,
             dma_handler: None,
+            rom: None,
+            msix_vectors: None,
         })
     }
 
@@ -3346,6 +4535,8 @@ impl DeviceManager {
                     id: memory_zone_id.clone(),
                     pci_segment: 0,
                     dma_handler: None,
+                    rom: None,
+                    msix_vectors: None,
                 });
 
                 // Fill the device tree with a new node. In case of restore, we
@@ -3374,11 +4565,20 @@ impl DeviceManager {
         let (pci_segment_id, pci_device_bdf, resources) =
             self.pci_resources(&id, pci_segment_id)?;
 
+        let reclaim_rate = self
+            .config
+            .lock()
+            .unwrap()
+            .pvmemcontrol
+            .as_ref()
+            .and_then(|pvmemcontrol| pvmemcontrol.reclaim_rate);
+
         info!("Creating pvmemcontrol device: id = {}", id);
         let (pvmemcontrol_pci_device, pvmemcontrol_bus_device) =
             devices::pvmemcontrol::PvmemcontrolDevice::make_device(
                 id.clone(),
                 self.memory_manager.lock().unwrap().guest_memory(),
+                reclaim_rate,
             );
 
         let pvmemcontrol_pci_device = Arc::new(Mutex::new(pvmemcontrol_pci_device));
@@ -3435,6 +4635,8 @@ impl DeviceManager {
                 id: id.clone(),
                 pci_segment: 0,
                 dma_handler: None,
+                rom: None,
+                msix_vectors: None,
             });
 
             self.device_tree
@@ -3459,7 +4661,8 @@ impl DeviceManager {
         let virtio_watchdog_device = Arc::new(Mutex::new(
             virtio_devices::Watchdog::new(
                 id.clone(),
-                self.reset_evt.try_clone().unwrap(),
+                self.crash_evt.try_clone().unwrap(),
+                self.cpu_manager.lock().unwrap().exit_reason().clone(),
                 self.seccomp_action.clone(),
                 self.exit_evt
                     .try_clone()
@@ -3476,6 +4679,8 @@ impl DeviceManager {
             id: id.clone(),
             pci_segment: 0,
             dma_handler: None,
+            rom: None,
+            msix_vectors: None,
         });
 
         self.device_tree
@@ -3486,6 +4691,53 @@ impl DeviceManager {
         Ok(devices)
     }
 
+    fn make_virtio_scmi_devices(&mut self) -> DeviceManagerResult<Vec<MetaVirtioDevice>> {
+        let mut devices = Vec::new();
+
+        let scmi_config = if let Some(scmi_config) = &self.config.lock().unwrap().scmi {
+            scmi_config.clone()
+        } else {
+            return Ok(devices);
+        };
+
+        let id = String::from(SCMI_DEVICE_NAME);
+        info!("Creating virtio-scmi device: {:?}", scmi_config);
+
+        let virtio_scmi_device = Arc::new(Mutex::new(
+            virtio_devices::Scmi::new(
+                id.clone(),
+                scmi_config.sensors.as_deref().unwrap_or_default(),
+                scmi_config.power_domains,
+                scmi_config.clocks.as_deref().unwrap_or_default(),
+                self.force_iommu | scmi_config.iommu,
+                self.seccomp_action.clone(),
+                self.exit_evt
+                    .try_clone()
+                    .map_err(DeviceManagerError::EventFd)?,
+                state_from_id(self.snapshot.as_ref(), id.as_str())
+                    .map_err(DeviceManagerError::RestoreGetState)?,
+            )
+            .map_err(DeviceManagerError::CreateVirtioScmi)?,
+        ));
+        devices.push(MetaVirtioDevice {
+            virtio_device: Arc::clone(&virtio_scmi_device)
+                as Arc<Mutex<dyn virtio_devices::VirtioDevice>>,
+            iommu: scmi_config.iommu,
+            id: id.clone(),
+            pci_segment: 0,
+            dma_handler: None,
+            rom: None,
+            msix_vectors: None,
+        });
+
+        self.device_tree
+            .lock()
+            .unwrap()
+            .insert(id.clone(), device_node!(id, virtio_scmi_device));
+
+        Ok(devices)
+    }
+
     fn make_vdpa_device(
         &mut self,
         vdpa_cfg: &mut VdpaConfig,
@@ -3534,6 +4786,8 @@ impl DeviceManager {
             id,
             pci_segment: vdpa_cfg.pci_segment,
             dma_handler: Some(vdpa_mapping),
+            rom: None,
+            msix_vectors: vdpa_cfg.msix_vectors,
         })
     }
 
@@ -3574,10 +4828,7 @@ impl DeviceManager {
         Err(DeviceManagerError::NoAvailableDeviceName)
     }
 
-    fn add_passthrough_device(
-        &mut self,
-        device_cfg: &mut DeviceConfig,
-    ) -> DeviceManagerResult<(PciBdf, String)> {
+    fn create_passthrough_device(&mut self) -> DeviceManagerResult<()> {
         // If the passthrough device has not been created yet, it is created
         // here and stored in the DeviceManager structure for future needs.
         if self.passthrough_device.is_none() {
@@ -3589,7 +4840,15 @@ impl DeviceManager {
             );
         }
 
-        self.add_vfio_device(device_cfg)
+        Ok(())
+    }
+
+    fn add_passthrough_device(
+        &mut self,
+        device_cfg: &mut DeviceConfig,
+    ) -> DeviceManagerResult<(PciBdf, String)> {
+        self.create_passthrough_device()?;
+        self.add_vfio_device(device_cfg, None)
     }
 
     fn create_vfio_container(&self) -> DeviceManagerResult<Arc<VfioContainer>> {
@@ -3607,9 +4866,112 @@ impl DeviceManager {
         ))
     }
 
+    // Here we create a new VFIO container for two reasons. Either this is
+    // the first VFIO device, meaning we need a new VFIO container, which
+    // will be shared with other VFIO devices. Or the new VFIO device is
+    // attached to a vIOMMU, meaning we must create a dedicated VFIO
+    // container. In the vIOMMU use case, we can't let all devices under
+    // the same VFIO container since we couldn't map/unmap memory for each
+    // device. That's simply because the map/unmap operations happen at the
+    // VFIO container level.
+    //
+    // Returns the container along with whether the guest memory must be DMA
+    // mapped through it.
+    fn vfio_container(
+        &mut self,
+        device_cfg: &DeviceConfig,
+    ) -> DeviceManagerResult<(Arc<VfioContainer>, bool)> {
+        if device_cfg.iommu {
+            Ok((self.create_vfio_container()?, false))
+        } else if let Some(vfio_container) = &self.vfio_container {
+            Ok((Arc::clone(vfio_container), false))
+        } else {
+            let vfio_container = self.create_vfio_container()?;
+            self.vfio_container = Some(Arc::clone(&vfio_container));
+
+            Ok((vfio_container, true))
+        }
+    }
+
+    fn open_vfio_device(
+        device_cfg: &DeviceConfig,
+        vfio_container: Arc<VfioContainer>,
+        needs_dma_mapping: bool,
+    ) -> DeviceManagerResult<OpenedVfioDevice> {
+        if device_cfg.is_mdev() {
+            // DMA for mediated devices is translated by the parent driver,
+            // the device can't be attached to a virtio-iommu domain.
+            if device_cfg.iommu {
+                return Err(DeviceManagerError::MdevIommuUnsupported(
+                    device_cfg.path.clone(),
+                ));
+            }
+
+            // Mediated devices are reset through their parent driver, there
+            // is no PCI function or bus to restrict the reset to.
+            if matches!(
+                device_cfg.reset_method,
+                VfioResetMethod::Flr | VfioResetMethod::Bus
+            ) {
+                return Err(DeviceManagerError::MdevResetMethodUnsupported(
+                    device_cfg.path.clone(),
+                ));
+            }
+
+            info!("Creating mediated device: {:?}", device_cfg.path);
+        }
+
+        let device = VfioDevice::new(&device_cfg.path, Arc::clone(&vfio_container))
+            .map_err(DeviceManagerError::VfioCreate)?;
+
+        Ok(OpenedVfioDevice {
+            device,
+            container: vfio_container,
+            needs_dma_mapping,
+        })
+    }
+
+    // Opening a VFIO device makes the host kernel reset it, which can take
+    // a significant amount of time, e.g. up to a second for a function level
+    // reset. The devices are all opened concurrently so that these delays
+    // don't add up.
+    fn open_vfio_devices(
+        &mut self,
+        device_list_cfg: &[DeviceConfig],
+    ) -> DeviceManagerResult<Vec<OpenedVfioDevice>> {
+        if device_list_cfg.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        self.create_passthrough_device()?;
+
+        let mut vfio_containers = Vec::new();
+        for device_cfg in device_list_cfg.iter() {
+            vfio_containers.push(self.vfio_container(device_cfg)?);
+        }
+
+        thread::scope(|s| {
+            let handles: Vec<_> = device_list_cfg
+                .iter()
+                .zip(vfio_containers)
+                .map(|(device_cfg, (vfio_container, needs_dma_mapping))| {
+                    s.spawn(move || {
+                        Self::open_vfio_device(device_cfg, vfio_container, needs_dma_mapping)
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .map(|h| h.join().unwrap_or_else(|e| panic::resume_unwind(e)))
+                .collect()
+        })
+    }
+
     fn add_vfio_device(
         &mut self,
         device_cfg: &mut DeviceConfig,
+        vfio_device: Option<OpenedVfioDevice>,
     ) -> DeviceManagerResult<(PciBdf, String)> {
         let vfio_name = if let Some(id) = &device_cfg.id {
             id.clone()
@@ -3622,19 +4984,18 @@ impl DeviceManager {
         let (pci_segment_id, pci_device_bdf, resources) =
             self.pci_resources(&vfio_name, device_cfg.pci_segment)?;
 
-        let mut needs_dma_mapping = false;
-
-        // Here we create a new VFIO container for two reasons. Either this is
-        // the first VFIO device, meaning we need a new VFIO container, which
-        // will be shared with other VFIO devices. Or the new VFIO device is
-        // attached to a vIOMMU, meaning we must create a dedicated VFIO
-        // container. In the vIOMMU use case, we can't let all devices under
-        // the same VFIO container since we couldn't map/unmap memory for each
-        // device. That's simply because the map/unmap operations happen at the
-        // VFIO container level.
-        let vfio_container = if device_cfg.iommu {
-            let vfio_container = self.create_vfio_container()?;
+        let OpenedVfioDevice {
+            device: vfio_device,
+            container: vfio_container,
+            needs_dma_mapping,
+        } = if let Some(vfio_device) = vfio_device {
+            vfio_device
+        } else {
+            let (vfio_container, needs_dma_mapping) = self.vfio_container(device_cfg)?;
+            Self::open_vfio_device(device_cfg, vfio_container, needs_dma_mapping)?
+        };
 
+        if device_cfg.iommu {
             let vfio_mapping = Arc::new(VfioDmaMapping::new(
                 Arc::clone(&vfio_container),
                 Arc::new(self.memory_manager.lock().unwrap().guest_memory()),
@@ -3649,20 +5010,7 @@ impl DeviceManager {
             } else {
                 return Err(DeviceManagerError::MissingVirtualIommu);
             }
-
-            vfio_container
-        } else if let Some(vfio_container) = &self.vfio_container {
-            Arc::clone(vfio_container)
-        } else {
-            let vfio_container = self.create_vfio_container()?;
-            needs_dma_mapping = true;
-            self.vfio_container = Some(Arc::clone(&vfio_container));
-
-            vfio_container
-        };
-
-        let vfio_device = VfioDevice::new(&device_cfg.path, Arc::clone(&vfio_container))
-            .map_err(DeviceManagerError::VfioCreate)?;
+        }
 
         if needs_dma_mapping {
             // Register DMA mapping in IOMMU.
@@ -3715,6 +5063,15 @@ impl DeviceManager {
 
         let memory_manager = self.memory_manager.clone();
 
+        let p2p_dma = device_cfg.p2p_dma;
+
+        let rom = device_cfg
+            .romfile
+            .as_deref()
+            .map(PciRom::from_file)
+            .transpose()
+            .map_err(DeviceManagerError::LoadOptionRom)?;
+
         let vfio_pci_device = VfioPciDevice::new(
             vfio_name.clone(),
             &self.address_manager.vm,
@@ -3728,6 +5085,10 @@ impl DeviceManager {
             vm_migration::snapshot_from_id(self.snapshot.as_ref(), vfio_name.as_str()),
             device_cfg.x_nv_gpudirect_clique,
             device_cfg.path.clone(),
+            p2p_dma,
+            device_cfg.reset_method,
+            device_cfg.reset_on_detach,
+            rom,
         )
         .map_err(DeviceManagerError::VfioPciCreate)?;
 
@@ -3747,8 +5108,40 @@ impl DeviceManager {
             .map_mmio_regions()
             .map_err(DeviceManagerError::VfioMapRegion)?;
 
-        for mmio_region in vfio_pci_device.lock().unwrap().mmio_regions() {
-            self.mmio_regions.lock().unwrap().push(mmio_region);
+        // The guest driver of an Intel IGD identifies the platform through
+        // the host bridge, which it expects on the first PCI segment.
+        let host_bridge_registers = vfio_pci_device.lock().unwrap().igd_host_bridge_registers();
+        if let Some(registers) = host_bridge_registers {
+            self.pci_segments[0]
+                .pci_bus
+                .lock()
+                .unwrap()
+                .mirror_host_bridge(&registers);
+        }
+
+        #[cfg(target_arch = "x86_64")]
+        if device_cfg.legacy_vga {
+            let vga_device = vfio_pci_device
+                .lock()
+                .unwrap()
+                .enable_legacy_vga()
+                .ok_or_else(|| {
+                    DeviceManagerError::VfioLegacyVgaUnavailable(device_cfg.path.clone())
+                })?;
+            for (base, len) in VGA_IO_PORT_RANGES {
+                self.address_manager
+                    .io_bus
+                    .insert(vga_device.clone(), base, len)
+                    .map_err(DeviceManagerError::BusError)?;
+            }
+        }
+
+        // Only the BARs of devices taking part in peer-to-peer DMA can be
+        // mapped through the virtual IOMMU.
+        if p2p_dma {
+            for mmio_region in vfio_pci_device.lock().unwrap().mmio_regions() {
+                self.mmio_regions.lock().unwrap().push(mmio_region);
+            }
         }
 
         let mut node = device_node!(vfio_name, vfio_pci_device);
@@ -3830,8 +5223,21 @@ impl DeviceManager {
         let mut devices = self.config.lock().unwrap().devices.clone();
 
         if let Some(device_list_cfg) = &mut devices {
-            for device_cfg in device_list_cfg.iter_mut() {
-                let (device_id, _) = self.add_passthrough_device(device_cfg)?;
+            let mut sriov_vfs = device_list_cfg
+                .iter_mut()
+                .map(Self::create_sriov_vf)
+                .collect::<DeviceManagerResult<Vec<_>>>()?;
+            let vfio_devices = self.open_vfio_devices(device_list_cfg)?;
+            for ((device_cfg, vfio_device), sriov_vf) in device_list_cfg
+                .iter_mut()
+                .zip(vfio_devices)
+                .zip(sriov_vfs.iter_mut())
+            {
+                let (device_id, device_name) =
+                    self.add_vfio_device(device_cfg, Some(vfio_device))?;
+                if let Some(sriov_vf) = sriov_vf.take() {
+                    self.sriov_vfs.insert(device_name, sriov_vf);
+                }
                 if device_cfg.iommu && self.iommu_device.is_some() {
                     iommu_attached_device_ids.push(device_id);
                 }
@@ -3844,6 +5250,21 @@ impl DeviceManager {
         Ok(iommu_attached_device_ids)
     }
 
+    // Create the SR-IOV virtual function of a device, if any, pointing the
+    // device to it.
+    fn create_sriov_vf(device_cfg: &mut DeviceConfig) -> DeviceManagerResult<Option<SriovVf>> {
+        let Some(sriov) = &device_cfg.sriov else {
+            return Ok(None);
+        };
+
+        info!("Creating SR-IOV virtual function: {:?}", sriov);
+        let sriov_vf = SriovVf::create(&sriov.pf, sriov.vf, sriov.mac, sriov.vlan, sriov.trust)
+            .map_err(DeviceManagerError::SriovVf)?;
+        device_cfg.path = sriov_vf.path();
+
+        Ok(Some(sriov_vf))
+    }
+
     fn add_vfio_user_device(
         &mut self,
         device_cfg: &mut UserDeviceConfig,
@@ -3969,6 +5390,8 @@ impl DeviceManager {
         virtio_device_id: String,
         pci_segment_id: u16,
         dma_handler: Option<Arc<dyn ExternalDmaMapping>>,
+        rom: Option<PciRom>,
+        msix_vectors: Option<u16>,
     ) -> DeviceManagerResult<PciBdf> {
         let id = format!("{VIRTIO_PCI_DEVICE_NAME_PREFIX}-{virtio_device_id}");
 
@@ -3988,8 +5411,10 @@ impl DeviceManager {
 
         // Allows support for one MSI-X vector per queue. It also adds 1
         // as we need to take into account the dedicated vector to notify
-        // about a virtio config change.
-        let msix_num = (virtio_device.lock().unwrap().queue_max_sizes().len() + 1) as u16;
+        // about a virtio config change. With fewer vectors than that, the
+        // guest shares them between the queues.
+        let msix_num = msix_vectors
+            .unwrap_or((virtio_device.lock().unwrap().queue_max_sizes().len() + 1) as u16);
 
         // Create the AccessPlatform trait from the implementation IommuMapping.
         // This will provide address translation for any virtio device sitting
@@ -4074,6 +5499,7 @@ impl DeviceManager {
                 dma_handler,
                 self.pending_activations.clone(),
                 vm_migration::snapshot_from_id(self.snapshot.as_ref(), id.as_str()),
+                rom,
             )
             .map_err(DeviceManagerError::VirtioDevice)?,
         ));
@@ -4105,6 +5531,83 @@ impl DeviceManager {
         Ok(pci_device_bdf)
     }
 
+    fn add_nvme_devices(&mut self) -> DeviceManagerResult<()> {
+        let mut block_devices = self.config.lock().unwrap().disks.clone();
+        if let Some(disk_list_cfg) = &mut block_devices {
+            let disk_images = self.open_disk_images(disk_list_cfg, DiskInterface::Nvme)?;
+            for (disk_cfg, disk_image) in disk_list_cfg.iter_mut().zip(disk_images) {
+                if let Some(disk_image) = disk_image {
+                    let nvme_device = self.add_nvme_device(disk_cfg, disk_image)?;
+                    self.nvme_devices.push(nvme_device);
+                }
+            }
+        }
+        self.config.lock().unwrap().disks = block_devices;
+
+        Ok(())
+    }
+
+    fn add_nvme_device(
+        &mut self,
+        disk_cfg: &mut DiskConfig,
+        disk_image: Box<dyn DiskFile>,
+    ) -> DeviceManagerResult<Arc<Mutex<devices::nvme::NvmeController>>> {
+        let id = if let Some(id) = &disk_cfg.id {
+            id.clone()
+        } else {
+            let id = self.next_device_name(DISK_DEVICE_NAME_PREFIX)?;
+            disk_cfg.id = Some(id.clone());
+            id
+        };
+
+        info!("Creating NVMe controller: {:?}", disk_cfg);
+
+        let (pci_segment_id, pci_device_bdf, resources) =
+            self.pci_resources(&id, disk_cfg.pci_segment)?;
+
+        let memory = self.memory_manager.lock().unwrap().guest_memory();
+        let nvme_device = devices::nvme::NvmeController::new(
+            id.clone(),
+            disk_image,
+            disk_cfg
+                .path
+                .as_ref()
+                .ok_or(DeviceManagerError::NoDiskPath)?
+                .clone(),
+            disk_cfg.readonly,
+            disk_cfg.num_queues,
+            disk_cfg.queue_size.max_size(MAXIMUM_BLOCK_QUEUE_SIZE),
+            disk_cfg.serial.clone(),
+            memory,
+            &self.msi_interrupt_manager,
+            pci_device_bdf.into(),
+            snapshot_from_id(self.snapshot.as_ref(), id.as_str()),
+        )
+        .map_err(DeviceManagerError::CreateNvme)?;
+
+        self.preserve_key_fd(disk_cfg);
+
+        let nvme_device = Arc::new(Mutex::new(nvme_device));
+
+        let new_resources = self.add_pci_device(
+            nvme_device.clone(),
+            nvme_device.clone(),
+            pci_segment_id,
+            pci_device_bdf,
+            resources,
+        )?;
+
+        let mut node = device_node!(id, nvme_device);
+
+        node.resources = new_resources;
+        node.pci_bdf = Some(pci_device_bdf);
+        node.pci_device_handle = None;
+
+        self.device_tree.lock().unwrap().insert(id, node);
+
+        Ok(nvme_device)
+    }
+
     fn add_pvpanic_device(
         &mut self,
     ) -> DeviceManagerResult<Option<Arc<Mutex<devices::PvPanicDevice>>>> {
@@ -4118,8 +5621,26 @@ impl DeviceManager {
 
         let snapshot = snapshot_from_id(self.snapshot.as_ref(), id.as_str());
 
-        let pvpanic_device = devices::PvPanicDevice::new(id.clone(), snapshot)
-            .map_err(DeviceManagerError::PvPanicCreate)?;
+        // Guest panics are only acted upon when a restart policy or crash
+        // dumps are set
+        let crash_evt = {
+            let config = self.config.lock().unwrap();
+            config.restart_policy.is_some() || config.crash_dump.is_some()
+        };
+        let crash_evt = if crash_evt {
+            Some(
+                self.crash_evt
+                    .try_clone()
+                    .map_err(DeviceManagerError::EventFd)?,
+            )
+        } else {
+            None
+        };
+
+        let exit_reason = self.cpu_manager.lock().unwrap().exit_reason().clone();
+        let pvpanic_device =
+            devices::PvPanicDevice::new(id.clone(), crash_evt, exit_reason, snapshot)
+                .map_err(DeviceManagerError::PvPanicCreate)?;
 
         let pvpanic_device = Arc::new(Mutex::new(pvpanic_device));
 
@@ -4197,6 +5718,11 @@ impl DeviceManager {
             .map(|ic| ic.clone() as Arc<Mutex<dyn InterruptController>>)
     }
 
+    #[cfg(not(target_arch = "riscv64"))]
+    pub fn tpm_device(&self) -> Option<Arc<Mutex<devices::tpm::Tpm>>> {
+        self.tpm_device.clone()
+    }
+
     pub(crate) fn pci_segments(&self) -> &Vec<PciSegment> {
         &self.pci_segments
     }
@@ -4277,123 +5803,676 @@ impl DeviceManager {
             .unwrap()
             .lock()
             .unwrap()
-            .notify(_notification_type)
-            .map_err(DeviceManagerError::HotPlugNotification);
+            .notify(_notification_type)
+            .map_err(DeviceManagerError::HotPlugNotification);
+    }
+
+    pub fn add_device(
+        &mut self,
+        device_cfg: &mut DeviceConfig,
+    ) -> DeviceManagerResult<PciDeviceInfo> {
+        self.validate_identifier(&device_cfg.id)?;
+
+        if device_cfg.iommu && !self.is_iommu_segment(device_cfg.pci_segment) {
+            return Err(DeviceManagerError::InvalidIommuHotplug);
+        }
+
+        // Given back to the host if the device can't be added.
+        let sriov_vf = Self::create_sriov_vf(device_cfg)?;
+
+        // Devices sharing the IOMMU group with the new device must be
+        // plugged along with it. Find the ones bound to vfio-pci which are
+        // not assigned to the VM yet, after making sure the group can be
+        // attached at all.
+        let mut group_devices = Vec::new();
+        if !device_cfg.is_mdev() {
+            let group =
+                IommuGroup::from_device(&device_cfg.path).map_err(DeviceManagerError::VfioGroup)?;
+            group
+                .check_viable()
+                .map_err(DeviceManagerError::VfioGroup)?;
+
+            let assigned: Vec<PathBuf> = self
+                .config
+                .lock()
+                .unwrap()
+                .devices
+                .iter()
+                .flatten()
+                .map(|d| d.path.clone())
+                .collect();
+            for path in group.vfio_devices() {
+                if same_device(&path, &device_cfg.path)
+                    || assigned.iter().any(|a| same_device(a, &path))
+                {
+                    continue;
+                }
+                group_devices.push(DeviceConfig {
+                    path,
+                    id: None,
+                    x_nv_gpudirect_clique: None,
+                    romfile: None,
+                    sriov: None,
+                    ..device_cfg.clone()
+                });
+            }
+        }
+
+        let (bdf, device_name) = self.add_passthrough_device(device_cfg)?;
+
+        // Plug the whole group or nothing, removing the devices already
+        // created if one of them fails.
+        let mut plugged = vec![bdf];
+        for group_device in group_devices.iter_mut() {
+            match self.add_passthrough_device(group_device) {
+                Ok((bdf, id)) => {
+                    info!("Adding device {} from IOMMU group of {}", id, device_name);
+                    plugged.push(bdf);
+                }
+                Err(e) => {
+                    for bdf in plugged {
+                        if let Err(e) = self.eject_device(bdf.segment(), bdf.device()) {
+                            error!("Failed rolling back VFIO device {}: {:?}", bdf, e);
+                        }
+                    }
+                    return Err(e);
+                }
+            }
+        }
+
+        // Update the PCIU bitmap
+        for bdf in plugged {
+            self.pci_segments[bdf.segment() as usize].pci_devices_up |= 1 << bdf.device();
+        }
+
+        // Record the group devices so they are recreated on reboot.
+        for group_device in group_devices {
+            add_to_config(&mut self.config.lock().unwrap().devices, group_device);
+        }
+
+        if let Some(sriov_vf) = sriov_vf {
+            self.sriov_vfs.insert(device_name.clone(), sriov_vf);
+        }
+
+        Ok(PciDeviceInfo {
+            id: device_name,
+            bdf,
+        })
+    }
+
+    // Identifiers of the other VFIO devices assigned to the VM which share
+    // the IOMMU group of the given device.
+    fn vfio_group_peers(&self, device_cfg: &DeviceConfig) -> Vec<String> {
+        if device_cfg.is_mdev() {
+            return Vec::new();
+        }
+
+        let group = match IommuGroup::from_device(&device_cfg.path) {
+            Ok(group) => group,
+            Err(e) => {
+                warn!("Could not read IOMMU group: {}", e);
+                return Vec::new();
+            }
+        };
+
+        self.config
+            .lock()
+            .unwrap()
+            .devices
+            .iter()
+            .flatten()
+            .filter(|d| d.id != device_cfg.id)
+            .filter(|d| group.devices.iter().any(|g| same_device(g, &d.path)))
+            .filter_map(|d| d.id.clone())
+            .collect()
+    }
+
+    pub fn add_user_device(
+        &mut self,
+        device_cfg: &mut UserDeviceConfig,
+    ) -> DeviceManagerResult<PciDeviceInfo> {
+        self.validate_identifier(&device_cfg.id)?;
+
+        let (bdf, device_name) = self.add_vfio_user_device(device_cfg)?;
+
+        // Update the PCIU bitmap
+        self.pci_segments[device_cfg.pci_segment as usize].pci_devices_up |= 1 << bdf.device();
+
+        Ok(PciDeviceInfo {
+            id: device_name,
+            bdf,
+        })
+    }
+
+    pub fn remove_device(&mut self, id: String) -> DeviceManagerResult<()> {
+        // The node can be directly a PCI node in case the 'id' refers to a
+        // VFIO device or a virtio-pci one.
+        // In case the 'id' refers to a virtio device, we must find the PCI
+        // node by looking at the parent.
+        let device_tree = self.device_tree.lock().unwrap();
+        let node = device_tree
+            .get(&id)
+            .ok_or(DeviceManagerError::UnknownDeviceId(id.clone()))?;
+
+        // Release advisory locks by dropping all references.
+        // Linux automatically releases all locks of that file if the last open FD is closed.
+        {
+            let maybe_block_device_index = self
+                .block_devices
+                .iter()
+                .enumerate()
+                .find(|(_, dev)| {
+                    let dev = dev.lock().unwrap();
+                    dev.id() == id
+                })
+                .map(|(i, _)| i);
+            if let Some(index) = maybe_block_device_index {
+                let _ = self.block_devices.swap_remove(index);
+            }
+        }
+        self.tap_names.remove(&id);
+
+        let pci_device_node = if node.pci_bdf.is_some() && node.pci_device_handle.is_some() {
+            node
+        } else {
+            let parent = node
+                .parent
+                .as_ref()
+                .ok_or(DeviceManagerError::MissingNode)?;
+            device_tree
+                .get(parent)
+                .ok_or(DeviceManagerError::MissingNode)?
+        };
+
+        let pci_device_bdf: PciBdf = pci_device_node
+            .pci_bdf
+            .ok_or(DeviceManagerError::MissingDeviceNodePciBdf)?;
+        let pci_segment_id = pci_device_bdf.segment();
+
+        let pci_device_handle = pci_device_node
+            .pci_device_handle
+            .as_ref()
+            .ok_or(DeviceManagerError::MissingPciDevice)?;
+        #[allow(irrefutable_let_patterns)]
+        if let PciDeviceHandle::Virtio(virtio_pci_device) = pci_device_handle {
+            let device_type = VirtioDeviceType::from(
+                virtio_pci_device
+                    .lock()
+                    .unwrap()
+                    .virtio_device()
+                    .lock()
+                    .unwrap()
+                    .device_type(),
+            );
+            match device_type {
+                VirtioDeviceType::Net
+                | VirtioDeviceType::Block
+                | VirtioDeviceType::Pmem
+                | VirtioDeviceType::Fs
+                | VirtioDeviceType::Vsock => {}
+                _ => return Err(DeviceManagerError::RemovalNotAllowed(device_type)),
+            }
+        }
+
+        // Update the PCID bitmap
+        self.pci_segments[pci_segment_id as usize].pci_devices_down |= 1 << pci_device_bdf.device();
+
+        // A VFIO device can only be removed along with the devices sharing
+        // its IOMMU group.
+        if matches!(pci_device_handle, PciDeviceHandle::Vfio(_)) {
+            let device_cfg = self
+                .config
+                .lock()
+                .unwrap()
+                .devices
+                .iter()
+                .flatten()
+                .find(|d| d.id.as_deref() == Some(id.as_str()))
+                .cloned();
+            if let Some(device_cfg) = device_cfg {
+                for peer in self.vfio_group_peers(&device_cfg) {
+                    let peer_bdf = device_tree
+                        .get(&peer)
+                        .and_then(|n| n.pci_bdf)
+                        .ok_or(DeviceManagerError::MissingDeviceNodePciBdf)?;
+                    info!("Removing device {} from IOMMU group of {}", peer, id);
+                    self.pci_segments[peer_bdf.segment() as usize].pci_devices_down |=
+                        1 << peer_bdf.device();
+                    self.config.lock().unwrap().remove_device(&peer);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reset a VFIO device through a simulated hot-unplug and hotplug, so
+    /// that the guest driver releases the device before it gets reset, and
+    /// probes it again afterwards. The device is reset and plugged back once
+    /// the guest has ejected it.
+    pub fn reset_device(&mut self, id: String) -> DeviceManagerResult<()> {
+        let pci_device_bdf = {
+            let device_tree = self.device_tree.lock().unwrap();
+            let node = device_tree
+                .get(&id)
+                .ok_or(DeviceManagerError::UnknownDeviceId(id.clone()))?;
+            if !matches!(node.pci_device_handle, Some(PciDeviceHandle::Vfio(_))) {
+                return Err(DeviceManagerError::ResetNotAllowed(id));
+            }
+            node.pci_bdf
+                .ok_or(DeviceManagerError::MissingDeviceNodePciBdf)?
+        };
+
+        if self
+            .pending_device_resets
+            .iter()
+            .any(|r| r.contains(pci_device_bdf))
+        {
+            return Err(DeviceManagerError::ResetPending(id));
+        }
+
+        let device_cfg = self
+            .config
+            .lock()
+            .unwrap()
+            .devices
+            .iter()
+            .flatten()
+            .find(|d| d.id.as_deref() == Some(id.as_str()))
+            .cloned()
+            .ok_or(DeviceManagerError::UnknownDeviceId(id.clone()))?;
+
+        // The devices sharing the IOMMU group are unplugged and plugged back
+        // along with the device, as resetting it may reset them as well, and
+        // a bus reset needs all the devices of the group to be released.
+        let mut devices = vec![(pci_device_bdf, device_cfg.clone())];
+        for peer in self.vfio_group_peers(&device_cfg) {
+            let peer_bdf = self
+                .device_tree
+                .lock()
+                .unwrap()
+                .get(&peer)
+                .and_then(|n| n.pci_bdf)
+                .ok_or(DeviceManagerError::MissingDeviceNodePciBdf)?;
+            // The peer may already be unplugged along with another device
+            // of the group being removed.
+            if self.pci_segments[peer_bdf.segment() as usize].pci_devices_down
+                & (1 << peer_bdf.device())
+                != 0
+            {
+                return Err(DeviceManagerError::ResetGroupBusy(id));
+            }
+            let peer_cfg = self
+                .config
+                .lock()
+                .unwrap()
+                .devices
+                .iter()
+                .flatten()
+                .find(|d| d.id.as_deref() == Some(peer.as_str()))
+                .cloned()
+                .ok_or(DeviceManagerError::UnknownDeviceId(peer.clone()))?;
+            info!("Resetting device {} from IOMMU group of {}", peer, id);
+            devices.push((peer_bdf, peer_cfg));
+        }
+
+        info!("Resetting device {}", id);
+        for (bdf, _) in devices.iter() {
+            // Update the PCID bitmap
+            self.pci_segments[bdf.segment() as usize].pci_devices_down |= 1 << bdf.device();
+        }
+        self.pending_device_resets
+            .push(PendingDeviceReset::new(devices));
+
+        Ok(())
+    }
+
+    fn create_rate_limit_group(
+        rate_limit_group_cfg: &RateLimiterGroupConfig,
+        exit_evt: &EventFd,
+    ) -> DeviceManagerResult<Arc<RateLimiterGroup>> {
+        let rate_limit_cfg = rate_limit_group_cfg.rate_limiter_config;
+        let bw = rate_limit_cfg.bandwidth.unwrap_or_default();
+        let ops = rate_limit_cfg.ops.unwrap_or_default();
+        let mut rate_limit_group = RateLimiterGroup::new(
+            &rate_limit_group_cfg.id,
+            bw.size,
+            bw.one_time_burst.unwrap_or(0),
+            bw.refill_time,
+            ops.size,
+            ops.one_time_burst.unwrap_or(0),
+            ops.refill_time,
+        )
+        .map_err(DeviceManagerError::RateLimiterGroupCreate)?;
+
+        let exit_evt = exit_evt.try_clone().map_err(DeviceManagerError::EventFd)?;
+
+        rate_limit_group.start_thread(exit_evt).unwrap();
+
+        Ok(Arc::new(rate_limit_group))
+    }
+
+    /// Create a new rate limiter group, which the disks and net devices added
+    /// afterwards can join.
+    pub fn add_rate_limit_group(
+        &mut self,
+        rate_limit_group_cfg: &RateLimiterGroupConfig,
+    ) -> DeviceManagerResult<()> {
+        if self
+            .rate_limit_groups
+            .contains_key(&rate_limit_group_cfg.id)
+        {
+            return Err(DeviceManagerError::DuplicateRateLimitGroup(
+                rate_limit_group_cfg.id.clone(),
+            ));
+        }
+
+        info!("Creating rate limiter group {}", rate_limit_group_cfg.id);
+        let rate_limit_group = Self::create_rate_limit_group(rate_limit_group_cfg, &self.exit_evt)?;
+        self.rate_limit_groups
+            .insert(rate_limit_group_cfg.id.clone(), rate_limit_group);
+
+        Ok(())
+    }
+
+    /// Move a virtio-block device to the `rate_limit_group`, or out of any
+    /// group, replacing the rate limiter it was created with.
+    pub fn set_rate_limit_group(
+        &mut self,
+        id: &str,
+        rate_limit_group: Option<&str>,
+    ) -> DeviceManagerResult<()> {
+        if !self.device_tree.lock().unwrap().contains_key(id) {
+            return Err(DeviceManagerError::UnknownDeviceId(id.to_string()));
+        }
+
+        let rate_limiter = rate_limit_group
+            .map(|group| {
+                self.rate_limit_groups
+                    .get(group)
+                    .cloned()
+                    .ok_or_else(|| DeviceManagerError::UnknownRateLimitGroup(group.to_string()))
+            })
+            .transpose()?;
+
+        let block_device = self
+            .block_devices
+            .iter()
+            .find(|dev| dev.lock().unwrap().id() == id)
+            .ok_or_else(|| DeviceManagerError::RateLimitGroupNotAllowed(id.to_string()))?;
+
+        info!(
+            "Moving device {} to rate limiter group {:?}",
+            id, rate_limit_group
+        );
+        block_device
+            .lock()
+            .unwrap()
+            .set_rate_limiter(rate_limiter)
+            .map_err(DeviceManagerError::SetRateLimitGroup)
+    }
+
+    /// Grow a disk to `size` bytes, notifying the guest of its new capacity.
+    pub fn resize_disk(&mut self, id: &str, size: u64) -> DeviceManagerResult<()> {
+        if !self.device_tree.lock().unwrap().contains_key(id) {
+            return Err(DeviceManagerError::UnknownDeviceId(id.to_string()));
+        }
+
+        let block_device = self
+            .block_devices
+            .iter()
+            .find(|dev| dev.lock().unwrap().id() == id)
+            .ok_or_else(|| DeviceManagerError::ResizeDiskNotAllowed(id.to_string()))?;
+
+        block_device
+            .lock()
+            .unwrap()
+            .resize(size)
+            .map_err(DeviceManagerError::ResizeDisk)
     }
 
-    pub fn add_device(
+    /// Create a QCOW2 overlay at `path` backed by the current image of the
+    /// disk `id`, returning the disk config using the overlay and the
+    /// opened overlay.
+    pub fn create_disk_overlay(
         &mut self,
-        device_cfg: &mut DeviceConfig,
-    ) -> DeviceManagerResult<PciDeviceInfo> {
-        self.validate_identifier(&device_cfg.id)?;
-
-        if device_cfg.iommu && !self.is_iommu_segment(device_cfg.pci_segment) {
-            return Err(DeviceManagerError::InvalidIommuHotplug);
+        id: &str,
+        path: &Path,
+    ) -> DeviceManagerResult<(DiskConfig, Box<dyn DiskFile>)> {
+        let disk_cfg = self
+            .config
+            .lock()
+            .unwrap()
+            .disks
+            .iter()
+            .flatten()
+            .find(|disk_cfg| disk_cfg.id.as_deref() == Some(id))
+            .cloned()
+            .ok_or_else(|| DeviceManagerError::UnknownDeviceId(id.to_string()))?;
+        if disk_cfg.vhost_user || disk_cfg.readonly {
+            return Err(DeviceManagerError::DiskOverlayNotAllowed(id.to_string()));
         }
+        let backing_path = disk_cfg
+            .path
+            .as_ref()
+            .ok_or(DeviceManagerError::NoDiskPath)?;
+        let backing_file_name = backing_path
+            .to_str()
+            .ok_or_else(|| DeviceManagerError::DiskOverlayBackingPath(backing_path.clone()))?;
 
-        let (bdf, device_name) = self.add_passthrough_device(device_cfg)?;
-
-        // Update the PCIU bitmap
-        self.pci_segments[device_cfg.pci_segment as usize].pci_devices_up |= 1 << bdf.device();
-
-        Ok(PciDeviceInfo {
-            id: device_name,
-            bdf,
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .map_err(|e| DeviceManagerError::CreateDiskOverlayFile(path.to_path_buf(), e))?;
+        let overlay_cfg = DiskConfig {
+            path: Some(path.to_path_buf()),
+            format: Some(ImageType::Qcow2),
+            ..disk_cfg
+        };
+        let io_uring_supported = self.io_uring_is_supported();
+        let aio_supported = self.aio_is_supported();
+        qcow::QcowFile::new_from_backing(
+            qcow::RawFile::new(file, false),
+            QCOW_OVERLAY_VERSION,
+            backing_file_name,
+            qcow::MAX_NESTING_DEPTH,
+        )
+        .map_err(DeviceManagerError::CreateDiskOverlay)
+        .and_then(|_| Self::open_disk_image(&overlay_cfg, None, io_uring_supported, aio_supported))
+        .map(|disk_image| (overlay_cfg, disk_image))
+        .inspect_err(|_| {
+            if let Err(e) = std::fs::remove_file(path) {
+                warn!("Failed to remove overlay {}: {}", path.display(), e);
+            }
         })
     }
 
-    pub fn add_user_device(
+    /// Switch the disk described by `disk_cfg` to `disk_image`, recording
+    /// its new config so that it is kept on reboot.
+    pub fn switch_disk_image(
         &mut self,
-        device_cfg: &mut UserDeviceConfig,
-    ) -> DeviceManagerResult<PciDeviceInfo> {
-        self.validate_identifier(&device_cfg.id)?;
+        disk_cfg: DiskConfig,
+        disk_image: Box<dyn DiskFile>,
+    ) -> DeviceManagerResult<DiskImageSwitchDone> {
+        let id = disk_cfg.id.clone().unwrap_or_default();
+        let block_device = self
+            .block_devices
+            .iter()
+            .find(|dev| dev.lock().unwrap().id() == id)
+            .ok_or_else(|| DeviceManagerError::DiskOverlayNotAllowed(id.clone()))?;
+
+        let done = block_device
+            .lock()
+            .unwrap()
+            .switch_disk_image(
+                disk_image,
+                disk_cfg
+                    .path
+                    .clone()
+                    .ok_or(DeviceManagerError::NoDiskPath)?,
+            )
+            .map_err(DeviceManagerError::SwitchDiskImage)?;
 
-        let (bdf, device_name) = self.add_vfio_user_device(device_cfg)?;
+        if let Some(disks) = self.config.lock().unwrap().disks.as_mut() {
+            if let Some(config) = disks.iter_mut().find(|d| d.id.as_deref() == Some(&id)) {
+                *config = disk_cfg;
+            }
+        }
 
-        // Update the PCIU bitmap
-        self.pci_segments[device_cfg.pci_segment as usize].pci_devices_up |= 1 << bdf.device();
+        Ok(done)
+    }
 
-        Ok(PciDeviceInfo {
-            id: device_name,
-            bdf,
-        })
+    /// Switch the disk described by `disk_cfg` back to the image this config
+    /// points to, undoing a switch to an overlay.
+    pub fn restore_disk_image(
+        &mut self,
+        disk_cfg: DiskConfig,
+    ) -> DeviceManagerResult<DiskImageSwitchDone> {
+        let disk_image = Self::open_disk_image(
+            &disk_cfg,
+            None,
+            self.io_uring_is_supported(),
+            self.aio_is_supported(),
+        )?;
+        self.switch_disk_image(disk_cfg, disk_image)
     }
 
-    pub fn remove_device(&mut self, id: String) -> DeviceManagerResult<()> {
-        // The node can be directly a PCI node in case the 'id' refers to a
-        // VFIO device or a virtio-pci one.
-        // In case the 'id' refers to a virtio device, we must find the PCI
-        // node by looking at the parent.
-        let device_tree = self.device_tree.lock().unwrap();
-        let node = device_tree
-            .get(&id)
-            .ok_or(DeviceManagerError::UnknownDeviceId(id.clone()))?;
+    /// Sizes of the queues of the disks, as negotiated with the guest.
+    pub fn disk_queues(&self) -> Vec<DiskQueueInfo> {
+        self.block_devices
+            .iter()
+            .map(|block_device| {
+                let block_device = block_device.lock().unwrap();
+                DiskQueueInfo {
+                    id: block_device.id(),
+                    queue_sizes: block_device.negotiated_queue_sizes().to_vec(),
+                }
+            })
+            .collect()
+    }
 
-        // Release advisory locks by dropping all references.
-        // Linux automatically releases all locks of that file if the last open FD is closed.
-        {
-            let maybe_block_device_index = self
-                .block_devices
-                .iter()
-                .enumerate()
-                .find(|(_, dev)| {
-                    let dev = dev.lock().unwrap();
-                    dev.id() == id
-                })
-                .map(|(i, _)| i);
-            if let Some(index) = maybe_block_device_index {
-                let _ = self.block_devices.swap_remove(index);
+    /// Check the paths of the host block devices backing the disks.
+    pub fn check_disk_paths(&self) {
+        for block_device in self.block_devices.iter() {
+            let mut block_device = block_device.lock().unwrap();
+            if let Err(e) = block_device.check_paths() {
+                warn!(
+                    "Error checking the paths of disk {}: {}",
+                    block_device.id(),
+                    e
+                );
             }
         }
+    }
 
-        let pci_device_node = if node.pci_bdf.is_some() && node.pci_device_handle.is_some() {
-            node
-        } else {
-            let parent = node
-                .parent
-                .as_ref()
-                .ok_or(DeviceManagerError::MissingNode)?;
-            device_tree
-                .get(parent)
-                .ok_or(DeviceManagerError::MissingNode)?
-        };
+    /// Applies the limits of the rate-limiter group schedules active at the
+    /// given minute of the day, restoring the limits of the groups outside
+    /// of any of their schedules.
+    pub fn update_rate_limit_schedules(&mut self, minute_of_day: u32) {
+        let config = self.config.lock().unwrap();
+        for group_cfg in config.rate_limit_groups.iter().flatten() {
+            let Some(schedules) = &group_cfg.schedules else {
+                continue;
+            };
+            let Some(rate_limit_group) = self.rate_limit_groups.get(&group_cfg.id) else {
+                continue;
+            };
 
-        let pci_device_bdf: PciBdf = pci_device_node
-            .pci_bdf
-            .ok_or(DeviceManagerError::MissingDeviceNodePciBdf)?;
-        let pci_segment_id = pci_device_bdf.segment();
+            let active = group_cfg.active_schedule(minute_of_day);
+            if self.active_rate_limit_schedules.get(&group_cfg.id).copied() == active {
+                continue;
+            }
 
-        let pci_device_handle = pci_device_node
-            .pci_device_handle
-            .as_ref()
-            .ok_or(DeviceManagerError::MissingPciDevice)?;
-        #[allow(irrefutable_let_patterns)]
-        if let PciDeviceHandle::Virtio(virtio_pci_device) = pci_device_handle {
-            let device_type = VirtioDeviceType::from(
-                virtio_pci_device
-                    .lock()
-                    .unwrap()
-                    .virtio_device()
-                    .lock()
-                    .unwrap()
-                    .device_type(),
+            let rate_limiter_config = match active {
+                Some(index) => {
+                    info!(
+                        "Applying schedule {}-{} to rate limiter group {}",
+                        schedules[index].start, schedules[index].end, group_cfg.id
+                    );
+                    self.active_rate_limit_schedules
+                        .insert(group_cfg.id.clone(), index);
+                    schedules[index].rate_limiter_config
+                }
+                None => {
+                    info!("Restoring limits of rate limiter group {}", group_cfg.id);
+                    self.active_rate_limit_schedules.remove(&group_cfg.id);
+                    group_cfg.rate_limiter_config
+                }
+            };
+
+            let bucket_update = |tb_config: Option<TokenBucketConfig>| {
+                tb_config
+                    .and_then(|tb| {
+                        TokenBucket::new(tb.size, tb.one_time_burst.unwrap_or(0), tb.refill_time)
+                    })
+                    .map_or(BucketUpdate::Disabled, BucketUpdate::Update)
+            };
+            rate_limit_group.update_buckets(
+                bucket_update(rate_limiter_config.bandwidth),
+                bucket_update(rate_limiter_config.ops),
             );
-            match device_type {
-                VirtioDeviceType::Net
-                | VirtioDeviceType::Block
-                | VirtioDeviceType::Pmem
-                | VirtioDeviceType::Fs
-                | VirtioDeviceType::Vsock => {}
-                _ => return Err(DeviceManagerError::RemovalNotAllowed(device_type)),
-            }
         }
+    }
 
-        // Update the PCID bitmap
-        self.pci_segments[pci_segment_id as usize].pci_devices_down |= 1 << pci_device_bdf.device();
+    // Record the guest ejected a device being reset. Once all the devices
+    // reset along with it are ejected as well, the VMM thread is kicked to
+    // plug them back, as recreating the devices can't happen from the vCPU
+    // thread the ejection comes from.
+    fn eject_device_reset(&mut self, pci_device_bdf: PciBdf) -> DeviceManagerResult<()> {
+        let Some(reset) = self
+            .pending_device_resets
+            .iter_mut()
+            .find(|r| r.contains(pci_device_bdf))
+        else {
+            return Ok(());
+        };
+
+        if reset.eject(pci_device_bdf) {
+            self.device_reset_evt
+                .write(1)
+                .map_err(DeviceManagerError::EventFd)?;
+        }
 
         Ok(())
     }
 
+    /// Plug back the devices being reset which the guest ejected. Creating
+    /// the devices again resets them according to their reset policy.
+    pub fn complete_device_resets(&mut self) -> DeviceManagerResult<()> {
+        let (ejected, pending) = std::mem::take(&mut self.pending_device_resets)
+            .into_iter()
+            .partition::<Vec<_>, _>(|r| r.ejected());
+        self.pending_device_resets = pending;
+        if ejected.is_empty() {
+            return Ok(());
+        }
+
+        for reset in ejected {
+            for (_, mut device_cfg) in reset.devices {
+                match self.add_passthrough_device(&mut device_cfg) {
+                    Ok((bdf, id)) => {
+                        info!("Device {} reset", id);
+                        // Update the PCIU bitmap
+                        self.pci_segments[bdf.segment() as usize].pci_devices_up |=
+                            1 << bdf.device();
+                    }
+                    Err(e) => {
+                        error!("Failed resetting device {:?}: {:?}", device_cfg.id, e);
+                        // The device is gone from the VM, it must not be
+                        // reported by the API nor created again on reboot.
+                        if let Some(id) = &device_cfg.id {
+                            self.config.lock().unwrap().remove_device(id);
+                        }
+                    }
+                }
+            }
+        }
+
+        self.notify_hotplug(AcpiNotificationFlags::PCI_DEVICES_CHANGED)
+    }
+
     pub fn eject_device(&mut self, pci_segment_id: u16, device_id: u8) -> DeviceManagerResult<()> {
         info!(
             "Ejecting device_id = {} on segment_id={}",
@@ -4457,6 +6536,13 @@ impl DeviceManager {
                         .retain(|x| x.start != mmio_region.start)
                 }
 
+                #[cfg(target_arch = "x86_64")]
+                if let Some(vga_device) = vfio_pci_device.lock().unwrap().legacy_vga_device() {
+                    self.io_bus()
+                        .remove_by_device(&(vga_device as Arc<dyn BusDeviceSync>))
+                        .map_err(DeviceManagerError::RemoveDeviceFromIoBus)?;
+                }
+
                 (
                     Arc::clone(&vfio_pci_device) as Arc<Mutex<dyn PciDevice>>,
                     Arc::clone(&vfio_pci_device) as Arc<dyn BusDeviceSync>,
@@ -4599,9 +6685,13 @@ impl DeviceManager {
         );
 
         // At this point, the device has been removed from all the list and
-        // buses where it was stored. At the end of this function, after
-        // any_device, bus_device and pci_device are released, the actual
-        // device will be dropped.
+        // buses where it was stored. Once bus_device and pci_device are
+        // released, the actual device is dropped, and its SR-IOV virtual
+        // function, if any, can be given back to the host.
+        drop(bus_device);
+        drop(pci_device);
+        self.sriov_vfs.remove(&id);
+
         Ok(())
     }
 
@@ -4626,6 +6716,8 @@ impl DeviceManager {
             handle.id.clone(),
             handle.pci_segment,
             handle.dma_handler,
+            handle.rom,
+            handle.msix_vectors,
         )?;
 
         // Update the PCIU bitmap
@@ -4650,14 +6742,33 @@ impl DeviceManager {
             .unwrap_or_default()
     }
 
+    pub fn add_pci_segment(&mut self) -> DeviceManagerResult<PciSegmentInfo> {
+        let segment = self
+            .pci_segments
+            .iter_mut()
+            .find(|s| !s.present)
+            .ok_or(DeviceManagerError::NoPciSegmentToAdd)?;
+        segment.hotplug(&self.address_manager)?;
+        let id = segment.id;
+
+        Ok(PciSegmentInfo {
+            id,
+            iommu: self.is_iommu_segment(id),
+        })
+    }
+
     pub fn add_disk(&mut self, disk_cfg: &mut DiskConfig) -> DeviceManagerResult<PciDeviceInfo> {
         self.validate_identifier(&disk_cfg.id)?;
 
+        if disk_cfg.interface == DiskInterface::Nvme {
+            return Err(DeviceManagerError::NvmeHotplugUnsupported);
+        }
+
         if disk_cfg.iommu && !self.is_iommu_segment(disk_cfg.pci_segment) {
             return Err(DeviceManagerError::InvalidIommuHotplug);
         }
 
-        let device = self.make_virtio_block_device(disk_cfg, true)?;
+        let device = self.make_virtio_block_device(disk_cfg, true, None)?;
         self.hotplug_virtio_pci_device(device)
     }
 
@@ -4671,6 +6782,10 @@ impl DeviceManager {
     pub fn add_pmem(&mut self, pmem_cfg: &mut PmemConfig) -> DeviceManagerResult<PciDeviceInfo> {
         self.validate_identifier(&pmem_cfg.id)?;
 
+        if pmem_cfg.nvdimm {
+            return Err(DeviceManagerError::NvdimmHotplugUnsupported);
+        }
+
         if pmem_cfg.iommu && !self.is_iommu_segment(pmem_cfg.pci_segment) {
             return Err(DeviceManagerError::InvalidIommuHotplug);
         }
@@ -4686,7 +6801,7 @@ impl DeviceManager {
             return Err(DeviceManagerError::InvalidIommuHotplug);
         }
 
-        let device = self.make_virtio_net_device(net_cfg)?;
+        let device = self.make_virtio_net_device(net_cfg, None)?;
         self.hotplug_virtio_pci_device(device)
     }
 
@@ -4720,6 +6835,21 @@ impl DeviceManager {
             if let Some(device_counters) = virtio_device.counters() {
                 counters.insert(handle.id.clone(), device_counters.clone());
             }
+            // The counters of the queues are reported on their own entries.
+            for (index, queue_counters) in virtio_device.queue_counters().into_iter().enumerate() {
+                counters.insert(format!("{}/q{index}", handle.id), queue_counters);
+            }
+        }
+
+        // The pvmemcontrol statistics are reported for each function code.
+        #[cfg(feature = "pvmemcontrol")]
+        if let Some((pvmemcontrol_bus_device, _)) = &self.pvmemcontrol_devices {
+            for (function, function_counters) in pvmemcontrol_bus_device.counters() {
+                counters.insert(
+                    format!("{PVMEMCONTROL_DEVICE_NAME}/{function}"),
+                    function_counters,
+                );
+            }
         }
 
         counters
@@ -4738,6 +6868,33 @@ impl DeviceManager {
         Err(DeviceManagerError::MissingVirtioBalloon)
     }
 
+    /// Sets the features the balloon offers to the guest, returning whether
+    /// free page reporting could be offered as requested.
+    pub fn set_balloon_features(
+        &mut self,
+        deflate_on_oom: bool,
+        free_page_reporting: bool,
+    ) -> DeviceManagerResult<bool> {
+        let Some(balloon) = &self.balloon else {
+            return Err(DeviceManagerError::MissingVirtioBalloon);
+        };
+
+        Ok(balloon
+            .lock()
+            .unwrap()
+            .set_features(deflate_on_oom, free_page_reporting))
+    }
+
+    pub fn balloon_info(&self) -> Option<BalloonInfo> {
+        self.balloon.as_ref().map(|balloon| {
+            let balloon = balloon.lock().unwrap();
+            BalloonInfo {
+                deflate_on_oom: balloon.deflate_on_oom(),
+                free_page_reporting: balloon.free_page_reporting(),
+            }
+        })
+    }
+
     pub fn balloon_size(&self) -> u64 {
         if let Some(balloon) = &self.balloon {
             return balloon.lock().unwrap().get_actual();
@@ -4750,8 +6907,31 @@ impl DeviceManager {
         self.device_tree.clone()
     }
 
+    pub fn tap_names(&self) -> &BTreeMap<String, String> {
+        &self.tap_names
+    }
+
+    #[cfg(not(target_arch = "riscv64"))]
+    pub fn nvdimm_controller(&self) -> Option<&Arc<Mutex<devices::nvdimm::NvdimmController>>> {
+        self.nvdimm_controller.as_ref()
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    pub fn boot_milestone(&self) -> Arc<AtomicBool> {
+        self.boot_milestone.clone()
+    }
+
     #[cfg(target_arch = "x86_64")]
     pub fn notify_power_button(&self) -> DeviceManagerResult<()> {
+        // Windows guests relying on VMBus can be asked to shut down through
+        // the shutdown integration service, which doesn't depend on the
+        // power button settings of the guest.
+        if let Some(vmbus) = &self.vmbus {
+            if vmbus.lock().unwrap().request_shutdown() {
+                return Ok(());
+            }
+        }
+
         self.ged_notification_device
             .as_ref()
             .unwrap()
@@ -4808,6 +6988,11 @@ impl DeviceManager {
     pub(crate) fn acpi_platform_addresses(&self) -> &AcpiPlatformAddresses {
         &self.acpi_platform_addresses
     }
+
+    #[cfg(target_arch = "x86_64")]
+    pub(crate) fn legacy_devices(&self) -> bool {
+        self.config.lock().unwrap().legacy_devices()
+    }
 }
 
 fn numa_node_id_from_memory_zone_id(numa_nodes: &NumaNodes, memory_zone_id: &str) -> Option<u32> {
@@ -4862,6 +7047,14 @@ impl Aml for DeviceManager {
         use arch::aarch64::DeviceInfoForFdt;
 
         let mut pci_scan_methods = Vec::new();
+        // Check for the segments added first, for their devices to be
+        // enumerated once the guest found them.
+        for segment in self.pci_segments.iter().filter(|s| s.hotpluggable) {
+            pci_scan_methods.push(aml::MethodCall::new(
+                format!("\\_SB_.PC{:02X}.SGNT", segment.id).as_str().into(),
+                vec![],
+            ));
+        }
         for i in 0..self.pci_segments.len() {
             pci_scan_methods.push(aml::MethodCall::new(
                 format!("\\_SB_.PC{i:02X}.PCNT").as_str().into(),
@@ -4908,6 +7101,8 @@ impl Aml for DeviceManager {
                         aml::FieldEntry::Named(*b"PCID", 32),
                         aml::FieldEntry::Named(*b"B0EJ", 32),
                         aml::FieldEntry::Named(*b"PSEG", 32),
+                        aml::FieldEntry::Named(*b"PSPR", 32),
+                        aml::FieldEntry::Named(*b"PSAD", 32),
                     ],
                 ),
                 &aml::Method::new(
@@ -5024,6 +7219,15 @@ impl Aml for DeviceManager {
             TpmDevice {}.to_aml_bytes(sink);
         }
 
+        if let Some(nvdimm_controller) = &self.nvdimm_controller {
+            nvdimm_controller.lock().unwrap().to_aml_bytes(sink);
+        }
+
+        #[cfg(target_arch = "x86_64")]
+        if let Some(vmbus) = &self.vmbus {
+            vmbus.lock().unwrap().to_aml_bytes(sink);
+        }
+
         self.ged_notification_device
             .as_ref()
             .unwrap()
@@ -5052,6 +7256,15 @@ impl Pausable for DeviceManager {
                 .pause()?;
         };
 
+        #[cfg(target_arch = "x86_64")]
+        if let Some(vmbus) = &self.vmbus {
+            vmbus.lock().unwrap().pause()?;
+        }
+
+        if let Some(rtc_clock) = &self.rtc_clock {
+            rtc_clock.lock().unwrap().pause();
+        }
+
         Ok(())
     }
 
@@ -5061,6 +7274,16 @@ impl Pausable for DeviceManager {
                 migratable.lock().unwrap().resume()?;
             }
         }
+
+        #[cfg(target_arch = "x86_64")]
+        if let Some(vmbus) = &self.vmbus {
+            vmbus.lock().unwrap().resume()?;
+        }
+
+        if let Some(rtc_clock) = &self.rtc_clock {
+            rtc_clock.lock().unwrap().resume();
+        }
+
         Ok(())
     }
 }
@@ -5094,6 +7317,35 @@ impl Migratable for DeviceManager {
                 migratable.lock().unwrap().start_dirty_log()?;
             }
         }
+
+        // Guest memory written by assigned devices through DMA
+        if let Some(vfio_container) = &self.vfio_container {
+            // The type1 backend reports every mapped page as dirty for the
+            // physical devices of the container, the migration would never
+            // converge. Devices attached to the virtual IOMMU have their own
+            // container.
+            if let Some(device) = self
+                .config
+                .lock()
+                .unwrap()
+                .devices
+                .iter()
+                .flatten()
+                .find(|d| !d.iommu && !d.is_mdev())
+            {
+                return Err(MigratableError::StartDirtyLog(anyhow!(
+                    "DMA dirty page tracking isn't supported for physical VFIO device {:?}",
+                    device.path
+                )));
+            }
+            vfio_start_dirty_log(vfio_container).map_err(|e| {
+                MigratableError::StartDirtyLog(anyhow!(
+                    "Error starting DMA dirty page tracking for VFIO devices: {:?}",
+                    e
+                ))
+            })?;
+        }
+
         Ok(())
     }
 
@@ -5103,6 +7355,16 @@ impl Migratable for DeviceManager {
                 migratable.lock().unwrap().stop_dirty_log()?;
             }
         }
+
+        if let Some(vfio_container) = &self.vfio_container {
+            vfio_stop_dirty_log(vfio_container).map_err(|e| {
+                MigratableError::StopDirtyLog(anyhow!(
+                    "Error stopping DMA dirty page tracking for VFIO devices: {:?}",
+                    e
+                ))
+            })?;
+        }
+
         Ok(())
     }
 
@@ -5113,6 +7375,28 @@ impl Migratable for DeviceManager {
                 tables.push(migratable.lock().unwrap().dirty_log()?);
             }
         }
+
+        // Each memory region is mapped as a whole, or by chunks in case of
+        // virtio-mem, meaning querying a region never splits a DMA mapping.
+        if let Some(vfio_container) = &self.vfio_container {
+            let guest_memory = self.memory_manager.lock().unwrap().guest_memory().memory();
+            for region in guest_memory.iter() {
+                tables.push(
+                    vfio_dirty_log(
+                        vfio_container,
+                        region.start_addr().raw_value(),
+                        region.len(),
+                    )
+                    .map_err(|e| {
+                        MigratableError::DirtyLog(anyhow!(
+                            "Error retrieving DMA dirty pages from VFIO devices: {:?}",
+                            e
+                        ))
+                    })?,
+                );
+            }
+        }
+
         Ok(MemoryRangeTable::new_from_tables(tables))
     }
 
@@ -5139,10 +7423,14 @@ const PCIU_FIELD_OFFSET: u64 = 0;
 const PCID_FIELD_OFFSET: u64 = 4;
 const B0EJ_FIELD_OFFSET: u64 = 8;
 const PSEG_FIELD_OFFSET: u64 = 12;
+const PSPR_FIELD_OFFSET: u64 = 16;
+const PSAD_FIELD_OFFSET: u64 = 20;
 const PCIU_FIELD_SIZE: usize = 4;
 const PCID_FIELD_SIZE: usize = 4;
 const B0EJ_FIELD_SIZE: usize = 4;
 const PSEG_FIELD_SIZE: usize = 4;
+const PSPR_FIELD_SIZE: usize = 4;
+const PSAD_FIELD_SIZE: usize = 4;
 
 impl BusDevice for DeviceManager {
     fn read(&mut self, base: u64, offset: u64, data: &mut [u8]) {
@@ -5177,6 +7465,20 @@ impl BusDevice for DeviceManager {
                 assert_eq!(data.len(), PSEG_FIELD_SIZE);
                 data.copy_from_slice(&(self.selected_segment as u32).to_le_bytes());
             }
+            PSPR_FIELD_OFFSET => {
+                assert_eq!(data.len(), PSPR_FIELD_SIZE);
+                data.copy_from_slice(
+                    &(self.pci_segments[self.selected_segment].present as u32).to_le_bytes(),
+                );
+            }
+            PSAD_FIELD_OFFSET => {
+                assert_eq!(data.len(), PSAD_FIELD_SIZE);
+                data.copy_from_slice(
+                    &(self.pci_segments[self.selected_segment].added as u32).to_le_bytes(),
+                );
+                // Clear the segment added flag
+                self.pci_segments[self.selected_segment].added = false;
+            }
             _ => error!(
                 "Accessing unknown location at base 0x{:x}, offset 0x{:x}",
                 base, offset
@@ -5201,6 +7503,13 @@ impl BusDevice for DeviceManager {
                     let slot_id = slot_bitmap.trailing_zeros();
                     if let Err(e) = self.eject_device(self.selected_segment as u16, slot_id as u8) {
                         error!("Failed ejecting device {}: {:?}", slot_id, e);
+                    } else if let Err(e) = self.eject_device_reset(PciBdf::new(
+                        self.selected_segment as u16,
+                        0,
+                        slot_id as u8,
+                        0,
+                    )) {
+                        error!("Failed resetting device {}: {:?}", slot_id, e);
                     }
                     slot_bitmap &= !(1 << slot_id);
                 }
@@ -5256,8 +7565,38 @@ impl Drop for DeviceManager {
 
 #[cfg(test)]
 mod tests {
+    use std::os::unix::fs::FileExt;
+
     use super::*;
 
+    #[test]
+    fn test_pending_device_reset() {
+        let device_cfg = |path: &str| DeviceConfig::parse(&format!("path={path}")).unwrap();
+        let bdf0 = PciBdf::new(0, 0, 3, 0);
+        let bdf1 = PciBdf::new(0, 0, 4, 0);
+        let other = PciBdf::new(1, 0, 3, 0);
+
+        let mut reset = PendingDeviceReset::new(vec![(bdf0, device_cfg("/dev0"))]);
+        assert!(reset.contains(bdf0));
+        assert!(!reset.contains(other));
+        assert!(!reset.ejected());
+        assert!(reset.eject(bdf0));
+        assert!(reset.ejected());
+
+        // Devices sharing the IOMMU group are plugged back once all of
+        // them are ejected.
+        let mut reset = PendingDeviceReset::new(vec![
+            (bdf0, device_cfg("/dev0")),
+            (bdf1, device_cfg("/dev1")),
+        ]);
+        assert!(reset.contains(bdf1));
+        assert!(!reset.eject(bdf1));
+        assert!(!reset.eject(bdf1));
+        assert!(!reset.ejected());
+        assert!(reset.eject(bdf0));
+        assert!(reset.ejected());
+    }
+
     #[test]
     fn test_create_mmio_allocators() {
         let res = create_mmio_allocators(0x100000, 0x400000, 1, vec![1], 4 << 10);
@@ -5309,4 +7648,52 @@ mod tests {
             vm_memory::GuestAddress(0x3fffff)
         );
     }
+
+    #[test]
+    fn test_open_disk_image() {
+        let image = vmm_sys_util::tempfile::TempFile::new().unwrap();
+        image.as_file().set_len(0x10_0000).unwrap();
+        let path = image.as_path().to_str().unwrap();
+
+        let disk_cfg = DiskConfig::parse(&format!("path={path}")).unwrap();
+        let mut disk = DeviceManager::open_disk_image(&disk_cfg, None, false, false).unwrap();
+        assert_eq!(disk.size().unwrap(), 0x10_0000);
+
+        let disk_cfg = DiskConfig::parse(&format!("path={path}.missing")).unwrap();
+        assert!(matches!(
+            DeviceManager::open_disk_image(&disk_cfg, None, false, false),
+            Err(DeviceManagerError::Disk(_))
+        ));
+    }
+
+    #[test]
+    fn test_open_disk_image_format() {
+        let image = vmm_sys_util::tempfile::TempFile::new().unwrap();
+        image.as_file().set_len(0x10_0000).unwrap();
+        let path = image.as_path().to_str().unwrap();
+
+        let disk_cfg = DiskConfig::parse(&format!("path={path},format=raw")).unwrap();
+        DeviceManager::open_disk_image(&disk_cfg, None, false, false).unwrap();
+
+        assert!(matches!(
+            DeviceManager::open_disk_image(
+                &DiskConfig::parse(&format!("path={path},format=vhdx")).unwrap(),
+                None,
+                false,
+                false
+            ),
+            Err(DeviceManagerError::ImageTypeMismatch(
+                ImageType::Vhdx,
+                ImageType::Raw
+            ))
+        ));
+
+        // An image given as raw isn't probed, whatever its header.
+        image
+            .as_file()
+            .write_all_at(&0x5146_49fb_u32.to_be_bytes(), 0)
+            .unwrap();
+        let mut disk = DeviceManager::open_disk_image(&disk_cfg, None, false, false).unwrap();
+        assert_eq!(disk.size().unwrap(), 0x10_0000);
+    }
 }
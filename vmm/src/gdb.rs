@@ -5,8 +5,12 @@
 //
 // SPDX-License-Identifier: BSD-3-Clause
 
+use std::collections::VecDeque;
+use std::net::{SocketAddr, TcpListener};
 use std::os::unix::net::UnixListener;
+use std::path::PathBuf;
 use std::sync::mpsc;
+use std::{fmt, io};
 
 use gdbstub::arch::Arch;
 use gdbstub::common::{Signal, Tid};
@@ -18,7 +22,8 @@ use gdbstub::target::ext::base::multithread::{
 };
 use gdbstub::target::ext::base::BaseOps;
 use gdbstub::target::ext::breakpoints::{
-    Breakpoints, BreakpointsOps, HwBreakpoint, HwBreakpointOps,
+    Breakpoints, BreakpointsOps, HwBreakpoint, HwBreakpointOps, HwWatchpoint, HwWatchpointOps,
+    WatchKind,
 };
 use gdbstub::target::{Target, TargetError, TargetResult};
 #[cfg(target_arch = "aarch64")]
@@ -29,6 +34,7 @@ use gdbstub_arch::aarch64::AArch64 as GdbArch;
 use gdbstub_arch::x86::reg::X86_64CoreRegs as CoreRegs;
 #[cfg(target_arch = "x86_64")]
 use gdbstub_arch::x86::X86_64_SSE as GdbArch;
+use hypervisor::{DebugExit, HwWatchpoint as GuestWatchpoint, WatchpointKind};
 use thiserror::Error;
 use vm_memory::{GuestAddress, GuestMemoryAtomic, GuestMemoryError};
 
@@ -63,8 +69,11 @@ pub trait Debuggable: vm_migration::Pausable {
         &self,
         cpu_id: usize,
         addrs: &[GuestAddress],
+        watchpoints: &[GuestWatchpoint],
         singlestep: bool,
     ) -> Result<(), DebuggableError>;
+    /// Returns, and forgets, the debug exits of the vCPUs since the last call.
+    fn debug_stops(&self) -> Vec<(usize, DebugExit)>;
     fn debug_pause(&mut self) -> std::result::Result<(), DebuggableError>;
     fn debug_resume(&mut self) -> std::result::Result<(), DebuggableError>;
     fn read_regs(&self, cpu_id: usize) -> std::result::Result<CoreRegs, DebuggableError>;
@@ -120,8 +129,12 @@ pub enum GdbRequestPayload {
     WriteMem(GuestAddress, Vec<u8>),
     Pause,
     Resume,
-    SetSingleStep(bool),
-    SetHwBreakPoint(Vec<GuestAddress>),
+    SetGuestDebug {
+        breakpoints: Vec<GuestAddress>,
+        watchpoints: Vec<GuestWatchpoint>,
+        single_step: Option<usize>,
+    },
+    DebugStops,
     ActiveVcpus,
 }
 
@@ -132,15 +145,40 @@ pub enum GdbResponsePayload {
     CommandComplete,
     RegValues(Box<CoreRegs>),
     MemoryRegion(Vec<u8>),
+    DebugStops(Vec<(usize, DebugExit)>),
     ActiveVcpus(usize),
 }
 
+/// Where the GDB stub waits for the connection of GDB.
+#[derive(Clone, Debug)]
+pub enum GdbListener {
+    Unix(PathBuf),
+    Tcp(SocketAddr),
+}
+
+impl fmt::Display for GdbListener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GdbListener::Unix(path) => write!(f, "{}", path.display()),
+            GdbListener::Tcp(addr) => write!(f, "{addr}"),
+        }
+    }
+}
+
 pub struct GdbStub {
     gdb_sender: mpsc::Sender<GdbRequest>,
     gdb_event: vmm_sys_util::eventfd::EventFd,
     vm_event: vmm_sys_util::eventfd::EventFd,
     hw_breakpoints: Vec<GuestAddress>,
-    single_step: bool,
+    max_hw_breakpoints: usize,
+    hw_watchpoints: Vec<GuestWatchpoint>,
+    max_hw_watchpoints: usize,
+    // vCPU to single step on the next resume.
+    single_step: Option<usize>,
+    // Single step as last applied to the vCPUs.
+    guest_single_step: Option<usize>,
+    // Stops not reported to GDB yet, when several vCPUs stopped at once.
+    pending_stops: VecDeque<(usize, DebugExit)>,
 }
 
 impl GdbStub {
@@ -149,13 +187,78 @@ impl GdbStub {
         gdb_event: vmm_sys_util::eventfd::EventFd,
         vm_event: vmm_sys_util::eventfd::EventFd,
         hw_breakpoints: usize,
+        hw_watchpoints: usize,
     ) -> Self {
         Self {
             gdb_sender,
             gdb_event,
             vm_event,
             hw_breakpoints: Vec::with_capacity(hw_breakpoints),
-            single_step: false,
+            max_hw_breakpoints: hw_breakpoints,
+            hw_watchpoints: Vec::with_capacity(hw_watchpoints),
+            max_hw_watchpoints: hw_watchpoints,
+            single_step: None,
+            guest_single_step: None,
+            pending_stops: VecDeque::new(),
+        }
+    }
+
+    fn hw_breakpoints_full(&self) -> bool {
+        // On x86_64, the breakpoints and the watchpoints share the same four
+        // debug address registers.
+        #[cfg(target_arch = "x86_64")]
+        {
+            self.hw_breakpoints.len() + self.hw_watchpoints.len() >= self.max_hw_breakpoints
+        }
+        #[cfg(target_arch = "aarch64")]
+        {
+            self.hw_breakpoints.len() >= self.max_hw_breakpoints
+        }
+    }
+
+    fn hw_watchpoints_full(&self) -> bool {
+        #[cfg(target_arch = "x86_64")]
+        {
+            self.hw_breakpoints.len() + self.hw_watchpoints.len() >= self.max_hw_watchpoints
+        }
+        #[cfg(target_arch = "aarch64")]
+        {
+            self.hw_watchpoints.len() >= self.max_hw_watchpoints
+        }
+    }
+
+    fn set_guest_debug(&mut self) -> GdbResult<()> {
+        let payload = GdbRequestPayload::SetGuestDebug {
+            breakpoints: self.hw_breakpoints.clone(),
+            watchpoints: self.hw_watchpoints.clone(),
+            single_step: self.single_step,
+        };
+        self.vm_request(payload, 0)?;
+        self.guest_single_step = self.single_step;
+        Ok(())
+    }
+
+    fn stop_reason(&self, cpu_id: usize, exit: DebugExit) -> MultiThreadStopReason<ArchUsize> {
+        let tid = cpuid_to_tid(cpu_id);
+        match exit {
+            DebugExit::Breakpoint => MultiThreadStopReason::HwBreak(tid),
+            DebugExit::Watchpoint(addr) => {
+                match self.hw_watchpoints.iter().find(|w| w.contains(addr)) {
+                    Some(watchpoint) => MultiThreadStopReason::Watch {
+                        tid,
+                        kind: watch_kind(watchpoint.kind),
+                        addr: addr.0,
+                    },
+                    None => MultiThreadStopReason::SignalWithThread {
+                        tid,
+                        signal: Signal::SIGTRAP,
+                    },
+                }
+            }
+            DebugExit::SingleStep => MultiThreadStopReason::SignalWithThread {
+                tid,
+                signal: Signal::SIGTRAP,
+            },
         }
     }
 
@@ -199,6 +302,34 @@ impl Target for GdbStub {
     }
 }
 
+fn watchpoint_kind(kind: WatchKind) -> WatchpointKind {
+    match kind {
+        WatchKind::Write => WatchpointKind::Write,
+        WatchKind::Read => WatchpointKind::Read,
+        WatchKind::ReadWrite => WatchpointKind::ReadWrite,
+    }
+}
+
+fn watch_kind(kind: WatchpointKind) -> WatchKind {
+    match kind {
+        WatchpointKind::Write => WatchKind::Write,
+        WatchpointKind::Read => WatchKind::Read,
+        WatchpointKind::ReadWrite => WatchKind::ReadWrite,
+    }
+}
+
+// Whether the debug registers can watch `len` bytes from `addr`.
+fn watchpoint_supported(addr: u64, len: u64) -> bool {
+    #[cfg(target_arch = "x86_64")]
+    {
+        matches!(len, 1 | 2 | 4 | 8) && addr & (len - 1) == 0
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        len > 0 && (addr & 0x7) + len <= 8
+    }
+}
+
 fn tid_to_cpuid(tid: Tid) -> usize {
     tid.get() - 1
 }
@@ -319,6 +450,16 @@ impl MultiThreadBase for GdbStub {
 
 impl MultiThreadResume for GdbStub {
     fn resume(&mut self) -> Result<(), Self::Error> {
+        // Report the stops of the other vCPUs before running the guest again.
+        if !self.pending_stops.is_empty() {
+            return Ok(());
+        }
+
+        if self.single_step != self.guest_single_step {
+            self.set_guest_debug()
+                .map_err(|e| format!("Failed to request SetGuestDebug: {e:?}"))?;
+        }
+
         match self.vm_request(GdbRequestPayload::Resume, 0) {
             Ok(_) => Ok(()),
             Err(e) => Err(format!("Failed to resume the target: {e:?}")),
@@ -326,31 +467,20 @@ impl MultiThreadResume for GdbStub {
     }
 
     fn clear_resume_actions(&mut self) -> Result<(), Self::Error> {
-        if self.single_step {
-            match self.vm_request(GdbRequestPayload::SetSingleStep(false), 0) {
-                Ok(_) => {
-                    self.single_step = false;
-                }
-                Err(e) => {
-                    return Err(format!("Failed to request SetSingleStep: {e:?}"));
-                }
-            }
-        }
+        self.single_step = None;
         Ok(())
     }
 
     fn set_resume_action_continue(
         &mut self,
-        tid: Tid,
+        _tid: Tid,
         signal: Option<Signal>,
     ) -> Result<(), Self::Error> {
         if signal.is_some() {
             return Err("no support for continuing with signal".to_owned());
         }
-        match self.vm_request(GdbRequestPayload::Resume, tid_to_cpuid(tid)) {
-            Ok(_) => Ok(()),
-            Err(e) => Err(format!("Failed to resume the target: {e:?}")),
-        }
+        // All the vCPUs run on resume.
+        Ok(())
     }
 
     #[inline(always)]
@@ -368,21 +498,8 @@ impl MultiThreadSingleStep for GdbStub {
         if signal.is_some() {
             return Err("no support for stepping with signal".to_owned());
         }
-
-        if !self.single_step {
-            match self.vm_request(GdbRequestPayload::SetSingleStep(true), tid_to_cpuid(tid)) {
-                Ok(_) => {
-                    self.single_step = true;
-                }
-                Err(e) => {
-                    return Err(format!("Failed to request SetSingleStep: {e:?}"));
-                }
-            }
-        }
-        match self.vm_request(GdbRequestPayload::Resume, tid_to_cpuid(tid)) {
-            Ok(_) => Ok(()),
-            Err(e) => Err(format!("Failed to resume the target: {e:?}")),
-        }
+        self.single_step = Some(tid_to_cpuid(tid));
+        Ok(())
     }
 }
 
@@ -391,6 +508,11 @@ impl Breakpoints for GdbStub {
     fn support_hw_breakpoint(&mut self) -> Option<HwBreakpointOps<'_, Self>> {
         Some(self)
     }
+
+    #[inline(always)]
+    fn support_hw_watchpoint(&mut self) -> Option<HwWatchpointOps<'_, Self>> {
+        Some(self)
+    }
 }
 
 impl HwBreakpoint for GdbStub {
@@ -400,21 +522,20 @@ impl HwBreakpoint for GdbStub {
         _kind: <Self::Arch as Arch>::BreakpointKind,
     ) -> TargetResult<bool, Self> {
         // If the HW breakpoints reach the limit, no more can be added.
-        if self.hw_breakpoints.len() >= self.hw_breakpoints.capacity() {
+        if self.hw_breakpoints_full() {
             error!(
                 "Not allowed to set more than {} HW breakpoints",
-                self.hw_breakpoints.capacity()
+                self.max_hw_breakpoints
             );
             return Ok(false);
         }
 
         self.hw_breakpoints.push(GuestAddress(addr));
 
-        let payload = GdbRequestPayload::SetHwBreakPoint(self.hw_breakpoints.clone());
-        match self.vm_request(payload, 0) {
+        match self.set_guest_debug() {
             Ok(_) => Ok(true),
             Err(e) => {
-                error!("Failed to request SetHwBreakPoint: {:?}", e);
+                error!("Failed to request SetGuestDebug: {:?}", e);
                 Err(TargetError::NonFatal)
             }
         }
@@ -429,11 +550,75 @@ impl HwBreakpoint for GdbStub {
             Some(pos) => self.hw_breakpoints.remove(pos),
         };
 
-        let payload = GdbRequestPayload::SetHwBreakPoint(self.hw_breakpoints.clone());
-        match self.vm_request(payload, 0) {
+        match self.set_guest_debug() {
             Ok(_) => Ok(true),
             Err(e) => {
-                error!("Failed to request SetHwBreakPoint: {:?}", e);
+                error!("Failed to request SetGuestDebug: {:?}", e);
+                Err(TargetError::NonFatal)
+            }
+        }
+    }
+}
+
+impl HwWatchpoint for GdbStub {
+    fn add_hw_watchpoint(
+        &mut self,
+        addr: <Self::Arch as Arch>::Usize,
+        len: <Self::Arch as Arch>::Usize,
+        kind: WatchKind,
+    ) -> TargetResult<bool, Self> {
+        if !watchpoint_supported(addr, len) {
+            error!(
+                "Not allowed to watch {} bytes at {:#x}, check the alignment",
+                len, addr
+            );
+            return Ok(false);
+        }
+
+        // If the HW watchpoints reach the limit, no more can be added.
+        if self.hw_watchpoints_full() {
+            error!(
+                "Not allowed to set more than {} HW watchpoints",
+                self.max_hw_watchpoints
+            );
+            return Ok(false);
+        }
+
+        self.hw_watchpoints.push(GuestWatchpoint {
+            addr: GuestAddress(addr),
+            len,
+            kind: watchpoint_kind(kind),
+        });
+
+        match self.set_guest_debug() {
+            Ok(_) => Ok(true),
+            Err(e) => {
+                error!("Failed to request SetGuestDebug: {:?}", e);
+                Err(TargetError::NonFatal)
+            }
+        }
+    }
+
+    fn remove_hw_watchpoint(
+        &mut self,
+        addr: <Self::Arch as Arch>::Usize,
+        len: <Self::Arch as Arch>::Usize,
+        kind: WatchKind,
+    ) -> TargetResult<bool, Self> {
+        let kind = watchpoint_kind(kind);
+        match self
+            .hw_watchpoints
+            .iter()
+            .position(|w| w.addr.0 == addr && w.len == len && w.kind == kind)
+        {
+            None => return Ok(false),
+            Some(pos) => self.hw_watchpoints.remove(pos),
+        };
+
+        match self.set_guest_debug() {
+            Ok(_) => Ok(true),
+            Err(e) => {
+                error!("Failed to request SetGuestDebug: {:?}", e);
                 Err(TargetError::NonFatal)
             }
         }
@@ -459,9 +644,14 @@ impl run_blocking::BlockingEventLoop for GdbEventLoop {
     > {
         // Polling
         loop {
+            if let Some((cpu_id, exit)) = target.pending_stops.pop_front() {
+                let stop_reason = target.stop_reason(cpu_id, exit);
+                return Ok(run_blocking::Event::TargetStopped(stop_reason));
+            }
+
             // This read is non-blocking.
             match target.vm_event.read() {
-                Ok(tid) => {
+                Ok(_) => {
                     target
                         .vm_request(GdbRequestPayload::Pause, 0)
                         .map_err(|_| {
@@ -469,10 +659,23 @@ impl run_blocking::BlockingEventLoop for GdbEventLoop {
                                 "Failed to pause VM".to_owned(),
                             )
                         })?;
-                    let stop_reason = if target.single_step {
-                        MultiThreadStopReason::DoneStep
-                    } else {
-                        MultiThreadStopReason::HwBreak(Tid::new(tid as usize).unwrap())
+                    // Other vCPUs may have stopped before the pause, their
+                    // stops are all fetched below.
+                    let _ = target.vm_event.read();
+
+                    let stops = match target.vm_request(GdbRequestPayload::DebugStops, 0) {
+                        Ok(GdbResponsePayload::DebugStops(stops)) => stops,
+                        _ => {
+                            return Err(run_blocking::WaitForStopReasonError::Target(
+                                "Failed to get the debug stops".to_owned(),
+                            ))
+                        }
+                    };
+                    target.pending_stops.extend(stops);
+
+                    let stop_reason = match target.pending_stops.pop_front() {
+                        Some((cpu_id, exit)) => target.stop_reason(cpu_id, exit),
+                        None => MultiThreadStopReason::Signal(Signal::SIGTRAP),
                     };
                     return Ok(run_blocking::Event::TargetStopped(stop_reason));
                 }
@@ -505,26 +708,41 @@ impl run_blocking::BlockingEventLoop for GdbEventLoop {
     }
 }
 
-pub fn gdb_thread(mut gdbstub: GdbStub, path: &std::path::Path) {
-    let listener = match UnixListener::bind(path) {
-        Ok(s) => s,
-        Err(e) => {
-            error!("Failed to create a Unix domain socket listener: {}", e);
-            return;
+fn accept_connection(
+    listener: &GdbListener,
+) -> io::Result<Box<dyn ConnectionExt<Error = io::Error>>> {
+    match listener {
+        GdbListener::Unix(path) => {
+            let listener = UnixListener::bind(path)?;
+            info!("Waiting for a GDB connection on {}...", path.display());
+            let (stream, addr) = listener.accept()?;
+            info!("GDB connected from {:?}", addr);
+            Ok(Box::new(stream))
         }
-    };
-    info!("Waiting for a GDB connection on {}...", path.display());
+        GdbListener::Tcp(addr) => {
+            let listener = TcpListener::bind(addr)?;
+            info!("Waiting for a GDB connection on {}...", addr);
+            let (stream, peer) = listener.accept()?;
+            // GDB sends small packets and waits for each reply.
+            stream.set_nodelay(true)?;
+            info!("GDB connected from {}", peer);
+            Ok(Box::new(stream))
+        }
+    }
+}
 
-    let (stream, addr) = match listener.accept() {
-        Ok(v) => v,
+pub fn gdb_thread(mut gdbstub: GdbStub, listener: &GdbListener) {
+    let connection = match accept_connection(listener) {
+        Ok(c) => c,
         Err(e) => {
-            error!("Failed to accept a connection from GDB: {}", e);
+            error!(
+                "Failed to accept a connection from GDB on {}: {}",
+                listener, e
+            );
             return;
         }
     };
-    info!("GDB connected from {:?}", addr);
 
-    let connection: Box<dyn ConnectionExt<Error = std::io::Error>> = Box::new(stream);
     let gdb = gdbstub::stub::GdbStub::new(connection);
 
     match gdb.run_blocking::<GdbEventLoop>(&mut gdbstub) {
@@ -532,14 +750,11 @@ pub fn gdb_thread(mut gdbstub: GdbStub, path: &std::path::Path) {
             DisconnectReason::Disconnect => {
                 info!("GDB client has disconnected. Running...");
 
-                if let Err(e) = gdbstub.vm_request(GdbRequestPayload::SetSingleStep(false), 0) {
-                    error!("Failed to disable single step: {:?}", e);
-                }
-
-                if let Err(e) =
-                    gdbstub.vm_request(GdbRequestPayload::SetHwBreakPoint(Vec::new()), 0)
-                {
-                    error!("Failed to remove breakpoints: {:?}", e);
+                gdbstub.hw_breakpoints.clear();
+                gdbstub.hw_watchpoints.clear();
+                gdbstub.single_step = None;
+                if let Err(e) = gdbstub.set_guest_debug() {
+                    error!("Failed to remove breakpoints and watchpoints: {:?}", e);
                 }
 
                 if let Err(e) = gdbstub.vm_request(GdbRequestPayload::Resume, 0) {
@@ -555,3 +770,120 @@ pub fn gdb_thread(mut gdbstub: GdbStub, path: &std::path::Path) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use vmm_sys_util::eventfd::EventFd;
+
+    use super::*;
+
+    fn create_gdb_stub(hw_breakpoints: usize, hw_watchpoints: usize) -> GdbStub {
+        let (gdb_sender, _) = mpsc::channel();
+        GdbStub::new(
+            gdb_sender,
+            EventFd::new(libc::EFD_NONBLOCK).unwrap(),
+            EventFd::new(libc::EFD_NONBLOCK).unwrap(),
+            hw_breakpoints,
+            hw_watchpoints,
+        )
+    }
+
+    #[test]
+    fn test_watchpoint_kind() {
+        for kind in [WatchKind::Write, WatchKind::Read, WatchKind::ReadWrite] {
+            assert_eq!(watch_kind(watchpoint_kind(kind)), kind);
+        }
+    }
+
+    #[test]
+    fn test_watchpoint_supported() {
+        assert!(watchpoint_supported(0x1000, 1));
+        assert!(watchpoint_supported(0x1000, 8));
+        assert!(!watchpoint_supported(0x1000, 0));
+        assert!(!watchpoint_supported(0x1004, 8));
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            assert!(watchpoint_supported(0x1002, 2));
+            assert!(!watchpoint_supported(0x1001, 2));
+            assert!(!watchpoint_supported(0x1000, 3));
+        }
+        #[cfg(target_arch = "aarch64")]
+        {
+            assert!(watchpoint_supported(0x1001, 3));
+            assert!(!watchpoint_supported(0x1006, 3));
+        }
+    }
+
+    #[test]
+    fn test_gdb_listener_display() {
+        assert_eq!(
+            GdbListener::Unix(PathBuf::from("/tmp/gdb.sock")).to_string(),
+            "/tmp/gdb.sock"
+        );
+        assert_eq!(
+            GdbListener::Tcp("127.0.0.1:1234".parse().unwrap()).to_string(),
+            "127.0.0.1:1234"
+        );
+    }
+
+    #[test]
+    fn test_hw_debug_registers_full() {
+        let mut gdb_stub = create_gdb_stub(4, 4);
+        assert!(!gdb_stub.hw_breakpoints_full());
+        assert!(!gdb_stub.hw_watchpoints_full());
+
+        gdb_stub.hw_breakpoints = vec![GuestAddress(0x1000); 2];
+        gdb_stub.hw_watchpoints = vec![
+            GuestWatchpoint {
+                addr: GuestAddress(0x2000),
+                len: 8,
+                kind: WatchpointKind::Write,
+            };
+            2
+        ];
+        // The debug address registers are shared on x86_64.
+        #[cfg(target_arch = "x86_64")]
+        {
+            assert!(gdb_stub.hw_breakpoints_full());
+            assert!(gdb_stub.hw_watchpoints_full());
+        }
+        #[cfg(target_arch = "aarch64")]
+        {
+            assert!(!gdb_stub.hw_breakpoints_full());
+            assert!(!gdb_stub.hw_watchpoints_full());
+        }
+    }
+
+    #[test]
+    fn test_stop_reason() {
+        let mut gdb_stub = create_gdb_stub(4, 4);
+        gdb_stub.hw_watchpoints.push(GuestWatchpoint {
+            addr: GuestAddress(0x2000),
+            len: 8,
+            kind: WatchpointKind::ReadWrite,
+        });
+
+        assert!(matches!(
+            gdb_stub.stop_reason(1, DebugExit::Breakpoint),
+            MultiThreadStopReason::HwBreak(tid) if tid == cpuid_to_tid(1)
+        ));
+        assert!(matches!(
+            gdb_stub.stop_reason(0, DebugExit::SingleStep),
+            MultiThreadStopReason::SignalWithThread { signal, .. } if signal == Signal::SIGTRAP
+        ));
+        assert!(matches!(
+            gdb_stub.stop_reason(0, DebugExit::Watchpoint(GuestAddress(0x2004))),
+            MultiThreadStopReason::Watch {
+                kind: WatchKind::ReadWrite,
+                addr: 0x2004,
+                ..
+            }
+        ));
+        // An access out of the watchpoints can't be reported as such.
+        assert!(matches!(
+            gdb_stub.stop_reason(0, DebugExit::Watchpoint(GuestAddress(0x2008))),
+            MultiThreadStopReason::SignalWithThread { .. }
+        ));
+    }
+}
@@ -0,0 +1,234 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Host side of the QEMU guest agent protocol.
+//!
+//! The agent (`qemu-ga`) runs in the guest, listening on a vsock port, and is
+//! reached through the hybrid vsock socket of the VM: the connection to the
+//! port is requested with a `CONNECT <port>` line, after which the JSON
+//! commands and their responses are exchanged, one per line.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{env, process};
+
+use serde_json::{json, Value};
+use thiserror::Error;
+
+use crate::vm_config::{VmConfig, VsockConfig};
+
+// Context ID of the vsock device created for the guest agent.
+const GUEST_AGENT_CID: u32 = 3;
+// Bounds the time the VMM thread can be blocked by an unresponsive agent.
+const GUEST_AGENT_TIMEOUT: Duration = Duration::from_secs(30);
+// Delimits the response to a guest-sync-delimited command from stale data.
+const SYNC_DELIMITER: u8 = 0xff;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Cannot connect to the vsock socket")]
+    Connect(#[source] io::Error),
+
+    #[error("The guest agent is not listening on vsock port {0}")]
+    NotListening(u32),
+
+    #[error("Error communicating with the guest agent")]
+    Io(#[source] io::Error),
+
+    #[error("Invalid guest agent response")]
+    InvalidResponse(#[source] serde_json::Error),
+
+    #[error("The guest agent could not synchronize")]
+    Sync,
+
+    #[error("Guest agent command failed: {0}")]
+    Command(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Adds the vsock device reaching the guest agent, unless the VM already
+/// has one.
+pub fn provision_vsock(config: &mut VmConfig) {
+    if config.guest_agent.is_none() || config.vsock.is_some() {
+        return;
+    }
+
+    let socket = env::temp_dir().join(format!(
+        "cloud-hypervisor-{}-guest-agent.sock",
+        process::id()
+    ));
+    info!("Creating vsock device {:?} for the guest agent", socket);
+    config.vsock = Some(VsockConfig {
+        cid: GUEST_AGENT_CID,
+        socket,
+        iommu: false,
+        id: None,
+        pci_segment: 0,
+    });
+}
+
+/// Connection to the guest agent.
+pub struct GuestAgent {
+    stream: BufReader<UnixStream>,
+}
+
+impl GuestAgent {
+    pub fn connect(socket: &Path, port: u32) -> Result<Self> {
+        let stream = UnixStream::connect(socket).map_err(Error::Connect)?;
+        stream
+            .set_read_timeout(Some(GUEST_AGENT_TIMEOUT))
+            .map_err(Error::Connect)?;
+        stream
+            .set_write_timeout(Some(GUEST_AGENT_TIMEOUT))
+            .map_err(Error::Connect)?;
+
+        let mut agent = GuestAgent {
+            stream: BufReader::new(stream),
+        };
+        agent.write(format!("CONNECT {port}\n").as_bytes())?;
+        let mut line = String::new();
+        agent.stream.read_line(&mut line).map_err(Error::Io)?;
+        if !line.starts_with("OK ") {
+            return Err(Error::NotListening(port));
+        }
+
+        agent.sync()?;
+
+        Ok(agent)
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        self.stream.get_ref().write_all(data).map_err(Error::Io)
+    }
+
+    fn send(&mut self, command: &str, arguments: Option<Value>) -> Result<()> {
+        let mut request = json!({ "execute": command });
+        if let Some(arguments) = arguments {
+            request["arguments"] = arguments;
+        }
+        let mut data = request.to_string().into_bytes();
+        data.push(b'\n');
+
+        self.write(&data)
+    }
+
+    fn receive(&mut self) -> Result<Value> {
+        let mut line = String::new();
+        if self.stream.read_line(&mut line).map_err(Error::Io)? == 0 {
+            return Err(Error::Io(io::ErrorKind::UnexpectedEof.into()));
+        }
+
+        let mut response: Value = serde_json::from_str(&line).map_err(Error::InvalidResponse)?;
+        if let Some(error) = response.get("error") {
+            let desc = error["desc"].as_str().unwrap_or("unknown error");
+            return Err(Error::Command(desc.to_string()));
+        }
+
+        Ok(response["return"].take())
+    }
+
+    // Discards any data left by previous connections, such as the response
+    // to a timed out command.
+    fn sync(&mut self) -> Result<()> {
+        let id = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or_default()
+            ^ process::id();
+        self.send("guest-sync-delimited", Some(json!({ "id": id })))?;
+
+        let mut skipped = Vec::new();
+        self.stream
+            .read_until(SYNC_DELIMITER, &mut skipped)
+            .map_err(Error::Io)?;
+        if skipped.last() != Some(&SYNC_DELIMITER) || self.receive()? != json!(id) {
+            return Err(Error::Sync);
+        }
+
+        Ok(())
+    }
+
+    /// Runs a command, returning its result.
+    pub fn execute(&mut self, command: &str, arguments: Option<Value>) -> Result<Value> {
+        self.send(command, arguments)?;
+        self.receive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::net::UnixListener;
+    use std::thread;
+
+    use vmm_sys_util::tempdir::TempDir;
+
+    use super::*;
+
+    // Emulates the hybrid vsock socket, with an agent listening on port 1234.
+    fn serve_agent(listener: UnixListener) {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut writer = stream;
+
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if line != "CONNECT 1234\n" {
+            return;
+        }
+        writer.write_all(b"OK 1073741824\n").unwrap();
+
+        loop {
+            line.clear();
+            if reader.read_line(&mut line).unwrap() == 0 {
+                return;
+            }
+            let request: Value = serde_json::from_str(&line).unwrap();
+            let response = match request["execute"].as_str().unwrap() {
+                "guest-sync-delimited" => {
+                    writer.write_all(b"{\"return\": 0}\n\xff").unwrap();
+                    json!({ "return": request["arguments"]["id"] })
+                }
+                "guest-get-osinfo" => json!({ "return": { "id": "debian" } }),
+                _ => json!({
+                    "error": { "class": "CommandNotFound", "desc": "command not found" }
+                }),
+            };
+            writer
+                .write_all(format!("{response}\n").as_bytes())
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_guest_agent() {
+        let dir = TempDir::new_with_prefix("/tmp/ch-guest-agent").unwrap();
+        let socket = dir.as_path().join("vsock.sock");
+        let listener = UnixListener::bind(&socket).unwrap();
+        let agent = thread::spawn(move || serve_agent(listener));
+
+        let mut guest_agent = GuestAgent::connect(&socket, 1234).unwrap();
+        assert_eq!(
+            guest_agent.execute("guest-get-osinfo", None).unwrap(),
+            json!({ "id": "debian" })
+        );
+        assert!(matches!(
+            guest_agent.execute("guest-unknown", None),
+            Err(Error::Command(_))
+        ));
+        drop(guest_agent);
+        agent.join().unwrap();
+
+        let listener = UnixListener::bind(dir.as_path().join("other.sock")).unwrap();
+        let agent = thread::spawn(move || serve_agent(listener));
+        assert!(matches!(
+            GuestAgent::connect(&dir.as_path().join("other.sock"), 4321),
+            Err(Error::NotListening(4321))
+        ));
+        agent.join().unwrap();
+    }
+}
@@ -0,0 +1,447 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Hooks run on the lifecycle events of the VM, either by executing a
+//! program or by posting the event to an HTTP endpoint, so that the
+//! orchestration doesn't have to poll the API.
+
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use hypervisor::HypervisorType;
+use option_parser::{OptionParser, OptionParserError, StringList};
+use seccompiler::{apply_filter, SeccompAction};
+use serde::Deserialize;
+use thiserror::Error;
+use vmm_sys_util::eventfd::EventFd;
+
+use crate::seccomp_filters::{get_seccomp_filter, Thread};
+use crate::{Error as VmmError, Result as VmmResult};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+// Time given to the last events to reach the hooks once the VMM has stopped.
+const STOP_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Error parsing --hook")]
+    Parse(#[source] OptionParserError),
+    #[error("Error parsing --hook: unknown event {0}")]
+    UnknownEvent(String),
+    #[error("Error parsing --hook: either exec or url is required")]
+    MissingAction,
+    #[error("Error parsing --hook: exec and url are mutually exclusive")]
+    ConflictingActions,
+    #[error("Error parsing --hook: {0} is not an http:// URL")]
+    InvalidUrl(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HookEvent {
+    Booted,
+    Paused,
+    Resumed,
+    Shutdown,
+    Crashed,
+    MigratedOut,
+}
+
+impl HookEvent {
+    fn name(self) -> &'static str {
+        match self {
+            HookEvent::Booted => "booted",
+            HookEvent::Paused => "paused",
+            HookEvent::Resumed => "resumed",
+            HookEvent::Shutdown => "shutdown",
+            HookEvent::Crashed => "crashed",
+            HookEvent::MigratedOut => "migrated-out",
+        }
+    }
+
+    // Event of the event monitor the hook event corresponds to.
+    fn from_monitor_event(source: &str, event: &str) -> Option<Self> {
+        match (source, event) {
+            ("vm", "booted") => Some(HookEvent::Booted),
+            ("vm", "paused") => Some(HookEvent::Paused),
+            ("vm", "resumed") => Some(HookEvent::Resumed),
+            ("vm", "shutdown") => Some(HookEvent::Shutdown),
+            ("guest", "panic") | ("cpu", "triple-fault") | ("watchdog", "expired") => {
+                Some(HookEvent::Crashed)
+            }
+            ("vm", "migrated-out") => Some(HookEvent::MigratedOut),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for HookEvent {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "booted" => Ok(HookEvent::Booted),
+            "paused" => Ok(HookEvent::Paused),
+            "resumed" => Ok(HookEvent::Resumed),
+            "shutdown" => Ok(HookEvent::Shutdown),
+            "crashed" => Ok(HookEvent::Crashed),
+            "migrated-out" => Ok(HookEvent::MigratedOut),
+            _ => Err(Error::UnknownEvent(s.to_owned())),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HookAction {
+    /// Program run with the event on its standard input
+    Exec(PathBuf),
+    /// HTTP endpoint the event is posted to
+    Http {
+        host: String,
+        port: u16,
+        path: String,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HookConfig {
+    pub events: Vec<HookEvent>,
+    pub action: HookAction,
+    pub timeout: Duration,
+}
+
+impl HookConfig {
+    pub const SYNTAX: &'static str = "Hook run on VM lifecycle events \
+        \"event=<list_of_events>,exec=<program_path>|url=<http_url>,timeout=<seconds>\" \
+        \n`event` is a list of booted, paused, resumed, shutdown, crashed and migrated-out, \
+        all of them by default \
+        \n`exec` runs the program with the event in JSON on its standard input \
+        \n`url` posts the event in JSON to an http://<host>[:<port>]/<path> endpoint \
+        \n`timeout` bounds the time a hook can take, 10 seconds by default";
+
+    pub fn parse(hook: &str) -> Result<Self, Error> {
+        let mut parser = OptionParser::new();
+        parser.add("event").add("exec").add("url").add("timeout");
+        parser.parse(hook).map_err(Error::Parse)?;
+
+        let events = match parser
+            .convert::<StringList>("event")
+            .map_err(Error::Parse)?
+        {
+            Some(StringList(events)) => events
+                .iter()
+                .map(|event| event.parse())
+                .collect::<Result<Vec<_>, _>>()?,
+            None => vec![
+                HookEvent::Booted,
+                HookEvent::Paused,
+                HookEvent::Resumed,
+                HookEvent::Shutdown,
+                HookEvent::Crashed,
+                HookEvent::MigratedOut,
+            ],
+        };
+        let action = match (parser.get("exec"), parser.get("url")) {
+            (Some(exec), None) => HookAction::Exec(PathBuf::from(exec)),
+            (None, Some(url)) => parse_http_url(&url)?,
+            (Some(_), Some(_)) => return Err(Error::ConflictingActions),
+            (None, None) => return Err(Error::MissingAction),
+        };
+        let timeout = parser
+            .convert("timeout")
+            .map_err(Error::Parse)?
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_TIMEOUT);
+
+        Ok(HookConfig {
+            events,
+            action,
+            timeout,
+        })
+    }
+
+    fn run(&self, event: HookEvent, payload: &str) {
+        let result = match &self.action {
+            HookAction::Exec(program) => exec_hook(program, event, payload, self.timeout),
+            HookAction::Http { host, port, path } => {
+                http_hook(host, *port, path, event, payload, self.timeout)
+            }
+        };
+        if let Err(e) = result {
+            warn!(
+                "Hook {:?} failed on {} event: {}",
+                self.action,
+                event.name(),
+                e
+            );
+        }
+    }
+}
+
+fn parse_http_url(url: &str) -> Result<HookAction, Error> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| Error::InvalidUrl(url.to_owned()))?;
+    let (authority, path) = match rest.find('/') {
+        Some(index) => rest.split_at(index),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (
+            host,
+            port.parse()
+                .map_err(|_| Error::InvalidUrl(url.to_owned()))?,
+        ),
+        None => (authority, 80),
+    };
+    if host.is_empty() {
+        return Err(Error::InvalidUrl(url.to_owned()));
+    }
+
+    Ok(HookAction::Http {
+        host: host.to_owned(),
+        port,
+        path: path.to_owned(),
+    })
+}
+
+fn exec_hook(
+    program: &Path,
+    event: HookEvent,
+    payload: &str,
+    timeout: Duration,
+) -> std::io::Result<()> {
+    let mut child = Command::new(program)
+        .env("CH_HOOK_EVENT", event.name())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()?;
+    // The hook may not read the event, which must not fail it.
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(payload.as_bytes())
+        .ok();
+
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return if status.success() {
+                Ok(())
+            } else {
+                Err(std::io::Error::other(format!("exited with {status}")))
+            };
+        }
+        if Instant::now() >= deadline {
+            child.kill().ok();
+            child.wait().ok();
+            return Err(std::io::Error::from(std::io::ErrorKind::TimedOut));
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn http_hook(
+    host: &str,
+    port: u16,
+    path: &str,
+    event: HookEvent,
+    payload: &str,
+    timeout: Duration,
+) -> std::io::Result<()> {
+    let address = (host, port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| std::io::Error::other(format!("{host} can't be resolved")))?;
+    let mut stream = TcpStream::connect_timeout(&address, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+
+    write!(
+        stream,
+        "POST {path} HTTP/1.1\r\n\
+         Host: {host}:{port}\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         X-Cloud-Hypervisor-Event: {}\r\n\
+         Connection: close\r\n\r\n{payload}",
+        payload.len(),
+        event.name(),
+    )?;
+
+    // Only the status line matters.
+    let mut response = [0u8; 32];
+    let len = stream.read(&mut response)?;
+    let status = String::from_utf8_lossy(&response[..len]);
+    match status.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        _ => Err(std::io::Error::other(format!(
+            "unexpected response {:?}",
+            status.lines().next().unwrap_or_default()
+        ))),
+    }
+}
+
+#[derive(Deserialize)]
+struct MonitorEvent<'a> {
+    source: &'a str,
+    event: &'a str,
+}
+
+enum Message {
+    Event(Arc<String>),
+    Stop,
+}
+
+pub struct HooksHandle {
+    thread: thread::JoinHandle<()>,
+    stop: flume::Sender<()>,
+}
+
+impl HooksHandle {
+    /// Waits for the hooks of the last events of the VMM to complete.
+    pub fn stop(self) {
+        self.stop.send(()).ok();
+        self.thread.join().ok();
+    }
+}
+
+/// Starts the thread running the hooks on the events reported by the event
+/// monitor.
+pub fn start_hooks_thread(
+    hooks: Vec<HookConfig>,
+    events: flume::Receiver<Arc<String>>,
+    seccomp_action: &SeccompAction,
+    hypervisor_type: HypervisorType,
+    exit_event: EventFd,
+) -> VmmResult<HooksHandle> {
+    // Programs inherit the seccomp filter of the thread spawning them, which
+    // isn't meant to confine them.
+    let seccomp_filter = if hooks
+        .iter()
+        .any(|hook| matches!(hook.action, HookAction::Exec(_)))
+    {
+        vec![]
+    } else {
+        get_seccomp_filter(seccomp_action, Thread::Hooks, hypervisor_type)
+            .map_err(VmmError::CreateSeccompFilter)?
+    };
+    let (stop_sender, stop) = flume::bounded(1);
+
+    let thread = thread::Builder::new()
+        .name("hooks".to_owned())
+        .spawn(move || {
+            if !seccomp_filter.is_empty() {
+                if let Err(e) = apply_filter(&seccomp_filter) {
+                    error!("Error applying seccomp filter: {:?}", e);
+                    exit_event.write(1).ok();
+                    return;
+                }
+            }
+
+            let mut deadline = None;
+            loop {
+                let message = match deadline {
+                    Some(deadline) => events.recv_deadline(deadline).ok().map(Message::Event),
+                    None => flume::Selector::new()
+                        .recv(&events, |event| event.ok().map(Message::Event))
+                        .recv(&stop, |_| Some(Message::Stop))
+                        .wait(),
+                };
+                let event = match message {
+                    Some(Message::Event(event)) => event,
+                    // Run the hooks of the events still on their way from
+                    // the event monitor.
+                    Some(Message::Stop) => {
+                        deadline = Some(Instant::now() + STOP_TIMEOUT);
+                        continue;
+                    }
+                    None => break,
+                };
+
+                let Ok(monitor_event) = serde_json::from_str::<MonitorEvent>(&event) else {
+                    continue;
+                };
+                // The VMM doesn't report any event after shutting down.
+                if (monitor_event.source, monitor_event.event) == ("vmm", "shutdown") {
+                    break;
+                }
+                let Some(hook_event) =
+                    HookEvent::from_monitor_event(monitor_event.source, monitor_event.event)
+                else {
+                    continue;
+                };
+                for hook in hooks
+                    .iter()
+                    .filter(|hook| hook.events.contains(&hook_event))
+                {
+                    hook.run(hook_event, &event);
+                }
+            }
+        })
+        .map_err(VmmError::HooksThreadSpawn)?;
+
+    Ok(HooksHandle {
+        thread,
+        stop: stop_sender,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hook_parsing() {
+        assert_eq!(
+            HookConfig::parse("event=[booted,crashed],exec=/usr/bin/notify").unwrap(),
+            HookConfig {
+                events: vec![HookEvent::Booted, HookEvent::Crashed],
+                action: HookAction::Exec(PathBuf::from("/usr/bin/notify")),
+                timeout: DEFAULT_TIMEOUT,
+            }
+        );
+        assert_eq!(
+            HookConfig::parse("url=http://orchestrator:8080/vms/1/events,timeout=2").unwrap(),
+            HookConfig {
+                events: vec![
+                    HookEvent::Booted,
+                    HookEvent::Paused,
+                    HookEvent::Resumed,
+                    HookEvent::Shutdown,
+                    HookEvent::Crashed,
+                    HookEvent::MigratedOut,
+                ],
+                action: HookAction::Http {
+                    host: "orchestrator".to_owned(),
+                    port: 8080,
+                    path: "/vms/1/events".to_owned(),
+                },
+                timeout: Duration::from_secs(2),
+            }
+        );
+        assert_eq!(
+            HookConfig::parse("event=shutdown,url=http://127.0.0.1")
+                .unwrap()
+                .action,
+            HookAction::Http {
+                host: "127.0.0.1".to_owned(),
+                port: 80,
+                path: "/".to_owned(),
+            }
+        );
+
+        HookConfig::parse("event=[booted]").unwrap_err();
+        HookConfig::parse("event=[rebooted],exec=/bin/true").unwrap_err();
+        HookConfig::parse("exec=/bin/true,url=http://localhost/").unwrap_err();
+        HookConfig::parse("url=https://localhost/").unwrap_err();
+    }
+}
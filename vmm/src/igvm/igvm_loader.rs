@@ -6,24 +6,28 @@ use std::collections::HashMap;
 use std::ffi::CString;
 use std::io::{Read, Seek, SeekFrom};
 use std::mem::size_of;
+#[cfg(feature = "sev_snp")]
+use std::path::Path;
 use std::sync::{Arc, Mutex};
 
 use igvm::snp_defs::SevVmsa;
 use igvm::{IgvmDirectiveHeader, IgvmFile, IgvmPlatformHeader, IsolationType};
+#[cfg(feature = "sev_snp")]
+use igvm_defs::IGVM_VHS_SNP_ID_BLOCK;
 use igvm_defs::{
-    IgvmPageDataType, IgvmPlatformType, IGVM_VHS_PARAMETER, IGVM_VHS_PARAMETER_INSERT,
+    IgvmPageDataType, IgvmPlatformType, MemoryMapEntryType, IGVM_VHS_MEMORY_MAP_ENTRY,
+    IGVM_VHS_PARAMETER, IGVM_VHS_PARAMETER_INSERT,
 };
-#[cfg(feature = "sev_snp")]
-use igvm_defs::{MemoryMapEntryType, IGVM_VHS_MEMORY_MAP_ENTRY};
 use mshv_bindings::*;
 use thiserror::Error;
+#[cfg(feature = "sev_snp")]
+use zerocopy::FromZeros;
 use zerocopy::IntoBytes;
 
 use crate::cpu::CpuManager;
 use crate::igvm::loader::Loader;
 use crate::igvm::{BootPageAcceptance, IgvmLoadedInfo, StartupMemoryType, HV_PAGE_SIZE};
 use crate::memory_manager::MemoryManager;
-#[cfg(feature = "sev_snp")]
 use crate::GuestMemoryMmap;
 
 #[derive(Debug, Error)]
@@ -46,6 +50,118 @@ pub enum Error {
     CompleteIsolatedImport(#[source] hypervisor::HypervisorVmError),
     #[error("Error decoding host data")]
     FailedToDecodeHostData(#[source] hex::FromHexError),
+    #[error("igvm file does not support the {0:?} platform")]
+    UnsupportedPlatform(IgvmPlatformType),
+    #[cfg(feature = "sev_snp")]
+    #[error("failed to read SNP ID block")]
+    SnpIdBlock(#[source] std::io::Error),
+    #[cfg(feature = "sev_snp")]
+    #[error("invalid SNP ID block size: {0} bytes")]
+    InvalidSnpIdBlock(usize),
+    #[cfg(feature = "sev_snp")]
+    #[error("invalid SNP ID authentication information size: {0} bytes")]
+    InvalidSnpIdAuth(usize),
+}
+
+// Layout of the ID block and ID authentication information structures,
+// as defined by the SNP firmware ABI (SNP_LAUNCH_FINISH).
+#[cfg(feature = "sev_snp")]
+const SNP_ID_BLOCK_SIZE: usize = 0x60;
+#[cfg(feature = "sev_snp")]
+const SNP_ID_AUTH_SIZE: usize = 0x1000;
+#[cfg(feature = "sev_snp")]
+const SNP_ID_AUTH_ID_BLOCK_SIG: usize = 0x40;
+#[cfg(feature = "sev_snp")]
+const SNP_ID_AUTH_ID_KEY: usize = 0x240;
+#[cfg(feature = "sev_snp")]
+const SNP_ID_AUTH_ID_KEY_SIG: usize = 0x680;
+#[cfg(feature = "sev_snp")]
+const SNP_ID_AUTH_AUTHOR_KEY: usize = 0x880;
+// ECDSA P-384 signature and public key components
+#[cfg(feature = "sev_snp")]
+const SNP_ECDSA_COMPONENT_SIZE: usize = 72;
+
+#[cfg(feature = "sev_snp")]
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+///
+/// Build the SNP ID block from the binary ID block and ID authentication
+/// information structures, overriding the one provided by the IGVM file.
+///
+#[cfg(feature = "sev_snp")]
+fn load_snp_id_block(
+    id_block_path: &Path,
+    id_auth_path: Option<&Path>,
+) -> Result<IGVM_VHS_SNP_ID_BLOCK, Error> {
+    let raw_id_block = std::fs::read(id_block_path).map_err(Error::SnpIdBlock)?;
+    if raw_id_block.len() != SNP_ID_BLOCK_SIZE {
+        return Err(Error::InvalidSnpIdBlock(raw_id_block.len()));
+    }
+
+    let mut id_block = IGVM_VHS_SNP_ID_BLOCK::new_zeroed();
+    id_block.ld.copy_from_slice(&raw_id_block[0x0..0x30]);
+    id_block
+        .family_id
+        .copy_from_slice(&raw_id_block[0x30..0x40]);
+    id_block.image_id.copy_from_slice(&raw_id_block[0x40..0x50]);
+    id_block.version = read_u32(&raw_id_block, 0x50);
+    id_block.guest_svn = read_u32(&raw_id_block, 0x54);
+
+    let Some(id_auth_path) = id_auth_path else {
+        return Ok(id_block);
+    };
+
+    let id_auth = std::fs::read(id_auth_path).map_err(Error::SnpIdBlock)?;
+    if id_auth.len() != SNP_ID_AUTH_SIZE {
+        return Err(Error::InvalidSnpIdAuth(id_auth.len()));
+    }
+
+    let c = SNP_ECDSA_COMPONENT_SIZE;
+    id_block.id_key_algorithm = read_u32(&id_auth, 0x0);
+    id_block.author_key_algorithm = read_u32(&id_auth, 0x4);
+
+    let sig = &id_auth[SNP_ID_AUTH_ID_BLOCK_SIG..];
+    id_block.id_key_signature.r_comp.copy_from_slice(&sig[..c]);
+    id_block
+        .id_key_signature
+        .s_comp
+        .copy_from_slice(&sig[c..2 * c]);
+
+    let key = &id_auth[SNP_ID_AUTH_ID_KEY..];
+    id_block.id_public_key.curve = read_u32(key, 0);
+    id_block.id_public_key.qx.copy_from_slice(&key[4..4 + c]);
+    id_block
+        .id_public_key
+        .qy
+        .copy_from_slice(&key[4 + c..4 + 2 * c]);
+
+    // The author key is optional, an all zero key meaning it isn't used.
+    let author_key = &id_auth[SNP_ID_AUTH_AUTHOR_KEY..];
+    if author_key[..4 + 2 * c].iter().any(|b| *b != 0) {
+        let sig = &id_auth[SNP_ID_AUTH_ID_KEY_SIG..];
+        id_block
+            .author_key_signature
+            .r_comp
+            .copy_from_slice(&sig[..c]);
+        id_block
+            .author_key_signature
+            .s_comp
+            .copy_from_slice(&sig[c..2 * c]);
+        id_block.author_public_key.curve = read_u32(author_key, 0);
+        id_block
+            .author_public_key
+            .qx
+            .copy_from_slice(&author_key[4..4 + c]);
+        id_block
+            .author_public_key
+            .qy
+            .copy_from_slice(&author_key[4 + c..4 + 2 * c]);
+        id_block.author_key_enabled = 1;
+    }
+
+    Ok(id_block)
 }
 
 #[allow(dead_code)]
@@ -64,7 +180,6 @@ enum ParameterAreaState {
     Inserted,
 }
 
-#[cfg(feature = "sev_snp")]
 fn igvm_memmap_from_ram_range(ram_range: (u64, u64)) -> IGVM_VHS_MEMORY_MAP_ENTRY {
     assert!(ram_range.0 % HV_PAGE_SIZE == 0);
     assert!((ram_range.1 - ram_range.0) % HV_PAGE_SIZE == 0);
@@ -78,7 +193,22 @@ fn igvm_memmap_from_ram_range(ram_range: (u64, u64)) -> IGVM_VHS_MEMORY_MAP_ENTR
     }
 }
 
-#[cfg(feature = "sev_snp")]
+// Index of the platform to load the directives of. Non isolated VMs use the
+// native platform, or the first one the file provides otherwise.
+fn select_platform(platforms: &[IgvmPlatformType], sev_snp_enabled: bool) -> Result<usize, Error> {
+    let platform_type = if sev_snp_enabled {
+        IgvmPlatformType::SEV_SNP
+    } else {
+        IgvmPlatformType::NATIVE
+    };
+
+    platforms
+        .iter()
+        .position(|p| *p == platform_type)
+        .or_else(|| Some(0).filter(|_| !sev_snp_enabled && !platforms.is_empty()))
+        .ok_or(Error::UnsupportedPlatform(platform_type))
+}
+
 fn generate_memory_map(
     guest_mem: &GuestMemoryMmap,
 ) -> Result<Vec<IGVM_VHS_MEMORY_MAP_ENTRY>, Error> {
@@ -126,8 +256,9 @@ fn import_parameter(
 ///
 /// Load the given IGVM file to guest memory.
 /// Right now it only supports SNP based isolation.
-/// We can boot legacy VM with an igvm file without
-/// any isolation.
+/// Non isolated VMs are booted from the native platform
+/// directives, or from the SNP ones if the file doesn't
+/// provide any, ignoring the isolation specific bits.
 ///
 pub fn load_igvm(
     mut file: &std::fs::File,
@@ -135,6 +266,8 @@ pub fn load_igvm(
     cpu_manager: Arc<Mutex<CpuManager>>,
     cmdline: &str,
     #[cfg(feature = "sev_snp")] host_data: &Option<String>,
+    #[cfg(feature = "sev_snp")] id_block: Option<&Path>,
+    #[cfg(feature = "sev_snp")] id_auth: Option<&Path>,
 ) -> Result<Box<IgvmLoadedInfo>, Error> {
     let mut loaded_info: Box<IgvmLoadedInfo> = Box::default();
     let command_line = CString::new(cmdline).map_err(Error::InvalidCommandLine)?;
@@ -151,25 +284,44 @@ pub fn load_igvm(
             .map_err(Error::FailedToDecodeHostData)?;
     }
 
+    #[cfg(feature = "sev_snp")]
+    let sev_snp_enabled = cpu_manager.lock().unwrap().sev_snp_enabled();
+    #[cfg(not(feature = "sev_snp"))]
+    let sev_snp_enabled = false;
+
     file.seek(SeekFrom::Start(0)).map_err(Error::Igvm)?;
     file.read_to_end(&mut file_contents).map_err(Error::Igvm)?;
 
-    let igvm_file = IgvmFile::new_from_binary(&file_contents, Some(IsolationType::Snp))
-        .map_err(Error::InvalidIgvmFile)?;
-
-    let mask = match &igvm_file.platforms()[0] {
-        IgvmPlatformHeader::SupportedPlatform(info) => {
-            debug_assert!(info.platform_type == IgvmPlatformType::SEV_SNP);
-            info.compatibility_mask
-        }
+    let isolation = if sev_snp_enabled {
+        Some(IsolationType::Snp)
+    } else {
+        None
     };
+    let igvm_file =
+        IgvmFile::new_from_binary(&file_contents, isolation).map_err(Error::InvalidIgvmFile)?;
+
+    let platforms: Vec<_> = igvm_file
+        .platforms()
+        .iter()
+        .map(|IgvmPlatformHeader::SupportedPlatform(info)| info)
+        .collect();
+    let platform_types: Vec<_> = platforms.iter().map(|info| info.platform_type).collect();
+    let platform = platforms[select_platform(&platform_types, sev_snp_enabled)?];
+    let mask = platform.compatibility_mask;
+    info!(
+        "Loading igvm file for the {:?} platform",
+        platform.platform_type
+    );
 
     let mut loader = Loader::new(memory);
 
     let mut parameter_areas: HashMap<u32, ParameterAreaState> = HashMap::new();
 
     for header in igvm_file.directives() {
-        debug_assert!(header.compatibility_mask().unwrap_or(mask) & mask == mask);
+        // Skip the directives meant for other platforms
+        if header.compatibility_mask().is_some_and(|m| m & mask == 0) {
+            continue;
+        }
 
         match header {
             IgvmDirectiveHeader::PageData {
@@ -283,16 +435,10 @@ pub fn load_igvm(
             IgvmDirectiveHeader::MmioRanges(_info) => {
                 todo!("unsupported IgvmPageDataType");
             }
-            IgvmDirectiveHeader::MemoryMap(_info) => {
-                #[cfg(feature = "sev_snp")]
-                {
-                    let guest_mem = memory_manager.lock().unwrap().boot_guest_memory();
-                    let memory_map = generate_memory_map(&guest_mem)?;
-                    import_parameter(&mut parameter_areas, _info, memory_map.as_bytes())?;
-                }
-
-                #[cfg(not(feature = "sev_snp"))]
-                todo!("Not implemented");
+            IgvmDirectiveHeader::MemoryMap(info) => {
+                let guest_mem = memory_manager.lock().unwrap().boot_guest_memory();
+                let memory_map = generate_memory_map(&guest_mem)?;
+                import_parameter(&mut parameter_areas, info, memory_map.as_bytes())?;
             }
             IgvmDirectiveHeader::CommandLine(info) => {
                 import_parameter(&mut parameter_areas, info, command_line.as_bytes_with_nul())?;
@@ -369,6 +515,17 @@ pub fn load_igvm(
                 loaded_info.snp_id_block.author_key_signature = **author_key_signature;
                 loaded_info.snp_id_block.author_public_key = **author_public_key;
             }
+            IgvmDirectiveHeader::X64NativeVpContext {
+                compatibility_mask: _,
+                context,
+                vp_index,
+            } => {
+                // Only the boot vCPU context is used, providing the entry
+                // point of the guest.
+                if *vp_index == 0 {
+                    loaded_info.native_entry = Some(context.rip);
+                }
+            }
             IgvmDirectiveHeader::X64VbsVpContext {
                 vtl: _,
                 registers: _,
@@ -417,7 +574,12 @@ pub fn load_igvm(
     }
 
     #[cfg(feature = "sev_snp")]
-    {
+    if let Some(id_block) = id_block {
+        loaded_info.snp_id_block = load_snp_id_block(id_block, id_auth)?;
+    }
+
+    #[cfg(feature = "sev_snp")]
+    if sev_snp_enabled {
         use std::time::Instant;
 
         let mut now = Instant::now();
@@ -488,3 +650,101 @@ pub fn load_igvm(
     debug!("Dumping the contents of VMSA page: {:x?}", loaded_info.vmsa);
     Ok(loaded_info)
 }
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "sev_snp")]
+    use vmm_sys_util::tempfile::TempFile;
+
+    use super::*;
+
+    #[cfg(feature = "sev_snp")]
+    #[test]
+    fn test_load_snp_id_block() {
+        let c = SNP_ECDSA_COMPONENT_SIZE;
+
+        let mut raw_id_block = vec![0u8; SNP_ID_BLOCK_SIZE];
+        raw_id_block[0x0..0x30].fill(0x11);
+        raw_id_block[0x30..0x40].fill(0x22);
+        raw_id_block[0x40..0x50].fill(0x33);
+        raw_id_block[0x50..0x54].copy_from_slice(&2u32.to_le_bytes());
+        raw_id_block[0x54..0x58].copy_from_slice(&5u32.to_le_bytes());
+        let id_block_file = TempFile::new().unwrap();
+        std::fs::write(id_block_file.as_path(), &raw_id_block).unwrap();
+
+        let id_block = load_snp_id_block(id_block_file.as_path(), None).unwrap();
+        assert_eq!(id_block.ld, [0x11; 0x30]);
+        assert_eq!(id_block.family_id, [0x22; 0x10]);
+        assert_eq!(id_block.image_id, [0x33; 0x10]);
+        assert_eq!(id_block.version, 2);
+        assert_eq!(id_block.guest_svn, 5);
+        assert_eq!(id_block.author_key_enabled, 0);
+
+        // ID key only, the author key being all zeros
+        let mut id_auth = vec![0u8; SNP_ID_AUTH_SIZE];
+        id_auth[0x0..0x4].copy_from_slice(&1u32.to_le_bytes());
+        id_auth[SNP_ID_AUTH_ID_BLOCK_SIG..SNP_ID_AUTH_ID_BLOCK_SIG + c].fill(0x44);
+        id_auth[SNP_ID_AUTH_ID_KEY..SNP_ID_AUTH_ID_KEY + 4].copy_from_slice(&2u32.to_le_bytes());
+        id_auth[SNP_ID_AUTH_ID_KEY + 4..SNP_ID_AUTH_ID_KEY + 4 + c].fill(0x55);
+        let id_auth_file = TempFile::new().unwrap();
+        std::fs::write(id_auth_file.as_path(), &id_auth).unwrap();
+
+        let id_block =
+            load_snp_id_block(id_block_file.as_path(), Some(id_auth_file.as_path())).unwrap();
+        assert_eq!(id_block.id_key_algorithm, 1);
+        assert_eq!(id_block.id_key_signature.r_comp, [0x44; 72]);
+        assert_eq!(id_block.id_public_key.curve, 2);
+        assert_eq!(id_block.id_public_key.qx, [0x55; 72]);
+        assert_eq!(id_block.author_key_enabled, 0);
+
+        // With an author key
+        id_auth[SNP_ID_AUTH_AUTHOR_KEY..SNP_ID_AUTH_AUTHOR_KEY + 4]
+            .copy_from_slice(&2u32.to_le_bytes());
+        id_auth[SNP_ID_AUTH_ID_KEY_SIG..SNP_ID_AUTH_ID_KEY_SIG + c].fill(0x66);
+        std::fs::write(id_auth_file.as_path(), &id_auth).unwrap();
+        let id_block =
+            load_snp_id_block(id_block_file.as_path(), Some(id_auth_file.as_path())).unwrap();
+        assert_eq!(id_block.author_key_enabled, 1);
+        assert_eq!(id_block.author_public_key.curve, 2);
+        assert_eq!(id_block.author_key_signature.r_comp, [0x66; 72]);
+
+        // Wrong sizes
+        std::fs::write(id_auth_file.as_path(), &id_auth[..0x100]).unwrap();
+        assert!(matches!(
+            load_snp_id_block(id_block_file.as_path(), Some(id_auth_file.as_path())),
+            Err(Error::InvalidSnpIdAuth(0x100))
+        ));
+        std::fs::write(id_block_file.as_path(), &raw_id_block[..0x50]).unwrap();
+        assert!(matches!(
+            load_snp_id_block(id_block_file.as_path(), None),
+            Err(Error::InvalidSnpIdBlock(0x50))
+        ));
+    }
+
+    #[test]
+    fn test_select_platform() {
+        let native = IgvmPlatformType::NATIVE;
+        let snp = IgvmPlatformType::SEV_SNP;
+
+        assert_eq!(select_platform(&[snp, native], false).unwrap(), 1);
+        assert_eq!(select_platform(&[snp, native], true).unwrap(), 0);
+        // Non isolated VMs fall back to the first platform of the file
+        assert_eq!(select_platform(&[snp], false).unwrap(), 0);
+        assert!(matches!(
+            select_platform(&[native], true),
+            Err(Error::UnsupportedPlatform(p)) if p == snp
+        ));
+        assert!(matches!(
+            select_platform(&[], false),
+            Err(Error::UnsupportedPlatform(p)) if p == native
+        ));
+    }
+
+    #[test]
+    fn test_igvm_memmap_from_ram_range() {
+        let entry = igvm_memmap_from_ram_range((0x10_0000, 0x20_0000));
+        assert_eq!(entry.starting_gpa_page_number, 0x100);
+        assert_eq!(entry.number_of_pages, 0x100);
+        assert_eq!(entry.entry_type, MemoryMapEntryType::MEMORY);
+    }
+}
@@ -22,7 +22,8 @@
  *
  *  This module takes the IGVM file, parses it, and loads it to the
  *  guest memory. Currently igvm only supported on Microsoft Hypervisor, as
- *  booting a legacy VM, as well as SNP based isolated VM.
+ *  booting a regular VM from the native platform, as well as SNP based
+ *  isolated VM.
  */
 
 pub mod igvm_loader;
@@ -37,6 +38,8 @@ pub struct IgvmLoadedInfo {
     pub vmsa_gpa: u64,
     pub snp_id_block: IGVM_VHS_SNP_ID_BLOCK,
     pub vmsa: SevVmsa,
+    /// Entry point from the native platform VP context, if any
+    pub native_entry: Option<u64>,
 }
 
 impl Default for IgvmLoadedInfo {
@@ -46,6 +49,7 @@ impl Default for IgvmLoadedInfo {
             vmsa_gpa: 0,
             snp_id_block: IGVM_VHS_SNP_ID_BLOCK::new_zeroed(),
             vmsa: SevVmsa::new_zeroed(),
+            native_entry: None,
         }
     }
 }
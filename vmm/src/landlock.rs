@@ -68,6 +68,7 @@ impl TryFrom<&str> for LandlockAccess {
 }
 pub struct Landlock {
     ruleset: RulesetCreated,
+    rules: Vec<(PathBuf, BitFlags<AccessFs>)>,
 }
 
 impl Landlock {
@@ -82,7 +83,10 @@ impl Landlock {
         // to enable all the supported rules and silently ignore the unsupported ones.
         let ruleset = def_ruleset.create().map_err(LandlockError::ManageRuleset)?;
 
-        Ok(Landlock { ruleset })
+        Ok(Landlock {
+            ruleset,
+            rules: Vec::new(),
+        })
     }
 
     pub(crate) fn add_rule(
@@ -99,6 +103,7 @@ impl Landlock {
             .as_mut()
             .add_rules(path_beneath_rules)
             .map_err(LandlockError::ManageRuleset)?;
+        self.rules.push((path, access));
         Ok(())
     }
 
@@ -111,6 +116,16 @@ impl Landlock {
         Ok(())
     }
 
+    /// Rules added so far, for restricting the worker threads of a device
+    /// to the files of its config.
+    pub(crate) fn thread_landlock(&self) -> virtio_devices::ThreadLandlock {
+        let mut landlock = virtio_devices::ThreadLandlock::default();
+        for (path, access) in self.rules.iter() {
+            landlock.add_rule(path.clone(), *access);
+        }
+        landlock
+    }
+
     pub fn restrict_self(self) -> Result<(), LandlockError> {
         self.ruleset
             .restrict_self()
@@ -153,3 +168,30 @@ fn test_try_from_access() {
 
     LandlockAccess::try_from("").unwrap_err();
 }
+
+#[test]
+fn test_thread_landlock() {
+    let mut landlock = Landlock::new().unwrap();
+    assert_eq!(
+        landlock.thread_landlock(),
+        virtio_devices::ThreadLandlock::default()
+    );
+
+    landlock
+        .add_rule_with_access(PathBuf::from("/tmp"), "r")
+        .unwrap();
+    landlock
+        .add_rule_with_access(PathBuf::from("/dev/null"), "rw")
+        .unwrap();
+
+    let mut thread_landlock = virtio_devices::ThreadLandlock::default();
+    thread_landlock.add_rule(
+        PathBuf::from("/tmp"),
+        LandlockAccess::try_from("r").unwrap().access,
+    );
+    thread_landlock.add_rule(
+        PathBuf::from("/dev/null"),
+        LandlockAccess::try_from("rw").unwrap().access,
+    );
+    assert_eq!(landlock.thread_landlock(), thread_landlock);
+}
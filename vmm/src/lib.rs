@@ -8,26 +8,27 @@ extern crate event_monitor;
 #[macro_use]
 extern crate log;
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
 use std::io::{stdout, Read, Write};
 use std::net::{TcpListener, TcpStream};
 use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
 use std::os::unix::net::{UnixListener, UnixStream};
 use std::panic::AssertUnwindSafe;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::sync::mpsc::{Receiver, RecvError, SendError, Sender};
 use std::sync::{Arc, Mutex};
 #[cfg(not(target_arch = "riscv64"))]
 use std::time::Instant;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::{io, result, thread};
 
 use anyhow::anyhow;
 #[cfg(feature = "dbus_api")]
 use api::dbus::{DBusApiOptions, DBusApiShutdownChannels};
 use api::http::HttpApiHandle;
-use console_devices::{pre_create_console_devices, ConsoleInfo};
+use console_devices::{pre_create_console_devices, ConsoleInfo, ConsoleTee};
 use landlock::LandlockError;
 use libc::{tcsetattr, termios, EFD_NONBLOCK, SIGINT, SIGTERM, TCSANOW};
 use memory_manager::MemoryManagerSnapshotData;
@@ -38,16 +39,21 @@ use serde::{Deserialize, Serialize};
 use signal_hook::iterator::{Handle, Signals};
 use thiserror::Error;
 use tracer::trace_scoped;
+use vm_device::ExitReason;
 use vm_memory::bitmap::{AtomicBitmap, BitmapSlice};
-use vm_memory::{ReadVolatile, VolatileMemoryError, VolatileSlice, WriteVolatile};
+use vm_memory::{
+    GuestAddressSpace, ReadVolatile, VolatileMemoryError, VolatileSlice, WriteVolatile,
+};
 use vm_migration::protocol::*;
 use vm_migration::{Migratable, MigratableError, Pausable, Snapshot, Snapshottable, Transportable};
 use vmm_sys_util::eventfd::EventFd;
 use vmm_sys_util::signal::unblock_signal;
 use vmm_sys_util::sock_ctrl_msg::ScmSocket;
+use vmm_sys_util::timerfd::TimerFd;
 
 use crate::api::{
-    ApiRequest, ApiResponse, RequestHandler, VmInfoResponse, VmReceiveMigrationData,
+    ApiRequest, ApiResponse, RequestHandler, VmBackupData, VmConsoleLogResponse,
+    VmDiskSnapshotData, VmGuestCommandData, VmInfoResponse, VmReceiveMigrationData,
     VmSendMigrationData, VmmPingResponse,
 };
 use crate::config::{add_to_config, RestoreConfig};
@@ -57,39 +63,62 @@ use crate::landlock::Landlock;
 use crate::memory_manager::MemoryManager;
 #[cfg(all(feature = "kvm", target_arch = "x86_64"))]
 use crate::migration::get_vm_snapshot;
-use crate::migration::{recv_vm_config, recv_vm_state};
+use crate::migration::{
+    recv_vm_config, recv_vm_state, send_hibernation_manifest, send_snapshot_manifest,
+    verify_snapshot_manifest, HibernationManifest,
+};
 use crate::seccomp_filters::{get_seccomp_filter, Thread};
 use crate::vm::{Error as VmError, Vm, VmState};
+#[cfg(target_arch = "x86_64")]
+use crate::vm_config::SgxEpcConfig;
 use crate::vm_config::{
-    DeviceConfig, DiskConfig, FsConfig, NetConfig, PmemConfig, UserDeviceConfig, VdpaConfig,
-    VmConfig, VsockConfig,
+    DeviceConfig, DiskConfig, FsConfig, NetConfig, PmemConfig, RateLimiterGroupConfig,
+    RestartPolicy, UserDeviceConfig, VdpaConfig, VmConfig, VsockConfig,
 };
 
 #[cfg(not(target_arch = "riscv64"))]
 mod acpi;
 pub mod api;
+mod balloon_pressure;
+pub mod builder;
 mod clone3;
+mod cloud_init;
 pub mod config;
 pub mod console_devices;
 #[cfg(all(target_arch = "x86_64", feature = "guest_debug"))]
 mod coredump;
 pub mod cpu;
+mod crash_dump;
+mod dax;
 pub mod device_manager;
 pub mod device_tree;
 #[cfg(feature = "guest_debug")]
 mod gdb;
+mod guest_agent;
+pub mod hooks;
 #[cfg(feature = "igvm")]
 mod igvm;
 pub mod interrupt;
 pub mod landlock;
+pub mod libvirt;
 pub mod memory_manager;
 pub mod migration;
+mod numa;
+pub mod ovf;
 mod pci_segment;
+mod resources;
 pub mod seccomp_filters;
 mod serial_manager;
 mod sigwinch_listener;
+pub mod systemd;
+mod vfio_group;
 pub mod vm;
 pub mod vm_config;
+mod xml;
+
+pub use crate::builder::{VmmBuilder, VmmHandle};
+#[cfg(feature = "guest_debug")]
+pub use crate::gdb::GdbListener;
 
 type GuestMemoryMmap = vm_memory::GuestMemoryMmap<AtomicBitmap>;
 type GuestRegionMmap = vm_memory::GuestRegionMmap<AtomicBitmap>;
@@ -143,6 +172,10 @@ pub enum Error {
     #[error("Error spawning `event-monitor` thread")]
     EventMonitorThreadSpawn(#[source] io::Error),
 
+    /// Cannot create hooks thread
+    #[error("Error spawning hooks thread")]
+    HooksThreadSpawn(#[source] io::Error),
+
     /// Cannot handle the VM STDIN stream
     #[error("Error handling VM stdin")]
     Stdin(#[source] VmError),
@@ -175,6 +208,10 @@ pub enum Error {
     #[error("Error activating virtio devices")]
     ActivateVirtioDevices(#[source] VmError),
 
+    /// Error plugging back the devices being reset
+    #[error("Error plugging back the devices being reset")]
+    CompleteDeviceResets(#[source] VmError),
+
     /// Error creating API server
     // TODO We should add #[source] here once the type implements Error.
     // Then we also can remove the `: {}` to align with the other errors.
@@ -212,6 +249,34 @@ pub enum Error {
     /// Cannot apply landlock based sandboxing
     #[error("Error applying landlock")]
     ApplyLandlock(#[source] LandlockError),
+
+    /// Error handling the systemd watchdog timer
+    #[error("Error handling the watchdog timer")]
+    WatchdogTimer(#[source] io::Error),
+
+    /// Error handling the rate-limit schedules timer
+    #[error("Error handling the rate-limit schedules timer")]
+    RateLimitScheduleTimer(#[source] io::Error),
+
+    /// Cannot restart the VM after a crash
+    #[error("Error restarting VM after a crash")]
+    VmRestart(#[source] VmError),
+
+    /// Error handling the restart backoff timer
+    #[error("Error handling the restart backoff timer")]
+    RestartTimer(#[source] io::Error),
+
+    /// Error handling the balloon pressure timer
+    #[error("Error handling the balloon pressure timer")]
+    BalloonPressureTimer(#[source] io::Error),
+
+    /// Error handling the disk paths timer
+    #[error("Error handling the disk paths timer")]
+    DiskPathsTimer(#[source] io::Error),
+
+    /// Cannot spawn the crash dump thread
+    #[error("Error spawning the crash dump thread")]
+    CrashDumpThreadSpawn(#[source] io::Error),
 }
 pub type Result<T> = result::Result<T, Error>;
 
@@ -223,18 +288,40 @@ pub enum EpollDispatch {
     Api = 2,
     ActivateVirtioDevices = 3,
     Debug = 4,
+    DeviceReset = 5,
+    Watchdog = 6,
+    RateLimitSchedule = 7,
+    Crash = 8,
+    Restart = 9,
+    Vms = 10,
+    BalloonPressure = 11,
+    DiskPaths = 12,
+    CrashDump = 13,
     Unknown,
 }
 
+// The events of the VMs of the multi-VM mode carry the slot of the VM in
+// their upper half.
+const EPOLL_DISPATCH_MASK: u64 = 0xffff_ffff;
+
 impl From<u64> for EpollDispatch {
     fn from(v: u64) -> Self {
         use EpollDispatch::*;
-        match v {
+        match v & EPOLL_DISPATCH_MASK {
             0 => Exit,
             1 => Reset,
             2 => Api,
             3 => ActivateVirtioDevices,
             4 => Debug,
+            5 => DeviceReset,
+            6 => Watchdog,
+            7 => RateLimitSchedule,
+            8 => Crash,
+            9 => Restart,
+            10 => Vms,
+            11 => BalloonPressure,
+            12 => DiskPaths,
+            13 => CrashDump,
             _ => Unknown,
         }
     }
@@ -352,6 +439,23 @@ impl EpollContext {
         Ok(())
     }
 
+    pub fn add_vm_event<T>(&mut self, fd: &T, slot: u64) -> result::Result<(), io::Error>
+    where
+        T: AsRawFd,
+    {
+        epoll::ctl(
+            self.epoll_file.as_raw_fd(),
+            epoll::ControlOptions::EPOLL_CTL_ADD,
+            fd.as_raw_fd(),
+            epoll::Event::new(
+                epoll::Events::EPOLLIN,
+                EpollDispatch::Vms as u64 | slot << 32,
+            ),
+        )?;
+
+        Ok(())
+    }
+
     #[cfg(fuzzing)]
     pub fn add_event_custom<T>(
         &mut self,
@@ -399,6 +503,12 @@ impl Serialize for PciDeviceInfo {
     }
 }
 
+#[derive(Serialize)]
+pub struct PciSegmentInfo {
+    pub id: u16,
+    pub iommu: bool,
+}
+
 pub fn feature_list() -> Vec<String> {
     vec![
         #[cfg(feature = "dbus_api")]
@@ -495,7 +605,7 @@ pub fn start_vmm_thread(
     api_event: EventFd,
     api_sender: Sender<ApiRequest>,
     api_receiver: Receiver<ApiRequest>,
-    #[cfg(feature = "guest_debug")] debug_path: Option<PathBuf>,
+    #[cfg(feature = "guest_debug")] debug_listener: Option<GdbListener>,
     #[cfg(feature = "guest_debug")] debug_event: EventFd,
     #[cfg(feature = "guest_debug")] vm_debug_event: EventFd,
     exit_event: EventFd,
@@ -506,6 +616,8 @@ pub fn start_vmm_thread(
     #[cfg(feature = "guest_debug")]
     let gdb_hw_breakpoints = hypervisor.get_guest_debug_hw_bps();
     #[cfg(feature = "guest_debug")]
+    let gdb_hw_watchpoints = hypervisor.get_guest_debug_hw_wps();
+    #[cfg(feature = "guest_debug")]
     let (gdb_sender, gdb_receiver) = std::sync::mpsc::channel();
     #[cfg(feature = "guest_debug")]
     let gdb_debug_event = debug_event.try_clone().map_err(Error::EventFdClone)?;
@@ -520,6 +632,7 @@ pub fn start_vmm_thread(
         .map_err(Error::CreateSeccompFilter)?;
 
     let vmm_seccomp_action = seccomp_action.clone();
+    let api_socket_path = http_path.clone();
     let thread = {
         let exit_event = exit_event.try_clone().map_err(Error::EventFdClone)?;
         thread::Builder::new()
@@ -541,6 +654,7 @@ pub fn start_vmm_thread(
                     hypervisor,
                     exit_event,
                 )?;
+                vmm.api_socket_path = api_socket_path;
 
                 vmm.setup_signal_handler(landlock_enable)?;
 
@@ -596,16 +710,17 @@ pub fn start_vmm_thread(
     };
 
     #[cfg(feature = "guest_debug")]
-    if let Some(debug_path) = debug_path {
+    if let Some(debug_listener) = debug_listener {
         let target = gdb::GdbStub::new(
             gdb_sender,
             gdb_debug_event,
             gdb_vm_debug_event,
             gdb_hw_breakpoints,
+            gdb_hw_watchpoints,
         );
         thread::Builder::new()
             .name("gdb".to_owned())
-            .spawn(move || gdb::gdb_thread(target, &debug_path))
+            .spawn(move || gdb::gdb_thread(target, &debug_listener))
             .map_err(Error::GdbThreadSpawn)?;
     }
 
@@ -651,6 +766,7 @@ pub struct Vmm {
     epoll: EpollContext,
     exit_evt: EventFd,
     reset_evt: EventFd,
+    crash_evt: EventFd,
     api_evt: EventFd,
     #[cfg(feature = "guest_debug")]
     debug_evt: EventFd,
@@ -662,11 +778,46 @@ pub struct Vmm {
     seccomp_action: SeccompAction,
     hypervisor: Arc<dyn hypervisor::Hypervisor>,
     activate_evt: EventFd,
+    device_reset_evt: EventFd,
     signals: Option<Handle>,
     threads: Vec<thread::JoinHandle<()>>,
     original_termios_opt: Arc<Mutex<Option<termios>>>,
     console_resize_pipe: Option<Arc<File>>,
     console_info: Option<ConsoleInfo>,
+    notifier: Option<systemd::Notifier>,
+    watchdog_timer: Option<TimerFd>,
+    rate_limit_schedule_timer: TimerFd,
+    api_socket_path: Option<String>,
+    // Consecutive restarts of the VM after crashes, and the timer delaying
+    // the pending one, if any.
+    restart_attempts: u32,
+    restart_timer: TimerFd,
+    restart_pending: bool,
+    // Cause of the last exit triggered by the guest
+    last_exit_reason: Option<ExitReason>,
+    balloon_pressure_timer: TimerFd,
+    disk_paths_timer: TimerFd,
+    // Dump of the guest memory taken after a guest panic, signaling its
+    // completion through the event.
+    crash_dump_evt: EventFd,
+    crash_dump_thread: Option<thread::JoinHandle<()>>,
+    // VMs managed through the /api/v1/vms/{id}/ endpoints, each by a Vmm of
+    // its own sharing the event loop of this one.
+    vms: BTreeMap<String, Vmm>,
+    vm_slot: Option<u64>,
+    next_vm_slot: u64,
+}
+
+// Arms the timer to expire at the start of the next minute of the system
+// clock, which the rate-limit schedules are evaluated against.
+fn arm_rate_limit_schedule_timer(timer: &mut TimerFd) -> io::Result<()> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let elapsed = Duration::new(now.as_secs() % 60, now.subsec_nanos());
+    timer
+        .reset(Duration::from_secs(60) - elapsed, None)
+        .map_err(io::Error::from)
 }
 
 impl Vmm {
@@ -776,7 +927,9 @@ impl Vmm {
     ) -> Result<Self> {
         let mut epoll = EpollContext::new().map_err(Error::Epoll)?;
         let reset_evt = EventFd::new(EFD_NONBLOCK).map_err(Error::EventFdCreate)?;
+        let crash_evt = EventFd::new(EFD_NONBLOCK).map_err(Error::EventFdCreate)?;
         let activate_evt = EventFd::new(EFD_NONBLOCK).map_err(Error::EventFdCreate)?;
+        let device_reset_evt = EventFd::new(EFD_NONBLOCK).map_err(Error::EventFdCreate)?;
 
         epoll
             .add_event(&exit_evt, EpollDispatch::Exit)
@@ -786,10 +939,18 @@ impl Vmm {
             .add_event(&reset_evt, EpollDispatch::Reset)
             .map_err(Error::Epoll)?;
 
+        epoll
+            .add_event(&crash_evt, EpollDispatch::Crash)
+            .map_err(Error::Epoll)?;
+
         epoll
             .add_event(&activate_evt, EpollDispatch::ActivateVirtioDevices)
             .map_err(Error::Epoll)?;
 
+        epoll
+            .add_event(&device_reset_evt, EpollDispatch::DeviceReset)
+            .map_err(Error::Epoll)?;
+
         epoll
             .add_event(&api_evt, EpollDispatch::Api)
             .map_err(Error::Epoll)?;
@@ -799,10 +960,69 @@ impl Vmm {
             .add_event(&debug_evt, EpollDispatch::Debug)
             .map_err(Error::Epoll)?;
 
+        // Keep-alive pings are sent from the VMM thread, so that the service
+        // manager notices whenever it stops handling requests.
+        let notifier = systemd::Notifier::from_env();
+        let watchdog_timer = match notifier.as_ref().and_then(|_| systemd::watchdog_interval()) {
+            Some(interval) => {
+                let mut timer = TimerFd::new().map_err(|e| Error::WatchdogTimer(e.into()))?;
+                timer
+                    .reset(interval / 2, Some(interval / 2))
+                    .map_err(|e| Error::WatchdogTimer(e.into()))?;
+                epoll
+                    .add_event(&timer, EpollDispatch::Watchdog)
+                    .map_err(Error::Epoll)?;
+                Some(timer)
+            }
+            None => None,
+        };
+
+        let mut rate_limit_schedule_timer =
+            TimerFd::new().map_err(|e| Error::RateLimitScheduleTimer(e.into()))?;
+        arm_rate_limit_schedule_timer(&mut rate_limit_schedule_timer)
+            .map_err(Error::RateLimitScheduleTimer)?;
+        epoll
+            .add_event(&rate_limit_schedule_timer, EpollDispatch::RateLimitSchedule)
+            .map_err(Error::Epoll)?;
+
+        let restart_timer = TimerFd::new().map_err(|e| Error::RestartTimer(e.into()))?;
+        epoll
+            .add_event(&restart_timer, EpollDispatch::Restart)
+            .map_err(Error::Epoll)?;
+
+        let mut balloon_pressure_timer =
+            TimerFd::new().map_err(|e| Error::BalloonPressureTimer(e.into()))?;
+        balloon_pressure_timer
+            .reset(
+                balloon_pressure::ADJUST_INTERVAL,
+                Some(balloon_pressure::ADJUST_INTERVAL),
+            )
+            .map_err(|e| Error::BalloonPressureTimer(e.into()))?;
+        epoll
+            .add_event(&balloon_pressure_timer, EpollDispatch::BalloonPressure)
+            .map_err(Error::Epoll)?;
+
+        let mut disk_paths_timer = TimerFd::new().map_err(|e| Error::DiskPathsTimer(e.into()))?;
+        disk_paths_timer
+            .reset(
+                block::multipath::CHECK_INTERVAL,
+                Some(block::multipath::CHECK_INTERVAL),
+            )
+            .map_err(|e| Error::DiskPathsTimer(e.into()))?;
+        epoll
+            .add_event(&disk_paths_timer, EpollDispatch::DiskPaths)
+            .map_err(Error::Epoll)?;
+
+        let crash_dump_evt = EventFd::new(EFD_NONBLOCK).map_err(Error::EventFdCreate)?;
+        epoll
+            .add_event(&crash_dump_evt, EpollDispatch::CrashDump)
+            .map_err(Error::Epoll)?;
+
         Ok(Vmm {
             epoll,
             exit_evt,
             reset_evt,
+            crash_evt,
             api_evt,
             #[cfg(feature = "guest_debug")]
             debug_evt,
@@ -814,14 +1034,200 @@ impl Vmm {
             seccomp_action,
             hypervisor,
             activate_evt,
+            device_reset_evt,
             signals: None,
             threads: vec![],
             original_termios_opt: Arc::new(Mutex::new(None)),
             console_resize_pipe: None,
             console_info: None,
+            notifier,
+            watchdog_timer,
+            rate_limit_schedule_timer,
+            api_socket_path: None,
+            restart_attempts: 0,
+            restart_timer,
+            restart_pending: false,
+            last_exit_reason: None,
+            balloon_pressure_timer,
+            disk_paths_timer,
+            crash_dump_evt,
+            crash_dump_thread: None,
+            vms: BTreeMap::new(),
+            vm_slot: None,
+            next_vm_slot: 0,
         })
     }
 
+    fn new_vm_vmm(&mut self) -> Result<Vmm> {
+        let slot = self.next_vm_slot;
+        self.next_vm_slot += 1;
+
+        let mut vmm = Vmm::new(
+            self.version.clone(),
+            EventFd::new(EFD_NONBLOCK).map_err(Error::EventFdCreate)?,
+            #[cfg(feature = "guest_debug")]
+            EventFd::new(EFD_NONBLOCK).map_err(Error::EventFdCreate)?,
+            #[cfg(feature = "guest_debug")]
+            EventFd::new(EFD_NONBLOCK).map_err(Error::EventFdCreate)?,
+            self.seccomp_action.clone(),
+            self.hypervisor.clone(),
+            EventFd::new(EFD_NONBLOCK).map_err(Error::EventFdCreate)?,
+        )?;
+        // Only this VMM reports to the service manager.
+        vmm.notifier = None;
+        vmm.watchdog_timer = None;
+        vmm.vm_slot = Some(slot);
+
+        self.epoll
+            .add_vm_event(&vmm.epoll, slot)
+            .map_err(Error::Epoll)?;
+
+        Ok(vmm)
+    }
+
+    // Handles the pending events of a VM of the multi-VM mode.
+    fn process_vm_events(&mut self) -> Result<()> {
+        const EPOLL_EVENTS_LEN: usize = 100;
+
+        let mut events = vec![epoll::Event::new(epoll::Events::empty(), 0); EPOLL_EVENTS_LEN];
+        let num_events = match epoll::wait(self.epoll.as_raw_fd(), 0, &mut events[..]) {
+            Ok(res) => res,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => 0,
+            Err(e) => return Err(Error::Epoll(e)),
+        };
+
+        for event in events.iter().take(num_events) {
+            // A VM stopping is deleted, as a VMM exits when its VM stops.
+            if self.handle_vm_event(event.data.into())? {
+                self.vm_delete().map_err(Error::VmmShutdown)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_vms_event(&mut self, slot: u64) {
+        let Some((id, vmm)) = self
+            .vms
+            .iter_mut()
+            .find(|(_, vmm)| vmm.vm_slot == Some(slot))
+        else {
+            return;
+        };
+
+        if let Err(e) = vmm.process_vm_events() {
+            error!("Error handling the events of VM {}: {}", id, e);
+            if let Err(e) = vmm.vm_delete() {
+                error!("Error deleting VM {}: {}", id, e);
+            }
+        }
+        if vmm.vm_config.is_none() {
+            let id = id.clone();
+            self.vms.remove(&id);
+        }
+    }
+
+    // Applies the restart policy to the crashed guest, returning whether the
+    // VMM should exit.
+    fn vm_crashed(&mut self) -> result::Result<bool, VmError> {
+        // Crashes signaled while the crashed VM was being shut down
+        if self.vm.is_none() {
+            return Ok(false);
+        }
+
+        let Some(restart_policy) = self
+            .vm_config
+            .as_ref()
+            .and_then(|config| config.lock().unwrap().restart_policy)
+        else {
+            // Without a restart policy, triple faults and watchdog expiries
+            // reset the guest, as they always did.
+            self.vm_reboot()?;
+            return Ok(false);
+        };
+
+        warn!("Guest crashed");
+        event!("vm", "crashed");
+
+        if restart_policy.policy == RestartPolicy::Never {
+            return Ok(true);
+        }
+        if self.restart_attempts >= restart_policy.max {
+            error!(
+                "Guest crashed after {} consecutive restarts, giving up",
+                self.restart_attempts
+            );
+            event!("vm", "restarts-exhausted");
+            return Ok(true);
+        }
+
+        self.restart_attempts += 1;
+        let delay = restart_policy
+            .backoff
+            .saturating_mul(2u64.saturating_pow(self.restart_attempts - 1));
+        info!(
+            "Restarting guest in {}ms, attempt {}/{}",
+            delay, self.restart_attempts, restart_policy.max
+        );
+        event!(
+            "vm",
+            "restarting",
+            "attempt",
+            self.restart_attempts.to_string(),
+            "delay",
+            delay.to_string()
+        );
+
+        // The crashed VM is stopped right away, its configuration being kept
+        // to boot it again.
+        if let Some(mut vm) = self.vm.take() {
+            vm.shutdown()?;
+        }
+        let _ = self.console_info.take();
+        if self.crash_evt.read().is_ok() {
+            warn!("Spurious second crash event received. Ignoring.");
+        }
+
+        self.restart_pending = true;
+        if delay == 0 {
+            self.vm_restart()?;
+        } else {
+            self.restart_timer
+                .reset(Duration::from_millis(delay), None)
+                .map_err(|e| VmError::RestartTimer(e.into()))?;
+        }
+
+        Ok(false)
+    }
+
+    fn vm_restart(&mut self) -> result::Result<(), VmError> {
+        // The restart may have been cancelled, or superseded by the VM being
+        // booted or deleted, while it was pending.
+        if !std::mem::take(&mut self.restart_pending)
+            || self.vm.is_some()
+            || self.vm_config.is_none()
+        {
+            return Ok(());
+        }
+
+        self.vm_boot()?;
+        event!(
+            "vm",
+            "restarted",
+            "attempt",
+            self.restart_attempts.to_string()
+        );
+
+        Ok(())
+    }
+
+    fn cancel_restart(&mut self) {
+        self.restart_pending = false;
+        if let Err(e) = self.restart_timer.clear() {
+            warn!("Error disarming the restart timer: {}", e);
+        }
+    }
+
     fn vm_receive_config<T>(
         &mut self,
         req: &Request,
@@ -849,6 +1255,30 @@ impl Vmm {
             &vm_migration_config.common_cpuid,
         )?;
 
+        #[cfg(feature = "sev_snp")]
+        if vm_migration_config
+            .vm_config
+            .lock()
+            .unwrap()
+            .is_sev_snp_enabled()
+        {
+            return Err(MigratableError::MigrateReceive(anyhow!(
+                "Live Migration is not supported when SEV-SNP is enabled"
+            )));
+        }
+
+        if let Some(resources) = vm_migration_config
+            .vm_config
+            .lock()
+            .unwrap()
+            .resources
+            .as_ref()
+        {
+            resources::join_cgroup(resources).map_err(|e| {
+                MigratableError::MigrateReceive(anyhow!("Error joining the cgroup: {:?}", e))
+            })?;
+        }
+
         let config = vm_migration_config.vm_config.clone();
         self.vm_config = Some(vm_migration_config.vm_config);
         self.console_info = Some(pre_create_console_devices(self).map_err(|e| {
@@ -876,6 +1306,8 @@ impl Vmm {
             false,
             #[cfg(feature = "sev_snp")]
             config.lock().unwrap().memory.total_size(),
+            #[cfg(target_arch = "x86_64")]
+            config.lock().unwrap().cpus.has_wide_apic_ids(),
         )
         .map_err(|e| {
             MigratableError::MigrateReceive(anyhow!(
@@ -936,6 +1368,9 @@ impl Vmm {
         let reset_evt = self.reset_evt.try_clone().map_err(|e| {
             MigratableError::MigrateReceive(anyhow!("Error cloning reset EventFd: {}", e))
         })?;
+        let crash_evt = self.crash_evt.try_clone().map_err(|e| {
+            MigratableError::MigrateReceive(anyhow!("Error cloning crash EventFd: {}", e))
+        })?;
         #[cfg(feature = "guest_debug")]
         let debug_evt = self.vm_debug_evt.try_clone().map_err(|e| {
             MigratableError::MigrateReceive(anyhow!("Error cloning debug EventFd: {}", e))
@@ -943,6 +1378,9 @@ impl Vmm {
         let activate_evt = self.activate_evt.try_clone().map_err(|e| {
             MigratableError::MigrateReceive(anyhow!("Error cloning activate EventFd: {}", e))
         })?;
+        let device_reset_evt = self.device_reset_evt.try_clone().map_err(|e| {
+            MigratableError::MigrateReceive(anyhow!("Error cloning device reset EventFd: {}", e))
+        })?;
 
         #[cfg(not(target_arch = "riscv64"))]
         let timestamp = Instant::now();
@@ -953,11 +1391,13 @@ impl Vmm {
             hypervisor_vm,
             exit_evt,
             reset_evt,
+            crash_evt,
             #[cfg(feature = "guest_debug")]
             debug_evt,
             &self.seccomp_action,
             self.hypervisor.clone(),
             activate_evt,
+            device_reset_evt,
             #[cfg(not(target_arch = "riscv64"))]
             timestamp,
             self.console_info.clone(),
@@ -1134,6 +1574,8 @@ impl Vmm {
                     sgx_epc_sections: None,
                     phys_bits,
                     kvm_hyperv: vm_config.lock().unwrap().cpus.kvm_hyperv,
+                    vmbus: vm_config.lock().unwrap().is_vmbus_enabled(),
+                    msi_ext_dest_id: vm_config.lock().unwrap().cpus.has_wide_apic_ids(),
                     #[cfg(feature = "tdx")]
                     tdx: false,
                     amx,
@@ -1273,6 +1715,8 @@ impl Vmm {
                     sgx_epc_sections: None,
                     phys_bits,
                     kvm_hyperv: vm_config.cpus.kvm_hyperv,
+                    vmbus: vm_config.is_vmbus_enabled(),
+                    msi_ext_dest_id: vm_config.cpus.has_wide_apic_ids(),
                     #[cfg(feature = "tdx")]
                     tdx: false,
                     amx: vm_config.cpus.features.amx,
@@ -1295,6 +1739,7 @@ impl Vmm {
         source_url: &str,
         vm_config: Arc<Mutex<VmConfig>>,
         prefault: bool,
+        cow_memory: bool,
     ) -> std::result::Result<(), VmError> {
         let snapshot = recv_vm_state(source_url).map_err(VmError::Restore)?;
         #[cfg(all(feature = "kvm", target_arch = "x86_64"))]
@@ -1304,6 +1749,10 @@ impl Vmm {
         self.vm_check_cpuid_compatibility(&vm_config, &vm_snapshot.common_cpuid)
             .map_err(VmError::Restore)?;
 
+        if let Some(resources) = vm_config.lock().unwrap().resources.as_ref() {
+            resources::join_cgroup(resources).map_err(VmError::JoinCgroup)?;
+        }
+
         self.vm_config = Some(Arc::clone(&vm_config));
 
         // Always re-populate the 'console_info' based on the new 'vm_config'
@@ -1312,6 +1761,7 @@ impl Vmm {
 
         let exit_evt = self.exit_evt.try_clone().map_err(VmError::EventFdClone)?;
         let reset_evt = self.reset_evt.try_clone().map_err(VmError::EventFdClone)?;
+        let crash_evt = self.crash_evt.try_clone().map_err(VmError::EventFdClone)?;
         #[cfg(feature = "guest_debug")]
         let debug_evt = self
             .vm_debug_evt
@@ -1321,22 +1771,29 @@ impl Vmm {
             .activate_evt
             .try_clone()
             .map_err(VmError::EventFdClone)?;
+        let device_reset_evt = self
+            .device_reset_evt
+            .try_clone()
+            .map_err(VmError::EventFdClone)?;
 
         let vm = Vm::new(
             vm_config,
             exit_evt,
             reset_evt,
+            crash_evt,
             #[cfg(feature = "guest_debug")]
             debug_evt,
             &self.seccomp_action,
             self.hypervisor.clone(),
             activate_evt,
+            device_reset_evt,
             self.console_info.clone(),
             self.console_resize_pipe.clone(),
             Arc::clone(&self.original_termios_opt),
             Some(snapshot),
             Some(source_url),
             Some(prefault),
+            cow_memory,
         )?;
         self.vm = Some(vm);
 
@@ -1360,6 +1817,205 @@ impl Vmm {
         }
     }
 
+    // Places the threads of the VMM into the children of the cgroup of the VM,
+    // if requested.
+    fn place_threads(&self) {
+        let Some(vm_config) = self.vm_config.as_ref() else {
+            return;
+        };
+        let vm_config = vm_config.lock().unwrap();
+        let Some(cgroup) = vm_config
+            .resources
+            .as_ref()
+            .filter(|resources| resources.threads)
+            .and_then(|resources| resources.cgroup.as_ref())
+        else {
+            return;
+        };
+
+        if let Err(e) = resources::place_threads(cgroup) {
+            warn!("Error placing the threads into the cgroup: {}", e);
+        }
+    }
+
+    // Records the cause of the exit the guest triggered, as reported by the
+    // device or vCPU signaling it, or the given fallback otherwise.
+    fn record_exit_reason(&mut self, fallback: Option<ExitReason>) {
+        let Some(reason) = self
+            .vm
+            .as_ref()
+            .and_then(|vm| vm.take_exit_reason())
+            .or(fallback)
+        else {
+            return;
+        };
+
+        info!("Guest exit reason: {}", reason);
+        event!("vm", "guest-exit", "reason", reason.to_string());
+        self.last_exit_reason = Some(reason);
+    }
+
+    // Pauses the panicked guest and dumps its memory from a thread of its
+    // own, returning whether a dump was started.
+    fn start_crash_dump(&mut self) -> Result<bool> {
+        let Some(dir) = self.vm_config.as_ref().and_then(|config| {
+            config
+                .lock()
+                .unwrap()
+                .crash_dump
+                .as_ref()
+                .map(|c| c.path.clone())
+        }) else {
+            return Ok(false);
+        };
+        if self.crash_dump_thread.is_some() {
+            warn!("Guest panicked while its memory was being dumped. Ignoring.");
+            return Ok(true);
+        }
+        let Some(ref mut vm) = self.vm else {
+            return Ok(false);
+        };
+
+        if let Err(e) = vm.pause() {
+            error!("Error pausing the VM for the crash dump: {:?}", e);
+            return Ok(false);
+        }
+        let memory = vm.guest_memory();
+        let path = crash_dump::dump_path(&dir);
+        let crash_dump_evt = self
+            .crash_dump_evt
+            .try_clone()
+            .map_err(Error::EventFdClone)?;
+
+        info!("Dumping the guest memory to {}", path.display());
+        let thread = thread::Builder::new()
+            .name("crash_dump".to_string())
+            .spawn(move || {
+                match crash_dump::dump_guest_memory(&memory.memory(), &path) {
+                    Ok(()) => {
+                        info!("Guest memory dumped to {}", path.display());
+                        event!("vm", "crash-dumped", "path", path.display().to_string());
+                    }
+                    Err(e) => error!("Error dumping the guest memory: {:?}", e),
+                }
+                if let Err(e) = crash_dump_evt.write(1) {
+                    error!("Error signaling the end of the crash dump: {:?}", e);
+                }
+            })
+            .map_err(Error::CrashDumpThreadSpawn)?;
+        self.crash_dump_thread = Some(thread);
+
+        Ok(true)
+    }
+
+    // Handles the events of the VM, returning whether the VMM should exit
+    // as the VM stopped.
+    fn handle_vm_event(&mut self, dispatch_event: EpollDispatch) -> Result<bool> {
+        match dispatch_event {
+            EpollDispatch::Exit => {
+                info!("VM exit event");
+                // Consume the event.
+                self.exit_evt.read().map_err(Error::EventFdRead)?;
+                self.record_exit_reason(None);
+                return Ok(true);
+            }
+            EpollDispatch::Reset => {
+                info!("VM reset event");
+                // Consume the event.
+                self.reset_evt.read().map_err(Error::EventFdRead)?;
+                // The i8042 and CMOS resets don't report their cause.
+                self.record_exit_reason(Some(ExitReason::Reset));
+                self.vm_reboot().map_err(Error::VmReboot)?;
+            }
+            EpollDispatch::Crash => {
+                info!("VM crash event");
+                // Consume the event.
+                self.crash_evt.read().map_err(Error::EventFdRead)?;
+                self.record_exit_reason(None);
+                // The restart policy applies once the guest memory is dumped.
+                if self.last_exit_reason == Some(ExitReason::Panic) && self.start_crash_dump()? {
+                    return Ok(false);
+                }
+                return self.vm_crashed().map_err(Error::VmRestart);
+            }
+            EpollDispatch::CrashDump => {
+                self.crash_dump_evt.read().map_err(Error::EventFdRead)?;
+                if let Some(thread) = self.crash_dump_thread.take() {
+                    if thread.join().is_err() {
+                        error!("Crash dump thread panicked");
+                    }
+                }
+                let restart_policy = self
+                    .vm_config
+                    .as_ref()
+                    .and_then(|config| config.lock().unwrap().restart_policy);
+                if restart_policy.is_some() {
+                    return self.vm_crashed().map_err(Error::VmRestart);
+                }
+                // Without a restart policy, the panicked guest is left running
+                // as it was before the dump.
+                if let Err(e) = self.vm_resume() {
+                    warn!("Error resuming the VM after the crash dump: {:?}", e);
+                }
+            }
+            EpollDispatch::Restart => {
+                self.restart_timer
+                    .wait()
+                    .map_err(|e| Error::RestartTimer(e.into()))?;
+                self.vm_restart().map_err(Error::VmRestart)?;
+            }
+            EpollDispatch::ActivateVirtioDevices => {
+                if let Some(ref vm) = self.vm {
+                    let count = self.activate_evt.read().map_err(Error::EventFdRead)?;
+                    info!(
+                        "Trying to activate pending virtio devices: count = {}",
+                        count
+                    );
+                    vm.activate_virtio_devices()
+                        .map_err(Error::ActivateVirtioDevices)?;
+                }
+            }
+            EpollDispatch::DeviceReset => {
+                self.device_reset_evt.read().map_err(Error::EventFdRead)?;
+                if let Some(ref vm) = self.vm {
+                    vm.complete_device_resets()
+                        .map_err(Error::CompleteDeviceResets)?;
+                }
+            }
+            EpollDispatch::RateLimitSchedule => {
+                self.rate_limit_schedule_timer
+                    .wait()
+                    .map_err(|e| Error::RateLimitScheduleTimer(e.into()))?;
+                arm_rate_limit_schedule_timer(&mut self.rate_limit_schedule_timer)
+                    .map_err(Error::RateLimitScheduleTimer)?;
+                if let Some(ref vm) = self.vm {
+                    vm.update_rate_limit_schedules();
+                }
+            }
+            EpollDispatch::BalloonPressure => {
+                self.balloon_pressure_timer
+                    .wait()
+                    .map_err(|e| Error::BalloonPressureTimer(e.into()))?;
+                if let Some(ref mut vm) = self.vm {
+                    if let Err(e) = vm.adjust_balloon() {
+                        error!("Error resizing the balloon: {:?}", e);
+                    }
+                }
+            }
+            EpollDispatch::DiskPaths => {
+                self.disk_paths_timer
+                    .wait()
+                    .map_err(|e| Error::DiskPathsTimer(e.into()))?;
+                if let Some(ref vm) = self.vm {
+                    vm.check_disk_paths();
+                }
+            }
+            _ => warn!("Unexpected VM event: {:?}", dispatch_event),
+        }
+
+        Ok(false)
+    }
+
     fn control_loop(
         &mut self,
         api_receiver: Rc<Receiver<ApiRequest>>,
@@ -1395,31 +2051,23 @@ impl Vmm {
                         let event = event.data;
                         warn!("Unknown VMM loop event: {}", event);
                     }
-                    EpollDispatch::Exit => {
-                        info!("VM exit event");
-                        // Consume the event.
-                        self.exit_evt.read().map_err(Error::EventFdRead)?;
-                        self.vmm_shutdown().map_err(Error::VmmShutdown)?;
-
-                        break 'outer;
-                    }
-                    EpollDispatch::Reset => {
-                        info!("VM reset event");
-                        // Consume the event.
-                        self.reset_evt.read().map_err(Error::EventFdRead)?;
-                        self.vm_reboot().map_err(Error::VmReboot)?;
-                    }
-                    EpollDispatch::ActivateVirtioDevices => {
-                        if let Some(ref vm) = self.vm {
-                            let count = self.activate_evt.read().map_err(Error::EventFdRead)?;
-                            info!(
-                                "Trying to activate pending virtio devices: count = {}",
-                                count
-                            );
-                            vm.activate_virtio_devices()
-                                .map_err(Error::ActivateVirtioDevices)?;
+                    EpollDispatch::Exit
+                    | EpollDispatch::Reset
+                    | EpollDispatch::Crash
+                    | EpollDispatch::Restart
+                    | EpollDispatch::ActivateVirtioDevices
+                    | EpollDispatch::DeviceReset
+                    | EpollDispatch::RateLimitSchedule
+                    | EpollDispatch::BalloonPressure
+                    | EpollDispatch::DiskPaths
+                    | EpollDispatch::CrashDump => {
+                        if self.handle_vm_event(dispatch_event)? {
+                            self.vmm_shutdown().map_err(Error::VmmShutdown)?;
+
+                            break 'outer;
                         }
                     }
+                    EpollDispatch::Vms => self.handle_vms_event(event.data >> 32),
                     EpollDispatch::Api => {
                         // Consume the events.
                         for _ in 0..self.api_evt.read().map_err(Error::EventFdRead)? {
@@ -1431,6 +2079,14 @@ impl Vmm {
                             }
                         }
                     }
+                    EpollDispatch::Watchdog => {
+                        if let Some(timer) = self.watchdog_timer.as_mut() {
+                            timer.wait().map_err(|e| Error::WatchdogTimer(e.into()))?;
+                        }
+                        if let Some(notifier) = &self.notifier {
+                            notifier.watchdog();
+                        }
+                    }
                     #[cfg(feature = "guest_debug")]
                     EpollDispatch::Debug => {
                         // Consume the events.
@@ -1455,6 +2111,9 @@ impl Vmm {
                     EpollDispatch::Debug => {}
                 }
             }
+
+            // vCPU and I/O threads may have been started by the events.
+            self.place_threads();
         }
 
         // Trigger the termination of the signal_handler thread
@@ -1481,7 +2140,14 @@ impl RequestHandler for Vmm {
         // We only store the passed VM config.
         // The VM will be created when being asked to boot it.
         if self.vm_config.is_none() {
-            self.vm_config = Some(Arc::new(Mutex::new(*config)));
+            let mut config = *config;
+            guest_agent::provision_vsock(&mut config);
+            // Join the cgroup before any of the VM resources is allocated,
+            // so that they are all accounted to it.
+            if let Some(resources) = config.resources.as_ref() {
+                resources::join_cgroup(resources).map_err(VmError::JoinCgroup)?;
+            }
+            self.vm_config = Some(Arc::new(Mutex::new(config)));
             self.console_info =
                 Some(pre_create_console_devices(self).map_err(VmError::CreateConsoleDevices)?);
 
@@ -1523,6 +2189,7 @@ impl RequestHandler for Vmm {
             if self.vm.is_none() {
                 let exit_evt = self.exit_evt.try_clone().map_err(VmError::EventFdClone)?;
                 let reset_evt = self.reset_evt.try_clone().map_err(VmError::EventFdClone)?;
+                let crash_evt = self.crash_evt.try_clone().map_err(VmError::EventFdClone)?;
                 #[cfg(feature = "guest_debug")]
                 let vm_debug_evt = self
                     .vm_debug_evt
@@ -1532,23 +2199,30 @@ impl RequestHandler for Vmm {
                     .activate_evt
                     .try_clone()
                     .map_err(VmError::EventFdClone)?;
+                let device_reset_evt = self
+                    .device_reset_evt
+                    .try_clone()
+                    .map_err(VmError::EventFdClone)?;
 
                 if let Some(ref vm_config) = self.vm_config {
                     let vm = Vm::new(
                         Arc::clone(vm_config),
                         exit_evt,
                         reset_evt,
+                        crash_evt,
                         #[cfg(feature = "guest_debug")]
                         vm_debug_evt,
                         &self.seccomp_action,
                         self.hypervisor.clone(),
                         activate_evt,
+                        device_reset_evt,
                         self.console_info.clone(),
                         self.console_resize_pipe.clone(),
                         Arc::clone(&self.original_termios_opt),
                         None,
                         None,
                         None,
+                        false,
                     )?;
 
                     self.vm = Some(vm);
@@ -1585,7 +2259,11 @@ impl RequestHandler for Vmm {
         }
     }
 
-    fn vm_snapshot(&mut self, destination_url: &str) -> result::Result<(), VmError> {
+    fn vm_snapshot(
+        &mut self,
+        destination_url: &str,
+        signing_key: Option<&Path>,
+    ) -> result::Result<(), VmError> {
         if let Some(ref mut vm) = self.vm {
             // Drain console_info so that FDs are not reused
             let _ = self.console_info.take();
@@ -1594,7 +2272,14 @@ impl RequestHandler for Vmm {
                 .and_then(|snapshot| {
                     vm.send(&snapshot, destination_url)
                         .map_err(VmError::SnapshotSend)
-                })
+                })?;
+
+            if let Some(signing_key) = signing_key {
+                send_snapshot_manifest(destination_url, signing_key)
+                    .map_err(VmError::SnapshotSign)?;
+            }
+
+            Ok(())
         } else {
             Err(VmError::VmNotRunning)
         }
@@ -1612,6 +2297,15 @@ impl RequestHandler for Vmm {
         // Safe to unwrap as we checked it was Some(&str).
         let source_url = source_url.unwrap();
 
+        if let Some(signing_key) = &restore_cfg.signing_key {
+            if let Err(e) = verify_snapshot_manifest(source_url, signing_key) {
+                if !restore_cfg.force {
+                    return Err(VmError::SnapshotVerify(e));
+                }
+                warn!("Restoring snapshot despite failed verification: {:?}", e);
+            }
+        }
+
         let vm_config = Arc::new(Mutex::new(
             recv_vm_config(source_url).map_err(VmError::Restore)?,
         ));
@@ -1633,29 +2327,113 @@ impl RequestHandler for Vmm {
             }
         }
 
-        self.vm_restore(source_url, vm_config, restore_cfg.prefault)
-            .map_err(|vm_restore_err| {
-                error!("VM Restore failed: {:?}", vm_restore_err);
-
-                // Cleanup the VM being created while vm restore
-                if let Err(e) = self.vm_delete() {
-                    return e;
+        // Give each clone of a snapshot its own disk images and TAP interfaces
+        if let (Some(restored_disks), Some(vm_disk_configs)) =
+            (restore_cfg.disk_paths, &mut vm_config.lock().unwrap().disks)
+        {
+            for disk in restored_disks {
+                match vm_disk_configs
+                    .iter_mut()
+                    .find(|d| d.id.as_ref() == Some(&disk.id))
+                {
+                    Some(disk_config) => disk_config.path = Some(disk.path),
+                    None => warn!("Ignoring 'disk_paths' for unknown disk {}", disk.id),
+                }
+            }
+        }
+        if let (Some(restored_taps), Some(vm_net_configs)) =
+            (restore_cfg.net_taps, &mut vm_config.lock().unwrap().net)
+        {
+            for tap in restored_taps {
+                match vm_net_configs
+                    .iter_mut()
+                    .find(|n| n.id.as_ref() == Some(&tap.id) && n.fds.is_none())
+                {
+                    Some(net_config) => net_config.tap = Some(tap.tap),
+                    None => warn!("Ignoring 'net_taps' for net device {}", tap.id),
                 }
+            }
+        }
 
-                vm_restore_err
-            })
+        self.vm_restore(
+            source_url,
+            vm_config,
+            restore_cfg.prefault,
+            restore_cfg.clone,
+        )
+        .map_err(|vm_restore_err| {
+            error!("VM Restore failed: {:?}", vm_restore_err);
+
+            // Cleanup the VM being created while vm restore
+            if let Err(e) = self.vm_delete() {
+                return e;
+            }
+
+            vm_restore_err
+        })
     }
 
-    #[cfg(all(target_arch = "x86_64", feature = "guest_debug"))]
-    fn vm_coredump(&mut self, destination_url: &str) -> result::Result<(), VmError> {
-        if let Some(ref mut vm) = self.vm {
-            vm.coredump(destination_url).map_err(VmError::Coredump)
-        } else {
-            Err(VmError::VmNotRunning)
+    fn vm_hibernate(
+        &mut self,
+        destination_url: &str,
+        signing_key: Option<&Path>,
+    ) -> result::Result<(), VmError> {
+        let vm = self.vm.as_mut().ok_or(VmError::VmNotRunning)?;
+        let was_running = vm.get_state()? == VmState::Running;
+        if was_running {
+            vm.pause().map_err(VmError::Pause)?;
         }
-    }
+        // The TAP interfaces are destroyed with the VMM, and must come back
+        // under the same names, which the host network setup refers to.
+        vm.pin_tap_names();
+
+        let manifest = HibernationManifest {
+            api_socket: self.api_socket_path.clone(),
+        };
+        // The hibernation settings are written before the snapshot gets
+        // signed, so that they are covered by the signature.
+        if let Err(e) = self
+            .vm_snapshot(destination_url, None)
+            .and_then(|_| {
+                send_hibernation_manifest(destination_url, &manifest)
+                    .map_err(VmError::HibernationSend)
+            })
+            .and_then(|_| match signing_key {
+                Some(signing_key) => send_snapshot_manifest(destination_url, signing_key)
+                    .map_err(VmError::SnapshotSign),
+                None => Ok(()),
+            })
+        {
+            if was_running {
+                if let Err(e) = self.vm_resume() {
+                    warn!("Failed resuming the VM after hibernation failure: {e}");
+                }
+            }
+            return Err(e);
+        }
+
+        event!("vm", "hibernated");
+        self.vmm_shutdown()
+    }
+
+    #[cfg(all(target_arch = "x86_64", feature = "guest_debug"))]
+    fn vm_coredump(&mut self, destination_url: &str) -> result::Result<(), VmError> {
+        if let Some(ref mut vm) = self.vm {
+            vm.coredump(destination_url).map_err(VmError::Coredump)
+        } else {
+            Err(VmError::VmNotRunning)
+        }
+    }
 
     fn vm_shutdown(&mut self) -> result::Result<(), VmError> {
+        self.restart_attempts = 0;
+        // A VM waiting to be restarted after a crash is already shut down.
+        if self.vm.is_none() && self.restart_pending {
+            self.cancel_restart();
+            event!("vm", "shutdown");
+            return Ok(());
+        }
+
         let r = if let Some(ref mut vm) = self.vm.take() {
             // Drain console_info so that the FDs are not reused
             let _ = self.console_info.take();
@@ -1673,6 +2451,10 @@ impl RequestHandler for Vmm {
 
     fn vm_reboot(&mut self) -> result::Result<(), VmError> {
         event!("vm", "rebooting");
+        self.restart_attempts = 0;
+        if let Some(notifier) = &self.notifier {
+            notifier.reloading();
+        }
 
         // First we stop the current VM
         let config = if let Some(mut vm) = self.vm.take() {
@@ -1689,6 +2471,7 @@ impl RequestHandler for Vmm {
 
         let exit_evt = self.exit_evt.try_clone().map_err(VmError::EventFdClone)?;
         let reset_evt = self.reset_evt.try_clone().map_err(VmError::EventFdClone)?;
+        let crash_evt = self.crash_evt.try_clone().map_err(VmError::EventFdClone)?;
         #[cfg(feature = "guest_debug")]
         let debug_evt = self
             .vm_debug_evt
@@ -1698,6 +2481,10 @@ impl RequestHandler for Vmm {
             .activate_evt
             .try_clone()
             .map_err(VmError::EventFdClone)?;
+        let device_reset_evt = self
+            .device_reset_evt
+            .try_clone()
+            .map_err(VmError::EventFdClone)?;
 
         // The Linux kernel fires off an i8042 reset after doing the ACPI reset so there may be
         // an event sitting in the shared reset_evt. Without doing this we get very early reboots
@@ -1714,17 +2501,20 @@ impl RequestHandler for Vmm {
             config,
             exit_evt,
             reset_evt,
+            crash_evt,
             #[cfg(feature = "guest_debug")]
             debug_evt,
             &self.seccomp_action,
             self.hypervisor.clone(),
             activate_evt,
+            device_reset_evt,
             self.console_info.clone(),
             self.console_resize_pipe.clone(),
             Arc::clone(&self.original_termios_opt),
             None,
             None,
             None,
+            false,
         )?;
 
         // And we boot it
@@ -1733,6 +2523,9 @@ impl RequestHandler for Vmm {
         self.vm = Some(vm);
 
         event!("vm", "rebooted");
+        if let Some(notifier) = &self.notifier {
+            notifier.ready();
+        }
 
         Ok(())
     }
@@ -1756,11 +2549,22 @@ impl RequestHandler for Vmm {
                     .as_ref()
                     .map(|vm| vm.device_tree().lock().unwrap().clone());
 
+                let disk_queues = self.vm.as_ref().map(|vm| vm.disk_queues());
+                let balloon = self.vm.as_ref().and_then(|vm| vm.balloon_info());
+
+                #[cfg(target_arch = "x86_64")]
+                let sgx_epc = self.vm.as_ref().and_then(|vm| vm.sgx_epc_sections());
+
                 Ok(VmInfoResponse {
                     config: Box::new(config),
                     state,
                     memory_actual_size,
                     device_tree,
+                    last_exit_reason: self.last_exit_reason,
+                    disk_queues,
+                    balloon,
+                    #[cfg(target_arch = "x86_64")]
+                    sgx_epc,
                 })
             }
             None => Err(VmError::VmNotCreated),
@@ -1792,6 +2596,7 @@ impl RequestHandler for Vmm {
         }
 
         self.vm_config = None;
+        self.last_exit_reason = None;
 
         event!("vm", "deleted");
 
@@ -1799,14 +2604,46 @@ impl RequestHandler for Vmm {
     }
 
     fn vmm_shutdown(&mut self) -> result::Result<(), VmError> {
+        if let Some(notifier) = &self.notifier {
+            notifier.stopping();
+        }
+        for (id, mut vmm) in std::mem::take(&mut self.vms) {
+            if let Err(e) = vmm.vm_delete() {
+                error!("Error deleting VM {}: {}", id, e);
+            }
+        }
         self.vm_delete()?;
-        event!("vmm", "shutdown");
+        // The VMs of the multi-VM mode stopping doesn't stop the VMM.
+        if self.vm_slot.is_none() {
+            event!("vmm", "shutdown");
+        }
         Ok(())
     }
 
+    fn vms_request(&mut self, id: &str, request: ApiRequest) -> Result<bool> {
+        let mut vmm = match self.vms.remove(id) {
+            Some(vmm) => vmm,
+            None => self.new_vm_vmm()?,
+        };
+
+        // The VM is only kept while created, so that requests to unknown VMs
+        // fail as they would on a VMM without a VM.
+        let r = request(&mut vmm);
+        if let Ok(true) = r {
+            if let Err(e) = vmm.vm_delete() {
+                error!("Error deleting VM {}: {}", id, e);
+            }
+        }
+        if vmm.vm_config.is_some() {
+            self.vms.insert(id.to_owned(), vmm);
+        }
+
+        r.map(|_| false)
+    }
+
     fn vm_resize(
         &mut self,
-        desired_vcpus: Option<u8>,
+        desired_vcpus: Option<u32>,
         desired_ram: Option<u64>,
         desired_balloon: Option<u64>,
     ) -> result::Result<(), VmError> {
@@ -1942,6 +2779,96 @@ impl RequestHandler for Vmm {
         }
     }
 
+    fn vm_reset_device(&mut self, id: String) -> result::Result<(), VmError> {
+        if let Some(ref mut vm) = self.vm {
+            if let Err(e) = vm.reset_device(id) {
+                error!("Error when resetting device: {:?}", e);
+                Err(e)
+            } else {
+                Ok(())
+            }
+        } else {
+            Err(VmError::VmNotRunning)
+        }
+    }
+
+    fn vm_add_rate_limit_group(
+        &mut self,
+        rate_limit_group_cfg: RateLimiterGroupConfig,
+    ) -> result::Result<(), VmError> {
+        self.vm_config.as_ref().ok_or(VmError::VmNotCreated)?;
+
+        {
+            // Validate the configuration change in a cloned configuration
+            let mut config = self.vm_config.as_ref().unwrap().lock().unwrap().clone();
+            add_to_config(&mut config.rate_limit_groups, rate_limit_group_cfg.clone());
+            config.validate().map_err(VmError::ConfigValidation)?;
+        }
+
+        if let Some(ref mut vm) = self.vm {
+            vm.add_rate_limit_group(rate_limit_group_cfg)
+                .inspect_err(|e| error!("Error when adding the rate limiter group: {:?}", e))
+        } else {
+            let mut config = self.vm_config.as_ref().unwrap().lock().unwrap();
+            add_to_config(&mut config.rate_limit_groups, rate_limit_group_cfg);
+            Ok(())
+        }
+    }
+
+    fn vm_set_rate_limit_group(
+        &mut self,
+        id: String,
+        rate_limit_group: Option<String>,
+    ) -> result::Result<(), VmError> {
+        self.vm_config.as_ref().ok_or(VmError::VmNotCreated)?;
+
+        {
+            // Validate the configuration change in a cloned configuration
+            let mut config = self.vm_config.as_ref().unwrap().lock().unwrap().clone();
+            if !config.set_rate_limit_group(&id, rate_limit_group.clone()) {
+                return Err(VmError::NoDiskWithId(id));
+            }
+            config.validate().map_err(VmError::ConfigValidation)?;
+        }
+
+        if let Some(ref mut vm) = self.vm {
+            vm.set_rate_limit_group(id, rate_limit_group)
+                .inspect_err(|e| error!("Error when changing the rate limiter group: {:?}", e))
+        } else {
+            let mut config = self.vm_config.as_ref().unwrap().lock().unwrap();
+            config.set_rate_limit_group(&id, rate_limit_group);
+            Ok(())
+        }
+    }
+
+    fn vm_set_balloon(
+        &mut self,
+        deflate_on_oom: Option<bool>,
+        free_page_reporting: Option<bool>,
+    ) -> result::Result<(), VmError> {
+        self.vm_config.as_ref().ok_or(VmError::VmNotCreated)?;
+
+        if let Some(ref mut vm) = self.vm {
+            vm.set_balloon(deflate_on_oom, free_page_reporting)
+                .inspect_err(|e| error!("Error when changing the balloon features: {:?}", e))
+        } else {
+            let mut config = self.vm_config.as_ref().unwrap().lock().unwrap();
+            config
+                .set_balloon(deflate_on_oom, free_page_reporting)
+                .ok_or(VmError::NoBalloon)
+                .map(|_| ())
+        }
+    }
+
+    fn vm_resize_disk(&mut self, id: String, desired_size: u64) -> result::Result<(), VmError> {
+        if let Some(ref mut vm) = self.vm {
+            vm.resize_disk(id, desired_size)
+                .inspect_err(|e| error!("Error when resizing the disk: {:?}", e))
+        } else {
+            Err(VmError::VmNotRunning)
+        }
+    }
+
     fn vm_add_disk(&mut self, disk_cfg: DiskConfig) -> result::Result<Option<Vec<u8>>, VmError> {
         self.vm_config.as_ref().ok_or(VmError::VmNotCreated)?;
 
@@ -2072,6 +2999,58 @@ impl RequestHandler for Vmm {
         }
     }
 
+    fn vm_add_pci_segment(&mut self) -> result::Result<Option<Vec<u8>>, VmError> {
+        self.vm_config.as_ref().ok_or(VmError::VmNotCreated)?;
+
+        if let Some(ref mut vm) = self.vm {
+            let info = vm.add_pci_segment().map_err(|e| {
+                error!("Error when adding new PCI segment to the VM: {:?}", e);
+                e
+            })?;
+            serde_json::to_vec(&info)
+                .map(Some)
+                .map_err(VmError::SerializeJson)
+        } else {
+            // Update VmConfig by making the next reserved segment present.
+            let mut config = self.vm_config.as_ref().unwrap().lock().unwrap();
+            let max_pci_segments = config.max_pci_segments();
+            match config.platform.as_mut() {
+                Some(platform) if platform.num_pci_segments < max_pci_segments => {
+                    platform.num_pci_segments += 1;
+                    Ok(None)
+                }
+                _ => Err(VmError::DeviceManager(
+                    device_manager::DeviceManagerError::NoPciSegmentToAdd,
+                )),
+            }
+        }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    fn vm_add_sgx_epc(&mut self, sgx_epc_cfg: SgxEpcConfig) -> result::Result<(), VmError> {
+        self.vm_config.as_ref().ok_or(VmError::VmNotCreated)?;
+
+        {
+            // Validate the configuration change in a cloned configuration
+            let mut config = self.vm_config.as_ref().unwrap().lock().unwrap().clone();
+            add_to_config(&mut config.sgx_epc, sgx_epc_cfg.clone());
+            config.validate().map_err(VmError::ConfigValidation)?;
+        }
+
+        // Guests only enumerate the EPC sections on boot, a section added
+        // to a running VM is mapped when the VM is rebooted.
+        if self.vm.is_some() {
+            info!(
+                "SGX EPC section {} will be available after the VM is rebooted",
+                sgx_epc_cfg.id
+            );
+        }
+
+        let mut config = self.vm_config.as_ref().unwrap().lock().unwrap();
+        add_to_config(&mut config.sgx_epc, sgx_epc_cfg);
+        Ok(())
+    }
+
     fn vm_add_vsock(&mut self, vsock_cfg: VsockConfig) -> result::Result<Option<Vec<u8>>, VmError> {
         self.vm_config.as_ref().ok_or(VmError::VmNotCreated)?;
 
@@ -2103,6 +3082,25 @@ impl RequestHandler for Vmm {
         }
     }
 
+    fn vm_console_log(&mut self) -> result::Result<Option<Vec<u8>>, VmError> {
+        self.vm.as_ref().ok_or(VmError::VmNotRunning)?;
+        let console_info = self.console_info.as_ref().ok_or(VmError::VmNotRunning)?;
+
+        let contents = |tee: &ConsoleTee| {
+            tee.ring
+                .as_ref()
+                .map(|ring| String::from_utf8_lossy(&ring.contents()).into_owned())
+        };
+        let console_log = VmConsoleLogResponse {
+            serial: contents(&console_info.serial_tee),
+            console: contents(&console_info.console_tee),
+        };
+
+        serde_json::to_vec(&console_log)
+            .map(Some)
+            .map_err(VmError::SerializeJson)
+    }
+
     fn vm_counters(&mut self) -> result::Result<Option<Vec<u8>>, VmError> {
         if let Some(ref mut vm) = self.vm {
             let info = vm.counters().map_err(|e| {
@@ -2117,6 +3115,19 @@ impl RequestHandler for Vmm {
         }
     }
 
+    fn vm_export_config(&self) -> result::Result<Option<Vec<u8>>, VmError> {
+        let vm_config = self.vm_config.as_ref().ok_or(VmError::VmNotCreated)?;
+
+        // Validating a copy of the live configuration brings it to the
+        // normalized form a VM created from it would get.
+        let mut config = vm_config.lock().unwrap().clone();
+        config.validate().map_err(VmError::ConfigValidation)?;
+
+        serde_json::to_vec(&config)
+            .map(Some)
+            .map_err(VmError::SerializeJson)
+    }
+
     fn vm_power_button(&mut self) -> result::Result<(), VmError> {
         if let Some(ref mut vm) = self.vm {
             vm.power_button()
@@ -2133,6 +3144,50 @@ impl RequestHandler for Vmm {
         }
     }
 
+    fn vm_refresh_certificates(&mut self) -> result::Result<(), VmError> {
+        if let Some(ref vm) = self.vm {
+            vm.refresh_platform_certificates()
+        } else {
+            Err(VmError::VmNotRunning)
+        }
+    }
+
+    fn vm_guest_command(
+        &mut self,
+        guest_command_data: VmGuestCommandData,
+    ) -> result::Result<Option<Vec<u8>>, VmError> {
+        if let Some(ref vm) = self.vm {
+            let response =
+                vm.guest_command(&guest_command_data.execute, guest_command_data.arguments)?;
+            serde_json::to_vec(&response)
+                .map(Some)
+                .map_err(VmError::SerializeJson)
+        } else {
+            Err(VmError::VmNotRunning)
+        }
+    }
+
+    fn vm_backup(&mut self, backup_data: VmBackupData) -> result::Result<(), VmError> {
+        if let Some(ref mut vm) = self.vm {
+            vm.backup(&backup_data)
+                .inspect_err(|e| error!("Error when switching the disks to overlays: {:?}", e))
+        } else {
+            Err(VmError::VmNotRunning)
+        }
+    }
+
+    fn vm_disk_snapshot(
+        &mut self,
+        disk_snapshot_data: VmDiskSnapshotData,
+    ) -> result::Result<(), VmError> {
+        if let Some(ref mut vm) = self.vm {
+            vm.disk_snapshot(&disk_snapshot_data.id, &disk_snapshot_data.overlay)
+                .inspect_err(|e| error!("Error when switching the disk to an overlay: {:?}", e))
+        } else {
+            Err(VmError::VmNotRunning)
+        }
+    }
+
     fn vm_receive_migration(
         &mut self,
         receive_data_migration: VmReceiveMigrationData,
@@ -2274,6 +3329,23 @@ impl RequestHandler for Vmm {
             send_data_migration.destination_url, send_data_migration.local
         );
 
+        // Guest memory of an SEV-SNP VM is encrypted with a key owned by the
+        // PSP of the source host. Moving it requires a migration agent inside
+        // the guest context, which isn't available yet.
+        #[cfg(feature = "sev_snp")]
+        if self
+            .vm_config
+            .as_ref()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .is_sev_snp_enabled()
+        {
+            return Err(MigratableError::MigrateSend(anyhow!(
+                "Live Migration is not supported when SEV-SNP is enabled"
+            )));
+        }
+
         if !self
             .vm_config
             .as_ref()
@@ -2313,6 +3385,7 @@ impl RequestHandler for Vmm {
 
                 migration_err
             })?;
+            event!("vm", "migrated-out");
 
             // Shutdown the VM after the migration succeeded
             self.exit_evt.write(1).map_err(|e| {
@@ -2337,8 +3410,8 @@ mod unit_tests {
     #[cfg(target_arch = "x86_64")]
     use crate::vm_config::DebugConsoleConfig;
     use crate::vm_config::{
-        ConsoleConfig, ConsoleOutputMode, CpuFeatures, CpusConfig, HotplugMethod, MemoryConfig,
-        PayloadConfig, RngConfig,
+        BalloonConfig, ConsoleConfig, ConsoleOutputMode, CpuFeatures, CpusConfig, HotplugMethod,
+        MemoryConfig, PayloadConfig, PlatformConfig, RngConfig,
     };
 
     fn create_dummy_vmm() -> Vmm {
@@ -2356,7 +3429,7 @@ mod unit_tests {
         .unwrap()
     }
 
-    fn create_dummy_vm_config() -> Box<VmConfig> {
+    pub(crate) fn create_dummy_vm_config() -> Box<VmConfig> {
         Box::new(VmConfig {
             cpus: CpusConfig {
                 boot_vcpus: 1,
@@ -2379,16 +3452,30 @@ mod unit_tests {
                 prefault: false,
                 zones: None,
                 thp: true,
+                thp_collapse: false,
             },
             payload: Some(PayloadConfig {
                 kernel: Some(PathBuf::from("/path/to/kernel")),
+                kernel_fd: None,
                 firmware: None,
+                firmware_fd: None,
                 cmdline: None,
                 initramfs: None,
+                initramfs_fd: None,
+                extra_initramfs: None,
                 #[cfg(feature = "igvm")]
                 igvm: None,
+                #[cfg(feature = "igvm")]
+                igvm_fd: None,
                 #[cfg(feature = "sev_snp")]
                 host_data: None,
+                #[cfg(feature = "sev_snp")]
+                id_block: None,
+                #[cfg(feature = "sev_snp")]
+                id_auth: None,
+                #[cfg(feature = "sev_snp")]
+                snp_certs: None,
+                fallback_firmware: None,
             }),
             rate_limit_groups: None,
             disks: None,
@@ -2405,12 +3492,14 @@ mod unit_tests {
                 mode: ConsoleOutputMode::Null,
                 iommu: false,
                 socket: None,
+                tee: Vec::new(),
             },
             console: ConsoleConfig {
                 file: None,
                 mode: ConsoleOutputMode::Tty,
                 iommu: false,
                 socket: None,
+                tee: Vec::new(),
             },
             #[cfg(target_arch = "x86_64")]
             debug_console: DebugConsoleConfig::default(),
@@ -2431,6 +3520,16 @@ mod unit_tests {
             pci_segments: None,
             platform: None,
             tpm: None,
+            scmi: None,
+            cloud_init: None,
+            imds: None,
+            netns: None,
+            guest_agent: None,
+            resources: None,
+            rtc: None,
+            restart_policy: None,
+            crash_dump: None,
+            io_threads: None,
             preserved_fds: None,
             landlock_enable: false,
             landlock_rules: None,
@@ -2676,6 +3775,38 @@ mod unit_tests {
         );
     }
 
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn test_vmm_vm_cold_add_sgx_epc() {
+        let mut vmm = create_dummy_vmm();
+        let sgx_epc_config = SgxEpcConfig::parse("id=epc0,size=64M").unwrap();
+
+        assert!(matches!(
+            vmm.vm_add_sgx_epc(sgx_epc_config.clone()),
+            Err(VmError::VmNotCreated)
+        ));
+
+        let _ = vmm.vm_create(create_dummy_vm_config());
+        vmm.vm_add_sgx_epc(sgx_epc_config.clone()).unwrap();
+        assert_eq!(
+            vmm.vm_config
+                .as_ref()
+                .unwrap()
+                .lock()
+                .unwrap()
+                .sgx_epc
+                .clone()
+                .unwrap(),
+            vec![sgx_epc_config.clone()]
+        );
+
+        // The section identifier must be unique
+        assert!(matches!(
+            vmm.vm_add_sgx_epc(sgx_epc_config),
+            Err(VmError::ConfigValidation(_))
+        ));
+    }
+
     #[test]
     fn test_vmm_vm_cold_add_net() {
         let mut vmm = create_dummy_vmm();
@@ -2804,4 +3935,362 @@ mod unit_tests {
             vsock_config
         );
     }
+
+    #[test]
+    fn test_vmm_vm_export_config() {
+        let mut vmm = create_dummy_vmm();
+
+        assert!(matches!(vmm.vm_export_config(), Err(VmError::VmNotCreated)));
+
+        let _ = vmm.vm_create(create_dummy_vm_config());
+        let vsock_config = VsockConfig::parse("socket=/tmp/sock,cid=3,iommu=on").unwrap();
+        vmm.vm_add_vsock(vsock_config).unwrap();
+
+        let body = vmm.vm_export_config().unwrap().unwrap();
+        let mut exported: VmConfig = serde_json::from_slice(&body).unwrap();
+        let mut config = vmm.vm_config.as_ref().unwrap().lock().unwrap().clone();
+        config.validate().unwrap();
+        assert!(exported.iommu);
+        assert_eq!(exported, config);
+
+        // The exported configuration is valid as it is.
+        exported.validate().unwrap();
+    }
+
+    #[test]
+    fn test_vmm_vm_refresh_certificates() {
+        let mut vmm = create_dummy_vmm();
+
+        assert!(matches!(
+            vmm.vm_refresh_certificates(),
+            Err(VmError::VmNotRunning)
+        ));
+
+        // The certificates are only served by a running VM.
+        let _ = vmm.vm_create(create_dummy_vm_config());
+        assert!(matches!(
+            vmm.vm_refresh_certificates(),
+            Err(VmError::VmNotRunning)
+        ));
+    }
+
+    #[test]
+    fn test_vmm_vm_set_rate_limit_group() {
+        let mut vmm = create_dummy_vmm();
+
+        assert!(matches!(
+            vmm.vm_set_rate_limit_group("disk0".to_string(), None),
+            Err(VmError::VmNotCreated)
+        ));
+
+        let mut config = create_dummy_vm_config();
+        config.disks = Some(vec![DiskConfig::parse(
+            "path=/path/to_file,id=disk0,bw_size=1000,bw_refill_time=100",
+        )
+        .unwrap()]);
+        config.rate_limit_groups = Some(vec![RateLimiterGroupConfig::parse(
+            "id=group0,bw_size=1000,bw_refill_time=100",
+        )
+        .unwrap()]);
+        let _ = vmm.vm_create(config);
+
+        assert!(matches!(
+            vmm.vm_set_rate_limit_group("disk1".to_string(), Some("group0".to_string())),
+            Err(VmError::NoDiskWithId(_))
+        ));
+        assert!(matches!(
+            vmm.vm_set_rate_limit_group("disk0".to_string(), Some("group1".to_string())),
+            Err(VmError::ConfigValidation(_))
+        ));
+
+        vmm.vm_set_rate_limit_group("disk0".to_string(), Some("group0".to_string()))
+            .unwrap();
+        let disk = vmm
+            .vm_config
+            .as_ref()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .disks
+            .clone()
+            .unwrap()[0]
+            .clone();
+        assert_eq!(disk.rate_limit_group.as_deref(), Some("group0"));
+        assert!(disk.rate_limiter_config.is_none());
+
+        vmm.vm_set_rate_limit_group("disk0".to_string(), None)
+            .unwrap();
+        let disk = vmm
+            .vm_config
+            .as_ref()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .disks
+            .clone()
+            .unwrap()[0]
+            .clone();
+        assert!(disk.rate_limit_group.is_none());
+    }
+
+    #[test]
+    fn test_vmm_vm_hibernate() {
+        let mut vmm = create_dummy_vmm();
+
+        assert!(matches!(
+            vmm.vm_hibernate("file:///tmp", None),
+            Err(VmError::VmNotRunning)
+        ));
+
+        // Only a booted VM can be hibernated.
+        let _ = vmm.vm_create(create_dummy_vm_config());
+        assert!(matches!(
+            vmm.vm_hibernate("file:///tmp", None),
+            Err(VmError::VmNotRunning)
+        ));
+        assert!(vmm.vm_config.is_some());
+    }
+
+    #[test]
+    fn test_epoll_dispatch_vm_slot() {
+        assert_eq!(
+            EpollDispatch::from(EpollDispatch::Exit as u64),
+            EpollDispatch::Exit
+        );
+        assert_eq!(
+            EpollDispatch::from(EpollDispatch::Vms as u64 | 3 << 32),
+            EpollDispatch::Vms
+        );
+        assert_eq!(EpollDispatch::from(u64::MAX), EpollDispatch::Unknown);
+    }
+
+    #[test]
+    fn test_vmm_vms_request() {
+        let mut vmm = create_dummy_vmm();
+
+        // Requests to unknown VMs don't create them.
+        let info: ApiRequest = Box::new(|vmm| {
+            assert!(matches!(vmm.vm_info(), Err(VmError::VmNotCreated)));
+            Ok(false)
+        });
+        assert!(!vmm.vms_request("vm0", info).unwrap());
+        assert!(vmm.vms.is_empty());
+
+        for id in ["vm0", "vm1"] {
+            let create: ApiRequest = Box::new(|vmm| {
+                vmm.vm_create(create_dummy_vm_config()).unwrap();
+                Ok(false)
+            });
+            assert!(!vmm.vms_request(id, create).unwrap());
+        }
+        assert_eq!(vmm.vms.len(), 2);
+        assert_ne!(vmm.vms["vm0"].vm_slot, vmm.vms["vm1"].vm_slot);
+        // The VMs are independent of the one of the VMM.
+        assert!(vmm.vm_config.is_none());
+
+        let delete: ApiRequest = Box::new(|vmm| {
+            vmm.vm_delete().unwrap();
+            Ok(false)
+        });
+        vmm.vms_request("vm0", delete).unwrap();
+        assert!(!vmm.vms.contains_key("vm0"));
+
+        // A request stopping the VMM only stops the VM.
+        let shutdown: ApiRequest = Box::new(|_| Ok(true));
+        assert!(!vmm.vms_request("vm1", shutdown).unwrap());
+        assert!(vmm.vms.is_empty());
+    }
+
+    #[test]
+    fn test_vmm_vm_cold_add_rate_limit_group() {
+        let mut vmm = create_dummy_vmm();
+        let group_config =
+            RateLimiterGroupConfig::parse("id=group0,bw_size=1000,bw_refill_time=100").unwrap();
+
+        assert!(matches!(
+            vmm.vm_add_rate_limit_group(group_config.clone()),
+            Err(VmError::VmNotCreated)
+        ));
+
+        let _ = vmm.vm_create(create_dummy_vm_config());
+        vmm.vm_add_rate_limit_group(group_config.clone()).unwrap();
+        assert_eq!(
+            vmm.vm_config
+                .as_ref()
+                .unwrap()
+                .lock()
+                .unwrap()
+                .rate_limit_groups
+                .clone()
+                .unwrap(),
+            vec![group_config.clone()]
+        );
+
+        // The identifiers must remain unique.
+        assert!(matches!(
+            vmm.vm_add_rate_limit_group(group_config),
+            Err(VmError::ConfigValidation(_))
+        ));
+        // A group must limit something.
+        assert!(matches!(
+            vmm.vm_add_rate_limit_group(RateLimiterGroupConfig::parse("id=group1").unwrap()),
+            Err(VmError::ConfigValidation(_))
+        ));
+        assert_eq!(
+            vmm.vm_config
+                .as_ref()
+                .unwrap()
+                .lock()
+                .unwrap()
+                .rate_limit_groups
+                .as_ref()
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_vmm_vm_resize_disk() {
+        let mut vmm = create_dummy_vmm();
+
+        assert!(matches!(
+            vmm.vm_resize_disk("disk0".to_string(), 1 << 30),
+            Err(VmError::VmNotRunning)
+        ));
+
+        let _ = vmm.vm_create(create_dummy_vm_config());
+        assert!(matches!(
+            vmm.vm_resize_disk("disk0".to_string(), 1 << 30),
+            Err(VmError::VmNotRunning)
+        ));
+    }
+
+    #[test]
+    fn test_vmm_vm_backup() {
+        let mut vmm = create_dummy_vmm();
+
+        let backup_data: VmBackupData =
+            serde_json::from_str(r#"{"disks": [{"id": "disk0", "overlay": "/tmp/disk0.qcow2"}]}"#)
+                .unwrap();
+        assert!(!backup_data.quiesce);
+        assert_eq!(backup_data.disks[0].id, "disk0");
+
+        assert!(matches!(
+            vmm.vm_backup(backup_data.clone()),
+            Err(VmError::VmNotRunning)
+        ));
+        let _ = vmm.vm_create(create_dummy_vm_config());
+        assert!(matches!(
+            vmm.vm_backup(backup_data),
+            Err(VmError::VmNotRunning)
+        ));
+    }
+
+    #[test]
+    fn test_vmm_vm_cold_add_pci_segment() {
+        let mut vmm = create_dummy_vmm();
+
+        assert!(matches!(
+            vmm.vm_add_pci_segment(),
+            Err(VmError::VmNotCreated)
+        ));
+
+        let mut config = create_dummy_vm_config();
+        config.platform =
+            Some(PlatformConfig::parse("num_pci_segments=1,max_pci_segments=2").unwrap());
+        let _ = vmm.vm_create(config);
+
+        assert!(vmm.vm_add_pci_segment().unwrap().is_none());
+        assert_eq!(
+            vmm.vm_config
+                .as_ref()
+                .unwrap()
+                .lock()
+                .unwrap()
+                .platform
+                .as_ref()
+                .unwrap()
+                .num_pci_segments,
+            2
+        );
+
+        // All the reserved segments were added.
+        assert!(matches!(
+            vmm.vm_add_pci_segment(),
+            Err(VmError::DeviceManager(
+                device_manager::DeviceManagerError::NoPciSegmentToAdd
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_vmm_vm_disk_snapshot() {
+        let mut vmm = create_dummy_vmm();
+        let disk_snapshot_data = VmDiskSnapshotData {
+            id: "disk0".to_string(),
+            overlay: PathBuf::from("/tmp/disk0.qcow2"),
+        };
+
+        assert!(matches!(
+            vmm.vm_disk_snapshot(disk_snapshot_data.clone()),
+            Err(VmError::VmNotRunning)
+        ));
+        let _ = vmm.vm_create(create_dummy_vm_config());
+        assert!(matches!(
+            vmm.vm_disk_snapshot(disk_snapshot_data),
+            Err(VmError::VmNotRunning)
+        ));
+    }
+
+    #[test]
+    fn test_vmm_vm_cold_set_balloon() {
+        let mut vmm = create_dummy_vmm();
+
+        assert!(matches!(
+            vmm.vm_set_balloon(Some(true), None),
+            Err(VmError::VmNotCreated)
+        ));
+
+        let _ = vmm.vm_create(create_dummy_vm_config());
+        assert!(matches!(
+            vmm.vm_set_balloon(Some(true), None),
+            Err(VmError::NoBalloon)
+        ));
+
+        let mut vmm = create_dummy_vmm();
+        let mut config = create_dummy_vm_config();
+        config.balloon = Some(BalloonConfig::parse("size=0,free_page_reporting=on").unwrap());
+        let _ = vmm.vm_create(config);
+        vmm.vm_set_balloon(Some(true), None).unwrap();
+        let balloon = vmm
+            .vm_config
+            .as_ref()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .balloon
+            .clone()
+            .unwrap();
+        assert!(balloon.deflate_on_oom);
+        assert!(balloon.free_page_reporting);
+    }
+
+    #[test]
+    fn test_vmm_vm_console_log() {
+        let mut vmm = create_dummy_vmm();
+
+        assert!(matches!(vmm.vm_console_log(), Err(VmError::VmNotRunning)));
+        let _ = vmm.vm_create(create_dummy_vm_config());
+        assert!(matches!(vmm.vm_console_log(), Err(VmError::VmNotRunning)));
+
+        let console_log: VmConsoleLogResponse =
+            serde_json::from_str(r#"{"serial": "login:"}"#).unwrap();
+        assert_eq!(console_log.serial.as_deref(), Some("login:"));
+        assert!(console_log.console.is_none());
+        assert_eq!(
+            serde_json::to_string(&console_log).unwrap(),
+            r#"{"serial":"login:"}"#
+        );
+    }
 }
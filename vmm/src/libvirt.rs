@@ -0,0 +1,516 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Translation of libvirt domain XML definitions into VM configurations.
+//!
+//! Only a subset of the domain format is supported: vCPUs and their topology,
+//! memory and its backing, direct kernel or firmware boot, disks, TAP and
+//! vhost-user interfaces, serial port, virtio console, RNG, balloon, watchdog
+//! and pvpanic devices. The devices which have no equivalent are ignored and
+//! reported as such, while the ones which can't be translated are rejected.
+//!
+//! The domain is translated into the command line syntax of each device, so
+//! that the resulting configuration is parsed and validated exactly like the
+//! command line one.
+
+use thiserror::Error;
+
+use crate::config::{self, VmParams};
+use crate::vm_config::{VmConfig, DEFAULT_RNG_SOURCE};
+use crate::xml::{self, Element};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Invalid domain XML")]
+    InvalidXml(#[source] xml::Error),
+
+    #[error("Not a libvirt domain definition")]
+    NotDomain,
+
+    #[error("Missing <{0}> element")]
+    MissingElement(&'static str),
+
+    #[error("Invalid <{0}> value: {1}")]
+    InvalidValue(&'static str, String),
+
+    #[error("Unsupported {0}: {1}")]
+    Unsupported(&'static str, String),
+
+    #[error("Invalid VM configuration")]
+    InvalidConfig(#[source] config::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// VM configuration translated from a libvirt domain.
+pub struct Domain {
+    pub config: VmConfig,
+    /// Elements of the domain which have no equivalent, and were ignored.
+    pub ignored: Vec<String>,
+}
+
+// Values are quoted so that they can't be confused with other options.
+fn quote(value: &str) -> String {
+    format!("\"{value}\"")
+}
+
+fn parse_number<T: std::str::FromStr>(element: &'static str, value: &str) -> Result<T> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| Error::InvalidValue(element, value.to_string()))
+}
+
+fn memory_size(memory: &Element) -> Result<u64> {
+    let value: u64 = parse_number("memory", &memory.text)?;
+    let unit = memory.attr("unit").unwrap_or("KiB");
+    let scale: u64 = match unit {
+        "b" | "bytes" => 1,
+        "KB" => 1_000,
+        "k" | "KiB" => 1 << 10,
+        "MB" => 1_000_000,
+        "M" | "MiB" => 1 << 20,
+        "GB" => 1_000_000_000,
+        "G" | "GiB" => 1 << 30,
+        "TB" => 1_000_000_000_000,
+        "T" | "TiB" => 1 << 40,
+        _ => return Err(Error::InvalidValue("memory", unit.to_string())),
+    };
+
+    value
+        .checked_mul(scale)
+        .ok_or_else(|| Error::InvalidValue("memory", memory.text.clone()))
+}
+
+fn cpus(domain: &Element) -> Result<String> {
+    let vcpu = domain.child("vcpu").ok_or(Error::MissingElement("vcpu"))?;
+    let max_vcpus: u32 = parse_number("vcpu", &vcpu.text)?;
+    let boot_vcpus = match vcpu.attr("current") {
+        Some(current) => parse_number("vcpu", current)?,
+        None => max_vcpus,
+    };
+    let mut cpus = format!("boot={boot_vcpus},max={max_vcpus}");
+
+    if let Some(topology) = domain.child("cpu").and_then(|c| c.child("topology")) {
+        let value = |name| match topology.attr(name) {
+            Some(value) => parse_number::<u8>("topology", value),
+            None => Ok(1),
+        };
+        cpus.push_str(&format!(
+            ",topology={}:{}:{}:{}",
+            value("threads")?,
+            value("cores")?,
+            value("dies")?,
+            value("sockets")?
+        ));
+    }
+
+    Ok(cpus)
+}
+
+fn memory(domain: &Element) -> Result<String> {
+    let size = memory_size(
+        domain
+            .child("memory")
+            .ok_or(Error::MissingElement("memory"))?,
+    )?;
+    let mut memory = format!("size={size}");
+
+    if let Some(backing) = domain.child("memoryBacking") {
+        if backing.child("hugepages").is_some() {
+            memory.push_str(",hugepages=on");
+        }
+        if backing.child("access").and_then(|a| a.attr("mode")) == Some("shared") {
+            memory.push_str(",shared=on");
+        }
+    }
+
+    Ok(memory)
+}
+
+// Returns None for an empty removable drive.
+fn disk(disk: &Element) -> Result<Option<String>> {
+    let device = disk.attr("device").unwrap_or("disk");
+    if !matches!(device, "disk" | "cdrom") {
+        return Err(Error::Unsupported("disk device", device.to_string()));
+    }
+
+    let source = disk.child("source");
+    let mut options = Vec::new();
+    match disk.attr("type").unwrap_or("file") {
+        disk_type @ ("file" | "block") => {
+            let path =
+                source.and_then(|s| s.attr(if disk_type == "file" { "file" } else { "dev" }));
+            match path {
+                Some(path) => options.push(format!("path={}", quote(path))),
+                None if device == "cdrom" => return Ok(None),
+                None => return Err(Error::MissingElement("source")),
+            }
+        }
+        "vhostuser" => {
+            let path = source
+                .and_then(|s| s.attr("path"))
+                .ok_or(Error::MissingElement("source"))?;
+            options.push(format!("vhost_user=on,socket={}", quote(path)));
+        }
+        disk_type => return Err(Error::Unsupported("disk type", disk_type.to_string())),
+    }
+
+    if device == "cdrom" || disk.child("readonly").is_some() {
+        options.push("readonly=on".to_string());
+    }
+    if let Some(driver) = disk.child("driver") {
+        match driver.attr("cache") {
+            Some("none" | "directsync") => options.push("direct=on".to_string()),
+            Some(cache @ ("writeback" | "writethrough" | "unsafe")) => {
+                options.push(format!("cache={cache}"))
+            }
+            _ => {}
+        }
+        if let Some(queues) = driver.attr("queues") {
+            options.push(format!(
+                "num_queues={}",
+                parse_number::<usize>("driver", queues)?
+            ));
+        }
+    }
+    if let Some(serial) = disk.child_text("serial") {
+        options.push(format!("serial={}", quote(serial)));
+    }
+
+    Ok(Some(options.join(",")))
+}
+
+fn interface(interface: &Element) -> Result<String> {
+    let mut options = Vec::new();
+    match interface.attr("type").unwrap_or_default() {
+        "ethernet" => {
+            // A TAP interface is created when none is provided.
+            if let Some(dev) = interface.child("target").and_then(|t| t.attr("dev")) {
+                options.push(format!("tap={}", quote(dev)));
+            }
+        }
+        "vhostuser" => {
+            let source = interface
+                .child("source")
+                .ok_or(Error::MissingElement("source"))?;
+            let path = source.attr("path").ok_or(Error::MissingElement("source"))?;
+            let mode = source.attr("mode").unwrap_or("client");
+            if !matches!(mode, "client" | "server") {
+                return Err(Error::Unsupported("vhost-user mode", mode.to_string()));
+            }
+            options.push(format!(
+                "vhost_user=on,socket={},vhost_mode={mode}",
+                quote(path)
+            ));
+        }
+        interface_type => {
+            return Err(Error::Unsupported(
+                "interface type",
+                interface_type.to_string(),
+            ))
+        }
+    }
+
+    if let Some(mac) = interface.child("mac").and_then(|m| m.attr("address")) {
+        options.push(format!("mac={}", quote(mac)));
+    }
+    if let Some(mtu) = interface.child("mtu").and_then(|m| m.attr("size")) {
+        options.push(format!("mtu={}", parse_number::<u16>("mtu", mtu)?));
+    }
+    if let Some(queues) = interface.child("driver").and_then(|d| d.attr("queues")) {
+        // Each queue pair counts as two queues.
+        let queues = parse_number::<usize>("driver", queues)?;
+        options.push(format!("num_queues={}", queues * 2));
+    }
+
+    Ok(options.join(","))
+}
+
+fn char_device(device: &Element) -> Result<String> {
+    let path = || {
+        device
+            .child("source")
+            .and_then(|s| s.attr("path"))
+            .ok_or(Error::MissingElement("source"))
+    };
+
+    match device.attr("type").unwrap_or("pty") {
+        "pty" => Ok("pty".to_string()),
+        "null" => Ok("null".to_string()),
+        "file" => Ok(format!("file={}", quote(path()?))),
+        // The VMM listens on the socket.
+        "unix" if device.child("source").and_then(|s| s.attr("mode")) != Some("connect") => {
+            Ok(format!("socket={}", quote(path()?)))
+        }
+        device_type => Err(Error::Unsupported(
+            "character device type",
+            device_type.to_string(),
+        )),
+    }
+}
+
+/// Translates a libvirt domain XML definition into a VM configuration.
+pub fn parse_domain(xml: &str) -> Result<Domain> {
+    let domain = xml::parse(xml).map_err(Error::InvalidXml)?;
+    if domain.name != "domain" {
+        return Err(Error::NotDomain);
+    }
+
+    let cpus = cpus(&domain)?;
+    let memory = memory(&domain)?;
+
+    let os = domain.child("os").ok_or(Error::MissingElement("os"))?;
+    let firmware = os.child_text("loader");
+    let kernel = os.child_text("kernel");
+    let initramfs = os.child_text("initrd");
+    let cmdline = os.child_text("cmdline");
+
+    let mut ignored = Vec::new();
+    let mut disks = Vec::new();
+    let mut net = Vec::new();
+    let mut serial = "null".to_string();
+    let mut console = "off".to_string();
+    let mut rng = format!("src={DEFAULT_RNG_SOURCE}");
+    let mut balloon = None;
+    let mut watchdog = false;
+    let mut pvpanic = false;
+
+    for device in domain
+        .child("devices")
+        .map(|d| d.children.as_slice())
+        .unwrap_or_default()
+    {
+        match device.name.as_str() {
+            "disk" => match disk(device)? {
+                Some(disk) => disks.push(disk),
+                None => ignored.push("empty cdrom".to_string()),
+            },
+            "interface" => net.push(interface(device)?),
+            "serial" => serial = char_device(device)?,
+            // Consoles are aliases of the serial port, unless they are virtio
+            // ones.
+            "console" => {
+                if device.child("target").and_then(|t| t.attr("type")) == Some("virtio") {
+                    console = char_device(device)?;
+                }
+            }
+            "rng" => {
+                if let Some(source) = device
+                    .child("backend")
+                    .filter(|b| b.attr("model") == Some("random"))
+                    .map(|b| b.text.trim())
+                    .filter(|t| !t.is_empty())
+                {
+                    rng = format!("src={}", quote(source));
+                }
+            }
+            "memballoon" => {
+                if device.attr("model") != Some("none") {
+                    let mut options = vec!["size=0".to_string()];
+                    if device.attr("autodeflate") == Some("on") {
+                        options.push("deflate_on_oom=on".to_string());
+                    }
+                    if device.attr("freePageReporting") == Some("on") {
+                        options.push("free_page_reporting=on".to_string());
+                    }
+                    balloon = Some(options.join(","));
+                }
+            }
+            "watchdog" => watchdog = true,
+            "panic" => pvpanic = true,
+            // Implicitly provided.
+            "emulator" | "controller" => {}
+            name => ignored.push(name.to_string()),
+        }
+    }
+
+    let list = |items: &[String]| -> Option<Vec<&str>> {
+        (!items.is_empty()).then(|| items.iter().map(|i| i.as_str()).collect())
+    };
+
+    let vm_params = VmParams {
+        cpus: &cpus,
+        memory: &memory,
+        memory_zones: None,
+        firmware: firmware.as_deref(),
+        fallback_firmware: None,
+        kernel: kernel.as_deref(),
+        initramfs: initramfs.map(|i| vec![i]),
+        cmdline: cmdline.as_deref(),
+        rate_limit_groups: None,
+        rate_limit_schedules: None,
+        disks: list(&disks),
+        net: list(&net),
+        rng: &rng,
+        balloon: balloon.as_deref(),
+        fs: None,
+        pmem: None,
+        serial: &serial,
+        console: &console,
+        #[cfg(target_arch = "x86_64")]
+        debug_console: "off",
+        devices: None,
+        user_devices: None,
+        vdpa: None,
+        vsock: None,
+        #[cfg(feature = "pvmemcontrol")]
+        pvmemcontrol: None,
+        pvpanic,
+        #[cfg(target_arch = "x86_64")]
+        sgx_epc: None,
+        numa: None,
+        watchdog,
+        #[cfg(feature = "guest_debug")]
+        gdb: false,
+        pci_segments: None,
+        platform: None,
+        tpm: None,
+        scmi: None,
+        cloud_init: None,
+        imds: None,
+        netns: None,
+        guest_agent: None,
+        resources: None,
+        rtc: None,
+        restart_policy: None,
+        crash_dump: None,
+        io_threads: None,
+        #[cfg(feature = "igvm")]
+        igvm: None,
+        #[cfg(feature = "sev_snp")]
+        host_data: None,
+        #[cfg(feature = "sev_snp")]
+        id_block: None,
+        #[cfg(feature = "sev_snp")]
+        id_auth: None,
+        #[cfg(feature = "sev_snp")]
+        snp_certs: None,
+        landlock_enable: false,
+        landlock_rules: None,
+    };
+
+    Ok(Domain {
+        config: VmConfig::parse(vm_params).map_err(Error::InvalidConfig)?,
+        ignored,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::vm_config::ConsoleOutputMode;
+
+    #[test]
+    fn test_parse_domain() {
+        let domain = parse_domain(
+            r#"<domain type='kvm'>
+  <name>vm0</name>
+  <memory unit='GiB'>2</memory>
+  <vcpu placement='static' current='2'>4</vcpu>
+  <os>
+    <type arch='x86_64' machine='q35'>hvm</type>
+    <kernel>/opt/vm0/vmlinux</kernel>
+    <cmdline>console=hvc0 root=/dev/vda1</cmdline>
+  </os>
+  <cpu mode='host-passthrough'>
+    <topology sockets='2' dies='1' cores='2' threads='1'/>
+  </cpu>
+  <devices>
+    <emulator>/usr/bin/qemu-system-x86_64</emulator>
+    <disk type='file' device='disk'>
+      <driver name='qemu' type='raw' cache='none' queues='2'/>
+      <source file='/opt/vm0/root,fs.raw'/>
+      <target dev='vda' bus='virtio'/>
+    </disk>
+    <disk type='file' device='cdrom'>
+      <target dev='sda' bus='sata'/>
+    </disk>
+    <interface type='ethernet'>
+      <mac address='52:54:00:12:34:56'/>
+      <target dev='tap0'/>
+      <model type='virtio'/>
+    </interface>
+    <serial type='pty'/>
+    <console type='pty'>
+      <target type='serial'/>
+    </console>
+    <graphics type='vnc'/>
+  </devices>
+</domain>"#,
+        )
+        .unwrap();
+
+        let config = domain.config;
+        assert_eq!(config.cpus.boot_vcpus, 2);
+        assert_eq!(config.cpus.max_vcpus, 4);
+        assert_eq!(config.cpus.topology.as_ref().unwrap().packages, 2);
+        assert_eq!(config.memory.size, 2 << 30);
+        assert_eq!(
+            config.payload.as_ref().unwrap().kernel,
+            Some(PathBuf::from("/opt/vm0/vmlinux"))
+        );
+
+        let disks = config.disks.unwrap();
+        assert_eq!(disks.len(), 1);
+        assert_eq!(disks[0].path, Some(PathBuf::from("/opt/vm0/root,fs.raw")));
+        assert!(disks[0].direct);
+        assert_eq!(disks[0].num_queues, 2);
+
+        let net = config.net.unwrap();
+        assert_eq!(net[0].tap.as_deref(), Some("tap0"));
+        assert_eq!(net[0].mac.to_string(), "52:54:00:12:34:56");
+
+        assert_eq!(config.serial.mode, ConsoleOutputMode::Pty);
+        assert_eq!(config.console.mode, ConsoleOutputMode::Off);
+        assert_eq!(domain.ignored, vec!["empty cdrom", "graphics"]);
+    }
+
+    #[test]
+    fn test_parse_domain_unsupported() {
+        let domain = |devices| {
+            format!(
+                "<domain><memory>1048576</memory><vcpu>1</vcpu>\
+                 <os><kernel>/vmlinux</kernel></os><devices>{devices}</devices></domain>"
+            )
+        };
+
+        parse_domain(&domain("")).unwrap();
+        assert!(matches!(
+            parse_domain(&domain("<interface type='bridge'/>")),
+            Err(Error::Unsupported("interface type", _))
+        ));
+        assert!(matches!(
+            parse_domain(&domain("<disk type='network'/>")),
+            Err(Error::Unsupported("disk type", _))
+        ));
+        assert!(matches!(parse_domain("<network/>"), Err(Error::NotDomain)));
+    }
+
+    #[test]
+    fn test_parse_domain_disk_cache() {
+        let disk = |cache| {
+            let domain = parse_domain(&format!(
+                "<domain><memory>1048576</memory><vcpu>1</vcpu>\
+                 <os><kernel>/vmlinux</kernel></os><devices>\
+                 <disk type='file'><driver cache='{cache}'/><source file='/disk.raw'/></disk>\
+                 </devices></domain>"
+            ))
+            .unwrap();
+            domain.config.disks.unwrap().remove(0)
+        };
+
+        let writethrough = disk("writethrough");
+        assert_eq!(writethrough.cache, Some(block::CacheMode::Writethrough));
+        assert!(!writethrough.direct);
+        assert_eq!(disk("unsafe").cache, Some(block::CacheMode::Unsafe));
+        let directsync = disk("directsync");
+        assert!(directsync.direct);
+        assert_eq!(directsync.cache, None);
+        assert_eq!(disk("default").cache, None);
+    }
+}
@@ -11,11 +11,12 @@ use std::io::{self};
 use std::ops::{BitAnd, Deref, Not, Sub};
 #[cfg(all(target_arch = "x86_64", feature = "guest_debug"))]
 use std::os::fd::AsFd;
+use std::os::unix::fs::OpenOptionsExt;
 use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
 use std::path::PathBuf;
-use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
 use std::sync::{Arc, Barrier, Mutex};
-use std::{ffi, result, thread};
+use std::{ffi, panic, result, thread};
 
 use acpi_tables::{aml, Aml};
 use anyhow::anyhow;
@@ -53,6 +54,7 @@ use vm_migration::{
 use crate::coredump::{
     CoredumpMemoryRegion, CoredumpMemoryRegions, DumpState, GuestDebuggableError,
 };
+use crate::dax::DaxDevice;
 use crate::migration::url_to_path;
 #[cfg(target_arch = "x86_64")]
 use crate::vm_config::SgxEpcConfig;
@@ -65,6 +67,11 @@ const DEFAULT_MEMORY_ZONE: &str = "mem0";
 
 const SNAPSHOT_FILENAME: &str = "memory-ranges";
 
+// Guest memory is saved to and restored from snapshots by chunks of this
+// size, distributed across at most MAX_SNAPSHOT_THREADS threads.
+const SNAPSHOT_CHUNK_SIZE: u64 = 64 << 20;
+const MAX_SNAPSHOT_THREADS: usize = 8;
+
 #[cfg(target_arch = "x86_64")]
 const X86_64_IRQ_BASE: u32 = 5;
 
@@ -83,6 +90,71 @@ const PLATFORM_DEVICE_AREA_SIZE: u64 = 1 << 20;
 
 const MAX_PREFAULT_THREAD_COUNT: usize = 16;
 
+// Not exposed by the libc crate yet.
+const MADV_COLLAPSE: libc::c_int = 25;
+
+// Guest memory is collapsed into huge pages by chunks of this size, aligned
+// on the largest transparent huge page size (512 MiB with 64 KiB pages).
+const COLLAPSE_CHUNK_SIZE: usize = 1 << 30;
+
+pub const SMAPS_PATH: &str = "/proc/self/smaps";
+
+/// Amount of guest memory resident on the host, and how much of it is backed
+/// by huge pages, either transparent or from hugetlbfs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HugepageUsage {
+    pub resident: u64,
+    pub hugepages: u64,
+}
+
+// Sum up the usage of the mappings of /proc/self/smaps starting within the
+// given host address ranges.
+fn parse_smaps(smaps: &str, ranges: &[(u64, u64)]) -> HugepageUsage {
+    let mut usage = HugepageUsage::default();
+    let mut in_guest_memory = false;
+    for line in smaps.lines() {
+        // Each mapping starts with a "start-end perms offset ..." header.
+        if let Some(start) = line
+            .split_whitespace()
+            .next()
+            .and_then(|range| range.split_once('-'))
+            .and_then(|(start, _)| u64::from_str_radix(start, 16).ok())
+        {
+            in_guest_memory = ranges
+                .iter()
+                .any(|(addr, len)| start >= *addr && start < addr + len);
+            continue;
+        }
+        if !in_guest_memory {
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let Some(bytes) = value
+            .trim()
+            .strip_suffix(" kB")
+            .and_then(|kb| kb.parse::<u64>().ok())
+            .map(|kb| kb << 10)
+        else {
+            continue;
+        };
+        match key {
+            "Rss" => usage.resident += bytes,
+            "AnonHugePages" | "ShmemPmdMapped" | "FilePmdMapped" => usage.hugepages += bytes,
+            // Hugetlbfs pages aren't accounted in Rss.
+            "Shared_Hugetlb" | "Private_Hugetlb" => {
+                usage.resident += bytes;
+                usage.hugepages += bytes;
+            }
+            _ => {}
+        }
+    }
+
+    usage
+}
+
 #[derive(Clone, Default, Serialize, Deserialize)]
 struct HotPlugState {
     base: u64,
@@ -366,6 +438,14 @@ pub enum Error {
     #[error("Error copying snapshot into region")]
     SnapshotCopy(#[source] GuestMemoryError),
 
+    /// Error mapping snapshot into region
+    #[error("Error mapping snapshot into region")]
+    SnapshotMap(#[source] io::Error),
+
+    /// Copy-on-write restore of shared or hugepage backed memory
+    #[error("Copy-on-write restore requires private memory without hugepages")]
+    InvalidCopyOnWriteRestore,
+
     /// Failed to allocate MMIO address
     #[error("Failed to allocate MMIO address")]
     AllocateMmioAddress,
@@ -386,6 +466,14 @@ pub enum Error {
     /// Memory size is misaligned with default page size or its hugepage size
     #[error("Memory size is misaligned with default page size or its hugepage size")]
     MisalignedMemorySize,
+
+    /// The device DAX backing the memory can't be used
+    #[error("Invalid device DAX backing the memory")]
+    DaxDevice(#[source] io::Error),
+
+    /// The memory backed by a device DAX isn't shared
+    #[error("Memory backed by a device DAX must be shared")]
+    DaxNotShared,
 }
 
 const ENABLE_FLAG: usize = 0;
@@ -443,6 +531,13 @@ fn memory_zone_get_align_size(zone: &MemoryZoneConfig) -> Result<u64, Error> {
         return Ok(page_size);
     }
 
+    // A device DAX is mapped with the alignment of the device.
+    if let Some(file) = zone.file.as_ref() {
+        if let Some(dax) = DaxDevice::probe(file).map_err(Error::DaxDevice)? {
+            return Ok(std::cmp::max(page_size, dax.align));
+        }
+    }
+
     // The `hugepages` is enabled and the `hugepage_size` is specified, just use it directly.
     if zone.hugepages && zone.hugepage_size.is_some() {
         return Ok(zone.hugepage_size.unwrap());
@@ -769,34 +864,77 @@ impl MemoryManager {
             return Ok(());
         }
 
-        // Open (read only) the snapshot file.
-        let mut memory_file = OpenOptions::new()
+        // Open (read only) the snapshot file, bypassing the page cache when
+        // the filesystem allows it.
+        let memory_file = match OpenOptions::new()
             .read(true)
-            .open(file_path)
-            .map_err(Error::SnapshotOpen)?;
+            .custom_flags(libc::O_DIRECT)
+            .open(&file_path)
+        {
+            Ok(file) => file,
+            Err(e) if e.raw_os_error() == Some(libc::EINVAL) => OpenOptions::new()
+                .read(true)
+                .open(file_path)
+                .map_err(Error::SnapshotOpen)?,
+            Err(e) => return Err(Error::SnapshotOpen(e)),
+        };
+
+        copy_snapshot_memory(
+            &self.guest_memory.memory(),
+            &saved_regions,
+            &memory_file,
+            SnapshotCopyDirection::Restore,
+        )
+        .map_err(|e| Error::SnapshotCopy(GuestMemoryError::IOError(e)))
+    }
+
+    // Map the saved regions privately from the snapshot file rather than
+    // copying them, so that the VMs restored from the same snapshot share
+    // the pages of the page cache until they write to them.
+    fn map_saved_regions(
+        &self,
+        file_path: PathBuf,
+        saved_regions: MemoryRangeTable,
+    ) -> Result<(), Error> {
+        if saved_regions.is_empty() {
+            return Ok(());
+        }
 
+        let memory_file = File::open(file_path).map_err(Error::SnapshotOpen)?;
         let guest_memory = self.guest_memory.memory();
+        let mut file_offset = 0;
         for range in saved_regions.regions() {
-            let mut offset: u64 = 0;
-            // Here we are manually handling the retry in case we can't write
-            // the whole region at once because we can't use the implementation
-            // from vm-memory::GuestMemory of read_exact_from() as it is not
-            // following the correct behavior. For more info about this issue
-            // see: https://github.com/rust-vmm/vm-memory/issues/174
-            loop {
-                let bytes_read = guest_memory
-                    .read_volatile_from(
-                        GuestAddress(range.gpa + offset),
-                        &mut memory_file,
-                        (range.length - offset) as usize,
-                    )
-                    .map_err(Error::SnapshotCopy)?;
-                offset += bytes_read as u64;
+            let region = guest_memory
+                .find_region(GuestAddress(range.gpa))
+                .ok_or_else(|| {
+                    Error::SnapshotCopy(GuestMemoryError::InvalidGuestAddress(GuestAddress(
+                        range.gpa,
+                    )))
+                })?;
+            let region_offset = range.gpa - region.start_addr().raw_value();
+            if region_offset + range.length > region.len() {
+                return Err(Error::SnapshotCopy(GuestMemoryError::InvalidGuestAddress(
+                    GuestAddress(range.gpa),
+                )));
+            }
 
-                if offset == range.length {
-                    break;
-                }
+            // SAFETY: the range lies within the mapping of the region, which
+            // is replaced with a mapping of the same size and protection.
+            let addr = unsafe {
+                libc::mmap(
+                    region.as_ptr().add(region_offset as usize) as *mut libc::c_void,
+                    range.length as usize,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_PRIVATE | libc::MAP_FIXED | libc::MAP_NORESERVE,
+                    memory_file.as_raw_fd(),
+                    file_offset as libc::off_t,
+                )
+            };
+            if addr == libc::MAP_FAILED {
+                return Err(Error::SnapshotMap(io::Error::last_os_error()));
             }
+
+            file_offset += range.length;
         }
 
         Ok(())
@@ -1279,6 +1417,17 @@ impl MemoryManager {
             thp: config.thp,
         };
 
+        // Prefaulted memory may have been populated with regular pages, which
+        // khugepaged would only collapse lazily, if ever.
+        if config.thp_collapse && restore_data.is_none() {
+            for zone in zones.iter().filter(|z| prefault.unwrap_or(z.prefault)) {
+                if let Some(memory_zone) = memory_manager.memory_zones.get(&zone.id) {
+                    Self::collapse_regions(memory_zone.regions());
+                }
+            }
+            memory_manager.log_hugepage_usage();
+        }
+
         #[cfg(target_arch = "x86_64")]
         if let Some(sgx_epc_config) = sgx_epc_config {
             memory_manager.setup_sgx(sgx_epc_config)?;
@@ -1293,9 +1442,24 @@ impl MemoryManager {
         config: &MemoryConfig,
         source_url: Option<&str>,
         prefault: bool,
+        cow_memory: bool,
         phys_bits: u8,
     ) -> Result<Arc<Mutex<MemoryManager>>, Error> {
         if let Some(source_url) = source_url {
+            // The saved regions are mapped over the guest memory, which can't
+            // be done while preserving shared or hugepage backed mappings.
+            if cow_memory
+                && (config.shared
+                    || config.hugepages
+                    || config
+                        .zones
+                        .iter()
+                        .flatten()
+                        .any(|z| z.shared || z.hugepages || z.file.is_some()))
+            {
+                return Err(Error::InvalidCopyOnWriteRestore);
+            }
+
             let mut memory_file_path = url_to_path(source_url).map_err(Error::Restore)?;
             memory_file_path.push(String::from(SNAPSHOT_FILENAME));
 
@@ -1315,9 +1479,20 @@ impl MemoryManager {
                 None,
             )?;
 
-            mm.lock()
-                .unwrap()
-                .fill_saved_regions(memory_file_path, mem_snapshot.memory_ranges)?;
+            if cow_memory {
+                mm.lock()
+                    .unwrap()
+                    .map_saved_regions(memory_file_path, mem_snapshot.memory_ranges)?;
+            } else {
+                let mut mm = mm.lock().unwrap();
+                mm.fill_saved_regions(memory_file_path, mem_snapshot.memory_ranges)?;
+                if config.thp_collapse {
+                    for memory_zone in mm.memory_zones.values() {
+                        Self::collapse_regions(memory_zone.regions());
+                    }
+                    mm.log_hugepage_usage();
+                }
+            }
 
             Ok(mm)
         } else {
@@ -1440,12 +1615,23 @@ impl MemoryManager {
             mmap_flags |= libc::MAP_SHARED;
             Some(FileOffset::new(f, file_offset))
         } else if let Some(backing_file) = backing_file {
-            if shared {
+            let fo = Self::open_backing_file(backing_file, file_offset)?;
+            if let Some(dax) = DaxDevice::probe(backing_file).map_err(Error::DaxDevice)? {
+                // A device DAX can't be mapped private. The mapping is made
+                // synchronous so that the guest writes are persistent once
+                // flushed from the CPU caches, without any msync().
+                if !shared {
+                    return Err(Error::DaxNotShared);
+                }
+                dax.check_mapping(file_offset, size as u64)
+                    .map_err(Error::DaxDevice)?;
+                mmap_flags |= libc::MAP_SHARED_VALIDATE | libc::MAP_SYNC;
+            } else if shared {
                 mmap_flags |= libc::MAP_SHARED;
             } else {
                 mmap_flags |= libc::MAP_PRIVATE;
             }
-            Some(Self::open_backing_file(backing_file, file_offset)?)
+            Some(fo)
         } else if shared || hugepages {
             // For hugepages we must also MAP_SHARED otherwise we will trigger #4805
             // because the MAP_PRIVATE will trigger CoW against the backing file with
@@ -1558,6 +1744,78 @@ impl MemoryManager {
         Ok(Arc::new(region))
     }
 
+    // Synchronously collapse the populated memory of the anonymous regions
+    // into transparent huge pages, in parallel.
+    fn collapse_regions(regions: &[Arc<GuestRegionMmap>]) {
+        let chunks: Vec<(usize, usize)> = regions
+            .iter()
+            .filter(|r| r.file_offset().is_none())
+            .flat_map(|r| {
+                let addr = r.as_ptr() as usize;
+                let len = r.len() as usize;
+                (0..len)
+                    .step_by(COLLAPSE_CHUNK_SIZE)
+                    .map(move |offset| (addr + offset, COLLAPSE_CHUNK_SIZE.min(len - offset)))
+            })
+            .collect();
+        if chunks.is_empty() {
+            return;
+        }
+
+        let num_threads = chunks.len().min(MAX_PREFAULT_THREAD_COUNT);
+        let failure = thread::scope(|s| {
+            let chunks = &chunks;
+            let handles: Vec<_> = (0..num_threads)
+                .map(|i| {
+                    s.spawn(move || {
+                        let mut failure = None;
+                        for (addr, len) in chunks.iter().skip(i).step_by(num_threads) {
+                            // SAFETY: FFI call with correct arguments
+                            let ret = unsafe { libc::madvise(*addr as _, *len, MADV_COLLAPSE) };
+                            if ret != 0 {
+                                failure = Some(io::Error::last_os_error());
+                            }
+                        }
+                        failure
+                    })
+                })
+                .collect();
+            handles.into_iter().filter_map(|h| h.join().unwrap()).last()
+        });
+
+        // Ranges which aren't populated can't be collapsed, nor the ones
+        // for which no huge page could be allocated.
+        if let Some(e) = failure {
+            warn!(
+                "Failed to collapse some guest memory into huge pages: {}",
+                e
+            );
+        }
+    }
+
+    pub fn hugepage_usage(&self) -> Result<HugepageUsage, io::Error> {
+        let ranges: Vec<(u64, u64)> = self
+            .guest_memory
+            .memory()
+            .iter()
+            .map(|r| (r.as_ptr() as u64, r.len()))
+            .collect();
+        let smaps = std::fs::read_to_string(SMAPS_PATH)?;
+
+        Ok(parse_smaps(&smaps, &ranges))
+    }
+
+    fn log_hugepage_usage(&self) {
+        match self.hugepage_usage() {
+            Ok(usage) => info!(
+                "Guest memory backed by huge pages: {} MiB out of {} MiB resident",
+                usage.hugepages >> 20,
+                usage.resident >> 20
+            ),
+            Err(e) => warn!("Failed to read guest memory usage: {}", e),
+        }
+    }
+
     // Duplicate of `memory_zone_get_align_size` that does not require a `zone`
     fn get_prefault_align_size(
         backing_file: &Option<PathBuf>,
@@ -2665,6 +2923,116 @@ impl Aml for MemoryManager {
     }
 }
 
+#[derive(Clone, Copy)]
+enum SnapshotCopyDirection {
+    Save,
+    Restore,
+}
+
+// Copy one chunk of guest memory to or from the snapshot file.
+fn copy_snapshot_chunk(
+    file: &File,
+    host_addr: usize,
+    file_offset: u64,
+    len: usize,
+    direction: SnapshotCopyDirection,
+) -> io::Result<()> {
+    let mut done = 0;
+    while done < len {
+        let iov = libc::iovec {
+            iov_base: (host_addr + done) as *mut libc::c_void,
+            iov_len: len - done,
+        };
+        let offset = (file_offset + done as u64) as libc::off_t;
+        // SAFETY: the iovec covers guest memory which remains mapped as long
+        // as the VM exists, and the file descriptor is valid.
+        let ret = unsafe {
+            match direction {
+                SnapshotCopyDirection::Save => libc::pwritev2(file.as_raw_fd(), &iov, 1, offset, 0),
+                SnapshotCopyDirection::Restore => {
+                    libc::preadv2(file.as_raw_fd(), &iov, 1, offset, 0)
+                }
+            }
+        };
+        if ret < 0 {
+            let e = io::Error::last_os_error();
+            if e.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(e);
+        }
+        if ret == 0 {
+            return Err(io::Error::from(match direction {
+                SnapshotCopyDirection::Save => io::ErrorKind::WriteZero,
+                SnapshotCopyDirection::Restore => io::ErrorKind::UnexpectedEof,
+            }));
+        }
+        done += ret as usize;
+    }
+
+    Ok(())
+}
+
+// Copy the guest memory ranges to or from the snapshot file, where they are
+// stored one after the other. The ranges are split into chunks, spread
+// across several threads so that checkpointing large guests isn't limited
+// by a single thread copying memory.
+fn copy_snapshot_memory(
+    guest_memory: &GuestMemoryMmap,
+    ranges: &MemoryRangeTable,
+    file: &File,
+    direction: SnapshotCopyDirection,
+) -> io::Result<()> {
+    let mut chunks = Vec::new();
+    let mut file_offset = 0;
+    for range in ranges.regions() {
+        let host_addr = guest_memory
+            .get_host_address(GuestAddress(range.gpa))
+            .map_err(io::Error::other)? as usize;
+        let mut offset = 0;
+        while offset < range.length {
+            let len = std::cmp::min(SNAPSHOT_CHUNK_SIZE, range.length - offset);
+            chunks.push((
+                host_addr + offset as usize,
+                file_offset + offset,
+                len as usize,
+            ));
+            offset += len;
+        }
+        file_offset += range.length;
+    }
+
+    let num_threads = thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(MAX_SNAPSHOT_THREADS)
+        .min(chunks.len());
+    let next_chunk = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+
+    thread::scope(|s| {
+        let handles: Vec<_> = (0..num_threads)
+            .map(|_| {
+                s.spawn(|| -> io::Result<()> {
+                    while !failed.load(Ordering::Relaxed) {
+                        let Some(&(host_addr, file_offset, len)) =
+                            chunks.get(next_chunk.fetch_add(1, Ordering::Relaxed))
+                        else {
+                            break;
+                        };
+                        copy_snapshot_chunk(file, host_addr, file_offset, len, direction)
+                            .inspect_err(|_| failed.store(true, Ordering::Relaxed))?;
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .try_for_each(|h| h.join().unwrap_or_else(|e| panic::resume_unwind(e)))
+    })
+}
+
 impl Pausable for MemoryManager {}
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -2719,38 +3087,41 @@ impl Transportable for MemoryManager {
         memory_file_path.push(String::from(SNAPSHOT_FILENAME));
 
         // Create the snapshot file for the entire memory
-        let mut memory_file = OpenOptions::new()
+        let memory_file = OpenOptions::new()
             .read(true)
             .write(true)
             .create_new(true)
-            .open(memory_file_path)
+            .open(&memory_file_path)
+            .map_err(|e| MigratableError::MigrateSend(e.into()))?;
+        memory_file
+            .set_len(
+                self.snapshot_memory_ranges
+                    .regions()
+                    .iter()
+                    .map(|r| r.length)
+                    .sum(),
+            )
             .map_err(|e| MigratableError::MigrateSend(e.into()))?;
 
-        let guest_memory = self.guest_memory.memory();
-
-        for range in self.snapshot_memory_ranges.regions() {
-            let mut offset: u64 = 0;
-            // Here we are manually handling the retry in case we can't read
-            // the whole region at once because we can't use the implementation
-            // from vm-memory::GuestMemory of write_all_to() as it is not
-            // following the correct behavior. For more info about this issue
-            // see: https://github.com/rust-vmm/vm-memory/issues/174
-            loop {
-                let bytes_written = guest_memory
-                    .write_volatile_to(
-                        GuestAddress(range.gpa + offset),
-                        &mut memory_file,
-                        (range.length - offset) as usize,
-                    )
-                    .map_err(|e| MigratableError::MigrateSend(e.into()))?;
-                offset += bytes_written as u64;
+        // Bypass the page cache when the filesystem allows it, as the guest
+        // memory content won't be read back by this host any time soon.
+        let memory_file = match OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_DIRECT)
+            .open(&memory_file_path)
+        {
+            Ok(file) => file,
+            Err(e) if e.raw_os_error() == Some(libc::EINVAL) => memory_file,
+            Err(e) => return Err(MigratableError::MigrateSend(e.into())),
+        };
 
-                if offset == range.length {
-                    break;
-                }
-            }
-        }
-        Ok(())
+        copy_snapshot_memory(
+            &self.guest_memory.memory(),
+            &self.snapshot_memory_ranges,
+            &memory_file,
+            SnapshotCopyDirection::Save,
+        )
+        .map_err(|e| MigratableError::MigrateSend(e.into()))
     }
 }
 
@@ -2824,3 +3195,115 @@ impl Migratable for MemoryManager {
         Ok(table)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use vm_memory::Bytes;
+    use vmm_sys_util::tempfile::TempFile;
+
+    use super::*;
+
+    #[test]
+    fn test_copy_snapshot_memory() {
+        let layout = [
+            (GuestAddress(0), 0x20_0000),
+            (GuestAddress(0x100_0000), 0x10_0000),
+        ];
+        let mut ranges = MemoryRangeTable::default();
+        ranges.push(MemoryRange {
+            gpa: 0x1000,
+            length: 0x1f_f000,
+        });
+        ranges.push(MemoryRange {
+            gpa: 0x100_0000,
+            length: 0x8000,
+        });
+        let pattern: Vec<u8> = (0..0x20_0000u32).map(|i| (i % 251) as u8).collect();
+
+        let guest_memory = GuestMemoryMmap::from_ranges(&layout).unwrap();
+        guest_memory.write_slice(&pattern, GuestAddress(0)).unwrap();
+        guest_memory
+            .write_slice(&pattern[..0x10_0000], GuestAddress(0x100_0000))
+            .unwrap();
+
+        let snapshot = TempFile::new().unwrap();
+        copy_snapshot_memory(
+            &guest_memory,
+            &ranges,
+            snapshot.as_file(),
+            SnapshotCopyDirection::Save,
+        )
+        .unwrap();
+
+        let restored = GuestMemoryMmap::from_ranges(&layout).unwrap();
+        copy_snapshot_memory(
+            &restored,
+            &ranges,
+            snapshot.as_file(),
+            SnapshotCopyDirection::Restore,
+        )
+        .unwrap();
+
+        let mut buf = vec![0u8; 0x20_0000];
+        restored.read_slice(&mut buf, GuestAddress(0)).unwrap();
+        assert!(buf[..0x1000].iter().all(|&b| b == 0));
+        assert_eq!(buf[0x1000..], pattern[0x1000..]);
+        restored
+            .read_slice(&mut buf[..0x10_0000], GuestAddress(0x100_0000))
+            .unwrap();
+        assert_eq!(buf[..0x8000], pattern[..0x8000]);
+        assert!(buf[0x8000..0x10_0000].iter().all(|&b| b == 0));
+
+        // Restoring from a truncated snapshot must fail.
+        snapshot.as_file().set_len(0x1000).unwrap();
+        assert!(copy_snapshot_memory(
+            &restored,
+            &ranges,
+            snapshot.as_file(),
+            SnapshotCopyDirection::Restore,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_smaps() {
+        let smaps = "\
+7f0000000000-7f0040000000 rw-p 00000000 00:00 0
+Size:            1048576 kB
+Rss:              524288 kB
+AnonHugePages:    262144 kB
+7f0040000000-7f0040001000 r--p 00000000 00:00 0
+Rss:                   4 kB
+AnonHugePages:         0 kB
+7f1000000000-7f1040000000 rw-s 00000000 00:10 1234                       /dev/hugepages/guest (deleted)
+Rss:                   0 kB
+Shared_Hugetlb:    65536 kB
+Private_Hugetlb:       0 kB
+VmFlags: rd wr sh mr mw me ms sd
+";
+        assert_eq!(
+            parse_smaps(smaps, &[(0x7f00_0000_0000, 0x4000_0000)]),
+            HugepageUsage {
+                resident: 512 << 20,
+                hugepages: 256 << 20,
+            }
+        );
+        assert_eq!(
+            parse_smaps(
+                smaps,
+                &[
+                    (0x7f00_0000_0000, 0x4000_0000),
+                    (0x7f10_0000_0000, 0x4000_0000)
+                ]
+            ),
+            HugepageUsage {
+                resident: 576 << 20,
+                hugepages: 320 << 20,
+            }
+        );
+        assert_eq!(
+            parse_smaps(smaps, &[(0x1000, 0x1000)]),
+            HugepageUsage::default()
+        );
+    }
+}
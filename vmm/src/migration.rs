@@ -2,11 +2,15 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
-use std::fs::File;
-use std::io::Read;
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
 
 use anyhow::anyhow;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use vm_migration::{MigratableError, Snapshot};
 
 #[cfg(all(target_arch = "x86_64", feature = "guest_debug"))]
@@ -16,6 +20,26 @@ use crate::vm_config::VmConfig;
 
 pub const SNAPSHOT_STATE_FILE: &str = "state.json";
 pub const SNAPSHOT_CONFIG_FILE: &str = "config.json";
+pub const HIBERNATION_FILE: &str = "hibernation.json";
+pub const SNAPSHOT_MANIFEST_FILE: &str = "manifest.json";
+
+/// Settings of the VMM a hibernated VM is resumed with, next to its
+/// snapshot.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct HibernationManifest {
+    /// Path of the API socket the VMM was listening on
+    pub api_socket: Option<String>,
+}
+
+/// Checksums of the files of a snapshot, signed with the key of the
+/// operator to detect corrupted or tampered snapshots.
+#[derive(Debug, Deserialize, Serialize)]
+struct SnapshotManifest {
+    /// SHA-256 of each file, by name
+    files: BTreeMap<String, String>,
+    /// HMAC-SHA256 of `files`
+    signature: String,
+}
 
 pub fn url_to_path(url: &str) -> std::result::Result<PathBuf, MigratableError> {
     let path: PathBuf = url
@@ -78,6 +102,28 @@ pub fn recv_vm_state(source_url: &str) -> std::result::Result<Snapshot, Migratab
     serde_json::from_slice(&bytes).map_err(|e| MigratableError::MigrateReceive(e.into()))
 }
 
+pub fn send_hibernation_manifest(
+    destination_url: &str,
+    manifest: &HibernationManifest,
+) -> std::result::Result<(), MigratableError> {
+    let mut manifest_path = url_to_path(destination_url)?;
+    manifest_path.push(HIBERNATION_FILE);
+
+    let manifest =
+        serde_json::to_vec(manifest).map_err(|e| MigratableError::MigrateSend(e.into()))?;
+    fs::write(manifest_path, manifest).map_err(|e| MigratableError::MigrateSend(e.into()))
+}
+
+pub fn recv_hibernation_manifest(
+    source_url: &str,
+) -> std::result::Result<HibernationManifest, MigratableError> {
+    let mut manifest_path = url_to_path(source_url)?;
+    manifest_path.push(HIBERNATION_FILE);
+
+    let bytes = fs::read(manifest_path).map_err(|e| MigratableError::MigrateReceive(e.into()))?;
+    serde_json::from_slice(&bytes).map_err(|e| MigratableError::MigrateReceive(e.into()))
+}
+
 pub fn get_vm_snapshot(snapshot: &Snapshot) -> std::result::Result<VmSnapshot, MigratableError> {
     if let Some(snapshot_data) = snapshot.snapshot_data.as_ref() {
         return snapshot_data.to_state();
@@ -87,3 +133,187 @@ pub fn get_vm_snapshot(snapshot: &Snapshot) -> std::result::Result<VmSnapshot, M
         "Could not find VM config snapshot section"
     )))
 }
+
+fn read_signing_key(path: &Path) -> anyhow::Result<Vec<u8>> {
+    let key = fs::read(path).map_err(|e| anyhow!("Cannot read signing key {:?}: {}", path, e))?;
+    if key.is_empty() {
+        return Err(anyhow!("Signing key {:?} is empty", path));
+    }
+
+    Ok(key)
+}
+
+fn file_checksum(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+
+    Ok(hex::encode(hasher.finalize()))
+}
+
+fn manifest_mac(key: &[u8], files: &BTreeMap<String, String>) -> anyhow::Result<Hmac<Sha256>> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key)?;
+    mac.update(&serde_json::to_vec(files)?);
+
+    Ok(mac)
+}
+
+// Names of the files of the snapshot in `path`, the manifest aside.
+fn snapshot_files(path: &Path) -> io::Result<Vec<String>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name != SNAPSHOT_MANIFEST_FILE {
+            files.push(name);
+        }
+    }
+
+    Ok(files)
+}
+
+/// Signs the snapshot at `destination_url` with the key in `key_path`.
+pub fn send_snapshot_manifest(
+    destination_url: &str,
+    key_path: &Path,
+) -> std::result::Result<(), MigratableError> {
+    let path = url_to_path(destination_url)?;
+    let key = read_signing_key(key_path).map_err(MigratableError::MigrateSend)?;
+
+    let mut files = BTreeMap::new();
+    for name in snapshot_files(&path).map_err(|e| MigratableError::MigrateSend(e.into()))? {
+        let checksum =
+            file_checksum(&path.join(&name)).map_err(|e| MigratableError::MigrateSend(e.into()))?;
+        files.insert(name, checksum);
+    }
+
+    let signature = hex::encode(
+        manifest_mac(&key, &files)
+            .map_err(MigratableError::MigrateSend)?
+            .finalize()
+            .into_bytes(),
+    );
+    let manifest = serde_json::to_vec(&SnapshotManifest { files, signature })
+        .map_err(|e| MigratableError::MigrateSend(e.into()))?;
+    fs::write(path.join(SNAPSHOT_MANIFEST_FILE), manifest)
+        .map_err(|e| MigratableError::MigrateSend(e.into()))
+}
+
+/// Verifies the snapshot at `source_url` was signed with the key in
+/// `key_path`, and that none of its files changed since.
+pub fn verify_snapshot_manifest(
+    source_url: &str,
+    key_path: &Path,
+) -> std::result::Result<(), MigratableError> {
+    let path = url_to_path(source_url)?;
+    let key = read_signing_key(key_path).map_err(MigratableError::MigrateReceive)?;
+
+    let bytes = fs::read(path.join(SNAPSHOT_MANIFEST_FILE))
+        .map_err(|e| MigratableError::MigrateReceive(anyhow!("Snapshot is not signed: {}", e)))?;
+    let manifest: SnapshotManifest =
+        serde_json::from_slice(&bytes).map_err(|e| MigratableError::MigrateReceive(e.into()))?;
+
+    let signature =
+        hex::decode(&manifest.signature).map_err(|e| MigratableError::MigrateReceive(e.into()))?;
+    manifest_mac(&key, &manifest.files)
+        .map_err(MigratableError::MigrateReceive)?
+        .verify_slice(&signature)
+        .map_err(|_| MigratableError::MigrateReceive(anyhow!("Invalid snapshot signature")))?;
+
+    for name in snapshot_files(&path).map_err(|e| MigratableError::MigrateReceive(e.into()))? {
+        if !manifest.files.contains_key(&name) {
+            return Err(MigratableError::MigrateReceive(anyhow!(
+                "Snapshot file {} is not signed",
+                name
+            )));
+        }
+    }
+
+    for (name, checksum) in manifest.files.iter() {
+        let actual = file_checksum(&path.join(name)).map_err(|e| {
+            MigratableError::MigrateReceive(anyhow!("Cannot read snapshot file {}: {}", name, e))
+        })?;
+        if &actual != checksum {
+            return Err(MigratableError::MigrateReceive(anyhow!(
+                "Snapshot file {} does not match its checksum",
+                name
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use vmm_sys_util::tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_snapshot_manifest() {
+        let dir = TempDir::new_with_prefix("/tmp/ch-snapshot-keys").unwrap();
+        let key = dir.as_path().join("key");
+        let other_key = dir.as_path().join("other-key");
+        let snapshot = TempDir::new_with_prefix("/tmp/ch-snapshot").unwrap();
+        let url = format!("file://{}", snapshot.as_path().display());
+        fs::write(&key, b"secret").unwrap();
+        fs::write(&other_key, b"another secret").unwrap();
+        fs::write(snapshot.as_path().join(SNAPSHOT_STATE_FILE), b"{}").unwrap();
+        fs::write(snapshot.as_path().join(SNAPSHOT_CONFIG_FILE), b"{}").unwrap();
+
+        // Not signed
+        verify_snapshot_manifest(&url, &key).unwrap_err();
+
+        send_snapshot_manifest(&url, &key).unwrap();
+        verify_snapshot_manifest(&url, &key).unwrap();
+        verify_snapshot_manifest(&url, &other_key).unwrap_err();
+
+        // The hibernation settings are signed along with the snapshot.
+        fs::write(snapshot.as_path().join(HIBERNATION_FILE), b"{}").unwrap();
+        verify_snapshot_manifest(&url, &key).unwrap_err();
+        send_snapshot_manifest(&url, &key).unwrap();
+        verify_snapshot_manifest(&url, &key).unwrap();
+        fs::write(snapshot.as_path().join(HIBERNATION_FILE), b"{ }").unwrap();
+        verify_snapshot_manifest(&url, &key).unwrap_err();
+        send_snapshot_manifest(&url, &key).unwrap();
+
+        // Tampered file
+        fs::write(snapshot.as_path().join(SNAPSHOT_STATE_FILE), b"{ }").unwrap();
+        verify_snapshot_manifest(&url, &key).unwrap_err();
+        send_snapshot_manifest(&url, &key).unwrap();
+
+        // Unsigned file
+        fs::write(snapshot.as_path().join("memory-ranges"), b"").unwrap();
+        verify_snapshot_manifest(&url, &key).unwrap_err();
+    }
+
+    #[test]
+    fn test_hibernation_manifest() {
+        let snapshot = TempDir::new_with_prefix("/tmp/ch-snapshot").unwrap();
+        let url = format!("file://{}", snapshot.as_path().display());
+
+        // Not hibernated
+        recv_hibernation_manifest(&url).unwrap_err();
+
+        let manifest = HibernationManifest {
+            api_socket: Some("/run/ch.sock".to_string()),
+        };
+        send_hibernation_manifest(&url, &manifest).unwrap();
+        assert_eq!(
+            recv_hibernation_manifest(&url).unwrap().api_socket,
+            manifest.api_socket
+        );
+
+        // Settings of older VMMs
+        fs::write(snapshot.as_path().join(HIBERNATION_FILE), b"{}").unwrap();
+        assert!(recv_hibernation_manifest(&url)
+            .unwrap()
+            .api_socket
+            .is_none());
+
+        send_hibernation_manifest("/not/a/url", &manifest).unwrap_err();
+    }
+}
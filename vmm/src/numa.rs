@@ -0,0 +1,481 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Automatic placement of the VM resources on the host NUMA topology.
+//!
+//! Each guest NUMA node, or the whole VM when it doesn't define any, is
+//! assigned to a host NUMA node. The node the VFIO devices attached to the
+//! guest node are local to is preferred, the remaining guest nodes being
+//! spread across the least used host nodes. The vCPUs, memory zones, I/O
+//! threads and disk queues of a guest node are then placed on the host node
+//! it was assigned to, unless they were explicitly placed already.
+//!
+//! The distances between the guest NUMA nodes bound to host NUMA nodes can
+//! also be derived from the distances between these host nodes.
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+use thiserror::Error;
+
+use crate::vm_config::*;
+
+pub const SYSFS_NODE_PATH: &str = "/sys/devices/system/node";
+
+// Distance of a NUMA node to itself.
+const LOCAL_DISTANCE: u8 = 10;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Cannot read the host NUMA topology")]
+    ReadHostTopology(#[source] io::Error),
+
+    #[error("Invalid host CPU list: {0}")]
+    InvalidCpuList(String),
+
+    #[error("No host NUMA node with CPUs")]
+    NoHostNode,
+
+    #[error("Invalid host NUMA distances: {0}")]
+    InvalidDistances(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Host NUMA node with CPUs attached.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HostNode {
+    pub id: u32,
+    pub cpus: Vec<usize>,
+}
+
+// Parse a CPU list as found in sysfs, such as "0-3,8,10-11".
+fn parse_cpu_list(list: &str) -> Result<Vec<usize>> {
+    let parse = |cpu: &str| {
+        cpu.parse::<usize>()
+            .map_err(|_| Error::InvalidCpuList(list.to_string()))
+    };
+
+    let mut cpus = Vec::new();
+    for range in list.trim().split(',').filter(|r| !r.is_empty()) {
+        match range.split_once('-') {
+            Some((first, last)) => cpus.extend(parse(first)?..=parse(last)?),
+            None => cpus.push(parse(range)?),
+        }
+    }
+
+    Ok(cpus)
+}
+
+fn node_path(id: u32) -> PathBuf {
+    Path::new(SYSFS_NODE_PATH).join(format!("node{id}"))
+}
+
+// Identifiers of the host NUMA nodes, sorted.
+fn host_node_ids() -> Result<Vec<u32>> {
+    let mut ids = Vec::new();
+    for entry in fs::read_dir(SYSFS_NODE_PATH).map_err(Error::ReadHostTopology)? {
+        let entry = entry.map_err(Error::ReadHostTopology)?;
+        if let Some(id) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.strip_prefix("node"))
+            .and_then(|id| id.parse().ok())
+        {
+            ids.push(id);
+        }
+    }
+    ids.sort_unstable();
+
+    Ok(ids)
+}
+
+/// Read the host NUMA nodes having CPUs attached, sorted by identifier.
+pub fn host_nodes() -> Result<Vec<HostNode>> {
+    let mut nodes = Vec::new();
+    for id in host_node_ids()? {
+        let cpus =
+            fs::read_to_string(node_path(id).join("cpulist")).map_err(Error::ReadHostTopology)?;
+        let cpus = parse_cpu_list(&cpus)?;
+        // Memory only nodes can't host any vCPU.
+        if !cpus.is_empty() {
+            nodes.push(HostNode { id, cpus });
+        }
+    }
+
+    if nodes.is_empty() {
+        return Err(Error::NoHostNode);
+    }
+
+    Ok(nodes)
+}
+
+// Parse the distances of a host node to all the host nodes, as found in
+// sysfs, such as "10 21".
+fn parse_distances(list: &str, num_nodes: usize) -> Result<Vec<u8>> {
+    let distances = list
+        .split_whitespace()
+        .map(|distance| distance.parse::<u8>())
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(|_| Error::InvalidDistances(list.trim().to_string()))?;
+    if distances.len() != num_nodes {
+        return Err(Error::InvalidDistances(list.trim().to_string()));
+    }
+
+    Ok(distances)
+}
+
+/// Read the distances between the host NUMA nodes, as reported by the host
+/// SLIT, indexed by source and destination node.
+pub fn host_distances() -> Result<BTreeMap<(u32, u32), u8>> {
+    let ids = host_node_ids()?;
+    let mut distances = BTreeMap::new();
+    for &source in ids.iter() {
+        let list = fs::read_to_string(node_path(source).join("distance"))
+            .map_err(Error::ReadHostTopology)?;
+        // The distances are listed in the order of the node identifiers.
+        for (&destination, distance) in ids.iter().zip(parse_distances(&list, ids.len())?) {
+            distances.insert((source, destination), distance);
+        }
+    }
+
+    Ok(distances)
+}
+
+/// Host NUMA node a VFIO device is local to, if the platform reports it.
+pub fn vfio_device_node(path: &Path) -> Option<u32> {
+    fs::read_to_string(path.join("numa_node"))
+        .ok()?
+        .trim()
+        .parse::<i32>()
+        .ok()
+        .and_then(|node| u32::try_from(node).ok())
+}
+
+struct GuestNode {
+    cpus: Vec<u32>,
+    memory_zones: Vec<String>,
+    pci_segments: Vec<u16>,
+}
+
+fn guest_nodes(config: &VmConfig) -> Vec<GuestNode> {
+    let num_pci_segments = config
+        .platform
+        .as_ref()
+        .map(|p| p.num_pci_segments)
+        .unwrap_or(DEFAULT_NUM_PCI_SEGMENTS);
+
+    let Some(numa) = config.numa.as_ref() else {
+        return vec![GuestNode {
+            cpus: (0..config.cpus.max_vcpus).collect(),
+            memory_zones: config
+                .memory
+                .zones
+                .iter()
+                .flatten()
+                .map(|zone| zone.id.clone())
+                .collect(),
+            pci_segments: (0..num_pci_segments).collect(),
+        }];
+    };
+
+    let mut nodes: Vec<GuestNode> = numa
+        .iter()
+        .map(|node| GuestNode {
+            cpus: node.cpus.clone().unwrap_or_default(),
+            memory_zones: node.memory_zones.clone().unwrap_or_default(),
+            pci_segments: node.pci_segments.clone().unwrap_or_default(),
+        })
+        .collect();
+
+    // PCI segments which aren't explicitly assigned belong to node 0.
+    let default_node = numa
+        .iter()
+        .position(|node| node.guest_numa_id == 0)
+        .unwrap_or_default();
+    for segment in 0..num_pci_segments {
+        if !nodes
+            .iter()
+            .any(|node| node.pci_segments.contains(&segment))
+        {
+            nodes[default_node].pci_segments.push(segment);
+        }
+    }
+
+    nodes
+}
+
+// Assign a host node to each guest node, given the number of VFIO devices of
+// the guest node local to each host node. Guest nodes with local devices
+// pick their host node first, the others being spread across the least used
+// host nodes.
+fn assign_host_nodes(votes: &[BTreeMap<u32, usize>], host_nodes: &[HostNode]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..votes.len()).collect();
+    order.sort_by_key(|&guest_node| votes[guest_node].is_empty());
+
+    let mut usage = vec![0usize; host_nodes.len()];
+    let mut placement = vec![0usize; votes.len()];
+    for guest_node in order {
+        let host_node = (0..host_nodes.len())
+            .max_by_key(|&h| {
+                (
+                    votes[guest_node]
+                        .get(&host_nodes[h].id)
+                        .copied()
+                        .unwrap_or_default(),
+                    Reverse(usage[h]),
+                    Reverse(h),
+                )
+            })
+            .unwrap();
+        usage[host_node] += 1;
+        placement[guest_node] = host_node;
+    }
+
+    placement
+}
+
+/// Place the vCPUs, memory zones, I/O threads and disk queues which aren't
+/// explicitly placed onto the host NUMA nodes. The `device_node` callback
+/// returns the host node a VFIO device is local to.
+pub fn place(
+    config: &mut VmConfig,
+    host_nodes: &[HostNode],
+    device_node: impl Fn(&Path) -> Option<u32>,
+) {
+    if host_nodes.is_empty() {
+        return;
+    }
+
+    let guest_nodes = guest_nodes(config);
+    if guest_nodes.is_empty() {
+        return;
+    }
+
+    // Count the VFIO devices of each guest node local to each host node.
+    let mut votes = vec![BTreeMap::<u32, usize>::new(); guest_nodes.len()];
+    for device in config.devices.iter().flatten() {
+        let Some(host_node) = device_node(&device.path) else {
+            continue;
+        };
+        if let Some(guest_node) = guest_nodes
+            .iter()
+            .position(|node| node.pci_segments.contains(&device.pci_segment))
+        {
+            *votes[guest_node].entry(host_node).or_default() += 1;
+        }
+    }
+
+    let placement = assign_host_nodes(&votes, host_nodes);
+
+    let host_node_of = |segment: u16| {
+        guest_nodes
+            .iter()
+            .position(|node| node.pci_segments.contains(&segment))
+            .map(|guest_node| &host_nodes[placement[guest_node]])
+    };
+
+    if config.cpus.affinity.is_none() {
+        let mut affinity: Vec<CpuAffinity> = guest_nodes
+            .iter()
+            .zip(placement.iter())
+            .flat_map(|(node, &host_node)| {
+                node.cpus.iter().map(move |&vcpu| CpuAffinity {
+                    vcpu,
+                    host_cpus: host_nodes[host_node].cpus.clone(),
+                })
+            })
+            .collect();
+        affinity.sort_by_key(|a| a.vcpu);
+        if !affinity.is_empty() {
+            config.cpus.affinity = Some(affinity);
+        }
+    }
+
+    for zone in config.memory.zones.iter_mut().flatten() {
+        // A host NUMA policy can't be set on shared file mappings.
+        if zone.host_numa_node.is_some() || (zone.shared && zone.file.is_some()) {
+            continue;
+        }
+        if let Some(guest_node) = guest_nodes
+            .iter()
+            .position(|node| node.memory_zones.contains(&zone.id))
+        {
+            zone.host_numa_node = Some(host_nodes[placement[guest_node]].id);
+        }
+    }
+
+    if let Some(io_threads) = config.io_threads.as_mut() {
+        if io_threads.affinity.is_none() {
+            let mut used_nodes = placement.clone();
+            used_nodes.sort_unstable();
+            used_nodes.dedup();
+            io_threads.affinity = Some(
+                (0..io_threads.count)
+                    .map(|io_thread| IoThreadAffinity {
+                        io_thread,
+                        host_cpus: host_nodes[used_nodes[io_thread as usize % used_nodes.len()]]
+                            .cpus
+                            .clone(),
+                    })
+                    .collect(),
+            );
+        }
+    } else {
+        // Pinning the queues of disks sharing I/O threads would take them
+        // out of the pool.
+        for disk in config.disks.iter_mut().flatten() {
+            if disk.vhost_user || disk.queue_affinity.is_some() {
+                continue;
+            }
+            if let Some(host_node) = host_node_of(disk.pci_segment) {
+                disk.queue_affinity = Some(
+                    (0..disk.num_queues as u16)
+                        .map(|queue_index| VirtQueueAffinity {
+                            queue_index,
+                            host_cpus: host_node.cpus.clone(),
+                        })
+                        .collect(),
+                );
+            }
+        }
+    }
+}
+
+// Distances between guest nodes, given as their identifier along with the
+// host node they are bound to. Distinct guest nodes bound to the same host
+// node are kept apart from the local distance, which the guest would reject.
+fn guest_distances(
+    nodes: &[(u32, Option<u32>)],
+    host_distances: &BTreeMap<(u32, u32), u8>,
+) -> BTreeMap<u32, Vec<NumaDistance>> {
+    let mut distances = BTreeMap::new();
+    for &(source, source_host) in nodes.iter() {
+        let Some(source_host) = source_host else {
+            continue;
+        };
+        let list: Vec<NumaDistance> = nodes
+            .iter()
+            .filter(|(destination, _)| *destination != source)
+            .filter_map(|&(destination, destination_host)| {
+                let distance = host_distances.get(&(source_host, destination_host?))?;
+                Some(NumaDistance {
+                    destination,
+                    distance: (*distance).max(LOCAL_DISTANCE + 1),
+                })
+            })
+            .collect();
+        if !list.is_empty() {
+            distances.insert(source, list);
+        }
+    }
+
+    distances
+}
+
+/// Set the distances of the guest NUMA nodes which don't explicitly define
+/// them, from the distances between the host nodes their memory zones are
+/// bound to. Only the guest nodes whose memory zones are all bound to the
+/// same host node are considered.
+pub fn derive_distances(config: &mut VmConfig, host_distances: &BTreeMap<(u32, u32), u8>) {
+    let Some(numa) = config.numa.as_mut() else {
+        return;
+    };
+
+    let zones = config.memory.zones.as_deref().unwrap_or_default();
+    let host_node = |node: &NumaConfig| {
+        let mut host_nodes = node.memory_zones.iter().flatten().map(|id| {
+            zones
+                .iter()
+                .find(|zone| &zone.id == id)
+                .and_then(|zone| zone.host_numa_node)
+        });
+        let first = host_nodes.next()??;
+        host_nodes
+            .all(|host_node| host_node == Some(first))
+            .then_some(first)
+    };
+    let nodes: Vec<(u32, Option<u32>)> = numa
+        .iter()
+        .map(|node| (node.guest_numa_id, host_node(node)))
+        .collect();
+
+    let mut distances = guest_distances(&nodes, host_distances);
+    for node in numa.iter_mut().filter(|node| node.distances.is_none()) {
+        node.distances = distances.remove(&node.guest_numa_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn host_topology() -> Vec<HostNode> {
+        (0..3)
+            .map(|id| HostNode {
+                id,
+                cpus: vec![2 * id as usize, 2 * id as usize + 1],
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_cpu_list() {
+        assert_eq!(
+            parse_cpu_list("0-3,8,10-11\n").unwrap(),
+            vec![0, 1, 2, 3, 8, 10, 11]
+        );
+        assert_eq!(parse_cpu_list("\n").unwrap(), Vec::<usize>::new());
+        parse_cpu_list("0-a").unwrap_err();
+    }
+
+    #[test]
+    fn test_parse_distances() {
+        assert_eq!(parse_distances("10 21\n", 2).unwrap(), vec![10, 21]);
+        parse_distances("10 21 31\n", 2).unwrap_err();
+        parse_distances("10 256\n", 2).unwrap_err();
+    }
+
+    #[test]
+    fn test_guest_distances() {
+        let host_distances =
+            BTreeMap::from([((0, 0), 10), ((0, 1), 21), ((1, 0), 21), ((1, 1), 10)]);
+        let distance = |destination, distance| NumaDistance {
+            destination,
+            distance,
+        };
+
+        // Guest nodes bound to the same host node aren't local to each
+        // other, and the ones not bound to any are left out.
+        let nodes = [(0, Some(0)), (1, Some(1)), (2, Some(0)), (3, None)];
+        assert_eq!(
+            guest_distances(&nodes, &host_distances),
+            BTreeMap::from([
+                (0, vec![distance(1, 21), distance(2, 11)]),
+                (1, vec![distance(0, 21), distance(2, 21)]),
+                (2, vec![distance(0, 11), distance(1, 21)]),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_assign_host_nodes() {
+        let host_nodes = host_topology();
+
+        // Without any device, guest nodes are spread across host nodes.
+        let votes = vec![BTreeMap::new(); 4];
+        assert_eq!(assign_host_nodes(&votes, &host_nodes), vec![0, 1, 2, 0]);
+
+        // Guest nodes follow the locality of their devices.
+        let votes = vec![
+            BTreeMap::new(),
+            BTreeMap::from([(0, 1), (2, 3)]),
+            BTreeMap::from([(2, 1)]),
+        ];
+        assert_eq!(assign_host_nodes(&votes, &host_nodes), vec![0, 2, 2]);
+    }
+}
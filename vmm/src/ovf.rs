@@ -0,0 +1,1064 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Import of OVF appliances, packaged either as an OVF descriptor alongside
+//! the files it references, or as an OVA archive.
+//!
+//! The virtual hardware of the appliance is translated into the command line
+//! syntax of each device, as done for libvirt domains, so that the resulting
+//! configuration is validated like the command line one. Only the vCPUs,
+//! memory, disks, CD/DVD drives and network adapters are translated, the
+//! other devices being ignored and reported as such.
+//!
+//! VMDK disks are converted into raw images, the block layer not supporting
+//! this format. Other disk images are used in place when the package is a
+//! directory, and extracted when it is an archive.
+//!
+//! The properties of the appliance can be mapped to cloud-init data, using
+//! the keys understood by the cloud-init OVF datasource.
+
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use thiserror::Error;
+
+use crate::config::{self, VmParams};
+use crate::vm_config::{VmConfig, DEFAULT_RNG_SOURCE};
+use crate::xml::{self, Element};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Error reading the package")]
+    ReadPackage(#[source] io::Error),
+
+    #[error("Error writing the imported files")]
+    WriteFile(#[source] io::Error),
+
+    #[error("Invalid OVA archive")]
+    InvalidArchive,
+
+    #[error("No OVF descriptor in the package")]
+    MissingDescriptor,
+
+    #[error("Missing file {0} in the package")]
+    MissingFile(String),
+
+    #[error("Invalid OVF descriptor")]
+    InvalidXml(#[source] xml::Error),
+
+    #[error("Not an OVF descriptor")]
+    NotEnvelope,
+
+    #[error("Missing <{0}> element")]
+    MissingElement(&'static str),
+
+    #[error("Invalid {0} value: {1}")]
+    InvalidValue(&'static str, String),
+
+    #[error("Unsupported {0}: {1}")]
+    Unsupported(&'static str, String),
+
+    #[error("Invalid VMDK disk {0}")]
+    InvalidVmdk(String),
+
+    #[error("Invalid VM configuration")]
+    InvalidConfig(#[source] config::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Options of an appliance import.
+#[derive(Debug, Default)]
+pub struct ImportOptions {
+    /// Firmware booting the appliance from its disks.
+    pub firmware: PathBuf,
+    /// Directory in which the converted and extracted disks, as well as the
+    /// cloud-init data, are written.
+    pub disk_dir: PathBuf,
+    /// Whether the properties of the appliance are mapped to cloud-init data.
+    pub cloud_init: bool,
+    /// Values of the properties, overriding the defaults of the descriptor.
+    pub properties: Vec<(String, String)>,
+}
+
+/// VM configuration translated from an OVF appliance.
+pub struct Appliance {
+    pub config: VmConfig,
+    /// Devices of the appliance which have no equivalent, and were ignored.
+    pub ignored: Vec<String>,
+}
+
+const SECTOR_SIZE: u64 = 512;
+const TAR_BLOCK_SIZE: u64 = 512;
+const COPY_CHUNK_SIZE: usize = 1 << 20;
+
+// CIM resource types of the virtual hardware items.
+const RESOURCE_PROCESSOR: u32 = 3;
+const RESOURCE_MEMORY: u32 = 4;
+const RESOURCE_IDE_CONTROLLER: u32 = 5;
+const RESOURCE_SCSI_CONTROLLER: u32 = 6;
+const RESOURCE_ETHERNET_ADAPTER: u32 = 10;
+const RESOURCE_CD_DRIVE: u32 = 15;
+const RESOURCE_DVD_DRIVE: u32 = 16;
+const RESOURCE_DISK_DRIVE: u32 = 17;
+const RESOURCE_OTHER_STORAGE: u32 = 20;
+
+const VMDK_MAGIC: &[u8; 4] = b"KDMV";
+const VMDK_FLAG_ZEROED_GTE: u32 = 1 << 2;
+const VMDK_FLAG_COMPRESSED: u32 = 1 << 16;
+const VMDK_FLAG_MARKERS: u32 = 1 << 17;
+const VMDK_MARKER_EOS: u32 = 0;
+// Grains can't be larger than 1 MiB, bounding the memory used to convert
+// them.
+const VMDK_MAX_GRAIN_SIZE: u64 = 2048;
+
+// File of the package, the members of an archive being read in place.
+struct PackageFile {
+    file: File,
+    offset: u64,
+    size: u64,
+    // Path of the file, for the ones which can be used in place.
+    path: Option<PathBuf>,
+}
+
+impl PackageFile {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        if offset.saturating_add(buf.len() as u64) > self.size {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.file.read_exact_at(buf, self.offset + offset)
+    }
+
+    fn read_vec(&self, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+        let mut buf = vec![0; len.min(self.size) as usize];
+        self.read_at(&mut buf, offset)?;
+        if buf.len() as u64 != len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(buf)
+    }
+}
+
+struct Member {
+    name: String,
+    offset: u64,
+    size: u64,
+}
+
+enum Package {
+    Directory { descriptor: PathBuf, dir: PathBuf },
+    Archive { file: File, members: Vec<Member> },
+}
+
+fn tar_field(field: &[u8]) -> &str {
+    let len = field.iter().position(|b| *b == 0).unwrap_or(field.len());
+    std::str::from_utf8(&field[..len]).unwrap_or_default()
+}
+
+fn tar_members(file: &File) -> Result<Vec<Member>> {
+    let len = file.metadata().map_err(Error::ReadPackage)?.len();
+    let mut members = Vec::new();
+    let mut header = [0u8; TAR_BLOCK_SIZE as usize];
+    let mut offset = 0;
+
+    while offset + TAR_BLOCK_SIZE <= len {
+        file.read_exact_at(&mut header, offset)
+            .map_err(Error::ReadPackage)?;
+        // The archive is terminated by zero blocks.
+        if header.iter().all(|b| *b == 0) {
+            break;
+        }
+
+        let size = tar_field(&header[124..136]).trim_matches(' ');
+        let size = u64::from_str_radix(size, 8).map_err(|_| Error::InvalidArchive)?;
+        let data = offset + TAR_BLOCK_SIZE;
+        if size > len - data {
+            return Err(Error::InvalidArchive);
+        }
+
+        // Only regular files are of interest, the extended headers and
+        // directories being skipped.
+        if matches!(header[156], b'0' | b'\0') {
+            let name = tar_field(&header[..100]);
+            let prefix = tar_field(&header[345..500]);
+            members.push(Member {
+                name: if prefix.is_empty() {
+                    name.to_string()
+                } else {
+                    format!("{prefix}/{name}")
+                },
+                offset: data,
+                size,
+            });
+        }
+
+        offset = data + size.div_ceil(TAR_BLOCK_SIZE) * TAR_BLOCK_SIZE;
+    }
+
+    Ok(members)
+}
+
+// Names of the files referenced by the descriptor, which must be located in
+// the package.
+fn file_name(href: &str) -> Result<&str> {
+    let name = href.trim_start_matches("./");
+    if name.is_empty()
+        || name.contains("://")
+        || name.starts_with('/')
+        || name.split('/').any(|c| c == "..")
+    {
+        return Err(Error::Unsupported("file reference", href.to_string()));
+    }
+
+    Ok(name)
+}
+
+impl Package {
+    fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path).map_err(Error::ReadPackage)?;
+
+        // OVA archives are USTAR ones.
+        let mut header = [0u8; TAR_BLOCK_SIZE as usize];
+        if file.read_exact_at(&mut header, 0).is_ok() && header[257..262] == *b"ustar" {
+            let members = tar_members(&file)?;
+            return Ok(Package::Archive { file, members });
+        }
+
+        Ok(Package::Directory {
+            descriptor: path.to_path_buf(),
+            dir: path.parent().unwrap_or(Path::new(".")).to_path_buf(),
+        })
+    }
+
+    fn descriptor(&self) -> Result<String> {
+        let data = match self {
+            Package::Directory { descriptor, .. } => {
+                fs::read(descriptor).map_err(Error::ReadPackage)?
+            }
+            Package::Archive { members, .. } => {
+                let member = members
+                    .iter()
+                    .find(|m| m.name.ends_with(".ovf"))
+                    .ok_or(Error::MissingDescriptor)?;
+                self.file(&member.name)?
+                    .read_vec(0, member.size)
+                    .map_err(Error::ReadPackage)?
+            }
+        };
+
+        String::from_utf8(data)
+            .map_err(|e| Error::InvalidXml(xml::Error(e.utf8_error().valid_up_to())))
+    }
+
+    fn file(&self, href: &str) -> Result<PackageFile> {
+        let name = file_name(href)?;
+        match self {
+            Package::Directory { dir, .. } => {
+                let path = dir.join(name);
+                let file = File::open(&path).map_err(|_| Error::MissingFile(name.to_string()))?;
+                Ok(PackageFile {
+                    size: file.metadata().map_err(Error::ReadPackage)?.len(),
+                    file,
+                    offset: 0,
+                    path: Some(path),
+                })
+            }
+            Package::Archive { file, members } => {
+                let member = members
+                    .iter()
+                    .find(|m| m.name.trim_start_matches("./") == name)
+                    .ok_or_else(|| Error::MissingFile(name.to_string()))?;
+                Ok(PackageFile {
+                    file: file.try_clone().map_err(Error::ReadPackage)?,
+                    offset: member.offset,
+                    size: member.size,
+                    path: None,
+                })
+            }
+        }
+    }
+}
+
+// Values are quoted so that they can't be confused with other options.
+fn quote(value: &str) -> String {
+    format!("\"{value}\"")
+}
+
+fn parse_number<T: std::str::FromStr>(name: &'static str, value: &str) -> Result<T> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| Error::InvalidValue(name, value.to_string()))
+}
+
+// Scale of allocation units such as "byte * 2^20", as defined by DSP0004.
+fn allocation_units(units: &str) -> Result<u64> {
+    let scale = match units.trim() {
+        "" | "byte" | "bytes" => Some(1),
+        "KB" | "KiloBytes" => Some(1 << 10),
+        "MB" | "MegaBytes" => Some(1 << 20),
+        "GB" | "GigaBytes" => Some(1 << 30),
+        "TB" | "TeraBytes" => Some(1 << 40),
+        units => units
+            .strip_prefix("byte")
+            .map(|u| u.trim_start())
+            .and_then(|u| u.strip_prefix('*'))
+            .map(|u| u.trim_start())
+            .and_then(|u| u.strip_prefix("2^"))
+            .and_then(|e| e.trim().parse::<u32>().ok())
+            .and_then(|e| 1u64.checked_shl(e)),
+    };
+
+    scale.ok_or_else(|| Error::InvalidValue("allocation units", units.to_string()))
+}
+
+fn size(name: &'static str, quantity: &str, units: Option<&str>) -> Result<u64> {
+    parse_number::<u64>(name, quantity)?
+        .checked_mul(allocation_units(units.unwrap_or_default())?)
+        .ok_or_else(|| Error::InvalidValue(name, quantity.to_string()))
+}
+
+struct Vmdk<'a> {
+    name: &'a str,
+    source: &'a PackageFile,
+    flags: u32,
+    capacity: u64,
+    grain_size: u64,
+    gt_entries: u64,
+    gd_offset: u64,
+    overhead: u64,
+}
+
+impl<'a> Vmdk<'a> {
+    fn error(&self) -> Error {
+        Error::InvalidVmdk(self.name.to_string())
+    }
+
+    fn new(name: &'a str, source: &'a PackageFile) -> Result<Self> {
+        let header = source
+            .read_vec(0, SECTOR_SIZE)
+            .map_err(Error::ReadPackage)?;
+        let u32_at = |o: usize| u32::from_le_bytes(header[o..o + 4].try_into().unwrap());
+        let u64_at = |o: usize| u64::from_le_bytes(header[o..o + 8].try_into().unwrap());
+
+        let vmdk = Vmdk {
+            name,
+            source,
+            flags: u32_at(8),
+            capacity: u64_at(12),
+            grain_size: u64_at(20),
+            gt_entries: u32_at(44) as u64,
+            gd_offset: u64_at(56),
+            overhead: u64_at(64),
+        };
+        if !vmdk.grain_size.is_power_of_two()
+            || vmdk.grain_size > VMDK_MAX_GRAIN_SIZE
+            || vmdk.gt_entries == 0
+            || vmdk.capacity.checked_mul(SECTOR_SIZE).is_none()
+        {
+            return Err(vmdk.error());
+        }
+
+        Ok(vmdk)
+    }
+
+    fn grain_bytes(&self) -> u64 {
+        self.grain_size * SECTOR_SIZE
+    }
+
+    // Compressed grains are prefixed with their LBA and compressed size.
+    fn read_compressed_grain(&self, offset: u64) -> Result<(u64, Vec<u8>, u64)> {
+        let header = self
+            .source
+            .read_vec(offset, 12)
+            .map_err(Error::ReadPackage)?;
+        let lba = u64::from_le_bytes(header[..8].try_into().unwrap());
+        let size = u32::from_le_bytes(header[8..].try_into().unwrap()) as u64;
+        let data = self
+            .source
+            .read_vec(offset + 12, size)
+            .map_err(Error::ReadPackage)?;
+        let grain = miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(
+            &data,
+            self.grain_bytes() as usize,
+        )
+        .map_err(|_| self.error())?;
+
+        Ok((lba, grain, size))
+    }
+
+    fn write_grain(&self, output: &File, lba: u64, grain: &[u8]) -> Result<()> {
+        let offset = lba.checked_mul(SECTOR_SIZE).ok_or_else(|| self.error())?;
+        if offset.saturating_add(grain.len() as u64) > self.capacity * SECTOR_SIZE {
+            return Err(self.error());
+        }
+        // Zeroed grains are left as holes.
+        if grain.iter().all(|b| *b == 0) {
+            return Ok(());
+        }
+        output.write_all_at(grain, offset).map_err(Error::WriteFile)
+    }
+
+    // Stream optimized disks are read sequentially, following their markers
+    // rather than their grain directory, which is usually located at the end
+    // of the file.
+    fn convert_stream(&self, output: &File) -> Result<()> {
+        let mut offset = self.overhead * SECTOR_SIZE;
+        loop {
+            let header = self
+                .source
+                .read_vec(offset, 16)
+                .map_err(Error::ReadPackage)?;
+            let value = u64::from_le_bytes(header[..8].try_into().unwrap());
+            let size = u32::from_le_bytes(header[8..12].try_into().unwrap()) as u64;
+            if size != 0 {
+                let (lba, grain, size) = self.read_compressed_grain(offset)?;
+                self.write_grain(output, lba, &grain)?;
+                offset = (offset + 12 + size).next_multiple_of(SECTOR_SIZE);
+                continue;
+            }
+
+            // Markers are followed by the number of metadata sectors given
+            // in place of the LBA.
+            if u32::from_le_bytes(header[12..].try_into().unwrap()) == VMDK_MARKER_EOS {
+                return Ok(());
+            }
+            offset = value
+                .checked_add(1)
+                .and_then(|s| s.checked_mul(SECTOR_SIZE))
+                .and_then(|s| s.checked_add(offset))
+                .ok_or_else(|| self.error())?;
+        }
+    }
+
+    fn convert_sparse(&self, output: &File) -> Result<()> {
+        let entry = |offset: u64| -> Result<u64> {
+            let mut buf = [0u8; 4];
+            self.source
+                .read_at(&mut buf, offset)
+                .map_err(Error::ReadPackage)?;
+            Ok(u32::from_le_bytes(buf) as u64)
+        };
+
+        let grains = self.capacity.div_ceil(self.grain_size);
+        for gd_index in 0..grains.div_ceil(self.gt_entries) {
+            let gt_offset = entry(self.gd_offset * SECTOR_SIZE + gd_index * 4)? * SECTOR_SIZE;
+            if gt_offset == 0 {
+                continue;
+            }
+
+            for gt_index in 0..self.gt_entries {
+                let grain_index = gd_index * self.gt_entries + gt_index;
+                if grain_index >= grains {
+                    break;
+                }
+                let grain_offset = entry(gt_offset + gt_index * 4)?;
+                if grain_offset == 0
+                    || (grain_offset == 1 && self.flags & VMDK_FLAG_ZEROED_GTE != 0)
+                {
+                    continue;
+                }
+
+                let lba = grain_index * self.grain_size;
+                let grain = if self.flags & VMDK_FLAG_COMPRESSED != 0 {
+                    self.read_compressed_grain(grain_offset * SECTOR_SIZE)?.1
+                } else {
+                    let len = self.grain_bytes().min((self.capacity - lba) * SECTOR_SIZE);
+                    self.source
+                        .read_vec(grain_offset * SECTOR_SIZE, len)
+                        .map_err(Error::ReadPackage)?
+                };
+                self.write_grain(output, lba, &grain)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn convert(&self, path: &Path) -> Result<()> {
+        let output = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .map_err(Error::WriteFile)?;
+        output
+            .set_len(self.capacity * SECTOR_SIZE)
+            .map_err(Error::WriteFile)?;
+
+        if self.flags & VMDK_FLAG_MARKERS != 0 {
+            self.convert_stream(&output)
+        } else {
+            self.convert_sparse(&output)
+        }
+    }
+}
+
+fn extract(source: &PackageFile, path: &Path) -> Result<()> {
+    let output = File::create(path).map_err(Error::WriteFile)?;
+    let mut buf = vec![0; COPY_CHUNK_SIZE];
+    let mut offset = 0;
+    while offset < source.size {
+        let len = (source.size - offset).min(COPY_CHUNK_SIZE as u64) as usize;
+        source
+            .read_at(&mut buf[..len], offset)
+            .map_err(Error::ReadPackage)?;
+        output
+            .write_all_at(&buf[..len], offset)
+            .map_err(Error::WriteFile)?;
+        offset += len as u64;
+    }
+
+    Ok(())
+}
+
+struct Importer<'a> {
+    package: Package,
+    envelope: Element,
+    disk_dir: PathBuf,
+    options: &'a ImportOptions,
+}
+
+impl Importer<'_> {
+    fn reference(&self, id: &str) -> Result<&Element> {
+        self.envelope
+            .child("References")
+            .into_iter()
+            .flat_map(|r| r.children("File"))
+            .find(|f| f.attr("id") == Some(id))
+            .ok_or_else(|| Error::InvalidValue("file reference", id.to_string()))
+    }
+
+    // Returns the path of the image for a file of the package, converting or
+    // extracting it as needed.
+    fn image(&self, id: &str) -> Result<PathBuf> {
+        let reference = self.reference(id)?;
+        let href = reference
+            .attr("href")
+            .ok_or(Error::MissingElement("File"))?;
+        if let Some(compression) = reference.attr("compression") {
+            return Err(Error::Unsupported("compression", compression.to_string()));
+        }
+
+        let name = file_name(href)?;
+        let source = self.package.file(name)?;
+        let base_name = Path::new(name).file_name().unwrap_or_default();
+
+        let mut magic = [0u8; 4];
+        if source.read_at(&mut magic, 0).is_ok() && magic == *VMDK_MAGIC {
+            let path = self
+                .disk_dir
+                .join(Path::new(base_name).with_extension("raw"));
+            Vmdk::new(name, &source)?.convert(&path)?;
+            return Ok(path);
+        }
+
+        match source.path {
+            Some(path) => Ok(path),
+            None => {
+                let path = self.disk_dir.join(base_name);
+                extract(&source, &path)?;
+                Ok(path)
+            }
+        }
+    }
+
+    // Returns the path of the image backing a disk, created empty when the
+    // disk doesn't reference any file.
+    fn disk_image(&self, id: &str) -> Result<PathBuf> {
+        let disk = self
+            .envelope
+            .child("DiskSection")
+            .into_iter()
+            .flat_map(|s| s.children("Disk"))
+            .find(|d| d.attr("diskId") == Some(id))
+            .ok_or_else(|| Error::InvalidValue("disk reference", id.to_string()))?;
+
+        if let Some(file_ref) = disk.attr("fileRef") {
+            return self.image(file_ref);
+        }
+
+        let capacity = size(
+            "disk capacity",
+            disk.attr("capacity").ok_or(Error::MissingElement("Disk"))?,
+            disk.attr("capacityAllocationUnits"),
+        )?;
+        let path = self
+            .disk_dir
+            .join(format!("{}.raw", file_name(id)?.replace('/', "_")));
+        File::create(&path)
+            .and_then(|f| f.set_len(capacity))
+            .map_err(Error::WriteFile)?;
+
+        Ok(path)
+    }
+
+    // Host resources are either disks, or files of the package.
+    fn host_resource(&self, resource: &str) -> Result<PathBuf> {
+        let resource = resource.trim();
+        if let Some(id) = resource
+            .strip_prefix("ovf:/disk/")
+            .or_else(|| resource.strip_prefix("/disk/"))
+        {
+            self.disk_image(id)
+        } else if let Some(id) = resource
+            .strip_prefix("ovf:/file/")
+            .or_else(|| resource.strip_prefix("/file/"))
+        {
+            self.image(id)
+        } else {
+            Err(Error::Unsupported("host resource", resource.to_string()))
+        }
+    }
+
+    fn properties(&self, system: &Element) -> Vec<(String, String)> {
+        let mut properties: Vec<(String, String)> = system
+            .children("ProductSection")
+            .flat_map(|s| s.children("Property"))
+            .filter_map(|p| {
+                let value = p.attr("value").unwrap_or_default();
+                Some((p.attr("key")?.to_string(), value.to_string()))
+            })
+            .collect();
+        properties.extend(self.options.properties.iter().cloned());
+
+        properties
+    }
+
+    // Maps the properties understood by the cloud-init OVF datasource to
+    // NoCloud data.
+    fn cloud_init(&self, system: &Element) -> Result<String> {
+        let properties = self.properties(system);
+        let property = |key: &str| {
+            properties
+                .iter()
+                .rev()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.as_str())
+                .filter(|v| !v.is_empty())
+        };
+        // JSON strings are valid YAML ones.
+        let yaml = |value: &str| serde_json::to_string(value).unwrap();
+
+        let instance_id = property("instance-id")
+            .or_else(|| system.attr("id"))
+            .unwrap_or("iid-ovf");
+        let mut meta_data = format!("instance-id: {}\n", yaml(instance_id));
+        if let Some(hostname) = property("hostname") {
+            meta_data.push_str(&format!("local-hostname: {}\n", yaml(hostname)));
+        }
+        if let Some(keys) = property("public-keys") {
+            meta_data.push_str("public-keys:\n");
+            for key in keys.lines().map(|k| k.trim()).filter(|k| !k.is_empty()) {
+                meta_data.push_str(&format!("  - {}\n", yaml(key)));
+            }
+        }
+
+        let user_data = match property("user-data") {
+            // The data may be wrapped over several lines.
+            Some(data) => STANDARD
+                .decode(data.split_whitespace().collect::<String>())
+                .map_err(|_| Error::InvalidValue("user-data", data.to_string()))?,
+            None => {
+                let mut user_data = "#cloud-config\n".to_string();
+                if let Some(password) = property("password") {
+                    user_data.push_str(&format!(
+                        "password: {}\nchpasswd: {{expire: false}}\nssh_pwauth: true\n",
+                        yaml(password)
+                    ));
+                }
+                user_data.into_bytes()
+            }
+        };
+
+        let meta_data_path = self.disk_dir.join("meta-data");
+        let user_data_path = self.disk_dir.join("user-data");
+        fs::write(&meta_data_path, meta_data).map_err(Error::WriteFile)?;
+        fs::write(&user_data_path, user_data).map_err(Error::WriteFile)?;
+
+        Ok(format!(
+            "user_data={},meta_data={}",
+            quote(&user_data_path.to_string_lossy()),
+            quote(&meta_data_path.to_string_lossy())
+        ))
+    }
+}
+
+/// Imports the OVF appliance at `path`, either an OVF descriptor or an OVA
+/// archive, into a VM configuration.
+pub fn import(path: &Path, options: &ImportOptions) -> Result<Appliance> {
+    // The paths of the configuration are resolved by the VMM, from its own
+    // working directory.
+    let path = std::path::absolute(path).map_err(Error::ReadPackage)?;
+    let disk_dir = std::path::absolute(&options.disk_dir).map_err(Error::WriteFile)?;
+
+    let package = Package::open(&path)?;
+    let envelope = xml::parse(&package.descriptor()?).map_err(Error::InvalidXml)?;
+    if envelope.local_name() != "Envelope" {
+        return Err(Error::NotEnvelope);
+    }
+    if envelope.child("VirtualSystemCollection").is_some() {
+        return Err(Error::Unsupported(
+            "virtual system",
+            "VirtualSystemCollection".to_string(),
+        ));
+    }
+
+    let importer = Importer {
+        package,
+        envelope,
+        disk_dir,
+        options,
+    };
+    let system = importer
+        .envelope
+        .child("VirtualSystem")
+        .ok_or(Error::MissingElement("VirtualSystem"))?;
+    let hardware = system
+        .child("VirtualHardwareSection")
+        .ok_or(Error::MissingElement("VirtualHardwareSection"))?;
+
+    let mut ignored = Vec::new();
+    let mut cpus = None;
+    let mut memory = None;
+    let mut disks = Vec::new();
+    let mut net = Vec::new();
+
+    for item in hardware
+        .children
+        .iter()
+        .filter(|c| c.local_name().ends_with("Item"))
+    {
+        let resource_type: u32 = parse_number(
+            "ResourceType",
+            item.child_text("ResourceType")
+                .ok_or(Error::MissingElement("ResourceType"))?,
+        )?;
+        let quantity = || {
+            item.child_text("VirtualQuantity")
+                .ok_or(Error::MissingElement("VirtualQuantity"))
+        };
+
+        match resource_type {
+            RESOURCE_PROCESSOR => {
+                let vcpus: u32 = parse_number("VirtualQuantity", quantity()?)?;
+                let mut options = format!("boot={vcpus}");
+                if let Some(cores) = item.child_text("CoresPerSocket") {
+                    let cores: u32 = parse_number("CoresPerSocket", cores)?;
+                    if cores == 0 || vcpus % cores != 0 {
+                        return Err(Error::InvalidValue("CoresPerSocket", cores.to_string()));
+                    }
+                    options.push_str(&format!(",topology=1:{cores}:1:{}", vcpus / cores));
+                }
+                cpus = Some(options);
+            }
+            RESOURCE_MEMORY => {
+                let size = size(
+                    "VirtualQuantity",
+                    quantity()?,
+                    item.child_text("AllocationUnits"),
+                )?;
+                memory = Some(format!("size={size}"));
+            }
+            RESOURCE_ETHERNET_ADAPTER => {
+                // The networks of the appliance have no equivalent, the
+                // adapters being backed by TAP interfaces created by the VMM.
+                net.push(
+                    item.child_text("Address")
+                        .map(|mac| format!("mac={mac}"))
+                        .unwrap_or_default(),
+                );
+            }
+            RESOURCE_DISK_DRIVE | RESOURCE_CD_DRIVE | RESOURCE_DVD_DRIVE => {
+                match item.child_text("HostResource") {
+                    Some(resource) => {
+                        let path = importer.host_resource(resource)?;
+                        let mut options = format!("path={}", quote(&path.to_string_lossy()));
+                        if resource_type != RESOURCE_DISK_DRIVE {
+                            options.push_str(",readonly=on");
+                        }
+                        disks.push(options);
+                    }
+                    None if resource_type != RESOURCE_DISK_DRIVE => {
+                        ignored.push("empty cdrom".to_string())
+                    }
+                    None => return Err(Error::MissingElement("HostResource")),
+                }
+            }
+            // Disks and CD/DVD drives are attached to virtio controllers.
+            RESOURCE_IDE_CONTROLLER | RESOURCE_SCSI_CONTROLLER | RESOURCE_OTHER_STORAGE => {}
+            _ => ignored.push(
+                item.child_text("ElementName")
+                    .map(|n| n.to_string())
+                    .unwrap_or_else(|| format!("resource type {resource_type}")),
+            ),
+        }
+    }
+
+    let cpus = cpus.unwrap_or_else(|| "boot=1".to_string());
+    let memory = memory.ok_or(Error::MissingElement("Item"))?;
+    let firmware = options.firmware.to_string_lossy();
+    let rng = format!("src={DEFAULT_RNG_SOURCE}");
+    let cloud_init = if options.cloud_init {
+        Some(importer.cloud_init(system)?)
+    } else {
+        None
+    };
+
+    let list = |items: &[String]| -> Option<Vec<&str>> {
+        (!items.is_empty()).then(|| items.iter().map(|i| i.as_str()).collect())
+    };
+
+    let vm_params = VmParams {
+        cpus: &cpus,
+        memory: &memory,
+        memory_zones: None,
+        firmware: Some(&*firmware),
+        fallback_firmware: None,
+        kernel: None,
+        initramfs: None,
+        cmdline: None,
+        rate_limit_groups: None,
+        rate_limit_schedules: None,
+        disks: list(&disks),
+        net: list(&net),
+        rng: &rng,
+        balloon: None,
+        fs: None,
+        pmem: None,
+        serial: "null",
+        console: "tty",
+        #[cfg(target_arch = "x86_64")]
+        debug_console: "off",
+        devices: None,
+        user_devices: None,
+        vdpa: None,
+        vsock: None,
+        #[cfg(feature = "pvmemcontrol")]
+        pvmemcontrol: None,
+        pvpanic: false,
+        #[cfg(target_arch = "x86_64")]
+        sgx_epc: None,
+        numa: None,
+        watchdog: false,
+        #[cfg(feature = "guest_debug")]
+        gdb: false,
+        pci_segments: None,
+        platform: None,
+        tpm: None,
+        scmi: None,
+        cloud_init: cloud_init.as_deref(),
+        imds: None,
+        netns: None,
+        guest_agent: None,
+        resources: None,
+        rtc: None,
+        restart_policy: None,
+        crash_dump: None,
+        io_threads: None,
+        #[cfg(feature = "igvm")]
+        igvm: None,
+        #[cfg(feature = "sev_snp")]
+        host_data: None,
+        #[cfg(feature = "sev_snp")]
+        id_block: None,
+        #[cfg(feature = "sev_snp")]
+        id_auth: None,
+        #[cfg(feature = "sev_snp")]
+        snp_certs: None,
+        landlock_enable: false,
+        landlock_rules: None,
+    };
+
+    Ok(Appliance {
+        config: VmConfig::parse(vm_params).map_err(Error::InvalidConfig)?,
+        ignored,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use vmm_sys_util::tempdir::TempDir;
+
+    use super::*;
+
+    fn tar_header(name: &str, size: usize) -> Vec<u8> {
+        let mut header = vec![0u8; TAR_BLOCK_SIZE as usize];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[124..136].copy_from_slice(format!("{size:011o}\0").as_bytes());
+        header[156] = b'0';
+        header[257..263].copy_from_slice(b"ustar\0");
+        header
+    }
+
+    fn stream_optimized_vmdk(capacity: u64, lba: u64, grain: &[u8]) -> Vec<u8> {
+        let mut vmdk = vec![0u8; SECTOR_SIZE as usize];
+        vmdk[..4].copy_from_slice(VMDK_MAGIC);
+        vmdk[4..8].copy_from_slice(&3u32.to_le_bytes());
+        vmdk[8..12].copy_from_slice(&(VMDK_FLAG_COMPRESSED | VMDK_FLAG_MARKERS).to_le_bytes());
+        vmdk[12..20].copy_from_slice(&capacity.to_le_bytes());
+        vmdk[20..28].copy_from_slice(&8u64.to_le_bytes());
+        vmdk[44..48].copy_from_slice(&512u32.to_le_bytes());
+        vmdk[56..64].copy_from_slice(&u64::MAX.to_le_bytes());
+        vmdk[64..72].copy_from_slice(&1u64.to_le_bytes());
+
+        let data = miniz_oxide::deflate::compress_to_vec_zlib(grain, 6);
+        vmdk.extend_from_slice(&lba.to_le_bytes());
+        vmdk.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        vmdk.extend_from_slice(&data);
+        vmdk.resize(vmdk.len().next_multiple_of(SECTOR_SIZE as usize), 0);
+        // End of stream marker.
+        vmdk.resize(vmdk.len() + SECTOR_SIZE as usize, 0);
+        vmdk
+    }
+
+    #[test]
+    fn test_allocation_units() {
+        assert_eq!(allocation_units("byte * 2^20").unwrap(), 1 << 20);
+        assert_eq!(allocation_units("byte*2^30").unwrap(), 1 << 30);
+        assert_eq!(allocation_units("MegaBytes").unwrap(), 1 << 20);
+        assert_eq!(allocation_units("").unwrap(), 1);
+        allocation_units("byte * 2^64").unwrap_err();
+        allocation_units("pages").unwrap_err();
+    }
+
+    #[test]
+    fn test_import_ova() {
+        let dir = TempDir::new_with_prefix("/tmp/ch-ovf").unwrap();
+        let grain = vec![0xa5u8; 8 * SECTOR_SIZE as usize];
+        let vmdk = stream_optimized_vmdk(64, 16, &grain);
+        let descriptor = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<Envelope xmlns="http://schemas.dmtf.org/ovf/envelope/1"
+    xmlns:ovf="http://schemas.dmtf.org/ovf/envelope/1"
+    xmlns:rasd="http://schemas.dmtf.org/wbem/wscim/1/cim-schema/2/CIM_ResourceAllocationSettingData">
+  <References>
+    <File ovf:id="file1" ovf:href="disk1.vmdk" ovf:size="{}"/>
+  </References>
+  <DiskSection>
+    <Disk ovf:diskId="vmdisk1" ovf:fileRef="file1" ovf:capacity="32768"/>
+  </DiskSection>
+  <VirtualSystem ovf:id="vm0">
+    <ProductSection>
+      <Property ovf:key="hostname" ovf:type="string" ovf:value="vm0"/>
+      <Property ovf:key="user-data" ovf:type="string" ovf:value=""/>
+    </ProductSection>
+    <VirtualHardwareSection>
+      <Item>
+        <rasd:ElementName>2 virtual CPUs</rasd:ElementName>
+        <rasd:ResourceType>3</rasd:ResourceType>
+        <rasd:VirtualQuantity>2</rasd:VirtualQuantity>
+      </Item>
+      <Item>
+        <rasd:AllocationUnits>byte * 2^20</rasd:AllocationUnits>
+        <rasd:ResourceType>4</rasd:ResourceType>
+        <rasd:VirtualQuantity>1024</rasd:VirtualQuantity>
+      </Item>
+      <Item>
+        <rasd:ResourceType>6</rasd:ResourceType>
+      </Item>
+      <Item>
+        <rasd:HostResource>ovf:/disk/vmdisk1</rasd:HostResource>
+        <rasd:ResourceType>17</rasd:ResourceType>
+      </Item>
+      <Item>
+        <rasd:ResourceType>15</rasd:ResourceType>
+      </Item>
+      <Item>
+        <rasd:Address>52:54:00:12:34:56</rasd:Address>
+        <rasd:Connection>VM Network</rasd:Connection>
+        <rasd:ResourceType>10</rasd:ResourceType>
+      </Item>
+      <Item>
+        <rasd:ElementName>Sound card</rasd:ElementName>
+        <rasd:ResourceType>35</rasd:ResourceType>
+      </Item>
+    </VirtualHardwareSection>
+  </VirtualSystem>
+</Envelope>"#,
+            vmdk.len()
+        );
+
+        let mut ova = Vec::new();
+        for (name, data) in [
+            ("vm0.ovf", descriptor.as_bytes()),
+            ("disk1.vmdk", vmdk.as_slice()),
+        ] {
+            ova.extend_from_slice(&tar_header(name, data.len()));
+            ova.extend_from_slice(data);
+            ova.resize(ova.len().next_multiple_of(TAR_BLOCK_SIZE as usize), 0);
+        }
+        ova.resize(ova.len() + 2 * TAR_BLOCK_SIZE as usize, 0);
+        let ova_path = dir.as_path().join("vm0.ova");
+        fs::write(&ova_path, ova).unwrap();
+
+        let options = ImportOptions {
+            firmware: PathBuf::from("/opt/hypervisor-fw"),
+            disk_dir: dir.as_path().to_path_buf(),
+            cloud_init: true,
+            properties: vec![("user-data".to_string(), "I2Nsb3VkLWNvbmZpZwo=".to_string())],
+        };
+        let appliance = import(&ova_path, &options).unwrap();
+
+        let config = appliance.config;
+        assert_eq!(config.cpus.boot_vcpus, 2);
+        assert_eq!(config.memory.size, 1 << 30);
+
+        let disks = config.disks.unwrap();
+        assert_eq!(disks.len(), 1);
+        let disk_path = dir.as_path().join("disk1.raw");
+        assert_eq!(disks[0].path.as_ref(), Some(&disk_path));
+        let disk = fs::read(disk_path).unwrap();
+        assert_eq!(disk.len(), 64 * SECTOR_SIZE as usize);
+        assert!(disk[..8 * SECTOR_SIZE as usize].iter().all(|b| *b == 0));
+        assert_eq!(
+            disk[16 * SECTOR_SIZE as usize..24 * SECTOR_SIZE as usize],
+            grain
+        );
+
+        let net = config.net.unwrap();
+        assert_eq!(net[0].mac.to_string(), "52:54:00:12:34:56");
+
+        assert_eq!(
+            fs::read_to_string(dir.as_path().join("meta-data")).unwrap(),
+            "instance-id: \"vm0\"\nlocal-hostname: \"vm0\"\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.as_path().join("user-data")).unwrap(),
+            "#cloud-config\n"
+        );
+        assert_eq!(appliance.ignored, vec!["empty cdrom", "Sound card"]);
+    }
+
+    #[test]
+    fn test_import_unsupported() {
+        let dir = TempDir::new_with_prefix("/tmp/ch-ovf").unwrap();
+        let path = dir.as_path().join("vm0.ovf");
+        let options = ImportOptions {
+            disk_dir: dir.as_path().to_path_buf(),
+            ..Default::default()
+        };
+
+        fs::write(&path, "<domain/>").unwrap();
+        assert!(matches!(import(&path, &options), Err(Error::NotEnvelope)));
+
+        fs::write(
+            &path,
+            "<Envelope><References><File id='f' href='../disk.img'/></References>\
+             <VirtualSystem><VirtualHardwareSection><Item><ResourceType>17</ResourceType>\
+             <HostResource>ovf:/file/f</HostResource></Item></VirtualHardwareSection>\
+             </VirtualSystem></Envelope>",
+        )
+        .unwrap();
+        assert!(matches!(
+            import(&path, &options),
+            Err(Error::Unsupported("file reference", _))
+        ));
+    }
+}
@@ -48,6 +48,13 @@ pub(crate) struct PciSegment {
 
     pub(crate) mem32_allocator: Arc<Mutex<AddressAllocator>>,
     pub(crate) mem64_allocator: Arc<Mutex<AddressAllocator>>,
+
+    // Whether the segment can be added at runtime, being reserved at boot.
+    pub(crate) hotpluggable: bool,
+    // Whether the segment is exposed to the guest.
+    pub(crate) present: bool,
+    // Whether the segment was added since the guest last checked.
+    pub(crate) added: bool,
 }
 
 impl PciSegment {
@@ -58,6 +65,7 @@ impl PciSegment {
         mem32_allocator: Arc<Mutex<AddressAllocator>>,
         mem64_allocator: Arc<Mutex<AddressAllocator>>,
         pci_irq_slots: &[u8; 32],
+        hotpluggable: bool,
     ) -> DeviceManagerResult<PciSegment> {
         let pci_root = PciRoot::new(None);
         let pci_bus = Arc::new(Mutex::new(PciBus::new(
@@ -69,22 +77,13 @@ impl PciSegment {
         let mmio_config_address =
             layout::PCI_MMCONFIG_START.0 + layout::PCI_MMIO_CONFIG_SIZE_PER_SEGMENT * id as u64;
 
-        address_manager
-            .mmio_bus
-            .insert(
-                Arc::clone(&pci_config_mmio) as Arc<dyn BusDeviceSync>,
-                mmio_config_address,
-                layout::PCI_MMIO_CONFIG_SIZE_PER_SEGMENT,
-            )
-            .map_err(DeviceManagerError::BusError)?;
-
         let start_of_mem32_area = mem32_allocator.lock().unwrap().base().0;
         let end_of_mem32_area = mem32_allocator.lock().unwrap().end().0;
 
         let start_of_mem64_area = mem64_allocator.lock().unwrap().base().0;
         let end_of_mem64_area = mem64_allocator.lock().unwrap().end().0;
 
-        let segment = PciSegment {
+        let mut segment = PciSegment {
             id,
             pci_bus,
             pci_config_mmio,
@@ -101,13 +100,52 @@ impl PciSegment {
             start_of_mem64_area,
             end_of_mem64_area,
             pci_irq_slots: *pci_irq_slots,
+            hotpluggable,
+            present: false,
+            added: false,
         };
 
+        if hotpluggable {
+            info!(
+                "Reserving PCI segment: id={}, PCI MMIO config address: 0x{:x}",
+                segment.id, segment.mmio_config_address
+            );
+        } else {
+            segment.plug(address_manager)?;
+        }
+
+        Ok(segment)
+    }
+
+    // Exposes the configuration space of the segment, whose devices can
+    // then be enumerated by the guest.
+    fn plug(&mut self, address_manager: &Arc<AddressManager>) -> DeviceManagerResult<()> {
+        address_manager
+            .mmio_bus
+            .insert(
+                Arc::clone(&self.pci_config_mmio) as Arc<dyn BusDeviceSync>,
+                self.mmio_config_address,
+                layout::PCI_MMIO_CONFIG_SIZE_PER_SEGMENT,
+            )
+            .map_err(DeviceManagerError::BusError)?;
+        self.present = true;
+
         info!(
             "Adding PCI segment: id={}, PCI MMIO config address: 0x{:x}, mem32 area [0x{:x}-0x{:x}, mem64 area [0x{:x}-0x{:x}",
-            segment.id, segment.mmio_config_address, segment.start_of_mem32_area, segment.end_of_mem32_area, segment.start_of_mem64_area, segment.end_of_mem64_area
+            self.id, self.mmio_config_address, self.start_of_mem32_area, self.end_of_mem32_area, self.start_of_mem64_area, self.end_of_mem64_area
         );
-        Ok(segment)
+        Ok(())
+    }
+
+    /// Adds a segment reserved at boot, the guest being notified through
+    /// ACPI.
+    pub(crate) fn hotplug(
+        &mut self,
+        address_manager: &Arc<AddressManager>,
+    ) -> DeviceManagerResult<()> {
+        self.plug(address_manager)?;
+        self.added = true;
+        Ok(())
     }
 
     #[cfg(target_arch = "x86_64")]
@@ -124,6 +162,7 @@ impl PciSegment {
             mem32_allocator,
             mem64_allocator,
             pci_irq_slots,
+            false,
         )?;
         let pci_config_io = Arc::new(Mutex::new(PciConfigIo::new(Arc::clone(&segment.pci_bus))));
 
@@ -155,6 +194,7 @@ impl PciSegment {
             mem32_allocator,
             mem64_allocator,
             pci_irq_slots,
+            false,
         )
     }
 
@@ -280,6 +320,56 @@ impl Aml for PciDevSlotMethods {
     }
 }
 
+// Methods of a segment reserved at boot, the guest finding it absent until
+// it's added.
+struct PciSegmentHotplugMethods {
+    id: u16,
+}
+
+impl Aml for PciSegmentHotplugMethods {
+    fn to_aml_bytes(&self, sink: &mut dyn acpi_tables::AmlSink) {
+        aml::Method::new(
+            "_STA".into(),
+            0,
+            true,
+            vec![
+                &aml::Acquire::new("\\_SB_.PHPR.BLCK".into(), 0xffff),
+                &aml::Store::new(&aml::Path::new("\\_SB_.PHPR.PSEG"), &aml::Path::new("_SEG")),
+                &aml::Store::new(&aml::Local(0), &aml::ZERO),
+                // Check if the segment is present, if so make the local
+                // variable 0xf (present, enabled, shown and functioning)
+                &aml::If::new(
+                    &aml::Equal::new(&aml::Path::new("\\_SB_.PHPR.PSPR"), &aml::ONE),
+                    vec![&aml::Store::new(&aml::Local(0), &0xfu8)],
+                ),
+                &aml::Release::new("\\_SB_.PHPR.BLCK".into()),
+                &aml::Return::new(&aml::Local(0)),
+            ],
+        )
+        .to_aml_bytes(sink);
+
+        let object = aml::Path::new(&format!("\\_SB_.PC{:02X}", self.id));
+        aml::Method::new(
+            "SGNT".into(),
+            0,
+            true,
+            vec![
+                &aml::Acquire::new("\\_SB_.PHPR.BLCK".into(), 0xffff),
+                &aml::Store::new(&aml::Path::new("\\_SB_.PHPR.PSEG"), &aml::Path::new("_SEG")),
+                // Reading PSAD clears it
+                &aml::Store::new(&aml::Local(0), &aml::Path::new("\\_SB_.PHPR.PSAD")),
+                &aml::Release::new("\\_SB_.PHPR.BLCK".into()),
+                // Notify the host bridge with a device check
+                &aml::If::new(
+                    &aml::Equal::new(&aml::Local(0), &aml::ONE),
+                    vec![&aml::Notify::new(&object, &aml::ONE)],
+                ),
+            ],
+        )
+        .to_aml_bytes(sink)
+    }
+}
+
 struct PciDsmMethod {}
 
 impl Aml for PciDsmMethod {
@@ -443,6 +533,11 @@ impl Aml for PciSegment {
         let pci_device_methods = PciDevSlotMethods {};
         pci_dsdt_inner_data.push(&pci_device_methods);
 
+        let segment_methods = PciSegmentHotplugMethods { id: self.id };
+        if self.hotpluggable {
+            pci_dsdt_inner_data.push(&segment_methods);
+        }
+
         // Build PCI routing table, listing IRQs assigned to PCI devices.
         let prt_package_list: Vec<(u32, u32)> = self
             .pci_irq_slots
@@ -0,0 +1,241 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Reservation of the host resources used by the VM, letting orchestrators
+//! such as the Kubernetes device and CNI plugins reconcile their ownership.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::{fs, io, process};
+
+use serde::Serialize;
+
+use crate::vm_config::{ResourcesConfig, VmConfig};
+
+const MEMINFO_PATH: &str = "/proc/meminfo";
+const TASKS_PATH: &str = "/proc/self/task";
+
+// Period of the CPU quota of the cgroup, in microseconds.
+const CPU_PERIOD_US: u64 = 100_000;
+
+// Children of the cgroup the vCPU, I/O and API threads are placed into.
+pub const VCPUS_CGROUP: &str = "vcpus";
+pub const IO_CGROUP: &str = "io";
+pub const API_CGROUP: &str = "api";
+
+const API_THREADS: [&str; 2] = ["http-server", "dbus-thread"];
+// Threads of the VMM itself, left in the cgroup along with the main thread.
+const VMM_THREADS: [&str; 8] = [
+    "vmm",
+    "vmm_signal_handler",
+    "event-monitor",
+    "hooks",
+    "gdb",
+    "serial-manager",
+    "payload_loader",
+    "boot_watchdog",
+];
+// Length of the thread names, as truncated by the kernel.
+const TASK_COMM_LEN: usize = 15;
+
+/// Moves the VMM process into the cgroup, creating and setting it up first
+/// if needed.
+pub fn join_cgroup(resources: &ResourcesConfig) -> io::Result<()> {
+    let Some(path) = resources.cgroup.as_ref() else {
+        return Ok(());
+    };
+
+    fs::create_dir_all(path)?;
+    if let Some(cpu_quota) = resources.cpu_quota {
+        let quota = u64::from(cpu_quota) * CPU_PERIOD_US / 100;
+        fs::write(path.join("cpu.max"), format!("{quota} {CPU_PERIOD_US}"))?;
+    }
+    if let Some(memory_max) = resources.memory_max {
+        fs::write(path.join("memory.max"), memory_max.to_string())?;
+    }
+    for io_max in resources.io_max.iter().flatten() {
+        fs::write(path.join("io.max"), io_max)?;
+    }
+    if resources.threads {
+        for child in [VCPUS_CGROUP, IO_CGROUP, API_CGROUP] {
+            let child = path.join(child);
+            fs::create_dir_all(&child)?;
+            fs::write(child.join("cgroup.type"), "threaded")?;
+        }
+        // Accounts the CPU time of the threads to the child they are in.
+        fs::write(path.join("cgroup.subtree_control"), "+cpu")?;
+    }
+
+    fs::write(path.join("cgroup.procs"), process::id().to_string())
+}
+
+/// Places the vCPU, I/O and API threads of the VMM into the children of the
+/// cgroup at `path` of the same names.
+pub fn place_threads(path: &Path) -> io::Result<()> {
+    let pid = process::id();
+    for task in fs::read_dir(TASKS_PATH)? {
+        let task = task?;
+        let Some(tid) = task.file_name().to_str().and_then(|tid| tid.parse().ok()) else {
+            continue;
+        };
+        if tid == pid {
+            continue;
+        }
+
+        // The thread may have exited in the meantime.
+        let Ok(name) = fs::read_to_string(task.path().join("comm")) else {
+            continue;
+        };
+        let Some(child) = thread_cgroup(name.trim_end()) else {
+            continue;
+        };
+        match fs::write(path.join(child).join("cgroup.threads"), tid.to_string()) {
+            Err(e) if e.raw_os_error() != Some(libc::ESRCH) => return Err(e),
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+// Child of the cgroup the thread of the given name belongs to, any thread
+// but the ones of the VMM itself being an I/O thread.
+fn thread_cgroup(name: &str) -> Option<&'static str> {
+    let truncated = |thread: &&str| thread.get(..TASK_COMM_LEN).unwrap_or(*thread) == name;
+    let is_vcpu = name
+        .strip_prefix("vcpu")
+        .is_some_and(|id| !id.is_empty() && id.bytes().all(|b| b.is_ascii_digit()));
+
+    if is_vcpu {
+        Some(VCPUS_CGROUP)
+    } else if API_THREADS.iter().any(truncated) {
+        Some(API_CGROUP)
+    } else if VMM_THREADS.iter().any(truncated) {
+        None
+    } else {
+        Some(IO_CGROUP)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct HugepageReservation {
+    pub size: u64,
+    pub count: u64,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct TapInterface {
+    pub id: String,
+    pub name: String,
+}
+
+/// Host resources used by the VM, reported as a JSON document.
+#[derive(Debug, Serialize)]
+pub struct ResourceManifest {
+    pub pid: u32,
+    pub cgroup: Option<PathBuf>,
+    pub hugepages: Vec<HugepageReservation>,
+    pub vfio_groups: Vec<u32>,
+    pub taps: Vec<TapInterface>,
+}
+
+impl ResourceManifest {
+    pub fn new(config: &VmConfig, tap_names: &BTreeMap<String, String>) -> io::Result<Self> {
+        let mut hugepage_sizes = Vec::new();
+        let memory = &config.memory;
+        if memory.hugepages {
+            let size = memory.size + memory.hotplugged_size.unwrap_or_default();
+            hugepage_sizes.push((memory.hugepage_size, size));
+        }
+        for zone in memory.zones.iter().flatten().filter(|z| z.hugepages) {
+            let size = zone.size + zone.hotplugged_size.unwrap_or_default();
+            hugepage_sizes.push((zone.hugepage_size, size));
+        }
+
+        let mut hugepages = BTreeMap::new();
+        for (page_size, size) in hugepage_sizes {
+            let page_size = match page_size {
+                Some(page_size) => page_size,
+                None => default_hugepage_size(&fs::read_to_string(MEMINFO_PATH)?)?,
+            };
+            *hugepages.entry(page_size).or_default() += size.div_ceil(page_size);
+        }
+
+        let mut vfio_groups = Vec::new();
+        for device in config.devices.iter().flatten() {
+            vfio_groups.push(iommu_group(&device.path)?);
+        }
+        vfio_groups.sort_unstable();
+        vfio_groups.dedup();
+
+        Ok(ResourceManifest {
+            pid: process::id(),
+            cgroup: config.resources.as_ref().and_then(|r| r.cgroup.clone()),
+            hugepages: hugepages
+                .into_iter()
+                .map(|(size, count)| HugepageReservation { size, count })
+                .collect(),
+            vfio_groups,
+            taps: tap_names
+                .iter()
+                .map(|(id, name)| TapInterface {
+                    id: id.clone(),
+                    name: name.clone(),
+                })
+                .collect(),
+        })
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, serde_json::to_vec_pretty(self)?)
+    }
+}
+
+// Default size of the huge pages, as reported by /proc/meminfo.
+fn default_hugepage_size(meminfo: &str) -> io::Result<u64> {
+    meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("Hugepagesize:"))
+        .and_then(|size| size.trim().strip_suffix("kB"))
+        .and_then(|size| size.trim().parse::<u64>().ok())
+        .map(|size| size << 10)
+        .ok_or_else(|| io::Error::other("Huge pages are not supported"))
+}
+
+// IOMMU group of the device at the given sysfs path, as named after
+// /dev/vfio/<group>.
+fn iommu_group(device: &Path) -> io::Result<u32> {
+    fs::read_link(device.join("iommu_group"))?
+        .file_name()
+        .and_then(|group| group.to_str())
+        .and_then(|group| group.parse().ok())
+        .ok_or_else(|| io::Error::other(format!("Invalid IOMMU group of {device:?}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_hugepage_size() {
+        let meminfo = "MemTotal:       16303872 kB\n\
+            HugePages_Total:       0\n\
+            Hugepagesize:       2048 kB\n\
+            Hugetlb:               0 kB\n";
+        assert_eq!(default_hugepage_size(meminfo).unwrap(), 2 << 20);
+        default_hugepage_size("MemTotal:       16303872 kB\n").unwrap_err();
+    }
+
+    #[test]
+    fn test_thread_cgroup() {
+        assert_eq!(thread_cgroup("vcpu12"), Some(VCPUS_CGROUP));
+        assert_eq!(thread_cgroup("http-server"), Some(API_CGROUP));
+        assert_eq!(thread_cgroup("_disk0_q0"), Some(IO_CGROUP));
+        assert_eq!(thread_cgroup("io_thread0"), Some(IO_CGROUP));
+        assert_eq!(thread_cgroup("vcpudisk_q0"), Some(IO_CGROUP));
+        assert_eq!(thread_cgroup("vmm_signal_hand"), None);
+        assert_eq!(thread_cgroup("vmm"), None);
+    }
+}
@@ -16,6 +16,7 @@ pub enum Thread {
     #[cfg(feature = "dbus_api")]
     DBusApi,
     EventMonitor,
+    Hooks,
     SignalHandler,
     Vcpu,
     Vmm,
@@ -61,6 +62,9 @@ const BLKIOOPT: u64 = 0x1279;
 // See include/uapi/linux/if_tun.h in the kernel code.
 const TUNGETIFF: u64 = 0x8004_54d2;
 const TUNSETIFF: u64 = 0x4004_54ca;
+const TUNSETPERSIST: u64 = 0x4004_54cb;
+const TUNSETOWNER: u64 = 0x4004_54cc;
+const TUNSETGROUP: u64 = 0x4004_54ce;
 const TUNSETOFFLOAD: u64 = 0x4004_54d0;
 const TUNSETVNETHDRSZ: u64 = 0x4004_54d8;
 const TUNGETFEATURES: u64 = 0x8004_54cf;
@@ -97,6 +101,7 @@ const VFIO_DEVICE_IOEVENTFD: u64 = 0x3b74;
 const VHOST_GET_FEATURES: u64 = 0x8008af00;
 const VHOST_SET_FEATURES: u64 = 0x4008af00;
 const VHOST_SET_OWNER: u64 = 0xaf01;
+const VHOST_SET_MEM_TABLE: u64 = 0x4008af03;
 const VHOST_SET_VRING_NUM: u64 = 0x4008af10;
 const VHOST_SET_VRING_ADDR: u64 = 0x4028af11;
 const VHOST_SET_VRING_BASE: u64 = 0x4008af12;
@@ -104,6 +109,7 @@ const VHOST_SET_VRING_KICK: u64 = 0x4008af20;
 const VHOST_SET_VRING_CALL: u64 = 0x4008af21;
 const VHOST_SET_BACKEND_FEATURES: u64 = 0x4008af25;
 const VHOST_GET_BACKEND_FEATURES: u64 = 0x8008af26;
+const VHOST_NET_SET_BACKEND: u64 = 0x4008af30;
 const VHOST_VDPA_GET_DEVICE_ID: u64 = 0x8004af70;
 const VHOST_VDPA_GET_STATUS: u64 = 0x8001af71;
 const VHOST_VDPA_SET_STATUS: u64 = 0x4001af72;
@@ -321,6 +327,9 @@ fn create_vmm_ioctl_seccomp_rule_common(
         and![Cond::new(1, ArgLen::Dword, Eq, TUNGETFEATURES)?],
         and![Cond::new(1, ArgLen::Dword, Eq, TUNGETIFF)?],
         and![Cond::new(1, ArgLen::Dword, Eq, TUNSETIFF)?],
+        and![Cond::new(1, ArgLen::Dword, Eq, TUNSETPERSIST)?],
+        and![Cond::new(1, ArgLen::Dword, Eq, TUNSETOWNER)?],
+        and![Cond::new(1, ArgLen::Dword, Eq, TUNSETGROUP)?],
         and![Cond::new(1, ArgLen::Dword, Eq, TUNSETOFFLOAD)?],
         and![Cond::new(1, ArgLen::Dword, Eq, TUNSETVNETHDRSZ)?],
         and![Cond::new(1, ArgLen::Dword, Eq, VFIO_GET_API_VERSION)?],
@@ -346,6 +355,7 @@ fn create_vmm_ioctl_seccomp_rule_common(
         and![Cond::new(1, ArgLen::Dword, Eq, VHOST_GET_FEATURES)?],
         and![Cond::new(1, ArgLen::Dword, Eq, VHOST_SET_FEATURES)?],
         and![Cond::new(1, ArgLen::Dword, Eq, VHOST_SET_OWNER)?],
+        and![Cond::new(1, ArgLen::Dword, Eq, VHOST_SET_MEM_TABLE)?],
         and![Cond::new(1, ArgLen::Dword, Eq, VHOST_SET_VRING_NUM)?],
         and![Cond::new(1, ArgLen::Dword, Eq, VHOST_SET_VRING_ADDR)?],
         and![Cond::new(1, ArgLen::Dword, Eq, VHOST_SET_VRING_BASE)?],
@@ -353,6 +363,7 @@ fn create_vmm_ioctl_seccomp_rule_common(
         and![Cond::new(1, ArgLen::Dword, Eq, VHOST_SET_VRING_CALL)?],
         and![Cond::new(1, ArgLen::Dword, Eq, VHOST_SET_BACKEND_FEATURES)?],
         and![Cond::new(1, ArgLen::Dword, Eq, VHOST_GET_BACKEND_FEATURES)?],
+        and![Cond::new(1, ArgLen::Dword, Eq, VHOST_NET_SET_BACKEND)?],
         and![Cond::new(1, ArgLen::Dword, Eq, VHOST_VDPA_GET_DEVICE_ID)?],
         and![Cond::new(1, ArgLen::Dword, Eq, VHOST_VDPA_GET_STATUS)?],
         and![Cond::new(1, ArgLen::Dword, Eq, VHOST_VDPA_SET_STATUS)?],
@@ -611,6 +622,8 @@ fn vmm_thread_rules(
         (libc::SYS_io_uring_enter, vec![]),
         (libc::SYS_io_uring_setup, vec![]),
         (libc::SYS_io_uring_register, vec![]),
+        // Needed by the worker threads of the threads disk I/O engine
+        (libc::SYS_ioprio_set, vec![]),
         (libc::SYS_kill, vec![]),
         (libc::SYS_landlock_create_ruleset, vec![]),
         (libc::SYS_landlock_add_rule, vec![]),
@@ -620,7 +633,15 @@ fn vmm_thread_rules(
         (libc::SYS_madvise, vec![]),
         (libc::SYS_mbind, vec![]),
         (libc::SYS_memfd_create, vec![]),
+        #[cfg(target_arch = "x86_64")]
+        (libc::SYS_mkdir, vec![]),
+        (libc::SYS_mkdirat, vec![]),
         (libc::SYS_mmap, vec![]),
+        // Needed to name the network namespace of the TAP interfaces
+        (
+            libc::SYS_mount,
+            or![and![Cond::new(3, ArgLen::Qword, Eq, libc::MS_BIND)?]],
+        ),
         (libc::SYS_mprotect, vec![]),
         (libc::SYS_mremap, vec![]),
         (libc::SYS_munmap, vec![]),
@@ -637,9 +658,11 @@ fn vmm_thread_rules(
         (libc::SYS_prctl, vec![]),
         (libc::SYS_pread64, vec![]),
         (libc::SYS_preadv, vec![]),
+        (libc::SYS_preadv2, vec![]),
         (libc::SYS_prlimit64, vec![]),
         (libc::SYS_pwrite64, vec![]),
         (libc::SYS_pwritev, vec![]),
+        (libc::SYS_pwritev2, vec![]),
         (libc::SYS_read, vec![]),
         (libc::SYS_readv, vec![]),
         #[cfg(target_arch = "x86_64")]
@@ -665,6 +688,15 @@ fn vmm_thread_rules(
         (libc::SYS_sendmsg, vec![]),
         (libc::SYS_sendto, vec![]),
         (libc::SYS_set_robust_list, vec![]),
+        (
+            libc::SYS_setns,
+            or![and![Cond::new(
+                1,
+                ArgLen::Dword,
+                Eq,
+                libc::CLONE_NEWNET as u64
+            )?]],
+        ),
         (libc::SYS_setsid, vec![]),
         (libc::SYS_setsockopt, vec![]),
         (libc::SYS_shutdown, vec![]),
@@ -675,6 +707,7 @@ fn vmm_thread_rules(
                 and![Cond::new(0, ArgLen::Dword, Eq, libc::AF_UNIX as u64)?],
                 and![Cond::new(0, ArgLen::Dword, Eq, libc::AF_INET as u64)?],
                 and![Cond::new(0, ArgLen::Dword, Eq, libc::AF_INET6 as u64)?],
+                and![Cond::new(0, ArgLen::Dword, Eq, libc::AF_NETLINK as u64)?],
             ],
         ),
         (libc::SYS_socketpair, vec![]),
@@ -690,10 +723,28 @@ fn vmm_thread_rules(
             libc::SYS_umask,
             or![and![Cond::new(0, ArgLen::Dword, Eq, 0o077)?]],
         ),
+        (
+            libc::SYS_umount2,
+            or![and![Cond::new(
+                1,
+                ArgLen::Dword,
+                Eq,
+                libc::MNT_DETACH as u64
+            )?]],
+        ),
         #[cfg(target_arch = "x86_64")]
         (libc::SYS_unlink, vec![]),
         #[cfg(target_arch = "aarch64")]
         (libc::SYS_unlinkat, vec![]),
+        (
+            libc::SYS_unshare,
+            or![and![Cond::new(
+                0,
+                ArgLen::Dword,
+                Eq,
+                libc::CLONE_NEWNET as u64
+            )?]],
+        ),
         (libc::SYS_wait4, vec![]),
         (libc::SYS_write, vec![]),
         (libc::SYS_writev, vec![]),
@@ -787,6 +838,7 @@ fn vcpu_thread_rules(
     hypervisor_type: HypervisorType,
 ) -> Result<Vec<(i64, Vec<SeccompRule>)>, BackendError> {
     Ok(vec![
+        (libc::SYS_accept4, vec![]),
         (libc::SYS_brk, vec![]),
         (libc::SYS_clock_gettime, vec![]),
         (libc::SYS_clock_nanosleep, vec![]),
@@ -823,6 +875,7 @@ fn vcpu_thread_rules(
         (libc::SYS_rt_sigreturn, vec![]),
         (libc::SYS_sched_yield, vec![]),
         (libc::SYS_sendmsg, vec![]),
+        (libc::SYS_sendto, vec![]),
         (libc::SYS_shutdown, vec![]),
         (libc::SYS_sigaltstack, vec![]),
         (libc::SYS_tgkill, vec![]),
@@ -925,6 +978,47 @@ fn event_monitor_thread_rules() -> Result<Vec<(i64, Vec<SeccompRule>)>, BackendE
     ])
 }
 
+// The filter of the thread posting the lifecycle events to HTTP endpoints,
+// which resolves their host names as well.
+fn hooks_thread_rules() -> Result<Vec<(i64, Vec<SeccompRule>)>, BackendError> {
+    Ok(vec![
+        (libc::SYS_brk, vec![]),
+        (libc::SYS_clock_gettime, vec![]),
+        (libc::SYS_close, vec![]),
+        (libc::SYS_connect, vec![]),
+        (libc::SYS_exit, vec![]),
+        (libc::SYS_fcntl, vec![]),
+        (libc::SYS_fstat, vec![]),
+        (libc::SYS_futex, vec![]),
+        (libc::SYS_getpeername, vec![]),
+        (libc::SYS_getrandom, vec![]),
+        (libc::SYS_getsockname, vec![]),
+        (libc::SYS_getsockopt, vec![]),
+        (libc::SYS_lseek, vec![]),
+        (libc::SYS_madvise, vec![]),
+        (libc::SYS_mmap, vec![]),
+        (libc::SYS_mprotect, vec![]),
+        (libc::SYS_munmap, vec![]),
+        (libc::SYS_newfstatat, vec![]),
+        (libc::SYS_openat, vec![]),
+        #[cfg(target_arch = "x86_64")]
+        (libc::SYS_poll, vec![]),
+        #[cfg(target_arch = "aarch64")]
+        (libc::SYS_ppoll, vec![]),
+        (libc::SYS_read, vec![]),
+        (libc::SYS_recvfrom, vec![]),
+        (libc::SYS_recvmsg, vec![]),
+        (libc::SYS_rt_sigprocmask, vec![]),
+        (libc::SYS_sched_yield, vec![]),
+        (libc::SYS_sendmmsg, vec![]),
+        (libc::SYS_sendto, vec![]),
+        (libc::SYS_setsockopt, vec![]),
+        (libc::SYS_sigaltstack, vec![]),
+        (libc::SYS_socket, vec![]),
+        (libc::SYS_write, vec![]),
+    ])
+}
+
 fn get_seccomp_rules(
     thread_type: Thread,
     hypervisor_type: HypervisorType,
@@ -934,6 +1028,7 @@ fn get_seccomp_rules(
         #[cfg(feature = "dbus_api")]
         Thread::DBusApi => Ok(dbus_api_thread_rules()?),
         Thread::EventMonitor => Ok(event_monitor_thread_rules()?),
+        Thread::Hooks => Ok(hooks_thread_rules()?),
         Thread::SignalHandler => Ok(signal_handler_thread_rules()?),
         Thread::Vcpu => Ok(vcpu_thread_rules(hypervisor_type)?),
         Thread::Vmm => Ok(vmm_thread_rules(hypervisor_type)?),
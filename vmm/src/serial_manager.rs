@@ -19,7 +19,7 @@ use devices::legacy::Pl011;
 #[cfg(any(target_arch = "x86_64", target_arch = "riscv64"))]
 use devices::legacy::Serial;
 use libc::EFD_NONBLOCK;
-use serial_buffer::SerialBuffer;
+use serial_buffer::{OutputSinks, SerialBuffer};
 use thiserror::Error;
 use vmm_sys_util::eventfd::EventFd;
 
@@ -118,6 +118,7 @@ pub struct SerialManager {
     handle: Option<thread::JoinHandle<()>>,
     pty_write_out: Option<Arc<AtomicBool>>,
     socket_path: Option<PathBuf>,
+    sinks: OutputSinks,
 }
 
 impl SerialManager {
@@ -125,6 +126,7 @@ impl SerialManager {
         #[cfg(any(target_arch = "x86_64", target_arch = "riscv64"))] serial: Arc<Mutex<Serial>>,
         #[cfg(target_arch = "aarch64")] serial: Arc<Mutex<Pl011>>,
         mut output: ConsoleOutput,
+        sinks: OutputSinks,
         socket: Option<PathBuf>,
     ) -> Result<Option<Self>> {
         let mut socket_path: Option<PathBuf> = None;
@@ -203,7 +205,7 @@ impl SerialManager {
                 .as_ref()
                 .lock()
                 .unwrap()
-                .set_out(Some(Box::new(buffer)));
+                .set_out(sinks.tee(Some(Box::new(buffer))));
         }
 
         // Use 'File' to enforce closing on 'epoll_fd'
@@ -218,6 +220,7 @@ impl SerialManager {
             handle: None,
             pty_write_out,
             socket_path,
+            sinks,
         }))
     }
 
@@ -258,6 +261,7 @@ impl SerialManager {
         let in_file = self.in_file.clone();
         let serial = self.serial.clone();
         let pty_write_out = self.pty_write_out.clone();
+        let sinks = self.sinks.clone();
         let mut reader: Option<UnixStream> = None;
 
         // In case of PTY, we want to be able to detect a connection on the
@@ -343,7 +347,10 @@ impl SerialManager {
                                         ),
                                     )
                                     .map_err(Error::Epoll)?;
-                                    serial.lock().unwrap().set_out(Some(Box::new(writer)));
+                                    serial
+                                        .lock()
+                                        .unwrap()
+                                        .set_out(sinks.tee(Some(Box::new(writer))));
                                 }
                                 EpollDispatch::File => {
                                     if event.events & libc::EPOLLIN as u32 != 0 {
@@ -364,7 +371,7 @@ impl SerialManager {
                                                             .as_ref()
                                                             .lock()
                                                             .unwrap()
-                                                            .set_out(None);
+                                                            .set_out(sinks.tee(None));
                                                     }
                                                     count
                                                 } else {
@@ -0,0 +1,154 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Integration with the systemd service manager.
+//!
+//! The API socket can be passed by systemd through socket activation, and
+//! the service manager is notified about the state of the VMM, as described
+//! in sd_listen_fds(3) and sd_notify(3).
+
+use std::ffi::OsStr;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::RawFd;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::time::Duration;
+use std::{env, io, process};
+
+const LISTEN_FDS_START: RawFd = 3;
+
+/// File descriptor of the socket passed through socket activation, if any.
+/// Only the first socket is used, the other ones being ignored.
+pub fn listen_fd() -> Option<RawFd> {
+    let pid = env::var("LISTEN_PID").ok()?.parse::<u32>().ok()?;
+    if pid != process::id() {
+        return None;
+    }
+
+    let fds = env::var("LISTEN_FDS").ok()?.parse::<RawFd>().ok()?;
+    if fds < 1 {
+        return None;
+    }
+    if fds > 1 {
+        warn!(
+            "Ignoring {} socket activated file descriptors, only one is supported",
+            fds - 1
+        );
+    }
+
+    // The file descriptor is not meant to be inherited by child processes.
+    // SAFETY: FFI call, the file descriptor being passed by systemd.
+    unsafe { libc::fcntl(LISTEN_FDS_START, libc::F_SETFD, libc::FD_CLOEXEC) };
+
+    Some(LISTEN_FDS_START)
+}
+
+/// Interval at which the service manager expects watchdog keep-alive pings,
+/// if the watchdog is enabled for the VMM.
+pub fn watchdog_interval() -> Option<Duration> {
+    if let Ok(pid) = env::var("WATCHDOG_PID") {
+        if pid.parse::<u32>().ok()? != process::id() {
+            return None;
+        }
+    }
+
+    let usecs = env::var("WATCHDOG_USEC").ok()?.parse::<u64>().ok()?;
+    if usecs == 0 {
+        return None;
+    }
+
+    Some(Duration::from_micros(usecs))
+}
+
+/// Sends status notifications to the service manager.
+pub struct Notifier {
+    socket: UnixDatagram,
+    addr: SocketAddr,
+}
+
+impl Notifier {
+    /// Creates a notifier when the VMM is run by the service manager with a
+    /// notification socket.
+    pub fn from_env() -> Option<Self> {
+        let path = env::var_os("NOTIFY_SOCKET")?;
+        match Self::new(&path) {
+            Ok(notifier) => Some(notifier),
+            Err(e) => {
+                warn!("Invalid notification socket {:?}: {}", path, e);
+                None
+            }
+        }
+    }
+
+    fn new(path: &OsStr) -> io::Result<Self> {
+        // Names starting with '@' refer to the abstract namespace.
+        let addr = match path.as_bytes().strip_prefix(b"@") {
+            Some(name) => SocketAddr::from_abstract_name(name)?,
+            None => SocketAddr::from_pathname(path)?,
+        };
+
+        Ok(Notifier {
+            socket: UnixDatagram::unbound()?,
+            addr,
+        })
+    }
+
+    pub fn notify(&self, state: &str) {
+        if let Err(e) = self.socket.send_to_addr(state.as_bytes(), &self.addr) {
+            warn!("Failed to notify the service manager: {}", e);
+        }
+    }
+
+    /// The VMM is ready to serve requests, and its VM booted if one was
+    /// requested on the command line.
+    pub fn ready(&self) {
+        self.notify("READY=1");
+    }
+
+    /// The VM is being rebooted.
+    pub fn reloading(&self) {
+        let mut now = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        // SAFETY: FFI call with a valid timespec.
+        unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+        let usecs = now.tv_sec as u64 * 1_000_000 + now.tv_nsec as u64 / 1_000;
+
+        self.notify(&format!("RELOADING=1\nMONOTONIC_USEC={usecs}"));
+    }
+
+    pub fn stopping(&self) {
+        self.notify("STOPPING=1");
+    }
+
+    pub fn watchdog(&self) {
+        self.notify("WATCHDOG=1");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsString;
+
+    use super::*;
+
+    #[test]
+    fn test_notifier() {
+        let name = format!("cloud-hypervisor-notify-{}", process::id());
+        let addr = SocketAddr::from_abstract_name(name.as_bytes()).unwrap();
+        let socket = UnixDatagram::bind_addr(&addr).unwrap();
+
+        let notifier = Notifier::new(&OsString::from(format!("@{name}"))).unwrap();
+        notifier.ready();
+        notifier.stopping();
+
+        let mut buf = [0u8; 64];
+        let len = socket.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"READY=1");
+        let len = socket.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"STOPPING=1");
+    }
+}
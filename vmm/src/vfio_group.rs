@@ -0,0 +1,180 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Host IOMMU group inspection for VFIO devices.
+//!
+//! All devices sharing an IOMMU group must be either assigned to the same
+//! VM or left without a host driver, otherwise the kernel refuses to attach
+//! the group to a VFIO container. This module reads the group topology from
+//! sysfs so that such situations can be detected and reported precisely
+//! before any device is created.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+// Host drivers which don't prevent a group from being viable.
+const VFIO_PCI_DRIVER: &str = "vfio-pci";
+const PCIE_PORT_DRIVER: &str = "pcieport";
+const PCI_STUB_DRIVER: &str = "pci-stub";
+
+#[derive(Debug, Error)]
+pub enum VfioGroupError {
+    #[error("Failed to read the IOMMU group of {0:?}")]
+    ReadGroup(PathBuf, #[source] io::Error),
+    #[error("Invalid IOMMU group for {0:?}")]
+    InvalidGroup(PathBuf),
+    #[error("IOMMU group {0} is not viable: {1:?} is bound to host driver {2}")]
+    NotViable(String, PathBuf, String),
+}
+
+pub type Result<T> = std::result::Result<T, VfioGroupError>;
+
+/// IOMMU group a VFIO device belongs to, along with every device of the
+/// group as found in `/sys/kernel/iommu_groups/<id>/devices`.
+#[derive(Debug)]
+pub struct IommuGroup {
+    pub id: String,
+    pub devices: Vec<PathBuf>,
+}
+
+impl IommuGroup {
+    /// Retrieve the IOMMU group of the device at the given sysfs path.
+    pub fn from_device(path: &Path) -> Result<Self> {
+        let group_path = fs::canonicalize(path.join("iommu_group"))
+            .map_err(|e| VfioGroupError::ReadGroup(path.to_path_buf(), e))?;
+        let id = group_path
+            .file_name()
+            .and_then(|f| f.to_str())
+            .ok_or_else(|| VfioGroupError::InvalidGroup(path.to_path_buf()))?
+            .to_string();
+
+        let mut devices = fs::read_dir(group_path.join("devices"))
+            .map_err(|e| VfioGroupError::ReadGroup(path.to_path_buf(), e))?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<io::Result<Vec<PathBuf>>>()
+            .map_err(|e| VfioGroupError::ReadGroup(path.to_path_buf(), e))?;
+        devices.sort();
+
+        Ok(IommuGroup { id, devices })
+    }
+
+    fn driver(device: &Path) -> Option<String> {
+        fs::read_link(device.join("driver"))
+            .ok()
+            .and_then(|d| d.file_name().map(|f| f.to_string_lossy().into_owned()))
+    }
+
+    /// Check every device of the group is either bound to vfio-pci or
+    /// pci-stub, not bound to any driver, or a bridge/port which can be left
+    /// to the host.
+    pub fn check_viable(&self) -> Result<()> {
+        for device in self.devices.iter() {
+            match Self::driver(device) {
+                None => {}
+                Some(driver)
+                    if driver == VFIO_PCI_DRIVER
+                        || driver == PCIE_PORT_DRIVER
+                        || driver == PCI_STUB_DRIVER => {}
+                Some(driver) => {
+                    return Err(VfioGroupError::NotViable(
+                        self.id.clone(),
+                        device.clone(),
+                        driver,
+                    ))
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Devices of the group which are bound to vfio-pci, meaning they are
+    /// meant to be assigned to a guest.
+    pub fn vfio_devices(&self) -> Vec<PathBuf> {
+        self.devices
+            .iter()
+            .filter(|d| Self::driver(d).as_deref() == Some(VFIO_PCI_DRIVER))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Compare two sysfs paths, possibly going through different symlinks, and
+/// tell if they refer to the same device.
+pub fn same_device(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::symlink;
+
+    use vmm_sys_util::tempdir::TempDir;
+
+    use super::*;
+
+    // Build a fake sysfs layout with a single IOMMU group holding a device
+    // per (name, driver) pair.
+    fn create_group(root: &Path, devices: &[(&str, Option<&str>)]) -> PathBuf {
+        let group = root.join("iommu_groups/7");
+        fs::create_dir_all(group.join("devices")).unwrap();
+        fs::create_dir_all(root.join("drivers/vfio-pci")).unwrap();
+        fs::create_dir_all(root.join("drivers/nvme")).unwrap();
+
+        for (name, driver) in devices {
+            let device = root.join("devices").join(name);
+            fs::create_dir_all(&device).unwrap();
+            symlink(&group, device.join("iommu_group")).unwrap();
+            if let Some(driver) = driver {
+                symlink(root.join("drivers").join(driver), device.join("driver")).unwrap();
+            }
+            symlink(&device, group.join("devices").join(name)).unwrap();
+        }
+
+        root.join("devices").join(devices[0].0)
+    }
+
+    #[test]
+    fn test_iommu_group_viability() {
+        let root = TempDir::new_with_prefix("/tmp/ch-iommu-group").unwrap();
+        let device = create_group(
+            root.as_path(),
+            &[
+                ("0000:01:00.0", Some("vfio-pci")),
+                ("0000:01:00.1", Some("vfio-pci")),
+                ("0000:01:00.2", None),
+                ("0000:01:00.3", Some("pci-stub")),
+            ],
+        );
+
+        let group = IommuGroup::from_device(&device).unwrap();
+        assert_eq!(group.id, "7");
+        assert_eq!(group.devices.len(), 4);
+        group.check_viable().unwrap();
+        assert_eq!(group.vfio_devices().len(), 2);
+        assert!(same_device(&device, &group.devices[0]));
+        assert!(!same_device(&device, &group.devices[1]));
+
+        let root = TempDir::new_with_prefix("/tmp/ch-iommu-group").unwrap();
+        let device = create_group(
+            root.as_path(),
+            &[
+                ("0000:01:00.0", Some("vfio-pci")),
+                ("0000:01:00.1", Some("nvme")),
+            ],
+        );
+        let group = IommuGroup::from_device(&device).unwrap();
+        assert!(matches!(
+            group.check_viable(),
+            Err(VfioGroupError::NotViable(id, _, driver)) if id == "7" && driver == "nvme"
+        ));
+    }
+}
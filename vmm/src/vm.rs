@@ -19,9 +19,12 @@ use std::mem::size_of;
 use std::num::Wrapping;
 use std::ops::Deref;
 use std::os::unix::net::UnixStream;
+#[cfg(target_arch = "x86_64")]
+use std::sync::atomic::Ordering;
 use std::sync::{Arc, Mutex, RwLock};
 #[cfg(not(target_arch = "riscv64"))]
 use std::time::Instant;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::{cmp, result, str, thread};
 
 use anyhow::anyhow;
@@ -34,6 +37,8 @@ use arch::PciSpaceInfo;
 use arch::{get_host_cpu_phys_bits, EntryPoint, NumaNode, NumaNodes};
 #[cfg(target_arch = "aarch64")]
 use devices::interrupt_controller;
+#[cfg(target_arch = "x86_64")]
+use devices::vmbus::{VmBus, HV_STATUS_INVALID_HYPERCALL_CODE};
 use devices::AcpiNotificationFlags;
 #[cfg(all(target_arch = "aarch64", feature = "guest_debug"))]
 use gdbstub_arch::aarch64::reg::AArch64CoreRegs as CoreRegs;
@@ -55,9 +60,13 @@ use linux_loader::loader::pe::Error::InvalidImageMagicNumber;
 use linux_loader::loader::KernelLoader;
 use seccompiler::SeccompAction;
 use serde::{Deserialize, Serialize};
+#[cfg(not(target_arch = "riscv64"))]
+use sha2::{Digest, Sha256};
 use thiserror::Error;
+#[cfg(not(target_arch = "riscv64"))]
+use tpm::event_log::{EventLog, Sha256Digest, EV_IPL, EV_POST_CODE, EV_SEPARATOR};
 use tracer::trace_scoped;
-use vm_device::Bus;
+use vm_device::{Bus, ExitReason};
 #[cfg(feature = "tdx")]
 use vm_memory::{Address, ByteValued, GuestMemoryRegion, ReadVolatile};
 use vm_memory::{
@@ -70,6 +79,9 @@ use vm_migration::{
 use vmm_sys_util::eventfd::EventFd;
 use vmm_sys_util::sock_ctrl_msg::ScmSocket;
 
+#[cfg(target_arch = "x86_64")]
+use crate::api::SgxEpcSectionInfo;
+use crate::api::{BalloonInfo, DiskQueueInfo, VmBackupData};
 use crate::config::{add_to_config, ValidationError};
 use crate::console_devices::{ConsoleDeviceError, ConsoleInfo};
 #[cfg(all(target_arch = "x86_64", feature = "guest_debug"))]
@@ -80,6 +92,7 @@ use crate::device_manager::{DeviceManager, DeviceManagerError};
 use crate::device_tree::DeviceTree;
 #[cfg(feature = "guest_debug")]
 use crate::gdb::{Debuggable, DebuggableError, GdbRequestPayload, GdbResponsePayload};
+use crate::guest_agent::{self, GuestAgent};
 #[cfg(feature = "igvm")]
 use crate::igvm::igvm_loader;
 use crate::landlock::LandlockError;
@@ -91,13 +104,17 @@ use crate::migration::get_vm_snapshot;
 #[cfg(all(target_arch = "x86_64", feature = "guest_debug"))]
 use crate::migration::url_to_file;
 use crate::migration::{url_to_path, SNAPSHOT_CONFIG_FILE, SNAPSHOT_STATE_FILE};
+use crate::numa;
+use crate::resources::ResourceManifest;
+#[cfg(target_arch = "aarch64")]
+use crate::vm_config::BootMethod;
 use crate::vm_config::{
     DeviceConfig, DiskConfig, FsConfig, HotplugMethod, NetConfig, NumaConfig, PayloadConfig,
-    PmemConfig, UserDeviceConfig, VdpaConfig, VmConfig, VsockConfig,
+    PmemConfig, RateLimiterGroupConfig, UserDeviceConfig, VdpaConfig, VmConfig, VsockConfig,
 };
 use crate::{
-    cpu, GuestMemoryMmap, PciDeviceInfo, CPU_MANAGER_SNAPSHOT_ID, DEVICE_MANAGER_SNAPSHOT_ID,
-    MEMORY_MANAGER_SNAPSHOT_ID,
+    balloon_pressure, cpu, GuestMemoryMmap, PciDeviceInfo, PciSegmentInfo, CPU_MANAGER_SNAPSHOT_ID,
+    DEVICE_MANAGER_SNAPSHOT_ID, MEMORY_MANAGER_SNAPSHOT_ID,
 };
 
 /// Errors associated with VM management
@@ -119,12 +136,34 @@ pub enum Error {
     #[error("Cannot load the initramfs into memory")]
     InitramfsLoad,
 
+    #[cfg(not(target_arch = "riscv64"))]
+    #[error("Cannot read the payload to measure it")]
+    MeasurePayload(#[source] io::Error),
+
+    #[cfg(not(target_arch = "riscv64"))]
+    #[error("Cannot extend the TPM PCRs with the payload measurements")]
+    ExtendPcr(#[source] devices::tpm::Error),
+
+    #[cfg(not(target_arch = "riscv64"))]
+    #[error("TPM event log of {0} bytes doesn't fit in its area")]
+    TpmEventLogTooLarge(usize),
+
+    #[cfg(not(target_arch = "riscv64"))]
+    #[error("Cannot write the TPM event log in memory")]
+    TpmEventLog(#[source] vm_memory::GuestMemoryError),
+
     #[error("Cannot load the kernel command line in memory")]
     LoadCmdLine(#[source] linux_loader::loader::Error),
 
     #[error("Failed to apply landlock config during vm_create")]
     ApplyLandlock(#[source] LandlockError),
 
+    #[error("Cannot place the VM on the host NUMA topology")]
+    AutoNuma(#[source] numa::Error),
+
+    #[error("Cannot derive the guest NUMA distances from the host ones")]
+    HostNumaDistances(#[source] numa::Error),
+
     #[error("Cannot modify the kernel command line")]
     CmdLineInsertStr(#[source] linux_loader::cmdline::Error),
 
@@ -150,6 +189,12 @@ pub enum Error {
     #[error("No device with id {0:?} to remove")]
     NoDeviceToRemove(String),
 
+    #[error("No disk with id {0:?}")]
+    NoDiskWithId(String),
+
+    #[error("No balloon device")]
+    NoBalloon,
+
     #[error("Cannot spawn a signal handler thread")]
     SignalHandlerSpawn(#[source] io::Error),
 
@@ -171,6 +216,14 @@ pub enum Error {
     #[error("Cannot clone EventFd")]
     EventFdClone(#[source] io::Error),
 
+    #[cfg(target_arch = "x86_64")]
+    #[error("Cannot create VMBus")]
+    CreateVmBus(#[source] io::Error),
+
+    #[cfg(target_arch = "x86_64")]
+    #[error("Cannot enable the x2APIC API")]
+    EnableX2ApicApi(#[source] hypervisor::HypervisorVmError),
+
     #[error("invalid VM state transition: {0:?} to {1:?}")]
     InvalidStateTransition(VmState, VmState),
 
@@ -210,6 +263,15 @@ pub enum Error {
     #[error("Cannot send VM snapshot")]
     SnapshotSend(#[source] MigratableError),
 
+    #[error("Cannot sign VM snapshot")]
+    SnapshotSign(#[source] MigratableError),
+
+    #[error("Cannot verify VM snapshot")]
+    SnapshotVerify(#[source] MigratableError),
+
+    #[error("Cannot send VM hibernation manifest")]
+    HibernationSend(#[source] MigratableError),
+
     #[error("Invalid restore source URL")]
     InvalidRestoreSourceUrl,
 
@@ -306,6 +368,13 @@ pub enum Error {
     #[error("Error spawning kernel loading thread")]
     KernelLoadThreadSpawn(#[source] std::io::Error),
 
+    #[cfg(target_arch = "x86_64")]
+    #[error("Error spawning boot watchdog thread")]
+    BootWatchdogThreadSpawn(#[source] std::io::Error),
+
+    #[error("Error arming the restart timer")]
+    RestartTimer(#[source] io::Error),
+
     #[error("Error joining kernel loading thread")]
     KernelLoadThreadJoin(std::boxed::Box<dyn std::any::Any + std::marker::Send>),
 
@@ -327,6 +396,19 @@ pub enum Error {
     #[error("Error injecting NMI")]
     ErrorNmi,
 
+    #[cfg(feature = "sev_snp")]
+    #[error("Cannot read the SEV-SNP certificate table")]
+    SevSnpCertificates(#[source] io::Error),
+
+    #[cfg(feature = "sev_snp")]
+    #[error("Error setting the SEV-SNP certificate table")]
+    SetSevSnpCertificates(#[source] hypervisor::HypervisorVmError),
+
+    #[error(
+        "Platform certificates can only be refreshed for SEV-SNP guests with a certificate table"
+    )]
+    PlatformCertificatesUnsupported,
+
     #[error("Error resuming the VM")]
     ResumeVm(#[source] hypervisor::HypervisorVmError),
 
@@ -335,9 +417,32 @@ pub enum Error {
 
     #[error("Error locking disk images: Another instance likely holds a lock")]
     LockingError(#[source] DeviceManagerError),
+
+    #[error("The guest agent is not enabled")]
+    GuestAgentNotEnabled,
+
+    #[error("Error running the guest agent command")]
+    GuestAgent(#[source] guest_agent::Error),
+
+    #[error("Timed out waiting for the disks to switch to their overlays")]
+    DiskImageSwitchTimeout,
+
+    #[error("Error joining the cgroup")]
+    JoinCgroup(#[source] io::Error),
+
+    #[error("Error writing the resource manifest")]
+    ResourceManifest(#[source] io::Error),
 }
 pub type Result<T> = result::Result<T, Error>;
 
+// How often the boot watchdog checks whether the guest reached its milestone
+#[cfg(target_arch = "x86_64")]
+const BOOT_WATCHDOG_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// How long the disks are given to complete their requests in flight when
+// switching to their overlays.
+const DISK_IMAGE_SWITCH_TIMEOUT: Duration = Duration::from_secs(30);
+
 #[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
 pub enum VmState {
     Created,
@@ -390,6 +495,8 @@ struct VmOpsHandler {
     #[cfg(target_arch = "x86_64")]
     io_bus: Arc<Bus>,
     mmio_bus: Arc<Bus>,
+    #[cfg(target_arch = "x86_64")]
+    vmbus: Option<Arc<Mutex<VmBus>>>,
 }
 
 impl VmOps for VmOpsHandler {
@@ -452,6 +559,31 @@ impl VmOps for VmOpsHandler {
         };
         Ok(())
     }
+
+    #[cfg(target_arch = "x86_64")]
+    fn hyperv_synic(
+        &self,
+        vcpu_id: u32,
+        control: u64,
+        evt_page: u64,
+        msg_page: u64,
+    ) -> result::Result<(), HypervisorVmError> {
+        if let Some(vmbus) = &self.vmbus {
+            vmbus
+                .lock()
+                .unwrap()
+                .synic_update(vcpu_id, control, evt_page, msg_page);
+        }
+        Ok(())
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    fn hyperv_hcall(&self, _vcpu_id: u32, input: u64, params: [u64; 2]) -> u64 {
+        match &self.vmbus {
+            Some(vmbus) => vmbus.lock().unwrap().hypercall(input, params),
+            None => HV_STATUS_INVALID_HYPERCALL_CODE,
+        }
+    }
 }
 
 pub fn physical_bits(hypervisor: &Arc<dyn hypervisor::Hypervisor>, max_phys_bits: u8) -> u8 {
@@ -460,10 +592,20 @@ pub fn physical_bits(hypervisor: &Arc<dyn hypervisor::Hypervisor>, max_phys_bits
     cmp::min(host_phys_bits, max_phys_bits)
 }
 
+// Minute of the current day in UTC, against which the rate-limit schedules
+// are evaluated.
+fn minute_of_day() -> u32 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    (now.as_secs() % 86400 / 60) as u32
+}
+
 pub struct Vm {
     #[cfg(feature = "tdx")]
     kernel: Option<File>,
-    initramfs: Option<File>,
+    // Initramfs images, concatenated when loaded into guest memory.
+    initramfs: Vec<File>,
     threads: Vec<thread::JoinHandle<()>>,
     device_manager: Arc<Mutex<DeviceManager>>,
     config: Arc<Mutex<VmConfig>>,
@@ -482,6 +624,9 @@ pub struct Vm {
     hypervisor: Arc<dyn hypervisor::Hypervisor>,
     stop_on_boot: bool,
     load_payload_handle: Option<thread::JoinHandle<Result<EntryPoint>>>,
+    #[cfg(target_arch = "x86_64")]
+    // Used by the boot watchdog to reboot on the fallback firmware
+    reset_evt: EventFd,
 }
 
 impl Vm {
@@ -494,10 +639,12 @@ impl Vm {
         vm: Arc<dyn hypervisor::Vm>,
         exit_evt: EventFd,
         reset_evt: EventFd,
+        crash_evt: EventFd,
         #[cfg(feature = "guest_debug")] vm_debug_evt: EventFd,
         seccomp_action: &SeccompAction,
         hypervisor: Arc<dyn hypervisor::Hypervisor>,
         activate_evt: EventFd,
+        device_reset_evt: EventFd,
         #[cfg(not(target_arch = "riscv64"))] timestamp: Instant,
         console_info: Option<ConsoleInfo>,
         console_resize_pipe: Option<Arc<File>>,
@@ -522,6 +669,32 @@ impl Vm {
         let tdx_enabled = config.lock().unwrap().is_tdx_enabled();
         #[cfg(feature = "sev_snp")]
         let sev_snp_enabled = config.lock().unwrap().is_sev_snp_enabled();
+        #[cfg(feature = "sev_snp")]
+        if sev_snp_enabled {
+            let policy = config
+                .lock()
+                .unwrap()
+                .platform
+                .as_ref()
+                .map(|p| hypervisor::SevSnpPolicy {
+                    smt: p.sev_snp_smt,
+                    debug: p.sev_snp_debug,
+                    migrate_ma: p.sev_snp_migrate_ma,
+                })
+                .unwrap_or_default();
+            vm.sev_snp_set_policy(policy)
+                .map_err(Error::InitializeSevSnpVm)?;
+
+            let snp_certs = config
+                .lock()
+                .unwrap()
+                .payload
+                .as_ref()
+                .and_then(|p| p.snp_certs.clone());
+            if let Some(snp_certs) = snp_certs {
+                Self::load_sev_snp_certificates(&vm, &snp_certs)?;
+            }
+        }
         #[cfg(feature = "tdx")]
         let force_iommu = tdx_enabled;
         #[cfg(feature = "sev_snp")]
@@ -538,11 +711,30 @@ impl Vm {
         let io_bus = Arc::new(Bus::new());
         let mmio_bus = Arc::new(Bus::new());
 
+        #[cfg(target_arch = "x86_64")]
+        let vmbus = if config.lock().unwrap().is_vmbus_enabled() {
+            if matches!(
+                hypervisor.hypervisor_type(),
+                hypervisor::HypervisorType::Kvm
+            ) {
+                Some(Arc::new(Mutex::new(
+                    VmBus::new(memory.clone()).map_err(Error::CreateVmBus)?,
+                )))
+            } else {
+                warn!("VMBus is only supported with KVM, ignoring it");
+                None
+            }
+        } else {
+            None
+        };
+
         let vm_ops: Arc<dyn VmOps> = Arc::new(VmOpsHandler {
             memory,
             #[cfg(target_arch = "x86_64")]
             io_bus: io_bus.clone(),
             mmio_bus: mmio_bus.clone(),
+            #[cfg(target_arch = "x86_64")]
+            vmbus: vmbus.clone(),
         });
 
         let cpus_config = { &config.lock().unwrap().cpus.clone() };
@@ -551,6 +743,7 @@ impl Vm {
             vm.clone(),
             exit_evt.try_clone().map_err(Error::EventFdClone)?,
             reset_evt.try_clone().map_err(Error::EventFdClone)?,
+            crash_evt.try_clone().map_err(Error::EventFdClone)?,
             #[cfg(feature = "guest_debug")]
             vm_debug_evt,
             &hypervisor,
@@ -571,6 +764,7 @@ impl Vm {
             .populate_cpuid(
                 &memory_manager,
                 &hypervisor,
+                config.lock().unwrap().is_vmbus_enabled(),
                 #[cfg(feature = "tdx")]
                 tdx_enabled,
             )
@@ -581,8 +775,15 @@ impl Vm {
         #[cfg(feature = "tdx")]
         if tdx_enabled {
             let cpuid = cpu_manager.lock().unwrap().common_cpuid();
-            let max_vcpus = cpu_manager.lock().unwrap().max_vcpus() as u32;
-            vm.tdx_init(&cpuid, max_vcpus)
+            let max_vcpus = cpu_manager.lock().unwrap().max_vcpus();
+            let num_l2_vms = config
+                .lock()
+                .unwrap()
+                .platform
+                .as_ref()
+                .map(|p| p.tdx_l2_vms)
+                .unwrap_or_default();
+            vm.tdx_init(&cpuid, max_vcpus, num_l2_vms)
                 .map_err(Error::InitializeTdxVm)?;
         }
 
@@ -610,16 +811,20 @@ impl Vm {
             memory_manager.clone(),
             cpu_manager.clone(),
             exit_evt.try_clone().map_err(Error::EventFdClone)?,
-            reset_evt,
+            reset_evt.try_clone().map_err(Error::EventFdClone)?,
+            crash_evt.try_clone().map_err(Error::EventFdClone)?,
             seccomp_action.clone(),
             numa_nodes.clone(),
             &activate_evt,
+            &device_reset_evt,
             force_iommu,
             boot_id_list,
             #[cfg(not(target_arch = "riscv64"))]
             timestamp,
             snapshot_from_id(snapshot.as_ref(), DEVICE_MANAGER_SNAPSHOT_ID),
             dynamic,
+            #[cfg(target_arch = "x86_64")]
+            vmbus,
         )
         .map_err(Error::DeviceManager)?;
 
@@ -673,8 +878,6 @@ impl Vm {
                 &config,
                 #[cfg(feature = "igvm")]
                 &cpu_manager,
-                #[cfg(feature = "sev_snp")]
-                sev_snp_enabled,
             )?
         } else {
             None
@@ -722,20 +925,20 @@ impl Vm {
             .unwrap()
             .payload
             .as_ref()
-            .map(|p| p.kernel.as_ref().map(File::open))
-            .unwrap_or_default()
+            .map(|p| p.open_kernel())
             .transpose()
-            .map_err(Error::KernelFile)?;
+            .map_err(Error::KernelFile)?
+            .flatten();
 
         let initramfs = config
             .lock()
             .unwrap()
             .payload
             .as_ref()
-            .map(|p| p.initramfs.as_ref().map(File::open))
-            .unwrap_or_default()
+            .map(|p| p.open_initramfs())
             .transpose()
-            .map_err(Error::InitramfsFile)?;
+            .map_err(Error::InitramfsFile)?
+            .unwrap_or_default();
 
         #[cfg(target_arch = "x86_64")]
         let saved_clock = if let Some(snapshot) = snapshot.as_ref() {
@@ -745,6 +948,12 @@ impl Vm {
             None
         };
 
+        // The VMM updates the rate-limit schedules every minute from now on.
+        device_manager
+            .lock()
+            .unwrap()
+            .update_rate_limit_schedules(minute_of_day());
+
         let vm_state = if snapshot.is_some() {
             VmState::Paused
         } else {
@@ -770,6 +979,8 @@ impl Vm {
             hypervisor,
             stop_on_boot,
             load_payload_handle,
+            #[cfg(target_arch = "x86_64")]
+            reset_evt,
         })
     }
 
@@ -862,16 +1073,19 @@ impl Vm {
         vm_config: Arc<Mutex<VmConfig>>,
         exit_evt: EventFd,
         reset_evt: EventFd,
+        crash_evt: EventFd,
         #[cfg(feature = "guest_debug")] vm_debug_evt: EventFd,
         seccomp_action: &SeccompAction,
         hypervisor: Arc<dyn hypervisor::Hypervisor>,
         activate_evt: EventFd,
+        device_reset_evt: EventFd,
         console_info: Option<ConsoleInfo>,
         console_resize_pipe: Option<Arc<File>>,
         original_termios: Arc<Mutex<Option<termios>>>,
         snapshot: Option<Snapshot>,
         source_url: Option<&str>,
         prefault: Option<bool>,
+        cow_memory: bool,
     ) -> Result<Self> {
         trace_scoped!("Vm::new");
 
@@ -900,8 +1114,27 @@ impl Vm {
             sev_snp_enabled,
             #[cfg(feature = "sev_snp")]
             vm_config.lock().unwrap().memory.total_size(),
+            #[cfg(target_arch = "x86_64")]
+            vm_config.lock().unwrap().cpus.has_wide_apic_ids(),
         )?;
 
+        // Restored VMs keep the placement they were created with.
+        if snapshot.is_none() && vm_config.lock().unwrap().is_auto_numa_enabled() {
+            let host_nodes = numa::host_nodes().map_err(Error::AutoNuma)?;
+            numa::place(
+                &mut vm_config.lock().unwrap(),
+                &host_nodes,
+                numa::vfio_device_node,
+            );
+        }
+
+        // Distances are derived once the memory zones are bound to the host
+        // nodes, which the automatic placement may have just done.
+        if snapshot.is_none() && vm_config.lock().unwrap().is_host_numa_distances_enabled() {
+            let host_distances = numa::host_distances().map_err(Error::HostNumaDistances)?;
+            numa::derive_distances(&mut vm_config.lock().unwrap(), &host_distances);
+        }
+
         let phys_bits = physical_bits(&hypervisor, vm_config.lock().unwrap().cpus.max_phys_bits);
 
         let memory_manager = if let Some(snapshot) =
@@ -913,6 +1146,7 @@ impl Vm {
                 &vm_config.lock().unwrap().memory.clone(),
                 source_url,
                 prefault.unwrap(),
+                cow_memory,
                 phys_bits,
             )
             .map_err(Error::MemoryManager)?
@@ -941,11 +1175,13 @@ impl Vm {
             vm,
             exit_evt,
             reset_evt,
+            crash_evt,
             #[cfg(feature = "guest_debug")]
             vm_debug_evt,
             seccomp_action,
             hypervisor,
             activate_evt,
+            device_reset_evt,
             #[cfg(not(target_arch = "riscv64"))]
             timestamp,
             console_info,
@@ -960,6 +1196,7 @@ impl Vm {
         #[cfg(feature = "tdx")] tdx_enabled: bool,
         #[cfg(feature = "sev_snp")] sev_snp_enabled: bool,
         #[cfg(feature = "sev_snp")] mem_size: u64,
+        #[cfg(target_arch = "x86_64")] wide_apic_ids: bool,
     ) -> Result<Arc<dyn hypervisor::Vm>> {
         hypervisor.check_required_extensions().unwrap();
 
@@ -989,29 +1226,57 @@ impl Vm {
                 .unwrap();
             vm.set_tss_address(KVM_TSS_START.0 as usize).unwrap();
             vm.enable_split_irq().unwrap();
+            // 32 bits APIC IDs are only needed to reach the vCPUs whose APIC
+            // ID doesn't fit in 8 bits, and they also drop the broadcast quirk
+            // from the LAPIC emulation.
+            if wide_apic_ids {
+                vm.enable_x2apic_api().map_err(Error::EnableX2ApicApi)?;
+            }
         }
 
         Ok(vm)
     }
 
     fn load_initramfs(&mut self, guest_mem: &GuestMemoryMmap) -> Result<arch::InitramfsConfig> {
-        let initramfs = self.initramfs.as_mut().unwrap();
-        let size: usize = initramfs
-            .seek(SeekFrom::End(0))
-            .map_err(|_| Error::InitramfsLoad)?
-            .try_into()
-            .unwrap();
-        initramfs.rewind().map_err(|_| Error::InitramfsLoad)?;
+        // Images are concatenated, each one starting on a 4 bytes boundary
+        // as expected by the kernel when unpacking cpio archives.
+        let mut offsets = Vec::with_capacity(self.initramfs.len());
+        let mut size = 0;
+        for initramfs in self.initramfs.iter_mut() {
+            let image_size: usize = initramfs
+                .seek(SeekFrom::End(0))
+                .map_err(|_| Error::InitramfsLoad)?
+                .try_into()
+                .unwrap();
+            initramfs.rewind().map_err(|_| Error::InitramfsLoad)?;
+
+            size = size.next_multiple_of(4);
+            offsets.push((size, image_size));
+            size += image_size;
+        }
 
         let address =
             arch::initramfs_load_addr(guest_mem, size).map_err(|_| Error::InitramfsLoad)?;
         let address = GuestAddress(address);
 
-        guest_mem
-            .read_volatile_from(address, initramfs, size)
-            .map_err(|_| Error::InitramfsLoad)?;
+        let mut end = 0;
+        for (initramfs, (offset, image_size)) in self.initramfs.iter_mut().zip(offsets) {
+            // Clear the padding, the memory might have been used before a
+            // reboot.
+            guest_mem
+                .write_slice(&[0u8; 3][..offset - end], address.unchecked_add(end as u64))
+                .map_err(|_| Error::InitramfsLoad)?;
+            guest_mem
+                .read_volatile_from(address.unchecked_add(offset as u64), initramfs, image_size)
+                .map_err(|_| Error::InitramfsLoad)?;
+            end = offset + image_size;
+        }
 
-        info!("Initramfs loaded: address = 0x{:x}", address.0);
+        info!(
+            "Initramfs loaded: address = 0x{:x}, images = {}",
+            address.0,
+            self.initramfs.len()
+        );
         Ok(arch::InitramfsConfig { address, size })
     }
 
@@ -1125,32 +1390,41 @@ impl Vm {
     #[cfg(feature = "igvm")]
     fn load_igvm(
         igvm: File,
+        cmdline: &str,
         memory_manager: Arc<Mutex<MemoryManager>>,
         cpu_manager: Arc<Mutex<cpu::CpuManager>>,
         #[cfg(feature = "sev_snp")] host_data: &Option<String>,
+        #[cfg(feature = "sev_snp")] id_block: Option<&std::path::Path>,
+        #[cfg(feature = "sev_snp")] id_auth: Option<&std::path::Path>,
     ) -> Result<EntryPoint> {
         let res = igvm_loader::load_igvm(
             &igvm,
             memory_manager,
             cpu_manager.clone(),
-            "",
+            cmdline,
             #[cfg(feature = "sev_snp")]
             host_data,
+            #[cfg(feature = "sev_snp")]
+            id_block,
+            #[cfg(feature = "sev_snp")]
+            id_auth,
         )
         .map_err(Error::IgvmLoad)?;
 
-        cfg_if::cfg_if! {
-            if #[cfg(feature = "sev_snp")] {
-                let entry_point = if cpu_manager.lock().unwrap().sev_snp_enabled() {
-                    EntryPoint { entry_addr: vm_memory::GuestAddress(res.vmsa_gpa), setup_header: None }
-                } else {
-                    EntryPoint {entry_addr: vm_memory::GuestAddress(res.vmsa.rip), setup_header: None }
-                };
-            } else {
-               let entry_point = EntryPoint { entry_addr: vm_memory::GuestAddress(res.vmsa.rip), setup_header: None };
-            }
+        // Non isolated guests start from the native VP context if the file
+        // provides one, falling back onto the SNP one.
+        let entry_addr = res.native_entry.unwrap_or(res.vmsa.rip);
+        #[cfg(feature = "sev_snp")]
+        let entry_addr = if cpu_manager.lock().unwrap().sev_snp_enabled() {
+            res.vmsa_gpa
+        } else {
+            entry_addr
         };
-        Ok(entry_point)
+
+        Ok(EntryPoint {
+            entry_addr: vm_memory::GuestAddress(entry_addr),
+            setup_header: None,
+        })
     }
 
     #[cfg(target_arch = "x86_64")]
@@ -1173,6 +1447,11 @@ impl Vm {
             &mut kernel,
             Some(arch::layout::HIGH_RAM_START),
         )
+        // Try 32-bit ELF binary with PVH boot.
+        .or_else(|e| {
+            arch::x86_64::elf32::load_elf32(mem.deref(), &mut kernel, arch::layout::HIGH_RAM_START)
+                .map_err(|_| e)
+        })
         // Try loading kernel as bzImage.
         .or_else(|_| {
             BzImage::load(
@@ -1216,33 +1495,32 @@ impl Vm {
         payload: &PayloadConfig,
         memory_manager: Arc<Mutex<MemoryManager>>,
         #[cfg(feature = "igvm")] cpu_manager: Arc<Mutex<cpu::CpuManager>>,
-        #[cfg(feature = "sev_snp")] sev_snp_enabled: bool,
     ) -> Result<EntryPoint> {
         trace_scoped!("load_payload");
         #[cfg(feature = "igvm")]
         {
-            if let Some(_igvm_file) = &payload.igvm {
-                let igvm = File::open(_igvm_file).map_err(Error::IgvmFile)?;
-                #[cfg(feature = "sev_snp")]
-                if sev_snp_enabled {
-                    return Self::load_igvm(igvm, memory_manager, cpu_manager, &payload.host_data);
-                }
-                #[cfg(not(feature = "sev_snp"))]
-                return Self::load_igvm(igvm, memory_manager, cpu_manager);
+            if let Some(igvm) = payload.open_igvm().map_err(Error::IgvmFile)? {
+                return Self::load_igvm(
+                    igvm,
+                    payload.cmdline.as_deref().unwrap_or_default(),
+                    memory_manager,
+                    cpu_manager,
+                    #[cfg(feature = "sev_snp")]
+                    &payload.host_data,
+                    #[cfg(feature = "sev_snp")]
+                    payload.id_block.as_deref(),
+                    #[cfg(feature = "sev_snp")]
+                    payload.id_auth.as_deref(),
+                );
             }
         }
-        match (
-            &payload.firmware,
-            &payload.kernel,
-            &payload.initramfs,
-            &payload.cmdline,
-        ) {
-            (Some(firmware), None, None, None) => {
-                let firmware = File::open(firmware).map_err(Error::FirmwareFile)?;
+        let firmware = payload.open_firmware().map_err(Error::FirmwareFile)?;
+        let kernel = payload.open_kernel().map_err(Error::KernelFile)?;
+        match (firmware, kernel, payload.has_initramfs(), &payload.cmdline) {
+            (Some(firmware), None, false, None) => {
                 Self::load_kernel(firmware, None, memory_manager)
             }
             (None, Some(kernel), _, _) => {
-                let kernel = File::open(kernel).map_err(Error::KernelFile)?;
                 let cmdline = Self::generate_cmdline(payload)?;
                 Self::load_kernel(kernel, Some(cmdline), memory_manager)
             }
@@ -1255,15 +1533,11 @@ impl Vm {
         payload: &PayloadConfig,
         memory_manager: Arc<Mutex<MemoryManager>>,
     ) -> Result<EntryPoint> {
-        match (&payload.firmware, &payload.kernel) {
-            (Some(firmware), None) => {
-                let firmware = File::open(firmware).map_err(Error::FirmwareFile)?;
-                Self::load_kernel(Some(firmware), None, memory_manager)
-            }
-            (None, Some(kernel)) => {
-                let kernel = File::open(kernel).map_err(Error::KernelFile)?;
-                Self::load_kernel(None, Some(kernel), memory_manager)
-            }
+        let firmware = payload.open_firmware().map_err(Error::FirmwareFile)?;
+        let kernel = payload.open_kernel().map_err(Error::KernelFile)?;
+        match (firmware, kernel) {
+            (Some(firmware), None) => Self::load_kernel(Some(firmware), None, memory_manager),
+            (None, Some(kernel)) => Self::load_kernel(None, Some(kernel), memory_manager),
             _ => Err(Error::InvalidPayload),
         }
     }
@@ -1272,7 +1546,6 @@ impl Vm {
         memory_manager: &Arc<Mutex<MemoryManager>>,
         config: &Arc<Mutex<VmConfig>>,
         #[cfg(feature = "igvm")] cpu_manager: &Arc<Mutex<cpu::CpuManager>>,
-        #[cfg(feature = "sev_snp")] sev_snp_enabled: bool,
     ) -> Result<Option<thread::JoinHandle<Result<EntryPoint>>>> {
         // Kernel with TDX is loaded in a different manner
         #[cfg(feature = "tdx")]
@@ -1299,8 +1572,6 @@ impl Vm {
                             memory_manager,
                             #[cfg(feature = "igvm")]
                             cpu_manager,
-                            #[cfg(feature = "sev_snp")]
-                            sev_snp_enabled,
                         )
                     })
                     .map_err(Error::KernelLoadThreadSpawn)
@@ -1314,9 +1585,10 @@ impl Vm {
         info!("Configuring system");
         let mem = self.memory_manager.lock().unwrap().boot_guest_memory();
 
-        let initramfs_config = match self.initramfs {
-            Some(_) => Some(self.load_initramfs(&mem)?),
-            None => None,
+        let initramfs_config = if self.initramfs.is_empty() {
+            None
+        } else {
+            Some(self.load_initramfs(&mem)?)
         };
 
         let boot_vcpus = self.cpu_manager.lock().unwrap().boot_vcpus();
@@ -1377,23 +1649,37 @@ impl Vm {
         Ok(())
     }
 
+    // Kernel command line as passed through the device tree
+    #[cfg(target_arch = "aarch64")]
+    fn boot_cmdline(&self) -> Result<Cmdline> {
+        let mut cmdline = Self::generate_cmdline(
+            self.config.lock().unwrap().payload.as_ref().unwrap(),
+            &self.device_manager,
+        )?;
+        // Make the kernel use ACPI even though a device tree is provided.
+        if self.config.lock().unwrap().boot_method() == BootMethod::Acpi {
+            cmdline
+                .insert_str("acpi=force")
+                .map_err(Error::CmdLineInsertStr)?;
+        }
+        Ok(cmdline)
+    }
+
     #[cfg(target_arch = "aarch64")]
     fn configure_system(
         &mut self,
         _rsdp_addr: GuestAddress,
         _entry_addr: EntryPoint,
     ) -> Result<()> {
-        let cmdline = Self::generate_cmdline(
-            self.config.lock().unwrap().payload.as_ref().unwrap(),
-            &self.device_manager,
-        )?;
+        let cmdline = self.boot_cmdline()?;
         let vcpu_mpidrs = self.cpu_manager.lock().unwrap().get_mpidrs();
         let vcpu_topology = self.cpu_manager.lock().unwrap().get_vcpu_topology();
         let mem = self.memory_manager.lock().unwrap().boot_guest_memory();
         let mut pci_space_info: Vec<PciSpaceInfo> = Vec::new();
-        let initramfs_config = match self.initramfs {
-            Some(_) => Some(self.load_initramfs(&mem)?),
-            None => None,
+        let initramfs_config = if self.initramfs.is_empty() {
+            None
+        } else {
+            Some(self.load_initramfs(&mem)?)
         };
 
         let device_info = &self
@@ -1403,7 +1689,16 @@ impl Vm {
             .get_device_info()
             .clone();
 
-        for pci_segment in self.device_manager.lock().unwrap().pci_segments().iter() {
+        // Segments reserved for being added at runtime can only be found
+        // through ACPI.
+        for pci_segment in self
+            .device_manager
+            .lock()
+            .unwrap()
+            .pci_segments()
+            .iter()
+            .filter(|s| s.present)
+        {
             let pci_space = PciSpaceInfo {
                 pci_segment_id: pci_segment.id,
                 mmio_config_address: pci_segment.mmio_config_address,
@@ -1462,6 +1757,7 @@ impl Vm {
             &vgic,
             &self.numa_nodes,
             pmu_supported,
+            self.config.lock().unwrap().scmi.is_some(),
         )
         .map_err(Error::ConfigureSystem)?;
 
@@ -1477,9 +1773,10 @@ impl Vm {
         let num_vcpu = self.cpu_manager.lock().unwrap().vcpus().len();
         let mem = self.memory_manager.lock().unwrap().boot_guest_memory();
         let mut pci_space_info: Vec<PciSpaceInfo> = Vec::new();
-        let initramfs_config = match self.initramfs {
-            Some(_) => Some(self.load_initramfs(&mem)?),
-            None => None,
+        let initramfs_config = if self.initramfs.is_empty() {
+            None
+        } else {
+            Some(self.load_initramfs(&mem)?)
         };
 
         let device_info = &self
@@ -1489,7 +1786,16 @@ impl Vm {
             .get_device_info()
             .clone();
 
-        for pci_segment in self.device_manager.lock().unwrap().pci_segments().iter() {
+        // Segments reserved for being added at runtime can only be found
+        // through ACPI.
+        for pci_segment in self
+            .device_manager
+            .lock()
+            .unwrap()
+            .pci_segments()
+            .iter()
+            .filter(|s| s.present)
+        {
             let pci_space = PciSpaceInfo {
                 pci_segment_id: pci_segment.id,
                 mmio_config_address: pci_segment.mmio_config_address,
@@ -1557,6 +1863,14 @@ impl Vm {
             .shutdown()
             .map_err(Error::CpuManager)?;
 
+        // Release the boot watchdog if it is still waiting for the guest
+        #[cfg(target_arch = "x86_64")]
+        self.device_manager
+            .lock()
+            .unwrap()
+            .boot_milestone()
+            .store(true, Ordering::SeqCst);
+
         // Wait for all the threads to finish
         for thread in self.threads.drain(..) {
             thread.join().map_err(Error::ThreadCleanup)?
@@ -1568,7 +1882,7 @@ impl Vm {
 
     pub fn resize(
         &mut self,
-        desired_vcpus: Option<u8>,
+        desired_vcpus: Option<u32>,
         desired_memory: Option<u64>,
         desired_balloon: Option<u64>,
     ) -> Result<()> {
@@ -1651,6 +1965,94 @@ impl Vm {
 
         event!("vm", "resized");
 
+        self.write_resource_manifest()
+    }
+
+    pub fn set_balloon(
+        &mut self,
+        deflate_on_oom: Option<bool>,
+        free_page_reporting: Option<bool>,
+    ) -> Result<()> {
+        let Some(balloon_config) = self.config.lock().unwrap().balloon.clone() else {
+            return Err(Error::NoBalloon);
+        };
+
+        let offered = self
+            .device_manager
+            .lock()
+            .unwrap()
+            .set_balloon_features(
+                deflate_on_oom.unwrap_or(balloon_config.deflate_on_oom),
+                free_page_reporting.unwrap_or(balloon_config.free_page_reporting),
+            )
+            .map_err(Error::DeviceManager)?;
+        if !offered {
+            info!("Free page reporting will be offered to the guest after a reboot");
+        }
+
+        // Update VmConfig so that the features are kept after a reboot, which
+        // also creates the free page reporting queue if needed.
+        self.config
+            .lock()
+            .unwrap()
+            .set_balloon(deflate_on_oom, free_page_reporting);
+
+        Ok(())
+    }
+
+    /// Resizes the balloon following the host memory pressure, if it was
+    /// configured with `auto_resize`.
+    pub fn adjust_balloon(&mut self) -> Result<()> {
+        let Some(balloon_config) = self
+            .config
+            .lock()
+            .unwrap()
+            .balloon
+            .clone()
+            .filter(|b| b.auto_resize)
+        else {
+            return Ok(());
+        };
+
+        if self.get_state()? != VmState::Running {
+            return Ok(());
+        }
+
+        let pressure = match balloon_pressure::host_memory_pressure() {
+            Ok(pressure) => pressure,
+            Err(e) => {
+                warn!("Not resizing the balloon: {e}");
+                return Ok(());
+            }
+        };
+
+        let Some(target) =
+            balloon_pressure::balloon_target(&balloon_config, balloon_config.size, pressure)
+        else {
+            return Ok(());
+        };
+
+        self.device_manager
+            .lock()
+            .unwrap()
+            .resize_balloon(target)
+            .map_err(Error::DeviceManager)?;
+
+        if let Some(balloon_config) = &mut self.config.lock().unwrap().balloon {
+            balloon_config.size = target;
+        }
+
+        event!(
+            "vm",
+            "balloon-adjusted",
+            "pressure",
+            format!("{pressure:.2}"),
+            "from",
+            balloon_config.size.to_string(),
+            "to",
+            target.to_string()
+        );
+
         Ok(())
     }
 
@@ -1711,6 +2113,8 @@ impl Vm {
             .notify_hotplug(AcpiNotificationFlags::PCI_DEVICES_CHANGED)
             .map_err(Error::DeviceManager)?;
 
+        self.write_resource_manifest()?;
+
         Ok(pci_device_info)
     }
 
@@ -1754,9 +2158,241 @@ impl Vm {
             .unwrap()
             .notify_hotplug(AcpiNotificationFlags::PCI_DEVICES_CHANGED)
             .map_err(Error::DeviceManager)?;
+
+        self.write_resource_manifest()
+    }
+
+    pub fn reset_device(&mut self, id: String) -> Result<()> {
+        self.device_manager
+            .lock()
+            .unwrap()
+            .reset_device(id)
+            .map_err(Error::DeviceManager)?;
+
+        self.device_manager
+            .lock()
+            .unwrap()
+            .notify_hotplug(AcpiNotificationFlags::PCI_DEVICES_CHANGED)
+            .map_err(Error::DeviceManager)?;
+        Ok(())
+    }
+
+    pub fn add_rate_limit_group(
+        &mut self,
+        rate_limit_group_cfg: RateLimiterGroupConfig,
+    ) -> Result<()> {
+        self.device_manager
+            .lock()
+            .unwrap()
+            .add_rate_limit_group(&rate_limit_group_cfg)
+            .map_err(Error::DeviceManager)?;
+
+        // Update VmConfig so that the group is recreated after a reboot.
+        add_to_config(
+            &mut self.config.lock().unwrap().rate_limit_groups,
+            rate_limit_group_cfg,
+        );
+
+        Ok(())
+    }
+
+    pub fn set_rate_limit_group(
+        &mut self,
+        id: String,
+        rate_limit_group: Option<String>,
+    ) -> Result<()> {
+        self.device_manager
+            .lock()
+            .unwrap()
+            .set_rate_limit_group(&id, rate_limit_group.as_deref())
+            .map_err(Error::DeviceManager)?;
+
+        // Update VmConfig so that the device remains in the group after a
+        // reboot.
+        self.config
+            .lock()
+            .unwrap()
+            .set_rate_limit_group(&id, rate_limit_group);
+
+        Ok(())
+    }
+
+    pub fn resize_disk(&mut self, id: String, desired_size: u64) -> Result<()> {
+        self.device_manager
+            .lock()
+            .unwrap()
+            .resize_disk(&id, desired_size)
+            .map_err(Error::DeviceManager)
+    }
+
+    /// Switches the disks to QCOW2 overlays backed by their current image,
+    /// leaving the latter unmodified for a backup. The guest filesystems
+    /// are frozen meanwhile through the guest agent when `quiesce` is set.
+    pub fn backup(&mut self, backup_data: &VmBackupData) -> Result<()> {
+        if backup_data.quiesce {
+            self.guest_command("guest-fsfreeze-freeze", None)?;
+        }
+
+        let result = self.switch_disks_to_overlays(backup_data);
+
+        if backup_data.quiesce {
+            let thawed = self
+                .guest_command("guest-fsfreeze-thaw", None)
+                .inspect_err(|e| error!("Error thawing the guest filesystems: {:?}", e));
+            return result.and(thawed.map(|_| ()));
+        }
+
+        result
+    }
+
+    fn switch_disks_to_overlays(&mut self, backup_data: &VmBackupData) -> Result<()> {
+        // Create all the overlays first, not to leave only part of the disks
+        // switched when one of them can't be created.
+        let mut overlays = Vec::new();
+        for disk in backup_data.disks.iter() {
+            let overlay = self
+                .device_manager
+                .lock()
+                .unwrap()
+                .create_disk_overlay(&disk.id, &disk.overlay);
+            match overlay {
+                Ok(overlay) => overlays.push(overlay),
+                Err(e) => {
+                    for (disk_cfg, _) in overlays {
+                        if let Some(path) = disk_cfg.path {
+                            let _ = std::fs::remove_file(path);
+                        }
+                    }
+                    return Err(Error::DeviceManager(e));
+                }
+            }
+        }
+
+        // The disks are switched with the vCPUs paused, so that their
+        // overlays all start at the same point of the guest execution.
+        let running = self.get_state()? == VmState::Running;
+        if running {
+            self.pause().map_err(Error::Pause)?;
+        }
+
+        let overlay_paths: Vec<std::path::PathBuf> = overlays
+            .iter()
+            .filter_map(|(disk_cfg, _)| disk_cfg.path.clone())
+            .collect();
+        let mut switched = Vec::new();
+        let mut switches = Vec::new();
+        let mut result = Ok(());
+        for (disk_cfg, disk_image) in overlays {
+            let current_cfg = self
+                .config
+                .lock()
+                .unwrap()
+                .disks
+                .iter()
+                .flatten()
+                .find(|current_cfg| current_cfg.id == disk_cfg.id)
+                .cloned();
+            let switch = self
+                .device_manager
+                .lock()
+                .unwrap()
+                .switch_disk_image(disk_cfg, disk_image);
+            match switch {
+                Ok(switch) => {
+                    switches.push(switch);
+                    switched.extend(current_cfg);
+                }
+                Err(e) => {
+                    result = Err(Error::DeviceManager(e));
+                    break;
+                }
+            }
+        }
+
+        // Switch the disks already moved to their overlays back to their
+        // images, not to leave only part of them switched. The queues only
+        // pick up the last switch, so the guest never writes to the overlays.
+        if result.is_err() {
+            for disk_cfg in switched {
+                let id = disk_cfg.id.clone().unwrap_or_default();
+                if let Err(e) = self
+                    .device_manager
+                    .lock()
+                    .unwrap()
+                    .restore_disk_image(disk_cfg)
+                {
+                    error!("Failed to switch disk {} back from its overlay: {}", id, e);
+                }
+            }
+            for path in overlay_paths {
+                if let Err(e) = std::fs::remove_file(&path) {
+                    warn!("Failed to remove overlay {}: {}", path.display(), e);
+                }
+            }
+        }
+
+        if running {
+            self.resume().map_err(Error::Resume)?;
+        }
+        result?;
+
+        // The queues of a paused VM only switch once it is resumed.
+        if running
+            && !switches
+                .into_iter()
+                .all(|switch| switch.wait(DISK_IMAGE_SWITCH_TIMEOUT))
+        {
+            return Err(Error::DiskImageSwitchTimeout);
+        }
+
+        Ok(())
+    }
+
+    /// Switches the disk `id` to a QCOW2 overlay created at `overlay` and
+    /// backed by its current image. Unlike a backup, only the queues of this
+    /// disk are held back while their requests in flight complete and the
+    /// current image is flushed, the vCPUs keep running.
+    pub fn disk_snapshot(&mut self, id: &str, overlay: &std::path::Path) -> Result<()> {
+        let (disk_cfg, disk_image) = self
+            .device_manager
+            .lock()
+            .unwrap()
+            .create_disk_overlay(id, overlay)
+            .map_err(Error::DeviceManager)?;
+
+        let switch = self
+            .device_manager
+            .lock()
+            .unwrap()
+            .switch_disk_image(disk_cfg, disk_image)
+            .map_err(Error::DeviceManager)
+            .inspect_err(|_| {
+                if let Err(e) = std::fs::remove_file(overlay) {
+                    warn!("Failed to remove overlay {}: {}", overlay.display(), e);
+                }
+            })?;
+
+        // The queues of a paused VM only switch once it is resumed.
+        if self.get_state()? == VmState::Running && !switch.wait(DISK_IMAGE_SWITCH_TIMEOUT) {
+            return Err(Error::DiskImageSwitchTimeout);
+        }
+
         Ok(())
     }
 
+    /// Applies the limits of the rate-limit schedules active at the current
+    /// time of the day.
+    pub fn update_rate_limit_schedules(&self) {
+        self.device_manager
+            .lock()
+            .unwrap()
+            .update_rate_limit_schedules(minute_of_day());
+    }
+
+    pub fn check_disk_paths(&self) {
+        self.device_manager.lock().unwrap().check_disk_paths();
+    }
+
     pub fn add_disk(&mut self, mut disk_cfg: DiskConfig) -> Result<PciDeviceInfo> {
         let pci_device_info = self
             .device_manager
@@ -1850,6 +2486,8 @@ impl Vm {
             .notify_hotplug(AcpiNotificationFlags::PCI_DEVICES_CHANGED)
             .map_err(Error::DeviceManager)?;
 
+        self.write_resource_manifest()?;
+
         Ok(pci_device_info)
     }
 
@@ -1877,6 +2515,33 @@ impl Vm {
         Ok(pci_device_info)
     }
 
+    pub fn add_pci_segment(&mut self) -> Result<PciSegmentInfo> {
+        let pci_segment_info = self
+            .device_manager
+            .lock()
+            .unwrap()
+            .add_pci_segment()
+            .map_err(Error::DeviceManager)?;
+
+        // Update VmConfig with the number of segments present. This is
+        // important to ensure the segment would be created in case of a
+        // reboot, segments being added in order.
+        {
+            let mut config = self.config.lock().unwrap();
+            if let Some(platform) = config.platform.as_mut() {
+                platform.num_pci_segments = pci_segment_info.id + 1;
+            }
+        }
+
+        self.device_manager
+            .lock()
+            .unwrap()
+            .notify_hotplug(AcpiNotificationFlags::PCI_DEVICES_CHANGED)
+            .map_err(Error::DeviceManager)?;
+
+        Ok(pci_segment_info)
+    }
+
     pub fn add_vsock(&mut self, mut vsock_cfg: VsockConfig) -> Result<PciDeviceInfo> {
         let pci_device_info = self
             .device_manager
@@ -1902,25 +2567,38 @@ impl Vm {
     }
 
     pub fn counters(&self) -> Result<HashMap<String, HashMap<&'static str, Wrapping<u64>>>> {
-        Ok(self.device_manager.lock().unwrap().counters())
+        let mut counters = self.device_manager.lock().unwrap().counters();
+
+        // Identifiers starting with "__" are reserved, so this can't clash
+        // with any device.
+        match self.memory_manager.lock().unwrap().hugepage_usage() {
+            Ok(usage) => {
+                let mut memory_counters = HashMap::new();
+                memory_counters.insert("resident_bytes", Wrapping(usage.resident));
+                memory_counters.insert("hugepage_bytes", Wrapping(usage.hugepages));
+                counters.insert("__memory".to_string(), memory_counters);
+            }
+            Err(e) => warn!("Failed to read guest memory usage: {}", e),
+        }
+
+        Ok(counters)
     }
 
     #[cfg(feature = "tdx")]
     fn extract_tdvf_sections(&mut self) -> Result<(Vec<TdvfSection>, bool)> {
         use arch::x86_64::tdx::*;
 
-        let firmware_path = self
+        // The TDVF file contains a table of section as well as code
+        let mut firmware_file = self
             .config
             .lock()
             .unwrap()
             .payload
             .as_ref()
             .unwrap()
-            .firmware
-            .clone()
+            .open_firmware()
+            .map_err(Error::LoadTdvf)?
             .ok_or(Error::TdxFirmwareMissing)?;
-        // The TDVF file contains a table of section as well as code
-        let mut firmware_file = File::open(firmware_path).map_err(Error::LoadTdvf)?;
 
         // For all the sections allocate some RAM backing them
         parse_tdvf_sections(&mut firmware_file).map_err(Error::ParseTdvf)
@@ -2019,17 +2697,16 @@ impl Vm {
         }
 
         // The TDVF file contains a table of section as well as code
-        let firmware_path = self
+        let mut firmware_file = self
             .config
             .lock()
             .unwrap()
             .payload
             .as_ref()
             .unwrap()
-            .firmware
-            .clone()
+            .open_firmware()
+            .map_err(Error::LoadTdvf)?
             .ok_or(Error::TdxFirmwareMissing)?;
-        let mut firmware_file = File::open(firmware_path).map_err(Error::LoadTdvf)?;
 
         // The guest memory at this point now has all the required regions so it
         // is safe to copy from the TDVF file into it.
@@ -2126,6 +2803,17 @@ impl Vm {
                 .map_err(Error::PopulateHob)?;
         }
 
+        // Permanent memory sections outside of guest RAM are left to the
+        // guest to accept, the same way as RAM.
+        for section in sections.iter().filter(|s| {
+            matches!(s.r#type, TdvfSectionType::PermMem)
+                && s.attributes & TDVF_SECTION_ATTRIBUTES_PAGE_AUG != 0
+                && !boot_guest_memory.address_in_range(GuestAddress(s.address))
+        }) {
+            hob.add_memory_resource(&mem, section.address, section.size, true, guid_found)
+                .map_err(Error::PopulateHob)?;
+        }
+
         // MMIO regions
         hob.add_mmio_resource(
             &mem,
@@ -2178,17 +2866,27 @@ impl Vm {
 
     #[cfg(feature = "tdx")]
     fn init_tdx_memory(&mut self, sections: &[TdvfSection]) -> Result<()> {
+        use arch::x86_64::tdx::{
+            TDVF_SECTION_ATTRIBUTES_EXTENDMR, TDVF_SECTION_ATTRIBUTES_PAGE_AUG,
+        };
+
         let guest_memory = self.memory_manager.lock().as_ref().unwrap().guest_memory();
         let mem = guest_memory.memory();
 
         for section in sections {
+            // Sections meant to be added lazily are not populated upfront,
+            // the guest accepting them when needed, as it does for RAM.
+            if section.attributes & TDVF_SECTION_ATTRIBUTES_PAGE_AUG != 0 {
+                info!("Leaving TDVF Section unaccepted: {:x?}", section);
+                continue;
+            }
+
             self.vm
                 .tdx_init_memory_region(
                     mem.get_host_address(GuestAddress(section.address)).unwrap() as u64,
                     section.address,
                     section.size,
-                    /* TDVF_SECTION_ATTRIBUTES_EXTENDMR */
-                    section.attributes == 1,
+                    section.attributes & TDVF_SECTION_ATTRIBUTES_EXTENDMR != 0,
                 )
                 .map_err(Error::InitializeTdxMemoryRegion)?;
         }
@@ -2221,6 +2919,92 @@ impl Vm {
         Some(rsdp_addr)
     }
 
+    // Measures the payload loaded by the VMM into the TPM PCRs, and writes
+    // the matching TCG2 event log where the TPM2 ACPI table points to, so
+    // the guest can replay the measurements.
+    #[cfg(not(target_arch = "riscv64"))]
+    fn measure_boot(&mut self) -> Result<()> {
+        let Some(tpm) = self.device_manager.lock().unwrap().tpm_device() else {
+            return Ok(());
+        };
+        // The memory of SEV-SNP guests is populated through IGVM only
+        #[cfg(feature = "sev_snp")]
+        if self.config.lock().unwrap().is_sev_snp_enabled() {
+            return Ok(());
+        }
+        let Some(payload) = self.config.lock().unwrap().payload.clone() else {
+            return Ok(());
+        };
+
+        fn hash_file(file: &mut File) -> Result<Sha256Digest> {
+            let mut hasher = Sha256::new();
+            file.rewind().map_err(Error::MeasurePayload)?;
+            io::copy(file, &mut hasher).map_err(Error::MeasurePayload)?;
+            Ok(hasher.finalize().into())
+        }
+
+        let mut tpm = tpm.lock().unwrap();
+        let mut log = EventLog::new();
+        let mut measure = |pcr: u32, event_type: u32, digest: Sha256Digest, event: &[u8]| {
+            tpm.extend_pcr(pcr, &digest).map_err(Error::ExtendPcr)?;
+            log.add_event(pcr, event_type, &digest, event);
+            Ok::<(), Error>(())
+        };
+
+        let firmware = payload.open_firmware().map_err(Error::FirmwareFile)?;
+        #[cfg(feature = "igvm")]
+        let firmware = match firmware {
+            Some(firmware) => Some(firmware),
+            None => payload.open_igvm().map_err(Error::IgvmFile)?,
+        };
+        if let Some(mut firmware) = firmware {
+            // The firmware measures the rest of the pre-OS environment
+            measure(0, EV_POST_CODE, hash_file(&mut firmware)?, b"Firmware")?;
+        } else if let Some(mut kernel) = payload.open_kernel().map_err(Error::KernelFile)? {
+            // No firmware runs before the kernel, the pre-OS environment
+            // being over once the VMM hands over to it.
+            for pcr in 0..8 {
+                let separator = [0u8; 4];
+                measure(
+                    pcr,
+                    EV_SEPARATOR,
+                    Sha256::digest(separator).into(),
+                    &separator,
+                )?;
+            }
+
+            measure(4, EV_IPL, hash_file(&mut kernel)?, b"Linux kernel")?;
+
+            #[cfg(target_arch = "x86_64")]
+            let cmdline = Self::generate_cmdline(&payload)?;
+            #[cfg(target_arch = "aarch64")]
+            let cmdline = self.boot_cmdline()?;
+            let cmdline = cmdline.as_cstring().map_err(Error::CmdLineCreate)?;
+            let cmdline = cmdline.as_bytes();
+            measure(8, EV_IPL, Sha256::digest(cmdline).into(), cmdline)?;
+
+            for initramfs in self.initramfs.iter_mut() {
+                measure(9, EV_IPL, hash_file(initramfs)?, b"Linux initramfs")?;
+            }
+        }
+
+        let log = log.as_slice();
+        let mut area = vec![0u8; arch::layout::TPM_EVENT_LOG_SIZE as usize];
+        area.get_mut(..log.len())
+            .ok_or(Error::TpmEventLogTooLarge(log.len()))?
+            .copy_from_slice(log);
+        self.memory_manager
+            .lock()
+            .unwrap()
+            .guest_memory()
+            .memory()
+            .write_slice(&area, arch::layout::TPM_EVENT_LOG_START)
+            .map_err(Error::TpmEventLog)?;
+
+        info!("Measured boot: {} bytes of TPM event log", log.len());
+        Ok(())
+    }
+
     fn entry_point(&mut self) -> Result<Option<EntryPoint>> {
         trace_scoped!("entry_point");
 
@@ -2274,6 +3058,12 @@ impl Vm {
         // finish.
         let entry_point = self.entry_point()?;
 
+        // Only payloads loaded by the VMM are measured
+        #[cfg(not(target_arch = "riscv64"))]
+        if entry_point.is_some() {
+            self.measure_boot()?;
+        }
+
         #[cfg(feature = "tdx")]
         let tdx_enabled = self.config.lock().unwrap().is_tdx_enabled();
 
@@ -2329,7 +3119,14 @@ impl Vm {
         // On aarch64 the ACPI tables depend on the vCPU mpidr which is only
         // available after they are configured
         #[cfg(target_arch = "aarch64")]
-        let rsdp_addr = self.create_acpi_tables();
+        let rsdp_addr = if self.config.lock().unwrap().boot_method() == BootMethod::DeviceTree {
+            // The guest is only described through the device tree. The RSDP
+            // address isn't used when configuring the system on aarch64.
+            info!("Skipping ACPI tables creation, booting with a device tree only");
+            Some(GuestAddress(0))
+        } else {
+            self.create_acpi_tables()
+        };
 
         #[cfg(not(target_arch = "riscv64"))]
         // Configure shared state based on loaded kernel
@@ -2372,8 +3169,70 @@ impl Vm {
             .start_boot_vcpus(new_state == VmState::BreakPoint)
             .map_err(Error::CpuManager)?;
 
+        #[cfg(target_arch = "x86_64")]
+        if new_state == VmState::Running {
+            self.start_boot_watchdog()?;
+        }
+
         let mut state = self.state.try_write().map_err(|_| Error::PoisonedState)?;
         *state = new_state;
+        drop(state);
+
+        self.write_resource_manifest()
+    }
+
+    // Reboot the VM on the fallback firmware if the primary one doesn't
+    // report any progress past the firmware range on the debug I/O port
+    // within the configured timeout. The configuration is updated in place
+    // so that the fallback firmware is used from then on.
+    #[cfg(target_arch = "x86_64")]
+    fn start_boot_watchdog(&mut self) -> Result<()> {
+        let Some(timeout) = self
+            .config
+            .lock()
+            .unwrap()
+            .payload
+            .as_ref()
+            .and_then(|p| p.fallback_firmware.as_ref())
+            .map(|f| Duration::from_secs(f.timeout))
+        else {
+            return Ok(());
+        };
+
+        let boot_milestone = self.device_manager.lock().unwrap().boot_milestone();
+        let config = self.config.clone();
+        let reset_evt = self.reset_evt.try_clone().map_err(Error::EventFdClone)?;
+        let handle = thread::Builder::new()
+            .name("boot_watchdog".into())
+            .spawn(move || {
+                let deadline = Instant::now() + timeout;
+                while !boot_milestone.load(Ordering::SeqCst) {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        if let Some(payload) = config.lock().unwrap().payload.as_mut() {
+                            if let Some(fallback) = payload.fallback_firmware.take() {
+                                warn!(
+                                    "Firmware did not hand over within {} seconds, \
+                                    rebooting on {:?}",
+                                    timeout.as_secs(),
+                                    fallback.path
+                                );
+                                payload.firmware = Some(fallback.path);
+                                payload.firmware_fd = None;
+                            }
+                        }
+                        event!("vm", "firmware-fallback");
+                        if let Err(e) = reset_evt.write(1) {
+                            error!("Error triggering fallback firmware reboot: {:?}", e);
+                        }
+                        return;
+                    }
+                    thread::sleep(cmp::min(deadline - now, BOOT_WATCHDOG_POLL_INTERVAL));
+                }
+            })
+            .map_err(Error::BootWatchdogThreadSpawn)?;
+        self.threads.push(handle);
+
         Ok(())
     }
 
@@ -2395,7 +3254,35 @@ impl Vm {
             .map_err(Error::CpuManager)?;
 
         event!("vm", "restored");
-        Ok(())
+        self.write_resource_manifest()
+    }
+
+    /// Reports the host resources used by the VM to the manifest file, if
+    /// any.
+    fn write_resource_manifest(&self) -> Result<()> {
+        let config = self.config.lock().unwrap();
+        let Some(manifest) = config.resources.as_ref().and_then(|r| r.manifest.as_ref()) else {
+            return Ok(());
+        };
+
+        ResourceManifest::new(&config, self.device_manager.lock().unwrap().tap_names())
+            .and_then(|resource_manifest| resource_manifest.write(manifest))
+            .map_err(Error::ResourceManifest)
+    }
+
+    /// Names the TAP interfaces created for the VM in its configuration, so
+    /// that restoring it recreates them with the same names.
+    pub fn pin_tap_names(&self) {
+        let tap_names = self.device_manager.lock().unwrap().tap_names().clone();
+        let mut config = self.config.lock().unwrap();
+        for net in config.net.iter_mut().flatten() {
+            if net.tap.is_some() || net.fds.is_some() {
+                continue;
+            }
+            if let Some(name) = net.id.as_ref().and_then(|id| tap_names.get(id)) {
+                net.tap = Some(name.clone());
+            }
+        }
     }
 
     /// Gets a thread-safe reference counted pointer to the VM configuration.
@@ -2411,11 +3298,24 @@ impl Vm {
             .map(|state| *state)
     }
 
+    /// Takes the cause of the pending guest-initiated exit, if any.
+    pub fn take_exit_reason(&self) -> Option<ExitReason> {
+        self.cpu_manager.lock().unwrap().exit_reason().take()
+    }
+
     /// Gets the actual size of the balloon.
     pub fn balloon_size(&self) -> u64 {
         self.device_manager.lock().unwrap().balloon_size()
     }
 
+    pub fn disk_queues(&self) -> Vec<DiskQueueInfo> {
+        self.device_manager.lock().unwrap().disk_queues()
+    }
+
+    pub fn balloon_info(&self) -> Option<BalloonInfo> {
+        self.device_manager.lock().unwrap().balloon_info()
+    }
+
     pub fn send_memory_fds(
         &mut self,
         socket: &mut UnixStream,
@@ -2500,6 +3400,39 @@ impl Vm {
         self.device_manager.lock().unwrap().device_tree()
     }
 
+    /// SGX EPC sections mapped into the guest, along with the NUMA node they
+    /// belong to. Sections added after boot are only mapped on reboot.
+    #[cfg(target_arch = "x86_64")]
+    pub fn sgx_epc_sections(&self) -> Option<Vec<SgxEpcSectionInfo>> {
+        let memory_manager = self.memory_manager.lock().unwrap();
+        let sgx_epc_region = memory_manager.sgx_epc_region().as_ref()?;
+        let config = self.config.lock().unwrap();
+
+        let mut sections: Vec<SgxEpcSectionInfo> = sgx_epc_region
+            .epc_sections()
+            .iter()
+            .map(|(id, section)| SgxEpcSectionInfo {
+                id: id.clone(),
+                start: section.start().raw_value(),
+                size: section.size(),
+                prefault: config
+                    .sgx_epc
+                    .iter()
+                    .flatten()
+                    .any(|sgx_epc| &sgx_epc.id == id && sgx_epc.prefault),
+                guest_numa_id: config
+                    .numa
+                    .iter()
+                    .flatten()
+                    .find(|node| node.sgx_epc_sections.iter().flatten().any(|s| s == id))
+                    .map(|node| node.guest_numa_id),
+            })
+            .collect();
+        sections.sort_by_key(|section| section.start);
+
+        Some(sections)
+    }
+
     /// Release all advisory locks held for the disk images.
     ///
     /// This should only be called when the VM is stopped and the VMM supposed
@@ -2523,6 +3456,14 @@ impl Vm {
             .map_err(Error::ActivateVirtioDevices)
     }
 
+    pub fn complete_device_resets(&self) -> Result<()> {
+        self.device_manager
+            .lock()
+            .unwrap()
+            .complete_device_resets()
+            .map_err(Error::DeviceManager)
+    }
+
     #[cfg(target_arch = "x86_64")]
     pub fn power_button(&self) -> Result<()> {
         return self
@@ -2551,6 +3492,10 @@ impl Vm {
         self.memory_manager.lock().unwrap().snapshot_data()
     }
 
+    pub fn guest_memory(&self) -> GuestMemoryAtomic<GuestMemoryMmap> {
+        self.memory_manager.lock().unwrap().guest_memory()
+    }
+
     #[cfg(feature = "guest_debug")]
     pub fn debug_request(
         &mut self,
@@ -2559,13 +3504,26 @@ impl Vm {
     ) -> Result<GdbResponsePayload> {
         use GdbRequestPayload::*;
         match gdb_request {
-            SetSingleStep(single_step) => {
-                self.set_guest_debug(cpu_id, &[], *single_step)
+            SetGuestDebug {
+                breakpoints,
+                watchpoints,
+                single_step,
+            } => {
+                // Breakpoints and watchpoints apply to every vCPU, while
+                // only the vCPU being stepped has single step enabled.
+                for vcpu_id in 0..self.active_vcpus() {
+                    self.set_guest_debug(
+                        vcpu_id,
+                        breakpoints,
+                        watchpoints,
+                        *single_step == Some(vcpu_id),
+                    )
                     .map_err(Error::Debug)?;
+                }
             }
-            SetHwBreakPoint(addrs) => {
-                self.set_guest_debug(cpu_id, addrs, false)
-                    .map_err(Error::Debug)?;
+            DebugStops => {
+                let stops = self.debug_stops();
+                return Ok(GdbResponsePayload::DebugStops(stops));
             }
             Pause => {
                 self.debug_pause().map_err(Error::Debug)?;
@@ -2605,7 +3563,7 @@ impl Vm {
         &mut self,
         destination_url: &str,
     ) -> std::result::Result<DumpState, GuestDebuggableError> {
-        let nr_cpus = self.config.lock().unwrap().cpus.boot_vcpus as u32;
+        let nr_cpus = self.config.lock().unwrap().cpus.boot_vcpus;
         let elf_note_size = self.get_note_size(NoteDescType::ElfAndVmm, nr_cpus) as isize;
         let mut elf_phdr_num = 1;
         let elf_sh_info = 0;
@@ -2656,6 +3614,61 @@ impl Vm {
             .nmi()
             .map_err(|_| Error::ErrorNmi);
     }
+
+    #[cfg(feature = "sev_snp")]
+    fn load_sev_snp_certificates(
+        vm: &Arc<dyn hypervisor::Vm>,
+        path: &std::path::Path,
+    ) -> Result<()> {
+        let certs = std::fs::read(path).map_err(Error::SevSnpCertificates)?;
+        vm.sev_snp_set_certificates(certs)
+            .map_err(Error::SetSevSnpCertificates)
+    }
+
+    /// Reload the platform certificates handed to the guest, so that it can
+    /// attest again after the host firmware or its endorsement changed.
+    pub fn refresh_platform_certificates(&self) -> Result<()> {
+        #[cfg(feature = "sev_snp")]
+        {
+            let config = self.config.lock().unwrap();
+            if config.is_sev_snp_enabled() {
+                if let Some(snp_certs) = config.payload.as_ref().and_then(|p| p.snp_certs.as_ref())
+                {
+                    Self::load_sev_snp_certificates(&self.vm, snp_certs)?;
+                    event!("vm", "platform-certificates-updated");
+                    return Ok(());
+                }
+            }
+        }
+
+        Err(Error::PlatformCertificatesUnsupported)
+    }
+
+    pub fn guest_command(
+        &self,
+        command: &str,
+        arguments: Option<serde_json::Value>,
+    ) -> Result<serde_json::Value> {
+        let (socket, port) = {
+            let config = self.config.lock().unwrap();
+            let port = config
+                .guest_agent
+                .as_ref()
+                .ok_or(Error::GuestAgentNotEnabled)?
+                .port;
+            let socket = config
+                .vsock
+                .as_ref()
+                .ok_or(Error::GuestAgentNotEnabled)?
+                .socket
+                .clone();
+            (socket, port)
+        };
+
+        GuestAgent::connect(&socket, port)
+            .and_then(|mut agent| agent.execute(command, arguments))
+            .map_err(Error::GuestAgent)
+    }
 }
 
 impl Pausable for Vm {
@@ -2784,6 +3797,8 @@ impl Snapshottable for Vm {
                     sgx_epc_sections: None,
                     phys_bits,
                     kvm_hyperv: self.config.lock().unwrap().cpus.kvm_hyperv,
+                    vmbus: self.config.lock().unwrap().is_vmbus_enabled(),
+                    msi_ext_dest_id: self.config.lock().unwrap().cpus.has_wide_apic_ids(),
                     #[cfg(feature = "tdx")]
                     tdx: false,
                     amx,
@@ -2919,12 +3934,17 @@ impl Debuggable for Vm {
         &self,
         cpu_id: usize,
         addrs: &[GuestAddress],
+        watchpoints: &[hypervisor::HwWatchpoint],
         singlestep: bool,
     ) -> std::result::Result<(), DebuggableError> {
         self.cpu_manager
             .lock()
             .unwrap()
-            .set_guest_debug(cpu_id, addrs, singlestep)
+            .set_guest_debug(cpu_id, addrs, watchpoints, singlestep)
+    }
+
+    fn debug_stops(&self) -> Vec<(usize, hypervisor::DebugExit)> {
+        self.cpu_manager.lock().unwrap().debug_stops()
     }
 
     fn debug_pause(&mut self) -> std::result::Result<(), DebuggableError> {
@@ -3402,6 +4422,7 @@ mod tests {
             &BTreeMap::new(),
             None,
             true,
+            false,
         )
         .unwrap();
     }
@@ -2,16 +2,24 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 //
+use std::fs::File;
+use std::io::{Read, Seek};
 use std::net::{IpAddr, Ipv4Addr};
+use std::os::fd::BorrowedFd;
+use std::os::unix::fs::FileTypeExt;
 use std::path::PathBuf;
-use std::{fs, result};
+use std::{fmt, fs, io, result};
 
+use block::{CacheMode, ImageType, IoClass};
+use net_util::macvtap::MacvtapMode;
 use net_util::MacAddr;
+use pci::VfioResetMethod;
 use serde::{Deserialize, Serialize};
 use virtio_devices::RateLimiterConfig;
+use zeroize::Zeroizing;
 
 use crate::landlock::LandlockError;
-use crate::Landlock;
+use crate::{balloon_pressure, memory_manager, numa, Landlock};
 
 pub type LandlockResult<T> = result::Result<T, LandlockError>;
 
@@ -23,7 +31,7 @@ pub(crate) trait ApplyLandlock {
 
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub struct CpuAffinity {
-    pub vcpu: u8,
+    pub vcpu: u32,
     pub host_cpus: Vec<usize>,
 }
 
@@ -53,8 +61,8 @@ pub fn default_cpuconfig_max_phys_bits() -> u8 {
 
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub struct CpusConfig {
-    pub boot_vcpus: u8,
-    pub max_vcpus: u8,
+    pub boot_vcpus: u32,
+    pub max_vcpus: u32,
     #[serde(default)]
     pub topology: Option<CpuTopology>,
     #[serde(default)]
@@ -67,7 +75,7 @@ pub struct CpusConfig {
     pub features: CpuFeatures,
 }
 
-pub const DEFAULT_VCPUS: u8 = 1;
+pub const DEFAULT_VCPUS: u32 = 1;
 
 impl Default for CpusConfig {
     fn default() -> Self {
@@ -93,10 +101,23 @@ pub fn default_platformconfig_iommu_address_width_bits() -> u8 {
     DEFAULT_IOMMU_ADDRESS_WIDTH_BITS
 }
 
+fn default_platformconfig_legacy_devices() -> bool {
+    true
+}
+
+#[cfg(feature = "sev_snp")]
+fn default_platformconfig_sev_snp_smt() -> bool {
+    true
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub struct PlatformConfig {
     #[serde(default = "default_platformconfig_num_pci_segments")]
     pub num_pci_segments: u16,
+    /// Segments reserved for being added at runtime, from
+    /// `num_pci_segments` onwards.
+    #[serde(default)]
+    pub max_pci_segments: Option<u16>,
     #[serde(default)]
     pub iommu_segments: Option<Vec<u16>>,
     #[serde(default = "default_platformconfig_iommu_address_width_bits")]
@@ -107,12 +128,50 @@ pub struct PlatformConfig {
     pub uuid: Option<String>,
     #[serde(default)]
     pub oem_strings: Option<Vec<String>>,
+    #[serde(default)]
+    pub boot_method: BootMethod,
+    #[serde(default)]
+    pub vmbus: bool,
+    #[serde(default = "default_platformconfig_legacy_devices")]
+    pub legacy_devices: bool,
+    /// Derive the placement of the vCPUs, memory zones and device workers
+    /// from the host NUMA topology.
+    #[serde(default)]
+    pub auto_numa: bool,
+    /// Derive the distances between the guest NUMA nodes bound to host NUMA
+    /// nodes from the host SLIT.
+    #[serde(default)]
+    pub host_numa_distances: bool,
     #[cfg(feature = "tdx")]
     #[serde(default)]
     pub tdx: bool,
+    #[cfg(feature = "tdx")]
+    #[serde(default)]
+    pub tdx_l2_vms: u8,
     #[cfg(feature = "sev_snp")]
     #[serde(default)]
     pub sev_snp: bool,
+    #[cfg(feature = "sev_snp")]
+    #[serde(default = "default_platformconfig_sev_snp_smt")]
+    pub sev_snp_smt: bool,
+    #[cfg(feature = "sev_snp")]
+    #[serde(default)]
+    pub sev_snp_debug: bool,
+    #[cfg(feature = "sev_snp")]
+    #[serde(default)]
+    pub sev_snp_migrate_ma: bool,
+}
+
+/// Firmware interfaces describing the platform to the guest on AArch64.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize, Default)]
+pub enum BootMethod {
+    /// Both device tree and ACPI tables, the guest picking one of them.
+    #[default]
+    Both,
+    /// Device tree only, no ACPI tables being created.
+    DeviceTree,
+    /// ACPI tables, with the guest kernel being told to prefer them.
+    Acpi,
 }
 
 pub const DEFAULT_PCI_SEGMENT_APERTURE_WEIGHT: u32 = 1;
@@ -196,6 +255,8 @@ pub struct MemoryConfig {
     pub zones: Option<Vec<MemoryZoneConfig>>,
     #[serde(default = "default_memoryconfig_thp")]
     pub thp: bool,
+    #[serde(default)]
+    pub thp_collapse: bool,
 }
 
 pub const DEFAULT_MEMORY_MB: u64 = 512;
@@ -214,6 +275,7 @@ impl Default for MemoryConfig {
             prefault: false,
             zones: None,
             thp: true,
+            thp_collapse: false,
         }
     }
 }
@@ -225,12 +287,71 @@ pub enum VhostMode {
     Server,
 }
 
+/// Encryption of the content of a disk image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum DiskEncryption {
+    #[serde(rename = "luks")]
+    Luks,
+}
+
+/// Interface a disk is exposed to the guest through.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum DiskInterface {
+    #[default]
+    #[serde(rename = "virtio")]
+    Virtio,
+    /// Emulated NVMe controller, for the guests lacking virtio drivers.
+    #[serde(rename = "nvme")]
+    Nvme,
+}
+
+/// Backend performing the I/O on a raw disk image. Without one, the fastest
+/// backend supported by the host is picked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum IoEngine {
+    #[serde(rename = "io_uring")]
+    IoUring,
+    #[serde(rename = "aio")]
+    Aio,
+    /// Requests run synchronously on the thread servicing the queue.
+    #[serde(rename = "sync")]
+    Sync,
+    /// Requests run on a pool of worker threads, for the filesystems where
+    /// io_uring and AIO regress.
+    #[serde(rename = "threads")]
+    Threads,
+}
+
+impl fmt::Display for IoEngine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let engine = match self {
+            IoEngine::IoUring => "io_uring",
+            IoEngine::Aio => "aio",
+            IoEngine::Sync => "sync",
+            IoEngine::Threads => "threads",
+        };
+        write!(f, "{engine}")
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub struct RateLimiterGroupConfig {
     #[serde(default)]
     pub id: String,
     #[serde(default)]
     pub rate_limiter_config: RateLimiterConfig,
+    #[serde(default)]
+    pub schedules: Option<Vec<RateLimitScheduleConfig>>,
+}
+
+/// Limits applied to a rate-limiter group between `start` and `end`, both
+/// "HH:MM" times of the day in UTC.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RateLimitScheduleConfig {
+    pub start: String,
+    pub end: String,
+    #[serde(default)]
+    pub rate_limiter_config: RateLimiterConfig,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
@@ -239,6 +360,67 @@ pub struct VirtQueueAffinity {
     pub host_cpus: Vec<usize>,
 }
 
+/// Size of the virtqueues of a device, either fixed or "auto" to let the
+/// guest negotiate the largest size it can allocate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QueueSize {
+    Auto,
+    Fixed(u16),
+}
+
+impl QueueSize {
+    /// Size advertised to the guest, `max` being the largest size supported
+    /// by the device.
+    pub fn max_size(self, max: u16) -> u16 {
+        match self {
+            QueueSize::Auto => max,
+            QueueSize::Fixed(size) => size,
+        }
+    }
+}
+
+impl Serialize for QueueSize {
+    fn serialize<S>(&self, s: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            QueueSize::Auto => s.serialize_str("auto"),
+            QueueSize::Fixed(size) => s.serialize_u16(*size),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for QueueSize {
+    fn deserialize<D>(d: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Value {
+            Fixed(u16),
+            Name(String),
+        }
+
+        match Value::deserialize(d)? {
+            Value::Fixed(size) => Ok(QueueSize::Fixed(size)),
+            Value::Name(name) if name == "auto" => Ok(QueueSize::Auto),
+            Value::Name(name) => Err(serde::de::Error::invalid_value(
+                serde::de::Unexpected::Str(&name),
+                &"a queue size or \"auto\"",
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CoalescingConfig {
+    pub usecs: u32,
+    #[serde(default)]
+    pub max_used: u32,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub struct DiskConfig {
     pub path: Option<PathBuf>,
@@ -251,7 +433,7 @@ pub struct DiskConfig {
     #[serde(default = "default_diskconfig_num_queues")]
     pub num_queues: usize,
     #[serde(default = "default_diskconfig_queue_size")]
-    pub queue_size: u16,
+    pub queue_size: QueueSize,
     #[serde(default)]
     pub vhost_user: bool,
     pub vhost_socket: Option<String>,
@@ -259,26 +441,128 @@ pub struct DiskConfig {
     pub rate_limit_group: Option<String>,
     #[serde(default)]
     pub rate_limiter_config: Option<RateLimiterConfig>,
+    /// Share the bandwidth of the rate limiter fairly between the queues
+    /// rather than on a first come, first served basis.
+    #[serde(default)]
+    pub fair_queues: bool,
     #[serde(default)]
     pub id: Option<String>,
-    // For testing use only. Not exposed in API.
     #[serde(default)]
-    pub disable_io_uring: bool,
-    // For testing use only. Not exposed in API.
+    pub io_engine: Option<IoEngine>,
+    #[serde(default)]
+    pub io_engine_threads: Option<usize>,
+    #[serde(default)]
+    pub io_uring_sqpoll: bool,
     #[serde(default)]
-    pub disable_aio: bool,
+    pub io_uring_fixed_buffers: bool,
+    #[serde(default)]
+    pub io_class: Option<IoClass>,
+    #[serde(default)]
+    pub io_prio: Option<u8>,
+    #[serde(default)]
+    pub msix_vectors: Option<u16>,
     #[serde(default)]
     pub pci_segment: u16,
     #[serde(default)]
     pub serial: Option<String>,
     #[serde(default)]
     pub queue_affinity: Option<Vec<VirtQueueAffinity>>,
+    #[serde(default)]
+    pub io_thread: Option<u16>,
+    #[serde(default)]
+    pub coalescing: Option<CoalescingConfig>,
+    #[serde(default)]
+    pub pause_on_path_failure: bool,
+    #[serde(default)]
+    pub format: Option<ImageType>,
+    #[serde(default)]
+    pub cache: Option<CacheMode>,
+    #[serde(default)]
+    pub encryption: Option<DiskEncryption>,
+    #[serde(default)]
+    pub key_file: Option<PathBuf>,
+    #[serde(
+        default,
+        serialize_with = "serialize_diskconfig_key_fd",
+        deserialize_with = "deserialize_diskconfig_key_fd"
+    )]
+    pub key_fd: Option<i32>,
+    #[serde(default)]
+    pub discard: bool,
+    #[serde(default)]
+    pub detect_zeroes: bool,
+    #[serde(default)]
+    pub interface: DiskInterface,
+}
+
+impl DiskConfig {
+    /// Whether the disk image is opened with O_DIRECT, bypassing the host
+    /// page cache.
+    pub fn direct_io(&self) -> bool {
+        self.direct || self.cache == Some(CacheMode::Direct)
+    }
+
+    /// Read the key unlocking an encrypted disk image, from the file
+    /// descriptor it was inherited as or from its file otherwise. The whole
+    /// content of the file is the key.
+    pub fn read_key(&self) -> io::Result<Zeroizing<Vec<u8>>> {
+        let mut key = Zeroizing::new(Vec::new());
+        if let Some(fd) = self.key_fd {
+            // SAFETY: the key FD is inherited from the parent process or
+            // received along with the API request, which is responsible for
+            // its validity. The VMM only closes it when dropping the VM
+            // configuration it has been added to the preserved FDs of, which
+            // happens once the disk has been created, after this first read.
+            let fd = unsafe { BorrowedFd::borrow_raw(fd) };
+            let mut file = File::from(fd.try_clone_to_owned()?);
+            file.rewind()?;
+            file.read_to_end(&mut key)?;
+        } else if let Some(key_file) = &self.key_file {
+            File::open(key_file)?.read_to_end(&mut key)?;
+        }
+
+        Ok(key)
+    }
+}
+
+fn serialize_diskconfig_key_fd<S>(x: &Option<i32>, s: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    if x.is_some() {
+        warn!("'DiskConfig' contains FDs that can't be serialized correctly. Serializing them as invalid FDs.");
+        s.serialize_some(&-1)
+    } else {
+        s.serialize_none()
+    }
+}
+
+fn deserialize_diskconfig_key_fd<'de, D>(d: D) -> Result<Option<i32>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let invalid_fd: Option<i32> = Option::deserialize(d)?;
+    if invalid_fd.is_some() {
+        warn!("'DiskConfig' contains FDs that can't be deserialized correctly. Deserializing them as invalid FDs.");
+        Ok(Some(-1))
+    } else {
+        Ok(None)
+    }
 }
 
 impl ApplyLandlock for DiskConfig {
     fn apply_landlock(&self, landlock: &mut Landlock) -> LandlockResult<()> {
         if let Some(path) = &self.path {
             landlock.add_rule_with_access(path.to_path_buf(), "rw")?;
+
+            // The paths of the block devices are monitored through sysfs.
+            if fs::metadata(path).is_ok_and(|m| m.file_type().is_block_device()) {
+                landlock.add_rule_with_access("/sys/dev/block".into(), "r")?;
+                landlock.add_rule_with_access("/sys/devices".into(), "r")?;
+            }
+        }
+        if let Some(key_file) = &self.key_file {
+            landlock.add_rule_with_access(key_file.to_path_buf(), "r")?;
         }
         Ok(())
     }
@@ -292,8 +576,8 @@ pub fn default_diskconfig_num_queues() -> usize {
 
 pub const DEFAULT_DISK_QUEUE_SIZE: u16 = 128;
 
-pub fn default_diskconfig_queue_size() -> u16 {
-    DEFAULT_DISK_QUEUE_SIZE
+pub fn default_diskconfig_queue_size() -> QueueSize {
+    QueueSize::Fixed(DEFAULT_DISK_QUEUE_SIZE)
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
@@ -339,6 +623,64 @@ pub struct NetConfig {
     pub offload_ufo: bool,
     #[serde(default = "default_netconfig_true")]
     pub offload_csum: bool,
+    #[serde(default)]
+    pub io_uring: bool,
+    #[serde(default)]
+    pub io_thread: Option<u16>,
+    #[serde(default)]
+    pub coalescing: Option<CoalescingConfig>,
+    #[serde(default)]
+    pub rate_limit_group: Option<String>,
+    /// Share of the bandwidth of the rate-limit group given to the device
+    /// when its devices compete for it, relative to their weights.
+    #[serde(default = "default_netconfig_weight")]
+    pub weight: u16,
+    #[serde(default)]
+    pub tap_persist: Option<bool>,
+    #[serde(default)]
+    pub tap_uid: Option<u32>,
+    #[serde(default)]
+    pub tap_gid: Option<u32>,
+    /// Macvtap interface backing the device instead of a TAP interface,
+    /// created on `macvtap_link` if it doesn't exist.
+    #[serde(default)]
+    pub macvtap: Option<String>,
+    #[serde(default)]
+    pub macvtap_link: Option<String>,
+    #[serde(default)]
+    pub macvtap_mode: MacvtapMode,
+    /// Offload the datapath to the vhost-net kernel module, falling back to
+    /// the userspace one when it can't be used.
+    #[serde(default)]
+    pub vhost: bool,
+    /// Option ROM exposed through the expansion ROM BAR of the device.
+    #[serde(default)]
+    pub romfile: Option<PathBuf>,
+    #[serde(default)]
+    pub msix_vectors: Option<u16>,
+}
+
+impl ApplyLandlock for NetConfig {
+    fn apply_landlock(&self, landlock: &mut Landlock) -> LandlockResult<()> {
+        if let Some(romfile) = &self.romfile {
+            landlock.add_rule_with_access(romfile.to_path_buf(), "r")?;
+        }
+
+        // The character device of the macvtap interface is named after its
+        // index, only known once the interface is created.
+        if self.macvtap.is_some() {
+            landlock.add_rule_with_access("/dev".into(), "rw")?;
+        }
+
+        if self.vhost {
+            landlock.add_rule_with_access("/dev/vhost-net".into(), "rw")?;
+        }
+        Ok(())
+    }
+}
+
+pub fn default_deviceconfig_p2p_dma() -> bool {
+    true
 }
 
 pub fn default_netconfig_true() -> bool {
@@ -373,6 +715,12 @@ pub fn default_netconfig_queue_size() -> u16 {
     DEFAULT_NET_QUEUE_SIZE
 }
 
+pub const DEFAULT_NET_WEIGHT: u16 = 100;
+
+pub fn default_netconfig_weight() -> u16 {
+    DEFAULT_NET_WEIGHT
+}
+
 fn serialize_netconfig_fds<S>(x: &Option<Vec<i32>>, s: S) -> Result<S::Ok, S::Error>
 where
     S: serde::Serializer,
@@ -425,6 +773,305 @@ impl ApplyLandlock for RngConfig {
     }
 }
 
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ScmiConfig {
+    /// Host files (typically hwmon attributes) exposed as SCMI sensors.
+    #[serde(default)]
+    pub sensors: Option<Vec<PathBuf>>,
+    /// Number of power domains exposed to the guest.
+    #[serde(default)]
+    pub power_domains: u32,
+    /// Rates in Hz of the fixed rate clocks exposed to the guest.
+    #[serde(default)]
+    pub clocks: Option<Vec<u64>>,
+    #[serde(default)]
+    pub iommu: bool,
+}
+
+impl ApplyLandlock for ScmiConfig {
+    fn apply_landlock(&self, landlock: &mut Landlock) -> LandlockResult<()> {
+        // Sensors only need read access
+        if let Some(sensors) = &self.sensors {
+            for sensor in sensors.iter() {
+                landlock.add_rule_with_access(sensor.to_path_buf(), "r")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CloudInitConfig {
+    /// Host file exposed as the NoCloud `user-data`.
+    #[serde(default)]
+    pub user_data: Option<PathBuf>,
+    /// Host file exposed as the NoCloud `meta-data`. A default one carrying
+    /// an `instance-id` is generated when omitted.
+    #[serde(default)]
+    pub meta_data: Option<PathBuf>,
+    /// Host file exposed as the NoCloud `network-config`.
+    #[serde(default)]
+    pub network_config: Option<PathBuf>,
+}
+
+impl ApplyLandlock for CloudInitConfig {
+    fn apply_landlock(&self, landlock: &mut Landlock) -> LandlockResult<()> {
+        // Seed files only need read access
+        for path in [&self.user_data, &self.meta_data, &self.network_config]
+            .into_iter()
+            .flatten()
+        {
+            landlock.add_rule_with_access(path.to_path_buf(), "r")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ImdsConfig {
+    /// JSON document served by the metadata service.
+    pub document: PathBuf,
+}
+
+impl ApplyLandlock for ImdsConfig {
+    fn apply_landlock(&self, landlock: &mut Landlock) -> LandlockResult<()> {
+        // The document only needs read access
+        landlock.add_rule_with_access(self.document.to_path_buf(), "r")?;
+        Ok(())
+    }
+}
+
+/// Maximum size of a network interface name, including its terminating
+/// null byte.
+pub const IFNAMSIZ: usize = 16;
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct NetnsConfig {
+    /// Network namespace the TAP interfaces are created in.
+    pub name: String,
+    /// Bridge the namespace is linked to when it gets created.
+    #[serde(default)]
+    pub bridge: Option<String>,
+    /// End of the veth pair linking the namespace to the bridge.
+    #[serde(default)]
+    pub veth: Option<String>,
+}
+
+impl NetnsConfig {
+    /// Name of the veth interface attached to the bridge, derived from the
+    /// name of the namespace unless given.
+    pub fn veth_name(&self) -> String {
+        self.veth.clone().unwrap_or_else(|| {
+            format!("veth-{}", self.name)
+                .chars()
+                .take(IFNAMSIZ - 1)
+                .collect()
+        })
+    }
+}
+
+impl ApplyLandlock for NetnsConfig {
+    fn apply_landlock(&self, landlock: &mut Landlock) -> LandlockResult<()> {
+        landlock.add_rule_with_access(net_util::netns::NETNS_RUN_DIR.into(), "rw")?;
+        Ok(())
+    }
+}
+
+pub const DEFAULT_GUEST_AGENT_PORT: u32 = 1234;
+
+pub fn default_guestagentconfig_port() -> u32 {
+    DEFAULT_GUEST_AGENT_PORT
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct GuestAgentConfig {
+    /// vsock port the QEMU guest agent listens on.
+    #[serde(default = "default_guestagentconfig_port")]
+    pub port: u32,
+}
+
+impl Default for GuestAgentConfig {
+    fn default() -> Self {
+        GuestAgentConfig {
+            port: default_guestagentconfig_port(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ResourcesConfig {
+    /// cgroup the VMM process joins, created if needed.
+    #[serde(default)]
+    pub cgroup: Option<PathBuf>,
+    /// File the host resources used by the VM are reported to.
+    #[serde(default)]
+    pub manifest: Option<PathBuf>,
+    /// CPU time the cgroup may use, in percent of a CPU.
+    #[serde(default)]
+    pub cpu_quota: Option<u32>,
+    /// Memory the cgroup may use, in bytes.
+    #[serde(default)]
+    pub memory_max: Option<u64>,
+    /// I/O limits of the cgroup, as written to io.max.
+    #[serde(default)]
+    pub io_max: Option<Vec<String>>,
+    /// Whether the vCPU, I/O and API threads are placed into children of
+    /// the cgroup.
+    #[serde(default)]
+    pub threads: bool,
+}
+
+impl ApplyLandlock for ResourcesConfig {
+    fn apply_landlock(&self, landlock: &mut Landlock) -> LandlockResult<()> {
+        if let Some(cgroup) = &self.cgroup {
+            landlock.add_rule_with_access(cgroup.to_path_buf(), "rw")?;
+        }
+        if let Some(manifest) = &self.manifest {
+            landlock.add_rule_with_access(manifest.to_path_buf(), "rw")?;
+        }
+        // The threads are listed to be placed into the children of the cgroup.
+        if self.cgroup.is_some() && self.threads {
+            landlock.add_rule_with_access(PathBuf::from("/proc/self/task"), "r")?;
+        }
+        Ok(())
+    }
+}
+
+/// Time the guest RTC starts at.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum RtcBase {
+    #[default]
+    Utc,
+    Localtime,
+    /// Fixed date, in seconds since the epoch.
+    Date(i64),
+}
+
+/// Correction of the guest RTC for the time the VM spent paused, e.g. while
+/// being migrated.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum RtcDrift {
+    /// The RTC jumps to account for the time spent paused.
+    #[default]
+    Step,
+    /// The RTC catches the time spent paused up gradually.
+    Slew,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RtcConfig {
+    #[serde(default)]
+    pub base: RtcBase,
+    #[serde(default)]
+    pub drift: RtcDrift,
+}
+
+impl ApplyLandlock for RtcConfig {
+    fn apply_landlock(&self, landlock: &mut Landlock) -> LandlockResult<()> {
+        // The local time zone is read when creating the RTC.
+        let localtime = PathBuf::from("/etc/localtime");
+        if self.base == RtcBase::Localtime && localtime.exists() {
+            landlock.add_rule_with_access(localtime, "r")?;
+        }
+        Ok(())
+    }
+}
+
+/// What the VMM does when the guest crashes, i.e. triple-faults, panics
+/// through pvpanic, or lets the virtio watchdog expire.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum RestartPolicy {
+    /// The VMM exits, as if the guest had shut down.
+    #[default]
+    Never,
+    /// The VMM restarts the guest in place.
+    OnCrash,
+}
+
+pub const DEFAULT_RESTART_MAX: u32 = 5;
+pub fn default_restartpolicyconfig_max() -> u32 {
+    DEFAULT_RESTART_MAX
+}
+
+pub const DEFAULT_RESTART_BACKOFF_MS: u64 = 1000;
+pub fn default_restartpolicyconfig_backoff() -> u64 {
+    DEFAULT_RESTART_BACKOFF_MS
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RestartPolicyConfig {
+    #[serde(default)]
+    pub policy: RestartPolicy,
+    /// Maximum number of consecutive restarts.
+    #[serde(default = "default_restartpolicyconfig_max")]
+    pub max: u32,
+    /// Delay before the first restart, in milliseconds, doubled on each
+    /// consecutive restart.
+    #[serde(default = "default_restartpolicyconfig_backoff")]
+    pub backoff: u64,
+}
+
+impl Default for RestartPolicyConfig {
+    fn default() -> Self {
+        RestartPolicyConfig {
+            policy: RestartPolicy::default(),
+            max: DEFAULT_RESTART_MAX,
+            backoff: DEFAULT_RESTART_BACKOFF_MS,
+        }
+    }
+}
+
+/// Dump of the guest memory taken when the guest panics through pvpanic,
+/// before the restart policy is applied.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CrashDumpConfig {
+    /// Directory the dumps are written to, one file per crash.
+    pub path: PathBuf,
+}
+
+impl ApplyLandlock for CrashDumpConfig {
+    fn apply_landlock(&self, landlock: &mut Landlock) -> LandlockResult<()> {
+        landlock.add_rule_with_access(self.path.to_path_buf(), "rw")?;
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct IoThreadAffinity {
+    pub io_thread: u16,
+    pub host_cpus: Vec<usize>,
+}
+
+pub const DEFAULT_IO_THREADS: u16 = 1;
+
+pub fn default_iothreadsconfig_count() -> u16 {
+    DEFAULT_IO_THREADS
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct IoThreadsConfig {
+    /// Number of threads shared by the virtio-block and virtio-net devices.
+    #[serde(default = "default_iothreadsconfig_count")]
+    pub count: u16,
+    #[serde(default)]
+    pub affinity: Option<Vec<IoThreadAffinity>>,
+}
+
+pub const DEFAULT_BALLOON_STEP: u64 = 128 << 20;
+pub fn default_balloonconfig_step() -> u64 {
+    DEFAULT_BALLOON_STEP
+}
+
+pub const DEFAULT_BALLOON_PRESSURE_HIGH: u8 = 10;
+pub fn default_balloonconfig_pressure_high() -> u8 {
+    DEFAULT_BALLOON_PRESSURE_HIGH
+}
+
+pub const DEFAULT_BALLOON_PRESSURE_LOW: u8 = 1;
+pub fn default_balloonconfig_pressure_low() -> u8 {
+    DEFAULT_BALLOON_PRESSURE_LOW
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub struct BalloonConfig {
     pub size: u64,
@@ -434,11 +1081,43 @@ pub struct BalloonConfig {
     /// Option to enable free page reporting from the guest.
     #[serde(default)]
     pub free_page_reporting: bool,
+    /// Option to resize the balloon following the memory pressure of the
+    /// host, between `min_size` and `max_size`.
+    #[serde(default)]
+    pub auto_resize: bool,
+    #[serde(default)]
+    pub min_size: u64,
+    #[serde(default)]
+    pub max_size: Option<u64>,
+    /// Bytes the balloon is inflated or deflated by on each adjustment.
+    #[serde(default = "default_balloonconfig_step")]
+    pub step: u64,
+    /// Host memory pressure, as the percentage of the last 10 seconds some
+    /// tasks stalled on memory, above which the balloon is inflated.
+    #[serde(default = "default_balloonconfig_pressure_high")]
+    pub pressure_high: u8,
+    /// Host memory pressure below which the balloon is deflated.
+    #[serde(default = "default_balloonconfig_pressure_low")]
+    pub pressure_low: u8,
+}
+
+impl ApplyLandlock for BalloonConfig {
+    fn apply_landlock(&self, landlock: &mut Landlock) -> LandlockResult<()> {
+        if self.auto_resize {
+            landlock.add_rule_with_access(balloon_pressure::HOST_MEMORY_PRESSURE.into(), "r")?;
+        }
+        Ok(())
+    }
 }
 
 #[cfg(feature = "pvmemcontrol")]
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, Default)]
-pub struct PvmemcontrolConfig {}
+pub struct PvmemcontrolConfig {
+    /// Bytes per second the guest may reclaim through cold and pageout
+    /// requests, unlimited if not set.
+    #[serde(default)]
+    pub reclaim_rate: Option<u64>,
+}
 
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub struct FsConfig {
@@ -452,6 +1131,8 @@ pub struct FsConfig {
     pub id: Option<String>,
     #[serde(default)]
     pub pci_segment: u16,
+    #[serde(default)]
+    pub msix_vectors: Option<u16>,
 }
 
 pub fn default_fsconfig_num_queues() -> usize {
@@ -479,6 +1160,8 @@ pub struct PmemConfig {
     #[serde(default)]
     pub discard_writes: bool,
     #[serde(default)]
+    pub nvdimm: bool,
+    #[serde(default)]
     pub id: Option<String>,
     #[serde(default)]
     pub pci_segment: u16,
@@ -502,6 +1185,17 @@ pub enum ConsoleOutputMode {
     Null,
 }
 
+/// Additional output the console output is duplicated to.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ConsoleSink {
+    File(PathBuf),
+    /// Unix socket streaming the output to any number of clients.
+    Socket(PathBuf),
+    /// In memory ring buffer of the given size, keeping the latest output
+    /// for the API to read.
+    Ring(u64),
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub struct ConsoleConfig {
     #[serde(default = "default_consoleconfig_file")]
@@ -510,6 +1204,8 @@ pub struct ConsoleConfig {
     #[serde(default)]
     pub iommu: bool,
     pub socket: Option<PathBuf>,
+    #[serde(default)]
+    pub tee: Vec<ConsoleSink>,
 }
 
 pub fn default_consoleconfig_file() -> Option<PathBuf> {
@@ -524,6 +1220,14 @@ impl ApplyLandlock for ConsoleConfig {
         if let Some(socket) = &self.socket {
             landlock.add_rule_with_access(socket.to_path_buf(), "rw")?;
         }
+        for sink in &self.tee {
+            match sink {
+                ConsoleSink::File(path) | ConsoleSink::Socket(path) => {
+                    landlock.add_rule_with_access(path.to_path_buf(), "rw")?;
+                }
+                ConsoleSink::Ring(_) => {}
+            }
+        }
         Ok(())
     }
 }
@@ -558,8 +1262,26 @@ impl ApplyLandlock for DebugConsoleConfig {
     }
 }
 
+/// SR-IOV virtual function created for a device and given back to the host
+/// once the device is removed.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SriovVfConfig {
+    /// Network interface of the physical function.
+    pub pf: String,
+    /// Index of the virtual function on the physical function.
+    pub vf: u32,
+    #[serde(default)]
+    pub mac: Option<MacAddr>,
+    #[serde(default)]
+    pub vlan: Option<u16>,
+    #[serde(default)]
+    pub trust: bool,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub struct DeviceConfig {
+    /// Filled in with the node of the virtual function when `sriov` is set.
+    #[serde(default)]
     pub path: PathBuf,
     #[serde(default)]
     pub iommu: bool,
@@ -569,10 +1291,71 @@ pub struct DeviceConfig {
     pub pci_segment: u16,
     #[serde(default)]
     pub x_nv_gpudirect_clique: Option<u8>,
+    /// Map the device BARs into the IOMMU domain shared with the other
+    /// assigned devices, allowing peer-to-peer DMA between them.
+    #[serde(default = "default_deviceconfig_p2p_dma")]
+    pub p2p_dma: bool,
+    /// Reset applied to the device when it's attached to the VM.
+    #[serde(default)]
+    pub reset_method: VfioResetMethod,
+    /// Reset the device as well when it's detached from the VM.
+    #[serde(default)]
+    pub reset_on_detach: bool,
+    /// Forward the legacy VGA I/O port ranges to the device.
+    #[serde(default)]
+    pub legacy_vga: bool,
+    /// Option ROM replacing the one of the device.
+    #[serde(default)]
+    pub romfile: Option<PathBuf>,
+    /// Virtual function to create and assign instead of an existing device.
+    #[serde(default)]
+    pub sriov: Option<SriovVfConfig>,
+}
+
+impl DeviceConfig {
+    /// Mediated devices are exposed through a sysfs node carrying an
+    /// `mdev_type` link, e.g. `/sys/bus/mdev/devices/<uuid>`.
+    pub fn is_mdev(&self) -> bool {
+        self.path.join("mdev_type").exists()
+    }
+
+    // Mediated devices always expose their group through an `iommu_group`
+    // link.
+    fn mdev_group_path(&self) -> LandlockResult<PathBuf> {
+        let group_path =
+            fs::read_link(self.path.join("iommu_group")).map_err(LandlockError::OpenPath)?;
+        let group = group_path
+            .file_name()
+            .ok_or(LandlockError::InvalidPath)?
+            .to_str()
+            .ok_or(LandlockError::InvalidPath)?;
+
+        Ok(PathBuf::from(format!("/dev/vfio/{group}")))
+    }
 }
 
 impl ApplyLandlock for DeviceConfig {
     fn apply_landlock(&self, landlock: &mut Landlock) -> LandlockResult<()> {
+        if let Some(romfile) = &self.romfile {
+            landlock.add_rule_with_access(romfile.to_path_buf(), "r")?;
+        }
+
+        // The virtual function, hence its IOMMU group, is only known once
+        // created through sysfs.
+        if self.sriov.is_some() {
+            landlock.add_rule_with_access("/sys/class/net".into(), "r")?;
+            landlock.add_rule_with_access("/sys/bus/pci".into(), "rw")?;
+            landlock.add_rule_with_access("/sys/devices".into(), "rw")?;
+            landlock.add_rule_with_access("/dev/vfio".into(), "rw")?;
+            landlock.add_rule_with_access("/proc/self/fd".into(), "r")?;
+            return Ok(());
+        }
+
+        if self.is_mdev() {
+            landlock.add_rule_with_access(self.mdev_group_path()?, "rw")?;
+            return Ok(());
+        }
+
         let device_path = fs::read_link(self.path.as_path()).map_err(LandlockError::OpenPath)?;
         let iommu_group = device_path.file_name();
         let iommu_group_str = iommu_group
@@ -583,6 +1366,16 @@ impl ApplyLandlock for DeviceConfig {
         let vfio_group_path = "/dev/vfio/".to_owned() + iommu_group_str;
         landlock.add_rule_with_access(vfio_group_path.into(), "rw")?;
 
+        // The reset method is selected through sysfs before each reset.
+        let reset_method_path = self.path.join("reset_method");
+        if matches!(
+            self.reset_method,
+            VfioResetMethod::Flr | VfioResetMethod::Bus
+        ) && reset_method_path.exists()
+        {
+            landlock.add_rule_with_access(reset_method_path, "rw")?;
+        }
+
         Ok(())
     }
 }
@@ -614,6 +1407,8 @@ pub struct VdpaConfig {
     pub id: Option<String>,
     #[serde(default)]
     pub pci_segment: u16,
+    #[serde(default)]
+    pub msix_vectors: Option<u16>,
 }
 
 pub fn default_vdpaconfig_num_queues() -> usize {
@@ -669,7 +1464,7 @@ pub struct NumaConfig {
     #[serde(default)]
     pub guest_numa_id: u32,
     #[serde(default)]
-    pub cpus: Option<Vec<u8>>,
+    pub cpus: Option<Vec<u32>>,
     #[serde(default)]
     pub distances: Option<Vec<NumaDistance>>,
     #[serde(default)]
@@ -685,18 +1480,156 @@ pub struct NumaConfig {
 pub struct PayloadConfig {
     #[serde(default)]
     pub firmware: Option<PathBuf>,
+    #[serde(
+        default,
+        serialize_with = "serialize_payloadconfig_fd",
+        deserialize_with = "deserialize_payloadconfig_fd"
+    )]
+    pub firmware_fd: Option<i32>,
     #[serde(default)]
     pub kernel: Option<PathBuf>,
+    #[serde(
+        default,
+        serialize_with = "serialize_payloadconfig_fd",
+        deserialize_with = "deserialize_payloadconfig_fd"
+    )]
+    pub kernel_fd: Option<i32>,
     #[serde(default)]
     pub cmdline: Option<String>,
     #[serde(default)]
     pub initramfs: Option<PathBuf>,
+    #[serde(
+        default,
+        serialize_with = "serialize_payloadconfig_fd",
+        deserialize_with = "deserialize_payloadconfig_fd"
+    )]
+    pub initramfs_fd: Option<i32>,
+    #[serde(default)]
+    pub extra_initramfs: Option<Vec<PathBuf>>,
     #[cfg(feature = "igvm")]
     #[serde(default)]
     pub igvm: Option<PathBuf>,
+    #[cfg(feature = "igvm")]
+    #[serde(
+        default,
+        serialize_with = "serialize_payloadconfig_fd",
+        deserialize_with = "deserialize_payloadconfig_fd"
+    )]
+    pub igvm_fd: Option<i32>,
     #[cfg(feature = "sev_snp")]
     #[serde(default)]
     pub host_data: Option<String>,
+    #[cfg(feature = "sev_snp")]
+    #[serde(default)]
+    pub id_block: Option<PathBuf>,
+    #[cfg(feature = "sev_snp")]
+    #[serde(default)]
+    pub id_auth: Option<PathBuf>,
+    #[cfg(feature = "sev_snp")]
+    #[serde(default)]
+    pub snp_certs: Option<PathBuf>,
+    #[serde(default)]
+    pub fallback_firmware: Option<FallbackFirmwareConfig>,
+}
+
+pub const DEFAULT_FALLBACK_FIRMWARE_TIMEOUT: u64 = 60;
+
+pub fn default_fallback_firmware_timeout() -> u64 {
+    DEFAULT_FALLBACK_FIRMWARE_TIMEOUT
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct FallbackFirmwareConfig {
+    /// Firmware booted when the primary one doesn't hand over in time.
+    pub path: PathBuf,
+    /// Delay, in seconds, given to the primary firmware to hand over.
+    #[serde(default = "default_fallback_firmware_timeout")]
+    pub timeout: u64,
+}
+
+fn serialize_payloadconfig_fd<S>(x: &Option<i32>, s: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    if x.is_some() {
+        warn!("'PayloadConfig' contains FDs that can't be serialized correctly. Serializing them as invalid FDs.");
+        s.serialize_some(&-1)
+    } else {
+        s.serialize_none()
+    }
+}
+
+fn deserialize_payloadconfig_fd<'de, D>(d: D) -> Result<Option<i32>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let invalid_fd: Option<i32> = Option::deserialize(d)?;
+    if invalid_fd.is_some() {
+        warn!("'PayloadConfig' contains FDs that can't be deserialized correctly. Deserializing them as invalid FDs.");
+        Ok(Some(-1))
+    } else {
+        Ok(None)
+    }
+}
+
+// Open a payload component from the file descriptor it was inherited as,
+// or from its path otherwise.
+fn open_payload_file(path: &Option<PathBuf>, fd: Option<i32>) -> io::Result<Option<File>> {
+    if let Some(fd) = fd {
+        // SAFETY: payload FDs are part of the preserved FDs of the VM
+        // configuration, keeping them open until the configuration is
+        // dropped.
+        let fd = unsafe { BorrowedFd::borrow_raw(fd) };
+        return Ok(Some(File::from(fd.try_clone_to_owned()?)));
+    }
+
+    path.as_ref().map(File::open).transpose()
+}
+
+impl PayloadConfig {
+    pub fn open_firmware(&self) -> io::Result<Option<File>> {
+        open_payload_file(&self.firmware, self.firmware_fd)
+    }
+
+    pub fn open_kernel(&self) -> io::Result<Option<File>> {
+        open_payload_file(&self.kernel, self.kernel_fd)
+    }
+
+    /// Open all initramfs images, in the order they must be concatenated.
+    pub fn open_initramfs(&self) -> io::Result<Vec<File>> {
+        let mut images: Vec<File> = open_payload_file(&self.initramfs, self.initramfs_fd)?
+            .into_iter()
+            .collect();
+        for path in self.extra_initramfs.iter().flatten() {
+            images.push(File::open(path)?);
+        }
+
+        Ok(images)
+    }
+
+    #[cfg(feature = "igvm")]
+    pub fn open_igvm(&self) -> io::Result<Option<File>> {
+        open_payload_file(&self.igvm, self.igvm_fd)
+    }
+
+    pub fn has_initramfs(&self) -> bool {
+        self.initramfs.is_some() || self.initramfs_fd.is_some()
+    }
+
+    /// File descriptors of the payload components, in the order they are
+    /// expected to be sent along with an API request.
+    pub fn fds_mut(&mut self) -> Vec<&mut i32> {
+        let fds = [
+            &mut self.firmware_fd,
+            &mut self.kernel_fd,
+            &mut self.initramfs_fd,
+        ]
+        .into_iter();
+        #[cfg(feature = "igvm")]
+        let fds = fds.chain(std::iter::once(&mut self.igvm_fd));
+
+        fds.flatten().collect()
+    }
 }
 
 impl ApplyLandlock for PayloadConfig {
@@ -714,11 +1647,34 @@ impl ApplyLandlock for PayloadConfig {
             landlock.add_rule_with_access(initramfs.to_path_buf(), "r")?;
         }
 
+        for initramfs in self.extra_initramfs.iter().flatten() {
+            landlock.add_rule_with_access(initramfs.to_path_buf(), "r")?;
+        }
+
         #[cfg(feature = "igvm")]
         if let Some(igvm) = &self.igvm {
             landlock.add_rule_with_access(igvm.to_path_buf(), "r")?;
         }
 
+        #[cfg(feature = "sev_snp")]
+        if let Some(id_block) = &self.id_block {
+            landlock.add_rule_with_access(id_block.to_path_buf(), "r")?;
+        }
+
+        #[cfg(feature = "sev_snp")]
+        if let Some(id_auth) = &self.id_auth {
+            landlock.add_rule_with_access(id_auth.to_path_buf(), "r")?;
+        }
+
+        #[cfg(feature = "sev_snp")]
+        if let Some(snp_certs) = &self.snp_certs {
+            landlock.add_rule_with_access(snp_certs.to_path_buf(), "r")?;
+        }
+
+        if let Some(fallback_firmware) = &self.fallback_firmware {
+            landlock.add_rule_with_access(fallback_firmware.path.to_path_buf(), "r")?;
+        }
+
         Ok(())
     }
 }
@@ -729,6 +1685,7 @@ pub fn default_serial() -> ConsoleConfig {
         mode: ConsoleOutputMode::Null,
         iommu: false,
         socket: None,
+        tee: Vec::new(),
     }
 }
 
@@ -738,6 +1695,7 @@ pub fn default_console() -> ConsoleConfig {
         mode: ConsoleOutputMode::Tty,
         iommu: false,
         socket: None,
+        tee: Vec::new(),
     }
 }
 
@@ -810,6 +1768,16 @@ pub struct VmConfig {
     pub pci_segments: Option<Vec<PciSegmentConfig>>,
     pub platform: Option<PlatformConfig>,
     pub tpm: Option<TpmConfig>,
+    pub scmi: Option<ScmiConfig>,
+    pub cloud_init: Option<CloudInitConfig>,
+    pub imds: Option<ImdsConfig>,
+    pub netns: Option<NetnsConfig>,
+    pub guest_agent: Option<GuestAgentConfig>,
+    pub resources: Option<ResourcesConfig>,
+    pub rtc: Option<RtcConfig>,
+    pub restart_policy: Option<RestartPolicyConfig>,
+    pub crash_dump: Option<CrashDumpConfig>,
+    pub io_threads: Option<IoThreadsConfig>,
     // Preserved FDs are the ones that share the same life-time as its holding
     // VmConfig instance, such as FDs for creating TAP devices.
     // Preserved FDs will stay open as long as the holding VmConfig instance is
@@ -838,6 +1806,12 @@ impl VmConfig {
             }
         }
 
+        if let Some(net_configs) = &self.net {
+            for net_config in net_configs.iter() {
+                net_config.apply_landlock(&mut landlock)?;
+            }
+        }
+
         self.rng.apply_landlock(&mut landlock)?;
 
         if let Some(fs_configs) = &self.fs {
@@ -892,10 +1866,49 @@ impl VmConfig {
             tpm_config.apply_landlock(&mut landlock)?;
         }
 
+        if let Some(scmi_config) = &self.scmi {
+            scmi_config.apply_landlock(&mut landlock)?;
+        }
+
+        if let Some(cloud_init_config) = &self.cloud_init {
+            cloud_init_config.apply_landlock(&mut landlock)?;
+        }
+
+        if let Some(imds_config) = &self.imds {
+            imds_config.apply_landlock(&mut landlock)?;
+        }
+
+        if let Some(netns_config) = &self.netns {
+            netns_config.apply_landlock(&mut landlock)?;
+        }
+
+        if let Some(resources_config) = &self.resources {
+            resources_config.apply_landlock(&mut landlock)?;
+        }
+
+        if let Some(rtc_config) = &self.rtc {
+            rtc_config.apply_landlock(&mut landlock)?;
+        }
+
+        if let Some(crash_dump_config) = &self.crash_dump {
+            crash_dump_config.apply_landlock(&mut landlock)?;
+        }
+
+        if let Some(balloon_config) = &self.balloon {
+            balloon_config.apply_landlock(&mut landlock)?;
+        }
+
         if self.net.is_some() {
             landlock.add_rule_with_access("/dev/net/tun".into(), "rw")?;
         }
 
+        if self.is_auto_numa_enabled() || self.is_host_numa_distances_enabled() {
+            landlock.add_rule_with_access(numa::SYSFS_NODE_PATH.into(), "r")?;
+        }
+
+        // Needed to report how much guest memory is backed by huge pages.
+        landlock.add_rule_with_access(memory_manager::SMAPS_PATH.into(), "r")?;
+
         if let Some(landlock_rules) = &self.landlock_rules {
             for landlock_rule in landlock_rules.iter() {
                 landlock_rule.apply_landlock(&mut landlock)?;
@@ -0,0 +1,302 @@
+// Copyright © 2026 Cloud Hypervisor Authors
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Minimal XML reader, enough for the VM definitions imported from other
+//! virtualization stacks.
+//!
+//! Elements and attributes are looked up by their local name, regardless of
+//! their namespace prefix.
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+#[error("Invalid XML document at offset {0}")]
+pub struct Error(pub usize);
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+// Nesting depth of the elements, way beyond what VM definitions need, so
+// that a malicious document can't exhaust the stack.
+const MAX_DEPTH: usize = 64;
+
+fn local_name(name: &str) -> &str {
+    name.rsplit_once(':').map_or(name, |(_, local)| local)
+}
+
+/// Element of an XML document, along with its content.
+#[derive(Debug, Default)]
+pub struct Element {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Element>,
+    pub text: String,
+}
+
+impl Element {
+    pub fn local_name(&self) -> &str {
+        local_name(&self.name)
+    }
+
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(n, _)| local_name(n) == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|c| local_name(&c.name) == name)
+    }
+
+    pub fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.children
+            .iter()
+            .filter(move |c| local_name(&c.name) == name)
+    }
+
+    pub fn child_text(&self, name: &str) -> Option<&str> {
+        self.child(name)
+            .map(|c| c.text.trim())
+            .filter(|t| !t.is_empty())
+    }
+}
+
+// Namespaces and DTDs are not interpreted, and the text of mixed content is
+// concatenated.
+struct XmlReader<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> XmlReader<'a> {
+    fn error(&self) -> Error {
+        Error(self.pos)
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn skip_past(&mut self, pattern: &str) -> Result<()> {
+        let len = self.rest().find(pattern).ok_or_else(|| self.error())?;
+        self.pos += len + pattern.len();
+        Ok(())
+    }
+
+    fn expect(&mut self, pattern: &str) -> Result<()> {
+        if !self.rest().starts_with(pattern) {
+            return Err(self.error());
+        }
+        self.pos += pattern.len();
+        Ok(())
+    }
+
+    // Skip the XML declaration, processing instructions, comments and
+    // document type declaration.
+    fn skip_misc(&mut self) -> Result<()> {
+        loop {
+            self.skip_whitespace();
+            let rest = self.rest();
+            if rest.starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if rest.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if rest.starts_with("<!") {
+                self.skip_doctype()?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    // Skip a document type declaration, including its internal subset whose
+    // markup declarations, comments and literals may contain '>'.
+    fn skip_doctype(&mut self) -> Result<()> {
+        self.expect("<!")?;
+        let mut in_subset = false;
+        loop {
+            let rest = self.rest();
+            if rest.starts_with("<!--") {
+                self.skip_past("-->")?;
+                continue;
+            }
+            let c = rest.chars().next().ok_or_else(|| self.error())?;
+            self.pos += c.len_utf8();
+            match c {
+                '"' | '\'' => {
+                    let len = self.rest().find(c).ok_or_else(|| self.error())?;
+                    self.pos += len + 1;
+                }
+                '[' => in_subset = true,
+                ']' => in_subset = false,
+                '>' if !in_subset => return Ok(()),
+                _ => {}
+            }
+        }
+    }
+
+    fn name(&mut self) -> Result<&'a str> {
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| c.is_whitespace() || matches!(c, '/' | '>' | '='))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return Err(self.error());
+        }
+        self.pos += len;
+        Ok(&rest[..len])
+    }
+
+    fn unescape(&self, s: &str) -> Result<String> {
+        let mut unescaped = String::with_capacity(s.len());
+        let mut rest = s;
+        while let Some(start) = rest.find('&') {
+            unescaped.push_str(&rest[..start]);
+            rest = &rest[start + 1..];
+            let end = rest.find(';').ok_or_else(|| self.error())?;
+            let c = match &rest[..end] {
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "amp" => Some('&'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                entity => match entity.strip_prefix("#x") {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => entity.strip_prefix('#').and_then(|d| d.parse().ok()),
+                }
+                .and_then(char::from_u32),
+            };
+            unescaped.push(c.ok_or_else(|| self.error())?);
+            rest = &rest[end + 1..];
+        }
+        unescaped.push_str(rest);
+
+        Ok(unescaped)
+    }
+
+    fn element(&mut self, depth: usize) -> Result<Element> {
+        if depth >= MAX_DEPTH {
+            return Err(self.error());
+        }
+
+        self.expect("<")?;
+        let mut element = Element {
+            name: self.name()?.to_string(),
+            ..Default::default()
+        };
+
+        loop {
+            self.skip_whitespace();
+            if self.rest().starts_with("/>") {
+                self.pos += 2;
+                return Ok(element);
+            }
+            if self.rest().starts_with('>') {
+                self.pos += 1;
+                break;
+            }
+
+            let name = self.name()?.to_string();
+            self.skip_whitespace();
+            self.expect("=")?;
+            self.skip_whitespace();
+            let quote = match self.rest().chars().next() {
+                Some(c @ ('"' | '\'')) => c,
+                _ => return Err(self.error()),
+            };
+            self.pos += 1;
+            let len = self.rest().find(quote).ok_or_else(|| self.error())?;
+            let value = self.unescape(&self.rest()[..len])?;
+            self.pos += len + 1;
+            element.attributes.push((name, value));
+        }
+
+        loop {
+            let len = self.rest().find('<').ok_or_else(|| self.error())?;
+            let text = self.unescape(&self.rest()[..len])?;
+            element.text.push_str(&text);
+            self.pos += len;
+
+            let rest = self.rest();
+            if rest.starts_with("</") {
+                self.pos += 2;
+                if self.name()? != element.name {
+                    return Err(self.error());
+                }
+                self.skip_whitespace();
+                self.expect(">")?;
+                return Ok(element);
+            } else if rest.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+                let len = cdata.find("]]>").ok_or_else(|| self.error())?;
+                element.text.push_str(&cdata[..len]);
+                self.pos += "<![CDATA[".len() + len + "]]>".len();
+            } else if rest.starts_with("<?") {
+                self.skip_past("?>")?;
+            } else {
+                element.children.push(self.element(depth + 1)?);
+            }
+        }
+    }
+}
+
+/// Parses an XML document, returning its root element.
+pub fn parse(xml: &str) -> Result<Element> {
+    let mut reader = XmlReader { input: xml, pos: 0 };
+    reader.skip_misc()?;
+    let root = reader.element(0)?;
+    reader.skip_misc()?;
+    if !reader.rest().is_empty() {
+        return Err(reader.error());
+    }
+
+    Ok(root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let root = parse(
+            "<?xml version='1.0'?>\n<!-- comment -->\n\
+             <a x=\"1\" y='&lt;2&#x3E;'><b/>text &amp; <![CDATA[<raw>]]><c>\n</c></a>\n",
+        )
+        .unwrap();
+        assert_eq!(root.name, "a");
+        assert_eq!(root.attr("x"), Some("1"));
+        assert_eq!(root.attr("y"), Some("<2>"));
+        assert_eq!(root.children.len(), 2);
+        assert_eq!(root.text, "text & <raw>");
+
+        parse("<a><b></a>").unwrap_err();
+        parse("<a></a><b/>").unwrap_err();
+        parse("<a x=1/>").unwrap_err();
+    }
+
+    #[test]
+    fn test_parse_doctype() {
+        let root =
+            parse("<!DOCTYPE a SYSTEM 'a>.dtd' [\n<!ENTITY e \"<b>\">\n<!-- ]> -->\n]>\n<a/>")
+                .unwrap();
+        assert_eq!(root.name, "a");
+
+        parse("<!DOCTYPE a [<!ENTITY e 'e'>").unwrap_err();
+    }
+
+    #[test]
+    fn test_parse_depth() {
+        let nested = |depth| "<a>".repeat(depth) + &"</a>".repeat(depth);
+        parse(&nested(MAX_DEPTH)).unwrap();
+        parse(&nested(MAX_DEPTH + 1)).unwrap_err();
+    }
+}